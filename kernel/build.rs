@@ -1,6 +1,45 @@
+use std::fmt::Write as _;
+
 fn main() {
     let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-    println!("cargo:rustc-link-arg=-T{}/linker.ld", manifest_dir);
-    println!("cargo:rerun-if-changed=linker.ld");
-    println!("cargo:rerun-if-changed=src/boot.S");
+    // Only the kernel links at its load address; host tests link normally
+    let script = match std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() {
+        Ok("aarch64") => "linker-aarch64.ld",
+        _             => "linker.ld",
+    };
+    println!("cargo:rustc-link-arg-bins=-T{}/{}", manifest_dir, script);
+    // Under SBI the firmware keeps the first 2 MiB of RAM
+    if std::env::var_os("CARGO_FEATURE_SBI").is_some() {
+        println!("cargo:rustc-link-arg-bins=--defsym=_load_offset=0x200000");
+    }
+    println!("cargo:rerun-if-changed={}", script);
+    println!("cargo:rerun-if-changed=src/arch");
+    // The built-in command line, for loaders that pass none
+    println!("cargo:rerun-if-env-changed=SURAKSHA_CMDLINE");
+    boot_anchors();
+}
+
+/// Embed the boot chain's trust anchors: the SLH-DSA public keys listed,
+/// one per line in hex, in the file SURAKSHA_BOOT_KEYS names.  Without
+/// one the kernel has no anchors and treats every image as untrusted.
+fn boot_anchors() {
+    println!("cargo:rerun-if-env-changed=SURAKSHA_BOOT_KEYS");
+    let mut out = String::from("&[\n");
+    if let Ok(path) = std::env::var("SURAKSHA_BOOT_KEYS") {
+        println!("cargo:rerun-if-changed={}", path);
+        let keys = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+        for line in keys.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            if line.len() != 64 || !line.bytes().all(|b| b.is_ascii_hexdigit()) {
+                panic!("{}: expected 32-byte keys in hex, got {:?}", path, line);
+            }
+            out.push_str("    [");
+            for i in (0..64).step_by(2) {
+                write!(out, "0x{}, ", &line[i..i + 2]).unwrap();
+            }
+            out.push_str("],\n");
+        }
+    }
+    out.push(']');
+    let dest = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("boot_anchors.rs");
+    std::fs::write(dest, out).unwrap();
 }
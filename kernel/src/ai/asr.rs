@@ -0,0 +1,245 @@
+//! Speech Recognition
+//! Streaming speech-to-text with a small CTC acoustic model (GGUF
+//! architecture "ctc").  Log-mel frames are stacked a few at a time and
+//! run through an input projection and a stack of blocks, each a causal
+//! depthwise convolution over recent frames followed by a SiLU
+//! feed-forward layer; every output frame scores the vocabulary plus a
+//! blank.  Greedy CTC decoding emits a token whenever the best label
+//! changes to something other than blank, so text is available as soon as
+//! it is heard, with no look-ahead.  Weights may be in any quantization
+//! the matmul kernels handle.  Audio stays in kernel memory only as long
+//! as it takes to compute its features, and is wiped afterwards; features
+//! and transcript are kept in zero-on-free memory.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::gguf::GgufFile;
+use super::kernels::{self, Matrix};
+use super::mel::MelExtractor;
+use super::stream::ModelRef;
+use super::transformer::{self, Loader};
+use crate::arch;
+use crate::memory::{SecureVec, ZeroOnFree};
+
+#[derive(Debug, Clone)]
+pub struct AsrConfig {
+    pub n_mels:  usize,
+    /// Mel frames per model frame.
+    pub stack:   usize,
+    pub n_embd:  usize,
+    pub n_layer: usize,
+    pub n_ff:    usize,
+    /// Frames each block's convolution looks at, including the current.
+    pub conv_k:  usize,
+    pub n_vocab: usize,
+    pub blank:   u32,
+    pub rms_eps: f32,
+}
+
+struct Block {
+    /// Depthwise kernel, [tap][channel], oldest tap first.
+    conv: Vec<f32>,
+    norm: Vec<f32>,
+    up:   usize,
+    down: usize,
+}
+
+pub struct AsrModel {
+    pub cfg:     AsrConfig,
+    /// Per-bin feature normalisation, if the model was trained with it.
+    feat_mean:   Option<Vec<f32>>,
+    feat_scale:  Option<Vec<f32>>,
+    input:       usize,
+    input_bias:  Option<Vec<f32>>,
+    blocks:      Vec<Block>,
+    output_norm: Vec<f32>,
+    output:      usize,
+    vocab:       Vec<String>,
+}
+
+impl AsrModel {
+    pub fn from_gguf(g: &GgufFile) -> Result<Self, &'static str> {
+        if g.architecture() != Some("ctc") { return Err("not a speech model"); }
+        let int = |k: &str| g.get_u64(&format!("ctc.{}", k)).filter(|&v| v > 0 && v <= u32::MAX as u64).map(|v| v as usize);
+
+        let vocab: Vec<String> = g.get("tokenizer.ggml.tokens").and_then(|v| v.as_array()).ok_or("speech model has no vocabulary")?
+            .iter().map(|t| t.as_str().map(|s| s.replace('\u{2581}', " ")).ok_or("bad vocabulary entry"))
+            .collect::<Result<_, _>>()?;
+
+        let cfg = AsrConfig {
+            n_mels:  int("mel_bins").unwrap_or(80),
+            stack:   int("frame_stack").unwrap_or(1),
+            n_embd:  int("embedding_length").ok_or("model has no embedding length")?,
+            n_layer: int("block_count").ok_or("model has no block count")?,
+            n_ff:    int("feed_forward_length").ok_or("model has no feed-forward length")?,
+            conv_k:  int("conv_kernel").unwrap_or(1),
+            n_vocab: vocab.len(),
+            blank:   g.get_u64("ctc.blank_id").unwrap_or(0) as u32,
+            rms_eps: g.get("ctc.norm_epsilon").and_then(|v| v.as_f32()).unwrap_or(1e-5),
+        };
+        if cfg.blank as usize >= cfg.n_vocab { return Err("blank outside vocabulary"); }
+
+        let ld = Loader { g };
+        let (e, n_in) = (cfg.n_embd, cfg.n_mels * cfg.stack);
+        let mut blocks = Vec::with_capacity(cfg.n_layer);
+        for i in 0..cfg.n_layer {
+            let t = |n: &str| format!("blk.{}.{}", i, n);
+            let conv_w = ld.matrix(&t("conv.weight"), e, cfg.conv_k)?;
+            let mut conv = vec![0.0; cfg.conv_k * e];
+            for (k, row) in conv.chunks_exact_mut(e).enumerate() {
+                Matrix::from_tensor(g, conv_w).row(k, row);
+            }
+            blocks.push(Block {
+                conv,
+                norm: ld.vector(&t("norm.weight"), e)?,
+                up:   ld.matrix(&t("ffn_up.weight"), e, cfg.n_ff)?,
+                down: ld.matrix(&t("ffn_down.weight"), cfg.n_ff, e)?,
+            });
+        }
+
+        Ok(AsrModel {
+            feat_mean:   ld.optional_vector("feat.mean", cfg.n_mels)?,
+            feat_scale:  ld.optional_vector("feat.inv_std", cfg.n_mels)?,
+            input:       ld.matrix("input.weight", n_in, e)?,
+            input_bias:  ld.optional_vector("input.bias", e)?,
+            output_norm: ld.vector("output_norm.weight", e)?,
+            output:      ld.matrix("output.weight", e, cfg.n_vocab)?,
+            cfg, blocks, vocab,
+        })
+    }
+
+    pub fn token_text(&self, id: u32) -> &str {
+        self.vocab.get(id as usize).map_or("", |s| s.as_str())
+    }
+
+    /// One model frame: `feats` is `stack` mel frames; `history` holds each
+    /// block's last `conv_k - 1` inputs.  Writes the label scores.
+    fn step(&self, g: &GgufFile, history: &mut [Vec<f32>], feats: &[f32], logits: &mut [f32]) {
+        let c = &self.cfg;
+        let mut input = feats.to_vec();
+        for frame in input.chunks_exact_mut(c.n_mels) {
+            if let Some(m) = &self.feat_mean  { for (v, m) in frame.iter_mut().zip(m) { *v -= m; } }
+            if let Some(s) = &self.feat_scale { for (v, s) in frame.iter_mut().zip(s) { *v *= s; } }
+        }
+
+        let mut x   = vec![0.0; c.n_embd];
+        let mut xb  = vec![0.0; c.n_embd];
+        let mut hb  = vec![0.0; c.n_ff];
+        kernels::matmul(&mut x, &Matrix::from_tensor(g, self.input), &input);
+        if let Some(b) = &self.input_bias { for (v, b) in x.iter_mut().zip(b) { *v += b; } }
+
+        for (block, hist) in self.blocks.iter().zip(history.iter_mut()) {
+            // Causal depthwise convolution over [history..., x]
+            let taps = c.conv_k;
+            xb.fill(0.0);
+            for k in 0..taps {
+                let w = &block.conv[k * c.n_embd..(k + 1) * c.n_embd];
+                let src = if k + 1 == taps { &x[..] } else { &hist[k * c.n_embd..(k + 1) * c.n_embd] };
+                for ((o, w), s) in xb.iter_mut().zip(w).zip(src) { *o += w * s; }
+            }
+            if taps > 1 {
+                hist.copy_within(c.n_embd.., 0);
+                let end = hist.len();
+                hist[end - c.n_embd..].copy_from_slice(&x);
+            }
+            for (a, b) in x.iter_mut().zip(&xb) { *a += b; }
+
+            // Feed-forward
+            kernels::rmsnorm(&mut xb, &x, &block.norm, c.rms_eps);
+            kernels::matmul(&mut hb, &Matrix::from_tensor(g, block.up), &xb);
+            for h in hb.iter_mut() { *h = kernels::silu(*h); }
+            kernels::matmul(&mut xb, &Matrix::from_tensor(g, block.down), &hb);
+            for (a, b) in x.iter_mut().zip(&xb) { *a += b; }
+        }
+
+        kernels::rmsnorm(&mut xb, &x, &self.output_norm, c.rms_eps);
+        kernels::matmul(logits, &Matrix::from_tensor(g, self.output), &xb);
+    }
+}
+
+// ─── streaming transcription ──────────────────────────────────────────────────
+
+pub struct Transcriber<'m> {
+    model:   ModelRef<'m>,
+    mel:     MelExtractor,
+    /// Mel frames waiting to fill a model frame.
+    frames:  SecureVec<f32>,
+    history: Vec<Vec<f32>>,
+    logits:  Vec<f32>,
+    /// Best label of the previous frame, for collapsing repeats.
+    last:    u32,
+    /// Always valid UTF-8.
+    text:    SecureVec<u8>,
+    samples: usize,
+}
+
+impl<'m> Transcriber<'m> {
+    pub(super) fn new(model: ModelRef<'m>) -> Result<Self, &'static str> {
+        let asr = model.asr.as_ref().ok_or("not a speech model")?;
+        let c = &asr.cfg;
+        Ok(Transcriber {
+            mel:     MelExtractor::new(c.n_mels),
+            frames:  Vec::new_in(ZeroOnFree),
+            history: (0..c.n_layer).map(|_| vec![0.0; (c.conv_k - 1) * c.n_embd]).collect(),
+            logits:  vec![0.0; c.n_vocab],
+            last:    c.blank,
+            text:    Vec::new_in(ZeroOnFree),
+            samples: 0,
+            model,
+        })
+    }
+
+    /// Transcribe more audio (16 kHz mono); returns the text it added.
+    pub fn push(&mut self, samples: &[i16]) -> Result<String, &'static str> {
+        let (Some(g), Some(asr)) = (&self.model.weights, &self.model.asr) else { return Err("model not loaded") };
+        self.samples += samples.len();
+        self.mel.push(samples, &mut self.frames);
+
+        let per_step = asr.cfg.n_mels * asr.cfg.stack;
+        let mut added = String::new();
+        let mut used = 0;
+        while used + per_step <= self.frames.len() {
+            let start = arch::read_mtime();
+            asr.step(g, &mut self.history, &self.frames[used..used + per_step], &mut self.logits);
+            if let Some(sandbox) = &self.model.sandbox { sandbox.charge(arch::read_mtime() - start); }
+            used += per_step;
+
+            let best = transformer::argmax(&self.logits);
+            if best != self.last && best != asr.cfg.blank {
+                let piece = asr.token_text(best);
+                added.push_str(if self.text.is_empty() && added.is_empty() { piece.trim_start() } else { piece });
+            }
+            self.last = best;
+        }
+        self.frames.copy_within(used.., 0);
+        self.frames.truncate(self.frames.len() - used);
+        self.text.extend_from_slice(added.as_bytes());
+        Ok(added)
+    }
+
+    /// Everything transcribed so far.
+    pub fn text(&self) -> &str {
+        core::str::from_utf8(&self.text).unwrap_or_default()
+    }
+
+    /// Length of the audio heard so far.
+    pub fn audio_ms(&self) -> u64 {
+        self.samples as u64 * 1000 / crate::audio::SAMPLE_RATE as u64
+    }
+
+    pub fn finish(self) -> String {
+        String::from(self.text())
+    }
+}
+
+impl Drop for Transcriber<'_> {
+    fn drop(&mut self) {
+        // Leave nothing derived from the audio behind
+        self.mel.reset();
+        self.frames.fill(0.0);
+        for h in &mut self.history { h.fill(0.0); }
+    }
+}
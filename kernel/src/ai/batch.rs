@@ -0,0 +1,108 @@
+//! Batch Inference
+//! Prompts an app queues to be run later at low priority, such as
+//! summarising the day's mail or notes overnight.  Jobs only run while the
+//! device is on external power and the user is idle, as the charger and
+//! the power-state notifications say; when either ends, the running prompt
+//! pauses between tokens and picks up again at the next chance.
+//! Interactive sessions always go first.  Each prompt's result comes back
+//! as a completion event when it finishes, and a last event closes the
+//! job.
+//!
+//! This module holds the queue and the power gate; `service` parses the
+//! requests, runs the jobs and delivers the events.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::stream::Generation;
+use crate::charger;
+use crate::ipc::ChannelId;
+use crate::memory::{SecureVec, ZeroOnFree};
+use crate::power::{Phase, PowerTransition, POWER_NOTIFY_LEN};
+use crate::process::ProcessId;
+
+/// Jobs one client may have queued.
+pub const MAX_JOBS: usize = 8;
+
+// ─── power gate ───────────────────────────────────────────────────────────────
+
+/// The user has gone idle (or the screen is off).
+static USER_IDLE:  AtomicBool = AtomicBool::new(false);
+/// A suspend is under way; nothing may run until the resume.
+static SUSPENDING: AtomicBool = AtomicBool::new(false);
+
+/// Note a power-state notification.  Returns the acknowledgement a `Pre`
+/// phase needs, and whether jobs may now be able to run.
+pub fn power_event(payload: &[u8]) -> (Option<Vec<u8>>, bool) {
+    if payload.len() < POWER_NOTIFY_LEN { return (None, false); }
+    let (transition, phase) = (payload[0], payload[1]);
+    let pre = phase == Phase::Pre as u8;
+    if transition == PowerTransition::Active as u8 {
+        USER_IDLE.store(false, Ordering::Relaxed);
+    } else if transition == PowerTransition::Suspend as u8 {
+        // Quiesced by the time this returns: the next token checks the flag
+        SUSPENDING.store(pre, Ordering::Relaxed);
+    } else if !pre {
+        USER_IDLE.store(true, Ordering::Relaxed);
+    }
+    (pre.then(|| payload[2..6].to_vec()), allowed())
+}
+
+/// Whether batch jobs may run now.
+pub fn allowed() -> bool {
+    USER_IDLE.load(Ordering::Relaxed) && !SUSPENDING.load(Ordering::Relaxed)
+        && charger::status().state.plugged_in()
+}
+
+// ─── jobs ─────────────────────────────────────────────────────────────────────
+
+pub struct BatchJob {
+    pub id:         u32,
+    pub channel:    ChannelId,
+    pub owner:      ProcessId,
+    pub max_tokens: usize,
+    /// Prompts not yet started, in submission order.
+    prompts:        VecDeque<SecureVec<u8>>,
+    /// Index of the next prompt to finish.
+    pub index:      u16,
+    pub gen:        Option<Generation<'static>>,
+    /// An event the client's full channel has not taken yet.
+    pub backlog:    Option<Vec<u8>>,
+    /// Every prompt has run and the closing event has been queued.
+    pub finished:   bool,
+}
+
+/// A job for `prompts`, each to be answered in at most `max_tokens`
+/// tokens.  The prompts are kept in zero-on-free memory until they run.
+pub fn new_job(id: u32, channel: ChannelId, owner: ProcessId, max_tokens: usize, prompts: &[&str]) -> BatchJob {
+    let prompts = prompts.iter().map(|p| {
+        let mut v = Vec::with_capacity_in(p.len(), ZeroOnFree);
+        v.extend_from_slice(p.as_bytes());
+        v
+    }).collect();
+    BatchJob { id, channel, owner, max_tokens, prompts, index: 0, gen: None, backlog: None, finished: false }
+}
+
+impl BatchJob {
+    /// Whether the job has a prompt to run or start.
+    pub fn is_ready(&self) -> bool {
+        self.backlog.is_none() && (self.gen.is_some() || !self.prompts.is_empty())
+    }
+
+    /// Every prompt has run; only the closing event is left to send.
+    pub fn is_done(&self) -> bool {
+        self.gen.is_none() && self.prompts.is_empty()
+    }
+
+    /// A copy of the next prompt, to start it outside the service lock.
+    pub fn next_prompt(&self) -> Option<SecureVec<u8>> {
+        self.prompts.front().cloned()
+    }
+
+    /// The next prompt has started as `gen`, or failed to start if None.
+    pub fn started(&mut self, gen: Option<Generation<'static>>) {
+        self.prompts.pop_front();
+        self.gen = gen;
+    }
+}
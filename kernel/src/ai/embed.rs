@@ -0,0 +1,106 @@
+//! Embeddings
+//! Fixed-size vectors for text, for private on-device search and
+//! retrieval.  The text runs through the model's transformer like a
+//! prompt, and the final hidden states of its tokens are pooled into one
+//! vector; the output head is never evaluated.  Like generation, the
+//! work is charged to the model's sandbox and its KV cache to the
+//! request's Memory capability.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::kvcache::{KvCache, KvPrecision, MemoryGrant};
+use super::langid;
+use super::AiModel;
+use crate::arch;
+use crate::memory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Pooling {
+    /// Average over every token.
+    #[default]
+    Mean,
+    /// The last token's state, which has attended to the whole text.
+    Last,
+    /// Element-wise maximum over every token.
+    Max,
+}
+
+impl Pooling {
+    pub fn from_u8(v: u8) -> Option<Pooling> {
+        match v {
+            0 => Some(Pooling::Mean),
+            1 => Some(Pooling::Last),
+            2 => Some(Pooling::Max),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmbedOptions {
+    pub pooling:   Pooling,
+    /// Scale to unit length, so cosine similarity is a dot product.
+    pub normalize: bool,
+    /// Memory capability the KV cache is charged to.
+    pub memory:    Option<MemoryGrant>,
+}
+
+impl Default for EmbedOptions {
+    fn default() -> Self {
+        EmbedOptions { pooling: Pooling::Mean, normalize: true, memory: None }
+    }
+}
+
+/// Embed each of `texts`, reusing one KV cache.  Texts longer than the
+/// context are embedded from their start.
+pub(super) fn embed_batch(model: &AiModel, texts: &[&str], opts: &EmbedOptions) -> Result<Vec<Vec<f32>>, &'static str> {
+    let (Some(g), Some(t)) = (&model.weights, &model.transformer) else { return Err("model not loaded") };
+    let n_embd = t.cfg.n_embd;
+    let mut cache = KvCache::new(&t.cfg, KvPrecision::F32, opts.memory.as_ref().or(model.sandbox_memory()))?;
+    let mut h = memory::secure_vec(0.0, n_embd);
+    let mut out = Vec::with_capacity(texts.len());
+
+    for text in texts {
+        let normalized = match langid::detect(text) {
+            Some(d) => langid::normalize_for(text, d.language),
+            None    => alloc::string::String::from(*text),
+        };
+        let mut tokens = model.tokenize(&normalized)?;
+        tokens.truncate(t.cfg.n_ctx);
+
+        cache.clear();
+        let mut pooled = vec![if opts.pooling == Pooling::Max { f32::NEG_INFINITY } else { 0.0 }; n_embd];
+        for &token in &tokens {
+            let start = arch::read_mtime();
+            t.hidden(g, None, &mut cache, token, &mut h)?;
+            if let Some(sandbox) = &model.sandbox { sandbox.charge(arch::read_mtime() - start); }
+            match opts.pooling {
+                Pooling::Mean => for (p, v) in pooled.iter_mut().zip(&h) { *p += v },
+                Pooling::Last => pooled.copy_from_slice(&h),
+                Pooling::Max  => for (p, v) in pooled.iter_mut().zip(&h) { *p = p.max(*v) },
+            }
+        }
+        if opts.pooling == Pooling::Mean {
+            let n = tokens.len() as f32;
+            for p in pooled.iter_mut() { *p /= n; }
+        }
+        if opts.normalize {
+            let norm = libm::sqrtf(pooled.iter().map(|v| v * v).sum());
+            if norm > 0.0 {
+                for p in pooled.iter_mut() { *p /= norm; }
+            }
+        }
+        memory::wipe(&mut tokens);
+        out.push(pooled);
+    }
+    Ok(out)
+}
+
+/// Cosine similarity of two embeddings.
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let na = libm::sqrtf(a.iter().map(|v| v * v).sum());
+    let nb = libm::sqrtf(b.iter().map(|v| v * v).sum());
+    if na == 0.0 || nb == 0.0 { 0.0 } else { dot / (na * nb) }
+}
@@ -0,0 +1,337 @@
+//! GGUF Model Container
+//! Parser for the GGUF (v2/v3) format used by llama.cpp-family models.
+//! The file is mapped rather than copied; tensors are slices of the
+//! mapping.  Every length, count and offset in the header is checked
+//! against the file before anything is handed out.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+const DEFAULT_ALIGNMENT: usize = 32;
+const MAX_TENSORS:     u64 = 1 << 16;
+const MAX_DIMS:        u32 = 4;
+const MAX_ARRAY_DEPTH: u32 = 2;
+
+// ─── metadata values ──────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    F32(f32),
+    Bool(bool),
+    Str(String),
+    Array(Vec<Value>),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+}
+
+impl Value {
+    /// Any non-negative integer value, widened.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Value::U8(v)  => Some(v as u64),
+            Value::U16(v) => Some(v as u64),
+            Value::U32(v) => Some(v as u64),
+            Value::U64(v) => Some(v),
+            Value::I8(v)  => u64::try_from(v).ok(),
+            Value::I16(v) => u64::try_from(v).ok(),
+            Value::I32(v) => u64::try_from(v).ok(),
+            Value::I64(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match *self {
+            Value::F32(v) => Some(v),
+            Value::F64(v) => Some(v as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self { Value::Bool(b) => Some(b), _ => None }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self { Value::Str(s) => Some(s), _ => None }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self { Value::Array(a) => Some(a), _ => None }
+    }
+}
+
+// ─── tensor types ─────────────────────────────────────────────────────────────
+
+/// ggml tensor element types supported by the inference engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GgmlType {
+    F32,
+    F16,
+    Q4_0,
+    Q4_1,
+    Q8_0,
+    Q4K,
+    Q6K,
+    I8,
+    BF16,
+}
+
+impl GgmlType {
+    fn from_u32(v: u32) -> Option<Self> {
+        Some(match v {
+            0  => GgmlType::F32,
+            1  => GgmlType::F16,
+            2  => GgmlType::Q4_0,
+            3  => GgmlType::Q4_1,
+            8  => GgmlType::Q8_0,
+            12 => GgmlType::Q4K,
+            14 => GgmlType::Q6K,
+            24 => GgmlType::I8,
+            30 => GgmlType::BF16,
+            _  => return None,
+        })
+    }
+
+    /// (elements per block, bytes per block)
+    pub fn block(self) -> (usize, usize) {
+        match self {
+            GgmlType::F32  => (1, 4),
+            GgmlType::F16  => (1, 2),
+            GgmlType::BF16 => (1, 2),
+            GgmlType::I8   => (1, 1),
+            GgmlType::Q4_0 => (32, 18),
+            GgmlType::Q4_1 => (32, 20),
+            GgmlType::Q8_0 => (32, 34),
+            GgmlType::Q4K  => (256, 144),
+            GgmlType::Q6K  => (256, 210),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GgmlType::F32  => "f32",
+            GgmlType::F16  => "f16",
+            GgmlType::BF16 => "bf16",
+            GgmlType::I8   => "i8",
+            GgmlType::Q4_0 => "q4_0",
+            GgmlType::Q4_1 => "q4_1",
+            GgmlType::Q8_0 => "q8_0",
+            GgmlType::Q4K  => "q4_k",
+            GgmlType::Q6K  => "q6_k",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TensorInfo {
+    pub name:   String,
+    /// Dimensions, innermost first.
+    pub dims:   Vec<u64>,
+    pub ty:     GgmlType,
+    /// Byte offset within the file.
+    pub offset: usize,
+    pub size:   usize,
+}
+
+impl TensorInfo {
+    pub fn elements(&self) -> u64 {
+        self.dims.iter().product()
+    }
+}
+
+// ─── reader ───────────────────────────────────────────────────────────────────
+
+struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], &'static str> {
+        let end = self.pos.checked_add(n).filter(|&e| e <= self.buf.len()).ok_or("gguf: truncated")?;
+        let b = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(b)
+    }
+
+    fn array<const W: usize>(&mut self) -> Result<[u8; W], &'static str> {
+        Ok(self.bytes(W)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self)  -> Result<u8,  &'static str> { Ok(self.bytes(1)?[0]) }
+    fn u16(&mut self) -> Result<u16, &'static str> { Ok(u16::from_le_bytes(self.array()?)) }
+    fn u32(&mut self) -> Result<u32, &'static str> { Ok(u32::from_le_bytes(self.array()?)) }
+    fn u64(&mut self) -> Result<u64, &'static str> { Ok(u64::from_le_bytes(self.array()?)) }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    /// A count of items each at least `min_size` bytes long, refused if the
+    /// rest of the file could not possibly hold them.
+    fn count(&mut self, min_size: usize) -> Result<usize, &'static str> {
+        let n = self.u64()?;
+        if n.saturating_mul(min_size as u64) > self.remaining() as u64 { return Err("gguf: count exceeds file"); }
+        Ok(n as usize)
+    }
+
+    fn string(&mut self) -> Result<String, &'static str> {
+        let n = self.count(1)?;
+        let b = self.bytes(n)?;
+        core::str::from_utf8(b).map(String::from).map_err(|_| "gguf: string not utf-8")
+    }
+
+    fn value(&mut self, ty: u32, depth: u32) -> Result<Value, &'static str> {
+        Ok(match ty {
+            0  => Value::U8(self.u8()?),
+            1  => Value::I8(self.u8()? as i8),
+            2  => Value::U16(self.u16()?),
+            3  => Value::I16(self.u16()? as i16),
+            4  => Value::U32(self.u32()?),
+            5  => Value::I32(self.u32()? as i32),
+            6  => Value::F32(f32::from_bits(self.u32()?)),
+            7  => match self.u8()? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                _ => return Err("gguf: bad bool"),
+            },
+            8  => Value::Str(self.string()?),
+            9  => {
+                if depth >= MAX_ARRAY_DEPTH { return Err("gguf: arrays nested too deep"); }
+                let elem = self.u32()?;
+                let n    = self.count(1)?;
+                let mut v = Vec::with_capacity(n);
+                for _ in 0..n { v.push(self.value(elem, depth + 1)?); }
+                Value::Array(v)
+            }
+            10 => Value::U64(self.u64()?),
+            11 => Value::I64(self.u64()? as i64),
+            12 => Value::F64(f64::from_bits(self.u64()?)),
+            _  => return Err("gguf: unknown value type"),
+        })
+    }
+}
+
+// ─── file ─────────────────────────────────────────────────────────────────────
+
+pub struct GgufFile {
+    map:          Arc<[u8]>,
+    pub version:  u32,
+    pub metadata: Vec<(String, Value)>,
+    pub tensors:  Vec<TensorInfo>,
+}
+
+impl GgufFile {
+    /// Parse and validate a mapped GGUF file.
+    pub fn parse(map: Arc<[u8]>) -> Result<Self, &'static str> {
+        let mut c = Cursor { buf: &map, pos: 0 };
+
+        if c.bytes(4)? != GGUF_MAGIC { return Err("gguf: bad magic"); }
+        let version = c.u32()?;
+        if !(2..=3).contains(&version) { return Err("gguf: unsupported version"); }
+
+        let n_tensors = c.u64()?;
+        if n_tensors > MAX_TENSORS { return Err("gguf: too many tensors"); }
+        let n_kv = c.count(8 + 4 + 1)?;
+
+        let mut metadata: Vec<(String, Value)> = Vec::with_capacity(n_kv);
+        for _ in 0..n_kv {
+            let key = c.string()?;
+            let ty  = c.u32()?;
+            let val = c.value(ty, 0)?;
+            if metadata.iter().any(|(k, _)| *k == key) { return Err("gguf: duplicate metadata key"); }
+            metadata.push((key, val));
+        }
+
+        let alignment = match metadata.iter().find(|(k, _)| k == "general.alignment") {
+            None        => DEFAULT_ALIGNMENT,
+            Some((_, v)) => match v.as_u64() {
+                Some(a) if a.is_power_of_two() && a <= 4096 => a as usize,
+                _ => return Err("gguf: bad alignment"),
+            },
+        };
+
+        let mut infos = Vec::new();
+        for _ in 0..n_tensors {
+            let name = c.string()?;
+            let n_dims = c.u32()?;
+            if n_dims == 0 || n_dims > MAX_DIMS { return Err("gguf: bad tensor rank"); }
+            let mut dims = Vec::with_capacity(n_dims as usize);
+            for _ in 0..n_dims { dims.push(c.u64()?); }
+            let ty  = GgmlType::from_u32(c.u32()?).ok_or("gguf: unsupported tensor type")?;
+            let off = c.u64()?;
+            infos.push((name, dims, ty, off));
+        }
+
+        // Padding may be omitted when there is no tensor data
+        let data_start = c.pos.next_multiple_of(alignment).min(map.len());
+        let data_len   = (map.len() - data_start) as u64;
+
+        let mut tensors: Vec<TensorInfo> = Vec::with_capacity(infos.len());
+        for (name, dims, ty, off) in infos {
+            if tensors.iter().any(|t| t.name == name) { return Err("gguf: duplicate tensor name"); }
+            if dims.contains(&0) { return Err("gguf: zero-sized dimension"); }
+            let elements = dims.iter().try_fold(1u64, |acc, &d| acc.checked_mul(d))
+                .ok_or("gguf: tensor too large")?;
+            let (block, block_bytes) = ty.block();
+            if dims[0] % block as u64 != 0 { return Err("gguf: row not a whole number of blocks"); }
+            let size = (elements / block as u64).checked_mul(block_bytes as u64)
+                .ok_or("gguf: tensor too large")?;
+            if off % alignment as u64 != 0 { return Err("gguf: misaligned tensor"); }
+            if off.checked_add(size).is_none_or(|end| end > data_len) {
+                return Err("gguf: tensor outside file");
+            }
+            tensors.push(TensorInfo {
+                name, dims, ty,
+                offset: data_start + off as usize,
+                size:   size as usize,
+            });
+        }
+
+        let mut spans: Vec<(usize, usize)> = tensors.iter().map(|t| (t.offset, t.offset + t.size)).collect();
+        spans.sort_unstable();
+        if spans.windows(2).any(|w| w[1].0 < w[0].1) { return Err("gguf: overlapping tensors"); }
+
+        Ok(GgufFile { map, version, metadata, tensors })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.metadata.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get(key).and_then(Value::as_u64)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Value::as_str)
+    }
+
+    /// Model architecture, e.g. "llama".
+    pub fn architecture(&self) -> Option<&str> {
+        self.get_str("general.architecture")
+    }
+
+    pub fn tensor(&self, name: &str) -> Option<&TensorInfo> {
+        self.tensors.iter().find(|t| t.name == name)
+    }
+
+    /// Raw bytes of a tensor, borrowed from the mapping.
+    pub fn tensor_data(&self, t: &TensorInfo) -> &[u8] {
+        &self.map[t.offset..t.offset + t.size]
+    }
+
+    pub fn file_size(&self) -> usize {
+        self.map.len()
+    }
+}
@@ -0,0 +1,286 @@
+//! GPU Compute
+//! Inference on the GPU through a compute interface modelled on Vulkan's.
+//! A GPU driver implements `GpuBackend` and registers itself against the
+//! device-tree `compatible` strings it handles, as NPU drivers do.  Work is
+//! expressed over device buffers: host data is written into them, kernel
+//! dispatches are submitted in batches that run in order and signal a
+//! fence, and results are read back once the fence has passed.
+//!
+//! Each dispatch costs a round trip for the activations, so the GPU only
+//! pays off for big layers.  When a model loads, each layer (and the output
+//! head) whose weights reach `GPU_MIN_LAYER_WEIGHTS` is copied to the GPU
+//! and its matmuls run there; its attention joins them once the context
+//! reaches `GPU_MIN_CONTEXT` positions.  Weights the NPU holds stay with
+//! the NPU.  Anything else, or any op the GPU fails, runs on the CPU.
+//! Scratch buffers that held activations are zeroed before they are freed.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::gguf::{GgmlType, GgufFile};
+use super::kernels::{self, Matrix};
+use super::kvcache::KvCache;
+use super::transformer::Config;
+use crate::fdt;
+use crate::memory::{self, SecureVec, ZeroOnFree};
+
+pub type BufferId = u32;
+pub type Fence    = u64;
+
+/// Weights a layer needs before it is worth running on the GPU.
+pub const GPU_MIN_LAYER_WEIGHTS: usize = 4 << 20;
+/// Context length from which attention of a GPU layer runs on the GPU.
+pub const GPU_MIN_CONTEXT: usize = 256;
+
+pub enum Dispatch {
+    /// out = W · x, W being `rows` rows of `cols` weights of type `ty`;
+    /// x and out are f32.
+    MatMul { ty: GgmlType, rows: usize, cols: usize, weights: BufferId, x: BufferId, out: BufferId },
+    /// Attention of `n_head` query heads over `positions` keys and values,
+    /// each position a row of `n_head_kv` heads; query head h reads KV
+    /// head h / (n_head / n_head_kv).  Writes the weighted values, f32.
+    Attention {
+        q: BufferId, k: BufferId, v: BufferId, out: BufferId,
+        n_head: usize, n_head_kv: usize, head_dim: usize, positions: usize, scale: f32,
+    },
+}
+
+pub trait GpuBackend: Send {
+    fn name(&self) -> &'static str;
+
+    /// Weight formats the matmul kernel handles.
+    fn supports(&self, ty: GgmlType) -> bool;
+
+    fn create_buffer(&mut self, size: usize) -> Result<BufferId, &'static str>;
+
+    fn destroy_buffer(&mut self, buffer: BufferId);
+
+    /// Copy host data into a buffer; ordered before later submissions.
+    fn write(&mut self, buffer: BufferId, offset: usize, data: &[u8]) -> Result<(), &'static str>;
+
+    /// Queue dispatches to run in order; they run asynchronously until
+    /// waited on.
+    fn submit(&mut self, work: &[Dispatch]) -> Result<Fence, &'static str>;
+
+    fn wait(&mut self, fence: Fence) -> Result<(), &'static str>;
+
+    /// Copy a buffer's contents back to the host.
+    fn read(&mut self, buffer: BufferId, offset: usize, out: &mut [u8]) -> Result<(), &'static str>;
+}
+
+// ─── drivers + detection ──────────────────────────────────────────────────────
+
+/// Bring up the GPU described by a device-tree node.
+pub type ProbeFn = fn(&fdt::Node) -> Result<Box<dyn GpuBackend>, &'static str>;
+
+static DRIVERS: Mutex<Vec<(&'static str, ProbeFn)>> = Mutex::new(Vec::new());
+
+static GPU: Mutex<Option<Box<dyn GpuBackend>>> = Mutex::new(None);
+
+pub fn register_driver(compatible: &'static str, probe: ProbeFn) {
+    DRIVERS.lock().push((compatible, probe));
+}
+
+/// Look for a GPU in the device tree and start its driver.  Returns the
+/// backend's name, or None when there is no GPU compute.
+pub fn probe() -> Option<&'static str> {
+    let dt = fdt::get()?;
+    let drivers = DRIVERS.lock();
+    for node in dt.nodes().filter(|n| n.is_enabled()) {
+        for &(compat, probe) in drivers.iter() {
+            if !node.is_compatible(compat) { continue; }
+            match probe(&node) {
+                Ok(backend) => {
+                    let name = backend.name();
+                    *GPU.lock() = Some(backend);
+                    return Some(name);
+                }
+                Err(e) => crate::println!("  [gpu] {} ({}): {}", node.name, compat, e),
+            }
+        }
+    }
+    None
+}
+
+pub fn has_gpu() -> bool {
+    GPU.lock().is_some()
+}
+
+pub fn backend_name() -> Option<&'static str> {
+    GPU.lock().as_ref().map(|b| b.name())
+}
+
+// ─── offloaded layers ─────────────────────────────────────────────────────────
+
+const X: usize = 0;
+const OUT: usize = 1;
+const Q: usize = 2;
+const K: usize = 3;
+const V: usize = 4;
+
+/// Activation buffers reused across dispatches, with their sizes.
+type Scratch = [Option<(BufferId, usize)>; 5];
+
+/// A model's large layers resident on the GPU.  Freed when dropped.
+pub struct GpuModel {
+    /// Weight buffer per tensor on the GPU, sorted by tensor.
+    weights: Vec<(usize, BufferId)>,
+    /// Which layers run on the GPU, by index.
+    layers:  Vec<bool>,
+    scratch: Mutex<Scratch>,
+    /// Set after the GPU fails an op; everything then runs on the CPU.
+    failed:  AtomicBool,
+}
+
+impl GpuModel {
+    /// Copy the layers among `layers` (each a list of weight tensors) that
+    /// are big enough and in formats the GPU runs.  None if there is no
+    /// GPU or no layer qualifies.
+    pub fn load(g: &GgufFile, layers: &[Vec<usize>]) -> Option<Self> {
+        let mut gpu = GPU.lock();
+        let backend = gpu.as_mut()?;
+        let fits = |tensors: &Vec<usize>| {
+            let size: usize = tensors.iter().map(|&i| g.tensors[i].dims.iter().product::<u64>() as usize).sum();
+            !tensors.is_empty() && size >= GPU_MIN_LAYER_WEIGHTS
+                && tensors.iter().all(|&i| backend.supports(g.tensors[i].ty))
+        };
+        let chosen: Vec<bool> = layers.iter().map(fits).collect();
+        if !chosen.contains(&true) { return None; }
+
+        let mut weights: Vec<(usize, BufferId)> = Vec::new();
+        for (tensors, _) in layers.iter().zip(&chosen).filter(|(_, on)| **on) {
+            for &i in tensors {
+                if weights.iter().any(|&(t, _)| t == i) { continue; }
+                let m = Matrix::from_tensor(g, i);
+                let loaded = backend.create_buffer(m.data.len())
+                    .and_then(|b| backend.write(b, 0, m.data).map(|_| b).inspect_err(|_| backend.destroy_buffer(b)));
+                match loaded {
+                    Ok(b)  => weights.push((i, b)),
+                    Err(e) => {
+                        crate::println!("  [gpu] {}: weight upload failed, using CPU: {}", backend.name(), e);
+                        for &(_, b) in &weights { backend.destroy_buffer(b); }
+                        return None;
+                    }
+                }
+            }
+        }
+        weights.sort_unstable_by_key(|&(t, _)| t);
+        Some(GpuModel { weights, layers: chosen, scratch: Mutex::new([None; 5]), failed: AtomicBool::new(false) })
+    }
+
+    /// Layers running on the GPU.
+    pub fn layer_count(&self) -> usize {
+        self.layers.iter().filter(|&&on| on).count()
+    }
+
+    pub fn runs_layer(&self, layer: usize) -> bool {
+        !self.failed.load(Ordering::Relaxed) && self.layers.get(layer).copied().unwrap_or(false)
+    }
+
+    fn buffer(&self, tensor: usize) -> Option<BufferId> {
+        if self.failed.load(Ordering::Relaxed) { return None; }
+        self.weights.binary_search_by_key(&tensor, |&(t, _)| t).ok().map(|i| self.weights[i].1)
+    }
+
+    pub fn is_offloaded(&self, tensor: usize) -> bool {
+        self.buffer(tensor).is_some()
+    }
+
+    /// out = W · x on the GPU, or on the CPU if it can't.
+    pub fn matmul(&self, tensor: usize, w: &Matrix, out: &mut [f32], x: &[f32]) {
+        if let Some(weights) = self.buffer(tensor) {
+            let result = self.run(|gpu, s| {
+                let x_buf   = upload(gpu, s, X, x)?;
+                let out_buf = scratch(gpu, s, OUT, w.rows * 4)?;
+                let fence = gpu.submit(&[Dispatch::MatMul { ty: w.ty, rows: w.rows, cols: w.cols, weights, x: x_buf, out: out_buf }])?;
+                gpu.wait(fence)?;
+                download(gpu, out_buf, &mut out[..w.rows])
+            });
+            if result { return; }
+        }
+        kernels::matmul(out, w, x);
+    }
+
+    /// Attention for every query head of `layer` over the cache, on the
+    /// GPU.  False if it is not the GPU's to do, or it failed; the caller
+    /// then attends on the CPU.
+    pub fn attend(&self, layer: usize, cache: &KvCache, cfg: &Config, q: &[f32], scale: f32, out: &mut [f32]) -> bool {
+        let positions = cache.live_tokens();
+        if !self.runs_layer(layer) || positions < GPU_MIN_CONTEXT { return false; }
+        let mut keys   = memory::secure_vec(0.0, positions * cfg.kv_dim());
+        let mut values = memory::secure_vec(0.0, positions * cfg.kv_dim());
+        cache.gather(layer, &mut keys, &mut values);
+        self.run(|gpu, s| {
+            let (q, k, v) = (upload(gpu, s, Q, q)?, upload(gpu, s, K, &keys)?, upload(gpu, s, V, &values)?);
+            let out_buf = scratch(gpu, s, OUT, out.len() * 4)?;
+            let fence = gpu.submit(&[Dispatch::Attention {
+                q, k, v, out: out_buf,
+                n_head: cfg.n_head, n_head_kv: cfg.n_head_kv, head_dim: cfg.head_dim, positions, scale,
+            }])?;
+            gpu.wait(fence)?;
+            download(gpu, out_buf, out)
+        })
+    }
+
+    /// Run `op` against the GPU; on failure, give up on the GPU for good.
+    fn run(&self, op: impl FnOnce(&mut dyn GpuBackend, &mut Scratch) -> Result<(), &'static str>) -> bool {
+        let mut s = self.scratch.lock();
+        let result = match GPU.lock().as_mut() {
+            Some(gpu) => op(gpu.as_mut(), &mut s),
+            None      => Err("GPU went away"),
+        };
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                crate::println!("  [gpu] op failed, falling back to CPU: {}", e);
+                self.failed.store(true, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+}
+
+impl Drop for GpuModel {
+    fn drop(&mut self) {
+        let mut gpu = GPU.lock();
+        let Some(gpu) = gpu.as_mut() else { return };
+        for &(_, b) in &self.weights { gpu.destroy_buffer(b); }
+        for (b, size) in self.scratch.get_mut().iter().flatten() {
+            let _ = gpu.write(*b, 0, &memory::secure_vec(0u8, *size));
+            gpu.destroy_buffer(*b);
+        }
+    }
+}
+
+/// Scratch buffer `slot`, grown to at least `size` bytes.
+fn scratch(gpu: &mut dyn GpuBackend, s: &mut Scratch, slot: usize, size: usize) -> Result<BufferId, &'static str> {
+    match s[slot] {
+        Some((b, have)) if have >= size => return Ok(b),
+        Some((b, have)) => {
+            let _ = gpu.write(b, 0, &memory::secure_vec(0u8, have));
+            gpu.destroy_buffer(b);
+            s[slot] = None;
+        }
+        None => {}
+    }
+    let b = gpu.create_buffer(size)?;
+    s[slot] = Some((b, size));
+    Ok(b)
+}
+
+fn upload(gpu: &mut dyn GpuBackend, s: &mut Scratch, slot: usize, data: &[f32]) -> Result<BufferId, &'static str> {
+    let b = scratch(gpu, s, slot, data.len() * 4)?;
+    let mut bytes: SecureVec<u8> = Vec::with_capacity_in(data.len() * 4, ZeroOnFree);
+    for v in data { bytes.extend_from_slice(&v.to_le_bytes()); }
+    gpu.write(b, 0, &bytes)?;
+    Ok(b)
+}
+
+fn download(gpu: &mut dyn GpuBackend, b: BufferId, out: &mut [f32]) -> Result<(), &'static str> {
+    let mut bytes = memory::secure_vec(0u8, out.len() * 4);
+    gpu.read(b, 0, &mut bytes)?;
+    for (o, b) in out.iter_mut().zip(bytes.as_chunks::<4>().0) { *o = f32::from_le_bytes(*b); }
+    Ok(())
+}
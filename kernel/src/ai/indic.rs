@@ -0,0 +1,116 @@
+//! Indic Script Support
+//! Normalisation and character classes for the Brahmic scripts of the
+//! Indian languages (U+0900–U+0D7F).  Text is brought to NFC for these
+//! blocks so the same word typed on different keyboards tokenises the
+//! same way: nukta letters that Unicode excludes from composition are
+//! decomposed, two-part vowel signs are composed, and nukta is ordered
+//! before virama.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+const ZWNJ: char = '\u{200C}';
+const ZWJ:  char = '\u{200D}';
+
+/// Precomposed letters that NFC decomposes (composition exclusions).
+const DECOMPOSE: &[(char, [char; 2])] = &[
+    // Devanagari
+    ('\u{0958}', ['\u{0915}', '\u{093C}']), ('\u{0959}', ['\u{0916}', '\u{093C}']),
+    ('\u{095A}', ['\u{0917}', '\u{093C}']), ('\u{095B}', ['\u{091C}', '\u{093C}']),
+    ('\u{095C}', ['\u{0921}', '\u{093C}']), ('\u{095D}', ['\u{0922}', '\u{093C}']),
+    ('\u{095E}', ['\u{092B}', '\u{093C}']), ('\u{095F}', ['\u{092F}', '\u{093C}']),
+    // Bengali
+    ('\u{09DC}', ['\u{09A1}', '\u{09BC}']), ('\u{09DD}', ['\u{09A2}', '\u{09BC}']),
+    ('\u{09DF}', ['\u{09AF}', '\u{09BC}']),
+    // Gurmukhi
+    ('\u{0A33}', ['\u{0A32}', '\u{0A3C}']), ('\u{0A36}', ['\u{0A38}', '\u{0A3C}']),
+    ('\u{0A59}', ['\u{0A16}', '\u{0A3C}']), ('\u{0A5A}', ['\u{0A17}', '\u{0A3C}']),
+    ('\u{0A5B}', ['\u{0A1C}', '\u{0A3C}']), ('\u{0A5E}', ['\u{0A2B}', '\u{0A3C}']),
+    // Odia
+    ('\u{0B5C}', ['\u{0B21}', '\u{0B3C}']), ('\u{0B5D}', ['\u{0B22}', '\u{0B3C}']),
+];
+
+/// Canonical compositions: (first, second) → composed.
+const COMPOSE: &[([char; 2], char)] = &[
+    // Devanagari
+    (['\u{0928}', '\u{093C}'], '\u{0929}'), (['\u{0930}', '\u{093C}'], '\u{0931}'),
+    (['\u{0933}', '\u{093C}'], '\u{0934}'),
+    // Bengali
+    (['\u{09C7}', '\u{09BE}'], '\u{09CB}'), (['\u{09C7}', '\u{09D7}'], '\u{09CC}'),
+    // Odia
+    (['\u{0B47}', '\u{0B56}'], '\u{0B48}'), (['\u{0B47}', '\u{0B3E}'], '\u{0B4B}'),
+    (['\u{0B47}', '\u{0B57}'], '\u{0B4C}'),
+    // Tamil
+    (['\u{0B92}', '\u{0BD7}'], '\u{0B94}'), (['\u{0BC6}', '\u{0BBE}'], '\u{0BCA}'),
+    (['\u{0BC7}', '\u{0BBE}'], '\u{0BCB}'), (['\u{0BC6}', '\u{0BD7}'], '\u{0BCC}'),
+    // Telugu
+    (['\u{0C46}', '\u{0C56}'], '\u{0C48}'),
+    // Kannada
+    (['\u{0CBF}', '\u{0CD5}'], '\u{0CC0}'), (['\u{0CC6}', '\u{0CD5}'], '\u{0CC7}'),
+    (['\u{0CC6}', '\u{0CD6}'], '\u{0CC8}'), (['\u{0CC6}', '\u{0CC2}'], '\u{0CCA}'),
+    (['\u{0CCA}', '\u{0CD5}'], '\u{0CCB}'),
+    // Malayalam
+    (['\u{0D46}', '\u{0D3E}'], '\u{0D4A}'), (['\u{0D47}', '\u{0D3E}'], '\u{0D4B}'),
+    (['\u{0D46}', '\u{0D57}'], '\u{0D4C}'),
+];
+
+/// Offset of a character within its 128-codepoint Brahmic block.
+fn block_offset(c: char) -> Option<u32> {
+    let cp = c as u32;
+    (0x0900..0x0D80).contains(&cp).then_some(cp & 0x7F)
+}
+
+fn is_nukta(c: char) -> bool {
+    block_offset(c) == Some(0x3C)
+}
+
+fn is_virama(c: char) -> bool {
+    block_offset(c) == Some(0x4D) || c == '\u{0D3B}' || c == '\u{0D3C}'
+}
+
+/// True for combining signs (matras, virama, nukta, anusvara…) and the
+/// joiners, which belong to the syllable before them.
+pub fn is_mark(c: char) -> bool {
+    if c == ZWJ || c == ZWNJ { return true; }
+    match block_offset(c) {
+        Some(o) => matches!(o, 0x00..=0x03 | 0x3A..=0x4F | 0x51..=0x57 | 0x62..=0x63),
+        None    => false,
+    }
+}
+
+/// True for characters of any Brahmic script block.
+pub fn is_indic(c: char) -> bool {
+    block_offset(c).is_some()
+}
+
+/// NFC-normalise the Indic characters of `text`; everything else passes
+/// through untouched.
+pub fn normalize(text: &str) -> String {
+    let mut chars: Vec<char> = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match DECOMPOSE.iter().find(|(p, _)| *p == c) {
+            Some((_, d)) => chars.extend_from_slice(d),
+            None         => chars.push(c),
+        }
+    }
+
+    // Nukta (ccc 7) sorts before virama (ccc 9)
+    for i in 1..chars.len() {
+        if is_virama(chars[i - 1]) && is_nukta(chars[i]) { chars.swap(i - 1, i); }
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut prev: Option<char> = None;
+    for c in chars {
+        if let Some(p) = prev {
+            if let Some((_, composed)) = COMPOSE.iter().find(|(pair, _)| *pair == [p, c]) {
+                prev = Some(*composed);
+                continue;
+            }
+            out.push(p);
+        }
+        prev = Some(c);
+    }
+    if let Some(p) = prev { out.push(p); }
+    out
+}
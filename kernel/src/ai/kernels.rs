@@ -0,0 +1,372 @@
+//! Inference Kernels
+//! Numeric building blocks of the transformer forward pass.  Quantized
+//! weights are never expanded in bulk: INT4/INT8 rows are multiplied
+//! directly against activations quantized to INT8 per 32-element block,
+//! and the k-quant formats are dequantized one row at a time.  The dot
+//! product has vector paths for RISC-V V and NEON when the target has
+//! them.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::gguf::{GgmlType, GgufFile};
+use crate::memory::{self, SecureVec};
+
+/// Elements per block for the INT4/INT8 formats.
+const QK: usize = 32;
+
+// ─── scalar conversions ───────────────────────────────────────────────────────
+
+pub fn f16_to_f32(h: u16) -> f32 {
+    let sign = (h as u32 & 0x8000) << 16;
+    let exp  = (h >> 10) & 0x1F;
+    let man  = (h & 0x3FF) as u32;
+    let bits = match exp {
+        0 if man == 0 => sign,
+        0 => {
+            // Subnormal: renormalise
+            let shift = man.leading_zeros() - 21;
+            sign | (113 - shift) << 23 | (man << shift & 0x3FF) << 13
+        }
+        0x1F => sign | 0x7F80_0000 | man << 13,
+        _    => sign | (exp as u32 + 112) << 23 | man << 13,
+    };
+    f32::from_bits(bits)
+}
+
+/// Nearest half-precision value, ties to even.
+pub fn f32_to_f16(f: f32) -> u16 {
+    let bits = f.to_bits();
+    let sign = (bits >> 16 & 0x8000) as u16;
+    let exp  = (bits >> 23 & 0xFF) as i32;
+    let man  = bits & 0x7F_FFFF;
+    if exp == 0xFF { return sign | 0x7C00 | if man != 0 { 0x200 } else { 0 }; }
+    let e = exp - 112;
+    if e >= 0x1F { return sign | 0x7C00; }
+    // Keep the bits that fit and round on the ones shifted out
+    let round = |v: u32, rest: u32, half: u32| v + (rest > half || rest == half && v & 1 == 1) as u32;
+    if e <= 0 {
+        // Subnormal (or zero) in half precision
+        if e < -10 { return sign; }
+        let m = man | 0x80_0000;
+        let shift = (14 - e) as u32;
+        return sign | round(m >> shift, m & ((1 << shift) - 1), 1 << (shift - 1)) as u16;
+    }
+    // A carry out of the mantissa correctly bumps the exponent
+    sign | round((e as u32) << 10 | man >> 13, man & 0x1FFF, 0x1000) as u16
+}
+
+fn f16_at(b: &[u8], i: usize) -> f32 {
+    f16_to_f32(u16::from_le_bytes([b[i], b[i + 1]]))
+}
+
+// ─── vector primitives ────────────────────────────────────────────────────────
+
+#[cfg(all(target_arch = "riscv64", target_feature = "v"))]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let n = a.len().min(b.len());
+    let sum: f32;
+    unsafe {
+        core::arch::asm!(
+            "vsetvli {vl}, zero, e32, m8, ta, ma",
+            "vmv.v.i v0, 0",
+            "2:",
+            "vsetvli {vl}, {n}, e32, m8, tu, ma",
+            "vle32.v v8, ({a})",
+            "vle32.v v16, ({b})",
+            "vfmacc.vv v0, v8, v16",
+            "sub {n}, {n}, {vl}",
+            "slli {vl}, {vl}, 2",
+            "add {a}, {a}, {vl}",
+            "add {b}, {b}, {vl}",
+            "bnez {n}, 2b",
+            "vsetvli {vl}, zero, e32, m8, ta, ma",
+            "vmv.s.x v24, zero",
+            "vfredusum.vs v24, v0, v24",
+            "vfmv.f.s {sum}, v24",
+            n   = inout(reg) n => _,
+            a   = inout(reg) a.as_ptr() => _,
+            b   = inout(reg) b.as_ptr() => _,
+            vl  = out(reg) _,
+            sum = out(freg) sum,
+            out("v0") _, out("v1") _, out("v2") _, out("v3") _,
+            out("v4") _, out("v5") _, out("v6") _, out("v7") _,
+            out("v8") _, out("v9") _, out("v10") _, out("v11") _,
+            out("v12") _, out("v13") _, out("v14") _, out("v15") _,
+            out("v16") _, out("v17") _, out("v18") _, out("v19") _,
+            out("v20") _, out("v21") _, out("v22") _, out("v23") _,
+            out("v24") _,
+            options(nostack, readonly),
+        );
+    }
+    sum
+}
+
+#[cfg(all(target_arch = "aarch64", target_feature = "neon"))]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    use core::arch::aarch64::*;
+    let n = a.len().min(b.len());
+    let body = n - n % 4;
+    let mut sum = unsafe {
+        let mut acc = vdupq_n_f32(0.0);
+        for i in (0..body).step_by(4) {
+            acc = vfmaq_f32(acc, vld1q_f32(a.as_ptr().add(i)), vld1q_f32(b.as_ptr().add(i)));
+        }
+        vaddvq_f32(acc)
+    };
+    for i in body..n { sum += a[i] * b[i]; }
+    sum
+}
+
+#[cfg(not(any(all(target_arch = "riscv64", target_feature = "v"),
+              all(target_arch = "aarch64", target_feature = "neon"))))]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    // Four partial sums give the compiler room to pipeline the FMAs
+    let n = a.len().min(b.len());
+    let (ca, ta) = a[..n].as_chunks::<4>();
+    let (cb, tb) = b[..n].as_chunks::<4>();
+    let mut s = [0.0f32; 4];
+    for (x, y) in ca.iter().zip(cb) {
+        for k in 0..4 { s[k] += x[k] * y[k]; }
+    }
+    let tail: f32 = ta.iter().zip(tb).map(|(x, y)| x * y).sum();
+    s[0] + s[1] + s[2] + s[3] + tail
+}
+
+pub fn rmsnorm(out: &mut [f32], x: &[f32], weight: &[f32], eps: f32) {
+    let ms = x.iter().map(|v| v * v).sum::<f32>() / x.len() as f32;
+    let scale = 1.0 / libm::sqrtf(ms + eps);
+    for ((o, &v), &w) in out.iter_mut().zip(x).zip(weight) {
+        *o = v * scale * w;
+    }
+}
+
+pub fn softmax(x: &mut [f32]) {
+    let max = x.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let mut sum = 0.0;
+    for v in x.iter_mut() {
+        *v = libm::expf(*v - max);
+        sum += *v;
+    }
+    for v in x.iter_mut() { *v /= sum; }
+}
+
+pub fn silu(x: f32) -> f32 {
+    x / (1.0 + libm::expf(-x))
+}
+
+/// Rotary position embedding over every head of `x`.  `neox` selects the
+/// rotate-half pairing (i, i + d/2) instead of adjacent pairs (2i, 2i+1).
+/// Only the first `rot_dim` dimensions of each head are rotated.
+pub fn rope(x: &mut [f32], head_dim: usize, rot_dim: usize, pos: usize, base: f32, neox: bool) {
+    for head in x.chunks_exact_mut(head_dim) {
+        for i in 0..rot_dim / 2 {
+            let theta = pos as f32 * libm::powf(base, -2.0 * i as f32 / rot_dim as f32);
+            let (sin, cos) = libm::sincosf(theta);
+            let (a, b) = if neox { (i, i + rot_dim / 2) } else { (2 * i, 2 * i + 1) };
+            let (x0, x1) = (head[a], head[b]);
+            head[a] = x0 * cos - x1 * sin;
+            head[b] = x0 * sin + x1 * cos;
+        }
+    }
+}
+
+// ─── quantized weights ────────────────────────────────────────────────────────
+
+/// Activations quantized to INT8 in 32-element blocks, with each block's
+/// scale and the sum of its quantized values (needed by Q4_1's offset).
+pub struct QuantizedActs {
+    qs:     SecureVec<i8>,
+    scales: Vec<f32>,
+    sums:   Vec<i32>,
+}
+
+impl QuantizedActs {
+    pub fn quantize(x: &[f32]) -> Self {
+        let nb = x.len() / QK;
+        let mut q = QuantizedActs { qs: memory::secure_vec(0, nb * QK), scales: vec![0.0; nb], sums: vec![0; nb] };
+        for (b, block) in x.as_chunks::<QK>().0.iter().enumerate() {
+            let amax = block.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+            let d = amax / 127.0;
+            let inv = if d == 0.0 { 0.0 } else { 1.0 / d };
+            let mut sum = 0;
+            for (i, &v) in block.iter().enumerate() {
+                let qv = libm::roundf(v * inv) as i8;
+                q.qs[b * QK + i] = qv;
+                sum += qv as i32;
+            }
+            q.scales[b] = d;
+            q.sums[b]   = sum;
+        }
+        q
+    }
+}
+
+fn dot_i8(w: impl Iterator<Item = i32>, x: &[i8]) -> i32 {
+    w.zip(x).map(|(a, &b)| a * b as i32).sum()
+}
+
+/// Row · x for INT4/INT8 weights, in integer arithmetic per block.
+fn dot_quantized(ty: GgmlType, row: &[u8], x: &QuantizedActs) -> f32 {
+    let (_, bb) = ty.block();
+    let mut sum = 0.0;
+    for (b, blk) in row.chunks_exact(bb).enumerate() {
+        let xq = &x.qs[b * QK..(b + 1) * QK];
+        let dx = x.scales[b];
+        sum += match ty {
+            GgmlType::Q8_0 => {
+                let q = blk[2..].iter().map(|&v| v as i8 as i32);
+                f16_at(blk, 0) * dx * dot_i8(q, xq) as f32
+            }
+            GgmlType::Q4_0 => {
+                let q = &blk[2..18];
+                let lo = q.iter().map(|&v| (v & 0xF) as i32 - 8);
+                let hi = q.iter().map(|&v| (v >> 4) as i32 - 8);
+                f16_at(blk, 0) * dx * (dot_i8(lo, &xq[..16]) + dot_i8(hi, &xq[16..])) as f32
+            }
+            GgmlType::Q4_1 => {
+                let q = &blk[4..20];
+                let lo = q.iter().map(|&v| (v & 0xF) as i32);
+                let hi = q.iter().map(|&v| (v >> 4) as i32);
+                let isum = dot_i8(lo, &xq[..16]) + dot_i8(hi, &xq[16..]);
+                f16_at(blk, 0) * dx * isum as f32 + f16_at(blk, 2) * dx * x.sums[b] as f32
+            }
+            _ => unreachable!(),
+        };
+    }
+    sum
+}
+
+/// Q4_K packs six-bit scale/min pairs for its eight sub-blocks.
+fn k4_scale_min(j: usize, q: &[u8]) -> (f32, f32) {
+    if j < 4 {
+        ((q[j] & 63) as f32, (q[j + 4] & 63) as f32)
+    } else {
+        (((q[j + 4] & 0xF) | (q[j - 4] >> 6) << 4) as f32,
+         ((q[j + 4] >> 4) | (q[j] >> 6) << 4) as f32)
+    }
+}
+
+/// Quantize `x` to Q8_0 blocks, appended to `out`.  `x.len()` must be a
+/// multiple of the block size.
+pub fn quantize_row_q8_0(x: &[f32], out: &mut Vec<u8>) {
+    for block in x.as_chunks::<QK>().0 {
+        let amax = block.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+        let d = amax / 127.0;
+        let inv = if d == 0.0 { 0.0 } else { 1.0 / d };
+        out.extend_from_slice(&f32_to_f16(d).to_le_bytes());
+        out.extend(block.iter().map(|&v| libm::roundf(v * inv) as i8 as u8));
+    }
+}
+
+/// Expand one row of any supported type into f32.
+pub fn dequantize_row(ty: GgmlType, row: &[u8], out: &mut [f32]) {
+    let (be, bb) = ty.block();
+    for (blk, y) in row.chunks_exact(bb).zip(out.chunks_exact_mut(be)) {
+        match ty {
+            GgmlType::F32  => y[0] = f32::from_le_bytes([blk[0], blk[1], blk[2], blk[3]]),
+            GgmlType::F16  => y[0] = f16_at(blk, 0),
+            GgmlType::BF16 => y[0] = f32::from_bits((u16::from_le_bytes([blk[0], blk[1]]) as u32) << 16),
+            GgmlType::I8   => y[0] = blk[0] as i8 as f32,
+            GgmlType::Q8_0 => {
+                let d = f16_at(blk, 0);
+                for i in 0..QK { y[i] = d * blk[2 + i] as i8 as f32; }
+            }
+            GgmlType::Q4_0 => {
+                let d = f16_at(blk, 0);
+                for i in 0..16 {
+                    y[i]      = d * ((blk[2 + i] & 0xF) as i32 - 8) as f32;
+                    y[i + 16] = d * ((blk[2 + i] >> 4) as i32 - 8) as f32;
+                }
+            }
+            GgmlType::Q4_1 => {
+                let (d, m) = (f16_at(blk, 0), f16_at(blk, 2));
+                for i in 0..16 {
+                    y[i]      = d * (blk[4 + i] & 0xF) as f32 + m;
+                    y[i + 16] = d * (blk[4 + i] >> 4) as f32 + m;
+                }
+            }
+            GgmlType::Q4K => {
+                let (d, dmin) = (f16_at(blk, 0), f16_at(blk, 2));
+                let scales = &blk[4..16];
+                let qs     = &blk[16..144];
+                for (k, q) in qs.as_chunks::<32>().0.iter().enumerate() {
+                    let (s1, m1) = k4_scale_min(2 * k, scales);
+                    let (s2, m2) = k4_scale_min(2 * k + 1, scales);
+                    let y = &mut y[64 * k..64 * (k + 1)];
+                    for l in 0..32 {
+                        y[l]      = d * s1 * (q[l] & 0xF) as f32 - dmin * m1;
+                        y[l + 32] = d * s2 * (q[l] >> 4) as f32 - dmin * m2;
+                    }
+                }
+            }
+            GgmlType::Q6K => {
+                let d = f16_at(blk, 208);
+                for half in 0..2 {
+                    let ql = &blk[64 * half..64 * half + 64];
+                    let qh = &blk[128 + 32 * half..128 + 32 * half + 32];
+                    let sc = &blk[192 + 8 * half..192 + 8 * half + 8];
+                    let y  = &mut y[128 * half..128 * (half + 1)];
+                    for l in 0..32 {
+                        let is = l / 16;
+                        let q1 = ((ql[l] & 0xF)      | (qh[l] & 3) << 4)      as i32 - 32;
+                        let q2 = ((ql[l + 32] & 0xF) | (qh[l] >> 2 & 3) << 4) as i32 - 32;
+                        let q3 = ((ql[l] >> 4)       | (qh[l] >> 4 & 3) << 4) as i32 - 32;
+                        let q4 = ((ql[l + 32] >> 4)  | (qh[l] >> 6 & 3) << 4) as i32 - 32;
+                        y[l]      = d * sc[is] as i8 as f32 * q1 as f32;
+                        y[l + 32] = d * sc[is + 2] as i8 as f32 * q2 as f32;
+                        y[l + 64] = d * sc[is + 4] as i8 as f32 * q3 as f32;
+                        y[l + 96] = d * sc[is + 6] as i8 as f32 * q4 as f32;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A weight matrix of `rows` rows by `cols` columns, borrowed from the
+/// model mapping.
+#[derive(Clone, Copy)]
+pub struct Matrix<'a> {
+    pub ty:   GgmlType,
+    pub data: &'a [u8],
+    pub rows: usize,
+    pub cols: usize,
+}
+
+impl<'a> Matrix<'a> {
+    /// Tensor `i` of `g`, viewed as a matrix of dims[1] rows.
+    pub fn from_tensor(g: &'a GgufFile, i: usize) -> Self {
+        let t = &g.tensors[i];
+        Matrix { ty: t.ty, data: g.tensor_data(t), cols: t.dims[0] as usize, rows: t.dims[1] as usize }
+    }
+
+    pub fn row_bytes(&self) -> usize {
+        let (be, bb) = self.ty.block();
+        self.cols / be * bb
+    }
+
+    pub fn row(&self, r: usize, out: &mut [f32]) {
+        let rb = self.row_bytes();
+        dequantize_row(self.ty, &self.data[r * rb..(r + 1) * rb], out);
+    }
+}
+
+/// out = W · x
+pub fn matmul(out: &mut [f32], w: &Matrix, x: &[f32]) {
+    let rb = w.row_bytes();
+    let rows = w.data.chunks_exact(rb).take(w.rows);
+    match w.ty {
+        GgmlType::Q8_0 | GgmlType::Q4_0 | GgmlType::Q4_1 => {
+            let xq = QuantizedActs::quantize(x);
+            for (o, row) in out.iter_mut().zip(rows) { *o = dot_quantized(w.ty, row, &xq); }
+        }
+        _ => {
+            let mut tmp = vec![0.0; w.cols];
+            for (o, row) in out.iter_mut().zip(rows) {
+                dequantize_row(w.ty, row, &mut tmp);
+                *o = dot(&tmp, x);
+            }
+        }
+    }
+}
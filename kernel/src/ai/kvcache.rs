@@ -0,0 +1,323 @@
+//! KV Cache
+//! Attention keys and values, stored in fixed-size pages of positions.
+//! Memory is reserved page by page against a Memory capability: the
+//! capability's length is the budget, shared by every cache charged to
+//! it.  When the next page would exceed the budget, the oldest page of
+//! context is evicted instead — except the first, which holds the start
+//! of the prompt that attention leans on most.  Entries can be kept as
+//! f32 or quantized to INT8 per head, which cuts memory by almost 4x.
+//! Pages are zero-on-free, so no context outlives its cache.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{kernels, metrics};
+use super::transformer::Config;
+use crate::capability::{self, CapId, Capability, CapabilityType, Permissions};
+use crate::memory::{self, SecureVec};
+use crate::process::ProcessId;
+
+/// Positions per page.
+pub const PAGE_TOKENS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KvPrecision {
+    #[default]
+    F32,
+    /// INT8 with one f32 scale per head per position.
+    Q8,
+}
+
+/// Permission to spend memory on KV pages: a Memory capability and the
+/// process presenting it.
+#[derive(Debug, Clone)]
+pub struct MemoryGrant {
+    pub owner: ProcessId,
+    pub cap:   Capability,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KvUsage {
+    pub precision:      KvPrecision,
+    pub bytes_reserved: usize,
+    pub pages:          usize,
+    pub live_tokens:    usize,
+    pub evicted_tokens: usize,
+}
+
+// ─── budget ledger ────────────────────────────────────────────────────────────
+
+struct Account {
+    cap:   CapId,
+    limit: usize,
+    used:  usize,
+}
+
+static LEDGER: Mutex<Vec<Account>> = Mutex::new(Vec::new());
+
+fn charge(cap: CapId, limit: usize, bytes: usize) -> bool {
+    let mut ledger = LEDGER.lock();
+    let i = match ledger.iter().position(|a| a.cap == cap) {
+        Some(i) => i,
+        None    => { ledger.push(Account { cap, limit, used: 0 }); ledger.len() - 1 }
+    };
+    let a = &mut ledger[i];
+    if a.used + bytes > a.limit { return false; }
+    a.used += bytes;
+    true
+}
+
+fn release(cap: CapId, bytes: usize) {
+    let mut ledger = LEDGER.lock();
+    if let Some(i) = ledger.iter().position(|a| a.cap == cap) {
+        ledger[i].used -= bytes;
+        if ledger[i].used == 0 { ledger.swap_remove(i); }
+    }
+}
+
+/// Bytes in use under `cap`, across all caches.
+pub fn budget_used(cap: CapId) -> usize {
+    LEDGER.lock().iter().find(|a| a.cap == cap).map_or(0, |a| a.used)
+}
+
+// ─── pages ────────────────────────────────────────────────────────────────────
+
+enum PageData {
+    F32(SecureVec<f32>),
+    Q8 { qs: SecureVec<i8>, scales: SecureVec<f32> },
+}
+
+struct Page {
+    k:    PageData,
+    v:    PageData,
+    used: usize,
+}
+
+struct Layout {
+    n_layer:   usize,
+    n_head_kv: usize,
+    head_dim:  usize,
+}
+
+impl Layout {
+    fn kv_dim(&self) -> usize {
+        self.n_head_kv * self.head_dim
+    }
+
+    fn new_data(&self, precision: KvPrecision) -> PageData {
+        let n = self.n_layer * PAGE_TOKENS * self.kv_dim();
+        match precision {
+            KvPrecision::F32 => PageData::F32(memory::secure_vec(0.0, n)),
+            KvPrecision::Q8  => PageData::Q8 {
+                qs:     memory::secure_vec(0, n),
+                scales: memory::secure_vec(0.0, self.n_layer * PAGE_TOKENS * self.n_head_kv),
+            },
+        }
+    }
+
+    fn page_bytes(&self, precision: KvPrecision) -> usize {
+        let n = self.n_layer * PAGE_TOKENS * self.kv_dim();
+        2 * match precision {
+            KvPrecision::F32 => n * 4,
+            KvPrecision::Q8  => n + self.n_layer * PAGE_TOKENS * self.n_head_kv * 4,
+        }
+    }
+
+    /// Index of (layer, slot, head) in units of heads.
+    fn head_index(&self, layer: usize, slot: usize, head: usize) -> usize {
+        (layer * PAGE_TOKENS + slot) * self.n_head_kv + head
+    }
+}
+
+impl PageData {
+    fn write(&mut self, ly: &Layout, layer: usize, slot: usize, x: &[f32]) {
+        for (h, src) in x.chunks_exact(ly.head_dim).enumerate() {
+            let hi = ly.head_index(layer, slot, h);
+            let range = hi * ly.head_dim..(hi + 1) * ly.head_dim;
+            match self {
+                PageData::F32(d) => d[range].copy_from_slice(src),
+                PageData::Q8 { qs, scales } => {
+                    let amax = src.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+                    let d = amax / 127.0;
+                    let inv = if d == 0.0 { 0.0 } else { 1.0 / d };
+                    for (q, &v) in qs[range].iter_mut().zip(src) { *q = libm::roundf(v * inv) as i8; }
+                    scales[hi] = d;
+                }
+            }
+        }
+    }
+
+    fn read(&self, ly: &Layout, layer: usize, slot: usize, head: usize, out: &mut [f32]) {
+        let hi = ly.head_index(layer, slot, head);
+        let range = hi * ly.head_dim..(hi + 1) * ly.head_dim;
+        match self {
+            PageData::F32(d) => out.copy_from_slice(&d[range]),
+            PageData::Q8 { qs, scales } => {
+                for (o, &q) in out.iter_mut().zip(&qs[range]) { *o = q as f32 * scales[hi]; }
+            }
+        }
+    }
+}
+
+// ─── cache ────────────────────────────────────────────────────────────────────
+
+pub struct KvCache {
+    layout:    Layout,
+    precision: KvPrecision,
+    pages:     VecDeque<Page>,
+    /// Absolute position of the next token (RoPE position).
+    next_pos:  usize,
+    n_ctx:     usize,
+    evicted:   usize,
+    budget:    Option<(CapId, usize)>,
+    reserved:  usize,
+}
+
+impl KvCache {
+    /// A cache for `cfg`, charged to `grant` if given.  Kernel-internal
+    /// callers may pass `None`; the context length is then the only bound.
+    pub fn new(cfg: &Config, precision: KvPrecision, grant: Option<&MemoryGrant>) -> Result<Self, &'static str> {
+        let budget = match grant {
+            None    => None,
+            Some(g) => {
+                let CapabilityType::Memory { len, .. } = g.cap.cap_type else {
+                    return Err("KV cache needs a Memory capability");
+                };
+                capability::validate(g.owner, &g.cap, g.cap.cap_type, Permissions::WRITE)?;
+                Some((g.cap.id, len))
+            }
+        };
+        Ok(KvCache {
+            layout:   Layout { n_layer: cfg.n_layer, n_head_kv: cfg.n_head_kv, head_dim: cfg.head_dim },
+            pages:    VecDeque::new(),
+            next_pos: 0,
+            n_ctx:    cfg.n_ctx,
+            evicted:  0,
+            reserved: 0,
+            precision, budget,
+        })
+    }
+
+    /// Positions processed so far, including evicted ones.
+    pub fn positions(&self) -> usize {
+        self.next_pos
+    }
+
+    pub fn live_tokens(&self) -> usize {
+        self.pages.iter().map(|p| p.used).sum()
+    }
+
+    /// True once the model's context length has been used up.
+    pub fn is_full(&self) -> bool {
+        self.next_pos >= self.n_ctx
+    }
+
+    pub fn usage(&self) -> KvUsage {
+        KvUsage {
+            precision:      self.precision,
+            bytes_reserved: self.reserved,
+            pages:          self.pages.len(),
+            live_tokens:    self.live_tokens(),
+            evicted_tokens: self.evicted,
+        }
+    }
+
+    fn new_page(&mut self) -> Result<(), &'static str> {
+        let bytes = self.layout.page_bytes(self.precision);
+        if let Some((cap, limit)) = self.budget {
+            if !charge(cap, limit, bytes) {
+                // Over budget: keep the first page, recycle the oldest after it
+                if self.pages.len() < 2 { return Err("KV cache budget too small"); }
+                let mut page = self.pages.remove(1).unwrap();
+                self.evicted += page.used;
+                page.used = 0;
+                self.pages.push_back(page);
+                return Ok(());
+            }
+        }
+        self.reserved += bytes;
+        metrics::record_kv(bytes as isize);
+        let page = Page { k: self.layout.new_data(self.precision), v: self.layout.new_data(self.precision), used: 0 };
+        self.pages.push_back(page);
+        Ok(())
+    }
+
+    /// Forget every position so the cache can be reused for another
+    /// sequence.  One page is kept; the rest go back to the budget.
+    pub fn clear(&mut self) {
+        let bytes = self.layout.page_bytes(self.precision);
+        while self.pages.len() > 1 {
+            self.pages.pop_back();
+            self.reserved -= bytes;
+            metrics::record_kv(-(bytes as isize));
+            if let Some((cap, _)) = self.budget { release(cap, bytes); }
+        }
+        if let Some(page) = self.pages.front_mut() { page.used = 0; }
+        self.next_pos = 0;
+        self.evicted  = 0;
+    }
+
+    /// Open a slot for the next token and return its position.
+    pub fn begin_position(&mut self) -> Result<usize, &'static str> {
+        if self.is_full() { return Err("context window full"); }
+        if self.pages.back().is_none_or(|p| p.used == PAGE_TOKENS) { self.new_page()?; }
+        self.pages.back_mut().unwrap().used += 1;
+        self.next_pos += 1;
+        Ok(self.next_pos - 1)
+    }
+
+    /// Store this position's keys and values for `layer`.
+    pub fn store(&mut self, layer: usize, k: &[f32], v: &[f32]) {
+        let page = self.pages.back_mut().expect("store before begin_position");
+        let slot = page.used - 1;
+        page.k.write(&self.layout, layer, slot, k);
+        page.v.write(&self.layout, layer, slot, v);
+    }
+
+    /// Keys and values of `layer` at every live position, oldest first,
+    /// one row of all KV heads per position.
+    pub fn gather(&self, layer: usize, keys: &mut [f32], values: &mut [f32]) {
+        let ly = &self.layout;
+        let kv_dim = ly.n_head_kv * ly.head_dim;
+        let slots = self.pages.iter().flat_map(|p| (0..p.used).map(move |slot| (p, slot)));
+        for (((page, slot), k), v) in slots.zip(keys.chunks_exact_mut(kv_dim)).zip(values.chunks_exact_mut(kv_dim)) {
+            for (h, (kh, vh)) in k.chunks_exact_mut(ly.head_dim).zip(v.chunks_exact_mut(ly.head_dim)).enumerate() {
+                page.k.read(ly, layer, slot, h, kh);
+                page.v.read(ly, layer, slot, h, vh);
+            }
+        }
+    }
+
+    /// Attention of one query head over every live position, using KV head
+    /// `kv_head`; writes the weighted sum of values to `out`.
+    pub fn attend(&self, layer: usize, kv_head: usize, q: &[f32], scale: f32, out: &mut [f32]) {
+        let ly = &self.layout;
+        let mut tmp = memory::secure_vec(0.0, ly.head_dim);
+        let mut scores = Vec::with_capacity(self.live_tokens());
+        for page in &self.pages {
+            for slot in 0..page.used {
+                page.k.read(ly, layer, slot, kv_head, &mut tmp);
+                scores.push(kernels::dot(q, &tmp) * scale);
+            }
+        }
+        kernels::softmax(&mut scores);
+
+        out.fill(0.0);
+        let mut s = scores.iter();
+        for page in &self.pages {
+            for slot in 0..page.used {
+                page.v.read(ly, layer, slot, kv_head, &mut tmp);
+                let w = *s.next().unwrap();
+                for (o, &v) in out.iter_mut().zip(&tmp) { *o += w * v; }
+            }
+        }
+    }
+}
+
+impl Drop for KvCache {
+    fn drop(&mut self) {
+        metrics::record_kv(-(self.reserved as isize));
+        if let Some((cap, _)) = self.budget { release(cap, self.reserved); }
+    }
+}
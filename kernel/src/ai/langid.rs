@@ -0,0 +1,281 @@
+//! Language Identification
+//! Works out which of the 22 scheduled languages of India (or English) a
+//! prompt is written in, so it can be routed to a model that knows the
+//! language and normalised the way that language's text expects.  The
+//! script narrows the choice first — most scripts belong to one language
+//! — and languages sharing a script (Devanagari, Bengali-Assamese,
+//! Perso-Arabic) are told apart by character n-grams: letters only one of
+//! them uses, frequent function words, and common suffixes.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::indic;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    Latin,
+    Devanagari,
+    Bengali,
+    Gurmukhi,
+    Gujarati,
+    Odia,
+    Tamil,
+    Telugu,
+    Kannada,
+    Malayalam,
+    Arabic,
+    OlChiki,
+    MeeteiMayek,
+}
+
+fn script_of(c: char) -> Option<Script> {
+    Some(match c as u32 {
+        0x41..=0x5A | 0x61..=0x7A | 0xC0..=0x24F => Script::Latin,
+        0x0900..=0x097F | 0xA8E0..=0xA8FF       => Script::Devanagari,
+        0x0980..=0x09FF                         => Script::Bengali,
+        0x0A00..=0x0A7F                         => Script::Gurmukhi,
+        0x0A80..=0x0AFF                         => Script::Gujarati,
+        0x0B00..=0x0B7F                         => Script::Odia,
+        0x0B80..=0x0BFF                         => Script::Tamil,
+        0x0C00..=0x0C7F                         => Script::Telugu,
+        0x0C80..=0x0CFF                         => Script::Kannada,
+        0x0D00..=0x0D7F                         => Script::Malayalam,
+        0x0600..=0x06FF | 0x0750..=0x077F | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => Script::Arabic,
+        0x1C50..=0x1C7F                         => Script::OlChiki,
+        0xAAE0..=0xAAFF | 0xABC0..=0xABFF       => Script::MeeteiMayek,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Language {
+    Assamese,
+    Bengali,
+    Bodo,
+    Dogri,
+    Gujarati,
+    Hindi,
+    Kannada,
+    Kashmiri,
+    Konkani,
+    Maithili,
+    Malayalam,
+    Manipuri,
+    Marathi,
+    Nepali,
+    Odia,
+    Punjabi,
+    Sanskrit,
+    Santali,
+    Sindhi,
+    Tamil,
+    Telugu,
+    Urdu,
+    English,
+}
+
+use Language::*;
+
+/// The languages of the Eighth Schedule.
+pub const SCHEDULED: [Language; 22] = [
+    Assamese, Bengali, Bodo, Dogri, Gujarati, Hindi, Kannada, Kashmiri, Konkani, Maithili, Malayalam,
+    Manipuri, Marathi, Nepali, Odia, Punjabi, Sanskrit, Santali, Sindhi, Tamil, Telugu, Urdu,
+];
+
+impl Language {
+    /// ISO 639 code.
+    pub fn code(self) -> &'static str {
+        match self {
+            Assamese => "as",  Bengali  => "bn",  Bodo     => "brx", Dogri    => "doi",
+            Gujarati => "gu",  Hindi    => "hi",  Kannada  => "kn",  Kashmiri => "ks",
+            Konkani  => "kok", Maithili => "mai", Malayalam => "ml", Manipuri => "mni",
+            Marathi  => "mr",  Nepali   => "ne",  Odia     => "or",  Punjabi  => "pa",
+            Sanskrit => "sa",  Santali  => "sat", Sindhi   => "sd",  Tamil    => "ta",
+            Telugu   => "te",  Urdu     => "ur",  English  => "en",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Language> {
+        SCHEDULED.iter().chain(&[English]).copied().find(|l| l.code() == code)
+    }
+
+    /// Index used on the wire (SCHEDULED order, English last).
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Detection {
+    pub language:   Language,
+    pub script:     Script,
+    /// 0–100: how clearly the text favoured this language over the others
+    /// sharing its script.
+    pub confidence: u8,
+}
+
+// ─── profiles ─────────────────────────────────────────────────────────────────
+
+struct Profile {
+    lang:    Language,
+    /// Letters this language uses and its script-mates don't.
+    markers: &'static [char],
+    /// Frequent whole words.
+    words:   &'static [&'static str],
+    /// Character n-grams; a leading or trailing space anchors to a word
+    /// boundary.
+    grams:   &'static [&'static str],
+}
+
+const MARKER_WEIGHT: u32 = 5;
+const WORD_WEIGHT:   u32 = 3;
+const GRAM_WEIGHT:   u32 = 1;
+
+const DEVANAGARI: &[Profile] = &[
+    Profile { lang: Hindi, markers: &[],
+        words: &["है", "हैं", "के", "में", "की", "का", "और", "को", "से", "नहीं", "यह", "था", "थी", "कि", "पर", "भी", "लिए", "क्या", "आप", "मैं"],
+        grams: &["ता है", "ते हैं", "ने ", "ियों"] },
+    Profile { lang: Marathi, markers: &[],
+        words: &["आहे", "आहेत", "आणि", "नाही", "हे", "या", "मी", "तुम्ही", "काय", "होते", "होता", "आम्ही", "पण", "म्हणून"],
+        grams: &["ळ", "च्या ", "ला ", "चे ", "ची ", "णार", "ले "] },
+    Profile { lang: Nepali, markers: &[],
+        words: &["छ", "छन्", "हो", "पनि", "यो", "त्यो", "गर्न", "हुन्छ", "थियो", "भएको", "गरेको", "मा", "लाई", "छु", "तपाईं"],
+        grams: &["ेको ", "लाई", "हरू", "नु ", "छ "] },
+    Profile { lang: Sanskrit, markers: &[],
+        words: &["अस्ति", "च", "एव", "इति", "तत्", "सः", "अपि", "यत्", "भवति", "अहम्", "त्वम्", "किम्", "वा", "सन्ति"],
+        grams: &["ः", "म् ", "स्य ", "ानि ", "ेन ", "ाय "] },
+    Profile { lang: Konkani, markers: &[],
+        words: &["आसा", "आसात", "हांव", "आनी", "कितें", "तूं", "म्हजें", "हें", "जाल्यार", "आमी", "तुमी", "खंय", "कशें"],
+        grams: &["ांव", "ें ", "ाक ", "ांक ", "ळ"] },
+    Profile { lang: Maithili, markers: &[],
+        words: &["अछि", "छथि", "छल", "हम", "अहाँ", "ओ", "सँ", "केँ", "मे", "आ", "नहि", "छी", "एहि", "ओहि"],
+        grams: &["ँ ", "थि ", "ैत ", "ल गेल"] },
+    Profile { lang: Dogri, markers: &[],
+        words: &["ऐ", "ऐन", "दा", "दे", "दी", "च", "नेईं", "गी", "तुस", "अस", "कन्नै", "बी", "होआ"],
+        grams: &["ऐ ", "्ना ", "गी ", "आ ऐ"] },
+    Profile { lang: Bodo, markers: &[],
+        words: &["आरो", "नाय", "जों", "दं", "मोन", "बे", "जानो", "नो", "गोनां", "आं", "नों", "हायो", "बिथिं"],
+        grams: &["खौ ", "नि ", "फोर", "गोना", "ाव "] },
+];
+
+const BENGALI: &[Profile] = &[
+    Profile { lang: Bengali, markers: &['র'],
+        words: &["এবং", "না", "আমি", "তুমি", "এই", "করে", "হয়", "ছিল", "থেকে", "জন্য", "আর", "যে", "সে", "আমার", "একটি"],
+        grams: &["ের ", "ছে ", "বে "] },
+    Profile { lang: Assamese, markers: &['ৰ', 'ৱ'],
+        words: &["আৰু", "নহয়", "মই", "তুমি", "এই", "কৰা", "হয়", "আছিল", "পৰা", "বাবে", "যে", "সি", "মোৰ", "এটা", "হৈছে"],
+        grams: &["ৰ ", "ত ", "ছে "] },
+    Profile { lang: Manipuri, markers: &[],
+        words: &["অমসুং", "ঐ", "নহাক", "মহাক", "মসি", "অদু", "লৈ", "নি", "দা", "গী", "ঙাই", "ঐখোই"],
+        grams: &["গী ", "দা ", "খি ", "রে ", "বা "] },
+];
+
+const ARABIC: &[Profile] = &[
+    Profile { lang: Urdu, markers: &['ے', 'ں', 'ٹ', 'ڈ', 'ڑ', 'ہ'],
+        words: &["ہے", "ہیں", "کے", "میں", "کی", "کا", "اور", "کو", "سے", "نہیں", "یہ", "تھا", "کہ", "پر", "بھی"],
+        grams: &["ھ", "ئے "] },
+    Profile { lang: Kashmiri, markers: &['ٲ', 'ٳ', 'ۆ', 'ێ', 'ۄ', 'ؠ', '\u{0655}', 'ژ'],
+        words: &["چھُ", "چھ", "تہٕ", "منٛز", "یِم", "سۭتۍ", "ۄن"],
+        grams: &["ُ ", "ٕ ", "ِ"] },
+    Profile { lang: Sindhi, markers: &['ڪ', 'ٻ', 'ڀ', 'ٽ', 'ٿ', 'ڄ', 'ڃ', 'ڇ', 'ڊ', 'ڌ', 'ڍ', 'ڏ', 'ڙ', 'ڦ', 'ڱ', 'ڳ', 'ڻ'],
+        words: &["آهي", "آهن", "۽", "جو", "جي", "کي", "۾", "ته", "به", "نه", "هن"],
+        grams: &["اهي", "ڻ "] },
+];
+
+fn candidates(script: Script) -> Result<&'static [Profile], Language> {
+    match script {
+        Script::Devanagari  => Ok(DEVANAGARI),
+        Script::Bengali     => Ok(BENGALI),
+        Script::Arabic      => Ok(ARABIC),
+        Script::Latin       => Err(English),
+        Script::Gurmukhi    => Err(Punjabi),
+        Script::Gujarati    => Err(Gujarati),
+        Script::Odia        => Err(Odia),
+        Script::Tamil       => Err(Tamil),
+        Script::Telugu      => Err(Telugu),
+        Script::Kannada     => Err(Kannada),
+        Script::Malayalam   => Err(Malayalam),
+        Script::OlChiki     => Err(Santali),
+        Script::MeeteiMayek => Err(Manipuri),
+    }
+}
+
+// ─── detection ────────────────────────────────────────────────────────────────
+
+/// Only the start of long prompts is looked at.
+const SAMPLE_CHARS: usize = 1024;
+
+fn is_separator(c: char) -> bool {
+    c.is_whitespace()
+        || c.is_ascii_punctuation()
+        || c.is_ascii_digit()
+        || matches!(c, '।' | '॥' | '،' | '؛' | '؟' | '۔')
+        || ('\u{2000}'..='\u{206F}').contains(&c) && !matches!(c, '\u{200C}' | '\u{200D}')
+}
+
+/// Identify the language of `text`.  None if it has no letters in a
+/// supported script.
+pub fn detect(text: &str) -> Option<Detection> {
+    let sample: String = text.chars().take(SAMPLE_CHARS).collect();
+    let sample = indic::normalize(&sample);
+
+    // Dominant script by letter count
+    let mut counts: Vec<(Script, usize)> = Vec::new();
+    for s in sample.chars().filter_map(script_of) {
+        match counts.iter_mut().find(|(k, _)| *k == s) {
+            Some((_, n)) => *n += 1,
+            None         => counts.push((s, 1)),
+        }
+    }
+    let &(script, _) = counts.iter().max_by_key(|(_, n)| *n)?;
+
+    let profiles = match candidates(script) {
+        Ok(p)     => p,
+        Err(lang) => return Some(Detection { language: lang, script, confidence: 100 }),
+    };
+
+    // Words of this script, space-separated and space-padded
+    let mut padded = String::from(" ");
+    for word in sample.split(is_separator).filter(|w| w.chars().any(|c| script_of(c) == Some(script))) {
+        padded.push_str(word);
+        padded.push(' ');
+    }
+    let words: Vec<&str> = padded.split(' ').filter(|w| !w.is_empty()).collect();
+
+    let scores: Vec<u32> = profiles.iter().map(|p| {
+        let markers = padded.chars().filter(|c| p.markers.contains(c)).count() as u32;
+        let hits    = words.iter().filter(|w| p.words.contains(w)).count() as u32;
+        let grams: u32 = p.grams.iter().map(|g| padded.matches(g).count() as u32).sum();
+        markers * MARKER_WEIGHT + hits * WORD_WEIGHT + grams * GRAM_WEIGHT
+    }).collect();
+
+    let (best, &top) = scores.iter().enumerate().max_by_key(|&(i, s)| (*s, core::cmp::Reverse(i)))?;
+    if top == 0 {
+        // Nothing distinctive: assume the script's most common language
+        return Some(Detection { language: profiles[0].lang, script, confidence: 0 });
+    }
+    let second = scores.iter().enumerate().filter(|&(i, _)| i != best).map(|(_, &s)| s).max().unwrap_or(0);
+    let confidence = ((top - second) * 100 / top) as u8;
+    Some(Detection { language: profiles[best].lang, script, confidence })
+}
+
+// ─── normalisation ────────────────────────────────────────────────────────────
+
+/// Language-specific clean-up before tokenising, on top of the Indic NFC
+/// the tokenizer always applies.  Perso-Arabic text typed on an Arabic
+/// keyboard uses Arabic yeh and kaf where Urdu and Kashmiri write Farsi
+/// yeh and keheh, and Sindhi writes its own kaf.
+pub fn normalize_for(text: &str, lang: Language) -> String {
+    let kaf = match lang {
+        Urdu | Kashmiri => 'ک',
+        Sindhi          => 'ڪ',
+        _               => return String::from(text),
+    };
+    text.chars().map(|c| match c {
+        'ي' => 'ی',
+        'ى' => 'ی',
+        'ك' => kaf,
+        _   => c,
+    }).collect()
+}
@@ -0,0 +1,137 @@
+//! LoRA Adapters
+//! Low-rank fine-tunes, such as a pack for one Indian language, applied on
+//! top of a base model, so a fine-tune ships as a few megabytes instead of
+//! a whole model.  An adapter is a GGUF file (`adapter.type` "lora") kept
+//! next to its base model and signed the same way.  For each weight W it
+//! adapts, it holds a pair `<W>.lora_a` (in × rank) and `<W>.lora_b`
+//! (rank × out), and the adapted weight is W + (alpha / rank) · B·A.
+//!
+//! An adapter is used in one of two ways.  It can be merged into the base
+//! model when the model is loaded, which costs nothing per token.  Or it
+//! can be applied at run time for only the sessions that ask for it, which
+//! leaves the base model untouched for everyone else.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::gguf::{self, GgufFile};
+use super::kernels::{self, Matrix};
+use super::langid::Language;
+use super::transformer::Transformer;
+use super::{verify_model, Provenance};
+use crate::memory;
+
+const A_SUFFIX: &str = ".lora_a";
+const B_SUFFIX: &str = ".lora_b";
+
+/// The low-rank update to one base weight.
+pub(super) struct Delta {
+    /// Index of the adapted tensor in the base model.
+    pub(super) tensor: usize,
+    pub(super) rank:   usize,
+    pub(super) cols:   usize,
+    pub(super) rows:   usize,
+    /// A, rank rows of `cols`.
+    a: Vec<f32>,
+    /// B with the scale folded in, `rows` rows of rank.
+    b: Vec<f32>,
+}
+
+impl Delta {
+    /// Row `r` of B·A, added to `row`.
+    pub(super) fn add_row(&self, r: usize, row: &mut [f32]) {
+        let b = &self.b[r * self.rank..(r + 1) * self.rank];
+        for (&bk, a) in b.iter().zip(self.a.chunks_exact(self.cols)) {
+            for (w, av) in row.iter_mut().zip(a) { *w += bk * av; }
+        }
+    }
+}
+
+pub struct LoraAdapter {
+    pub name:       String,
+    pub provenance: Provenance,
+    pub rank:       usize,
+    pub alpha:      f32,
+    /// Languages it was tuned for (`general.languages`).
+    pub languages:  Vec<Language>,
+    /// Sorted by tensor.
+    deltas:         Vec<Delta>,
+}
+
+impl LoraAdapter {
+    /// Load the adapter at `path` for `base`, the weights of the model it
+    /// was trained on.  Its signature is checked before anything is parsed.
+    pub fn load(name: &str, path: &str, base: &GgufFile, t: &Transformer) -> Result<Self, &'static str> {
+        let map        = crate::fs::map_file(path)?;
+        let provenance = verify_model(&map, &format!("{}.sig", path))?;
+        let g          = GgufFile::parse(map)?;
+        if g.get_str("adapter.type") != Some("lora") { return Err("not a LoRA adapter"); }
+        if g.architecture() != base.architecture() { return Err("adapter is for another architecture"); }
+
+        let adaptable = t.weight_tensors();
+        let mut deltas = Vec::new();
+        let mut rank = 0;
+        for (ia, a) in g.tensors.iter().enumerate().filter(|(_, t)| t.name.ends_with(A_SUFFIX)) {
+            let target = &a.name[..a.name.len() - A_SUFFIX.len()];
+            let b_name = format!("{}{}", target, B_SUFFIX);
+            let ib = g.tensors.iter().position(|t| t.name == b_name).ok_or("adapter tensor has no B half")?;
+            let b = &g.tensors[ib];
+            let tensor = base.tensors.iter().position(|t| t.name == target)
+                .filter(|i| adaptable.contains(i)).ok_or("adapter targets a weight the model does not have")?;
+
+            let (cols, rows) = (base.tensors[tensor].dims[0] as usize, base.tensors[tensor].dims[1] as usize);
+            let r = a.dims.get(1).copied().unwrap_or(0) as usize;
+            if a.dims[..] != [cols as u64, r as u64] || b.dims[..] != [r as u64, rows as u64] || r == 0 {
+                return Err("adapter tensor has the wrong shape");
+            }
+            if rank != 0 && r != rank { return Err("adapter mixes ranks"); }
+            rank = r;
+
+            let mut da = vec![0.0; r * cols];
+            let mut db = vec![0.0; rows * r];
+            for (k, row) in da.chunks_exact_mut(cols).enumerate() { Matrix::from_tensor(&g, ia).row(k, row); }
+            for (k, row) in db.chunks_exact_mut(r).enumerate()    { Matrix::from_tensor(&g, ib).row(k, row); }
+            deltas.push(Delta { tensor, rank: r, cols, rows, a: da, b: db });
+        }
+        if deltas.is_empty() { return Err("adapter adapts nothing"); }
+
+        let alpha = g.get("adapter.lora.alpha").and_then(|v| v.as_f32()).unwrap_or(rank as f32);
+        let scale = alpha / rank as f32;
+        for d in &mut deltas {
+            for v in d.b.iter_mut() { *v *= scale; }
+        }
+        deltas.sort_unstable_by_key(|d| d.tensor);
+
+        Ok(LoraAdapter {
+            name: String::from(name),
+            languages: g.get("general.languages").and_then(gguf::Value::as_array).unwrap_or(&[])
+                .iter().filter_map(|v| v.as_str().and_then(Language::from_code)).collect(),
+            provenance, rank, alpha, deltas,
+        })
+    }
+
+    pub(super) fn deltas(&self) -> &[Delta] {
+        &self.deltas
+    }
+
+    /// Weights the adapter changes.
+    pub fn tensor_count(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Bytes the adapter holds in memory.
+    pub fn size(&self) -> usize {
+        self.deltas.iter().map(|d| (d.a.len() + d.b.len()) * 4).sum()
+    }
+
+    /// out += scale · B·A·x, if the adapter changes weight `w`.
+    pub(super) fn apply(&self, w: usize, x: &[f32], out: &mut [f32]) {
+        let Ok(i) = self.deltas.binary_search_by_key(&w, |d| d.tensor) else { return };
+        let d = &self.deltas[i];
+        let mut ax = memory::secure_vec(0.0, d.rank);
+        for (v, row) in ax.iter_mut().zip(d.a.chunks_exact(d.cols)) { *v = kernels::dot(row, x); }
+        for (o, b) in out.iter_mut().zip(d.b.chunks_exact(d.rank)) { *o += kernels::dot(b, &ax); }
+    }
+}
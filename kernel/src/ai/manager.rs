@@ -0,0 +1,289 @@
+//! Model Manager
+//! Keeps the catalogue of models on the device and loads them into the AI
+//! service on demand.  A catalogued model keeps its service model id while
+//! it is unloaded, so AI capabilities granted for it stay valid, and the
+//! next request for it loads it again.  Under memory pressure, models
+//! nobody is using are unloaded: those idle for a while at moderate
+//! pressure, any of them at critical pressure.  Switching the preferred
+//! model — the one any-model clients get — loads the new one before
+//! letting the old one go.  A model's LoRA adapters are catalogued with
+//! it and applied each time it loads, merged or for sessions to choose.
+//! The catalogue and preference persist in CONFIG_PATH.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::service;
+use super::AiModel;
+use crate::memory::{self, Pressure};
+use crate::{arch, fs};
+
+pub const CONFIG_PATH: &str = "/etc/ai/models.conf";
+
+/// At moderate pressure, only models idle this long are unloaded.
+const IDLE_UNLOAD_MS: u64 = 60_000;
+
+struct Adapter {
+    name:  String,
+    path:  String,
+    /// Merged into the weights rather than chosen per session.
+    merge: bool,
+}
+
+struct Entry {
+    name:      String,
+    path:      String,
+    /// Service model id, fixed while the model is catalogued.
+    id:        u32,
+    last_used: u64,
+    adapters:  Vec<Adapter>,
+}
+
+struct Manager {
+    entries:   Vec<Entry>,
+    preferred: Option<u32>,
+}
+
+static MANAGER: Mutex<Manager> = Mutex::new(Manager { entries: Vec::new(), preferred: None });
+
+#[derive(Debug, Clone)]
+pub struct ModelInfo {
+    pub id:        u32,
+    pub name:      String,
+    pub path:      String,
+    pub loaded:    bool,
+    pub preferred: bool,
+    pub idle_ms:   u64,
+    /// (name, merged) of each adapter.
+    pub adapters:  Vec<(String, bool)>,
+}
+
+/// Register the shrinker and restore the saved catalogue, loading the
+/// preferred model.
+pub fn init() -> Result<(), &'static str> {
+    memory::register_shrinker("ai-models", shrink);
+    let Ok(conf) = fs::read_file(CONFIG_PATH) else { return Ok(()) };
+    let conf = core::str::from_utf8(&conf).map_err(|_| "model config is not UTF-8")?;
+
+    let mut preferred = None;
+    for line in conf.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["model", name, path] => { add(name, path); }
+            ["adapter", model, name, path, mode @ ("merge" | "runtime")] => {
+                let mut m = MANAGER.lock();
+                let e = m.entries.iter_mut().find(|e| e.name == model).ok_or("adapter for unknown model in config")?;
+                e.adapters.push(Adapter { name: String::from(name), path: String::from(path), merge: mode == "merge" });
+            }
+            ["preferred", name]   => preferred = Some(name),
+            _                     => return Err("bad line in model config"),
+        }
+    }
+    match preferred {
+        Some(name) => set_preferred(name),
+        None       => Ok(()),
+    }
+}
+
+fn add(name: &str, path: &str) -> u32 {
+    let id = service::reserve_model_id();
+    let entry = Entry {
+        name: String::from(name), path: String::from(path), id, last_used: arch::read_mtime(), adapters: Vec::new(),
+    };
+    MANAGER.lock().entries.push(entry);
+    id
+}
+
+fn id_of(name: &str) -> Result<u32, &'static str> {
+    MANAGER.lock().entries.iter().find(|e| e.name == name).map(|e| e.id).ok_or("no such model")
+}
+
+/// Write the catalogue back to CONFIG_PATH.
+fn save() -> Result<(), &'static str> {
+    let mut conf = String::from(
+        "# SurakshaOS models: model <name> <path> | adapter <model> <name> <path> merge|runtime | preferred <name>\n");
+    {
+        let m = MANAGER.lock();
+        for e in &m.entries {
+            conf.push_str(&format!("model {} {}\n", e.name, e.path));
+            for a in &e.adapters {
+                conf.push_str(&format!("adapter {} {} {} {}\n", e.name, a.name, a.path, if a.merge { "merge" } else { "runtime" }));
+            }
+        }
+        if let Some(p) = m.entries.iter().find(|e| Some(e.id) == m.preferred) {
+            conf.push_str(&format!("preferred {}\n", p.name));
+        }
+    }
+    fs::create_dir("/etc/ai").ok();
+    fs::write_file(CONFIG_PATH, conf.as_bytes())
+}
+
+// ─── catalogue ────────────────────────────────────────────────────────────────
+
+/// Add the model at `path` to the catalogue; returns its model id.  It is
+/// not loaded until it is first used.
+pub fn register(name: &str, path: &str) -> Result<u32, &'static str> {
+    if name.contains(char::is_whitespace) || path.contains(char::is_whitespace) {
+        return Err("model names and paths cannot contain spaces");
+    }
+    if id_of(name).is_ok() { return Err("model name in use"); }
+    let id = add(name, path);
+    save()?;
+    Ok(id)
+}
+
+/// Drop a model from the catalogue.  Generations already running on it
+/// finish first.
+pub fn unregister(name: &str) -> Result<(), &'static str> {
+    let id = id_of(name)?;
+    {
+        let mut m = MANAGER.lock();
+        m.entries.retain(|e| e.id != id);
+        if m.preferred == Some(id) { m.preferred = None; }
+    }
+    service::remove_model(id);
+    save()
+}
+
+pub fn list() -> Vec<ModelInfo> {
+    let now = arch::read_mtime();
+    let mut infos: Vec<ModelInfo> = {
+        let m = MANAGER.lock();
+        m.entries.iter().map(|e| ModelInfo {
+            id:        e.id,
+            name:      e.name.clone(),
+            path:      e.path.clone(),
+            loaded:    false,
+            preferred: m.preferred == Some(e.id),
+            idle_ms:   arch::ticks_to_us(now.saturating_sub(e.last_used)) / 1000,
+            adapters:  e.adapters.iter().map(|a| (a.name.clone(), a.merge)).collect(),
+        }).collect()
+    };
+    // The service is asked outside the manager lock
+    for info in &mut infos { info.loaded = service::installed(info.id).is_some(); }
+    infos
+}
+
+pub fn preferred_id() -> Option<u32> {
+    MANAGER.lock().preferred
+}
+
+pub(super) fn is_known(id: u32) -> bool {
+    MANAGER.lock().entries.iter().any(|e| e.id == id)
+}
+
+/// Note that model `id` has just been used.
+pub(super) fn touch(id: u32) {
+    if let Some(e) = MANAGER.lock().entries.iter_mut().find(|e| e.id == id) {
+        e.last_used = arch::read_mtime();
+    }
+}
+
+/// Catalogue a LoRA adapter for `model`.  A runtime adapter is usable at
+/// once if the model is loaded; a merged one from the model's next load,
+/// which is now if the model is idle.
+pub fn add_adapter(model: &str, name: &str, path: &str, merge: bool) -> Result<(), &'static str> {
+    if name.contains(char::is_whitespace) || path.contains(char::is_whitespace) {
+        return Err("adapter names and paths cannot contain spaces");
+    }
+    let id = id_of(model)?;
+    if let Some(m) = service::installed(id) {
+        if merge {
+            service::unload_if_idle(id);
+        } else {
+            m.load_adapter(name, path)?;
+        }
+    }
+    {
+        let mut m = MANAGER.lock();
+        let e = m.entries.iter_mut().find(|e| e.id == id).ok_or("no such model")?;
+        e.adapters.retain(|a| a.name != name);
+        e.adapters.push(Adapter { name: String::from(name), path: String::from(path), merge });
+    }
+    save()
+}
+
+/// Drop an adapter from `model`'s catalogue.  A merged adapter stays in
+/// the weights until the model is next loaded.
+pub fn remove_adapter(model: &str, name: &str) -> Result<(), &'static str> {
+    let id = id_of(model)?;
+    {
+        let mut m = MANAGER.lock();
+        let e = m.entries.iter_mut().find(|e| e.id == id).ok_or("no such model")?;
+        let before = e.adapters.len();
+        e.adapters.retain(|a| a.name != name);
+        if e.adapters.len() == before { return Err("no such adapter"); }
+    }
+    if let Some(m) = service::installed(id) { m.unload_adapter(name).ok(); }
+    save()
+}
+
+// ─── loading ──────────────────────────────────────────────────────────────────
+
+/// Model `id`, loading it into the service if it is not loaded.
+pub(super) fn load_id(id: u32) -> Result<Arc<AiModel>, &'static str> {
+    if let Some(m) = service::installed(id) { return Ok(m); }
+    let (name, path, adapters) = MANAGER.lock().entries.iter().find(|e| e.id == id)
+        .map(|e| (e.name.clone(), e.path.clone(),
+                  e.adapters.iter().map(|a| (a.name.clone(), a.path.clone(), a.merge)).collect::<Vec<_>>()))
+        .ok_or("no such model")?;
+
+    // Make room before mapping the weights
+    memory::reclaim();
+    let mut model = AiModel::new(&name);
+    model.load_weights(&path)?;
+    for (name, path, merge) in adapters {
+        if merge { model.merge_adapter(&name, &path)?; } else { model.load_adapter(&name, &path)?; }
+    }
+    let model = Arc::new(model);
+    service::install_as(id, model.clone());
+    touch(id);
+    Ok(model)
+}
+
+pub fn load(name: &str) -> Result<u32, &'static str> {
+    let id = id_of(name)?;
+    load_id(id).map(|_| id)
+}
+
+pub fn unload(name: &str) -> Result<(), &'static str> {
+    if service::unload_if_idle(id_of(name)?) { Ok(()) } else { Err("model is in use") }
+}
+
+/// Switch any-model clients to `name`.  It is loaded first so the switch
+/// is seamless; the previous preferred model is unloaded if it is idle.
+pub fn set_preferred(name: &str) -> Result<(), &'static str> {
+    let id = id_of(name)?;
+    load_id(id)?;
+    let old = MANAGER.lock().preferred.replace(id);
+    if let Some(old) = old.filter(|&old| old != id) { service::unload_if_idle(old); }
+    save()
+}
+
+// ─── memory pressure ──────────────────────────────────────────────────────────
+
+fn shrink(level: Pressure) -> bool {
+    let now  = arch::read_mtime();
+    let idle = arch::ms_to_ticks(IDLE_UNLOAD_MS);
+    let mut victims: Vec<(u64, u32)> = {
+        let m = MANAGER.lock();
+        m.entries.iter()
+            .filter(|e| level == Pressure::Critical
+                || (Some(e.id) != m.preferred && now.saturating_sub(e.last_used) >= idle))
+            .map(|e| (e.last_used, e.id))
+            .collect()
+    };
+    // Least recently used first
+    victims.sort_unstable();
+
+    let mut freed = false;
+    for (_, id) in victims {
+        if service::unload_if_idle(id) {
+            freed = true;
+            if memory::pressure() < level { break; }
+        }
+    }
+    freed
+}
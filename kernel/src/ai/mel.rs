@@ -0,0 +1,141 @@
+//! Log-Mel Features
+//! Turns 16 kHz PCM into the log-mel spectrogram speech models take as
+//! input: 25 ms Hann windows every 10 ms, a 512-point FFT, triangular mel
+//! filters up to 8 kHz, and the natural log of each filter's energy.
+//! Audio is pushed in pieces of any size and frames come out as soon as
+//! their window is complete.  Audio waiting for its window is held in
+//! zero-on-free memory.
+
+use alloc::alloc::Allocator;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::f32::consts::PI;
+
+use crate::memory::{SecureVec, ZeroOnFree};
+
+pub const WINDOW: usize = 400;
+pub const HOP:    usize = 160;
+const N_FFT:      usize = 512;
+const N_BINS:     usize = N_FFT / 2 + 1;
+const SAMPLE_RATE: f32  = 16_000.0;
+/// Floor on filter energy before taking the log.
+const ENERGY_FLOOR: f32 = 1e-10;
+
+fn hz_to_mel(hz: f32) -> f32 {
+    2595.0 * libm::log10f(1.0 + hz / 700.0)
+}
+
+fn mel_to_hz(mel: f32) -> f32 {
+    700.0 * (libm::powf(10.0, mel / 2595.0) - 1.0)
+}
+
+struct Filter {
+    first:   usize,
+    weights: Vec<f32>,
+}
+
+pub struct MelExtractor {
+    n_mels:  usize,
+    window:  Vec<f32>,
+    filters: Vec<Filter>,
+    /// (cos, sin) twiddles for the FFT.
+    twiddle: Vec<(f32, f32)>,
+    /// Samples not yet consumed by a full frame.
+    pending: SecureVec<f32>,
+}
+
+impl MelExtractor {
+    pub fn new(n_mels: usize) -> Self {
+        let window = (0..WINDOW).map(|i| 0.5 - 0.5 * libm::cosf(2.0 * PI * i as f32 / WINDOW as f32)).collect();
+
+        // Filter edges evenly spaced in mel, as FFT bin positions
+        let top = hz_to_mel(SAMPLE_RATE / 2.0);
+        let edges: Vec<f32> = (0..n_mels + 2)
+            .map(|i| mel_to_hz(top * i as f32 / (n_mels + 1) as f32) * N_FFT as f32 / SAMPLE_RATE)
+            .collect();
+        let filters = edges.windows(3).map(|e| {
+            let (lo, mid, hi) = (e[0], e[1], e[2]);
+            let first = libm::ceilf(lo) as usize;
+            let last  = (libm::floorf(hi) as usize).min(N_BINS - 1);
+            let weights = (first..=last).map(|b| {
+                let b = b as f32;
+                if b <= mid { (b - lo) / (mid - lo).max(f32::EPSILON) } else { (hi - b) / (hi - mid).max(f32::EPSILON) }
+            }).collect();
+            Filter { first, weights }
+        }).collect();
+
+        let twiddle = (0..N_FFT / 2).map(|k| {
+            let a = -2.0 * PI * k as f32 / N_FFT as f32;
+            (libm::cosf(a), libm::sinf(a))
+        }).collect();
+
+        MelExtractor { n_mels, window, filters, twiddle, pending: Vec::new_in(ZeroOnFree) }
+    }
+
+    pub fn n_mels(&self) -> usize {
+        self.n_mels
+    }
+
+    /// Add samples; every completed frame (`n_mels` values) is appended
+    /// to `frames`.
+    pub fn push<A: Allocator>(&mut self, samples: &[i16], frames: &mut Vec<f32, A>) {
+        self.pending.extend(samples.iter().map(|&s| s as f32 / 32768.0));
+        let mut start = 0;
+        while start + WINDOW <= self.pending.len() {
+            let at = frames.len();
+            frames.resize(at + self.n_mels, 0.0);
+            self.frame(start, &mut frames[at..]);
+            start += HOP;
+        }
+        self.pending.copy_within(start.., 0);
+        self.pending.truncate(self.pending.len() - start);
+    }
+
+    /// Clear pending audio and wipe it from memory.
+    pub fn reset(&mut self) {
+        self.pending.fill(0.0);
+        self.pending.clear();
+    }
+
+    fn frame(&self, start: usize, out: &mut [f32]) {
+        let mut re = vec![0.0; N_FFT];
+        let mut im = vec![0.0; N_FFT];
+        for (r, (x, w)) in re.iter_mut().zip(self.pending[start..start + WINDOW].iter().zip(&self.window)) {
+            *r = x * w;
+        }
+        self.fft(&mut re, &mut im);
+
+        let power: Vec<f32> = re[..N_BINS].iter().zip(&im[..N_BINS]).map(|(r, i)| r * r + i * i).collect();
+        for (o, f) in out.iter_mut().zip(&self.filters) {
+            let e: f32 = f.weights.iter().zip(&power[f.first..]).map(|(w, p)| w * p).sum();
+            *o = libm::logf(e.max(ENERGY_FLOOR));
+        }
+    }
+
+    /// In-place iterative radix-2 FFT.
+    fn fft(&self, re: &mut [f32], im: &mut [f32]) {
+        let n = re.len();
+        let bits = n.trailing_zeros();
+        for i in 0..n {
+            let j = i.reverse_bits() >> (usize::BITS - bits);
+            if j > i { re.swap(i, j); im.swap(i, j); }
+        }
+        let mut len = 2;
+        while len <= n {
+            let step = n / len;
+            for base in (0..n).step_by(len) {
+                for k in 0..len / 2 {
+                    let (c, s) = self.twiddle[k * step];
+                    let (a, b) = (base + k, base + k + len / 2);
+                    let tr = re[b] * c - im[b] * s;
+                    let ti = re[b] * s + im[b] * c;
+                    re[b] = re[a] - tr;
+                    im[b] = im[a] - ti;
+                    re[a] += tr;
+                    im[a] += ti;
+                }
+            }
+            len *= 2;
+        }
+    }
+}
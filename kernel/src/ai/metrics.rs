@@ -0,0 +1,128 @@
+//! Inference Metrics
+//! Device-wide counters over every generation: tokens and time spent on
+//! prompts and on decoding, per-token latency (a histogram and the worst
+//! seen), time lost to throttling, and KV-cache memory, current and peak.
+//! They are updated without locks from the generation loop, so reading
+//! them never holds up inference.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Upper bounds of the per-token latency buckets (µs); the last bucket
+/// takes everything slower.
+pub const LATENCY_BUCKETS_US: [u64; 7] = [5_000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000];
+
+static GENERATIONS:   AtomicU64 = AtomicU64::new(0);
+static PROMPT_TOKENS: AtomicU64 = AtomicU64::new(0);
+static PREFILL_US:    AtomicU64 = AtomicU64::new(0);
+static TOKENS:        AtomicU64 = AtomicU64::new(0);
+static DECODE_US:     AtomicU64 = AtomicU64::new(0);
+static MAX_TOKEN_US:  AtomicU64 = AtomicU64::new(0);
+static THROTTLED_US:  AtomicU64 = AtomicU64::new(0);
+static THERMAL_STEPS: AtomicU64 = AtomicU64::new(0);
+static KV_BYTES:      AtomicUsize = AtomicUsize::new(0);
+static KV_PEAK:       AtomicUsize = AtomicUsize::new(0);
+static LATENCY:       [AtomicU64; LATENCY_BUCKETS_US.len() + 1] = [const { AtomicU64::new(0) }; LATENCY_BUCKETS_US.len() + 1];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AiMetrics {
+    pub generations:   u64,
+    pub prompt_tokens: u64,
+    pub prefill_us:    u64,
+    pub tokens:        u64,
+    pub decode_us:     u64,
+    pub max_token_us:  u64,
+    /// Decode latencies per bucket of LATENCY_BUCKETS_US.
+    pub latency:       [u64; LATENCY_BUCKETS_US.len() + 1],
+    /// Time inference waited on its sandbox's CPU share.
+    pub throttled_us:  u64,
+    /// Times the AI service changed pace for the temperature.
+    pub thermal_steps: u64,
+    pub kv_bytes:      usize,
+    pub kv_peak_bytes: usize,
+}
+
+impl AiMetrics {
+    /// Decoding speed; 0 before any token.
+    pub fn tokens_per_sec(&self) -> u64 {
+        (self.tokens * 1_000_000).checked_div(self.decode_us).unwrap_or(0)
+    }
+
+    /// Prompt processing speed.
+    pub fn prompt_tokens_per_sec(&self) -> u64 {
+        (self.prompt_tokens * 1_000_000).checked_div(self.prefill_us).unwrap_or(0)
+    }
+
+    /// Latency bound that `pct` percent of tokens came in under, to bucket
+    /// precision; `None` if they fell in the open-ended last bucket.
+    pub fn latency_percentile_us(&self, pct: u64) -> Option<u64> {
+        let total: u64 = self.latency.iter().sum();
+        let want = (total * pct).div_ceil(100);
+        let mut seen = 0;
+        for (i, n) in self.latency.iter().enumerate() {
+            seen += n;
+            if seen >= want { return LATENCY_BUCKETS_US.get(i).copied(); }
+        }
+        None
+    }
+}
+
+pub fn snapshot() -> AiMetrics {
+    let mut latency = [0; LATENCY_BUCKETS_US.len() + 1];
+    for (l, a) in latency.iter_mut().zip(&LATENCY) { *l = a.load(Ordering::Relaxed); }
+    AiMetrics {
+        generations:   GENERATIONS.load(Ordering::Relaxed),
+        prompt_tokens: PROMPT_TOKENS.load(Ordering::Relaxed),
+        prefill_us:    PREFILL_US.load(Ordering::Relaxed),
+        tokens:        TOKENS.load(Ordering::Relaxed),
+        decode_us:     DECODE_US.load(Ordering::Relaxed),
+        max_token_us:  MAX_TOKEN_US.load(Ordering::Relaxed),
+        throttled_us:  THROTTLED_US.load(Ordering::Relaxed),
+        thermal_steps: THERMAL_STEPS.load(Ordering::Relaxed),
+        kv_bytes:      KV_BYTES.load(Ordering::Relaxed),
+        kv_peak_bytes: KV_PEAK.load(Ordering::Relaxed),
+        latency,
+    }
+}
+
+// ─── recording ────────────────────────────────────────────────────────────────
+
+pub(super) fn record_prefill(tokens: usize, us: u64) {
+    GENERATIONS.fetch_add(1, Ordering::Relaxed);
+    PROMPT_TOKENS.fetch_add(tokens as u64, Ordering::Relaxed);
+    PREFILL_US.fetch_add(us, Ordering::Relaxed);
+}
+
+pub(super) fn record_token(us: u64) {
+    TOKENS.fetch_add(1, Ordering::Relaxed);
+    DECODE_US.fetch_add(us, Ordering::Relaxed);
+    MAX_TOKEN_US.fetch_max(us, Ordering::Relaxed);
+    let bucket = LATENCY_BUCKETS_US.iter().position(|&b| us <= b).unwrap_or(LATENCY_BUCKETS_US.len());
+    LATENCY[bucket].fetch_add(1, Ordering::Relaxed);
+}
+
+pub(super) fn record_throttle(us: u64) {
+    THROTTLED_US.fetch_add(us, Ordering::Relaxed);
+}
+
+pub(super) fn record_thermal_step() {
+    THERMAL_STEPS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// KV pages reserved (positive) or freed (negative).
+pub(super) fn record_kv(delta: isize) {
+    if delta >= 0 {
+        let now = KV_BYTES.fetch_add(delta as usize, Ordering::Relaxed) + delta as usize;
+        KV_PEAK.fetch_max(now, Ordering::Relaxed);
+    } else {
+        KV_BYTES.fetch_sub(delta.unsigned_abs(), Ordering::Relaxed);
+    }
+}
+
+/// Start the counters over (the KV figures track live caches and stay).
+pub fn reset() {
+    for a in [&GENERATIONS, &PROMPT_TOKENS, &PREFILL_US, &TOKENS, &DECODE_US, &MAX_TOKEN_US, &THROTTLED_US, &THERMAL_STEPS] {
+        a.store(0, Ordering::Relaxed);
+    }
+    for a in &LATENCY { a.store(0, Ordering::Relaxed); }
+    KV_PEAK.store(KV_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
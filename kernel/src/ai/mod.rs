@@ -0,0 +1,353 @@
+//! SurakshaOS On-Device AI
+//! Models run entirely on the device; prompts and outputs never leave it.
+//! Model weights are only used after their signature has been checked
+//! against a key the platform trusts.
+
+pub mod asr;
+pub mod batch;
+pub mod embed;
+pub mod gguf;
+pub mod gpu;
+pub mod indic;
+pub mod kernels;
+pub mod kvcache;
+pub mod langid;
+pub mod lora;
+pub mod manager;
+pub mod mel;
+pub mod metrics;
+pub mod npu;
+pub mod privacy;
+pub mod sampler;
+pub mod sandbox;
+pub mod service;
+pub mod stream;
+pub mod tokenizer;
+pub mod transformer;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use asr::{AsrModel, Transcriber};
+use crate::crypto::{mldsa, sha3};
+use embed::EmbedOptions;
+use gguf::GgufFile;
+use kvcache::MemoryGrant;
+use langid::Language;
+use lora::LoraAdapter;
+use sandbox::{Sandbox, SandboxConfig};
+use tokenizer::Tokenizer;
+use stream::{CancelToken, GenerateOptions, Generation, InferenceResponse, ModelRef, TokenEvent};
+use transformer::Transformer;
+
+// ─── model signing ────────────────────────────────────────────────────────────
+//
+// A model at `path` is signed by a sidecar file `path.sig`:
+//
+//   magic "SKMS" | version u8 (1) | SHAKE-256 digest [64] | ML-DSA-65 signature
+//
+// The signature covers the digest, under the context string MODEL_SIG_CTX.
+
+const SIG_MAGIC:   &[u8; 4] = b"SKMS";
+const SIG_VERSION: u8       = 1;
+const DIGEST_LEN:  usize    = 64;
+const SIG_FILE_LEN: usize   = 4 + 1 + DIGEST_LEN + mldsa::SIGNATURE_LEN;
+
+/// ML-DSA context string for model signatures.
+pub const MODEL_SIG_CTX: &[u8] = b"surakshaos-model-v1";
+
+/// Public keys whose model signatures are accepted.
+static TRUSTED_KEYS: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// Unsigned models are refused unless this is cleared (development only).
+static REQUIRE_SIGNED: AtomicBool = AtomicBool::new(true);
+
+pub fn trust_signing_key(public_key: &[u8]) -> Result<(), &'static str> {
+    if public_key.len() != mldsa::PUBLIC_KEY_LEN { return Err("bad ML-DSA-65 public key length"); }
+    let mut keys = TRUSTED_KEYS.lock();
+    if !keys.iter().any(|k| k == public_key) { keys.push(public_key.to_vec()); }
+    Ok(())
+}
+
+pub fn set_require_signed(required: bool) {
+    REQUIRE_SIGNED.store(required, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    Signed,
+    /// Loaded without a signature because policy allowed it.
+    Unsigned,
+}
+
+/// Check `data` against the sidecar signature at `sig_path`.
+fn verify_model(data: &[u8], sig_path: &str) -> Result<Provenance, &'static str> {
+    let Ok(sig) = crate::fs::map_file(sig_path) else {
+        return if REQUIRE_SIGNED.load(Ordering::Relaxed) {
+            Err("model is unsigned; rejected by policy")
+        } else {
+            Ok(Provenance::Unsigned)
+        };
+    };
+    if sig.len() != SIG_FILE_LEN || &sig[..4] != SIG_MAGIC || sig[4] != SIG_VERSION {
+        return Err("malformed model signature");
+    }
+    let digest    = &sig[5..5 + DIGEST_LEN];
+    let signature = &sig[5 + DIGEST_LEN..];
+
+    let mut actual = [0u8; DIGEST_LEN];
+    sha3::shake256_into(data, &mut actual);
+    if actual[..] != *digest { return Err("model checksum mismatch"); }
+
+    let trusted = TRUSTED_KEYS.lock().iter().any(|k| mldsa::verify(k, digest, MODEL_SIG_CTX, signature));
+    if !trusted { return Err("model signature not from a trusted key"); }
+    Ok(Provenance::Signed)
+}
+
+// ─── models ───────────────────────────────────────────────────────────────────
+
+pub struct AiModel {
+    pub name:       String,
+    pub provenance: Option<Provenance>,
+    weights:        Option<GgufFile>,
+    tokenizer:      Option<Tokenizer>,
+    transformer:    Option<Transformer>,
+    /// Set instead of the tokenizer and transformer for speech models.
+    asr:            Option<AsrModel>,
+    sandbox:        Option<Sandbox>,
+    /// Languages the model was trained for (`general.languages`); empty
+    /// if the file does not say.
+    languages:      Vec<Language>,
+    /// Adapters merged into the weights, by name.
+    merged:         Vec<String>,
+    /// Adapters sessions may apply at run time.
+    adapters:       Mutex<Vec<Arc<LoraAdapter>>>,
+}
+
+impl AiModel {
+    pub fn new(name: &str) -> Self {
+        AiModel {
+            name: String::from(name),
+            provenance: None, weights: None, tokenizer: None, transformer: None, asr: None, sandbox: None,
+            languages: Vec::new(), merged: Vec::new(), adapters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Map the GGUF file at `path`, verify its signature, and validate its
+    /// tensor metadata.  Nothing from the file is parsed before the
+    /// signature has been checked.
+    pub fn load_weights(&mut self, path: &str) -> Result<(), &'static str> {
+        let map        = crate::fs::map_file(path)?;
+        let provenance = verify_model(&map, &format!("{}.sig", path))?;
+        let weights    = GgufFile::parse(map)?;
+        if weights.architecture() == Some("ctc") {
+            self.asr = Some(AsrModel::from_gguf(&weights)?);
+        } else {
+            self.tokenizer   = Some(Tokenizer::from_gguf(&weights)?);
+            self.transformer = Some(Transformer::from_gguf(&weights)?);
+        }
+        self.languages   = weights.get("general.languages").and_then(gguf::Value::as_array).unwrap_or(&[])
+            .iter().filter_map(|v| v.as_str().and_then(Language::from_code)).collect();
+        self.weights     = Some(weights);
+        self.provenance  = Some(provenance);
+        Ok(())
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.weights.is_some()
+    }
+
+    pub fn weights(&self) -> Option<&GgufFile> {
+        self.weights.as_ref()
+    }
+
+    pub fn transformer(&self) -> Option<&Transformer> {
+        self.transformer.as_ref()
+    }
+
+    pub fn tokenizer(&self) -> Option<&Tokenizer> {
+        self.tokenizer.as_ref()
+    }
+
+    pub fn asr(&self) -> Option<&AsrModel> {
+        self.asr.as_ref()
+    }
+
+    /// Speech-to-text rather than text generation.
+    pub fn is_speech(&self) -> bool {
+        self.asr.is_some()
+    }
+
+    pub fn languages(&self) -> &[Language] {
+        &self.languages
+    }
+
+    /// Whether the model handles `lang`.  A model that does not list its
+    /// languages is assumed to handle any.
+    pub fn supports_language(&self, lang: Language) -> bool {
+        self.languages.is_empty() || self.languages.contains(&lang)
+    }
+
+    // ─── adapters ─────────────────────────────────────────────────────────────
+
+    fn read_adapter(&self, name: &str, path: &str) -> Result<LoraAdapter, &'static str> {
+        let (Some(g), Some(t)) = (&self.weights, &self.transformer) else { return Err("model not loaded") };
+        LoraAdapter::load(name, path, g, t)
+    }
+
+    /// Merge the LoRA adapter at `path` into the weights.  Every later
+    /// generation uses it; an unsigned adapter makes the model unsigned.
+    pub fn merge_adapter(&mut self, name: &str, path: &str) -> Result<(), &'static str> {
+        let adapter = self.read_adapter(name, path)?;
+        let (Some(g), Some(t)) = (&self.weights, &mut self.transformer) else { return Err("model not loaded") };
+        t.merge(g, &adapter);
+        if adapter.provenance == Provenance::Unsigned { self.provenance = Some(Provenance::Unsigned); }
+        self.merged.push(String::from(name));
+        Ok(())
+    }
+
+    /// Load the LoRA adapter at `path` for generations that ask for it by
+    /// `name`, replacing any adapter of that name.
+    pub fn load_adapter(&self, name: &str, path: &str) -> Result<(), &'static str> {
+        let adapter = Arc::new(self.read_adapter(name, path)?);
+        let mut adapters = self.adapters.lock();
+        adapters.retain(|a| a.name != name);
+        adapters.push(adapter);
+        Ok(())
+    }
+
+    /// Generations already using the adapter keep it until they finish.
+    pub fn unload_adapter(&self, name: &str) -> Result<(), &'static str> {
+        let mut adapters = self.adapters.lock();
+        let before = adapters.len();
+        adapters.retain(|a| a.name != name);
+        if adapters.len() == before { Err("no such adapter") } else { Ok(()) }
+    }
+
+    pub fn adapter(&self, name: &str) -> Option<Arc<LoraAdapter>> {
+        self.adapters.lock().iter().find(|a| a.name == name).cloned()
+    }
+
+    /// Runtime adapters.
+    pub fn adapters(&self) -> Vec<Arc<LoraAdapter>> {
+        self.adapters.lock().clone()
+    }
+
+    /// Names of the adapters merged into the weights.
+    pub fn merged_adapters(&self) -> &[String] {
+        &self.merged
+    }
+
+    /// Confine inference to `cfg`'s CPU share and memory capability.
+    pub fn set_sandbox(&mut self, cfg: SandboxConfig) -> Result<(), &'static str> {
+        self.sandbox = Some(Sandbox::new(cfg)?);
+        Ok(())
+    }
+
+    pub fn sandbox(&self) -> Option<&Sandbox> {
+        self.sandbox.as_ref()
+    }
+
+    fn sandbox_memory(&self) -> Option<&MemoryGrant> {
+        self.sandbox.as_ref().and_then(|s| s.memory())
+    }
+
+    /// Token ids for `text`, starting with BOS.
+    pub fn tokenize(&self, text: &str) -> Result<Vec<u32>, &'static str> {
+        Ok(self.tokenizer.as_ref().ok_or("model not loaded")?.encode(text, true))
+    }
+
+    /// Tokenize a prompt in `opts.language`, detecting it if not given.
+    fn tokenize_prompt(&self, prompt: &str, opts: &GenerateOptions)
+        -> Result<(Vec<u32>, Option<Language>), &'static str>
+    {
+        let language = opts.language.or_else(|| langid::detect(prompt).map(|d| d.language));
+        let tokens = match language {
+            Some(lang) => self.tokenize(&langid::normalize_for(prompt, lang))?,
+            None       => self.tokenize(prompt)?,
+        };
+        Ok((tokens, language))
+    }
+
+    pub fn detokenize(&self, tokens: &[u32]) -> Result<String, &'static str> {
+        Ok(self.tokenizer.as_ref().ok_or("model not loaded")?.decode(tokens))
+    }
+
+    /// Start generating from `prompt`.  Nothing runs until the returned
+    /// iterator is polled; `cancel` stops it at the next token.
+    pub fn stream(&self, prompt: &str, opts: &GenerateOptions, cancel: CancelToken)
+        -> Result<Generation<'_>, &'static str>
+    {
+        let (tokens, language) = self.tokenize_prompt(prompt, opts)?;
+        Generation::new(ModelRef::Borrowed(self), tokens, language, opts, cancel)
+    }
+
+    /// Like `stream`, for a model shared with a service: the generation
+    /// keeps the model alive and is not tied to the caller's borrow.
+    pub fn stream_shared(self: &Arc<Self>, prompt: &str, opts: &GenerateOptions, cancel: CancelToken)
+        -> Result<Generation<'static>, &'static str>
+    {
+        let (tokens, language) = self.tokenize_prompt(prompt, opts)?;
+        Generation::new(ModelRef::Shared(self.clone()), tokens, language, opts, cancel)
+    }
+
+    /// Generate with a callback per token; returning false from `on_token`
+    /// cancels, and the partial result is returned.
+    pub fn complete_with(&self, prompt: &str, opts: &GenerateOptions, mut on_token: impl FnMut(&TokenEvent) -> bool)
+        -> Result<InferenceResponse, &'static str>
+    {
+        let mut gen = self.stream(prompt, opts, CancelToken::new())?;
+        let cancel = gen.cancel_token();
+        for ev in gen.by_ref() {
+            if !on_token(&ev) { cancel.cancel(); }
+        }
+        Ok(gen.finish())
+    }
+
+    /// Embedding vector for `text` (`n_embd` values).
+    pub fn embed(&self, text: &str, opts: &EmbedOptions) -> Result<Vec<f32>, &'static str> {
+        Ok(embed::embed_batch(self, &[text], opts)?.pop().unwrap())
+    }
+
+    /// Embeddings for several texts, in order.
+    pub fn embed_batch(&self, texts: &[&str], opts: &EmbedOptions) -> Result<Vec<Vec<f32>>, &'static str> {
+        embed::embed_batch(self, texts, opts)
+    }
+
+    /// Start a streaming transcription on a speech model.
+    pub fn transcriber(&self) -> Result<Transcriber<'_>, &'static str> {
+        Transcriber::new(ModelRef::Borrowed(self))
+    }
+
+    /// Like `transcriber`, for a model shared with a service.
+    pub fn transcriber_shared(self: &Arc<Self>) -> Result<Transcriber<'static>, &'static str> {
+        Transcriber::new(ModelRef::Shared(self.clone()))
+    }
+
+    /// Transcribe a complete recording (16 kHz mono).
+    pub fn transcribe(&self, samples: &[i16]) -> Result<String, &'static str> {
+        let mut t = self.transcriber()?;
+        t.push(samples)?;
+        Ok(t.finish())
+    }
+
+    /// Greedily decode up to `max_tokens` tokens following `prompt`,
+    /// stopping early at end-of-sequence or when the context is full.
+    pub fn generate(&self, prompt: &[u32], max_tokens: usize) -> Result<Vec<u32>, &'static str> {
+        let gen = Generation::new(ModelRef::Borrowed(self), prompt.to_vec(), None, &GenerateOptions::new(max_tokens), CancelToken::new())?;
+        let done = gen.finish();
+        match done.stop {
+            Some(stream::StopReason::Error(e)) => Err(e),
+            _ => Ok(done.tokens),
+        }
+    }
+
+    /// Prompt in, text out.
+    pub fn complete(&self, prompt: &str, max_tokens: usize) -> Result<String, &'static str> {
+        Ok(self.stream(prompt, &GenerateOptions::new(max_tokens), CancelToken::new())?.finish().text)
+    }
+}
@@ -0,0 +1,160 @@
+//! NPU Offload
+//! Interface to neural accelerators.  An NPU driver implements
+//! `NpuBackend` and registers itself against the device-tree `compatible`
+//! strings it handles; at boot `probe` walks the device tree and brings up
+//! the first enabled match.  A model's quantized weight matrices are loaded
+//! onto the NPU as a graph, and their matmuls are sent there.  Anything the
+//! NPU cannot run, or any op it fails, is computed on the CPU instead, so
+//! callers never see the difference except in speed.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::gguf::{GgmlType, GgufFile};
+use super::kernels::{self, Matrix, QuantizedActs};
+use crate::fdt;
+
+pub type GraphId = u32;
+pub type Fence   = u64;
+
+/// Weight matrices to keep resident on the NPU, keyed by GGUF tensor index.
+pub struct Graph<'a> {
+    pub weights: Vec<(usize, Matrix<'a>)>,
+}
+
+pub enum NpuOp<'a> {
+    /// W · x for `weight` of `graph`, with x already quantized to INT8
+    /// blocks; produces one f32 per row of W.
+    MatMul { graph: GraphId, weight: usize, x: &'a QuantizedActs },
+}
+
+pub trait NpuBackend: Send {
+    fn name(&self) -> &'static str;
+
+    /// Weight formats the hardware runs natively.
+    fn supports(&self, ty: GgmlType) -> bool;
+
+    /// Copy (or pin) a graph's weights into NPU memory.
+    fn load_graph(&mut self, graph: &Graph) -> Result<GraphId, &'static str>;
+
+    fn unload_graph(&mut self, graph: GraphId);
+
+    /// Queue an op; it runs asynchronously until waited on.
+    fn enqueue(&mut self, op: NpuOp<'_>) -> Result<Fence, &'static str>;
+
+    /// Block until `fence` completes and copy its result to `out`.
+    fn wait(&mut self, fence: Fence, out: &mut [f32]) -> Result<(), &'static str>;
+}
+
+// ─── drivers + detection ──────────────────────────────────────────────────────
+
+/// Bring up the NPU described by a device-tree node.
+pub type ProbeFn = fn(&fdt::Node) -> Result<Box<dyn NpuBackend>, &'static str>;
+
+static DRIVERS: Mutex<Vec<(&'static str, ProbeFn)>> = Mutex::new(Vec::new());
+
+static NPU: Mutex<Option<Box<dyn NpuBackend>>> = Mutex::new(None);
+
+pub fn register_driver(compatible: &'static str, probe: ProbeFn) {
+    DRIVERS.lock().push((compatible, probe));
+}
+
+/// Look for an NPU in the device tree and start its driver.  Returns the
+/// backend's name, or None when inference stays on the CPU.
+pub fn probe() -> Option<&'static str> {
+    let dt = fdt::get()?;
+    let drivers = DRIVERS.lock();
+    for node in dt.nodes().filter(|n| n.is_enabled()) {
+        for &(compat, probe) in drivers.iter() {
+            if !node.is_compatible(compat) { continue; }
+            match probe(&node) {
+                Ok(backend) => {
+                    let name = backend.name();
+                    *NPU.lock() = Some(backend);
+                    return Some(name);
+                }
+                Err(e) => crate::println!("  [npu] {} ({}): {}", node.name, compat, e),
+            }
+        }
+    }
+    None
+}
+
+pub fn has_npu() -> bool {
+    NPU.lock().is_some()
+}
+
+pub fn backend_name() -> Option<&'static str> {
+    NPU.lock().as_ref().map(|b| b.name())
+}
+
+// ─── offloaded graphs ─────────────────────────────────────────────────────────
+
+/// A model's weights resident on the NPU.  Unloaded when dropped.
+pub struct NpuGraph {
+    id:      GraphId,
+    /// Tensor indices on the NPU, sorted.
+    weights: Vec<usize>,
+    /// Set after the NPU fails an op; everything then runs on the CPU.
+    failed:  AtomicBool,
+}
+
+impl NpuGraph {
+    /// Load the quantized matrices among `tensors` that the NPU supports.
+    /// None if there is no NPU or nothing to offload.
+    pub fn load(g: &GgufFile, tensors: &[usize]) -> Option<Self> {
+        let mut npu = NPU.lock();
+        let backend = npu.as_mut()?;
+        let mut weights: Vec<usize> = tensors.iter().copied()
+            .filter(|&i| is_quantized(g.tensors[i].ty) && backend.supports(g.tensors[i].ty))
+            .collect();
+        weights.sort_unstable();
+        weights.dedup();
+        if weights.is_empty() { return None; }
+
+        let graph = Graph { weights: weights.iter().map(|&i| (i, Matrix::from_tensor(g, i))).collect() };
+        match backend.load_graph(&graph) {
+            Ok(id) => Some(NpuGraph { id, weights, failed: AtomicBool::new(false) }),
+            Err(e) => {
+                crate::println!("  [npu] {}: graph load failed, using CPU: {}", backend.name(), e);
+                None
+            }
+        }
+    }
+
+    pub fn is_offloaded(&self, tensor: usize) -> bool {
+        !self.failed.load(Ordering::Relaxed) && self.weights.binary_search(&tensor).is_ok()
+    }
+
+    /// out = W · x on the NPU, or on the CPU if it can't.
+    pub fn matmul(&self, tensor: usize, w: &Matrix, out: &mut [f32], x: &[f32]) {
+        if self.is_offloaded(tensor) {
+            let xq = QuantizedActs::quantize(x);
+            let result = match NPU.lock().as_mut() {
+                Some(npu) => npu.enqueue(NpuOp::MatMul { graph: self.id, weight: tensor, x: &xq })
+                    .and_then(|fence| npu.wait(fence, &mut out[..w.rows])),
+                None => Err("NPU went away"),
+            };
+            match result {
+                Ok(()) => return,
+                Err(e) => {
+                    crate::println!("  [npu] op failed, falling back to CPU: {}", e);
+                    self.failed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        kernels::matmul(out, w, x);
+    }
+}
+
+impl Drop for NpuGraph {
+    fn drop(&mut self) {
+        if let Some(npu) = NPU.lock().as_mut() { npu.unload_graph(self.id); }
+    }
+}
+
+fn is_quantized(ty: GgmlType) -> bool {
+    !matches!(ty, GgmlType::F32 | GgmlType::F16 | GgmlType::BF16)
+}
@@ -0,0 +1,142 @@
+//! Content Privacy
+//! Prompts, generated text and transcripts belong to the user, and the AI
+//! stack never lets them out of the request that carries them: nothing
+//! writes them to the kernel console, the capability audit log records
+//! only which process used which capability, and every buffer that holds
+//! them (prompt and output tokens, logits, activations, the KV cache, mel
+//! features) is zero-on-free memory.
+//!
+//! Debugging a model sometimes needs the content anyway.  A process
+//! holding an `AiDebug` capability with READ can start a capture; while it
+//! runs, the service copies each prompt, completion and transcript into a
+//! bounded ring of zero-on-free records that only that process can take.
+//! The console announces when a capture starts and stops, and it ends by
+//! itself once the capability is revoked or expires.  Under confidentiality
+//! lockdown no capture can start.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::memory::{SecureVec, ZeroOnFree};
+use crate::process::{self, ProcessId};
+
+/// Content a capture holds at most; the oldest records make way.
+pub const CAPTURE_BYTES: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureKind {
+    Prompt,
+    Completion,
+    Transcript,
+}
+
+impl CaptureKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            CaptureKind::Prompt     => "prompt",
+            CaptureKind::Completion => "completion",
+            CaptureKind::Transcript => "transcript",
+        }
+    }
+}
+
+pub struct CaptureRecord {
+    pub time_ms: u64,
+    pub session: u32,
+    pub kind:    CaptureKind,
+    text:        SecureVec<u8>,
+}
+
+impl CaptureRecord {
+    pub fn text(&self) -> &str {
+        core::str::from_utf8(&self.text).unwrap_or_default()
+    }
+}
+
+struct Capture {
+    cap:     Capability,
+    records: VecDeque<CaptureRecord>,
+    bytes:   usize,
+}
+
+static CAPTURE: Mutex<Option<Capture>> = Mutex::new(None);
+/// Set while a capture runs, so requests skip the lock when none does.
+static CAPTURING: AtomicBool = AtomicBool::new(false);
+
+fn check(pid: ProcessId, cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(pid, cap, CapabilityType::AiDebug, Permissions::READ)
+}
+
+/// Start capturing AI content for `pid`.
+pub fn start_capture(pid: ProcessId, cap: &Capability) -> Result<(), &'static str> {
+    check(pid, cap)?;
+    crate::lockdown::check(crate::lockdown::Level::Confidentiality, "AI content capture")?;
+    let mut capture = CAPTURE.lock();
+    if capture.as_ref().is_some_and(|c| c.cap.owner != pid) { return Err("another process is capturing"); }
+    if capture.is_none() {
+        crate::println!("[aid] content capture started by pid {}", pid.0);
+    }
+    *capture = Some(Capture { cap: cap.clone(), records: VecDeque::new(), bytes: 0 });
+    CAPTURING.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Stop the capture `pid` started; anything not taken is wiped.
+pub fn stop_capture(pid: ProcessId, cap: &Capability) -> Result<(), &'static str> {
+    check(pid, cap)?;
+    let mut capture = CAPTURE.lock();
+    match capture.as_ref() {
+        Some(c) if c.cap.owner == pid => end(&mut capture),
+        Some(_) => return Err("another process is capturing"),
+        None    => {}
+    }
+    Ok(())
+}
+
+fn end(capture: &mut Option<Capture>) {
+    if let Some(c) = capture.take() {
+        crate::println!("[aid] content capture by pid {} ended", c.cap.owner.0);
+    }
+    CAPTURING.store(false, Ordering::Relaxed);
+}
+
+/// Whether a capture is running.
+pub fn capturing() -> bool {
+    CAPTURING.load(Ordering::Relaxed)
+}
+
+/// Hand over the records captured so far, oldest first.
+pub fn take_capture(pid: ProcessId, cap: &Capability) -> Result<Vec<CaptureRecord>, &'static str> {
+    check(pid, cap)?;
+    let mut capture = CAPTURE.lock();
+    let c = capture.as_mut().filter(|c| c.cap.owner == pid).ok_or("not capturing")?;
+    c.bytes = 0;
+    Ok(c.records.drain(..).collect())
+}
+
+/// Copy `text` into the capture, if one is running and its capability
+/// still holds.
+pub(super) fn record(session: u32, kind: CaptureKind, text: &str) {
+    if !capturing() || text.is_empty() { return; }
+    let mut capture = CAPTURE.lock();
+    let Some(c) = capture.as_mut() else { return };
+    if check(c.cap.owner, &c.cap).is_err() {
+        end(&mut capture);
+        return;
+    }
+
+    let mut len = text.len().min(CAPTURE_BYTES);
+    while !text.is_char_boundary(len) { len -= 1; }
+    let text = &text.as_bytes()[..len];
+    while c.bytes + text.len() > CAPTURE_BYTES {
+        let Some(old) = c.records.pop_front() else { break };
+        c.bytes -= old.text.len();
+    }
+    let mut copy = Vec::with_capacity_in(text.len(), ZeroOnFree);
+    copy.extend_from_slice(text);
+    c.bytes += copy.len();
+    c.records.push_back(CaptureRecord { time_ms: process::uptime_ms(), session, kind, text: copy });
+}
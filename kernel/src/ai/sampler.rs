@@ -0,0 +1,117 @@
+//! Sampler
+//! Picks the next token from the logits.  Repetition, frequency and
+//! presence penalties are applied over a window of recent tokens; then a
+//! temperature of zero decodes greedily, and anything above it samples
+//! from the top-k / nucleus (top-p) candidates.  Random draws come from
+//! the kernel CSPRNG, so sampled output cannot be predicted by another
+//! process.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::kernels;
+use super::transformer;
+use crate::entropy;
+
+#[derive(Debug, Clone)]
+pub struct SamplerConfig {
+    /// 0 is greedy decoding.
+    pub temperature:       f32,
+    /// Keep only the k most likely tokens (0 = no limit).
+    pub top_k:             usize,
+    /// Keep the smallest set of tokens whose probability reaches p (1 = off).
+    pub top_p:             f32,
+    /// Divides positive logits (multiplies negative ones) of recent
+    /// tokens (1 = off).
+    pub repeat_penalty:    f32,
+    /// Subtracted once per occurrence of a recent token.
+    pub frequency_penalty: f32,
+    /// Subtracted once from any recent token.
+    pub presence_penalty:  f32,
+    /// How many recent tokens the penalties look at.
+    pub penalty_window:    usize,
+    /// Generation stops when the output contains any of these; the stop
+    /// sequence itself is not part of the output.
+    pub stop:              Vec<String>,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        SamplerConfig {
+            temperature:       0.0,
+            top_k:             0,
+            top_p:             1.0,
+            repeat_penalty:    1.0,
+            frequency_penalty: 0.0,
+            presence_penalty:  0.0,
+            penalty_window:    64,
+            stop:              Vec::new(),
+        }
+    }
+}
+
+impl SamplerConfig {
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !(self.temperature >= 0.0 && self.temperature.is_finite()) { return Err("bad temperature"); }
+        if !(self.top_p > 0.0 && self.top_p <= 1.0) { return Err("top_p must be in (0, 1]"); }
+        if !(self.repeat_penalty > 0.0 && self.repeat_penalty.is_finite()) { return Err("bad repeat penalty"); }
+        if self.stop.iter().any(|s| s.is_empty()) { return Err("empty stop sequence"); }
+        Ok(())
+    }
+
+    pub fn is_greedy(&self) -> bool {
+        self.temperature == 0.0
+    }
+}
+
+/// Choose the next token.  `logits` is modified; `recent` yields the most
+/// recent tokens first.
+pub fn sample<'a>(cfg: &SamplerConfig, logits: &mut [f32], recent: impl Iterator<Item = &'a u32>) -> u32 {
+    apply_penalties(cfg, logits, recent);
+    if cfg.is_greedy() { return transformer::argmax(logits); }
+
+    let mut cands: Vec<(u32, f32)> = logits.iter().enumerate().map(|(i, &l)| (i as u32, l)).collect();
+    let by_logit = |a: &(u32, f32), b: &(u32, f32)| b.1.total_cmp(&a.1);
+    if cfg.top_k > 0 && cfg.top_k < cands.len() {
+        cands.select_nth_unstable_by(cfg.top_k - 1, by_logit);
+        cands.truncate(cfg.top_k);
+    }
+    cands.sort_unstable_by(by_logit);
+
+    let mut probs: Vec<f32> = cands.iter().map(|&(_, l)| l / cfg.temperature).collect();
+    kernels::softmax(&mut probs);
+
+    // Nucleus: keep the head of the distribution up to top_p
+    let mut keep = probs.len();
+    if cfg.top_p < 1.0 {
+        let mut cum = 0.0;
+        for (i, &p) in probs.iter().enumerate() {
+            cum += p;
+            if cum >= cfg.top_p { keep = i + 1; break; }
+        }
+    }
+    let total: f32 = probs[..keep].iter().sum();
+
+    // Uniform in [0, total) from 24 random bits
+    let r = (entropy::next_u32() >> 8) as f32 / (1u32 << 24) as f32 * total;
+    let mut cum = 0.0;
+    for (&(id, _), &p) in cands.iter().zip(&probs[..keep]) {
+        cum += p;
+        if r < cum { return id; }
+    }
+    cands[keep - 1].0
+}
+
+fn apply_penalties<'a>(cfg: &SamplerConfig, logits: &mut [f32], recent: impl Iterator<Item = &'a u32>) {
+    if cfg.repeat_penalty == 1.0 && cfg.frequency_penalty == 0.0 && cfg.presence_penalty == 0.0 { return; }
+    let mut counts: BTreeMap<u32, u32> = BTreeMap::new();
+    for &t in recent.take(cfg.penalty_window) {
+        *counts.entry(t).or_default() += 1;
+    }
+    for (&t, &n) in &counts {
+        let Some(l) = logits.get_mut(t as usize) else { continue };
+        *l = if *l > 0.0 { *l / cfg.repeat_penalty } else { *l * cfg.repeat_penalty };
+        *l -= n as f32 * cfg.frequency_penalty + cfg.presence_penalty;
+    }
+}
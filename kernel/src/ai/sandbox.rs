@@ -0,0 +1,113 @@
+//! Model Sandbox
+//! Resource limits around a loaded model, so a large model cannot starve
+//! the phone.  Each sandbox is a CPU bandwidth group: inference may use a
+//! share of every period, and once it has used its share it sleeps until
+//! the next period refills it.  The share shrinks while the thermal
+//! subsystem is capping the CPU, and drops to the background share while
+//! the foreground app is busy.  The sandbox also carries the Memory
+//! capability its model's KV caches are charged to.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::kvcache::MemoryGrant;
+use crate::{arch, cpuidle, power, thermal};
+
+/// Set by the UI while the foreground app needs the CPU.
+static FOREGROUND_BUSY: AtomicBool = AtomicBool::new(false);
+
+pub fn set_foreground_busy(busy: bool) {
+    FOREGROUND_BUSY.store(busy, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone)]
+pub struct SandboxConfig {
+    /// Share of each period inference may use (1–100 %).
+    pub cpu_pct:        u8,
+    /// Share while the foreground app is busy.
+    pub background_pct: u8,
+    pub period_ms:      u64,
+    /// Memory capability for KV caches.
+    pub memory:         Option<MemoryGrant>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        SandboxConfig { cpu_pct: 100, background_pct: 25, period_ms: 100, memory: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SandboxStats {
+    pub cpu_us:       u64,
+    pub throttled_us: u64,
+    /// Times inference was made to wait for the next period.
+    pub throttles:    u64,
+}
+
+struct Bandwidth {
+    period_start: u64,
+    /// CPU ticks used in the current period, including debt carried over
+    /// from steps longer than the quota.
+    used:         u64,
+    stats:        SandboxStats,
+}
+
+pub struct Sandbox {
+    cfg:   SandboxConfig,
+    state: Mutex<Bandwidth>,
+}
+
+impl Sandbox {
+    pub fn new(cfg: SandboxConfig) -> Result<Self, &'static str> {
+        if !(1..=100).contains(&cfg.cpu_pct) || !(1..=100).contains(&cfg.background_pct) {
+            return Err("CPU share must be 1-100%");
+        }
+        if cfg.period_ms == 0 { return Err("zero bandwidth period"); }
+        let state = Bandwidth { period_start: arch::read_mtime(), used: 0, stats: SandboxStats::default() };
+        Ok(Sandbox { cfg, state: Mutex::new(state) })
+    }
+
+    pub fn memory(&self) -> Option<&MemoryGrant> {
+        self.cfg.memory.as_ref()
+    }
+
+    pub fn stats(&self) -> SandboxStats {
+        self.state.lock().stats
+    }
+
+    /// Share of each period allowed right now, after thermal and
+    /// foreground backpressure.
+    pub fn effective_pct(&self) -> u8 {
+        let mut pct = self.cfg.cpu_pct as usize;
+        if FOREGROUND_BUSY.load(Ordering::Relaxed) { pct = pct.min(self.cfg.background_pct as usize); }
+
+        // Scale by how far thermal trips have capped the clock
+        let levels = power::CPU_FREQ_TABLE.len();
+        let cap = thermal::limits().cpu_max_level.min(levels - 1);
+        pct = pct * (cap + 1) / levels;
+        pct.max(1) as u8
+    }
+
+    /// Charge `ticks` of inference CPU time, then block until the group is
+    /// back within its share.
+    pub fn charge(&self, ticks: u64) {
+        let period = arch::ms_to_ticks(self.cfg.period_ms);
+        let quota  = period * self.effective_pct() as u64 / 100;
+        let mut s = self.state.lock();
+        s.stats.cpu_us += arch::ticks_to_us(ticks);
+
+        // Each elapsed period pays off one quota of use
+        let now = arch::read_mtime();
+        let elapsed = (now - s.period_start) / period;
+        s.period_start += elapsed * period;
+        s.used = s.used.saturating_sub(elapsed * quota) + ticks;
+        if s.used <= quota || quota == period { return; }
+
+        let until = s.period_start + (s.used - quota).div_ceil(quota) * period;
+        s.stats.throttles += 1;
+        s.stats.throttled_us += arch::ticks_to_us(until.saturating_sub(now));
+        drop(s);
+        cpuidle::sleep_until(until);
+    }
+}
@@ -0,0 +1,820 @@
+//! AI Service (aid)
+//! Inference for userspace over IPC.  The kernel installs models with the
+//! service and grants clients an AI capability for a model; a client
+//! presents it to `connect` and gets a channel to `aid`.  On that channel
+//! it opens sessions and submits prompts, and the generated text streams
+//! back as notifications, one per token, followed by a summary when the
+//! generation ends.  Generation runs as deferred work, so it only uses
+//! CPU time nothing else wants.  If a client stops draining its channel,
+//! its stream pauses until it sends another request.
+//!
+//! Every session has its own KV cache over the shared model, and sessions
+//! take turns a slice of tokens at a time: the next slice goes to the
+//! ready session that has had the least CPU time, with the foreground
+//! app's time counted at a quarter so it gets most of the model.  While
+//! a thermal trip is active, turns shrink to fewer tokens, so inference
+//! comes in shorter bursts that the model's sandbox can space out.
+//!
+//! A session can name one of its model's LoRA adapters, and its prompts
+//! then run with that fine-tune applied; other sessions on the model are
+//! unaffected.
+//!
+//! Clients can also embed text for on-device search; embeddings are
+//! computed while the request waits and come back in the reply.
+//!
+//! A session on a speech model can listen instead: the kernel attaches a
+//! mic the client holds a READ capability for, and the transcript streams
+//! back as it is heard.  The mic is read as the client, so revoking its
+//! capability stops the transcription, and no audio outlives the features
+//! computed from it.
+//!
+//! Clients can queue batch jobs of prompts for later, for work like
+//! summarising mail overnight.  Jobs yield to every interactive session
+//! and only run while the device is charging and the user is idle (see
+//! `batch`); each prompt's result comes back as a completion event.
+//!
+//! A client granted `AI_ANY_MODEL` has each prompt routed by its detected
+//! language to an installed model that handles that language.
+//!
+//! The service never logs what it is asked or what it answers; content
+//! leaves it only in replies to the client, or in a debug capture (see
+//! `privacy`).
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use super::asr::Transcriber;
+use super::batch::{self, BatchJob};
+use super::embed::{EmbedOptions, Pooling};
+use super::langid::{self, Language};
+use super::privacy::{self, CaptureKind};
+use super::stream::{CancelToken, GenerateOptions, Generation, InferenceResponse, StopReason};
+use super::{manager, metrics, AiModel};
+use crate::{arch, audio, charger, power, thermal};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::driver::{self, DeviceId};
+use crate::ipc::{self, ChannelId, IpcError, Message, MessageKind, MAX_MESSAGE_SIZE};
+use crate::process::{self, ProcessId};
+
+/// Request opcodes (first payload byte).  Replies are [1, ...] on success
+/// and [0] on failure.
+pub const AI_REQ_OPEN:    u8 = 1; // [] -> [1, session u32]
+pub const AI_REQ_SUBMIT:  u8 = 2; // [session u32, max_tokens u16, prompt utf-8...]
+pub const AI_REQ_CANCEL:  u8 = 3; // [session u32]
+pub const AI_REQ_CLOSE:   u8 = 4; // [session u32]
+/// Nothing but a nudge: resumes a stream paused on a full channel.
+pub const AI_REQ_POLL:    u8 = 5; // []
+pub const AI_REQ_EMBED:   u8 = 6; // [pooling u8, count u8, (len u16, text utf-8)...]
+                                  //  -> [1, dim u32, count × dim f32]
+pub const AI_REQ_STATS:   u8 = 7; // [session u32] -> [1, requests u32, tokens u64, cpu_us u64,
+                                  //  wait_us u64, slices u64]
+/// Adapter for the session's later prompts; an empty name clears it.
+pub const AI_REQ_ADAPTER: u8 = 8; // [session u32, name utf-8...]
+/// Queue prompts to run while charging and idle.
+pub const AI_REQ_BATCH:   u8 = 9; // [max_tokens u16, count u8, (len u16, prompt utf-8)...] -> [1, job u32]
+pub const AI_REQ_BATCH_CANCEL: u8 = 10; // [job u32]
+
+/// Notification kinds (first payload byte).
+pub const AI_NOTIFY_TOKEN:      u8 = 1; // [session u32, text utf-8...]
+pub const AI_NOTIFY_DONE:       u8 = 2; // [session u32, stop u8, tokens u32, prompt_tokens u32,
+                                        //  prefill_us u64, decode_us u64, kv_bytes u32, kv_evicted u32,
+                                        //  language u8 (Language::index, 0xFF unknown)]
+pub const AI_NOTIFY_TRANSCRIPT: u8 = 3; // [session u32, text utf-8...]
+pub const AI_NOTIFY_LISTEN_END: u8 = 4; // [session u32, reason u8 (0 end of audio, 1 cancelled,
+                                        //  2 mic error), audio_ms u32]
+/// A batch prompt finished; the text is cut short if it would not fit.
+pub const AI_NOTIFY_BATCH_ITEM: u8 = 5; // [job u32, index u16, stop u8, text utf-8...]
+/// Every prompt of a batch job has been answered.
+pub const AI_NOTIFY_BATCH_DONE: u8 = 6; // [job u32, count u16]
+
+/// Model id in an AI capability that lets the service pick the model.
+pub const AI_ANY_MODEL: u32 = 0;
+
+/// Sessions one client may have open.
+const MAX_SESSIONS: usize = 4;
+/// Tokens a session generates per turn (when the SoC is cool).
+const TOKENS_PER_SLICE: usize = 4;
+/// Audio transcribed per turn of a listening session (200 ms).
+const SAMPLES_PER_SLICE: usize = 3200;
+/// The foreground app's CPU time counts this many times less.
+const FOREGROUND_WEIGHT: u64 = 4;
+
+pub fn stop_code(stop: Option<StopReason>) -> u8 {
+    match stop {
+        Some(StopReason::EndOfSequence) => 0,
+        Some(StopReason::StopSequence)  => 1,
+        Some(StopReason::MaxTokens)     => 2,
+        Some(StopReason::ContextFull)   => 3,
+        Some(StopReason::Cancelled)     => 4,
+        Some(StopReason::Error(_)) | None => 5,
+    }
+}
+
+// ─── state ────────────────────────────────────────────────────────────────────
+
+struct Client {
+    pid:     ProcessId,
+    channel: ChannelId,
+    /// The service's end of the channel.
+    cap:     Capability,
+    model:   u32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SessionStats {
+    /// Prompts submitted.
+    pub requests: u32,
+    pub tokens:   u64,
+    /// Time spent generating for this session.
+    pub cpu_us:   u64,
+    /// Time spent ready but waiting for other sessions' turns.
+    pub wait_us:  u64,
+    pub slices:   u64,
+}
+
+/// A mic attached to a session.
+struct Listen {
+    transcriber: Transcriber<'static>,
+    mic:         DeviceId,
+    /// The client's READ capability on the mic.
+    cap:         Capability,
+    owner:       ProcessId,
+    cancelled:   bool,
+}
+
+impl Drop for Listen {
+    fn drop(&mut self) {
+        let _ = process::run_as(self.owner, || driver::ioctl(self.mic, &self.cap, audio::AUDIO_IOC_STOP, 0));
+    }
+}
+
+struct Session {
+    id:       u32,
+    channel:  ChannelId,
+    owner:    ProcessId,
+    /// Runtime LoRA adapter prompts run with.
+    adapter:  Option<String>,
+    gen:      Option<Generation<'static>>,
+    listen:   Option<Listen>,
+    /// Listening, but the mic had nothing new last turn.
+    starved:  bool,
+    /// A notification the client's full channel has not taken yet.
+    backlog:  Option<Vec<u8>>,
+    /// Weighted CPU ticks received; the lowest ready session runs next.
+    vruntime: u64,
+    /// When the session last became ready to run.
+    ready_at: u64,
+    stats:    SessionStats,
+}
+
+impl Session {
+    fn is_ready(&self) -> bool {
+        self.backlog.is_none() && (self.gen.is_some() || self.listen.is_some() && !self.starved)
+    }
+
+    fn is_busy(&self) -> bool {
+        self.gen.is_some() || self.listen.is_some() || self.backlog.is_some()
+    }
+}
+
+struct AiService {
+    pid:          Option<ProcessId>,
+    models:       Vec<(u32, Arc<AiModel>)>,
+    clients:      Vec<Client>,
+    sessions:     Vec<Session>,
+    foreground:   Option<ProcessId>,
+    /// Lower bound of ready sessions' vruntime, so a session that was idle
+    /// does not come back owed the time it did not use.
+    min_vruntime: u64,
+    /// Batch jobs, oldest first.
+    jobs:         Vec<BatchJob>,
+    /// Where power-state notifications arrive.
+    power:        Option<ChannelId>,
+}
+
+static SERVICE: Mutex<AiService> = Mutex::new(AiService {
+    pid: None, models: Vec::new(), clients: Vec::new(), sessions: Vec::new(),
+    foreground: None, min_vruntime: 0, jobs: Vec::new(), power: None,
+});
+static NEXT_MODEL:   AtomicU32 = AtomicU32::new(1);
+static NEXT_SESSION: AtomicU32 = AtomicU32::new(1);
+/// Tokens per turn at the current temperature.
+static SLICE_TOKENS: AtomicUsize = AtomicUsize::new(TOKENS_PER_SLICE);
+
+// ─── kernel API ───────────────────────────────────────────────────────────────
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("aid")?;
+    let (power, _) = power::subscribe(pid)?;
+    {
+        let mut svc = SERVICE.lock();
+        svc.pid   = Some(pid);
+        svc.power = Some(power);
+    }
+    ipc::register_kernel_server(pid, handle_request);
+    audio::on_data(mic_ready);
+    thermal::on_change(thermal_changed);
+    charger::on_change(run_batch);
+    Ok(())
+}
+
+/// Make a loaded model available to clients; returns its model id.
+pub fn install_model(model: AiModel) -> Result<u32, &'static str> {
+    if !model.is_loaded() { return Err("model not loaded"); }
+    let id = reserve_model_id();
+    install_as(id, Arc::new(model));
+    Ok(id)
+}
+
+pub(super) fn reserve_model_id() -> u32 {
+    NEXT_MODEL.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Serve `model` as model `id`, replacing any model already under it.
+pub(super) fn install_as(id: u32, model: Arc<AiModel>) {
+    let mut svc = SERVICE.lock();
+    svc.models.retain(|(m, _)| *m != id);
+    svc.models.push((id, model));
+}
+
+pub(super) fn installed(id: u32) -> Option<Arc<AiModel>> {
+    SERVICE.lock().models.iter().find(|(m, _)| *m == id).map(|(_, m)| m.clone())
+}
+
+/// Stop serving model `id` if no generation is running on it.
+pub(super) fn unload_if_idle(id: u32) -> bool {
+    let mut svc = SERVICE.lock();
+    let Some(i) = svc.models.iter().position(|(m, _)| *m == id) else { return false };
+    if Arc::strong_count(&svc.models[i].1) > 1 { return false; }
+    svc.models.swap_remove(i);
+    true
+}
+
+/// Stop serving model `id`; generations already running keep it alive
+/// until they finish.
+pub(super) fn remove_model(id: u32) {
+    SERVICE.lock().models.retain(|(m, _)| *m != id);
+}
+
+/// Whether `id` names a model: installed, or catalogued by the model
+/// manager and loadable on demand.
+fn known(id: u32) -> bool {
+    id == AI_ANY_MODEL || installed(id).is_some() || manager::is_known(id)
+}
+
+/// Installed models as (id, name).
+pub fn models() -> Vec<(u32, String)> {
+    SERVICE.lock().models.iter().map(|(id, m)| (*id, m.name.clone())).collect()
+}
+
+/// Mint `client`'s capability to run `model` (or `AI_ANY_MODEL`).
+/// Whether the client should have it is the caller's policy decision.
+pub fn grant(client: ProcessId, model: u32) -> Result<Capability, &'static str> {
+    if !known(model) { return Err("no such model"); }
+    Ok(capability::create_capability(client, CapabilityType::Ai(model), Permissions::EXECUTE))
+}
+
+/// Open a channel from `client` to `aid` for the model named by its AI
+/// capability.
+pub fn connect(client: ProcessId, ai_cap: &Capability) -> Result<(ChannelId, Capability), &'static str> {
+    let CapabilityType::Ai(model) = ai_cap.cap_type else { return Err("not an AI capability") };
+    capability::validate(client, ai_cap, CapabilityType::Ai(model), Permissions::EXECUTE)?;
+    if !known(model) { return Err("no such model"); }
+    let pid = SERVICE.lock().pid.ok_or("AI service not running")?;
+    let (ch, client_cap, svc_cap) = ipc::create_channel(client, pid);
+    SERVICE.lock().clients.push(Client { pid: client, channel: ch, cap: svc_cap, model });
+    Ok((ch, client_cap))
+}
+
+/// Tell the scheduler which app is in the foreground.
+pub fn set_foreground(pid: Option<ProcessId>) {
+    SERVICE.lock().foreground = pid;
+}
+
+/// Every open session as (session id, owner, statistics).
+pub fn session_stats() -> Vec<(u32, ProcessId, SessionStats)> {
+    SERVICE.lock().sessions.iter().map(|s| (s.id, s.owner, s.stats)).collect()
+}
+
+/// Transcribe from the mic `mic_cap` names into `session` on `client`'s
+/// channel `ch`.  The client must hold READ on the mic, and its model (or
+/// for an any-model client, some installed model) must be a speech model.
+pub fn listen(client: ProcessId, ch: ChannelId, session: u32, mic_cap: &Capability) -> Result<(), &'static str> {
+    let CapabilityType::Device(mic) = mic_cap.cap_type else { return Err("not a device capability") };
+    capability::validate(client, mic_cap, mic_cap.cap_type, Permissions::READ)?;
+
+    let (model_id, found) = {
+        let svc = SERVICE.lock();
+        let c = svc.clients.iter().find(|c| c.channel == ch && c.pid == client).ok_or("not connected")?;
+        if c.model == AI_ANY_MODEL {
+            let m = svc.models.iter().find(|(_, m)| m.is_speech()).ok_or("no speech model installed")?;
+            (m.0, Some(m.1.clone()))
+        } else {
+            (c.model, svc.models.iter().find(|(id, _)| *id == c.model).map(|(_, m)| m.clone()))
+        }
+    };
+    let model = match found {
+        Some(m) => m,
+        None    => manager::load_id(model_id)?,
+    };
+    manager::touch(model_id);
+    let transcriber = model.transcriber_shared()?;
+
+    let mut svc = SERVICE.lock();
+    let s = svc.sessions.iter_mut().find(|s| s.channel == ch && s.id == session).ok_or("no such session")?;
+    if s.is_busy() { return Err("session busy"); }
+    process::run_as(client, || driver::ioctl(mic, mic_cap, audio::AUDIO_IOC_START, 0))?;
+    s.listen   = Some(Listen { transcriber, mic, cap: mic_cap.clone(), owner: client, cancelled: false });
+    s.starved  = false;
+    s.ready_at = arch::read_mtime();
+    s.stats.requests += 1;
+    drop(svc);
+    process::defer(pump);
+    Ok(())
+}
+
+/// Drop a client's channel and everything running on it.
+pub fn disconnect(channel: ChannelId) {
+    let mut svc = SERVICE.lock();
+    svc.sessions.retain(|s| s.channel != channel);
+    svc.jobs.retain(|j| j.channel != channel);
+    svc.clients.retain(|c| c.channel != channel);
+    drop(svc);
+    ipc::close_channel(channel);
+}
+
+// ─── requests ─────────────────────────────────────────────────────────────────
+
+fn session_id(p: &[u8]) -> Result<u32, &'static str> {
+    Ok(u32::from_le_bytes(p.get(1..5).ok_or("short request")?.try_into().unwrap()))
+}
+
+fn handle_request(ch: ChannelId, msg: &Message) -> Option<Vec<u8>> {
+    if SERVICE.lock().power == Some(ch) {
+        let (ack, runnable) = batch::power_event(&msg.payload);
+        if runnable { process::defer(run_batch); }
+        return ack;
+    }
+    let p = &msg.payload;
+    let result = match p.first() {
+        Some(&AI_REQ_OPEN)    => open(ch, msg.sender),
+        Some(&AI_REQ_SUBMIT)  => submit(ch, msg.sender, p),
+        Some(&AI_REQ_CANCEL)  => with_session(ch, msg.sender, p, |s| {
+            if let Some(gen) = &s.gen { gen.cancel_token().cancel(); }
+            if let Some(l) = &mut s.listen { l.cancelled = true; s.starved = false; }
+        }),
+        Some(&AI_REQ_CLOSE)   => session_id(p).map(|id| {
+            SERVICE.lock().sessions.retain(|s| !(s.channel == ch && s.id == id));
+            Vec::new()
+        }),
+        Some(&AI_REQ_POLL)    => Ok(Vec::new()),
+        Some(&AI_REQ_EMBED)   => embed(ch, msg.sender, p),
+        Some(&AI_REQ_ADAPTER) => match core::str::from_utf8(p.get(5..).unwrap_or(&[])) {
+            Ok(name) => with_session(ch, msg.sender, p, |s| s.adapter = (!name.is_empty()).then(|| String::from(name))),
+            Err(_)   => Err("adapter name is not UTF-8"),
+        },
+        Some(&AI_REQ_BATCH)   => submit_batch(ch, msg.sender, p),
+        Some(&AI_REQ_BATCH_CANCEL) => session_id(p).map(|id| {
+            SERVICE.lock().jobs.retain(|j| !(j.channel == ch && j.owner == msg.sender && j.id == id));
+            Vec::new()
+        }),
+        Some(&AI_REQ_STATS)   => {
+            let mut st = SessionStats::default();
+            with_session(ch, msg.sender, p, |s| st = s.stats).map(|_| {
+                let mut body = Vec::with_capacity(36);
+                body.extend_from_slice(&st.requests.to_le_bytes());
+                for v in [st.tokens, st.cpu_us, st.wait_us, st.slices] { body.extend_from_slice(&v.to_le_bytes()); }
+                body
+            })
+        }
+        _                     => Err("bad request"),
+    };
+    // Any request may mean the client has room for more notifications
+    process::defer(pump);
+    Some(match result {
+        Ok(body) => [&[1u8][..], &body].concat(),
+        Err(_)   => Vec::from([0u8]),
+    })
+}
+
+fn open(ch: ChannelId, sender: ProcessId) -> Result<Vec<u8>, &'static str> {
+    let mut svc = SERVICE.lock();
+    if !svc.clients.iter().any(|c| c.channel == ch && c.pid == sender) { return Err("not connected"); }
+    if svc.sessions.iter().filter(|s| s.channel == ch).count() >= MAX_SESSIONS { return Err("too many sessions"); }
+    let id = NEXT_SESSION.fetch_add(1, Ordering::Relaxed);
+    svc.sessions.push(Session {
+        id, channel: ch, owner: sender, adapter: None, gen: None, listen: None, starved: false, backlog: None,
+        vruntime: 0, ready_at: 0, stats: SessionStats::default(),
+    });
+    Ok(id.to_le_bytes().to_vec())
+}
+
+fn with_session(ch: ChannelId, sender: ProcessId, p: &[u8], f: impl FnOnce(&mut Session))
+    -> Result<Vec<u8>, &'static str>
+{
+    let id = session_id(p)?;
+    let mut svc = SERVICE.lock();
+    if !svc.clients.iter().any(|c| c.channel == ch && c.pid == sender) { return Err("not connected"); }
+    let s = svc.sessions.iter_mut().find(|s| s.channel == ch && s.id == id).ok_or("no such session")?;
+    f(s);
+    Ok(Vec::new())
+}
+
+fn submit(ch: ChannelId, sender: ProcessId, p: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let max_tokens = u16::from_le_bytes(p.get(5..7).ok_or("short request")?.try_into().unwrap());
+    let prompt = core::str::from_utf8(&p[7..]).map_err(|_| "prompt is not UTF-8")?;
+    let id = session_id(p)?;
+    let mut opts = GenerateOptions::new(max_tokens as usize);
+    opts.language = langid::detect(prompt).map(|d| d.language);
+    opts.adapter  = SERVICE.lock().sessions.iter().find(|s| s.channel == ch && s.id == id)
+        .ok_or("no such session")?.adapter.clone();
+    let model = client_model(ch, sender, opts.language)?;
+    // Tokenizing and setting up the cache happen outside the service lock
+    let gen = model.stream_shared(prompt, &opts, CancelToken::new())?;
+    let mut svc = SERVICE.lock();
+    let floor = svc.min_vruntime;
+    let s = svc.sessions.iter_mut().find(|s| s.channel == ch && s.id == id).ok_or("no such session")?;
+    if s.is_busy() { return Err("session busy"); }
+    s.gen      = Some(gen);
+    s.vruntime = s.vruntime.max(floor);
+    s.ready_at = arch::read_mtime();
+    s.stats.requests += 1;
+    privacy::record(id, CaptureKind::Prompt, prompt);
+    Ok(Vec::new())
+}
+
+/// Parse `count` length-prefixed UTF-8 strings from `rest`.
+fn texts(mut rest: &[u8], count: usize) -> Result<Vec<&str>, &'static str> {
+    let mut texts = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = u16::from_le_bytes(rest.get(..2).ok_or("short request")?.try_into().unwrap()) as usize;
+        let text = rest.get(2..2 + len).ok_or("short request")?;
+        texts.push(core::str::from_utf8(text).map_err(|_| "text is not UTF-8")?);
+        rest = &rest[2 + len..];
+    }
+    Ok(texts)
+}
+
+fn embed(ch: ChannelId, sender: ProcessId, p: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let pooling = Pooling::from_u8(*p.get(1).ok_or("short request")?).ok_or("bad pooling")?;
+    let count = *p.get(2).ok_or("short request")? as usize;
+    let texts = texts(&p[3..], count)?;
+    if texts.is_empty() { return Err("nothing to embed"); }
+
+    let model = client_model(ch, sender, langid::detect(texts[0]).map(|d| d.language))?;
+    let dim = model.transformer().ok_or("model not loaded")?.cfg.n_embd;
+    if 1 + 4 + count * dim * 4 > MAX_MESSAGE_SIZE { return Err("embeddings too large for one reply"); }
+
+    let opts = EmbedOptions { pooling, ..EmbedOptions::default() };
+    let mut body = Vec::with_capacity(4 + count * dim * 4);
+    body.extend_from_slice(&(dim as u32).to_le_bytes());
+    for v in model.embed_batch(&texts, &opts)? {
+        for x in v { body.extend_from_slice(&x.to_le_bytes()); }
+    }
+    Ok(body)
+}
+
+fn submit_batch(ch: ChannelId, sender: ProcessId, p: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let max_tokens = u16::from_le_bytes(p.get(1..3).ok_or("short request")?.try_into().unwrap());
+    let count = *p.get(3).ok_or("short request")? as usize;
+    let prompts = texts(&p[4..], count)?;
+    if prompts.is_empty() { return Err("nothing to run"); }
+
+    let mut svc = SERVICE.lock();
+    if !svc.clients.iter().any(|c| c.channel == ch && c.pid == sender) { return Err("not connected"); }
+    if svc.jobs.iter().filter(|j| j.channel == ch).count() >= batch::MAX_JOBS { return Err("too many batch jobs"); }
+    // Job ids come from the session counter so captures can tell them apart
+    let id = NEXT_SESSION.fetch_add(1, Ordering::Relaxed);
+    svc.jobs.push(batch::new_job(id, ch, sender, max_tokens as usize, &prompts));
+    for prompt in prompts { privacy::record(id, CaptureKind::Prompt, prompt); }
+    Ok(id.to_le_bytes().to_vec())
+}
+
+/// The model a request on `ch` runs on: the client's own, or for an
+/// any-model client the one routed to by `language`.  A catalogued model
+/// that is not loaded is loaded now.
+fn client_model(ch: ChannelId, sender: ProcessId, language: Option<Language>) -> Result<Arc<AiModel>, &'static str> {
+    let preferred = manager::preferred_id();
+    let (id, found) = {
+        let svc = SERVICE.lock();
+        let client = svc.clients.iter().find(|c| c.channel == ch && c.pid == sender).ok_or("not connected")?;
+        if client.model == AI_ANY_MODEL {
+            match route(&svc.models, language, preferred) {
+                Some((id, m)) => (id, Some(m)),
+                None          => (preferred.ok_or("no model for this language")?, None),
+            }
+        } else {
+            (client.model, svc.models.iter().find(|(m, _)| *m == client.model).map(|(_, m)| m.clone()))
+        }
+    };
+    let model = match found {
+        Some(m) => m,
+        None    => manager::load_id(id)?,
+    };
+    manager::touch(id);
+    Ok(model)
+}
+
+/// An installed model that handles `language`, the preferred one if it
+/// does; any model if the language could not be told.
+fn route(models: &[(u32, Arc<AiModel>)], language: Option<Language>, preferred: Option<u32>)
+    -> Option<(u32, Arc<AiModel>)>
+{
+    let fits = |m: &AiModel| language.is_none_or(|l| m.supports_language(l));
+    models.iter().filter(|(_, m)| fits(m))
+        .min_by_key(|(id, _)| Some(*id) != preferred)
+        .map(|(id, m)| (*id, m.clone()))
+}
+
+// ─── streaming ────────────────────────────────────────────────────────────────
+
+fn token_notification(session: u32, text: &str) -> Vec<u8> {
+    let mut n = Vec::with_capacity(5 + text.len());
+    n.push(AI_NOTIFY_TOKEN);
+    n.extend_from_slice(&session.to_le_bytes());
+    n.extend_from_slice(text.as_bytes());
+    n
+}
+
+fn done_notification(session: u32, r: &InferenceResponse) -> Vec<u8> {
+    let mut n = Vec::with_capacity(39);
+    n.push(AI_NOTIFY_DONE);
+    n.extend_from_slice(&session.to_le_bytes());
+    n.push(stop_code(r.stop));
+    n.extend_from_slice(&(r.tokens.len() as u32).to_le_bytes());
+    n.extend_from_slice(&(r.timings.prompt_tokens as u32).to_le_bytes());
+    n.extend_from_slice(&r.timings.prefill_us.to_le_bytes());
+    n.extend_from_slice(&r.timings.decode_us.to_le_bytes());
+    n.extend_from_slice(&(r.kv.bytes_reserved as u32).to_le_bytes());
+    n.extend_from_slice(&(r.kv.evicted_tokens as u32).to_le_bytes());
+    n.push(r.language.map_or(0xFF, Language::index));
+    n
+}
+
+fn transcript_notification(session: u32, text: &str) -> Vec<u8> {
+    let mut n = token_notification(session, text);
+    n[0] = AI_NOTIFY_TRANSCRIPT;
+    n
+}
+
+fn listen_end_notification(session: u32, reason: u8, audio_ms: u64) -> Vec<u8> {
+    let mut n = Vec::with_capacity(10);
+    n.push(AI_NOTIFY_LISTEN_END);
+    n.extend_from_slice(&session.to_le_bytes());
+    n.push(reason);
+    n.extend_from_slice(&(audio_ms as u32).to_le_bytes());
+    n
+}
+
+fn batch_item_notification(job: u32, index: u16, stop: u8, text: &str) -> Vec<u8> {
+    const HEADER: usize = 8;
+    let mut end = text.len().min(MAX_MESSAGE_SIZE - HEADER);
+    while !text.is_char_boundary(end) { end -= 1; }
+    let mut n = Vec::with_capacity(HEADER + end);
+    n.push(AI_NOTIFY_BATCH_ITEM);
+    n.extend_from_slice(&job.to_le_bytes());
+    n.extend_from_slice(&index.to_le_bytes());
+    n.push(stop);
+    n.extend_from_slice(&text.as_bytes()[..end]);
+    n
+}
+
+fn batch_done_notification(job: u32, count: u16) -> Vec<u8> {
+    let mut n = Vec::with_capacity(7);
+    n.push(AI_NOTIFY_BATCH_DONE);
+    n.extend_from_slice(&job.to_le_bytes());
+    n.extend_from_slice(&count.to_le_bytes());
+    n
+}
+
+/// Send the session's pending notification.  False if it is still
+/// pending or the channel is gone.
+fn deliver(s: &mut Session, from: ProcessId, cap: &Capability, dead: &mut Vec<ChannelId>) -> bool {
+    send_backlog(s.channel, &mut s.backlog, from, cap, dead)
+}
+
+fn send_backlog(ch: ChannelId, backlog: &mut Option<Vec<u8>>, from: ProcessId, cap: &Capability,
+                dead: &mut Vec<ChannelId>) -> bool
+{
+    let Some(note) = backlog.take() else { return true };
+    match ipc::send_message(ch, from, cap, MessageKind::Notification, &note) {
+        Ok(())                    => true,
+        Err(IpcError::BufferFull) => { *backlog = Some(note); false }
+        Err(_)                    => { dead.push(ch); false }
+    }
+}
+
+/// Deliver pending notifications, then give one slice to the ready
+/// session with the lowest vruntime.  Reschedules itself while there is
+/// progress to make.
+fn pump() {
+    let mut svc = SERVICE.lock();
+    let Some(pid) = svc.pid else { return };
+    let AiService { clients, sessions, foreground, min_vruntime, .. } = &mut *svc;
+    let mut progressed = false;
+    let mut dead = Vec::new();
+
+    for s in sessions.iter_mut() {
+        let Some(client) = clients.iter().find(|c| c.channel == s.channel) else { continue };
+        let pending = s.backlog.is_some();
+        if deliver(s, pid, &client.cap, &mut dead) && pending {
+            progressed = true;
+            if s.gen.is_some() || s.listen.is_some() { s.ready_at = arch::read_mtime(); }
+        }
+    }
+
+    let next = sessions.iter_mut().filter(|s| s.is_ready()).min_by_key(|s| s.vruntime);
+    if let Some(s) = next {
+        if let Some(client) = clients.iter().find(|c| c.channel == s.channel) {
+            let start = arch::read_mtime();
+            s.stats.wait_us += arch::ticks_to_us(start.saturating_sub(s.ready_at));
+            s.stats.slices  += 1;
+            if s.gen.is_some() {
+                generate_slice(s, pid, &client.cap, &mut dead);
+            } else {
+                listen_slice(s, pid, &client.cap, &mut dead);
+            }
+            let now = arch::read_mtime();
+            let weight = if *foreground == Some(s.owner) { FOREGROUND_WEIGHT } else { 1 };
+            s.vruntime      += (now - start) / weight;
+            s.stats.cpu_us  += arch::ticks_to_us(now - start);
+            s.ready_at       = now;
+            progressed = true;
+        }
+    }
+    if let Some(min) = sessions.iter().filter(|s| s.is_ready()).map(|s| s.vruntime).min() {
+        *min_vruntime = (*min_vruntime).max(min);
+    }
+    let active = sessions.iter().any(|s| s.gen.is_some() || s.backlog.is_some() || s.is_ready());
+    let idle = !sessions.iter().any(Session::is_ready) && !svc.jobs.is_empty();
+    drop(svc);
+
+    for ch in dead { disconnect(ch); }
+    if active && progressed { process::defer(pump); }
+    // Batch jobs get whatever interactive sessions leave
+    if idle { process::defer(run_batch); }
+}
+
+fn generate_slice(s: &mut Session, from: ProcessId, cap: &Capability, dead: &mut Vec<ChannelId>) {
+    for _ in 0..SLICE_TOKENS.load(Ordering::Relaxed) {
+        if !deliver(s, from, cap, dead) { break; }
+        let Some(gen) = &mut s.gen else { break };
+        match gen.next() {
+            Some(ev) => {
+                s.stats.tokens += 1;
+                if !ev.text.is_empty() { s.backlog = Some(token_notification(s.id, &ev.text)); }
+            }
+            None => {
+                let done = s.gen.take().unwrap().finish();
+                privacy::record(s.id, CaptureKind::Completion, &done.text);
+                s.backlog = Some(done_notification(s.id, &done));
+            }
+        }
+    }
+}
+
+/// Transcribe what the mic has captured since the session's last turn.
+fn listen_slice(s: &mut Session, from: ProcessId, cap: &Capability, dead: &mut Vec<ChannelId>) {
+    if !deliver(s, from, cap, dead) { return; }
+    let Some(l) = &mut s.listen else { return };
+
+    let mut buf = vec![0u8; SAMPLES_PER_SLICE * 2];
+    let read = if l.cancelled { Ok(0) } else {
+        process::run_as(l.owner, || driver::read(l.mic, &l.cap, &mut buf))
+    };
+    let mut samples: Vec<i16> = buf[..*read.as_ref().unwrap_or(&0)].as_chunks::<2>().0.iter()
+        .map(|b| i16::from_le_bytes(*b)).collect();
+    buf.fill(0);
+
+    let mut end = match read {
+        _ if l.cancelled => Some(1),
+        Err(_)           => Some(2),
+        Ok(0) => match process::run_as(l.owner, || driver::ioctl(l.mic, &l.cap, audio::AUDIO_IOC_ENDED, 0)) {
+            Ok(1)  => Some(0),
+            Ok(_)  => { s.starved = true; return; }
+            Err(_) => Some(2),
+        },
+        Ok(_) => None,
+    };
+    if !samples.is_empty() {
+        match l.transcriber.push(&samples) {
+            Ok(text) if !text.is_empty() => s.backlog = Some(transcript_notification(s.id, &text)),
+            Ok(_)                        => {}
+            Err(_)                       => end = Some(2),
+        }
+        samples.fill(0);
+    }
+    // The end is reported once any transcript ahead of it is delivered
+    if let (Some(reason), None) = (end, &s.backlog) {
+        let l = s.listen.take().unwrap();
+        privacy::record(s.id, CaptureKind::Transcript, l.transcriber.text());
+        s.backlog = Some(listen_end_notification(s.id, reason, l.transcriber.audio_ms()));
+    }
+}
+
+/// A mic has new audio: wake listening sessions.
+fn mic_ready() {
+    for s in SERVICE.lock().sessions.iter_mut() { s.starved = false; }
+    pump();
+}
+
+/// Halve the turn at a passive trip, and go a token at a time once hot.
+fn thermal_changed() {
+    let tokens = match thermal::severity() {
+        None                             => TOKENS_PER_SLICE,
+        Some(thermal::TripKind::Passive) => TOKENS_PER_SLICE / 2,
+        Some(_)                          => 1,
+    };
+    if SLICE_TOKENS.swap(tokens, Ordering::Relaxed) != tokens { metrics::record_thermal_step(); }
+}
+
+/// Tokens each session generates per turn right now.
+pub fn slice_tokens() -> usize {
+    SLICE_TOKENS.load(Ordering::Relaxed)
+}
+
+// ─── batch jobs ───────────────────────────────────────────────────────────────
+
+/// Deliver finished batch results, then, if no session is ready and the
+/// device is charging with the user idle, give the oldest runnable job a
+/// slice.  A job's next prompt is started outside the service lock, since
+/// its model may have to be loaded.  Reschedules itself while it makes
+/// progress.
+fn run_batch() {
+    let mut dead = Vec::new();
+    let mut progressed = false;
+
+    let start = {
+        let mut svc = SERVICE.lock();
+        let Some(pid) = svc.pid else { return };
+        let AiService { clients, sessions, jobs, .. } = &mut *svc;
+        for j in jobs.iter_mut() {
+            let Some(client) = clients.iter().find(|c| c.channel == j.channel) else { continue };
+            let pending = j.backlog.is_some();
+            if !send_backlog(j.channel, &mut j.backlog, pid, &client.cap, &mut dead) { continue; }
+            progressed |= pending;
+            if j.is_done() && !j.finished {
+                j.backlog  = Some(batch_done_notification(j.id, j.index));
+                j.finished = true;
+                progressed |= send_backlog(j.channel, &mut j.backlog, pid, &client.cap, &mut dead);
+            }
+        }
+        jobs.retain(|j| !(j.finished && j.backlog.is_none()));
+
+        if sessions.iter().any(Session::is_ready) || !batch::allowed() {
+            None
+        } else {
+            match jobs.iter_mut().find(|j| j.is_ready()) {
+                Some(j) if j.gen.is_none() => j.next_prompt().map(|p| (j.id, j.channel, j.owner, j.max_tokens, p)),
+                Some(j) => {
+                    let client = clients.iter().find(|c| c.channel == j.channel).unwrap();
+                    batch_slice(j, pid, &client.cap, &mut dead);
+                    progressed = true;
+                    None
+                }
+                None => None,
+            }
+        }
+    };
+
+    if let Some((id, ch, owner, max_tokens, prompt)) = start {
+        let prompt = core::str::from_utf8(&prompt).unwrap_or("");
+        let mut opts = GenerateOptions::new(max_tokens);
+        opts.language = langid::detect(prompt).map(|d| d.language);
+        let gen = client_model(ch, owner, opts.language)
+            .and_then(|m| m.stream_shared(prompt, &opts, CancelToken::new()))
+            .ok();
+        let mut svc = SERVICE.lock();
+        // The job may have been cancelled meanwhile
+        if let Some(j) = svc.jobs.iter_mut().find(|j| j.id == id) {
+            if gen.is_none() {
+                j.backlog = Some(batch_item_notification(id, j.index, stop_code(None), ""));
+                j.index  += 1;
+            }
+            j.started(gen);
+        }
+        progressed = true;
+    }
+
+    for ch in dead { disconnect(ch); }
+    if progressed { process::defer(run_batch); }
+}
+
+/// Generate a slice of a batch job's running prompt; its result is queued
+/// as one event when it finishes.
+fn batch_slice(j: &mut BatchJob, from: ProcessId, cap: &Capability, dead: &mut Vec<ChannelId>) {
+    for _ in 0..SLICE_TOKENS.load(Ordering::Relaxed) {
+        let Some(gen) = &mut j.gen else { break };
+        if gen.next().is_some() { continue; }
+        let done = j.gen.take().unwrap().finish();
+        privacy::record(j.id, CaptureKind::Completion, &done.text);
+        j.backlog = Some(batch_item_notification(j.id, j.index, stop_code(done.stop), &done.text));
+        j.index  += 1;
+        send_backlog(j.channel, &mut j.backlog, from, cap, dead);
+        break;
+    }
+}
@@ -0,0 +1,375 @@
+//! Streaming Generation
+//! Token-at-a-time output so a chat UI can show text as it is produced.
+//! A `Generation` is a pull-based iterator of `TokenEvent`s; each event
+//! carries the text the token completes and how long it took.  Text is
+//! only released on UTF-8 character boundaries, since a single Devanagari
+//! character is often spread over several byte tokens.  Generation stops
+//! at end-of-sequence, a stop sequence, the token limit, a full context,
+//! or cancellation, and everything produced up to that point stays
+//! available.  KV memory
+//! is charged to the Memory capability in the request's options, if any.
+//! The prompt, logits and text being built are kept in zero-on-free memory.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::kvcache::{KvCache, KvPrecision, KvUsage, MemoryGrant};
+use super::langid::Language;
+use super::lora::LoraAdapter;
+use super::metrics;
+use super::sampler::{self, SamplerConfig};
+use super::AiModel;
+use crate::arch;
+use crate::memory::{self, SecureVec, ZeroOnFree};
+
+/// Shared flag that stops a generation at the next token.  Clones refer
+/// to the same flag, so it can be handed to whoever may need to cancel.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    EndOfSequence,
+    StopSequence,
+    MaxTokens,
+    ContextFull,
+    Cancelled,
+    Error(&'static str),
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenEvent {
+    pub token:      u32,
+    /// Position in the output, from 0.
+    pub index:      usize,
+    /// Text completed by this token; empty while a character is still
+    /// partial.
+    pub text:       String,
+    /// Time since the previous event (for the first token: time to first
+    /// token, including the prompt).
+    pub elapsed_us: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    pub prefill_us:    u64,
+    pub prompt_tokens: usize,
+    pub decode_us:     u64,
+    pub tokens:        usize,
+    /// Slowest single token.
+    pub max_token_us:  u64,
+    /// Time spent waiting on the model's sandbox CPU share.
+    pub throttled_us:  u64,
+}
+
+impl Timings {
+    /// Decoding speed; 0 before any token.
+    pub fn tokens_per_sec(&self) -> u64 {
+        (self.tokens as u64 * 1_000_000).checked_div(self.decode_us).unwrap_or(0)
+    }
+}
+
+/// Settings for one generation request.
+#[derive(Debug, Clone, Default)]
+pub struct GenerateOptions {
+    pub max_tokens:   usize,
+    pub kv_precision: KvPrecision,
+    /// Memory capability the KV cache is charged to.  Requests on behalf
+    /// of processes must carry one; `None` is for kernel-internal use.
+    pub memory:       Option<MemoryGrant>,
+    pub sampling:     SamplerConfig,
+    /// Language of the prompt; `None` detects it.
+    pub language:     Option<Language>,
+    /// Runtime LoRA adapter of the model to generate with, by name.
+    pub adapter:      Option<String>,
+}
+
+impl GenerateOptions {
+    pub fn new(max_tokens: usize) -> Self {
+        GenerateOptions { max_tokens, ..Self::default() }
+    }
+}
+
+/// Final or partial result of a generation.
+#[derive(Debug, Clone)]
+pub struct InferenceResponse {
+    pub text:    String,
+    pub tokens:  Vec<u32>,
+    pub stop:    Option<StopReason>,
+    pub timings: Timings,
+    pub kv:      KvUsage,
+    /// Language the prompt was taken to be in.
+    pub language: Option<Language>,
+}
+
+/// The model a generation runs on: borrowed from the caller, or shared
+/// with a service so the generation can outlive the call that started it.
+pub(super) enum ModelRef<'m> {
+    Borrowed(&'m AiModel),
+    Shared(Arc<AiModel>),
+}
+
+impl core::ops::Deref for ModelRef<'_> {
+    type Target = AiModel;
+
+    fn deref(&self) -> &AiModel {
+        match self {
+            ModelRef::Borrowed(m) => m,
+            ModelRef::Shared(m)   => m,
+        }
+    }
+}
+
+pub struct Generation<'m> {
+    model:      ModelRef<'m>,
+    /// Keeps the adapter alive if it is unloaded mid-generation.
+    adapter:    Option<Arc<LoraAdapter>>,
+    prompt:     SecureVec<u32>,
+    language:   Option<Language>,
+    cache:      KvCache,
+    logits:     SecureVec<f32>,
+    max_tokens: usize,
+    sampling:   SamplerConfig,
+    cancel:     CancelToken,
+    tokens:     SecureVec<u32>,
+    /// Always valid UTF-8.
+    text:       SecureVec<u8>,
+    /// Bytes of `text` handed out in events so far.
+    emitted:    usize,
+    /// Bytes of a character not yet complete.
+    pending:    SecureVec<u8>,
+    stop:       Option<StopReason>,
+    timings:    Timings,
+    last_at:    u64,
+}
+
+impl<'m> Generation<'m> {
+    pub(super) fn new(
+        model: ModelRef<'m>, mut prompt: Vec<u32>, language: Option<Language>, opts: &GenerateOptions, cancel: CancelToken,
+    ) -> Result<Self, &'static str> {
+        let Some(t) = &model.transformer else { return Err("model not loaded") };
+        if prompt.is_empty() { return Err("empty prompt"); }
+        opts.sampling.validate()?;
+        let adapter = match &opts.adapter {
+            Some(name) => Some(model.adapter(name).ok_or("model has no such adapter")?),
+            None       => None,
+        };
+        let cache  = KvCache::new(&t.cfg, opts.kv_precision, opts.memory.as_ref().or(model.sandbox_memory()))?;
+        let logits = memory::secure_vec(0.0, t.cfg.n_vocab);
+        let mut secure = Vec::with_capacity_in(prompt.len(), ZeroOnFree);
+        secure.extend_from_slice(&prompt);
+        memory::wipe(&mut prompt);
+        Ok(Generation {
+            cache, logits,
+            prompt:  secure,
+            tokens:  Vec::new_in(ZeroOnFree),
+            text:    Vec::new_in(ZeroOnFree),
+            emitted: 0,
+            pending: Vec::new_in(ZeroOnFree),
+            stop:    None,
+            timings: Timings { prompt_tokens: prompt.len(), ..Timings::default() },
+            last_at: 0,
+            max_tokens: opts.max_tokens,
+            sampling:   opts.sampling.clone(),
+            model, adapter, language, cancel,
+        })
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop
+    }
+
+    /// Text produced so far.
+    pub fn text(&self) -> &str {
+        as_str(&self.text)
+    }
+
+    pub fn tokens(&self) -> &[u32] {
+        &self.tokens
+    }
+
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    pub fn kv_usage(&self) -> KvUsage {
+        self.cache.usage()
+    }
+
+    pub fn language(&self) -> Option<Language> {
+        self.language
+    }
+
+    /// Run to completion (or cancellation) and return the result.
+    pub fn finish(mut self) -> InferenceResponse {
+        for _ in self.by_ref() {}
+        let kv = self.cache.usage();
+        InferenceResponse {
+            text:    String::from(self.text()),
+            tokens:  self.tokens.to_vec(),
+            stop:    self.stop,
+            timings: self.timings,
+            kv,
+            language: self.language,
+        }
+    }
+
+    fn forward(&mut self, token: u32) -> Result<(), &'static str> {
+        let (Some(g), Some(t)) = (&self.model.weights, &self.model.transformer) else {
+            return Err("model not loaded");
+        };
+        let start = arch::read_mtime();
+        t.forward(g, self.adapter.as_deref(), &mut self.cache, token, &mut self.logits)?;
+        if let Some(sandbox) = &self.model.sandbox {
+            let now = arch::read_mtime();
+            sandbox.charge(now - start);
+            let throttled = arch::ticks_to_us(arch::read_mtime() - now);
+            self.timings.throttled_us += throttled;
+            metrics::record_throttle(throttled);
+        }
+        Ok(())
+    }
+
+    fn prefill(&mut self) -> Result<(), &'static str> {
+        let start = arch::read_mtime();
+        for i in 0..self.prompt.len() {
+            self.forward(self.prompt[i])?;
+        }
+        self.timings.prefill_us = arch::ticks_to_us(arch::read_mtime() - start);
+        metrics::record_prefill(self.prompt.len(), self.timings.prefill_us);
+        Ok(())
+    }
+
+    /// Move the longest complete UTF-8 prefix of `pending` into the text.
+    fn release_text(&mut self) {
+        let valid = match core::str::from_utf8(&self.pending) {
+            Ok(s)  => s.len(),
+            Err(e) => match e.error_len() {
+                // Invalid bytes will never complete; emit them replaced
+                Some(_) => self.pending.len(),
+                None    => e.valid_up_to(),
+            },
+        };
+        self.text.extend_from_slice(String::from_utf8_lossy(&self.pending[..valid]).as_bytes());
+        self.pending.copy_within(valid.., 0);
+        self.pending.truncate(self.pending.len() - valid);
+    }
+
+    /// Text not yet handed out that can no longer be part of a stop
+    /// sequence, and whether a stop sequence has appeared.  The output is
+    /// cut before the stop sequence.
+    fn take_output(&mut self) -> (String, bool) {
+        let stops = &self.sampling.stop;
+        // Everything before `emitted` was ruled out as the start of a match
+        let found = stops.iter().filter_map(|s| as_str(&self.text)[self.emitted..].find(s.as_str())).min();
+        let end = match found {
+            Some(at) => {
+                self.text.truncate(self.emitted + at);
+                self.text.len()
+            }
+            None => {
+                let unsent = &as_str(&self.text)[self.emitted..];
+                let hold = stops.iter()
+                    .filter_map(|s| (1..s.len().min(unsent.len() + 1)).rev()
+                        .find(|&k| s.is_char_boundary(k) && unsent.ends_with(&s[..k])))
+                    .max()
+                    .unwrap_or(0);
+                self.text.len() - hold
+            }
+        };
+        let out = String::from(&as_str(&self.text)[self.emitted..end]);
+        self.emitted = end;
+        (out, found.is_some())
+    }
+
+    fn step(&mut self) -> Result<TokenEvent, StopReason> {
+        if self.cancel.is_cancelled() { return Err(StopReason::Cancelled); }
+        if self.cache.positions() == 0 {
+            self.last_at = arch::read_mtime();
+            self.prefill().map_err(StopReason::Error)?;
+        }
+        if self.tokens.len() >= self.max_tokens { return Err(StopReason::MaxTokens); }
+
+        // Feed back the previous token to get the logits for this one
+        if let Some(&last) = self.tokens.last() {
+            if self.cache.is_full() { return Err(StopReason::ContextFull); }
+            self.forward(last).map_err(StopReason::Error)?;
+        }
+
+        let tok = self.model.tokenizer.as_ref().ok_or(StopReason::Error("model not loaded"))?;
+        let recent = self.tokens.iter().rev().chain(self.prompt.iter().rev());
+        let next = sampler::sample(&self.sampling, &mut self.logits, recent);
+        if Some(next) == tok.eos { return Err(StopReason::EndOfSequence); }
+
+        tok.token_bytes(next, &mut self.pending);
+        if self.tokens.is_empty() && tok.add_space_prefix() && self.pending.first() == Some(&b' ') {
+            self.pending.remove(0);
+        }
+        self.tokens.push(next);
+        self.release_text();
+        let (text, stopped) = self.take_output();
+        if stopped {
+            // This event is the last; the rest of the token is dropped
+            self.stop = Some(StopReason::StopSequence);
+            self.pending.clear();
+        }
+
+        let now = arch::read_mtime();
+        let elapsed_us = arch::ticks_to_us(now - self.last_at);
+        self.last_at = now;
+        let token_us = match self.tokens.len() {
+            1 => elapsed_us.saturating_sub(self.timings.prefill_us),
+            _ => elapsed_us,
+        };
+        self.timings.tokens       += 1;
+        self.timings.decode_us    += token_us;
+        self.timings.max_token_us  = self.timings.max_token_us.max(token_us);
+        metrics::record_token(token_us);
+        Ok(TokenEvent { token: next, index: self.tokens.len() - 1, text, elapsed_us })
+    }
+}
+
+impl Iterator for Generation<'_> {
+    type Item = TokenEvent;
+
+    fn next(&mut self) -> Option<TokenEvent> {
+        if self.stop.is_some() { return None; }
+        match self.step() {
+            Ok(ev) => Some(ev),
+            Err(reason) => {
+                self.stop = Some(reason);
+                // Flush a dangling partial character
+                if !self.pending.is_empty() {
+                    self.text.extend_from_slice(String::from_utf8_lossy(&self.pending).as_bytes());
+                    self.pending.clear();
+                }
+                None
+            }
+        }
+    }
+}
+
+/// The generated text; it only ever has whole characters appended.
+fn as_str(text: &[u8]) -> &str {
+    core::str::from_utf8(text).unwrap_or_default()
+}
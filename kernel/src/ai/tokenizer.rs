@@ -0,0 +1,393 @@
+//! Tokenizer
+//! Text ↔ token conversion using the vocabulary stored in the model file.
+//! Three algorithms cover the models we ship:
+//!   - SentencePiece BPE ("llama"): merge the adjacent pair that forms the
+//!     highest-scoring piece until no pair is in the vocabulary.
+//!   - SentencePiece unigram ("t5"): Viterbi search for the segmentation
+//!     with the best total log-probability.
+//!   - Byte-level BPE ("gpt2"): apply the ranked merge list to each
+//!     pre-tokenized word.
+//!
+//! Input is NFC-normalised for the Indic scripts first, and characters
+//! missing from the vocabulary fall back to byte tokens, so no text is
+//! ever lost.  Control tokens are never matched from text, so a prompt
+//! cannot forge them.
+
+use alloc::alloc::Allocator;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::gguf::{GgufFile, Value};
+use super::indic;
+
+/// SentencePiece word-boundary marker "▁".
+const SPM_SPACE: char = '\u{2581}';
+
+// tokenizer.ggml.token_type values
+const TOKEN_NORMAL:       u8 = 1;
+const TOKEN_CONTROL:      u8 = 3;
+const TOKEN_USER_DEFINED: u8 = 4;
+const TOKEN_UNUSED:       u8 = 5;
+const TOKEN_BYTE:         u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    SentencePieceBpe,
+    Unigram,
+    Bpe,
+}
+
+pub struct Tokenizer {
+    pub algorithm:    Algorithm,
+    pieces:           Vec<String>,
+    scores:           Vec<f32>,
+    types:            Vec<u8>,
+    lookup:           BTreeMap<String, u32>,
+    /// (left, right) → (rank, merged token); byte-level BPE only.
+    merges:           BTreeMap<(u32, u32), (u32, u32)>,
+    byte_tokens:      [Option<u32>; 256],
+    max_piece_len:    usize,
+    add_space_prefix: bool,
+    pub bos:          Option<u32>,
+    pub eos:          Option<u32>,
+    pub unk:          Option<u32>,
+}
+
+// ─── byte-level BPE alphabet ──────────────────────────────────────────────────
+//
+// GPT-2 vocabularies spell raw bytes as printable characters: printable
+// Latin-1 bytes stand for themselves, the rest are shifted to U+0100 up.
+
+fn byte_to_char(b: u8) -> char {
+    let cp = match b {
+        b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF => b as u32,
+        0x00..=0x20 => 0x100 + b as u32,
+        0x7F..=0xA0 => 0x100 + 33 + (b - 0x7F) as u32,
+        _           => 0x100 + 67, // 0xAD
+    };
+    char::from_u32(cp).unwrap_or('?')
+}
+
+fn char_to_byte(c: char) -> Option<u8> {
+    let cp = c as u32;
+    match cp {
+        0x21..=0x7E | 0xA1..=0xAC | 0xAE..=0xFF => Some(cp as u8),
+        0x100..=0x120 => Some((cp - 0x100) as u8),
+        0x121..=0x142 => Some((cp - 0x121 + 0x7F) as u8),
+        0x143         => Some(0xAD),
+        _             => None,
+    }
+}
+
+/// "<0xAB>" → 0xAB
+fn parse_byte_piece(p: &str) -> Option<u8> {
+    let hex = p.strip_prefix("<0x")?.strip_suffix('>')?;
+    if hex.len() != 2 { return None; }
+    u8::from_str_radix(hex, 16).ok()
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum CharClass { Letter, Number, Space, Other }
+
+fn classify(c: char) -> CharClass {
+    if c.is_whitespace() { CharClass::Space }
+    // Matras, virama and joiners stay with their letters so conjuncts
+    // are not torn apart
+    else if c.is_alphabetic() || indic::is_mark(c) { CharClass::Letter }
+    else if c.is_numeric() { CharClass::Number }
+    else { CharClass::Other }
+}
+
+/// GPT-2 pre-tokenization: contractions, an optional leading space plus a
+/// run of letters, digits or punctuation, and whitespace runs (leaving the
+/// last space to the following word).
+fn pretokenize(text: &str) -> Vec<&str> {
+    const CONTRACTIONS: [&str; 7] = ["'s", "'t", "'re", "'ve", "'m", "'ll", "'d"];
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let at = |k: usize| chars.get(k).map_or(text.len(), |c| c.0);
+    let mut words = Vec::new();
+    let mut k = 0;
+    while k < chars.len() {
+        let start = chars[k].0;
+        if let Some(c) = CONTRACTIONS.iter().find(|c| text[start..].starts_with(**c)) {
+            words.push(&text[start..start + c.len()]);
+            k += c.len();
+            continue;
+        }
+        let mut j = k;
+        if chars[k].1 == ' ' && chars.get(k + 1).is_some_and(|c| !c.1.is_whitespace()) { j += 1; }
+        let class = classify(chars[j].1);
+        let mut e = j + 1;
+        while e < chars.len() && classify(chars[e].1) == class { e += 1; }
+        if class == CharClass::Space && e < chars.len() && e - j > 1 { e -= 1; }
+        words.push(&text[start..at(e)]);
+        k = e;
+    }
+    words
+}
+
+// ─── construction ─────────────────────────────────────────────────────────────
+
+fn array<'a>(g: &'a GgufFile, key: &str) -> Option<&'a [Value]> {
+    g.get(key).and_then(Value::as_array)
+}
+
+impl Tokenizer {
+    /// Build the tokenizer described by a model's `tokenizer.ggml.*`
+    /// metadata.
+    pub fn from_gguf(g: &GgufFile) -> Result<Self, &'static str> {
+        let algorithm = match g.get_str("tokenizer.ggml.model") {
+            Some("llama")          => Algorithm::SentencePieceBpe,
+            Some("t5" | "unigram") => Algorithm::Unigram,
+            Some("gpt2")           => Algorithm::Bpe,
+            Some(_)                => return Err("unsupported tokenizer model"),
+            None                   => return Err("model has no tokenizer"),
+        };
+
+        let pieces: Vec<String> = array(g, "tokenizer.ggml.tokens").ok_or("model has no vocabulary")?
+            .iter().map(|v| v.as_str().map(String::from)).collect::<Option<_>>()
+            .ok_or("vocabulary entry is not a string")?;
+        if pieces.is_empty() || pieces.len() > u32::MAX as usize { return Err("bad vocabulary size"); }
+
+        let scores: Vec<f32> = match array(g, "tokenizer.ggml.scores") {
+            Some(a) if a.len() == pieces.len() => a.iter().map(|v| v.as_f32().unwrap_or(0.0)).collect(),
+            Some(_) => return Err("score count does not match vocabulary"),
+            None if algorithm == Algorithm::Bpe => vec![0.0; pieces.len()],
+            None => return Err("sentencepiece vocabulary has no scores"),
+        };
+        let types: Vec<u8> = match array(g, "tokenizer.ggml.token_type") {
+            Some(a) if a.len() == pieces.len() => a.iter().map(|v| v.as_u64().unwrap_or(1) as u8).collect(),
+            Some(_) => return Err("token type count does not match vocabulary"),
+            None    => vec![TOKEN_NORMAL; pieces.len()],
+        };
+
+        let mut lookup = BTreeMap::new();
+        for (id, p) in pieces.iter().enumerate() {
+            lookup.entry(p.clone()).or_insert(id as u32);
+        }
+
+        let mut byte_tokens = [None; 256];
+        if algorithm == Algorithm::Bpe {
+            for b in 0..=255u8 {
+                let mut buf = [0u8; 4];
+                byte_tokens[b as usize] = lookup.get(&*byte_to_char(b).encode_utf8(&mut buf)).copied();
+            }
+        } else {
+            for (id, p) in pieces.iter().enumerate() {
+                if let Some(b) = parse_byte_piece(p) { byte_tokens[b as usize].get_or_insert(id as u32); }
+            }
+        }
+
+        let mut merges = BTreeMap::new();
+        if algorithm == Algorithm::Bpe {
+            let list = array(g, "tokenizer.ggml.merges").ok_or("bpe vocabulary has no merges")?;
+            for (rank, m) in list.iter().enumerate() {
+                let Some((l, r)) = m.as_str().and_then(|s| s.split_once(' ')) else { continue };
+                let mut both = String::from(l);
+                both.push_str(r);
+                if let (Some(&li), Some(&ri), Some(&mi)) = (lookup.get(l), lookup.get(r), lookup.get(&both)) {
+                    merges.entry((li, ri)).or_insert((rank as u32, mi));
+                }
+            }
+        }
+
+        let id = |key: &str| g.get_u64(key).filter(|&i| (i as usize) < pieces.len()).map(|i| i as u32);
+        let bos = id("tokenizer.ggml.bos_token_id");
+        let eos = id("tokenizer.ggml.eos_token_id");
+        let unk = id("tokenizer.ggml.unknown_token_id")
+            .or_else(|| lookup.get("<unk>").copied());
+        let add_space_prefix = g.get("tokenizer.ggml.add_space_prefix").and_then(Value::as_bool)
+            .unwrap_or(algorithm != Algorithm::Bpe);
+        let max_piece_len = pieces.iter().map(|p| p.len()).max().unwrap_or(1);
+
+        Ok(Tokenizer {
+            algorithm, pieces, scores, types, lookup, merges, byte_tokens,
+            max_piece_len, add_space_prefix, bos, eos, unk,
+        })
+    }
+
+    pub fn vocab_size(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn piece(&self, id: u32) -> Option<&str> {
+        self.pieces.get(id as usize).map(String::as_str)
+    }
+
+    /// Whether encoding prepends a space that decoding must drop again.
+    pub fn add_space_prefix(&self) -> bool {
+        self.add_space_prefix && self.algorithm != Algorithm::Bpe
+    }
+
+    pub fn is_control(&self, id: u32) -> bool {
+        self.types.get(id as usize).is_some_and(|&t| t == TOKEN_CONTROL)
+    }
+
+    /// Piece `s` as a token that text may produce.
+    fn matchable(&self, s: &str) -> Option<u32> {
+        let id = *self.lookup.get(s)?;
+        matches!(self.types[id as usize], TOKEN_NORMAL | TOKEN_USER_DEFINED).then_some(id)
+    }
+
+    /// Emit `s` as one token, or its bytes if it is not in the vocabulary.
+    fn push_piece(&self, s: &str, out: &mut Vec<u32>) {
+        if let Some(id) = self.matchable(s) {
+            out.push(id);
+            return;
+        }
+        for b in s.bytes() {
+            match (self.byte_tokens[b as usize], self.unk) {
+                (Some(t), _)    => out.push(t),
+                (None, Some(u)) => if out.last() != Some(&u) { out.push(u) },
+                (None, None)    => {}
+            }
+        }
+    }
+
+    // ─── encoding ─────────────────────────────────────────────────────────────
+
+    pub fn encode(&self, text: &str, add_bos: bool) -> Vec<u32> {
+        let text = indic::normalize(text);
+        let mut out = Vec::new();
+        if add_bos { out.extend(self.bos); }
+        if text.is_empty() { return out; }
+
+        match self.algorithm {
+            Algorithm::SentencePieceBpe | Algorithm::Unigram => {
+                let mut s = String::with_capacity(text.len() + 3);
+                if self.add_space_prefix { s.push(SPM_SPACE); }
+                s.extend(text.chars().map(|c| if c == ' ' { SPM_SPACE } else { c }));
+                if self.algorithm == Algorithm::Unigram {
+                    self.encode_unigram(&s, &mut out);
+                } else {
+                    self.encode_spm_bpe(&s, &mut out);
+                }
+            }
+            Algorithm::Bpe => {
+                for word in pretokenize(&text) { self.encode_bpe(word, &mut out); }
+            }
+        }
+        out
+    }
+
+    fn encode_spm_bpe(&self, s: &str, out: &mut Vec<u32>) {
+        // Symbols are byte ranges of `s`, starting as single characters
+        let mut syms: Vec<(usize, usize)> = s.char_indices().map(|(i, c)| (i, i + c.len_utf8())).collect();
+        loop {
+            let mut best: Option<(f32, usize)> = None;
+            for i in 0..syms.len().saturating_sub(1) {
+                let (a, b) = (syms[i].0, syms[i + 1].1);
+                if b - a > self.max_piece_len { continue; }
+                if let Some(id) = self.matchable(&s[a..b]) {
+                    let score = self.scores[id as usize];
+                    if best.is_none_or(|(bs, _)| score > bs) { best = Some((score, i)); }
+                }
+            }
+            let Some((_, i)) = best else { break };
+            syms[i].1 = syms[i + 1].1;
+            syms.remove(i + 1);
+        }
+        for (a, b) in syms { self.push_piece(&s[a..b], out); }
+    }
+
+    fn encode_unigram(&self, s: &str, out: &mut Vec<u32>) {
+        let min_score = self.scores.iter().copied().fold(0.0f32, f32::min);
+        let unk_score = min_score - 10.0;
+
+        // best[i]: (score, start of last piece, token) of the best
+        // segmentation of s[..i]; token None means "unknown character"
+        let mut best: Vec<(f32, usize, Option<u32>)> = vec![(f32::NEG_INFINITY, 0, None); s.len() + 1];
+        best[0].0 = 0.0;
+        for (start, c) in s.char_indices() {
+            let base = best[start].0;
+            if base == f32::NEG_INFINITY { continue; }
+            for (off, ch) in s[start..].char_indices() {
+                let end = start + off + ch.len_utf8();
+                if end - start > self.max_piece_len { break; }
+                if let Some(id) = self.matchable(&s[start..end]) {
+                    let cand = base + self.scores[id as usize];
+                    if cand > best[end].0 { best[end] = (cand, start, Some(id)); }
+                }
+            }
+            let end = start + c.len_utf8();
+            if base + unk_score > best[end].0 { best[end] = (base + unk_score, start, None); }
+        }
+
+        let mut segs = Vec::new();
+        let mut end = s.len();
+        while end > 0 {
+            let (_, start, tok) = best[end];
+            segs.push((start, end, tok));
+            end = start;
+        }
+        for (start, end, tok) in segs.into_iter().rev() {
+            match tok {
+                Some(id) => out.push(id),
+                None     => self.push_piece(&s[start..end], out),
+            }
+        }
+    }
+
+    fn encode_bpe(&self, word: &str, out: &mut Vec<u32>) {
+        let mut syms: Vec<Option<u32>> = word.bytes().map(|b| self.byte_tokens[b as usize]).collect();
+        loop {
+            let mut best: Option<(u32, usize, u32)> = None;
+            for i in 0..syms.len().saturating_sub(1) {
+                let (Some(l), Some(r)) = (syms[i], syms[i + 1]) else { continue };
+                if let Some(&(rank, merged)) = self.merges.get(&(l, r)) {
+                    if best.is_none_or(|(br, _, _)| rank < br) { best = Some((rank, i, merged)); }
+                }
+            }
+            let Some((_, i, merged)) = best else { break };
+            syms[i] = Some(merged);
+            syms.remove(i + 1);
+        }
+        for s in syms {
+            match (s, self.unk) {
+                (Some(t), _)    => out.push(t),
+                (None, Some(u)) => out.push(u),
+                (None, None)    => {}
+            }
+        }
+    }
+
+    // ─── decoding ─────────────────────────────────────────────────────────────
+
+    /// Append the bytes token `id` stands for.  Control and unused tokens
+    /// produce nothing.  A multi-byte character may be split across
+    /// tokens, so callers streaming output must buffer incomplete UTF-8.
+    pub fn token_bytes<A: Allocator>(&self, id: u32, out: &mut Vec<u8, A>) {
+        let Some(piece) = self.pieces.get(id as usize) else { return };
+        match self.types[id as usize] {
+            TOKEN_CONTROL | TOKEN_UNUSED => {}
+            TOKEN_BYTE if self.algorithm != Algorithm::Bpe => out.extend(parse_byte_piece(piece)),
+            _ => match self.algorithm {
+                Algorithm::Bpe => {
+                    for c in piece.chars() {
+                        match char_to_byte(c) {
+                            Some(b) => out.push(b),
+                            None    => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                        }
+                    }
+                }
+                _ => {
+                    for c in piece.chars() {
+                        let c = if c == SPM_SPACE { ' ' } else { c };
+                        out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                }
+            },
+        }
+    }
+
+    pub fn decode(&self, ids: &[u32]) -> String {
+        let mut bytes = Vec::new();
+        for &id in ids { self.token_bytes(id, &mut bytes); }
+        if self.add_space_prefix() && bytes.first() == Some(&b' ') {
+            bytes.remove(0);
+        }
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
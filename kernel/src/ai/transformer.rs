@@ -0,0 +1,346 @@
+//! Transformer
+//! Forward pass of decoder-only, llama-style models (llama, mistral,
+//! qwen2): RMSNorm, rotary attention with grouped KV heads over a KV
+//! cache, and a SwiGLU feed-forward block.  Weights are read straight
+//! from the model mapping; matmuls run on the NPU when there is one.
+//! Weights with a LoRA adapter merged in are held in memory instead and
+//! run on the CPU; an adapter applied at run time is passed per call.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::gguf::{GgmlType, GgufFile};
+use super::kernels::{self, Matrix};
+use super::kvcache::KvCache;
+use super::lora::LoraAdapter;
+use super::gpu::{self, GpuModel};
+use super::npu::{self, NpuGraph};
+use crate::memory;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub n_embd:    usize,
+    pub n_layer:   usize,
+    pub n_head:    usize,
+    pub n_head_kv: usize,
+    pub head_dim:  usize,
+    pub n_ff:      usize,
+    pub n_ctx:     usize,
+    pub n_vocab:   usize,
+    pub rms_eps:   f32,
+    pub rope_base: f32,
+    pub rope_dim:  usize,
+    /// Rotate-half RoPE pairing (qwen2) rather than adjacent pairs (llama).
+    pub rope_neox: bool,
+}
+
+impl Config {
+    pub fn kv_dim(&self) -> usize {
+        self.n_head_kv * self.head_dim
+    }
+}
+
+struct Layer {
+    attn_norm: Vec<f32>,
+    wq:        usize,
+    wk:        usize,
+    wv:        usize,
+    wo:        usize,
+    bq:        Option<Vec<f32>>,
+    bk:        Option<Vec<f32>>,
+    bv:        Option<Vec<f32>>,
+    ffn_norm:  Vec<f32>,
+    gate:      usize,
+    up:        usize,
+    down:      usize,
+}
+
+pub struct Transformer {
+    pub cfg:     Config,
+    tok_embd:    usize,
+    output_norm: Vec<f32>,
+    output:      usize,
+    layers:      Vec<Layer>,
+    /// Weights offloaded to the NPU, if there is one.
+    npu:         Option<NpuGraph>,
+    /// Layers offloaded to the GPU, if there is one.
+    gpu:         Option<GpuModel>,
+    /// Weights with an adapter merged in, by tensor; they shadow the
+    /// mapped weight.
+    merged:      Vec<(usize, GgmlType, Vec<u8>)>,
+}
+
+// ─── loading ──────────────────────────────────────────────────────────────────
+
+/// Looks up and shape-checks weight tensors.
+pub(super) struct Loader<'a> {
+    pub(super) g: &'a GgufFile,
+}
+
+impl Loader<'_> {
+    pub(super) fn index(&self, name: &str) -> Option<usize> {
+        self.g.tensors.iter().position(|t| t.name == name)
+    }
+
+    /// A 2-D weight of `cols` × `rows` (ggml order: innermost first).
+    pub(super) fn matrix(&self, name: &str, cols: usize, rows: usize) -> Result<usize, &'static str> {
+        let i = self.index(name).ok_or("model is missing a weight tensor")?;
+        if self.g.tensors[i].dims[..] != [cols as u64, rows as u64] { return Err("weight tensor has the wrong shape"); }
+        Ok(i)
+    }
+
+    pub(super) fn vector(&self, name: &str, len: usize) -> Result<Vec<f32>, &'static str> {
+        let t = self.g.tensor(name).ok_or("model is missing a norm/bias tensor")?;
+        if t.dims[..] != [len as u64] { return Err("norm/bias tensor has the wrong shape"); }
+        let mut v = vec![0.0; len];
+        kernels::dequantize_row(t.ty, self.g.tensor_data(t), &mut v);
+        Ok(v)
+    }
+
+    pub(super) fn optional_vector(&self, name: &str, len: usize) -> Result<Option<Vec<f32>>, &'static str> {
+        if self.index(name).is_none() { return Ok(None); }
+        self.vector(name, len).map(Some)
+    }
+}
+
+impl Transformer {
+    pub fn from_gguf(g: &GgufFile) -> Result<Self, &'static str> {
+        let arch = g.architecture().ok_or("model has no architecture")?;
+        let rope_neox = match arch {
+            "llama" | "mistral" => false,
+            "qwen2"             => true,
+            _                   => return Err("unsupported model architecture"),
+        };
+        let key = |k: &str| -> String { format!("{}.{}", arch, k) };
+        let int = |k: &str| g.get_u64(&key(k)).filter(|&v| v > 0 && v <= u32::MAX as u64).map(|v| v as usize);
+        let float = |k: &str| g.get(&key(k)).and_then(|v| v.as_f32());
+
+        let n_embd  = int("embedding_length").ok_or("model has no embedding length")?;
+        let n_layer = int("block_count").ok_or("model has no block count")?;
+        let n_head  = int("attention.head_count").ok_or("model has no head count")?;
+        let n_head_kv = int("attention.head_count_kv").unwrap_or(n_head);
+        if n_head % n_head_kv != 0 { return Err("head count not a multiple of KV heads"); }
+        let head_dim = int("attention.key_length").unwrap_or(n_embd / n_head);
+        let rope_dim = int("rope.dimension_count").unwrap_or(head_dim);
+        if rope_dim > head_dim || rope_dim % 2 != 0 { return Err("bad rope dimension"); }
+
+        let ld = Loader { g };
+        let tok_embd = ld.index("token_embd.weight").ok_or("model has no token embeddings")?;
+        let n_vocab  = match g.tensors[tok_embd].dims[..] {
+            [e, v] if e as usize == n_embd => v as usize,
+            _ => return Err("token embeddings have the wrong shape"),
+        };
+
+        let cfg = Config {
+            n_embd, n_layer, n_head, n_head_kv, head_dim, rope_dim, rope_neox, n_vocab,
+            n_ff:      int("feed_forward_length").ok_or("model has no feed-forward length")?,
+            n_ctx:     int("context_length").unwrap_or(2048),
+            rms_eps:   float("attention.layer_norm_rms_epsilon").unwrap_or(1e-5),
+            rope_base: float("rope.freq_base").unwrap_or(10000.0),
+        };
+
+        let q_dim = n_head * head_dim;
+        let kv_dim = cfg.kv_dim();
+        let mut layers = Vec::with_capacity(n_layer);
+        for i in 0..n_layer {
+            let t = |n: &str| format!("blk.{}.{}", i, n);
+            layers.push(Layer {
+                attn_norm: ld.vector(&t("attn_norm.weight"), n_embd)?,
+                wq:        ld.matrix(&t("attn_q.weight"), n_embd, q_dim)?,
+                wk:        ld.matrix(&t("attn_k.weight"), n_embd, kv_dim)?,
+                wv:        ld.matrix(&t("attn_v.weight"), n_embd, kv_dim)?,
+                wo:        ld.matrix(&t("attn_output.weight"), q_dim, n_embd)?,
+                bq:        ld.optional_vector(&t("attn_q.bias"), q_dim)?,
+                bk:        ld.optional_vector(&t("attn_k.bias"), kv_dim)?,
+                bv:        ld.optional_vector(&t("attn_v.bias"), kv_dim)?,
+                ffn_norm:  ld.vector(&t("ffn_norm.weight"), n_embd)?,
+                gate:      ld.matrix(&t("ffn_gate.weight"), n_embd, cfg.n_ff)?,
+                up:        ld.matrix(&t("ffn_up.weight"), n_embd, cfg.n_ff)?,
+                down:      ld.matrix(&t("ffn_down.weight"), cfg.n_ff, n_embd)?,
+            });
+        }
+
+        // Models with tied embeddings reuse them as the output projection
+        let output = match ld.index("output.weight") {
+            Some(_) => ld.matrix("output.weight", n_embd, n_vocab)?,
+            None    => tok_embd,
+        };
+
+        let mut t = Transformer {
+            output_norm: ld.vector("output_norm.weight", n_embd)?,
+            npu:         None,
+            gpu:         None,
+            merged:      Vec::new(),
+            cfg, tok_embd, output, layers,
+        };
+        t.npu = NpuGraph::load(g, &t.weight_tensors());
+        // The GPU takes the big layers, less whatever the NPU has
+        let on_npu = |i: &usize| t.npu.as_ref().is_some_and(|n| n.is_offloaded(*i));
+        let layers: Vec<Vec<usize>> = t.layers.iter()
+            .map(|l| [l.wq, l.wk, l.wv, l.wo, l.gate, l.up, l.down].to_vec())
+            .chain([vec![t.output]])
+            .map(|ws| ws.into_iter().filter(|i| !on_npu(i)).collect())
+            .collect();
+        t.gpu = GpuModel::load(g, &layers);
+        Ok(t)
+    }
+
+    /// Tensors multiplied as weight matrices (the ones an adapter may
+    /// change).
+    pub fn weight_tensors(&self) -> Vec<usize> {
+        self.layers.iter()
+            .flat_map(|l| [l.wq, l.wk, l.wv, l.wo, l.gate, l.up, l.down])
+            .chain([self.output])
+            .collect()
+    }
+
+    /// out = W · x for weight tensor `w`, on the NPU or GPU when one holds
+    /// it, plus `lora`'s update to it.
+    fn matmul(&self, g: &GgufFile, lora: Option<&LoraAdapter>, out: &mut [f32], w: usize, x: &[f32]) {
+        match self.merged.iter().find(|(t, _, _)| *t == w) {
+            Some((_, ty, data)) => kernels::matmul(out, &self.merged_matrix(g, w, *ty, data), x),
+            None => {
+                let m = Matrix::from_tensor(g, w);
+                match (&self.npu, &self.gpu) {
+                    (Some(npu), _) if npu.is_offloaded(w) => npu.matmul(w, &m, out, x),
+                    (_, Some(gpu)) if gpu.is_offloaded(w) => gpu.matmul(w, &m, out, x),
+                    _                                     => kernels::matmul(out, &m, x),
+                }
+            }
+        }
+        if let Some(a) = lora { a.apply(w, x, out); }
+    }
+
+    fn merged_matrix<'a>(&self, g: &GgufFile, w: usize, ty: GgmlType, data: &'a [u8]) -> Matrix<'a> {
+        let dims = &g.tensors[w].dims;
+        Matrix { ty, data, cols: dims[0] as usize, rows: dims[1] as usize }
+    }
+
+    /// Fold `adapter` into the weights for good.  Merged weights are kept
+    /// as Q8_0 (F32 if their rows do not split into blocks), whatever the
+    /// model's own quantization.
+    pub fn merge(&mut self, g: &GgufFile, adapter: &LoraAdapter) {
+        for d in adapter.deltas() {
+            let ty = if d.cols % GgmlType::Q8_0.block().0 == 0 { GgmlType::Q8_0 } else { GgmlType::F32 };
+            let mut data = Vec::with_capacity(d.rows * d.cols / ty.block().0 * ty.block().1);
+            let mut row = vec![0.0; d.cols];
+            {
+                // Merge on top of any adapter merged before
+                let base = match self.merged.iter().find(|(t, _, _)| *t == d.tensor) {
+                    Some((_, ty, data)) => self.merged_matrix(g, d.tensor, *ty, data),
+                    None                => Matrix::from_tensor(g, d.tensor),
+                };
+                for r in 0..d.rows {
+                    base.row(r, &mut row);
+                    d.add_row(r, &mut row);
+                    match ty {
+                        GgmlType::Q8_0 => kernels::quantize_row_q8_0(&row, &mut data),
+                        _              => data.extend(row.iter().flat_map(|v| v.to_le_bytes())),
+                    }
+                }
+            }
+            self.merged.retain(|(t, _, _)| *t != d.tensor);
+            self.merged.push((d.tensor, ty, data));
+        }
+    }
+
+    /// Bytes of merged weights held in memory.
+    pub fn merged_bytes(&self) -> usize {
+        self.merged.iter().map(|(_, _, d)| d.len()).sum()
+    }
+
+    /// Name of the accelerator running this model's matmuls, if any.
+    pub fn accelerator(&self) -> Option<&'static str> {
+        self.npu.as_ref().and_then(|_| npu::backend_name())
+            .or_else(|| self.gpu.as_ref().and_then(|_| gpu::backend_name()))
+    }
+
+    /// Layers (counting the output head) running on the GPU.
+    pub fn gpu_layers(&self) -> usize {
+        self.gpu.as_ref().map_or(0, GpuModel::layer_count)
+    }
+
+    // ─── forward pass ─────────────────────────────────────────────────────────
+
+    /// Run `token` through the model at the next cache position and write
+    /// the next-token logits.  `lora` is applied on top of the weights.
+    pub fn forward(&self, g: &GgufFile, lora: Option<&LoraAdapter>, cache: &mut KvCache, token: u32, logits: &mut [f32])
+        -> Result<(), &'static str>
+    {
+        let mut h = memory::secure_vec(0.0, self.cfg.n_embd);
+        self.hidden(g, lora, cache, token, &mut h)?;
+        self.matmul(g, lora, logits, self.output, &h);
+        Ok(())
+    }
+
+    /// Run `token` through the model's layers at the next cache position
+    /// and write its final normalised hidden state (`n_embd` values), the
+    /// input to the output head.
+    pub fn hidden(&self, g: &GgufFile, lora: Option<&LoraAdapter>, cache: &mut KvCache, token: u32, out: &mut [f32])
+        -> Result<(), &'static str>
+    {
+        let c = &self.cfg;
+        if token as usize >= c.n_vocab { return Err("token outside vocabulary"); }
+        let pos = cache.begin_position()?;
+        let group = c.n_head / c.n_head_kv;
+        let scale = 1.0 / libm::sqrtf(c.head_dim as f32);
+
+        let mut x   = memory::secure_vec(0.0, c.n_embd);
+        let mut xb  = memory::secure_vec(0.0, c.n_embd);
+        let mut q   = memory::secure_vec(0.0, c.n_head * c.head_dim);
+        let mut k   = memory::secure_vec(0.0, c.kv_dim());
+        let mut v   = memory::secure_vec(0.0, c.kv_dim());
+        let mut att = memory::secure_vec(0.0, c.n_head * c.head_dim);
+        let mut hb  = memory::secure_vec(0.0, c.n_ff);
+        let mut hb2 = memory::secure_vec(0.0, c.n_ff);
+
+        Matrix::from_tensor(g, self.tok_embd).row(token as usize, &mut x);
+
+        for (l, layer) in self.layers.iter().enumerate() {
+            // Attention
+            kernels::rmsnorm(&mut xb, &x, &layer.attn_norm, c.rms_eps);
+            self.matmul(g, lora, &mut q, layer.wq, &xb);
+            self.matmul(g, lora, &mut k, layer.wk, &xb);
+            self.matmul(g, lora, &mut v, layer.wv, &xb);
+            for (out, bias) in [(&mut q, &layer.bq), (&mut k, &layer.bk), (&mut v, &layer.bv)] {
+                if let Some(b) = bias {
+                    for (o, b) in out.iter_mut().zip(b) { *o += b; }
+                }
+            }
+            kernels::rope(&mut q, c.head_dim, c.rope_dim, pos, c.rope_base, c.rope_neox);
+            kernels::rope(&mut k, c.head_dim, c.rope_dim, pos, c.rope_base, c.rope_neox);
+
+            cache.store(l, &k, &v);
+            if !self.gpu.as_ref().is_some_and(|gpu| gpu.attend(l, cache, c, &q, scale, &mut att)) {
+                for h in 0..c.n_head {
+                    let qh = &q[h * c.head_dim..(h + 1) * c.head_dim];
+                    cache.attend(l, h / group, qh, scale, &mut att[h * c.head_dim..(h + 1) * c.head_dim]);
+                }
+            }
+            self.matmul(g, lora, &mut xb, layer.wo, &att);
+            for (a, b) in x.iter_mut().zip(&xb) { *a += b; }
+
+            // Feed-forward (SwiGLU)
+            kernels::rmsnorm(&mut xb, &x, &layer.ffn_norm, c.rms_eps);
+            self.matmul(g, lora, &mut hb,  layer.gate, &xb);
+            self.matmul(g, lora, &mut hb2, layer.up, &xb);
+            for (a, b) in hb.iter_mut().zip(&hb2) { *a = kernels::silu(*a) * b; }
+            self.matmul(g, lora, &mut xb, layer.down, &hb);
+            for (a, b) in x.iter_mut().zip(&xb) { *a += b; }
+        }
+
+        kernels::rmsnorm(out, &x, &self.output_norm, c.rms_eps);
+        Ok(())
+    }
+}
+
+/// Index of the largest logit (greedy decoding).
+pub fn argmax(logits: &[f32]) -> u32 {
+    let mut best = 0;
+    for (i, &v) in logits.iter().enumerate() {
+        if v > logits[best] { best = i; }
+    }
+    best as u32
+}
@@ -0,0 +1,230 @@
+//! SurakshaOS Alarms
+//! Wall-clock alarms for alarm clocks and periodic sync.  Wakeup alarms
+//! program the RTC as a wake source so they fire even while the system is
+//! suspended; all alarms are delivered to their owner as an IPC
+//! notification on the channel opened with `connect()`.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+
+use crate::capability::Capability;
+use crate::ipc::{self, ChannelId, Message, MessageKind};
+use crate::process::{self, ProcessId};
+
+// ─── goldfish RTC (QEMU virt) ─────────────────────────────────────────────────
+
+const RTC_BASE:            usize = 0x0010_1000;
+const RTC_TIME_LOW:        usize = RTC_BASE;        // reading latches TIME_HIGH
+const RTC_TIME_HIGH:       usize = RTC_BASE + 0x04;
+const RTC_ALARM_LOW:       usize = RTC_BASE + 0x08; // writing arms the alarm
+const RTC_ALARM_HIGH:      usize = RTC_BASE + 0x0C;
+const RTC_IRQ_ENABLED:     usize = RTC_BASE + 0x10;
+const RTC_CLEAR_ALARM:     usize = RTC_BASE + 0x14;
+const RTC_CLEAR_INTERRUPT: usize = RTC_BASE + 0x1C;
+
+/// PLIC source number of the RTC on QEMU virt.
+pub const RTC_IRQ: u32 = 11;
+
+fn rtc_write(reg: usize, v: u32) {
+    unsafe { core::ptr::write_volatile(reg as *mut u32, v); }
+}
+
+fn rtc_read(reg: usize) -> u32 {
+    unsafe { core::ptr::read_volatile(reg as *const u32) }
+}
+
+/// Wall-clock time in nanoseconds since the Unix epoch.
+pub fn rtc_now_ns() -> u64 {
+    let lo = rtc_read(RTC_TIME_LOW) as u64;
+    let hi = rtc_read(RTC_TIME_HIGH) as u64;
+    hi << 32 | lo
+}
+
+fn rtc_arm(at_ns: u64) {
+    rtc_write(RTC_IRQ_ENABLED, 1);
+    rtc_write(RTC_ALARM_HIGH, (at_ns >> 32) as u32);
+    rtc_write(RTC_ALARM_LOW, at_ns as u32);
+}
+
+fn rtc_disarm() {
+    rtc_write(RTC_CLEAR_ALARM, 1);
+    rtc_write(RTC_CLEAR_INTERRUPT, 1);
+    rtc_write(RTC_IRQ_ENABLED, 0);
+}
+
+/// Time the RTC alarm is currently programmed for.
+static ARMED_AT: Mutex<Option<u64>> = Mutex::new(None);
+
+/// True if the programmed RTC alarm has gone off.
+pub fn rtc_fired() -> bool {
+    ARMED_AT.lock().is_some_and(|at| rtc_now_ns() >= at)
+}
+
+// ─── alarms ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlarmId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmKind {
+    /// Fires even if the system is suspended.
+    Wakeup,
+    /// Delivered at the next opportunity once the system is running.
+    NonWakeup,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Alarm {
+    pub id:    AlarmId,
+    pub owner: ProcessId,
+    pub at_ns: u64,
+    pub kind:  AlarmKind,
+}
+
+/// Request opcodes (first payload byte).
+pub const ALARM_REQ_SET:    u8 = 1; // [kind (0 wakeup, 1 non-wakeup), at_ns u64 LE] -> [ok, id u32 LE]
+pub const ALARM_REQ_CANCEL: u8 = 2; // [id u32 LE] -> [ok]
+
+/// Notification payload: [id u32 LE, at_ns u64 LE].
+pub const ALARM_NOTIFY_LEN: usize = 12;
+
+struct Client {
+    pid:     ProcessId,
+    channel: ChannelId,
+    /// The service's end of the channel.
+    cap:     Capability,
+}
+
+struct AlarmService {
+    pid:     Option<ProcessId>,
+    alarms:  Vec<Alarm>,
+    clients: Vec<Client>,
+}
+
+static SERVICE: Mutex<AlarmService> = Mutex::new(AlarmService {
+    pid: None, alarms: Vec::new(), clients: Vec::new(),
+});
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Refuse to suspend if a wakeup alarm is due sooner than this.
+const SUSPEND_GUARD_NS: u64 = 2_000_000_000;
+
+/// The timer tick reaches the alarm list, so it is only held with
+/// interrupts masked.
+fn with_service<R>(f: impl FnOnce(&mut AlarmService) -> R) -> R {
+    let prev = crate::arch::interrupts_disable();
+    let r = f(&mut SERVICE.lock());
+    crate::arch::interrupts_restore(prev);
+    r
+}
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("alarmd")?;
+    with_service(|s| s.pid = Some(pid));
+    ipc::register_kernel_server(pid, handle_request);
+    Ok(())
+}
+
+/// Open a channel from `client` to `alarmd`; requests are sent and alarm
+/// notifications received on it.
+pub fn connect(client: ProcessId) -> Result<(ChannelId, Capability), &'static str> {
+    let pid = with_service(|s| s.pid).ok_or("alarm service not running")?;
+    let (ch, client_cap, svc_cap) = ipc::create_channel(client, pid);
+    with_service(|s| s.clients.push(Client { pid: client, channel: ch, cap: svc_cap }));
+    Ok((ch, client_cap))
+}
+
+pub fn set(owner: ProcessId, at_ns: u64, kind: AlarmKind) -> AlarmId {
+    let id = AlarmId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    with_service(|s| s.alarms.push(Alarm { id, owner, at_ns, kind }));
+    id
+}
+
+pub fn cancel(owner: ProcessId, id: AlarmId) -> Result<(), &'static str> {
+    with_service(|s| {
+        let i = s.alarms.iter().position(|a| a.id == id).ok_or("no such alarm")?;
+        if s.alarms[i].owner != owner { return Err("alarm owned by another process"); }
+        s.alarms.remove(i);
+        Ok(())
+    })
+}
+
+pub fn pending() -> Vec<Alarm> {
+    with_service(|s| s.alarms.clone())
+}
+
+fn handle_request(_ch: ChannelId, msg: &Message) -> Option<Vec<u8>> {
+    let p = &msg.payload;
+    match p.first() {
+        Some(&ALARM_REQ_SET) if p.len() >= 10 => {
+            let kind  = if p[1] == 0 { AlarmKind::Wakeup } else { AlarmKind::NonWakeup };
+            let at_ns = u64::from_le_bytes(p[2..10].try_into().ok()?);
+            let id    = set(msg.sender, at_ns, kind);
+            let mut reply = Vec::from([1u8]);
+            reply.extend_from_slice(&id.0.to_le_bytes());
+            Some(reply)
+        }
+        Some(&ALARM_REQ_CANCEL) if p.len() >= 5 => {
+            let id = AlarmId(u32::from_le_bytes(p[1..5].try_into().ok()?));
+            Some(Vec::from([cancel(msg.sender, id).is_ok() as u8]))
+        }
+        _ => Some(Vec::from([0u8])),
+    }
+}
+
+// ─── firing ───────────────────────────────────────────────────────────────────
+
+/// Send notifications for every alarm that is due.  Runs as deferred work
+/// because IPC delivery cannot happen in interrupt context.
+fn deliver() {
+    let now = rtc_now_ns();
+    let (svc_pid, due) = with_service(|s| {
+        let due: Vec<Alarm> = s.alarms.iter().filter(|a| a.at_ns <= now).copied().collect();
+        s.alarms.retain(|a| a.at_ns > now);
+        (s.pid, due)
+    });
+    let Some(svc_pid) = svc_pid else { return };
+    for a in due {
+        let Some((ch, cap)) = with_service(|s| {
+            s.clients.iter().find(|c| c.pid == a.owner).map(|c| (c.channel, c.cap.clone()))
+        }) else { continue };
+        let mut payload = Vec::with_capacity(ALARM_NOTIFY_LEN);
+        payload.extend_from_slice(&a.id.0.to_le_bytes());
+        payload.extend_from_slice(&a.at_ns.to_le_bytes());
+        let _ = ipc::send_message(ch, svc_pid, &cap, MessageKind::Notification, &payload);
+    }
+}
+
+/// Timer-tick check for due alarms.
+pub fn alarm_tick() {
+    let Some(s) = SERVICE.try_lock() else { return };
+    let now = rtc_now_ns();
+    if s.alarms.iter().any(|a| a.at_ns <= now) {
+        process::defer(deliver);
+    }
+}
+
+// ─── suspend coordination ─────────────────────────────────────────────────────
+
+/// Program the RTC for the earliest wakeup alarm before suspending.
+/// Returns whether the RTC is armed as a wake source, or an error if an
+/// alarm is due too soon to be worth suspending.
+pub fn arm_for_suspend() -> Result<bool, &'static str> {
+    let next = with_service(|s| {
+        s.alarms.iter().filter(|a| a.kind == AlarmKind::Wakeup).map(|a| a.at_ns).min()
+    });
+    let Some(at) = next else { return Ok(false) };
+    if at <= rtc_now_ns() + SUSPEND_GUARD_NS { return Err("wakeup alarm imminent"); }
+    *ARMED_AT.lock() = Some(at);
+    rtc_arm(at);
+    Ok(true)
+}
+
+/// Undo `arm_for_suspend` after resume and queue delivery of anything that
+/// came due while suspended.
+pub fn disarm_after_resume() {
+    rtc_disarm();
+    *ARMED_AT.lock() = None;
+    process::defer(deliver);
+}
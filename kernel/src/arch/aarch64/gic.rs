@@ -0,0 +1,140 @@
+//! GICv2 Interrupt Controller
+//! The distributor routes device interrupts (SPIs, from 32) to CPU 0 and
+//! holds each CPU's banked SGIs (0–15, used as IPIs) and PPIs (16–31, the
+//! timer among them); each CPU's interface signals the highest-priority
+//! pending one and is acknowledged and completed per interrupt.
+//!
+//! Priorities do the work of the RISC-V port's mie classes: the timer and
+//! SGIs sit at PRIO_CORE, devices at PRIO_DEVICE, and the priority mask
+//! lets devices through or not.  SGIs cannot be disabled in a GICv2.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+// Distributor registers
+const GICD_CTLR:       usize = 0x000;
+const GICD_TYPER:      usize = 0x004;
+const GICD_ISENABLER:  usize = 0x100;
+const GICD_ICENABLER:  usize = 0x180;
+const GICD_ISPENDR:    usize = 0x200;
+const GICD_IPRIORITYR: usize = 0x400;
+const GICD_ITARGETSR:  usize = 0x800;
+const GICD_SGIR:       usize = 0xF00;
+const GICD_CPENDSGIR:  usize = 0xF10;
+
+// CPU interface registers
+const GICC_CTLR: usize = 0x00;
+const GICC_PMR:  usize = 0x04;
+const GICC_IAR:  usize = 0x0C;
+const GICC_EOIR: usize = 0x10;
+
+/// What the interface reads as when nothing is pending.
+pub const SPURIOUS:    u32 = 1023;
+/// Timer and SGIs.
+pub const PRIO_CORE:   u8  = 0x80;
+pub const PRIO_DEVICE: u8  = 0xA0;
+/// Priority masks: devices held back, or everything let through.
+const PMR_CORE: u32 = PRIO_DEVICE as u32;
+const PMR_ALL:  u32 = 0xF0;
+
+// QEMU virt's, until `configure` reads the device tree's
+static DIST: AtomicUsize = AtomicUsize::new(0x0800_0000);
+static CPU:  AtomicUsize = AtomicUsize::new(0x0801_0000);
+
+pub fn configure(dist: usize, cpu: usize) {
+    DIST.store(dist, Ordering::Relaxed);
+    CPU.store(cpu, Ordering::Relaxed);
+}
+
+fn dist_read(off: usize) -> u32 {
+    unsafe { core::ptr::read_volatile((DIST.load(Ordering::Relaxed) + off) as *const u32) }
+}
+
+fn dist_write(off: usize, v: u32) {
+    unsafe { core::ptr::write_volatile((DIST.load(Ordering::Relaxed) + off) as *mut u32, v) }
+}
+
+fn cpu_read(off: usize) -> u32 {
+    unsafe { core::ptr::read_volatile((CPU.load(Ordering::Relaxed) + off) as *const u32) }
+}
+
+fn cpu_write(off: usize, v: u32) {
+    unsafe { core::ptr::write_volatile((CPU.load(Ordering::Relaxed) + off) as *mut u32, v) }
+}
+
+/// Interrupt lines the distributor implements.
+fn lines() -> u32 {
+    32 * ((dist_read(GICD_TYPER) & 0x1f) + 1)
+}
+
+pub fn set_priority(irq: u32, prio: u8) {
+    let off = GICD_IPRIORITYR + (irq as usize & !3);
+    let shift = 8 * (irq % 4);
+    dist_write(off, dist_read(off) & !(0xff << shift) | (prio as u32) << shift);
+}
+
+/// Turn the distributor on, every device interrupt off and routed to
+/// CPU 0.  Boot CPU only.
+pub fn init_distributor() {
+    dist_write(GICD_CTLR, 0);
+    for irq in (32..lines()).step_by(32) {
+        dist_write(GICD_ICENABLER + irq as usize / 8, u32::MAX);
+    }
+    for irq in (32..lines()).step_by(4) {
+        dist_write(GICD_ITARGETSR + irq as usize, 0x0101_0101);
+    }
+    dist_write(GICD_CTLR, 1);
+}
+
+/// Turn on the calling CPU's interface, devices masked, with its SGIs and
+/// PPIs at PRIO_CORE.
+pub fn init_cpu() {
+    for irq in 0..32 { set_priority(irq, PRIO_CORE); }
+    cpu_write(GICC_PMR, PMR_CORE);
+    cpu_write(GICC_CTLR, 1);
+}
+
+pub fn enable(irq: u32, enabled: bool) {
+    let reg = if enabled { GICD_ISENABLER } else { GICD_ICENABLER };
+    if irq >= 32 { set_priority(irq, PRIO_DEVICE); }
+    dist_write(reg + 4 * (irq as usize / 32), 1 << (irq % 32));
+}
+
+/// Let device interrupts reach this CPU, or hold them back.
+pub fn set_devices_masked(masked: bool) {
+    cpu_write(GICC_PMR, if masked { PMR_CORE } else { PMR_ALL });
+}
+
+/// Whether an enabled device interrupt is pending.
+pub fn device_pending() -> bool {
+    (32..lines()).step_by(32).any(|irq| {
+        let w = irq as usize / 8;
+        dist_read(GICD_ISPENDR + w) & dist_read(GICD_ISENABLER + w) != 0
+    })
+}
+
+/// Whether an SGI is pending on this CPU.
+pub fn sgi_pending() -> bool {
+    dist_read(GICD_ISPENDR) & 0xffff != 0
+}
+
+/// Acknowledge the highest-priority pending interrupt: the raw IAR value,
+/// whose low ten bits are the interrupt id (SPURIOUS for none).
+pub fn acknowledge() -> u32 {
+    cpu_read(GICC_IAR)
+}
+
+/// Complete an interrupt `acknowledge` returned.
+pub fn end(iar: u32) {
+    cpu_write(GICC_EOIR, iar);
+}
+
+/// Raise SGI `sgi` on the CPU with interface number `target` (0–7).
+pub fn send_sgi(target: usize, sgi: u32) {
+    dist_write(GICD_SGIR, 1 << (16 + (target & 7)) | (sgi & 0xf));
+}
+
+/// Drop SGI `sgi` pending on this CPU, from whichever CPU sent it.
+pub fn clear_sgi(sgi: u32) {
+    let off = GICD_CPENDSGIR + (sgi as usize & !3);
+    dist_write(off, 0xff << (8 * (sgi % 4)));
+}
@@ -0,0 +1,202 @@
+//! AArch64 Translation Tables
+//! The kernel runs identity-mapped at EL1, 4 KiB granule, 39-bit addresses
+//! through TTBR0 (three levels from level 1):
+//!
+//! - the first GiB, where QEMU virt keeps its devices, as Device-nGnRE;
+//! - RAM as the device tree gives it (1 GiB at 0x4000_0000 without one),
+//!   as normal write-back memory in 1 GiB blocks.
+//!
+//! Everything starts out read/write/execute.  `seal_text` switches to a
+//! second set of tables in which the kernel image (.text and .rodata) is
+//! read-only and nothing else executes, and sets SCTLR_EL1.WXN so nothing
+//! writable can be made executable: the RISC-V port's locked PMP entry.
+//! Building the new tables beside the live ones and switching TTBR0 saves
+//! a break-before-make on every block split.  Other CPUs switch at their
+//! next IPI.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+const PAGE: u64 = 4096;
+const MIB2: u64 = 2 << 20;
+const GIB:  u64 = 1 << 30;
+
+// Descriptor bits
+const VALID:       u64 = 1 << 0;
+/// A table at levels 1 and 2, a page at level 3.
+const TABLE:       u64 = 1 << 1;
+const ATTR_DEVICE: u64 = 0 << 2;
+const ATTR_NORMAL: u64 = 1 << 2;
+const AP_RO:       u64 = 1 << 7;
+const SH_INNER:    u64 = 3 << 8;
+const AF:          u64 = 1 << 10;
+const PXN:         u64 = 1 << 53;
+const UXN:         u64 = 1 << 54;
+
+const DEVICE: u64 = VALID | ATTR_DEVICE | AF | PXN | UXN;
+const NORMAL: u64 = VALID | ATTR_NORMAL | SH_INNER | AF | UXN;
+
+/// Attribute 0: Device-nGnRE; 1: normal, inner/outer write-back.
+const MAIR: u64 = 0x04 | 0xff << 8;
+/// T0SZ = 25 (39 bits), walks inner/outer write-back and inner shareable,
+/// 4 KiB granule, no TTBR1 walks; IPS is added from the CPU.
+const TCR:  u64 = 25 | 1 << 8 | 1 << 10 | 3 << 12 | 1 << 23;
+
+// SCTLR_EL1 bits
+const SCTLR_M:   u64 = 1 << 0;
+const SCTLR_C:   u64 = 1 << 2;
+const SCTLR_I:   u64 = 1 << 12;
+const SCTLR_WXN: u64 = 1 << 19;
+
+/// Level-3 tables for the blocks the kernel image touches: 16 MiB.
+const IMAGE_TABLES: usize = 8;
+
+#[repr(C, align(4096))]
+struct Table([u64; 512]);
+
+static mut L1:        Table = Table([0; 512]);
+static mut L1_SEALED: Table = Table([0; 512]);
+static mut L2_SEALED: Table = Table([0; 512]);
+static mut L3_SEALED: [Table; IMAGE_TABLES] = [const { Table([0; 512]) }; IMAGE_TABLES];
+
+/// MAIR, TCR, TTBR0 and SCTLR as the boot CPU last set them, for the
+/// others; boot.S loads them with the MMU off, so they are cleaned to
+/// memory whenever they change.
+#[no_mangle]
+static mut MMU_STATE: [u64; 4] = [0; 4];
+static SEALED: AtomicBool = AtomicBool::new(false);
+
+/// SAFETY: boot CPU only, with nothing else walking `t` through TTBR0.
+unsafe fn table(t: *mut Table) -> &'static mut [u64; 512] {
+    &mut (*t).0
+}
+
+/// RAM regions from the device tree at `dtb`.  Runs with the MMU off, so
+/// the walk takes no locks (no exclusives on device memory).
+fn ram(dtb: usize) -> ([(u64, u64); crate::fdt::MAX_REGIONS], usize) {
+    let mut out = [(0, 0); crate::fdt::MAX_REGIONS];
+    let header = unsafe { core::slice::from_raw_parts(dtb as *const u8, 8) };
+    let field = |i: usize| u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+    let mut n = 0;
+    let blob = (field(0) == 0xd00d_feed && field(4) < 1 << 24)
+        .then(|| unsafe { core::slice::from_raw_parts(dtb as *const u8, field(4) as usize) });
+    if let Some(Ok(fdt)) = blob.map(crate::fdt::Fdt::parse) {
+        for node in fdt.nodes().filter(|n| n.property("device_type") == Some(b"memory\0")) {
+            for r in (0..).map_while(|i| node.reg(i)) {
+                if n == out.len() { break; }
+                out[n] = r;
+                n += 1;
+            }
+        }
+    }
+    if n == 0 {
+        out[0] = (0x4000_0000, GIB);
+        n = 1;
+    }
+    (out, n)
+}
+
+/// Build the boot tables and turn the MMU and caches on.  Called from
+/// boot.S, MMU off, before anything else in Rust.
+#[no_mangle]
+extern "C" fn mmu_init(dtb: usize) {
+    unsafe {
+        let l1 = table(&raw mut L1);
+        l1[0] = DEVICE;
+        let (regions, count) = ram(dtb);
+        for &(base, size) in &regions[..count] {
+            for g in base / GIB..(base + size).div_ceil(GIB) {
+                if let Some(e) = l1.get_mut(g as usize) { *e = (g * GIB) | NORMAL; }
+            }
+        }
+
+        let mmfr0: u64;
+        asm!("mrs {}, id_aa64mmfr0_el1", out(reg) mmfr0);
+        let tcr = TCR | (mmfr0 & 7) << 32;
+        let mut sctlr: u64;
+        asm!("mrs {}, sctlr_el1", out(reg) sctlr);
+        sctlr |= SCTLR_M | SCTLR_C | SCTLR_I;
+        MMU_STATE = [MAIR, tcr, &raw const L1 as u64, sctlr];
+        asm!(
+            "msr mair_el1, {mair}",
+            "msr tcr_el1, {tcr}",
+            "msr ttbr0_el1, {ttbr}",
+            "isb",
+            "tlbi vmalle1",
+            "dsb nsh",
+            "isb",
+            "msr sctlr_el1, {sctlr}",
+            "isb",
+            mair = in(reg) MAIR, tcr = in(reg) tcr, ttbr = in(reg) &raw const L1 as u64, sctlr = in(reg) sctlr,
+        );
+    }
+}
+
+/// Write the lines holding MMU_STATE back to memory, where a CPU starting
+/// with its MMU off reads them.
+pub fn publish() {
+    let at = &raw const MMU_STATE as usize;
+    unsafe { asm!("dc cvac, {}", "dsb sy", in(reg) at); }
+}
+
+/// Make `start..end` read-only and executable, and everything else not
+/// executable.  Irreversible: WXN stays set.
+pub fn seal_text(start: usize, end: usize) {
+    if SEALED.swap(true, Ordering::SeqCst) { return; }
+    let (start, end) = (start as u64 & !(PAGE - 1), (end as u64).next_multiple_of(PAGE));
+    unsafe {
+        let (l1, sealed) = (table(&raw mut L1), table(&raw mut L1_SEALED));
+        for (s, &e) in sealed.iter_mut().zip(l1.iter()) {
+            *s = if e & VALID != 0 { e | PXN } else { 0 };
+        }
+
+        // The image's GiB, in 2 MiB blocks, and pages where the image is
+        let gib = start / GIB;
+        let l2 = table(&raw mut L2_SEALED);
+        let mut spare = 0;
+        for (i, e) in l2.iter_mut().enumerate() {
+            let base = gib * GIB + i as u64 * MIB2;
+            *e = base | NORMAL | PXN;
+            if base + MIB2 <= start || base >= end { continue; }
+            if spare == IMAGE_TABLES {
+                // Out of tables: the whole block read-only, still never
+                // both writable and executable
+                *e = base | NORMAL | AP_RO;
+                continue;
+            }
+            let l3 = table(&raw mut L3_SEALED[spare]);
+            spare += 1;
+            for (j, p) in l3.iter_mut().enumerate() {
+                let at = base + j as u64 * PAGE;
+                *p = at | NORMAL | TABLE | if (start..end).contains(&at) { AP_RO } else { PXN };
+            }
+            *e = l3.as_ptr() as u64 | VALID | TABLE;
+        }
+        sealed[gib as usize] = l2.as_ptr() as u64 | VALID | TABLE;
+
+        MMU_STATE[2] = &raw const L1_SEALED as u64;
+        MMU_STATE[3] |= SCTLR_WXN;
+    }
+    publish();
+    sync();
+}
+
+/// Load the tables MMU_STATE names, if this CPU is not already on them.
+pub fn sync() {
+    unsafe {
+        let (ttbr, sctlr) = (MMU_STATE[2], MMU_STATE[3]);
+        let current: u64;
+        asm!("mrs {}, ttbr0_el1", out(reg) current);
+        if current == ttbr { return; }
+        asm!(
+            "dsb ishst",
+            "msr ttbr0_el1, {ttbr}",
+            "isb",
+            "tlbi vmalle1",
+            "dsb nsh",
+            "msr sctlr_el1, {sctlr}",
+            "isb",
+            ttbr = in(reg) ttbr, sctlr = in(reg) sctlr,
+        );
+    }
+}
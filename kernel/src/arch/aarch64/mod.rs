@@ -0,0 +1,307 @@
+//! SurakshaOS AArch64 Architecture Support
+//! Boot, exception vectors, the generic timer, the GICv2 and the MMU, for
+//! EL1 on QEMU virt (`qemu-system-aarch64 -machine virt -cpu cortex-a76`).
+//!
+//! The interface is the RISC-V port's, so the rest of the kernel runs
+//! unchanged:
+//!
+//! - `read_mtime` / `set_timer_compare` are CNTPCT / CNTP_CVAL.
+//! - The mie/mip bits are interrupt classes: MTIE masks the timer, MEIE
+//!   lets device interrupts through the GIC's priority mask, MSIE is the
+//!   SGIs (always on in a GICv2).  `read_mip` reports what is pending.
+//! - `plic_enable` / `plic_claim_complete` drive GIC SPIs.
+//! - Syscalls are `svc #0` with the number in x8, arguments in x0–x5, and
+//!   the result in x0.
+//! - Secondary CPUs start through PSCI CPU_ON; power-off is PSCI too.
+//!
+//! The GICv2 limits SMP to eight CPUs, addressed by MPIDR Aff0.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+mod gic;
+mod mmu;
+mod timer;
+mod trap;
+
+pub use trap::{trap_init, trap_init_secondary, TrapFrame};
+
+// Boot assembly (drops to EL1, sets up stack, clears BSS, MMU on, calls
+// kernel_main)
+global_asm!(include_str!("boot.S"));
+
+// ─── platform ────────────────────────────────────────────────────────────────
+// QEMU virt's layout, until `configure` reads the device tree's.
+
+static TIMEBASE_HZ: AtomicU64   = AtomicU64::new(62_500_000);
+/// PL011 UART0: SPI 1.
+static UART_IRQ:    AtomicU32   = AtomicU32::new(33);
+static PSCI_SMC:    AtomicBool  = AtomicBool::new(false);
+
+/// Take the GIC, PSCI conduit and console interrupt from the device tree,
+/// where it gives them.  The timebase is the counter's own frequency.
+pub fn configure(p: &crate::fdt::Platform) {
+    if let Some((dist, cpu)) = p.gic { gic::configure(dist, cpu); }
+    PSCI_SMC.store(p.psci == Some(crate::fdt::Psci::Smc), Ordering::Relaxed);
+    if let Some(irq) = p.console.and_then(|u| u.irq) { UART_IRQ.store(irq, Ordering::Relaxed); }
+    let hz = timer::frequency();
+    if hz >= 1_000_000 { TIMEBASE_HZ.store(hz, Ordering::Relaxed); }
+}
+
+/// GIC interrupt id of the console UART.
+pub fn uart_irq() -> u32 {
+    UART_IRQ.load(Ordering::Relaxed)
+}
+
+/// Counter ticks per second.
+pub fn timebase_hz() -> u64 {
+    TIMEBASE_HZ.load(Ordering::Relaxed)
+}
+
+/// Interrupt classes, in the RISC-V mie layout
+pub const MIE_MSIE: usize = 1 << 3;
+pub const MIE_MTIE: usize = 1 << 7;
+pub const MIE_MEIE: usize = 1 << 11;
+
+/// Timer interval in counter ticks (~1 s)
+fn timer_interval() -> u64 {
+    timebase_hz()
+}
+
+// ─── tick counter ────────────────────────────────────────────────────────────
+static TICK_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Number of timer ticks since boot.
+pub fn ticks() -> u64 {
+    TICK_COUNT.load(Ordering::Relaxed)
+}
+
+/// Raw counter value (CNTPCT_EL0).
+pub fn read_mtime() -> u64 {
+    timer::counter()
+}
+
+/// Approximate milliseconds since boot.
+pub fn uptime_millis() -> u64 {
+    (read_mtime() as u128 * 1000 / timebase_hz() as u128) as u64
+}
+
+/// Busy-wait for `ms` milliseconds.
+pub fn delay_ms(ms: u64) {
+    let end = uptime_millis() + ms;
+    while uptime_millis() < end {
+        core::hint::spin_loop();
+    }
+}
+
+/// Program the next timer interrupt for absolute count `at`.
+pub fn set_timer_compare(at: u64) {
+    timer::set_compare(at);
+}
+
+/// Count of the next programmed timer interrupt.
+pub fn read_timer_compare() -> u64 {
+    timer::compare()
+}
+
+/// Re-arm the periodic scheduler tick one interval from now.
+pub fn rearm_tick() {
+    set_timer_compare(read_mtime() + timer_interval());
+}
+
+/// Convert milliseconds to counter ticks.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    (ms as u128 * timebase_hz() as u128 / 1000) as u64
+}
+
+/// Convert counter ticks to microseconds.
+pub fn ticks_to_us(ticks: u64) -> u64 {
+    (ticks as u128 * 1_000_000 / timebase_hz() as u128) as u64
+}
+
+// ─── harts ───────────────────────────────────────────────────────────────────
+
+/// SGI used as the IPI.
+const SGI_IPI: u32 = 0;
+
+/// MPIDR_EL1 affinity (Aff2.Aff1.Aff0), as the device tree's cpu `reg`.
+pub fn hart_id() -> usize {
+    let mpidr: usize;
+    unsafe { asm!("mrs {}, mpidr_el1", out(reg) mpidr); }
+    mpidr & 0xff_ffff
+}
+
+/// Raise an SGI on `hart`.
+pub fn send_ipi(hart: usize) {
+    unsafe { asm!("dsb ishst"); }
+    gic::send_sgi(hart & 0xff, SGI_IPI);
+}
+
+/// Drop the IPI pending on this CPU; `hart` must be the caller.
+pub fn clear_ipi(_hart: usize) {
+    gic::clear_sgi(SGI_IPI);
+}
+
+// PSCI function ids (SMC64 calling convention where it matters)
+const PSCI_CPU_ON:     u32 = 0xC400_0003;
+const PSCI_SYSTEM_OFF: u32 = 0x8400_0008;
+
+fn psci(fid: u32, a1: usize, a2: usize, a3: usize) -> isize {
+    let r: isize;
+    unsafe {
+        if PSCI_SMC.load(Ordering::Relaxed) {
+            asm!("smc #0", inlateout("x0") fid as usize => r, in("x1") a1, in("x2") a2, in("x3") a3);
+        } else {
+            asm!("hvc #0", inlateout("x0") fid as usize => r, in("x1") a1, in("x2") a2, in("x3") a3);
+        }
+    }
+    r
+}
+
+extern "C" {
+    fn _secondary();
+}
+
+/// Start CPU `hart` at boot.S's `_secondary`, onto the `smp::Hart` block
+/// at `block`.
+pub fn start_hart(hart: usize, block: usize) -> Result<(), &'static str> {
+    mmu::publish();
+    match psci(PSCI_CPU_ON, hart, _secondary as *const () as usize, block) {
+        0  => Ok(()),
+        -4 => Err("already on"),
+        _  => Err("PSCI CPU_ON failed"),
+    }
+}
+
+/// Nothing to close: CPU_ON names its CPU.
+pub fn start_harts_done() {}
+
+/// The hart-local pointer (TPIDR_EL1); 0 on the boot CPU.
+pub fn hart_local() -> usize {
+    let tp: usize;
+    unsafe { asm!("mrs {}, tpidr_el1", out(reg) tp); }
+    tp
+}
+
+// ─── registers ───────────────────────────────────────────────────────────────
+
+pub fn stack_pointer() -> usize {
+    let sp: usize;
+    unsafe { asm!("mov {}, sp", out(reg) sp); }
+    sp
+}
+
+/// (ELR_EL1, ESR_EL1) of the exception being handled.
+pub fn trap_cause() -> (usize, usize) {
+    let elr: usize;
+    let esr: usize;
+    unsafe {
+        asm!("mrs {}, elr_el1", out(reg) elr);
+        asm!("mrs {}, esr_el1", out(reg) esr);
+    }
+    (elr, esr)
+}
+
+/// Cycle counter, for entropy and timing: the virtual counter, since the
+/// PMU's cycle counter is off at EL1 until set up.
+pub fn cycles() -> u64 {
+    let c: u64;
+    unsafe { asm!("isb", "mrs {}, cntvct_el0", out(reg) c); }
+    c
+}
+
+// ─── interrupt control ───────────────────────────────────────────────────────
+
+const DAIF_I: usize = 1 << 7;
+
+/// Mask IRQs and return the previous DAIF.
+pub fn interrupts_disable() -> usize {
+    let prev: usize;
+    unsafe { asm!("mrs {}, daif", "msr daifset, #2", out(reg) prev); }
+    prev
+}
+
+pub fn interrupts_enable() {
+    unsafe { asm!("msr daifclr, #2"); }
+}
+
+/// Restore the IRQ mask from a value returned by `interrupts_disable`.
+pub fn interrupts_restore(prev: usize) {
+    if prev & DAIF_I == 0 {
+        unsafe { asm!("msr daifclr, #2"); }
+    }
+}
+
+static MIE: AtomicUsize = AtomicUsize::new(0);
+
+pub fn read_mie() -> usize {
+    MIE.load(Ordering::Relaxed)
+}
+
+/// Pending interrupt classes (same layout as mie).
+pub fn read_mip() -> usize {
+    (if timer::fired() { MIE_MTIE } else { 0 })
+        | if gic::device_pending() { MIE_MEIE } else { 0 }
+        | if gic::sgi_pending() { MIE_MSIE } else { 0 }
+}
+
+pub fn write_mie(v: usize) {
+    MIE.store(v, Ordering::Relaxed);
+    timer::set_masked(v & MIE_MTIE == 0);
+    gic::set_devices_masked(v & MIE_MEIE == 0);
+}
+
+/// Stall until an interrupt is pending.  With IRQs masked this returns
+/// without taking the exception, which is how suspend waits.
+pub fn wait_for_interrupt() {
+    unsafe { asm!("wfi", options(nomem, nostack)); }
+}
+
+/// Power the machine off through PSCI.
+pub fn power_off() -> ! {
+    psci(PSCI_SYSTEM_OFF, 0, 0, 0);
+    loop { wait_for_interrupt(); }
+}
+
+/// Power off, telling QEMU whether this is a failure.  PSCI has no way to,
+/// so a failure exits through semihosting SYS_EXIT with status 1: QEMU
+/// must run with `-semihosting`, or the HLT is an undefined instruction.
+pub fn exit(failed: bool) -> ! {
+    if failed {
+        // ADP_Stopped_ApplicationExit, and the status
+        let block: [u64; 2] = [0x2_0026, 1];
+        unsafe { asm!("hlt #0xf000", in("x0") 0x18usize, in("x1") block.as_ptr()); }
+    }
+    power_off()
+}
+
+/// Enable or disable device interrupt `irq` (a GIC id, 32 and up).
+pub fn plic_enable(irq: u32, enabled: bool) {
+    gic::enable(irq, enabled);
+}
+
+/// Claim the highest-priority pending device interrupt (0 = none) and
+/// immediately complete it.  A timer tick or IPI claimed on the way is
+/// handled as if it had been taken.
+pub fn plic_claim_complete() -> u32 {
+    loop {
+        let iar = gic::acknowledge();
+        let irq = iar & 0x3ff;
+        if irq == gic::SPURIOUS { return 0; }
+        gic::end(iar);
+        match irq {
+            timer::IRQ => trap::handle_timer(),
+            0..=15     => trap::handle_ipi(),
+            _          => return irq,
+        }
+    }
+}
+
+// ─── memory protection ───────────────────────────────────────────────────────
+
+/// Make `start..end` read-only and executable, and all else never
+/// executable, for good (see mmu.rs).
+pub fn seal_text(start: usize, end: usize) {
+    mmu::seal_text(start, end);
+    crate::smp::kick_all();
+}
@@ -0,0 +1,54 @@
+//! ARM Generic Timer
+//! The EL1 physical timer: CNTPCT_EL0 counts at CNTFRQ_EL0, and the timer
+//! fires once the count reaches CNTP_CVAL_EL0 — the RISC-V port's mtime
+//! and mtimecmp.  Its interrupt is PPI 14, GIC interrupt 30.
+
+use core::arch::asm;
+
+pub const IRQ: u32 = 30;
+
+// CNTP_CTL_EL0 bits
+const CTL_ENABLE:  usize = 1 << 0;
+const CTL_IMASK:   usize = 1 << 1;
+const CTL_ISTATUS: usize = 1 << 2;
+
+/// Counter frequency, as the firmware set it.
+pub fn frequency() -> u64 {
+    let f: u64;
+    unsafe { asm!("mrs {}, cntfrq_el0", out(reg) f); }
+    f
+}
+
+pub fn counter() -> u64 {
+    let c: u64;
+    unsafe { asm!("isb", "mrs {}, cntpct_el0", out(reg) c); }
+    c
+}
+
+pub fn set_compare(at: u64) {
+    unsafe { asm!("msr cntp_cval_el0, {}", in(reg) at); }
+}
+
+pub fn compare() -> u64 {
+    let c: u64;
+    unsafe { asm!("mrs {}, cntp_cval_el0", out(reg) c); }
+    c
+}
+
+fn ctl() -> usize {
+    let c: usize;
+    unsafe { asm!("mrs {}, cntp_ctl_el0", out(reg) c); }
+    c
+}
+
+/// Run the timer, with its interrupt masked or not.  Masked, it still
+/// counts and reports `fired`, like a pending but disabled MTIP.
+pub fn set_masked(masked: bool) {
+    let v = CTL_ENABLE | if masked { CTL_IMASK } else { 0 };
+    unsafe { asm!("msr cntp_ctl_el0, {}", "isb", in(reg) v); }
+}
+
+/// Whether the count has reached the compare value.
+pub fn fired() -> bool {
+    ctl() & CTL_ISTATUS != 0
+}
@@ -0,0 +1,339 @@
+//! AArch64 exception vectors and dispatch.
+//! Every vector saves x0–x30, the interrupted sp, ELR/SPSR and ESR/FAR
+//! in a `TrapFrame` on SP_EL1, with the stack canary above it, and hands
+//! it to `_trap_handler_rust` with the vector's index.  That dispatches:
+//!
+//! - IRQs: SGIs (IPIs), the timer tick, and device interrupts, each to
+//!   the handler its driver registered;
+//! - SVC: a system call;
+//! - BRK: logged and stepped over;
+//! - a BTI fault: a CFI violation;
+//! - anything else is a fault.  A process's fault kills it (a tag check
+//!   fault is reported to the security monitor first); the kernel's is
+//!   an oops — exception class, registers and all — and a panic.
+//!
+//! A trap from EL0 arrives on SP_EL1 by itself; the process's sp is
+//! SP_EL0, saved in the frame and restored from it on the way back.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::Ordering;
+
+use super::{gic, mmu, timer};
+use crate::arch::trap::{self as common, Access};
+
+/// Bytes of stack a trap takes: the frame, the canary above it, and
+/// padding to keep sp 16-byte aligned.
+const FRAME_SIZE: usize = core::mem::size_of::<TrapFrame>() + 16;
+
+// ESR_EL1 exception classes
+const EC_UNKNOWN:      usize = 0x00;
+const EC_BTI:          usize = 0x0D;
+const EC_ILLEGAL:      usize = 0x0E;
+const EC_SVC64:        usize = 0x15;
+const EC_SYSREG:       usize = 0x18;
+const EC_IABT_LOWER:   usize = 0x20;
+const EC_IABT_CUR:     usize = 0x21;
+const EC_PC_ALIGN:     usize = 0x22;
+const EC_DABT_LOWER:   usize = 0x24;
+const EC_DABT_CUR:     usize = 0x25;
+const EC_SP_ALIGN:     usize = 0x26;
+const EC_SERROR:       usize = 0x2F;
+const EC_BRK:          usize = 0x3C;
+
+/// Data abort status code of a synchronous tag check fault.
+const DFSC_TAG_CHECK: usize = 0x11;
+/// ISS bit: the data abort was a write.
+const ISS_WNR: usize = 1 << 6;
+
+/// Everything a trap saves, in stack order.  The handler may change it:
+/// the registers, ELR and SPSR are restored from it on return (a
+/// syscall's result, the pc past a BRK), as is sp for a trap from EL0.
+#[repr(C)]
+pub struct TrapFrame {
+    /// x0–x28.
+    pub x:    [usize; 29],
+    pub fp:   usize,
+    /// x30, the link register.
+    pub ra:   usize,
+    /// sp as it was when the trap was taken: SP_EL0 from EL0.
+    pub sp:   usize,
+    pub elr:  usize,
+    pub spsr: usize,
+    pub esr:  usize,
+    pub far:  usize,
+}
+
+impl TrapFrame {
+    /// Whether the trap came from EL0.
+    pub fn from_user(&self) -> bool {
+        self.spsr & 0xf == 0
+    }
+
+    fn class(&self) -> usize {
+        self.esr >> 26 & 0x3f
+    }
+
+    /// What ESR's exception class says happened.
+    pub fn cause(&self) -> &'static str {
+        match self.class() {
+            EC_UNKNOWN    => "undefined instruction",
+            EC_BTI        => "branch target exception",
+            EC_ILLEGAL    => "illegal execution state",
+            EC_SVC64      => "svc",
+            EC_SYSREG     => "trapped system register access",
+            EC_IABT_LOWER | EC_IABT_CUR => "instruction abort",
+            EC_PC_ALIGN   => "pc alignment fault",
+            EC_DABT_LOWER | EC_DABT_CUR if self.esr & 0x3f == DFSC_TAG_CHECK => "tag check fault",
+            EC_DABT_LOWER | EC_DABT_CUR => "data abort",
+            EC_SP_ALIGN   => "sp alignment fault",
+            EC_SERROR     => "SError",
+            EC_BRK        => "breakpoint",
+            _             => "unknown exception",
+        }
+    }
+}
+
+// ─── initialisation ──────────────────────────────────────────────────────────
+
+/// Install the exception vectors, bring up the GIC and enable the timer
+/// interrupt.
+pub fn trap_init() {
+    unsafe { asm!("msr vbar_el1, {}", "isb", in(reg) _vectors as *const () as usize); }
+    gic::init_distributor();
+    gic::init_cpu();
+    gic::enable(timer::IRQ, true);
+    super::write_mie(super::MIE_MSIE | super::MIE_MTIE);
+    // Arm the first timer compare
+    super::rearm_tick();
+    unsafe { asm!("msr daifclr, #2"); }
+    crate::power::governor_tick();
+}
+
+/// The same on a secondary CPU, but with SGIs only: the tick and device
+/// interrupts stay with the boot CPU.
+pub fn trap_init_secondary() {
+    unsafe { asm!("msr vbar_el1, {}", "isb", in(reg) _vectors as *const () as usize); }
+    gic::init_cpu();
+    unsafe { asm!("msr daifclr, #2"); }
+}
+
+// ─── exception vectors ───────────────────────────────────────────────────────
+
+extern "C" {
+    fn _vectors();
+}
+
+// Sixteen vectors, 0x80 apart: {sync, IRQ, FIQ, SError} taken from EL1
+// on SP_EL0, EL1 on SP_EL1, EL0 AArch64 and EL0 AArch32.  Each saves x0/x1
+// and passes its index to `_trap_common`, which saves the rest of the
+// frame and the stack canary above it, calls the Rust handler, checks the
+// canary, then restores and returns via `eret`.
+global_asm!(
+    ".macro VECTOR kind",
+    ".balign 0x80",
+    "sub sp, sp, #{size}",
+    "stp x0, x1, [sp, #0]",
+    "mov x1, #\\kind",
+    "b _trap_common",
+    ".endm",
+
+    ".section .text",
+    ".balign 0x800",
+    ".globl _vectors",
+    "_vectors:",
+    "VECTOR 0", "VECTOR 1", "VECTOR 2", "VECTOR 3",
+    "VECTOR 4", "VECTOR 5", "VECTOR 6", "VECTOR 7",
+    "VECTOR 8", "VECTOR 9", "VECTOR 10", "VECTOR 11",
+    "VECTOR 12", "VECTOR 13", "VECTOR 14", "VECTOR 15",
+
+    "_trap_common:",
+    "stp x2,  x3,  [sp, #16]",
+    "stp x4,  x5,  [sp, #32]",
+    "stp x6,  x7,  [sp, #48]",
+    "stp x8,  x9,  [sp, #64]",
+    "stp x10, x11, [sp, #80]",
+    "stp x12, x13, [sp, #96]",
+    "stp x14, x15, [sp, #112]",
+    "stp x16, x17, [sp, #128]",
+    "stp x18, x19, [sp, #144]",
+    "stp x20, x21, [sp, #160]",
+    "stp x22, x23, [sp, #176]",
+    "stp x24, x25, [sp, #192]",
+    "stp x26, x27, [sp, #208]",
+    "stp x28, x29, [sp, #224]",
+    // The interrupted sp: SP_EL0 from EL0 (vectors 8 and up), or the
+    // kernel's from above the frame
+    "cmp x1, #8",
+    "b.lo 1f",
+    "mrs x9, sp_el0",
+    "b 2f",
+    "1:",
+    "add x9, sp, #{size}",
+    "2:",
+    "stp x30, x9,  [sp, #240]",
+    "mrs x9,  elr_el1",
+    "mrs x10, spsr_el1",
+    "stp x9,  x10, [sp, #256]",
+    "mrs x9,  esr_el1",
+    "mrs x10, far_el1",
+    "stp x9,  x10, [sp, #272]",
+    "adrp x9, {canary}",
+    "ldr x9, [x9, :lo12:{canary}]",
+    "str x9, [sp, #288]",
+
+    // Call the Rust handler with the frame and the vector
+    "mov x0, sp",
+    "bl {handler}",
+
+    // A handler that overran its frame has clobbered the canary
+    "adrp x9, {canary}",
+    "ldr x9, [x9, :lo12:{canary}]",
+    "ldr x10, [sp, #288]",
+    "cmp x9, x10",
+    "b.ne 4f",
+
+    "ldp x9,  x10, [sp, #256]",
+    "msr elr_el1, x9",
+    "msr spsr_el1, x10",
+    // Back to EL0: its sp from the frame
+    "tst x10, #0xf",
+    "b.ne 3f",
+    "ldr x9, [sp, #248]",
+    "msr sp_el0, x9",
+    "3:",
+    "ldr x30,      [sp, #240]",
+    "ldp x0,  x1,  [sp, #0]",
+    "ldp x2,  x3,  [sp, #16]",
+    "ldp x4,  x5,  [sp, #32]",
+    "ldp x6,  x7,  [sp, #48]",
+    "ldp x8,  x9,  [sp, #64]",
+    "ldp x10, x11, [sp, #80]",
+    "ldp x12, x13, [sp, #96]",
+    "ldp x14, x15, [sp, #112]",
+    "ldp x16, x17, [sp, #128]",
+    "ldp x18, x19, [sp, #144]",
+    "ldp x20, x21, [sp, #160]",
+    "ldp x22, x23, [sp, #176]",
+    "ldp x24, x25, [sp, #192]",
+    "ldp x26, x27, [sp, #208]",
+    "ldp x28, x29, [sp, #224]",
+    "add sp, sp, #{size}",
+    "eret",
+
+    "4:",
+    "mov x0, sp",
+    "mov x1, x10",
+    "bl {smashed}",
+    size    = const FRAME_SIZE,
+    handler = sym _trap_handler_rust,
+    canary  = sym crate::stackguard::STACK_CANARY,
+    smashed = sym crate::stackguard::_stack_smashed,
+);
+
+// Offsets the assembly above uses
+const _: () = {
+    assert!(core::mem::offset_of!(TrapFrame, ra) == 240);
+    assert!(core::mem::offset_of!(TrapFrame, elr) == 256);
+    assert!(core::mem::offset_of!(TrapFrame, esr) == 272);
+    assert!(FRAME_SIZE == 304);
+};
+
+// ─── dispatch ────────────────────────────────────────────────────────────────
+
+#[no_mangle]
+extern "C" fn _trap_handler_rust(frame: &mut TrapFrame, vector: usize) {
+    match vector % 4 {
+        1 => handle_irq(),
+        0 => match frame.class() {
+            EC_SVC64 => {
+                // svc: x8 = number, x0–x5 = arguments; ELR is already past it
+                let x = &frame.x;
+                let args = [x[0], x[1], x[2], x[3], x[4], x[5]];
+                frame.x[0] = crate::syscall::dispatch(x[8], args) as usize;
+            }
+            EC_BRK => {
+                crate::warn!("breakpoint at pc={:#x}", frame.elr);
+                frame.elr += 4;
+            }
+            // A branch to something other than a BTI landing pad
+            EC_BTI => crate::cfi::violation(frame.elr, 2),
+            EC_IABT_LOWER | EC_IABT_CUR => page_fault(frame, Access::Execute),
+            EC_DABT_LOWER | EC_DABT_CUR if frame.esr & 0x3f == DFSC_TAG_CHECK => {
+                if frame.from_user() { crate::mte::tag_fault(crate::process::current_pid(), Some(frame.far)); }
+                fault(frame)
+            }
+            EC_DABT_LOWER | EC_DABT_CUR => {
+                page_fault(frame, if frame.esr & ISS_WNR != 0 { Access::Write } else { Access::Read })
+            }
+            _ => fault(frame),
+        },
+        2 => crate::warn!("FIQ at pc={:#x} ignored", frame.elr),
+        _ => fault(frame), // SError
+    }
+
+    crate::stackguard::check(frame.elr, frame.esr);
+}
+
+/// Take every pending interrupt this CPU handles: the tick and IPIs here,
+/// device interrupts in their drivers' handlers.
+fn handle_irq() {
+    loop {
+        let iar = gic::acknowledge();
+        let irq = iar & 0x3ff;
+        if irq == gic::SPURIOUS { break; }
+        match irq {
+            timer::IRQ => handle_timer(),
+            0..=15     => handle_ipi(),
+            _          => common::dispatch_irq(irq),
+        }
+        gic::end(iar);
+    }
+}
+
+pub(super) fn handle_ipi() {
+    mmu::sync();
+    crate::smp::ipi();
+}
+
+/// Reset the timer for the next tick.
+pub(super) fn handle_timer() {
+    super::TICK_COUNT.fetch_add(1, Ordering::Relaxed);
+    super::rearm_tick();
+    crate::power::governor_tick();
+    crate::power::idle_tick();
+    crate::thermal::thermal_tick();
+    crate::charger::charger_tick();
+    crate::alarm::alarm_tick();
+    crate::brightness::brightness_tick();
+    crate::net::net_tick();
+    crate::init::init_tick();
+    crate::capability::capability_tick();
+}
+
+/// A translation or permission fault.  Nothing is paged: the kernel's
+/// tables map all of RAM and the devices for good, so any such fault is
+/// a bad access.
+fn page_fault(frame: &mut TrapFrame, access: Access) {
+    crate::debug!("page fault: {} of {:#x} at pc={:#x}", access.as_str(), frame.far, frame.elr);
+    fault(frame)
+}
+
+/// A fault the code that took it cannot get past.
+fn fault(frame: &mut TrapFrame) -> ! {
+    if frame.from_user() { common::user_fault(frame.cause(), frame.elr, frame.far); }
+    oops(frame);
+    panic!("{} at pc={:#x} (far={:#x} esr={:#x})", frame.cause(), frame.elr, frame.far, frame.esr);
+}
+
+/// Log everything the frame holds, to the console and to the kernel log,
+/// which outlives the reset that follows.
+fn oops(frame: &TrapFrame) {
+    let f = frame;
+    crate::error!("oops: {} on cpu {}, pid {}", f.cause(), super::hart_id(), crate::process::current_pid());
+    crate::error!("  elr {:#018x}  far {:#018x}  esr {:#x}  spsr {:#x}", f.elr, f.far, f.esr, f.spsr);
+    for row in (0..28).step_by(4) {
+        crate::error!("  x{:<2} {:#018x}  x{:<2} {:#018x}  x{:<2} {:#018x}  x{:<2} {:#018x}",
+            row, f.x[row], row + 1, f.x[row + 1], row + 2, f.x[row + 2], row + 3, f.x[row + 3]);
+    }
+    crate::error!("  x28 {:#018x}  fp  {:#018x}  lr  {:#018x}  sp  {:#018x}", f.x[28], f.fp, f.ra, f.sp);
+}
@@ -0,0 +1,29 @@
+//! SurakshaOS Architecture Support
+//! One module per ISA, each with its boot assembly, trap handling, timer
+//! and interrupt controller, behind the same interface: the RISC-V one the
+//! kernel was written against (mie/mip interrupt classes, `plic_*`,
+//! `read_mtime`), which the AArch64 port maps onto the GIC and the
+//! generic timer.
+//!
+//! Trap entry, cause decoding and oops output are per port; the device
+//! interrupt table and what becomes of a faulting process are shared
+//! (trap.rs).
+//!
+//! - riscv64: M-mode on QEMU virt (`-bios none`), CLINT and PLIC; or,
+//!   with the `sbi` feature, S-mode under OpenSBI, through SBI calls.
+//! - aarch64: EL1 on QEMU virt (`-machine virt,gic-version=2`), GICv2,
+//!   the EL1 physical timer, PSCI, and 4 KiB-granule page tables.
+
+pub mod trap;
+
+pub use trap::{register_irq, unregister_irq, Access};
+
+#[cfg(target_arch = "riscv64")]
+mod riscv64;
+#[cfg(target_arch = "riscv64")]
+pub use riscv64::*;
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64;
+#[cfg(target_arch = "aarch64")]
+pub use aarch64::*;
@@ -0,0 +1,371 @@
+//! SurakshaOS RISC-V Architecture Support
+//! Timer management, the CLINT and PLIC, and basic CSR helpers; trap
+//! entry and dispatch are in trap.rs.
+//!
+//! By default the kernel runs in M-mode (QEMU virt with -bios none) and
+//! drives the CLINT itself.  Built with the `sbi` feature it runs in
+//! S-mode under OpenSBI (`make run-sbi`), linked 2 MiB up to leave the
+//! firmware its RAM, and asks the firmware (sbi.rs) for what only M-mode
+//! can do: the timer compare, IPIs, starting harts and powering off.  The
+//! interface keeps its M-mode names either way — `read_mie` reads sie,
+//! `MIE_MTIE` is STIE.
+
+use core::arch::{asm, global_asm};
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+/// Whether the kernel runs in S-mode under SBI firmware.
+pub const SMODE: bool = cfg!(feature = "sbi");
+
+/// The trap CSR `x` (or the return, `mode!("ret")`) of the mode the
+/// kernel runs in: mstatus, or sstatus under SBI.
+#[cfg(not(feature = "sbi"))]
+macro_rules! mode { ($x:literal) => { concat!("m", $x) } }
+#[cfg(feature = "sbi")]
+macro_rules! mode { ($x:literal) => { concat!("s", $x) } }
+
+pub mod sbi;
+mod trap;
+
+pub use trap::{trap_init, trap_init_secondary, TrapFrame};
+
+// Boot assembly (sets up stack, clears BSS, calls kernel_main)
+global_asm!(include_str!("boot.S"), sbi = const SMODE as u8);
+
+// ─── platform ────────────────────────────────────────────────────────────────
+// QEMU virt's layout, until `configure` reads the device tree's.
+
+static CLINT_BASE:  AtomicUsize = AtomicUsize::new(0x0200_0000);
+static PLIC_BASE:   AtomicUsize = AtomicUsize::new(0x0C00_0000);
+static TIMEBASE_HZ: AtomicU64   = AtomicU64::new(10_000_000);
+static UART_IRQ:    AtomicU32   = AtomicU32::new(10);
+
+/// Take the interrupt controllers, timebase and console interrupt from
+/// the device tree, where it gives them.
+pub fn configure(p: &crate::fdt::Platform) {
+    if let Some(base) = p.clint { CLINT_BASE.store(base, Ordering::Relaxed); }
+    if let Some(base) = p.plic { PLIC_BASE.store(base, Ordering::Relaxed); }
+    if let Some(hz) = p.timebase_hz.filter(|&hz| hz >= 1_000_000) { TIMEBASE_HZ.store(hz, Ordering::Relaxed); }
+    if let Some(irq) = p.console.and_then(|u| u.irq) { UART_IRQ.store(irq, Ordering::Relaxed); }
+}
+
+// CLINT registers (hart 0)
+fn clint_msip(hart: usize) -> usize { CLINT_BASE.load(Ordering::Relaxed) + 4 * hart }
+fn clint_mtimecmp() -> usize { CLINT_BASE.load(Ordering::Relaxed) + 0x4000 }
+fn clint_mtime() -> usize { CLINT_BASE.load(Ordering::Relaxed) + 0xBFF8 }
+
+// PLIC registers, for the boot hart's context in the kernel's mode:
+// QEMU virt gives each hart an M-mode context and then an S-mode one
+fn plic_base() -> usize { PLIC_BASE.load(Ordering::Relaxed) }
+fn plic_context() -> usize { if SMODE { 2 * BOOT_HART_ID.load(Ordering::Relaxed) + 1 } else { 0 } }
+fn plic_enable_base() -> usize { plic_base() + 0x2000 + 0x80 * plic_context() }
+fn plic_threshold() -> usize { plic_base() + 0x20_0000 + 0x1000 * plic_context() }
+fn plic_claim() -> usize { plic_threshold() + 4 }
+
+/// PLIC source number of the console UART.
+pub fn uart_irq() -> u32 {
+    UART_IRQ.load(Ordering::Relaxed)
+}
+
+/// mtime ticks per second.
+pub fn timebase_hz() -> u64 {
+    TIMEBASE_HZ.load(Ordering::Relaxed)
+}
+
+/// mie bits (sie's SSIE, STIE and SEIE under SBI)
+pub const MIE_MSIE: usize = if SMODE { 1 << 1 } else { 1 << 3 };
+pub const MIE_MTIE: usize = if SMODE { 1 << 5 } else { 1 << 7 };
+pub const MIE_MEIE: usize = if SMODE { 1 << 9 } else { 1 << 11 };
+
+/// mstatus.MIE, or sstatus.SIE
+const STATUS_IE: usize = if SMODE { 1 << 1 } else { 1 << 3 };
+
+/// Timer interval in CLINT ticks (~1 s)
+fn timer_interval() -> u64 {
+    timebase_hz()
+}
+
+// ─── tick counter ────────────────────────────────────────────────────────────
+static mut TICK_COUNT: u64 = 0;
+
+/// Number of timer ticks since boot.
+pub fn ticks() -> u64 {
+    unsafe { core::ptr::read_volatile(&raw const TICK_COUNT) }
+}
+
+/// Raw CLINT mtime counter value (the `time` CSR under SBI).
+pub fn read_mtime() -> u64 {
+    if SMODE {
+        let t: u64;
+        unsafe { asm!("rdtime {}", out(reg) t); }
+        return t;
+    }
+    unsafe { core::ptr::read_volatile(clint_mtime() as *const u64) }
+}
+
+/// Approximate milliseconds since boot (based on CLINT mtime).
+pub fn uptime_millis() -> u64 {
+    (read_mtime() as u128 * 1000 / timebase_hz() as u128) as u64
+}
+
+/// Busy-wait for `ms` milliseconds.
+pub fn delay_ms(ms: u64) {
+    let end = uptime_millis() + ms;
+    while uptime_millis() < end {
+        core::hint::spin_loop();
+    }
+}
+
+/// The compare last given the firmware, which cannot be read back.
+static TIMER_COMPARE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Program the next timer interrupt for absolute mtime `at`.
+pub fn set_timer_compare(at: u64) {
+    if SMODE {
+        TIMER_COMPARE.store(at, Ordering::Relaxed);
+        sbi::set_timer(at);
+        return;
+    }
+    unsafe { core::ptr::write_volatile(clint_mtimecmp() as *mut u64, at); }
+}
+
+/// mtime of the next programmed timer interrupt.
+pub fn read_timer_compare() -> u64 {
+    if SMODE { return TIMER_COMPARE.load(Ordering::Relaxed); }
+    unsafe { core::ptr::read_volatile(clint_mtimecmp() as *const u64) }
+}
+
+/// Re-arm the periodic scheduler tick one interval from now.
+pub fn rearm_tick() {
+    set_timer_compare(read_mtime() + timer_interval());
+}
+
+/// Convert milliseconds to CLINT ticks.
+pub fn ms_to_ticks(ms: u64) -> u64 {
+    (ms as u128 * timebase_hz() as u128 / 1000) as u64
+}
+
+/// Convert CLINT ticks to microseconds.
+pub fn ticks_to_us(ticks: u64) -> u64 {
+    (ticks as u128 * 1_000_000 / timebase_hz() as u128) as u64
+}
+
+// ─── harts ───────────────────────────────────────────────────────────────────
+
+/// The hart the firmware booted, as boot.S found it in a0.  Under SBI
+/// it need not be hart 0.
+#[no_mangle]
+static BOOT_HART_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub fn hart_id() -> usize {
+    if SMODE {
+        // mhartid is M-mode only: the boot hart's id came in a0, and the
+        // others' are in their blocks
+        return if hart_local() == 0 { BOOT_HART_ID.load(Ordering::Relaxed) } else { crate::smp::current().id };
+    }
+    let id: usize;
+    unsafe { asm!("csrr {}, mhartid", out(reg) id); }
+    id
+}
+
+/// Raise a software interrupt on `hart`.
+pub fn send_ipi(hart: usize) {
+    unsafe { asm!("fence rw, rw"); }
+    if SMODE {
+        if let Err(e) = sbi::send_ipi(hart) { crate::warn!("ipi to hart {}: {}", hart, e); }
+        return;
+    }
+    unsafe { core::ptr::write_volatile(clint_msip(hart) as *mut u32, 1); }
+}
+
+/// Clear `hart`'s software interrupt; under SBI a hart can only clear
+/// its own.
+pub fn clear_ipi(hart: usize) {
+    if SMODE {
+        unsafe { asm!("csrc sip, {}", in(reg) MIE_MSIE); }
+        return;
+    }
+    unsafe { core::ptr::write_volatile(clint_msip(hart) as *mut u32, 0); }
+}
+
+/// The mailbox boot.S reads: the hart being released, and its block.
+#[no_mangle]
+static SMP_BOOT_HART:  AtomicUsize = AtomicUsize::new(usize::MAX);
+#[no_mangle]
+static SMP_BOOT_BLOCK: AtomicUsize = AtomicUsize::new(0);
+
+/// Release `hart` from boot.S onto the `smp::Hart` block at `block`.
+/// Under SBI the hart is stopped in the firmware, and HSM starts it at
+/// boot.S's `_secondary` with the block in a1.
+pub fn start_hart(hart: usize, block: usize) -> Result<(), &'static str> {
+    if SMODE {
+        extern "C" { fn _secondary(); }
+        return sbi::hart_start(hart, _secondary as *const () as usize, block);
+    }
+    SMP_BOOT_BLOCK.store(block, Ordering::Release);
+    SMP_BOOT_HART.store(hart, Ordering::Release);
+    send_ipi(hart);
+    Ok(())
+}
+
+/// Close the mailbox once every hart is released.
+pub fn start_harts_done() {
+    SMP_BOOT_HART.store(usize::MAX, Ordering::Release);
+}
+
+/// The hart-local pointer (tp); 0 on the boot hart.
+pub fn hart_local() -> usize {
+    let tp: usize;
+    unsafe { asm!("mv {}, tp", out(reg) tp); }
+    tp
+}
+
+// ─── registers ───────────────────────────────────────────────────────────────
+
+pub fn stack_pointer() -> usize {
+    let sp: usize;
+    unsafe { asm!("mv {}, sp", out(reg) sp); }
+    sp
+}
+
+/// (mepc, mcause) of the trap being handled.
+pub fn trap_cause() -> (usize, usize) {
+    let mepc: usize;
+    let mcause: usize;
+    unsafe {
+        asm!(concat!("csrr {}, ", mode!("epc")),   out(reg) mepc);
+        asm!(concat!("csrr {}, ", mode!("cause")), out(reg) mcause);
+    }
+    (mepc, mcause)
+}
+
+/// Cycle counter, for entropy and timing.
+pub fn cycles() -> u64 {
+    let c: u64;
+    if SMODE {
+        unsafe { asm!("rdcycle {}", out(reg) c); }
+    } else {
+        unsafe { asm!("csrr {}, mcycle", out(reg) c); }
+    }
+    c
+}
+
+// ─── interrupt control ───────────────────────────────────────────────────────
+
+/// Clear mstatus.MIE and return the previous mstatus.
+pub fn interrupts_disable() -> usize {
+    let prev: usize;
+    unsafe { asm!(concat!("csrrc {}, ", mode!("status"), ", {}"), out(reg) prev, in(reg) STATUS_IE); }
+    prev
+}
+
+pub fn interrupts_enable() {
+    unsafe { asm!(concat!("csrs ", mode!("status"), ", {}"), in(reg) STATUS_IE); }
+}
+
+/// Restore mstatus.MIE from a value returned by `interrupts_disable`.
+pub fn interrupts_restore(prev: usize) {
+    if prev & STATUS_IE != 0 { interrupts_enable(); }
+}
+
+pub fn read_mie() -> usize {
+    let v: usize;
+    unsafe { asm!(concat!("csrr {}, ", mode!("ie")), out(reg) v); }
+    v
+}
+
+/// Pending-interrupt bits (same layout as mie).
+pub fn read_mip() -> usize {
+    let v: usize;
+    unsafe { asm!(concat!("csrr {}, ", mode!("ip")), out(reg) v); }
+    v
+}
+
+pub fn write_mie(v: usize) {
+    unsafe { asm!(concat!("csrw ", mode!("ie"), ", {}"), in(reg) v); }
+}
+
+/// Stall until an enabled interrupt is pending.  With mstatus.MIE clear
+/// this returns without taking the trap, which is how suspend waits.
+pub fn wait_for_interrupt() {
+    unsafe { asm!("wfi", options(nomem, nostack)); }
+}
+
+/// Power the machine off: via the SiFive test device, or under SBI by
+/// asking the firmware.
+pub fn power_off() -> ! {
+    if SMODE {
+        let e = sbi::system_reset(sbi::Reset::Shutdown, false);
+        crate::error!("power off: {}", e);
+    } else {
+        unsafe { core::ptr::write_volatile(0x10_0000 as *mut u32, 0x5555); }
+    }
+    loop { wait_for_interrupt(); }
+}
+
+/// Power off, telling QEMU whether this is a failure: the test device's
+/// FAIL makes it exit with status 1.  Under SBI, OpenSBI's driver for the
+/// device shuts down the same either way, so only the reason is passed.
+pub fn exit(failed: bool) -> ! {
+    if SMODE {
+        let e = sbi::system_reset(sbi::Reset::Shutdown, failed);
+        crate::error!("power off: {}", e);
+    } else {
+        let code = if failed { 1 << 16 | 0x3333 } else { 0x5555 };
+        unsafe { core::ptr::write_volatile(0x10_0000 as *mut u32, code); }
+    }
+    loop { wait_for_interrupt(); }
+}
+
+pub fn plic_enable(irq: u32, enabled: bool) {
+    unsafe {
+        let prio = (plic_base() + 4 * irq as usize) as *mut u32;
+        core::ptr::write_volatile(prio, 1);
+        let word = (plic_enable_base() + 4 * (irq as usize / 32)) as *mut u32;
+        let bit  = 1u32 << (irq % 32);
+        let cur  = core::ptr::read_volatile(word);
+        core::ptr::write_volatile(word, if enabled { cur | bit } else { cur & !bit });
+        core::ptr::write_volatile(plic_threshold() as *mut u32, 0);
+    }
+}
+
+/// Claim the highest-priority pending external interrupt (0 = none) and
+/// immediately mark it complete.
+pub fn plic_claim_complete() -> u32 {
+    let irq = plic_claim_irq();
+    if irq != 0 { plic_complete(irq); }
+    irq
+}
+
+/// Claim the highest-priority pending external interrupt (0 = none).
+fn plic_claim_irq() -> u32 {
+    unsafe { core::ptr::read_volatile(plic_claim() as *const u32) }
+}
+
+/// Tell the PLIC `irq` has been handled.
+fn plic_complete(irq: u32) {
+    unsafe { core::ptr::write_volatile(plic_claim() as *mut u32, irq); }
+}
+
+// ─── memory protection ───────────────────────────────────────────────────────
+
+/// Make `start..end` read/execute-only with a locked PMP entry (entry 1,
+/// top-of-range, entry 0 as its base).  A locked entry binds M-mode as
+/// well and cannot be changed again until reset.
+///
+/// The PMP is M-mode's: under SBI the firmware owns it, and the text
+/// stays writable until the kernel has page tables of its own.
+pub fn seal_text(start: usize, end: usize) {
+    if SMODE {
+        crate::warn!("kernel text {:#x}..{:#x} not sealed: no PMP in S-mode", start, end);
+        return;
+    }
+    const PMP_R:   usize = 1 << 0;
+    const PMP_X:   usize = 1 << 2;
+    const PMP_TOR: usize = 1 << 3;
+    const PMP_L:   usize = 1 << 7;
+    unsafe {
+        asm!("csrw pmpaddr0, {}", in(reg) start >> 2);
+        asm!("csrw pmpaddr1, {}", in(reg) end.next_multiple_of(4) >> 2);
+        asm!("csrs pmpcfg0, {}", in(reg) (PMP_L | PMP_TOR | PMP_X | PMP_R) << 8);
+    }
+}
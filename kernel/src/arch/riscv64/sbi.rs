@@ -0,0 +1,146 @@
+//! RISC-V Supervisor Binary Interface calls.
+//! What an S-mode kernel asks of the M-mode firmware beneath it (OpenSBI):
+//! an `ecall` with the extension in a7, the function in a6 and arguments
+//! in a0–a5, answered with an error in a0 and a value in a1.
+//!
+//! The `sbi` build runs in S-mode and takes its timer, IPIs, secondary
+//! harts, console fallback and shutdown from here.  The M-mode build has
+//! no firmware beneath it: an ecall would trap into the kernel itself.
+
+use core::arch::asm;
+
+// Extension ids
+pub const EID_BASE:  usize = 0x10;
+pub const EID_TIME:  usize = 0x5449_4D45; // "TIME"
+pub const EID_IPI:   usize = 0x0073_5049; // "sPI"
+pub const EID_HSM:   usize = 0x0048_534D; // "HSM"
+pub const EID_SRST:  usize = 0x5352_5354; // "SRST"
+pub const EID_DBCN:  usize = 0x4442_434E; // "DBCN"
+/// The legacy console_putchar, for firmware older than DBCN.
+const EID_LEGACY_PUTCHAR: usize = 0x01;
+
+// Base functions
+const FID_SPEC_VERSION:    usize = 0;
+const FID_IMPL_ID:         usize = 1;
+const FID_PROBE_EXTENSION: usize = 3;
+
+// HSM hart states
+pub const HART_STARTED:       usize = 0;
+pub const HART_STOPPED:       usize = 1;
+pub const HART_START_PENDING: usize = 2;
+pub const HART_STOP_PENDING:  usize = 3;
+
+/// SRST reset types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reset {
+    Shutdown   = 0,
+    ColdReboot = 1,
+    WarmReboot = 2,
+}
+
+/// A raw call: (error, value).
+pub fn ecall(eid: usize, fid: usize, args: [usize; 3]) -> (isize, usize) {
+    let (err, val): (isize, usize);
+    unsafe {
+        asm!("ecall",
+            inlateout("a0") args[0] => err, inlateout("a1") args[1] => val,
+            in("a2") args[2], in("a6") fid, in("a7") eid);
+    }
+    (err, val)
+}
+
+/// What an SBI error code means.
+pub fn error_str(err: isize) -> &'static str {
+    match err {
+        -1 => "failed",
+        -2 => "not supported",
+        -3 => "invalid parameter",
+        -4 => "denied",
+        -5 => "invalid address",
+        -6 => "already available",
+        -7 => "already started",
+        -8 => "already stopped",
+        -9 => "no shared memory",
+        _  => "unknown SBI error",
+    }
+}
+
+fn call(eid: usize, fid: usize, args: [usize; 3]) -> Result<usize, &'static str> {
+    match ecall(eid, fid, args) {
+        (0, val) => Ok(val),
+        (err, _) => Err(error_str(err)),
+    }
+}
+
+// ─── base ────────────────────────────────────────────────────────────────────
+
+/// (major, minor) of the SBI specification the firmware implements.
+pub fn spec_version() -> (usize, usize) {
+    let v = call(EID_BASE, FID_SPEC_VERSION, [0; 3]).unwrap_or(0);
+    ((v >> 24) & 0x7f, v & 0xff_ffff)
+}
+
+/// Who implemented it: 1 is OpenSBI.
+pub fn impl_id() -> usize {
+    call(EID_BASE, FID_IMPL_ID, [0; 3]).unwrap_or(0)
+}
+
+pub fn probe(eid: usize) -> bool {
+    call(EID_BASE, FID_PROBE_EXTENSION, [eid, 0, 0]).is_ok_and(|v| v != 0)
+}
+
+// ─── timer, IPIs ─────────────────────────────────────────────────────────────
+
+/// Raise the supervisor timer interrupt once `time` reaches `at`, and
+/// clear any pending one.
+pub fn set_timer(at: u64) {
+    let _ = call(EID_TIME, 0, [at as usize, 0, 0]);
+}
+
+/// Raise a supervisor software interrupt on `hart`.
+pub fn send_ipi(hart: usize) -> Result<(), &'static str> {
+    // hart_mask bit 0, relative to hart_mask_base = hart
+    call(EID_IPI, 0, [1, hart, 0]).map(drop)
+}
+
+// ─── harts ───────────────────────────────────────────────────────────────────
+
+/// Start stopped hart `hart` in S-mode at `entry`, with a0 = its id and
+/// a1 = `opaque`, translation and interrupts off.
+pub fn hart_start(hart: usize, entry: usize, opaque: usize) -> Result<(), &'static str> {
+    call(EID_HSM, 0, [hart, entry, opaque]).map(drop)
+}
+
+/// `hart`'s HSM state (HART_STARTED, ...).
+pub fn hart_status(hart: usize) -> Result<usize, &'static str> {
+    call(EID_HSM, 2, [hart, 0, 0])
+}
+
+// ─── console ─────────────────────────────────────────────────────────────────
+
+/// Write `bytes` to the firmware's console, before the kernel has its own.
+pub fn console_write(bytes: &[u8]) {
+    if probe(EID_DBCN) {
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            // The address is physical: the kernel maps 1:1
+            match call(EID_DBCN, 0, [rest.len(), rest.as_ptr() as usize, 0]) {
+                Ok(n) => rest = &rest[n.min(rest.len())..],
+                Err(_) => return,
+            }
+        }
+    } else {
+        for &b in bytes { ecall(EID_LEGACY_PUTCHAR, 0, [b as usize, 0, 0]); }
+    }
+}
+
+// ─── reset ───────────────────────────────────────────────────────────────────
+
+/// Shut down or reboot the machine, saying whether it is for a system
+/// failure; returns only if the firmware refused.
+pub fn system_reset(kind: Reset, failure: bool) -> &'static str {
+    match call(EID_SRST, 0, [kind as usize, failure as usize, 0]) {
+        Ok(_)  => "reset returned",
+        Err(e) => e,
+    }
+}
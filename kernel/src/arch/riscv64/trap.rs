@@ -0,0 +1,415 @@
+//! RISC-V trap entry and dispatch.
+//! `_trap_entry` saves every register and the trap CSRs in a `TrapFrame`
+//! on the kernel stack, with the stack canary above it, and hands it to
+//! `_trap_handler_rust`, which decodes mcause and dispatches:
+//!
+//! - interrupts: software (IPIs), the timer tick, and external interrupts,
+//!   claimed from the PLIC and passed to the handler a driver registered;
+//! - ecall: a system call;
+//! - ebreak: logged and stepped over;
+//! - a software check: a CFI violation;
+//! - anything else is a fault.  A process's fault kills it; the kernel's
+//!   is an oops — cause, registers and all — and a panic.
+//!
+//! A trap from U-mode arrives on the process's stack.  mscratch is zero
+//! while the hart is in M-mode, and holds the kernel stack while it runs
+//! a process, so entry can tell the two apart and switch stacks; the
+//! kernel's gp and tp ride in the frame across the process's run.
+//!
+//! Under SBI the same code runs on the S-mode CSRs (sscratch, sepc, ...,
+//! `sret`), and the frame's m-named fields hold them.
+
+use core::arch::asm;
+
+use super::SMODE;
+use crate::arch::trap::{self as common, Access};
+
+/// Bytes of stack a trap takes: the frame and the canary above it,
+/// keeping sp 16-byte aligned.
+const FRAME_SIZE: usize = core::mem::size_of::<TrapFrame>() + 8;
+
+// mcause codes: the interrupts taken in the kernel's mode
+const IRQ_SOFT:  usize = if SMODE { 1 } else { 3 };
+const IRQ_TIMER: usize = if SMODE { 5 } else { 7 };
+const IRQ_EXT:   usize = if SMODE { 9 } else { 11 };
+
+const EXC_INST_MISALIGNED:  usize = 0;
+const EXC_INST_ACCESS:      usize = 1;
+const EXC_ILLEGAL:          usize = 2;
+const EXC_BREAKPOINT:       usize = 3;
+const EXC_LOAD_MISALIGNED:  usize = 4;
+const EXC_LOAD_ACCESS:      usize = 5;
+const EXC_STORE_MISALIGNED: usize = 6;
+const EXC_STORE_ACCESS:     usize = 7;
+const EXC_ECALL_U:          usize = 8;
+const EXC_ECALL_S:          usize = 9;
+const EXC_ECALL_M:          usize = 11;
+const EXC_INST_PAGE:        usize = 12;
+const EXC_LOAD_PAGE:        usize = 13;
+const EXC_STORE_PAGE:       usize = 15;
+const EXC_SOFTWARE_CHECK:   usize = 18;
+
+/// mstatus.MPP, or sstatus.SPP: the mode the trap came from.
+const MSTATUS_MPP: usize = if SMODE { 1 << 8 } else { 3 << 11 };
+
+/// Everything a trap saves, in stack order.  The handler may change it:
+/// the registers, mepc and mstatus are restored from it on return (a
+/// syscall's result, the pc past an ecall); sp is too, but only a trap
+/// from U-mode should move it.
+#[repr(C)]
+pub struct TrapFrame {
+    pub ra:      usize,
+    /// sp as it was when the trap was taken.
+    pub sp:      usize,
+    pub gp:      usize,
+    pub tp:      usize,
+    /// t0–t6.
+    pub t:       [usize; 7],
+    /// s0–s11.
+    pub s:       [usize; 12],
+    /// a0–a7.
+    pub a:       [usize; 8],
+    pub mepc:    usize,
+    pub mstatus: usize,
+    pub mcause:  usize,
+    pub mtval:   usize,
+}
+
+impl TrapFrame {
+    /// Whether the trap came from U-mode.
+    pub fn from_user(&self) -> bool {
+        self.mstatus & MSTATUS_MPP == 0
+    }
+
+    fn is_interrupt(&self) -> bool {
+        self.mcause >> 63 != 0
+    }
+
+    fn code(&self) -> usize {
+        self.mcause & !(1 << 63)
+    }
+
+    /// What mcause says happened.
+    pub fn cause(&self) -> &'static str {
+        if self.is_interrupt() {
+            return match self.code() {
+                1  => "supervisor software interrupt",
+                3  => "machine software interrupt",
+                5  => "supervisor timer interrupt",
+                7  => "machine timer interrupt",
+                9  => "supervisor external interrupt",
+                11 => "machine external interrupt",
+                _  => "unknown interrupt",
+            };
+        }
+        match self.code() {
+            EXC_INST_MISALIGNED  => "instruction address misaligned",
+            EXC_INST_ACCESS      => "instruction access fault",
+            EXC_ILLEGAL          => "illegal instruction",
+            EXC_BREAKPOINT       => "breakpoint",
+            EXC_LOAD_MISALIGNED  => "load address misaligned",
+            EXC_LOAD_ACCESS      => "load access fault",
+            EXC_STORE_MISALIGNED => "store address misaligned",
+            EXC_STORE_ACCESS     => "store access fault",
+            EXC_ECALL_U          => "ecall from U-mode",
+            EXC_ECALL_S          => "ecall from S-mode",
+            EXC_ECALL_M          => "ecall from M-mode",
+            EXC_INST_PAGE        => "instruction page fault",
+            EXC_LOAD_PAGE        => "load page fault",
+            EXC_STORE_PAGE       => "store page fault",
+            EXC_SOFTWARE_CHECK   => "software check",
+            19                   => "hardware error",
+            _                    => "unknown exception",
+        }
+    }
+
+    /// Length of the instruction at mepc: 2 if compressed.
+    fn insn_len(&self) -> usize {
+        // SAFETY: the hart just fetched it (a breakpoint is not a fetch fault)
+        let low = unsafe { core::ptr::read_volatile(self.mepc as *const u16) };
+        if low & 3 == 3 { 4 } else { 2 }
+    }
+}
+
+// ─── initialisation ──────────────────────────────────────────────────────────
+
+/// Install the trap vector and enable timer interrupts.
+pub fn trap_init() {
+    unsafe {
+        // Set mtvec to our trap handler (direct mode), and mark the hart
+        // as in the kernel for it
+        asm!(concat!("csrw ", mode!("tvec"), ", {}"), in(reg) _trap_entry as *const () as usize);
+        asm!(concat!("csrw ", mode!("scratch"), ", zero"));
+        super::interrupts_enable();
+        asm!(concat!("csrs ", mode!("ie"), ", {}"), in(reg) super::MIE_MTIE);
+    }
+    if SMODE {
+        let (major, minor) = super::sbi::spec_version();
+        crate::info!("S-mode under SBI v{}.{} (implementation {})", major, minor, super::sbi::impl_id());
+    }
+    // Arm the first timer compare
+    super::rearm_tick();
+    crate::power::governor_tick();
+}
+
+/// The same on a secondary hart, but with software interrupts only: the
+/// tick and device interrupts stay with the boot hart.
+pub fn trap_init_secondary() {
+    unsafe {
+        asm!(concat!("csrw ", mode!("tvec"), ", {}"), in(reg) _trap_entry as *const () as usize);
+        asm!(concat!("csrw ", mode!("scratch"), ", zero"));
+    }
+    super::write_mie(super::MIE_MSIE);
+    super::interrupts_enable();
+}
+
+// ─── entry (naked — saves/restores context) ──────────────────────────────────
+
+/// Low-level trap entry, written as a naked function so we control the
+/// prologue/epilogue exactly.  Saves the whole register file, the trap
+/// CSRs and the stack canary, calls the Rust handler, checks the canary,
+/// then restores everything and returns via `mret` (`sret`).
+#[unsafe(naked)]
+#[no_mangle]
+#[link_section = ".text"]
+extern "C" fn _trap_entry() {
+    core::arch::naked_asm!(
+        // mscratch is the kernel stack if the trap came from U-mode, and
+        // zero from M-mode, in which case swap sp back
+        concat!("csrrw sp, ", mode!("scratch"), ", sp"),
+        "bnez sp, 1f",
+        concat!("csrrw sp, ", mode!("scratch"), ", sp"),
+        "addi sp, sp, -{size}",
+        "sd gp,  16(sp)",
+        "sd tp,  24(sp)",
+        "j 2f",
+        // From U-mode: the kernel's gp and tp were left in the frame
+        "1:",
+        "addi sp, sp, -{size}",
+        "sd gp,  16(sp)",
+        "sd tp,  24(sp)",
+        "ld gp, 264(sp)",
+        "ld tp, 272(sp)",
+        "2:",
+        "sd ra,   0(sp)",
+        "sd t0,  32(sp)",
+        "sd t1,  40(sp)",
+        "sd t2,  48(sp)",
+        "sd t3,  56(sp)",
+        "sd t4,  64(sp)",
+        "sd t5,  72(sp)",
+        "sd t6,  80(sp)",
+        "sd s0,  88(sp)",
+        "sd s1,  96(sp)",
+        "sd s2, 104(sp)",
+        "sd s3, 112(sp)",
+        "sd s4, 120(sp)",
+        "sd s5, 128(sp)",
+        "sd s6, 136(sp)",
+        "sd s7, 144(sp)",
+        "sd s8, 152(sp)",
+        "sd s9, 160(sp)",
+        "sd s10, 168(sp)",
+        "sd s11, 176(sp)",
+        "sd a0, 184(sp)",
+        "sd a1, 192(sp)",
+        "sd a2, 200(sp)",
+        "sd a3, 208(sp)",
+        "sd a4, 216(sp)",
+        "sd a5, 224(sp)",
+        "sd a6, 232(sp)",
+        "sd a7, 240(sp)",
+        // The interrupted sp: the process's, left in mscratch, or the
+        // kernel's from above the frame.  mscratch is zero from here on.
+        concat!("csrrw t0, ", mode!("scratch"), ", zero"),
+        "bnez t0, 3f",
+        "addi t0, sp, {size}",
+        "3:",
+        "sd t0,   8(sp)",
+        concat!("csrr t0, ", mode!("epc")),
+        "sd t0, 248(sp)",
+        concat!("csrr t0, ", mode!("status")),
+        "sd t0, 256(sp)",
+        concat!("csrr t0, ", mode!("cause")),
+        "sd t0, 264(sp)",
+        concat!("csrr t0, ", mode!("tval")),
+        "sd t0, 272(sp)",
+        "la t0, {canary}",
+        "ld t0, 0(t0)",
+        "sd t0, 280(sp)",
+
+        // Call the Rust handler with a pointer to the frame
+        "mv a0, sp",
+        "call {handler}",
+
+        // A handler that overran its frame has clobbered the canary
+        "la t0, {canary}",
+        "ld t0, 0(t0)",
+        "ld t1, 280(sp)",
+        "bne t0, t1, 5f",
+
+        "ld t0, 248(sp)",
+        concat!("csrw ", mode!("epc"), ", t0"),
+        "ld t0, 256(sp)",
+        concat!("csrw ", mode!("status"), ", t0"),
+        // Back to U-mode: the next trap takes this stack, and finds the
+        // kernel's gp and tp in the frame
+        "li t1, {mpp}",
+        "and t0, t0, t1",
+        "bnez t0, 4f",
+        "addi t0, sp, {size}",
+        concat!("csrw ", mode!("scratch"), ", t0"),
+        "sd gp, 264(sp)",
+        "sd tp, 272(sp)",
+        "4:",
+        "ld ra,   0(sp)",
+        "ld gp,  16(sp)",
+        "ld tp,  24(sp)",
+        "ld t0,  32(sp)",
+        "ld t1,  40(sp)",
+        "ld t2,  48(sp)",
+        "ld t3,  56(sp)",
+        "ld t4,  64(sp)",
+        "ld t5,  72(sp)",
+        "ld t6,  80(sp)",
+        "ld s0,  88(sp)",
+        "ld s1,  96(sp)",
+        "ld s2, 104(sp)",
+        "ld s3, 112(sp)",
+        "ld s4, 120(sp)",
+        "ld s5, 128(sp)",
+        "ld s6, 136(sp)",
+        "ld s7, 144(sp)",
+        "ld s8, 152(sp)",
+        "ld s9, 160(sp)",
+        "ld s10, 168(sp)",
+        "ld s11, 176(sp)",
+        "ld a0, 184(sp)",
+        "ld a1, 192(sp)",
+        "ld a2, 200(sp)",
+        "ld a3, 208(sp)",
+        "ld a4, 216(sp)",
+        "ld a5, 224(sp)",
+        "ld a6, 232(sp)",
+        "ld a7, 240(sp)",
+        "ld sp,   8(sp)",
+        mode!("ret"),
+
+        "5:",
+        "mv a0, sp",
+        "mv a1, t1",
+        "call {smashed}",
+        size    = const FRAME_SIZE,
+        mpp     = const MSTATUS_MPP,
+        handler = sym _trap_handler_rust,
+        canary  = sym crate::stackguard::STACK_CANARY,
+        smashed = sym crate::stackguard::_stack_smashed,
+    );
+}
+
+// Offsets the assembly above uses
+const _: () = {
+    assert!(core::mem::offset_of!(TrapFrame, a) == 184);
+    assert!(core::mem::offset_of!(TrapFrame, mepc) == 248);
+    assert!(core::mem::offset_of!(TrapFrame, mcause) == 264);
+    assert!(FRAME_SIZE == 288);
+};
+
+// ─── dispatch ────────────────────────────────────────────────────────────────
+
+#[no_mangle]
+extern "C" fn _trap_handler_rust(frame: &mut TrapFrame) {
+    if frame.is_interrupt() {
+        match frame.code() {
+            IRQ_SOFT  => crate::smp::ipi(),
+            IRQ_TIMER => handle_timer(),
+            IRQ_EXT   => handle_external(),
+            _           => crate::warn!("{} ({:#x}) ignored", frame.cause(), frame.mcause),
+        }
+    } else {
+        match frame.code() {
+            // The kernel's own ecalls go to the firmware under SBI
+            EXC_ECALL_U | EXC_ECALL_M => {
+                // a7 = number, a0–a5 = arguments
+                let a = &frame.a;
+                let args = [a[0], a[1], a[2], a[3], a[4], a[5]];
+                frame.a[0] = crate::syscall::dispatch(a[7], args) as usize;
+                frame.mepc += 4;
+            }
+            EXC_BREAKPOINT => {
+                crate::warn!("breakpoint at pc={:#x}", frame.mepc);
+                frame.mepc += frame.insn_len();
+            }
+            EXC_SOFTWARE_CHECK => crate::cfi::violation(frame.mepc, frame.mtval),
+            EXC_INST_PAGE  => page_fault(frame, Access::Execute),
+            EXC_LOAD_PAGE  => page_fault(frame, Access::Read),
+            EXC_STORE_PAGE => page_fault(frame, Access::Write),
+            _ => fault(frame),
+        }
+    }
+
+    crate::stackguard::check(frame.mepc, frame.mcause);
+}
+
+/// Take every pending external interrupt, each to its driver's handler.
+fn handle_external() {
+    loop {
+        let irq = super::plic_claim_irq();
+        if irq == 0 { break; }
+        common::dispatch_irq(irq);
+        super::plic_complete(irq);
+    }
+}
+
+/// Reset the timer for the next tick.
+fn handle_timer() {
+    unsafe {
+        super::TICK_COUNT += 1;
+    }
+    super::rearm_tick();
+    crate::power::governor_tick();
+    crate::power::idle_tick();
+    crate::thermal::thermal_tick();
+    crate::charger::charger_tick();
+    crate::alarm::alarm_tick();
+    crate::brightness::brightness_tick();
+    crate::net::net_tick();
+    crate::init::init_tick();
+    crate::capability::capability_tick();
+}
+
+/// A page fault.  Nothing is paged: the kernel runs on physical
+/// addresses, so any page fault is a bad access.
+fn page_fault(frame: &mut TrapFrame, access: Access) {
+    crate::debug!("page fault: {} of {:#x} at pc={:#x}", access.as_str(), frame.mtval, frame.mepc);
+    fault(frame)
+}
+
+/// A fault the code that took it cannot get past.
+fn fault(frame: &mut TrapFrame) -> ! {
+    if frame.from_user() { common::user_fault(frame.cause(), frame.mepc, frame.mtval); }
+    oops(frame);
+    panic!("{} at pc={:#x} (mtval={:#x})", frame.cause(), frame.mepc, frame.mtval);
+}
+
+/// Log everything the frame holds, to the console and to the kernel log,
+/// which outlives the reset that follows.
+fn oops(frame: &TrapFrame) {
+    const NAMES: [&str; 32] = [
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+        "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+    ];
+    let f = frame;
+    let regs: [usize; 32] = [
+        0, f.ra, f.sp, f.gp, f.tp, f.t[0], f.t[1], f.t[2], f.s[0], f.s[1],
+        f.a[0], f.a[1], f.a[2], f.a[3], f.a[4], f.a[5], f.a[6], f.a[7],
+        f.s[2], f.s[3], f.s[4], f.s[5], f.s[6], f.s[7], f.s[8], f.s[9], f.s[10], f.s[11],
+        f.t[3], f.t[4], f.t[5], f.t[6],
+    ];
+    crate::error!("oops: {} on hart {}, pid {}", f.cause(), super::hart_id(), crate::process::current_pid());
+    crate::error!("  mepc {:#018x}  mtval {:#018x}  mcause {:#x}  mstatus {:#x}", f.mepc, f.mtval, f.mcause, f.mstatus);
+    for row in (0..32).step_by(4) {
+        crate::error!("  {:>4} {:#018x}  {:>4} {:#018x}  {:>4} {:#018x}  {:>4} {:#018x}",
+            NAMES[row], regs[row], NAMES[row + 1], regs[row + 1], NAMES[row + 2], regs[row + 2], NAMES[row + 3], regs[row + 3]);
+    }
+}
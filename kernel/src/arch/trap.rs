@@ -0,0 +1,88 @@
+//! Trap handling common to both ports: the table of device interrupt
+//! handlers, and what becomes of a process whose access faulted.  Each
+//! port decodes its own causes and prints its own oops.
+
+use spin::Mutex;
+
+use crate::process;
+
+/// Interrupt numbers either controller uses: PLIC sources, GIC ids.
+pub const MAX_IRQS: usize = 1024;
+
+/// What a faulting access was doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Access {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Access::Read    => "read",
+            Access::Write   => "write",
+            Access::Execute => "execute",
+        }
+    }
+}
+
+// ─── device interrupts ───────────────────────────────────────────────────────
+
+/// A driver's handler, called with its interrupt number.
+pub type IrqHandler = fn(u32);
+
+static HANDLERS: Mutex<[Option<IrqHandler>; MAX_IRQS]> = Mutex::new([None; MAX_IRQS]);
+
+/// Call `handler` for device interrupt `irq`, and enable it.  Device
+/// interrupts go to the boot hart only, so masking them here is enough to
+/// keep the handler out while the table changes.
+pub fn register_irq(irq: u32, handler: IrqHandler) -> Result<(), &'static str> {
+    let prev = super::interrupts_disable();
+    let r = match HANDLERS.lock().get_mut(irq as usize) {
+        None          => Err("no such interrupt"),
+        Some(Some(_)) => Err("interrupt already has a handler"),
+        Some(slot)    => { *slot = Some(handler); Ok(()) }
+    };
+    super::interrupts_restore(prev);
+    r?;
+    super::plic_enable(irq, true);
+    Ok(())
+}
+
+/// Disable `irq` and drop its handler.
+pub fn unregister_irq(irq: u32) {
+    super::plic_enable(irq, false);
+    let prev = super::interrupts_disable();
+    if let Some(s) = HANDLERS.lock().get_mut(irq as usize) { *s = None; }
+    super::interrupts_restore(prev);
+}
+
+/// Run `irq`'s handler.  One that nobody handles is disabled, so a
+/// level-triggered source cannot hold the hart in the trap handler.
+pub(super) fn dispatch_irq(irq: u32) {
+    let handler = HANDLERS.lock().get(irq as usize).copied().flatten();
+    match handler {
+        Some(h) => h(irq),
+        None => {
+            super::plic_enable(irq, false);
+            crate::warn!("irq {}: no handler; disabled", irq);
+        }
+    }
+}
+
+// ─── faults ──────────────────────────────────────────────────────────────────
+
+/// A process's access faulted and cannot go on: report it, kill the
+/// process and release everything it held.  With no scheduler yet there
+/// is nothing else for the hart to run, so it idles, still taking
+/// interrupts.
+pub(super) fn user_fault(what: &str, pc: usize, addr: usize) -> ! {
+    let pid = process::current_pid();
+    crate::error!("pid {}: {} at pc={:#x} addr={:#x}; killed", pid, what, pc, addr);
+    if let Err(e) = process::kill(pid) {
+        panic!("pid {}: {} at pc={:#x} addr={:#x}, and it cannot be killed: {}", pid, what, pc, addr, e);
+    }
+    super::interrupts_enable();
+    loop { super::wait_for_interrupt(); }
+}
@@ -0,0 +1,323 @@
+//! SurakshaOS Measured Boot and Attestation
+//! Boot measures each stage into a bank of registers that can only be
+//! extended: `r ← SHAKE-256(r ‖ digest)`, so a register's value commits
+//! to every measurement folded into it, in order.  An event log alongside
+//! records what each measurement was, for a verifier to replay.
+//!
+//! The registers, the device secret, the anti-rollback counter and the
+//! authentication attempt counters live in a root of trust.  A PUF or HSM driver installs one with `install`, and
+//! the events measured so far are replayed into it.  Until then, and on
+//! QEMU virt, which has neither, the kernel keeps the registers itself and
+//! draws the device secret from the entropy pool at boot, so the device
+//! key changes on every boot — and its counter starts from zero, so
+//! rollback is only caught once a root with storage is installed.
+//!
+//! attestd signs quotes: the registers and a relying party's nonce,
+//! signed with the ML-DSA-65 device key derived from the device secret.
+//! The quote carries the public key; the relying party checks it against
+//! the key it enrolled, replays the event log against the registers, and
+//! checks the nonce is the one it sent.  Asking for a quote needs the
+//! Attestation capability with READ rights.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::crypto::{mldsa, sha3};
+use crate::ipc::{self, ChannelId, Message};
+use crate::keyring::{self, KeyId};
+use crate::process::{self, ProcessId};
+
+pub const DIGEST_LEN: usize = 32;
+/// Registers in the bank.
+pub const REGISTERS:  usize = 8;
+
+/// The kernel's code and read-only data.
+pub const REG_KERNEL:    usize = 0;
+/// The initramfs, if one was loaded.
+pub const REG_INITRAMFS: usize = 1;
+/// The secure-boot policy, trust anchors and decision, and the
+/// anti-rollback check.
+pub const REG_POLICY:    usize = 2;
+
+/// Longest nonce a quote takes.
+pub const MAX_NONCE: usize = 64;
+/// Starts every quote body.
+pub const QUOTE_MAGIC: &[u8; 8] = b"SKQUOTE1";
+/// Context string the device key signs quotes under.
+pub const QUOTE_CTX: &[u8] = b"suraksha attestation quote";
+
+// ─── root of trust ────────────────────────────────────────────────────────────
+
+/// Hardware that holds the measurement registers and the device secret.
+pub trait RootOfTrust: Send {
+    fn name(&self) -> &'static str;
+    /// Fold `digest` into register `index`.
+    fn extend(&mut self, index: usize, digest: &[u8; DIGEST_LEN]) -> Result<(), &'static str>;
+    fn read(&self, index: usize) -> Result<[u8; DIGEST_LEN], &'static str>;
+    /// The secret the device key is derived from, the same every time it
+    /// is asked for.
+    fn device_secret(&mut self, out: &mut [u8; mldsa::SEED_LEN]) -> Result<(), &'static str>;
+    /// The lowest security version the device will boot, from storage
+    /// that survives a reflash.
+    fn security_version(&self) -> Result<u32, &'static str>;
+    /// Raise the stored security version to `version`.  It never goes
+    /// down: a lower `version` is an error.
+    fn advance_security_version(&mut self, version: u32) -> Result<(), &'static str>;
+    /// Failed-attempt counter `id` (see `authlimit`), from the same storage.
+    fn attempts(&self, id: u32) -> Result<u32, &'static str>;
+    fn set_attempts(&mut self, id: u32, count: u32) -> Result<(), &'static str>;
+}
+
+/// `SHAKE-256(register ‖ digest)`, the extend operation.
+pub fn fold(register: &[u8; DIGEST_LEN], digest: &[u8; DIGEST_LEN]) -> [u8; DIGEST_LEN] {
+    let mut s = sha3::shake256();
+    s.absorb(register);
+    s.absorb(digest);
+    let mut out = [0u8; DIGEST_LEN];
+    s.squeeze(&mut out);
+    out
+}
+
+/// The kernel's own registers, used when no hardware root is installed.
+struct SoftRoot {
+    registers: [[u8; DIGEST_LEN]; REGISTERS],
+    /// The device secret, held in the keyring.
+    secret:    Option<KeyId>,
+    /// Lost at reset, like everything else here.
+    version:   u32,
+    /// (id, count) of each attempt counter that is not zero.
+    attempts:  Vec<(u32, u32)>,
+}
+
+impl RootOfTrust for SoftRoot {
+    fn name(&self) -> &'static str { "kernel" }
+
+    fn extend(&mut self, index: usize, digest: &[u8; DIGEST_LEN]) -> Result<(), &'static str> {
+        let r = self.registers.get_mut(index).ok_or("no such register")?;
+        *r = fold(r, digest);
+        Ok(())
+    }
+
+    fn read(&self, index: usize) -> Result<[u8; DIGEST_LEN], &'static str> {
+        self.registers.get(index).copied().ok_or("no such register")
+    }
+
+    fn device_secret(&mut self, out: &mut [u8; mldsa::SEED_LEN]) -> Result<(), &'static str> {
+        let id = match self.secret {
+            Some(id) => id,
+            None => {
+                crate::entropy::fill_bytes(out);
+                *self.secret.insert(keyring::add_kernel("device attestation secret", out)?)
+            }
+        };
+        out.copy_from_slice(&keyring::open(id, None)?);
+        Ok(())
+    }
+
+    fn security_version(&self) -> Result<u32, &'static str> {
+        Ok(self.version)
+    }
+
+    fn advance_security_version(&mut self, version: u32) -> Result<(), &'static str> {
+        if version < self.version { return Err("security version cannot go down"); }
+        self.version = version;
+        Ok(())
+    }
+
+    fn attempts(&self, id: u32) -> Result<u32, &'static str> {
+        Ok(self.attempts.iter().find(|&&(i, _)| i == id).map_or(0, |&(_, n)| n))
+    }
+
+    fn set_attempts(&mut self, id: u32, count: u32) -> Result<(), &'static str> {
+        self.attempts.retain(|&(i, _)| i != id);
+        if count != 0 { self.attempts.push((id, count)); }
+        Ok(())
+    }
+}
+
+// ─── measurements ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub register:    usize,
+    pub digest:      [u8; DIGEST_LEN],
+    pub description: String,
+}
+
+struct Attestation {
+    root:   Box<dyn RootOfTrust>,
+    events: Vec<Event>,
+    /// The service's process, once started.
+    pid:    Option<ProcessId>,
+}
+
+static STATE: Mutex<Option<Attestation>> = Mutex::new(None);
+
+fn with<R>(f: impl FnOnce(&mut Attestation) -> R) -> R {
+    let mut state = STATE.lock();
+    let a = state.get_or_insert_with(|| Attestation {
+        root: Box::new(SoftRoot { registers: [[0; DIGEST_LEN]; REGISTERS], secret: None, version: 0, attempts: Vec::new() }),
+        events: Vec::new(),
+        pid: None,
+    });
+    f(a)
+}
+
+/// Extend register `register` with `digest` and log it.
+pub fn measure(register: usize, digest: &[u8; DIGEST_LEN], description: &str) -> Result<(), &'static str> {
+    with(|a| {
+        a.root.extend(register, digest)?;
+        a.events.push(Event { register, digest: *digest, description: String::from(description) });
+        Ok(())
+    })
+}
+
+/// Hand the registers over to a hardware root of trust.  Everything
+/// measured so far is replayed into it, and the booted image is checked
+/// against its anti-rollback counter.
+pub fn install(mut root: Box<dyn RootOfTrust>) -> Result<(), &'static str> {
+    with(|a| {
+        for e in &a.events { root.extend(e.register, &e.digest)?; }
+        crate::audit::note("attestation", &format!("root of trust now {}", root.name()));
+        a.root = root;
+        Ok(())
+    })?;
+    crate::secure_boot::check_rollback();
+    Ok(())
+}
+
+pub fn root_name() -> &'static str {
+    with(|a| a.root.name())
+}
+
+/// The root of trust's anti-rollback counter.
+pub fn security_version() -> Result<u32, &'static str> {
+    with(|a| a.root.security_version())
+}
+
+pub fn advance_security_version(version: u32) -> Result<(), &'static str> {
+    with(|a| a.root.advance_security_version(version))
+}
+
+/// The root of trust's failed-attempt counter `id`.
+pub fn attempts(id: u32) -> Result<u32, &'static str> {
+    with(|a| a.root.attempts(id))
+}
+
+pub fn set_attempts(id: u32, count: u32) -> Result<(), &'static str> {
+    with(|a| a.root.set_attempts(id, count))
+}
+
+fn read_all(a: &Attestation) -> Result<[[u8; DIGEST_LEN]; REGISTERS], &'static str> {
+    let mut regs = [[0; DIGEST_LEN]; REGISTERS];
+    for (i, r) in regs.iter_mut().enumerate() { *r = a.root.read(i)?; }
+    Ok(regs)
+}
+
+pub fn registers() -> Result<[[u8; DIGEST_LEN]; REGISTERS], &'static str> {
+    with(|a| read_all(a))
+}
+
+pub fn events() -> Vec<Event> {
+    with(|a| a.events.clone())
+}
+
+// ─── quotes ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub struct Quote {
+    /// QUOTE_MAGIC, the nonce (length byte first), the time in seconds
+    /// since the epoch (u64 LE), the registers, and the device public key.
+    pub body:      Vec<u8>,
+    /// ML-DSA-65 over the body, under QUOTE_CTX.
+    pub signature: Vec<u8>,
+    pub events:    Vec<Event>,
+}
+
+fn device_key(a: &mut Attestation) -> Result<mldsa::SigningKey, &'static str> {
+    let mut seed = [0u8; mldsa::SEED_LEN];
+    let r = a.root.device_secret(&mut seed).map(|_| mldsa::SigningKey::from_seed(&seed));
+    crate::crypto::wipe(&mut seed);
+    r
+}
+
+fn authorize(pid: ProcessId, cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(pid, cap, CapabilityType::Attestation, Permissions::READ)
+}
+
+fn make_quote(nonce: &[u8]) -> Result<Quote, &'static str> {
+    if nonce.len() > MAX_NONCE { return Err("nonce too long"); }
+    with(|a| {
+        let regs = read_all(a)?;
+        let key = device_key(a)?;
+        let mut body = Vec::with_capacity(QUOTE_MAGIC.len() + 1 + nonce.len() + 8 + REGISTERS * DIGEST_LEN + mldsa::PUBLIC_KEY_LEN);
+        body.extend_from_slice(QUOTE_MAGIC);
+        body.push(nonce.len() as u8);
+        body.extend_from_slice(nonce);
+        body.extend_from_slice(&(crate::alarm::rtc_now_ns() / 1_000_000_000).to_le_bytes());
+        for r in &regs { body.extend_from_slice(r); }
+        body.extend_from_slice(key.public_key());
+        let mut rnd = [0u8; 32];
+        crate::entropy::fill_bytes(&mut rnd);
+        let signature = key.sign(&body, QUOTE_CTX, &rnd);
+        Ok(Quote { body, signature, events: a.events.clone() })
+    })
+}
+
+/// A quote over the registers and `nonce`, for the caller.
+pub fn quote(cap: &Capability, nonce: &[u8]) -> Result<Quote, &'static str> {
+    authorize(process::current_pid(), cap)?;
+    make_quote(nonce)
+}
+
+/// The device public key quotes are signed with.
+pub fn public_key() -> Result<Vec<u8>, &'static str> {
+    with(|a| device_key(a).map(|k| k.public_key().to_vec()))
+}
+
+// ─── attestd ──────────────────────────────────────────────────────────────────
+
+/// Request opcodes (first payload byte).
+pub const ATTEST_REQ_QUOTE:      u8 = 1; // [nonce...] -> [ok, body len u32 LE, body, signature]
+pub const ATTEST_REQ_PUBLIC_KEY: u8 = 2; // [] -> [ok, public key]
+
+/// Clients that connected with the Attestation capability.
+static CLIENTS: Mutex<Vec<ChannelId>> = Mutex::new(Vec::new());
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("attestd")?;
+    with(|a| a.pid = Some(pid));
+    ipc::register_kernel_server(pid, handle_request);
+    Ok(())
+}
+
+/// Open a channel from `client` to attestd; `cap` must be the client's
+/// Attestation capability.
+pub fn connect(client: ProcessId, cap: &Capability) -> Result<(ChannelId, Capability), &'static str> {
+    authorize(client, cap)?;
+    let pid = with(|a| a.pid).ok_or("attestation service not running")?;
+    let (ch, client_cap, _) = ipc::create_channel(client, pid);
+    CLIENTS.lock().push(ch);
+    Ok((ch, client_cap))
+}
+
+fn handle_request(ch: ChannelId, msg: &Message) -> Option<Vec<u8>> {
+    if !CLIENTS.lock().contains(&ch) { return Some(Vec::from([0u8])); }
+    let p = &msg.payload;
+    let reply = match p.first() {
+        Some(&ATTEST_REQ_QUOTE) => make_quote(&p[1..]).map(|q| {
+            let mut reply = Vec::from([1u8]);
+            reply.extend_from_slice(&(q.body.len() as u32).to_le_bytes());
+            reply.extend_from_slice(&q.body);
+            reply.extend_from_slice(&q.signature);
+            reply
+        }),
+        Some(&ATTEST_REQ_PUBLIC_KEY) => public_key().map(|k| [&[1u8][..], &k].concat()),
+        _ => Err("bad request"),
+    };
+    Some(reply.unwrap_or_else(|_| Vec::from([0u8])))
+}
@@ -0,0 +1,197 @@
+//! SurakshaOS Audio Capture
+//! Microphones as capture devices in the driver framework.  Every mic
+//! delivers 16 kHz mono signed 16-bit PCM; its driver converts from
+//! whatever the hardware produces.  Reading needs a READ capability on the
+//! mic's device, checked on every read, so revoking it cuts the audio off
+//! at once.  While any mic is capturing, `capturing()` is true, so the UI
+//! can show the recording indicator.  Consumers register for a
+//! data-ready callback instead of polling.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::capability::{Capability, Permissions};
+use crate::driver::{self, check_access, Device, DeviceClass, DeviceId, Driver};
+use crate::process;
+
+/// Capture format: mono, signed 16-bit little-endian.
+pub const SAMPLE_RATE: u32 = 16_000;
+
+/// ioctl commands on a mic device.
+pub const AUDIO_IOC_START:  u32 = 1;
+pub const AUDIO_IOC_STOP:   u32 = 2;
+/// Returns 1 once a finite source (e.g. a recording) has been read to
+/// the end.
+pub const AUDIO_IOC_ENDED:  u32 = 3;
+
+/// Hardware (or a recording) that produces capture samples.
+pub trait CaptureSource: Send {
+    fn start(&mut self) -> Result<(), &'static str>;
+
+    fn stop(&mut self);
+
+    /// Take the samples captured so far, up to `out.len()`.  Never blocks.
+    fn read(&mut self, out: &mut [i16]) -> usize;
+
+    /// True once a finite source has nothing more to give.
+    fn ended(&self) -> bool {
+        false
+    }
+}
+
+/// Mics capturing right now, for the recording indicator.
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+/// Called (as deferred work) when a mic has new samples.
+static CONSUMERS: Mutex<Vec<fn()>> = Mutex::new(Vec::new());
+
+pub fn capturing() -> bool {
+    ACTIVE.load(Ordering::Relaxed) > 0
+}
+
+/// Have `f` run whenever a mic has new samples.
+pub fn on_data(f: fn()) {
+    let mut consumers = CONSUMERS.lock();
+    if !consumers.iter().any(|&g| core::ptr::fn_addr_eq(g, f)) { consumers.push(f); }
+}
+
+/// For capture drivers: new samples are waiting.  Safe from interrupts.
+pub fn data_ready() {
+    let Some(consumers) = CONSUMERS.try_lock() else { return };
+    for &f in consumers.iter() { process::defer(f); }
+}
+
+// ─── driver ───────────────────────────────────────────────────────────────────
+
+pub struct MicDriver {
+    source:  Box<dyn CaptureSource>,
+    running: bool,
+}
+
+impl MicDriver {
+    fn set_running(&mut self, on: bool) -> Result<(), &'static str> {
+        if on == self.running { return Ok(()); }
+        if on {
+            self.source.start()?;
+            ACTIVE.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.source.stop();
+            ACTIVE.fetch_sub(1, Ordering::Relaxed);
+        }
+        self.running = on;
+        Ok(())
+    }
+}
+
+impl Driver for MicDriver {
+    fn name(&self) -> &'static str {
+        "mic"
+    }
+
+    /// Fills `buf` with little-endian samples; returns the bytes written.
+    fn read(&mut self, dev: &Device, cap: &Capability, buf: &mut [u8]) -> Result<usize, &'static str> {
+        check_access(dev, cap, Permissions::READ)?;
+        if !self.running { return Err("mic not started"); }
+        let mut samples = vec![0i16; buf.len() / 2];
+        let n = self.source.read(&mut samples);
+        for (out, s) in buf.as_chunks_mut::<2>().0.iter_mut().zip(&samples[..n]) {
+            *out = s.to_le_bytes();
+        }
+        Ok(n * 2)
+    }
+
+    fn write(&mut self, _dev: &Device, _cap: &Capability, _buf: &[u8]) -> Result<usize, &'static str> {
+        Err("capture device is read-only")
+    }
+
+    fn ioctl(&mut self, dev: &Device, cap: &Capability, cmd: u32, _arg: usize) -> Result<usize, &'static str> {
+        check_access(dev, cap, Permissions::READ)?;
+        match cmd {
+            AUDIO_IOC_START => self.set_running(true).map(|_| 0),
+            AUDIO_IOC_STOP  => self.set_running(false).map(|_| 0),
+            AUDIO_IOC_ENDED => Ok(self.source.ended() as usize),
+            _               => Err("unknown audio ioctl"),
+        }
+    }
+
+    /// Capturing is reading: the READ capability covers every command.
+    fn ioctl_permissions(&self, _cmd: u32) -> Permissions {
+        Permissions::READ
+    }
+
+    fn suspend(&mut self, _dev: &Device) -> Result<(), &'static str> {
+        self.set_running(false)
+    }
+}
+
+/// Register a mic with the driver framework.
+pub fn register_mic(name: &'static str, source: Box<dyn CaptureSource>) -> Result<DeviceId, &'static str> {
+    driver::register(name, DeviceClass::Audio, Box::new(MicDriver { source, running: false }))
+}
+
+// ─── recordings ───────────────────────────────────────────────────────────────
+
+/// A WAV recording played back as a mic, for testing speech features
+/// without hardware.  Must already be in the capture format.
+pub struct WavSource {
+    data: Arc<[u8]>,
+    pos:  usize,
+    end:  usize,
+}
+
+impl WavSource {
+    pub fn open(path: &str) -> Result<Self, &'static str> {
+        let data = crate::fs::map_file(path)?;
+        if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"WAVE" { return Err("not a WAV file"); }
+
+        let mut off = 12;
+        let mut format_ok = false;
+        while off + 8 <= data.len() {
+            let id  = &data[off..off + 4];
+            let len = u32::from_le_bytes(data[off + 4..off + 8].try_into().unwrap()) as usize;
+            let body = off + 8;
+            if body + len > data.len() && id != b"data" { return Err("truncated WAV file"); }
+            match id {
+                b"fmt " if len >= 16 => {
+                    let f = &data[body..body + 16];
+                    let u16_at = |i: usize| u16::from_le_bytes([f[i], f[i + 1]]);
+                    let rate = u32::from_le_bytes(f[4..8].try_into().unwrap());
+                    // PCM, mono, 16 kHz, 16-bit
+                    format_ok = u16_at(0) == 1 && u16_at(2) == 1 && rate == SAMPLE_RATE && u16_at(14) == 16;
+                }
+                b"data" => {
+                    if !format_ok { return Err("WAV must be 16 kHz mono 16-bit PCM"); }
+                    let end = (body + len).min(data.len());
+                    return Ok(WavSource { data, pos: body, end });
+                }
+                _ => {}
+            }
+            off = body + len + (len & 1);
+        }
+        Err("WAV file has no data")
+    }
+}
+
+impl CaptureSource for WavSource {
+    fn start(&mut self) -> Result<(), &'static str> {
+        data_ready();
+        Ok(())
+    }
+
+    fn stop(&mut self) {}
+
+    fn read(&mut self, out: &mut [i16]) -> usize {
+        let n = ((self.end - self.pos) / 2).min(out.len());
+        let bytes = &self.data[self.pos..self.pos + n * 2];
+        for (o, b) in out.iter_mut().zip(bytes.as_chunks::<2>().0) { *o = i16::from_le_bytes(*b); }
+        self.pos += n * 2;
+        n
+    }
+
+    fn ended(&self) -> bool {
+        self.end - self.pos < 2
+    }
+}
@@ -0,0 +1,297 @@
+//! SurakshaOS Security Audit Log
+//! A tamper-evident record of the events the security monitor sees.  Each
+//! record carries a SHAKE-256 hash over the previous record's hash and its
+//! own contents, so changing, dropping or reordering any record breaks the
+//! chain from there on; `verify` walks it.
+//!
+//! Records are batched and written out as segments under `AUDIT_DIR`, one
+//! file each, numbered in order and never rewritten: the log only grows by
+//! new segments.  A segment is sealed with ChaCha20-Poly1305 under a key
+//! the kernel keeps in its keyring, its number bound in as associated data
+//! so segments cannot be swapped.  On first use the log picks up the chain
+//! from the segments already there.  The log lasts as long as the
+//! filesystem under it does; with the in-memory VFS, that is until reboot.
+//!
+//! Reading the log needs the AuditLog capability with READ rights.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::crypto::{chacha20poly1305 as aead, sha3};
+use crate::keyring::{self, KeyId};
+use crate::process::ProcessId;
+use crate::security::SecurityEvent;
+
+pub const AUDIT_DIR: &str = "/var/log/audit";
+
+/// Records held before a segment is written.
+const BATCH:    usize = 16;
+const HASH_LEN: usize = 32;
+/// The hash the first record chains from.
+const GENESIS:  [u8; HASH_LEN] = [0; HASH_LEN];
+/// Stands in for "no process" in the encoding.
+const NO_PID:   u32 = u32::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Position in the log, from 1.
+    pub seq:     u64,
+    /// Wall-clock time, seconds since the Unix epoch.
+    pub time:    u64,
+    /// The process the event concerns.
+    pub pid:     Option<ProcessId>,
+    pub kind:    String,
+    pub message: String,
+    /// SHAKE-256 over the previous record's hash and this record.
+    pub hash:    [u8; HASH_LEN],
+}
+
+impl Record {
+    /// The fields the hash covers, as written to a segment.
+    fn body(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(24 + self.kind.len() + self.message.len());
+        b.extend_from_slice(&self.seq.to_le_bytes());
+        b.extend_from_slice(&self.time.to_le_bytes());
+        b.extend_from_slice(&self.pid.map_or(NO_PID, |p| p.0 as u32).to_le_bytes());
+        b.push(self.kind.len() as u8);
+        b.extend_from_slice(self.kind.as_bytes());
+        b.extend_from_slice(&(self.message.len() as u16).to_le_bytes());
+        b.extend_from_slice(self.message.as_bytes());
+        b
+    }
+
+    fn chain(prev: &[u8; HASH_LEN], body: &[u8]) -> [u8; HASH_LEN] {
+        let mut s = sha3::shake256();
+        s.absorb(prev);
+        s.absorb(body);
+        let mut h = [0u8; HASH_LEN];
+        s.squeeze(&mut h);
+        h
+    }
+
+    /// Parse one record off the front of `b`, checking it chains from
+    /// `prev`.
+    fn decode(b: &mut &[u8], prev: &[u8; HASH_LEN]) -> Result<Record, &'static str> {
+        fn take<'a>(b: &mut &'a [u8], n: usize) -> Result<&'a [u8], &'static str> {
+            let (head, rest) = b.split_at_checked(n).ok_or("truncated audit record")?;
+            *b = rest;
+            Ok(head)
+        }
+        fn le<const N: usize>(b: &mut &[u8]) -> Result<[u8; N], &'static str> {
+            take(b, N).map(|h| h.try_into().unwrap_or([0; N]))
+        }
+        let start = *b;
+        let seq = u64::from_le_bytes(le(b)?);
+        let time = u64::from_le_bytes(le(b)?);
+        let pid = u32::from_le_bytes(le(b)?);
+        let [kind_len] = le(b)?;
+        let kind = String::from_utf8(take(b, kind_len as usize)?.to_vec()).map_err(|_| "bad audit record")?;
+        let len = u16::from_le_bytes(le(b)?) as usize;
+        let message = String::from_utf8(take(b, len)?.to_vec()).map_err(|_| "bad audit record")?;
+        let body = &start[..start.len() - b.len()];
+        let hash: [u8; HASH_LEN] = le(b)?;
+        if Record::chain(prev, body) != hash { return Err("audit chain broken"); }
+        let pid = (pid != NO_PID).then_some(ProcessId(pid as usize));
+        Ok(Record { seq, time, pid, kind, message, hash })
+    }
+}
+
+/// What to return from `query`.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    /// Records after this sequence number.
+    pub after: u64,
+    pub pid:   Option<ProcessId>,
+    pub kind:  Option<String>,
+    /// At most this many, the oldest first; all if 0.
+    pub limit: usize,
+}
+
+/// The outcome of walking the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verified {
+    pub segments: u64,
+    pub records:  u64,
+}
+
+struct Audit {
+    /// Whether the segments already written have been read.
+    loaded:   bool,
+    key:      Option<KeyId>,
+    /// Sequence number and hash of the last record.
+    seq:      u64,
+    head:     [u8; HASH_LEN],
+    /// Segments written.
+    segments: u64,
+    /// Records not yet in a segment.
+    pending:  Vec<Record>,
+}
+
+static AUDIT: Mutex<Audit> = Mutex::new(Audit {
+    loaded: false, key: None, seq: 0, head: GENESIS, segments: 0, pending: Vec::new(),
+});
+
+fn segment_path(n: u64) -> String {
+    format!("{}/{:08}.seg", AUDIT_DIR, n)
+}
+
+fn nonce(n: u64) -> [u8; aead::NONCE_LEN] {
+    let mut nonce = [0u8; aead::NONCE_LEN];
+    nonce[4..].copy_from_slice(&n.to_le_bytes());
+    nonce
+}
+
+impl Audit {
+    fn key(&mut self) -> Result<[u8; aead::KEY_LEN], &'static str> {
+        let id = match self.key {
+            Some(id) => id,
+            None => {
+                let mut k = [0u8; aead::KEY_LEN];
+                crate::entropy::fill_bytes(&mut k);
+                let id = keyring::add_kernel("audit log", &k);
+                crate::crypto::wipe(&mut k);
+                *self.key.insert(id?)
+            }
+        };
+        let secret = keyring::open(id, None)?;
+        secret[..].try_into().map_err(|_| "bad audit key")
+    }
+
+    /// The records of segment `n`, checked against the chain so far.
+    fn read_segment(&mut self, n: u64, prev: &mut [u8; HASH_LEN], seq: &mut u64) -> Result<Vec<Record>, &'static str> {
+        let sealed = crate::fs::read_file(&segment_path(n))?;
+        let mut key = self.key()?;
+        let plain = aead::open(&key, &nonce(n), &n.to_le_bytes(), &sealed).map_err(|_| "audit segment altered");
+        crate::crypto::wipe(&mut key);
+        let plain = plain?;
+        let mut b = plain.as_slice();
+        let mut records = Vec::new();
+        while !b.is_empty() {
+            let r = Record::decode(&mut b, prev)?;
+            if r.seq != *seq + 1 { return Err("audit records out of order"); }
+            *seq = r.seq;
+            *prev = r.hash;
+            records.push(r);
+        }
+        Ok(records)
+    }
+
+    /// Continue the chain from the segments already written.  A segment
+    /// that fails to check is still counted, so the log is never written
+    /// over it and `verify` goes on reporting it.
+    fn load(&mut self) {
+        if self.loaded { return; }
+        self.loaded = true;
+        let (mut prev, mut seq, mut n, mut intact) = (GENESIS, 0, 0, true);
+        while crate::fs::stat(&segment_path(n + 1)).is_ok() {
+            intact = intact && self.read_segment(n + 1, &mut prev, &mut seq).is_ok();
+            n += 1;
+        }
+        (self.head, self.seq, self.segments) = (prev, seq, n);
+    }
+
+    fn append(&mut self, pid: Option<ProcessId>, kind: &str, message: &str) {
+        self.load();
+        let mut r = Record {
+            seq: self.seq + 1, time: crate::alarm::rtc_now_ns() / 1_000_000_000, pid,
+            kind: String::from(&kind[..kind.len().min(255)]),
+            message: String::from(&message[..message.len().min(u16::MAX as usize)]),
+            hash: GENESIS,
+        };
+        r.hash = Record::chain(&self.head, &r.body());
+        self.seq = r.seq;
+        self.head = r.hash;
+        self.pending.push(r);
+        if self.pending.len() >= BATCH { let _ = self.flush(); }
+    }
+
+    /// Write the pending records as the next segment.
+    fn flush(&mut self) -> Result<(), &'static str> {
+        if self.pending.is_empty() { return Ok(()); }
+        let n = self.segments + 1;
+        let path = segment_path(n);
+        // Append-only: a segment is never written over
+        if crate::fs::stat(&path).is_ok() { return Err("audit segment exists"); }
+        let mut plain = Vec::new();
+        for r in &self.pending {
+            plain.extend_from_slice(&r.body());
+            plain.extend_from_slice(&r.hash);
+        }
+        let mut key = self.key()?;
+        let sealed = aead::seal(&key, &nonce(n), &n.to_le_bytes(), &plain);
+        crate::crypto::wipe(&mut key);
+        crate::fs::create_dir(AUDIT_DIR).ok();
+        crate::fs::write_file(&path, &sealed)?;
+        self.segments = n;
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Every record: the segments', then those pending.
+    fn all(&mut self) -> Result<Vec<Record>, &'static str> {
+        let (mut prev, mut seq) = (GENESIS, 0);
+        let mut records = Vec::new();
+        for n in 1..=self.segments {
+            records.extend(self.read_segment(n, &mut prev, &mut seq)?);
+        }
+        for r in &self.pending {
+            if r.seq != seq + 1 || Record::chain(&prev, &r.body()) != r.hash { return Err("audit chain broken"); }
+            (prev, seq) = (r.hash, r.seq);
+            records.push(r.clone());
+        }
+        Ok(records)
+    }
+}
+
+fn authorize(cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(crate::process::current_pid(), cap, CapabilityType::AuditLog, Permissions::READ)
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Append a security event to the log.
+pub fn record(event: &SecurityEvent) {
+    AUDIT.lock().append(event.pid(), event.kind(), &format!("{}", event));
+}
+
+/// Append a note from the kernel itself, such as a boot decision.
+pub fn note(kind: &str, message: &str) {
+    AUDIT.lock().append(None, kind, message);
+}
+
+/// Write out the records not yet in a segment.
+pub fn sync() -> Result<(), &'static str> {
+    AUDIT.lock().flush()
+}
+
+/// Walk the whole chain, every segment and the records pending, and say
+/// how much of it holds together.
+pub fn verify(cap: &Capability) -> Result<Verified, &'static str> {
+    authorize(cap)?;
+    let mut a = AUDIT.lock();
+    a.load();
+    let records = a.all()?.len() as u64;
+    if records != a.seq { return Err("audit log truncated"); }
+    Ok(Verified { segments: a.segments, records })
+}
+
+/// The records `q` asks for.
+pub fn query(cap: &Capability, q: &Query) -> Result<Vec<Record>, &'static str> {
+    authorize(cap)?;
+    let mut a = AUDIT.lock();
+    a.load();
+    let matching = a.all()?.into_iter()
+        .filter(|r| r.seq > q.after)
+        .filter(|r| q.pid.is_none_or(|p| r.pid == Some(p)))
+        .filter(|r| q.kind.as_ref().is_none_or(|k| r.kind == *k));
+    Ok(if q.limit == 0 { matching.collect() } else { matching.take(q.limit).collect() })
+}
+
+/// Records logged, and segments written.
+pub fn stats() -> (u64, u64) {
+    let a = AUDIT.lock();
+    (a.seq, a.segments)
+}
@@ -0,0 +1,193 @@
+//! SurakshaOS Authentication Rate Limiting
+//! Brute-force protection for whatever verifies a user: the PIN service,
+//! the biometric matcher.  Each (factor, user) pair has a failed-attempt
+//! counter.  The first FREE_ATTEMPTS failures cost nothing; after that
+//! each failure doubles the wait before the next attempt, from BASE_DELAY_MS
+//! up to MAX_DELAY_MS.
+//!
+//! A verifier calls `begin` before checking a credential and `finish`
+//! with the outcome.  `begin` counts the attempt as failed up front and
+//! `finish` clears the counter on success, so cutting power mid-check, or
+//! never reporting back, costs the attacker an attempt rather than saving
+//! one.
+//!
+//! The counters live in the root of trust, not the filesystem, so wiping
+//! userspace data does not reset them.  The wait restarts at boot: a
+//! reboot gains nothing, since the clock that times it starts from zero.
+//! (The kernel's own root of trust, used until a hardware one is
+//! installed, keeps them in memory only.)
+//!
+//! Kernel services call this directly.  authd offers the same to
+//! verification services in userspace that hold an Authenticator
+//! capability.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::ipc::{self, ChannelId, Message};
+use crate::process::{self, ProcessId};
+
+/// Failures allowed before any wait.
+pub const FREE_ATTEMPTS: u32 = 5;
+/// Wait after the first failure past the free ones.
+pub const BASE_DELAY_MS: u64 = 30_000;
+/// Longest wait.
+pub const MAX_DELAY_MS:  u64 = 24 * 60 * 60_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Factor {
+    Pin,
+    Password,
+    Biometric,
+}
+
+impl Factor {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Factor::Pin       => "pin",
+            Factor::Password  => "password",
+            Factor::Biometric => "biometric",
+        }
+    }
+
+    pub fn from_u8(b: u8) -> Option<Factor> {
+        match b {
+            0 => Some(Factor::Pin),
+            1 => Some(Factor::Password),
+            2 => Some(Factor::Biometric),
+            _ => None,
+        }
+    }
+}
+
+/// The root of trust's counter for `factor` and `user`.  User ids are
+/// taken mod 2^24, which is plenty for the users of one device.
+fn counter_id(factor: Factor, user: u32) -> u32 {
+    (factor as u32) << 24 | (user & 0x00ff_ffff)
+}
+
+/// How long `failures` failures in a row make the next attempt wait.
+pub fn delay_for(failures: u32) -> u64 {
+    match failures.checked_sub(FREE_ATTEMPTS) {
+        None | Some(0) => 0,
+        Some(n) => BASE_DELAY_MS.saturating_mul(1 << (n - 1).min(32)).min(MAX_DELAY_MS),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Status {
+    pub factor:   Factor,
+    pub user:     u32,
+    pub failures: u32,
+    /// How long until the next attempt is allowed.
+    pub wait_ms:  u64,
+}
+
+/// (factor, user, uptime of the last counted attempt) since boot.  Pairs
+/// not here last failed before boot.
+static LAST: Mutex<Vec<(Factor, u32, u64)>> = Mutex::new(Vec::new());
+
+fn wait_ms(factor: Factor, user: u32, failures: u32, now: u64) -> u64 {
+    let last = LAST.lock().iter().find(|&&(f, u, _)| f == factor && u == user).map_or(0, |&(.., t)| t);
+    (last + delay_for(failures)).saturating_sub(now)
+}
+
+pub fn status(factor: Factor, user: u32) -> Result<Status, &'static str> {
+    let failures = crate::attest::attempts(counter_id(factor, user))?;
+    let wait_ms = wait_ms(factor, user, failures, crate::arch::uptime_millis());
+    Ok(Status { factor, user, failures, wait_ms })
+}
+
+/// Start an attempt: refused while `user` must wait, and otherwise counted
+/// as a failure until `finish` says it succeeded.
+pub fn begin(factor: Factor, user: u32) -> Result<(), &'static str> {
+    let id = counter_id(factor, user);
+    let failures = crate::attest::attempts(id)?;
+    let now = crate::arch::uptime_millis();
+    if wait_ms(factor, user, failures, now) > 0 { return Err("too many failed attempts; try later"); }
+    crate::attest::set_attempts(id, failures.saturating_add(1))?;
+    let mut last = LAST.lock();
+    last.retain(|&(f, u, _)| f != factor || u != user);
+    last.push((factor, user, now));
+    Ok(())
+}
+
+/// End an attempt `begin` allowed.  Success clears the counter.
+pub fn finish(factor: Factor, user: u32, success: bool) {
+    if !success {
+        let failures = crate::attest::attempts(counter_id(factor, user)).unwrap_or(0);
+        crate::audit::note("auth", &alloc::format!("{} failed for user {} ({} in a row)", factor.as_str(), user, failures));
+        return;
+    }
+    let _ = crate::attest::set_attempts(counter_id(factor, user), 0);
+    LAST.lock().retain(|&(f, u, _)| f != factor || u != user);
+}
+
+/// `begin`, run `verify`, and `finish` with what it said.
+pub fn guard(factor: Factor, user: u32, verify: impl FnOnce() -> Result<bool, &'static str>) -> Result<bool, &'static str> {
+    begin(factor, user)?;
+    let r = verify();
+    // An error is not a success: the attempt stays counted
+    finish(factor, user, r == Ok(true));
+    r
+}
+
+/// Pairs attempted since boot, with their state.
+pub fn statuses() -> Vec<Status> {
+    let pairs: Vec<(Factor, u32)> = LAST.lock().iter().map(|&(f, u, _)| (f, u)).collect();
+    pairs.into_iter().filter_map(|(f, u)| status(f, u).ok()).collect()
+}
+
+// ─── authd ────────────────────────────────────────────────────────────────────
+
+/// Request opcodes (first payload byte); factor and user follow as
+/// [factor, user u32 LE].
+pub const AUTH_REQ_BEGIN:  u8 = 1; // -> [ok]
+pub const AUTH_REQ_FINISH: u8 = 2; // [factor, user, success] -> [ok]
+pub const AUTH_REQ_STATUS: u8 = 3; // -> [ok, failures u32 LE, wait ms u64 LE]
+
+static PID:     Mutex<Option<ProcessId>> = Mutex::new(None);
+/// Channels of verifiers that connected with the Authenticator capability.
+static CLIENTS: Mutex<Vec<ChannelId>> = Mutex::new(Vec::new());
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("authd")?;
+    *PID.lock() = Some(pid);
+    ipc::register_kernel_server(pid, handle_request);
+    Ok(())
+}
+
+/// Open a channel from `client` to authd; `cap` must be its Authenticator
+/// capability with EXECUTE.
+pub fn connect(client: ProcessId, cap: &Capability) -> Result<(ChannelId, Capability), &'static str> {
+    capability::validate(client, cap, CapabilityType::Authenticator, Permissions::EXECUTE)?;
+    let pid = PID.lock().ok_or("authentication service not running")?;
+    let (ch, client_cap, _) = ipc::create_channel(client, pid);
+    CLIENTS.lock().push(ch);
+    Ok((ch, client_cap))
+}
+
+fn handle_request(ch: ChannelId, msg: &Message) -> Option<Vec<u8>> {
+    if !CLIENTS.lock().contains(&ch) { return Some(Vec::from([0u8])); }
+    let p = &msg.payload;
+    let target = match (p.get(1).copied().and_then(Factor::from_u8), p.get(2..6)) {
+        (Some(f), Some(u)) => Some((f, u32::from_le_bytes([u[0], u[1], u[2], u[3]]))),
+        _ => None,
+    };
+    let reply = match (p.first(), target) {
+        (Some(&AUTH_REQ_BEGIN), Some((f, u))) => begin(f, u).map(|_| Vec::from([1u8])),
+        (Some(&AUTH_REQ_FINISH), Some((f, u))) if p.len() >= 7 => {
+            finish(f, u, p[6] != 0);
+            Ok(Vec::from([1u8]))
+        }
+        (Some(&AUTH_REQ_STATUS), Some((f, u))) => status(f, u).map(|s| {
+            let mut reply = Vec::from([1u8]);
+            reply.extend_from_slice(&s.failures.to_le_bytes());
+            reply.extend_from_slice(&s.wait_ms.to_le_bytes());
+            reply
+        }),
+        _ => Err("bad request"),
+    };
+    Some(reply.unwrap_or_else(|_| Vec::from([0u8])))
+}
@@ -0,0 +1,625 @@
+//! SurakshaOS Bluetooth
+//! HCI controller driver (H4 framing over a UART transport), command/event
+//! handling, L2CAP channel multiplexing over ACL links, and the start of
+//! LE pairing.  The controller always advertises and connects from a
+//! non-resolvable private address that is rotated periodically, so the
+//! phone cannot be tracked by its public BD_ADDR.
+//!
+//! The device is exposed as `bt0`; every operation needs a capability for
+//! it (`CapabilityType::Device`).
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::capability::{Capability, Permissions};
+use crate::driver::{self, check_access, Device, DeviceClass, DeviceId, Driver};
+
+// ─── HCI constants ────────────────────────────────────────────────────────────
+
+const H4_COMMAND: u8 = 0x01;
+const H4_ACL:     u8 = 0x02;
+const H4_EVENT:   u8 = 0x04;
+
+const OP_DISCONNECT:         u16 = 0x0406;
+const OP_RESET:              u16 = 0x0C03;
+const OP_READ_BD_ADDR:       u16 = 0x1009;
+const OP_LE_SET_RANDOM_ADDR: u16 = 0x2005;
+
+const EVT_DISCONNECT_COMPLETE: u8 = 0x05;
+const EVT_COMMAND_COMPLETE:    u8 = 0x0E;
+const EVT_COMMAND_STATUS:      u8 = 0x0F;
+const EVT_LE_META:             u8 = 0x3E;
+const LE_SUB_CONN_COMPLETE:    u8 = 0x01;
+
+/// How long to wait for Command Complete before giving up.
+const COMMAND_TIMEOUT_MS: u64 = 1000;
+
+/// Private address lifetime (Core spec recommends 15 minutes).
+const PRIVATE_ADDR_ROTATE_MS: u64 = 15 * 60 * 1000;
+
+// ─── L2CAP constants ──────────────────────────────────────────────────────────
+
+const CID_SIGNALING:    u16 = 0x0001;
+const CID_LE_SIGNALING: u16 = 0x0005;
+const CID_SMP:          u16 = 0x0006;
+const CID_DYNAMIC_BASE: u16 = 0x0040;
+
+const SIG_COMMAND_REJECT: u8 = 0x01;
+const SIG_CONN_REQ:       u8 = 0x02;
+const SIG_CONN_RSP:       u8 = 0x03;
+const SIG_DISCONN_REQ:    u8 = 0x06;
+const SIG_DISCONN_RSP:    u8 = 0x07;
+
+const CONN_SUCCESS:          u16 = 0x0000;
+const CONN_PSM_UNSUPPORTED:  u16 = 0x0002;
+
+const SMP_PAIRING_REQUEST:  u8 = 0x01;
+const SMP_PAIRING_RESPONSE: u8 = 0x02;
+const SMP_PAIRING_FAILED:   u8 = 0x05;
+
+// ─── ioctl interface ──────────────────────────────────────────────────────────
+
+/// Reset the controller and re-apply a fresh private address.
+pub const BT_IOCTL_RESET:          u32 = 0xB701;
+/// Rotate the private address now.
+pub const BT_IOCTL_ROTATE_ADDRESS: u32 = 0xB702;
+/// Open an L2CAP channel; arg = (acl_handle << 16) | psm.  Returns the local CID.
+pub const BT_IOCTL_L2CAP_CONNECT:  u32 = 0xB703;
+/// Close an L2CAP channel; arg = local CID.
+pub const BT_IOCTL_L2CAP_CLOSE:    u32 = 0xB704;
+/// Accept inbound channels on a PSM; arg = psm.
+pub const BT_IOCTL_L2CAP_LISTEN:   u32 = 0xB705;
+/// Begin LE pairing on a connection; arg = acl_handle.
+pub const BT_IOCTL_PAIR:           u32 = 0xB706;
+/// Number of open ACL links (query).
+pub const BT_IOCTL_CONNECTIONS:    u32 = 0xB707;
+
+// ─── transport ────────────────────────────────────────────────────────────────
+
+/// Byte-stream transport carrying H4-framed HCI packets (UART, USB bulk, ...).
+pub trait HciTransport: Send {
+    fn send(&mut self, bytes: &[u8]);
+    /// Read whatever is available without blocking.
+    fn recv(&mut self, buf: &mut [u8]) -> usize;
+}
+
+/// H4 over a second NS16550A-compatible UART.
+pub struct UartTransport {
+    base: usize,
+}
+
+impl UartTransport {
+    const LSR: usize = 0x05;
+    const LSR_DATA_READY: u8 = 0x01;
+    const LSR_TX_EMPTY:   u8 = 0x20;
+
+    pub const fn new(base: usize) -> Self {
+        UartTransport { base }
+    }
+}
+
+impl HciTransport for UartTransport {
+    fn send(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            unsafe {
+                while core::ptr::read_volatile((self.base + Self::LSR) as *const u8) & Self::LSR_TX_EMPTY == 0 {
+                    core::hint::spin_loop();
+                }
+                core::ptr::write_volatile(self.base as *mut u8, b);
+            }
+        }
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        while n < buf.len() {
+            let lsr = unsafe { core::ptr::read_volatile((self.base + Self::LSR) as *const u8) };
+            if lsr & Self::LSR_DATA_READY == 0 { break; }
+            buf[n] = unsafe { core::ptr::read_volatile(self.base as *const u8) };
+            n += 1;
+        }
+        n
+    }
+}
+
+// ─── types ────────────────────────────────────────────────────────────────────
+
+/// 48-bit device address, little-endian as on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BdAddr(pub [u8; 6]);
+
+impl BdAddr {
+    /// Non-resolvable private address: 46 random bits, top two bits 00,
+    /// and never all-zero or all-one.
+    fn random_non_resolvable() -> BdAddr {
+        loop {
+            let mut a = [0u8; 6];
+            crate::entropy::fill_bytes(&mut a);
+            a[5] &= 0x3F;
+            let rand_bits = u64::from_le_bytes([a[0], a[1], a[2], a[3], a[4], a[5], 0, 0]);
+            if rand_bits != 0 && rand_bits != 0x3FFF_FFFF_FFFF {
+                return BdAddr(a);
+            }
+        }
+    }
+}
+
+impl core::fmt::Display for BdAddr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let a = &self.0;
+        write!(f, "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", a[5], a[4], a[3], a[2], a[1], a[0])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    WaitConnectRsp,
+    Open,
+    WaitDisconnectRsp,
+}
+
+pub struct L2capChannel {
+    pub handle:     u16,
+    pub psm:        u16,
+    pub local_cid:  u16,
+    pub remote_cid: u16,
+    pub state:      ChannelState,
+    rx:             VecDeque<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairingState {
+    None,
+    RequestSent,
+    /// Feature exchange done; the LE Secure Connections public-key phase
+    /// needs a P-256 implementation that the kernel does not have yet.
+    FeaturesExchanged,
+    Failed(u8),
+}
+
+pub struct AclLink {
+    pub handle:  u16,
+    pub peer:    BdAddr,
+    pub pairing: PairingState,
+    /// Reassembly buffer for fragmented L2CAP PDUs.
+    partial:     Vec<u8>,
+}
+
+enum Packet {
+    Event { code: u8, params: Vec<u8> },
+    Acl   { handle: u16, flags: u8, data: Vec<u8> },
+}
+
+// ─── controller ───────────────────────────────────────────────────────────────
+
+pub struct HciController {
+    transport:       Box<dyn HciTransport>,
+    rx:              Vec<u8>,
+    pub public_addr: Option<BdAddr>,
+    pub random_addr: Option<BdAddr>,
+    addr_set_ms:     u64,
+    links:           Vec<AclLink>,
+    channels:        Vec<L2capChannel>,
+    listening:       Vec<u16>,
+    next_cid:        u16,
+    next_ident:      u8,
+}
+
+impl HciController {
+    pub fn new(transport: Box<dyn HciTransport>) -> Self {
+        HciController {
+            transport,
+            rx: Vec::new(),
+            public_addr: None,
+            random_addr: None,
+            addr_set_ms: 0,
+            links: Vec::new(),
+            channels: Vec::new(),
+            listening: Vec::new(),
+            next_cid: CID_DYNAMIC_BASE,
+            next_ident: 1,
+        }
+    }
+
+    /// Reset the controller, learn its public address, and switch to a
+    /// private one.
+    pub fn bring_up(&mut self) -> Result<(), &'static str> {
+        self.rx.clear();
+        self.links.clear();
+        self.channels.clear();
+        self.command(OP_RESET, &[])?;
+        let ret = self.command(OP_READ_BD_ADDR, &[])?;
+        if ret.len() >= 7 {
+            let mut a = [0u8; 6];
+            a.copy_from_slice(&ret[1..7]);
+            self.public_addr = Some(BdAddr(a));
+        }
+        self.rotate_address()
+    }
+
+    pub fn rotate_address(&mut self) -> Result<(), &'static str> {
+        let addr = BdAddr::random_non_resolvable();
+        self.command(OP_LE_SET_RANDOM_ADDR, &addr.0)?;
+        self.random_addr = Some(addr);
+        self.addr_set_ms = crate::arch::uptime_millis();
+        Ok(())
+    }
+
+    // ─── HCI commands & events ───────────────────────────────────────────────
+
+    fn send_command(&mut self, opcode: u16, params: &[u8]) {
+        let mut pkt = Vec::with_capacity(4 + params.len());
+        pkt.push(H4_COMMAND);
+        pkt.extend_from_slice(&opcode.to_le_bytes());
+        pkt.push(params.len() as u8);
+        pkt.extend_from_slice(params);
+        self.transport.send(&pkt);
+    }
+
+    /// Issue a command and wait for its Command Complete.  Returns the
+    /// return parameters (status first).  Unrelated events that arrive
+    /// while waiting are processed normally.
+    fn command(&mut self, opcode: u16, params: &[u8]) -> Result<Vec<u8>, &'static str> {
+        self.send_command(opcode, params);
+        let deadline = crate::arch::uptime_millis() + COMMAND_TIMEOUT_MS;
+        while crate::arch::uptime_millis() < deadline {
+            while let Some(pkt) = self.next_packet() {
+                match pkt {
+                    Packet::Event { code: EVT_COMMAND_COMPLETE, params }
+                        if params.len() >= 4 && u16::from_le_bytes([params[1], params[2]]) == opcode =>
+                    {
+                        let ret = params[3..].to_vec();
+                        return if ret[0] == 0 { Ok(ret) } else { Err("hci command failed") };
+                    }
+                    Packet::Event { code: EVT_COMMAND_STATUS, params }
+                        if params.len() >= 4 && u16::from_le_bytes([params[2], params[3]]) == opcode =>
+                    {
+                        return if params[0] == 0 { Ok(Vec::from([0u8])) } else { Err("hci command rejected") };
+                    }
+                    other => self.dispatch(other),
+                }
+            }
+            core::hint::spin_loop();
+        }
+        Err("hci command timed out")
+    }
+
+    /// Pull bytes from the transport and split off one complete H4 packet.
+    fn next_packet(&mut self) -> Option<Packet> {
+        let mut buf = [0u8; 64];
+        loop {
+            let n = self.transport.recv(&mut buf);
+            if n == 0 { break; }
+            self.rx.extend_from_slice(&buf[..n]);
+        }
+        loop {
+            let kind = *self.rx.first()?;
+            let (hdr, len) = match kind {
+                H4_EVENT if self.rx.len() >= 3 => (3, self.rx[2] as usize),
+                H4_ACL   if self.rx.len() >= 5 => (5, u16::from_le_bytes([self.rx[3], self.rx[4]]) as usize),
+                H4_EVENT | H4_ACL              => return None,
+                _ => {
+                    // Lost sync: drop the byte and look for the next packet type
+                    self.rx.remove(0);
+                    continue;
+                }
+            };
+            if self.rx.len() < hdr + len { return None; }
+            let body: Vec<u8> = self.rx.drain(..hdr + len).collect();
+            return Some(match kind {
+                H4_EVENT => Packet::Event { code: body[1], params: body[hdr..].to_vec() },
+                _ => {
+                    let hf = u16::from_le_bytes([body[1], body[2]]);
+                    Packet::Acl { handle: hf & 0x0FFF, flags: (hf >> 12) as u8, data: body[hdr..].to_vec() }
+                }
+            });
+        }
+    }
+
+    /// Process everything the controller has sent; also rotates the
+    /// private address when it is due.
+    pub fn poll(&mut self) {
+        while let Some(pkt) = self.next_packet() {
+            self.dispatch(pkt);
+        }
+        if self.random_addr.is_some()
+            && crate::arch::uptime_millis() - self.addr_set_ms >= PRIVATE_ADDR_ROTATE_MS
+        {
+            let _ = self.rotate_address();
+        }
+    }
+
+    fn dispatch(&mut self, pkt: Packet) {
+        match pkt {
+            Packet::Event { code, params } => self.handle_event(code, &params),
+            Packet::Acl { handle, flags, data } => self.handle_acl(handle, flags, &data),
+        }
+    }
+
+    fn handle_event(&mut self, code: u8, p: &[u8]) {
+        match code {
+            EVT_LE_META if p.len() >= 12 && p[0] == LE_SUB_CONN_COMPLETE && p[1] == 0 => {
+                let handle = u16::from_le_bytes([p[2], p[3]]) & 0x0FFF;
+                let mut peer = [0u8; 6];
+                peer.copy_from_slice(&p[6..12]);
+                self.links.push(AclLink { handle, peer: BdAddr(peer), pairing: PairingState::None, partial: Vec::new() });
+            }
+            EVT_DISCONNECT_COMPLETE if p.len() >= 3 && p[0] == 0 => {
+                let handle = u16::from_le_bytes([p[1], p[2]]) & 0x0FFF;
+                self.links.retain(|l| l.handle != handle);
+                self.channels.retain(|c| c.handle != handle);
+            }
+            _ => { /* flow control and unsolicited events are ignored */ }
+        }
+    }
+
+    // ─── L2CAP ───────────────────────────────────────────────────────────────
+
+    fn handle_acl(&mut self, handle: u16, flags: u8, data: &[u8]) {
+        let Some(link) = self.links.iter_mut().find(|l| l.handle == handle) else { return };
+        // PB flag 0b01 = continuing fragment, anything else starts a PDU
+        if flags & 0x3 != 0x1 {
+            link.partial.clear();
+        }
+        link.partial.extend_from_slice(data);
+        if link.partial.len() < 4 { return; }
+        let len = u16::from_le_bytes([link.partial[0], link.partial[1]]) as usize;
+        if link.partial.len() < 4 + len { return; }
+        let cid     = u16::from_le_bytes([link.partial[2], link.partial[3]]);
+        let payload = link.partial[4..4 + len].to_vec();
+        link.partial.clear();
+
+        match cid {
+            CID_SIGNALING | CID_LE_SIGNALING => self.handle_signaling(handle, cid, &payload),
+            CID_SMP => self.handle_smp(handle, &payload),
+            _ => {
+                if let Some(ch) = self.channels.iter_mut()
+                    .find(|c| c.handle == handle && c.local_cid == cid && c.state == ChannelState::Open)
+                {
+                    ch.rx.push_back(payload);
+                }
+            }
+        }
+    }
+
+    fn send_l2cap(&mut self, handle: u16, cid: u16, payload: &[u8]) {
+        let mut pkt = Vec::with_capacity(9 + payload.len());
+        pkt.push(H4_ACL);
+        // PB = 0b10 (first, automatically flushable), BC = 0
+        pkt.extend_from_slice(&(handle | 0x2000).to_le_bytes());
+        pkt.extend_from_slice(&((payload.len() + 4) as u16).to_le_bytes());
+        pkt.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        pkt.extend_from_slice(&cid.to_le_bytes());
+        pkt.extend_from_slice(payload);
+        self.transport.send(&pkt);
+    }
+
+    fn send_signal(&mut self, handle: u16, sig_cid: u16, code: u8, ident: u8, data: &[u8]) {
+        let mut p = Vec::with_capacity(4 + data.len());
+        p.push(code);
+        p.push(ident);
+        p.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        p.extend_from_slice(data);
+        self.send_l2cap(handle, sig_cid, &p);
+    }
+
+    fn alloc_ident(&mut self) -> u8 {
+        let id = self.next_ident;
+        self.next_ident = if id == 0xFF { 1 } else { id + 1 };
+        id
+    }
+
+    fn alloc_cid(&mut self) -> u16 {
+        let cid = self.next_cid;
+        self.next_cid = if cid == 0xFFFF { CID_DYNAMIC_BASE } else { cid + 1 };
+        cid
+    }
+
+    fn handle_signaling(&mut self, handle: u16, sig_cid: u16, p: &[u8]) {
+        if p.len() < 4 { return; }
+        let (code, ident) = (p[0], p[1]);
+        let d = &p[4..];
+        let u16_at = |i: usize| u16::from_le_bytes([d[i], d[i + 1]]);
+        match code {
+            SIG_CONN_REQ if d.len() >= 4 => {
+                let (psm, scid) = (u16_at(0), u16_at(2));
+                let (dcid, result) = if self.listening.contains(&psm) {
+                    let dcid = self.alloc_cid();
+                    self.channels.push(L2capChannel {
+                        handle, psm, local_cid: dcid, remote_cid: scid,
+                        state: ChannelState::Open, rx: VecDeque::new(),
+                    });
+                    (dcid, CONN_SUCCESS)
+                } else {
+                    (0, CONN_PSM_UNSUPPORTED)
+                };
+                let mut rsp = [0u8; 8];
+                rsp[0..2].copy_from_slice(&dcid.to_le_bytes());
+                rsp[2..4].copy_from_slice(&scid.to_le_bytes());
+                rsp[4..6].copy_from_slice(&result.to_le_bytes());
+                self.send_signal(handle, sig_cid, SIG_CONN_RSP, ident, &rsp);
+            }
+            SIG_CONN_RSP if d.len() >= 8 => {
+                let (dcid, scid, result) = (u16_at(0), u16_at(2), u16_at(4));
+                if let Some(ch) = self.channels.iter_mut().find(|c| c.handle == handle && c.local_cid == scid) {
+                    if result == CONN_SUCCESS {
+                        ch.remote_cid = dcid;
+                        ch.state = ChannelState::Open;
+                    }
+                }
+                if result != CONN_SUCCESS && result != 0x0001 /* pending */ {
+                    self.channels.retain(|c| !(c.handle == handle && c.local_cid == scid));
+                }
+            }
+            SIG_DISCONN_REQ if d.len() >= 4 => {
+                let (dcid, scid) = (u16_at(0), u16_at(2));
+                self.channels.retain(|c| !(c.handle == handle && c.local_cid == dcid));
+                let mut rsp = [0u8; 4];
+                rsp[0..2].copy_from_slice(&dcid.to_le_bytes());
+                rsp[2..4].copy_from_slice(&scid.to_le_bytes());
+                self.send_signal(handle, sig_cid, SIG_DISCONN_RSP, ident, &rsp);
+            }
+            SIG_DISCONN_RSP if d.len() >= 4 => {
+                let scid = u16_at(2);
+                self.channels.retain(|c| !(c.handle == handle && c.local_cid == scid));
+            }
+            SIG_COMMAND_REJECT | SIG_DISCONN_RSP | SIG_CONN_RSP | SIG_CONN_REQ | SIG_DISCONN_REQ => {}
+            _ => {
+                // Command not understood
+                self.send_signal(handle, sig_cid, SIG_COMMAND_REJECT, ident, &[0x00, 0x00]);
+            }
+        }
+    }
+
+    pub fn l2cap_listen(&mut self, psm: u16) {
+        if !self.listening.contains(&psm) { self.listening.push(psm); }
+    }
+
+    pub fn l2cap_connect(&mut self, handle: u16, psm: u16) -> Result<u16, &'static str> {
+        if !self.links.iter().any(|l| l.handle == handle) { return Err("no such ACL link"); }
+        let scid  = self.alloc_cid();
+        let ident = self.alloc_ident();
+        self.channels.push(L2capChannel {
+            handle, psm, local_cid: scid, remote_cid: 0,
+            state: ChannelState::WaitConnectRsp, rx: VecDeque::new(),
+        });
+        let mut req = [0u8; 4];
+        req[0..2].copy_from_slice(&psm.to_le_bytes());
+        req[2..4].copy_from_slice(&scid.to_le_bytes());
+        self.send_signal(handle, CID_SIGNALING, SIG_CONN_REQ, ident, &req);
+        Ok(scid)
+    }
+
+    pub fn l2cap_close(&mut self, local_cid: u16) -> Result<(), &'static str> {
+        let ch = self.channels.iter_mut().find(|c| c.local_cid == local_cid).ok_or("no such channel")?;
+        ch.state = ChannelState::WaitDisconnectRsp;
+        let (handle, dcid) = (ch.handle, ch.remote_cid);
+        let ident = self.alloc_ident();
+        let mut req = [0u8; 4];
+        req[0..2].copy_from_slice(&dcid.to_le_bytes());
+        req[2..4].copy_from_slice(&local_cid.to_le_bytes());
+        self.send_signal(handle, CID_SIGNALING, SIG_DISCONN_REQ, ident, &req);
+        Ok(())
+    }
+
+    pub fn l2cap_send(&mut self, local_cid: u16, data: &[u8]) -> Result<usize, &'static str> {
+        let ch = self.channels.iter().find(|c| c.local_cid == local_cid).ok_or("no such channel")?;
+        if ch.state != ChannelState::Open { return Err("channel not open"); }
+        let (handle, rcid) = (ch.handle, ch.remote_cid);
+        self.send_l2cap(handle, rcid, data);
+        Ok(data.len())
+    }
+
+    pub fn l2cap_recv(&mut self, local_cid: u16, buf: &mut [u8]) -> Result<usize, &'static str> {
+        let ch = self.channels.iter_mut().find(|c| c.local_cid == local_cid).ok_or("no such channel")?;
+        match ch.rx.pop_front() {
+            Some(sdu) => {
+                let n = sdu.len().min(buf.len());
+                buf[..n].copy_from_slice(&sdu[..n]);
+                Ok(n)
+            }
+            None => Ok(0),
+        }
+    }
+
+    pub fn disconnect(&mut self, handle: u16) {
+        // Reason 0x13: remote user terminated connection
+        self.send_command(OP_DISCONNECT, &[handle as u8, (handle >> 8) as u8, 0x13]);
+    }
+
+    // ─── pairing (SMP) ───────────────────────────────────────────────────────
+
+    /// Send an LE Pairing Request asking for bonding with Secure
+    /// Connections, and distribute our identity key so a bonded peer can
+    /// still recognise us behind rotating private addresses.
+    pub fn start_pairing(&mut self, handle: u16) -> Result<(), &'static str> {
+        let link = self.links.iter_mut().find(|l| l.handle == handle).ok_or("no such ACL link")?;
+        link.pairing = PairingState::RequestSent;
+        let req = [
+            SMP_PAIRING_REQUEST,
+            0x03, // IO capability: NoInputNoOutput
+            0x00, // OOB data not present
+            0x09, // AuthReq: bonding | Secure Connections
+            16,   // max encryption key size
+            0x02, // initiator key distribution: IdKey
+            0x03, // responder key distribution: EncKey | IdKey
+        ];
+        self.send_l2cap(handle, CID_SMP, &req);
+        Ok(())
+    }
+
+    fn handle_smp(&mut self, handle: u16, p: &[u8]) {
+        let Some(link) = self.links.iter_mut().find(|l| l.handle == handle) else { return };
+        match p.first() {
+            Some(&SMP_PAIRING_RESPONSE) if link.pairing == PairingState::RequestSent && p.len() >= 7 => {
+                link.pairing = PairingState::FeaturesExchanged;
+            }
+            Some(&SMP_PAIRING_FAILED) if p.len() >= 2 => {
+                link.pairing = PairingState::Failed(p[1]);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn links(&self) -> &[AclLink] {
+        &self.links
+    }
+}
+
+// ─── device driver ───────────────────────────────────────────────────────────
+
+/// `bt0`: read/write move L2CAP SDUs.  The first two bytes of every
+/// buffer are the local CID (little-endian); the rest is payload.
+pub struct BluetoothDriver {
+    hci: HciController,
+}
+
+impl Driver for BluetoothDriver {
+    fn name(&self) -> &'static str { "hci-h4" }
+
+    fn read(&mut self, dev: &Device, cap: &Capability, buf: &mut [u8]) -> Result<usize, &'static str> {
+        check_access(dev, cap, Permissions::READ)?;
+        if buf.len() < 2 { return Err("buffer too small"); }
+        let cid = u16::from_le_bytes([buf[0], buf[1]]);
+        self.hci.poll();
+        self.hci.l2cap_recv(cid, &mut buf[2..]).map(|n| n + 2)
+    }
+
+    fn write(&mut self, dev: &Device, cap: &Capability, buf: &[u8]) -> Result<usize, &'static str> {
+        check_access(dev, cap, Permissions::WRITE)?;
+        if buf.len() < 2 { return Err("buffer too small"); }
+        let cid = u16::from_le_bytes([buf[0], buf[1]]);
+        self.hci.poll();
+        self.hci.l2cap_send(cid, &buf[2..]).map(|n| n + 2)
+    }
+
+    fn ioctl(&mut self, dev: &Device, cap: &Capability, cmd: u32, arg: usize) -> Result<usize, &'static str> {
+        check_access(dev, cap, self.ioctl_permissions(cmd))?;
+        self.hci.poll();
+        match cmd {
+            BT_IOCTL_RESET          => self.hci.bring_up().map(|_| 0),
+            BT_IOCTL_ROTATE_ADDRESS => self.hci.rotate_address().map(|_| 0),
+            BT_IOCTL_L2CAP_CONNECT  => self.hci.l2cap_connect((arg >> 16) as u16, arg as u16).map(|c| c as usize),
+            BT_IOCTL_L2CAP_CLOSE    => self.hci.l2cap_close(arg as u16).map(|_| 0),
+            BT_IOCTL_L2CAP_LISTEN   => { self.hci.l2cap_listen(arg as u16); Ok(0) }
+            BT_IOCTL_PAIR           => self.hci.start_pairing(arg as u16).map(|_| 0),
+            BT_IOCTL_CONNECTIONS    => Ok(self.hci.links().len()),
+            _                       => Err("unsupported ioctl"),
+        }
+    }
+
+    fn ioctl_permissions(&self, cmd: u32) -> Permissions {
+        match cmd {
+            BT_IOCTL_CONNECTIONS => Permissions::READ,
+            _                    => Permissions::CONTROL,
+        }
+    }
+}
+
+/// Bring up a controller on `transport` and register it as `bt0`.
+pub fn attach(transport: Box<dyn HciTransport>) -> Result<DeviceId, &'static str> {
+    let mut hci = HciController::new(transport);
+    hci.bring_up()?;
+    driver::register("bt0", DeviceClass::Bluetooth, Box::new(BluetoothDriver { hci }))
+}
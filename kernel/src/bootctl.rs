@@ -0,0 +1,354 @@
+//! SurakshaOS Boot Control (A/B slots)
+//! The system is installed twice, in slots A and B, so an update can be
+//! written to the slot that is not running and booted into, with the old
+//! one kept to fall back on.  Each slot has a priority (0–15; 0 is
+//! unbootable), a count of tries left, and whether it has ever booted
+//! successfully.
+//!
+//! Early in boot `select` picks the bootable slot with the highest
+//! priority — one that has booted successfully, or has tries left — and,
+//! if it has not yet booted successfully, spends one of its tries before
+//! going on.  Init marks the boot successful once its critical services
+//! are up (`mark_successful`, also SYS_BOOT_CONTROL).  Until then whatever
+//! resets the device — the watchdog, a panic reboot, the user — counts as
+//! a failed try, and a slot whose tries run out is marked unbootable, so
+//! the next boot falls back to the other.
+//!
+//! An update marks the slot it is about to write unbootable, writes it,
+//! then sets it active (`set_active`): top priority, a full count of
+//! tries, not yet successful.
+//!
+//! A loader that picks the slot itself says which with `slot=a|b` on the
+//! command line, and keeps the tries itself.
+//!
+//! The metadata is kept by a `MetadataStore`.  A flash or RPMB driver
+//! installs one with `install`; until then the kernel keeps it in a
+//! section boot does not clear (`.bootctl`, as klog does its ring), which
+//! outlives a warm reset but not a power cycle.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use spin::Mutex;
+
+use crate::attest;
+use crate::crypto::sha3;
+
+pub const SLOTS:        usize = 2;
+pub const MAX_PRIORITY: u8 = 15;
+/// Tries a newly active slot gets to boot successfully.
+pub const MAX_TRIES:    u8 = 7;
+/// Bytes of encoded metadata.
+pub const METADATA_LEN: usize = 16;
+
+const MAGIC:   &[u8; 4] = b"SKAB";
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    A,
+    B,
+}
+
+impl Slot {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Slot::A => "a",
+            Slot::B => "b",
+        }
+    }
+
+    /// Appended to partition names: `system_a`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Slot::A => "_a",
+            Slot::B => "_b",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Slot> {
+        match s {
+            "a" | "A" | "_a" => Some(Slot::A),
+            "b" | "B" | "_b" => Some(Slot::B),
+            _ => None,
+        }
+    }
+
+    pub fn from_index(i: usize) -> Option<Slot> {
+        match i {
+            0 => Some(Slot::A),
+            1 => Some(Slot::B),
+            _ => None,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    pub fn other(self) -> Slot {
+        match self {
+            Slot::A => Slot::B,
+            Slot::B => Slot::A,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub priority:   u8,
+    pub tries:      u8,
+    pub successful: bool,
+}
+
+impl SlotInfo {
+    pub fn bootable(&self) -> bool {
+        self.priority > 0 && (self.successful || self.tries > 0)
+    }
+}
+
+/// A factory-fresh device: A first, B behind it, neither yet proven.
+const DEFAULT: [SlotInfo; SLOTS] = [
+    SlotInfo { priority: MAX_PRIORITY,     tries: MAX_TRIES, successful: false },
+    SlotInfo { priority: MAX_PRIORITY - 1, tries: MAX_TRIES, successful: false },
+];
+
+fn tag(body: &[u8]) -> [u8; 4] {
+    let mut t = [0u8; 4];
+    sha3::shake256_into(body, &mut t);
+    t
+}
+
+/// Magic, version, three bytes per slot, then a SHAKE-256 tag over the
+/// rest to catch a torn or stray write.
+fn encode(slots: &[SlotInfo; SLOTS]) -> [u8; METADATA_LEN] {
+    let mut b = [0u8; METADATA_LEN];
+    b[..4].copy_from_slice(MAGIC);
+    b[4] = VERSION;
+    for (i, s) in slots.iter().enumerate() {
+        b[5 + i * 3..8 + i * 3].copy_from_slice(&[s.priority, s.tries, s.successful as u8]);
+    }
+    let t = tag(&b[..12]);
+    b[12..].copy_from_slice(&t);
+    b
+}
+
+fn decode(b: &[u8; METADATA_LEN]) -> Option<[SlotInfo; SLOTS]> {
+    if &b[..4] != MAGIC || b[4] != VERSION || b[12..] != tag(&b[..12]) { return None; }
+    let slot = |i: usize| SlotInfo {
+        priority:   b[5 + i * 3].min(MAX_PRIORITY),
+        tries:      b[6 + i * 3].min(MAX_TRIES),
+        successful: b[7 + i * 3] != 0,
+    };
+    Some([slot(0), slot(1)])
+}
+
+// ─── storage ──────────────────────────────────────────────────────────────────
+
+/// Storage for the slot metadata that survives a reset.
+pub trait MetadataStore: Send {
+    fn name(&self) -> &'static str;
+    fn load(&self) -> Result<[u8; METADATA_LEN], &'static str>;
+    fn store(&mut self, data: &[u8; METADATA_LEN]) -> Result<(), &'static str>;
+}
+
+/// Not zeroed at boot; `decode` checks what it finds.
+#[link_section = ".bootctl"]
+static mut SAVED: core::mem::MaybeUninit<[u8; METADATA_LEN]> = core::mem::MaybeUninit::uninit();
+
+/// The reset-surviving section, used until a driver installs a store.
+struct RamStore;
+
+impl MetadataStore for RamStore {
+    fn name(&self) -> &'static str { "ram" }
+
+    fn load(&self) -> Result<[u8; METADATA_LEN], &'static str> {
+        // SAFETY: any bit pattern is a valid byte array; only ever
+        // touched under STATE's lock
+        Ok(unsafe { (*core::ptr::addr_of!(SAVED)).assume_init() })
+    }
+
+    fn store(&mut self, data: &[u8; METADATA_LEN]) -> Result<(), &'static str> {
+        unsafe { (*core::ptr::addr_of_mut!(SAVED)).write(*data); }
+        Ok(())
+    }
+}
+
+// ─── state ────────────────────────────────────────────────────────────────────
+
+struct BootControl {
+    store:   Box<dyn MetadataStore>,
+    slots:   [SlotInfo; SLOTS],
+    /// The slot booted, once `select` has run.
+    current: Option<Slot>,
+    /// Whether the loader chose it (`slot=`), rather than `select`.
+    by_loader: bool,
+    /// Whether `select` spent one of its tries.
+    spent:   bool,
+}
+
+impl BootControl {
+    fn save(&mut self) -> Result<(), &'static str> {
+        self.store.store(&encode(&self.slots))
+    }
+
+    fn load(&mut self) {
+        self.slots = match self.store.load().ok().and_then(|b| decode(&b)) {
+            Some(slots) => slots,
+            None => {
+                crate::warn!("no valid slot metadata in {}; starting afresh", self.store.name());
+                DEFAULT
+            }
+        };
+    }
+}
+
+static STATE: Mutex<Option<BootControl>> = Mutex::new(None);
+
+fn with<R>(f: impl FnOnce(&mut BootControl) -> R) -> R {
+    let mut state = STATE.lock();
+    let b = state.get_or_insert_with(|| {
+        let mut b = BootControl { store: Box::new(RamStore), slots: DEFAULT, current: None, by_loader: false, spent: false };
+        b.load();
+        b
+    });
+    f(b)
+}
+
+/// Keep the metadata in `store` from now on.  Metadata it already holds
+/// wins, less the try this boot spent; if it has none, it takes the
+/// kernel's.
+pub fn install(store: Box<dyn MetadataStore>) -> Result<(), &'static str> {
+    with(|b| {
+        b.store = store;
+        if let Some(mut slots) = b.store.load().ok().and_then(|d| decode(&d)) {
+            if let (Some(slot), true) = (b.current, b.spent) {
+                let s = &mut slots[slot.index()];
+                if !s.successful { s.tries = s.tries.saturating_sub(1); }
+            }
+            b.slots = slots;
+        }
+        b.save()
+    })
+}
+
+// ─── boot ─────────────────────────────────────────────────────────────────────
+
+/// Pick the slot to boot and spend a try on it if it is not yet proven.
+/// Called once, early in `kernel_main`, after the heap and the command line.
+pub fn select() -> Slot {
+    let (slot, line) = with(|b| {
+        if let Some(s) = crate::cmdline::get("slot") {
+            match Slot::parse(s) {
+                Some(slot) => {
+                    b.current = Some(slot);
+                    b.by_loader = true;
+                    return (slot, format!("slot {} (chosen by the loader)", slot.as_str()));
+                }
+                None => crate::warn!("slot={}: expected a or b", s),
+            }
+        }
+
+        // A slot out of tries that never booted successfully is dead
+        for (i, s) in b.slots.iter_mut().enumerate() {
+            if s.priority > 0 && !s.successful && s.tries == 0 {
+                s.priority = 0;
+                crate::warn!("slot {} failed to boot {} times; marked unbootable",
+                    Slot::from_index(i).map_or("?", Slot::as_str), MAX_TRIES);
+            }
+        }
+        let best = [Slot::A, Slot::B].into_iter()
+            .filter(|s| b.slots[s.index()].bootable())
+            .max_by_key(|s| (b.slots[s.index()].priority, s.index() == 0));
+        let line = match best {
+            Some(slot) => {
+                let s = &mut b.slots[slot.index()];
+                if s.successful {
+                    format!("slot {}", slot.as_str())
+                } else {
+                    s.tries -= 1;
+                    b.spent = true;
+                    format!("slot {} (unproven, {} tries left)", slot.as_str(), s.tries)
+                }
+            }
+            None => {
+                crate::error!("no bootable slot; booting slot a to recover");
+                String::from("slot a (no bootable slot)")
+            }
+        };
+        let slot = best.unwrap_or(Slot::A);
+        b.current = Some(slot);
+        if let Err(e) = b.save() {
+            crate::error!("slot metadata not saved to {}: {}", b.store.name(), e);
+        }
+        (slot, line)
+    });
+
+    crate::info!("booting {}", line);
+    let mut digest = [0u8; attest::DIGEST_LEN];
+    sha3::shake256_into(line.as_bytes(), &mut digest);
+    let _ = attest::measure(attest::REG_POLICY, &digest, &format!("boot control: {}", line));
+    crate::audit::note("bootctl", &line);
+    slot
+}
+
+/// The slot booted, once `select` has run.
+pub fn current() -> Option<Slot> {
+    with(|b| b.current)
+}
+
+pub fn slots() -> [SlotInfo; SLOTS] {
+    with(|b| b.slots)
+}
+
+pub fn store_name() -> &'static str {
+    with(|b| b.store.name())
+}
+
+/// Record that the running slot booted successfully, so it stops
+/// spending tries.  Init calls this once its critical services are up.
+pub fn mark_successful() -> Result<(), &'static str> {
+    with(|b| {
+        let slot = b.current.ok_or("no slot selected")?;
+        let s = &mut b.slots[slot.index()];
+        if s.successful { return Ok(()); }
+        s.successful = true;
+        s.tries = MAX_TRIES;
+        // The loader may have booted a slot the metadata wrote off
+        if s.priority == 0 { s.priority = MAX_PRIORITY; }
+        b.save()
+    })?;
+    crate::audit::note("bootctl", "boot marked successful");
+    Ok(())
+}
+
+/// Boot `slot` next: top priority and a full count of tries, to prove
+/// itself in.  The other slot, if bootable, stays behind it to fall
+/// back on.
+pub fn set_active(slot: Slot) -> Result<(), &'static str> {
+    with(|b| {
+        b.slots[slot.index()] = SlotInfo { priority: MAX_PRIORITY, tries: MAX_TRIES, successful: false };
+        let other = &mut b.slots[slot.other().index()];
+        other.priority = other.priority.min(MAX_PRIORITY - 1);
+        b.save()
+    })?;
+    crate::audit::note("bootctl", &format!("slot {} set active", slot.as_str()));
+    Ok(())
+}
+
+/// Never boot `slot`, e.g. while an update is being written to it.  The
+/// running slot cannot be written off.
+pub fn set_unbootable(slot: Slot) -> Result<(), &'static str> {
+    with(|b| {
+        if b.current == Some(slot) { return Err("slot is running"); }
+        b.slots[slot.index()] = SlotInfo { priority: 0, tries: 0, successful: false };
+        b.save()
+    })?;
+    crate::audit::note("bootctl", &format!("slot {} marked unbootable", slot.as_str()));
+    Ok(())
+}
+
+/// Whether the loader chose the slot booted.
+pub fn chosen_by_loader() -> bool {
+    with(|b| b.by_loader)
+}
@@ -0,0 +1,155 @@
+//! SurakshaOS Adaptive Brightness
+//! Control loop from the ambient light sensor to the display backlight.
+//! Lux readings are mapped through a configurable response curve, and a
+//! hysteresis band keeps small light changes from making the screen
+//! flicker.  A manual override takes the loop out of the picture.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+// ─── hardware interfaces ──────────────────────────────────────────────────────
+
+pub trait AmbientLightSensor: Send {
+    fn read_lux(&mut self) -> Result<u32, &'static str>;
+}
+
+pub trait Backlight: Send {
+    /// Set brightness in permille of maximum (0–1000).
+    fn set_permille(&mut self, level: u16) -> Result<(), &'static str>;
+}
+
+// ─── response curve ───────────────────────────────────────────────────────────
+
+/// One point of the lux → brightness curve.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurvePoint {
+    pub lux:      u32,
+    pub permille: u16,
+}
+
+pub const DEFAULT_CURVE: &[CurvePoint] = &[
+    CurvePoint { lux:      0, permille:   20 },
+    CurvePoint { lux:     10, permille:   80 },
+    CurvePoint { lux:    100, permille:  250 },
+    CurvePoint { lux:   1000, permille:  550 },
+    CurvePoint { lux:  10000, permille: 1000 },
+];
+
+/// Linear interpolation along `curve` (sorted by lux).
+fn evaluate(curve: &[CurvePoint], lux: u32) -> u16 {
+    let Some(first) = curve.first() else { return 1000 };
+    if lux <= first.lux { return first.permille; }
+    for w in curve.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        if lux <= b.lux {
+            let span = (b.lux - a.lux).max(1) as i64;
+            let d    = b.permille as i64 - a.permille as i64;
+            return (a.permille as i64 + d * (lux - a.lux) as i64 / span) as u16;
+        }
+    }
+    curve[curve.len() - 1].permille
+}
+
+/// Lux must rise this far (percent) above the last adjustment to brighten…
+const BRIGHTEN_THRESHOLD_PCT: u32 = 10;
+/// …or fall this far below it to darken.
+const DARKEN_THRESHOLD_PCT: u32 = 20;
+
+/// Largest brightness change applied per tick, so adjustments fade.
+const MAX_STEP_PERMILLE: u16 = 100;
+
+// ─── control loop ─────────────────────────────────────────────────────────────
+
+struct Controller {
+    sensor:    Option<Box<dyn AmbientLightSensor>>,
+    backlight: Option<Box<dyn Backlight>>,
+    curve:     Vec<CurvePoint>,
+    /// Lux at which the target was last recomputed.
+    anchor:    Option<u32>,
+    target:    u16,
+    current:   u16,
+    manual:    Option<u16>,
+}
+
+static CONTROLLER: Mutex<Controller> = Mutex::new(Controller {
+    sensor:    None,
+    backlight: None,
+    curve:     Vec::new(),
+    anchor:    None,
+    target:    500,
+    current:   500,
+    manual:    None,
+});
+
+impl Controller {
+    fn update(&mut self) {
+        if let Some(level) = self.manual {
+            self.target = level;
+        } else if let Some(lux) = self.sensor.as_mut().and_then(|s| s.read_lux().ok()) {
+            let outside = match self.anchor {
+                None    => true,
+                Some(a) => lux > a + a * BRIGHTEN_THRESHOLD_PCT / 100
+                        || lux < a - a * DARKEN_THRESHOLD_PCT / 100,
+            };
+            if outside {
+                self.anchor = Some(lux);
+                let curve = if self.curve.is_empty() { DEFAULT_CURVE } else { &self.curve };
+                self.target = evaluate(curve, lux);
+            }
+        }
+
+        let next = if self.current < self.target {
+            self.target.min(self.current + MAX_STEP_PERMILLE)
+        } else {
+            self.target.max(self.current.saturating_sub(MAX_STEP_PERMILLE))
+        };
+        if next != self.current {
+            if let Some(bl) = self.backlight.as_mut() {
+                if bl.set_permille(next).is_ok() { self.current = next; }
+            }
+        }
+    }
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+pub fn attach_sensor(sensor: Box<dyn AmbientLightSensor>) {
+    CONTROLLER.lock().sensor = Some(sensor);
+}
+
+pub fn attach_backlight(backlight: Box<dyn Backlight>) {
+    CONTROLLER.lock().backlight = Some(backlight);
+}
+
+/// Replace the response curve.  Points must be in increasing lux order.
+pub fn set_curve(curve: &[CurvePoint]) -> Result<(), &'static str> {
+    if curve.is_empty() { return Err("empty brightness curve"); }
+    if curve.windows(2).any(|w| w[0].lux >= w[1].lux) { return Err("curve not sorted by lux"); }
+    if curve.iter().any(|p| p.permille > 1000) { return Err("brightness above 1000 permille"); }
+    let mut c = CONTROLLER.lock();
+    c.curve  = curve.to_vec();
+    c.anchor = None;
+    Ok(())
+}
+
+/// Fix the brightness at `level` permille, or return to automatic control
+/// with `None`.
+pub fn set_manual(level: Option<u16>) {
+    let mut c = CONTROLLER.lock();
+    c.manual = level.map(|l| l.min(1000));
+    c.anchor = None;
+}
+
+/// (current, target) brightness in permille.
+pub fn level() -> (u16, u16) {
+    let c = CONTROLLER.lock();
+    (c.current, c.target)
+}
+
+/// Run one step of the control loop; called from the timer tick.
+pub fn brightness_tick() {
+    if let Some(mut c) = CONTROLLER.try_lock() {
+        c.update();
+    }
+}
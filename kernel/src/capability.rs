@@ -0,0 +1,734 @@
+//! SurakshaOS Capability System
+//! Unforgeable tokens that grant a process specific rights over a resource.
+//! The registry mints, validates and revokes capabilities and keeps an
+//! audit trail of every decision it makes.  A capability may be minted
+//! with an expiry; past it, validation refuses it, and a sweep driven by
+//! the timer tick retires it from the registry.
+//!
+//! Processes never hold capabilities themselves, only handles: each
+//! process has a capability table (its CSpace) in the registry, and a
+//! handle is an index into its own table, naming nothing in any other.
+//! Validation resolves the handle in the caller's table and checks the
+//! registry's record of what it names, so a `Capability` value copied,
+//! passed along or made up grants nothing its holder's table does not.
+//!
+//! A holder with GRANT can delegate: give another process a capability
+//! derived from its own, over the same resource with some of its rights
+//! and expiring no later.  Delegations chain, and revoking a capability
+//! revokes everything delegated from it, however many hands down — as does
+//! closing it, or its holder exiting.  The server of an IPC port hands
+//! out its clients' capabilities this way, each with a badge of its own.
+//!
+//! Every decision goes into an audit ring of the last AUDIT_CAPACITY,
+//! numbered in order so a reader can tell what it missed; `audit_query`
+//! picks from it by process, capability and operation, and SYS_AUDIT_READ
+//! hands it to a holder of the AuditLog capability.
+
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::memory::{TypeSlab, CAPABILITIES};
+use crate::process::ProcessId;
+
+// ─── identifiers & rights ─────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CapId(pub u64);
+
+impl core::fmt::Display for CapId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:#06x}", self.0)
+    }
+}
+
+/// What a process holds for a capability: a slot in its own table and
+/// the slot's generation, so a handle to a slot since emptied names
+/// nothing.  Zero is never a handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapHandle(pub u64);
+
+impl CapHandle {
+    pub const NULL: CapHandle = CapHandle(0);
+
+    fn new(slot: usize, generation: u32) -> CapHandle {
+        CapHandle(((generation as u64) << 32) | (slot as u64 + 1))
+    }
+
+    fn slot(self) -> Option<usize> {
+        (self.0 as u32 as usize).checked_sub(1)
+    }
+
+    fn generation(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+}
+
+impl core::fmt::Display for CapHandle {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Bit set of rights carried by a capability.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions(pub u32);
+
+impl Permissions {
+    pub const NONE:    Permissions = Permissions(0);
+    pub const READ:    Permissions = Permissions(1 << 0);
+    pub const WRITE:   Permissions = Permissions(1 << 1);
+    pub const EXECUTE: Permissions = Permissions(1 << 2);
+    /// Device control requests (ioctl) beyond plain data transfer.
+    pub const CONTROL: Permissions = Permissions(1 << 3);
+    /// May hand a subset of these rights to another process.
+    pub const GRANT:   Permissions = Permissions(1 << 4);
+
+    /// True if every right in `other` is also present in `self`.
+    pub const fn contains(self, other: Permissions) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for Permissions {
+    type Output = Permissions;
+    fn bitor(self, rhs: Permissions) -> Permissions { Permissions(self.0 | rhs.0) }
+}
+
+impl core::fmt::Display for Permissions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let flags = [
+            (Permissions::READ, 'r'), (Permissions::WRITE, 'w'), (Permissions::EXECUTE, 'x'),
+            (Permissions::CONTROL, 'c'), (Permissions::GRANT, 'g'),
+        ];
+        for (bit, ch) in flags {
+            write!(f, "{}", if self.contains(bit) { ch } else { '-' })?;
+        }
+        Ok(())
+    }
+}
+
+/// The kind of resource a capability refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapabilityType {
+    /// A hardware device, identified by its driver-registry id.
+    Device(u32),
+    /// A region of physical memory (base, length).
+    Memory { base: usize, len: usize },
+    /// A filesystem subtree.
+    File,
+    /// Network access.
+    Network,
+    /// An IPC channel, by channel id.
+    Ipc(u32),
+    /// Holding wakelocks (keeping the system out of suspend).
+    WakeLock,
+    /// Running an on-device AI model, by its AI-service model id.
+    Ai(u32),
+    /// Capturing the prompts and output of the AI service for debugging.
+    AiDebug,
+    /// Capturing network traffic for debugging.
+    NetDiagnostics,
+    /// Configuring network interfaces: links, addresses and name servers.
+    InterfaceAdmin,
+    /// Reading and verifying the security audit log.
+    AuditLog,
+    /// Asking for attestation quotes signed with the device key.
+    Attestation,
+    /// Reading the user's contacts.
+    Contacts,
+    /// Answering permission prompts and changing the user's decisions.
+    PermissionAdmin,
+    /// Receiving the security monitor's escalations.
+    SecurityEvents,
+    /// Opening sessions to trusted apps in the TEE.
+    TrustedApp,
+    /// Storing keys in the TEE key store and using them.
+    SecureKeys,
+    /// Enrolling and matching biometric templates.
+    Biometric,
+    /// Verifying users' credentials, under authd's rate limits.
+    Authenticator,
+    /// Installing and removing apps; with CONTROL, changing install policy.
+    AppInstall,
+    /// Reading or writing the clipboard.
+    Clipboard,
+    /// Putting windows on the screen; with CONTROL, in the system UI's layers.
+    Display,
+    /// An IPC port, by port id: its server's, with badge 0, or a client's,
+    /// whose messages carry its badge.
+    Port { port: u32, badge: u64 },
+}
+
+impl CapabilityType {
+    /// The kind, by its place in the declaration (Device is 0), and the
+    /// resource's number where it has one (a Memory region's base), for
+    /// the system call that lists a process's capabilities.
+    pub fn code(&self) -> (u32, u64) {
+        use CapabilityType::*;
+        match *self {
+            Device(d)             => (0, d as u64),
+            Memory { base, .. }   => (1, base as u64),
+            File                  => (2, 0),
+            Network               => (3, 0),
+            Ipc(ch)               => (4, ch as u64),
+            WakeLock              => (5, 0),
+            Ai(m)                 => (6, m as u64),
+            AiDebug               => (7, 0),
+            NetDiagnostics        => (8, 0),
+            InterfaceAdmin        => (9, 0),
+            AuditLog              => (10, 0),
+            Attestation           => (11, 0),
+            Contacts              => (12, 0),
+            PermissionAdmin       => (13, 0),
+            SecurityEvents        => (14, 0),
+            TrustedApp            => (15, 0),
+            SecureKeys            => (16, 0),
+            Biometric             => (17, 0),
+            Authenticator         => (18, 0),
+            AppInstall            => (19, 0),
+            Clipboard             => (20, 0),
+            Display               => (21, 0),
+            Port { port, .. }     => (22, port as u64),
+        }
+    }
+}
+
+// ─── capability ───────────────────────────────────────────────────────────────
+
+/// The registry's record of a capability, as the kernel passes it about.
+#[derive(Debug, Clone)]
+pub struct Capability {
+    pub id:       CapId,
+    /// The owner's handle to it: all validation looks at.
+    pub handle:   CapHandle,
+    pub owner:    ProcessId,
+    pub cap_type: CapabilityType,
+    pub perms:    Permissions,
+    /// Uptime in ms after which the capability is void (0 = never).
+    pub expiry:   u64,
+}
+
+/// Audit entries kept; older ones make way.
+pub const AUDIT_CAPACITY: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOp {
+    Create,
+    Validate,
+    Deny,
+    Revoke,
+    /// Retired by the sweep at its expiry.
+    Expire,
+    /// Handed on, in part, to another process.
+    Delegate,
+}
+
+impl AuditOp {
+    /// Its bit in `AuditFilter::ops`.
+    pub const fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    /// Position in the trail, from 1; a gap is entries the ring dropped.
+    pub seq:     u64,
+    pub time_ms: u64,
+    pub pid:     ProcessId,
+    pub cap:     CapId,
+    pub op:      AuditOp,
+}
+
+struct Entry {
+    cap:     Capability,
+    revoked: bool,
+    /// What it was delegated from.
+    parent:  Option<CapId>,
+}
+
+#[derive(Clone, Copy)]
+struct Slot {
+    cap:        Option<CapId>,
+    generation: u32,
+}
+
+/// A process's capability table.  A revoked or expired capability keeps
+/// its slot, so validation can say why it fails, until the process
+/// closes the handle or the slot is needed for another.
+struct CSpace {
+    owner: ProcessId,
+    slots: Vec<Slot>,
+}
+
+impl CSpace {
+    /// Put `id` in an empty slot, or one whose capability is `dead`.
+    fn insert(&mut self, id: CapId, dead: impl Fn(CapId) -> bool) -> CapHandle {
+        let free = self.slots.iter().position(|s| s.cap.is_none_or(&dead));
+        let i = match free {
+            Some(i) => i,
+            None => {
+                self.slots.push(Slot { cap: None, generation: 0 });
+                self.slots.len() - 1
+            }
+        };
+        let slot = &mut self.slots[i];
+        if slot.cap.is_some() { slot.generation = slot.generation.wrapping_add(1); }
+        slot.cap = Some(id);
+        CapHandle::new(i, slot.generation)
+    }
+
+    fn get(&self, handle: CapHandle) -> Option<CapId> {
+        let s = self.slots.get(handle.slot()?)?;
+        if s.generation == handle.generation() { s.cap } else { None }
+    }
+
+    /// Empty the slot holding `id`, moving its generation on.
+    fn remove(&mut self, id: CapId) {
+        for s in self.slots.iter_mut().filter(|s| s.cap == Some(id)) {
+            s.cap = None;
+            s.generation = s.generation.wrapping_add(1);
+        }
+    }
+}
+
+/// Which audit entries a query wants.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditFilter {
+    /// Only those numbered after this.
+    pub after: u64,
+    pub pid:   Option<ProcessId>,
+    pub cap:   Option<CapId>,
+    /// `AuditOp::bit`s of the operations wanted; 0 for all.
+    pub ops:   u32,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, e: &AuditEntry) -> bool {
+        e.seq > self.after
+            && self.pid.is_none_or(|p| p == e.pid)
+            && self.cap.is_none_or(|c| c == e.cap)
+            && (self.ops == 0 || self.ops & e.op.bit() != 0)
+    }
+}
+
+/// The last AUDIT_CAPACITY audit entries.
+struct AuditRing {
+    entries:  VecDeque<AuditEntry>,
+    next_seq: u64,
+}
+
+impl AuditRing {
+    const fn new() -> Self {
+        AuditRing { entries: VecDeque::new(), next_seq: 1 }
+    }
+
+    fn push(&mut self, pid: ProcessId, cap: CapId, op: AuditOp) {
+        if self.entries.len() >= AUDIT_CAPACITY { self.entries.pop_front(); }
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.entries.push_back(AuditEntry { seq, time_ms: crate::arch::uptime_millis(), pid, cap, op });
+    }
+
+    /// The oldest `max` entries `filter` picks.
+    fn query(&self, filter: &AuditFilter, max: usize) -> Vec<AuditEntry> {
+        // Entries are in seq order, so skip straight to those after
+        let first = self.entries.partition_point(|e| e.seq <= filter.after);
+        self.entries.range(first..).filter(|e| filter.matches(e)).take(max).cloned().collect()
+    }
+}
+
+/// A further check on validations, letting a subsystem withhold whole
+/// kinds of resource from some processes.  Runs with the registry locked.
+pub type Policy = fn(ProcessId, CapabilityType) -> Result<(), &'static str>;
+
+pub struct CapabilityRegistry {
+    /// In a slab of their own under heap hardening.  Only ever appended
+    /// to, so in id order, and a delegation comes after what it was
+    /// delegated from.
+    entries:   Vec<Entry, &'static TypeSlab>,
+    cspaces:   Vec<CSpace>,
+    audit:     AuditRing,
+    policy:    Option<Policy>,
+}
+
+static NEXT_CAP_ID: AtomicU64 = AtomicU64::new(0xA000);
+/// Uptime in ms of the earliest expiry among live capabilities, read by
+/// the timer tick without taking the registry lock.
+static NEXT_EXPIRY: AtomicU64 = AtomicU64::new(u64::MAX);
+
+static REGISTRY: Mutex<CapabilityRegistry> = Mutex::new(CapabilityRegistry::new());
+
+impl Default for CapabilityRegistry {
+    fn default() -> Self { Self::new() }
+}
+
+impl CapabilityRegistry {
+    pub const fn new() -> Self {
+        CapabilityRegistry { entries: Vec::new_in(&CAPABILITIES), cspaces: Vec::new(), audit: AuditRing::new(), policy: None }
+    }
+
+    fn audit(&mut self, pid: ProcessId, cap: CapId, op: AuditOp) {
+        self.audit.push(pid, cap, op);
+    }
+
+    fn position(&self, id: CapId) -> Option<usize> {
+        self.entries.binary_search_by_key(&id, |e| e.cap.id).ok()
+    }
+
+    fn entry(&self, id: CapId) -> Option<&Entry> {
+        self.position(id).map(|i| &self.entries[i])
+    }
+
+    /// Put `id` in `owner`'s table.
+    fn map(&mut self, owner: ProcessId, id: CapId) -> CapHandle {
+        let i = match self.cspaces.iter().position(|c| c.owner == owner) {
+            Some(i) => i,
+            None => {
+                self.cspaces.push(CSpace { owner, slots: Vec::new() });
+                self.cspaces.len() - 1
+            }
+        };
+        let entries = &self.entries;
+        let live = |old| entries.binary_search_by_key(&old, |e| e.cap.id).is_ok_and(|j| !entries[j].revoked);
+        self.cspaces[i].insert(id, |old| !live(old))
+    }
+
+    /// The entry `handle` names in `caller`'s table.
+    fn resolve(&self, caller: ProcessId, handle: CapHandle) -> Option<&Entry> {
+        let id = self.cspaces.iter().find(|c| c.owner == caller)?.get(handle)?;
+        self.entry(id)
+    }
+
+    fn unmap(&mut self, owner: ProcessId, id: CapId) {
+        if let Some(c) = self.cspaces.iter_mut().find(|c| c.owner == owner) { c.remove(id); }
+    }
+
+    pub fn create(&mut self, owner: ProcessId, cap_type: CapabilityType, perms: Permissions) -> Capability {
+        self.create_until(owner, cap_type, perms, 0)
+    }
+
+    /// Mint a capability that is void from uptime `expiry` ms (0 = never).
+    pub fn create_until(&mut self, owner: ProcessId, cap_type: CapabilityType, perms: Permissions, expiry: u64) -> Capability {
+        self.mint(owner, cap_type, perms, expiry, None)
+    }
+
+    fn mint(&mut self, owner: ProcessId, cap_type: CapabilityType, perms: Permissions, expiry: u64, parent: Option<CapId>) -> Capability {
+        let id     = CapId(NEXT_CAP_ID.fetch_add(1, Ordering::SeqCst));
+        let handle = self.map(owner, id);
+        let cap    = Capability { id, handle, owner, cap_type, perms, expiry };
+        self.entries.push(Entry { cap: cap.clone(), revoked: false, parent });
+        self.audit(owner, id, AuditOp::Create);
+        if expiry != 0 { NEXT_EXPIRY.fetch_min(expiry, Ordering::Relaxed); }
+        cap
+    }
+
+    /// Mint a capability that is void `duration_ms` from now.
+    pub fn create_for(&mut self, owner: ProcessId, cap_type: CapabilityType, perms: Permissions, duration_ms: u64) -> Capability {
+        let expiry = crate::arch::uptime_millis().saturating_add(duration_ms.max(1));
+        self.create_until(owner, cap_type, perms, expiry)
+    }
+
+    /// Give `to` a capability derived from the one `handle` names in
+    /// `caller`'s table, which must carry GRANT: over the same resource,
+    /// with `perms` of its rights, and expiring when it does.
+    pub fn delegate(&mut self, caller: ProcessId, handle: CapHandle, to: ProcessId, perms: Permissions) -> Result<Capability, &'static str> {
+        self.derive(caller, handle, to, perms, Some)
+    }
+
+    /// Delegate, as `delegate`, from the unbadged capability for a port
+    /// its server holds, a client's capability for it carrying `badge`.
+    pub fn badge(&mut self, caller: ProcessId, handle: CapHandle, to: ProcessId, perms: Permissions, badge: u64) -> Result<Capability, &'static str> {
+        if badge == 0 { return Err("badge 0 is the server's"); }
+        self.derive(caller, handle, to, perms, |t| match t {
+            CapabilityType::Port { port, badge: 0 } => Some(CapabilityType::Port { port, badge }),
+            _                                       => None,
+        })
+    }
+
+    /// Delegate over what `retype` makes of the resource, if it allows it.
+    fn derive(
+        &mut self,
+        caller: ProcessId,
+        handle: CapHandle,
+        to:     ProcessId,
+        perms:  Permissions,
+        retype: impl FnOnce(CapabilityType) -> Option<CapabilityType>,
+    ) -> Result<Capability, &'static str> {
+        let now = crate::arch::uptime_millis();
+        let e = self.resolve(caller, handle).ok_or("unknown capability")?;
+        let (parent, cap_type, expiry) = (e.cap.id, retype(e.cap.cap_type), e.cap.expiry);
+        let refused = match e {
+            e if e.cap.expired(now)                         => Some("capability expired"),
+            e if e.revoked                                  => Some("capability revoked"),
+            e if !e.cap.perms.contains(Permissions::GRANT)  => Some("capability cannot be delegated"),
+            e if !e.cap.perms.contains(perms)               => Some("insufficient permissions"),
+            _                                               => None,
+        };
+        let cap_type = match (refused, cap_type) {
+            (None, Some(t)) => t,
+            (why, _)        => {
+                self.audit(caller, parent, AuditOp::Deny);
+                return Err(why.unwrap_or("capability cannot be badged"));
+            }
+        };
+        self.audit(caller, parent, AuditOp::Delegate);
+        Ok(self.mint(to, cap_type, perms, expiry, Some(parent)))
+    }
+
+    /// Check that `cap`'s handle names, in `caller`'s table, a live
+    /// capability over `target` with at least `required` rights.
+    pub fn validate(
+        &mut self,
+        caller:   ProcessId,
+        cap:      &Capability,
+        target:   CapabilityType,
+        required: Permissions,
+    ) -> Result<(), &'static str> {
+        self.validate_handle(caller, cap.handle, target, required).map(drop)
+    }
+
+    /// Check what `handle` names in `caller`'s table, as `validate`, and
+    /// return the registry's record of it.
+    pub fn validate_handle(
+        &mut self,
+        caller:   ProcessId,
+        handle:   CapHandle,
+        target:   CapabilityType,
+        required: Permissions,
+    ) -> Result<Capability, &'static str> {
+        // Expiry first: a swept capability is revoked, but reports why
+        let now = crate::arch::uptime_millis();
+        let found = self.resolve(caller, handle);
+        let id = found.map_or(CapId(0), |e| e.cap.id);
+        let result = match found {
+            None                                   => Err("unknown capability"),
+            Some(e) if e.cap.expired(now)          => Err("capability expired"),
+            Some(e) if e.revoked                   => Err("capability revoked"),
+            Some(e) if e.cap.cap_type != target    => Err("capability does not cover resource"),
+            Some(e) if !e.cap.perms.contains(required) => Err("insufficient permissions"),
+            Some(e)                                => Ok(e.cap.clone()),
+        };
+        let result = result.and_then(|cap| self.policy.map_or(Ok(()), |p| p(caller, target)).map(|_| cap));
+        let op = if result.is_ok() { AuditOp::Validate } else { AuditOp::Deny };
+        self.audit(caller, id, op);
+        result
+    }
+
+    /// The live capability `handle` names in `caller`'s table.
+    pub fn lookup(&self, caller: ProcessId, handle: CapHandle) -> Result<Capability, &'static str> {
+        let now = crate::arch::uptime_millis();
+        match self.resolve(caller, handle) {
+            Some(e) if !e.revoked && !e.cap.expired(now) => Ok(e.cap.clone()),
+            _ => Err("unknown capability"),
+        }
+    }
+
+    /// Give up the capability `handle` names in `caller`'s table.
+    pub fn close(&mut self, caller: ProcessId, handle: CapHandle) -> Result<(), &'static str> {
+        let e = self.resolve(caller, handle).ok_or("unknown capability")?;
+        let (id, live) = (e.cap.id, !e.revoked);
+        if live { self.revoke(id)?; }
+        self.unmap(caller, id);
+        Ok(())
+    }
+
+    /// The live capabilities in `owner`'s table.
+    pub fn held_by(&self, owner: ProcessId) -> Vec<Capability> {
+        let Some(c) = self.cspaces.iter().find(|c| c.owner == owner) else { return Vec::new() };
+        c.slots.iter().filter_map(|s| s.cap)
+            .filter_map(|id| self.entry(id).filter(|e| !e.revoked))
+            .map(|e| e.cap.clone())
+            .collect()
+    }
+
+    /// Drop `owner`'s table; it has exited.
+    fn forget(&mut self, owner: ProcessId) {
+        self.cspaces.retain(|c| c.owner != owner);
+    }
+
+    /// Revoke `id` and everything delegated from it, down every chain;
+    /// returns how many capabilities that revoked.
+    pub fn revoke(&mut self, id: CapId) -> Result<usize, &'static str> {
+        let first = self.position(id).ok_or("unknown capability")?;
+        // Delegations come after their parents, so one pass from `id`
+        // meets each parent before its children
+        let mut subtree = BTreeSet::from([id]);
+        let mut revoked = Vec::new();
+        for e in self.entries[first..].iter_mut() {
+            let inside = e.cap.id == id || e.parent.is_some_and(|p| subtree.contains(&p));
+            if !inside { continue; }
+            subtree.insert(e.cap.id);
+            if e.cap.id == id || !e.revoked {
+                e.revoked = true;
+                revoked.push((e.cap.owner, e.cap.id));
+            }
+        }
+        for &(owner, cap) in &revoked {
+            self.audit(owner, cap, AuditOp::Revoke);
+        }
+        Ok(revoked.len())
+    }
+
+    /// Revoke every live capability `owner` holds over a resource `matches`
+    /// picks out; returns how many.
+    pub fn revoke_owner(&mut self, owner: ProcessId, matches: impl Fn(&CapabilityType) -> bool) -> usize {
+        let ids: Vec<CapId> = self.entries.iter()
+            .filter(|e| e.cap.owner == owner && !e.revoked && matches(&e.cap.cap_type))
+            .map(|e| e.cap.id)
+            .collect();
+        for &id in &ids {
+            let _ = self.revoke(id);
+        }
+        ids.len()
+    }
+
+    /// Retire every live capability expired at `now`, and return the
+    /// earliest expiry still to come.
+    pub fn sweep(&mut self, now: u64) -> Option<u64> {
+        let mut expired = Vec::new();
+        for e in self.entries.iter_mut().filter(|e| !e.revoked && e.cap.expired(now)) {
+            e.revoked = true;
+            expired.push((e.cap.owner, e.cap.id));
+        }
+        for (owner, id) in expired {
+            self.audit(owner, id, AuditOp::Expire);
+        }
+        self.entries.iter().filter(|e| !e.revoked && e.cap.expiry != 0).map(|e| e.cap.expiry).min()
+    }
+
+    /// The oldest `max` audit entries `filter` picks.
+    pub fn audit_query(&self, filter: &AuditFilter, max: usize) -> Vec<AuditEntry> {
+        self.audit.query(filter, max)
+    }
+}
+
+impl Capability {
+    /// Whether it is past its expiry at uptime `now` ms.
+    pub fn expired(&self, now: u64) -> bool {
+        self.expiry != 0 && now >= self.expiry
+    }
+
+    /// Milliseconds it has left at uptime `now`, if it expires at all.
+    pub fn remaining_ms(&self, now: u64) -> Option<u64> {
+        (self.expiry != 0).then(|| self.expiry.saturating_sub(now))
+    }
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+pub fn create_capability(owner: ProcessId, cap_type: CapabilityType, perms: Permissions) -> Capability {
+    REGISTRY.lock().create(owner, cap_type, perms)
+}
+
+/// Mint a capability that is void from uptime `expiry` ms.
+pub fn create_capability_until(owner: ProcessId, cap_type: CapabilityType, perms: Permissions, expiry: u64) -> Capability {
+    REGISTRY.lock().create_until(owner, cap_type, perms, expiry)
+}
+
+/// Mint a capability that is void `duration_ms` from now: camera access
+/// for five minutes is `create_capability_with_expiry(pid, camera, READ, 300_000)`.
+pub fn create_capability_with_expiry(owner: ProcessId, cap_type: CapabilityType, perms: Permissions, duration_ms: u64) -> Capability {
+    REGISTRY.lock().create_for(owner, cap_type, perms, duration_ms)
+}
+
+pub fn validate(
+    caller:   ProcessId,
+    cap:      &Capability,
+    target:   CapabilityType,
+    required: Permissions,
+) -> Result<(), &'static str> {
+    REGISTRY.lock().validate(caller, cap, target, required)
+}
+
+/// Validate what `handle` names in `caller`'s table, for a system call,
+/// and return the capability for the subsystem it goes on to.
+pub fn validate_handle(
+    caller:   ProcessId,
+    handle:   CapHandle,
+    target:   CapabilityType,
+    required: Permissions,
+) -> Result<Capability, &'static str> {
+    REGISTRY.lock().validate_handle(caller, handle, target, required)
+}
+
+pub fn lookup(caller: ProcessId, handle: CapHandle) -> Result<Capability, &'static str> {
+    REGISTRY.lock().lookup(caller, handle)
+}
+
+/// Give up the capability `handle` names in `caller`'s table.
+pub fn close_handle(caller: ProcessId, handle: CapHandle) -> Result<(), &'static str> {
+    REGISTRY.lock().close(caller, handle)
+}
+
+/// What `owner`'s table holds.
+pub fn held_by(owner: ProcessId) -> Vec<Capability> {
+    REGISTRY.lock().held_by(owner)
+}
+
+/// Hand `to` part of what `handle` names in `caller`'s table; see
+/// `CapabilityRegistry::delegate`.
+pub fn delegate(caller: ProcessId, handle: CapHandle, to: ProcessId, perms: Permissions) -> Result<Capability, &'static str> {
+    REGISTRY.lock().delegate(caller, handle, to, perms)
+}
+
+/// Give `to` a capability for the port whose unbadged capability `handle`
+/// names in `caller`'s table, carrying `badge`; see
+/// `CapabilityRegistry::badge`.
+pub fn badge(caller: ProcessId, handle: CapHandle, to: ProcessId, perms: Permissions, badge: u64) -> Result<Capability, &'static str> {
+    REGISTRY.lock().badge(caller, handle, to, perms, badge)
+}
+
+/// Revoke `id` and every capability delegated from it; returns how many.
+pub fn revoke_capability(id: CapId) -> Result<usize, &'static str> {
+    REGISTRY.lock().revoke(id)
+}
+
+/// Revoke everything `owner` holds and drop its table; returns how many
+/// capabilities that was.
+pub fn revoke_all(owner: ProcessId) -> usize {
+    let mut reg = REGISTRY.lock();
+    let n = reg.revoke_owner(owner, |_| true);
+    reg.forget(owner);
+    n
+}
+
+/// Revoke what `owner` holds over resources `matches` picks out.
+pub fn revoke_matching(owner: ProcessId, matches: impl Fn(&CapabilityType) -> bool) -> usize {
+    REGISTRY.lock().revoke_owner(owner, matches)
+}
+
+/// Install `policy`, consulted by every validation that passes the
+/// registry's own checks.
+pub fn set_policy(policy: Policy) {
+    REGISTRY.lock().policy = Some(policy);
+}
+
+/// Called from the timer tick: once a capability is due to expire, have
+/// `sweep` run as deferred work.
+pub fn capability_tick() {
+    if crate::arch::uptime_millis() >= NEXT_EXPIRY.load(Ordering::Relaxed) {
+        NEXT_EXPIRY.store(u64::MAX, Ordering::Relaxed);
+        crate::process::defer(sweep);
+    }
+}
+
+/// Retire expired capabilities and arm the tick for the next expiry.
+fn sweep() {
+    let mut reg = REGISTRY.lock();
+    let next = reg.sweep(crate::arch::uptime_millis()).unwrap_or(u64::MAX);
+    NEXT_EXPIRY.fetch_min(next, Ordering::Relaxed);
+}
+
+/// Snapshot of the audit trail the ring still holds.
+pub fn audit_log() -> Vec<AuditEntry> {
+    audit_query(&AuditFilter::default(), AUDIT_CAPACITY)
+}
+
+/// The oldest `max` audit entries `filter` picks; a reader keeping up
+/// passes the last `seq` it saw as `filter.after`.
+pub fn audit_query(filter: &AuditFilter, max: usize) -> Vec<AuditEntry> {
+    REGISTRY.lock().audit_query(filter, max)
+}
@@ -0,0 +1,243 @@
+//! SurakshaOS Control-Flow Integrity
+//! Hardware checks on indirect jumps (forward edges) and returns (backward
+//! edges), where the CPU has them:
+//!
+//! - Zicfilp landing pads: an indirect jump must land on an `lpad`.
+//! - Zicfiss shadow stack: returns are checked against a stack ordinary
+//!   stores cannot reach.
+//!
+//! Both are found in the device tree's ISA strings.  For user binaries
+//! the ELF loader asks `admit` what an image was built with — its
+//! `.note.gnu.property` — and turns on what both it and the CPU support
+//! with `enable_user` whenever the process runs.  Built with the `cfi`
+//! feature, `admit` refuses a binary missing either where the CPU has
+//! it.  The same markings are read from aarch64 images, where they stand
+//! for BTI and PAC, for an ARM port.
+//!
+//! The kernel runs in M-mode, where Zicfiss does not apply; its return
+//! addresses are kept by the software shadow call stack (`stackguard`).
+//! Landing pads do apply, and the `cfi` feature turns them on for the
+//! kernel too — which needs every indirect target compiled with `lpad`,
+//! so only a kernel built by a toolchain that emits them (see the
+//! Makefile's `cfi` target) may be built with it.
+//!
+//! In S-mode under SBI (the `sbi` feature) the firmware decides whether
+//! the kernel has landing pads, and the kernel sets U-mode's in senvcfg,
+//! whose bits match menvcfg's.
+//!
+//! A violation raises a software-check exception, which panics.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether `admit` refuses binaries built without CFI the CPU supports,
+/// and the kernel enforces its own landing pads.
+const ENFORCING: bool = cfg!(feature = "cfi");
+
+/// Control-flow protections: what a CPU has, or what a binary was built
+/// for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Features {
+    /// Landing pads (Zicfilp; BTI on aarch64).
+    pub forward:  bool,
+    /// Shadow stack (Zicfiss; PAC on aarch64).
+    pub backward: bool,
+}
+
+impl Features {
+    pub const NONE: Features = Features { forward: false, backward: false };
+
+    fn and(self, other: Features) -> Features {
+        Features { forward: self.forward && other.forward, backward: self.backward && other.backward }
+    }
+}
+
+impl core::fmt::Display for Features {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match (self.forward, self.backward) {
+            (false, false) => write!(f, "none"),
+            (true,  false) => write!(f, "landing pads"),
+            (false, true)  => write!(f, "shadow stack"),
+            (true,  true)  => write!(f, "landing pads, shadow stack"),
+        }
+    }
+}
+
+static HW_FORWARD:  AtomicBool = AtomicBool::new(false);
+static HW_BACKWARD: AtomicBool = AtomicBool::new(false);
+static KERNEL_LP:   AtomicBool = AtomicBool::new(false);
+
+// ─── hardware ─────────────────────────────────────────────────────────────────
+
+const MENVCFG_LPE:  usize = 1 << 2;
+const MENVCFG_SSE:  usize = 1 << 3;
+const MSECCFG_MLPE: usize = 1 << 10;
+
+/// Whether every hart's ISA in the device tree names `ext`.
+fn all_harts_have(fdt: &crate::fdt::Fdt, ext: &str) -> bool {
+    let mut harts = fdt.nodes().filter(|n| n.depth == 2 && n.name.starts_with("cpu@")).peekable();
+    harts.peek().is_some() && harts.all(|n| {
+        let listed = n.property("riscv,isa-extensions").is_some_and(|list| {
+            list.split(|&b| b == 0).any(|e| e == ext.as_bytes())
+        });
+        let in_isa = n.property("riscv,isa").is_some_and(|isa| {
+            isa.split(|&b| b == b'_' || b == 0).any(|e| e == ext.as_bytes())
+        });
+        listed || in_isa
+    })
+}
+
+/// Find what the CPU supports and, when enforcing, turn on landing pads
+/// for the kernel.  Called once the device tree is known.
+pub fn init() {
+    let Some(fdt) = crate::fdt::get() else { return };
+    let hw = Features { forward: all_harts_have(&fdt, "zicfilp"), backward: all_harts_have(&fdt, "zicfiss") };
+    HW_FORWARD.store(hw.forward, Ordering::Relaxed);
+    HW_BACKWARD.store(hw.backward, Ordering::Relaxed);
+    #[cfg(target_arch = "riscv64")]
+    if ENFORCING && hw.forward && !crate::arch::SMODE {
+        unsafe { core::arch::asm!("csrs mseccfg, {}", in(reg) MSECCFG_MLPE) };
+        KERNEL_LP.store(true, Ordering::Relaxed);
+    }
+    crate::audit::note("cfi", &alloc::format!("hardware: {}; kernel landing pads {}", hw,
+        if KERNEL_LP.load(Ordering::Relaxed) { "on" } else { "off" }));
+}
+
+/// What the CPU supports.
+pub fn hardware() -> Features {
+    Features { forward: HW_FORWARD.load(Ordering::Relaxed), backward: HW_BACKWARD.load(Ordering::Relaxed) }
+}
+
+/// Whether the kernel's own indirect jumps must land on landing pads.
+pub fn kernel_landing_pads() -> bool {
+    KERNEL_LP.load(Ordering::Relaxed)
+}
+
+/// Turn `features` on, and the rest off, for U-mode.  The process switch
+/// calls it with what `admit` returned for the process about to run.
+pub fn enable_user(features: Features) {
+    let hw = hardware();
+    if !hw.forward && !hw.backward { return; }
+    let set = if features.forward && hw.forward { MENVCFG_LPE } else { 0 }
+        | if features.backward && hw.backward { MENVCFG_SSE } else { 0 };
+    #[cfg(target_arch = "riscv64")]
+    unsafe {
+        if crate::arch::SMODE {
+            core::arch::asm!("csrc senvcfg, {}", in(reg) MENVCFG_LPE | MENVCFG_SSE);
+            core::arch::asm!("csrs senvcfg, {}", in(reg) set);
+        } else {
+            core::arch::asm!("csrc menvcfg, {}", in(reg) MENVCFG_LPE | MENVCFG_SSE);
+            core::arch::asm!("csrs menvcfg, {}", in(reg) set);
+        }
+    }
+    // AArch64 never gets here: BTI and PAC are not detected yet
+    #[cfg(not(target_arch = "riscv64"))]
+    let _ = set;
+}
+
+/// A software-check exception: `mtval` says which check failed.
+pub fn violation(mepc: usize, mtval: usize) -> ! {
+    let what = match mtval {
+        2 => "indirect jump missed a landing pad",
+        3 => "return address does not match the shadow stack",
+        _ => "software check failed",
+    };
+    panic!("control-flow integrity: {} (mepc={:#x} mtval={:#x})", what, mepc, mtval);
+}
+
+// ─── binaries ─────────────────────────────────────────────────────────────────
+
+const EM_AARCH64: u16 = 183;
+const EM_RISCV:   u16 = 243;
+
+const PT_NOTE:                u32 = 4;
+const PT_GNU_PROPERTY:        u32 = 0x6474_e553;
+const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+/// GNU_PROPERTY_RISCV_FEATURE_1_AND and GNU_PROPERTY_AARCH64_FEATURE_1_AND
+/// share a number.
+const GNU_PROPERTY_FEATURE_1_AND: u32 = 0xc000_0000;
+
+const RISCV_CFI_LP_UNLABELED: u32 = 1 << 0;
+const RISCV_CFI_SS:           u32 = 1 << 1;
+const RISCV_CFI_LP_FUNC_SIG:  u32 = 1 << 2;
+const AARCH64_BTI:            u32 = 1 << 0;
+const AARCH64_PAC:            u32 = 1 << 1;
+
+fn u16_at(b: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(b.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(b: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(b.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(b: &[u8], at: usize) -> Option<usize> {
+    Some(u64::from_le_bytes(b.get(at..at + 8)?.try_into().ok()?) as usize)
+}
+
+/// The FEATURE_1_AND bits in the GNU property notes of `notes`.
+fn feature_bits(notes: &[u8], align: usize) -> Option<u32> {
+    let mut pos = 0;
+    while pos + 12 <= notes.len() {
+        let (namesz, descsz, ty) = (u32_at(notes, pos)? as usize, u32_at(notes, pos + 4)? as usize, u32_at(notes, pos + 8)?);
+        let name = pos + 12;
+        let desc = (name + namesz).next_multiple_of(align);
+        let next = (desc + descsz).next_multiple_of(align);
+        if ty == NT_GNU_PROPERTY_TYPE_0 && notes.get(name..name + namesz)? == b"GNU\0" {
+            let props = notes.get(desc..desc + descsz)?;
+            let mut p = 0;
+            while p + 8 <= props.len() {
+                let (pr_type, size) = (u32_at(props, p)?, u32_at(props, p + 4)? as usize);
+                if pr_type == GNU_PROPERTY_FEATURE_1_AND && size >= 4 { return u32_at(props, p + 8); }
+                p = (p + 8 + size).next_multiple_of(8);
+            }
+        }
+        pos = next;
+    }
+    None
+}
+
+/// What a little-endian ELF64 image was built for, from its program
+/// headers' GNU property note.  No note means no protections.
+pub fn elf_features(image: &[u8]) -> Result<Features, &'static str> {
+    if image.len() < 64 || &image[..4] != b"\x7fELF" { return Err("not an ELF image"); }
+    if image[4] != 2 || image[5] != 1 { return Err("not a little-endian ELF64 image"); }
+    let machine = u16_at(image, 0x12).ok_or("truncated ELF header")?;
+    let phoff   = u64_at(image, 0x20).ok_or("truncated ELF header")?;
+    let phsize  = u16_at(image, 0x36).ok_or("truncated ELF header")? as usize;
+    let phnum   = u16_at(image, 0x38).ok_or("truncated ELF header")? as usize;
+
+    let mut bits = None;
+    for i in 0..phnum {
+        let ph = phoff + i * phsize;
+        let ty = u32_at(image, ph).ok_or("truncated program header")?;
+        if ty != PT_GNU_PROPERTY && ty != PT_NOTE { continue; }
+        let off   = u64_at(image, ph + 0x08).ok_or("truncated program header")?;
+        let size  = u64_at(image, ph + 0x20).ok_or("truncated program header")?;
+        let align = u64_at(image, ph + 0x30).ok_or("truncated program header")?.max(4);
+        let notes = image.get(off..off.checked_add(size).ok_or("bad note segment")?).ok_or("bad note segment")?;
+        bits = bits.or(feature_bits(notes, align));
+        // PT_GNU_PROPERTY, if present, is the authority
+        if ty == PT_GNU_PROPERTY { break; }
+    }
+    let bits = bits.unwrap_or(0);
+    Ok(match machine {
+        EM_RISCV => Features {
+            forward:  bits & (RISCV_CFI_LP_UNLABELED | RISCV_CFI_LP_FUNC_SIG) != 0,
+            backward: bits & RISCV_CFI_SS != 0,
+        },
+        EM_AARCH64 => Features { forward: bits & AARCH64_BTI != 0, backward: bits & AARCH64_PAC != 0 },
+        _ => Features::NONE,
+    })
+}
+
+/// Decide whether a user binary may be loaded.  Returns the protections to
+/// run it with: those both it and the CPU support.  When enforcing, a
+/// binary missing one the CPU has is refused.
+pub fn admit(image: &[u8]) -> Result<Features, &'static str> {
+    let built = elf_features(image)?;
+    let hw = hardware();
+    if ENFORCING && ((hw.forward && !built.forward) || (hw.backward && !built.backward)) {
+        return Err("binary not built with the control-flow integrity this CPU enforces");
+    }
+    Ok(built.and(hw))
+}
@@ -0,0 +1,199 @@
+//! SurakshaOS Charging Policy
+//! Drives the battery charger to protect battery longevity: an upper
+//! charge limit (e.g. stop at 80%), charge current reduced by thermal
+//! throttling, and adaptive charging that holds at the limit and tops up
+//! just in time for a scheduled completion (e.g. the morning alarm).
+//! Services that only work on external power register with `on_change()`
+//! and are told (as deferred work) when it is plugged in or removed.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+// ─── charger hardware ─────────────────────────────────────────────────────────
+
+/// Interface to a fuel gauge + charger IC.
+pub trait ChargerHw: Send {
+    fn name(&self) -> &'static str;
+    /// True when external power is connected.
+    fn online(&mut self) -> bool;
+    /// State of charge, 0–100.
+    fn level_pct(&mut self) -> u8;
+    fn capacity_mah(&self) -> u32;
+    /// Largest current the charger IC will deliver.
+    fn max_current_ma(&self) -> u32;
+    /// Program the charge current; 0 stops charging.
+    fn set_current_ma(&mut self, ma: u32) -> Result<(), &'static str>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargeState {
+    NoCharger,
+    Discharging,
+    Charging,
+    /// Paused at the charge limit (or waiting for adaptive top-up).
+    Holding,
+    Full,
+}
+
+impl ChargeState {
+    /// External power is connected, whether or not current is flowing.
+    pub fn plugged_in(self) -> bool {
+        matches!(self, ChargeState::Charging | ChargeState::Holding | ChargeState::Full)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChargeStatus {
+    pub state:      ChargeState,
+    pub level_pct:  u8,
+    pub limit_pct:  u8,
+    pub current_ma: u32,
+    /// Uptime by which adaptive charging should reach 100%.
+    pub full_by_ms: Option<u64>,
+}
+
+/// Level adaptive charging holds at until it is time to finish.
+const ADAPTIVE_HOLD_PCT: u8 = 80;
+
+/// Extra time allowed for the top-up so it finishes before the deadline.
+const ADAPTIVE_MARGIN_MS: u64 = 30 * 60 * 1000;
+
+struct Charger {
+    hw:         Option<Box<dyn ChargerHw>>,
+    limit_pct:  u8,
+    /// User-set current ceiling (None = charger maximum).
+    max_ma:     Option<u32>,
+    full_by_ms: Option<u64>,
+    current_ma: u32,
+    state:      ChargeState,
+    level_pct:  u8,
+}
+
+static CHARGER: Mutex<Charger> = Mutex::new(Charger {
+    hw:         None,
+    limit_pct:  100,
+    max_ma:     None,
+    full_by_ms: None,
+    current_ma: 0,
+    state:      ChargeState::NoCharger,
+    level_pct:  0,
+});
+
+impl Charger {
+    /// Level the battery should currently be charged to.
+    fn target_pct(&self, level: u8, capacity_mah: u32, rate_ma: u32, now: u64) -> u8 {
+        let Some(deadline) = self.full_by_ms else { return self.limit_pct };
+        let hold = ADAPTIVE_HOLD_PCT.min(self.limit_pct);
+        if rate_ma == 0 { return hold; }
+        // Time to charge from max(level, hold) to the limit at `rate_ma`
+        let remaining_mah = capacity_mah as u64 * self.limit_pct.saturating_sub(level.max(hold)) as u64 / 100;
+        let needed_ms     = remaining_mah * 3_600_000 / rate_ma as u64 + ADAPTIVE_MARGIN_MS;
+        if now + needed_ms >= deadline { self.limit_pct } else { hold }
+    }
+
+    fn apply(&mut self) {
+        let now     = crate::arch::uptime_millis();
+        let thermal = crate::thermal::limits().charge_max_ma;
+        let Some(hw) = self.hw.as_mut() else { self.state = ChargeState::NoCharger; return };
+
+        let online   = hw.online();
+        let level    = hw.level_pct();
+        let capacity = hw.capacity_mah();
+        let mut rate = hw.max_current_ma();
+        if let Some(m) = self.max_ma { rate = rate.min(m); }
+        if let Some(m) = thermal { rate = rate.min(m); }
+        self.level_pct = level;
+
+        let target = self.target_pct(level, capacity, rate, now);
+        let (state, current) = if !online {
+            (ChargeState::Discharging, 0)
+        } else if level >= 100 {
+            (ChargeState::Full, 0)
+        } else if level >= target {
+            (ChargeState::Holding, 0)
+        } else {
+            (ChargeState::Charging, rate)
+        };
+
+        let hw = self.hw.as_mut().unwrap();
+        if current != self.current_ma && hw.set_current_ma(current).is_ok() {
+            self.current_ma = current;
+        }
+        if state.plugged_in() != self.state.plugged_in() {
+            if let Some(listeners) = LISTENERS.try_lock() {
+                for &f in listeners.iter() { crate::process::defer(f); }
+            }
+        }
+        self.state = state;
+        if state == ChargeState::Full || !online { self.full_by_ms = None; }
+    }
+}
+
+/// Called (as deferred work) when external power comes or goes.
+static LISTENERS: Mutex<Vec<fn()>> = Mutex::new(Vec::new());
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Attach the platform charger driver.
+pub fn attach(hw: Box<dyn ChargerHw>) {
+    let mut c = CHARGER.lock();
+    c.hw = Some(hw);
+    c.apply();
+}
+
+/// Stop charging once the battery reaches `pct` (50–100).
+pub fn set_charge_limit(pct: u8) -> Result<(), &'static str> {
+    if !(50..=100).contains(&pct) { return Err("charge limit must be 50-100%"); }
+    let mut c = CHARGER.lock();
+    c.limit_pct = pct;
+    c.apply();
+    Ok(())
+}
+
+/// Cap the charge current below the charger maximum (None lifts the cap).
+pub fn set_max_current(ma: Option<u32>) {
+    let mut c = CHARGER.lock();
+    c.max_ma = ma;
+    c.apply();
+}
+
+/// Adaptive charging: hold at 80% and finish charging by `at_ms` uptime.
+pub fn schedule_full_by(at_ms: u64) -> Result<(), &'static str> {
+    if at_ms <= crate::arch::uptime_millis() { return Err("completion time is in the past"); }
+    let mut c = CHARGER.lock();
+    c.full_by_ms = Some(at_ms);
+    c.apply();
+    Ok(())
+}
+
+pub fn cancel_adaptive() {
+    let mut c = CHARGER.lock();
+    c.full_by_ms = None;
+    c.apply();
+}
+
+pub fn status() -> ChargeStatus {
+    let c = CHARGER.lock();
+    ChargeStatus {
+        state:      c.state,
+        level_pct:  c.level_pct,
+        limit_pct:  c.limit_pct,
+        current_ma: c.current_ma,
+        full_by_ms: c.full_by_ms,
+    }
+}
+
+/// Have `f` run whenever external power is plugged in or removed.
+pub fn on_change(f: fn()) {
+    let mut listeners = LISTENERS.lock();
+    if !listeners.iter().any(|&g| core::ptr::fn_addr_eq(g, f)) { listeners.push(f); }
+}
+
+/// Re-evaluate the policy; called from the timer tick so thermal limits
+/// and plug/unplug events take effect.
+pub fn charger_tick() {
+    if let Some(mut c) = CHARGER.try_lock() {
+        c.apply();
+    }
+}
@@ -0,0 +1,167 @@
+//! SurakshaOS Kernel Command Line
+//! Boot options from `/chosen/bootargs` (QEMU's -append, or whatever the
+//! bootloader put there), or failing that the line built in with
+//! `SURAKSHA_CMDLINE` at compile time.  Words are separated by spaces and
+//! are either `key=value` or bare flags; a value may be double-quoted to
+//! hold spaces.  The kernel itself honours:
+//!
+//!   loglevel=<level>      log at this level and above, and print it: a
+//!                         syslog priority (0–7) or error/warn/info/debug
+//!   quiet                 print only warnings and errors on the console
+//!   debug                 log and print everything
+//!   log.<module>=<level>  that module's log level
+//!   console=<path|alias>  the console UART, in place of stdout-path
+//!   init=<path>           what init hands off to (default /bin/sursh)
+//!   nosmp                 leave the other harts parked
+//!   lockdown=<level>      a stricter lockdown for the end of boot
+//!
+//! Words the kernel does not know are kept, for `get` and `flag`, and
+//! shown in /proc/cmdline.  Parsing borrows the line and never allocates,
+//! so it runs before the heap.
+
+use spin::Mutex;
+
+use crate::klog::{self, Level};
+use crate::lockdown;
+
+/// What init hands off to unless `init=` says otherwise: the shell.
+pub const DEFAULT_INIT: &str = "/bin/sursh";
+
+/// The options the kernel acts on.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub loglevel: Option<Level>,
+    pub quiet:    bool,
+    pub debug:    bool,
+    pub console:  Option<&'static str>,
+    pub init:     &'static str,
+    pub nosmp:    bool,
+    pub lockdown: Option<lockdown::Level>,
+}
+
+impl Options {
+    const fn new() -> Self {
+        Options {
+            loglevel: None, quiet: false, debug: false, console: None, init: DEFAULT_INIT, nosmp: false,
+            lockdown: None,
+        }
+    }
+}
+
+static LINE:    Mutex<&'static str> = Mutex::new("");
+static OPTIONS: Mutex<Options>      = Mutex::new(Options::new());
+
+/// The command line as given.
+pub fn raw() -> &'static str {
+    *LINE.lock()
+}
+
+pub fn options() -> Options {
+    *OPTIONS.lock()
+}
+
+/// The value of the last `key=value` word for `key`.
+pub fn get(key: &str) -> Option<&'static str> {
+    words(raw()).filter(|&(k, _)| k == key).filter_map(|(_, v)| v).last()
+}
+
+/// Whether `name` appears as a bare flag.
+pub fn flag(name: &str) -> bool {
+    words(raw()).any(|w| w == (name, None))
+}
+
+/// (key, value) of each word in `line`, with any quotes around the value
+/// removed.
+pub fn words(line: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    let mut rest = line;
+    core::iter::from_fn(move || {
+        rest = rest.trim_start();
+        if rest.is_empty() { return None; }
+        let mut quoted = false;
+        let end = rest.char_indices()
+            .find(|&(_, c)| { if c == '"' { quoted = !quoted; } c.is_whitespace() && !quoted })
+            .map_or(rest.len(), |(i, _)| i);
+        let (word, tail) = rest.split_at(end);
+        rest = tail;
+        Some(match word.split_once('=') {
+            Some((k, v)) => (k, Some(v.strip_prefix('"').map_or(v, |v| v.strip_suffix('"').unwrap_or(v)))),
+            None         => (word, None),
+        })
+    })
+}
+
+/// `loglevel=` as a level: a syslog priority, or a level's name.
+fn parse_level(s: &str) -> Option<Level> {
+    match s.parse::<u8>() {
+        Ok(0..=3) => Some(Level::Error),
+        Ok(4 | 5) => Some(Level::Warn),
+        Ok(6)     => Some(Level::Info),
+        Ok(7)     => Some(Level::Debug),
+        Ok(_)     => None,
+        Err(_)    => Level::parse(s),
+    }
+}
+
+// ─── boot ─────────────────────────────────────────────────────────────────────
+
+/// Read the command line from the device tree (after `fdt::init`), and
+/// act on the options that must take effect before the heap: the log and
+/// console levels and the console device.
+pub fn init() {
+    let line = crate::fdt::get()
+        .and_then(|fdt| fdt.nodes().find(|n| n.depth == 1 && n.name == "chosen"))
+        .and_then(|chosen| chosen.property("bootargs"))
+        .and_then(|v| core::str::from_utf8(&v[..v.iter().position(|&b| b == 0).unwrap_or(v.len())]).ok())
+        .filter(|s| !s.trim().is_empty())
+        .or(option_env!("SURAKSHA_CMDLINE"))
+        .unwrap_or("");
+    *LINE.lock() = line;
+
+    let mut o = Options::new();
+    for (key, value) in words(line) {
+        match (key, value) {
+            ("loglevel", Some(v)) => match parse_level(v) {
+                Some(l) => o.loglevel = Some(l),
+                None    => crate::warn!("loglevel={}: expected 0-7 or error, warn, info or debug", v),
+            },
+            ("quiet", None)         => o.quiet = true,
+            ("debug", None)         => o.debug = true,
+            ("console", Some(v))    => o.console = Some(v),
+            ("init", Some(v))       => o.init = v,
+            ("nosmp", None)         => o.nosmp = true,
+            ("lockdown", Some(v))   => match lockdown::Level::parse(v) {
+                Some(l) if l < lockdown::BOOT_LEVEL => {
+                    crate::warn!("lockdown={}: boot always ends at {} or above", v, lockdown::BOOT_LEVEL.as_str());
+                }
+                Some(l) => o.lockdown = Some(l),
+                None    => crate::warn!("lockdown={}: expected integrity or confidentiality", v),
+            },
+            _ => {}
+        }
+    }
+    *OPTIONS.lock() = o;
+
+    let level = if o.debug { Some(Level::Debug) } else { o.loglevel };
+    if let Some(l) = level {
+        klog::set_level(None, l);
+        klog::set_console_level(l);
+    }
+    if o.quiet { klog::set_console_level(Level::Warn); }
+    if let Some(path) = o.console {
+        if let Err(e) = crate::fdt::set_console(path) {
+            crate::warn!("console={}: {}", path, e);
+        }
+    }
+}
+
+/// Apply the per-module log levels, which need the heap.
+pub fn init_late() {
+    for (key, value) in words(raw()) {
+        let (Some(module), Some(v)) = (key.strip_prefix("log."), value) else { continue };
+        match parse_level(v) {
+            Some(l) => klog::set_level(Some(module), l),
+            None    => crate::warn!("{}={}: expected 0-7 or error, warn, info or debug", key, v),
+        }
+    }
+    if !raw().is_empty() { crate::info!("command line: {}", raw()); }
+}
@@ -1,18 +1,71 @@
-/// SurakshaOS Console Driver
-/// Wraps the NS16550A UART for formatted, line-buffered I/O.
-/// Provides print!/println! macros and blocking read_line().
+//! SurakshaOS Console Driver
+//! Wraps the NS16550A UART (or, on AArch64, the PL011) for formatted,
+//! line-buffered I/O.  Provides print!/println! macros and blocking
+//! read_line().
 
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use spin::Mutex;
 
+use crate::capability::{Capability, Permissions};
+use crate::driver::{check_access, Device, Driver};
+
 // NS16550A register offsets (MMIO, 8-bit registers)
-const UART_BASE: usize = 0x1000_0000;
-const UART_RBR:  usize = UART_BASE + 0x00; // Receive Buffer Register  (read)
-const UART_THR:  usize = UART_BASE + 0x00; // Transmit Holding Register (write)
-const UART_LSR:  usize = UART_BASE + 0x05; // Line Status Register
+const UART_RBR:  usize = 0x00; // Receive Buffer Register  (read)
+const UART_THR:  usize = 0x00; // Transmit Holding Register (write)
+const UART_IER:  usize = 0x01; // Interrupt Enable Register
+const UART_LSR:  usize = 0x05; // Line Status Register
 const UART_LSR_DATA_READY: u8 = 0x01;
 const UART_LSR_TX_EMPTY:   u8 = 0x20;
 
+// PL011 register offsets (32-bit registers)
+const PL011_DR:   usize = 0x00; // Data Register
+const PL011_FR:   usize = 0x18; // Flag Register
+const PL011_IMSC: usize = 0x38; // Interrupt Mask Set/Clear
+const PL011_FR_RXFE: u32 = 1 << 4;
+const PL011_FR_TXFF: u32 = 1 << 5;
+const PL011_IMSC_RX: u32 = 1 << 4;
+
+/// Where the UART is, and whether it is a PL011: QEMU virt's, until the
+/// device tree names another.
+static UART_BASE: AtomicUsize = AtomicUsize::new(if cfg!(target_arch = "aarch64") { 0x0900_0000 } else { 0x1000_0000 });
+static PL011:     AtomicBool  = AtomicBool::new(cfg!(target_arch = "aarch64"));
+
+/// Address of UART register `off`.
+fn uart(off: usize) -> usize {
+    UART_BASE.load(Ordering::Relaxed) + off
+}
+
+/// Use the console UART the device tree names.
+pub fn configure(p: &crate::fdt::Platform) {
+    if let Some(u) = p.console {
+        UART_BASE.store(u.base, Ordering::Relaxed);
+        PL011.store(u.kind == crate::fdt::UartKind::Pl011, Ordering::Relaxed);
+    }
+}
+
+fn pl011() -> bool {
+    PL011.load(Ordering::Relaxed)
+}
+
+fn read_reg(off: usize) -> u32 {
+    unsafe {
+        if pl011() { core::ptr::read_volatile(uart(off) as *const u32) }
+        else { core::ptr::read_volatile(uart(off) as *const u8) as u32 }
+    }
+}
+
+fn write_reg(off: usize, v: u32) {
+    unsafe {
+        if pl011() { core::ptr::write_volatile(uart(off) as *mut u32, v) }
+        else { core::ptr::write_volatile(uart(off) as *mut u8, v as u8) }
+    }
+}
+
+fn tx_ready() -> bool {
+    if pl011() { read_reg(PL011_FR) & PL011_FR_TXFF == 0 } else { read_reg(UART_LSR) as u8 & UART_LSR_TX_EMPTY != 0 }
+}
+
 pub static CONSOLE: Mutex<Console> = Mutex::new(Console);
 
 pub struct Console;
@@ -21,22 +74,16 @@ impl Console {
     #[inline]
     fn write_byte(&self, byte: u8) {
         // Spin until the TX FIFO has room
-        loop {
-            let lsr = unsafe { core::ptr::read_volatile(UART_LSR as *const u8) };
-            if lsr & UART_LSR_TX_EMPTY != 0 { break; }
+        while !tx_ready() {
             core::hint::spin_loop();
         }
-        unsafe { core::ptr::write_volatile(UART_THR as *mut u8, byte); }
+        write_reg(if pl011() { PL011_DR } else { UART_THR }, byte as u32);
     }
 
     #[inline]
-    fn read_byte_blocking(&self) -> u8 {
-        loop {
-            let lsr = unsafe { core::ptr::read_volatile(UART_LSR as *const u8) };
-            if lsr & UART_LSR_DATA_READY != 0 { break; }
-            core::hint::spin_loop();
-        }
-        unsafe { core::ptr::read_volatile(UART_RBR as *const u8) }
+    fn try_read_byte(&self) -> Option<u8> {
+        if !rx_ready() { return None; }
+        Some(read_reg(if pl011() { PL011_DR } else { UART_RBR }) as u8)
     }
 
     pub fn write_str_raw(&self, s: &str) {
@@ -56,8 +103,35 @@ impl Write for Console {
 
 // ─── public API ──────────────────────────────────────────────────────────────
 
+/// Run `f` with the console held and interrupts masked, so a handler that
+/// prints (e.g. a thermal trip) cannot deadlock against the code it
+/// interrupted.
+pub fn with_console<R>(f: impl FnOnce(&mut Console) -> R) -> R {
+    let prev = crate::arch::interrupts_disable();
+    let r = f(&mut CONSOLE.lock());
+    crate::arch::interrupts_restore(prev);
+    r
+}
+
 pub fn print_str(s: &str) {
-    CONSOLE.lock().write_str_raw(s);
+    with_console(|c| c.write_str_raw(s));
+}
+
+/// Wait for one byte of input, idling the CPU until the UART interrupt
+/// fires.  The console is not held while waiting so output from interrupt
+/// handlers can still get through.
+fn read_byte() -> u8 {
+    set_rx_interrupt(true);
+    crate::arch::plic_enable(crate::arch::uart_irq(), true);
+    let b = loop {
+        if let Some(b) = with_console(|c| c.try_read_byte()) { break b; }
+        crate::cpuidle::idle(crate::arch::MIE_MEIE);
+    };
+    crate::arch::plic_claim_complete();
+    crate::arch::plic_enable(crate::arch::uart_irq(), false);
+    set_rx_interrupt(false);
+    crate::power::user_activity();
+    b
 }
 
 /// Read a line of input (blocking), with basic editing:
@@ -67,13 +141,11 @@ pub fn print_str(s: &str) {
 pub fn read_line() -> alloc::string::String {
     use alloc::string::String;
     let mut buf = String::new();
-    let console = CONSOLE.lock();
     loop {
-        let b = console.read_byte_blocking();
+        let b = read_byte();
         match b {
             b'\r' | b'\n' => {
                 // Echo newline and return
-                drop(console);
                 print_str("\n");
                 return buf;
             }
@@ -82,43 +154,104 @@ pub fn read_line() -> alloc::string::String {
                 if !buf.is_empty() {
                     buf.pop();
                     // Erase the character on screen: BS + space + BS
-                    console.write_str_raw("\x08 \x08");
+                    print_str("\x08 \x08");
                 }
             }
             0x03 => {
                 // Ctrl-C: clear line
                 buf.clear();
-                console.write_str_raw("^C\n");
+                print_str("^C\n");
                 return buf;
             }
             0x04 => {
                 // Ctrl-D: EOF / exit
-                drop(console);
                 print_str("\n");
                 return "exit".into();
             }
-            b if b >= 0x20 && b < 0x7F => {
+            b if (0x20..0x7F).contains(&b) => {
                 // Printable ASCII: echo and append
                 buf.push(b as char);
-                console.write_byte(b);
+                crate::print!("{}", b as char);
             }
             _ => { /* ignore other control bytes */ }
         }
     }
 }
 
+/// Enable/disable the "received data available" UART interrupt.
+pub fn set_rx_interrupt(enabled: bool) {
+    if pl011() { write_reg(PL011_IMSC, if enabled { PL011_IMSC_RX } else { 0 }); }
+    else { write_reg(UART_IER, enabled as u32); }
+}
+
+/// True if a received byte is waiting (does not consume it).
+pub fn rx_ready() -> bool {
+    if pl011() { read_reg(PL011_FR) & PL011_FR_RXFE == 0 } else { read_reg(UART_LSR) as u8 & UART_LSR_DATA_READY != 0 }
+}
+
 pub fn read_char() -> char {
-    let c = CONSOLE.lock().read_byte_blocking();
-    c as char
+    read_byte() as char
+}
+
+// ─── device driver ───────────────────────────────────────────────────────────
+
+/// ioctl: returns 1 if at least one received byte is waiting, else 0.
+pub const UART_IOCTL_RX_READY: u32 = 0x5501;
+
+/// Exposes the UART through the driver registry as `uart0`.
+/// Reads are non-blocking and return only the bytes already received.
+pub struct UartDriver;
+
+impl Driver for UartDriver {
+    fn name(&self) -> &'static str { if pl011() { "pl011" } else { "ns16550a" } }
+
+    fn read(&mut self, dev: &Device, cap: &Capability, buf: &mut [u8]) -> Result<usize, &'static str> {
+        check_access(dev, cap, Permissions::READ)?;
+        Ok(with_console(|console| {
+            let mut n = 0;
+            while n < buf.len() {
+                match console.try_read_byte() {
+                    Some(b) => { buf[n] = b; n += 1; }
+                    None    => break,
+                }
+            }
+            n
+        }))
+    }
+
+    fn write(&mut self, dev: &Device, cap: &Capability, buf: &[u8]) -> Result<usize, &'static str> {
+        check_access(dev, cap, Permissions::WRITE)?;
+        with_console(|console| for &b in buf { console.write_byte(b); });
+        Ok(buf.len())
+    }
+
+    fn ioctl(&mut self, dev: &Device, cap: &Capability, cmd: u32, _arg: usize) -> Result<usize, &'static str> {
+        check_access(dev, cap, self.ioctl_permissions(cmd))?;
+        match cmd {
+            UART_IOCTL_RX_READY => Ok(rx_ready() as usize),
+            _ => Err("unsupported ioctl"),
+        }
+    }
+
+    fn ioctl_permissions(&self, cmd: u32) -> Permissions {
+        match cmd {
+            UART_IOCTL_RX_READY => Permissions::READ,
+            _                   => Permissions::CONTROL,
+        }
+    }
 }
 
 // ─── fmt macros ──────────────────────────────────────────────────────────────
 
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    with_console(|c| { let _ = c.write_fmt(args); });
+}
+
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => {{
-        use core::fmt::Write;
-        let _ = write!($crate::console::CONSOLE.lock(), $($arg)*);
+        $crate::console::_print(format_args!($($arg)*));
     }};
 }
 
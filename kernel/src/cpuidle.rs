@@ -0,0 +1,147 @@
+//! SurakshaOS CPU Idle
+//! Platform idle states from shallow (plain wfi) to deep (power-gated).
+//! When there is nothing to run, the deepest state is chosen whose target
+//! residency fits before the next timer event and whose exit latency is
+//! within the current latency limit.  Time spent in each state is recorded.
+
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+
+/// Platform routine that enters an idle state and returns on wake-up.
+pub type EnterFn = fn();
+
+#[derive(Debug, Clone, Copy)]
+pub struct IdleState {
+    pub name:                &'static str,
+    pub desc:                &'static str,
+    /// Worst-case time to get back to running code.
+    pub exit_latency_us:     u32,
+    /// Minimum stay for the state to save energy overall.
+    pub target_residency_us: u32,
+}
+
+/// Supported idle states, shallowest first.
+pub const IDLE_STATES: &[IdleState] = &[
+    IdleState { name: "wfi",   desc: "core halted, clocks running", exit_latency_us:   1, target_residency_us:    1 },
+    IdleState { name: "clkgt", desc: "core clock gated",           exit_latency_us:  50, target_residency_us:  200 },
+    IdleState { name: "pwrgt", desc: "core power gated",           exit_latency_us: 500, target_residency_us: 3000 },
+];
+
+/// Per-state residency statistics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdleStats {
+    pub usage:     u64,
+    pub time_us:   u64,
+    /// Entries that woke before the target residency was reached.
+    pub too_short: u64,
+}
+
+struct CpuIdle {
+    /// Platform entry hooks; `None` falls back to `wfi`.  QEMU has no
+    /// power controller so every state is a plain `wfi` there.
+    enter: [Option<EnterFn>; IDLE_STATES.len()],
+    stats: [IdleStats; IDLE_STATES.len()],
+}
+
+static CPUIDLE: Mutex<CpuIdle> = Mutex::new(CpuIdle {
+    enter: [None; IDLE_STATES.len()],
+    stats: [IdleStats { usage: 0, time_us: 0, too_short: 0 }; IDLE_STATES.len()],
+});
+
+/// Idle periods ended by: timer, external interrupt, anything else.
+static WAKEUPS: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+
+/// Exit latency the system can currently tolerate (e.g. lowered during
+/// audio playback).
+static LATENCY_LIMIT_US: AtomicU32 = AtomicU32::new(u32::MAX);
+
+pub fn register_enter(state: usize, enter: EnterFn) -> Result<(), &'static str> {
+    let mut c = CPUIDLE.lock();
+    *c.enter.get_mut(state).ok_or("invalid idle state")? = Some(enter);
+    Ok(())
+}
+
+pub fn set_latency_limit_us(limit: u32) {
+    LATENCY_LIMIT_US.store(limit, Ordering::Relaxed);
+}
+
+pub fn latency_limit_us() -> u32 {
+    LATENCY_LIMIT_US.load(Ordering::Relaxed)
+}
+
+/// Deepest state whose residency fits in `predicted_us` and whose exit
+/// latency is within `limit_us`.
+pub fn select(predicted_us: u64, limit_us: u32) -> usize {
+    IDLE_STATES.iter()
+        .rposition(|s| s.target_residency_us as u64 <= predicted_us && s.exit_latency_us <= limit_us)
+        .unwrap_or(0)
+}
+
+/// Idle the CPU until an interrupt arrives.  `wake_mie` adds interrupt
+/// sources (mie bits) that should end the idle period besides the timer;
+/// they are only enabled while idle, so the caller must check for and
+/// acknowledge the event itself after returning.
+pub fn idle(wake_mie: usize) {
+    // Nothing to run means deferred work gets its turn first, then any
+    // memory reclaim the heap needs
+    crate::process::run_deferred();
+    crate::memory::reclaim();
+    enter_idle(wake_mie);
+}
+
+fn enter_idle(wake_mie: usize) {
+    use crate::arch;
+
+    let mstatus = arch::interrupts_disable();
+    let saved   = arch::read_mie();
+    arch::write_mie(saved | wake_mie);
+
+    let now       = arch::read_mtime();
+    let predicted = arch::ticks_to_us(arch::read_timer_compare().saturating_sub(now));
+    let state     = select(predicted, latency_limit_us());
+    let enter     = CPUIDLE.lock().enter[state].unwrap_or(arch::wait_for_interrupt);
+
+    crate::process::idle_enter();
+    enter();
+    crate::process::idle_exit();
+
+    let pending = arch::read_mip();
+    let source  = if pending & arch::MIE_MTIE != 0 { 0 } else if pending & arch::MIE_MEIE != 0 { 1 } else { 2 };
+    WAKEUPS[source].fetch_add(1, Ordering::Relaxed);
+
+    let slept = arch::ticks_to_us(arch::read_mtime().saturating_sub(now));
+    {
+        let mut c = CPUIDLE.lock();
+        let st = &mut c.stats[state];
+        st.usage   += 1;
+        st.time_us += slept;
+        if slept < IDLE_STATES[state].target_residency_us as u64 { st.too_short += 1; }
+    }
+
+    // Pending interrupts (e.g. the tick that woke us) are taken here
+    arch::write_mie(saved);
+    arch::interrupts_restore(mstatus);
+}
+
+/// Idle until mtime reaches `deadline`, pulling the next timer interrupt
+/// in if it would come later.  The caller may hold locks, so deferred
+/// work and reclaim wait for a real idle period.
+pub fn sleep_until(deadline: u64) {
+    use crate::arch;
+
+    while arch::read_mtime() < deadline {
+        let mstatus = arch::interrupts_disable();
+        if arch::read_timer_compare() > deadline { arch::set_timer_compare(deadline); }
+        arch::interrupts_restore(mstatus);
+        enter_idle(0);
+    }
+}
+
+pub fn stats() -> [IdleStats; IDLE_STATES.len()] {
+    CPUIDLE.lock().stats
+}
+
+/// Idle wake-ups by source: [timer, external interrupt, other].
+pub fn wakeups() -> [u64; 3] {
+    WAKEUPS.each_ref().map(|w| w.load(Ordering::Relaxed))
+}
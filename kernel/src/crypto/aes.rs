@@ -0,0 +1,258 @@
+//! AES-128, AES-128-GCM, CMAC and Key Wrap (FIPS 197, SP 800-38D,
+//! RFC 4493, RFC 3394)
+//! The cipher QUIC fixes for its Initial packets and their header
+//! protection.  A plain table implementation: the handful of packets it
+//! protects carry nothing secret from an on-path observer, which can
+//! derive their keys anyway.  The Wi-Fi key handshake also uses it, for
+//! EAPOL-Key MICs and to unwrap the group keys the access point sends;
+//! that is a few blocks per association.
+
+use alloc::vec::Vec;
+
+pub const KEY_LEN:   usize = 16;
+pub const BLOCK_LEN: usize = 16;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN:   usize = 16;
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7C, 0x77, 0x7B, 0xF2, 0x6B, 0x6F, 0xC5, 0x30, 0x01, 0x67, 0x2B, 0xFE, 0xD7, 0xAB, 0x76,
+    0xCA, 0x82, 0xC9, 0x7D, 0xFA, 0x59, 0x47, 0xF0, 0xAD, 0xD4, 0xA2, 0xAF, 0x9C, 0xA4, 0x72, 0xC0,
+    0xB7, 0xFD, 0x93, 0x26, 0x36, 0x3F, 0xF7, 0xCC, 0x34, 0xA5, 0xE5, 0xF1, 0x71, 0xD8, 0x31, 0x15,
+    0x04, 0xC7, 0x23, 0xC3, 0x18, 0x96, 0x05, 0x9A, 0x07, 0x12, 0x80, 0xE2, 0xEB, 0x27, 0xB2, 0x75,
+    0x09, 0x83, 0x2C, 0x1A, 0x1B, 0x6E, 0x5A, 0xA0, 0x52, 0x3B, 0xD6, 0xB3, 0x29, 0xE3, 0x2F, 0x84,
+    0x53, 0xD1, 0x00, 0xED, 0x20, 0xFC, 0xB1, 0x5B, 0x6A, 0xCB, 0xBE, 0x39, 0x4A, 0x4C, 0x58, 0xCF,
+    0xD0, 0xEF, 0xAA, 0xFB, 0x43, 0x4D, 0x33, 0x85, 0x45, 0xF9, 0x02, 0x7F, 0x50, 0x3C, 0x9F, 0xA8,
+    0x51, 0xA3, 0x40, 0x8F, 0x92, 0x9D, 0x38, 0xF5, 0xBC, 0xB6, 0xDA, 0x21, 0x10, 0xFF, 0xF3, 0xD2,
+    0xCD, 0x0C, 0x13, 0xEC, 0x5F, 0x97, 0x44, 0x17, 0xC4, 0xA7, 0x7E, 0x3D, 0x64, 0x5D, 0x19, 0x73,
+    0x60, 0x81, 0x4F, 0xDC, 0x22, 0x2A, 0x90, 0x88, 0x46, 0xEE, 0xB8, 0x14, 0xDE, 0x5E, 0x0B, 0xDB,
+    0xE0, 0x32, 0x3A, 0x0A, 0x49, 0x06, 0x24, 0x5C, 0xC2, 0xD3, 0xAC, 0x62, 0x91, 0x95, 0xE4, 0x79,
+    0xE7, 0xC8, 0x37, 0x6D, 0x8D, 0xD5, 0x4E, 0xA9, 0x6C, 0x56, 0xF4, 0xEA, 0x65, 0x7A, 0xAE, 0x08,
+    0xBA, 0x78, 0x25, 0x2E, 0x1C, 0xA6, 0xB4, 0xC6, 0xE8, 0xDD, 0x74, 0x1F, 0x4B, 0xBD, 0x8B, 0x8A,
+    0x70, 0x3E, 0xB5, 0x66, 0x48, 0x03, 0xF6, 0x0E, 0x61, 0x35, 0x57, 0xB9, 0x86, 0xC1, 0x1D, 0x9E,
+    0xE1, 0xF8, 0x98, 0x11, 0x69, 0xD9, 0x8E, 0x94, 0x9B, 0x1E, 0x87, 0xE9, 0xCE, 0x55, 0x28, 0xDF,
+    0x8C, 0xA1, 0x89, 0x0D, 0xBF, 0xE6, 0x42, 0x68, 0x41, 0x99, 0x2D, 0x0F, 0xB0, 0x54, 0xBB, 0x16,
+];
+
+/// The inverse of `SBOX`.
+const INV_SBOX: [u8; 256] = {
+    let mut inv = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        inv[SBOX[i] as usize] = i as u8;
+        i += 1;
+    }
+    inv
+};
+
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1B, 0x36];
+
+/// An expanded AES-128 key.
+#[derive(Clone)]
+pub struct Aes128 {
+    round_keys: [[u8; 16]; 11],
+}
+
+fn xtime(b: u8) -> u8 {
+    (b << 1) ^ if b & 0x80 != 0 { 0x1B } else { 0 }
+}
+
+/// Multiply in GF(2^8).
+fn gmul(mut a: u8, mut b: u8) -> u8 {
+    let mut p = 0;
+    while b != 0 {
+        if b & 1 != 0 { p ^= a; }
+        a = xtime(a);
+        b >>= 1;
+    }
+    p
+}
+
+impl Aes128 {
+    pub fn new(key: &[u8; KEY_LEN]) -> Aes128 {
+        let mut w = [[0u8; 4]; 44];
+        for (i, word) in w[..4].iter_mut().enumerate() { word.copy_from_slice(&key[4 * i..4 * i + 4]); }
+        for i in 4..44 {
+            let mut t = w[i - 1];
+            if i % 4 == 0 {
+                t = [SBOX[t[1] as usize] ^ RCON[i / 4 - 1], SBOX[t[2] as usize], SBOX[t[3] as usize], SBOX[t[0] as usize]];
+            }
+            for j in 0..4 { w[i][j] = w[i - 4][j] ^ t[j]; }
+        }
+        let mut round_keys = [[0u8; 16]; 11];
+        for (r, rk) in round_keys.iter_mut().enumerate() {
+            for c in 0..4 { rk[4 * c..4 * c + 4].copy_from_slice(&w[4 * r + c]); }
+        }
+        Aes128 { round_keys }
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; BLOCK_LEN]) {
+        let add = |s: &mut [u8; 16], k: &[u8; 16]| for (b, k) in s.iter_mut().zip(k) { *b ^= k; };
+        add(block, &self.round_keys[0]);
+        for (round, key) in self.round_keys.iter().enumerate().skip(1) {
+            for b in block.iter_mut() { *b = SBOX[*b as usize]; }
+            // ShiftRows: row r of the column-major state moves left by r
+            let s = *block;
+            for c in 0..4 {
+                for r in 0..4 { block[4 * c + r] = s[4 * ((c + r) % 4) + r]; }
+            }
+            if round < 10 {
+                for col in block.chunks_mut(4) {
+                    let (a0, a1, a2, a3) = (col[0], col[1], col[2], col[3]);
+                    let all = a0 ^ a1 ^ a2 ^ a3;
+                    col[0] ^= all ^ xtime(a0 ^ a1);
+                    col[1] ^= all ^ xtime(a1 ^ a2);
+                    col[2] ^= all ^ xtime(a2 ^ a3);
+                    col[3] ^= all ^ xtime(a3 ^ a0);
+                }
+            }
+            add(block, key);
+        }
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8; BLOCK_LEN]) {
+        let add = |s: &mut [u8; 16], k: &[u8; 16]| for (b, k) in s.iter_mut().zip(k) { *b ^= k; };
+        add(block, &self.round_keys[10]);
+        for round in (0..10).rev() {
+            // InvShiftRows: row r moves right by r
+            let s = *block;
+            for c in 0..4 {
+                for r in 0..4 { block[4 * ((c + r) % 4) + r] = s[4 * c + r]; }
+            }
+            for b in block.iter_mut() { *b = INV_SBOX[*b as usize]; }
+            add(block, &self.round_keys[round]);
+            if round > 0 {
+                for col in block.chunks_mut(4) {
+                    let a = [col[0], col[1], col[2], col[3]];
+                    for r in 0..4 {
+                        col[r] = gmul(a[r], 14) ^ gmul(a[(r + 1) % 4], 11) ^ gmul(a[(r + 2) % 4], 13) ^ gmul(a[(r + 3) % 4], 9);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// ─── GCM ──────────────────────────────────────────────────────────────────────
+
+/// Multiply in GF(2^128) with GCM's bit order.
+fn gf_mul(x: u128, y: u128) -> u128 {
+    let mut z = 0u128;
+    let mut v = y;
+    for i in 0..128 {
+        if x >> (127 - i) & 1 == 1 { z ^= v; }
+        v = if v & 1 == 1 { (v >> 1) ^ (0xE1 << 120) } else { v >> 1 };
+    }
+    z
+}
+
+fn ghash(h: u128, aad: &[u8], ct: &[u8]) -> u128 {
+    let mut y = 0u128;
+    for data in [aad, ct] {
+        for chunk in data.chunks(16) {
+            let mut b = [0u8; 16];
+            b[..chunk.len()].copy_from_slice(chunk);
+            y = gf_mul(y ^ u128::from_be_bytes(b), h);
+        }
+    }
+    let lens = ((aad.len() as u128 * 8) << 64) | (ct.len() as u128 * 8);
+    gf_mul(y ^ lens, h)
+}
+
+/// CTR mode from counter block `J0 + 1`.
+fn ctr(aes: &Aes128, nonce: &[u8; NONCE_LEN], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(16).enumerate() {
+        let mut block = [0u8; 16];
+        block[..12].copy_from_slice(nonce);
+        block[12..].copy_from_slice(&(i as u32 + 2).to_be_bytes());
+        aes.encrypt_block(&mut block);
+        for (d, k) in chunk.iter_mut().zip(block) { *d ^= k; }
+    }
+}
+
+fn tag(aes: &Aes128, nonce: &[u8; NONCE_LEN], aad: &[u8], ct: &[u8]) -> [u8; TAG_LEN] {
+    let mut h = [0u8; 16];
+    aes.encrypt_block(&mut h);
+    let mut j0 = [0u8; 16];
+    j0[..12].copy_from_slice(nonce);
+    j0[15] = 1;
+    aes.encrypt_block(&mut j0);
+    (ghash(u128::from_be_bytes(h), aad, ct) ^ u128::from_be_bytes(j0)).to_be_bytes()
+}
+
+/// Encrypt `plaintext`, returning ciphertext followed by the tag.
+pub fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let aes = Aes128::new(key);
+    let mut out = plaintext.to_vec();
+    ctr(&aes, nonce, &mut out);
+    let t = tag(&aes, nonce, aad, &out);
+    out.extend_from_slice(&t);
+    out
+}
+
+/// Check and decrypt `sealed` (ciphertext then tag).
+pub fn open(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if sealed.len() < TAG_LEN { return Err("ciphertext too short"); }
+    let (ct, t) = sealed.split_at(sealed.len() - TAG_LEN);
+    let aes = Aes128::new(key);
+    let expect = tag(&aes, nonce, aad, ct);
+    if expect.iter().zip(t).fold(0u8, |d, (a, b)| d | (a ^ b)) != 0 { return Err("authentication failed"); }
+    let mut out = ct.to_vec();
+    ctr(&aes, nonce, &mut out);
+    Ok(out)
+}
+
+// ─── CMAC and key wrap ────────────────────────────────────────────────────────
+
+/// AES-CMAC over the concatenation of `parts`.
+pub fn cmac(key: &[u8; KEY_LEN], parts: &[&[u8]]) -> [u8; TAG_LEN] {
+    let aes = Aes128::new(key);
+    let encrypt = |x: u128| {
+        let mut b = x.to_be_bytes();
+        aes.encrypt_block(&mut b);
+        u128::from_be_bytes(b)
+    };
+    let dbl = |x: u128| (x << 1) ^ if x >> 127 == 1 { 0x87 } else { 0 };
+    let k1 = dbl(encrypt(0));
+    let k2 = dbl(k1);
+
+    let data = parts.concat();
+    let blocks = data.len().div_ceil(16).max(1);
+    let mut x = 0u128;
+    for chunk in data.chunks(16).take(blocks - 1) {
+        x = encrypt(x ^ u128::from_be_bytes(chunk.try_into().unwrap_or([0; 16])));
+    }
+    let tail = &data[16 * (blocks - 1)..];
+    let last = if tail.len() == 16 {
+        u128::from_be_bytes(tail.try_into().unwrap_or([0; 16])) ^ k1
+    } else {
+        let mut b = [0u8; 16];
+        b[..tail.len()].copy_from_slice(tail);
+        b[tail.len()] = 0x80;
+        u128::from_be_bytes(b) ^ k2
+    };
+    encrypt(x ^ last).to_be_bytes()
+}
+
+/// Unwrap key data `wrapped` under `kek`, checking its integrity value.
+pub fn unwrap_key(kek: &[u8; KEY_LEN], wrapped: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if wrapped.len() < 24 || wrapped.len() % 8 != 0 { return Err("bad wrapped key length"); }
+    let aes = Aes128::new(kek);
+    let n = wrapped.len() / 8 - 1;
+    let mut a = u64::from_be_bytes(wrapped[..8].try_into().unwrap_or([0; 8]));
+    let mut r = wrapped[8..].to_vec();
+    for j in (0..6).rev() {
+        for i in (0..n).rev() {
+            let mut b = [0u8; 16];
+            b[..8].copy_from_slice(&(a ^ (n * j + i + 1) as u64).to_be_bytes());
+            b[8..].copy_from_slice(&r[8 * i..8 * i + 8]);
+            aes.decrypt_block(&mut b);
+            a = u64::from_be_bytes(b[..8].try_into().unwrap_or([0; 8]));
+            r[8 * i..8 * i + 8].copy_from_slice(&b[8..]);
+        }
+    }
+    if a != 0xA6A6_A6A6_A6A6_A6A6 {
+        super::wipe(&mut r);
+        return Err("key unwrap failed");
+    }
+    Ok(r)
+}
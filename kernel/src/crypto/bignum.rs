@@ -0,0 +1,188 @@
+//! Modular Arithmetic for Public-Key Verification
+//! Unsigned integers as little-endian 64-bit limbs, with Montgomery
+//! multiplication for RSA exponentiation and the field and scalar
+//! arithmetic of ECDSA.  Only public values (keys, signatures, digests)
+//! pass through here, so none of it is constant time.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+pub type Limbs = Vec<u64>;
+
+/// Big-endian `bytes` as `limbs` limbs, or None if it does not fit.
+pub fn from_be(bytes: &[u8], limbs: usize) -> Option<Limbs> {
+    let mut out = vec![0u64; limbs];
+    for (i, &b) in bytes.iter().rev().enumerate() {
+        if b == 0 { continue; }
+        *out.get_mut(i / 8)? |= (b as u64) << (8 * (i % 8));
+    }
+    Some(out)
+}
+
+/// `a` as `len` big-endian bytes, dropping anything above.
+pub fn to_be(a: &[u64], len: usize) -> Vec<u8> {
+    (0..len).rev().map(|i| a.get(i / 8).map_or(0, |l| (l >> (8 * (i % 8))) as u8)).collect()
+}
+
+pub fn is_zero(a: &[u64]) -> bool {
+    a.iter().all(|&l| l == 0)
+}
+
+/// Compare two numbers of the same length.
+pub fn cmp(a: &[u64], b: &[u64]) -> Ordering {
+    a.iter().rev().cmp(b.iter().rev())
+}
+
+/// The number of significant bits.
+pub fn bits(a: &[u64]) -> usize {
+    a.iter().rposition(|&l| l != 0).map_or(0, |i| 64 * i + 64 - a[i].leading_zeros() as usize)
+}
+
+fn add_in(a: &mut [u64], b: &[u64]) -> bool {
+    let mut carry = false;
+    for (x, &y) in a.iter_mut().zip(b) {
+        let (s, c1) = x.overflowing_add(y);
+        let (s, c2) = s.overflowing_add(carry as u64);
+        *x = s;
+        carry = c1 || c2;
+    }
+    carry
+}
+
+fn sub_in(a: &mut [u64], b: &[u64]) -> bool {
+    let mut borrow = false;
+    for (x, &y) in a.iter_mut().zip(b) {
+        let (d, b1) = x.overflowing_sub(y);
+        let (d, b2) = d.overflowing_sub(borrow as u64);
+        *x = d;
+        borrow = b1 || b2;
+    }
+    borrow
+}
+
+// ─── Montgomery arithmetic ────────────────────────────────────────────────────
+
+/// An odd modulus m with R = 2^(64·limbs).  Values "in Montgomery form"
+/// are stored as x·R mod m.
+pub struct Modulus {
+    m:  Limbs,
+    n0: u64,   // -m⁻¹ mod 2^64
+    r2: Limbs, // R² mod m
+}
+
+impl Modulus {
+    pub fn new(m: Limbs) -> Result<Modulus, &'static str> {
+        if m.first().is_none_or(|&l| l & 1 == 0) || bits(&m) < 2 { return Err("modulus must be odd"); }
+        let mut inv = 1u64;
+        for _ in 0..6 { inv = inv.wrapping_mul(2u64.wrapping_sub(m[0].wrapping_mul(inv))); }
+        // R² mod m by doubling 1 modulo m, 2·64·limbs times
+        let mut r2 = vec![0u64; m.len()];
+        r2[0] = 1;
+        let mut modulus = Modulus { n0: inv.wrapping_neg(), r2: Vec::new(), m };
+        for _ in 0..128 * r2.len() {
+            let copy = r2.clone();
+            r2 = modulus.add(&r2, &copy);
+        }
+        modulus.r2 = r2;
+        Ok(modulus)
+    }
+
+    pub fn limbs(&self) -> usize {
+        self.m.len()
+    }
+
+    pub fn value(&self) -> &[u64] {
+        &self.m
+    }
+
+    /// a·b·R⁻¹ mod m, for a, b < m.
+    pub fn mul(&self, a: &[u64], b: &[u64]) -> Limbs {
+        let k = self.m.len();
+        let mut t = vec![0u64; k + 2];
+        for &bi in b {
+            let mut c = 0u128;
+            for j in 0..k {
+                let v = t[j] as u128 + a[j] as u128 * bi as u128 + c;
+                t[j] = v as u64;
+                c = v >> 64;
+            }
+            let v = t[k] as u128 + c;
+            t[k] = v as u64;
+            t[k + 1] = (v >> 64) as u64;
+
+            let u = t[0].wrapping_mul(self.n0);
+            let mut c = (t[0] as u128 + u as u128 * self.m[0] as u128) >> 64;
+            for j in 1..k {
+                let v = t[j] as u128 + u as u128 * self.m[j] as u128 + c;
+                t[j - 1] = v as u64;
+                c = v >> 64;
+            }
+            let v = t[k] as u128 + c;
+            t[k - 1] = v as u64;
+            t[k] = t[k + 1] + (v >> 64) as u64;
+            t[k + 1] = 0;
+        }
+        let high = t[k] != 0;
+        t.truncate(k);
+        if high || cmp(&t, &self.m) != Ordering::Less { sub_in(&mut t, &self.m); }
+        t
+    }
+
+    pub fn to_mont(&self, a: &[u64]) -> Limbs {
+        self.mul(a, &self.r2)
+    }
+
+    pub fn from_mont(&self, a: &[u64]) -> Limbs {
+        let mut one = vec![0u64; self.m.len()];
+        one[0] = 1;
+        self.mul(a, &one)
+    }
+
+    /// 1 in Montgomery form.
+    pub fn one(&self) -> Limbs {
+        let mut one = vec![0u64; self.m.len()];
+        one[0] = 1;
+        self.to_mont(&one)
+    }
+
+    pub fn add(&self, a: &[u64], b: &[u64]) -> Limbs {
+        let mut s = a.to_vec();
+        let carry = add_in(&mut s, b);
+        if carry || cmp(&s, &self.m) != Ordering::Less { sub_in(&mut s, &self.m); }
+        s
+    }
+
+    pub fn sub(&self, a: &[u64], b: &[u64]) -> Limbs {
+        let mut d = a.to_vec();
+        if sub_in(&mut d, b) { add_in(&mut d, &self.m); }
+        d
+    }
+
+    /// a mod m for a < 2m, as when reducing a field element modulo a
+    /// curve's slightly smaller group order.
+    pub fn reduce_once(&self, a: &[u64]) -> Limbs {
+        let mut r = a.to_vec();
+        if cmp(&r, &self.m) != Ordering::Less { sub_in(&mut r, &self.m); }
+        r
+    }
+
+    /// base^exp with `base` and the result in Montgomery form.
+    pub fn pow(&self, base: &[u64], exp: &[u64]) -> Limbs {
+        let mut r = self.one();
+        for i in (0..bits(exp)).rev() {
+            r = self.mul(&r, &r);
+            if (exp[i / 64] >> (i % 64)) & 1 == 1 { r = self.mul(&r, base); }
+        }
+        r
+    }
+
+    /// a⁻¹ (Montgomery form in and out) by Fermat, for prime m.
+    pub fn invert(&self, a: &[u64]) -> Limbs {
+        let mut two = vec![0u64; self.m.len()];
+        two[0] = 2;
+        let mut e = self.m.clone();
+        sub_in(&mut e, &two);
+        self.pow(a, &e)
+    }
+}
@@ -0,0 +1,145 @@
+//! BLAKE2s (RFC 7693)
+//! The 32-bit BLAKE2 variant, incremental, with an optional key and any
+//! digest length up to 32 bytes, plus HMAC over it as WireGuard's key
+//! derivation uses it.
+
+const IV: [u32; 8] = [
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+];
+
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+pub const BLOCK_LEN: usize = 64;
+pub const HASH_LEN:  usize = 32;
+
+#[derive(Clone)]
+pub struct Blake2s {
+    h:       [u32; 8],
+    buf:     [u8; BLOCK_LEN],
+    pos:     usize,
+    /// Bytes compressed so far.
+    len:     u64,
+    out_len: usize,
+}
+
+impl Default for Blake2s {
+    fn default() -> Self {
+        Blake2s::new(HASH_LEN, &[])
+    }
+}
+
+impl Blake2s {
+    /// A hash of `out_len` (1..=32) bytes, keyed if `key` (at most 32
+    /// bytes) is not empty.
+    pub fn new(out_len: usize, key: &[u8]) -> Blake2s {
+        debug_assert!((1..=HASH_LEN).contains(&out_len) && key.len() <= HASH_LEN);
+        let mut h = IV;
+        h[0] ^= 0x0101_0000 ^ (key.len() as u32) << 8 ^ out_len as u32;
+        let mut s = Blake2s { h, buf: [0; BLOCK_LEN], pos: 0, len: 0, out_len };
+        if !key.is_empty() {
+            // The key is the first block, padded with zeros
+            s.buf[..key.len()].copy_from_slice(key);
+            s.pos = BLOCK_LEN;
+        }
+        s
+    }
+
+    fn compress(&mut self, last: bool) {
+        let mut m = [0u32; 16];
+        for (w, c) in m.iter_mut().zip(self.buf.as_chunks::<4>().0) { *w = u32::from_le_bytes(*c); }
+        let mut v = [0u32; 16];
+        v[..8].copy_from_slice(&self.h);
+        v[8..].copy_from_slice(&IV);
+        v[12] ^= self.len as u32;
+        v[13] ^= (self.len >> 32) as u32;
+        if last { v[14] = !v[14]; }
+        for s in SIGMA.iter() {
+            g(&mut v, 0, 4, 8, 12, m[s[0]], m[s[1]]);
+            g(&mut v, 1, 5, 9, 13, m[s[2]], m[s[3]]);
+            g(&mut v, 2, 6, 10, 14, m[s[4]], m[s[5]]);
+            g(&mut v, 3, 7, 11, 15, m[s[6]], m[s[7]]);
+            g(&mut v, 0, 5, 10, 15, m[s[8]], m[s[9]]);
+            g(&mut v, 1, 6, 11, 12, m[s[10]], m[s[11]]);
+            g(&mut v, 2, 7, 8, 13, m[s[12]], m[s[13]]);
+            g(&mut v, 3, 4, 9, 14, m[s[14]], m[s[15]]);
+        }
+        for i in 0..8 { self.h[i] ^= v[i] ^ v[i + 8]; }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            // The final block is compressed differently, so a full buffer
+            // waits until more data shows it is not the last
+            if self.pos == BLOCK_LEN {
+                self.len += BLOCK_LEN as u64;
+                self.compress(false);
+                self.pos = 0;
+            }
+            let n = (BLOCK_LEN - self.pos).min(data.len());
+            self.buf[self.pos..self.pos + n].copy_from_slice(&data[..n]);
+            self.pos += n;
+            data = &data[n..];
+        }
+    }
+
+    /// The digest, in the first `out_len` bytes.
+    pub fn finish(mut self) -> [u8; HASH_LEN] {
+        self.len += self.pos as u64;
+        self.buf[self.pos..].fill(0);
+        self.compress(true);
+        let mut out = [0u8; HASH_LEN];
+        for (c, w) in out.as_chunks_mut::<4>().0.iter_mut().zip(self.h) { *c = w.to_le_bytes(); }
+        out[self.out_len..].fill(0);
+        out
+    }
+}
+
+fn g(v: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(12);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(8);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(7);
+}
+
+/// BLAKE2s-256 of the concatenation of `parts`.
+pub fn hash(parts: &[&[u8]]) -> [u8; HASH_LEN] {
+    let mut h = Blake2s::default();
+    for p in parts { h.update(p); }
+    h.finish()
+}
+
+/// Keyed BLAKE2s with a 16-byte digest.
+pub fn mac(key: &[u8], data: &[u8]) -> [u8; 16] {
+    let mut h = Blake2s::new(16, key);
+    h.update(data);
+    let mut out = [0u8; 16];
+    out.copy_from_slice(&h.finish()[..16]);
+    out
+}
+
+pub fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; HASH_LEN] {
+    let mut k = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN { k[..HASH_LEN].copy_from_slice(&hash(&[key])); } else { k[..key.len()].copy_from_slice(key); }
+    let mut inner = Blake2s::default();
+    inner.update(&k.map(|b| b ^ 0x36));
+    for p in parts { inner.update(p); }
+    let mut outer = Blake2s::default();
+    outer.update(&k.map(|b| b ^ 0x5C));
+    outer.update(&inner.finish());
+    outer.finish()
+}
@@ -0,0 +1,154 @@
+//! ChaCha20-Poly1305 (RFC 8439)
+//! The AEAD used by TLS 1.3 and WireGuard-style tunnels.  The tag is
+//! compared in constant time before anything is decrypted.
+
+use alloc::vec::Vec;
+
+pub const KEY_LEN:   usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const TAG_LEN:   usize = 16;
+
+fn quarter(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]); s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]); s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]); s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]); s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+fn block(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN]) -> [u8; 64] {
+    let mut s = [0u32; 16];
+    s[..4].copy_from_slice(&[0x61707865, 0x3320646E, 0x79622D32, 0x6B206574]);
+    for (w, c) in s[4..12].iter_mut().zip(key.as_chunks::<4>().0) { *w = u32::from_le_bytes(*c); }
+    s[12] = counter;
+    for (w, c) in s[13..].iter_mut().zip(nonce.as_chunks::<4>().0) { *w = u32::from_le_bytes(*c); }
+    let init = s;
+    for _ in 0..10 {
+        quarter(&mut s, 0, 4, 8, 12);
+        quarter(&mut s, 1, 5, 9, 13);
+        quarter(&mut s, 2, 6, 10, 14);
+        quarter(&mut s, 3, 7, 11, 15);
+        quarter(&mut s, 0, 5, 10, 15);
+        quarter(&mut s, 1, 6, 11, 12);
+        quarter(&mut s, 2, 7, 8, 13);
+        quarter(&mut s, 3, 4, 9, 14);
+    }
+    let mut out = [0u8; 64];
+    for (i, o) in out.as_chunks_mut::<4>().0.iter_mut().enumerate() { *o = s[i].wrapping_add(init[i]).to_le_bytes(); }
+    out
+}
+
+/// XOR `data` with the keystream starting at block `counter`.
+pub fn chacha20(key: &[u8; KEY_LEN], counter: u32, nonce: &[u8; NONCE_LEN], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let ks = block(key, counter.wrapping_add(i as u32), nonce);
+        for (d, k) in chunk.iter_mut().zip(ks) { *d ^= k; }
+    }
+}
+
+/// Poly1305 over the AEAD construction's padded AAD and ciphertext.
+fn poly1305(key: &[u8; 32], aad: &[u8], ct: &[u8]) -> [u8; TAG_LEN] {
+    let le = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    // r clamped, in 26-bit limbs
+    let r = [
+        le(&key[0..]) & 0x3FFFFFF,
+        (le(&key[3..]) >> 2) & 0x3FFFF03,
+        (le(&key[6..]) >> 4) & 0x3FFC0FF,
+        (le(&key[9..]) >> 6) & 0x3F03FFF,
+        (le(&key[12..]) >> 8) & 0x00FFFFF,
+    ];
+    let s = [r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+    let mut h = [0u32; 5];
+    let mut absorb = |m: &[u8; 16], hibit: u32| {
+        h[0] += le(&m[0..]) & 0x3FFFFFF;
+        h[1] += (le(&m[3..]) >> 2) & 0x3FFFFFF;
+        h[2] += (le(&m[6..]) >> 4) & 0x3FFFFFF;
+        h[3] += (le(&m[9..]) >> 6) & 0x3FFFFFF;
+        h[4] += (le(&m[12..]) >> 8) | hibit;
+        let m = |a: u32, b: u32| a as u64 * b as u64;
+        let d0 = m(h[0], r[0]) + m(h[1], s[3]) + m(h[2], s[2]) + m(h[3], s[1]) + m(h[4], s[0]);
+        let mut d1 = m(h[0], r[1]) + m(h[1], r[0]) + m(h[2], s[3]) + m(h[3], s[2]) + m(h[4], s[1]);
+        let mut d2 = m(h[0], r[2]) + m(h[1], r[1]) + m(h[2], r[0]) + m(h[3], s[3]) + m(h[4], s[2]);
+        let mut d3 = m(h[0], r[3]) + m(h[1], r[2]) + m(h[2], r[1]) + m(h[3], r[0]) + m(h[4], s[3]);
+        let mut d4 = m(h[0], r[4]) + m(h[1], r[3]) + m(h[2], r[2]) + m(h[3], r[1]) + m(h[4], r[0]);
+        let mut c;
+        c = d0 >> 26; h[0] = d0 as u32 & 0x3FFFFFF;
+        d1 += c; c = d1 >> 26; h[1] = d1 as u32 & 0x3FFFFFF;
+        d2 += c; c = d2 >> 26; h[2] = d2 as u32 & 0x3FFFFFF;
+        d3 += c; c = d3 >> 26; h[3] = d3 as u32 & 0x3FFFFFF;
+        d4 += c; c = d4 >> 26; h[4] = d4 as u32 & 0x3FFFFFF;
+        h[0] += c as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= 0x3FFFFFF;
+    };
+    let mut feed = |data: &[u8]| {
+        let (blocks, rest) = data.as_chunks::<16>();
+        for b in blocks { absorb(b, 1 << 24); }
+        if !rest.is_empty() {
+            // Padded with zeros to a whole block, as the AEAD construction does
+            let mut b = [0u8; 16];
+            b[..rest.len()].copy_from_slice(rest);
+            absorb(&b, 1 << 24);
+        }
+    };
+    feed(aad);
+    feed(ct);
+    let mut lens = [0u8; 16];
+    lens[..8].copy_from_slice(&(aad.len() as u64).to_le_bytes());
+    lens[8..].copy_from_slice(&(ct.len() as u64).to_le_bytes());
+    absorb(&lens, 1 << 24);
+
+    // Fully carry, then reduce mod 2^130 - 5
+    let mut c = h[1] >> 26; h[1] &= 0x3FFFFFF;
+    h[2] += c; c = h[2] >> 26; h[2] &= 0x3FFFFFF;
+    h[3] += c; c = h[3] >> 26; h[3] &= 0x3FFFFFF;
+    h[4] += c; c = h[4] >> 26; h[4] &= 0x3FFFFFF;
+    h[0] += c * 5; c = h[0] >> 26; h[0] &= 0x3FFFFFF;
+    h[1] += c;
+    let mut g = [0u32; 5];
+    g[0] = h[0].wrapping_add(5); c = g[0] >> 26; g[0] &= 0x3FFFFFF;
+    g[1] = h[1].wrapping_add(c); c = g[1] >> 26; g[1] &= 0x3FFFFFF;
+    g[2] = h[2].wrapping_add(c); c = g[2] >> 26; g[2] &= 0x3FFFFFF;
+    g[3] = h[3].wrapping_add(c); c = g[3] >> 26; g[3] &= 0x3FFFFFF;
+    g[4] = h[4].wrapping_add(c).wrapping_sub(1 << 26);
+    // g is h - p; keep it if that did not go negative
+    let mask = (g[4] >> 31).wrapping_sub(1);
+    for i in 0..5 { h[i] = (h[i] & !mask) | (g[i] & mask); }
+
+    let h0 = h[0] | (h[1] << 26);
+    let h1 = (h[1] >> 6) | (h[2] << 20);
+    let h2 = (h[2] >> 12) | (h[3] << 14);
+    let h3 = (h[3] >> 18) | (h[4] << 8);
+    let mut f: u64;
+    let mut tag = [0u8; TAG_LEN];
+    f = h0 as u64 + le(&key[16..]) as u64;             tag[0..4].copy_from_slice(&(f as u32).to_le_bytes());
+    f = h1 as u64 + le(&key[20..]) as u64 + (f >> 32); tag[4..8].copy_from_slice(&(f as u32).to_le_bytes());
+    f = h2 as u64 + le(&key[24..]) as u64 + (f >> 32); tag[8..12].copy_from_slice(&(f as u32).to_le_bytes());
+    f = h3 as u64 + le(&key[28..]) as u64 + (f >> 32); tag[12..16].copy_from_slice(&(f as u32).to_le_bytes());
+    tag
+}
+
+fn poly_key(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN]) -> [u8; 32] {
+    let mut k = [0u8; 32];
+    k.copy_from_slice(&block(key, 0, nonce)[..32]);
+    k
+}
+
+/// Encrypt `plaintext`, returning ciphertext followed by the tag.
+pub fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut out = plaintext.to_vec();
+    chacha20(key, 1, nonce, &mut out);
+    let tag = poly1305(&poly_key(key, nonce), aad, &out);
+    out.extend_from_slice(&tag);
+    out
+}
+
+/// Check and decrypt `sealed` (ciphertext then tag).
+pub fn open(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if sealed.len() < TAG_LEN { return Err("ciphertext too short"); }
+    let (ct, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+    let expect = poly1305(&poly_key(key, nonce), aad, ct);
+    if expect.iter().zip(tag).fold(0u8, |d, (a, b)| d | (a ^ b)) != 0 { return Err("authentication failed"); }
+    let mut out = ct.to_vec();
+    chacha20(key, 1, nonce, &mut out);
+    Ok(out)
+}
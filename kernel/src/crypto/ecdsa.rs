@@ -0,0 +1,229 @@
+//! ECDSA Signature Verification (FIPS 186-4) on P-256 and P-384
+//! The curves TLS servers sign handshakes and certificates with.  Points
+//! are kept in Jacobian coordinates with field elements in Montgomery
+//! form; both curves have a = -3, which the doubling formula relies on.
+//! SAE does its group arithmetic on P-256 with the same code.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::bignum::{self, Limbs, Modulus};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Curve {
+    P256,
+    P384,
+}
+
+struct Params {
+    p:  &'static str,
+    n:  &'static str,
+    b:  &'static str,
+    gx: &'static str,
+    gy: &'static str,
+}
+
+const P256: Params = Params {
+    p:  "FFFFFFFF00000001000000000000000000000000FFFFFFFFFFFFFFFFFFFFFFFF",
+    n:  "FFFFFFFF00000000FFFFFFFFFFFFFFFFBCE6FAADA7179E84F3B9CAC2FC632551",
+    b:  "5AC635D8AA3A93E7B3EBBD55769886BC651D06B0CC53B0F63BCE3C3E27D2604B",
+    gx: "6B17D1F2E12C4247F8BCE6E563A440F277037D812DEB33A0F4A13945D898C296",
+    gy: "4FE342E2FE1A7F9B8EE7EB4A7C0F9E162BCE33576B315ECECBB6406837BF51F5",
+};
+
+const P384: Params = Params {
+    p:  "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFEFFFFFFFF0000000000000000FFFFFFFF",
+    n:  "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFC7634D81F4372DDF581A0DB248B0A77AECEC196ACCC52973",
+    b:  "B3312FA7E23EE7E4988E056BE3F82D19181D9C6EFE8141120314088F5013875AC656398D8A2ED19D2A85C8EDD3EC2AEF",
+    gx: "AA87CA22BE8B05378EB1C71EF320AD746E1D3B628BA79B9859F741E082542A385502F25DBF55296C3A545E3872760AB7",
+    gy: "3617DE4A96262C6F5D9E98BF9292DC29F8F41DBD289A147CE9DA3113B5F0B8C00A60B1CE1D7E819D7A431D7C90EA0E5F",
+};
+
+impl Curve {
+    fn params(self) -> &'static Params {
+        match self {
+            Curve::P256 => &P256,
+            Curve::P384 => &P384,
+        }
+    }
+
+    /// Bytes in a field element or scalar.
+    pub fn scalar_len(self) -> usize {
+        match self {
+            Curve::P256 => 32,
+            Curve::P384 => 48,
+        }
+    }
+
+    /// The curve's field, group order and generator.
+    pub(super) fn group(self) -> Result<(Field, Modulus, Point), &'static str> {
+        let params = self.params();
+        let limbs = self.scalar_len().div_ceil(8);
+        let num = |h: &str| bignum::from_be(&hex(h), limbs).ok_or("bad curve");
+        let p = Modulus::new(num(params.p)?)?;
+        let g = Point { x: p.to_mont(&num(params.gx)?), y: p.to_mont(&num(params.gy)?), z: p.one() };
+        let field = Field { b: p.to_mont(&num(params.b)?), p };
+        Ok((field, Modulus::new(num(params.n)?)?, g))
+    }
+}
+
+fn hex(s: &str) -> Vec<u8> {
+    s.as_bytes().chunks(2).map(|c| {
+        let d = |x: u8| (x as char).to_digit(16).unwrap_or(0) as u8;
+        (d(c[0]) << 4) | d(c[1])
+    }).collect()
+}
+
+// ─── points ───────────────────────────────────────────────────────────────────
+
+/// (X, Y, Z) with x = X/Z², y = Y/Z³; Z = 0 is the point at infinity.
+#[derive(Clone)]
+pub(super) struct Point {
+    pub(super) x: Limbs,
+    pub(super) y: Limbs,
+    pub(super) z: Limbs,
+}
+
+pub(super) struct Field {
+    pub(super) p: Modulus,
+    pub(super) b: Limbs, // Montgomery form
+}
+
+impl Field {
+    pub(super) fn mul(&self, a: &[u64], b: &[u64]) -> Limbs { self.p.mul(a, b) }
+    pub(super) fn add(&self, a: &[u64], b: &[u64]) -> Limbs { self.p.add(a, b) }
+    pub(super) fn sub(&self, a: &[u64], b: &[u64]) -> Limbs { self.p.sub(a, b) }
+
+    pub(super) fn infinity(&self) -> Point {
+        Point { x: self.p.one(), y: self.p.one(), z: vec![0; self.p.limbs()] }
+    }
+
+    /// Whether affine (x, y), in Montgomery form, satisfies y² = x³ - 3x + b.
+    pub(super) fn on_curve(&self, x: &[u64], y: &[u64]) -> bool {
+        let x3 = self.mul(&self.mul(x, x), x);
+        let three_x = self.add(&self.add(x, x), x);
+        let rhs = self.add(&self.sub(&x3, &three_x), &self.b);
+        self.mul(y, y) == rhs
+    }
+
+    fn double(&self, q: &Point) -> Point {
+        if bignum::is_zero(&q.z) || bignum::is_zero(&q.y) { return self.infinity(); }
+        let delta = self.mul(&q.z, &q.z);
+        let gamma = self.mul(&q.y, &q.y);
+        let beta = self.mul(&q.x, &gamma);
+        let t = self.mul(&self.sub(&q.x, &delta), &self.add(&q.x, &delta));
+        let alpha = self.add(&self.add(&t, &t), &t);
+        let beta2 = self.add(&beta, &beta);
+        let beta4 = self.add(&beta2, &beta2);
+        let beta8 = self.add(&beta4, &beta4);
+        let x = self.sub(&self.mul(&alpha, &alpha), &beta8);
+        let yz = self.add(&q.y, &q.z);
+        let z = self.sub(&self.sub(&self.mul(&yz, &yz), &gamma), &delta);
+        let mut gamma8 = self.mul(&gamma, &gamma);
+        for _ in 0..3 { gamma8 = self.add(&gamma8, &gamma8); }
+        let y = self.sub(&self.mul(&alpha, &self.sub(&beta4, &x)), &gamma8);
+        Point { x, y, z }
+    }
+
+    pub(super) fn add_points(&self, a: &Point, b: &Point) -> Point {
+        if bignum::is_zero(&a.z) { return b.clone(); }
+        if bignum::is_zero(&b.z) { return a.clone(); }
+        let z1z1 = self.mul(&a.z, &a.z);
+        let z2z2 = self.mul(&b.z, &b.z);
+        let u1 = self.mul(&a.x, &z2z2);
+        let u2 = self.mul(&b.x, &z1z1);
+        let s1 = self.mul(&a.y, &self.mul(&b.z, &z2z2));
+        let s2 = self.mul(&b.y, &self.mul(&a.z, &z1z1));
+        if u1 == u2 {
+            return if s1 == s2 { self.double(a) } else { self.infinity() };
+        }
+        let h = self.sub(&u2, &u1);
+        let r = self.sub(&s2, &s1);
+        let hh = self.mul(&h, &h);
+        let hhh = self.mul(&hh, &h);
+        let v = self.mul(&u1, &hh);
+        let x = self.sub(&self.sub(&self.mul(&r, &r), &hhh), &self.add(&v, &v));
+        let y = self.sub(&self.mul(&r, &self.sub(&v, &x)), &self.mul(&s1, &hhh));
+        let z = self.mul(&h, &self.mul(&a.z, &b.z));
+        Point { x, y, z }
+    }
+
+    /// k₁·P + k₂·Q, sharing the doublings (Shamir's trick).
+    pub(super) fn mul2(&self, k1: &[u64], p: &Point, k2: &[u64], q: &Point) -> Point {
+        let pq = self.add_points(p, q);
+        let mut r = self.infinity();
+        let bit = |k: &[u64], i: usize| (k[i / 64] >> (i % 64)) & 1 == 1;
+        for i in (0..bignum::bits(k1).max(bignum::bits(k2))).rev() {
+            r = self.double(&r);
+            match (bit(k1, i), bit(k2, i)) {
+                (true, true)  => r = self.add_points(&r, &pq),
+                (true, false) => r = self.add_points(&r, p),
+                (false, true) => r = self.add_points(&r, q),
+                _ => {}
+            }
+        }
+        r
+    }
+
+    /// k·P.
+    pub(super) fn mul_point(&self, k: &[u64], p: &Point) -> Point {
+        self.mul2(k, p, &vec![0; k.len()], &self.infinity())
+    }
+
+    /// Affine (x, y) of `q`, still in Montgomery form; None at infinity.
+    pub(super) fn affine(&self, q: &Point) -> Option<(Limbs, Limbs)> {
+        if bignum::is_zero(&q.z) { return None; }
+        let zinv = self.p.invert(&q.z);
+        let zinv2 = self.mul(&zinv, &zinv);
+        Some((self.mul(&q.x, &zinv2), self.mul(&q.y, &self.mul(&zinv2, &zinv))))
+    }
+}
+
+// ─── verification ─────────────────────────────────────────────────────────────
+
+/// Verify signature (`r`, `s`) over message digest `digest` with public key
+/// `key`, an uncompressed SEC1 point (0x04 ‖ x ‖ y).
+pub fn verify(curve: Curve, key: &[u8], digest: &[u8], r: &[u8], s: &[u8]) -> Result<(), &'static str> {
+    let len = curve.scalar_len();
+    let limbs = len.div_ceil(8);
+    let (field, order, g) = curve.group()?;
+
+    if key.len() != 1 + 2 * len || key[0] != 4 { return Err("unsupported public key encoding"); }
+    let coord = |c: &[u8]| {
+        let v = bignum::from_be(c, limbs).ok_or("bad public key")?;
+        if bignum::cmp(&v, field.p.value()).is_ge() { return Err("bad public key"); }
+        Ok(field.p.to_mont(&v))
+    };
+    let (qx, qy) = (coord(&key[1..1 + len])?, coord(&key[1 + len..])?);
+    if !field.on_curve(&qx, &qy) { return Err("public key not on curve"); }
+
+    let scalar = |v: &[u8]| {
+        let v = bignum::from_be(v, limbs).ok_or("bad signature")?;
+        if bignum::is_zero(&v) || bignum::cmp(&v, order.value()).is_ge() { return Err("bad signature"); }
+        Ok(v)
+    };
+    let (r, s) = (scalar(r)?, scalar(s)?);
+    // The digest's leftmost bits, as many as the order has
+    let e = bignum::from_be(&digest[..digest.len().min(len)], limbs).ok_or("bad digest")?;
+    let e = order.reduce_once(&e);
+
+    let w = order.invert(&order.to_mont(&s));
+    let u1 = order.from_mont(&order.mul(&order.to_mont(&e), &w));
+    let u2 = order.from_mont(&order.mul(&order.to_mont(&r), &w));
+
+    let q = Point { x: qx, y: qy, z: field.p.one() };
+    let sum = field.mul2(&u1, &g, &u2, &q);
+    let (x, _) = field.affine(&sum).ok_or("signature mismatch")?;
+    let x = field.p.from_mont(&x);
+    if order.reduce_once(&x) == r { Ok(()) } else { Err("signature mismatch") }
+}
+
+/// Split a DER `Ecdsa-Sig-Value` (SEQUENCE { r INTEGER, s INTEGER }).
+pub fn parse_der_signature(der: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (tag, body, _) = super::x509::der_next(der)?;
+    if tag != 0x30 { return None; }
+    let (t1, r, rest) = super::x509::der_next(body)?;
+    let (t2, s, _) = super::x509::der_next(rest)?;
+    if t1 != 2 || t2 != 2 { return None; }
+    Some((r, s))
+}
@@ -0,0 +1,526 @@
+//! ML-DSA-65 Signatures (FIPS 204)
+//! Post-quantum lattice signatures used to authenticate code and data
+//! the kernel loads.  The kernel signs with one key of its own, the
+//! device attestation key, derived from the root of trust's secret; every
+//! other key it only verifies with.  Messages are bound to a context
+//! string as in ML-DSA.Sign and ML-DSA.Verify, so a signature made for one
+//! purpose (say a model file) cannot be replayed for another.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::sha3::{shake128, shake256};
+
+// ─── parameters (ML-DSA-65) ───────────────────────────────────────────────────
+
+const N:      usize = 256;
+const Q:      i32   = 8_380_417;
+const D:      u32   = 13;
+const TAU:    usize = 49;
+const GAMMA1: i32   = 1 << 19;
+const GAMMA2: i32   = (Q - 1) / 32;
+const K:      usize = 6;
+const L:      usize = 5;
+const ETA:    i32   = 4;
+const BETA:   i32   = 196; // τ·η
+const OMEGA:  usize = 55;
+const CTILDE: usize = 48;  // λ/4
+
+pub const PUBLIC_KEY_LEN: usize = 32 + K * N * 10 / 8;                       // 1952
+pub const SIGNATURE_LEN:  usize = CTILDE + L * N * 20 / 8 + OMEGA + K;      // 3309
+pub const SEED_LEN:       usize = 32;
+
+type Poly = [i32; N];
+
+// ─── arithmetic mod q ─────────────────────────────────────────────────────────
+
+fn reduce(a: i64) -> i32 {
+    a.rem_euclid(Q as i64) as i32
+}
+
+const fn pow_mod(mut b: i64, mut e: u32) -> i64 {
+    let mut r = 1;
+    while e > 0 {
+        if e & 1 == 1 { r = r * b % Q as i64; }
+        b = b * b % Q as i64;
+        e >>= 1;
+    }
+    r
+}
+
+/// ζ^brv(k) for the primitive 512th root of unity ζ = 1753.
+const ZETAS: [i32; N] = {
+    let mut z = [0i32; N];
+    let mut k = 0;
+    while k < N {
+        z[k] = pow_mod(1753, (k as u8).reverse_bits() as u32) as i32;
+        k += 1;
+    }
+    z
+};
+
+fn ntt(w: &mut Poly) {
+    let mut m = 0;
+    let mut len = 128;
+    while len >= 1 {
+        let mut start = 0;
+        while start < N {
+            m += 1;
+            let z = ZETAS[m] as i64;
+            for j in start..start + len {
+                let t = reduce(z * w[j + len] as i64);
+                w[j + len] = reduce(w[j] as i64 - t as i64);
+                w[j]       = reduce(w[j] as i64 + t as i64);
+            }
+            start += 2 * len;
+        }
+        len /= 2;
+    }
+}
+
+fn ntt_inverse(w: &mut Poly) {
+    let mut m = N;
+    let mut len = 1;
+    while len < N {
+        let mut start = 0;
+        while start < N {
+            m -= 1;
+            let z = -(ZETAS[m] as i64);
+            for j in start..start + len {
+                let t = w[j];
+                w[j]       = reduce(t as i64 + w[j + len] as i64);
+                w[j + len] = reduce(z * (t as i64 - w[j + len] as i64));
+            }
+            start += 2 * len;
+        }
+        len *= 2;
+    }
+    const N_INV: i64 = 8_347_681; // 256⁻¹ mod q
+    for c in w.iter_mut() { *c = reduce(*c as i64 * N_INV); }
+}
+
+// ─── sampling ─────────────────────────────────────────────────────────────────
+
+/// RejNTTPoly: uniform polynomial in NTT form from SHAKE128(seed).
+fn rej_ntt_poly(seed: &[u8; 34]) -> Poly {
+    let mut xof = shake128();
+    xof.absorb(seed);
+    let mut a = [0i32; N];
+    let mut j = 0;
+    let mut b = [0u8; 3];
+    while j < N {
+        xof.squeeze(&mut b);
+        let c = b[0] as i32 | (b[1] as i32) << 8 | ((b[2] & 0x7F) as i32) << 16;
+        if c < Q { a[j] = c; j += 1; }
+    }
+    a
+}
+
+/// SampleInBall: challenge polynomial with τ coefficients in {−1, 1}.
+fn sample_in_ball(seed: &[u8]) -> Poly {
+    let mut xof = shake256();
+    xof.absorb(seed);
+    let mut s = [0u8; 8];
+    xof.squeeze(&mut s);
+    let signs = u64::from_le_bytes(s);
+
+    let mut c = [0i32; N];
+    let mut b = [0u8; 1];
+    for (h, i) in (N - TAU..N).enumerate() {
+        let j = loop {
+            xof.squeeze(&mut b);
+            if b[0] as usize <= i { break b[0] as usize; }
+        };
+        c[i] = c[j];
+        c[j] = if signs >> h & 1 == 1 { Q - 1 } else { 1 };
+    }
+    c
+}
+
+/// RejBoundedPoly: polynomial with coefficients in [−η, η] from
+/// SHAKE256(seed), as q-residues.
+fn rej_bounded_poly(seed: &[u8; 66]) -> Poly {
+    let mut xof = shake256();
+    xof.absorb(seed);
+    let mut a = [0i32; N];
+    let mut j = 0;
+    let mut b = [0u8; 1];
+    while j < N {
+        xof.squeeze(&mut b);
+        for half in [b[0] & 0x0F, b[0] >> 4] {
+            if half < 9 && j < N {
+                a[j] = (ETA - half as i32).rem_euclid(Q);
+                j += 1;
+            }
+        }
+    }
+    a
+}
+
+// ─── encoding ─────────────────────────────────────────────────────────────────
+
+/// Write `bits`-wide little-endian fields; the inverse of `unpack`.
+fn pack(p: &Poly, bits: u32, out: &mut Vec<u8>) {
+    let mut acc: u64 = 0;
+    let mut have = 0;
+    for &c in p {
+        acc |= (c as u64 & ((1 << bits) - 1)) << have;
+        have += bits;
+        while have >= 8 {
+            out.push(acc as u8);
+            acc >>= 8;
+            have -= 8;
+        }
+    }
+}
+
+/// Read `bits`-wide little-endian fields from a packed byte string.
+fn unpack(bytes: &[u8], bits: u32) -> Poly {
+    let mut p = [0i32; N];
+    let mut acc: u64 = 0;
+    let mut have = 0;
+    let mut it = bytes.iter();
+    for c in p.iter_mut() {
+        while have < bits {
+            acc |= (*it.next().unwrap_or(&0) as u64) << have;
+            have += 8;
+        }
+        *c = (acc & ((1 << bits) - 1)) as i32;
+        acc >>= bits;
+        have -= bits;
+    }
+    p
+}
+
+/// HintBitUnpack; `None` if the encoding is malformed.
+fn unpack_hints(y: &[u8]) -> Option<[Poly; K]> {
+    let mut h = [[0i32; N]; K];
+    let mut index = 0;
+    for (i, hi) in h.iter_mut().enumerate() {
+        let end = y[OMEGA + i] as usize;
+        if end < index || end > OMEGA { return None; }
+        let first = index;
+        while index < end {
+            if index > first && y[index - 1] >= y[index] { return None; }
+            hi[y[index] as usize] = 1;
+            index += 1;
+        }
+    }
+    if y[index..OMEGA].iter().any(|&b| b != 0) { return None; }
+    Some(h)
+}
+
+// ─── rounding ─────────────────────────────────────────────────────────────────
+
+/// Decompose r into (r1, r0) with r = r1·2γ2 + r0.
+fn decompose(r: i32) -> (i32, i32) {
+    let mut r0 = r % (2 * GAMMA2);
+    if r0 > GAMMA2 { r0 -= 2 * GAMMA2; }
+    if r - r0 == Q - 1 { (0, r0 - 1) } else { ((r - r0) / (2 * GAMMA2), r0) }
+}
+
+fn use_hint(h: i32, r: i32) -> i32 {
+    const M: i32 = (Q - 1) / (2 * GAMMA2);
+    let (r1, r0) = decompose(r);
+    match (h, r0 > 0) {
+        (1, true)  => (r1 + 1).rem_euclid(M),
+        (1, false) => (r1 - 1).rem_euclid(M),
+        _          => r1,
+    }
+}
+
+// ─── verification ─────────────────────────────────────────────────────────────
+
+/// Verify an ML-DSA-65 signature over `msg` under context string `ctx`
+/// (at most 255 bytes).
+pub fn verify(public_key: &[u8], msg: &[u8], ctx: &[u8], sig: &[u8]) -> bool {
+    if public_key.len() != PUBLIC_KEY_LEN || sig.len() != SIGNATURE_LEN || ctx.len() > 255 {
+        return false;
+    }
+
+    // pkDecode
+    let rho = &public_key[..32];
+    let t1: Vec<Poly> = public_key[32..].chunks(N * 10 / 8).map(|b| unpack(b, 10)).collect();
+
+    // sigDecode
+    let c_tilde = &sig[..CTILDE];
+    let z: Vec<Poly> = sig[CTILDE..CTILDE + L * N * 20 / 8].chunks(N * 20 / 8)
+        .map(|b| {
+            let mut p = unpack(b, 20);
+            for c in p.iter_mut() { *c = GAMMA1 - *c; }
+            p
+        })
+        .collect();
+    let Some(h) = unpack_hints(&sig[CTILDE + L * N * 20 / 8..]) else { return false };
+
+    if z.iter().flatten().any(|c| c.abs() >= GAMMA1 - BETA) { return false; }
+
+    // μ = H(H(pk) ‖ 0 ‖ |ctx| ‖ ctx ‖ M)
+    let mut tr = [0u8; 64];
+    super::sha3::shake256_into(public_key, &mut tr);
+    let mut mu = [0u8; 64];
+    let mut hs = shake256();
+    hs.absorb(&tr);
+    hs.absorb(&[0, ctx.len() as u8]);
+    hs.absorb(ctx);
+    hs.absorb(msg);
+    hs.squeeze(&mut mu);
+
+    let mut c = sample_in_ball(c_tilde);
+    ntt(&mut c);
+
+    let z_hat: Vec<Poly> = z.iter().map(|p| {
+        let mut p = p.map(|v| v.rem_euclid(Q));
+        ntt(&mut p);
+        p
+    }).collect();
+
+    // w'_approx = NTT⁻¹(Â·ẑ − ĉ·NTT(t1·2^d)), then w1' = UseHint(h, w'_approx)
+    let mut w1_encoded = vec![0u8; K * N / 2];
+    let mut seed = [0u8; 34];
+    seed[..32].copy_from_slice(rho);
+    for r in 0..K {
+        let mut acc = [0i64; N];
+        for (s, zs) in z_hat.iter().enumerate() {
+            seed[32] = s as u8;
+            seed[33] = r as u8;
+            let a = rej_ntt_poly(&seed);
+            for i in 0..N { acc[i] += a[i] as i64 * zs[i] as i64 % Q as i64; }
+        }
+        let mut t = t1[r].map(|v| v << D);
+        ntt(&mut t);
+        let mut w = [0i32; N];
+        for i in 0..N {
+            w[i] = reduce(acc[i] - c[i] as i64 * t[i] as i64 % Q as i64);
+        }
+        ntt_inverse(&mut w);
+
+        let out = &mut w1_encoded[r * N / 2..(r + 1) * N / 2];
+        for i in 0..N {
+            let w1 = use_hint(h[r][i], w[i]) as u8;
+            out[i / 2] |= w1 << (4 * (i % 2));
+        }
+    }
+
+    let mut expect = [0u8; CTILDE];
+    let mut hs = shake256();
+    hs.absorb(&mu);
+    hs.absorb(&w1_encoded);
+    hs.squeeze(&mut expect);
+
+    // Not secret, so no need for a constant-time compare
+    expect[..] == *c_tilde
+}
+
+// ─── signing ──────────────────────────────────────────────────────────────────
+
+/// r mod± q: the representative in (−q/2, q/2].
+fn centered(r: i32) -> i32 {
+    if r > Q / 2 { r - Q } else { r }
+}
+
+/// The pointwise product of two NTT-form polynomials.
+fn mul(a: &Poly, b: &Poly) -> Poly {
+    core::array::from_fn(|i| reduce(a[i] as i64 * b[i] as i64))
+}
+
+/// NTT⁻¹(ĉ ∘ v̂) for each polynomial of v̂.
+fn times_c(c: &Poly, v: &[Poly]) -> Vec<Poly> {
+    v.iter().map(|p| {
+        let mut p = mul(c, p);
+        ntt_inverse(&mut p);
+        p
+    }).collect()
+}
+
+/// An ML-DSA-65 private key.  Its secret parts are wiped when dropped.
+pub struct SigningKey {
+    rho: [u8; 32],
+    key: [u8; 32],
+    tr:  [u8; 64],
+    /// ŝ1, ŝ2 and t̂0, kept in NTT form.
+    s1:  Vec<Poly>,
+    s2:  Vec<Poly>,
+    t0:  Vec<Poly>,
+    public_key: Vec<u8>,
+}
+
+impl SigningKey {
+    /// ML-DSA.KeyGen_internal: the key pair determined by seed ξ.
+    pub fn from_seed(xi: &[u8; SEED_LEN]) -> SigningKey {
+        let mut expanded = [0u8; 128];
+        let mut h = shake256();
+        h.absorb(xi);
+        h.absorb(&[K as u8, L as u8]);
+        h.squeeze(&mut expanded);
+        let (rho, rest) = expanded.split_at(32);
+        let (rho_prime, key) = rest.split_at(64);
+
+        let mut seed = [0u8; 66];
+        seed[..64].copy_from_slice(rho_prime);
+        let mut small = |r: usize| {
+            seed[64..].copy_from_slice(&(r as u16).to_le_bytes());
+            let mut p = rej_bounded_poly(&seed);
+            ntt(&mut p);
+            p
+        };
+        let s1: Vec<Poly> = (0..L).map(&mut small).collect();
+        let s2: Vec<Poly> = (L..L + K).map(&mut small).collect();
+        super::wipe(&mut seed);
+
+        // t = NTT⁻¹(Â ∘ ŝ1) + s2, split into t1·2^d + t0
+        let mut public_key = Vec::with_capacity(PUBLIC_KEY_LEN);
+        public_key.extend_from_slice(rho);
+        let mut t0 = Vec::with_capacity(K);
+        let mut a_seed = [0u8; 34];
+        a_seed[..32].copy_from_slice(rho);
+        for (r, s2r) in s2.iter().enumerate() {
+            let mut acc = [0i64; N];
+            for (s, s1s) in s1.iter().enumerate() {
+                a_seed[32] = s as u8;
+                a_seed[33] = r as u8;
+                let a = rej_ntt_poly(&a_seed);
+                for i in 0..N { acc[i] += a[i] as i64 * s1s[i] as i64 % Q as i64; }
+            }
+            let mut t = acc.map(reduce);
+            ntt_inverse(&mut t);
+            let mut s2r = *s2r;
+            ntt_inverse(&mut s2r);
+            let (mut hi, mut lo) = ([0i32; N], [0i32; N]);
+            for i in 0..N {
+                let t = reduce(t[i] as i64 + s2r[i] as i64);
+                let mut r0 = t & ((1 << D) - 1);
+                if r0 > 1 << (D - 1) { r0 -= 1 << D; }
+                hi[i] = (t - r0) >> D;
+                lo[i] = r0.rem_euclid(Q);
+            }
+            pack(&hi, 10, &mut public_key);
+            ntt(&mut lo);
+            t0.push(lo);
+        }
+
+        let mut tr = [0u8; 64];
+        super::sha3::shake256_into(&public_key, &mut tr);
+        let mut sk = SigningKey {
+            rho: [0; 32], key: [0; 32], tr, s1, s2, t0, public_key,
+        };
+        sk.rho.copy_from_slice(rho);
+        sk.key.copy_from_slice(key);
+        super::wipe(&mut expanded);
+        sk
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// ML-DSA.Sign over `msg` under context string `ctx` (at most 255
+    /// bytes), hedged with the fresh randomness `rnd`.
+    pub fn sign(&self, msg: &[u8], ctx: &[u8], rnd: &[u8; 32]) -> Vec<u8> {
+        // μ = H(tr ‖ 0 ‖ |ctx| ‖ ctx ‖ M), ρ'' = H(K ‖ rnd ‖ μ)
+        let mut mu = [0u8; 64];
+        let mut hs = shake256();
+        hs.absorb(&self.tr);
+        hs.absorb(&[0, ctx.len().min(255) as u8]);
+        hs.absorb(&ctx[..ctx.len().min(255)]);
+        hs.absorb(msg);
+        hs.squeeze(&mut mu);
+        let mut rho_pp = [0u8; 66];
+        let mut hs = shake256();
+        hs.absorb(&self.key);
+        hs.absorb(rnd);
+        hs.absorb(&mu);
+        hs.squeeze(&mut rho_pp[..64]);
+
+        let mut a_seed = [0u8; 34];
+        a_seed[..32].copy_from_slice(&self.rho);
+        let a_hat: Vec<Poly> = (0..K).flat_map(|r| (0..L).map(move |s| (r, s))).map(|(r, s)| {
+            a_seed[32] = s as u8;
+            a_seed[33] = r as u8;
+            rej_ntt_poly(&a_seed)
+        }).collect();
+
+        let mut kappa: u16 = 0;
+        let sig = loop {
+            // y = ExpandMask(ρ'', κ)
+            let y: Vec<Poly> = (0..L as u16).map(|r| {
+                rho_pp[64..].copy_from_slice(&(kappa + r).to_le_bytes());
+                let mut v = [0u8; N * 20 / 8];
+                super::sha3::shake256_into(&rho_pp, &mut v);
+                unpack(&v, 20).map(|c| (GAMMA1 - c).rem_euclid(Q))
+            }).collect();
+            kappa += L as u16;
+
+            let y_hat: Vec<Poly> = y.iter().map(|p| { let mut p = *p; ntt(&mut p); p }).collect();
+            let w: Vec<Poly> = (0..K).map(|r| {
+                let mut acc = [0i64; N];
+                for (s, ys) in y_hat.iter().enumerate() {
+                    let a = &a_hat[r * L + s];
+                    for i in 0..N { acc[i] += a[i] as i64 * ys[i] as i64 % Q as i64; }
+                }
+                let mut w = acc.map(reduce);
+                ntt_inverse(&mut w);
+                w
+            }).collect();
+
+            let mut w1_encoded = vec![0u8; K * N / 2];
+            for (r, wr) in w.iter().enumerate() {
+                for i in 0..N {
+                    w1_encoded[r * N / 2 + i / 2] |= (decompose(wr[i]).0 as u8) << (4 * (i % 2));
+                }
+            }
+            let mut c_tilde = [0u8; CTILDE];
+            let mut hs = shake256();
+            hs.absorb(&mu);
+            hs.absorb(&w1_encoded);
+            hs.squeeze(&mut c_tilde);
+            let mut c = sample_in_ball(&c_tilde);
+            ntt(&mut c);
+
+            let cs1 = times_c(&c, &self.s1);
+            let z: Vec<Poly> = y.iter().zip(&cs1).map(|(y, cs)| core::array::from_fn(|i| reduce(y[i] as i64 + cs[i] as i64))).collect();
+            if z.iter().flatten().any(|&v| centered(v).abs() >= GAMMA1 - BETA) { continue; }
+
+            // r = w − cs2; its low bits must leave room for the hint
+            let cs2 = times_c(&c, &self.s2);
+            let r: Vec<Poly> = w.iter().zip(&cs2).map(|(w, cs)| core::array::from_fn(|i| reduce(w[i] as i64 - cs[i] as i64))).collect();
+            if r.iter().flatten().any(|&v| decompose(v).1.abs() >= GAMMA2 - BETA) { continue; }
+
+            let ct0 = times_c(&c, &self.t0);
+            if ct0.iter().flatten().any(|&v| centered(v).abs() >= GAMMA2) { continue; }
+            // h = MakeHint(−ct0, r + ct0): where adding ct0 moves the high bits
+            let mut hints = Vec::new();
+            let mut ends = [0u8; K];
+            for (j, (rj, ct0j)) in r.iter().zip(&ct0).enumerate() {
+                for i in 0..N {
+                    if decompose(rj[i]).0 != decompose(reduce(rj[i] as i64 + ct0j[i] as i64)).0 {
+                        hints.push(i as u8);
+                    }
+                }
+                ends[j] = hints.len().min(255) as u8;
+            }
+            if hints.len() > OMEGA { continue; }
+
+            let mut sig = Vec::with_capacity(SIGNATURE_LEN);
+            sig.extend_from_slice(&c_tilde);
+            for p in &z { pack(&p.map(|v| GAMMA1 - centered(v)), 20, &mut sig); }
+            hints.resize(OMEGA, 0);
+            sig.extend_from_slice(&hints);
+            sig.extend_from_slice(&ends);
+            break sig;
+        };
+        super::wipe(&mut rho_pp);
+        sig
+    }
+}
+
+impl Drop for SigningKey {
+    fn drop(&mut self) {
+        super::wipe(&mut self.key);
+        for p in self.s1.iter_mut().chain(self.s2.iter_mut()).chain(self.t0.iter_mut()) {
+            for c in p.iter_mut() {
+                unsafe { core::ptr::write_volatile(c, 0); }
+            }
+        }
+    }
+}
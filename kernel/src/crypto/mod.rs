@@ -0,0 +1,25 @@
+//! SurakshaOS Kernel Cryptography
+//! Primitives the kernel itself needs to authenticate what it loads and
+//! to secure the connections its own services make.
+
+pub mod sha3;             // SHA3-256, SHAKE128, SHAKE256
+pub mod mldsa;            // ML-DSA-65 signature verification
+pub mod slhdsa;           // SLH-DSA-SHAKE-128s signature verification
+pub mod sha2;             // SHA-256, SHA-384, HMAC, HKDF, 802.11 KDF
+pub mod sha1;             // SHA-1, for NSEC3 name hashing only
+pub mod chacha20poly1305; // ChaCha20-Poly1305 AEAD
+pub mod aes;              // AES-128, AES-128-GCM, CMAC and key wrap
+pub mod x25519;           // X25519 key agreement
+pub mod blake2s;          // BLAKE2s, keyed MAC and HMAC
+pub mod bignum;           // Montgomery arithmetic for RSA and ECDSA
+pub mod ecdsa;            // ECDSA verification on P-256 and P-384
+pub mod rsa;              // RSA PKCS#1 v1.5 and PSS verification
+pub mod x509;             // X.509 certificate parsing and checks
+pub mod sae;              // SAE (WPA3 password authentication) on P-256
+
+/// Overwrite a secret so it does not linger in memory once dropped.
+pub fn wipe(buf: &mut [u8]) {
+    for b in buf.iter_mut() {
+        unsafe { core::ptr::write_volatile(b, 0); }
+    }
+}
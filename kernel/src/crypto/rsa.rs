@@ -0,0 +1,91 @@
+//! RSA Signature Verification (RFC 8017)
+//! PKCS#1 v1.5 for certificate signatures and PSS, with MGF1 and a salt
+//! as long as the hash, for TLS 1.3 handshake signatures.
+
+use alloc::vec::Vec;
+
+use super::bignum::{self, Modulus};
+use super::x509::Hash;
+
+/// Smallest modulus accepted, in bits.
+const MIN_BITS: usize = 2048;
+/// Largest, to bound the work a peer can make us do.
+const MAX_BITS: usize = 8192;
+
+const SHA256_DIGEST_INFO: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20,
+];
+const SHA384_DIGEST_INFO: &[u8] = &[
+    0x30, 0x41, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0x04, 0x30,
+];
+
+/// s^e mod n as many bytes as n has, plus n's bit length.
+fn public_op(n: &[u8], e: &[u8], sig: &[u8]) -> Result<(Vec<u8>, usize), &'static str> {
+    let k = n.len();
+    let limbs = k.div_ceil(8);
+    let m = bignum::from_be(n, limbs).ok_or("bad RSA key")?;
+    let bits = bignum::bits(&m);
+    if !(MIN_BITS..=MAX_BITS).contains(&bits) { return Err("unsupported RSA key size"); }
+    if sig.len() != k { return Err("bad RSA signature length"); }
+    let exp = bignum::from_be(e, limbs).ok_or("bad RSA key")?;
+    let s = bignum::from_be(sig, limbs).ok_or("bad RSA signature")?;
+    if bignum::cmp(&s, &m).is_ge() { return Err("bad RSA signature"); }
+    let modulus = Modulus::new(m)?;
+    let em = modulus.from_mont(&modulus.pow(&modulus.to_mont(&s), &exp));
+    Ok((bignum::to_be(&em, k), bits))
+}
+
+pub fn verify_pkcs1(n: &[u8], e: &[u8], hash: Hash, digest: &[u8], sig: &[u8]) -> Result<(), &'static str> {
+    let (em, _) = public_op(n, e, sig)?;
+    let prefix = match hash {
+        Hash::Sha256 => SHA256_DIGEST_INFO,
+        Hash::Sha384 => SHA384_DIGEST_INFO,
+    };
+    // 00 01 FF..FF 00 DigestInfo, rebuilt and compared whole
+    let t_len = prefix.len() + digest.len();
+    if em.len() < t_len + 11 { return Err("RSA key too small"); }
+    let mut expect = Vec::with_capacity(em.len());
+    expect.extend_from_slice(&[0, 1]);
+    expect.resize(em.len() - t_len - 1, 0xFF);
+    expect.push(0);
+    expect.extend_from_slice(prefix);
+    expect.extend_from_slice(digest);
+    if em == expect { Ok(()) } else { Err("signature mismatch") }
+}
+
+fn mgf1(hash: Hash, seed: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len);
+    let mut counter = 0u32;
+    while out.len() < len {
+        let mut block = seed.to_vec();
+        block.extend_from_slice(&counter.to_be_bytes());
+        out.extend_from_slice(&hash.digest(&block));
+        counter += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+pub fn verify_pss(n: &[u8], e: &[u8], hash: Hash, digest: &[u8], sig: &[u8]) -> Result<(), &'static str> {
+    let (em, bits) = public_op(n, e, sig)?;
+    let em_bits = bits - 1;
+    let em_len = em_bits.div_ceil(8);
+    // A modulus one bit past a byte boundary leaves a zero byte in front
+    let (lead, em) = em.split_at(em.len() - em_len);
+    if lead.iter().any(|&b| b != 0) { return Err("signature mismatch"); }
+    let h_len = digest.len();
+    if em_len < 2 * h_len + 2 || em[em_len - 1] != 0xBC { return Err("signature mismatch"); }
+    let (masked, h) = em[..em_len - 1].split_at(em_len - h_len - 1);
+    let top = 0xFFu8 >> (8 * em_len - em_bits);
+    if masked[0] & !top != 0 { return Err("signature mismatch"); }
+    let mut db: Vec<u8> = masked.iter().zip(mgf1(hash, h, masked.len())).map(|(a, b)| a ^ b).collect();
+    db[0] &= top;
+    // PS zeros, 0x01, then the salt
+    let ps = db.len() - h_len - 1;
+    if db[..ps].iter().any(|&b| b != 0) || db[ps] != 1 { return Err("signature mismatch"); }
+    let mut m = Vec::with_capacity(8 + 2 * h_len);
+    m.extend_from_slice(&[0; 8]);
+    m.extend_from_slice(digest);
+    m.extend_from_slice(&db[ps + 1..]);
+    if hash.digest(&m) == h { Ok(()) } else { Err("signature mismatch") }
+}
@@ -0,0 +1,221 @@
+//! SAE: Simultaneous Authentication of Equals (IEEE 802.11-2020 12.4)
+//! The password-authenticated key exchange WPA3-Personal runs before a
+//! station associates, on ECC group 19 (P-256) with the password element
+//! found by hunting and pecking.  Each side commits to a scalar and an
+//! element built from the password element and its own secret, derives
+//! the shared secret from the other's commit, and proves with its
+//! confirm that it used the same password.  Neither message reveals the
+//! password to a listener, and every run yields a fresh PMK and PMKID.
+//!
+//! The hunt always runs all of its rounds, whichever one finds the
+//! element, so its duration does not depend on the password.  The field
+//! arithmetic is the ECDSA verifier's and is not itself constant time.
+
+use alloc::vec::Vec;
+
+use super::bignum::{self, Limbs, Modulus};
+use super::ecdsa::{Curve, Field, Point};
+use super::sha2::{hmac_sha256, kdf_sha256};
+
+/// IANA number of the group used, P-256.
+pub const GROUP_P256:  u16   = 19;
+/// A commit's scalar and element, as sent.
+pub const COMMIT_LEN:  usize = 3 * LEN;
+pub const CONFIRM_LEN: usize = 32;
+pub const PMK_LEN:     usize = 32;
+pub const PMKID_LEN:   usize = 16;
+
+const LEN:   usize = 32;
+const LIMBS: usize = 4;
+/// Hunting-and-pecking rounds, all run whichever finds the element.
+const ROUNDS: u8 = 40;
+
+/// (a + add) >> shift, for shift in 1..64.
+fn add_shr(a: &[u64], add: u64, shift: u32) -> Limbs {
+    let mut v = a.to_vec();
+    v.push(0);
+    let mut carry = add;
+    for l in v.iter_mut() {
+        let (s, c) = l.overflowing_add(carry);
+        *l = s;
+        carry = c as u64;
+    }
+    (0..a.len()).map(|i| (v[i] >> shift) | (v[i + 1] << (64 - shift))).collect()
+}
+
+fn zeroize(limbs: &mut [u64]) {
+    for l in limbs.iter_mut() {
+        unsafe { core::ptr::write_volatile(l, 0); }
+    }
+}
+
+/// A uniformly random scalar in [2, order).
+fn random_scalar(order: &Modulus) -> Limbs {
+    loop {
+        let mut b = [0u8; LEN];
+        crate::entropy::fill_bytes(&mut b);
+        let v = bignum::from_be(&b, LIMBS).unwrap_or_default();
+        super::wipe(&mut b);
+        if bignum::cmp(&v, order.value()).is_lt() && bignum::bits(&v) > 1 { return v; }
+    }
+}
+
+/// The password element for `password` between stations `a` and `b`.
+fn password_element(field: &Field, password: &[u8], a: [u8; 6], b: [u8; 6]) -> Result<Point, &'static str> {
+    let p = field.p.value();
+    let prime = bignum::to_be(p, LEN);
+    let key = if a > b { [a, b].concat() } else { [b, a].concat() };
+    let legendre_exp = add_shr(p, 0, 1); // (p - 1) / 2
+    let sqrt_exp = add_shr(p, 1, 2);     // (p + 1) / 4, as p ≡ 3 (mod 4)
+
+    // (x, x³ - 3x + b, parity of the seed), all from the first round
+    // whose x is on the curve
+    let mut found: Option<(Limbs, Limbs, bool)> = None;
+    for counter in 1..=ROUNDS {
+        let mut seed = hmac_sha256(&key, &[password, &[counter]]);
+        let mut value = [0u8; LEN];
+        kdf_sha256(&seed, "SAE Hunting and Pecking", &prime, &mut value);
+        let x = bignum::from_be(&value, LIMBS).ok_or("bad password value")?;
+        let odd = seed[LEN - 1] & 1 == 1;
+        super::wipe(&mut seed);
+        super::wipe(&mut value);
+        if bignum::cmp(&x, p).is_ge() { continue; }
+        let x = field.p.to_mont(&x);
+        let x3 = field.mul(&field.mul(&x, &x), &x);
+        let three_x = field.add(&field.add(&x, &x), &x);
+        let rhs = field.add(&field.sub(&x3, &three_x), &field.b);
+        let residue = field.p.pow(&rhs, &legendre_exp) == field.p.one();
+        if residue && found.is_none() { found = Some((x, rhs, odd)); }
+    }
+    let (x, rhs, odd) = found.ok_or("no password element")?;
+    let mut y = field.p.pow(&rhs, &sqrt_exp);
+    if (field.p.from_mont(&y)[0] & 1 == 1) != odd { y = field.sub(&[0; LIMBS], &y); }
+    Ok(Point { x, y, z: field.p.one() })
+}
+
+// ─── exchange ─────────────────────────────────────────────────────────────────
+
+/// One side of an SAE exchange.
+pub struct Sae {
+    field:   Field,
+    order:   Modulus,
+    pwe:     Point,
+    rand:    Limbs,
+    scalar:  Limbs,
+    /// Affine, Montgomery form.
+    element: (Limbs, Limbs),
+    /// The peer's scalar and element once its commit is taken.
+    peer:    Option<(Limbs, (Limbs, Limbs))>,
+    kck:     [u8; 32],
+    pmk:     [u8; PMK_LEN],
+    pmkid:   [u8; PMKID_LEN],
+}
+
+impl Sae {
+    /// Start an exchange from our address `own` with `peer`.
+    pub fn new(password: &[u8], own: [u8; 6], peer: [u8; 6]) -> Result<Sae, &'static str> {
+        let (field, order, _) = Curve::P256.group()?;
+        let pwe = password_element(&field, password, own, peer)?;
+        loop {
+            let rand = random_scalar(&order);
+            let mut mask = random_scalar(&order);
+            let scalar = order.add(&rand, &mask);
+            if bignum::bits(&scalar) <= 1 { continue; }
+            // The element is the inverse of mask·PWE
+            let masked = field.mul_point(&mask, &pwe);
+            zeroize(&mut mask);
+            let Some((x, y)) = field.affine(&masked) else { continue };
+            let element = (x, field.sub(&[0; LIMBS], &y));
+            return Ok(Sae {
+                field, order, pwe, rand, scalar, element, peer: None,
+                kck: [0; 32], pmk: [0; PMK_LEN], pmkid: [0; PMKID_LEN],
+            });
+        }
+    }
+
+    fn encode(&self, scalar: &[u64], element: &(Limbs, Limbs)) -> Vec<u8> {
+        let mut out = bignum::to_be(scalar, LEN);
+        out.extend_from_slice(&bignum::to_be(&self.field.p.from_mont(&element.0), LEN));
+        out.extend_from_slice(&bignum::to_be(&self.field.p.from_mont(&element.1), LEN));
+        out
+    }
+
+    /// Our commit: scalar ‖ element x ‖ element y.
+    pub fn commit(&self) -> Vec<u8> {
+        self.encode(&self.scalar, &self.element)
+    }
+
+    /// Take the peer's commit and derive the keys from it.
+    pub fn peer_commit(&mut self, commit: &[u8]) -> Result<(), &'static str> {
+        if commit.len() != COMMIT_LEN { return Err("bad commit length"); }
+        if commit == self.commit().as_slice() { return Err("commit reflected"); }
+        let num = |b: &[u8]| bignum::from_be(b, LIMBS).ok_or("bad commit");
+        let scalar = num(&commit[..LEN])?;
+        if bignum::bits(&scalar) <= 1 || bignum::cmp(&scalar, self.order.value()).is_ge() { return Err("bad commit scalar"); }
+        let (x, y) = (num(&commit[LEN..2 * LEN])?, num(&commit[2 * LEN..])?);
+        let p = self.field.p.value();
+        if bignum::cmp(&x, p).is_ge() || bignum::cmp(&y, p).is_ge() { return Err("bad commit element"); }
+        let (x, y) = (self.field.p.to_mont(&x), self.field.p.to_mont(&y));
+        if !self.field.on_curve(&x, &y) { return Err("commit element not on curve"); }
+
+        // K = rand·(scalar·PWE + element)
+        let mut one = [0u64; LIMBS];
+        one[0] = 1;
+        let peer = Point { x: x.clone(), y: y.clone(), z: self.field.p.one() };
+        let sum = self.field.mul2(&scalar, &self.pwe, &one, &peer);
+        let shared = self.field.mul_point(&self.rand, &sum);
+        let (mut k, _) = self.field.affine(&shared).ok_or("shared secret at infinity")?;
+        let mut k_bytes = bignum::to_be(&self.field.p.from_mont(&k), LEN);
+        zeroize(&mut k);
+
+        let mut keyseed = hmac_sha256(&[0; 32], &[&k_bytes]);
+        super::wipe(&mut k_bytes);
+        let context = bignum::to_be(&self.order.add(&self.scalar, &scalar), LEN);
+        let mut keys = [0u8; 64];
+        kdf_sha256(&keyseed, "SAE KCK and PMK", &context, &mut keys);
+        super::wipe(&mut keyseed);
+        self.kck.copy_from_slice(&keys[..32]);
+        self.pmk.copy_from_slice(&keys[32..]);
+        super::wipe(&mut keys);
+        self.pmkid.copy_from_slice(&context[..PMKID_LEN]);
+        zeroize(&mut self.rand);
+        self.peer = Some((scalar, (x, y)));
+        Ok(())
+    }
+
+    fn confirm_over(&self, send_confirm: u16, first: Vec<u8>, second: Vec<u8>) -> [u8; CONFIRM_LEN] {
+        hmac_sha256(&self.kck, &[&send_confirm.to_le_bytes(), &first, &second])
+    }
+
+    /// Our confirm, sent with counter `send_confirm`.
+    pub fn confirm(&self, send_confirm: u16) -> Result<[u8; CONFIRM_LEN], &'static str> {
+        let (scalar, element) = self.peer.as_ref().ok_or("no peer commit")?;
+        Ok(self.confirm_over(send_confirm, self.commit(), self.encode(scalar, element)))
+    }
+
+    /// Check the peer's confirm, sent with its counter `send_confirm`.
+    pub fn check_confirm(&self, send_confirm: u16, confirm: &[u8]) -> Result<(), &'static str> {
+        let (scalar, element) = self.peer.as_ref().ok_or("no peer commit")?;
+        let expect = self.confirm_over(send_confirm, self.encode(scalar, element), self.commit());
+        if confirm.len() != CONFIRM_LEN || expect.iter().zip(confirm).fold(0u8, |d, (a, b)| d | (a ^ b)) != 0 {
+            return Err("confirm mismatch");
+        }
+        Ok(())
+    }
+
+    pub fn pmk(&self) -> &[u8; PMK_LEN] {
+        &self.pmk
+    }
+
+    pub fn pmkid(&self) -> [u8; PMKID_LEN] {
+        self.pmkid
+    }
+}
+
+impl Drop for Sae {
+    fn drop(&mut self) {
+        zeroize(&mut self.rand);
+        super::wipe(&mut self.kck);
+        super::wipe(&mut self.pmk);
+    }
+}
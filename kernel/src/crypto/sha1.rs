@@ -0,0 +1,36 @@
+//! SHA-1 (FIPS 180-4)
+//! Only for hashing owner names in NSEC3 records (RFC 5155), where the
+//! zone chose it and collisions gain an attacker nothing; nothing the
+//! kernel trusts is signed over SHA-1.
+
+use alloc::vec::Vec;
+
+fn block(h: &mut [u32; 5], block: &[u8; 64]) {
+    let mut w = [0u32; 80];
+    for (i, c) in block.as_chunks::<4>().0.iter().enumerate() { w[i] = u32::from_be_bytes(*c); }
+    for i in 16..80 { w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1); }
+    let [mut a, mut b, mut c, mut d, mut e] = *h;
+    for (i, w) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..20  => ((b & c) | (!b & d), 0x5A827999),
+            20..40 => (b ^ c ^ d, 0x6ED9EBA1),
+            40..60 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _      => (b ^ c ^ d, 0xCA62C1D6),
+        };
+        let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*w);
+        (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+    }
+    for (s, v) in h.iter_mut().zip([a, b, c, d, e]) { *s = s.wrapping_add(v); }
+}
+
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut msg: Vec<u8> = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 { msg.push(0); }
+    msg.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for b in msg.as_chunks::<64>().0 { block(&mut h, b); }
+    let mut out = [0u8; 20];
+    for (o, h) in out.as_chunks_mut::<4>().0.iter_mut().zip(h) { *o = h.to_be_bytes(); }
+    out
+}
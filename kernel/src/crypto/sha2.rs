@@ -0,0 +1,225 @@
+//! SHA-2 (FIPS 180-4), HMAC (RFC 2104) and HKDF (RFC 5869)
+//! SHA-256 and SHA-384, incremental, plus HMAC and HKDF over SHA-256 as
+//! the TLS 1.3 key schedule uses them.  SHA-384 is here for certificate
+//! signatures made with it.  IEEE 802.11's KDF, an HMAC-SHA-256 counter
+//! construction, derives the WPA3 keys.
+
+const K256: [u32; 64] = [
+    0x428A2F98, 0x71374491, 0xB5C0FBCF, 0xE9B5DBA5, 0x3956C25B, 0x59F111F1, 0x923F82A4, 0xAB1C5ED5,
+    0xD807AA98, 0x12835B01, 0x243185BE, 0x550C7DC3, 0x72BE5D74, 0x80DEB1FE, 0x9BDC06A7, 0xC19BF174,
+    0xE49B69C1, 0xEFBE4786, 0x0FC19DC6, 0x240CA1CC, 0x2DE92C6F, 0x4A7484AA, 0x5CB0A9DC, 0x76F988DA,
+    0x983E5152, 0xA831C66D, 0xB00327C8, 0xBF597FC7, 0xC6E00BF3, 0xD5A79147, 0x06CA6351, 0x14292967,
+    0x27B70A85, 0x2E1B2138, 0x4D2C6DFC, 0x53380D13, 0x650A7354, 0x766A0ABB, 0x81C2C92E, 0x92722C85,
+    0xA2BFE8A1, 0xA81A664B, 0xC24B8B70, 0xC76C51A3, 0xD192E819, 0xD6990624, 0xF40E3585, 0x106AA070,
+    0x19A4C116, 0x1E376C08, 0x2748774C, 0x34B0BCB5, 0x391C0CB3, 0x4ED8AA4A, 0x5B9CCA4F, 0x682E6FF3,
+    0x748F82EE, 0x78A5636F, 0x84C87814, 0x8CC70208, 0x90BEFFFA, 0xA4506CEB, 0xBEF9A3F7, 0xC67178F2,
+];
+
+const K512: [u64; 80] = [
+    0x428A2F98D728AE22, 0x7137449123EF65CD, 0xB5C0FBCFEC4D3B2F, 0xE9B5DBA58189DBBC,
+    0x3956C25BF348B538, 0x59F111F1B605D019, 0x923F82A4AF194F9B, 0xAB1C5ED5DA6D8118,
+    0xD807AA98A3030242, 0x12835B0145706FBE, 0x243185BE4EE4B28C, 0x550C7DC3D5FFB4E2,
+    0x72BE5D74F27B896F, 0x80DEB1FE3B1696B1, 0x9BDC06A725C71235, 0xC19BF174CF692694,
+    0xE49B69C19EF14AD2, 0xEFBE4786384F25E3, 0x0FC19DC68B8CD5B5, 0x240CA1CC77AC9C65,
+    0x2DE92C6F592B0275, 0x4A7484AA6EA6E483, 0x5CB0A9DCBD41FBD4, 0x76F988DA831153B5,
+    0x983E5152EE66DFAB, 0xA831C66D2DB43210, 0xB00327C898FB213F, 0xBF597FC7BEEF0EE4,
+    0xC6E00BF33DA88FC2, 0xD5A79147930AA725, 0x06CA6351E003826F, 0x142929670A0E6E70,
+    0x27B70A8546D22FFC, 0x2E1B21385C26C926, 0x4D2C6DFC5AC42AED, 0x53380D139D95B3DF,
+    0x650A73548BAF63DE, 0x766A0ABB3C77B2A8, 0x81C2C92E47EDAEE6, 0x92722C851482353B,
+    0xA2BFE8A14CF10364, 0xA81A664BBC423001, 0xC24B8B70D0F89791, 0xC76C51A30654BE30,
+    0xD192E819D6EF5218, 0xD69906245565A910, 0xF40E35855771202A, 0x106AA07032BBD1B8,
+    0x19A4C116B8D2D0C8, 0x1E376C085141AB53, 0x2748774CDF8EEB99, 0x34B0BCB5E19B48A8,
+    0x391C0CB3C5C95A63, 0x4ED8AA4AE3418ACB, 0x5B9CCA4F7763E373, 0x682E6FF3D6B2B8A3,
+    0x748F82EE5DEFB2FC, 0x78A5636F43172F60, 0x84C87814A1F0AB72, 0x8CC702081A6439EC,
+    0x90BEFFFA23631E28, 0xA4506CEBDE82BDE9, 0xBEF9A3F7B2C67915, 0xC67178F2E372532B,
+    0xCA273ECEEA26619C, 0xD186B8C721C0C207, 0xEADA7DD6CDE0EB1E, 0xF57D4F7FEE6ED178,
+    0x06F067AA72176FBA, 0x0A637DC5A2C898A6, 0x113F9804BEF90DAE, 0x1B710B35131C471B,
+    0x28DB77F523047D84, 0x32CAAB7B40C72493, 0x3C9EBE0A15C9BEBC, 0x431D67C49C100D4C,
+    0x4CC5D4BECB3E42B6, 0x597F299CFC657E2A, 0x5FCB6FAB3AD6FAEC, 0x6C44198C4A475817,
+];
+
+// ─── SHA-256 ──────────────────────────────────────────────────────────────────
+
+#[derive(Clone)]
+pub struct Sha256 {
+    h:   [u32; 8],
+    buf: [u8; 64],
+    pos: usize,
+    len: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256 {
+            h: [0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A, 0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19],
+            buf: [0; 64], pos: 0, len: 0,
+        }
+    }
+}
+
+impl Sha256 {
+    fn block(&mut self, block: &[u8; 64]) {
+        let mut w = [0u32; 64];
+        for (i, c) in block.as_chunks::<4>().0.iter().enumerate() { w[i] = u32::from_be_bytes(*c); }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K256[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+        }
+        for (s, v) in self.h.iter_mut().zip([a, b, c, d, e, f, g, h]) { *s = s.wrapping_add(v); }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        while !data.is_empty() {
+            let n = (64 - self.pos).min(data.len());
+            self.buf[self.pos..self.pos + n].copy_from_slice(&data[..n]);
+            self.pos += n;
+            data = &data[n..];
+            if self.pos == 64 {
+                let b = self.buf;
+                self.block(&b);
+                self.pos = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> [u8; 32] {
+        let bits = self.len * 8;
+        self.update(&[0x80]);
+        while self.pos != 56 { self.update(&[0]); }
+        self.update(&bits.to_be_bytes());
+        let mut out = [0u8; 32];
+        for (o, h) in out.as_chunks_mut::<4>().0.iter_mut().zip(self.h) { *o = h.to_be_bytes(); }
+        out
+    }
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h = Sha256::default();
+    h.update(data);
+    h.finish()
+}
+
+// ─── SHA-384 ──────────────────────────────────────────────────────────────────
+
+#[derive(Clone)]
+pub struct Sha384 {
+    h:   [u64; 8],
+    buf: [u8; 128],
+    pos: usize,
+    len: u128,
+}
+
+impl Default for Sha384 {
+    fn default() -> Self {
+        Sha384 {
+            h: [
+                0xCBBB9D5DC1059ED8, 0x629A292A367CD507, 0x9159015A3070DD17, 0x152FECD8F70E5939,
+                0x67332667FFC00B31, 0x8EB44A8768581511, 0xDB0C2E0D64F98FA7, 0x47B5481DBEFA4FA4,
+            ],
+            buf: [0; 128], pos: 0, len: 0,
+        }
+    }
+}
+
+impl Sha384 {
+    fn block(&mut self, block: &[u8; 128]) {
+        let mut w = [0u64; 80];
+        for (i, c) in block.as_chunks::<8>().0.iter().enumerate() { w[i] = u64::from_be_bytes(*c); }
+        for i in 16..80 {
+            let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+            let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.h;
+        for i in 0..80 {
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K512[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+        }
+        for (s, v) in self.h.iter_mut().zip([a, b, c, d, e, f, g, h]) { *s = s.wrapping_add(v); }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u128;
+        while !data.is_empty() {
+            let n = (128 - self.pos).min(data.len());
+            self.buf[self.pos..self.pos + n].copy_from_slice(&data[..n]);
+            self.pos += n;
+            data = &data[n..];
+            if self.pos == 128 {
+                let b = self.buf;
+                self.block(&b);
+                self.pos = 0;
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> [u8; 48] {
+        let bits = self.len * 8;
+        self.update(&[0x80]);
+        while self.pos != 112 { self.update(&[0]); }
+        self.update(&bits.to_be_bytes());
+        let mut out = [0u8; 48];
+        for (o, h) in out.as_chunks_mut::<8>().0.iter_mut().zip(self.h) { *o = h.to_be_bytes(); }
+        out
+    }
+}
+
+pub fn sha384(data: &[u8]) -> [u8; 48] {
+    let mut h = Sha384::default();
+    h.update(data);
+    h.finish()
+}
+
+// ─── HMAC and HKDF over SHA-256 ───────────────────────────────────────────────
+
+pub fn hmac_sha256(key: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+    let mut k = [0u8; 64];
+    if key.len() > 64 { k[..32].copy_from_slice(&sha256(key)); } else { k[..key.len()].copy_from_slice(key); }
+    let mut inner = Sha256::default();
+    inner.update(&k.map(|b| b ^ 0x36));
+    for p in parts { inner.update(p); }
+    let mut outer = Sha256::default();
+    outer.update(&k.map(|b| b ^ 0x5C));
+    outer.update(&inner.finish());
+    outer.finish()
+}
+
+pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
+    hmac_sha256(salt, &[ikm])
+}
+
+/// Fill `out` (at most 255 hash lengths) from pseudorandom key `prk`.
+pub fn hkdf_expand(prk: &[u8], info: &[u8], out: &mut [u8]) {
+    let mut t: [u8; 32] = [0; 32];
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let prev: &[u8] = if i == 0 { &[] } else { &t };
+        t = hmac_sha256(prk, &[prev, info, &[i as u8 + 1]]);
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
+
+/// IEEE 802.11 KDF-SHA-256: fill `out` from `key`, `label` and `context`.
+pub fn kdf_sha256(key: &[u8], label: &str, context: &[u8], out: &mut [u8]) {
+    let bits = (out.len() as u16 * 8).to_le_bytes();
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let block = hmac_sha256(key, &[&(i as u16 + 1).to_le_bytes(), label.as_bytes(), context, &bits]);
+        chunk.copy_from_slice(&block[..chunk.len()]);
+    }
+}
@@ -0,0 +1,125 @@
+//! SHA-3 / SHAKE (FIPS 202)
+//! Keccak-f[1600] sponge with the SHA3-256, SHAKE128 and SHAKE256
+//! instances.  SHAKE is incremental: absorb any number of times, then
+//! squeeze as much output as needed.
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001, 0x0000000000008082, 0x800000000000808A, 0x8000000080008000,
+    0x000000000000808B, 0x0000000080000001, 0x8000000080008081, 0x8000000000008009,
+    0x000000000000008A, 0x0000000000000088, 0x0000000080008009, 0x000000008000000A,
+    0x000000008000808B, 0x800000000000008B, 0x8000000000008089, 0x8000000000008003,
+    0x8000000000008002, 0x8000000000000080, 0x000000000000800A, 0x800000008000000A,
+    0x8000000080008081, 0x8000000000008080, 0x0000000080000001, 0x8000000080008008,
+];
+
+/// Rotation offsets, indexed x + 5y.
+const RHO: [u32; 25] = [
+     0,  1, 62, 28, 27,
+    36, 44,  6, 55, 20,
+     3, 10, 43, 25, 39,
+    41, 45, 15, 21,  8,
+    18,  2, 61, 56, 14,
+];
+
+fn keccak_f(a: &mut [u64; 25]) {
+    for rc in ROUND_CONSTANTS {
+        // θ
+        let mut c = [0u64; 5];
+        for x in 0..5 {
+            c[x] = a[x] ^ a[x + 5] ^ a[x + 10] ^ a[x + 15] ^ a[x + 20];
+        }
+        for x in 0..5 {
+            let d = c[(x + 4) % 5] ^ c[(x + 1) % 5].rotate_left(1);
+            for y in 0..5 { a[x + 5 * y] ^= d; }
+        }
+        // ρ and π
+        let mut b = [0u64; 25];
+        for x in 0..5 {
+            for y in 0..5 {
+                b[y + 5 * ((2 * x + 3 * y) % 5)] = a[x + 5 * y].rotate_left(RHO[x + 5 * y]);
+            }
+        }
+        // χ
+        for y in 0..5 {
+            for x in 0..5 {
+                a[x + 5 * y] = b[x + 5 * y] ^ (!b[(x + 1) % 5 + 5 * y] & b[(x + 2) % 5 + 5 * y]);
+            }
+        }
+        // ι
+        a[0] ^= rc;
+    }
+}
+
+/// Keccak sponge with a byte-oriented rate.
+#[derive(Clone)]
+pub struct Sponge {
+    state:     [u64; 25],
+    rate:      usize,
+    pos:       usize,
+    domain:    u8,
+    squeezing: bool,
+}
+
+impl Sponge {
+    const fn new(rate: usize, domain: u8) -> Self {
+        Sponge { state: [0; 25], rate, pos: 0, domain, squeezing: false }
+    }
+
+    fn xor_byte(&mut self, i: usize, b: u8) {
+        self.state[i / 8] ^= (b as u64) << (8 * (i % 8));
+    }
+
+    fn byte(&self, i: usize) -> u8 {
+        (self.state[i / 8] >> (8 * (i % 8))) as u8
+    }
+
+    pub fn absorb(&mut self, data: &[u8]) {
+        debug_assert!(!self.squeezing, "absorb after squeeze");
+        for &b in data {
+            self.xor_byte(self.pos, b);
+            self.pos += 1;
+            if self.pos == self.rate {
+                keccak_f(&mut self.state);
+                self.pos = 0;
+            }
+        }
+    }
+
+    fn finalize(&mut self) {
+        self.xor_byte(self.pos, self.domain);
+        self.xor_byte(self.rate - 1, 0x80);
+        keccak_f(&mut self.state);
+        self.pos = 0;
+        self.squeezing = true;
+    }
+
+    pub fn squeeze(&mut self, out: &mut [u8]) {
+        if !self.squeezing { self.finalize(); }
+        for o in out.iter_mut() {
+            if self.pos == self.rate {
+                keccak_f(&mut self.state);
+                self.pos = 0;
+            }
+            *o = self.byte(self.pos);
+            self.pos += 1;
+        }
+    }
+}
+
+pub fn shake128() -> Sponge { Sponge::new(168, 0x1F) }
+pub fn shake256() -> Sponge { Sponge::new(136, 0x1F) }
+
+/// One-shot SHAKE256 of `data` into `out`.
+pub fn shake256_into(data: &[u8], out: &mut [u8]) {
+    let mut s = shake256();
+    s.absorb(data);
+    s.squeeze(out);
+}
+
+pub fn sha3_256(data: &[u8]) -> [u8; 32] {
+    let mut s = Sponge::new(136, 0x06);
+    s.absorb(data);
+    let mut out = [0u8; 32];
+    s.squeeze(&mut out);
+    out
+}
@@ -0,0 +1,221 @@
+//! SLH-DSA-SHAKE-128s Signature Verification (FIPS 205)
+//! Stateless hash-based signatures, used to verify the boot chain: their
+//! security rests on SHAKE-256 alone, which suits a check that has to
+//! stay sound for the life of the device.  Only verification is
+//! implemented, and only the pure (non-prehash) form, with the message
+//! bound to a context string as in ML-DSA.
+
+use alloc::vec::Vec;
+
+use super::sha3::shake256;
+
+// ─── parameters (SLH-DSA-SHAKE-128s) ──────────────────────────────────────────
+
+const N:     usize = 16;
+const H:     usize = 63;
+const D:     usize = 7;
+const HP:    usize = H / D;  // 9, height of each XMSS tree
+const A:     usize = 12;
+const K:     usize = 14;
+const LG_W:  usize = 4;
+const W:     u32   = 1 << LG_W;
+const M:     usize = 30;
+const LEN1:  usize = 8 * N / LG_W;  // 32
+const LEN2:  usize = 3;
+const LEN:   usize = LEN1 + LEN2;
+
+const FORS_SIG_LEN: usize = K * (1 + A) * N;
+const XMSS_SIG_LEN: usize = (LEN + HP) * N;
+
+pub const PUBLIC_KEY_LEN: usize = 2 * N;                                   // 32
+pub const SIGNATURE_LEN:  usize = N + FORS_SIG_LEN + D * XMSS_SIG_LEN;     // 7856
+
+// ─── addresses ────────────────────────────────────────────────────────────────
+
+const WOTS_HASH:  u32 = 0;
+const WOTS_PK:    u32 = 1;
+const TREE:       u32 = 2;
+const FORS_TREE:  u32 = 3;
+const FORS_ROOTS: u32 = 4;
+
+/// The 32-byte hash address (ADRS) that separates every call of the
+/// tweakable hash.
+#[derive(Clone, Copy)]
+struct Adrs([u8; 32]);
+
+impl Adrs {
+    fn set(&mut self, at: usize, v: u32) {
+        self.0[at..at + 4].copy_from_slice(&v.to_be_bytes());
+    }
+
+    fn get(&self, at: usize) -> u32 {
+        u32::from_be_bytes([self.0[at], self.0[at + 1], self.0[at + 2], self.0[at + 3]])
+    }
+
+    fn set_layer(&mut self, layer: u32)   { self.set(0, layer); }
+    fn set_tree(&mut self, tree: u64) {
+        self.0[4..8].fill(0);
+        self.0[8..16].copy_from_slice(&tree.to_be_bytes());
+    }
+    /// Set the type and clear the three words after it.
+    fn set_type(&mut self, ty: u32) {
+        self.set(16, ty);
+        self.0[20..].fill(0);
+    }
+    fn set_key_pair(&mut self, kp: u32)   { self.set(20, kp); }
+    fn key_pair(&self) -> u32             { self.get(20) }
+    fn set_chain(&mut self, i: u32)       { self.set(24, i); }
+    fn set_tree_height(&mut self, h: u32) { self.set(24, h); }
+    fn set_hash(&mut self, i: u32)        { self.set(28, i); }
+    fn set_tree_index(&mut self, i: u32)  { self.set(28, i); }
+    fn tree_index(&self) -> u32           { self.get(28) }
+}
+
+// ─── hashing ──────────────────────────────────────────────────────────────────
+
+type Node = [u8; N];
+
+/// The tweakable hash F, H and T_l: SHAKE-256(PK.seed ‖ ADRS ‖ M).
+fn thash(seed: &[u8], adrs: &Adrs, parts: &[&[u8]]) -> Node {
+    let mut s = shake256();
+    s.absorb(seed);
+    s.absorb(&adrs.0);
+    for p in parts { s.absorb(p); }
+    let mut out = [0u8; N];
+    s.squeeze(&mut out);
+    out
+}
+
+/// Split `x` into `out.len()` integers of `b` bits each, big-endian.
+fn base_2b(x: &[u8], b: usize, out: &mut [u32]) {
+    let (mut bits, mut total, mut bytes) = (0usize, 0u64, x.iter());
+    for o in out.iter_mut() {
+        while bits < b {
+            total = total << 8 | *bytes.next().unwrap_or(&0) as u64;
+            bits += 8;
+        }
+        bits -= b;
+        *o = (total >> bits) as u32 & ((1 << b) - 1);
+    }
+}
+
+/// Climb an authentication path from `node`, the leaf at `idx`.
+fn climb(mut node: Node, mut idx: u32, auth: &[u8], seed: &[u8], adrs: &mut Adrs) -> Node {
+    for (j, sibling) in auth.chunks(N).enumerate() {
+        adrs.set_tree_height(j as u32 + 1);
+        node = if idx & 1 == 0 {
+            adrs.set_tree_index(adrs.tree_index() / 2);
+            thash(seed, adrs, &[&node, sibling])
+        } else {
+            adrs.set_tree_index((adrs.tree_index() - 1) / 2);
+            thash(seed, adrs, &[sibling, &node])
+        };
+        idx >>= 1;
+    }
+    node
+}
+
+// ─── WOTS+, XMSS, hypertree ───────────────────────────────────────────────────
+
+fn wots_pk_from_sig(sig: &[u8], msg: &Node, seed: &[u8], adrs: &mut Adrs) -> Node {
+    let mut digits = [0u32; LEN];
+    base_2b(msg, LG_W, &mut digits[..LEN1]);
+    let csum: u32 = digits[..LEN1].iter().map(|d| W - 1 - d).sum();
+    let csum = csum << ((8 - (LEN2 * LG_W) % 8) % 8);
+    base_2b(&(csum as u16).to_be_bytes(), LG_W, &mut digits[LEN1..]);
+
+    let mut tops = Vec::with_capacity(LEN * N);
+    for (i, (chunk, &d)) in sig.chunks(N).zip(digits.iter()).enumerate() {
+        adrs.set_chain(i as u32);
+        let mut node: Node = chunk.try_into().unwrap_or([0; N]);
+        for j in d..W - 1 {
+            adrs.set_hash(j);
+            node = thash(seed, adrs, &[&node]);
+        }
+        tops.extend_from_slice(&node);
+    }
+    let mut pk_adrs = *adrs;
+    pk_adrs.set_type(WOTS_PK);
+    pk_adrs.set_key_pair(adrs.key_pair());
+    thash(seed, &pk_adrs, &[&tops])
+}
+
+fn xmss_pk_from_sig(idx: u32, sig: &[u8], msg: &Node, seed: &[u8], adrs: &mut Adrs) -> Node {
+    adrs.set_type(WOTS_HASH);
+    adrs.set_key_pair(idx);
+    let leaf = wots_pk_from_sig(&sig[..LEN * N], msg, seed, adrs);
+    adrs.set_type(TREE);
+    adrs.set_tree_index(idx);
+    climb(leaf, idx, &sig[LEN * N..XMSS_SIG_LEN], seed, adrs)
+}
+
+fn ht_verify(msg: &Node, sig: &[u8], seed: &[u8], mut tree: u64, mut leaf: u32, root: &[u8]) -> bool {
+    let mut adrs = Adrs([0; 32]);
+    adrs.set_tree(tree);
+    let mut node = xmss_pk_from_sig(leaf, &sig[..XMSS_SIG_LEN], msg, seed, &mut adrs);
+    for j in 1..D {
+        leaf = (tree & ((1 << HP) - 1)) as u32;
+        tree >>= HP;
+        adrs.set_layer(j as u32);
+        adrs.set_tree(tree);
+        node = xmss_pk_from_sig(leaf, &sig[j * XMSS_SIG_LEN..], &node, seed, &mut adrs);
+    }
+    node[..] == *root
+}
+
+// ─── FORS ─────────────────────────────────────────────────────────────────────
+
+fn fors_pk_from_sig(sig: &[u8], md: &[u8], seed: &[u8], adrs: &mut Adrs) -> Node {
+    let mut indices = [0u32; K];
+    base_2b(md, A, &mut indices);
+    let mut roots = Vec::with_capacity(K * N);
+    for (i, (part, &idx)) in sig.chunks((1 + A) * N).zip(indices.iter()).enumerate() {
+        adrs.set_tree_height(0);
+        adrs.set_tree_index(((i as u32) << A) + idx);
+        let leaf = thash(seed, adrs, &[&part[..N]]);
+        roots.extend_from_slice(&climb(leaf, idx, &part[N..], seed, adrs));
+    }
+    let mut pk_adrs = *adrs;
+    pk_adrs.set_type(FORS_ROOTS);
+    pk_adrs.set_key_pair(adrs.key_pair());
+    thash(seed, &pk_adrs, &[&roots])
+}
+
+// ─── verification ─────────────────────────────────────────────────────────────
+
+/// Verify an SLH-DSA-SHAKE-128s signature over `msg` under context string
+/// `ctx` (at most 255 bytes).
+pub fn verify(public_key: &[u8], msg: &[u8], ctx: &[u8], sig: &[u8]) -> bool {
+    if public_key.len() != PUBLIC_KEY_LEN || sig.len() != SIGNATURE_LEN || ctx.len() > 255 {
+        return false;
+    }
+    let (seed, root) = public_key.split_at(N);
+    let (r, rest) = sig.split_at(N);
+    let (sig_fors, sig_ht) = rest.split_at(FORS_SIG_LEN);
+
+    // digest = H_msg(R, PK.seed, PK.root, 0 ‖ |ctx| ‖ ctx ‖ M)
+    let mut digest = [0u8; M];
+    let mut s = shake256();
+    s.absorb(r);
+    s.absorb(seed);
+    s.absorb(root);
+    s.absorb(&[0, ctx.len() as u8]);
+    s.absorb(ctx);
+    s.absorb(msg);
+    s.squeeze(&mut digest);
+
+    const MD_LEN:   usize = (K * A).div_ceil(8);       // 21
+    const TREE_LEN: usize = (H - HP).div_ceil(8);      // 7
+    let md = &digest[..MD_LEN];
+    let tree = digest[MD_LEN..MD_LEN + TREE_LEN].iter().fold(0u64, |acc, &b| acc << 8 | b as u64);
+    let tree = tree & ((1 << (H - HP)) - 1);
+    let leaf = u16::from_be_bytes([digest[MD_LEN + TREE_LEN], digest[MD_LEN + TREE_LEN + 1]]) as u32;
+    let leaf = leaf & ((1 << HP) - 1);
+
+    let mut adrs = Adrs([0; 32]);
+    adrs.set_tree(tree);
+    adrs.set_type(FORS_TREE);
+    adrs.set_key_pair(leaf);
+    let pk_fors = fors_pk_from_sig(sig_fors, md, seed, &mut adrs);
+    ht_verify(&pk_fors, sig_ht, seed, tree, leaf, root)
+}
@@ -0,0 +1,163 @@
+//! X25519 (RFC 7748)
+//! Diffie-Hellman over Curve25519 with the Montgomery ladder, in constant
+//! time.  Field elements are five 51-bit limbs.
+
+pub const KEY_LEN: usize = 32;
+
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+const MASK: u64 = (1 << 51) - 1;
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE:  Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_bytes(b: &[u8; 32]) -> Fe {
+        let load = |i: usize| u64::from_le_bytes(b[i..i + 8].try_into().unwrap());
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut h = self.carry().0;
+        // Subtract p if h >= p: add 19 and see whether it carries past 2^255
+        let mut q = (h[0] + 19) >> 51;
+        for l in &h[1..] { q = (l + q) >> 51; }
+        h[0] += 19 * q;
+        for i in 0..4 { h[i + 1] += h[i] >> 51; h[i] &= MASK; }
+        h[4] &= MASK;
+        let mut out = [0u8; 32];
+        let words = [
+            h[0] | (h[1] << 51),
+            (h[1] >> 13) | (h[2] << 38),
+            (h[2] >> 26) | (h[3] << 25),
+            (h[3] >> 39) | (h[4] << 12),
+        ];
+        for (o, w) in out.as_chunks_mut::<8>().0.iter_mut().zip(words) { *o = w.to_le_bytes(); }
+        out
+    }
+
+    fn carry(self) -> Fe {
+        let mut h = self.0;
+        for i in 0..4 { h[i + 1] += h[i] >> 51; h[i] &= MASK; }
+        h[0] += 19 * (h[4] >> 51);
+        h[4] &= MASK;
+        h[1] += h[0] >> 51;
+        h[0] &= MASK;
+        Fe(h)
+    }
+
+    fn add(self, o: Fe) -> Fe {
+        Fe(core::array::from_fn(|i| self.0[i] + o.0[i])).carry()
+    }
+
+    /// `self - o`, adding 2p first so no limb underflows.
+    fn sub(self, o: Fe) -> Fe {
+        const TWO_P: [u64; 5] = [0xFFFFFFFFFFFDA, 0xFFFFFFFFFFFFE, 0xFFFFFFFFFFFFE, 0xFFFFFFFFFFFFE, 0xFFFFFFFFFFFFE];
+        Fe(core::array::from_fn(|i| self.0[i] + TWO_P[i] - o.0[i])).carry()
+    }
+
+    fn mul(self, o: Fe) -> Fe {
+        let a = self.0.map(|x| x as u128);
+        let b = o.0.map(|x| x as u128);
+        let b19 = b.map(|x| x * 19);
+        let mut t = [0u128; 5];
+        t[0] = a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1];
+        t[1] = a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2];
+        t[2] = a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3];
+        t[3] = a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4];
+        t[4] = a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0];
+        for i in 0..4 { t[i + 1] += t[i] >> 51; t[i] &= MASK as u128; }
+        let mut h = t.map(|x| x as u64 & MASK);
+        h[0] += 19 * (t[4] >> 51) as u64;
+        Fe(h).carry()
+    }
+
+    fn square(self) -> Fe {
+        self.mul(self)
+    }
+
+    fn mul_small(self, k: u64) -> Fe {
+        let t = self.0.map(|x| x as u128 * k as u128);
+        let mut h = [0u64; 5];
+        let mut c = 0u128;
+        for i in 0..5 { let v = t[i] + c; h[i] = v as u64 & MASK; c = v >> 51; }
+        h[0] += 19 * c as u64;
+        Fe(h).carry()
+    }
+
+    /// self^(p-2)
+    fn invert(self) -> Fe {
+        let sq = |mut x: Fe, n: usize| { for _ in 0..n { x = x.square(); } x };
+        let z2 = self.square();
+        let z9 = sq(z2, 2).mul(self);
+        let z11 = z9.mul(z2);
+        let z2_5_0 = z11.square().mul(z9);
+        let z2_10_0 = sq(z2_5_0, 5).mul(z2_5_0);
+        let z2_20_0 = sq(z2_10_0, 10).mul(z2_10_0);
+        let z2_40_0 = sq(z2_20_0, 20).mul(z2_20_0);
+        let z2_50_0 = sq(z2_40_0, 10).mul(z2_10_0);
+        let z2_100_0 = sq(z2_50_0, 50).mul(z2_50_0);
+        let z2_200_0 = sq(z2_100_0, 100).mul(z2_100_0);
+        let z2_250_0 = sq(z2_200_0, 50).mul(z2_50_0);
+        sq(z2_250_0, 5).mul(z11)
+    }
+}
+
+fn swap(a: &mut Fe, b: &mut Fe, bit: u64) {
+    let mask = 0u64.wrapping_sub(bit);
+    for i in 0..5 {
+        let t = mask & (a.0[i] ^ b.0[i]);
+        a.0[i] ^= t;
+        b.0[i] ^= t;
+    }
+}
+
+/// The shared secret of `scalar` and peer public key `point`.
+pub fn x25519(scalar: &[u8; KEY_LEN], point: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+    let mut u = *point;
+    u[31] &= 127;
+    let x1 = Fe::from_bytes(&u);
+    let (mut x2, mut z2, mut x3, mut z3) = (Fe::ONE, Fe::ZERO, x1, Fe::ONE);
+    let mut swapped = 0;
+    for t in (0..255).rev() {
+        let bit = ((k[t / 8] >> (t % 8)) & 1) as u64;
+        swapped ^= bit;
+        swap(&mut x2, &mut x3, swapped);
+        swap(&mut z2, &mut z3, swapped);
+        swapped = bit;
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(121665)));
+    }
+    swap(&mut x2, &mut x3, swapped);
+    swap(&mut z2, &mut z3, swapped);
+    x2.mul(z2.invert()).to_bytes()
+}
+
+/// The public key for private key `scalar`.
+pub fn public_key(scalar: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut base = [0u8; KEY_LEN];
+    base[0] = 9;
+    x25519(scalar, &base)
+}
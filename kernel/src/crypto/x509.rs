@@ -0,0 +1,309 @@
+//! X.509 Certificates (RFC 5280)
+//! Just enough DER to check a TLS server's chain: the signed part and its
+//! signature, names, validity, public key, subjectAltName DNS names and
+//! basicConstraints.  Certificates carrying a critical extension we do not
+//! understand are rejected rather than half-checked.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::ecdsa::{self, Curve};
+use super::{rsa, sha2};
+
+// ─── DER ──────────────────────────────────────────────────────────────────────
+
+/// The first TLV of `input`: (tag, contents, what follows).
+pub fn der_next(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    let (len, rest) = match first {
+        0..=0x7F => (first as usize, rest),
+        0x81..=0x83 => {
+            let n = (first & 0x7F) as usize;
+            if rest.len() < n { return None; }
+            let len = rest[..n].iter().fold(0usize, |l, &b| l << 8 | b as usize);
+            (len, &rest[n..])
+        }
+        _ => return None,
+    };
+    if rest.len() < len { return None; }
+    Some((tag, &rest[..len], &rest[len..]))
+}
+
+/// The whole first TLV of `input`, header included, and what follows.
+fn der_raw(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (_, _, rest) = der_next(input)?;
+    Some(input.split_at(input.len() - rest.len()))
+}
+
+fn expect(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match der_next(input)? {
+        (t, body, rest) if t == tag => Some((body, rest)),
+        _ => None,
+    }
+}
+
+const BOOLEAN:      u8 = 0x01;
+const INTEGER:      u8 = 0x02;
+const BIT_STRING:   u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OID:          u8 = 0x06;
+const SEQUENCE:     u8 = 0x30;
+
+// ─── object identifiers ───────────────────────────────────────────────────────
+
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+const OID_SHA256_RSA:     &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0B];
+const OID_SHA384_RSA:     &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0C];
+const OID_EC_PUBLIC_KEY:  &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+const OID_ECDSA_SHA256:   &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02];
+const OID_ECDSA_SHA384:   &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x03];
+const OID_P256:           &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+const OID_P384:           &[u8] = &[0x2B, 0x81, 0x04, 0x00, 0x22];
+
+const OID_SUBJECT_KEY_ID:    &[u8] = &[0x55, 0x1D, 0x0E];
+const OID_KEY_USAGE:         &[u8] = &[0x55, 0x1D, 0x0F];
+const OID_SUBJECT_ALT_NAME:  &[u8] = &[0x55, 0x1D, 0x11];
+const OID_BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1D, 0x13];
+const OID_POLICIES:          &[u8] = &[0x55, 0x1D, 0x20];
+const OID_AUTHORITY_KEY_ID:  &[u8] = &[0x55, 0x1D, 0x23];
+const OID_EXT_KEY_USAGE:     &[u8] = &[0x55, 0x1D, 0x25];
+
+// ─── types ────────────────────────────────────────────────────────────────────
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Hash {
+    Sha256,
+    Sha384,
+}
+
+impl Hash {
+    pub fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Hash::Sha256 => sha2::sha256(data).to_vec(),
+            Hash::Sha384 => sha2::sha384(data).to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SignatureAlgorithm {
+    RsaPkcs1(Hash),
+    RsaPss(Hash),
+    Ecdsa(Hash),
+}
+
+#[derive(Clone, Debug)]
+pub enum PublicKey {
+    /// Big-endian modulus and public exponent.
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    /// Uncompressed SEC1 point.
+    Ec { curve: Curve, point: Vec<u8> },
+}
+
+impl PublicKey {
+    /// Check `signature` over `message` made with this key.
+    pub fn verify(&self, alg: SignatureAlgorithm, message: &[u8], signature: &[u8]) -> Result<(), &'static str> {
+        match (self, alg) {
+            (PublicKey::Rsa { n, e }, SignatureAlgorithm::RsaPkcs1(h)) => rsa::verify_pkcs1(n, e, h, &h.digest(message), signature),
+            (PublicKey::Rsa { n, e }, SignatureAlgorithm::RsaPss(h))   => rsa::verify_pss(n, e, h, &h.digest(message), signature),
+            (PublicKey::Ec { curve, point }, SignatureAlgorithm::Ecdsa(h)) => {
+                let (r, s) = ecdsa::parse_der_signature(signature).ok_or("bad ECDSA signature")?;
+                ecdsa::verify(*curve, point, &h.digest(message), r, s)
+            }
+            _ => Err("signature algorithm does not match key"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Certificate {
+    /// The signed TBSCertificate, header included.
+    pub tbs:        Vec<u8>,
+    pub sig_alg:    SignatureAlgorithm,
+    pub signature:  Vec<u8>,
+    /// DER of the issuer and subject Names, compared byte for byte.
+    pub issuer:     Vec<u8>,
+    pub subject:    Vec<u8>,
+    /// Validity period, seconds since the Unix epoch.
+    pub not_before: u64,
+    pub not_after:  u64,
+    pub key:        PublicKey,
+    /// SHA-256 of the DER SubjectPublicKeyInfo, as key pins name it.
+    pub spki_hash:  [u8; 32],
+    pub dns_names:  Vec<String>,
+    pub is_ca:      bool,
+    pub path_len:   Option<u32>,
+}
+
+// ─── parsing ──────────────────────────────────────────────────────────────────
+
+fn signature_algorithm(alg_id: &[u8]) -> Option<SignatureAlgorithm> {
+    let (oid, _) = expect(alg_id, OID)?;
+    Some(match oid {
+        OID_SHA256_RSA   => SignatureAlgorithm::RsaPkcs1(Hash::Sha256),
+        OID_SHA384_RSA   => SignatureAlgorithm::RsaPkcs1(Hash::Sha384),
+        OID_ECDSA_SHA256 => SignatureAlgorithm::Ecdsa(Hash::Sha256),
+        OID_ECDSA_SHA384 => SignatureAlgorithm::Ecdsa(Hash::Sha384),
+        _ => return None,
+    })
+}
+
+/// Parse a DER SubjectPublicKeyInfo.
+pub fn parse_spki(spki: &[u8]) -> Option<PublicKey> {
+    let (body, _) = expect(spki, SEQUENCE)?;
+    let (alg_id, rest) = expect(body, SEQUENCE)?;
+    let (bits, _) = expect(rest, BIT_STRING)?;
+    let (&0, key) = bits.split_first()? else { return None };
+    let (oid, params) = expect(alg_id, OID)?;
+    match oid {
+        OID_RSA_ENCRYPTION => {
+            let (body, _) = expect(key, SEQUENCE)?;
+            let (n, rest) = expect(body, INTEGER)?;
+            let (e, _) = expect(rest, INTEGER)?;
+            let strip = |v: &[u8]| v[v.iter().position(|&b| b != 0).unwrap_or(v.len())..].to_vec();
+            Some(PublicKey::Rsa { n: strip(n), e: strip(e) })
+        }
+        OID_EC_PUBLIC_KEY => {
+            let curve = match expect(params, OID)?.0 {
+                OID_P256 => Curve::P256,
+                OID_P384 => Curve::P384,
+                _ => return None,
+            };
+            Some(PublicKey::Ec { curve, point: key.to_vec() })
+        }
+        _ => None,
+    }
+}
+
+/// UTCTime or GeneralizedTime to seconds since the epoch.
+fn parse_time(input: &[u8]) -> Option<(u64, &[u8])> {
+    let (tag, body, rest) = der_next(input)?;
+    let digits = |s: &[u8]| s.iter().try_fold(0u64, |v, &c| c.is_ascii_digit().then(|| v * 10 + (c - b'0') as u64));
+    let (year, body) = match (tag, body.len()) {
+        (0x17, 13) => { let y = digits(&body[..2])?; (if y < 50 { 2000 + y } else { 1900 + y }, &body[2..]) }
+        (0x18, 15) => (digits(&body[..4])?, &body[4..]),
+        _ => return None,
+    };
+    if body[10] != b'Z' { return None; }
+    let field = |i: usize| digits(&body[i..i + 2]);
+    let (month, day) = (field(0)?, field(2)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) { return None; }
+    // Days from civil (proleptic Gregorian), March-based year
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = (era * 146_097 + doe).checked_sub(719_468)?;
+    Some((days * 86_400 + field(4)? * 3600 + field(6)? * 60 + field(8)?, rest))
+}
+
+fn parse_extensions(cert: &mut Certificate, input: &[u8]) -> Option<()> {
+    let (mut exts, _) = expect(input, SEQUENCE)?;
+    while !exts.is_empty() {
+        let (ext, rest) = expect(exts, SEQUENCE)?;
+        exts = rest;
+        let (oid, mut ext) = expect(ext, OID)?;
+        let mut critical = false;
+        if let Some((flag, rest)) = expect(ext, BOOLEAN) {
+            critical = flag.first().is_some_and(|&b| b != 0);
+            ext = rest;
+        }
+        let (value, _) = expect(ext, OCTET_STRING)?;
+        match oid {
+            OID_SUBJECT_ALT_NAME => {
+                let (mut names, _) = expect(value, SEQUENCE)?;
+                while !names.is_empty() {
+                    let (tag, name, rest) = der_next(names)?;
+                    names = rest;
+                    // dNSName [2] IA5String
+                    if tag == 0x82 { cert.dns_names.push(String::from(core::str::from_utf8(name).ok()?)); }
+                }
+            }
+            OID_BASIC_CONSTRAINTS => {
+                let (mut body, _) = expect(value, SEQUENCE)?;
+                if let Some((flag, rest)) = expect(body, BOOLEAN) {
+                    cert.is_ca = flag.first().is_some_and(|&b| b != 0);
+                    body = rest;
+                }
+                if let Some((len, _)) = expect(body, INTEGER) {
+                    cert.path_len = Some(len.iter().fold(0u32, |v, &b| v.saturating_mul(256).saturating_add(b as u32)));
+                }
+            }
+            OID_KEY_USAGE | OID_EXT_KEY_USAGE | OID_POLICIES | OID_SUBJECT_KEY_ID | OID_AUTHORITY_KEY_ID => {}
+            _ if critical => return None,
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+/// Parse a DER certificate.
+pub fn parse(der: &[u8]) -> Result<Certificate, &'static str> {
+    parse_inner(der).ok_or("malformed or unsupported certificate")
+}
+
+fn parse_inner(der: &[u8]) -> Option<Certificate> {
+    let (cert, _) = expect(der, SEQUENCE)?;
+    let (tbs, rest) = der_raw(cert)?;
+    let (alg_id, rest) = expect(rest, SEQUENCE)?;
+    let (sig, _) = expect(rest, BIT_STRING)?;
+    let (&0, signature) = sig.split_first()? else { return None };
+    let sig_alg = signature_algorithm(alg_id)?;
+
+    let (mut t, _) = expect(tbs, SEQUENCE)?;
+    if let Some((_, rest)) = expect(t, 0xA0) { t = rest; }        // version
+    let (_, t) = expect(t, INTEGER)?;                              // serialNumber
+    let (inner_alg, t) = expect(t, SEQUENCE)?;
+    if signature_algorithm(inner_alg)? != sig_alg { return None; }
+    let (issuer, t) = der_raw(t)?;
+    let (validity, t) = expect(t, SEQUENCE)?;
+    let (not_before, v) = parse_time(validity)?;
+    let (not_after, _) = parse_time(v)?;
+    let (subject, t) = der_raw(t)?;
+    let (spki, mut t) = der_raw(t)?;
+
+    let mut c = Certificate {
+        tbs: tbs.to_vec(),
+        sig_alg,
+        signature: signature.to_vec(),
+        issuer: issuer.to_vec(),
+        subject: subject.to_vec(),
+        not_before,
+        not_after,
+        key: parse_spki(spki)?,
+        spki_hash: super::sha2::sha256(spki),
+        dns_names: Vec::new(),
+        is_ca: false,
+        path_len: None,
+    };
+    while let Some((tag, body, rest)) = der_next(t) {
+        if tag == 0xA3 { parse_extensions(&mut c, body)?; }
+        t = rest;
+    }
+    Some(c)
+}
+
+// ─── checks ───────────────────────────────────────────────────────────────────
+
+impl Certificate {
+    /// Whether `issuer` signed this certificate.
+    pub fn verify_signed_by(&self, issuer: &Certificate) -> Result<(), &'static str> {
+        if self.issuer != issuer.subject { return Err("issuer name mismatch"); }
+        issuer.key.verify(self.sig_alg, &self.tbs, &self.signature)
+    }
+
+    pub fn valid_at(&self, secs: u64) -> bool {
+        (self.not_before..=self.not_after).contains(&secs)
+    }
+
+    /// Whether the certificate names `host`, allowing one leftmost `*.` label.
+    pub fn matches_host(&self, host: &str) -> bool {
+        self.dns_names.iter().any(|name| {
+            if name.eq_ignore_ascii_case(host) { return true; }
+            let Some(suffix) = name.strip_prefix("*.") else { return false };
+            host.split_once('.').is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(suffix))
+        })
+    }
+}
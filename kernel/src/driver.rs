@@ -0,0 +1,181 @@
+//! SurakshaOS Driver Framework
+//! The `Driver` trait implemented by every device driver, plus the device
+//! registry that routes read/write/ioctl requests to the right driver.
+//! Every operation carries the caller's device capability and is checked
+//! before the driver touches hardware.  Once the kernel is locked down only
+//! drivers built into the verified kernel image may register.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::lockdown;
+use crate::process::current_pid;
+use crate::security::{self, SecurityEvent};
+
+// ─── devices ──────────────────────────────────────────────────────────────────
+
+pub type DeviceId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceClass {
+    Serial,
+    Bluetooth,
+    Haptics,
+    Audio,
+    Wifi,
+    Modem,
+    Camera,
+    /// Satellite positioning (GNSS) receivers.
+    Location,
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub id:    DeviceId,
+    pub name:  &'static str,
+    pub class: DeviceClass,
+}
+
+// ─── driver trait ─────────────────────────────────────────────────────────────
+
+pub trait Driver: Send {
+    fn name(&self) -> &'static str;
+
+    fn read(&mut self, dev: &Device, cap: &Capability, buf: &mut [u8]) -> Result<usize, &'static str>;
+
+    fn write(&mut self, dev: &Device, cap: &Capability, buf: &[u8]) -> Result<usize, &'static str>;
+
+    fn ioctl(&mut self, dev: &Device, cap: &Capability, cmd: u32, arg: usize) -> Result<usize, &'static str>;
+
+    /// Rights a caller needs to issue `cmd`.  Drivers override this for
+    /// commands that are harmless queries (READ) or that change device
+    /// state in ways that should need more than CONTROL.
+    fn ioctl_permissions(&self, _cmd: u32) -> Permissions {
+        Permissions::CONTROL
+    }
+
+    /// Lockdown level at which `cmd` is refused.  Drivers override this
+    /// for commands that reach past the driver: raw register access, DMA
+    /// set-up, firmware upload.
+    fn ioctl_lockdown(&self, _cmd: u32) -> Option<lockdown::Level> {
+        None
+    }
+
+    /// Quiesce the device before system suspend: finish or abort in-flight
+    /// work and save any state the hardware will lose.  Returning an error
+    /// aborts the suspend.
+    fn suspend(&mut self, _dev: &Device) -> Result<(), &'static str> {
+        Ok(())
+    }
+
+    /// Restore the device after the system wakes up.
+    fn resume(&mut self, _dev: &Device) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+/// Validate that the current process may perform an operation needing
+/// `required` on `dev` using `cap`.  Denials are reported to the security
+/// monitor before the error is returned to the driver.
+pub fn check_access(dev: &Device, cap: &Capability, required: Permissions) -> Result<(), &'static str> {
+    let caller = current_pid();
+    capability::validate(caller, cap, CapabilityType::Device(dev.id), required).inspect_err(|&reason| {
+        security::report(SecurityEvent::CapabilityViolation {
+            pid: caller,
+            cap: cap.id,
+            resource: dev.name,
+            required,
+            reason,
+        });
+    })
+}
+
+// ─── registry ─────────────────────────────────────────────────────────────────
+
+struct Registered {
+    device: Device,
+    driver: Box<dyn Driver>,
+}
+
+static DEVICES: Mutex<Vec<Registered>> = Mutex::new(Vec::new());
+
+/// Register the built-in platform drivers.
+pub fn init() {
+    let _ = register("uart0", DeviceClass::Serial, Box::new(crate::console::UartDriver));
+}
+
+/// Add a device to the registry.  Under lockdown the driver's code must be
+/// part of the kernel image secure boot verified.
+pub fn register(name: &'static str, class: DeviceClass, driver: Box<dyn Driver>) -> Result<DeviceId, &'static str> {
+    // SAFETY: a trait-object pointer is its data pointer and its vtable
+    let (_, vtable): (usize, usize) = unsafe { core::mem::transmute(&*driver as *const dyn Driver) };
+    if !lockdown::in_kernel_image(vtable) {
+        lockdown::check(lockdown::Level::Integrity, "register a driver from outside the kernel image")?;
+    }
+    let mut devices = DEVICES.lock();
+    let id = devices.len() as DeviceId;
+    devices.push(Registered { device: Device { id, name, class }, driver });
+    Ok(id)
+}
+
+pub fn find_device(name: &str) -> Option<Device> {
+    DEVICES.lock().iter().find(|r| r.device.name == name).map(|r| r.device.clone())
+}
+
+pub fn devices() -> Vec<Device> {
+    DEVICES.lock().iter().map(|r| r.device.clone()).collect()
+}
+
+/// Suspend every device in registration order.  If one refuses, devices
+/// already suspended are resumed again and the error is returned.
+pub fn suspend_all() -> Result<(), &'static str> {
+    let mut devices = DEVICES.lock();
+    for i in 0..devices.len() {
+        let r = &mut devices[i];
+        if let Err(e) = r.driver.suspend(&r.device) {
+            crate::println!("  [pm] {} refused to suspend: {}", r.device.name, e);
+            for r in devices[..i].iter_mut().rev() {
+                let _ = r.driver.resume(&r.device);
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Resume every device in reverse registration order.
+pub fn resume_all() {
+    for r in DEVICES.lock().iter_mut().rev() {
+        if let Err(e) = r.driver.resume(&r.device) {
+            crate::println!("  [pm] {} failed to resume: {}", r.device.name, e);
+        }
+    }
+}
+
+fn with_driver<R>(
+    id: DeviceId,
+    f: impl FnOnce(&Device, &mut dyn Driver) -> Result<R, &'static str>,
+) -> Result<R, &'static str> {
+    let mut devices = DEVICES.lock();
+    let entry = devices.iter_mut().find(|r| r.device.id == id).ok_or("no such device")?;
+    f(&entry.device, entry.driver.as_mut())
+}
+
+pub fn read(id: DeviceId, cap: &Capability, buf: &mut [u8]) -> Result<usize, &'static str> {
+    with_driver(id, |dev, drv| drv.read(dev, cap, buf))
+}
+
+pub fn write(id: DeviceId, cap: &Capability, buf: &[u8]) -> Result<usize, &'static str> {
+    with_driver(id, |dev, drv| drv.write(dev, cap, buf))
+}
+
+pub fn ioctl(id: DeviceId, cap: &Capability, cmd: u32, arg: usize) -> Result<usize, &'static str> {
+    with_driver(id, |dev, drv| {
+        if let Some(level) = drv.ioctl_lockdown(cmd) {
+            lockdown::check(level, "device command")?;
+        }
+        drv.ioctl(dev, cap, cmd, arg)
+    })
+}
@@ -0,0 +1,103 @@
+//! SurakshaOS Energy Accounting
+//! Charges busy CPU time to the running process, split by DVFS operating
+//! point, and converts it into an energy estimate (P = C·V²·f) so the
+//! battery-usage UI can show which processes drain the battery.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::power::{OperatingPoint, CPU_FREQ_TABLE};
+use crate::process::ProcessId;
+
+/// Effective switched capacitance of the CPU, in picofarads.
+const CPU_CAP_PF: u64 = 850;
+
+/// Estimated CPU power at an operating point, in microwatts.
+pub const fn power_uw(opp: &OperatingPoint) -> u64 {
+    CPU_CAP_PF * (opp.voltage_mv as u64 * opp.voltage_mv as u64) * opp.freq_mhz as u64 / 1_000_000
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessEnergy {
+    pub pid:       ProcessId,
+    /// Busy time at each entry of `CPU_FREQ_TABLE`.
+    pub time_us:   [u64; CPU_FREQ_TABLE.len()],
+    pub energy_uj: u64,
+}
+
+impl ProcessEnergy {
+    pub fn cpu_time_us(&self) -> u64 {
+        self.time_us.iter().sum()
+    }
+}
+
+struct Accounting {
+    /// mtime up to which busy time has been charged (0 = CPU idle).
+    last:  u64,
+    procs: Vec<ProcessEnergy>,
+}
+
+static ACCOUNTING: Mutex<Accounting> = Mutex::new(Accounting { last: 0, procs: Vec::new() });
+
+/// The frequency governor reaches us from the timer interrupt, so the
+/// table is only ever held with interrupts masked.
+fn with_accounting<R>(f: impl FnOnce(&mut Accounting) -> R) -> R {
+    let prev = crate::arch::interrupts_disable();
+    let r = f(&mut ACCOUNTING.lock());
+    crate::arch::interrupts_restore(prev);
+    r
+}
+
+impl Accounting {
+    fn charge(&mut self, now: u64) {
+        let since = core::mem::replace(&mut self.last, now);
+        if since == 0 { return; }
+        let us    = crate::arch::ticks_to_us(now.saturating_sub(since));
+        let level = crate::power::cpu_level();
+        let pid   = crate::process::current_pid();
+        let entry = match self.procs.iter().position(|p| p.pid == pid) {
+            Some(i) => &mut self.procs[i],
+            None => {
+                self.procs.push(ProcessEnergy { pid, time_us: [0; CPU_FREQ_TABLE.len()], energy_uj: 0 });
+                self.procs.last_mut().unwrap()
+            }
+        };
+        entry.time_us[level] += us;
+        entry.energy_uj      += power_uw(&CPU_FREQ_TABLE[level]) * us / 1_000_000;
+    }
+}
+
+// ─── hooks ────────────────────────────────────────────────────────────────────
+
+/// Charge the CPU time since the last checkpoint to the current process at
+/// the current operating point.  Called before the running process or the
+/// frequency changes.
+pub fn checkpoint() {
+    with_accounting(|a| a.charge(crate::arch::read_mtime().max(1)));
+}
+
+/// The CPU is going idle: charge the busy stretch and stop the clock.
+pub fn cpu_idle() {
+    with_accounting(|a| { a.charge(crate::arch::read_mtime().max(1)); a.last = 0; });
+}
+
+/// The CPU is busy again: start charging from now.
+pub fn cpu_busy() {
+    with_accounting(|a| a.last = crate::arch::read_mtime().max(1));
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Per-process energy, highest consumer first.
+pub fn ranking() -> Vec<ProcessEnergy> {
+    let mut v = with_accounting(|a| {
+        a.charge(crate::arch::read_mtime().max(1));
+        a.procs.clone()
+    });
+    v.sort_by_key(|p| core::cmp::Reverse(p.energy_uj));
+    v
+}
+
+pub fn process_energy(pid: ProcessId) -> Option<ProcessEnergy> {
+    ranking().into_iter().find(|p| p.pid == pid)
+}
@@ -0,0 +1,118 @@
+//! SurakshaOS Entropy Pool
+//! Kernel CSPRNG: a ChaCha20 keystream with fast key erasure, seeded from
+//! timer/cycle-counter jitter and topped up by `add_entropy()` as drivers
+//! observe unpredictable events.
+
+use spin::Mutex;
+
+// ─── ChaCha20 core ────────────────────────────────────────────────────────────
+
+const SIGMA: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+#[inline(always)]
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]); s[d] ^= s[a]; s[d] = s[d].rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]); s[b] ^= s[c]; s[b] = s[b].rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]); s[d] ^= s[a]; s[d] = s[d].rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]); s[b] ^= s[c]; s[b] = s[b].rotate_left(7);
+}
+
+fn chacha20_block(key: &[u32; 8], counter: u64, out: &mut [u8; 64]) {
+    let mut input = [0u32; 16];
+    input[..4].copy_from_slice(&SIGMA);
+    input[4..12].copy_from_slice(key);
+    input[12] = counter as u32;
+    input[13] = (counter >> 32) as u32;
+    let mut s = input;
+    for _ in 0..10 {
+        quarter_round(&mut s, 0, 4,  8, 12);
+        quarter_round(&mut s, 1, 5,  9, 13);
+        quarter_round(&mut s, 2, 6, 10, 14);
+        quarter_round(&mut s, 3, 7, 11, 15);
+        quarter_round(&mut s, 0, 5, 10, 15);
+        quarter_round(&mut s, 1, 6, 11, 12);
+        quarter_round(&mut s, 2, 7,  8, 13);
+        quarter_round(&mut s, 3, 4,  9, 14);
+    }
+    for i in 0..16 {
+        out[i * 4..i * 4 + 4].copy_from_slice(&s[i].wrapping_add(input[i]).to_le_bytes());
+    }
+}
+
+// ─── generator ────────────────────────────────────────────────────────────────
+
+struct Csprng {
+    key:     [u32; 8],
+    counter: u64,
+    seeded:  bool,
+}
+
+static RNG: Mutex<Csprng> = Mutex::new(Csprng { key: [0; 8], counter: 0, seeded: false });
+
+impl Csprng {
+    fn seed_from_jitter(&mut self) {
+        // Each sample mixes mtime with the cycle counter; the low bits
+        // vary with cache/bus timing even under QEMU.
+        let mut pool = [0u8; 64];
+        for (i, byte) in pool.iter_mut().enumerate() {
+            let cycles = crate::arch::cycles();
+            let t = crate::arch::read_mtime();
+            *byte = (cycles ^ t.rotate_left(i as u32 % 64)) as u8;
+        }
+        self.mix(&pool);
+        self.seeded = true;
+    }
+
+    fn mix(&mut self, data: &[u8]) {
+        for (i, b) in data.iter().enumerate() {
+            self.key[(i / 4) % 8] ^= (*b as u32) << ((i % 4) * 8);
+        }
+        self.rekey();
+    }
+
+    /// Fast key erasure: replace the key with fresh keystream so earlier
+    /// outputs cannot be reconstructed from a later state compromise.
+    fn rekey(&mut self) {
+        let mut block = [0u8; 64];
+        chacha20_block(&self.key, self.counter, &mut block);
+        self.counter = self.counter.wrapping_add(1);
+        for i in 0..8 {
+            self.key[i] = u32::from_le_bytes([block[i * 4], block[i * 4 + 1], block[i * 4 + 2], block[i * 4 + 3]]);
+        }
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        if !self.seeded { self.seed_from_jitter(); }
+        let mut block = [0u8; 64];
+        for chunk in buf.chunks_mut(64) {
+            chacha20_block(&self.key, self.counter, &mut block);
+            self.counter = self.counter.wrapping_add(1);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.rekey();
+    }
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Fill `buf` with cryptographically secure random bytes.
+pub fn fill_bytes(buf: &mut [u8]) {
+    RNG.lock().fill(buf);
+}
+
+pub fn next_u32() -> u32 {
+    let mut b = [0u8; 4];
+    fill_bytes(&mut b);
+    u32::from_le_bytes(b)
+}
+
+pub fn next_u64() -> u64 {
+    let mut b = [0u8; 8];
+    fill_bytes(&mut b);
+    u64::from_le_bytes(b)
+}
+
+/// Mix externally gathered entropy (interrupt timing, radio noise, ...).
+pub fn add_entropy(data: &[u8]) {
+    RNG.lock().mix(data);
+}
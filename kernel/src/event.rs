@@ -0,0 +1,213 @@
+//! SurakshaOS Events
+//! Readiness notification in the style of epoll, so one thread can drive
+//! many sources: a process gathers them into an event set and waits on the
+//! set as a whole, then makes non-blocking calls on whichever are ready.
+//!
+//! A set is level-triggered: a source is reported on every wait while it
+//! is ready.  An edge-triggered registration is reported only when the
+//! source becomes ready for something it was not before.  Errors and
+//! hang-ups are reported whatever the registration asked for.  A source
+//! that goes away leaves every set it was in.
+//!
+//! Sources report their readiness when asked and call `notify` when it
+//! may have changed; sockets are the only sources so far.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+
+use crate::net::socket::{self, Socket};
+use crate::process::{ProcessId, WaitQueue};
+
+/// Sources one set may hold.
+pub const MAX_REGISTRATIONS: usize = 4096;
+
+// ─── types ────────────────────────────────────────────────────────────────────
+
+/// Bit set of readiness conditions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ready(pub u8);
+
+impl Ready {
+    pub const NONE:     Ready = Ready(0);
+    /// A read (or accept) would not block.
+    pub const READABLE: Ready = Ready(1 << 0);
+    /// A write would not block.
+    pub const WRITABLE: Ready = Ready(1 << 1);
+    /// The source failed; the next call reports why.
+    pub const ERROR:    Ready = Ready(1 << 2);
+    /// The peer has finished, or the connection is gone.
+    pub const HANGUP:   Ready = Ready(1 << 3);
+
+    pub const fn contains(self, other: Ready) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl core::ops::BitOr for Ready {
+    type Output = Ready;
+    fn bitor(self, rhs: Ready) -> Ready { Ready(self.0 | rhs.0) }
+}
+
+impl core::ops::BitAnd for Ready {
+    type Output = Ready;
+    fn bitand(self, rhs: Ready) -> Ready { Ready(self.0 & rhs.0) }
+}
+
+impl core::ops::Not for Ready {
+    type Output = Ready;
+    fn not(self) -> Ready { Ready(!self.0) }
+}
+
+/// Something whose readiness can be waited for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Socket(Socket),
+}
+
+impl Source {
+    /// The caller's view of the source; an error once it is gone.
+    fn readiness(self) -> Result<Ready, &'static str> {
+        match self {
+            Source::Socket(s) => socket::readiness(s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventSet(pub u32);
+
+/// A ready source, as returned by `wait`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// What the registration was given to identify the source.
+    pub token: u64,
+    pub ready: Ready,
+}
+
+struct Registration {
+    source:   Source,
+    interest: Ready,
+    token:    u64,
+    edge:     bool,
+    /// Readiness at the last wait, for edge triggering.
+    last:     Ready,
+}
+
+struct Set {
+    id:    EventSet,
+    owner: ProcessId,
+    regs:  Vec<Registration>,
+    /// Where the next wait starts looking, so a wait that fills up does
+    /// not keep reporting the same sources.
+    next:  usize,
+}
+
+impl Set {
+    /// Ready sources, at most `max`, dropping those that have gone.
+    fn collect(&mut self, max: usize) -> Vec<Event> {
+        let mut ready = Vec::with_capacity(self.regs.len());
+        self.regs.retain(|r| r.source.readiness().map(|now| ready.push(now)).is_ok());
+        let n = self.regs.len();
+        let mut events = Vec::new();
+        for k in 0..n {
+            if events.len() >= max { break; }
+            let i = (self.next + k) % n;
+            let r = &mut self.regs[i];
+            let now = ready[i] & (r.interest | Ready::ERROR | Ready::HANGUP);
+            let report = if r.edge { now & !r.last } else { now };
+            r.last = now;
+            if !report.is_empty() {
+                events.push(Event { token: r.token, ready: report });
+                self.next = (i + 1) % n;
+            }
+        }
+        events
+    }
+}
+
+static SETS: Mutex<Vec<Set>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// Woken whenever a source's readiness may have changed.
+static EVENTS: WaitQueue = WaitQueue::new();
+
+/// Run `f` on the current process's set `set`.
+fn with_set<R>(set: EventSet, f: impl FnOnce(&mut Set) -> Result<R, &'static str>) -> Result<R, &'static str> {
+    let owner = crate::process::current_pid();
+    let mut sets = SETS.lock();
+    let s = sets.iter_mut().find(|s| s.id == set && s.owner == owner).ok_or("no such event set")?;
+    f(s)
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Create an empty event set for the current process.
+pub fn create() -> EventSet {
+    let id = EventSet(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    SETS.lock().push(Set { id, owner: crate::process::current_pid(), regs: Vec::new(), next: 0 });
+    id
+}
+
+/// Watch `source` for `interest`, reporting it as `token`; edge-triggered
+/// if `edge`.  The source must be the caller's.
+pub fn register(set: EventSet, source: Source, interest: Ready, token: u64, edge: bool) -> Result<(), &'static str> {
+    source.readiness()?;
+    with_set(set, |s| {
+        if s.regs.iter().any(|r| r.source == source) { return Err("already registered"); }
+        if s.regs.len() >= MAX_REGISTRATIONS { return Err("event set full"); }
+        s.regs.push(Registration { source, interest, token, edge, last: Ready::NONE });
+        Ok(())
+    })
+}
+
+/// Change what a registered source is watched for and reported as.  An
+/// edge-triggered source is reported again if it is ready.
+pub fn modify(set: EventSet, source: Source, interest: Ready, token: u64) -> Result<(), &'static str> {
+    with_set(set, |s| {
+        let r = s.regs.iter_mut().find(|r| r.source == source).ok_or("not registered")?;
+        r.interest = interest;
+        r.token = token;
+        r.last = Ready::NONE;
+        Ok(())
+    })?;
+    notify();
+    Ok(())
+}
+
+pub fn deregister(set: EventSet, source: Source) -> Result<(), &'static str> {
+    with_set(set, |s| {
+        let i = s.regs.iter().position(|r| r.source == source).ok_or("not registered")?;
+        s.regs.remove(i);
+        Ok(())
+    })
+}
+
+/// Wait until some registered source is ready, for at most `timeout_ms`
+/// (None: indefinitely; 0 only looks), and return up to `max` of them.
+/// An empty result means the wait timed out.
+pub fn wait(set: EventSet, max: usize, timeout_ms: Option<u64>) -> Result<Vec<Event>, &'static str> {
+    if max == 0 { return Err("no room for events"); }
+    let deadline = timeout_ms.map(|t| crate::arch::uptime_millis() + t);
+    EVENTS.wait_until(deadline, || match with_set(set, |s| Ok(s.collect(max))) {
+        Ok(events) if events.is_empty() => None,
+        r => Some(r),
+    }).unwrap_or(Ok(Vec::new()))
+}
+
+pub fn close(set: EventSet) -> Result<(), &'static str> {
+    let owner = crate::process::current_pid();
+    let mut sets = SETS.lock();
+    let i = sets.iter().position(|s| s.id == set && s.owner == owner).ok_or("no such event set")?;
+    sets.remove(i);
+    Ok(())
+}
+
+/// Some source's readiness may have changed; waiters look again.
+pub fn notify() {
+    EVENTS.wake_all();
+}
@@ -0,0 +1,419 @@
+//! Flattened Device Tree
+//! Read-only walker over the DTB the firmware hands to `kernel_main`.
+//! Drivers use it to find their hardware by `compatible` string and read
+//! register windows; nothing here allocates or copies the blob.
+//!
+//! At boot, before the heap is up, `init` also reads what the rest of the
+//! kernel needs about the machine into a `Platform`: its model, the harts,
+//! the timebase frequency, the memory map, the console UART, the
+//! interrupt controllers and how to reach PSCI.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+const FDT_MAGIC:      u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE:   u32 = 2;
+const FDT_PROP:       u32 = 3;
+const FDT_NOP:        u32 = 4;
+const FDT_END:        u32 = 9;
+
+const HEADER_LEN: usize = 40;
+
+fn be32(b: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(b.get(off..off + 4)?.try_into().ok()?))
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+#[derive(Clone, Copy)]
+pub struct Fdt<'a> {
+    structs: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> Fdt<'a> {
+    pub fn parse(blob: &'a [u8]) -> Result<Self, &'static str> {
+        let field = |i: usize| be32(blob, i * 4).ok_or("fdt: truncated header");
+        if blob.len() < HEADER_LEN || field(0)? != FDT_MAGIC { return Err("fdt: bad magic"); }
+        let total = field(1)? as usize;
+        if total > blob.len() { return Err("fdt: truncated"); }
+        if field(5)? < 17 { return Err("fdt: unsupported version"); }
+
+        let (strings_off, strings_len) = (field(3)? as usize, field(8)? as usize);
+        let (structs_off, structs_len) = (field(2)? as usize, field(9)? as usize);
+        let structs = blob[..total].get(structs_off..structs_off.checked_add(structs_len).ok_or("fdt: bad layout")?);
+        let strings = blob[..total].get(strings_off..strings_off.checked_add(strings_len).ok_or("fdt: bad layout")?);
+        match (structs, strings) {
+            (Some(structs), Some(strings)) => Ok(Fdt { structs, strings }),
+            _ => Err("fdt: blocks out of bounds"),
+        }
+    }
+
+    /// Every node, in document order.
+    pub fn nodes(&self) -> Nodes<'a> {
+        Nodes { fdt: *self, pos: 0, depth: 0, cells: [(2, 1); MAX_DEPTH], done: false }
+    }
+
+    /// First enabled node whose `compatible` list contains `compat`.
+    pub fn find_compatible(&self, compat: &str) -> Option<Node<'a>> {
+        self.nodes().find(|n| n.is_enabled() && n.is_compatible(compat))
+    }
+
+    fn string(&self, off: usize) -> &'a str {
+        let s = self.strings.get(off..).unwrap_or(&[]);
+        let end = s.iter().position(|&b| b == 0).unwrap_or(s.len());
+        core::str::from_utf8(&s[..end]).unwrap_or("")
+    }
+
+    /// Properties starting at `pos` in the structure block.
+    fn props(&self, mut pos: usize) -> impl Iterator<Item = (&'a str, &'a [u8])> + 'a {
+        let fdt = *self;
+        core::iter::from_fn(move || loop {
+            match be32(fdt.structs, pos)? {
+                FDT_NOP  => pos += 4,
+                FDT_PROP => {
+                    let len  = be32(fdt.structs, pos + 4)? as usize;
+                    let name = be32(fdt.structs, pos + 8)? as usize;
+                    let data = fdt.structs.get(pos + 12..pos + 12 + len)?;
+                    pos += 12 + align4(len);
+                    return Some((fdt.string(name), data));
+                }
+                _ => return None,
+            }
+        })
+    }
+}
+
+// ─── nodes ────────────────────────────────────────────────────────────────────
+
+const MAX_DEPTH: usize = 16;
+
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    fdt:   Fdt<'a>,
+    pub name:  &'a str,
+    pub depth: usize,
+    props: usize,
+    /// #address-cells and #size-cells that apply to this node's `reg`.
+    cells: (u32, u32),
+}
+
+impl<'a> Node<'a> {
+    pub fn property(&self, name: &str) -> Option<&'a [u8]> {
+        self.fdt.props(self.props).find(|&(n, _)| n == name).map(|(_, v)| v)
+    }
+
+    pub fn property_u32(&self, name: &str) -> Option<u32> {
+        be32(self.property(name)?, 0)
+    }
+
+    pub fn compatible(&self) -> impl Iterator<Item = &'a str> {
+        self.property("compatible").unwrap_or(&[])
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .filter_map(|s| core::str::from_utf8(s).ok())
+    }
+
+    pub fn is_compatible(&self, compat: &str) -> bool {
+        self.compatible().any(|c| c == compat)
+    }
+
+    /// Nodes are enabled unless `status` says otherwise.
+    pub fn is_enabled(&self) -> bool {
+        match self.property("status") {
+            Some(s) => s.starts_with(b"okay") || s.starts_with(b"ok\0"),
+            None    => true,
+        }
+    }
+
+    /// The `index`th (address, size) pair of `reg`.
+    pub fn reg(&self, index: usize) -> Option<(u64, u64)> {
+        let reg = self.property("reg")?;
+        let (ac, sc) = (self.cells.0 as usize, self.cells.1 as usize);
+        if ac > 2 || sc > 2 { return None; }
+        let read = |off: usize, n: usize| -> Option<u64> {
+            (0..n).try_fold(0u64, |acc, i| Some(acc << 32 | be32(reg, off + i * 4)? as u64))
+        };
+        let off = index * (ac + sc) * 4;
+        Some((read(off, ac)?, read(off + ac * 4, sc)?))
+    }
+}
+
+pub struct Nodes<'a> {
+    fdt:   Fdt<'a>,
+    pos:   usize,
+    depth: usize,
+    /// Cells declared by the node at each depth, for its children.
+    cells: [(u32, u32); MAX_DEPTH],
+    done:  bool,
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Node<'a>> {
+        while !self.done {
+            let Some(token) = be32(self.fdt.structs, self.pos) else { break };
+            match token {
+                FDT_BEGIN_NODE => {
+                    let rest = self.fdt.structs.get(self.pos + 4..)?;
+                    let len  = rest.iter().position(|&b| b == 0)?;
+                    let name = core::str::from_utf8(&rest[..len]).unwrap_or("");
+                    let props = self.pos + 4 + align4(len + 1);
+                    if self.depth >= MAX_DEPTH { break; }
+
+                    let parent = if self.depth == 0 { (2, 1) } else { self.cells[self.depth - 1] };
+                    let node = Node { fdt: self.fdt, name, depth: self.depth, props, cells: parent };
+                    self.cells[self.depth] = (
+                        node.property_u32("#address-cells").unwrap_or(2),
+                        node.property_u32("#size-cells").unwrap_or(1),
+                    );
+                    self.depth += 1;
+
+                    // Skip past the properties to the first child or END_NODE
+                    self.pos = props;
+                    while let Some(FDT_PROP | FDT_NOP) = be32(self.fdt.structs, self.pos) {
+                        self.pos += match be32(self.fdt.structs, self.pos)? {
+                            FDT_PROP => 12 + align4(be32(self.fdt.structs, self.pos + 4)? as usize),
+                            _        => 4,
+                        };
+                    }
+                    return Some(node);
+                }
+                FDT_END_NODE => { self.depth = self.depth.saturating_sub(1); self.pos += 4; }
+                FDT_NOP      => self.pos += 4,
+                FDT_PROP     => self.pos += 12 + align4(be32(self.fdt.structs, self.pos + 4)? as usize),
+                _            => break, // FDT_END or garbage
+            }
+        }
+        self.done = true;
+        None
+    }
+}
+
+// ─── boot blob ────────────────────────────────────────────────────────────────
+
+static DTB: AtomicUsize = AtomicUsize::new(0);
+
+/// Remember the firmware's DTB if it has a valid header, and read the
+/// platform from it.
+pub fn init(dtb_ptr: usize) -> Result<(), &'static str> {
+    if dtb_ptr == 0 { return Err("fdt: no device tree"); }
+    // SAFETY: the firmware passes a pointer to a DTB that stays mapped
+    // and untouched for the life of the kernel.
+    let header = unsafe { core::slice::from_raw_parts(dtb_ptr as *const u8, HEADER_LEN) };
+    if be32(header, 0) != Some(FDT_MAGIC) { return Err("fdt: bad magic"); }
+    DTB.store(dtb_ptr, Ordering::Relaxed);
+    let Some(fdt) = get() else {
+        DTB.store(0, Ordering::Relaxed);
+        return Err("fdt: malformed device tree");
+    };
+    *PLATFORM.lock() = Some(Platform::read(&fdt));
+    Ok(())
+}
+
+fn blob() -> Option<&'static [u8]> {
+    let ptr = DTB.load(Ordering::Relaxed);
+    if ptr == 0 { return None; }
+    // SAFETY: init() checked the magic; the size comes from the header.
+    unsafe {
+        let total = be32(core::slice::from_raw_parts(ptr as *const u8, HEADER_LEN), 4)? as usize;
+        Some(core::slice::from_raw_parts(ptr as *const u8, total))
+    }
+}
+
+/// The boot device tree, if firmware provided a valid one.
+pub fn get() -> Option<Fdt<'static>> {
+    Fdt::parse(blob()?).ok()
+}
+
+// ─── platform ─────────────────────────────────────────────────────────────────
+
+pub const MAX_HARTS:   usize = 32;
+pub const MAX_REGIONS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UartKind {
+    Ns16550,
+    Pl011,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Uart {
+    pub kind:     UartKind,
+    pub base:     usize,
+    /// Interrupt number: the PLIC source, or the GIC interrupt id.
+    pub irq:      Option<u32>,
+    pub clock_hz: Option<u32>,
+}
+
+/// How PSCI calls reach the firmware (or hypervisor).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Psci {
+    Hvc,
+    Smc,
+}
+
+/// What the device tree says about the machine.
+#[derive(Debug, Clone, Copy)]
+pub struct Platform {
+    /// The root's `model`, or failing that its first `compatible`.
+    pub model:       &'static str,
+    hart_ids:        [u32; MAX_HARTS],
+    hart_count:      usize,
+    /// Frequency of `mtime`.
+    pub timebase_hz: Option<u64>,
+    /// RAM, as (base, size).
+    regions:         [(u64, u64); MAX_REGIONS],
+    region_count:    usize,
+    /// The UART `/chosen/stdout-path` names, or the first 16550 or PL011.
+    pub console:     Option<Uart>,
+    pub clint:       Option<usize>,
+    pub plic:        Option<usize>,
+    /// GICv2 distributor and CPU interface.
+    pub gic:         Option<(usize, usize)>,
+    pub psci:        Option<Psci>,
+}
+
+static PLATFORM: Mutex<Option<Platform>> = Mutex::new(None);
+
+/// The platform, if the firmware passed a device tree.
+pub fn platform() -> Option<Platform> {
+    *PLATFORM.lock()
+}
+
+impl Platform {
+    /// Ids of the enabled harts.
+    pub fn harts(&self) -> &[u32] {
+        &self.hart_ids[..self.hart_count]
+    }
+
+    pub fn memory(&self) -> &[(u64, u64)] {
+        &self.regions[..self.region_count]
+    }
+
+    fn read(fdt: &Fdt<'static>) -> Platform {
+        let mut p = Platform {
+            model: "", hart_ids: [0; MAX_HARTS], hart_count: 0, timebase_hz: None,
+            regions: [(0, 0); MAX_REGIONS], region_count: 0, console: None, clint: None, plic: None,
+            gic: None, psci: None,
+        };
+        let str_prop = |n: &Node<'static>, name: &str| {
+            let v = n.property(name)?;
+            core::str::from_utf8(&v[..v.iter().position(|&b| b == 0).unwrap_or(v.len())]).ok()
+        };
+        let timebase = |n: &Node| match n.property("timebase-frequency")? {
+            v if v.len() >= 8 => Some((be32(v, 0)? as u64) << 32 | be32(v, 4)? as u64),
+            v                 => be32(v, 0).map(u64::from),
+        };
+        let mut stdout = None;
+        for n in fdt.nodes() {
+            let device_type = str_prop(&n, "device_type");
+            match (n.depth, n.name) {
+                (0, _) => p.model = str_prop(&n, "model").or(n.compatible().next()).unwrap_or(""),
+                (1, "cpus") => p.timebase_hz = timebase(&n),
+                (1, "chosen") => stdout = str_prop(&n, "stdout-path"),
+                (2, _) if device_type == Some("cpu") && n.is_enabled() && p.hart_count < MAX_HARTS => {
+                    if let Some((id, _)) = n.reg(0) {
+                        p.hart_ids[p.hart_count] = id as u32;
+                        p.hart_count += 1;
+                    }
+                    p.timebase_hz = p.timebase_hz.or(timebase(&n));
+                }
+                _ if device_type == Some("memory") => {
+                    for i in 0.. {
+                        let Some(r) = n.reg(i) else { break };
+                        if p.region_count == MAX_REGIONS { break; }
+                        p.regions[p.region_count] = r;
+                        p.region_count += 1;
+                    }
+                }
+                _ if n.is_compatible("riscv,clint0") || n.is_compatible("sifive,clint0") => {
+                    p.clint = n.reg(0).map(|(a, _)| a as usize);
+                }
+                _ if n.is_compatible("riscv,plic0") || n.is_compatible("sifive,plic-1.0.0") => {
+                    p.plic = n.reg(0).map(|(a, _)| a as usize);
+                }
+                _ if n.is_compatible("arm,cortex-a15-gic") || n.is_compatible("arm,gic-400") => {
+                    p.gic = n.reg(0).zip(n.reg(1)).map(|((d, _), (c, _))| (d as usize, c as usize));
+                }
+                _ if n.compatible().any(|c| c.starts_with("arm,psci")) => {
+                    p.psci = match str_prop(&n, "method") {
+                        Some("hvc") => Some(Psci::Hvc),
+                        Some("smc") => Some(Psci::Smc),
+                        _ => None,
+                    };
+                }
+                _ => {}
+            }
+        }
+        let uart = stdout.map(|s| s.split(':').next().unwrap_or(s))
+            .and_then(|path| resolve_alias(fdt, path))
+            .and_then(|path| find_path(fdt, path))
+            .or_else(|| fdt.find_compatible("ns16550a"))
+            .or_else(|| fdt.find_compatible("arm,pl011"));
+        p.console = uart.and_then(|n| uart_of(&n));
+        p
+    }
+}
+
+fn uart_of(n: &Node) -> Option<Uart> {
+    Some(Uart {
+        kind:     if n.is_compatible("arm,pl011") { UartKind::Pl011 } else { UartKind::Ns16550 },
+        base:     n.reg(0)?.0 as usize,
+        irq:      irq(n),
+        clock_hz: n.property_u32("clock-frequency"),
+    })
+}
+
+/// Make the UART at `path` (an absolute path or an alias) the console,
+/// in place of the one `stdout-path` names.
+pub fn set_console(path: &'static str) -> Result<(), &'static str> {
+    let fdt = get().ok_or("no device tree")?;
+    let node = resolve_alias(&fdt, path).and_then(|p| find_path(&fdt, p)).ok_or("no such device")?;
+    let uart = uart_of(&node).ok_or("not a UART")?;
+    PLATFORM.lock().as_mut().ok_or("no platform")?.console = Some(uart);
+    Ok(())
+}
+
+/// A node's first interrupt.  A single cell is a PLIC source; the GIC's
+/// three cells (type, number, flags) number SPIs from 32 and PPIs from 16.
+fn irq(n: &Node) -> Option<u32> {
+    let v = n.property("interrupts")?;
+    if v.len() < 12 { return be32(v, 0); }
+    let base = if be32(v, 0)? == 1 { 16 } else { 32 };
+    Some(base + be32(v, 4)?)
+}
+
+/// `path` as an absolute path: a name in /aliases is replaced by its value.
+fn resolve_alias(fdt: &Fdt<'static>, path: &'static str) -> Option<&'static str> {
+    if path.starts_with('/') { return Some(path); }
+    let aliases = fdt.nodes().find(|n| n.depth == 1 && n.name == "aliases")?;
+    let v = aliases.property(path)?;
+    core::str::from_utf8(&v[..v.iter().position(|&b| b == 0).unwrap_or(v.len())]).ok()
+}
+
+/// The node at absolute `path`.
+fn find_path<'a>(fdt: &Fdt<'a>, path: &str) -> Option<Node<'a>> {
+    let want: [&str; MAX_DEPTH] = {
+        let mut w = [""; MAX_DEPTH];
+        for (i, c) in path.split('/').filter(|c| !c.is_empty()).enumerate() { *w.get_mut(i + 1)? = c; }
+        w
+    };
+    let depth = want.iter().rposition(|c| !c.is_empty()).unwrap_or(0);
+    // The names of the current node's ancestors that match so far
+    let mut matched = 0;
+    for n in fdt.nodes() {
+        if n.depth == 0 { continue; }
+        if n.depth > matched + 1 { continue; }
+        matched = n.depth - 1;
+        if n.name == want[n.depth] {
+            if n.depth == depth { return Some(n); }
+            matched = n.depth;
+        }
+    }
+    None
+}
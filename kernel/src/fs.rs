@@ -1,8 +1,9 @@
-/// SurakshaOS Filesystem Extension
-/// Adds stat(), list_dir(), and the FileInfo type needed by the shell.
-/// Sits on top of the existing in-memory VFS from v0.1.
+//! SurakshaOS Filesystem Extension
+//! Adds stat(), list_dir(), and the FileInfo type needed by the shell.
+//! Sits on top of the existing in-memory VFS from v0.1.
 
 use alloc::string::{String, ToString};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 // ─── types ────────────────────────────────────────────────────────────────────
@@ -18,7 +19,8 @@ pub struct FileInfo {
 
 #[derive(Clone)]
 enum VfsNode {
-    File { data: Vec<u8> },
+    /// Contents are immutable once written and shared with mappings.
+    File { data: Arc<[u8]> },
     Dir  { children: Vec<(String, VfsNode)> },
 }
 
@@ -53,10 +55,23 @@ pub fn write_file(path: &str, data: &[u8]) -> Result<(), &'static str> {
     let mut root = VFS_ROOT.lock();
     let node = root.as_mut().ok_or("vfs not initialised")?;
     let parts = split_path(path);
-    insert_node(node, &parts, VfsNode::File { data: data.to_vec() })
+    insert_node(node, &parts, VfsNode::File { data: Arc::from(data) })
 }
 
 pub fn read_file(path: &str) -> Result<Vec<u8>, &'static str> {
+    let root = VFS_ROOT.lock();
+    let node = root.as_ref().ok_or("vfs not initialised")?;
+    let parts = split_path(path);
+    match find_node(node, &parts)? {
+        VfsNode::File { data } => Ok(data.to_vec()),
+        VfsNode::Dir  { .. }   => Err("is a directory"),
+    }
+}
+
+/// Map a file read-only without copying it.  The mapping shares the
+/// file's storage and keeps the contents it saw alive even if the file
+/// is later rewritten or removed.
+pub fn map_file(path: &str) -> Result<Arc<[u8]>, &'static str> {
     let root = VFS_ROOT.lock();
     let node = root.as_ref().ok_or("vfs not initialised")?;
     let parts = split_path(path);
@@ -0,0 +1,246 @@
+//! SurakshaOS Haptics
+//! Vibration motor driver (LRA or ERM behind a PWM channel) with on/off,
+//! amplitude control and pattern playback, plus the `hapticsd` IPC service
+//! the UI framework uses to trigger effects.  Clients must hold a WRITE
+//! capability on `vib0` before the service will open a channel for them.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::driver::{self, check_access, Device, DeviceClass, DeviceId, Driver};
+use crate::ipc::{self, ChannelId, Message};
+use crate::process::{self, ProcessId};
+
+/// Upper bound on a single pattern; playback blocks the caller.
+const MAX_PATTERN_MS: u32 = 2000;
+
+// ─── hardware ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MotorKind {
+    /// Linear resonant actuator: must be driven near its resonant frequency.
+    Lra { resonant_hz: u32 },
+    /// Eccentric rotating mass: plain DC drive, slow spin-up.
+    Erm,
+}
+
+/// SiFive-compatible PWM block driving the motor on channel 1.
+pub struct PwmMotor {
+    base:     usize,
+    kind:     MotorKind,
+    clock_hz: u32,
+}
+
+impl PwmMotor {
+    const PWMCFG:  usize = 0x00;
+    const PWMCMP0: usize = 0x20; // period
+    const PWMCMP1: usize = 0x24; // duty
+    const CFG_ENALWAYS: u32 = 1 << 12;
+    const CFG_ZEROCMP:  u32 = 1 << 9;
+
+    pub const fn new(base: usize, kind: MotorKind, clock_hz: u32) -> Self {
+        PwmMotor { base, kind, clock_hz }
+    }
+
+    fn reg(&self, off: usize) -> *mut u32 { (self.base + off) as *mut u32 }
+
+    fn period(&self) -> u32 {
+        match self.kind {
+            MotorKind::Lra { resonant_hz } => self.clock_hz / resonant_hz.max(1),
+            MotorKind::Erm                 => self.clock_hz / 25_000, // above audible range
+        }
+    }
+
+    /// 0 = off, 255 = full drive.
+    pub fn set_amplitude(&mut self, amp: u8) {
+        let period = self.period().min(0xFFFF);
+        let duty   = period * amp as u32 / 255;
+        unsafe {
+            if amp == 0 {
+                core::ptr::write_volatile(self.reg(Self::PWMCFG), 0);
+                return;
+            }
+            core::ptr::write_volatile(self.reg(Self::PWMCMP0), period);
+            core::ptr::write_volatile(self.reg(Self::PWMCMP1), duty);
+            core::ptr::write_volatile(self.reg(Self::PWMCFG), Self::CFG_ENALWAYS | Self::CFG_ZEROCMP);
+        }
+    }
+}
+
+// ─── effects ──────────────────────────────────────────────────────────────────
+
+/// One step of a pattern: drive at `amplitude` for `ms` milliseconds.
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub ms:        u16,
+    pub amplitude: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    Tick,
+    Click,
+    DoubleClick,
+    HeavyClick,
+}
+
+impl Effect {
+    pub fn from_id(id: u8) -> Option<Effect> {
+        match id {
+            0 => Some(Effect::Tick),
+            1 => Some(Effect::Click),
+            2 => Some(Effect::DoubleClick),
+            3 => Some(Effect::HeavyClick),
+            _ => None,
+        }
+    }
+
+    fn pattern(self) -> &'static [Segment] {
+        const fn s(ms: u16, amplitude: u8) -> Segment { Segment { ms, amplitude } }
+        const TICK:   [Segment; 1] = [s(8, 128)];
+        const CLICK:  [Segment; 1] = [s(12, 200)];
+        const DOUBLE: [Segment; 3] = [s(12, 200), s(60, 0), s(12, 200)];
+        const HEAVY:  [Segment; 1] = [s(20, 255)];
+        match self {
+            Effect::Tick        => &TICK,
+            Effect::Click       => &CLICK,
+            Effect::DoubleClick => &DOUBLE,
+            Effect::HeavyClick  => &HEAVY,
+        }
+    }
+}
+
+// ─── driver ───────────────────────────────────────────────────────────────────
+
+/// Play `pattern`, scaling each segment's amplitude by `scale`/255.
+fn play(motor: &mut PwmMotor, scale: u8, pattern: &[Segment]) -> Result<(), &'static str> {
+    let total: u32 = pattern.iter().map(|s| s.ms as u32).sum();
+    if total > MAX_PATTERN_MS { return Err("pattern too long"); }
+    for seg in pattern {
+        motor.set_amplitude((seg.amplitude as u32 * scale as u32 / 255) as u8);
+        crate::arch::delay_ms(seg.ms as u64);
+    }
+    motor.set_amplitude(0);
+    Ok(())
+}
+
+/// Vibrate for `arg` ms at the current amplitude scale.
+pub const HAPTIC_IOCTL_ON:            u32 = 0x4801;
+pub const HAPTIC_IOCTL_OFF:           u32 = 0x4802;
+/// Set the global amplitude scale (0-255); applies to every later effect.
+pub const HAPTIC_IOCTL_SET_AMPLITUDE: u32 = 0x4803;
+/// Play a predefined `Effect` by id.
+pub const HAPTIC_IOCTL_EFFECT:        u32 = 0x4804;
+
+/// `vib0`: writes are patterns encoded as (ms_lo, ms_hi, amplitude)
+/// triples; ioctls cover on/off, amplitude and predefined effects.
+pub struct HapticsDriver {
+    motor: PwmMotor,
+    scale: u8,
+}
+
+impl HapticsDriver {
+    pub fn new(motor: PwmMotor) -> Self {
+        HapticsDriver { motor, scale: 255 }
+    }
+}
+
+impl Driver for HapticsDriver {
+    fn name(&self) -> &'static str { "pwm-vibrator" }
+
+    fn read(&mut self, dev: &Device, cap: &Capability, buf: &mut [u8]) -> Result<usize, &'static str> {
+        check_access(dev, cap, Permissions::READ)?;
+        if buf.is_empty() { return Ok(0); }
+        buf[0] = self.scale;
+        Ok(1)
+    }
+
+    fn write(&mut self, dev: &Device, cap: &Capability, buf: &[u8]) -> Result<usize, &'static str> {
+        check_access(dev, cap, Permissions::WRITE)?;
+        let pattern: Vec<Segment> = buf.as_chunks::<3>().0.iter()
+            .map(|c| Segment { ms: u16::from_le_bytes([c[0], c[1]]), amplitude: c[2] })
+            .collect();
+        play(&mut self.motor, self.scale, &pattern)?;
+        Ok(buf.len())
+    }
+
+    fn ioctl(&mut self, dev: &Device, cap: &Capability, cmd: u32, arg: usize) -> Result<usize, &'static str> {
+        check_access(dev, cap, self.ioctl_permissions(cmd))?;
+        match cmd {
+            HAPTIC_IOCTL_ON => {
+                let ms = (arg as u32).min(MAX_PATTERN_MS) as u16;
+                play(&mut self.motor, self.scale, &[Segment { ms, amplitude: 255 }])?;
+                Ok(0)
+            }
+            HAPTIC_IOCTL_OFF => { self.motor.set_amplitude(0); Ok(0) }
+            HAPTIC_IOCTL_SET_AMPLITUDE => { self.scale = arg.min(255) as u8; Ok(0) }
+            HAPTIC_IOCTL_EFFECT => {
+                let effect = Effect::from_id(arg as u8).ok_or("unknown effect")?;
+                play(&mut self.motor, self.scale, effect.pattern())?;
+                Ok(0)
+            }
+            _ => Err("unsupported ioctl"),
+        }
+    }
+
+    fn ioctl_permissions(&self, _cmd: u32) -> Permissions {
+        Permissions::WRITE
+    }
+}
+
+// ─── hapticsd service ─────────────────────────────────────────────────────────
+
+/// Request opcodes (first payload byte).
+pub const HAPTICS_REQ_EFFECT:  u8 = 1; // [effect_id]
+pub const HAPTICS_REQ_PATTERN: u8 = 2; // [(ms_lo, ms_hi, amp)...]
+pub const HAPTICS_REQ_OFF:     u8 = 3;
+
+struct Service {
+    pid:    ProcessId,
+    device: DeviceId,
+    /// The service's own device capability, used for every hardware call.
+    cap:    Capability,
+}
+
+static SERVICE: Mutex<Option<Service>> = Mutex::new(None);
+
+/// Register the motor as `vib0` and start `hapticsd`.
+pub fn init(motor: PwmMotor) -> Result<(), &'static str> {
+    let device = driver::register("vib0", DeviceClass::Haptics, Box::new(HapticsDriver::new(motor)))?;
+    let pid = process::spawn_process("hapticsd")?;
+    let cap = capability::create_capability(pid, CapabilityType::Device(device), Permissions::WRITE);
+    *SERVICE.lock() = Some(Service { pid, device, cap });
+    ipc::register_kernel_server(pid, handle_request);
+    Ok(())
+}
+
+/// Open a channel from `client` to `hapticsd`.  The client proves it may
+/// vibrate the device by presenting its own `vib0` capability.
+pub fn connect(client: ProcessId, device_cap: &Capability) -> Result<(ChannelId, Capability), &'static str> {
+    let (pid, device) = {
+        let svc = SERVICE.lock();
+        let svc = svc.as_ref().ok_or("haptics service not running")?;
+        (svc.pid, svc.device)
+    };
+    capability::validate(client, device_cap, CapabilityType::Device(device), Permissions::WRITE)?;
+    let (ch, client_cap, _svc_cap) = ipc::create_channel(client, pid);
+    Ok((ch, client_cap))
+}
+
+fn handle_request(_ch: ChannelId, msg: &Message) -> Option<Vec<u8>> {
+    let (device, cap) = {
+        let svc = SERVICE.lock();
+        let svc = svc.as_ref()?;
+        (svc.device, svc.cap.clone())
+    };
+    let result = match msg.payload.first() {
+        Some(&HAPTICS_REQ_EFFECT) if msg.payload.len() >= 2 =>
+            driver::ioctl(device, &cap, HAPTIC_IOCTL_EFFECT, msg.payload[1] as usize),
+        Some(&HAPTICS_REQ_PATTERN) => driver::write(device, &cap, &msg.payload[1..]),
+        Some(&HAPTICS_REQ_OFF)     => driver::ioctl(device, &cap, HAPTIC_IOCTL_OFF, 0),
+        _                          => Err("bad request"),
+    };
+    Some(Vec::from([result.is_ok() as u8]))
+}
@@ -1,56 +1,448 @@
-/// SurakshaOS Init System
-/// The first process spawned by the kernel after boot.
-/// Responsible for: setting up the environment, launching services,
-/// and handing off to the interactive shell.
+//! SurakshaOS Init System
+//! The first process spawned by the kernel after boot.
+//! Responsible for: setting up the environment, launching services,
+//! and handing off to the interactive shell.
+//!
+//! Services say what they require and what they start after.  Init sorts
+//! them topologically, refusing a cycle, and starts each as soon as what
+//! it waits for is ready, so independent services come up together.  A
+//! service is running once it reports ready (SYS_SERVICE_NOTIFY); one
+//! that does not within its timeout has failed.
+//!
+//! A service may instead be socket-activated: init opens the sockets it
+//! listens on at boot, and starts it when the first connection arrives,
+//! handing it the sockets (SYS_SERVICE_SOCKETS).  Clients can connect
+//! from the start, and services that require it need not wait for it.
+//!
+//! A service given a sandbox profile is confined before it starts: its
+//! system calls filtered to the profile's, its CPU share and memory held
+//! to its limits (`crate::sandbox`), and the capabilities it is allowed
+//! granted to it and no others of their kind.
+//!
+//! Once boot is over init supervises what it started.  It hears of every
+//! service process that exits or is killed, and restarts it as its
+//! restart policy says, backing off exponentially between restarts and
+//! giving up on one restarted too often in too short a time.  A service
+//! with a watchdog must ping init (SYS_SERVICE_NOTIFY) within the
+//! interval, or it is taken to have hung and is restarted.
 
 extern crate alloc;
+use alloc::string::String;
 use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
 
 use crate::{print, println};
-use crate::process::ProcessId;
-use crate::fs::{create_dir, write_file};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::event::Ready;
+use crate::net::socket::{self, Socket};
+use crate::net::{Ipv4Addr, SocketAddr};
+use crate::process::{self, ProcessId, WaitQueue};
+use crate::sandbox::{self, SandboxConfig};
+use crate::syscall;
+use crate::fs::{create_dir, read_file, write_file};
 use crate::shell::Shell;
 
+/// How long a service has to report ready unless its config says.
+pub const READY_TIMEOUT_MS: u64 = 5_000;
+
+/// The wait before the first restart, doubled for each restart since.
+const RESTART_DELAY_MS: u64 = 100;
+const RESTART_DELAY_MAX_MS: u64 = 30_000;
+
+/// A service as init is told to run it.
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    pub name:     &'static str,
+    /// Boot cannot go on without it.
+    pub critical: bool,
+    /// Services that must be ready before this one starts.  If one
+    /// fails, this one is not started.
+    pub requires: &'static [&'static str],
+    /// Services that, where they exist, start and settle first — ready
+    /// or failed — before this one; ordering only.
+    pub after:    &'static [&'static str],
+    /// How long it has to report ready once started.
+    pub ready_timeout_ms: u64,
+    /// Sockets init opens for it.  A service with any is socket-activated:
+    /// started on the first connection rather than at boot.
+    pub listen:   &'static [Listen],
+    /// The sandbox it runs in; None runs it unconfined, and the limits
+    /// below apply only in a sandbox.
+    pub sandbox:  Option<Profile>,
+    /// Share of the CPU it may use (1–100 %).
+    pub cpu_pct:  u8,
+    /// Bytes of kernel memory that may be charged to it.
+    pub memory:   Option<usize>,
+    /// Capabilities init grants it as it starts.  In a sandbox these are
+    /// all it gets of network and filesystem access.
+    pub capabilities: &'static [(CapabilityType, Permissions)],
+    /// When init restarts it after boot.
+    pub restart:  RestartPolicy,
+    /// It is not restarted again once it has been this many times within
+    /// `restart_interval_ms`.
+    pub restart_burst: usize,
+    pub restart_interval_ms: u64,
+    /// How often it must ping init once running; None: it need not.
+    pub watchdog_ms: Option<u64>,
+}
+
+impl ServiceConfig {
+    pub const DEFAULT: ServiceConfig = ServiceConfig {
+        name: "", critical: false, requires: &[], after: &[], ready_timeout_ms: READY_TIMEOUT_MS, listen: &[],
+        sandbox: None, cpu_pct: 100, memory: None, capabilities: &[],
+        restart: RestartPolicy::Never, restart_burst: 5, restart_interval_ms: 60_000, watchdog_ms: None,
+    };
+
+    /// Its sandbox, if it has one.  It has network access if it is allowed
+    /// a network capability or listens on sockets, and the filesystem if
+    /// it is allowed a file capability.
+    fn sandbox_config(&self) -> Option<SandboxConfig> {
+        let profile = self.sandbox?;
+        let allowed = |f: fn(&CapabilityType) -> bool| self.capabilities.iter().any(|(ty, _)| f(ty));
+        Some(SandboxConfig {
+            syscalls:   profile.syscalls(),
+            cpu_pct:    self.cpu_pct,
+            memory:     self.memory,
+            network:    !self.listen.is_empty() || allowed(|ty| matches!(ty, CapabilityType::Network)),
+            filesystem: allowed(|ty| matches!(ty, CapabilityType::File)),
+        })
+    }
+}
+
+/// When a service whose process has gone is started again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    Never,
+    /// If it was killed, did not report ready in time, or missed a
+    /// watchdog ping, but not if it exited.
+    OnFailure,
+    Always,
+}
+
+impl RestartPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RestartPolicy::Never     => "never",
+            RestartPolicy::OnFailure => "on-failure",
+            RestartPolicy::Always    => "always",
+        }
+    }
+}
+
+/// Which system calls a sandboxed service may make.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// Only its dealings with init — reporting ready, taking its
+    /// sockets — its capabilities and the IPC channels and ports they name,
+    /// reading the clock and exiting.
+    Minimal,
+    /// Those, and reading the system's statistics, the kernel log and,
+    /// with the capability for it, the capability audit log.
+    Observer,
+    /// Exactly these: bit n allows system call n.
+    Syscalls(u64),
+}
+
+impl Profile {
+    pub fn syscalls(self) -> u64 {
+        let init = 1 << syscall::SYS_SERVICE_NOTIFY | 1 << syscall::SYS_SERVICE_SOCKETS
+            | 1 << syscall::SYS_CLOCK | 1 << syscall::SYS_EXIT
+            | 1 << syscall::SYS_CAP | 1 << syscall::SYS_IPC_SEND | 1 << syscall::SYS_IPC_RECV
+            | 1 << syscall::SYS_IPC_RECV_WAIT | 1 << syscall::SYS_PORT
+            | 1 << syscall::SYS_IPC_CALL | 1 << syscall::SYS_IPC_REPLY;
+        match self {
+            Profile::Minimal     => init,
+            Profile::Observer    => init | 1 << syscall::SYS_POWER_STATS | 1 << syscall::SYS_NET_STATS | 1 << syscall::SYS_DMESG
+                | 1 << syscall::SYS_AUDIT_READ,
+            Profile::Syscalls(s) => s,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Profile::Minimal     => "minimal",
+            Profile::Observer    => "observer",
+            Profile::Syscalls(_) => "custom",
+        }
+    }
+}
+
+/// A socket a service is activated by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Listen {
+    /// A TCP port, taking up to `backlog` connections before the service
+    /// is up to accept them.
+    Stream { port: u16, backlog: usize },
+    /// A UDP port.
+    Datagram { port: u16 },
+}
+
+impl Listen {
+    /// Open it as the current process, under `cap`.
+    fn open(self, cap: &capability::Capability) -> Result<Socket, &'static str> {
+        match self {
+            Listen::Stream { port, backlog } => socket::listen(cap, port, backlog),
+            Listen::Datagram { port } => socket::bind(cap, SocketAddr::new(Ipv4Addr::UNSPECIFIED, port)),
+        }
+    }
+}
+
+/// The core services, started by `start_all` in dependency order.
+const CORE_SERVICES: &[ServiceConfig] = &[
+    ServiceConfig { name: "memory-guard",   critical: true, ..ServiceConfig::DEFAULT },
+    ServiceConfig { name: "entropy-pool",   critical: true, ..ServiceConfig::DEFAULT },
+    ServiceConfig { name: "capability-mgr", critical: true, requires: &["entropy-pool"], ..ServiceConfig::DEFAULT },
+    ServiceConfig {
+        name: "logger", sandbox: Some(Profile::Observer), cpu_pct: 10, memory: Some(256 * 1024),
+        capabilities: &[(CapabilityType::File, Permissions::WRITE)], restart: RestartPolicy::OnFailure,
+        ..ServiceConfig::DEFAULT
+    },
+    ServiceConfig {
+        name: "device-manager", requires: &["capability-mgr"], after: &["logger"], ..ServiceConfig::DEFAULT
+    },
+];
+
 pub struct InitSystem {
     pid: ProcessId,
     services: Vec<Service>,
+    /// Boot is over and the shell has the console: report to the log.
+    booted: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct Service {
-    pub name: &'static str,
+    pub config: ServiceConfig,
     pub pid: Option<ProcessId>,
     pub status: ServiceStatus,
-    pub critical: bool,
+    /// Uptime it was started at, while it is starting.
+    started_ms: u64,
+    /// Uptime of its last watchdog ping, while it is running.
+    pinged_ms: u64,
+    /// Uptime it is to be restarted at, while it is restarting.
+    restart_at: u64,
+    /// Uptimes it was restarted at, within its restart interval.
+    restarts: Vec<u64>,
+    /// Its `listen` sockets, in order: init's until it starts, then its.
+    pub sockets: Vec<Socket>,
+    /// The capabilities init granted it.
+    pub granted: Vec<Capability>,
+}
+
+impl Service {
+    pub fn new(config: ServiceConfig) -> Self {
+        Service {
+            config, pid: None, status: ServiceStatus::Stopped, started_ms: 0, pinged_ms: 0, restart_at: 0,
+            restarts: Vec::new(), sockets: Vec::new(), granted: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.config.name
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ServiceStatus {
     Stopped,
+    /// Its sockets are open; it starts on the first connection.
+    Listening,
+    /// Started, and not yet reported ready.
     Starting,
     Running,
+    /// Its process has gone; it is waiting out its backoff to restart.
+    Restarting,
     Failed,
 }
 
+impl ServiceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServiceStatus::Stopped   => "stopped",
+            ServiceStatus::Listening => "listening",
+            ServiceStatus::Starting  => "starting",
+            ServiceStatus::Running   => "running",
+            ServiceStatus::Restarting => "restarting",
+            ServiceStatus::Failed    => "failed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitError {
+    /// A service requires one init does not know.
+    UnknownDependency { service: &'static str, dependency: &'static str },
+    /// Services that wait on each other, in order, the first repeated.
+    DependencyCycle(Vec<&'static str>),
+}
+
+impl core::fmt::Display for InitError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InitError::UnknownDependency { service, dependency } => {
+                write!(f, "{} requires unknown service {}", service, dependency)
+            }
+            InitError::DependencyCycle(cycle) => write!(f, "dependency cycle: {}", cycle.join(" -> ")),
+        }
+    }
+}
+
+// ─── readiness ───────────────────────────────────────────────────────────────
+
+/// Services that have reported ready since init last looked.
+static READY: Mutex<Vec<ProcessId>> = Mutex::new(Vec::new());
+static READY_WAIT: WaitQueue = WaitQueue::new();
+
+/// `pid` is up and serving (SYS_SERVICE_NOTIFY): anything that waits on
+/// it may start.
+pub fn notify_ready(pid: ProcessId) {
+    READY.lock().push(pid);
+    READY_WAIT.wake_all();
+    process::defer(supervise);
+}
+
+// ─── after boot ──────────────────────────────────────────────────────────────
+
+/// Init once boot is over, for the work it does from then on.
+static INIT: Mutex<Option<InitSystem>> = Mutex::new(None);
+
+/// Service processes that have exited or been killed since init last
+/// looked.
+static EXITED: Mutex<Vec<ProcessId>> = Mutex::new(Vec::new());
+
+/// Watchdog pings since init last looked, with the uptime of each.
+static PINGS: Mutex<Vec<(ProcessId, u64)>> = Mutex::new(Vec::new());
+
+/// Uptime init next has something to do at: a restart, or a deadline to
+/// report ready or ping by.  u64::MAX: nothing.
+static NEXT_DUE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// `pid` has exited or been killed: if it was a service, init restarts it
+/// as its policy says.
+pub fn child_exited(pid: ProcessId) {
+    EXITED.lock().push(pid);
+    process::defer(supervise);
+}
+
+/// `pid` is still alive (SYS_SERVICE_NOTIFY): its watchdog starts over.
+pub fn notify_watchdog(pid: ProcessId) {
+    PINGS.lock().push((pid, process::uptime_ms()));
+    process::defer(supervise);
+}
+
+/// Timer-tick check for supervision that has come due.
+pub fn init_tick() {
+    if process::uptime_ms() >= NEXT_DUE.load(Ordering::Relaxed) {
+        process::defer(supervise);
+    }
+}
+
+/// The network stack has run: a socket-activated service may have a
+/// connection waiting.
+pub fn socket_activity() {
+    if INIT.lock().as_ref().is_some_and(|init| init.services.iter().any(|s| s.status == ServiceStatus::Listening)) {
+        process::defer(supervise);
+    }
+}
+
+/// Init's work after boot, run as deferred work: take readiness reports
+/// and watchdog pings, deal with services whose process has gone or that
+/// are late to report ready or to ping, restart those that are due, and
+/// start socket-activated services that have a client.
+fn supervise() {
+    let mut init = INIT.lock();
+    let Some(init) = init.as_mut() else { return };
+    let ready = core::mem::take(&mut *READY.lock());
+    init.mark_ready(&ready);
+    let pings = core::mem::take(&mut *PINGS.lock());
+    init.pinged(&pings);
+    let exited = core::mem::take(&mut *EXITED.lock());
+    init.exited(&exited);
+    init.expire();
+    init.watchdog();
+    init.restart_due();
+    init.activate();
+    NEXT_DUE.store(init.next_due().unwrap_or(u64::MAX), Ordering::Relaxed);
+}
+
+/// The sockets init passed the calling service, in the order its config
+/// lists them (SYS_SERVICE_SOCKETS).
+pub fn listen_sockets(pid: ProcessId) -> Vec<Socket> {
+    INIT.lock().as_ref()
+        .and_then(|init| init.services.iter().find(|s| s.pid == Some(pid) && s.status != ServiceStatus::Listening))
+        .map(|s| s.sockets.clone())
+        .unwrap_or_default()
+}
+
+/// Each service and how it is doing, once boot is over.
+pub fn services() -> Vec<Service> {
+    INIT.lock().as_ref().map(|init| init.services.clone()).unwrap_or_default()
+}
+
+/// In-kernel services started but not yet reported in.  They have no
+/// main loop of their own, so they report from deferred work, as soon as
+/// init waits.
+static BUILTIN_STARTING: Mutex<Vec<ProcessId>> = Mutex::new(Vec::new());
+
+fn builtins_ready() {
+    let pids = core::mem::take(&mut *BUILTIN_STARTING.lock());
+    for pid in pids { notify_ready(pid); }
+}
+
+impl Default for InitSystem {
+    fn default() -> Self { Self::new() }
+}
+
 impl InitSystem {
     pub fn new() -> Self {
         InitSystem {
             pid: ProcessId(1),
-            services: Vec::new(),
+            services: CORE_SERVICES.iter().cloned().map(Service::new).collect(),
+            booted: false,
         }
     }
 
     /// Entry point — called by kernel after all hardware is initialised
-    pub fn run(&mut self) -> ! {
+    pub fn run(mut self) -> ! {
         self.print_boot_banner();
         self.setup_filesystem();
         self.start_services();
         self.print_ready();
-        // Hand off to the interactive shell — never returns
+        // From here init works in the background
+        self.booted = true;
+        *INIT.lock() = Some(self);
+        process::defer(supervise);
+        // A test kernel runs its tests in place of the shell
+        #[cfg(feature = "ktest")]
+        crate::ktest::run();
+        #[cfg(not(feature = "ktest"))]
+        Self::hand_off()
+    }
+
+    /// Hand off to the interactive shell — never returns.  Another
+    /// `init=` is a sursh script, run first.
+    fn hand_off() -> ! {
         let mut shell = Shell::new();
+        let init = crate::cmdline::options().init;
+        if init != crate::cmdline::DEFAULT_INIT {
+            Self::run_script(&mut shell, init);
+        }
         shell.run()
     }
 
+    fn run_script(shell: &mut Shell, path: &str) {
+        let script = match read_file(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                println!("  [init] init={}: {}; starting the shell", path, e);
+                return;
+            }
+        };
+        for line in String::from_utf8_lossy(&script).lines() {
+            shell.execute(line);
+        }
+    }
+
     fn print_boot_banner(&self) {
         println!("");
         println!("╔══════════════════════════════════════════════════════════════╗");
@@ -84,6 +476,7 @@ impl InitSystem {
         // Create essential config files
         write_file("/etc/hostname", b"suraksha\n").ok();
         write_file("/etc/os-release", b"NAME=SurakshaOS\nVERSION=0.2.0\n").ok();
+        write_file("/proc/cmdline", alloc::format!("{}\n", crate::cmdline::raw()).as_bytes()).ok();
         write_file(
             "/etc/motd",
             b"Welcome to SurakshaOS - Digital Sovereignty for All\n",
@@ -94,55 +487,387 @@ impl InitSystem {
 
     fn start_services(&mut self) {
         println!("  [init] Starting core services...");
+        if let Err(e) = self.start_all() {
+            println!("         └─ {}", e);
+            let name = match &e {
+                InitError::UnknownDependency { service, .. } => service,
+                InitError::DependencyCycle(cycle) => &cycle[0],
+            };
+            self.kernel_panic(name);
+        }
+        println!("         └─ all services started");
 
-        let service_defs: &[(&'static str, bool)] = &[
-            ("memory-guard",  true),
-            ("capability-mgr", true),
-            ("entropy-pool",   true),
-            ("device-manager", false),
-            ("logger",         false),
-        ];
+        // The critical services came up: this slot boots
+        if let Err(e) = crate::bootctl::mark_successful() {
+            println!("  [init] boot not marked successful: {}", e);
+        }
+    }
+
+    /// Order the services so each comes after everything it requires or
+    /// is to start after: a topological sort, or the cycle that prevents
+    /// one.
+    pub fn resolve_dependencies(&self) -> Result<Vec<usize>, InitError> {
+        let n = self.services.len();
+        // deps[i]: the services i waits for
+        let mut deps: Vec<Vec<usize>> = Vec::with_capacity(n);
+        for s in &self.services {
+            let mut d = Vec::new();
+            for &name in s.config.requires {
+                let j = self.find(name)
+                    .ok_or(InitError::UnknownDependency { service: s.name(), dependency: name })?;
+                d.push(j);
+            }
+            d.extend(s.config.after.iter().filter_map(|name| self.find(name)));
+            d.sort_unstable();
+            d.dedup();
+            deps.push(d);
+        }
+
+        // Kahn's algorithm, taking services in the order configured
+        let mut waiting: Vec<usize> = deps.iter().map(Vec::len).collect();
+        let mut order = Vec::with_capacity(n);
+        let mut done = alloc::vec![false; n];
+        while order.len() < n {
+            let Some(i) = (0..n).find(|&i| !done[i] && waiting[i] == 0) else { break };
+            done[i] = true;
+            order.push(i);
+            for (j, d) in deps.iter().enumerate() {
+                if d.contains(&i) { waiting[j] -= 1; }
+            }
+        }
+        if order.len() == n { return Ok(order); }
+
+        // Every service left waits on another one left: follow them
+        // until one repeats
+        let mut path = Vec::new();
+        let mut i = (0..n).find(|&i| !done[i]).unwrap_or(0);
+        while !path.contains(&i) {
+            path.push(i);
+            i = deps[i].iter().copied().find(|&j| !done[j]).unwrap_or(i);
+        }
+        let from = path.iter().position(|&j| j == i).unwrap_or(0);
+        let mut cycle: Vec<&'static str> = path[from..].iter().map(|&j| self.services[j].name()).collect();
+        cycle.push(self.services[i].name());
+        Err(InitError::DependencyCycle(cycle))
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.services.iter().position(|s| s.name() == name)
+    }
 
-        for &(name, critical) in service_defs {
-            print!("         ├─ {:<20}", name);
-            match self.start_service(name, critical) {
-                Ok(pid) => {
-                    println!(" [  OK  ]  pid={}", pid.0);
-                    self.services.push(Service {
-                        name,
-                        pid: Some(pid),
-                        status: ServiceStatus::Running,
-                        critical,
-                    });
+    /// Start every service, each as soon as what it waits for has
+    /// settled, so services that do not depend on each other start
+    /// together and come up in parallel.  Socket-activated services only
+    /// have their sockets opened.  A critical service that fails stops
+    /// boot.
+    pub fn start_all(&mut self) -> Result<(), InitError> {
+        let order = self.resolve_dependencies()?;
+        for &i in &order {
+            if !self.services[i].config.listen.is_empty() { self.open_sockets(i); }
+        }
+        loop {
+            for &i in &order {
+                if self.services[i].status != ServiceStatus::Stopped { continue; }
+                match self.dependencies_settled(i) {
+                    None => {}
+                    Some(Ok(())) => self.launch(i),
+                    Some(Err(dep)) => self.failed(i, &alloc::format!("requires {}, which failed", dep)),
                 }
-                Err(e) => {
-                    println!(" [FAIL ]  {}", e);
-                    self.services.push(Service {
-                        name,
-                        pid: None,
-                        status: ServiceStatus::Failed,
-                        critical,
-                    });
-                    if critical {
-                        self.kernel_panic(name);
+            }
+
+            let Some(deadline) = self.next_deadline() else { break };
+            let ready = READY_WAIT.wait_until(Some(deadline), || {
+                let pids = core::mem::take(&mut *READY.lock());
+                (!pids.is_empty()).then_some(pids)
+            });
+            self.mark_ready(&ready.unwrap_or_default());
+            self.expire();
+        }
+        Ok(())
+    }
+
+    /// When the first service still starting runs out of time to report
+    /// ready; None if none is starting.
+    fn next_deadline(&self) -> Option<u64> {
+        self.services.iter()
+            .filter(|s| s.status == ServiceStatus::Starting)
+            .map(|s| s.started_ms + s.config.ready_timeout_ms)
+            .min()
+    }
+
+    /// Services in `ready` that were starting are running.
+    fn mark_ready(&mut self, ready: &[ProcessId]) {
+        for &pid in ready {
+            let Some(s) = self.services.iter_mut()
+                .find(|s| s.pid == Some(pid) && s.status == ServiceStatus::Starting) else { continue };
+            s.status = ServiceStatus::Running;
+            s.pinged_ms = process::uptime_ms();
+            let took = s.pinged_ms.saturating_sub(s.started_ms);
+            if self.booted {
+                crate::info!("{}: ready, pid {}, {} ms", s.name(), pid, took);
+            } else {
+                println!("         ├─ {:<20} [  OK  ]  pid={}  {} ms", s.name(), pid.0, took);
+            }
+        }
+    }
+
+    /// Services that did not report ready in time have failed.
+    fn expire(&mut self) {
+        let now = process::uptime_ms();
+        for i in 0..self.services.len() {
+            let s = &self.services[i];
+            if s.status == ServiceStatus::Starting && now >= s.started_ms + s.config.ready_timeout_ms {
+                self.failed(i, "not ready in time");
+            }
+        }
+    }
+
+    /// Running services that pinged start their watchdog over.
+    fn pinged(&mut self, pings: &[(ProcessId, u64)]) {
+        for &(pid, at) in pings {
+            if let Some(s) = self.services.iter_mut().find(|s| s.pid == Some(pid) && s.status == ServiceStatus::Running) {
+                s.pinged_ms = at;
+            }
+        }
+    }
+
+    /// Running services that did not ping in time have hung.
+    fn watchdog(&mut self) {
+        let now = process::uptime_ms();
+        for i in 0..self.services.len() {
+            let s = &self.services[i];
+            let Some(interval) = s.config.watchdog_ms else { continue };
+            if s.status == ServiceStatus::Running && now >= s.pinged_ms + interval {
+                self.failed(i, "watchdog expired");
+            }
+        }
+    }
+
+    /// Services whose process in `exited` has gone.  One that was killed
+    /// has failed; one that exited is restarted only if it always is.
+    fn exited(&mut self, exited: &[ProcessId]) {
+        for &pid in exited {
+            let Some(i) = self.services.iter().position(|s| {
+                s.pid == Some(pid) && matches!(s.status, ServiceStatus::Starting | ServiceStatus::Running)
+            }) else { continue };
+            self.services[i].pid = None;
+            self.reclaim(i, pid);
+            if process::is_killed(pid) {
+                self.failed(i, "killed");
+                continue;
+            }
+            let s = &mut self.services[i];
+            if s.config.restart == RestartPolicy::Always {
+                crate::info!("{}: exited", s.name());
+                self.schedule_restart(i);
+            } else {
+                crate::info!("{}: exited; stopped", s.name());
+                s.status = ServiceStatus::Stopped;
+            }
+        }
+    }
+
+    /// Take back the sockets service `i` was handed as `pid`, which has
+    /// gone, to listen on again.
+    fn reclaim(&mut self, i: usize, pid: ProcessId) {
+        let s = &mut self.services[i];
+        s.granted.clear();
+        if s.sockets.is_empty() { return; }
+        let (init, sockets) = (self.pid, s.sockets.clone());
+        let cap = capability::create_capability(init, CapabilityType::Network, Permissions::READ | Permissions::WRITE);
+        let taken = process::run_as(pid, || sockets.iter().try_for_each(|&k| socket::give(k, init, cap.clone())));
+        if let Err(e) = taken {
+            crate::warn!("{}: sockets not taken back: {}", s.name(), e);
+            process::run_as(pid, || for &k in &sockets { let _ = socket::close(k); });
+            s.sockets.clear();
+        }
+    }
+
+    /// Restart service `i` once its backoff is over, unless it has been
+    /// restarted too often lately.
+    fn schedule_restart(&mut self, i: usize) {
+        let now = process::uptime_ms();
+        let s = &mut self.services[i];
+        let interval = s.config.restart_interval_ms;
+        s.restarts.retain(|&at| now.saturating_sub(at) < interval);
+        if s.restarts.len() >= s.config.restart_burst {
+            crate::error!("{}: restarted {} times in {} ms; giving up", s.name(), s.restarts.len(), interval);
+            s.status = ServiceStatus::Failed;
+            return;
+        }
+        let delay = RESTART_DELAY_MS.checked_shl(s.restarts.len() as u32).unwrap_or(u64::MAX).min(RESTART_DELAY_MAX_MS);
+        s.restarts.push(now + delay);
+        s.restart_at = now + delay;
+        s.status = ServiceStatus::Restarting;
+        crate::info!("{}: restarting in {} ms", s.name(), delay);
+    }
+
+    /// Restart each service whose backoff is over.  A socket-activated
+    /// one goes back to listening, to start on its next client.
+    fn restart_due(&mut self) {
+        let now = process::uptime_ms();
+        for i in 0..self.services.len() {
+            let s = &self.services[i];
+            if s.status != ServiceStatus::Restarting || now < s.restart_at { continue; }
+            if s.config.listen.is_empty() {
+                self.launch(i);
+            } else if s.sockets.is_empty() {
+                self.open_sockets(i);
+            } else {
+                self.services[i].status = ServiceStatus::Listening;
+            }
+        }
+    }
+
+    /// When init next has something to do: a service due to restart, or
+    /// to report ready or ping by.
+    fn next_due(&self) -> Option<u64> {
+        let watchdogs = self.services.iter()
+            .filter(|s| s.status == ServiceStatus::Running)
+            .filter_map(|s| s.config.watchdog_ms.map(|w| s.pinged_ms + w));
+        let restarts = self.services.iter()
+            .filter(|s| s.status == ServiceStatus::Restarting)
+            .map(|s| s.restart_at);
+        watchdogs.chain(restarts).chain(self.next_deadline()).min()
+    }
+
+    /// Whether what service `i` waits for has all settled: None while any
+    /// has not, or the name of a required one that failed.  A
+    /// socket-activated service counts as ready once its sockets are open.
+    fn dependencies_settled(&self, i: usize) -> Option<Result<(), &'static str>> {
+        let config = &self.services[i].config;
+        for &name in config.requires.iter().chain(config.after) {
+            let Some(dep) = self.services.iter().find(|s| s.name() == name) else { continue };
+            match dep.status {
+                ServiceStatus::Running | ServiceStatus::Listening => {}
+                ServiceStatus::Failed if config.after.contains(&name) && !config.requires.contains(&name) => {}
+                ServiceStatus::Failed => return Some(Err(dep.name())),
+                _ => return None,
+            }
+        }
+        Some(Ok(()))
+    }
+
+    /// Open service `i`'s sockets as init, to wait for a client on.
+    fn open_sockets(&mut self, i: usize) {
+        let cap = capability::create_capability(self.pid, CapabilityType::Network, Permissions::READ | Permissions::WRITE);
+        let listen = self.services[i].config.listen;
+        let opened: Result<Vec<Socket>, &'static str> = process::run_as(self.pid, || {
+            let mut sockets = Vec::new();
+            for l in listen {
+                match l.open(&cap) {
+                    Ok(s) => sockets.push(s),
+                    Err(e) => {
+                        for s in sockets { let _ = socket::close(s); }
+                        return Err(e);
                     }
                 }
             }
+            Ok(sockets)
+        });
+        match opened {
+            Ok(sockets) => {
+                let s = &mut self.services[i];
+                s.sockets = sockets;
+                s.status = ServiceStatus::Listening;
+                if !self.booted {
+                    println!("         ├─ {:<20} [LISTEN]  {} socket(s)", s.name(), s.sockets.len());
+                }
+            }
+            Err(e) => self.failed(i, e),
+        }
+    }
+
+    /// Start each socket-activated service with a client waiting on one
+    /// of its sockets, and hand it the sockets.
+    fn activate(&mut self) {
+        for i in 0..self.services.len() {
+            let s = &self.services[i];
+            if s.status != ServiceStatus::Listening { continue; }
+            let waiting = process::run_as(self.pid, || {
+                s.sockets.iter().any(|&k| socket::readiness(k).is_ok_and(|r| r.contains(Ready::READABLE)))
+            });
+            if waiting { self.launch(i); }
         }
-        println!("         └─ all services started");
     }
 
-    fn start_service(&self, name: &str, _critical: bool) -> Result<ProcessId, &'static str> {
+    fn launch(&mut self, i: usize) {
+        let name = self.services[i].name();
+        let pid = match self.start_service(&self.services[i].config) {
+            Ok(pid) => pid,
+            Err(e) => return self.failed(i, e),
+        };
+        // A socket-activated service takes its sockets over
+        let sockets = self.services[i].sockets.clone();
+        if !sockets.is_empty() {
+            let cap = capability::create_capability(pid, CapabilityType::Network, Permissions::READ | Permissions::WRITE);
+            let given = process::run_as(self.pid, || {
+                sockets.iter().try_for_each(|&k| socket::give(k, pid, cap.clone()))
+            });
+            if let Err(e) = given { return self.failed(i, e); }
+            if self.booted { crate::info!("{}: activated by a client", name); }
+        }
+        self.grant(i, pid);
+        let s = &mut self.services[i];
+        s.pid = Some(pid);
+        s.status = ServiceStatus::Starting;
+        s.started_ms = process::uptime_ms();
+    }
+
+    /// Service `i` has failed: its process, if it still has one, is
+    /// killed.  During boot a critical one takes the system down with it;
+    /// after boot it is restarted if its policy says to.
+    fn failed(&mut self, i: usize, why: &str) {
+        if let Some(pid) = self.services[i].pid.take() {
+            // Built-in services cannot be killed, but are started afresh
+            let _ = process::kill(pid);
+            self.reclaim(i, pid);
+        }
+        let s = &mut self.services[i];
+        s.status = ServiceStatus::Failed;
+        if self.booted {
+            crate::error!("{}: failed: {}", s.name(), why);
+            if s.config.restart != RestartPolicy::Never { self.schedule_restart(i); }
+            return;
+        }
+        println!("         ├─ {:<20} [FAIL ]  {}", s.name(), why);
+        if s.config.critical {
+            let name = s.name();
+            self.kernel_panic(name);
+        }
+    }
+
+    /// Start the service `config` describes: confine it and grant it its
+    /// capabilities first, so it never runs with more.
+    fn start_service(&self, config: &ServiceConfig) -> Result<ProcessId, &'static str> {
         // In a real OS this would exec a binary from /bin/
         // For now we simulate with in-kernel service stubs
-        match name {
-            "memory-guard"   => Ok(ProcessId(2)),
-            "capability-mgr" => Ok(ProcessId(3)),
-            "entropy-pool"   => Ok(ProcessId(4)),
-            "device-manager" => Ok(ProcessId(5)),
-            "logger"         => Ok(ProcessId(6)),
-            _                => Err("unknown service"),
+        let pid = match config.name {
+            // A confined service is an ordinary process: system ones
+            // cannot be sandboxed
+            "memory-guard" | "capability-mgr" | "entropy-pool" | "device-manager" | "logger"
+                if config.sandbox.is_some() => process::spawn_process(config.name)?,
+            "memory-guard"   => ProcessId(2),
+            "capability-mgr" => ProcessId(3),
+            "entropy-pool"   => ProcessId(4),
+            "device-manager" => ProcessId(5),
+            "logger"         => ProcessId(6),
+            _                => return Err("unknown service"),
+        };
+        if let Some(sandbox) = config.sandbox_config() {
+            sandbox::create_sandbox(pid, sandbox)?;
         }
+        BUILTIN_STARTING.lock().push(pid);
+        process::defer(builtins_ready);
+        Ok(pid)
+    }
+
+    /// Grant service `i`, started as `pid`, the capabilities it is allowed.
+    fn grant(&mut self, i: usize, pid: ProcessId) {
+        let s = &mut self.services[i];
+        s.granted = s.config.capabilities.iter()
+            .map(|&(ty, perms)| capability::create_capability(pid, ty, perms))
+            .collect();
     }
 
     fn print_ready(&self) {
@@ -157,6 +882,6 @@ impl InitSystem {
         println!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
         println!("  KERNEL PANIC: critical service '{}' failed to start", service);
         println!("!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!");
-        loop { unsafe { core::arch::asm!("wfi"); } }
+        loop { crate::arch::wait_for_interrupt(); }
     }
 }
@@ -0,0 +1,319 @@
+//! Android packages: APK Signature Scheme v2 and v3, and the package name
+//! and version from the binary AndroidManifest.xml.
+//!
+//! The signing block sits between the ZIP entries and the central
+//! directory, and signs everything around it: the entries, the central
+//! directory and the end record, hashed in 1 MiB chunks.  v3 is checked
+//! where present, v2 otherwise; a v2 block saying it was accompanied by
+//! v3 is refused, the v3 one having been stripped.  JAR (v1) signatures
+//! alone are not accepted.
+//!
+//! Signature algorithms: RSASSA-PKCS1-v1_5 and RSASSA-PSS with SHA-256,
+//! and ECDSA P-256 with SHA-256.  Every one a signer uses that we know
+//! must verify, with at least one; a package with more than one signer
+//! is refused.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::zip::{self, Archive};
+use crate::crypto::sha2;
+use crate::crypto::x509::{self, Hash, PublicKey, SignatureAlgorithm};
+
+const BLOCK_MAGIC: &[u8; 16] = b"APK Sig Block 42";
+
+const V2_BLOCK_ID: u32 = 0x7109_871a;
+const V3_BLOCK_ID: u32 = 0xf053_68c0;
+/// v2 signed attribute: the schemes the package was also signed with.
+const STRIPPING_PROTECTION: u32 = 0xbeef_f00d;
+
+const RSA_PSS_SHA256:   u32 = 0x0101;
+const RSA_PKCS1_SHA256: u32 = 0x0103;
+const ECDSA_SHA256:     u32 = 0x0201;
+
+const CHUNK: usize = 1 << 20;
+
+const MANIFEST: &str = "AndroidManifest.xml";
+/// Largest manifest we inflate.
+const MAX_MANIFEST: usize = 1 << 20;
+
+/// What a verified APK holds.
+#[derive(Debug, Clone)]
+pub struct Apk {
+    pub package: String,
+    pub version: u32,
+    /// 2 or 3.
+    pub scheme:  u8,
+    /// The signer's DER SubjectPublicKeyInfo, and the key in it.
+    pub spki:    Vec<u8>,
+    pub key:     PublicKey,
+}
+
+/// Check `apk`'s signature and read its manifest.  Whether its signer is
+/// trusted is the installer's to decide.
+pub fn verify(apk: &[u8]) -> Result<Apk, &'static str> {
+    let zip = zip::open(apk)?;
+    let (start, blocks) = signing_block(&zip)?;
+    let (scheme, signer) = match (find(&blocks, V3_BLOCK_ID), find(&blocks, V2_BLOCK_ID)) {
+        (Some(v3), _) => (3, v3),
+        (None, Some(v2)) => (2, v2),
+        (None, None) => return Err("package is not signed"),
+    };
+    let (spki, key) = verify_signer(&zip, start, scheme, signer)?;
+    let manifest = zip.read(zip.find(MANIFEST).ok_or("no AndroidManifest.xml")?)?;
+    if manifest.len() > MAX_MANIFEST { return Err("AndroidManifest.xml too large"); }
+    let (package, version) = manifest_identity(&manifest)?;
+    Ok(Apk { package, version, scheme, spki, key })
+}
+
+// ─── signing block ────────────────────────────────────────────────────────────
+
+fn u32_at(b: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(b.get(i..i + 4)?.try_into().ok()?))
+}
+
+fn u64_at(b: &[u8], i: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(b.get(i..i + 8)?.try_into().ok()?))
+}
+
+/// A signing block's (id, value) pairs.
+type Blocks<'a> = Vec<(u32, &'a [u8])>;
+
+/// Where the signing block starts, and its pairs.
+fn signing_block<'a>(zip: &Archive<'a>) -> Result<(usize, Blocks<'a>), &'static str> {
+    let bad = "malformed APK signing block";
+    let end = zip.central;
+    let data = zip.data;
+    if end < 32 || &data[end - 16..end] != BLOCK_MAGIC { return Err("package is not signed"); }
+    let size = u64_at(data, end - 24).ok_or(bad)? as usize;
+    let start = end.checked_sub(size.checked_add(8).ok_or(bad)?).ok_or(bad)?;
+    if u64_at(data, start) != Some(size as u64) { return Err(bad); }
+
+    let mut pairs = &data[start + 8..end - 24];
+    let mut blocks = Vec::new();
+    while !pairs.is_empty() {
+        let len = u64_at(pairs, 0).ok_or(bad)? as usize;
+        if len < 4 || len > pairs.len() - 8 { return Err(bad); }
+        blocks.push((u32_at(pairs, 8).ok_or(bad)?, &pairs[12..8 + len]));
+        pairs = &pairs[8 + len..];
+    }
+    Ok((start, blocks))
+}
+
+fn find<'a>(blocks: &[(u32, &'a [u8])], id: u32) -> Option<&'a [u8]> {
+    blocks.iter().find(|(i, _)| *i == id).map(|(_, v)| *v)
+}
+
+/// Reads length-prefixed (u32 LE) fields.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        let v = u32_at(self.0, 0).ok_or("truncated APK signature")?;
+        self.0 = &self.0[4..];
+        Ok(v)
+    }
+
+    fn prefixed(&mut self) -> Result<&'a [u8], &'static str> {
+        let len = self.u32()? as usize;
+        let v = self.0.get(..len).ok_or("truncated APK signature")?;
+        self.0 = &self.0[len..];
+        Ok(v)
+    }
+
+    /// Each length-prefixed item in a length-prefixed sequence.
+    fn sequence(&mut self) -> Result<Vec<&'a [u8]>, &'static str> {
+        let mut items = Fields(self.prefixed()?);
+        let mut v = Vec::new();
+        while !items.0.is_empty() { v.push(items.prefixed()?); }
+        Ok(v)
+    }
+}
+
+fn algorithm(id: u32) -> Option<SignatureAlgorithm> {
+    match id {
+        RSA_PSS_SHA256   => Some(SignatureAlgorithm::RsaPss(Hash::Sha256)),
+        RSA_PKCS1_SHA256 => Some(SignatureAlgorithm::RsaPkcs1(Hash::Sha256)),
+        ECDSA_SHA256     => Some(SignatureAlgorithm::Ecdsa(Hash::Sha256)),
+        _ => None,
+    }
+}
+
+/// Check the one signer in a v2 or v3 block: its signatures over its
+/// signed data, that data's digest of the package, and its certificate.
+fn verify_signer(zip: &Archive, start: usize, scheme: u8, block: &[u8]) -> Result<(Vec<u8>, PublicKey), &'static str> {
+    let signers = Fields(block).sequence()?;
+    let [signer] = signers[..] else {
+        return Err(if signers.is_empty() { "package is not signed" } else { "packages with several signers are not supported" });
+    };
+    let mut f = Fields(signer);
+    let signed = f.prefixed()?;
+    if scheme == 3 { f.u32()?; f.u32()?; } // SDK range
+    let signatures = f.sequence()?;
+    let spki = f.prefixed()?;
+    let key = x509::parse_spki(spki).ok_or("unsupported signer key")?;
+
+    let mut used = Vec::new();
+    for s in signatures {
+        let mut s = Fields(s);
+        let id = s.u32()?;
+        let sig = s.prefixed()?;
+        used.push(id);
+        if let Some(alg) = algorithm(id) { key.verify(alg, signed, sig)?; }
+    }
+    if !used.iter().any(|&id| algorithm(id).is_some()) { return Err("no signature algorithm we support"); }
+
+    let mut d = Fields(signed);
+    let digests = d.sequence()?;
+    let certificates = d.sequence()?;
+    if scheme == 3 { d.u32()?; d.u32()?; }
+    let attributes = d.sequence()?;
+
+    let mut digested = Vec::new();
+    let content = chunked_sha256(zip, start);
+    for dg in digests {
+        let mut dg = Fields(dg);
+        let id = dg.u32()?;
+        let digest = dg.prefixed()?;
+        digested.push(id);
+        if algorithm(id).is_some() && digest != content { return Err("package contents do not match the signature"); }
+    }
+    used.sort_unstable();
+    digested.sort_unstable();
+    if used != digested { return Err("signatures and digests do not match"); }
+
+    let cert = x509::parse(certificates.first().ok_or("no signer certificate")?)?;
+    if cert.spki_hash != sha2::sha256(spki) { return Err("certificate is not the signer's"); }
+
+    if scheme == 2 {
+        for a in attributes {
+            let mut a = Fields(a);
+            if a.u32()? == STRIPPING_PROTECTION && a.u32()? == 3 { return Err("v3 signature was stripped"); }
+        }
+    }
+    Ok((spki.to_vec(), key))
+}
+
+/// The v2/v3 content digest: SHA-256 over each 1 MiB chunk of the
+/// entries, the central directory and the end record (its central
+/// directory offset pointing at the signing block), then over those.
+fn chunked_sha256(zip: &Archive, start: usize) -> [u8; 32] {
+    let mut eocd = zip.data[zip.eocd..].to_vec();
+    eocd[16..20].copy_from_slice(&(start as u32).to_le_bytes());
+    let sections: [&[u8]; 3] = [&zip.data[..start], &zip.data[zip.central..zip.eocd], &eocd];
+    let chunks: Vec<&[u8]> = sections.iter().flat_map(|s| s.chunks(CHUNK)).collect();
+    let mut top = sha2::Sha256::default();
+    top.update(&[0x5a]);
+    top.update(&(chunks.len() as u32).to_le_bytes());
+    for c in chunks {
+        let mut h = sha2::Sha256::default();
+        h.update(&[0xa5]);
+        h.update(&(c.len() as u32).to_le_bytes());
+        h.update(c);
+        top.update(&h.finish());
+    }
+    top.finish()
+}
+
+// ─── manifest ─────────────────────────────────────────────────────────────────
+
+const RES_XML_TYPE:           u16 = 0x0003;
+const RES_STRING_POOL_TYPE:   u16 = 0x0001;
+const RES_XML_RESOURCE_MAP:   u16 = 0x0180;
+const RES_XML_START_ELEMENT:  u16 = 0x0102;
+
+const UTF8_FLAG: u32 = 1 << 8;
+
+const TYPE_STRING:  u8 = 0x03;
+const TYPE_INT_DEC: u8 = 0x10;
+const TYPE_INT_HEX: u8 = 0x11;
+/// android:versionCode
+const ATTR_VERSION_CODE: u32 = 0x0101_021b;
+
+fn u16_at(b: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(b.get(i..i + 2)?.try_into().ok()?))
+}
+
+/// String `i` of the pool chunk `pool`.
+fn pool_string(pool: &[u8], i: u32) -> Option<String> {
+    let count = u32_at(pool, 8)?;
+    if i >= count { return None; }
+    let utf8 = u32_at(pool, 16)? & UTF8_FLAG != 0;
+    let strings = u32_at(pool, 20)? as usize;
+    let header = u16_at(pool, 2)? as usize;
+    let at = strings + u32_at(pool, header + 4 * i as usize)? as usize;
+    if utf8 {
+        // UTF-16 length, then UTF-8 length, each one or two bytes
+        let len_at = |p: usize| -> Option<(usize, usize)> {
+            let b = *pool.get(p)? as usize;
+            if b & 0x80 != 0 { Some(((b & 0x7f) << 8 | *pool.get(p + 1)? as usize, p + 2)) } else { Some((b, p + 1)) }
+        };
+        let (_, p) = len_at(at)?;
+        let (len, p) = len_at(p)?;
+        Some(String::from(core::str::from_utf8(pool.get(p..p + len)?).ok()?))
+    } else {
+        let mut len = u16_at(pool, at)? as usize;
+        let mut p = at + 2;
+        if len & 0x8000 != 0 {
+            len = (len & 0x7fff) << 16 | u16_at(pool, p)? as usize;
+            p += 2;
+        }
+        let units: Vec<u16> = (0..len).map(|k| u16_at(pool, p + 2 * k)).collect::<Option<_>>()?;
+        char::decode_utf16(units).collect::<Result<String, _>>().ok()
+    }
+}
+
+/// The package name and versionCode from the `<manifest>` element of a
+/// binary AndroidManifest.xml.
+fn manifest_identity(xml: &[u8]) -> Result<(String, u32), &'static str> {
+    let bad = "malformed AndroidManifest.xml";
+    if u16_at(xml, 0) != Some(RES_XML_TYPE) { return Err(bad); }
+    let mut at = u16_at(xml, 2).ok_or(bad)? as usize;
+    let mut pool: &[u8] = &[];
+    let mut ids: Vec<u32> = Vec::new();
+    while at + 8 <= xml.len() {
+        let ty = u16_at(xml, at).ok_or(bad)?;
+        let header = u16_at(xml, at + 2).ok_or(bad)? as usize;
+        let size = u32_at(xml, at + 4).ok_or(bad)? as usize;
+        let chunk = xml.get(at..at.checked_add(size).ok_or(bad)?).ok_or(bad)?;
+        if size < 8 { return Err(bad); }
+        match ty {
+            RES_STRING_POOL_TYPE => pool = chunk,
+            RES_XML_RESOURCE_MAP => ids = chunk.get(header..).ok_or(bad)?.as_chunks::<4>().0.iter().map(|c| u32::from_le_bytes(*c)).collect(),
+            RES_XML_START_ELEMENT => {
+                let name = u32_at(chunk, 20).ok_or(bad)?;
+                if pool_string(pool, name).as_deref() != Some("manifest") { return Err(bad); }
+                return manifest_attributes(chunk, pool, &ids);
+            }
+            _ => {}
+        }
+        at += size;
+    }
+    Err(bad)
+}
+
+fn manifest_attributes(element: &[u8], pool: &[u8], ids: &[u32]) -> Result<(String, u32), &'static str> {
+    let bad = "malformed AndroidManifest.xml";
+    let start = 16 + u16_at(element, 24).ok_or(bad)? as usize;
+    let size = u16_at(element, 26).ok_or(bad)? as usize;
+    let count = u16_at(element, 28).ok_or(bad)? as usize;
+    if size < 20 { return Err(bad); }
+    let (mut package, mut version) = (None, None);
+    for i in 0..count {
+        let a = element.get(start + i * size..start + (i + 1) * size).ok_or(bad)?;
+        let name_idx = u32_at(a, 4).ok_or(bad)?;
+        let raw = u32_at(a, 8).ok_or(bad)?;
+        let ty = a[15];
+        let data = u32_at(a, 16).ok_or(bad)?;
+        let name = pool_string(pool, name_idx).unwrap_or_default();
+        let id = ids.get(name_idx as usize).copied();
+        if name == "package" {
+            let idx = if raw != u32::MAX { raw } else if ty == TYPE_STRING { data } else { return Err(bad) };
+            package = pool_string(pool, idx);
+        } else if id == Some(ATTR_VERSION_CODE) || (id.is_none() && name == "versionCode") {
+            if ty != TYPE_INT_DEC && ty != TYPE_INT_HEX { return Err("versionCode is not an integer"); }
+            version = Some(data);
+        }
+    }
+    Ok((package.ok_or("manifest has no package name")?, version.ok_or("manifest has no versionCode")?))
+}
@@ -0,0 +1,453 @@
+//! SurakshaOS App Installer
+//! installd: the one way an app gets onto the device.  A package is
+//! checked before anything of it is written:
+//!
+//! - its signature, ML-DSA-65 or ECDSA P-256, over everything before the
+//!   signature block — or for an Android package (APK), its v2/v3 APK
+//!   signature, RSA or ECDSA P-256, with the name and version taken from
+//!   its manifest (see `apk`);
+//! - the signing key against policy: a store key is always accepted, a
+//!   developer key only in developer mode, anything else never — and an
+//!   unsigned package is refused outright;
+//! - on an upgrade, that the signer is the one the app was first installed
+//!   with and the version does not go backwards;
+//! - for a native (ELF) payload, that `cfi::admit` will let it run.
+//!
+//! Trusted keys are the files in STORE_KEYS and DEVELOPER_KEYS, raw public
+//! keys named `*.mldsa`, `*.p256` (uncompressed SEC1) or `*.rsa` (DER
+//! SubjectPublicKeyInfo, APKs only).  Installs, upgrades, removals and
+//! refusals all go to the audit log.
+//!
+//! Package format (little-endian):
+//!
+//!   magic "SPKG" | format u8 (1) | name len u8 | name | version u32
+//!   | payload len u32 | payload
+//!   | alg u8 | key len u16 | key | signature len u16 | signature
+//!
+//! An ML-DSA signature is over the SHAKE-256 digest of the signed bytes,
+//! under INSTALL_SIG_CTX; an ECDSA one (DER) over their SHA-256.  An APK
+//! is installed whole, as the app's image.
+
+pub mod apk;
+pub mod zip;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::crypto::x509::{self, PublicKey};
+use crate::crypto::{ecdsa, mldsa, sha2, sha3};
+use crate::fs;
+use crate::ipc::{self, ChannelId, Message};
+use crate::process::{self, ProcessId};
+
+pub const STORE_KEYS:     &str = "/etc/install/store";
+pub const DEVELOPER_KEYS: &str = "/etc/install/developer";
+/// The installed apps, one per line.
+pub const REGISTRY_PATH:  &str = "/etc/install/apps";
+/// Present while developer mode is on.
+pub const DEV_MODE_PATH:  &str = "/etc/install/developer-mode";
+/// Each app's payload is APP_DIR/<name>/image.
+pub const APP_DIR:        &str = "/apps";
+
+const MAGIC:  &[u8; 4] = b"SPKG";
+const FORMAT: u8       = 1;
+
+/// ML-DSA context string for package signatures.
+pub const INSTALL_SIG_CTX: &[u8] = b"surakshaos-package-v1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    MlDsa65,
+    EcdsaP256,
+    /// RSA, for APK signers only.
+    Rsa,
+}
+
+impl Algorithm {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Algorithm::MlDsa65   => "ml-dsa-65",
+            Algorithm::EcdsaP256 => "ecdsa-p256",
+            Algorithm::Rsa       => "rsa",
+        }
+    }
+
+    fn from_u8(b: u8) -> Option<Algorithm> {
+        match b {
+            1 => Some(Algorithm::MlDsa65),
+            2 => Some(Algorithm::EcdsaP256),
+            _ => None,
+        }
+    }
+}
+
+/// Whose key signed a package.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Store,
+    Developer,
+}
+
+impl Source {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Source::Store     => "store",
+            Source::Developer => "developer",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Source> {
+        match s {
+            "store"     => Some(Source::Store),
+            "developer" => Some(Source::Developer),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Installed {
+    pub name:    String,
+    pub version: u32,
+    pub source:  Source,
+    /// First 16 bytes of the SHAKE-256 digest of the signing key.
+    pub signer:  [u8; 16],
+}
+
+struct TrustedKey {
+    source: Source,
+    alg:    Algorithm,
+    key:    Vec<u8>,
+}
+
+struct Installer {
+    pid:      Option<ProcessId>,
+    keys:     Vec<TrustedKey>,
+    apps:     Vec<Installed>,
+    dev_mode: bool,
+}
+
+static INSTALLER: Mutex<Installer> = Mutex::new(Installer { pid: None, keys: Vec::new(), apps: Vec::new(), dev_mode: false });
+/// Channels of clients, with the AppInstall capability each connected with.
+static CLIENTS:   Mutex<Vec<(ChannelId, Capability)>> = Mutex::new(Vec::new());
+
+fn fingerprint(key: &[u8]) -> [u8; 16] {
+    let mut fp = [0u8; 16];
+    sha3::shake256_into(key, &mut fp);
+    fp
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= crate::permission::MAX_APP_NAME
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"._-".contains(&b))
+        && !name.starts_with('.')
+}
+
+// ─── packages ─────────────────────────────────────────────────────────────────
+
+/// A package that parsed; its signature is not yet checked.
+pub struct Package<'a> {
+    pub name:    &'a str,
+    pub version: u32,
+    pub payload: &'a [u8],
+    pub alg:     Algorithm,
+    pub key:     &'a [u8],
+    signed:      &'a [u8],
+    signature:   &'a [u8],
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], &'static str> {
+        let out = self.buf.get(self.pos..self.pos.checked_add(n).ok_or("truncated package")?).ok_or("truncated package")?;
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, &'static str> { Ok(self.take(1)?[0]) }
+
+    fn u16(&mut self) -> Result<usize, &'static str> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]) as usize)
+    }
+
+    fn u32(&mut self) -> Result<u32, &'static str> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+pub fn parse(package: &[u8]) -> Result<Package<'_>, &'static str> {
+    let mut r = Reader { buf: package, pos: 0 };
+    if r.take(4)? != MAGIC { return Err("not a SurakshaOS package"); }
+    if r.u8()? != FORMAT { return Err("unsupported package format"); }
+    let len = r.u8()? as usize;
+    let name = core::str::from_utf8(r.take(len)?).map_err(|_| "bad app name")?;
+    if !valid_name(name) { return Err("bad app name"); }
+    let version = r.u32()?;
+    let len = r.u32()? as usize;
+    let payload = r.take(len)?;
+    let signed = &package[..r.pos];
+    if r.pos == package.len() { return Err("package is not signed"); }
+    let alg = Algorithm::from_u8(r.u8()?).ok_or("unknown signature algorithm")?;
+    let len = r.u16()?;
+    let key = r.take(len)?;
+    let len = r.u16()?;
+    let signature = r.take(len)?;
+    if r.pos != package.len() { return Err("trailing bytes after signature"); }
+    Ok(Package { name, version, payload, alg, key, signed, signature })
+}
+
+impl Package<'_> {
+    /// Check the signature against the key the package carries.
+    pub fn check_signature(&self) -> Result<(), &'static str> {
+        match self.alg {
+            Algorithm::MlDsa65 => {
+                if self.key.len() != mldsa::PUBLIC_KEY_LEN { return Err("bad ML-DSA-65 public key length"); }
+                let mut digest = [0u8; 64];
+                sha3::shake256_into(self.signed, &mut digest);
+                if mldsa::verify(self.key, &digest, INSTALL_SIG_CTX, self.signature) { Ok(()) } else { Err("signature mismatch") }
+            }
+            Algorithm::EcdsaP256 => {
+                let (r, s) = ecdsa::parse_der_signature(self.signature).ok_or("bad ECDSA signature encoding")?;
+                ecdsa::verify(ecdsa::Curve::P256, self.key, &sha2::sha256(self.signed), r, s)
+            }
+            Algorithm::Rsa => Err("RSA signatures are only accepted on APKs"),
+        }
+    }
+}
+
+/// Whose key `key` is, if it is trusted at all.
+fn source_of(inst: &Installer, alg: Algorithm, key: &[u8]) -> Option<Source> {
+    let k = inst.keys.iter().find(|k| k.alg == alg && k.key == key)?;
+    Some(k.source)
+}
+
+/// The policy, for a package whose signature checked: a trusted signer,
+/// the same one as the installed app's, and no downgrade.
+fn admit(name: &str, version: u32, alg: Algorithm, key: &[u8]) -> Result<Installed, &'static str> {
+    let inst = INSTALLER.lock();
+    let source = source_of(&inst, alg, key).ok_or("signed by a key that is not trusted")?;
+    if source == Source::Developer && !inst.dev_mode { return Err("developer-signed packages need developer mode"); }
+    let signer = fingerprint(key);
+    if let Some(old) = inst.apps.iter().find(|a| a.name == name) {
+        if old.signer != signer { return Err("signed by a different key than the installed app"); }
+        if version < old.version { return Err("older than the installed version"); }
+    }
+    Ok(Installed { name: String::from(name), version, source, signer })
+}
+
+/// Everything short of writing it: parse `package`, SPKG or APK, check
+/// its signature and the policy, and say what installing it would record.
+pub fn verify(package: &[u8]) -> Result<Installed, &'static str> {
+    if package.starts_with(zip::LOCAL_MAGIC) {
+        let apk = apk::verify(package)?;
+        if !valid_name(&apk.package) { return Err("bad app name"); }
+        // EC keys are trusted as raw points, as for SPKG packages
+        let (alg, key) = match &apk.key {
+            PublicKey::Ec { curve: ecdsa::Curve::P256, point } => (Algorithm::EcdsaP256, point.as_slice()),
+            PublicKey::Rsa { .. } => (Algorithm::Rsa, apk.spki.as_slice()),
+            PublicKey::Ec { .. } => return Err("unsupported signer key"),
+        };
+        return admit(&apk.package, apk.version, alg, key);
+    }
+    let pkg = parse(package)?;
+    pkg.check_signature()?;
+    let app = admit(pkg.name, pkg.version, pkg.alg, pkg.key)?;
+    if pkg.payload.starts_with(b"\x7fELF") { crate::cfi::admit(pkg.payload)?; }
+    Ok(app)
+}
+
+// ─── policy ───────────────────────────────────────────────────────────────────
+
+/// Accept packages signed with `key` as coming from `source`.
+pub fn trust_key(source: Source, alg: Algorithm, key: &[u8]) -> Result<(), &'static str> {
+    let len_ok = match alg {
+        Algorithm::MlDsa65   => key.len() == mldsa::PUBLIC_KEY_LEN,
+        Algorithm::EcdsaP256 => key.len() == 65 && key[0] == 4,
+        Algorithm::Rsa       => matches!(x509::parse_spki(key), Some(PublicKey::Rsa { .. })),
+    };
+    if !len_ok { return Err("bad public key"); }
+    let mut inst = INSTALLER.lock();
+    if !inst.keys.iter().any(|k| k.alg == alg && k.key == key) {
+        inst.keys.push(TrustedKey { source, alg, key: key.to_vec() });
+    }
+    Ok(())
+}
+
+fn load_keys(dir: &str, source: Source) -> Result<(), &'static str> {
+    let Ok(files) = fs::list_dir(dir) else { return Ok(()) };
+    for f in files.iter().filter(|f| !f.is_dir) {
+        let alg = if f.name.ends_with(".mldsa") { Algorithm::MlDsa65 }
+            else if f.name.ends_with(".p256") { Algorithm::EcdsaP256 }
+            else if f.name.ends_with(".rsa") { Algorithm::Rsa }
+            else { continue };
+        trust_key(source, alg, &fs::read_file(&format!("{}/{}", dir, f.name))?)?;
+    }
+    Ok(())
+}
+
+pub fn developer_mode() -> bool {
+    INSTALLER.lock().dev_mode
+}
+
+/// Turn developer mode on or off.  Apps already installed from developer
+/// keys stay; turning it off only stops new ones.
+pub fn set_developer_mode(cap: &Capability, on: bool) -> Result<(), &'static str> {
+    capability::validate(process::current_pid(), cap, CapabilityType::AppInstall, Permissions::CONTROL)?;
+    let mut inst = INSTALLER.lock();
+    if inst.dev_mode == on { return Ok(()); }
+    if on { fs::write_file(DEV_MODE_PATH, b"on\n")?; } else { fs::remove_file(DEV_MODE_PATH)?; }
+    inst.dev_mode = on;
+    crate::audit::note("install", &format!("developer mode {}", if on { "on" } else { "off" }));
+    Ok(())
+}
+
+pub fn installed() -> Vec<Installed> {
+    INSTALLER.lock().apps.clone()
+}
+
+// ─── install ──────────────────────────────────────────────────────────────────
+
+fn load() -> Result<(), &'static str> {
+    load_keys(STORE_KEYS, Source::Store)?;
+    load_keys(DEVELOPER_KEYS, Source::Developer)?;
+    let mut inst = INSTALLER.lock();
+    inst.dev_mode = fs::stat(DEV_MODE_PATH).is_ok();
+    let Ok(reg) = fs::read_file(REGISTRY_PATH) else { return Ok(()) };
+    let reg = core::str::from_utf8(&reg).map_err(|_| "install registry is not UTF-8")?;
+    for line in reg.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let [name, version, source, signer] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err("bad line in install registry");
+        };
+        let version = version.parse().map_err(|_| "bad version in install registry")?;
+        let source = Source::parse(source).ok_or("bad source in install registry")?;
+        let mut fp = [0u8; 16];
+        if signer.len() != 32 { return Err("bad signer in install registry"); }
+        for (i, b) in fp.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&signer[2 * i..2 * i + 2], 16).map_err(|_| "bad signer in install registry")?;
+        }
+        inst.apps.push(Installed { name: String::from(name), version, source, signer: fp });
+    }
+    Ok(())
+}
+
+fn save(inst: &Installer) -> Result<(), &'static str> {
+    let mut reg = String::from("# SurakshaOS installed apps: <name> <version> store|developer <signer>\n");
+    for a in &inst.apps {
+        reg.push_str(&format!("{} {} {} {}\n", a.name, a.version, a.source.as_str(), hex(&a.signer)));
+    }
+    fs::write_file(REGISTRY_PATH, reg.as_bytes())
+}
+
+fn authorize(pid: ProcessId, cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(pid, cap, CapabilityType::AppInstall, Permissions::EXECUTE)
+}
+
+fn install_as(pid: ProcessId, cap: &Capability, path: &str) -> Result<Installed, &'static str> {
+    authorize(pid, cap)?;
+    let package = fs::read_file(path)?;
+    let app = match verify(&package) {
+        Ok(app) => app,
+        Err(e) => {
+            crate::audit::note("install", &format!("refused {}: {}", path, e));
+            return Err(e);
+        }
+    };
+    let payload = if package.starts_with(zip::LOCAL_MAGIC) { &package[..] } else { parse(&package)?.payload };
+    let mut inst = INSTALLER.lock();
+    fs::write_file(&format!("{}/{}/image", APP_DIR, app.name), payload)?;
+    let upgrade = inst.apps.iter().position(|a| a.name == app.name).map(|i| inst.apps.remove(i).version);
+    inst.apps.push(app.clone());
+    save(&inst)?;
+    crate::audit::note("install", &match upgrade {
+        Some(old) => format!("{} upgraded {} -> {} ({}, signer {})", app.name, old, app.version, app.source.as_str(), hex(&app.signer)),
+        None      => format!("{} {} installed ({}, signer {})", app.name, app.version, app.source.as_str(), hex(&app.signer)),
+    });
+    Ok(app)
+}
+
+fn remove_as(pid: ProcessId, cap: &Capability, name: &str) -> Result<(), &'static str> {
+    authorize(pid, cap)?;
+    let mut inst = INSTALLER.lock();
+    let i = inst.apps.iter().position(|a| a.name == name).ok_or("app is not installed")?;
+    fs::remove_file(&format!("{}/{}", APP_DIR, name)).ok();
+    inst.apps.remove(i);
+    save(&inst)?;
+    crate::audit::note("install", &format!("{} removed", name));
+    Ok(())
+}
+
+/// Install the package at `path`, or upgrade the app it holds.  `cap`
+/// must be the caller's AppInstall capability with EXECUTE.
+pub fn install(cap: &Capability, path: &str) -> Result<Installed, &'static str> {
+    install_as(process::current_pid(), cap, path)
+}
+
+pub fn remove(cap: &Capability, name: &str) -> Result<(), &'static str> {
+    remove_as(process::current_pid(), cap, name)
+}
+
+// ─── installd ─────────────────────────────────────────────────────────────────
+
+/// Request opcodes (first payload byte).
+pub const INSTALL_REQ_INSTALL: u8 = 1; // [path...] -> [ok, version u32 LE]
+pub const INSTALL_REQ_REMOVE:  u8 = 2; // [name...] -> [ok]
+pub const INSTALL_REQ_LIST:    u8 = 3; // [] -> [ok, (source, version u32 LE, name len, name)...]
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("installd")?;
+    INSTALLER.lock().pid = Some(pid);
+    ipc::register_kernel_server(pid, handle_request);
+    load()
+}
+
+/// Open a channel from `client` to installd; `cap` must be its AppInstall
+/// capability with EXECUTE, which installd checks again for each request.
+pub fn connect(client: ProcessId, cap: &Capability) -> Result<(ChannelId, Capability), &'static str> {
+    authorize(client, cap)?;
+    let pid = INSTALLER.lock().pid.ok_or("install service not running")?;
+    let (ch, client_cap, _) = ipc::create_channel(client, pid);
+    CLIENTS.lock().push((ch, cap.clone()));
+    Ok((ch, client_cap))
+}
+
+fn handle_request(ch: ChannelId, msg: &Message) -> Option<Vec<u8>> {
+    let Some(cap) = CLIENTS.lock().iter().find(|(c, _)| *c == ch).map(|(_, cap)| cap.clone()) else {
+        return Some(Vec::from([0u8]));
+    };
+    let p = &msg.payload;
+    let arg = p.get(1..).and_then(|a| core::str::from_utf8(a).ok());
+    // Checked again each time, so revoking the capability cuts the client off
+    let reply = match (p.first(), arg) {
+        (Some(&INSTALL_REQ_INSTALL), Some(path)) => install_as(msg.sender, &cap, path).map(|a| {
+            let mut reply = Vec::from([1u8]);
+            reply.extend_from_slice(&a.version.to_le_bytes());
+            reply
+        }),
+        (Some(&INSTALL_REQ_REMOVE), Some(name)) => remove_as(msg.sender, &cap, name).map(|_| Vec::from([1u8])),
+        (Some(&INSTALL_REQ_LIST), _) => authorize(msg.sender, &cap).map(|_| {
+            let mut reply = Vec::from([1u8]);
+            for a in installed() {
+                reply.push(a.source as u8);
+                reply.extend_from_slice(&a.version.to_le_bytes());
+                reply.push(a.name.len() as u8);
+                reply.extend_from_slice(a.name.as_bytes());
+            }
+            reply
+        }),
+        _ => Err("bad request"),
+    };
+    Some(reply.unwrap_or_else(|_| Vec::from([0u8])))
+}
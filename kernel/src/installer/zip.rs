@@ -0,0 +1,282 @@
+//! ZIP archives (PKWARE APPNOTE), as APKs are: the central directory, and
+//! entries stored or deflated (RFC 1951), checked against their CRC-32.
+//! No ZIP64, encryption or multi-disk archives.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+pub const LOCAL_MAGIC: &[u8; 4] = b"PK\x03\x04";
+
+const EOCD_SIG:    u32 = 0x0605_4b50;
+const CENTRAL_SIG: u32 = 0x0201_4b50;
+const LOCAL_SIG:   u32 = 0x0403_4b50;
+
+const EOCD_LEN:    usize = 22;
+const CENTRAL_LEN: usize = 46;
+const LOCAL_LEN:   usize = 30;
+
+const STORED:   u16 = 0;
+const DEFLATED: u16 = 8;
+
+fn u16_at(b: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(b.get(i..i + 2)?.try_into().ok()?))
+}
+
+fn u32_at(b: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(b.get(i..i + 4)?.try_into().ok()?))
+}
+
+/// One file in the central directory.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name:   String,
+    method:     u16,
+    crc:        u32,
+    compressed: usize,
+    pub size:   usize,
+    /// Where its local header is.
+    local:      usize,
+}
+
+pub struct Archive<'a> {
+    pub data:    &'a [u8],
+    pub entries: Vec<Entry>,
+    /// Where the central directory starts and the end record is.
+    pub central: usize,
+    pub eocd:    usize,
+}
+
+/// Read `data`'s central directory.
+pub fn open(data: &[u8]) -> Result<Archive<'_>, &'static str> {
+    // The end record is last, with a comment of at most 64 KiB after it
+    let lowest = data.len().saturating_sub(EOCD_LEN + u16::MAX as usize);
+    let eocd = (lowest..=data.len().saturating_sub(EOCD_LEN)).rev()
+        .find(|&i| u32_at(data, i) == Some(EOCD_SIG)
+            && u16_at(data, i + 20).is_some_and(|c| i + EOCD_LEN + c as usize == data.len()))
+        .ok_or("not a ZIP archive")?;
+    let bad = "malformed ZIP archive";
+    if u16_at(data, eocd + 4) != Some(0) || u16_at(data, eocd + 6) != Some(0) { return Err("multi-disk ZIP archive"); }
+    let count = u16_at(data, eocd + 10).ok_or(bad)? as usize;
+    let size = u32_at(data, eocd + 12).ok_or(bad)? as usize;
+    let central = u32_at(data, eocd + 16).ok_or(bad)? as usize;
+    if central.checked_add(size) != Some(eocd) { return Err(bad); }
+
+    let mut entries = Vec::with_capacity(count);
+    let mut at = central;
+    for _ in 0..count {
+        if u32_at(data, at) != Some(CENTRAL_SIG) { return Err(bad); }
+        let field = |off: usize| u32_at(data, at + off).ok_or(bad);
+        let flags = u16_at(data, at + 8).ok_or(bad)?;
+        if flags & 1 != 0 { return Err("encrypted ZIP entry"); }
+        let (compressed, size, local) = (field(20)?, field(24)?, field(42)?);
+        if [compressed, size, local].contains(&u32::MAX) { return Err("ZIP64 archives are not supported"); }
+        let nlen = u16_at(data, at + 28).ok_or(bad)? as usize;
+        let rest = u16_at(data, at + 30).ok_or(bad)? as usize + u16_at(data, at + 32).ok_or(bad)? as usize;
+        let name = data.get(at + CENTRAL_LEN..at + CENTRAL_LEN + nlen).ok_or(bad)?;
+        entries.push(Entry {
+            name: String::from(core::str::from_utf8(name).map_err(|_| "bad ZIP entry name")?),
+            method: u16_at(data, at + 10).ok_or(bad)?,
+            crc: field(16)?,
+            compressed: compressed as usize,
+            size: size as usize,
+            local: local as usize,
+        });
+        at += CENTRAL_LEN + nlen + rest;
+    }
+    if at != eocd { return Err(bad); }
+    Ok(Archive { data, entries, central, eocd })
+}
+
+impl Archive<'_> {
+    pub fn find(&self, name: &str) -> Option<&Entry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// `entry`'s contents, uncompressed and checked.
+    pub fn read(&self, entry: &Entry) -> Result<Vec<u8>, &'static str> {
+        let bad = "malformed ZIP entry";
+        if u32_at(self.data, entry.local) != Some(LOCAL_SIG) { return Err(bad); }
+        let skip = u16_at(self.data, entry.local + 26).ok_or(bad)? as usize
+            + u16_at(self.data, entry.local + 28).ok_or(bad)? as usize;
+        let start = entry.local + LOCAL_LEN + skip;
+        let raw = self.data.get(start..start + entry.compressed).ok_or(bad)?;
+        if start + entry.compressed > self.central { return Err(bad); }
+        let out = match entry.method {
+            STORED   => raw.to_vec(),
+            DEFLATED => inflate(raw, entry.size)?,
+            _        => return Err("unsupported ZIP compression method"),
+        };
+        if out.len() != entry.size || crc32(&out) != entry.crc { return Err("ZIP entry is corrupt"); }
+        Ok(out)
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| {
+        (0..8).fold(crc ^ b as u32, |c, _| if c & 1 != 0 { c >> 1 ^ 0xEDB8_8320 } else { c >> 1 })
+    })
+}
+
+// ─── inflate ──────────────────────────────────────────────────────────────────
+
+const MAX_BITS: usize = 15;
+
+const LENGTH_BASE:  [u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA: [u8; 29]  = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DIST_BASE:    [u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DIST_EXTRA:   [u8; 30]  = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+/// The order code length code lengths come in.
+const CLEN_ORDER:   [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos:  usize,
+    bit:  u32,
+}
+
+impl Bits<'_> {
+    fn bits(&mut self, n: u32) -> Result<u32, &'static str> {
+        let mut v = 0;
+        for i in 0..n {
+            let byte = *self.data.get(self.pos).ok_or("truncated deflate stream")?;
+            v |= (byte as u32 >> self.bit & 1) << i;
+            self.bit += 1;
+            if self.bit == 8 { self.bit = 0; self.pos += 1; }
+        }
+        Ok(v)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 { self.bit = 0; self.pos += 1; }
+    }
+
+    /// The next symbol in canonical code `h`.
+    fn decode(&mut self, h: &Huffman) -> Result<usize, &'static str> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..=MAX_BITS {
+            code |= self.bits(1)? as i32;
+            let count = h.count[len] as i32;
+            if code - first < count { return Ok(h.symbol[(index + code - first) as usize] as usize); }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("bad Huffman code")
+    }
+}
+
+/// A canonical Huffman code: how many codes of each length, and the
+/// symbols in code order.
+struct Huffman {
+    count:  [u16; MAX_BITS + 1],
+    symbol: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, &'static str> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &l in lengths { count[l as usize] += 1; }
+        count[0] = 0;
+        let mut left = 1i32;
+        for &c in &count[1..] {
+            left = (left << 1) - c as i32;
+            if left < 0 { return Err("over-subscribed Huffman code"); }
+        }
+        let mut offset = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS { offset[len + 1] = offset[len] + count[len]; }
+        let mut symbol = vec![0u16; lengths.len()];
+        for (sym, &l) in lengths.iter().enumerate().filter(|(_, &l)| l != 0) {
+            symbol[offset[l as usize] as usize] = sym as u16;
+            offset[l as usize] += 1;
+        }
+        Ok(Huffman { count, symbol })
+    }
+}
+
+/// Decompress raw deflate `data`, refusing to produce more than `limit`
+/// bytes.
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, &'static str> {
+    let mut input = Bits { data, pos: 0, bit: 0 };
+    let mut out = Vec::with_capacity(limit);
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => {
+                input.align();
+                let header = data.get(input.pos..input.pos + 4).ok_or("truncated deflate stream")?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                if len != !u16::from_le_bytes([header[2], header[3]]) { return Err("bad stored block"); }
+                input.pos += 4;
+                let block = data.get(input.pos..input.pos + len as usize).ok_or("truncated deflate stream")?;
+                if out.len() + block.len() > limit { return Err("inflates past its size"); }
+                out.extend_from_slice(block);
+                input.pos += len as usize;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                codes(&mut input, &mut out, limit, &Huffman::new(&lengths)?, &Huffman::new(&[5; 30])?)?;
+            }
+            2 => {
+                let (lit, dist) = dynamic(&mut input)?;
+                codes(&mut input, &mut out, limit, &lit, &dist)?;
+            }
+            _ => return Err("bad deflate block type"),
+        }
+        if last { return Ok(out); }
+    }
+}
+
+/// A dynamic block's literal/length and distance codes.
+fn dynamic(input: &mut Bits) -> Result<(Huffman, Huffman), &'static str> {
+    let nlen = input.bits(5)? as usize + 257;
+    let ndist = input.bits(5)? as usize + 1;
+    let ncode = input.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 { return Err("bad deflate code counts"); }
+    let mut clen = [0u8; 19];
+    for &i in &CLEN_ORDER[..ncode] { clen[i] = input.bits(3)? as u8; }
+    let clen = Huffman::new(&clen)?;
+
+    let mut lengths = [0u8; 286 + 30];
+    let mut i = 0;
+    while i < nlen + ndist {
+        let (value, repeat) = match input.decode(&clen)? {
+            sym @ 0..=15 => (sym as u8, 1),
+            16 => (*lengths[..i].last().ok_or("repeat with no length")?, 3 + input.bits(2)? as usize),
+            17 => (0, 3 + input.bits(3)? as usize),
+            _  => (0, 11 + input.bits(7)? as usize),
+        };
+        if i + repeat > nlen + ndist { return Err("too many code lengths"); }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 { return Err("no end-of-block code"); }
+    Ok((Huffman::new(&lengths[..nlen])?, Huffman::new(&lengths[nlen..nlen + ndist])?))
+}
+
+/// A compressed block's literals and back-references.
+fn codes(input: &mut Bits, out: &mut Vec<u8>, limit: usize, lit: &Huffman, dist: &Huffman) -> Result<(), &'static str> {
+    loop {
+        let sym = input.decode(lit)?;
+        if sym < 256 {
+            if out.len() >= limit { return Err("inflates past its size"); }
+            out.push(sym as u8);
+            continue;
+        }
+        if sym == 256 { return Ok(()); }
+        let i = sym - 257;
+        if i >= LENGTH_BASE.len() { return Err("bad length code"); }
+        let len = LENGTH_BASE[i] as usize + input.bits(LENGTH_EXTRA[i] as u32)? as usize;
+        let d = input.decode(dist)?;
+        if d >= DIST_BASE.len() { return Err("bad distance code"); }
+        let back = DIST_BASE[d] as usize + input.bits(DIST_EXTRA[d] as u32)? as usize;
+        if back > out.len() { return Err("distance before the start"); }
+        if out.len() + len > limit { return Err("inflates past its size"); }
+        let from = out.len() - back;
+        for k in 0..len { out.push(out[from + k]); }
+    }
+}
@@ -0,0 +1,483 @@
+//! SurakshaOS Inter-Process Communication
+//! Point-to-point message channels between two processes.  Each endpoint
+//! holds an IPC capability for the channel; every send/receive is checked
+//! against it.  Kernel-resident services register a handler for their PID
+//! and are invoked directly when a message is addressed to them.
+//!
+//! Processes reach a channel through the handle to their capability for
+//! it (`send`, `receive`): the handle names the channel, so there is no
+//! channel id to get wrong.
+//!
+//! A port is many-to-one, for servers: its server holds the capability
+//! to receive from it, and gives each client a capability to send to it
+//! carrying a badge of the server's choosing (`badge_sender`), which
+//! comes with every message that client sends so the server knows whose
+//! it is.  The same `send` and `receive` take a port's handle; the
+//! clients' capabilities are delegated from the server's, so they go
+//! when it does.
+//!
+//! A receiver with nothing queued can wait for a message rather than
+//! poll: `receive_timeout` parks it, Blocked, until a send on any channel
+//! or port wakes it to look again, or the channel goes, or its time runs
+//! out.
+//!
+//! A client wanting an answer `call`s: it sends a request and waits for
+//! the reply to that request, which the server gives with `reply` and the
+//! call id the request came with, not with a send of its own.  When the
+//! other end of a channel is a kernel-resident service the request never
+//! queues: its handler runs there and then, on the caller's time, and its
+//! answer is the reply.  Otherwise the caller waits as a receiver does,
+//! and the deferred work it runs meanwhile — where servers in the kernel
+//! do theirs — has its time.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::capability::{self, CapHandle, Capability, CapabilityType, Permissions};
+use crate::process::{self, ProcessId, WaitQueue};
+
+/// Largest payload carried by a single message.
+pub const MAX_MESSAGE_SIZE: usize = 4096;
+/// Messages queued per direction before senders get `BufferFull`.
+const CHANNEL_CAPACITY: usize = 32;
+/// Messages queued for a port's server, from all its clients.
+const PORT_CAPACITY:    usize = 64;
+
+// ─── types ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Request,
+    Reply,
+    Notification,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub sender:  ProcessId,
+    pub kind:    MessageKind,
+    /// On a port, the badge of the capability it was sent with; 0 on a
+    /// channel.
+    pub badge:   u64,
+    /// A request made with `call`: the id to `reply` to it with; 0
+    /// otherwise.
+    pub call:    u64,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    fn new(sender: ProcessId, kind: MessageKind, payload: Vec<u8>) -> Message {
+        Message { sender, kind, badge: 0, call: 0, payload }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcError {
+    NoSuchChannel,
+    NoSuchPort,
+    NotAnEndpoint,
+    PermissionDenied,
+    MessageTooLarge,
+    BufferFull,
+    BufferEmpty,
+    /// Nothing came before the receiver stopped waiting.
+    TimedOut,
+    /// No call by that id is waiting for this process's reply.
+    NoSuchCall,
+}
+
+impl IpcError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IpcError::NoSuchChannel    => "no such channel",
+            IpcError::NoSuchPort       => "no such port",
+            IpcError::NotAnEndpoint    => "process is not an endpoint of this channel",
+            IpcError::PermissionDenied => "permission denied",
+            IpcError::MessageTooLarge  => "message too large",
+            IpcError::BufferFull       => "channel buffer full",
+            IpcError::BufferEmpty      => "no message available",
+            IpcError::TimedOut         => "timed out waiting for a message",
+            IpcError::NoSuchCall       => "no call waiting on this reply",
+        }
+    }
+}
+
+/// Handler for a kernel-resident service.  Returns an optional reply that
+/// is queued back to the sender.
+pub type KernelHandler = fn(ChannelId, &Message) -> Option<Vec<u8>>;
+
+pub struct IpcChannel {
+    pub id:    ChannelId,
+    pub ends:  [ProcessId; 2],
+    /// queues[i] holds messages waiting to be received by ends[i].
+    queues:    [VecDeque<Message>; 2],
+}
+
+/// Many clients' messages for one server.
+pub struct Port {
+    pub id:     PortId,
+    pub server: ProcessId,
+    queue:      VecDeque<Message>,
+}
+
+/// A call waiting for its reply.
+struct PendingCall {
+    id:     u64,
+    /// Who is to reply: the channel's other end, or the port's server.
+    callee: ProcessId,
+    reply:  Option<Message>,
+}
+
+static CHANNELS: Mutex<Vec<IpcChannel>> = Mutex::new(Vec::new());
+static PORTS: Mutex<Vec<Port>> = Mutex::new(Vec::new());
+static KERNEL_SERVERS: Mutex<Vec<(ProcessId, KernelHandler)>> = Mutex::new(Vec::new());
+static CALLS: Mutex<Vec<PendingCall>> = Mutex::new(Vec::new());
+static NEXT_CHANNEL: AtomicU32 = AtomicU32::new(1);
+static NEXT_PORT: AtomicU32 = AtomicU32::new(1);
+static NEXT_CALL: AtomicU64 = AtomicU64::new(1);
+/// Receivers waiting for a message, and callers for a reply: woken by
+/// every send and reply, and by channels closing under them.
+static RECEIVERS: WaitQueue = WaitQueue::new();
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Create a channel between `a` and `b`, returning the channel and the
+/// capability each side must present to use it.
+pub fn create_channel(a: ProcessId, b: ProcessId) -> (ChannelId, Capability, Capability) {
+    let id = ChannelId(NEXT_CHANNEL.fetch_add(1, Ordering::SeqCst));
+    CHANNELS.lock().push(IpcChannel {
+        id,
+        ends: [a, b],
+        queues: [VecDeque::new(), VecDeque::new()],
+    });
+    let rw = Permissions::READ | Permissions::WRITE;
+    let cap_a = capability::create_capability(a, CapabilityType::Ipc(id.0), rw);
+    let cap_b = capability::create_capability(b, CapabilityType::Ipc(id.0), rw);
+    (id, cap_a, cap_b)
+}
+
+pub fn close_channel(id: ChannelId) {
+    CHANNELS.lock().retain(|c| c.id != id);
+    RECEIVERS.wake_all();
+}
+
+/// Close every channel `pid` is an endpoint of; returns how many.
+pub fn close_channels_of(pid: ProcessId) -> usize {
+    let closed = {
+        let mut channels = CHANNELS.lock();
+        let before = channels.len();
+        channels.retain(|c| !c.ends.contains(&pid));
+        before - channels.len()
+    };
+    if closed > 0 { RECEIVERS.wake_all(); }
+    closed
+}
+
+/// Create a port `server` receives on, returning it and the server's
+/// capability, from which it badges its clients'.
+pub fn create_port(server: ProcessId) -> (PortId, Capability) {
+    let id = PortId(NEXT_PORT.fetch_add(1, Ordering::SeqCst));
+    PORTS.lock().push(Port { id, server, queue: VecDeque::new() });
+    let perms = Permissions::READ | Permissions::WRITE | Permissions::GRANT;
+    (id, capability::create_capability(server, CapabilityType::Port { port: id.0, badge: 0 }, perms))
+}
+
+/// Give `client` a capability to send to the port whose server
+/// capability `handle` names in `server`'s table, its messages carrying
+/// `badge` (not 0, the server's own).
+pub fn badge_sender(server: ProcessId, handle: CapHandle, client: ProcessId, badge: u64) -> Result<Capability, IpcError> {
+    capability::badge(server, handle, client, Permissions::WRITE, badge).map_err(|_| IpcError::PermissionDenied)
+}
+
+pub fn close_port(id: PortId) {
+    PORTS.lock().retain(|p| p.id != id);
+    RECEIVERS.wake_all();
+}
+
+/// Close every port `pid` serves; returns how many.
+pub fn close_ports_of(pid: ProcessId) -> usize {
+    let closed = {
+        let mut ports = PORTS.lock();
+        let before = ports.len();
+        ports.retain(|p| p.server != pid);
+        before - ports.len()
+    };
+    if closed > 0 { RECEIVERS.wake_all(); }
+    closed
+}
+
+/// Route messages addressed to `pid` straight into `handler`.
+pub fn register_kernel_server(pid: ProcessId, handler: KernelHandler) {
+    KERNEL_SERVERS.lock().push((pid, handler));
+}
+
+/// True if `pid` is a kernel-resident service.
+pub fn is_kernel_server(pid: ProcessId) -> bool {
+    KERNEL_SERVERS.lock().iter().any(|(p, _)| *p == pid)
+}
+
+fn kernel_handler(pid: ProcessId) -> Option<KernelHandler> {
+    KERNEL_SERVERS.lock().iter().find(|(p, _)| *p == pid).map(|(_, h)| *h)
+}
+
+fn check(caller: ProcessId, cap: &Capability, id: ChannelId, need: Permissions) -> Result<(), IpcError> {
+    capability::validate(caller, cap, CapabilityType::Ipc(id.0), need).map_err(|_| IpcError::PermissionDenied)
+}
+
+/// Which end of `ch` messages from `sender` go to.
+fn other_end(ch: &IpcChannel, sender: ProcessId) -> Result<usize, IpcError> {
+    match ch.ends.iter().position(|&p| p == sender) {
+        Some(0) => Ok(1),
+        Some(_) => Ok(0),
+        None    => Err(IpcError::NotAnEndpoint),
+    }
+}
+
+/// The process at the other end of channel `id` from `sender`.
+fn peer(id: ChannelId, sender: ProcessId) -> Result<ProcessId, IpcError> {
+    let channels = CHANNELS.lock();
+    let ch = channels.iter().find(|c| c.id == id).ok_or(IpcError::NoSuchChannel)?;
+    Ok(ch.ends[other_end(ch, sender)?])
+}
+
+/// Queue `msg` for the other end from its sender; who that is.
+fn enqueue(id: ChannelId, msg: Message) -> Result<ProcessId, IpcError> {
+    let mut channels = CHANNELS.lock();
+    let ch = channels.iter_mut().find(|c| c.id == id).ok_or(IpcError::NoSuchChannel)?;
+    let to = other_end(ch, msg.sender)?;
+    if ch.queues[to].len() >= CHANNEL_CAPACITY { return Err(IpcError::BufferFull); }
+    ch.queues[to].push_back(msg);
+    RECEIVERS.wake_all();
+    Ok(ch.ends[to])
+}
+
+fn dequeue(id: ChannelId, receiver: ProcessId) -> Result<Message, IpcError> {
+    dequeue_within(id, receiver, usize::MAX)
+}
+
+/// The next message for `receiver`, if its payload is at most `max`
+/// bytes; a longer one stays queued.
+fn dequeue_within(id: ChannelId, receiver: ProcessId, max: usize) -> Result<Message, IpcError> {
+    let mut channels = CHANNELS.lock();
+    let ch = channels.iter_mut().find(|c| c.id == id).ok_or(IpcError::NoSuchChannel)?;
+    let me = ch.ends.iter().position(|&p| p == receiver).ok_or(IpcError::NotAnEndpoint)?;
+    let len = ch.queues[me].front().ok_or(IpcError::BufferEmpty)?.payload.len();
+    if len > max { return Err(IpcError::MessageTooLarge); }
+    ch.queues[me].pop_front().ok_or(IpcError::BufferEmpty)
+}
+
+/// Queue `msg` for the server of port `id`; who that is.
+fn port_enqueue(id: PortId, msg: Message) -> Result<ProcessId, IpcError> {
+    let mut ports = PORTS.lock();
+    let port = ports.iter_mut().find(|p| p.id == id).ok_or(IpcError::NoSuchPort)?;
+    if port.queue.len() >= PORT_CAPACITY { return Err(IpcError::BufferFull); }
+    port.queue.push_back(msg);
+    RECEIVERS.wake_all();
+    Ok(port.server)
+}
+
+/// The next message for the server of port `id`, as `dequeue_within`.
+fn port_dequeue(id: PortId, receiver: ProcessId, max: usize) -> Result<Message, IpcError> {
+    let mut ports = PORTS.lock();
+    let port = ports.iter_mut().find(|p| p.id == id).ok_or(IpcError::NoSuchPort)?;
+    if port.server != receiver { return Err(IpcError::NotAnEndpoint); }
+    let len = port.queue.front().ok_or(IpcError::BufferEmpty)?.payload.len();
+    if len > max { return Err(IpcError::MessageTooLarge); }
+    port.queue.pop_front().ok_or(IpcError::BufferEmpty)
+}
+
+pub fn send_message(
+    id:      ChannelId,
+    sender:  ProcessId,
+    cap:     &Capability,
+    kind:    MessageKind,
+    payload: &[u8],
+) -> Result<(), IpcError> {
+    if payload.len() > MAX_MESSAGE_SIZE { return Err(IpcError::MessageTooLarge); }
+    check(sender, cap, id, Permissions::WRITE)?;
+    let receiver = enqueue(id, Message::new(sender, kind, payload.to_vec()))?;
+
+    // Kernel-resident receiver: handle now, outside the channel lock
+    if let Some(handler) = kernel_handler(receiver) {
+        let msg = dequeue(id, receiver)?;
+        // The handler runs in the server's context, so capability checks
+        // it triggers are made against the server's PID.
+        if let Some(reply) = process::run_as(receiver, || handler(id, &msg)) {
+            enqueue(id, Message::new(receiver, MessageKind::Reply, reply))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn receive_message(id: ChannelId, receiver: ProcessId, cap: &Capability) -> Result<Message, IpcError> {
+    check(receiver, cap, id, Permissions::READ)?;
+    dequeue(id, receiver)
+}
+
+/// As `receive_message`, but wait for a message if none has come: for
+/// `timeout_ms` (None: as long as it takes), then `TimedOut`.
+pub fn receive_message_timeout(
+    id:         ChannelId,
+    receiver:   ProcessId,
+    cap:        &Capability,
+    timeout_ms: Option<u64>,
+) -> Result<Message, IpcError> {
+    check(receiver, cap, id, Permissions::READ)?;
+    wait_message(receiver, cap.handle, timeout_ms, || dequeue(id, receiver))
+}
+
+/// Park `receiver` until `take` finds it a message.  It stops waiting,
+/// with why, if `take` fails otherwise — the channel or port has gone —
+/// or it gives up its capability `handle` meanwhile.
+fn wait_message(
+    receiver:   ProcessId,
+    handle:     CapHandle,
+    timeout_ms: Option<u64>,
+    mut take:   impl FnMut() -> Result<Message, IpcError>,
+) -> Result<Message, IpcError> {
+    let deadline = timeout_ms.map(|t| process::uptime_ms().saturating_add(t));
+    RECEIVERS.wait_until(deadline, || {
+        if capability::lookup(receiver, handle).is_err() { return Some(Err(IpcError::PermissionDenied)); }
+        match take() {
+            Err(IpcError::BufferEmpty) => None,
+            result                     => Some(result),
+        }
+    }).unwrap_or(Err(IpcError::TimedOut))
+}
+
+/// What a handle names to send to or receive from.
+#[derive(Clone, Copy)]
+enum Endpoint {
+    Channel(ChannelId),
+    Port(PortId, u64),
+}
+
+/// The channel or port the capability `handle` names in `pid`'s table.
+fn endpoint_of(pid: ProcessId, handle: CapHandle) -> Result<(Endpoint, Capability), IpcError> {
+    let cap = capability::lookup(pid, handle).map_err(|_| IpcError::PermissionDenied)?;
+    let endpoint = match cap.cap_type {
+        CapabilityType::Ipc(id)              => Endpoint::Channel(ChannelId(id)),
+        CapabilityType::Port { port, badge } => Endpoint::Port(PortId(port), badge),
+        _                                    => return Err(IpcError::PermissionDenied),
+    };
+    Ok((endpoint, cap))
+}
+
+/// As `endpoint_of`, checked for `need`.
+fn checked_endpoint(pid: ProcessId, handle: CapHandle, need: Permissions) -> Result<Endpoint, IpcError> {
+    let (endpoint, cap) = endpoint_of(pid, handle)?;
+    capability::validate(pid, &cap, cap.cap_type, need).map_err(|_| IpcError::PermissionDenied)?;
+    Ok(endpoint)
+}
+
+/// Send on the channel or to the port `handle` names.
+pub fn send(sender: ProcessId, handle: CapHandle, kind: MessageKind, payload: &[u8]) -> Result<(), IpcError> {
+    match endpoint_of(sender, handle)? {
+        (Endpoint::Channel(id), cap) => send_message(id, sender, &cap, kind, payload),
+        (Endpoint::Port(id, badge), cap) => {
+            if payload.len() > MAX_MESSAGE_SIZE { return Err(IpcError::MessageTooLarge); }
+            capability::validate(sender, &cap, cap.cap_type, Permissions::WRITE).map_err(|_| IpcError::PermissionDenied)?;
+            port_enqueue(id, Message { badge, ..Message::new(sender, kind, payload.to_vec()) }).map(drop)
+        }
+    }
+}
+
+/// The next message of at most `max` bytes for `receiver` at `endpoint`.
+fn take(endpoint: Endpoint, receiver: ProcessId, max: usize) -> Result<Message, IpcError> {
+    match endpoint {
+        Endpoint::Channel(id) => dequeue_within(id, receiver, max),
+        Endpoint::Port(id, _) => port_dequeue(id, receiver, max),
+    }
+}
+
+/// Receive from the channel or port `handle` names a message of at most
+/// `max` bytes; a longer one is left for a bigger buffer.
+pub fn receive(receiver: ProcessId, handle: CapHandle, max: usize) -> Result<Message, IpcError> {
+    take(checked_endpoint(receiver, handle, Permissions::READ)?, receiver, max)
+}
+
+/// As `receive`, but wait for a message if none has come: for
+/// `timeout_ms` (None: as long as it takes), then `TimedOut`.
+pub fn receive_timeout(receiver: ProcessId, handle: CapHandle, max: usize, timeout_ms: Option<u64>) -> Result<Message, IpcError> {
+    let endpoint = checked_endpoint(receiver, handle, Permissions::READ)?;
+    wait_message(receiver, handle, timeout_ms, || take(endpoint, receiver, max))
+}
+
+/// Whether the channel or port `endpoint` names is still there.
+fn still_open(endpoint: Endpoint) -> Result<(), IpcError> {
+    match endpoint {
+        Endpoint::Channel(id) if !CHANNELS.lock().iter().any(|c| c.id == id) => Err(IpcError::NoSuchChannel),
+        Endpoint::Port(id, _) if !PORTS.lock().iter().any(|p| p.id == id)    => Err(IpcError::NoSuchPort),
+        _                                                                     => Ok(()),
+    }
+}
+
+/// Its entry in the calls waiting, for as long as a call waits.
+struct Pending(u64);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        CALLS.lock().retain(|c| c.id != self.0);
+    }
+}
+
+/// Send `payload` as a request on the channel or to the port `handle`
+/// names, and wait for the reply to it: for `timeout_ms` (None: as long
+/// as it takes), then `TimedOut`.  It stops waiting, with why, if the
+/// channel or port goes, or the caller gives up its capability.
+pub fn call(caller: ProcessId, handle: CapHandle, payload: &[u8], timeout_ms: Option<u64>) -> Result<Message, IpcError> {
+    if payload.len() > MAX_MESSAGE_SIZE { return Err(IpcError::MessageTooLarge); }
+    let endpoint = checked_endpoint(caller, handle, Permissions::WRITE)?;
+    let id = NEXT_CALL.fetch_add(1, Ordering::SeqCst);
+    let request = Message { call: id, ..Message::new(caller, MessageKind::Request, payload.to_vec()) };
+
+    let callee = match endpoint {
+        Endpoint::Channel(ch) => peer(ch, caller)?,
+        Endpoint::Port(port, _) => PORTS.lock().iter().find(|p| p.id == port).ok_or(IpcError::NoSuchPort)?.server,
+    };
+    // Fastpath: a kernel-resident server answers at once, in its own
+    // context but on the caller's time, and nothing is queued either way
+    if let (Endpoint::Channel(ch), Some(handler)) = (endpoint, kernel_handler(callee)) {
+        let reply = process::run_as(callee, || handler(ch, &request)).unwrap_or_default();
+        return Ok(Message { call: id, ..Message::new(callee, MessageKind::Reply, reply) });
+    }
+
+    // Waiting before it is sent, so no reply can come too soon to be kept
+    CALLS.lock().push(PendingCall { id, callee, reply: None });
+    let _pending = Pending(id);
+    match endpoint {
+        Endpoint::Channel(ch)       => enqueue(ch, request)?,
+        Endpoint::Port(port, badge) => port_enqueue(port, Message { badge, ..request })?,
+    };
+    let deadline = timeout_ms.map(|t| process::uptime_ms().saturating_add(t));
+    RECEIVERS.wait_until(deadline, || {
+        let reply = CALLS.lock().iter_mut().find(|c| c.id == id).and_then(|c| c.reply.take());
+        if let Some(reply) = reply { return Some(Ok(reply)); }
+        if capability::lookup(caller, handle).is_err() { return Some(Err(IpcError::PermissionDenied)); }
+        still_open(endpoint).err().map(Err)
+    }).unwrap_or(Err(IpcError::TimedOut))
+}
+
+/// Answer call `call`, which `server` received the request of, with
+/// `payload`.  `NoSuchCall` if it is not waiting on `server`: answered
+/// already, given up on, or never made to it.
+pub fn reply(server: ProcessId, call: u64, payload: &[u8]) -> Result<(), IpcError> {
+    if payload.len() > MAX_MESSAGE_SIZE { return Err(IpcError::MessageTooLarge); }
+    {
+        let mut calls = CALLS.lock();
+        let pending = calls.iter_mut()
+            .find(|c| c.id == call && c.callee == server && c.reply.is_none())
+            .ok_or(IpcError::NoSuchCall)?;
+        pending.reply = Some(Message { call, ..Message::new(server, MessageKind::Reply, payload.to_vec()) });
+    }
+    RECEIVERS.wake_all();
+    Ok(())
+}
@@ -0,0 +1,204 @@
+//! SurakshaOS Kernel Keyring
+//! Secrets the kernel keeps for processes and for itself: Wi-Fi
+//! passphrases, the PMKs cached from past associations, and the like.
+//! A secret never leaves the kernel once added.  Between uses it is
+//! sealed with ChaCha20-Poly1305 under a key drawn from the entropy pool
+//! on first use, so it does not sit in memory as plaintext; kernel code
+//! opens it only for the operation that needs it, as a `Secret` that is
+//! wiped when dropped.
+//!
+//! A process can replace, remove and find only its own keys, and never
+//! reads a secret back; it hands a key's id to the service that uses it,
+//! which checks the key is the caller's.  Keys the kernel holds for
+//! itself have no owner.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::crypto::{self, chacha20poly1305};
+use crate::memory::{TypeSlab, CREDENTIALS};
+use crate::process::{current_pid, ProcessId};
+
+/// Largest secret accepted.
+pub const MAX_SECRET: usize = 512;
+/// Keys held at most, all owners together.
+const MAX_KEYS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyId(pub u32);
+
+/// A key as reported to callers: everything but the secret.
+#[derive(Debug, Clone)]
+pub struct KeyInfo {
+    pub id:          KeyId,
+    /// None for the kernel's own keys.
+    pub owner:       Option<ProcessId>,
+    pub description: String,
+    pub len:         usize,
+}
+
+/// An opened secret, wiped when dropped.
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    pub fn new(bytes: &[u8]) -> Secret {
+        Secret(bytes.to_vec())
+    }
+}
+
+impl core::ops::Deref for Secret {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        crypto::wipe(&mut self.0);
+    }
+}
+
+struct Key {
+    id:          KeyId,
+    owner:       Option<ProcessId>,
+    description: String,
+    /// Nonce the secret was last sealed with.
+    nonce:       u64,
+    /// Ciphertext and tag.
+    sealed:      Vec<u8, &'static TypeSlab>,
+}
+
+impl Key {
+    /// The associated data binding a sealed secret to its key and owner.
+    fn aad(&self) -> [u8; 8] {
+        let owner = self.owner.map_or(u32::MAX, |p| p.0 as u32);
+        let mut aad = [0u8; 8];
+        aad[..4].copy_from_slice(&self.id.0.to_le_bytes());
+        aad[4..].copy_from_slice(&owner.to_le_bytes());
+        aad
+    }
+
+    fn info(&self) -> KeyInfo {
+        KeyInfo {
+            id: self.id, owner: self.owner, description: self.description.clone(),
+            len: self.sealed.len() - chacha20poly1305::TAG_LEN,
+        }
+    }
+}
+
+struct Keyring {
+    master:     Option<[u8; chacha20poly1305::KEY_LEN]>,
+    /// Keys and their sealed secrets are in a slab of their own under
+    /// heap hardening.
+    keys:       Vec<Key, &'static TypeSlab>,
+    next_id:    u32,
+    next_nonce: u64,
+}
+
+static KEYRING: Mutex<Keyring> = Mutex::new(Keyring { master: None, keys: Vec::new_in(&CREDENTIALS), next_id: 1, next_nonce: 1 });
+
+impl Keyring {
+    fn master(&mut self) -> [u8; chacha20poly1305::KEY_LEN] {
+        *self.master.get_or_insert_with(|| {
+            let mut k = [0u8; chacha20poly1305::KEY_LEN];
+            crate::entropy::fill_bytes(&mut k);
+            k
+        })
+    }
+
+    fn seal(&mut self, i: usize, secret: &[u8]) {
+        let master = self.master();
+        let n = self.next_nonce;
+        self.next_nonce += 1;
+        let mut nonce = [0u8; chacha20poly1305::NONCE_LEN];
+        nonce[4..].copy_from_slice(&n.to_le_bytes());
+        let key = &mut self.keys[i];
+        key.nonce = n;
+        let sealed = chacha20poly1305::seal(&master, &nonce, &key.aad(), secret);
+        key.sealed.clear();
+        key.sealed.extend_from_slice(&sealed);
+    }
+
+    fn insert(&mut self, owner: Option<ProcessId>, description: &str, secret: &[u8]) -> Result<KeyId, &'static str> {
+        if secret.len() > MAX_SECRET { return Err("secret too long"); }
+        if self.keys.len() >= MAX_KEYS { return Err("keyring full"); }
+        let id = KeyId(self.next_id);
+        self.next_id += 1;
+        self.keys.push(Key { id, owner, description: String::from(description), nonce: 0, sealed: Vec::new_in(&CREDENTIALS) });
+        let i = self.keys.len() - 1;
+        self.seal(i, secret);
+        Ok(id)
+    }
+
+    fn position(&self, id: KeyId, owner: Option<ProcessId>) -> Result<usize, &'static str> {
+        let i = self.keys.iter().position(|k| k.id == id).ok_or("no such key")?;
+        if self.keys[i].owner != owner { return Err("key belongs to another process"); }
+        Ok(i)
+    }
+
+    fn open(&mut self, i: usize) -> Result<Secret, &'static str> {
+        let master = self.master();
+        let key = &self.keys[i];
+        let mut nonce = [0u8; chacha20poly1305::NONCE_LEN];
+        nonce[4..].copy_from_slice(&key.nonce.to_le_bytes());
+        chacha20poly1305::open(&master, &nonce, &key.aad(), &key.sealed).map(Secret).map_err(|_| "key corrupted")
+    }
+}
+
+// ─── process keys ─────────────────────────────────────────────────────────────
+
+/// Add a key owned by the caller.
+pub fn add(description: &str, secret: &[u8]) -> Result<KeyId, &'static str> {
+    KEYRING.lock().insert(Some(current_pid()), description, secret)
+}
+
+/// Replace the secret of one of the caller's keys.
+pub fn update(id: KeyId, secret: &[u8]) -> Result<(), &'static str> {
+    if secret.len() > MAX_SECRET { return Err("secret too long"); }
+    let mut k = KEYRING.lock();
+    let i = k.position(id, Some(current_pid()))?;
+    k.seal(i, secret);
+    Ok(())
+}
+
+/// Remove one of the caller's keys.
+pub fn remove(id: KeyId) -> Result<(), &'static str> {
+    let mut k = KEYRING.lock();
+    let i = k.position(id, Some(current_pid()))?;
+    k.keys.remove(i);
+    Ok(())
+}
+
+/// The caller's key with `description`.
+pub fn find(description: &str) -> Option<KeyId> {
+    let pid = current_pid();
+    KEYRING.lock().keys.iter().find(|k| k.owner == Some(pid) && k.description == description).map(|k| k.id)
+}
+
+pub fn info(id: KeyId) -> Option<KeyInfo> {
+    KEYRING.lock().keys.iter().find(|k| k.id == id).map(Key::info)
+}
+
+pub fn keys() -> Vec<KeyInfo> {
+    KEYRING.lock().keys.iter().map(Key::info).collect()
+}
+
+// ─── kernel use ───────────────────────────────────────────────────────────────
+
+/// Open key `id`, which must belong to `owner` (the kernel if None).
+pub(crate) fn open(id: KeyId, owner: Option<ProcessId>) -> Result<Secret, &'static str> {
+    let mut k = KEYRING.lock();
+    let i = k.position(id, owner)?;
+    k.open(i)
+}
+
+/// Add a key the kernel holds for itself.
+pub(crate) fn add_kernel(description: &str, secret: &[u8]) -> Result<KeyId, &'static str> {
+    KEYRING.lock().insert(None, description, secret)
+}
+
+pub(crate) fn remove_kernel(id: KeyId) {
+    KEYRING.lock().keys.retain(|k| !(k.id == id && k.owner.is_none()));
+}
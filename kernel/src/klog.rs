@@ -0,0 +1,263 @@
+//! SurakshaOS Kernel Log
+//! Leveled kernel messages (`error!`, `warn!`, `info!`, `debug!`), kept in
+//! a ring buffer and echoed to the UART console.  Each record is one line:
+//!
+//!   <level>[seconds.millis] module: message
+//!
+//! with the level as a syslog priority (3 error, 4 warning, 6 info,
+//! 7 debug), so userspace tools read it as they would a Linux kmsg.
+//!
+//! What is logged at all is filtered per module: the longest module-path
+//! prefix with a level set wins, and `set_level(None, ..)` sets the
+//! default.  Of what is logged, records at or above the console level are
+//! also printed.
+//!
+//! The ring lives in its own section (`.klog`) outside .bss, which boot
+//! does not clear, so after a warm reset — a watchdog, a panic reboot —
+//! the last boot's messages, its panic included, are still there.
+//!
+//! Nothing here allocates, so logging works before the heap is up and in
+//! the panic handler.  `dmesg` reads the ring (also SYS_DMESG).
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicU8, Ordering};
+use spin::Mutex;
+
+/// Bytes in the ring.
+pub const LOG_SIZE: usize = 64 * 1024;
+
+pub(crate) const MAGIC: u64 = 0x4b4c_4f47_5352_4b31; // "SRK1KLOG"
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 3,
+    Warn  = 4,
+    Info  = 6,
+    Debug = 7,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn  => "warn",
+            Level::Info  => "info",
+            Level::Debug => "debug",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Level> {
+        match s {
+            "error" => Some(Level::Error),
+            "warn"  => Some(Level::Warn),
+            "info"  => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+
+    fn from_u8(b: u8) -> Level {
+        match b {
+            3 => Level::Error,
+            4 => Level::Warn,
+            6 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+}
+
+// ─── ring ─────────────────────────────────────────────────────────────────────
+
+/// Crate-visible for the proofs in tests/verify.rs.
+#[repr(C)]
+pub(crate) struct Ring {
+    pub(crate) magic: u64,
+    /// Oldest byte, and how many are held.
+    pub(crate) head:  usize,
+    pub(crate) len:   usize,
+    pub(crate) buf:   [u8; LOG_SIZE],
+}
+
+impl Ring {
+    /// Whether what a warm reset left is a ring to keep.
+    pub(crate) fn valid(&self) -> bool {
+        self.magic == MAGIC && self.head < LOG_SIZE && self.len <= LOG_SIZE
+    }
+
+    pub(crate) fn push(&mut self, b: u8) {
+        self.buf[(self.head + self.len) % LOG_SIZE] = b;
+        if self.len == LOG_SIZE { self.head = (self.head + 1) % LOG_SIZE; } else { self.len += 1; }
+    }
+
+    pub(crate) fn byte(&self, i: usize) -> u8 {
+        self.buf[(self.head + i) % LOG_SIZE]
+    }
+}
+
+impl Write for Ring {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() { self.push(b); }
+        Ok(())
+    }
+}
+
+/// Not zeroed at boot; `init` checks what it finds.
+#[link_section = ".klog"]
+static mut RING: core::mem::MaybeUninit<Ring> = core::mem::MaybeUninit::uninit();
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn with_ring<R>(f: impl FnOnce(&mut Ring) -> R) -> R {
+    let prev = crate::arch::interrupts_disable();
+    let r = {
+        let _held = LOCK.lock();
+        f(unsafe { (*core::ptr::addr_of_mut!(RING)).assume_init_mut() })
+    };
+    crate::arch::interrupts_restore(prev);
+    r
+}
+
+/// Take over the ring: keep what the last boot left if it is intact, and
+/// start afresh if not (a cold boot).  Must run before the first record.
+pub fn init() {
+    let kept = with_ring(|r| {
+        if r.valid() { return true; }
+        r.magic = MAGIC;
+        r.head = 0;
+        r.len = 0;
+        false
+    });
+    if kept { crate::info!("---- reset; log from the previous boot above ----"); }
+}
+
+// ─── filters ──────────────────────────────────────────────────────────────────
+
+static DEFAULT_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static CONSOLE_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+/// Module-path prefixes (without the crate name) and their levels.
+static FILTERS: Mutex<Vec<(String, Level)>> = Mutex::new(Vec::new());
+
+/// The module path a record is filed under: `module_path!()` less the
+/// crate name.
+fn module_name(path: &'static str) -> &'static str {
+    path.split_once("::").map_or(path, |(_, m)| m)
+}
+
+/// Log records at `level` and above from `module` and the modules under
+/// it, or, with no module, from everywhere without a level of its own.
+pub fn set_level(module: Option<&str>, level: Level) {
+    let Some(module) = module else { return DEFAULT_LEVEL.store(level as u8, Ordering::Relaxed) };
+    let mut filters = FILTERS.lock();
+    filters.retain(|(m, _)| m != module);
+    filters.push((String::from(module), level));
+}
+
+/// Drop `module`'s own level, so it follows its parent's or the default.
+pub fn clear_level(module: &str) {
+    FILTERS.lock().retain(|(m, _)| m != module);
+}
+
+pub fn set_console_level(level: Level) {
+    CONSOLE_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn default_level() -> Level {
+    Level::from_u8(DEFAULT_LEVEL.load(Ordering::Relaxed))
+}
+
+pub fn console_level() -> Level {
+    Level::from_u8(CONSOLE_LEVEL.load(Ordering::Relaxed))
+}
+
+pub fn filters() -> Vec<(String, Level)> {
+    FILTERS.lock().clone()
+}
+
+fn level_for(module: &str) -> Level {
+    let prefix_of = |m: &str| module == m || module.strip_prefix(m).is_some_and(|rest| rest.starts_with("::"));
+    // An interrupt handler may log while `set_level` holds the filters
+    let Some(filters) = FILTERS.try_lock() else { return default_level() };
+    filters.iter().filter(|(m, _)| prefix_of(m)).max_by_key(|(m, _)| m.len()).map_or_else(default_level, |&(_, l)| l)
+}
+
+// ─── logging ──────────────────────────────────────────────────────────────────
+
+#[doc(hidden)]
+pub fn _log(level: Level, module: &'static str, args: fmt::Arguments) {
+    let module = module_name(module);
+    if level > level_for(module) { return; }
+    let ms = crate::arch::uptime_millis();
+    with_ring(|r| {
+        let _ = writeln!(r, "<{}>[{:>5}.{:03}] {}: {}", level as u8, ms / 1000, ms % 1000, module, args);
+    });
+    if level <= console_level() {
+        crate::console::_print(format_args!("[{:>5}.{:03}] {}: {}\n", ms / 1000, ms % 1000, module, args));
+    }
+}
+
+/// Record a panic.  The panicking code may hold the ring's lock, so it is
+/// taken regardless; nothing else runs after a panic to mind.
+pub fn panic_record(args: fmt::Arguments) {
+    unsafe { if LOCK.is_locked() { LOCK.force_unlock(); } }
+    let ms = crate::arch::uptime_millis();
+    with_ring(|r| {
+        let _ = writeln!(r, "<{}>[{:>5}.{:03}] panic: {}", Level::Error as u8, ms / 1000, ms % 1000, args);
+    });
+}
+
+/// The newest records that fit in `max` bytes, whole lines only.
+pub fn read(max: usize) -> Vec<u8> {
+    with_ring(|r| {
+        let mut start = r.len.saturating_sub(max);
+        // A full ring has overwritten the start of its oldest line, and the
+        // cut may fall mid-line: skip to the next whole one
+        let partial = if start > 0 { r.byte(start - 1) != b'\n' } else { r.len == LOG_SIZE };
+        if partial {
+            while start < r.len && r.byte(start) != b'\n' { start += 1; }
+            start += 1;
+        }
+        (start.min(r.len)..r.len).map(|i| r.byte(i)).collect()
+    })
+}
+
+pub fn clear() {
+    with_ring(|r| {
+        r.head = 0;
+        r.len = 0;
+    });
+}
+
+/// Bytes held.
+pub fn len() -> usize {
+    with_ring(|r| r.len)
+}
+
+// ─── macros ───────────────────────────────────────────────────────────────────
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {{
+        $crate::klog::_log($level, module_path!(), format_args!($($arg)*));
+    }};
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log!($crate::klog::Level::Error, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::log!($crate::klog::Level::Warn, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log!($crate::klog::Level::Info, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log!($crate::klog::Level::Debug, $($arg)*) };
+}
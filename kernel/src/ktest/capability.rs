@@ -0,0 +1,177 @@
+//! Capabilities: delegation, revocation down the chains it makes, and the
+//! audit trail of it all.
+
+use alloc::vec::Vec;
+
+use super::Test;
+use crate::capability::{self, AuditFilter, AuditOp, Capability, CapabilityType, Permissions, AUDIT_CAPACITY};
+use crate::process::{self, ProcessId};
+
+pub const TESTS: &[Test] = tests![
+    delegate_narrows, long_chain, revoke_mid_chain, wide_tree, close_and_exit_cascade, expiry_inherited,
+    audit_filtered, audit_bounded,
+];
+
+const RES: CapabilityType = CapabilityType::Device(0x4b54);
+const RG:  Permissions = Permissions(Permissions::READ.0 | Permissions::GRANT.0);
+
+fn spawn(n: usize) -> Result<Vec<ProcessId>, &'static str> {
+    (0..n).map(|_| process::spawn_process("ktest")).collect()
+}
+
+fn done(pids: &[ProcessId]) {
+    for &p in pids { process::kill(p).ok(); }
+}
+
+fn live(cap: &Capability) -> bool {
+    capability::validate(cap.owner, cap, RES, Permissions::READ).is_ok()
+}
+
+fn delegate_narrows() -> Result<(), &'static str> {
+    let p = spawn(3)?;
+    let all = Permissions::READ | Permissions::WRITE | Permissions::GRANT;
+    let root = capability::create_capability(p[0], RES, all);
+    let child = capability::delegate(p[0], root.handle, p[1], Permissions::READ)?;
+    check!(child.owner == p[1] && child.cap_type == RES && child.perms == Permissions::READ);
+    check!(capability::validate(p[1], &child, RES, Permissions::WRITE).is_err());
+    // No GRANT, no passing it on; and no rights it does not have
+    check!(capability::delegate(p[1], child.handle, p[2], Permissions::READ).is_err());
+    let rw = capability::delegate(p[0], root.handle, p[1], RG | Permissions::WRITE)?;
+    check!(capability::delegate(p[1], rw.handle, p[2], Permissions::CONTROL).is_err());
+    // Nor from someone else's table
+    check!(capability::delegate(p[2], root.handle, p[2], Permissions::READ).is_err());
+    done(&p);
+    Ok(())
+}
+
+/// A chain `depth` long from a root `pids[0]` holds, each link handed to
+/// the next process round.
+fn chain(pids: &[ProcessId], depth: usize) -> Result<Vec<Capability>, &'static str> {
+    let mut caps = alloc::vec![capability::create_capability(pids[0], RES, RG)];
+    for i in 1..=depth {
+        let prev = &caps[i - 1];
+        let next = capability::delegate(prev.owner, prev.handle, pids[i % pids.len()], RG)?;
+        caps.push(next);
+    }
+    Ok(caps)
+}
+
+fn long_chain() -> Result<(), &'static str> {
+    let p = spawn(8)?;
+    let caps = chain(&p, 500)?;
+    check!(caps.iter().all(live));
+    check!(capability::revoke_capability(caps[0].id)? == 501);
+    check!(!caps.iter().any(live));
+    done(&p);
+    Ok(())
+}
+
+fn revoke_mid_chain() -> Result<(), &'static str> {
+    let p = spawn(5)?;
+    let caps = chain(&p, 60)?;
+    check!(capability::revoke_capability(caps[30].id)? == 31);
+    check!(caps[..30].iter().all(live));
+    check!(!caps[30..].iter().any(live));
+    // What is left can still delegate, and revoking it takes the rest
+    let more = capability::delegate(caps[29].owner, caps[29].handle, p[0], RG)?;
+    check!(capability::revoke_capability(caps[0].id)? == 31);
+    check!(!live(&more) && !caps.iter().any(live));
+    done(&p);
+    Ok(())
+}
+
+fn wide_tree() -> Result<(), &'static str> {
+    let p = spawn(8)?;
+    let root = capability::create_capability(p[0], RES, RG);
+    // Three children each, six levels down: 1 + 3 + ... + 729 = 1093
+    let mut levels: Vec<Vec<Capability>> = alloc::vec![alloc::vec![root]];
+    for depth in 1..=6 {
+        let mut level = Vec::new();
+        for (i, parent) in levels[depth - 1].iter().enumerate() {
+            for k in 0..3 {
+                // Unrelated capabilities minted in between must be left alone
+                if k == 1 { capability::create_capability(p[(i + k) % 8], RES, RG); }
+                level.push(capability::delegate(parent.owner, parent.handle, p[(i * 3 + k) % 8], RG)?);
+            }
+        }
+        levels.push(level);
+    }
+    let bystander = capability::create_capability(p[1], RES, RG);
+    // Revoking the first child takes its 364 and leaves its siblings' 728
+    let first = levels[1][0].id;
+    check!(capability::revoke_capability(first)? == 364);
+    for level in &levels[1..] {
+        let cut = level.len() / 3;
+        check!(!level[..cut].iter().any(live));
+        check!(level[cut..].iter().all(live));
+    }
+    check!(live(&levels[0][0]) && live(&bystander));
+    check!(capability::revoke_capability(levels[0][0].id)? == 1 + 728);
+    check!(!levels.iter().flatten().any(live) && live(&bystander));
+    done(&p);
+    Ok(())
+}
+
+fn close_and_exit_cascade() -> Result<(), &'static str> {
+    let p = spawn(4)?;
+    let caps = chain(&p, 12)?;
+    // Closing a handle revokes what was delegated from it
+    capability::close_handle(caps[4].owner, caps[4].handle)?;
+    check!(caps[..4].iter().all(live) && !caps[4..].iter().any(live));
+    // So does its holder exiting
+    let caps = chain(&p[1..], 6)?;
+    process::kill(p[1])?;
+    check!(!caps.iter().any(live));
+    done(&p);
+    Ok(())
+}
+
+fn expiry_inherited() -> Result<(), &'static str> {
+    let p = spawn(3)?;
+    let root = capability::create_capability_with_expiry(p[0], RES, RG, 60_000);
+    let child = capability::delegate(p[0], root.handle, p[1], RG)?;
+    let grandchild = capability::delegate(p[1], child.handle, p[2], Permissions::READ)?;
+    check!(child.expiry == root.expiry && grandchild.expiry == root.expiry);
+    done(&p);
+    Ok(())
+}
+
+fn audit_filtered() -> Result<(), &'static str> {
+    let p = spawn(2)?;
+    let start = capability::audit_log().last().map_or(0, |e| e.seq);
+    let root = capability::create_capability(p[0], RES, RG);
+    let child = capability::delegate(p[0], root.handle, p[1], Permissions::READ)?;
+    check!(live(&child));
+    check!(capability::validate(p[1], &child, RES, Permissions::WRITE).is_err());
+    let mine = |pid, ops| capability::audit_query(&AuditFilter { after: start, pid, cap: Some(child.id), ops }, usize::MAX);
+    let all = mine(None, 0);
+    check!(all.len() == 3 && all.windows(2).all(|w| w[0].seq < w[1].seq));
+    check!(all.iter().map(|e| e.op).eq([AuditOp::Create, AuditOp::Validate, AuditOp::Deny]));
+    check!(mine(Some(p[1]), AuditOp::Deny.bit()).len() == 1);
+    check!(mine(Some(p[0]), 0).is_empty());
+    // The delegation itself is on the one delegated from
+    let from = AuditFilter { after: start, pid: Some(p[0]), cap: Some(root.id), ops: AuditOp::Delegate.bit() };
+    check!(capability::audit_query(&from, usize::MAX).len() == 1);
+    // Reading on from the last one seen gets only what came after
+    let last = all[2].seq;
+    check!(capability::revoke_capability(root.id)? == 2);
+    let next = capability::audit_query(&AuditFilter { after: last, pid: Some(p[0]), ..Default::default() }, usize::MAX);
+    check!(next.len() == 1 && next[0].op == AuditOp::Revoke && next[0].cap == root.id);
+    done(&p);
+    Ok(())
+}
+
+fn audit_bounded() -> Result<(), &'static str> {
+    let p = spawn(1)?;
+    let cap = capability::create_capability(p[0], RES, RG);
+    let start = capability::audit_log().last().map_or(0, |e| e.seq);
+    for _ in 0..AUDIT_CAPACITY + 100 { check!(live(&cap)); }
+    let log = capability::audit_log();
+    check!(log.len() == AUDIT_CAPACITY);
+    // The oldest went first, and the numbering shows how many
+    check!(log[0].seq > start + 100 && log.last().map(|e| e.seq) == Some(start + AUDIT_CAPACITY as u64 + 100));
+    let page = capability::audit_query(&AuditFilter { after: start, ..Default::default() }, 10);
+    check!(page.len() == 10 && page[0].seq == log[0].seq);
+    done(&p);
+    Ok(())
+}
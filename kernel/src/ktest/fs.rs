@@ -0,0 +1,98 @@
+//! The in-memory VFS.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use super::Test;
+use crate::fs;
+
+pub const TESTS: &[Test] = tests![write_read, rewrite, mapping_outlives_rewrite, list, remove, errors];
+
+const DIR: &str = "/tmp/ktest";
+
+/// A fresh, empty directory for a test; creating one over another
+/// replaces it.
+fn fresh(name: &str) -> Result<String, &'static str> {
+    let dir = alloc::format!("{}/{}", DIR, name);
+    fs::create_dir(&dir)?;
+    Ok(dir)
+}
+
+fn write_read() -> Result<(), &'static str> {
+    let dir = fresh("write_read")?;
+    let path = alloc::format!("{}/a", dir);
+    let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+    fs::write_file(&path, &data)?;
+    check!(fs::read_file(&path)? == data);
+    let st = fs::stat(&path)?;
+    check!(st.size == data.len() && !st.is_dir && st.name == "a");
+    fs::remove_file(&dir)?;
+    Ok(())
+}
+
+fn rewrite() -> Result<(), &'static str> {
+    let dir = fresh("rewrite")?;
+    let path = alloc::format!("{}/a", dir);
+    fs::write_file(&path, b"a longer first version")?;
+    fs::write_file(&path, b"short")?;
+    check!(fs::read_file(&path)? == b"short");
+    check!(fs::stat(&path)?.size == 5);
+    fs::create_file(&path)?;
+    check!(fs::read_file(&path)?.is_empty());
+    fs::remove_file(&dir)?;
+    Ok(())
+}
+
+fn mapping_outlives_rewrite() -> Result<(), &'static str> {
+    let dir = fresh("mapping")?;
+    let path = alloc::format!("{}/a", dir);
+    fs::write_file(&path, b"first")?;
+    let map = fs::map_file(&path)?;
+    fs::write_file(&path, b"second")?;
+    fs::remove_file(&path)?;
+    check!(&map[..] == b"first");
+    fs::remove_file(&dir)?;
+    Ok(())
+}
+
+fn list() -> Result<(), &'static str> {
+    let dir = fresh("list")?;
+    for name in ["x", "y", "z"] {
+        fs::write_file(&alloc::format!("{}/{}", dir, name), name.as_bytes())?;
+    }
+    fs::create_dir(&alloc::format!("{}/sub", dir))?;
+    let mut entries = fs::list_dir(&dir)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+    check!(names == ["sub", "x", "y", "z"]);
+    check!(entries[0].is_dir && !entries[1].is_dir && entries[1].size == 1);
+    fs::remove_file(&dir)?;
+    Ok(())
+}
+
+fn remove() -> Result<(), &'static str> {
+    let dir = fresh("remove")?;
+    let path = alloc::format!("{}/a", dir);
+    fs::write_file(&path, b"x")?;
+    fs::remove_file(&path)?;
+    check!(fs::read_file(&path).is_err());
+    check!(fs::stat(&path).is_err());
+    check!(fs::remove_file(&path).is_err());
+    // A directory goes with everything in it
+    fs::write_file(&path, b"x")?;
+    fs::remove_file(&dir)?;
+    check!(fs::stat(&dir).is_err() && fs::read_file(&path).is_err());
+    Ok(())
+}
+
+fn errors() -> Result<(), &'static str> {
+    let dir = fresh("errors")?;
+    let path = alloc::format!("{}/a", dir);
+    fs::write_file(&path, b"x")?;
+    check!(fs::read_file(&dir) == Err("is a directory"));
+    check!(fs::list_dir(&path).is_err());
+    check!(fs::read_file(&alloc::format!("{}/missing", dir)).is_err());
+    check!(fs::write_file(&alloc::format!("{}/under", path), b"x") == Err("not a directory"));
+    fs::remove_file(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,304 @@
+//! IPC channels and ports, the capabilities that guard them, waiting on
+//! them, and calls through them.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::Test;
+use crate::capability::{self, CapHandle, Capability};
+use crate::ipc::{self, ChannelId, IpcError, Message, MessageKind, MAX_MESSAGE_SIZE};
+use crate::process::{self, ProcessId, ProcessState};
+
+pub const TESTS: &[Test] = tests![
+    round_trip, by_handle, other_end_cap_refused, too_large, fills_up, order_kept, gone_with_endpoint,
+    receive_waits, receive_times_out, wait_ends_with_channel,
+    port_many_to_one, port_badge_rules, port_gone_with_server,
+    call_kernel_server, call_port, call_times_out, call_ends_with_server,
+];
+
+fn pair() -> Result<(ProcessId, ProcessId), &'static str> {
+    Ok((process::spawn_process("ktest-a")?, process::spawn_process("ktest-b")?))
+}
+
+fn done(pids: &[ProcessId]) {
+    for &p in pids { process::kill(p).ok(); }
+}
+
+fn round_trip() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (id, cap_a, cap_b) = ipc::create_channel(a, b);
+    ipc::send_message(id, a, &cap_a, MessageKind::Request, b"ping").map_err(IpcError::as_str)?;
+    let m = ipc::receive_message(id, b, &cap_b).map_err(IpcError::as_str)?;
+    check!(m.sender == a && m.kind == MessageKind::Request && m.payload == b"ping");
+    ipc::send_message(id, b, &cap_b, MessageKind::Reply, b"pong").map_err(IpcError::as_str)?;
+    let m = ipc::receive_message(id, a, &cap_a).map_err(IpcError::as_str)?;
+    check!(m.sender == b && m.payload == b"pong");
+    check!(ipc::receive_message(id, a, &cap_a).err() == Some(IpcError::BufferEmpty));
+    done(&[a, b]);
+    Ok(())
+}
+
+fn by_handle() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (_, cap_a, cap_b) = ipc::create_channel(a, b);
+    ipc::send(a, cap_a.handle, MessageKind::Notification, b"hello").map_err(IpcError::as_str)?;
+    let m = ipc::receive(b, cap_b.handle, 64).map_err(IpcError::as_str)?;
+    check!(m.payload == b"hello");
+    let made_up = capability::CapHandle(u64::MAX);
+    check!(ipc::send(b, made_up, MessageKind::Notification, b"x").err() == Some(IpcError::PermissionDenied));
+    done(&[a, b]);
+    Ok(())
+}
+
+fn other_end_cap_refused() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (id, _, cap_b) = ipc::create_channel(a, b);
+    check!(ipc::send_message(id, a, &cap_b, MessageKind::Request, b"x").err() == Some(IpcError::PermissionDenied));
+    capability::close_handle(b, cap_b.handle)?;
+    check!(ipc::receive_message(id, b, &cap_b).err() == Some(IpcError::PermissionDenied));
+    done(&[a, b]);
+    Ok(())
+}
+
+fn too_large() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (id, cap_a, cap_b) = ipc::create_channel(a, b);
+    let big = alloc::vec![0u8; MAX_MESSAGE_SIZE + 1];
+    check!(ipc::send_message(id, a, &cap_a, MessageKind::Request, &big).err() == Some(IpcError::MessageTooLarge));
+    ipc::send_message(id, a, &cap_a, MessageKind::Request, &big[..100]).map_err(IpcError::as_str)?;
+    // Too big for the buffer: it stays queued for a bigger one
+    check!(ipc::receive(b, cap_b.handle, 99).err() == Some(IpcError::MessageTooLarge));
+    check!(ipc::receive(b, cap_b.handle, 100).map(|m| m.payload.len()) == Ok(100));
+    done(&[a, b]);
+    Ok(())
+}
+
+fn fills_up() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (id, cap_a, cap_b) = ipc::create_channel(a, b);
+    let mut sent = 0;
+    loop {
+        match ipc::send_message(id, a, &cap_a, MessageKind::Notification, &[sent as u8]) {
+            Ok(()) => sent += 1,
+            Err(IpcError::BufferFull) => break,
+            Err(e) => return Err(e.as_str()),
+        }
+        check!(sent <= 1024);
+    }
+    check!(sent > 0);
+    // The other direction has room of its own
+    ipc::send_message(id, b, &cap_b, MessageKind::Notification, b"x").map_err(IpcError::as_str)?;
+    for _ in 0..sent { ipc::receive_message(id, b, &cap_b).map_err(IpcError::as_str)?; }
+    check!(ipc::receive_message(id, b, &cap_b).err() == Some(IpcError::BufferEmpty));
+    done(&[a, b]);
+    Ok(())
+}
+
+fn order_kept() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (id, cap_a, cap_b) = ipc::create_channel(a, b);
+    for i in 0..16u8 {
+        ipc::send_message(id, a, &cap_a, MessageKind::Notification, &[i; 3]).map_err(IpcError::as_str)?;
+    }
+    let got: Vec<u8> = (0..16).filter_map(|_| ipc::receive_message(id, b, &cap_b).ok()).map(|m| m.payload[0]).collect();
+    check!(got == (0..16).collect::<Vec<u8>>());
+    done(&[a, b]);
+    Ok(())
+}
+
+fn gone_with_endpoint() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (id, cap_a, cap_b) = ipc::create_channel(a, b);
+    ipc::send_message(id, a, &cap_a, MessageKind::Request, b"x").map_err(IpcError::as_str)?;
+    process::kill(a)?;
+    check!(ipc::receive_message(id, b, &cap_b).err() == Some(IpcError::NoSuchChannel));
+    check!(capability::held_by(a).is_empty());
+    done(&[b]);
+    Ok(())
+}
+
+/// For deferred work to send on: the channel, the sender and its
+/// capability, and the receiver waiting.
+static LATER: Mutex<Option<(ChannelId, ProcessId, Capability, ProcessId)>> = Mutex::new(None);
+static SAW_BLOCKED: AtomicBool = AtomicBool::new(false);
+
+fn send_later() {
+    let Some((id, from, cap, to)) = LATER.lock().take() else { return };
+    SAW_BLOCKED.store(matches!(process::state(to), ProcessState::Blocked { .. }), Ordering::Relaxed);
+    ipc::send_message(id, from, &cap, MessageKind::Request, b"late").ok();
+}
+
+fn receive_waits() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (id, cap_a, cap_b) = ipc::create_channel(a, b);
+    *LATER.lock() = Some((id, a, cap_a.clone(), b));
+    process::defer(send_later);
+    let m = process::run_as(b, || ipc::receive_timeout(b, cap_b.handle, 64, Some(1000))).map_err(IpcError::as_str)?;
+    check!(m.sender == a && m.payload == b"late");
+    check!(SAW_BLOCKED.load(Ordering::Relaxed) && process::state(b) == ProcessState::Running);
+    // One already there is taken without waiting
+    ipc::send_message(id, a, &cap_a, MessageKind::Request, b"now").map_err(IpcError::as_str)?;
+    check!(ipc::receive_message_timeout(id, b, &cap_b, Some(0)).map(|m| m.payload) == Ok(b"now".to_vec()));
+    done(&[a, b]);
+    Ok(())
+}
+
+fn receive_times_out() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (_, _, cap_b) = ipc::create_channel(a, b);
+    let start = process::uptime_ms();
+    let r = process::run_as(b, || ipc::receive_timeout(b, cap_b.handle, 64, Some(30)));
+    check!(r.err() == Some(IpcError::TimedOut));
+    check!(process::uptime_ms() >= start + 30 && process::state(b) == ProcessState::Running);
+    done(&[a, b]);
+    Ok(())
+}
+
+static SENDER: Mutex<Option<ProcessId>> = Mutex::new(None);
+
+fn kill_sender() {
+    if let Some(p) = SENDER.lock().take() { process::kill(p).ok(); }
+}
+
+fn wait_ends_with_channel() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (id, _, cap_b) = ipc::create_channel(a, b);
+    *SENDER.lock() = Some(a);
+    process::defer(kill_sender);
+    let r = process::run_as(b, || ipc::receive_message_timeout(id, b, &cap_b, None));
+    check!(r.err() == Some(IpcError::NoSuchChannel));
+    done(&[b]);
+    Ok(())
+}
+
+fn port_many_to_one() -> Result<(), &'static str> {
+    let server = process::spawn_process("ktest-server")?;
+    let clients: Vec<ProcessId> = (0..4).map(|_| process::spawn_process("ktest-client")).collect::<Result<_, _>>()?;
+    let (_, port) = ipc::create_port(server);
+    let mut caps = Vec::new();
+    for (i, &c) in clients.iter().enumerate() {
+        caps.push(ipc::badge_sender(server, port.handle, c, 100 + i as u64).map_err(IpcError::as_str)?);
+    }
+    for round in 0..3u8 {
+        for (&c, cap) in clients.iter().zip(&caps) {
+            ipc::send(c, cap.handle, MessageKind::Request, &[round]).map_err(IpcError::as_str)?;
+        }
+    }
+    // All in one queue, in the order sent, each with its sender's badge
+    for round in 0..3u8 {
+        for (i, &c) in clients.iter().enumerate() {
+            let m = ipc::receive(server, port.handle, 64).map_err(IpcError::as_str)?;
+            check!(m.sender == c && m.badge == 100 + i as u64 && m.payload == [round]);
+        }
+    }
+    check!(ipc::receive(server, port.handle, 64).err() == Some(IpcError::BufferEmpty));
+    // Clients send, but only the server receives
+    check!(ipc::receive(clients[0], caps[0].handle, 64).err() == Some(IpcError::PermissionDenied));
+    done(&clients);
+    done(&[server]);
+    Ok(())
+}
+
+fn port_badge_rules() -> Result<(), &'static str> {
+    let server = process::spawn_process("ktest-server")?;
+    let (a, b) = pair()?;
+    let (_, port) = ipc::create_port(server);
+    check!(ipc::badge_sender(server, port.handle, a, 0).is_err());
+    let cap_a = ipc::badge_sender(server, port.handle, a, 7).map_err(IpcError::as_str)?;
+    // A client can neither badge others nor re-badge itself
+    check!(ipc::badge_sender(a, cap_a.handle, b, 8).is_err());
+    // Nor is a channel badged
+    let (_, chan, _) = ipc::create_channel(server, b);
+    check!(ipc::badge_sender(server, chan.handle, b, 9).is_err());
+    done(&[a, b, server]);
+    Ok(())
+}
+
+fn port_gone_with_server() -> Result<(), &'static str> {
+    let server = process::spawn_process("ktest-server")?;
+    let a = process::spawn_process("ktest-client")?;
+    let (_, port) = ipc::create_port(server);
+    let cap = ipc::badge_sender(server, port.handle, a, 1).map_err(IpcError::as_str)?;
+    ipc::send(a, cap.handle, MessageKind::Notification, b"x").map_err(IpcError::as_str)?;
+    process::kill(server)?;
+    // Its clients' capabilities went with its own
+    check!(ipc::send(a, cap.handle, MessageKind::Notification, b"x").err() == Some(IpcError::PermissionDenied));
+    check!(capability::held_by(a).is_empty());
+    done(&[a]);
+    Ok(())
+}
+
+fn reversed(_: ChannelId, m: &Message) -> Option<Vec<u8>> {
+    Some(m.payload.iter().rev().copied().collect())
+}
+
+fn call_kernel_server() -> Result<(), &'static str> {
+    let (a, server) = pair()?;
+    ipc::register_kernel_server(server, reversed);
+    let (_, cap_a, cap_s) = ipc::create_channel(a, server);
+    // Answered at once: no waiting, even with no time to wait
+    let m = ipc::call(a, cap_a.handle, b"abc", Some(0)).map_err(IpcError::as_str)?;
+    check!(m.sender == server && m.kind == MessageKind::Reply && m.payload == b"cba");
+    // And nothing left queued either way
+    check!(ipc::receive(a, cap_a.handle, 64).err() == Some(IpcError::BufferEmpty));
+    check!(ipc::receive(server, cap_s.handle, 64).err() == Some(IpcError::BufferEmpty));
+    done(&[a, server]);
+    Ok(())
+}
+
+/// For deferred work to serve a call on: the server, its port handle and
+/// the client.
+static SERVING: Mutex<Option<(ProcessId, CapHandle, ProcessId)>> = Mutex::new(None);
+static OTHERS_REFUSED: AtomicBool = AtomicBool::new(false);
+
+fn serve_later() {
+    let Some((server, port, client)) = SERVING.lock().take() else { return };
+    let Ok(m) = ipc::receive(server, port, 64) else { return };
+    // Only the server it was made to can answer it
+    OTHERS_REFUSED.store(ipc::reply(client, m.call, b"forged").err() == Some(IpcError::NoSuchCall), Ordering::Relaxed);
+    let mut answer = m.payload.clone();
+    answer.push(m.badge as u8);
+    ipc::reply(server, m.call, &answer).ok();
+}
+
+fn call_port() -> Result<(), &'static str> {
+    let server = process::spawn_process("ktest-server")?;
+    let client = process::spawn_process("ktest-client")?;
+    let (_, port) = ipc::create_port(server);
+    let cap = ipc::badge_sender(server, port.handle, client, 5).map_err(IpcError::as_str)?;
+    *SERVING.lock() = Some((server, port.handle, client));
+    process::defer(serve_later);
+    let m = process::run_as(client, || ipc::call(client, cap.handle, b"hi", Some(1000))).map_err(IpcError::as_str)?;
+    check!(m.sender == server && m.kind == MessageKind::Reply && m.payload == b"hi\x05");
+    check!(OTHERS_REFUSED.load(Ordering::Relaxed));
+    // Once answered it is answered
+    check!(ipc::reply(server, m.call, b"again").err() == Some(IpcError::NoSuchCall));
+    done(&[client, server]);
+    Ok(())
+}
+
+fn call_times_out() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (_, cap_a, cap_b) = ipc::create_channel(a, b);
+    let r = process::run_as(a, || ipc::call(a, cap_a.handle, b"x", Some(20)));
+    check!(r.err() == Some(IpcError::TimedOut));
+    // The request was sent; the reply to it, too late, has no one to go to
+    let m = ipc::receive(b, cap_b.handle, 64).map_err(IpcError::as_str)?;
+    check!(m.kind == MessageKind::Request && m.call != 0);
+    check!(ipc::reply(b, m.call, b"late").err() == Some(IpcError::NoSuchCall));
+    check!(ipc::receive(a, cap_a.handle, 64).err() == Some(IpcError::BufferEmpty));
+    done(&[a, b]);
+    Ok(())
+}
+
+fn call_ends_with_server() -> Result<(), &'static str> {
+    let (a, b) = pair()?;
+    let (_, cap_a, _) = ipc::create_channel(a, b);
+    *SENDER.lock() = Some(b);
+    process::defer(kill_sender);
+    let r = process::run_as(a, || ipc::call(a, cap_a.handle, b"x", None));
+    check!(r.err() == Some(IpcError::NoSuchChannel));
+    done(&[a]);
+    Ok(())
+}
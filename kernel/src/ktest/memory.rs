@@ -0,0 +1,63 @@
+//! The kernel heap.
+
+use alloc::alloc::{alloc, dealloc, Layout};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use super::Test;
+use crate::memory;
+
+pub const TESTS: &[Test] = tests![alloc_and_free, alignment, many_small, grow_keeps_contents, wipe];
+
+fn alloc_and_free() -> Result<(), &'static str> {
+    let before = memory::heap_used();
+    let v: Vec<u8> = alloc::vec![0xa5; 64 * 1024];
+    check!(memory::heap_used() >= before + 64 * 1024);
+    check!(v.iter().all(|&b| b == 0xa5));
+    drop(v);
+    // Quarantined blocks still count as used
+    if !memory::heap_hardening() {
+        check!(memory::heap_used() <= before);
+    }
+    Ok(())
+}
+
+fn alignment() -> Result<(), &'static str> {
+    for align in [8, 64, 4096] {
+        let layout = Layout::from_size_align(100, align).map_err(|_| "bad layout")?;
+        let p = unsafe { alloc(layout) };
+        check!(!p.is_null());
+        check!((p as usize).is_multiple_of(align));
+        unsafe { dealloc(p, layout) };
+    }
+    Ok(())
+}
+
+fn many_small() -> Result<(), &'static str> {
+    let boxes: Vec<Box<u64>> = (0..10_000u64).map(Box::new).collect();
+    check!(boxes.iter().enumerate().all(|(i, b)| **b == i as u64));
+    // No two live boxes share memory
+    let mut addrs: Vec<usize> = boxes.iter().map(|b| &**b as *const u64 as usize).collect();
+    addrs.sort_unstable();
+    check!(addrs.windows(2).all(|w| w[1] - w[0] >= 8));
+    Ok(())
+}
+
+fn grow_keeps_contents() -> Result<(), &'static str> {
+    let mut v: Vec<u32> = Vec::with_capacity(4);
+    for i in 0..100_000 { v.push(i); }
+    check!(v.iter().enumerate().all(|(i, &x)| x == i as u32));
+    v.truncate(10);
+    v.shrink_to_fit();
+    check!(v == (0..10).collect::<Vec<u32>>());
+    Ok(())
+}
+
+fn wipe() -> Result<(), &'static str> {
+    let mut key = [0x5au8; 32];
+    memory::wipe(&mut key);
+    check!(key.iter().all(|&b| b == 0));
+    let secret = memory::secure_vec(0x77u8, 48);
+    check!(secret.len() == 48 && secret.iter().all(|&b| b == 0x77));
+    Ok(())
+}
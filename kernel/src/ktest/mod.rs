@@ -0,0 +1,104 @@
+//! SurakshaOS In-Kernel Tests
+//! Built with the `ktest` feature, init runs these suites in place of the
+//! shell and powers off with the verdict, for scripts/ktest.sh to boot
+//! under QEMU.  The kernel's boot-time subsystems are all up, so a test
+//! drives the real ones: the heap, IPC channels, capabilities and their
+//! delegation, the deferred-work and wait-queue machinery, the VFS.
+//!
+//! Progress goes to the console, one line each, for the runner to read:
+//!
+//!   KTEST BEGIN <arch> tests=<n>
+//!   KTEST RUN <suite>::<test>
+//!   KTEST PASS <suite>::<test> <µs>us
+//!   KTEST FAIL <suite>::<test> <reason>
+//!   KTEST DONE passed=<n> failed=<n>
+//!   KTEST PANIC <message>
+//!
+//! and QEMU exits with status 0 if every test passed.  A panic ends the
+//! run there; the last RUN line names the test that caused it.
+//!
+//! `ktest=<suite|suite::test>,...` on the command line runs only those.
+
+use core::panic::PanicInfo;
+
+/// Fail the test, with where and what, unless `cond` holds.
+macro_rules! check {
+    ($cond:expr) => {
+        if !$cond { return Err(concat!(file!(), ":", line!(), ": ", stringify!($cond))); }
+    };
+}
+
+/// A suite's tests, each named after its function.
+macro_rules! tests {
+    ($($test:ident),* $(,)?) => {
+        &[$(super::Test { name: stringify!($test), run: $test }),*]
+    };
+}
+
+mod capability;
+mod fs;
+mod ipc;
+mod memory;
+mod scheduler;
+
+pub struct Test {
+    pub name: &'static str,
+    pub run:  fn() -> Result<(), &'static str>,
+}
+
+pub struct Suite {
+    pub name:  &'static str,
+    pub tests: &'static [Test],
+}
+
+pub const SUITES: &[Suite] = &[
+    Suite { name: "memory",     tests: memory::TESTS },
+    Suite { name: "ipc",        tests: ipc::TESTS },
+    Suite { name: "capability", tests: capability::TESTS },
+    Suite { name: "scheduler",  tests: scheduler::TESTS },
+    Suite { name: "fs",         tests: fs::TESTS },
+];
+
+const ARCH: &str = if cfg!(target_arch = "riscv64") { "riscv64" } else { "aarch64" };
+
+/// Whether `ktest=` picks `suite::test`; everything, without it.
+fn selected(filter: Option<&str>, suite: &str, test: &str) -> bool {
+    let Some(filter) = filter else { return true };
+    filter.split(',').any(|f| match f.split_once("::") {
+        Some((s, t)) => s == suite && t == test,
+        None         => f == suite,
+    })
+}
+
+/// Run the selected tests and power off with the verdict.
+pub fn run() -> ! {
+    let filter = crate::cmdline::get("ktest");
+    let chosen = || SUITES.iter().flat_map(|s| s.tests.iter().map(move |t| (s.name, t)))
+        .filter(|(s, t)| selected(filter, s, t.name));
+    crate::println!("KTEST BEGIN {} tests={}", ARCH, chosen().count());
+    let (mut passed, mut failed) = (0, 0);
+    for (suite, test) in chosen() {
+        crate::println!("KTEST RUN {}::{}", suite, test.name);
+        let t0 = crate::arch::read_mtime();
+        let result = (test.run)();
+        let us = crate::arch::ticks_to_us(crate::arch::read_mtime() - t0);
+        match result {
+            Ok(()) => {
+                passed += 1;
+                crate::println!("KTEST PASS {}::{} {}us", suite, test.name, us);
+            }
+            Err(e) => {
+                failed += 1;
+                crate::println!("KTEST FAIL {}::{} {}", suite, test.name, e);
+            }
+        }
+    }
+    crate::println!("KTEST DONE passed={} failed={}", passed, failed);
+    crate::arch::exit(failed != 0)
+}
+
+/// The kernel panicked mid-run: say so, and fail.
+pub fn panicked(info: &PanicInfo) -> ! {
+    crate::println!("KTEST PANIC {}", info.message());
+    crate::arch::exit(true)
+}
@@ -0,0 +1,99 @@
+//! Processes, deferred work and wait queues: what there is of a scheduler.
+
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use super::Test;
+use crate::capability::{self, CapabilityType, Permissions};
+use crate::process::{self, ProcessId, WaitQueue};
+
+pub const TESTS: &[Test] = tests![
+    pids_unique, run_as_restores, deferred_once, deferred_in_order, wait_woken, wait_times_out, kill_reaps,
+];
+
+fn pids_unique() -> Result<(), &'static str> {
+    let a = process::spawn_process("ktest-a")?;
+    let b = process::spawn_process("ktest-b")?;
+    check!(a != b && b.0 > a.0);
+    check!(!process::is_system(a));
+    Ok(())
+}
+
+fn run_as_restores() -> Result<(), &'static str> {
+    let me = process::current_pid();
+    let (a, b) = (process::spawn_process("ktest-a")?, process::spawn_process("ktest-b")?);
+    let (outer, inner, back) = process::run_as(a, || {
+        let outer = process::current_pid();
+        let inner = process::run_as(b, process::current_pid);
+        (outer, inner, process::current_pid())
+    });
+    check!(outer == a && inner == b && back == a);
+    check!(process::current_pid() == me);
+    Ok(())
+}
+
+static RUNS: AtomicU32 = AtomicU32::new(0);
+
+fn count_run() {
+    RUNS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn deferred_once() -> Result<(), &'static str> {
+    RUNS.store(0, Ordering::Relaxed);
+    process::defer(count_run);
+    process::defer(count_run);
+    process::run_deferred();
+    check!(RUNS.load(Ordering::Relaxed) == 1);
+    process::run_deferred();
+    check!(RUNS.load(Ordering::Relaxed) == 1);
+    Ok(())
+}
+
+static ORDER: AtomicU32 = AtomicU32::new(0);
+
+fn first() { ORDER.store(ORDER.load(Ordering::Relaxed) * 10 + 1, Ordering::Relaxed); }
+fn second() { ORDER.store(ORDER.load(Ordering::Relaxed) * 10 + 2, Ordering::Relaxed); }
+
+fn deferred_in_order() -> Result<(), &'static str> {
+    ORDER.store(0, Ordering::Relaxed);
+    process::defer(first);
+    process::defer(second);
+    process::run_deferred();
+    check!(ORDER.load(Ordering::Relaxed) == 12);
+    Ok(())
+}
+
+static QUEUE: WaitQueue = WaitQueue::new();
+static READY: AtomicBool = AtomicBool::new(false);
+
+fn make_ready() {
+    READY.store(true, Ordering::Release);
+    QUEUE.wake_all();
+}
+
+fn wait_woken() -> Result<(), &'static str> {
+    READY.store(false, Ordering::Relaxed);
+    process::defer(make_ready);
+    let deadline = process::uptime_ms() + 1000;
+    let woke = QUEUE.wait_until(Some(deadline), || READY.load(Ordering::Acquire).then_some(()));
+    check!(woke.is_some());
+    Ok(())
+}
+
+fn wait_times_out() -> Result<(), &'static str> {
+    let start = process::uptime_ms();
+    let woke = QUEUE.wait_until(Some(start + 30), || None::<()>);
+    check!(woke.is_none());
+    check!(process::uptime_ms() >= start + 30);
+    Ok(())
+}
+
+fn kill_reaps() -> Result<(), &'static str> {
+    let pid = process::spawn_process("ktest")?;
+    let cap = capability::create_capability(pid, CapabilityType::Display, Permissions::READ);
+    capability::validate(pid, &cap, CapabilityType::Display, Permissions::READ)?;
+    process::kill(pid)?;
+    check!(process::is_killed(pid));
+    check!(capability::validate(pid, &cap, CapabilityType::Display, Permissions::READ).is_err());
+    check!(process::kill(ProcessId(1)).is_err());
+    Ok(())
+}
@@ -0,0 +1,91 @@
+//! SurakshaOS Kernel Lockdown
+//! Once boot completes the kernel locks itself down, so that nothing that
+//! runs afterwards can change it or read it out.  The level only ever
+//! rises, until the next reset:
+//!
+//! - Integrity: kernel text and read-only data are sealed read/execute-only
+//!   by a locked PMP entry, which binds M-mode too; only drivers whose code
+//!   is part of the verified kernel image may register; device commands a
+//!   driver marks as reaching past it (raw registers and the like) are
+//!   refused.
+//! - Confidentiality: all of that, and the debug interfaces that read data
+//!   out of the kernel (AI content capture, packet capture) are closed.
+//!
+//! `kernel_main` enters `BOOT_LEVEL` just before handing off to init, or
+//! a stricter level if the command line asks for one (`lockdown=`).
+//! Refusals are reported to the security monitor.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::security::{self, SecurityEvent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    None,
+    Integrity,
+    Confidentiality,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Level::None            => "none",
+            Level::Integrity       => "integrity",
+            Level::Confidentiality => "confidentiality",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Level> {
+        match s {
+            "none"            => Some(Level::None),
+            "integrity"       => Some(Level::Integrity),
+            "confidentiality" => Some(Level::Confidentiality),
+            _                 => None,
+        }
+    }
+
+    fn from_u8(v: u8) -> Level {
+        match v {
+            0 => Level::None,
+            1 => Level::Integrity,
+            _ => Level::Confidentiality,
+        }
+    }
+}
+
+/// The level boot ends at.
+pub const BOOT_LEVEL: Level = Level::Integrity;
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::None as u8);
+
+pub fn level() -> Level {
+    Level::from_u8(LEVEL.load(Ordering::SeqCst))
+}
+
+/// Raise lockdown to `to`.  Lowering it is refused; asking for the level
+/// already in force does nothing.
+pub fn enter(to: Level) -> Result<(), &'static str> {
+    let from = Level::from_u8(LEVEL.fetch_max(to as u8, Ordering::SeqCst));
+    if to < from { return Err("lockdown cannot be lowered"); }
+    if to == from { return Ok(()); }
+    if from < Level::Integrity {
+        let (start, end) = crate::secure_boot::image_range();
+        crate::arch::seal_text(start, end);
+    }
+    crate::audit::note("lockdown", to.as_str());
+    crate::println!("  lockdown: {}", to.as_str());
+    Ok(())
+}
+
+/// Refuse `what` if lockdown is at `from` or above.
+pub fn check(from: Level, what: &'static str) -> Result<(), &'static str> {
+    if level() < from { return Ok(()); }
+    security::report(SecurityEvent::LockdownDenied { pid: crate::process::current_pid(), what });
+    Err("refused by kernel lockdown")
+}
+
+/// True if `addr` lies in the kernel image secure boot verified.
+pub fn in_kernel_image(addr: usize) -> bool {
+    let (start, end) = crate::secure_boot::image_range();
+    (start..end).contains(&addr)
+}
@@ -1,26 +1,70 @@
 //! SurakshaOS Kernel — v0.2.0
-//! Entry point after RISC-V boot assembly.
+//! Entry point after the boot assembly (RISC-V or AArch64, see arch/).
 //! Initialises hardware, memory, scheduler, then launches init.
 
 #![no_std]
 #![no_main]
 #![feature(alloc_error_handler)]
+#![feature(allocator_ext)] // const Vec::new_in, for slab-backed statics
+// Subsystem APIs are reached through syscalls and services that are still
+// being wired up; don't let that drown real warnings in noise.
+#![allow(dead_code)]
 
 extern crate alloc;
 
-use core::arch::global_asm;
-
-// Include RISC-V boot assembly (sets up stack, clears BSS, calls kernel_main)
-global_asm!(include_str!("boot.S"));
-
 // ─── kernel modules ───────────────────────────────────────────────────────────
 pub mod console;   // UART driver + print!/println! macros
+pub mod klog;      // Leveled kernel log: ring buffer, module filters, dmesg
 pub mod memory;    // Buddy allocator (existing from v0.1)
-pub mod arch;      // RISC-V arch init, trap vector (existing)
+pub mod arch;      // Boot assembly, traps, timer, interrupt controller (RISC-V, AArch64)
+pub mod stackguard; // Stack canaries + guard words, checked on trap return
+pub mod smp;       // Secondary hart bring-up, hart-local blocks, barriers
+pub mod cfi;       // Zicfilp/Zicfiss control-flow integrity, ELF CFI markings
+pub mod mte;       // ARM memory tagging: heap tags, per-process check modes
 pub mod process;   // Process table + scheduler stubs
 pub mod fs;        // VFS + in-memory filesystem
 pub mod shell;     // Interactive sursh shell
 pub mod init;      // Init system (PID 1)
+pub mod capability; // Capability registry (mint / validate / revoke)
+pub mod security;  // Security monitor (violation reports)
+pub mod sandbox;   // Per-process syscall filter, CPU/memory limits, withheld capabilities
+pub mod audit;     // Hash-chained audit log in sealed segments
+pub mod secure_boot; // Boot chain signature checks + measurements
+pub mod bootctl;   // A/B slots: selection, tries, rollback to the other slot
+pub mod lockdown;  // Integrity/confidentiality lockdown after boot
+pub mod attest;    // Measurement registers + attestd quotes
+pub mod tee;       // Trusted execution: TA sessions, key store, biometrics
+pub mod authlimit; // Failed-attempt counters + backoff for PIN/biometric checks
+pub mod permission; // User-facing permissions, consent prompts, permd
+pub mod installer; // installd: signed packages, store/developer key policy
+pub mod vmbridge;  // Android VM bridge: vsock, clipboard/file transfers with consent
+pub mod ui;        // Compositor: window layers, damage, vsync-paced frames
+pub mod driver;    // Driver trait + device registry
+pub mod fdt;       // Flattened device tree reader
+pub mod cmdline;   // Boot options: loglevel=, console=, init=, nosmp
+pub mod entropy;   // ChaCha20 CSPRNG
+pub mod bluetooth; // HCI controller + L2CAP
+pub mod wifi;      // WPA3 station: SAE, 4-way handshake, key install
+pub mod keyring;   // Sealed in-memory secrets (credentials, PMKs)
+pub mod modem;     // 5G/LTE modem over MBIM: SIM, attach, data bearer
+pub mod ipc;       // Capability-checked message channels
+pub mod event;     // epoll-style readiness sets over sockets
+pub mod haptics;   // Vibration motor driver + hapticsd
+pub mod audio;     // Microphone capture devices
+pub mod power;     // DVFS operating points + governors
+pub mod wakelock;  // Suspend blockers with per-holder stats
+pub mod thermal;   // Thermal zones, trip points, throttling
+pub mod cpuidle;   // Idle states + latency-aware selection
+pub mod energy;    // Per-process CPU energy accounting
+pub mod charger;   // Charge limit, thermal current limit, adaptive charging
+pub mod alarm;     // RTC alarms + wakeups from suspend
+pub mod brightness; // Ambient-light adaptive backlight
+pub mod syscall;   // ecall dispatch
+pub mod crypto;    // SHA-3 / SHAKE, ML-DSA verification
+pub mod ai;        // On-device models (GGUF, signed weights)
+pub mod net;       // Interfaces, IPv4, TCP, UDP
+#[cfg(feature = "ktest")]
+pub mod ktest;     // In-kernel test suites, run by init under QEMU
 
 use core::panic::PanicInfo;
 
@@ -32,16 +76,116 @@ use core::panic::PanicInfo;
 pub extern "C" fn kernel_main(hart_id: usize, dtb_ptr: usize) -> ! {
     // 1. Initialise the UART (console is usable after this point)
     //    The NS16550A is already configured by QEMU; we just start using it.
+    //    Take over the log ring, keeping the last boot's if it survived.
+    klog::init();
+
+    // 1a. Read the device tree: harts, timebase, memory map, console UART
+    let dt = fdt::init(dtb_ptr);
+    cmdline::init(); // log levels and console= apply from here on
+    if let Some(p) = fdt::platform() {
+        arch::configure(&p);
+        console::configure(&p);
+    }
 
     // 2. Initialise memory allocator (sets up the global heap)
     memory::init_heap();
+    cmdline::init_late();
+
+    // 2a. Give the boot stack its canary, before any trap frame holds one
+    stackguard::init();
 
     // 3. Set up RISC-V trap/interrupt vector
     arch::trap_init();
+    energy::cpu_busy(); // start charging CPU time to the running process
 
     // 4. Initialise the VFS root
     fs::vfs_init();
 
+    match fdt::platform() {
+        Some(p) => info!("{}: {} harts, timebase {} Hz, {} MiB RAM", p.model, p.harts().len(), arch::timebase_hz(),
+            p.memory().iter().map(|&(_, size)| size).sum::<u64>() >> 20),
+        None    => warn!("{}; assuming QEMU virt", dt.err().unwrap_or("no platform")),
+    }
+    cfi::init(); // landing pads / shadow stack, as far as the CPU has them
+    mte::init(); // memory tagging, where the CPU has it (ARMv9)
+
+    // Release the other harts, now the heap and traps are up; they idle
+    // until there is work for them
+    smp::init(hart_id);
+
+    // 4a. Pick the A/B slot to boot, spending a try if it is unproven
+    bootctl::select();
+
+    // 4b. Verify the boot chain and its security version (halts here if
+    //     enforcing and either fails)
+    secure_boot::verify_boot_chain();
+
+    // 4c. Register platform device drivers, then look for an NPU and GPU
+    driver::init();
+    if let Some(npu) = ai::npu::probe() {
+        info!("NPU: {}", npu);
+    }
+    if let Some(gpu) = ai::gpu::probe() {
+        info!("GPU: {}", gpu);
+    }
+
+    // 4d. Bring up networking (loopback; NIC drivers attach as they probe)
+    net::init();
+    if let Err(e) = net::networkd::init() {
+        warn!("networkd failed to start: {}", e);
+    }
+
+    // 4e. Register thermal zones
+    thermal::init();
+
+    // 4f. Start the alarm service
+    if let Err(e) = alarm::init() {
+        warn!("alarmd failed to start: {}", e);
+    }
+
+    // 4g. Start the attestation service
+    if let Err(e) = attest::init() {
+        warn!("attestd failed to start: {}", e);
+    }
+
+    // 4h. Bring up trusted execution
+    tee::init();
+    if let Some((backend, isolated)) = tee::backend() {
+        info!("TEE: {}{}", backend, if isolated { "" } else { " (not isolated)" });
+    }
+
+    // 4i. Start the authentication rate limiter
+    if let Err(e) = authlimit::init() {
+        warn!("authd failed to start: {}", e);
+    }
+
+    // 4j. Start the permission manager
+    if let Err(e) = permission::init() {
+        warn!("permd failed to start: {}", e);
+    }
+
+    // 4k. Start the app installer
+    if let Err(e) = installer::init() {
+        warn!("installd failed to start: {}", e);
+    }
+
+    // 4l. Start the Android VM bridge
+    if let Err(e) = vmbridge::init() {
+        warn!("vmbridge failed to start: {}", e);
+    }
+
+    // 4m. Start the compositor
+    if let Err(e) = ui::init() {
+        warn!("compositor failed to start: {}", e);
+    }
+
+    // 4n. Start the AI inference service
+    if let Err(e) = ai::service::init() {
+        warn!("aid failed to start: {}", e);
+    } else if let Err(e) = ai::manager::init() {
+        warn!("AI model manager: {}", e);
+    }
+
     // 5. Print welcome line (before full init banner)
     println!("");
     println!("  suraksha-kernel booting on hart {}", hart_id);
@@ -49,8 +193,14 @@ pub extern "C" fn kernel_main(hart_id: usize, dtb_ptr: usize) -> ! {
         println!("  DTB at {:#x}", dtb_ptr);
     }
 
+    // 5a. Lock the kernel down before anything else gets to run
+    let level = cmdline::options().lockdown.map_or(lockdown::BOOT_LEVEL, |l| l.max(lockdown::BOOT_LEVEL));
+    if let Err(e) = lockdown::enter(level) {
+        error!("lockdown failed: {}", e);
+    }
+
     // 6. Hand off to init (PID 1) — never returns
-    let mut init = init::InitSystem::new();
+    let init = init::InitSystem::new();
     init.run()
 }
 
@@ -66,9 +216,13 @@ fn panic(info: &PanicInfo) -> ! {
         println!("  at {}:{}:{}", loc.file(), loc.line(), loc.column());
     }
     println!("  {}", info);
+    klog::panic_record(format_args!("{}", info));
+    #[cfg(feature = "ktest")]
+    ktest::panicked(info);
     // Halt all harts
+    #[cfg(not(feature = "ktest"))]
     loop {
-        unsafe { core::arch::asm!("wfi", options(nomem, nostack)); }
+        arch::wait_for_interrupt();
     }
 }
 
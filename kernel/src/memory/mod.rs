@@ -0,0 +1,121 @@
+//! SurakshaOS Memory Management
+//! Buddy/linked-list heap allocator providing the global allocator,
+//! heap initialisation, and memory usage statistics.  Under memory
+//! pressure, subsystems holding reclaimable memory are asked to shrink.
+//! Buffers holding private data can carry the zero-on-free attribute.
+//!
+//! The `heap-hardening` feature holds freed blocks in a quarantine before
+//! reuse, and gives capabilities and credentials slabs of their own.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+mod quarantine;
+mod secure;
+mod slab;
+pub use quarantine::{QuarantineStats, MAX_QUARANTINED, POISON, QUARANTINE_BYTES, QUARANTINE_SLOTS};
+pub use secure::{secure_vec, wipe, SecureVec, ZeroOnFree};
+pub use slab::{slabs, SlabStats, TypeSlab, CAPABILITIES, CREDENTIALS};
+
+#[global_allocator]
+static ALLOCATOR: quarantine::KernelHeap = quarantine::KernelHeap::empty();
+
+// Heap boundaries defined by the linker script
+extern "C" {
+    static _heap_start: u8;
+    static _heap_end: u8;
+}
+
+/// Maximum heap size (64 MB — matches the kernel_main comment)
+const MAX_HEAP: usize = 64 * 1024 * 1024;
+
+static mut HEAP_TOTAL_SIZE: usize = 0;
+
+/// Initialise the global heap allocator.
+/// Must be called exactly once, before any allocation, and after
+/// `fdt::init` so the memory map is known.
+pub fn init_heap() {
+    unsafe {
+        let start = &_heap_start as *const u8 as usize;
+        let end   = &_heap_end   as *const u8 as usize;
+        // Stop at the end of the RAM the heap starts in, where the device
+        // tree says where that is
+        let end = crate::fdt::platform().and_then(|p| {
+            p.memory().iter().map(|&(base, size)| (base as usize, (base + size) as usize))
+                .find(|&(base, top)| (base..top).contains(&start))
+        }).map_or(end, |(_, top)| end.min(top));
+        let size  = (end - start).min(MAX_HEAP);
+        HEAP_TOTAL_SIZE = size;
+        ALLOCATOR.heap().lock().init(start as *mut u8, size);
+    }
+    if cfg!(feature = "heap-hardening") {
+        register_shrinker("quarantine", |_| ALLOCATOR.flush());
+    }
+}
+
+/// Bytes currently allocated on the heap, quarantined blocks included.
+pub fn heap_used() -> usize {
+    ALLOCATOR.heap().lock().used()
+}
+
+/// Freed blocks waiting out their quarantine.
+pub fn quarantine() -> QuarantineStats {
+    ALLOCATOR.quarantine_stats()
+}
+
+pub fn heap_hardening() -> bool {
+    cfg!(feature = "heap-hardening")
+}
+
+/// Total heap size in bytes.
+pub fn heap_total() -> usize {
+    unsafe { HEAP_TOTAL_SIZE }
+}
+
+// ─── memory pressure ──────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pressure {
+    None,
+    /// Heap above MODERATE_PCT: drop caches that are cheap to rebuild.
+    Moderate,
+    /// Heap above CRITICAL_PCT: free everything that is not in use.
+    Critical,
+}
+
+const MODERATE_PCT: usize = 75;
+const CRITICAL_PCT: usize = 90;
+
+pub fn pressure() -> Pressure {
+    let total = heap_total();
+    if total == 0 { return Pressure::None; }
+    match heap_used() * 100 / total {
+        p if p >= CRITICAL_PCT => Pressure::Critical,
+        p if p >= MODERATE_PCT => Pressure::Moderate,
+        _                      => Pressure::None,
+    }
+}
+
+/// Subsystem callback that releases memory at the given pressure; returns
+/// whether it freed anything.
+pub type Shrinker = fn(Pressure) -> bool;
+
+static SHRINKERS: Mutex<Vec<(&'static str, Shrinker)>> = Mutex::new(Vec::new());
+
+pub fn register_shrinker(name: &'static str, shrink: Shrinker) {
+    SHRINKERS.lock().push((name, shrink));
+}
+
+/// Ask shrinkers to free memory while the heap is under pressure.  Called
+/// when the CPU goes idle and before large allocations; never from an
+/// interrupt handler.  Returns the heap bytes recovered.
+pub fn reclaim() -> usize {
+    let level = pressure();
+    if level == Pressure::None { return 0; }
+    let before = heap_used();
+    let shrinkers = SHRINKERS.lock().clone();
+    for (_, shrink) in shrinkers {
+        if shrink(level) && pressure() == Pressure::None { break; }
+    }
+    before.saturating_sub(heap_used())
+}
@@ -0,0 +1,139 @@
+//! Freed-memory Quarantine
+//! The kernel heap, wrapped so that with the `heap-hardening` feature a
+//! freed block is not reused at once.  It is filled with `POISON` and held
+//! in a quarantine until `QUARANTINE_SLOTS` later frees, or
+//! `QUARANTINE_BYTES` of newer quarantined memory, push it out.  A
+//! dangling pointer then reads poison rather than whatever was allocated
+//! next, and a write through one is caught when the block leaves: its
+//! poison has changed, and the kernel panics naming the block.
+//!
+//! Blocks larger than `MAX_QUARANTINED` go straight back; holding them
+//! would cost too much of the heap.  Under memory pressure the quarantine
+//! is flushed.
+//!
+//! Where the CPU has memory tagging, every block is also tagged as it is
+//! handed out and untagged as it comes back (see `mte`).
+
+use core::alloc::{GlobalAlloc, Layout};
+use linked_list_allocator::LockedHeap;
+use spin::Mutex;
+
+const HARDENED: bool = cfg!(feature = "heap-hardening");
+
+/// Frees held at most.
+pub const QUARANTINE_SLOTS: usize = 512;
+/// Bytes held at most.
+pub const QUARANTINE_BYTES: usize = 1024 * 1024;
+/// Larger blocks skip the quarantine.
+pub const MAX_QUARANTINED:  usize = 64 * 1024;
+/// What quarantined memory is filled with.
+pub const POISON: u8 = 0xa5;
+
+#[derive(Clone, Copy)]
+struct Block {
+    ptr:   usize,
+    size:  usize,
+    align: usize,
+}
+
+struct Quarantine {
+    ring:    [Block; QUARANTINE_SLOTS],
+    /// Oldest block, and how many are held.
+    head:    usize,
+    len:     usize,
+    bytes:   usize,
+    evicted: u64,
+}
+
+impl Quarantine {
+    fn pop(&mut self) -> Option<Block> {
+        if self.len == 0 { return None; }
+        let b = self.ring[self.head];
+        self.head = (self.head + 1) % QUARANTINE_SLOTS;
+        self.len -= 1;
+        self.bytes -= b.size;
+        self.evicted += 1;
+        Some(b)
+    }
+
+    /// Hold `b`; returns the oldest block if that leaves too much held.
+    fn push(&mut self, b: Block) -> Option<Block> {
+        let out = if self.len == QUARANTINE_SLOTS || self.bytes + b.size > QUARANTINE_BYTES { self.pop() } else { None };
+        self.ring[(self.head + self.len) % QUARANTINE_SLOTS] = b;
+        self.len += 1;
+        self.bytes += b.size;
+        out
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QuarantineStats {
+    pub blocks:  usize,
+    pub bytes:   usize,
+    /// Blocks that have served their time and gone back to the heap.
+    pub evicted: u64,
+}
+
+/// The global allocator: the linked-list heap behind the quarantine.
+pub struct KernelHeap {
+    heap:       LockedHeap,
+    quarantine: Mutex<Quarantine>,
+}
+
+impl KernelHeap {
+    pub const fn empty() -> KernelHeap {
+        KernelHeap {
+            heap:       LockedHeap::empty(),
+            quarantine: Mutex::new(Quarantine {
+                ring: [Block { ptr: 0, size: 0, align: 1 }; QUARANTINE_SLOTS],
+                head: 0, len: 0, bytes: 0, evicted: 0,
+            }),
+        }
+    }
+
+    pub fn heap(&self) -> &LockedHeap {
+        &self.heap
+    }
+
+    /// Check `b` still holds nothing but poison, and free it.
+    unsafe fn release(&self, b: Block) {
+        let p = b.ptr as *const u8;
+        if let Some(at) = (0..b.size).find(|&i| core::ptr::read_volatile(p.add(i)) != POISON) {
+            panic!("use after free: block {:#x} ({} B) written at +{} after it was freed", b.ptr, b.size, at);
+        }
+        let layout = Layout::from_size_align_unchecked(b.size, b.align);
+        self.heap.dealloc(b.ptr as *mut u8, layout);
+    }
+
+    /// Return every quarantined block to the heap; whether there were any.
+    pub fn flush(&self) -> bool {
+        let mut any = false;
+        loop {
+            // Unlocked while checking, since a detected write panics
+            let Some(b) = self.quarantine.lock().pop() else { return any };
+            unsafe { self.release(b) };
+            any = true;
+        }
+    }
+
+    pub fn quarantine_stats(&self) -> QuarantineStats {
+        let q = self.quarantine.lock();
+        QuarantineStats { blocks: q.len, bytes: q.bytes, evicted: q.evicted }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        crate::mte::tag_region(self.heap.alloc(layout), layout.size())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let ptr = crate::mte::untag_region(ptr, layout.size());
+        if !HARDENED || layout.size() > MAX_QUARANTINED {
+            return self.heap.dealloc(ptr, layout);
+        }
+        core::ptr::write_bytes(ptr, POISON, layout.size());
+        let out = self.quarantine.lock().push(Block { ptr: ptr as usize, size: layout.size(), align: layout.align() });
+        if let Some(b) = out { self.release(b); }
+    }
+}
@@ -0,0 +1,42 @@
+//! Zero-on-free Memory
+//! An allocator attribute for buffers that hold private data: memory is
+//! wiped before it goes back to the heap, including the old block when a
+//! buffer grows or shrinks, so nothing written to it can turn up in a
+//! later allocation.
+
+use alloc::alloc::{AllocError, Allocator, Global, Layout};
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+
+/// Allocator with the zero-on-free attribute.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZeroOnFree;
+
+/// A vector in zero-on-free memory.
+pub type SecureVec<T> = Vec<T, ZeroOnFree>;
+
+unsafe impl Allocator for ZeroOnFree {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        // Volatile, so the stores are not dropped as dead before the free
+        let p = ptr.as_ptr();
+        for i in 0..layout.size() { core::ptr::write_volatile(p.add(i), 0); }
+        Global.deallocate(ptr, layout)
+    }
+}
+
+/// `len` copies of `value` in zero-on-free memory.
+pub fn secure_vec<T: Clone>(value: T, len: usize) -> SecureVec<T> {
+    let mut v = Vec::with_capacity_in(len, ZeroOnFree);
+    v.resize(len, value);
+    v
+}
+
+/// Overwrite `buf` with zeroes, for private data in ordinary memory that
+/// is about to be dropped.
+pub fn wipe<T: Copy + Default>(buf: &mut [T]) {
+    for v in buf.iter_mut() { unsafe { core::ptr::write_volatile(v, T::default()) }; }
+}
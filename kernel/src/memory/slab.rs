@@ -0,0 +1,130 @@
+//! Type-isolated Slabs
+//! Dedicated memory for security-sensitive objects.  A `TypeSlab` takes
+//! chunks from the heap and never gives them back, so memory that once
+//! held capabilities only ever holds capabilities: a driver's dangling
+//! pointer into freed heap memory can never end up aimed at one.
+//!
+//! Objects come in power-of-two size classes, each with its own freelist.
+//! A new chunk is cut into objects that go onto the freelist in random
+//! order, so where the next object lands cannot be predicted from where
+//! the last one did.  Freed objects are zeroed.
+//!
+//! All of this is the `heap-hardening` feature; without it a `TypeSlab`
+//! passes allocations straight to the heap.
+
+use alloc::alloc::{AllocError, Allocator, Global, Layout};
+use core::ptr::NonNull;
+use spin::Mutex;
+
+const MIN_SIZE: usize = 16;
+/// Size classes: 16 B to 1 MiB.
+const CLASSES:  usize = 17;
+/// Chunks are at least this big.
+const CHUNK:    usize = 4096;
+
+const HARDENED: bool = cfg!(feature = "heap-hardening");
+
+#[derive(Clone, Copy)]
+struct Class {
+    /// First free object, 0 if none.  Each free object's first word links
+    /// to the next.
+    free:    usize,
+    in_use:  usize,
+    objects: usize,
+}
+
+pub struct TypeSlab {
+    pub name: &'static str,
+    classes:  Mutex<[Class; CLASSES]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlabStats {
+    pub name:     &'static str,
+    /// Bytes taken from the heap, for good.
+    pub reserved: usize,
+    /// Bytes in objects handed out.
+    pub in_use:   usize,
+}
+
+/// The capability registry.
+pub static CAPABILITIES: TypeSlab = TypeSlab::new("capabilities");
+/// Keys and other secrets held for processes.
+pub static CREDENTIALS:  TypeSlab = TypeSlab::new("credentials");
+
+pub fn slabs() -> [&'static TypeSlab; 2] {
+    [&CAPABILITIES, &CREDENTIALS]
+}
+
+fn class_of(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align()).max(MIN_SIZE).next_power_of_two();
+    let class = (size / MIN_SIZE).trailing_zeros() as usize;
+    (class < CLASSES).then_some(class)
+}
+
+impl TypeSlab {
+    pub const fn new(name: &'static str) -> TypeSlab {
+        TypeSlab { name, classes: Mutex::new([Class { free: 0, in_use: 0, objects: 0 }; CLASSES]) }
+    }
+
+    /// Cut a fresh chunk into objects of class `class`, onto its freelist
+    /// in random order.
+    fn grow(class: &mut Class, size: usize) -> Result<(), AllocError> {
+        let chunk = size.max(CHUNK);
+        let base = Global.allocate(Layout::from_size_align(chunk, size).map_err(|_| AllocError)?)?;
+        let base = base.as_ptr() as *mut u8 as usize;
+        let n = chunk / size;
+        let mut order = [0u16; CHUNK / MIN_SIZE];
+        for (i, o) in order[..n].iter_mut().enumerate() { *o = i as u16; }
+        for i in (1..n).rev() {
+            order.swap(i, crate::entropy::next_u32() as usize % (i + 1));
+        }
+        for &i in &order[..n] {
+            let obj = base + i as usize * size;
+            unsafe { *(obj as *mut usize) = class.free };
+            class.free = obj;
+        }
+        class.objects += n;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        let classes = self.classes.lock();
+        let (mut reserved, mut in_use) = (0, 0);
+        for (i, c) in classes.iter().enumerate() {
+            reserved += c.objects * (MIN_SIZE << i);
+            in_use   += c.in_use * (MIN_SIZE << i);
+        }
+        SlabStats { name: self.name, reserved, in_use }
+    }
+}
+
+unsafe impl Allocator for &'static TypeSlab {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if !HARDENED { return Global.allocate(layout); }
+        let i = class_of(layout).ok_or(AllocError)?;
+        let size = MIN_SIZE << i;
+        let mut classes = self.classes.lock();
+        let class = &mut classes[i];
+        if class.free == 0 { TypeSlab::grow(class, size)?; }
+        let obj = class.free;
+        class.free = unsafe { *(obj as *const usize) };
+        class.in_use += 1;
+        unsafe { *(obj as *mut usize) = 0 };
+        let ptr = NonNull::new(obj as *mut u8).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, size))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if !HARDENED { return Global.deallocate(ptr, layout); }
+        let Some(i) = class_of(layout) else { return };
+        let size = MIN_SIZE << i;
+        let p = ptr.as_ptr();
+        for b in 0..size { core::ptr::write_volatile(p.add(b), 0); }
+        let mut classes = self.classes.lock();
+        let class = &mut classes[i];
+        *(p as *mut usize) = class.free;
+        class.free = p as usize;
+        class.in_use -= 1;
+    }
+}
@@ -0,0 +1,400 @@
+//! MBIM: Mobile Broadband Interface Model (USB-IF MBIM 1.0)
+//! The modem's control channel and data framing.  Control messages carry
+//! commands to, and their completions and unsolicited indications from,
+//! the modem's Basic Connect service; messages longer than the control
+//! transfer size come in fragments, put back together by `Defrag`.  Data
+//! moves in NCM transfer blocks (NTB16), each IP packet tagged with the
+//! session (bearer) it belongs to.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+// ─── messages ─────────────────────────────────────────────────────────────────
+
+const OPEN_MSG:        u32 = 0x0000_0001;
+const CLOSE_MSG:       u32 = 0x0000_0002;
+const COMMAND_MSG:     u32 = 0x0000_0003;
+const OPEN_DONE:       u32 = 0x8000_0001;
+const CLOSE_DONE:      u32 = 0x8000_0002;
+const COMMAND_DONE:    u32 = 0x8000_0003;
+const FUNCTION_ERROR:  u32 = 0x8000_0004;
+const INDICATE_STATUS: u32 = 0x8000_0007;
+
+const HEADER:   usize = 12;
+/// Header and fragment header.
+const FRAGMENT: usize = 20;
+
+const COMMAND_QUERY: u32 = 0;
+const COMMAND_SET:   u32 = 1;
+
+/// The Basic Connect service, as its UUID is sent.
+pub const BASIC_CONNECT: [u8; 16] = [
+    0xA2, 0x89, 0xCC, 0x33, 0xBC, 0xBB, 0x8B, 0x4F, 0xB6, 0xB0, 0x13, 0x3E, 0xC2, 0xAA, 0xE6, 0xDF,
+];
+/// Context type for Internet access.
+const CONTEXT_INTERNET: [u8; 16] = [
+    0x7E, 0x5E, 0x2A, 0x7E, 0x4E, 0x6F, 0x72, 0x72, 0x73, 0x6B, 0x65, 0x6E, 0x7E, 0x5E, 0x2A, 0x7E,
+];
+
+// Basic Connect commands
+pub const CID_SUBSCRIBER_READY: u32 = 2;
+pub const CID_RADIO_STATE:      u32 = 3;
+pub const CID_PIN:              u32 = 4;
+pub const CID_REGISTER_STATE:   u32 = 9;
+pub const CID_PACKET_SERVICE:   u32 = 10;
+pub const CID_SIGNAL_STATE:     u32 = 11;
+pub const CID_CONNECT:          u32 = 12;
+pub const CID_IP_CONFIGURATION: u32 = 15;
+
+pub const STATUS_SUCCESS:          u32 = 0;
+pub const STATUS_BUSY:             u32 = 1;
+pub const STATUS_FAILURE:          u32 = 2;
+pub const STATUS_SIM_NOT_INSERTED: u32 = 3;
+pub const STATUS_BAD_SIM:          u32 = 4;
+pub const STATUS_PIN_REQUIRED:     u32 = 5;
+pub const STATUS_NOT_REGISTERED:   u32 = 7;
+pub const STATUS_DETACHED:         u32 = 12;
+pub const STATUS_BAD_APN:          u32 = 18;
+pub const STATUS_RADIO_OFF:        u32 = 20;
+
+/// What a failed command's status means.
+pub fn status_error(status: u32) -> &'static str {
+    match status {
+        STATUS_BUSY             => "modem busy",
+        STATUS_SIM_NOT_INSERTED => "no SIM",
+        STATUS_BAD_SIM          => "SIM unusable",
+        STATUS_PIN_REQUIRED     => "SIM locked",
+        STATUS_NOT_REGISTERED   => "not registered with a network",
+        STATUS_DETACHED         => "packet service detached",
+        STATUS_BAD_APN          => "APN rejected",
+        STATUS_RADIO_OFF        => "radio off",
+        _                       => "modem command failed",
+    }
+}
+
+/// A message from the modem.
+pub enum Message {
+    OpenDone { tid: u32, status: u32 },
+    CloseDone { tid: u32, status: u32 },
+    CommandDone { tid: u32, service: [u8; 16], cid: u32, status: u32, info: Vec<u8> },
+    Indication { service: [u8; 16], cid: u32, info: Vec<u8> },
+    /// The modem could not take message `tid`.
+    Error { tid: u32, code: u32 },
+}
+
+fn u32_at(b: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(b.get(off..off + 4)?.try_into().ok()?))
+}
+
+fn header(kind: u32, len: usize, tid: u32) -> Vec<u8> {
+    let mut m = Vec::with_capacity(len);
+    m.extend_from_slice(&kind.to_le_bytes());
+    m.extend_from_slice(&(len as u32).to_le_bytes());
+    m.extend_from_slice(&tid.to_le_bytes());
+    m
+}
+
+pub fn open(tid: u32, max_transfer: u32) -> Vec<u8> {
+    let mut m = header(OPEN_MSG, HEADER + 4, tid);
+    m.extend_from_slice(&max_transfer.to_le_bytes());
+    m
+}
+
+pub fn close(tid: u32) -> Vec<u8> {
+    header(CLOSE_MSG, HEADER, tid)
+}
+
+/// A Basic Connect command, in one fragment.
+pub fn command(tid: u32, cid: u32, set: bool, info: &[u8]) -> Vec<u8> {
+    let mut m = header(COMMAND_MSG, FRAGMENT + 28 + info.len(), tid);
+    m.extend_from_slice(&1u32.to_le_bytes());
+    m.extend_from_slice(&0u32.to_le_bytes());
+    m.extend_from_slice(&BASIC_CONNECT);
+    m.extend_from_slice(&cid.to_le_bytes());
+    m.extend_from_slice(&(if set { COMMAND_SET } else { COMMAND_QUERY }).to_le_bytes());
+    m.extend_from_slice(&(info.len() as u32).to_le_bytes());
+    m.extend_from_slice(info);
+    m
+}
+
+pub fn parse(m: &[u8]) -> Option<Message> {
+    let (kind, len, tid) = (u32_at(m, 0)?, u32_at(m, 4)? as usize, u32_at(m, 8)?);
+    let m = m.get(..len)?;
+    let service = |m: &[u8]| -> Option<[u8; 16]> { m.get(FRAGMENT..FRAGMENT + 16)?.try_into().ok() };
+    Some(match kind {
+        OPEN_DONE      => Message::OpenDone { tid, status: u32_at(m, HEADER)? },
+        CLOSE_DONE     => Message::CloseDone { tid, status: u32_at(m, HEADER)? },
+        FUNCTION_ERROR => Message::Error { tid, code: u32_at(m, HEADER)? },
+        COMMAND_DONE   => {
+            let n = u32_at(m, FRAGMENT + 24)? as usize;
+            Message::CommandDone {
+                tid, service: service(m)?, cid: u32_at(m, FRAGMENT + 16)?, status: u32_at(m, FRAGMENT + 20)?,
+                info: m.get(FRAGMENT + 28..FRAGMENT + 28 + n)?.to_vec(),
+            }
+        }
+        INDICATE_STATUS => {
+            let n = u32_at(m, FRAGMENT + 20)? as usize;
+            Message::Indication { service: service(m)?, cid: u32_at(m, FRAGMENT + 16)?, info: m.get(FRAGMENT + 24..FRAGMENT + 24 + n)?.to_vec() }
+        }
+        _ => return None,
+    })
+}
+
+/// Puts fragmented messages back together.  Each fragment after the
+/// first carries only its headers and the next piece of the body.
+#[derive(Default)]
+pub struct Defrag {
+    /// The message so far and the fragment expected next.
+    partial: Option<(Vec<u8>, u32)>,
+}
+
+impl Defrag {
+    /// Take a message as received; returns it once whole.
+    pub fn push(&mut self, m: &[u8]) -> Option<Vec<u8>> {
+        let kind = u32_at(m, 0)?;
+        if !matches!(kind, COMMAND_DONE | INDICATE_STATUS) { return Some(m.to_vec()); }
+        let len = (u32_at(m, 4)? as usize).min(m.len());
+        let (total, current) = (u32_at(m, HEADER)?, u32_at(m, HEADER + 4)?);
+        if len < FRAGMENT || current >= total { return None; }
+        if current == 0 {
+            self.partial = None;
+            if total == 1 { return Some(m[..len].to_vec()); }
+            self.partial = Some((m[..len].to_vec(), 1));
+            return None;
+        }
+        let (msg, next) = self.partial.as_mut()?;
+        // Out of sequence, or another message's: drop what we have
+        if current != *next || u32_at(msg, 8) != u32_at(m, 8) {
+            self.partial = None;
+            return None;
+        }
+        msg.extend_from_slice(&m[FRAGMENT..len]);
+        *next += 1;
+        if *next < total { return None; }
+        let (mut msg, _) = self.partial.take()?;
+        let len = msg.len() as u32;
+        msg[4..8].copy_from_slice(&len.to_le_bytes());
+        msg[HEADER..HEADER + 4].copy_from_slice(&1u32.to_le_bytes());
+        Some(msg)
+    }
+}
+
+// ─── information buffers ──────────────────────────────────────────────────────
+
+/// Builds an information buffer: fixed fields first, then the strings
+/// they point to by (offset, size), each padded to four bytes.
+struct Info {
+    fixed_len: usize,
+    fixed:     Vec<u8>,
+    data:      Vec<u8>,
+}
+
+impl Info {
+    fn new(fixed_len: usize) -> Info {
+        Info { fixed_len, fixed: Vec::with_capacity(fixed_len), data: Vec::new() }
+    }
+
+    fn u32(mut self, v: u32) -> Info {
+        self.fixed.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn bytes(mut self, b: &[u8]) -> Info {
+        self.fixed.extend_from_slice(b);
+        self
+    }
+
+    /// A UTF-16LE string; empty strings are (0, 0).
+    fn string(mut self, s: &str) -> Info {
+        if s.is_empty() { return self.u32(0).u32(0); }
+        let offset = self.fixed_len + self.data.len();
+        let start = self.data.len();
+        for u in s.encode_utf16() { self.data.extend_from_slice(&u.to_le_bytes()); }
+        let size = self.data.len() - start;
+        while !self.data.len().is_multiple_of(4) { self.data.push(0); }
+        self.u32(offset as u32).u32(size as u32)
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        debug_assert_eq!(self.fixed.len(), self.fixed_len);
+        self.fixed.append(&mut self.data);
+        self.fixed
+    }
+}
+
+/// The string an (offset, size) pair at `off` points to.
+fn string_at(b: &[u8], off: usize) -> Option<String> {
+    let (start, size) = (u32_at(b, off)? as usize, u32_at(b, off + 4)? as usize);
+    let units = b.get(start..start.checked_add(size)?)?.as_chunks::<2>().0.iter().map(|c| u16::from_le_bytes(*c));
+    Some(char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect())
+}
+
+pub fn radio_state(on: bool) -> Vec<u8> {
+    Info::new(4).u32(on as u32).finish()
+}
+
+pub const PIN_TYPE_NONE: u32 = 0;
+pub const PIN_TYPE_PIN1: u32 = 2;
+pub const PIN_TYPE_PUK1: u32 = 11;
+const PIN_STATE_LOCKED:  u32 = 1;
+const PIN_OP_ENTER:      u32 = 0;
+
+/// Enter `pin` (a PIN, or a PUK with the `new_pin` to set).
+pub fn enter_pin(pin_type: u32, pin: &str, new_pin: &str) -> Vec<u8> {
+    Info::new(24).u32(pin_type).u32(PIN_OP_ENTER).string(pin).string(new_pin).finish()
+}
+
+/// Register with whichever network the modem picks.
+pub fn register_automatic() -> Vec<u8> {
+    Info::new(16).string("").u32(0).u32(0).finish()
+}
+
+pub fn packet_service(attach: bool) -> Vec<u8> {
+    Info::new(4).u32(if attach { 0 } else { 1 }).finish()
+}
+
+const AUTH_NONE: u32 = 0;
+const IP_V4V6:   u32 = 3;
+
+/// Activate or deactivate Internet context `session` on `apn`.
+pub fn connect(session: u32, activate: bool, apn: &str) -> Vec<u8> {
+    Info::new(60).u32(session).u32(activate as u32).string(apn).string("").string("")
+        .u32(0).u32(AUTH_NONE).u32(IP_V4V6).bytes(&CONTEXT_INTERNET).finish()
+}
+
+pub fn ip_configuration(session: u32) -> Vec<u8> {
+    let mut info = Info::new(60).u32(session);
+    for _ in 1..15 { info = info.u32(0); }
+    info.finish()
+}
+
+// ─── responses and indications ────────────────────────────────────────────────
+
+pub fn subscriber_ready(info: &[u8]) -> Option<u32> {
+    u32_at(info, 0)
+}
+
+/// (PIN type, locked, attempts left).
+pub fn pin_info(info: &[u8]) -> Option<(u32, bool, u32)> {
+    Some((u32_at(info, 0)?, u32_at(info, 4)? == PIN_STATE_LOCKED, u32_at(info, 8)?))
+}
+
+/// (register state, provider name).
+pub fn register_state(info: &[u8]) -> Option<(u32, String)> {
+    Some((u32_at(info, 4)?, string_at(info, 28).unwrap_or_default()))
+}
+
+pub const PACKET_ATTACHED: u32 = 2;
+
+/// (packet service state, highest data class available).
+pub fn packet_state(info: &[u8]) -> Option<(u32, u32)> {
+    Some((u32_at(info, 4)?, u32_at(info, 8)?))
+}
+
+/// (RSSI, error rate), as 3GPP TS 27.007 codes them.
+pub fn signal_state(info: &[u8]) -> Option<(u32, u32)> {
+    Some((u32_at(info, 0)?, u32_at(info, 4)?))
+}
+
+pub const ACTIVATED:   u32 = 1;
+pub const DEACTIVATED: u32 = 3;
+
+/// (session, activation state).
+pub fn connect_info(info: &[u8]) -> Option<(u32, u32)> {
+    Some((u32_at(info, 0)?, u32_at(info, 4)?))
+}
+
+const IP_ADDRESS: u32 = 1;
+const IP_GATEWAY: u32 = 2;
+const IP_DNS:     u32 = 4;
+const IP_MTU:     u32 = 8;
+
+/// A bearer's addressing, as the network assigned it.
+#[derive(Debug, Clone, Default)]
+pub struct IpConfig {
+    pub v4:      Option<([u8; 4], u8)>,
+    pub gateway: Option<[u8; 4]>,
+    pub dns:     Vec<[u8; 4]>,
+    pub mtu:     Option<u32>,
+    pub v6:      Option<([u8; 16], u8)>,
+}
+
+pub fn ip_config(info: &[u8]) -> Option<IpConfig> {
+    let (avail4, avail6) = (u32_at(info, 4)?, u32_at(info, 8)?);
+    let at = |off: usize, len: usize| -> Option<&[u8]> { info.get(off..off + len) };
+    let mut c = IpConfig::default();
+    if avail4 & IP_ADDRESS != 0 && u32_at(info, 12)? > 0 {
+        let off = u32_at(info, 16)? as usize;
+        c.v4 = Some((at(off + 4, 4)?.try_into().ok()?, u32_at(info, off)?.min(32) as u8));
+    }
+    if avail4 & IP_GATEWAY != 0 {
+        c.gateway = Some(at(u32_at(info, 28)? as usize, 4)?.try_into().ok()?);
+    }
+    if avail4 & IP_DNS != 0 {
+        let off = u32_at(info, 40)? as usize;
+        for i in 0..u32_at(info, 36)?.min(4) as usize {
+            c.dns.push(at(off + 4 * i, 4)?.try_into().ok()?);
+        }
+    }
+    if avail4 & IP_MTU != 0 { c.mtu = u32_at(info, 52); }
+    if avail6 & IP_ADDRESS != 0 && u32_at(info, 20)? > 0 {
+        let off = u32_at(info, 24)? as usize;
+        c.v6 = Some((at(off + 4, 16)?.try_into().ok()?, u32_at(info, off)?.min(128) as u8));
+    }
+    Some(c)
+}
+
+// ─── data framing ─────────────────────────────────────────────────────────────
+
+const NTH16_SIGNATURE: [u8; 4] = *b"NCMH";
+const NTH16_LEN:       usize   = 12;
+/// An IP datagram table, for the session in the last byte.
+const NDP16_IPS:       [u8; 3] = *b"IPS";
+/// Table header and one entry plus the terminating one.
+const NDP16_ONE:       usize   = 16;
+
+/// One IP packet for `session` as a transfer block.
+pub fn ntb(seq: u16, session: u8, packet: &[u8]) -> Vec<u8> {
+    let offset = NTH16_LEN + NDP16_ONE;
+    let len = offset + packet.len();
+    let mut b = Vec::with_capacity(len);
+    b.extend_from_slice(&NTH16_SIGNATURE);
+    b.extend_from_slice(&(NTH16_LEN as u16).to_le_bytes());
+    b.extend_from_slice(&seq.to_le_bytes());
+    b.extend_from_slice(&(len as u16).to_le_bytes());
+    b.extend_from_slice(&(NTH16_LEN as u16).to_le_bytes());
+    b.extend_from_slice(&NDP16_IPS);
+    b.push(session);
+    b.extend_from_slice(&(NDP16_ONE as u16).to_le_bytes());
+    b.extend_from_slice(&0u16.to_le_bytes());
+    b.extend_from_slice(&(offset as u16).to_le_bytes());
+    b.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+    b.extend_from_slice(&[0; 4]);
+    b.extend_from_slice(packet);
+    b
+}
+
+/// The IP packets for `session` in a received transfer block.
+pub fn ntb_packets(b: &[u8], session: u8) -> Vec<Vec<u8>> {
+    let u16_at = |off: usize| b.get(off..off + 2).map(|s| u16::from_le_bytes([s[0], s[1]]) as usize);
+    let mut packets = Vec::new();
+    if b.len() < NTH16_LEN || b[..4] != NTH16_SIGNATURE { return packets; }
+    let block = u16_at(8).unwrap_or(0).min(b.len());
+    let mut ndp = u16_at(10).unwrap_or(0);
+    // Tables chain forward; a pointer back would loop
+    let mut last = 0;
+    while ndp > last && ndp + 8 <= block {
+        let ours = b[ndp..ndp + 3] == NDP16_IPS && b[ndp + 3] == session;
+        let len = u16_at(ndp + 4).unwrap_or(0).min(block - ndp);
+        if ours {
+            for e in (ndp + 8..ndp + len).step_by(4) {
+                let (Some(start), Some(n)) = (u16_at(e), u16_at(e + 2)) else { break };
+                if start == 0 || n == 0 { break; }
+                if let Some(p) = b.get(start..start + n).filter(|_| start + n <= block) { packets.push(p.to_vec()); }
+            }
+        }
+        last = ndp;
+        ndp = u16_at(ndp + 6).unwrap_or(0);
+    }
+    packets
+}
@@ -0,0 +1,624 @@
+//! SurakshaOS Cellular Modem
+//! Control of a 5G/LTE modem over MBIM (see `mbim`): the SIM and its PIN,
+//! registration and packet service attach, the data bearer, and signal
+//! quality.  Commands are synchronous, as HCI's are: the caller waits for
+//! the modem to complete each, up to `COMMAND_TIMEOUT_MS`, or
+//! `NETWORK_TIMEOUT_MS` for those that wait on the network.  What the
+//! modem reports unprompted (the SIM coming ready, registration, signal,
+//! a bearer the network dropped) is taken in as it arrives, while waiting
+//! on a command or when the stack polls the data interface.
+//!
+//! The modem appears twice: as network interface `wwan0`, a bare-IP link
+//! that carries the bearer's traffic and gets its addresses while the
+//! bearer is up, and as device `wwan0`, for which every control operation
+//! needs a capability (`CapabilityType::Device`).  One bearer, on the
+//! Internet context, is supported.
+
+pub mod mbim;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{Capability, Permissions};
+use crate::driver::{self, check_access, Device, DeviceClass, DeviceId, Driver};
+use crate::net::{self, Ipv4Addr, Ipv6Addr, MacAddr, NetDevice};
+use mbim::Message;
+
+/// How long an ordinary command may take.
+const COMMAND_TIMEOUT_MS: u64 = 2000;
+/// How long attach and bearer activation may take, waiting on the network.
+const NETWORK_TIMEOUT_MS: u64 = 30_000;
+/// Largest control message we take in one piece.
+const MAX_CONTROL_TRANSFER: u32 = 4096;
+/// The bearer's MBIM session.
+const SESSION: u32 = 0;
+/// MTU when the network does not say.
+const DEFAULT_MTU: usize = 1500;
+/// Received packets held for the stack.
+const RX_QUEUE: usize = 256;
+
+// ─── ioctl interface ──────────────────────────────────────────────────────────
+
+/// Signal strength (query): RSSI as 3GPP TS 27.007 codes it, 0-31, or 99
+/// if unknown.
+pub const MODEM_IOCTL_SIGNAL:       u32 = 0x4D01;
+/// Registration state (query); see `Registration`.
+pub const MODEM_IOCTL_REGISTRATION: u32 = 0x4D02;
+/// Tear down the bearer.
+pub const MODEM_IOCTL_DISCONNECT:   u32 = 0x4D03;
+/// Detach from packet service, tearing down the bearer.
+pub const MODEM_IOCTL_DETACH:       u32 = 0x4D04;
+
+// ─── transport ────────────────────────────────────────────────────────────────
+
+/// How MBIM reaches the modem: on USB, encapsulated commands on the
+/// control endpoint and transfer blocks on the bulk endpoints.
+pub trait ModemTransport: Send {
+    fn send_control(&mut self, msg: &[u8]) -> Result<(), &'static str>;
+    /// Next control message (or fragment) from the modem, if any.
+    fn recv_control(&mut self) -> Option<Vec<u8>>;
+    fn send_data(&mut self, ntb: &[u8]) -> Result<(), &'static str>;
+    /// Next received transfer block, if any.
+    fn recv_data(&mut self) -> Option<Vec<u8>>;
+}
+
+// ─── types ────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sim {
+    /// Not yet initialised, or not yet asked.
+    Unknown,
+    Ready,
+    Absent,
+    /// Rejected, failed, or not activated by the operator.
+    Unusable,
+    /// Needs its PIN or PUK; see `PinStatus`.
+    Locked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinKind {
+    Pin,
+    /// Blocked after too many wrong PINs; the PUK sets a new one.
+    Puk,
+    /// Another lock (a network or device lock, say).
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PinStatus {
+    /// The code the SIM wants, if it wants one.
+    pub required: Option<PinKind>,
+    /// Tries left before the SIM blocks (or, for the PUK, is lost).
+    pub attempts: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registration {
+    Unknown,
+    Deregistered,
+    Searching,
+    Home,
+    Roaming,
+    Denied,
+}
+
+/// The best radio access the network offers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radio {
+    Unknown,
+    Gsm,
+    Umts,
+    Lte,
+    /// 5G alongside an LTE anchor.
+    Nr5gNsa,
+    Nr5gSa,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Signal {
+    /// Received signal strength in dBm, if known.
+    pub rssi:       Option<i16>,
+    /// Bit error rate class 0-7, if known.
+    pub error_rate: Option<u8>,
+}
+
+impl Signal {
+    fn from_coded(rssi: u32, error_rate: u32) -> Signal {
+        Signal {
+            rssi:       (rssi <= 31).then_some(-113 + 2 * rssi as i16),
+            error_rate: (error_rate <= 7).then_some(error_rate as u8),
+        }
+    }
+
+    /// Signal bars, 0-4.
+    pub fn bars(&self) -> u8 {
+        match self.rssi {
+            Some(r) if r >= -73  => 4,
+            Some(r) if r >= -83  => 3,
+            Some(r) if r >= -93  => 2,
+            Some(r) if r >= -109 => 1,
+            _                    => 0,
+        }
+    }
+}
+
+/// The data bearer and the addressing the network gave it.
+#[derive(Debug, Clone)]
+pub struct Bearer {
+    pub apn:     String,
+    /// Address and prefix length.
+    pub addr:    (Ipv4Addr, u8),
+    pub gateway: Option<Ipv4Addr>,
+    pub dns:     Vec<Ipv4Addr>,
+    pub addr6:   Option<(Ipv6Addr, u8)>,
+    pub mtu:     usize,
+}
+
+/// The modem's state as reported to callers.
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub sim:          Sim,
+    pub pin:          Option<PinStatus>,
+    pub registration: Registration,
+    /// The network's name.
+    pub provider:     String,
+    pub radio:        Radio,
+    pub attached:     bool,
+    pub bearer:       Option<Bearer>,
+    pub signal:       Option<Signal>,
+    /// Why the bearer was lost, if the network dropped it.
+    pub error:        Option<&'static str>,
+}
+
+fn sim_state(ready: u32) -> Sim {
+    match ready {
+        1     => Sim::Ready,
+        2     => Sim::Absent,
+        3..=5 => Sim::Unusable,
+        6     => Sim::Locked,
+        _     => Sim::Unknown,
+    }
+}
+
+fn registration(state: u32) -> Registration {
+    match state {
+        1     => Registration::Deregistered,
+        2     => Registration::Searching,
+        3     => Registration::Home,
+        4 | 5 => Registration::Roaming,
+        6     => Registration::Denied,
+        _     => Registration::Unknown,
+    }
+}
+
+/// The best of a set of MBIM data classes.
+fn radio(classes: u32) -> Radio {
+    match classes {
+        c if c & 0x80 != 0 => Radio::Nr5gSa,
+        c if c & 0x40 != 0 => Radio::Nr5gNsa,
+        c if c & 0x20 != 0 => Radio::Lte,
+        c if c & 0x1C != 0 => Radio::Umts,
+        c if c & 0x03 != 0 => Radio::Gsm,
+        _                  => Radio::Unknown,
+    }
+}
+
+// ─── modem ────────────────────────────────────────────────────────────────────
+
+struct Modem {
+    transport: Box<dyn ModemTransport>,
+    /// The network interface, once attached.
+    index:     Option<usize>,
+    next_tid:  u32,
+    defrag:    mbim::Defrag,
+    seq:       u16,
+    status:    Status,
+    /// The bearer the interface is configured for.
+    applied:   Option<Bearer>,
+    rx:        VecDeque<Vec<u8>>,
+}
+
+static MODEM: Mutex<Option<Modem>> = Mutex::new(None);
+
+impl Modem {
+    fn tid(&mut self) -> u32 {
+        self.next_tid = self.next_tid.wrapping_add(1).max(1);
+        self.next_tid
+    }
+
+    /// Next whole message from the modem.
+    fn next_message(&mut self) -> Option<Message> {
+        while let Some(m) = self.transport.recv_control() {
+            if let Some(m) = self.defrag.push(&m).and_then(|m| mbim::parse(&m)) { return Some(m); }
+        }
+        None
+    }
+
+    /// Wait up to `timeout` for the answer to message `tid`, taking in
+    /// indications meanwhile.
+    fn wait(&mut self, tid: u32, timeout: u64) -> Result<Message, &'static str> {
+        let deadline = crate::arch::uptime_millis() + timeout;
+        while crate::arch::uptime_millis() < deadline {
+            while let Some(m) = self.next_message() {
+                match m {
+                    Message::Indication { service, cid, info } => self.indication(service, cid, &info),
+                    Message::Error { tid: t, .. } if t == tid => return Err("modem rejected the message"),
+                    Message::OpenDone { tid: t, .. } | Message::CloseDone { tid: t, .. } | Message::CommandDone { tid: t, .. }
+                        if t == tid => return Ok(m),
+                    _ => {}
+                }
+            }
+            core::hint::spin_loop();
+        }
+        Err("modem timed out")
+    }
+
+    /// Issue a Basic Connect command; returns its status and information
+    /// buffer.
+    fn transact(&mut self, cid: u32, set: bool, info: &[u8], timeout: u64) -> Result<(u32, Vec<u8>), &'static str> {
+        let tid = self.tid();
+        self.transport.send_control(&mbim::command(tid, cid, set, info))?;
+        match self.wait(tid, timeout)? {
+            Message::CommandDone { status, info, .. } => Ok((status, info)),
+            _ => Err("unexpected reply from modem"),
+        }
+    }
+
+    fn command(&mut self, cid: u32, set: bool, info: &[u8], timeout: u64) -> Result<Vec<u8>, &'static str> {
+        let (status, info) = self.transact(cid, set, info, timeout)?;
+        if status != mbim::STATUS_SUCCESS { return Err(mbim::status_error(status)); }
+        Ok(info)
+    }
+
+    fn query(&mut self, cid: u32) -> Result<Vec<u8>, &'static str> {
+        self.command(cid, false, &[], COMMAND_TIMEOUT_MS)
+    }
+
+    /// Open the control channel, turn the radio on and learn the
+    /// modem's state.
+    fn bring_up(&mut self) -> Result<(), &'static str> {
+        let tid = self.tid();
+        self.transport.send_control(&mbim::open(tid, MAX_CONTROL_TRANSFER))?;
+        match self.wait(tid, COMMAND_TIMEOUT_MS)? {
+            Message::OpenDone { status: mbim::STATUS_SUCCESS, .. } => {}
+            _ => return Err("modem refused to open"),
+        }
+        let _ = self.command(mbim::CID_RADIO_STATE, true, &mbim::radio_state(true), COMMAND_TIMEOUT_MS);
+        self.refresh();
+        Ok(())
+    }
+
+    /// Ask the modem everything it reports by indication.  A query the
+    /// SIM's state makes moot (a PIN query with no SIM) just fails.
+    fn refresh(&mut self) {
+        for cid in [mbim::CID_SUBSCRIBER_READY, mbim::CID_PIN, mbim::CID_REGISTER_STATE, mbim::CID_PACKET_SERVICE, mbim::CID_SIGNAL_STATE] {
+            if let Ok(info) = self.query(cid) { self.update(cid, &info); }
+        }
+    }
+
+    fn indication(&mut self, service: [u8; 16], cid: u32, info: &[u8]) {
+        if service == mbim::BASIC_CONNECT { self.update(cid, info); }
+    }
+
+    /// Take in what a response or indication says.
+    fn update(&mut self, cid: u32, info: &[u8]) {
+        let s = &mut self.status;
+        match cid {
+            mbim::CID_SUBSCRIBER_READY => if let Some(r) = mbim::subscriber_ready(info) { s.sim = sim_state(r); },
+            mbim::CID_PIN => if let Some((kind, locked, attempts)) = mbim::pin_info(info) {
+                let required = match kind {
+                    _ if !locked           => None,
+                    mbim::PIN_TYPE_NONE    => None,
+                    mbim::PIN_TYPE_PIN1    => Some(PinKind::Pin),
+                    mbim::PIN_TYPE_PUK1    => Some(PinKind::Puk),
+                    _                      => Some(PinKind::Other),
+                };
+                s.pin = Some(PinStatus { required, attempts });
+            },
+            mbim::CID_REGISTER_STATE => if let Some((state, provider)) = mbim::register_state(info) {
+                s.registration = registration(state);
+                s.provider = provider;
+            },
+            mbim::CID_PACKET_SERVICE => if let Some((state, classes)) = mbim::packet_state(info) {
+                s.attached = state == mbim::PACKET_ATTACHED;
+                s.radio = radio(classes);
+                if !s.attached { self.lost("packet service detached"); }
+            },
+            mbim::CID_SIGNAL_STATE => if let Some((rssi, ber)) = mbim::signal_state(info) {
+                s.signal = Some(Signal::from_coded(rssi, ber));
+            },
+            mbim::CID_CONNECT => if let Some((session, state)) = mbim::connect_info(info) {
+                if session == SESSION && state == mbim::DEACTIVATED { self.lost("bearer dropped by the network"); }
+            },
+            _ => {}
+        }
+    }
+
+    /// The bearer went away without our asking.
+    fn lost(&mut self, reason: &'static str) {
+        if self.status.bearer.take().is_none() { return; }
+        self.status.error = Some(reason);
+        crate::println!("  [modem] wwan0: {}", reason);
+        crate::process::defer(bearer_changed);
+    }
+
+    fn ready(&self) -> Result<(), &'static str> {
+        match self.status.sim {
+            Sim::Ready    => Ok(()),
+            Sim::Absent   => Err("no SIM"),
+            Sim::Locked   => Err("SIM locked"),
+            Sim::Unusable => Err("SIM unusable"),
+            Sim::Unknown  => Err("SIM not ready"),
+        }
+    }
+
+    /// Enter a PIN, or a PUK and the new PIN it sets.  The SIM's answer,
+    /// with the tries left, is kept either way.
+    fn enter_pin(&mut self, pin_type: u32, code: &str, new_pin: &str) -> Result<(), &'static str> {
+        let mut info = mbim::enter_pin(pin_type, code, new_pin);
+        let r = self.transact(mbim::CID_PIN, true, &info, COMMAND_TIMEOUT_MS);
+        crate::crypto::wipe(&mut info);
+        let (status, reply) = r?;
+        self.update(mbim::CID_PIN, &reply);
+        match status {
+            mbim::STATUS_SUCCESS => {}
+            mbim::STATUS_FAILURE => return Err(if pin_type == mbim::PIN_TYPE_PUK1 { "wrong PUK" } else { "wrong PIN" }),
+            s                    => return Err(mbim::status_error(s)),
+        }
+        if let Ok(info) = self.query(mbim::CID_SUBSCRIBER_READY) { self.update(mbim::CID_SUBSCRIBER_READY, &info); }
+        Ok(())
+    }
+
+    fn packet_service(&mut self, attach: bool) -> Result<(), &'static str> {
+        if attach {
+            self.ready()?;
+            if self.status.registration == Registration::Deregistered {
+                self.command(mbim::CID_REGISTER_STATE, true, &mbim::register_automatic(), NETWORK_TIMEOUT_MS)?;
+            }
+        } else {
+            self.disconnect()?;
+        }
+        let info = self.command(mbim::CID_PACKET_SERVICE, true, &mbim::packet_service(attach), NETWORK_TIMEOUT_MS)?;
+        self.update(mbim::CID_PACKET_SERVICE, &info);
+        if self.status.attached != attach { return Err(if attach { "attach refused" } else { "detach refused" }); }
+        Ok(())
+    }
+
+    /// Bring the bearer up on `apn`, attaching first if need be.
+    fn connect(&mut self, apn: &str) -> Result<(), &'static str> {
+        if self.status.bearer.as_ref().is_some_and(|b| b.apn == apn) { return Ok(()); }
+        self.disconnect()?;
+        if !self.status.attached { self.packet_service(true)?; }
+        let info = self.command(mbim::CID_CONNECT, true, &mbim::connect(SESSION, true, apn), NETWORK_TIMEOUT_MS)?;
+        if mbim::connect_info(&info).map(|c| c.1) != Some(mbim::ACTIVATED) { return Err("bearer activation refused"); }
+
+        let config = self.command(mbim::CID_IP_CONFIGURATION, false, &mbim::ip_configuration(SESSION), COMMAND_TIMEOUT_MS)
+            .ok().and_then(|info| mbim::ip_config(&info));
+        let Some((config, (addr, prefix))) = config.and_then(|c| c.v4.map(|a| (c, a))) else {
+            let _ = self.command(mbim::CID_CONNECT, true, &mbim::connect(SESSION, false, ""), NETWORK_TIMEOUT_MS);
+            return Err("network gave no IPv4 address");
+        };
+        self.status.bearer = Some(Bearer {
+            apn:     String::from(apn),
+            addr:    (Ipv4Addr(addr), prefix),
+            gateway: config.gateway.map(Ipv4Addr),
+            dns:     config.dns.into_iter().map(Ipv4Addr).collect(),
+            addr6:   config.v6.map(|(a, p)| (Ipv6Addr(a), p)),
+            mtu:     config.mtu.map_or(DEFAULT_MTU, |m| m as usize),
+        });
+        self.status.error = None;
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<(), &'static str> {
+        if self.status.bearer.is_none() { return Ok(()); }
+        self.command(mbim::CID_CONNECT, true, &mbim::connect(SESSION, false, ""), NETWORK_TIMEOUT_MS)?;
+        self.status.bearer = None;
+        self.rx.clear();
+        Ok(())
+    }
+
+    fn signal(&mut self) -> Result<Signal, &'static str> {
+        let info = self.query(mbim::CID_SIGNAL_STATE)?;
+        self.update(mbim::CID_SIGNAL_STATE, &info);
+        self.status.signal.ok_or("no signal report")
+    }
+
+    /// Take in indications and received packets.
+    fn poll(&mut self) {
+        while let Some(m) = self.next_message() {
+            if let Message::Indication { service, cid, info } = m { self.indication(service, cid, &info); }
+        }
+        while let Some(block) = self.transport.recv_data() {
+            if self.status.bearer.is_none() { continue; }
+            for p in mbim::ntb_packets(&block, SESSION as u8) {
+                if self.rx.len() < RX_QUEUE { self.rx.push_back(p); }
+            }
+        }
+    }
+}
+
+/// Configure `wwan0` for the bearer as it now is, or strip it if the
+/// bearer is gone.
+fn bearer_changed() {
+    let Some((index, bearer, old)) = with_modem(|m| {
+        let old = core::mem::replace(&mut m.applied, m.status.bearer.clone());
+        m.index.map(|i| (i, m.status.bearer.clone(), old))
+    }).ok().flatten() else { return };
+    if let Some((a, _)) = old.and_then(|b| b.addr6) { let _ = net::ipv6::remove_address(index, a); }
+    let r = match &bearer {
+        Some(b) => net::configure(index, b.addr.0, b.addr.1, b.gateway)
+            .and_then(|_| net::set_dns(index, &b.dns))
+            .and_then(|_| b.addr6.map_or(Ok(()), |(a, p)| net::ipv6::add_address(index, a, p))),
+        None => net::deconfigure(index),
+    };
+    if let Err(e) = r { crate::println!("  [modem] wwan0: {}", e); }
+}
+
+fn with_modem<R>(f: impl FnOnce(&mut Modem) -> R) -> Result<R, &'static str> {
+    MODEM.lock().as_mut().map(f).ok_or("no modem")
+}
+
+// ─── network interface ────────────────────────────────────────────────────────
+
+/// `wwan0` as the stack sees it.
+struct Wwan;
+
+impl NetDevice for Wwan {
+    fn mac(&self) -> Option<MacAddr> {
+        None
+    }
+
+    fn mtu(&self) -> usize {
+        MODEM.lock().as_ref().and_then(|m| m.status.bearer.as_ref().map(|b| b.mtu)).unwrap_or(DEFAULT_MTU)
+    }
+
+    fn transmit(&mut self, packet: &[u8]) -> Result<(), &'static str> {
+        with_modem(|m| {
+            if m.status.bearer.is_none() { return Err("no data bearer"); }
+            m.seq = m.seq.wrapping_add(1);
+            let block = mbim::ntb(m.seq, SESSION as u8, packet);
+            m.transport.send_data(&block)
+        })?
+    }
+
+    /// The stack polls this every tick, which also takes in the
+    /// modem's indications.
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        with_modem(|m| {
+            m.poll();
+            m.rx.pop_front()
+        }).ok().flatten()
+    }
+}
+
+// ─── device driver ────────────────────────────────────────────────────────────
+
+/// `wwan0` in the device registry, for control.  Data moves over the
+/// network interface, not read and write.
+pub struct ModemDriver;
+
+impl Driver for ModemDriver {
+    fn name(&self) -> &'static str { "mbim" }
+
+    fn read(&mut self, _dev: &Device, _cap: &Capability, _buf: &mut [u8]) -> Result<usize, &'static str> {
+        Err("use the wwan0 network interface")
+    }
+
+    fn write(&mut self, _dev: &Device, _cap: &Capability, _buf: &[u8]) -> Result<usize, &'static str> {
+        Err("use the wwan0 network interface")
+    }
+
+    fn ioctl(&mut self, dev: &Device, cap: &Capability, cmd: u32, _arg: usize) -> Result<usize, &'static str> {
+        check_access(dev, cap, self.ioctl_permissions(cmd))?;
+        let r = match cmd {
+            MODEM_IOCTL_SIGNAL       => with_modem(|m| m.signal().map(|s| s.rssi.map_or(99, |r| ((r + 113) / 2) as usize)))?,
+            MODEM_IOCTL_REGISTRATION => with_modem(|m| { m.poll(); m.status.registration as usize }),
+            MODEM_IOCTL_DISCONNECT   => with_modem(Modem::disconnect)?.map(|_| 0),
+            MODEM_IOCTL_DETACH       => with_modem(|m| m.packet_service(false))?.map(|_| 0),
+            _                        => Err("unsupported ioctl"),
+        };
+        if matches!(cmd, MODEM_IOCTL_DISCONNECT | MODEM_IOCTL_DETACH) { bearer_changed(); }
+        r
+    }
+
+    fn ioctl_permissions(&self, cmd: u32) -> Permissions {
+        match cmd {
+            MODEM_IOCTL_SIGNAL | MODEM_IOCTL_REGISTRATION => Permissions::READ,
+            _                                             => Permissions::CONTROL,
+        }
+    }
+}
+
+/// Bring up the modem on `transport` and register it as `wwan0`, both
+/// network interface and device.
+pub fn attach(transport: Box<dyn ModemTransport>) -> Result<DeviceId, &'static str> {
+    if MODEM.lock().is_some() { return Err("modem already attached"); }
+    let mut modem = Modem {
+        transport, index: None, next_tid: 0, defrag: mbim::Defrag::default(), seq: 0,
+        status: Status {
+            sim: Sim::Unknown, pin: None, registration: Registration::Unknown, provider: String::new(),
+            radio: Radio::Unknown, attached: false, bearer: None, signal: None, error: None,
+        },
+        applied: None, rx: VecDeque::new(),
+    };
+    modem.bring_up()?;
+    *MODEM.lock() = Some(modem);
+    // Up from the start, so the stack polls it; it has no address until
+    // a bearer is
+    let index = net::attach("wwan0", Box::new(Wwan));
+    with_modem(|m| m.index = Some(index))?;
+    net::set_up(index, true)?;
+    driver::register("wwan0", DeviceClass::Modem, Box::new(ModemDriver))
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+fn authorize(cap: &Capability, perms: Permissions) -> Result<(), &'static str> {
+    let dev = driver::find_device("wwan0").ok_or("no modem")?;
+    check_access(&dev, cap, perms)
+}
+
+fn valid_code(code: &str, lengths: core::ops::RangeInclusive<usize>) -> bool {
+    lengths.contains(&code.len()) && code.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Unlock the SIM with its PIN.
+pub fn unlock(cap: &Capability, pin: &str) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    if !valid_code(pin, 4..=8) { return Err("a PIN is 4 to 8 digits"); }
+    with_modem(|m| m.enter_pin(mbim::PIN_TYPE_PIN1, pin, ""))?
+}
+
+/// Unblock the SIM with its PUK, setting `new_pin`.
+pub fn unblock(cap: &Capability, puk: &str, new_pin: &str) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    if !valid_code(puk, 8..=8) { return Err("a PUK is 8 digits"); }
+    if !valid_code(new_pin, 4..=8) { return Err("a PIN is 4 to 8 digits"); }
+    with_modem(|m| m.enter_pin(mbim::PIN_TYPE_PUK1, puk, new_pin))?
+}
+
+/// Attach to packet service, registering first if need be.
+pub fn attach_packet(cap: &Capability) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    with_modem(|m| m.packet_service(true))?
+}
+
+/// Detach from packet service, tearing down the bearer.
+pub fn detach_packet(cap: &Capability) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    let r = with_modem(|m| m.packet_service(false))?;
+    bearer_changed();
+    r
+}
+
+/// Bring the data bearer up on `apn` and configure `wwan0` for it.
+pub fn connect(cap: &Capability, apn: &str) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    if apn.len() > 100 { return Err("APN too long"); }
+    let r = with_modem(|m| m.connect(apn))?;
+    bearer_changed();
+    r
+}
+
+pub fn disconnect(cap: &Capability) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    let r = with_modem(Modem::disconnect)?;
+    bearer_changed();
+    r
+}
+
+/// Ask the modem for the signal quality now.
+pub fn signal(cap: &Capability) -> Result<Signal, &'static str> {
+    authorize(cap, Permissions::READ)?;
+    with_modem(Modem::signal)?
+}
+
+/// The state last reported, without asking the modem.
+pub fn status() -> Option<Status> {
+    MODEM.lock().as_ref().map(|m| m.status.clone())
+}
@@ -0,0 +1,238 @@
+//! SurakshaOS Memory Tagging
+//! ARM MTE, for an ARMv9 port.  Every 16-byte granule of memory carries a
+//! 4-bit tag, and so does the top byte of every pointer; an access whose
+//! pointer tag differs from the memory's faults.  An allocation gets a
+//! fresh random tag, different from its neighbours' (spatial bugs), and
+//! is retagged when freed (temporal bugs), so a dangling or overflowing
+//! pointer almost always holds the wrong one.
+//!
+//! Each process checks tags in one of three modes: off; sync, where the
+//! faulting access traps precisely and the process is killed; or async,
+//! where the CPU only records that a fault happened and the kernel finds
+//! out at its next entry — cheap enough to leave on in production.  Apps
+//! run with DEFAULT_MODE unless `set_mode` says otherwise.  Faults are
+//! reported to the security monitor either way.
+//!
+//! Tag 0 is left for untagged memory.  The tag operations themselves are
+//! only compiled for aarch64; on this RISC-V kernel `active` is false and
+//! the allocator hooks do nothing.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use crate::process::ProcessId;
+use crate::security::{self, SecurityEvent};
+
+/// Bytes covered by one tag.
+pub const GRANULE:   usize = 16;
+/// Where a pointer's tag sits.
+const TAG_SHIFT:     u32   = 56;
+const TAG_MASK:      usize = 0xf << TAG_SHIFT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Off,
+    Sync,
+    Async,
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Off   => "off",
+            Mode::Sync  => "sync",
+            Mode::Async => "async",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Mode> {
+        match s {
+            "off"   => Some(Mode::Off),
+            "sync"  => Some(Mode::Sync),
+            "async" => Some(Mode::Async),
+            _ => None,
+        }
+    }
+}
+
+/// What a process runs with unless told otherwise.
+pub const DEFAULT_MODE: Mode = Mode::Async;
+
+/// Whether the CPU has MTE and the kernel tags its heap.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+/// Processes whose mode is not DEFAULT_MODE.
+static MODES:  Mutex<Vec<(ProcessId, Mode)>> = Mutex::new(Vec::new());
+
+// ─── hardware ─────────────────────────────────────────────────────────────────
+
+#[cfg(target_arch = "aarch64")]
+mod hw {
+    use super::*;
+
+    /// ID_AA64PFR1_EL1.MTE >= 2: tag checks, not just the instructions.
+    pub fn present() -> bool {
+        let pfr1: usize;
+        unsafe { core::arch::asm!("mrs {}, id_aa64pfr1_el1", out(reg) pfr1) };
+        (pfr1 >> 8) & 0xf >= 2
+    }
+
+    /// The tag of the granule at `addr`.
+    pub fn load_tag(addr: usize) -> u8 {
+        let mut p = addr;
+        unsafe { core::arch::asm!(".arch_extension memtag", "ldg {0}, [{0}]", inout(reg) p) };
+        tag_of(p)
+    }
+
+    pub unsafe fn set_tags(ptr: usize, len: usize) {
+        for g in (ptr..ptr + len).step_by(GRANULE) {
+            core::arch::asm!(".arch_extension memtag", "stg {0}, [{0}]", in(reg) g);
+        }
+    }
+
+    /// SCTLR_EL1.TCF0: how EL0 tag faults are raised.
+    pub fn set_user_mode(mode: Mode) {
+        let tcf0 = match mode { Mode::Off => 0, Mode::Sync => 1, Mode::Async => 2 };
+        unsafe {
+            let mut sctlr: usize;
+            core::arch::asm!("mrs {}, sctlr_el1", out(reg) sctlr);
+            sctlr = (sctlr & !(3 << 38)) | tcf0 << 38;
+            core::arch::asm!("msr sctlr_el1, {}", "isb", in(reg) sctlr);
+        }
+    }
+
+    /// TFSRE0_EL1.TF0: an async fault at EL0 since the last look.  Cleared.
+    pub fn take_async_fault() -> bool {
+        let tfsr: usize;
+        unsafe {
+            core::arch::asm!("mrs {}, tfsre0_el1", out(reg) tfsr);
+            core::arch::asm!("msr tfsre0_el1, xzr");
+        }
+        tfsr & 1 != 0
+    }
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+mod hw {
+    use super::*;
+
+    pub fn present() -> bool { false }
+
+    pub fn load_tag(_addr: usize) -> u8 { 0 }
+
+    pub unsafe fn set_tags(_ptr: usize, _len: usize) {}
+
+    pub fn set_user_mode(_mode: Mode) {}
+
+    pub fn take_async_fault() -> bool { false }
+}
+
+pub fn init() {
+    ACTIVE.store(hw::present(), Ordering::Relaxed);
+    if active() { crate::audit::note("mte", "memory tagging on"); }
+}
+
+pub fn active() -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+// ─── tags ─────────────────────────────────────────────────────────────────────
+
+pub fn tag_of(ptr: usize) -> u8 {
+    ((ptr & TAG_MASK) >> TAG_SHIFT) as u8
+}
+
+pub fn with_tag(ptr: usize, tag: u8) -> usize {
+    (ptr & !TAG_MASK) | ((tag as usize & 0xf) << TAG_SHIFT)
+}
+
+/// A random tag from 1 to 15, not one of `exclude`'s bits.
+pub fn choose_tag(exclude: u16) -> u8 {
+    let allowed = !(exclude | 1) & 0xfffe;
+    if allowed == 0 { return 1; }
+    let n = allowed.count_ones();
+    let mut pick = crate::entropy::next_u32() % n;
+    let mut tag = 1;
+    loop {
+        if allowed & (1 << tag) != 0 {
+            if pick == 0 { return tag; }
+            pick -= 1;
+        }
+        tag += 1;
+    }
+}
+
+/// Tag `len` bytes at `ptr` with a tag unlike `ptr`'s current one and the
+/// granules either side, and return `ptr` carrying it.  Nothing is done
+/// (and `ptr` returned as is) unless tagging is on.
+pub fn tag_region(ptr: *mut u8, len: usize) -> *mut u8 {
+    if !active() || ptr.is_null() { return ptr; }
+    let p = ptr as usize;
+    let end = p + len.next_multiple_of(GRANULE);
+    let exclude = 1u16 << tag_of(p) | 1 << hw::load_tag(p.wrapping_sub(GRANULE)) | 1 << hw::load_tag(end);
+    let tagged = with_tag(p, choose_tag(exclude));
+    unsafe { hw::set_tags(tagged, len.next_multiple_of(GRANULE)) };
+    tagged as *mut u8
+}
+
+/// Give `len` bytes at `ptr` back tag 0, the heap's own, and return `ptr`
+/// without a tag.  Freed memory gets this, so any pointer still carrying
+/// the allocation's tag faults.
+pub fn untag_region(ptr: *mut u8, len: usize) -> *mut u8 {
+    if !active() { return ptr; }
+    let p = with_tag(ptr as usize, 0);
+    unsafe { hw::set_tags(p, len.next_multiple_of(GRANULE)) };
+    p as *mut u8
+}
+
+/// Memory newly mapped for `pid` with tagging asked for: clear its tags so
+/// the process's allocator starts from untagged memory.  Refused if the
+/// process runs with tag checks off, where tags would be meaningless.
+pub fn prepare_mapping(pid: ProcessId, base: usize, len: usize) -> Result<(), &'static str> {
+    if !active() { return Err("memory tagging not supported"); }
+    if mode_of(pid) == Mode::Off { return Err("tag checks are off for this process"); }
+    untag_region(base as *mut u8, len);
+    Ok(())
+}
+
+// ─── per-process mode ─────────────────────────────────────────────────────────
+
+pub fn mode_of(pid: ProcessId) -> Mode {
+    MODES.lock().iter().find(|(p, _)| *p == pid).map_or(DEFAULT_MODE, |&(_, m)| m)
+}
+
+pub fn set_mode(pid: ProcessId, mode: Mode) {
+    let mut modes = MODES.lock();
+    modes.retain(|(p, _)| *p != pid);
+    if mode != DEFAULT_MODE { modes.push((pid, mode)); }
+}
+
+/// Processes not running with DEFAULT_MODE.
+pub fn modes() -> Vec<(ProcessId, Mode)> {
+    MODES.lock().clone()
+}
+
+pub fn forget(pid: ProcessId) {
+    MODES.lock().retain(|(p, _)| *p != pid);
+}
+
+/// Load `pid`'s mode before it runs.
+pub fn switch_to(pid: ProcessId) {
+    if active() { hw::set_user_mode(mode_of(pid)); }
+}
+
+// ─── faults ───────────────────────────────────────────────────────────────────
+
+/// A tag check fault in `pid`: at `addr` for a sync fault, unknown for an
+/// async one.  Reported either way; a sync fault also kills the process,
+/// since the access cannot go ahead.
+pub fn tag_fault(pid: ProcessId, addr: Option<usize>) {
+    security::report(SecurityEvent::TagCheckFault { pid, addr });
+    if addr.is_some() { let _ = crate::process::kill(pid); }
+}
+
+/// On kernel entry from `pid`: report an async fault it made since the last
+/// entry, if any.
+pub fn check_async(pid: ProcessId) {
+    if active() && mode_of(pid) == Mode::Async && hw::take_async_fault() { tag_fault(pid, None); }
+}
@@ -0,0 +1,270 @@
+//! Traffic Accounting
+//! Bytes and packets each app sends and receives, per interface, so the
+//! data-usage UI can tell Wi-Fi from cellular.  Usage is kept as running
+//! totals and in per-day buckets (UTC days by the real-time clock) for
+//! the last `HISTORY_DAYS`; both are read through `SYS_NET_STATS`.
+//!
+//! Counting happens where the stack knows whose traffic it is: TCP
+//! segments (headers and retransmissions included) and UDP and ICMP
+//! datagrams, as IP packets.  Under the smoltcp backend, traffic on the
+//! interface it drives is counted as the payload apps pass through the
+//! socket API.  Traffic to our own addresses is local and not counted.
+//!
+//! A data cap limits an app to so many bytes over a rolling window of
+//! days, on one interface or on all of them.  The firewall enforces it:
+//! once reached, the app's new connections and datagrams over the capped
+//! interface are refused and its TCP connections there are cut, until
+//! old days leave the window or the cap is lifted.  Setting caps needs
+//! the Network capability with CONTROL rights.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::IpAddr;
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::process::ProcessId;
+
+/// Days of per-day usage kept.
+pub const HISTORY_DAYS: u64 = 62;
+
+const NS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counters {
+    pub rx_bytes:   u64,
+    pub rx_packets: u64,
+    pub tx_bytes:   u64,
+    pub tx_packets: u64,
+}
+
+impl Counters {
+    pub fn bytes(&self) -> u64 {
+        self.rx_bytes + self.tx_bytes
+    }
+
+    fn add(&mut self, dir: Dir, bytes: usize) {
+        match dir {
+            Dir::Rx => { self.rx_bytes += bytes as u64; self.rx_packets += 1; }
+            Dir::Tx => { self.tx_bytes += bytes as u64; self.tx_packets += 1; }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dir {
+    Rx,
+    Tx,
+}
+
+/// One app's usage of one interface.
+struct Entry {
+    pid:   ProcessId,
+    index: usize,
+    total: Counters,
+    /// (day, usage), oldest first.
+    days:  Vec<(u64, Counters)>,
+}
+
+/// A data cap as reported to callers.
+#[derive(Debug, Clone, Copy)]
+pub struct DataCap {
+    pub pid:   ProcessId,
+    /// The interface it applies to; all of them if None.
+    pub index: Option<usize>,
+    pub limit: u64,
+    /// Length of the rolling window, today included.
+    pub days:  u32,
+    /// Bytes used within the window.
+    pub used:  u64,
+}
+
+struct Cap {
+    pid:     ProcessId,
+    index:   Option<usize>,
+    limit:   u64,
+    days:    u32,
+    reached: bool,
+}
+
+struct Accounting {
+    entries: Vec<Entry>,
+    caps:    Vec<Cap>,
+}
+
+static ACCOUNTING: Mutex<Accounting> = Mutex::new(Accounting { entries: Vec::new(), caps: Vec::new() });
+
+fn today() -> u64 {
+    crate::alarm::rtc_now_ns() / NS_PER_DAY
+}
+
+impl Accounting {
+    /// Bytes `pid` moved over `index` (any interface if None) in the
+    /// `days` days up to `today`.
+    fn used(&self, pid: ProcessId, index: Option<usize>, days: u32, today: u64) -> u64 {
+        let first = today.saturating_sub(days.saturating_sub(1) as u64);
+        self.entries.iter().filter(|e| e.pid == pid && index.is_none_or(|i| i == e.index))
+            .flat_map(|e| e.days.iter().filter(|d| d.0 >= first).map(|d| d.1.bytes()))
+            .sum()
+    }
+
+    fn add(&mut self, pid: ProcessId, index: usize, dir: Dir, bytes: usize) {
+        let day = today();
+        let i = match self.entries.iter().position(|e| e.pid == pid && e.index == index) {
+            Some(i) => i,
+            None    => {
+                self.entries.push(Entry { pid, index, total: Counters::default(), days: Vec::new() });
+                self.entries.len() - 1
+            }
+        };
+        let e = &mut self.entries[i];
+        e.total.add(dir, bytes);
+        if e.days.last().is_none_or(|d| d.0 != day) {
+            e.days.retain(|d| d.0 + HISTORY_DAYS > day);
+            e.days.push((day, Counters::default()));
+        }
+        if let Some(d) = e.days.last_mut() { d.1.add(dir, bytes); }
+
+        // Caps just reached cut the app's connections, once the stack is
+        // out of the path that counted
+        let mut reached = false;
+        for c in 0..self.caps.len() {
+            let cap = &self.caps[c];
+            if cap.pid != pid || cap.index.is_some_and(|i| i != index) { continue; }
+            let over = self.used(pid, cap.index, cap.days, day) >= cap.limit;
+            reached |= over && !cap.reached;
+            self.caps[c].reached = over;
+        }
+        if reached { crate::process::defer(enforce); }
+    }
+
+    fn over(&self, pid: ProcessId, index: Option<usize>) -> bool {
+        let day = today();
+        self.caps.iter().filter(|c| c.pid == pid)
+            .any(|c| c.index.is_none_or(|i| Some(i) == index) && self.used(pid, c.index, c.days, day) >= c.limit)
+    }
+}
+
+fn authorize(cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(crate::process::current_pid(), cap, CapabilityType::Network, Permissions::CONTROL)
+}
+
+/// End the TCP connections of apps over a cap on the interface they use.
+fn enforce() {
+    super::tcp::cut_off("data cap reached", |owner, local| {
+        let index = super::interface_of(local);
+        ACCOUNTING.lock().over(owner, index)
+    });
+}
+
+// ─── counting ─────────────────────────────────────────────────────────────────
+
+fn count(owner: ProcessId, local: IpAddr, remote: IpAddr, dir: Dir, bytes: usize) {
+    if remote.is_loopback() || super::is_local(remote) { return; }
+    let Some(index) = super::interface_of(local) else { return };
+    ACCOUNTING.lock().add(owner, index, dir, bytes);
+}
+
+/// `owner` sent a packet of `bytes` from our address `local` to `remote`.
+pub(super) fn sent(owner: ProcessId, local: IpAddr, remote: IpAddr, bytes: usize) {
+    count(owner, local, remote, Dir::Tx, bytes);
+}
+
+/// `owner` received a packet of `bytes` from `remote` at our address
+/// `local`.
+pub(super) fn received(owner: ProcessId, local: IpAddr, remote: IpAddr, bytes: usize) {
+    count(owner, local, remote, Dir::Rx, bytes);
+}
+
+/// `owner` passed `bytes` of payload to or from interface `index`, for a
+/// backend that only sees traffic at the socket API.
+pub(super) fn payload(owner: ProcessId, index: usize, sent: bool, bytes: usize) {
+    if bytes == 0 { return; }
+    ACCOUNTING.lock().add(owner, index, if sent { Dir::Tx } else { Dir::Rx }, bytes);
+}
+
+/// Has `owner` reached a cap on the interface traffic to `dst` leaves
+/// by: `uplink` if its namespace has one, else the routed one?
+pub(super) fn capped(owner: ProcessId, uplink: Option<usize>, dst: IpAddr) -> bool {
+    if !ACCOUNTING.lock().caps.iter().any(|c| c.pid == owner) { return false; }
+    let index = uplink.or_else(|| super::source_for(dst).and_then(super::interface_of));
+    ACCOUNTING.lock().over(owner, index)
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Cap `pid` at `limit` bytes over the last `days` days, on interface
+/// `index` or on all of them.  Replaces a cap on the same interfaces.
+pub fn set_cap(cap: &Capability, pid: ProcessId, index: Option<usize>, limit: u64, days: u32) -> Result<(), &'static str> {
+    authorize(cap)?;
+    if days == 0 || days as u64 > HISTORY_DAYS { return Err("bad cap period"); }
+    if index.is_some_and(|i| super::interface(i).is_none()) { return Err("no such interface"); }
+    let mut a = ACCOUNTING.lock();
+    a.caps.retain(|c| !(c.pid == pid && c.index == index));
+    let reached = a.used(pid, index, days, today()) >= limit;
+    a.caps.push(Cap { pid, index, limit, days, reached });
+    drop(a);
+    if reached { enforce(); }
+    Ok(())
+}
+
+pub fn clear_cap(cap: &Capability, pid: ProcessId, index: Option<usize>) -> Result<(), &'static str> {
+    authorize(cap)?;
+    let mut a = ACCOUNTING.lock();
+    let before = a.caps.len();
+    a.caps.retain(|c| !(c.pid == pid && c.index == index));
+    if a.caps.len() == before { Err("no such cap") } else { Ok(()) }
+}
+
+pub fn caps() -> Vec<DataCap> {
+    let a = ACCOUNTING.lock();
+    let day = today();
+    a.caps.iter().map(|c| DataCap {
+        pid: c.pid, index: c.index, limit: c.limit, days: c.days, used: a.used(c.pid, c.index, c.days, day),
+    }).collect()
+}
+
+/// Usage record returned by `SYS_NET_STATS`.  Plain `u64`s so it can be
+/// copied to the caller byte for byte.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct UsageRecord {
+    pub pid:   u64,
+    /// Interface index.
+    pub index: u64,
+    /// Days since 1970-01-01 (UTC); 0 in totals.
+    pub day:   u64,
+    pub usage: Counters,
+}
+
+/// Each app's running totals per interface.
+pub fn totals() -> Vec<UsageRecord> {
+    ACCOUNTING.lock().entries.iter().map(|e| UsageRecord {
+        pid: e.pid.0 as u64, index: e.index as u64, day: 0, usage: e.total,
+    }).collect()
+}
+
+/// Each app's usage per interface per day, oldest day first within each.
+pub fn daily() -> Vec<UsageRecord> {
+    let a = ACCOUNTING.lock();
+    let first = (today() + 1).saturating_sub(HISTORY_DAYS);
+    a.entries.iter().flat_map(|e| e.days.iter().filter(move |d| d.0 >= first).map(move |&(day, usage)| UsageRecord {
+        pid: e.pid.0 as u64, index: e.index as u64, day, usage,
+    })).collect()
+}
+
+/// `pid`'s usage of each interface over the last `days` days.
+pub fn usage(pid: ProcessId, days: u32) -> Vec<(usize, Counters)> {
+    let a = ACCOUNTING.lock();
+    let first = (today() + 1).saturating_sub(days as u64);
+    a.entries.iter().filter(|e| e.pid == pid).map(|e| {
+        let mut sum = Counters::default();
+        for (_, d) in e.days.iter().filter(|d| d.0 >= first) {
+            sum.rx_bytes += d.rx_bytes;
+            sum.rx_packets += d.rx_packets;
+            sum.tx_bytes += d.tx_bytes;
+            sum.tx_packets += d.tx_packets;
+        }
+        (e.index, sum)
+    }).collect()
+}
@@ -0,0 +1,73 @@
+//! ARP
+//! Resolves IPv4 next hops to Ethernet addresses (RFC 826).  Requests for
+//! our address are answered, and the sender of any ARP packet aimed at us
+//! is learned on the way; replies complete the entries the output path is
+//! waiting on.  An interface announces its address with a gratuitous ARP
+//! when it comes up or is readdressed, and another station claiming that
+//! address is reported.
+
+use alloc::vec::Vec;
+
+use super::{Interface, Ipv4Addr, MacAddr, ETHERTYPE_IP4};
+
+pub(super) const ETHERTYPE_ARP: u16 = 0x0806;
+
+const HTYPE_ETHERNET: u16   = 1;
+const OP_REQUEST:     u16   = 1;
+const OP_REPLY:       u16   = 2;
+const PACKET_LEN:     usize = 28;
+
+fn packet(op: u16, sha: MacAddr, spa: Ipv4Addr, tha: MacAddr, tpa: Ipv4Addr) -> Vec<u8> {
+    let mut p = Vec::with_capacity(PACKET_LEN);
+    p.extend_from_slice(&HTYPE_ETHERNET.to_be_bytes());
+    p.extend_from_slice(&ETHERTYPE_IP4.to_be_bytes());
+    p.extend_from_slice(&[6, 4]);
+    p.extend_from_slice(&op.to_be_bytes());
+    p.extend_from_slice(&sha.0);
+    p.extend_from_slice(&spa.0);
+    p.extend_from_slice(&tha.0);
+    p.extend_from_slice(&tpa.0);
+    p
+}
+
+/// Broadcast a request for `target`'s address.
+pub(super) fn request(i: &mut Interface, target: Ipv4Addr) {
+    let (Some(mac), Some((addr, _))) = (i.mac(), i.addr) else { return };
+    let p = packet(OP_REQUEST, mac, addr, MacAddr([0; 6]), target);
+    let _ = i.transmit_frame(MacAddr::BROADCAST, ETHERTYPE_ARP, &p);
+}
+
+/// Gratuitous ARP: tell the link our address is here, refreshing stale
+/// entries other hosts hold for it.
+pub(super) fn announce(i: &mut Interface) {
+    if let Some((addr, _)) = i.addr { request(i, addr); }
+}
+
+/// An ARP packet arrived on `i`.
+pub(super) fn input(i: &mut Interface, p: &[u8], now: u64) {
+    if p.len() < PACKET_LEN || p[..6] != [0, 1, 8, 0, 6, 4] { return; }
+    let Some(mac) = i.mac() else { return };
+    let op  = u16::from_be_bytes([p[6], p[7]]);
+    let sha = MacAddr([p[8], p[9], p[10], p[11], p[12], p[13]]);
+    let spa = Ipv4Addr([p[14], p[15], p[16], p[17]]);
+    let tpa = Ipv4Addr([p[24], p[25], p[26], p[27]]);
+    let ours = i.addr.map(|(a, _)| a);
+    if Some(spa) == ours {
+        if sha != mac { crate::println!("  [net] {}: {} is also claimed by {}", i.name, spa, sha); }
+        return;
+    }
+    if spa == Ipv4Addr::UNSPECIFIED { return; }
+    let for_us = Some(tpa) == ours;
+    for waiting in i.arp.update(spa, sha, now, for_us) {
+        let _ = i.transmit_frame(sha, ETHERTYPE_IP4, &waiting);
+    }
+    if for_us && op == OP_REQUEST {
+        let reply = packet(OP_REPLY, mac, tpa, sha, spa);
+        let _ = i.transmit_frame(sha, ETHERTYPE_ARP, &reply);
+    }
+}
+
+/// Resend due requests and expire old entries.
+pub(super) fn tick(i: &mut Interface, now: u64) {
+    for target in i.arp.tick(now) { request(i, target); }
+}
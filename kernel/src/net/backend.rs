@@ -0,0 +1,145 @@
+//! Stack Backends
+//! The TCP/IP stack behind the socket API.  The native stack (`tcp`,
+//! `udp` and the layers below them) is the default; building with the
+//! `smoltcp` feature serves sockets from smoltcp instead (see `smol`).
+//!
+//! A backend sits on the native interface layer: it may claim Ethernet
+//! interfaces as they are attached, after which their received frames go
+//! to it rather than to ARP and IP, and it sends through them.  Backend
+//! calls never block; one that cannot proceed yet fails with "would
+//! block", and `socket` waits for the stack to move.
+
+use alloc::vec::Vec;
+
+use super::tcp::{self, TcpHandle, TcpState};
+use super::udp::{self, UdpHandle};
+use super::SocketAddr;
+use crate::event::Ready;
+
+/// A TCP/IP stack the socket API can be served from.  Socket handles are
+/// the backend's own.
+pub trait Backend: Sync {
+    fn name(&self) -> &'static str;
+
+    /// Interface `index` was attached; returns whether the backend takes
+    /// it over.
+    fn claim(&self, index: usize) -> bool;
+    fn owns(&self, index: usize) -> bool;
+    /// A frame received on an interface the backend owns.
+    fn input(&self, index: usize, frame: Vec<u8>);
+    /// Process received frames and run timers.
+    fn poll(&self, now: u64);
+
+    fn tcp_connect(&self, remote: SocketAddr) -> Result<u32, &'static str>;
+    /// Listen on `port`, holding up to `backlog` connections unaccepted.
+    fn tcp_listen(&self, port: u16, backlog: usize) -> Result<u32, &'static str>;
+    /// The next established connection on listener `h`, with the peer's
+    /// address.
+    fn tcp_accept(&self, h: u32) -> Result<(u32, SocketAddr), &'static str>;
+    /// Whether the handshake is done: `Ok(false)` while still opening or
+    /// listening, an error if it failed.
+    fn tcp_connected(&self, h: u32) -> Result<bool, &'static str>;
+    fn tcp_send(&self, h: u32, data: &[u8]) -> Result<usize, &'static str>;
+    /// `Ok(0)` once the peer has closed its side.
+    fn tcp_recv(&self, h: u32, buf: &mut [u8]) -> Result<usize, &'static str>;
+    fn tcp_close(&self, h: u32) -> Result<(), &'static str>;
+    /// What a call on connection or listener `h` would find.
+    fn tcp_ready(&self, h: u32) -> Result<Ready, &'static str>;
+
+    fn udp_bind(&self, local: SocketAddr) -> Result<u32, &'static str>;
+    fn udp_send_to(&self, h: u32, data: &[u8], to: SocketAddr) -> Result<usize, &'static str>;
+    fn udp_recv_from(&self, h: u32, buf: &mut [u8]) -> Result<(usize, SocketAddr), &'static str>;
+    fn udp_close(&self, h: u32) -> Result<(), &'static str>;
+    fn udp_ready(&self, h: u32) -> Result<Ready, &'static str>;
+}
+
+/// The backend this kernel was built with.
+pub fn get() -> &'static dyn Backend {
+    #[cfg(feature = "smoltcp")]
+    { &super::smol::Smoltcp }
+    #[cfg(not(feature = "smoltcp"))]
+    { &Native }
+}
+
+// ─── native stack ─────────────────────────────────────────────────────────────
+
+/// The native stack, which drives every interface itself.
+pub struct Native;
+
+impl Backend for Native {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn claim(&self, _index: usize) -> bool {
+        false
+    }
+
+    fn owns(&self, _index: usize) -> bool {
+        false
+    }
+
+    fn input(&self, _index: usize, _frame: Vec<u8>) {}
+
+    fn poll(&self, _now: u64) {}
+
+    fn tcp_connect(&self, remote: SocketAddr) -> Result<u32, &'static str> {
+        tcp::connect(remote).map(|h| h.0)
+    }
+
+    fn tcp_listen(&self, port: u16, backlog: usize) -> Result<u32, &'static str> {
+        tcp::listen(port, backlog).map(|h| h.0)
+    }
+
+    fn tcp_accept(&self, h: u32) -> Result<(u32, SocketAddr), &'static str> {
+        tcp::accept(TcpHandle(h)).map(|(c, remote)| (c.0, remote))
+    }
+
+    fn tcp_connected(&self, h: u32) -> Result<bool, &'static str> {
+        if tcp::state(TcpHandle(h)) == Some(TcpState::Listen) { return Ok(false); }
+        // A zero timeout only looks
+        match tcp::wait_established(TcpHandle(h), 0) {
+            Ok(())           => Ok(true),
+            Err("timed out") => Ok(false),
+            Err(e)           => Err(e),
+        }
+    }
+
+    fn tcp_send(&self, h: u32, data: &[u8]) -> Result<usize, &'static str> {
+        tcp::send(TcpHandle(h), data)
+    }
+
+    fn tcp_recv(&self, h: u32, buf: &mut [u8]) -> Result<usize, &'static str> {
+        tcp::recv(TcpHandle(h), buf)
+    }
+
+    fn tcp_close(&self, h: u32) -> Result<(), &'static str> {
+        tcp::close(TcpHandle(h))
+    }
+
+    fn tcp_ready(&self, h: u32) -> Result<Ready, &'static str> {
+        tcp::readiness(TcpHandle(h))
+    }
+
+    fn udp_bind(&self, local: SocketAddr) -> Result<u32, &'static str> {
+        let h = udp::bind(local)?;
+        udp::set_nonblocking(h, true)?;
+        Ok(h.0)
+    }
+
+    fn udp_send_to(&self, h: u32, data: &[u8], to: SocketAddr) -> Result<usize, &'static str> {
+        udp::send_to(UdpHandle(h), data, to)
+    }
+
+    fn udp_recv_from(&self, h: u32, buf: &mut [u8]) -> Result<(usize, SocketAddr), &'static str> {
+        udp::recv_from(UdpHandle(h), buf, None)
+    }
+
+    fn udp_close(&self, h: u32) -> Result<(), &'static str> {
+        udp::close(UdpHandle(h))
+    }
+
+    fn udp_ready(&self, h: u32) -> Result<Ready, &'static str> {
+        udp::readiness(UdpHandle(h))
+    }
+}
@@ -0,0 +1,532 @@
+//! Packet Capture
+//! pcap-style taps for debugging the stack.  A tap copies the frames an
+//! interface sends and receives, those its filter matches and cut to its
+//! snap length, into a bounded ring whose oldest records make way.  Saved
+//! with `write_pcap`, a tap reads in tcpdump or Wireshark.
+//!
+//! Traffic is the user's, so taps are held as tightly as AI content
+//! captures: opening one needs the NetDiagnostics capability with READ
+//! rights, only the process that opened it can read or save it, the
+//! console announces when it starts and stops, and it ends by itself
+//! once the capability is revoked or expires.  Under confidentiality
+//! lockdown no tap can be opened.
+//!
+//! Filters take the core of the tcpdump language:
+//!   - `[src|dst] host <addr>`, `[src|dst] net <addr/len>`,
+//!     `[src|dst] port <n>`
+//!   - `ip`, `ip6`, `arp`, `tcp`, `udp`, `icmp`, `icmp6`
+//!   - `not`, `and`, `or` (or `!`, `&&`, `||`) and parentheses; `and`
+//!     binds tighter than `or`, and an empty filter takes everything
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+
+use super::arp::ETHERTYPE_ARP;
+use super::ipv6::PROTO_ICMPV6;
+use super::{ipv4, ipv6, IpAddr, Ipv4Addr, Ipv6Addr, ETHERTYPE_IP4, ETHERTYPE_IP6, ETH_HEADER};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::process::ProcessId;
+
+/// Record data a tap holds at most; the oldest records make way.
+pub const TAP_BYTES: usize = 256 * 1024;
+/// Taps open at once, across all processes.
+pub const MAX_TAPS: usize = 8;
+/// Snap length when none is given: whole frames.
+pub const DEFAULT_SNAPLEN: usize = 65535;
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW:      u32 = 101;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tap(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// One captured frame.
+pub struct Record {
+    /// Microseconds since boot.
+    pub time_us:  u64,
+    pub dir:      Direction,
+    /// Length on the wire; `data` may be cut short of it.
+    pub orig_len: usize,
+    pub data:     Vec<u8>,
+    ethernet:     bool,
+}
+
+impl Record {
+    /// A one-line tcpdump-style description.
+    pub fn summary(&self) -> String {
+        Fields::parse(&self.data, self.ethernet).summary()
+    }
+}
+
+/// A tap as reported to callers.
+#[derive(Debug, Clone)]
+pub struct TapInfo {
+    pub tap:     Tap,
+    pub owner:   ProcessId,
+    pub index:   usize,
+    pub snaplen: usize,
+    pub filter:  String,
+    /// Records waiting to be read.
+    pub records: usize,
+    /// Frames the filter matched.
+    pub matched: u64,
+    /// Records the ring lost before they were read.
+    pub dropped: u64,
+}
+
+struct TapState {
+    id:       u32,
+    cap:      Capability,
+    index:    usize,
+    name:     String,
+    ethernet: bool,
+    snaplen:  usize,
+    filter:   Filter,
+    expr:     String,
+    records:  VecDeque<Record>,
+    bytes:    usize,
+    matched:  u64,
+    dropped:  u64,
+}
+
+struct Taps {
+    taps:    Vec<TapState>,
+    next_id: u32,
+}
+
+static TAPS: Mutex<Taps> = Mutex::new(Taps { taps: Vec::new(), next_id: 1 });
+/// Set while any tap is open, so the packet path skips the lock when none
+/// is.
+static TAPPING: AtomicBool = AtomicBool::new(false);
+
+fn check(pid: ProcessId, cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(pid, cap, CapabilityType::NetDiagnostics, Permissions::READ)
+}
+
+fn now_us() -> u64 {
+    crate::arch::ticks_to_us(crate::arch::read_mtime())
+}
+
+impl Taps {
+    /// The caller's tap `t`, if its capability still holds.
+    fn get(&mut self, cap: &Capability, t: Tap) -> Result<&mut TapState, &'static str> {
+        let pid = crate::process::current_pid();
+        check(pid, cap)?;
+        self.taps.iter_mut().find(|s| s.id == t.0 && s.cap.owner == pid).ok_or("no such tap")
+    }
+
+    fn end(&mut self, id: u32) {
+        let Some(i) = self.taps.iter().position(|s| s.id == id) else { return };
+        let s = self.taps.remove(i);
+        crate::println!("  [net] capture {} on {} by pid {} ended", s.id, s.name, s.cap.owner.0);
+        TAPPING.store(!self.taps.is_empty(), Ordering::Relaxed);
+    }
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Open a tap on interface `index` keeping the first `snaplen` bytes of
+/// each frame `filter` matches.
+pub fn open(cap: &Capability, index: usize, snaplen: usize, filter: &str) -> Result<Tap, &'static str> {
+    let pid = crate::process::current_pid();
+    check(pid, cap)?;
+    crate::lockdown::check(crate::lockdown::Level::Confidentiality, "packet capture")?;
+    let info = super::interface(index).ok_or("no such interface")?;
+    if snaplen == 0 || snaplen > DEFAULT_SNAPLEN { return Err("bad snap length"); }
+    let parsed = Filter::parse(filter)?;
+    let mut taps = TAPS.lock();
+    if taps.taps.len() >= MAX_TAPS { return Err("too many taps"); }
+    let id = taps.next_id;
+    taps.next_id = taps.next_id.wrapping_add(1).max(1);
+    taps.taps.push(TapState {
+        id, cap: cap.clone(), index, name: info.name.clone(), ethernet: info.mac.is_some(), snaplen, filter: parsed,
+        expr: String::from(filter.trim()), records: VecDeque::new(), bytes: 0, matched: 0, dropped: 0,
+    });
+    TAPPING.store(true, Ordering::Relaxed);
+    crate::println!("  [net] capture {} on {} started by pid {}", id, info.name, pid.0);
+    Ok(Tap(id))
+}
+
+/// Close a tap; records not read are discarded.
+pub fn close(cap: &Capability, t: Tap) -> Result<(), &'static str> {
+    let mut taps = TAPS.lock();
+    let id = taps.get(cap, t)?.id;
+    taps.end(id);
+    Ok(())
+}
+
+/// Hand over the records captured so far, oldest first.
+pub fn read(cap: &Capability, t: Tap) -> Result<Vec<Record>, &'static str> {
+    let mut taps = TAPS.lock();
+    let s = taps.get(cap, t)?;
+    s.bytes = 0;
+    Ok(s.records.drain(..).collect())
+}
+
+/// Save the records captured so far to `path` as a pcap file, taking
+/// them from the tap; returns how many were written.
+pub fn write_pcap(cap: &Capability, t: Tap, path: &str) -> Result<usize, &'static str> {
+    let (records, ethernet, snaplen) = {
+        let mut taps = TAPS.lock();
+        let s = taps.get(cap, t)?;
+        s.bytes = 0;
+        (s.records.drain(..).collect::<Vec<_>>(), s.ethernet, s.snaplen)
+    };
+    let linktype = if ethernet { LINKTYPE_ETHERNET } else { LINKTYPE_RAW };
+    let mut file = Vec::with_capacity(24 + records.iter().map(|r| 16 + r.data.len()).sum::<usize>());
+    file.extend_from_slice(&0xA1B2_C3D4u32.to_le_bytes());
+    file.extend_from_slice(&2u16.to_le_bytes());
+    file.extend_from_slice(&4u16.to_le_bytes());
+    file.extend_from_slice(&0i32.to_le_bytes());
+    file.extend_from_slice(&0u32.to_le_bytes());
+    file.extend_from_slice(&(snaplen as u32).to_le_bytes());
+    file.extend_from_slice(&linktype.to_le_bytes());
+    for r in &records {
+        file.extend_from_slice(&((r.time_us / 1_000_000) as u32).to_le_bytes());
+        file.extend_from_slice(&((r.time_us % 1_000_000) as u32).to_le_bytes());
+        file.extend_from_slice(&(r.data.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(r.orig_len as u32).to_le_bytes());
+        file.extend_from_slice(&r.data);
+    }
+    crate::fs::write_file(path, &file)?;
+    Ok(records.len())
+}
+
+pub fn taps() -> Vec<TapInfo> {
+    TAPS.lock().taps.iter().map(|s| TapInfo {
+        tap: Tap(s.id), owner: s.cap.owner, index: s.index, snaplen: s.snaplen, filter: s.expr.clone(),
+        records: s.records.len(), matched: s.matched, dropped: s.dropped,
+    }).collect()
+}
+
+// ─── packet path ──────────────────────────────────────────────────────────────
+
+/// Copy a frame interface `index` sent or received into its taps.
+/// Called with the interfaces held.
+pub(super) fn tap(index: usize, dir: Direction, frame: &[u8]) {
+    if !TAPPING.load(Ordering::Relaxed) { return; }
+    let mut taps = TAPS.lock();
+    let mut fields = None;
+    for s in taps.taps.iter_mut().filter(|s| s.index == index) {
+        let f = fields.get_or_insert_with(|| Fields::parse(frame, s.ethernet));
+        if !s.filter.matches(f) { continue; }
+        s.matched += 1;
+        let data = frame[..frame.len().min(s.snaplen)].to_vec();
+        while s.bytes + data.len() > TAP_BYTES {
+            let Some(old) = s.records.pop_front() else { break };
+            s.bytes -= old.data.len();
+            s.dropped += 1;
+        }
+        s.bytes += data.len();
+        s.records.push_back(Record { time_us: now_us(), dir, orig_len: frame.len(), data, ethernet: s.ethernet });
+    }
+}
+
+/// End taps whose capability no longer holds; run once per tick.
+pub(super) fn tick() {
+    if !TAPPING.load(Ordering::Relaxed) { return; }
+    let mut taps = TAPS.lock();
+    let dead: Vec<u32> = taps.taps.iter().filter(|s| check(s.cap.owner, &s.cap).is_err()).map(|s| s.id).collect();
+    for id in dead { taps.end(id); }
+}
+
+// ─── frame fields ─────────────────────────────────────────────────────────────
+
+/// What filters look at in a frame, as far as it could be parsed.
+#[derive(Default)]
+struct Fields {
+    /// Ethertype; bare-IP links report the IP version's.
+    ethertype: u16,
+    src:       Option<IpAddr>,
+    dst:       Option<IpAddr>,
+    /// Transport protocol, after any IPv6 extension headers.
+    proto:     Option<u8>,
+    ports:     Option<(u16, u16)>,
+    len:       usize,
+}
+
+impl Fields {
+    fn parse(frame: &[u8], ethernet: bool) -> Fields {
+        let mut f = Fields { len: frame.len(), ..Fields::default() };
+        let payload = if ethernet {
+            if frame.len() < ETH_HEADER { return f; }
+            f.ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+            &frame[ETH_HEADER..]
+        } else {
+            f.ethertype = match frame.first().map(|b| b >> 4) {
+                Some(4) => ETHERTYPE_IP4,
+                Some(6) => ETHERTYPE_IP6,
+                _       => 0,
+            };
+            frame
+        };
+        match f.ethertype {
+            ETHERTYPE_IP4 => f.ipv4(payload),
+            ETHERTYPE_IP6 => f.ipv6(payload),
+            ETHERTYPE_ARP => f.arp(payload),
+            _ => {}
+        }
+        f
+    }
+
+    fn ipv4(&mut self, p: &[u8]) {
+        if p.len() < ipv4::HEADER_LEN || p[0] >> 4 != 4 { return; }
+        let ihl = (p[0] & 0x0F) as usize * 4;
+        self.src = Some(IpAddr::V4(Ipv4Addr([p[12], p[13], p[14], p[15]])));
+        self.dst = Some(IpAddr::V4(Ipv4Addr([p[16], p[17], p[18], p[19]])));
+        self.proto = Some(p[9]);
+        // Only the first fragment carries ports
+        let first = u16::from_be_bytes([p[6], p[7]]) & 0x1FFF == 0;
+        if first && ihl >= ipv4::HEADER_LEN && ihl <= p.len() { self.transport(&p[ihl..]); }
+    }
+
+    fn ipv6(&mut self, p: &[u8]) {
+        if p.len() < ipv6::HEADER_LEN || p[0] >> 4 != 6 { return; }
+        let addr = |i: usize| { let mut a = [0u8; 16]; a.copy_from_slice(&p[i..i + 16]); IpAddr::V6(Ipv6Addr(a)) };
+        self.src = Some(addr(8));
+        self.dst = Some(addr(24));
+        let (mut next, mut at) = (p[6], ipv6::HEADER_LEN);
+        loop {
+            match next {
+                ipv6::NEXT_HOP_BY_HOP | ipv6::NEXT_ROUTING | ipv6::NEXT_DEST_OPTS if at + 8 <= p.len() => {
+                    next = p[at];
+                    at += (p[at + 1] as usize + 1) * 8;
+                }
+                ipv6::NEXT_FRAGMENT if at + 8 <= p.len() => {
+                    let first = u16::from_be_bytes([p[at + 2], p[at + 3]]) & 0xFFF8 == 0;
+                    next = p[at];
+                    at += 8;
+                    if !first { self.proto = Some(next); return; }
+                }
+                ipv6::NEXT_HOP_BY_HOP | ipv6::NEXT_ROUTING | ipv6::NEXT_FRAGMENT | ipv6::NEXT_DEST_OPTS => return,
+                _ => break,
+            }
+        }
+        self.proto = Some(next);
+        if at <= p.len() { self.transport(&p[at..]); }
+    }
+
+    fn transport(&mut self, p: &[u8]) {
+        if matches!(self.proto, Some(ipv4::PROTO_TCP | ipv4::PROTO_UDP)) && p.len() >= 4 {
+            self.ports = Some((u16::from_be_bytes([p[0], p[1]]), u16::from_be_bytes([p[2], p[3]])));
+        }
+    }
+
+    /// ARP's sender and target protocol addresses stand for source and
+    /// destination.
+    fn arp(&mut self, p: &[u8]) {
+        if p.len() < 28 || u16::from_be_bytes([p[2], p[3]]) != ETHERTYPE_IP4 { return; }
+        self.src = Some(IpAddr::V4(Ipv4Addr([p[14], p[15], p[16], p[17]])));
+        self.dst = Some(IpAddr::V4(Ipv4Addr([p[24], p[25], p[26], p[27]])));
+    }
+
+    fn summary(&self) -> String {
+        let end = |ip: Option<IpAddr>, port: Option<u16>| match (ip, port) {
+            (Some(IpAddr::V6(a)), Some(p)) => format!("[{}]:{}", a, p),
+            (Some(a), Some(p))             => format!("{}:{}", a, p),
+            (Some(a), None)                => format!("{}", a),
+            (None, _)                      => String::from("?"),
+        };
+        let (sport, dport) = self.ports.unzip();
+        let proto = match self.proto {
+            None                     => String::new(),
+            Some(ipv4::PROTO_TCP)    => String::from(" tcp"),
+            Some(ipv4::PROTO_UDP)    => String::from(" udp"),
+            Some(ipv4::PROTO_ICMP)   => String::from(" icmp"),
+            Some(PROTO_ICMPV6)       => String::from(" icmp6"),
+            Some(p)                  => format!(" proto {}", p),
+        };
+        match self.ethertype {
+            ETHERTYPE_IP4 | ETHERTYPE_IP6 if self.src.is_some() => format!(
+                "{} {} > {}{}, length {}", if self.ethertype == ETHERTYPE_IP4 { "ip" } else { "ip6" },
+                end(self.src, sport), end(self.dst, dport), proto, self.len),
+            ETHERTYPE_ARP if self.src.is_some() =>
+                format!("arp {} > {}, length {}", end(self.src, None), end(self.dst, None), self.len),
+            t => format!("ethertype {:#06x}, length {}", t, self.len),
+        }
+    }
+}
+
+// ─── filters ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Src,
+    Dst,
+    Either,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Proto {
+    Ip,
+    Ip6,
+    Arp,
+    Tcp,
+    Udp,
+    Icmp,
+    Icmp6,
+}
+
+#[derive(Debug)]
+enum Filter {
+    Any,
+    Proto(Proto),
+    Net(Side, IpAddr, u8),
+    Port(Side, u16),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    fn parse(expr: &str) -> Result<Filter, &'static str> {
+        let tokens = tokenize(expr);
+        if tokens.is_empty() { return Ok(Filter::Any); }
+        let mut p = Parser { tokens, at: 0 };
+        let f = p.or()?;
+        if p.at != p.tokens.len() { return Err("bad filter: unexpected token"); }
+        Ok(f)
+    }
+
+    fn matches(&self, f: &Fields) -> bool {
+        let side = |side: Side, test: &dyn Fn(IpAddr) -> bool| match side {
+            Side::Src    => f.src.is_some_and(test),
+            Side::Dst    => f.dst.is_some_and(test),
+            Side::Either => f.src.is_some_and(test) || f.dst.is_some_and(test),
+        };
+        match self {
+            Filter::Any => true,
+            Filter::Proto(p) => match p {
+                Proto::Ip    => f.ethertype == ETHERTYPE_IP4,
+                Proto::Ip6   => f.ethertype == ETHERTYPE_IP6,
+                Proto::Arp   => f.ethertype == ETHERTYPE_ARP,
+                Proto::Tcp   => f.proto == Some(ipv4::PROTO_TCP),
+                Proto::Udp   => f.proto == Some(ipv4::PROTO_UDP),
+                Proto::Icmp  => f.proto == Some(ipv4::PROTO_ICMP) && f.ethertype == ETHERTYPE_IP4,
+                Proto::Icmp6 => f.proto == Some(PROTO_ICMPV6) && f.ethertype == ETHERTYPE_IP6,
+            },
+            Filter::Net(s, net, len) => side(*s, &|ip| ip.same_prefix(*net, *len)),
+            Filter::Port(s, port) => f.ports.is_some_and(|(src, dst)| match s {
+                Side::Src    => src == *port,
+                Side::Dst    => dst == *port,
+                Side::Either => src == *port || dst == *port,
+            }),
+            Filter::Not(a)    => !a.matches(f),
+            Filter::And(a, b) => a.matches(f) && b.matches(f),
+            Filter::Or(a, b)  => a.matches(f) || b.matches(f),
+        }
+    }
+}
+
+/// Words, with parentheses and `!` split off as tokens of their own.
+fn tokenize(expr: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in expr.char_indices() {
+        let single = c == '(' || c == ')' || (c == '!' && start.is_none());
+        if c.is_whitespace() || single {
+            if let Some(s) = start.take() { tokens.push(&expr[s..i]); }
+            if single { tokens.push(&expr[i..i + 1]); }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start { tokens.push(&expr[s..]); }
+    tokens
+}
+
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    at:     usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.at).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, &'static str> {
+        let t = self.peek().ok_or("bad filter: unexpected end")?;
+        self.at += 1;
+        Ok(t)
+    }
+
+    fn or(&mut self) -> Result<Filter, &'static str> {
+        let mut f = self.and()?;
+        while matches!(self.peek(), Some("or" | "||")) {
+            self.at += 1;
+            f = Filter::Or(Box::new(f), Box::new(self.and()?));
+        }
+        Ok(f)
+    }
+
+    fn and(&mut self) -> Result<Filter, &'static str> {
+        let mut f = self.not()?;
+        while matches!(self.peek(), Some("and" | "&&")) {
+            self.at += 1;
+            f = Filter::And(Box::new(f), Box::new(self.not()?));
+        }
+        Ok(f)
+    }
+
+    fn not(&mut self) -> Result<Filter, &'static str> {
+        match self.next()? {
+            "not" | "!" => Ok(Filter::Not(Box::new(self.not()?))),
+            "(" => {
+                let f = self.or()?;
+                if self.next()? != ")" { return Err("bad filter: missing )"); }
+                Ok(f)
+            }
+            _ => { self.at -= 1; self.primitive() }
+        }
+    }
+
+    fn primitive(&mut self) -> Result<Filter, &'static str> {
+        let side = match self.peek() {
+            Some("src") => { self.at += 1; Side::Src }
+            Some("dst") => { self.at += 1; Side::Dst }
+            _           => Side::Either,
+        };
+        let word = self.next()?;
+        let proto = match word {
+            "ip"    => Some(Proto::Ip),
+            "ip6"   => Some(Proto::Ip6),
+            "arp"   => Some(Proto::Arp),
+            "tcp"   => Some(Proto::Tcp),
+            "udp"   => Some(Proto::Udp),
+            "icmp"  => Some(Proto::Icmp),
+            "icmp6" => Some(Proto::Icmp6),
+            _       => None,
+        };
+        if let Some(p) = proto {
+            if side != Side::Either { return Err("bad filter: src or dst before a protocol"); }
+            return Ok(Filter::Proto(p));
+        }
+        match word {
+            "host" => {
+                let ip = IpAddr::parse(self.next()?).ok_or("bad filter: bad address")?;
+                Ok(Filter::Net(side, ip, if matches!(ip, IpAddr::V4(_)) { 32 } else { 128 }))
+            }
+            "net" => {
+                let (a, len) = self.next()?.split_once('/').ok_or("bad filter: net needs addr/len")?;
+                let ip = IpAddr::parse(a).ok_or("bad filter: bad address")?;
+                let max = if matches!(ip, IpAddr::V4(_)) { 32 } else { 128 };
+                let len = len.parse().ok().filter(|&l| l <= max).ok_or("bad filter: bad prefix length")?;
+                Ok(Filter::Net(side, ip, len))
+            }
+            "port" => Ok(Filter::Port(side, self.next()?.parse().map_err(|_| "bad filter: bad port")?)),
+            _ => Err("bad filter: unknown primitive"),
+        }
+    }
+}
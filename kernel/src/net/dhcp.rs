@@ -0,0 +1,398 @@
+//! DHCP Client
+//! Acquires and keeps an IPv4 lease per interface (RFC 2131): DISCOVER,
+//! take the first OFFER, REQUEST it, and on ACK configure the interface's
+//! address, gateway and DNS servers.  The lease is renewed with its server
+//! at T1, from any server at T2, and dropped when it expires.  Clients
+//! share one socket on port 68 and are driven from the network poll, so
+//! replies and timers are handled as they come.
+//!
+//! Requests ask for broadcast replies, since until the ACK there is no
+//! address to receive a unicast on.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::udp::{self, UdpHandle};
+use super::{ipv4, Ipv4Addr, MacAddr, SocketAddr};
+
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+
+const OP_REQUEST: u8 = 1;
+const OP_REPLY:   u8 = 2;
+const MAGIC: [u8; 4] = [99, 130, 83, 99];
+/// Ask servers to broadcast their replies.
+const FLAG_BROADCAST: u16 = 0x8000;
+/// Fixed part of a message, up to and including the magic cookie.
+const FIXED_LEN: usize = 240;
+
+const DISCOVER: u8 = 1;
+const OFFER:    u8 = 2;
+const REQUEST:  u8 = 3;
+const ACK:      u8 = 5;
+const NAK:      u8 = 6;
+const RELEASE:  u8 = 7;
+
+const OPT_PAD:         u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER:      u8 = 3;
+const OPT_DNS:         u8 = 6;
+const OPT_REQUESTED:   u8 = 50;
+const OPT_LEASE_TIME:  u8 = 51;
+const OPT_MSG_TYPE:    u8 = 53;
+const OPT_SERVER_ID:   u8 = 54;
+const OPT_PARAMS:      u8 = 55;
+const OPT_T1:          u8 = 58;
+const OPT_T2:          u8 = 59;
+const OPT_END:         u8 = 255;
+
+/// First retransmission interval; it doubles up to `RETRY_MAX_MS`.
+const RETRY_MIN_MS:  u64 = 4000;
+const RETRY_MAX_MS:  u64 = 64_000;
+/// Shortest wait between renewal attempts (RFC 2131 4.4.5).
+const RENEW_MIN_MS:  u64 = 60_000;
+/// REQUESTs sent for an offer before starting over.
+const REQUEST_TRIES: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpState {
+    Init,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lease {
+    pub addr:    Ipv4Addr,
+    pub prefix:  u8,
+    pub router:  Option<Ipv4Addr>,
+    pub dns:     Vec<Ipv4Addr>,
+    pub server:  Ipv4Addr,
+    pub secs:    u32,
+}
+
+struct Client {
+    index:    usize,
+    mac:      MacAddr,
+    state:    DhcpState,
+    xid:      u32,
+    /// The offer being requested, or the lease held.
+    lease:    Option<Lease>,
+    /// When the current exchange began, for the `secs` field.
+    started:  u64,
+    /// Next (re)transmission.
+    next_tx:  u64,
+    interval: u64,
+    tries:    u32,
+    t1:       u64,
+    t2:       u64,
+    expiry:   u64,
+}
+
+/// A client as reported to callers.
+#[derive(Debug, Clone)]
+pub struct DhcpInfo {
+    pub index:      usize,
+    pub state:      DhcpState,
+    pub lease:      Option<Lease>,
+    /// Seconds until the lease expires.
+    pub expires_in: Option<u64>,
+}
+
+struct Dhcp {
+    clients: Vec<Client>,
+    socket:  Option<UdpHandle>,
+}
+
+static DHCP: Mutex<Dhcp> = Mutex::new(Dhcp { clients: Vec::new(), socket: None });
+
+// ─── messages ─────────────────────────────────────────────────────────────────
+
+struct Reply {
+    xid:     u32,
+    yiaddr:  Ipv4Addr,
+    chaddr:  MacAddr,
+    kind:    u8,
+    server:  Option<Ipv4Addr>,
+    mask:    Option<Ipv4Addr>,
+    router:  Option<Ipv4Addr>,
+    dns:     Vec<Ipv4Addr>,
+    lease:   Option<u32>,
+    t1:      Option<u32>,
+    t2:      Option<u32>,
+}
+
+fn addr_at(b: &[u8], i: usize) -> Ipv4Addr {
+    Ipv4Addr([b[i], b[i + 1], b[i + 2], b[i + 3]])
+}
+
+fn parse(m: &[u8]) -> Option<Reply> {
+    if m.len() < FIXED_LEN || m[0] != OP_REPLY || m[236..240] != MAGIC { return None; }
+    let mut r = Reply {
+        xid: u32::from_be_bytes([m[4], m[5], m[6], m[7]]), yiaddr: addr_at(m, 16),
+        chaddr: MacAddr([m[28], m[29], m[30], m[31], m[32], m[33]]), kind: 0,
+        server: None, mask: None, router: None, dns: Vec::new(), lease: None, t1: None, t2: None,
+    };
+    let mut opts = &m[FIXED_LEN..];
+    while let [code, rest @ ..] = opts {
+        match *code {
+            OPT_END => break,
+            OPT_PAD => { opts = rest; continue; }
+            _ => {}
+        }
+        let (&len, rest) = rest.split_first()?;
+        let value = rest.get(..len as usize)?;
+        let u32_of = |v: &[u8]| (v.len() == 4).then(|| u32::from_be_bytes([v[0], v[1], v[2], v[3]]));
+        let addr_of = |v: &[u8]| (v.len() >= 4).then(|| addr_at(v, 0));
+        match *code {
+            OPT_MSG_TYPE    => r.kind = *value.first()?,
+            OPT_SERVER_ID   => r.server = addr_of(value),
+            OPT_SUBNET_MASK => r.mask = addr_of(value),
+            OPT_ROUTER      => r.router = addr_of(value),
+            OPT_DNS         => r.dns = value.as_chunks::<4>().0.iter().map(|a| Ipv4Addr(*a)).collect(),
+            OPT_LEASE_TIME  => r.lease = u32_of(value),
+            OPT_T1          => r.t1 = u32_of(value),
+            OPT_T2          => r.t2 = u32_of(value),
+            _ => {}
+        }
+        opts = &rest[len as usize..];
+    }
+    (r.kind != 0).then_some(r)
+}
+
+impl Client {
+    /// A message of `kind`; `ciaddr` is set when renewing or rebinding a
+    /// lease we hold.
+    fn message(&self, kind: u8, now: u64) -> Vec<u8> {
+        let ciaddr = match self.state {
+            DhcpState::Bound | DhcpState::Renewing | DhcpState::Rebinding => self.lease.as_ref().map(|l| l.addr),
+            _ => None,
+        };
+        let secs = ((now - self.started) / 1000).min(u16::MAX as u64) as u16;
+        let flags = if ciaddr.is_some() { 0 } else { FLAG_BROADCAST };
+        let mut m = alloc::vec![0u8; FIXED_LEN];
+        m[..4].copy_from_slice(&[OP_REQUEST, 1, 6, 0]);
+        m[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        m[8..10].copy_from_slice(&secs.to_be_bytes());
+        m[10..12].copy_from_slice(&flags.to_be_bytes());
+        m[12..16].copy_from_slice(&ciaddr.unwrap_or(Ipv4Addr::UNSPECIFIED).0);
+        m[28..34].copy_from_slice(&self.mac.0);
+        m[236..240].copy_from_slice(&MAGIC);
+        m.extend_from_slice(&[OPT_MSG_TYPE, 1, kind]);
+        // Selecting a server's offer names the server and the address
+        if self.state == DhcpState::Requesting {
+            if let Some(l) = &self.lease {
+                m.extend_from_slice(&[OPT_REQUESTED, 4]);
+                m.extend_from_slice(&l.addr.0);
+                m.extend_from_slice(&[OPT_SERVER_ID, 4]);
+                m.extend_from_slice(&l.server.0);
+            }
+        }
+        if kind == RELEASE {
+            if let Some(l) = &self.lease {
+                m.extend_from_slice(&[OPT_SERVER_ID, 4]);
+                m.extend_from_slice(&l.server.0);
+            }
+        } else {
+            m.extend_from_slice(&[OPT_PARAMS, 6, OPT_SUBNET_MASK, OPT_ROUTER, OPT_DNS, OPT_LEASE_TIME, OPT_T1, OPT_T2]);
+        }
+        m.push(OPT_END);
+        m
+    }
+
+    /// Broadcast on our interface, from 0.0.0.0 unless rebinding a lease.
+    fn broadcast(&self, msg: &[u8]) {
+        let ip = match (&self.lease, self.state) {
+            (Some(l), DhcpState::Rebinding) => l.addr,
+            _ => Ipv4Addr::UNSPECIFIED,
+        };
+        let src = SocketAddr::new(ip, CLIENT_PORT);
+        let dst = SocketAddr::new(Ipv4Addr::BROADCAST, SERVER_PORT);
+        let _ = ipv4::send_via(self.index, ip, Ipv4Addr::BROADCAST, ipv4::PROTO_UDP, &udp::build(src, dst, msg));
+    }
+
+    fn restart(&mut self, now: u64) {
+        self.state = DhcpState::Init;
+        self.next_tx = now;
+        self.lease = None;
+    }
+
+    /// Send whatever the state calls for and set the retransmission.
+    fn transmit(&mut self, socket: UdpHandle, now: u64) {
+        use DhcpState::*;
+        let retry = |interval: u64| (interval * 2).clamp(RETRY_MIN_MS, RETRY_MAX_MS);
+        match self.state {
+            Init => {
+                self.xid = crate::entropy::next_u32();
+                self.started = now;
+                self.state = Selecting;
+                self.interval = 0;
+                self.transmit(socket, now);
+            }
+            Selecting => {
+                self.broadcast(&self.message(DISCOVER, now));
+                self.interval = retry(self.interval);
+                self.next_tx = now + self.interval;
+            }
+            Requesting if self.tries >= REQUEST_TRIES => self.restart(now),
+            Requesting => {
+                self.broadcast(&self.message(REQUEST, now));
+                self.tries += 1;
+                self.interval = retry(self.interval);
+                self.next_tx = now + self.interval;
+            }
+            Renewing => {
+                if let Some(server) = self.lease.as_ref().map(|l| l.server) {
+                    let to = SocketAddr::new(server, SERVER_PORT);
+                    let _ = udp::send_to(socket, &self.message(REQUEST, now), to);
+                }
+                self.next_tx = now + ((self.t2.saturating_sub(now)) / 2).max(RENEW_MIN_MS);
+            }
+            Rebinding => {
+                self.broadcast(&self.message(REQUEST, now));
+                self.next_tx = now + ((self.expiry.saturating_sub(now)) / 2).max(RENEW_MIN_MS);
+            }
+            Bound => self.next_tx = u64::MAX,
+        }
+    }
+
+    fn reply(&mut self, r: Reply, now: u64) {
+        use DhcpState::*;
+        match (self.state, r.kind) {
+            (Selecting, OFFER) => {
+                let Some(server) = r.server else { return };
+                self.lease = Some(Lease {
+                    addr: r.yiaddr, prefix: 0, router: None, dns: Vec::new(), server, secs: 0,
+                });
+                self.state = Requesting;
+                self.tries = 0;
+                self.interval = 0;
+                self.next_tx = now;
+            }
+            (Requesting | Renewing | Rebinding, ACK) => {
+                let secs = r.lease.unwrap_or(u32::MAX);
+                let lease = Lease {
+                    addr: r.yiaddr,
+                    prefix: r.mask.map_or(24, |m| m.to_u32().leading_ones() as u8),
+                    router: r.router,
+                    dns: r.dns,
+                    server: r.server.or(self.lease.as_ref().map(|l| l.server)).unwrap_or(Ipv4Addr::UNSPECIFIED),
+                    secs,
+                };
+                let ms = |s: u32| now.saturating_add(s as u64 * 1000);
+                self.t1 = ms(r.t1.unwrap_or(secs / 2));
+                self.t2 = ms(r.t2.unwrap_or(secs / 8 * 7));
+                self.expiry = ms(secs);
+                if self.state == Requesting {
+                    crate::println!("  [dhcp] {}: leased {}/{} from {} for {} s",
+                        interface_name(self.index), lease.addr, lease.prefix, lease.server, secs);
+                }
+                let _ = super::configure(self.index, lease.addr, lease.prefix, lease.router);
+                let _ = super::set_dns(self.index, &lease.dns);
+                self.lease = Some(lease);
+                self.state = Bound;
+                self.next_tx = u64::MAX;
+            }
+            (Requesting | Renewing | Rebinding, NAK) => {
+                if self.state != Requesting { let _ = super::deconfigure(self.index); }
+                self.restart(now);
+            }
+            _ => {}
+        }
+    }
+
+    /// Run the lease timers.
+    fn timers(&mut self, socket: UdpHandle, now: u64) {
+        use DhcpState::*;
+        if matches!(self.state, Bound | Renewing | Rebinding) {
+            if now >= self.expiry {
+                crate::println!("  [dhcp] {}: lease expired", interface_name(self.index));
+                let _ = super::deconfigure(self.index);
+                self.restart(now);
+            } else if now >= self.t2 && self.state != Rebinding {
+                self.state = Rebinding;
+                self.next_tx = now;
+            } else if now >= self.t1 && self.state == Bound {
+                self.state = Renewing;
+                self.xid = crate::entropy::next_u32();
+                self.started = now;
+                self.next_tx = now;
+            }
+        }
+        if now >= self.next_tx { self.transmit(socket, now); }
+    }
+}
+
+fn interface_name(index: usize) -> alloc::string::String {
+    super::interface(index).map(|i| i.name).unwrap_or_default()
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Start acquiring a lease on Ethernet interface `index`.
+pub fn start(index: usize) -> Result<(), &'static str> {
+    let mac = super::interface(index).ok_or("no such interface")?.mac.ok_or("not an Ethernet interface")?;
+    let mut dhcp = DHCP.lock();
+    if dhcp.clients.iter().any(|c| c.index == index) { return Err("DHCP already running"); }
+    if dhcp.socket.is_none() {
+        let socket = udp::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED, CLIENT_PORT))?;
+        udp::set_nonblocking(socket, true)?;
+        dhcp.socket = Some(socket);
+    }
+    let now = crate::arch::uptime_millis();
+    dhcp.clients.push(Client {
+        index, mac, state: DhcpState::Init, xid: 0, lease: None, started: now, next_tx: now,
+        interval: 0, tries: 0, t1: 0, t2: 0, expiry: 0,
+    });
+    drop(dhcp);
+    super::rx_ready();
+    Ok(())
+}
+
+/// Stop the client on `index`, releasing any lease it holds.
+pub fn stop(index: usize) -> Result<(), &'static str> {
+    let mut dhcp = DHCP.lock();
+    let i = dhcp.clients.iter().position(|c| c.index == index).ok_or("DHCP not running")?;
+    let c = dhcp.clients.remove(i);
+    if let (Some(socket), Some(lease)) = (dhcp.socket, &c.lease) {
+        if matches!(c.state, DhcpState::Bound | DhcpState::Renewing | DhcpState::Rebinding) {
+            let msg = c.message(RELEASE, crate::arch::uptime_millis());
+            let _ = udp::send_to(socket, &msg, SocketAddr::new(lease.server, SERVER_PORT));
+            let _ = super::deconfigure(index);
+        }
+    }
+    if dhcp.clients.is_empty() {
+        if let Some(socket) = dhcp.socket.take() { let _ = udp::close(socket); }
+    }
+    Ok(())
+}
+
+pub fn running(index: usize) -> bool {
+    DHCP.lock().clients.iter().any(|c| c.index == index)
+}
+
+pub fn status() -> Vec<DhcpInfo> {
+    let now = crate::arch::uptime_millis();
+    DHCP.lock().clients.iter().map(|c| DhcpInfo {
+        index: c.index, state: c.state, lease: c.lease.clone(),
+        expires_in: (c.state == DhcpState::Bound || c.state == DhcpState::Renewing || c.state == DhcpState::Rebinding)
+            .then(|| c.expiry.saturating_sub(now) / 1000),
+    }).collect()
+}
+
+/// Handle replies and timers; called from the network poll.
+pub(super) fn tick(now: u64) {
+    let mut dhcp = DHCP.lock();
+    let Some(socket) = dhcp.socket else { return };
+    let mut buf = [0u8; 1500];
+    while let Ok((n, _)) = udp::recv_from(socket, &mut buf, None) {
+        let Some(r) = parse(&buf[..n]) else { continue };
+        if let Some(c) = dhcp.clients.iter_mut().find(|c| c.xid == r.xid && c.mac == r.chaddr) {
+            c.reply(r, now);
+        }
+    }
+    for c in dhcp.clients.iter_mut() { c.timers(socket, now); }
+}
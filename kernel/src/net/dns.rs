@@ -0,0 +1,721 @@
+//! DNS Resolver
+//! Stub resolution of names to addresses (RFC 1035), over DNS-over-HTTPS
+//! (RFC 8484) by default.  A DoH query is a POST of the DNS message to
+//! the server's `/dns-query` over HTTP/1.1 and TLS; the connection is kept
+//! open and reused for the next query.  Plain DNS over UDP (falling back
+//! to TCP for truncated answers) goes to the servers the interfaces
+//! learned from DHCP or router advertisements.
+//!
+//! Which transports are tried, and in what order, is the `Policy`.  A
+//! server that fails is skipped for a backoff that doubles with each
+//! failure, unless every server is backing off.  Answers are cached for
+//! their TTL, and that there is nothing for as long as the zone's SOA
+//! allows (RFC 2308).  Queries go out as networkd; an app that is offline
+//! gets none, nor does one whose proxy resolves its names.
+//!
+//! Answers are checked with `dnssec` unless that is turned off; `lookup`
+//! says what it found.  By default a bogus answer is refused and one from
+//! an unsigned zone accepted; `Dnssec::Require` accepts only secure ones.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::dnssec::{self, Validation};
+use super::tls::{self, TlsStream};
+use super::{netns, networkd, proxy, tcp, udp, IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+pub const TYPE_A:     u16 = 1;
+pub const TYPE_NS:    u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA:   u16 = 6;
+pub const TYPE_AAAA:  u16 = 28;
+const TYPE_OPT: u16 = 41;
+const CLASS_IN: u16 = 1;
+
+const RCODE_NXDOMAIN: u8 = 3;
+const FLAG_QR: u16 = 0x8000;
+const FLAG_TC: u16 = 0x0200;
+const FLAG_RD: u16 = 0x0100;
+/// EDNS0 "DNSSEC OK", asking for signatures (RFC 3225).
+const EDNS_DO: u16 = 0x8000;
+/// UDP payload size offered, small enough not to fragment (RFC 9715).
+const EDNS_PAYLOAD: u16 = 1232;
+
+const HEADER_LEN: usize = 12;
+/// Longest name on the wire.
+const MAX_NAME:   usize = 255;
+/// Compression pointers followed while reading one name.
+const MAX_JUMPS:  usize = 16;
+/// CNAME links followed from the query name.
+const MAX_CNAMES: usize = 8;
+
+const CACHE_MAX:        usize = 128;
+/// TTL bounds applied to answers, seconds.
+const MIN_TTL:          u32 = 5;
+const MAX_TTL:          u32 = 86_400;
+/// For a negative answer without an SOA to say, and at most.
+const NEGATIVE_TTL:     u32 = 60;
+const MAX_NEGATIVE_TTL: u32 = 10_800;
+
+const QUERY_TIMEOUT_MS: u64 = 3000;
+const BACKOFF_MIN_MS:   u64 = 5_000;
+const BACKOFF_MAX_MS:   u64 = 300_000;
+/// Largest DoH response body accepted.
+const MAX_BODY:         usize = 65535;
+
+// ─── messages ─────────────────────────────────────────────────────────────────
+
+/// A query for `name` of type `qtype`, with recursion desired.  It offers
+/// EDNS0 with the DO bit, so signed zones answer with their signatures.
+pub fn encode_query(id: u16, name: &str, qtype: u16) -> Result<Vec<u8>, &'static str> {
+    let mut m = Vec::with_capacity(HEADER_LEN + name.len() + 17);
+    m.extend_from_slice(&id.to_be_bytes());
+    m.extend_from_slice(&FLAG_RD.to_be_bytes());
+    m.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 1]);
+    let name = name.trim_end_matches('.');
+    if name.len() + 2 > MAX_NAME { return Err("name too long"); }
+    // The root has no labels
+    for label in name.split('.').filter(|_| !name.is_empty()) {
+        if label.is_empty() || label.len() > 63 { return Err("invalid name"); }
+        m.push(label.len() as u8);
+        m.extend_from_slice(label.as_bytes());
+    }
+    m.push(0);
+    m.extend_from_slice(&qtype.to_be_bytes());
+    m.extend_from_slice(&CLASS_IN.to_be_bytes());
+    // OPT: root owner, payload size as class, DO among the TTL's flags
+    m.push(0);
+    m.extend_from_slice(&TYPE_OPT.to_be_bytes());
+    m.extend_from_slice(&EDNS_PAYLOAD.to_be_bytes());
+    m.extend_from_slice(&[0, 0]);
+    m.extend_from_slice(&EDNS_DO.to_be_bytes());
+    m.extend_from_slice(&[0, 0]);
+    Ok(m)
+}
+
+/// The name at `pos` in `msg`, following compression pointers, and the
+/// offset just past it where it started.
+pub(super) fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    let mut end = None;
+    let mut jumps = 0;
+    loop {
+        let len = *msg.get(pos)? as usize;
+        match len {
+            0 => break,
+            0xC0.. => {
+                let target = (len & 0x3F) << 8 | *msg.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                jumps += 1;
+                if jumps > MAX_JUMPS { return None; }
+                pos = target;
+            }
+            1..=63 => {
+                let label = msg.get(pos + 1..pos + 1 + len)?;
+                if !name.is_empty() { name.push('.'); }
+                name.push_str(core::str::from_utf8(label).ok()?);
+                if name.len() > MAX_NAME { return None; }
+                pos += 1 + len;
+            }
+            _ => return None,
+        }
+    }
+    Some((name, end.unwrap_or(pos + 1)))
+}
+
+/// A resource record.  The owner is lower-cased without the final dot;
+/// names inside NS, CNAME and SOA data are expanded to their canonical
+/// wire form, so the data stands alone.
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub name:  String,
+    pub ty:    u16,
+    pub ttl:   u32,
+    pub rdata: Vec<u8>,
+}
+
+/// A response's header fields and the records that matter to a stub.
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub id:        u16,
+    pub rcode:     u8,
+    pub answers:   Vec<Record>,
+    pub authority: Vec<Record>,
+}
+
+/// Parse a response; the additional section is skipped.
+pub fn parse_message(msg: &[u8]) -> Result<Message, &'static str> {
+    const MALFORMED: &str = "malformed DNS response";
+    if msg.len() < HEADER_LEN { return Err("short DNS response"); }
+    let word = |i: usize| u16::from_be_bytes([msg[i], msg[i + 1]]);
+    let flags = word(2);
+    if flags & FLAG_QR == 0 { return Err("mismatched DNS response"); }
+    let (qdcount, ancount, nscount) = (word(4), word(6), word(8));
+    let mut pos = HEADER_LEN;
+    for _ in 0..qdcount {
+        let (_, next) = read_name(msg, pos).ok_or(MALFORMED)?;
+        pos = next + 4;
+    }
+    let mut sections = [Vec::new(), Vec::new()];
+    for (section, count) in sections.iter_mut().zip([ancount, nscount]) {
+        for _ in 0..count {
+            let (owner, next) = read_name(msg, pos).ok_or(MALFORMED)?;
+            let fixed = msg.get(next..next + 10).ok_or(MALFORMED)?;
+            let ty = u16::from_be_bytes([fixed[0], fixed[1]]);
+            let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+            let rdlen = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+            let at = next + 10;
+            let raw = msg.get(at..at + rdlen).ok_or(MALFORMED)?;
+            let rdata = match ty {
+                TYPE_NS | TYPE_CNAME => dnssec::wire_name(&read_name(msg, at).ok_or(MALFORMED)?.0),
+                TYPE_SOA => {
+                    let (mname, next) = read_name(msg, at).ok_or(MALFORMED)?;
+                    let (rname, next) = read_name(msg, next).ok_or(MALFORMED)?;
+                    let mut d = dnssec::wire_name(&mname);
+                    d.extend(dnssec::wire_name(&rname));
+                    d.extend_from_slice(msg.get(next..next + 20).ok_or(MALFORMED)?);
+                    d
+                }
+                _ => raw.to_vec(),
+            };
+            section.push(Record { name: owner.to_ascii_lowercase(), ty, ttl, rdata });
+            pos = at + rdlen;
+        }
+    }
+    let [answers, authority] = sections;
+    Ok(Message { id: word(0), rcode: (flags & 0xF) as u8, answers, authority })
+}
+
+/// What a response says about a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    /// Addresses, with the smallest TTL on the path to them; for none,
+    /// how long that may be cached.
+    Records(Vec<IpAddr>, u32),
+    /// The name does not exist, for this many seconds.
+    NxDomain(u32),
+}
+
+/// How long a response saying there is nothing may be cached: the zone's
+/// SOA says, in its TTL and MINIMUM field (RFC 2308 5).
+fn negative_ttl(msg: &Message) -> u32 {
+    msg.authority.iter().find(|r| r.ty == TYPE_SOA && r.rdata.len() >= 4).map_or(NEGATIVE_TTL, |soa| {
+        let m = &soa.rdata[soa.rdata.len() - 4..];
+        soa.ttl.min(u32::from_be_bytes([m[0], m[1], m[2], m[3]]))
+    }).clamp(MIN_TTL, MAX_NEGATIVE_TTL)
+}
+
+/// The addresses `msg` gives for `name`, following CNAMEs.
+fn answer(msg: &Message, name: &str) -> Result<Answer, &'static str> {
+    match msg.rcode {
+        0 => {}
+        RCODE_NXDOMAIN => return Ok(Answer::NxDomain(negative_ttl(msg))),
+        _ => return Err("DNS server failure"),
+    }
+    // Follow the CNAME chain from the query name, then take its addresses
+    let mut target = name.trim_end_matches('.').to_ascii_lowercase();
+    let mut ttl = MAX_TTL;
+    for _ in 0..MAX_CNAMES {
+        let Some(r) = msg.answers.iter().find(|r| r.ty == TYPE_CNAME && r.name == target) else { break };
+        target = read_name(&r.rdata, 0).ok_or("malformed DNS response")?.0;
+        ttl = ttl.min(r.ttl);
+    }
+    let mut addrs = Vec::new();
+    for r in msg.answers.iter().filter(|r| r.name == target) {
+        let addr = match (r.ty, r.rdata.len()) {
+            (TYPE_A, 4) => IpAddr::V4(Ipv4Addr([r.rdata[0], r.rdata[1], r.rdata[2], r.rdata[3]])),
+            (TYPE_AAAA, 16) => {
+                let mut a = [0u8; 16];
+                a.copy_from_slice(&r.rdata);
+                IpAddr::V6(Ipv6Addr(a))
+            }
+            _ => continue,
+        };
+        ttl = ttl.min(r.ttl);
+        addrs.push(addr);
+    }
+    if addrs.is_empty() { return Ok(Answer::Records(addrs, negative_ttl(msg))); }
+    Ok(Answer::Records(addrs, ttl.clamp(MIN_TTL, MAX_TTL)))
+}
+
+/// Parse a response to query `id` for `name`, following CNAMEs.
+pub fn parse_response(msg: &[u8], id: u16, name: &str) -> Result<Answer, &'static str> {
+    let msg = parse_message(msg)?;
+    if msg.id != id { return Err("mismatched DNS response"); }
+    answer(&msg, name)
+}
+
+// ─── configuration ────────────────────────────────────────────────────────────
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Never send a query in the clear.
+    DohOnly,
+    /// DoH first; plain DNS if no DoH server answers.
+    DohThenPlain,
+    PlainOnly,
+}
+
+impl Policy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Policy::DohOnly      => "doh-only",
+            Policy::DohThenPlain => "doh-then-plain",
+            Policy::PlainOnly    => "plain-only",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Policy> {
+        [Policy::DohOnly, Policy::DohThenPlain, Policy::PlainOnly].into_iter().find(|p| p.as_str() == s)
+    }
+}
+
+/// What to do with DNSSEC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dnssec {
+    /// Answers are not validated.
+    Off,
+    /// Answers are validated and bogus ones refused; unsigned zones work.
+    Validate,
+    /// Only answers that validate as secure are accepted.
+    Require,
+}
+
+impl Dnssec {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Dnssec::Off      => "off",
+            Dnssec::Validate => "validate",
+            Dnssec::Require  => "require",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Dnssec> {
+        [Dnssec::Off, Dnssec::Validate, Dnssec::Require].into_iter().find(|d| d.as_str() == s)
+    }
+}
+
+/// A DoH server: the name its certificate must carry, and the address to
+/// reach it at (a DoH server cannot be looked up through itself).
+#[derive(Clone, Debug)]
+pub struct DohServer {
+    pub host: String,
+    pub addr: SocketAddr,
+}
+
+impl DohServer {
+    pub fn new(host: &str, addr: IpAddr) -> DohServer {
+        DohServer { host: host.to_string(), addr: SocketAddr::new(addr, 443) }
+    }
+}
+
+/// Failures of one server, for backoff.
+#[derive(Clone, Copy)]
+struct Health {
+    addr:     SocketAddr,
+    failures: u32,
+    retry_at: u64,
+}
+
+struct Resolver {
+    policy:  Policy,
+    dnssec:  Dnssec,
+    doh:     Vec<DohServer>,
+    health:  Vec<Health>,
+    cache:   Vec<CacheEntry>,
+    /// The open DoH connection and the server it goes to.
+    conn:    Option<(SocketAddr, TlsStream)>,
+}
+
+#[derive(Clone, Debug)]
+struct CacheEntry {
+    name:       String,
+    answer:     Answer,
+    validation: Validation,
+    expires:    u64,
+}
+
+static RESOLVER: Mutex<Resolver> = Mutex::new(Resolver {
+    policy: Policy::DohThenPlain, dnssec: Dnssec::Validate, doh: Vec::new(), health: Vec::new(), cache: Vec::new(), conn: None,
+});
+
+fn now() -> u64 {
+    crate::arch::uptime_millis()
+}
+
+fn default_doh_servers() -> Vec<DohServer> {
+    vec![
+        DohServer::new("cloudflare-dns.com", IpAddr::V4(Ipv4Addr([1, 1, 1, 1]))),
+        DohServer::new("cloudflare-dns.com", IpAddr::V4(Ipv4Addr([1, 0, 0, 1]))),
+        DohServer::new("dns.quad9.net",      IpAddr::V4(Ipv4Addr([9, 9, 9, 9]))),
+    ]
+}
+
+pub fn set_policy(policy: Policy) {
+    RESOLVER.lock().policy = policy;
+}
+
+pub fn policy() -> Policy {
+    RESOLVER.lock().policy
+}
+
+/// Set the DNSSEC mode.  Cached answers were judged under the old one, so
+/// they go.
+pub fn set_dnssec(mode: Dnssec) {
+    RESOLVER.lock().dnssec = mode;
+    flush_cache();
+}
+
+pub fn dnssec() -> Dnssec {
+    RESOLVER.lock().dnssec
+}
+
+/// Replace the DoH servers, tried in the order given.
+pub fn set_doh_servers(servers: Vec<DohServer>) {
+    let open = {
+        let mut r = RESOLVER.lock();
+        r.doh = servers;
+        r.conn.take()
+    };
+    // Closed outside the lock, as closing sends
+    networkd::run(|| drop(open));
+}
+
+pub fn doh_servers() -> Vec<DohServer> {
+    let mut r = RESOLVER.lock();
+    if r.doh.is_empty() { r.doh = default_doh_servers(); }
+    r.doh.clone()
+}
+
+// ─── server health ────────────────────────────────────────────────────────────
+
+/// `servers` with those backing off moved behind the rest (earliest
+/// retry first), so a query still goes somewhere when all are failing.
+fn order<T>(mut servers: Vec<T>, addr: impl Fn(&T) -> SocketAddr) -> Vec<T> {
+    let r = RESOLVER.lock();
+    let now = now();
+    let retry_at = |s: &T| r.health.iter().find(|h| h.addr == addr(s)).map_or(0, |h| h.retry_at);
+    servers.sort_by_key(|s| { let t = retry_at(s); if t <= now { 0 } else { t } });
+    servers
+}
+
+fn record(addr: SocketAddr, ok: bool) {
+    let mut r = RESOLVER.lock();
+    let i = match r.health.iter().position(|h| h.addr == addr) {
+        Some(i) => i,
+        None if ok => return,
+        None => { r.health.push(Health { addr, failures: 0, retry_at: 0 }); r.health.len() - 1 }
+    };
+    if ok { r.health.swap_remove(i); return; }
+    let h = &mut r.health[i];
+    h.failures += 1;
+    let backoff = BACKOFF_MIN_MS.saturating_mul(1 << (h.failures - 1).min(16)).min(BACKOFF_MAX_MS);
+    h.retry_at = now() + backoff;
+}
+
+// ─── transports ───────────────────────────────────────────────────────────────
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Read one HTTP/1.1 response; returns (status, body, keep-alive).
+fn read_http(conn: &mut TlsStream) -> Result<(u16, Vec<u8>, bool), &'static str> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    let head_end = loop {
+        if let Some(i) = find(&buf, b"\r\n\r\n") { break i; }
+        if buf.len() > 8192 { return Err("HTTP header too long"); }
+        let n = conn.read(&mut chunk, QUERY_TIMEOUT_MS)?;
+        if n == 0 { return Err("connection closed"); }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let head = core::str::from_utf8(&buf[..head_end]).map_err(|_| "bad HTTP response")?;
+    let mut lines = head.split("\r\n");
+    let status = lines.next().and_then(|l| l.split(' ').nth(1)).and_then(|s| s.parse().ok()).ok_or("bad HTTP response")?;
+    let (mut length, mut chunked, mut keep_alive) = (None, false, true);
+    for line in lines {
+        let Some((k, v)) = line.split_once(':') else { continue };
+        let v = v.trim();
+        if k.eq_ignore_ascii_case("content-length") { length = v.parse::<usize>().ok(); }
+        if k.eq_ignore_ascii_case("transfer-encoding") { chunked = v.eq_ignore_ascii_case("chunked"); }
+        if k.eq_ignore_ascii_case("connection") { keep_alive = !v.eq_ignore_ascii_case("close"); }
+    }
+    let mut rest: Vec<u8> = buf.split_off(head_end + 4);
+    let mut fill = |rest: &mut Vec<u8>, want: usize| -> Result<(), &'static str> {
+        while rest.len() < want {
+            let n = conn.read(&mut chunk, QUERY_TIMEOUT_MS)?;
+            if n == 0 { return Err("connection closed"); }
+            rest.extend_from_slice(&chunk[..n]);
+        }
+        Ok(())
+    };
+    let body = if chunked {
+        let mut body = Vec::new();
+        loop {
+            let line_end = loop {
+                if let Some(i) = find(&rest, b"\r\n") { break i; }
+                if rest.len() > 64 { return Err("bad chunk header"); }
+                let want = rest.len() + 1;
+                fill(&mut rest, want)?;
+            };
+            let size = core::str::from_utf8(&rest[..line_end]).ok()
+                .and_then(|s| usize::from_str_radix(s.split(';').next().unwrap_or("").trim(), 16).ok())
+                .ok_or("bad chunk header")?;
+            if body.len() + size > MAX_BODY { return Err("DoH response too large"); }
+            fill(&mut rest, line_end + 2 + size + 2)?;
+            body.extend_from_slice(&rest[line_end + 2..line_end + 2 + size]);
+            rest.drain(..line_end + 2 + size + 2);
+            // Trailers are not expected from a DoH server
+            if size == 0 { break body; }
+        }
+    } else {
+        let length = length.ok_or("HTTP response without length")?;
+        if length > MAX_BODY { return Err("DoH response too large"); }
+        fill(&mut rest, length)?;
+        rest.truncate(length);
+        rest
+    };
+    Ok((status, body, keep_alive))
+}
+
+/// One query over an open DoH connection.
+fn doh_exchange(conn: &mut TlsStream, host: &str, query: &[u8]) -> Result<(Vec<u8>, bool), &'static str> {
+    let mut req = alloc::format!(
+        "POST /dns-query HTTP/1.1\r\nHost: {}\r\nContent-Type: application/dns-message\r\n\
+         Accept: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+        host, query.len()).into_bytes();
+    req.extend_from_slice(query);
+    conn.write(&req)?;
+    let (status, body, keep_alive) = read_http(conn)?;
+    if status != 200 { return Err("DoH server refused the query"); }
+    Ok((body, keep_alive))
+}
+
+/// Query `server` over DoH, reusing the open connection if it goes there.
+fn doh_query(server: &DohServer, query: &[u8]) -> Result<Vec<u8>, &'static str> {
+    // The connection is taken out while in use so the lock is not held
+    // across network waits; a concurrent query opens its own.
+    let open = RESOLVER.lock().conn.take();
+    let reused = match open {
+        Some((addr, conn)) if addr == server.addr && !conn.is_closed() => Some(conn),
+        _ => None,
+    };
+    let fresh = reused.is_none();
+    let mut conn = match reused {
+        Some(c) => c,
+        None => tls::connect(server.addr, &server.host, &["http/1.1"])?,
+    };
+    let (body, keep_alive) = match doh_exchange(&mut conn, &server.host, query) {
+        Ok(r) => r,
+        // A reused connection may have been closed by the server while idle
+        Err(_) if !fresh => {
+            conn = tls::connect(server.addr, &server.host, &["http/1.1"])?;
+            doh_exchange(&mut conn, &server.host, query)?
+        }
+        Err(e) => return Err(e),
+    };
+    if keep_alive {
+        let _replaced = RESOLVER.lock().conn.replace((server.addr, conn));
+    }
+    Ok(body)
+}
+
+fn udp_exchange(sock: udp::UdpHandle, server: SocketAddr, query: &[u8]) -> Result<Vec<u8>, &'static str> {
+    udp::connect(sock, server)?;
+    udp::send(sock, query)?;
+    let mut buf = vec![0u8; 1500];
+    let deadline = now() + QUERY_TIMEOUT_MS;
+    loop {
+        let (n, _) = udp::recv_from(sock, &mut buf, Some(deadline.saturating_sub(now())))?;
+        // Only the answer to this query's ID counts
+        if n >= 2 && buf[..2] == query[..2] { buf.truncate(n); return Ok(buf); }
+    }
+}
+
+fn tcp_exchange(conn: tcp::TcpHandle, query: &[u8]) -> Result<Vec<u8>, &'static str> {
+    tcp::wait_established(conn, QUERY_TIMEOUT_MS)?;
+    let mut framed = (query.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(query);
+    tcp::send_all(conn, &framed, QUERY_TIMEOUT_MS)?;
+    let mut msg = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while msg.len() < 2 || msg.len() < 2 + u16::from_be_bytes([msg[0], msg[1]]) as usize {
+        let n = tcp::recv_timeout(conn, &mut chunk, QUERY_TIMEOUT_MS)?;
+        if n == 0 { return Err("connection closed"); }
+        msg.extend_from_slice(&chunk[..n]);
+    }
+    let len = u16::from_be_bytes([msg[0], msg[1]]) as usize;
+    Ok(msg[2..2 + len].to_vec())
+}
+
+/// Query `server` over UDP, retrying over TCP if the answer is truncated.
+fn plain_query(server: SocketAddr, query: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let any = match server.ip {
+        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    let sock = udp::bind(SocketAddr::new(any, 0))?;
+    let result = udp_exchange(sock, server, query);
+    let _ = udp::close(sock);
+    let msg = result?;
+    if msg.len() < 4 || u16::from_be_bytes([msg[2], msg[3]]) & FLAG_TC == 0 { return Ok(msg); }
+    let conn = tcp::connect(server)?;
+    let result = tcp_exchange(conn, query);
+    let _ = tcp::close(conn);
+    result
+}
+
+// ─── resolution ───────────────────────────────────────────────────────────────
+
+/// Ask the servers `policy` allows until one answers `qtype` for `name`
+/// with something other than a server failure.
+fn exchange(name: &str, qtype: u16) -> Result<Message, &'static str> {
+    let policy = policy();
+    let mut last = "no DNS servers";
+    let answered = |msg: Result<Vec<u8>, &'static str>, id: u16| {
+        let msg = parse_message(&msg?)?;
+        if msg.id != id { return Err("mismatched DNS response"); }
+        if msg.rcode != 0 && msg.rcode != RCODE_NXDOMAIN { return Err("DNS server failure"); }
+        Ok(msg)
+    };
+    if policy != Policy::PlainOnly {
+        // RFC 8484 asks for ID 0, which also caches better
+        let msg = encode_query(0, name, qtype)?;
+        for server in order(doh_servers(), |s| s.addr) {
+            match answered(doh_query(&server, &msg), 0) {
+                Ok(m) => { record(server.addr, true); return Ok(m); }
+                Err(e) => { record(server.addr, false); last = e; }
+            }
+        }
+    }
+    if policy != Policy::DohOnly {
+        let id = crate::entropy::next_u32() as u16;
+        let msg = encode_query(id, name, qtype)?;
+        let servers: Vec<SocketAddr> = super::dns_servers().into_iter().map(|ip| SocketAddr::new(ip, 53)).collect();
+        for server in order(servers, |s| *s) {
+            match answered(plain_query(server, &msg), id) {
+                Ok(m) => { record(server, true); return Ok(m); }
+                Err(e) => { record(server, false); last = e; }
+            }
+        }
+    }
+    Err(last)
+}
+
+/// The answer to `qtype` for `name`, and what DNSSEC says of it.
+fn query(name: &str, qtype: u16) -> Result<(Answer, Validation), &'static str> {
+    let msg = exchange(name, qtype)?;
+    let validation = match dnssec() {
+        Dnssec::Off => Validation::Indeterminate,
+        _ => dnssec::validate(name, qtype, &msg, &mut exchange),
+    };
+    Ok((answer(&msg, name)?, validation))
+}
+
+fn cached(name: &str) -> Option<(Answer, Validation)> {
+    let mut r = RESOLVER.lock();
+    let now = now();
+    r.cache.retain(|e| e.expires > now);
+    r.cache.iter().find(|e| e.name == name).map(|e| (e.answer.clone(), e.validation))
+}
+
+fn insert(name: &str, answer: Answer, validation: Validation, ttl: u32) {
+    let mut r = RESOLVER.lock();
+    r.cache.retain(|e| e.name != name);
+    if r.cache.len() >= CACHE_MAX {
+        // Evict whatever would expire first
+        if let Some(i) = r.cache.iter().enumerate().min_by_key(|(_, e)| e.expires).map(|(i, _)| i) { r.cache.swap_remove(i); }
+    }
+    r.cache.push(CacheEntry { name: name.to_string(), answer, validation, expires: now() + ttl as u64 * 1000 });
+}
+
+/// The outcome of looking a name up.
+#[derive(Debug, Clone)]
+pub struct Resolution {
+    /// Addresses, IPv6 first when we have a route for them.
+    pub result:     Result<Vec<IpAddr>, &'static str>,
+    /// The worse of the A and AAAA answers'; secure for literals and
+    /// localhost, which need no vouching for.
+    pub validation: Validation,
+}
+
+/// Look `name` up, reporting how far DNSSEC vouches for the answer.  An
+/// answer the mode does not accept is an error, and is cached as one.
+pub fn lookup(name: &str) -> Resolution {
+    let known = |result| Resolution { result, validation: Validation::Secure };
+    if let Some(ip) = IpAddr::parse(name) { return known(Ok(vec![ip])); }
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    if name == "localhost" { return known(Ok(vec![IpAddr::V6(Ipv6Addr::LOCALHOST), IpAddr::V4(Ipv4Addr::LOCALHOST)])); }
+    let pid = crate::process::current_pid();
+    if !netns::online(pid) {
+        return Resolution { result: Err("network disabled for app"), validation: Validation::Indeterminate };
+    }
+    // The proxy resolves a proxied app's names; asking here would leak them
+    if proxy::is_proxied(pid) {
+        return Resolution { result: Err("name lookups go through the proxy"), validation: Validation::Indeterminate };
+    }
+
+    let (answer, validation) = match cached(&name) {
+        Some(a) => a,
+        None => {
+            // Queries are networkd's, sharing its connection to the server
+            let (v4, v6) = networkd::run(|| (query(&name, TYPE_A), query(&name, TYPE_AAAA)));
+            if let (Err(e), Err(_)) = (&v4, &v6) { return Resolution { result: Err(*e), validation: Validation::Indeterminate }; }
+            let (mut addrs, mut ttl, mut nxdomain, mut validation) = (Vec::new(), MAX_TTL, false, Validation::Secure);
+            for (a, v) in [v4, v6].into_iter().flatten() {
+                validation = validation.max(v);
+                match a {
+                    Answer::Records(mut r, t) => { ttl = ttl.min(t); addrs.append(&mut r); }
+                    Answer::NxDomain(t) => { ttl = ttl.min(t); nxdomain = true; }
+                }
+            }
+            // A bogus answer may be an attack in progress, so is not
+            // remembered for long
+            if validation == Validation::Bogus { ttl = ttl.min(NEGATIVE_TTL); }
+            let answer = if addrs.is_empty() && nxdomain { Answer::NxDomain(ttl) } else { Answer::Records(addrs, ttl) };
+            insert(&name, answer.clone(), validation, ttl);
+            (answer, validation)
+        }
+    };
+    let refused = match dnssec() {
+        Dnssec::Off      => false,
+        Dnssec::Validate => validation == Validation::Bogus,
+        Dnssec::Require  => validation != Validation::Secure,
+    };
+    let result = match answer {
+        _ if refused => Err("DNSSEC validation failed"),
+        Answer::Records(addrs, _) if !addrs.is_empty() => {
+            let (mut routed, unrouted): (Vec<IpAddr>, Vec<IpAddr>) = addrs.into_iter().partition(|a| super::source_for(*a).is_some());
+            routed.sort_by_key(|a| !matches!(a, IpAddr::V6(_)));
+            routed.extend(unrouted);
+            Ok(routed)
+        }
+        Answer::Records(..) => Err("no addresses for name"),
+        Answer::NxDomain(_) => Err("name does not exist"),
+    };
+    Resolution { result, validation }
+}
+
+/// The addresses of `name`, IPv6 first when we have a route for them.
+pub fn resolve(name: &str) -> Result<Vec<IpAddr>, &'static str> {
+    lookup(name).result
+}
+
+/// Forget cached answers, and the zone keys they were validated with.
+pub fn flush_cache() {
+    RESOLVER.lock().cache.clear();
+    dnssec::flush();
+}
+
+/// Cached names with their addresses, validation and seconds left to live.
+pub fn cache() -> Vec<(String, Vec<IpAddr>, Validation, u64)> {
+    let r = RESOLVER.lock();
+    let now = now();
+    r.cache.iter().filter(|e| e.expires > now).map(|e| {
+        let addrs = match &e.answer { Answer::Records(a, _) => a.clone(), Answer::NxDomain(_) => Vec::new() };
+        (e.name.clone(), addrs, e.validation, (e.expires - now) / 1000)
+    }).collect()
+}
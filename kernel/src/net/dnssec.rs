@@ -0,0 +1,615 @@
+//! DNSSEC Validation
+//! Checks answers against the chain of trust from the root zone's keys
+//! (RFC 4033–4035): an RRset's RRSIG must verify under a DNSKEY of the zone
+//! that signed it, that zone's DNSKEY RRset under a key its parent's DS
+//! RRset vouches for, and so on up to the root trust anchor.  That a name
+//! or type does not exist is proven by the zone's NSEC or NSEC3 (RFC 5155)
+//! records, checked the same way.  An answer without signatures passes as
+//! insecure only if some zone above it provably has no DS.
+//!
+//! Signature and digest algorithms are looked up by number, so supporting
+//! another, a post-quantum one included once it is assigned a number, is a
+//! row in `ALGORITHMS` or `DIGESTS`.  A zone signed only with algorithms
+//! not listed is treated as unsigned (RFC 4035 5.2), as is one whose NSEC3
+//! records take more than `NSEC3_MAX_ITERATIONS` (RFC 9276).
+//!
+//! Zone keys, and what is known of zones without them, are cached for
+//! their TTL.  Not implemented: proving that no wildcard matched a name
+//! that does not exist, or that a wildcard-expanded answer's own name does
+//! not.
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use spin::Mutex;
+
+use super::dns::{self, Message, Record, TYPE_CNAME, TYPE_NS, TYPE_SOA};
+use crate::crypto::ecdsa::{self, Curve};
+use crate::crypto::x509::Hash;
+use crate::crypto::{rsa, sha1, sha2};
+
+pub const TYPE_DS:     u16 = 43;
+pub const TYPE_RRSIG:  u16 = 46;
+pub const TYPE_NSEC:   u16 = 47;
+pub const TYPE_DNSKEY: u16 = 48;
+pub const TYPE_NSEC3:  u16 = 50;
+const CLASS_IN: u16 = 1;
+
+const RCODE_NXDOMAIN: u8 = 3;
+
+const FLAG_ZONE:     u16 = 0x0100;
+const FLAG_REVOKE:   u16 = 0x0080;
+const NSEC3_SHA1:    u8  = 1;
+const NSEC3_OPT_OUT: u8  = 0x01;
+/// NSEC3 hash iterations beyond which a zone counts as unsigned.
+const NSEC3_MAX_ITERATIONS: u16 = 150;
+
+/// Zones being fetched at once while validating one answer.
+const MAX_DEPTH:  usize = 32;
+const MAX_CNAMES: usize = 8;
+const KEYS_MAX:   usize = 64;
+/// TTL bounds for cached zone keys, seconds; bogus zones are retried
+/// after the least.
+const MIN_TTL:    u32 = 60;
+const MAX_TTL:    u32 = 86_400;
+
+/// The root zone's key-signing keys as DS records: KSK-2017 and KSK-2024.
+const TRUST_ANCHORS: &[(u16, u8, u8, &str)] = &[
+    (20326, 8, 2, "E06D44B80B8F1D39A95C0B0D7C65D08458E880409BBC683457104237C7F8EC8D"),
+    (38696, 8, 2, "683D2D0ACB8C9B712A1948B27F741219298D0A450D612C483AF444A4C0FB2B16"),
+];
+
+/// What DNSSEC says about an answer (RFC 4033 5), best first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Validation {
+    /// Signatures chain to the trust anchor.
+    Secure,
+    /// From a zone provably not signed.
+    Insecure,
+    /// Not known: validation is off, or keys could not be fetched.
+    Indeterminate,
+    /// Signatures missing, expired or wrong where the chain says there
+    /// should be good ones.
+    Bogus,
+}
+
+impl Validation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Validation::Secure        => "secure",
+            Validation::Insecure      => "insecure",
+            Validation::Indeterminate => "indeterminate",
+            Validation::Bogus         => "bogus",
+        }
+    }
+}
+
+// ─── algorithms ───────────────────────────────────────────────────────────────
+
+/// Checks a signature given a DNSKEY's public key field, the signed data
+/// and the signature.
+type Verify = fn(&[u8], &[u8], &[u8]) -> Result<(), &'static str>;
+
+/// A signature algorithm by its DNSSEC number (RFC 8624).
+struct Algorithm {
+    number: u8,
+    verify: Verify,
+}
+
+const ALGORITHMS: &[Algorithm] = &[
+    Algorithm { number: 8,  verify: rsa_sha256 },
+    Algorithm { number: 13, verify: ecdsa_p256 },
+    Algorithm { number: 14, verify: ecdsa_p384 },
+];
+
+/// DS digest types.
+const DIGESTS: &[(u8, Hash)] = &[(2, Hash::Sha256), (4, Hash::Sha384)];
+
+fn algorithm(number: u8) -> Option<&'static Algorithm> {
+    ALGORITHMS.iter().find(|a| a.number == number)
+}
+
+fn digest(ty: u8) -> Option<Hash> {
+    DIGESTS.iter().find(|d| d.0 == ty).map(|d| d.1)
+}
+
+/// RSA keys are the exponent's length, one byte or zero and two, then the
+/// exponent and modulus (RFC 3110).
+fn rsa_sha256(key: &[u8], data: &[u8], sig: &[u8]) -> Result<(), &'static str> {
+    let (e, n) = match key {
+        [0, hi, lo, rest @ ..] => rest.split_at_checked(u16::from_be_bytes([*hi, *lo]) as usize),
+        [len, rest @ ..]       => rest.split_at_checked(*len as usize),
+        []                     => None,
+    }.ok_or("bad RSA key")?;
+    rsa::verify_pkcs1(n, e, Hash::Sha256, &sha2::sha256(data), sig)
+}
+
+/// ECDSA keys and signatures are bare coordinates, x ‖ y and r ‖ s
+/// (RFC 6605).
+fn ecdsa_sig(curve: Curve, hash: Hash, key: &[u8], data: &[u8], sig: &[u8]) -> Result<(), &'static str> {
+    let len = curve.scalar_len();
+    if key.len() != 2 * len || sig.len() != 2 * len { return Err("bad ECDSA key or signature"); }
+    let mut point = vec![4];
+    point.extend_from_slice(key);
+    ecdsa::verify(curve, &point, &hash.digest(data), &sig[..len], &sig[len..])
+}
+
+fn ecdsa_p256(key: &[u8], data: &[u8], sig: &[u8]) -> Result<(), &'static str> {
+    ecdsa_sig(Curve::P256, Hash::Sha256, key, data, sig)
+}
+
+fn ecdsa_p384(key: &[u8], data: &[u8], sig: &[u8]) -> Result<(), &'static str> {
+    ecdsa_sig(Curve::P384, Hash::Sha384, key, data, sig)
+}
+
+// ─── names ────────────────────────────────────────────────────────────────────
+
+/// `name` in wire form, lower-cased: its canonical form (RFC 4034 6.2).
+pub fn wire_name(name: &str) -> Vec<u8> {
+    let mut w = Vec::with_capacity(name.len() + 2);
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        w.push(label.len() as u8);
+        w.extend(label.bytes().map(|b| b.to_ascii_lowercase()));
+    }
+    w.push(0);
+    w
+}
+
+fn labels(name: &str) -> usize {
+    name.split('.').filter(|l| !l.is_empty()).count()
+}
+
+/// `name` without its first label; the root's parent is the root.
+fn parent(name: &str) -> &str {
+    name.split_once('.').map_or("", |(_, p)| p)
+}
+
+/// Whether `name` is `zone` or inside it.
+fn is_subdomain(name: &str, zone: &str) -> bool {
+    zone.is_empty() || name == zone
+        || (name.len() > zone.len() && name.ends_with(zone) && name.as_bytes()[name.len() - zone.len() - 1] == b'.')
+}
+
+/// Canonical name order (RFC 4034 6.1): label by label from the right.
+fn canonical_cmp(a: &str, b: &str) -> Ordering {
+    fn labels(n: &str) -> impl Iterator<Item = &[u8]> {
+        n.rsplit('.').filter(|l| !l.is_empty()).map(str::as_bytes)
+    }
+    labels(a).cmp(labels(b))
+}
+
+/// Whether the span from `owner` to `next` holds `name`; the zone's last
+/// span wraps round to its apex.
+fn covers(owner: &str, name: &str, next: &str) -> bool {
+    canonical_cmp(owner, name).is_lt()
+        && (canonical_cmp(name, next).is_lt() || canonical_cmp(next, owner).is_le())
+}
+
+// ─── records ──────────────────────────────────────────────────────────────────
+
+fn be16(b: &[u8], i: usize) -> u16 {
+    u16::from_be_bytes([b[i], b[i + 1]])
+}
+
+fn be32(b: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]])
+}
+
+fn rrset<'a>(section: &'a [Record], name: &str, ty: u16) -> Vec<&'a Record> {
+    section.iter().filter(|r| r.name == name && r.ty == ty).collect()
+}
+
+fn min_ttl(records: &[&Record]) -> u32 {
+    records.iter().map(|r| r.ttl).min().unwrap_or(MIN_TTL)
+}
+
+struct Rrsig<'a> {
+    covered:    u16,
+    algorithm:  u8,
+    labels:     u8,
+    ttl:        u32,
+    expiration: u32,
+    inception:  u32,
+    key_tag:    u16,
+    signer:     String,
+    /// The RDATA ahead of the signature, signer in canonical form: what
+    /// the signature covers before the records.
+    header:     Vec<u8>,
+    signature:  &'a [u8],
+}
+
+fn rrsig(rdata: &[u8]) -> Option<Rrsig<'_>> {
+    if rdata.len() < 19 { return None; }
+    let (signer, end) = dns::read_name(rdata, 18)?;
+    let signer = signer.to_ascii_lowercase();
+    let mut header = rdata[..18].to_vec();
+    header.extend(wire_name(&signer));
+    Some(Rrsig {
+        covered: be16(rdata, 0), algorithm: rdata[2], labels: rdata[3], ttl: be32(rdata, 4),
+        expiration: be32(rdata, 8), inception: be32(rdata, 12), key_tag: be16(rdata, 16),
+        signer, header, signature: &rdata[end..],
+    })
+}
+
+/// RFC 4034 appendix B.
+fn key_tag(dnskey: &[u8]) -> u16 {
+    let mut ac: u32 = 0;
+    for (i, b) in dnskey.iter().enumerate() {
+        ac += if i & 1 == 0 { (*b as u32) << 8 } else { *b as u32 };
+    }
+    ac += (ac >> 16) & 0xFFFF;
+    ac as u16
+}
+
+/// Whether DS record `ds` at `owner` is the digest of `dnskey`.
+fn ds_matches(ds: &[u8], owner: &str, dnskey: &[u8]) -> bool {
+    if ds.len() < 5 || dnskey.len() < 4 { return false; }
+    let Some(hash) = digest(ds[3]) else { return false };
+    if be16(ds, 0) != key_tag(dnskey) || ds[2] != dnskey[3] { return false; }
+    let mut data = wire_name(owner);
+    data.extend_from_slice(dnskey);
+    hash.digest(&data) == ds[4..]
+}
+
+fn anchor(tag: u16, algorithm: u8, digest_type: u8, digest: &str) -> Vec<u8> {
+    let mut ds = tag.to_be_bytes().to_vec();
+    ds.extend_from_slice(&[algorithm, digest_type]);
+    ds.extend(digest.as_bytes().chunks(2).map(|c| {
+        let d = |x: u8| (x as char).to_digit(16).unwrap_or(0) as u8;
+        (d(c[0]) << 4) | d(c[1])
+    }));
+    ds
+}
+
+/// Whether `bitmap`, NSEC-style type windows, has type `ty`.
+fn has_type(mut bitmap: &[u8], ty: u16) -> bool {
+    let (window, bit) = ((ty >> 8) as u8, (ty & 0xFF) as usize);
+    while let [w, len, rest @ ..] = bitmap {
+        let len = *len as usize;
+        if rest.len() < len { return false; }
+        if *w == window { return rest[..len].get(bit / 8).is_some_and(|b| b & (0x80 >> (bit % 8)) != 0); }
+        bitmap = &rest[len..];
+    }
+    false
+}
+
+struct Nsec3<'a> {
+    hash:       u8,
+    flags:      u8,
+    iterations: u16,
+    salt:       &'a [u8],
+    next:       String,
+    bitmap:     &'a [u8],
+}
+
+fn nsec3(rdata: &[u8]) -> Option<Nsec3<'_>> {
+    let salt_len = *rdata.get(4)? as usize;
+    let salt = rdata.get(5..5 + salt_len)?;
+    let hash_len = *rdata.get(5 + salt_len)? as usize;
+    let next = rdata.get(6 + salt_len..6 + salt_len + hash_len)?;
+    Some(Nsec3 {
+        hash: rdata[0], flags: rdata[1], iterations: be16(rdata, 2), salt,
+        next: base32hex(next), bitmap: &rdata[6 + salt_len + hash_len..],
+    })
+}
+
+/// Lower-case base32 with the extended hex alphabet, unpadded, as NSEC3
+/// owner names carry hashes.
+fn base32hex(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+    let mut out = String::with_capacity(data.len().div_ceil(5) * 8);
+    let (mut acc, mut bits) = (0u32, 0);
+    for b in data {
+        acc = (acc << 8) | *b as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[(acc >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 { out.push(ALPHABET[(acc << (5 - bits)) as usize & 31] as char); }
+    out
+}
+
+fn nsec3_hash(name: &str, salt: &[u8], iterations: u16) -> String {
+    let mut data = wire_name(name);
+    data.extend_from_slice(salt);
+    let mut h = sha1::sha1(&data);
+    for _ in 0..iterations {
+        let mut data = h.to_vec();
+        data.extend_from_slice(salt);
+        h = sha1::sha1(&data);
+    }
+    base32hex(&h)
+}
+
+/// What NSEC or NSEC3 records show about a name.
+enum Proof<'a> {
+    /// The name does not exist.
+    NoName,
+    /// The name exists with the types in this bitmap only.
+    Types(&'a [u8]),
+    /// An opt-out NSEC3 span covers the name, which may be an unsigned
+    /// delegation.
+    OptOut,
+}
+
+/// An NSEC record matching or covering `name`, and the records used.
+fn nsec_proof<'a>(name: &str, authority: &'a [Record]) -> Option<(Proof<'a>, Vec<&'a Record>)> {
+    for r in authority.iter().filter(|r| r.ty == TYPE_NSEC) {
+        let Some((next, end)) = dns::read_name(&r.rdata, 0) else { continue };
+        if r.name == name { return Some((Proof::Types(&r.rdata[end..]), vec![r])); }
+        if covers(&r.name, name, &next.to_ascii_lowercase()) { return Some((Proof::NoName, vec![r])); }
+    }
+    None
+}
+
+/// An NSEC3 record matching `name`, or the closest encloser proof that
+/// it does not exist (RFC 5155 8.4), and the records used.
+fn nsec3_proof<'a>(name: &str, authority: &'a [Record]) -> Option<(Proof<'a>, Vec<&'a Record>)> {
+    let records: Vec<(&Record, Nsec3)> = authority.iter().filter(|r| r.ty == TYPE_NSEC3)
+        .filter_map(|r| nsec3(&r.rdata).map(|n| (r, n))).filter(|(_, n)| n.hash == NSEC3_SHA1).collect();
+    let (first, params) = records.first()?;
+    let zone = parent(&first.name);
+    if !is_subdomain(name, zone) { return None; }
+    let hash = |n: &str| nsec3_hash(n, params.salt, params.iterations);
+    let label = |r: &Record| r.name.split('.').next().unwrap_or("").to_string();
+    let matching = |h: &str| records.iter().find(|(r, _)| label(r) == h);
+    let covering = |h: &str| records.iter().find(|(r, n)| {
+        let owner = label(r);
+        owner.as_str() < h && (h < n.next.as_str() || n.next <= owner)
+    });
+    if let Some((r, n)) = matching(&hash(name)) { return Some((Proof::Types(n.bitmap), vec![r])); }
+    let (mut next_closer, mut encloser) = (name, parent(name));
+    loop {
+        if let Some((ce, _)) = matching(&hash(encloser)) {
+            let (cover, n) = covering(&hash(next_closer))?;
+            let proof = if n.flags & NSEC3_OPT_OUT != 0 { Proof::OptOut } else { Proof::NoName };
+            return Some((proof, vec![ce, cover]));
+        }
+        if encloser == zone { return None; }
+        (next_closer, encloser) = (encloser, parent(encloser));
+    }
+}
+
+// ─── zone keys ────────────────────────────────────────────────────────────────
+
+/// What validation found at a name that might start a zone.
+#[derive(Clone)]
+enum Keys {
+    /// A signed zone starts here; its DNSKEYs, validated.
+    Secure(Vec<Vec<u8>>),
+    /// No zone starts here: the name is part of its parent's.
+    NoCut,
+    /// The zone, and everything below it, is this.
+    Not(Validation),
+}
+
+struct CachedKeys {
+    zone:    String,
+    keys:    Keys,
+    expires: u64,
+}
+
+static KEYS: Mutex<Vec<CachedKeys>> = Mutex::new(Vec::new());
+
+/// Send a query and parse the response; the resolver's, so validation
+/// uses the same servers and transport as the answer.
+pub type Fetch<'a> = dyn FnMut(&str, u16) -> Result<Message, &'static str> + 'a;
+
+struct Validator<'a, 'b> {
+    fetch:   &'a mut Fetch<'b>,
+    /// Wall-clock seconds, for signature validity.
+    now:     u32,
+    /// Zones being worked out, outermost first.
+    pending: Vec<String>,
+}
+
+impl Validator<'_, '_> {
+    fn zone(&mut self, zone: &str) -> Keys {
+        let uptime = crate::arch::uptime_millis();
+        if let Some(k) = KEYS.lock().iter().find(|k| k.zone == zone && k.expires > uptime) { return k.keys.clone(); }
+        // A zone needed to work out itself is not one
+        if self.pending.iter().any(|p| p == zone) { return Keys::NoCut; }
+        if self.pending.len() >= MAX_DEPTH { return Keys::Not(Validation::Indeterminate); }
+        self.pending.push(zone.to_string());
+        let (keys, ttl) = if zone.is_empty() { self.root() } else { self.delegation(zone) };
+        self.pending.pop();
+        let ttl = match keys {
+            Keys::Not(Validation::Indeterminate) => return keys,
+            Keys::Not(Validation::Bogus)         => MIN_TTL,
+            _                                    => ttl.clamp(MIN_TTL, MAX_TTL),
+        };
+        let mut cache = KEYS.lock();
+        cache.retain(|k| k.zone != zone && k.expires > uptime);
+        if cache.len() >= KEYS_MAX {
+            if let Some(i) = cache.iter().enumerate().min_by_key(|(_, k)| k.expires).map(|(i, _)| i) { cache.swap_remove(i); }
+        }
+        cache.push(CachedKeys { zone: zone.to_string(), keys: keys.clone(), expires: uptime + ttl as u64 * 1000 });
+        keys
+    }
+
+    /// The root's keys: those the trust anchors name must sign the rest.
+    fn root(&mut self) -> (Keys, u32) {
+        let Ok(msg) = (self.fetch)("", TYPE_DNSKEY) else { return (Keys::Not(Validation::Indeterminate), 0) };
+        let dnskeys = rrset(&msg.answers, "", TYPE_DNSKEY);
+        let anchors: Vec<Vec<u8>> = TRUST_ANCHORS.iter().map(|a| anchor(a.0, a.1, a.2, a.3)).collect();
+        let trusted: Vec<&[u8]> = dnskeys.iter().map(|k| k.rdata.as_slice())
+            .filter(|k| anchors.iter().any(|a| ds_matches(a, "", k))).collect();
+        self.signed_keys(&msg, &dnskeys, &trusted)
+    }
+
+    /// A zone's keys, vouched for by its parent's DS records.
+    fn delegation(&mut self, zone: &str) -> (Keys, u32) {
+        let Ok(msg) = (self.fetch)(zone, TYPE_DS) else { return (Keys::Not(Validation::Indeterminate), 0) };
+        let ds = rrset(&msg.answers, zone, TYPE_DS);
+        if ds.is_empty() { return (self.no_ds(zone, &msg), min_ttl(&msg.authority.iter().collect::<Vec<_>>())); }
+        let ttl = min_ttl(&ds);
+        match self.check(&ds, &msg.answers) {
+            Validation::Secure => {}
+            v => return (Keys::Not(v), ttl),
+        }
+        let usable: Vec<&[u8]> = ds.iter().map(|d| d.rdata.as_slice())
+            .filter(|d| d.len() > 4 && algorithm(d[2]).is_some() && digest(d[3]).is_some()).collect();
+        if usable.is_empty() { return (Keys::Not(Validation::Insecure), ttl); }
+        let Ok(msg) = (self.fetch)(zone, TYPE_DNSKEY) else { return (Keys::Not(Validation::Indeterminate), 0) };
+        let dnskeys = rrset(&msg.answers, zone, TYPE_DNSKEY);
+        let trusted: Vec<&[u8]> = dnskeys.iter().map(|k| k.rdata.as_slice())
+            .filter(|k| usable.iter().any(|d| ds_matches(d, zone, k))).collect();
+        self.signed_keys(&msg, &dnskeys, &trusted)
+    }
+
+    /// A DNSKEY RRset is good if one of the `trusted` keys signs it.
+    fn signed_keys(&self, msg: &Message, dnskeys: &[&Record], trusted: &[&[u8]]) -> (Keys, u32) {
+        let Some(first) = dnskeys.first() else { return (Keys::Not(Validation::Bogus), 0) };
+        let signed = msg.answers.iter().filter(|r| r.ty == TYPE_RRSIG && r.name == first.name)
+            .filter_map(|r| rrsig(&r.rdata)).filter(|s| s.covered == TYPE_DNSKEY && s.signer == first.name)
+            .any(|s| trusted.iter().any(|k| verify(dnskeys, &s, k, self.now).is_ok()));
+        if !signed { return (Keys::Not(Validation::Bogus), 0); }
+        (Keys::Secure(dnskeys.iter().map(|k| k.rdata.clone()).collect()), min_ttl(dnskeys))
+    }
+
+    /// The parent answered that `zone` has no DS; its proof says whether
+    /// that is an unsigned delegation or no zone at all.
+    fn no_ds(&mut self, zone: &str, msg: &Message) -> Keys {
+        let Some((proof, v)) = self.denial(zone, &msg.authority) else {
+            return match self.unsigned(parent(zone)) {
+                Validation::Secure => Keys::Not(Validation::Bogus),
+                v                  => Keys::Not(v),
+            };
+        };
+        if v != Validation::Secure { return Keys::Not(v); }
+        match proof {
+            // The child's own apex record, or a DS after all, proves nothing
+            Proof::Types(types) if has_type(types, TYPE_DS) || has_type(types, TYPE_SOA) => Keys::Not(Validation::Bogus),
+            Proof::Types(types) if has_type(types, TYPE_NS) => Keys::Not(Validation::Insecure),
+            Proof::OptOut => Keys::Not(Validation::Insecure),
+            Proof::Types(_) | Proof::NoName => Keys::NoCut,
+        }
+    }
+
+    /// How far the signatures on `rrset`, found in `section`, go.
+    fn check(&mut self, rrset: &[&Record], section: &[Record]) -> Validation {
+        let (name, ty) = (&rrset[0].name, rrset[0].ty);
+        // A DS is its parent's to sign
+        let sigs: Vec<Rrsig> = section.iter().filter(|r| r.ty == TYPE_RRSIG && r.name == *name)
+            .filter_map(|r| rrsig(&r.rdata))
+            .filter(|s| s.covered == ty && is_subdomain(name, &s.signer) && !(ty == TYPE_DS && s.signer == *name))
+            .collect();
+        if sigs.is_empty() { return self.unsigned(name); }
+        let mut best = Validation::Bogus;
+        for sig in &sigs {
+            match self.zone(&sig.signer) {
+                Keys::Secure(keys) => if keys.iter().any(|k| verify(rrset, sig, k, self.now).is_ok()) { return Validation::Secure },
+                Keys::Not(v) => best = best.min(v),
+                Keys::NoCut => {}
+            }
+        }
+        best
+    }
+
+    /// Records at `name` came unsigned: insecure if a zone on the way down
+    /// to it is, bogus if every one is signed.
+    fn unsigned(&mut self, name: &str) -> Validation {
+        let labels: Vec<&str> = name.split('.').filter(|l| !l.is_empty()).collect();
+        for i in (0..=labels.len()).rev() {
+            match self.zone(&labels[i..].join(".")) {
+                Keys::Secure(_) | Keys::NoCut => {}
+                Keys::Not(v) => return v,
+            }
+        }
+        Validation::Bogus
+    }
+
+    /// What the NSEC or NSEC3 records in `authority` prove about `name`,
+    /// and how far their signatures go; None if they prove nothing.
+    fn denial<'a>(&mut self, name: &str, authority: &'a [Record]) -> Option<(Proof<'a>, Validation)> {
+        let costly = authority.iter().filter(|r| r.ty == TYPE_NSEC3).filter_map(|r| nsec3(&r.rdata))
+            .any(|n| n.iterations > NSEC3_MAX_ITERATIONS);
+        if costly { return Some((Proof::NoName, Validation::Insecure)); }
+        let (proof, used) = nsec_proof(name, authority).or_else(|| nsec3_proof(name, authority))?;
+        let v = used.iter().map(|r| self.check(&[*r], authority)).max().unwrap_or(Validation::Bogus);
+        Some((proof, v))
+    }
+
+    /// How far DNSSEC vouches for `msg`, the answer to `qtype` at `name`.
+    fn response(&mut self, name: &str, qtype: u16, msg: &Message) -> Validation {
+        let mut target = name.to_string();
+        let mut worst = Validation::Secure;
+        for _ in 0..MAX_CNAMES {
+            let cname = rrset(&msg.answers, &target, TYPE_CNAME);
+            let Some(next) = cname.first().and_then(|c| dns::read_name(&c.rdata, 0)) else { break };
+            worst = worst.max(self.check(&cname, &msg.answers));
+            target = next.0;
+        }
+        let answer = rrset(&msg.answers, &target, qtype);
+        if !answer.is_empty() { return worst.max(self.check(&answer, &msg.answers)); }
+        // Nothing of the type: the zone must prove there is none
+        let Some((proof, v)) = self.denial(&target, &msg.authority) else {
+            return worst.max(match self.unsigned(&target) {
+                Validation::Secure => Validation::Bogus,
+                v                  => v,
+            });
+        };
+        if v != Validation::Secure { return worst.max(v); }
+        let nxdomain = msg.rcode == RCODE_NXDOMAIN;
+        let proven = match proof {
+            Proof::NoName       => nxdomain,
+            Proof::Types(types) => !nxdomain && !has_type(types, qtype) && !has_type(types, TYPE_CNAME),
+            Proof::OptOut       => return worst.max(Validation::Insecure),
+        };
+        if proven { worst } else { Validation::Bogus }
+    }
+}
+
+/// Check `sig` over `rrset` with DNSKEY `key` at wall-clock second `now`.
+fn verify(rrset: &[&Record], sig: &Rrsig, key: &[u8], now: u32) -> Result<(), &'static str> {
+    let (name, ty) = (&rrset[0].name, rrset[0].ty);
+    if key.len() < 4 || key[2] != 3 || key[3] != sig.algorithm || key_tag(key) != sig.key_tag { return Err("wrong key"); }
+    let flags = be16(key, 0);
+    if flags & FLAG_ZONE == 0 || flags & FLAG_REVOKE != 0 { return Err("not a zone key"); }
+    // Serial number arithmetic (RFC 1982), as the times wrap
+    if (now.wrapping_sub(sig.inception) as i32) < 0 || (sig.expiration.wrapping_sub(now) as i32) < 0 {
+        return Err("signature expired or not yet valid");
+    }
+    let alg = algorithm(sig.algorithm).ok_or("unsupported algorithm")?;
+    let owner_labels = labels(name);
+    let sig_labels = sig.labels as usize;
+    if sig_labels > owner_labels { return Err("bad RRSIG labels"); }
+    // An answer expanded from a wildcard is signed as the wildcard
+    let mut owner = Vec::new();
+    if sig_labels < owner_labels { owner.extend_from_slice(&[1, b'*']); }
+    let suffix: Vec<&str> = name.split('.').skip(owner_labels - sig_labels).collect();
+    owner.extend(wire_name(&suffix.join(".")));
+
+    let mut rdatas: Vec<&[u8]> = rrset.iter().map(|r| r.rdata.as_slice()).collect();
+    rdatas.sort();
+    rdatas.dedup();
+    let mut data = sig.header.clone();
+    for rdata in rdatas {
+        data.extend_from_slice(&owner);
+        data.extend_from_slice(&ty.to_be_bytes());
+        data.extend_from_slice(&CLASS_IN.to_be_bytes());
+        data.extend_from_slice(&sig.ttl.to_be_bytes());
+        data.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        data.extend_from_slice(rdata);
+    }
+    (alg.verify)(&key[4..], &data, sig.signature)
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// How far DNSSEC vouches for `msg`, the response to a `qtype` query for
+/// `name`: the RRsets answering it, or its proof that there are none.
+/// Keys and DS records are asked for through `fetch`.
+pub fn validate(name: &str, qtype: u16, msg: &Message, fetch: &mut Fetch) -> Validation {
+    let now = (crate::alarm::rtc_now_ns() / 1_000_000_000) as u32;
+    let mut v = Validator { fetch, now, pending: Vec::new() };
+    v.response(&name.trim_end_matches('.').to_ascii_lowercase(), qtype, msg)
+}
+
+/// Forget the zone keys learned.
+pub fn flush() {
+    KEYS.lock().clear();
+}
@@ -0,0 +1,302 @@
+//! ICMP
+//! Echo replies, error reporting and raw ICMP sockets (RFC 792).  Echo
+//! requests to us are answered; a datagram no socket or protocol wants is
+//! answered with destination unreachable; unreachable and time-exceeded
+//! reports about our own traffic are passed to the TCP or UDP endpoint
+//! that sent it.  A raw socket, which needs the Network capability with
+//! CONTROL rights, sends ICMP messages of its own, subject to its app's
+//! namespace, and receives a copy of the echo replies and errors that
+//! concern the echo identifiers it has sent; `ping` is built on it.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{accounting, checksum_add, checksum_finish, ipv4, netns, IpAddr, Ipv4Addr, SocketAddr};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::process::{ProcessId, WaitQueue};
+
+pub const TYPE_ECHO_REPLY:       u8 = 0;
+pub const TYPE_DEST_UNREACHABLE: u8 = 3;
+pub const TYPE_ECHO_REQUEST:     u8 = 8;
+pub const TYPE_TIME_EXCEEDED:    u8 = 11;
+
+pub const CODE_NET_UNREACHABLE:      u8 = 0;
+pub const CODE_HOST_UNREACHABLE:     u8 = 1;
+pub const CODE_PROTOCOL_UNREACHABLE: u8 = 2;
+pub const CODE_PORT_UNREACHABLE:     u8 = 3;
+pub const CODE_FRAGMENTATION_NEEDED: u8 = 4;
+
+pub const HEADER_LEN: usize = 8;
+/// Messages a raw socket holds before new arrivals are dropped.
+const RX_QUEUE:       usize = 64;
+/// Errors we send per second, so a flood of stray datagrams cannot turn
+/// us into an amplifier.
+const ERRORS_PER_SEC: u32   = 10;
+
+fn checksum(msg: &[u8]) -> u16 {
+    checksum_finish(checksum_add(0, msg))
+}
+
+/// Fill in the checksum of an ICMP message.
+fn seal(mut msg: Vec<u8>) -> Vec<u8> {
+    msg[2..4].copy_from_slice(&[0, 0]);
+    let sum = checksum(&msg);
+    msg[2..4].copy_from_slice(&sum.to_be_bytes());
+    msg
+}
+
+/// An echo request with `payload`.
+pub fn echo_request(ident: u16, seq: u16, payload: &[u8]) -> Vec<u8> {
+    let mut m = Vec::with_capacity(HEADER_LEN + payload.len());
+    m.extend_from_slice(&[TYPE_ECHO_REQUEST, 0, 0, 0]);
+    m.extend_from_slice(&ident.to_be_bytes());
+    m.extend_from_slice(&seq.to_be_bytes());
+    m.extend_from_slice(payload);
+    seal(m)
+}
+
+// ─── errors ───────────────────────────────────────────────────────────────────
+
+/// (second of uptime, errors sent in it)
+static ERROR_RATE: Mutex<(u64, u32)> = Mutex::new((0, 0));
+
+/// Tell the sender of `packet`, an IP packet we received, that it could
+/// not be delivered.  Never sent about ICMP errors, broadcasts or
+/// fragments, per RFC 1122.
+pub(super) fn unreachable(code: u8, packet: &[u8]) {
+    error(TYPE_DEST_UNREACHABLE, code, [0; 4], packet);
+}
+
+/// Tell the sender of `packet`, which we were forwarding, that its TTL
+/// ran out.
+pub(super) fn time_exceeded(packet: &[u8]) {
+    error(TYPE_TIME_EXCEEDED, 0, [0; 4], packet);
+}
+
+/// Tell the sender of `packet`, which we were forwarding, that it does
+/// not fit the next hop's `mtu` unfragmented.
+pub(super) fn fragmentation_needed(mtu: u16, packet: &[u8]) {
+    let m = mtu.to_be_bytes();
+    error(TYPE_DEST_UNREACHABLE, CODE_FRAGMENTATION_NEEDED, [0, 0, m[0], m[1]], packet);
+}
+
+fn error(ty: u8, code: u8, rest: [u8; 4], packet: &[u8]) {
+    let Some((h, payload)) = ipv4::parse(packet) else { return };
+    if h.dst == Ipv4Addr::BROADCAST || h.src == Ipv4Addr::UNSPECIFIED || h.src == Ipv4Addr::BROADCAST { return; }
+    if h.proto == ipv4::PROTO_ICMP && payload.first().is_some_and(|&t| t != TYPE_ECHO_REQUEST && t != TYPE_ECHO_REPLY) {
+        return;
+    }
+    {
+        let second = crate::arch::uptime_millis() / 1000;
+        let mut rate = ERROR_RATE.lock();
+        if rate.0 != second { *rate = (second, 0); }
+        if rate.1 >= ERRORS_PER_SEC { return; }
+        rate.1 += 1;
+    }
+    // The offending header and the first 8 bytes of its payload
+    let ihl = (packet[0] & 0x0F) as usize * 4;
+    let quoted = ihl + payload.len().min(8);
+    let mut m = Vec::with_capacity(HEADER_LEN + quoted);
+    m.extend_from_slice(&[ty, code, 0, 0]);
+    m.extend_from_slice(&rest);
+    m.extend_from_slice(&packet[..quoted]);
+    // A packet passing through is answered from our address towards its
+    // sender
+    let src = super::is_local(h.dst.into()).then_some(h.dst);
+    let _ = ipv4::send(src, h.src, ipv4::PROTO_ICMP, &seal(m));
+}
+
+/// Pass an error about a packet we sent to the endpoint that sent it.
+fn report(ty: u8, code: u8, rest: [u8; 4], quoted: &[u8]) {
+    // The quoted header may be longer than the payload it carries, so
+    // it is taken apart by hand rather than with `ipv4::parse`
+    if quoted.len() < ipv4::HEADER_LEN || quoted[0] >> 4 != 4 { return; }
+    let ihl = (quoted[0] & 0x0F) as usize * 4;
+    if quoted.len() < ihl + 8 { return; }
+    let src = Ipv4Addr([quoted[12], quoted[13], quoted[14], quoted[15]]);
+    let dst = Ipv4Addr([quoted[16], quoted[17], quoted[18], quoted[19]]);
+    let t = &quoted[ihl..];
+    let local  = SocketAddr::new(src, u16::from_be_bytes([t[0], t[1]]));
+    let remote = SocketAddr::new(dst, u16::from_be_bytes([t[2], t[3]]));
+    let error = match (ty, code) {
+        (TYPE_TIME_EXCEEDED, _)                   => IcmpError::TimeExceeded,
+        (_, CODE_NET_UNREACHABLE)                 => IcmpError::NetUnreachable,
+        (_, CODE_HOST_UNREACHABLE)                => IcmpError::HostUnreachable,
+        (_, CODE_PROTOCOL_UNREACHABLE)            => IcmpError::ProtocolUnreachable,
+        (_, CODE_PORT_UNREACHABLE)                => IcmpError::PortUnreachable,
+        (_, CODE_FRAGMENTATION_NEEDED)            => IcmpError::FragmentationNeeded(u16::from_be_bytes([rest[2], rest[3]])),
+        _                                         => IcmpError::HostUnreachable,
+    };
+    match quoted[9] {
+        ipv4::PROTO_TCP => super::tcp::icmp_error(local, remote, u32::from_be_bytes([t[4], t[5], t[6], t[7]]), error),
+        ipv4::PROTO_UDP => super::udp::icmp_error(local, remote, error),
+        _ => {}
+    }
+}
+
+/// An ICMP error, as reported to TCP and UDP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IcmpError {
+    NetUnreachable,
+    HostUnreachable,
+    ProtocolUnreachable,
+    PortUnreachable,
+    /// The path MTU is smaller than the packet; carries the next hop's MTU.
+    FragmentationNeeded(u16),
+    TimeExceeded,
+}
+
+impl IcmpError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IcmpError::NetUnreachable         => "network unreachable",
+            IcmpError::HostUnreachable        => "host unreachable",
+            IcmpError::ProtocolUnreachable    => "protocol unreachable",
+            IcmpError::PortUnreachable        => "connection refused",
+            IcmpError::FragmentationNeeded(_) => "message too long",
+            IcmpError::TimeExceeded           => "time to live exceeded",
+        }
+    }
+
+    /// Whether the error means the peer will never answer (RFC 1122
+    /// 4.2.3.9), rather than that something on the way may recover.
+    pub fn is_hard(self) -> bool {
+        matches!(self, IcmpError::ProtocolUnreachable | IcmpError::PortUnreachable)
+    }
+}
+
+// ─── raw sockets ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpHandle(pub u32);
+
+struct RawSocket {
+    id:    u32,
+    owner:  ProcessId,
+    /// Identifiers of the echo requests sent, whose replies are ours.
+    idents: Vec<u16>,
+    queue:  VecDeque<(Ipv4Addr, Vec<u8>)>,
+    drops:  u64,
+}
+
+struct Raw {
+    sockets: Vec<RawSocket>,
+    next_id: u32,
+}
+
+impl Raw {
+    /// The current process's socket `h`.
+    fn get(&mut self, h: IcmpHandle) -> Result<&mut RawSocket, &'static str> {
+        let owner = crate::process::current_pid();
+        self.sockets.iter_mut().find(|s| s.id == h.0 && s.owner == owner).ok_or("no such socket")
+    }
+}
+
+static RAW: Mutex<Raw> = Mutex::new(Raw { sockets: Vec::new(), next_id: 1 });
+
+/// Woken whenever a message is queued on any raw socket.
+static RX_WAIT: WaitQueue = WaitQueue::new();
+
+/// Open a raw ICMP socket for `owner`, who must hold `cap`: the Network
+/// capability with CONTROL rights.
+pub fn open_raw(owner: ProcessId, cap: &Capability) -> Result<IcmpHandle, &'static str> {
+    capability::validate(owner, cap, CapabilityType::Network, Permissions::CONTROL)?;
+    let mut raw = RAW.lock();
+    let id = raw.next_id;
+    raw.next_id = raw.next_id.wrapping_add(1).max(1);
+    raw.sockets.push(RawSocket { id, owner, idents: Vec::new(), queue: VecDeque::new(), drops: 0 });
+    Ok(IcmpHandle(id))
+}
+
+/// Send the ICMP message `msg` (type, code, checksum and body; the
+/// checksum is filled in) to `dst`.
+pub fn send_to(h: IcmpHandle, dst: Ipv4Addr, msg: &[u8]) -> Result<usize, &'static str> {
+    if msg.len() < HEADER_LEN { return Err("message too short"); }
+    let owner = RAW.lock().get(h)?.owner;
+    netns::check(owner, ipv4::PROTO_ICMP, SocketAddr::new(dst, 0))?;
+    let IpAddr::V4(src) = netns::source(owner, dst.into(), Ipv4Addr::UNSPECIFIED.into())? else { return Err("no route to host") };
+    if msg[0] == TYPE_ECHO_REQUEST {
+        let ident = u16::from_be_bytes([msg[4], msg[5]]);
+        let mut raw = RAW.lock();
+        let s = raw.get(h)?;
+        if !s.idents.contains(&ident) { s.idents.push(ident); }
+    }
+    ipv4::send(Some(src), dst, ipv4::PROTO_ICMP, &seal(msg.to_vec()))?;
+    accounting::sent(owner, src.into(), dst.into(), ipv4::HEADER_LEN + msg.len());
+    Ok(msg.len())
+}
+
+/// Take the next ICMP message into `buf`, returning its length and
+/// sender.  Waits at most `timeout_ms` (None: indefinitely).
+pub fn recv_from(h: IcmpHandle, buf: &mut [u8], timeout_ms: Option<u64>) -> Result<(usize, Ipv4Addr), &'static str> {
+    let deadline = timeout_ms.map(|t| crate::arch::uptime_millis() + t);
+    RX_WAIT.wait_until(deadline, || {
+        let mut raw = RAW.lock();
+        let s = match raw.get(h) { Ok(s) => s, Err(e) => return Some(Err(e)) };
+        let (from, msg) = s.queue.pop_front()?;
+        let n = msg.len().min(buf.len());
+        buf[..n].copy_from_slice(&msg[..n]);
+        Some(Ok((n, from)))
+    }).unwrap_or(Err("timed out"))
+}
+
+pub fn close(h: IcmpHandle) -> Result<(), &'static str> {
+    let mut raw = RAW.lock();
+    raw.get(h)?;
+    raw.sockets.retain(|s| s.id != h.0);
+    RX_WAIT.wake_all();
+    Ok(())
+}
+
+/// Close every raw socket `owner` holds, as when it exits.
+pub fn close_all(owner: ProcessId) {
+    RAW.lock().sockets.retain(|s| s.owner != owner);
+    RX_WAIT.wake_all();
+}
+
+// ─── stack entry point ────────────────────────────────────────────────────────
+
+/// The echo identifier `msg` concerns: its own in an echo reply, or that
+/// of the request an error quotes.
+fn echo_ident(msg: &[u8]) -> Option<u16> {
+    match msg[0] {
+        TYPE_ECHO_REPLY => Some(u16::from_be_bytes([msg[4], msg[5]])),
+        TYPE_DEST_UNREACHABLE | TYPE_TIME_EXCEEDED => {
+            let quoted = &msg[HEADER_LEN..];
+            if quoted.len() < ipv4::HEADER_LEN || quoted[0] >> 4 != 4 || quoted[9] != ipv4::PROTO_ICMP { return None; }
+            let t = quoted.get((quoted[0] & 0x0F) as usize * 4..)?;
+            (t.len() >= 6 && t[0] == TYPE_ECHO_REQUEST).then(|| u16::from_be_bytes([t[4], t[5]]))
+        }
+        _ => None,
+    }
+}
+
+/// An ICMP message from `src` to `dst`.
+pub(super) fn input(src: Ipv4Addr, dst: Ipv4Addr, msg: &[u8]) {
+    if msg.len() < HEADER_LEN || checksum(msg) != 0 { return; }
+    let rest = [msg[4], msg[5], msg[6], msg[7]];
+    match msg[0] {
+        // Broadcast pings go unanswered (RFC 1122 3.2.2.6 allows it)
+        TYPE_ECHO_REQUEST if dst != Ipv4Addr::BROADCAST => {
+            let mut reply = msg.to_vec();
+            reply[0] = TYPE_ECHO_REPLY;
+            let _ = ipv4::send(Some(dst), src, ipv4::PROTO_ICMP, &seal(reply));
+        }
+        TYPE_DEST_UNREACHABLE | TYPE_TIME_EXCEEDED => report(msg[0], msg[1], rest, &msg[HEADER_LEN..]),
+        _ => {}
+    }
+
+    let Some(ident) = echo_ident(msg) else { return };
+    let mut raw = RAW.lock();
+    if raw.sockets.is_empty() { return; }
+    let from = SocketAddr::new(src, 0);
+    for s in raw.sockets.iter_mut().filter(|s| s.idents.contains(&ident)) {
+        if netns::admit(s.owner, ipv4::PROTO_ICMP, from, dst.into()).is_err() { continue; }
+        if s.queue.len() >= RX_QUEUE { s.drops += 1; continue; }
+        s.queue.push_back((src, msg.to_vec()));
+        accounting::received(s.owner, dst.into(), src.into(), ipv4::HEADER_LEN + msg.len());
+    }
+    drop(raw);
+    RX_WAIT.wake_all();
+}
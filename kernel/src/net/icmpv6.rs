@@ -0,0 +1,125 @@
+//! ICMPv6
+//! Echo replies and error reporting for IPv6 (RFC 4443).  Error messages
+//! about our own traffic reach the TCP or UDP endpoint that sent it, as
+//! with ICMP for IPv4; neighbor discovery messages are handed to `ndp`.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::icmp::IcmpError;
+use super::ipv6::{self, Header, PROTO_ICMPV6};
+use super::{ipv4, transport_checksum, IpAddr, Ipv6Addr, SocketAddr};
+
+pub const TYPE_DEST_UNREACHABLE: u8 = 1;
+pub const TYPE_PACKET_TOO_BIG:   u8 = 2;
+pub const TYPE_TIME_EXCEEDED:    u8 = 3;
+pub const TYPE_PARAM_PROBLEM:    u8 = 4;
+pub const TYPE_ECHO_REQUEST:     u8 = 128;
+pub const TYPE_ECHO_REPLY:       u8 = 129;
+
+pub const CODE_NO_ROUTE:            u8 = 0;
+pub const CODE_ADMIN_PROHIBITED:    u8 = 1;
+pub const CODE_ADDR_UNREACHABLE:    u8 = 3;
+pub const CODE_PORT_UNREACHABLE:    u8 = 4;
+/// Parameter problem code.
+pub const CODE_UNKNOWN_NEXT_HEADER: u8 = 1;
+
+pub const HEADER_LEN: usize = 8;
+/// Errors we send per second.
+const ERRORS_PER_SEC: u32 = 10;
+
+/// Fill in the checksum of a message from `src` to `dst`.
+pub(super) fn seal(src: Ipv6Addr, dst: Ipv6Addr, mut msg: Vec<u8>) -> Vec<u8> {
+    msg[2..4].copy_from_slice(&[0, 0]);
+    let sum = transport_checksum(IpAddr::V6(src), IpAddr::V6(dst), PROTO_ICMPV6, &msg);
+    msg[2..4].copy_from_slice(&sum.to_be_bytes());
+    msg
+}
+
+// ─── errors ───────────────────────────────────────────────────────────────────
+
+/// (second of uptime, errors sent in it)
+static ERROR_RATE: Mutex<(u64, u32)> = Mutex::new((0, 0));
+
+/// Tell the sender of `packet`, an IPv6 packet we received, that it could
+/// not be delivered.  Never sent about ICMPv6 errors, multicast or from
+/// the unspecified address (RFC 4443 2.4).
+fn error(ty: u8, code: u8, rest: [u8; 4], packet: &[u8]) {
+    let Some((h, payload)) = ipv6::parse(packet) else { return };
+    if h.dst.is_multicast() || h.src.is_unspecified() || h.src.is_multicast() { return; }
+    if h.next == PROTO_ICMPV6 && payload.first().is_some_and(|&t| t < TYPE_ECHO_REQUEST) { return; }
+    {
+        let second = crate::arch::uptime_millis() / 1000;
+        let mut rate = ERROR_RATE.lock();
+        if rate.0 != second { *rate = (second, 0); }
+        if rate.1 >= ERRORS_PER_SEC { return; }
+        rate.1 += 1;
+    }
+    // As much of the packet as fits the minimum MTU
+    let quoted = packet.len().min(ipv6::MIN_MTU - ipv6::HEADER_LEN - HEADER_LEN);
+    let mut m = Vec::with_capacity(HEADER_LEN + quoted);
+    m.extend_from_slice(&[ty, code, 0, 0]);
+    m.extend_from_slice(&rest);
+    m.extend_from_slice(&packet[..quoted]);
+    let _ = ipv6::send(Some(h.dst), h.src, PROTO_ICMPV6, &seal(h.dst, h.src, m));
+}
+
+pub(super) fn unreachable(code: u8, packet: &[u8]) {
+    error(TYPE_DEST_UNREACHABLE, code, [0; 4], packet);
+}
+
+/// A problem with the packet; `pointer` is the offset of the bad field.
+pub(super) fn param_problem(code: u8, pointer: u32, packet: &[u8]) {
+    error(TYPE_PARAM_PROBLEM, code, pointer.to_be_bytes(), packet);
+}
+
+/// Pass an error about a packet we sent to the endpoint that sent it.
+fn report(ty: u8, code: u8, rest: [u8; 4], quoted: &[u8]) {
+    // Only reports quoting a transport header right after the IPv6 one
+    if quoted.len() < ipv6::HEADER_LEN + 8 || quoted[0] >> 4 != 6 { return; }
+    let addr = |i: usize| { let mut a = [0u8; 16]; a.copy_from_slice(&quoted[i..i + 16]); Ipv6Addr(a) };
+    let t = &quoted[ipv6::HEADER_LEN..];
+    let local  = SocketAddr::new(addr(8),  u16::from_be_bytes([t[0], t[1]]));
+    let remote = SocketAddr::new(addr(24), u16::from_be_bytes([t[2], t[3]]));
+    let error = match (ty, code) {
+        (TYPE_PACKET_TOO_BIG, _) => {
+            let mtu = u32::from_be_bytes(rest).min(u16::MAX as u32) as u16;
+            IcmpError::FragmentationNeeded(mtu)
+        }
+        (TYPE_TIME_EXCEEDED, _)                        => IcmpError::TimeExceeded,
+        (TYPE_PARAM_PROBLEM, CODE_UNKNOWN_NEXT_HEADER) => IcmpError::ProtocolUnreachable,
+        (TYPE_DEST_UNREACHABLE, CODE_NO_ROUTE)         => IcmpError::NetUnreachable,
+        (TYPE_DEST_UNREACHABLE, CODE_PORT_UNREACHABLE) => IcmpError::PortUnreachable,
+        (TYPE_DEST_UNREACHABLE, _)                     => IcmpError::HostUnreachable,
+        _ => return,
+    };
+    match quoted[6] {
+        ipv4::PROTO_TCP => super::tcp::icmp_error(local, remote, u32::from_be_bytes([t[4], t[5], t[6], t[7]]), error),
+        ipv4::PROTO_UDP => super::udp::icmp_error(local, remote, error),
+        _ => {}
+    }
+}
+
+// ─── stack entry point ────────────────────────────────────────────────────────
+
+/// An ICMPv6 message that arrived on interface `index`.
+pub(super) fn input(index: usize, h: &Header, msg: &[u8]) {
+    let (src, dst) = (IpAddr::V6(h.src), IpAddr::V6(h.dst));
+    if msg.len() < HEADER_LEN || transport_checksum(src, dst, PROTO_ICMPV6, msg) != 0 { return; }
+    let rest = [msg[4], msg[5], msg[6], msg[7]];
+    match msg[0] {
+        TYPE_ECHO_REQUEST if !h.dst.is_multicast() => {
+            let mut reply = msg.to_vec();
+            reply[0] = TYPE_ECHO_REPLY;
+            let _ = ipv6::send(Some(h.dst), h.src, PROTO_ICMPV6, &seal(h.dst, h.src, reply));
+        }
+        TYPE_DEST_UNREACHABLE | TYPE_PACKET_TOO_BIG | TYPE_TIME_EXCEEDED | TYPE_PARAM_PROBLEM => {
+            report(msg[0], msg[1], rest, &msg[HEADER_LEN..]);
+        }
+        super::ndp::TYPE_ROUTER_SOLICIT..=super::ndp::TYPE_NEIGHBOR_ADVERT => {
+            let now = crate::arch::uptime_millis();
+            let _ = super::with_interface(index, |i| super::ndp::input(i, h, msg, now));
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,112 @@
+//! IPv4
+//! Header validation on input and header construction on output.
+//! Fragments are dropped rather than reassembled: TCP sizes its segments
+//! to the path, and nothing else we speak needs datagrams that large.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use super::{checksum_add, checksum_finish, Ipv4Addr};
+
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_TCP:  u8 = 6;
+pub const PROTO_UDP:  u8 = 17;
+
+pub const HEADER_LEN: usize = 20;
+const DEFAULT_TTL:   u8    = 64;
+/// Don't Fragment.
+const FLAG_DF:       u16   = 0x4000;
+const FLAG_MF:       u16   = 0x2000;
+const OFFSET_MASK:   u16   = 0x1FFF;
+
+static NEXT_ID: AtomicU16 = AtomicU16::new(1);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ipv4Header {
+    pub src:   Ipv4Addr,
+    pub dst:   Ipv4Addr,
+    pub proto: u8,
+    pub ttl:   u8,
+}
+
+/// Validate `packet` and split it into its header and payload.
+pub fn parse(packet: &[u8]) -> Option<(Ipv4Header, &[u8])> {
+    if packet.len() < HEADER_LEN || packet[0] >> 4 != 4 { return None; }
+    let ihl = (packet[0] & 0x0F) as usize * 4;
+    let total = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    if ihl < HEADER_LEN || total < ihl || total > packet.len() { return None; }
+    if checksum_finish(checksum_add(0, &packet[..ihl])) != 0 { return None; }
+    let frag = u16::from_be_bytes([packet[6], packet[7]]);
+    if frag & (FLAG_MF | OFFSET_MASK) != 0 { return None; }
+    let header = Ipv4Header {
+        src:   Ipv4Addr([packet[12], packet[13], packet[14], packet[15]]),
+        dst:   Ipv4Addr([packet[16], packet[17], packet[18], packet[19]]),
+        proto: packet[9],
+        ttl:   packet[8],
+    };
+    Some((header, &packet[ihl..total]))
+}
+
+/// An IPv4 packet carrying `payload`.
+pub fn build(src: Ipv4Addr, dst: Ipv4Addr, proto: u8, payload: &[u8]) -> Vec<u8> {
+    let total = (HEADER_LEN + payload.len()) as u16;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut p = Vec::with_capacity(total as usize);
+    p.extend_from_slice(&[0x45, 0]);
+    p.extend_from_slice(&total.to_be_bytes());
+    p.extend_from_slice(&id.to_be_bytes());
+    p.extend_from_slice(&FLAG_DF.to_be_bytes());
+    p.extend_from_slice(&[DEFAULT_TTL, proto, 0, 0]);
+    p.extend_from_slice(&src.0);
+    p.extend_from_slice(&dst.0);
+    let sum = checksum_finish(checksum_add(0, &p));
+    p[10..12].copy_from_slice(&sum.to_be_bytes());
+    p.extend_from_slice(payload);
+    p
+}
+
+/// Send `payload` to `dst` from `src` (the routed interface's address if
+/// None).
+pub fn send(src: Option<Ipv4Addr>, dst: Ipv4Addr, proto: u8, payload: &[u8]) -> Result<(), &'static str> {
+    let route = match src {
+        Some(s) => super::route_from(s, dst),
+        None    => super::route(dst),
+    };
+    let (index, src, next_hop) = route.ok_or("no route to host")?;
+    let packet = build(src, dst, proto, payload);
+    super::output(index, next_hop, &packet)
+}
+
+/// Send out of interface `index` without consulting the routing table,
+/// for traffic such as DHCP that must leave a link before it has an
+/// address.  `dst` is the next hop.
+pub fn send_via(index: usize, src: Ipv4Addr, dst: Ipv4Addr, proto: u8, payload: &[u8]) -> Result<(), &'static str> {
+    super::output(index, dst, &build(src, dst, proto, payload))
+}
+
+/// Largest transport payload that fits the route to `dst` unfragmented.
+pub fn max_payload(dst: Ipv4Addr) -> Option<usize> {
+    let (index, _, _) = super::route(dst)?;
+    Some(super::interface(index)?.mtu - HEADER_LEN)
+}
+
+/// A packet arrived on interface `index`.  Packets passing through are
+/// for `nat` to forward.
+pub fn input(index: usize, packet: &[u8]) {
+    if super::nat::input(index, packet) { return; }
+    let Some((h, payload)) = parse(packet) else { return };
+    let broadcast = h.dst == Ipv4Addr::BROADCAST;
+    if !broadcast && !super::is_local(h.dst.into()) { return; }
+    match h.proto {
+        PROTO_TCP if !broadcast => super::tcp::input(h.src.into(), h.dst.into(), payload),
+        PROTO_UDP => {
+            if !super::udp::input(h.src.into(), h.dst.into(), payload) && !broadcast {
+                super::icmp::unreachable(super::icmp::CODE_PORT_UNREACHABLE, packet);
+            }
+        }
+        PROTO_ICMP => super::icmp::input(h.src, h.dst, payload),
+        PROTO_TCP => {}
+        _ if !broadcast => super::icmp::unreachable(super::icmp::CODE_PROTOCOL_UNREACHABLE, packet),
+        _ => {}
+    }
+}
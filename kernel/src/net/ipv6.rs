@@ -0,0 +1,383 @@
+//! IPv6
+//! Header checks, extension headers, addressing, routing and output (RFC
+//! 8200).  Each interface keeps its own addresses, default routers and
+//! on-link prefixes, which `ndp` fills in from router advertisements; a
+//! destination is reached directly when on-link and through the first
+//! live default router otherwise.  Source addresses are chosen per RFC
+//! 6724: matching scope first, then preferred over deprecated, then
+//! temporary addresses (when privacy extensions are on), then the longest
+//! matching prefix.
+//!
+//! Link-scope destinations carry no zone, so they leave by the first
+//! autoconfigured interface.  Fragments are dropped: we never fragment,
+//! and TCP shrinks its segments on Packet Too Big instead.
+
+use alloc::vec::Vec;
+
+use super::{ipv4, IpAddr, Ipv6Addr, INTERFACES};
+
+pub const HEADER_LEN:   usize = 40;
+/// Every link carries at least this much (RFC 8200 5).
+pub const MIN_MTU:      usize = 1280;
+pub const PROTO_ICMPV6: u8    = 58;
+
+pub(super) const NEXT_HOP_BY_HOP: u8 = 0;
+pub(super) const NEXT_ROUTING:    u8 = 43;
+pub(super) const NEXT_FRAGMENT:   u8 = 44;
+const NEXT_NONE:                  u8 = 59;
+pub(super) const NEXT_DEST_OPTS:  u8 = 60;
+
+pub const DEFAULT_HOP_LIMIT: u8 = 64;
+
+pub struct Header {
+    pub src:       Ipv6Addr,
+    pub dst:       Ipv6Addr,
+    pub next:      u8,
+    pub hop_limit: u8,
+}
+
+/// Check a packet's fixed header; returns it and the payload.
+pub fn parse(packet: &[u8]) -> Option<(Header, &[u8])> {
+    if packet.len() < HEADER_LEN || packet[0] >> 4 != 6 { return None; }
+    let len = u16::from_be_bytes([packet[4], packet[5]]) as usize;
+    if HEADER_LEN + len > packet.len() { return None; }
+    let addr = |i: usize| { let mut a = [0u8; 16]; a.copy_from_slice(&packet[i..i + 16]); Ipv6Addr(a) };
+    let h = Header { src: addr(8), dst: addr(24), next: packet[6], hop_limit: packet[7] };
+    Some((h, &packet[HEADER_LEN..HEADER_LEN + len]))
+}
+
+pub fn build(src: Ipv6Addr, dst: Ipv6Addr, next: u8, hop_limit: u8, payload: &[u8]) -> Vec<u8> {
+    let mut p = Vec::with_capacity(HEADER_LEN + payload.len());
+    p.extend_from_slice(&[0x60, 0, 0, 0]);
+    p.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    p.extend_from_slice(&[next, hop_limit]);
+    p.extend_from_slice(&src.0);
+    p.extend_from_slice(&dst.0);
+    p.extend_from_slice(payload);
+    p
+}
+
+enum Upper {
+    /// Protocol and the offset of its header in the payload.
+    Found(u8, usize),
+    /// An unknown next header, named by the field at this offset in the
+    /// packet.
+    Unknown(usize),
+    Drop,
+}
+
+/// Walk the extension headers to the upper-layer protocol.  Options we
+/// do not know are obeyed only as far as "skip"; any other action means
+/// the packet is dropped.
+fn upper_layer(mut next: u8, payload: &[u8]) -> Upper {
+    let mut off = 0;
+    let mut field = 6;
+    loop {
+        match next {
+            NEXT_HOP_BY_HOP | NEXT_DEST_OPTS | NEXT_ROUTING => {
+                let Some(&[n, len]) = payload.get(off..off + 2) else { return Upper::Drop };
+                let end = off + (len as usize + 1) * 8;
+                let Some(ext) = payload.get(off..end) else { return Upper::Drop };
+                if next == NEXT_ROUTING {
+                    // Segments left: we are not a router, so it must be the last hop
+                    if ext[3] != 0 { return Upper::Drop; }
+                } else if !options_skippable(&ext[2..]) {
+                    return Upper::Drop;
+                }
+                field = HEADER_LEN + off;
+                next = n;
+                off = end;
+            }
+            NEXT_FRAGMENT | NEXT_NONE => return Upper::Drop,
+            ipv4::PROTO_TCP | ipv4::PROTO_UDP | PROTO_ICMPV6 => return Upper::Found(next, off),
+            _ => return Upper::Unknown(field),
+        }
+    }
+}
+
+fn options_skippable(mut opts: &[u8]) -> bool {
+    while let [ty, rest @ ..] = opts {
+        if *ty == 0 { opts = rest; continue; }
+        let Some(&len) = rest.first() else { return false };
+        // The top two bits of the type say what to do with unknown ones
+        if *ty != 1 && ty >> 6 != 0 { return false; }
+        let Some(next) = rest.get(1 + len as usize..) else { return false };
+        opts = next;
+    }
+    true
+}
+
+// ─── per-interface configuration ──────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrState {
+    /// Duplicate address detection is under way; not usable yet.
+    Tentative,
+    Preferred,
+    /// Past its preferred lifetime: kept for existing traffic, not chosen
+    /// for new.
+    Deprecated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrOrigin {
+    Manual,
+    LinkLocal,
+    /// SLAAC, from the interface's EUI-64.
+    Stable,
+    /// SLAAC, with a random identifier (RFC 4941).
+    Temporary,
+}
+
+pub(super) struct Address {
+    pub(super) addr:            Ipv6Addr,
+    pub(super) prefix:          u8,
+    pub(super) state:           AddrState,
+    pub(super) origin:          AddrOrigin,
+    pub(super) created:         u64,
+    /// Uptimes at which the address is deprecated and removed; u64::MAX
+    /// for never.
+    pub(super) preferred_until: u64,
+    pub(super) valid_until:     u64,
+    /// When duplicate address detection completes.
+    pub(super) dad_until:       u64,
+    /// Temporary: whether its successor has been made.
+    pub(super) regenerated:     bool,
+}
+
+/// An address as reported to callers.
+#[derive(Debug, Clone)]
+pub struct Ipv6AddrInfo {
+    pub addr:      Ipv6Addr,
+    pub prefix:    u8,
+    pub state:     AddrState,
+    pub origin:    AddrOrigin,
+    /// Seconds of validity left; None for forever.
+    pub valid_for: Option<u64>,
+}
+
+pub(super) struct Router {
+    pub(super) addr:    Ipv6Addr,
+    pub(super) expires: u64,
+}
+
+pub(super) struct Prefix {
+    pub(super) prefix:  Ipv6Addr,
+    pub(super) len:     u8,
+    pub(super) expires: u64,
+}
+
+pub(super) struct NameServer {
+    pub(super) addr:    Ipv6Addr,
+    pub(super) expires: u64,
+}
+
+/// An interface's IPv6 state.
+pub(super) struct Ipv6Config {
+    pub(super) addrs:        Vec<Address>,
+    pub(super) routers:      Vec<Router>,
+    pub(super) prefixes:     Vec<Prefix>,
+    pub(super) dns:          Vec<NameServer>,
+    /// Whether link-local and SLAAC addresses are made: Ethernet links.
+    pub(super) autoconf:     bool,
+    /// Whether temporary addresses are made and preferred as sources.
+    pub(super) privacy:      bool,
+    pub(super) hop_limit:    u8,
+    /// The link MTU a router advertised, if smaller than the device's.
+    pub(super) mtu:          Option<usize>,
+    /// Router solicitations sent since the link-local address was ready.
+    pub(super) solicits:     u8,
+    pub(super) next_solicit: u64,
+    /// Taken off temporary addresses' preferred lifetime so that hosts
+    /// booted together do not all change address at once.
+    pub(super) desync:       u64,
+    /// Temporary addresses lost to duplicates in a row.
+    pub(super) dad_failures: u8,
+}
+
+impl Ipv6Config {
+    pub(super) fn new(autoconf: bool) -> Ipv6Config {
+        Ipv6Config {
+            addrs: Vec::new(), routers: Vec::new(), prefixes: Vec::new(), dns: Vec::new(),
+            autoconf, privacy: true, hop_limit: DEFAULT_HOP_LIMIT, mtu: None, solicits: 0, next_solicit: u64::MAX,
+            desync: crate::entropy::next_u64() % super::ndp::MAX_DESYNC_MS, dad_failures: 0,
+        }
+    }
+
+    /// Whether `a` is one of our usable addresses.
+    pub(super) fn has(&self, a: Ipv6Addr) -> bool {
+        self.addrs.iter().any(|x| x.addr == a && x.state != AddrState::Tentative)
+    }
+
+    /// Whether we listen to multicast group `group`: all-nodes, or the
+    /// solicited-node group of any address, tentative ones included.
+    pub(super) fn joined(&self, group: Ipv6Addr) -> bool {
+        group == Ipv6Addr::ALL_NODES || self.addrs.iter().any(|a| a.addr.solicited_node() == group)
+    }
+
+    pub(super) fn link_local(&self) -> Option<Ipv6Addr> {
+        self.addrs.iter().find(|a| a.origin == AddrOrigin::LinkLocal && a.state != AddrState::Tentative).map(|a| a.addr)
+    }
+
+    pub(super) fn on_link(&self, dst: Ipv6Addr) -> bool {
+        self.prefixes.iter().any(|p| p.prefix.same_prefix(dst, p.len))
+            || self.addrs.iter().any(|a| a.origin == AddrOrigin::Manual && !a.addr.is_loopback() && a.addr.same_prefix(dst, a.prefix))
+    }
+
+    /// The source address for `dst` (RFC 6724 5, rules 2, 3, 7 and 8).
+    pub(super) fn source(&self, dst: Ipv6Addr) -> Option<Ipv6Addr> {
+        let link_scope = dst.is_link_scope();
+        self.addrs.iter()
+            .filter(|a| a.state != AddrState::Tentative && !a.addr.is_loopback())
+            .filter(|a| !link_scope || a.addr.is_link_local())
+            .max_by_key(|a| (
+                a.addr.is_link_local() == link_scope,
+                a.state == AddrState::Preferred,
+                (a.origin == AddrOrigin::Temporary) == self.privacy,
+                a.addr.common_prefix(dst),
+            ))
+            .map(|a| a.addr)
+    }
+
+    pub(super) fn addresses(&self, now: u64) -> Vec<Ipv6AddrInfo> {
+        self.addrs.iter().map(|a| Ipv6AddrInfo {
+            addr: a.addr, prefix: a.prefix, state: a.state, origin: a.origin,
+            valid_for: (a.valid_until != u64::MAX).then(|| a.valid_until.saturating_sub(now) / 1000),
+        }).collect()
+    }
+}
+
+// ─── configuration API ────────────────────────────────────────────────────────
+
+/// Give an interface a manual address.  On Ethernet it is checked for
+/// duplicates before use.
+pub fn add_address(index: usize, addr: Ipv6Addr, prefix: u8) -> Result<(), &'static str> {
+    if prefix > 128 { return Err("bad prefix length"); }
+    if addr.is_multicast() || addr.is_unspecified() { return Err("not a unicast address"); }
+    let now = crate::arch::uptime_millis();
+    super::with_interface(index, |i| {
+        if i.ip6.addrs.iter().any(|a| a.addr == addr) { return Err("address exists"); }
+        super::ndp::add(i, addr, prefix, AddrOrigin::Manual, u64::MAX, u64::MAX, now);
+        Ok(())
+    })??;
+    super::netlink::changed(index);
+    Ok(())
+}
+
+pub fn remove_address(index: usize, addr: Ipv6Addr) -> Result<(), &'static str> {
+    super::with_interface(index, |i| {
+        let before = i.ip6.addrs.len();
+        i.ip6.addrs.retain(|a| a.addr != addr);
+        if i.ip6.addrs.len() == before { Err("no such address") } else { Ok(()) }
+    })??;
+    super::netlink::changed(index);
+    Ok(())
+}
+
+/// Turn privacy extensions (RFC 4941) on or off.  Turning them off stops
+/// new temporary addresses; existing ones live out their lifetimes but
+/// are no longer preferred.
+pub fn set_privacy(index: usize, on: bool) -> Result<(), &'static str> {
+    super::with_interface(index, |i| i.ip6.privacy = on)
+}
+
+/// An interface's neighbor cache.
+pub fn neighbors(index: usize) -> Vec<super::NeighborInfo<Ipv6Addr>> {
+    INTERFACES.lock().get(index).map(|i| i.ndp.entries()).unwrap_or_default()
+}
+
+/// An interface's live default routers.
+pub fn routers(index: usize) -> Vec<Ipv6Addr> {
+    INTERFACES.lock().get(index).map(|i| i.ip6.routers.iter().map(|r| r.addr).collect()).unwrap_or_default()
+}
+
+// ─── routing and output ───────────────────────────────────────────────────────
+
+/// How to reach `dst`: (interface, source address, next hop).
+pub fn route(dst: Ipv6Addr) -> Option<(usize, Ipv6Addr, Ipv6Addr)> {
+    let ifs = INTERFACES.lock();
+    let up = || ifs.iter().filter(|i| i.up);
+    if dst.is_loopback() || up().any(|i| i.ip6.has(dst)) {
+        let lo = up().find(|i| i.ip6.has(Ipv6Addr::LOCALHOST))?;
+        return Some((lo.index, if dst.is_loopback() { Ipv6Addr::LOCALHOST } else { dst }, dst));
+    }
+    if dst.is_link_scope() {
+        let i = up().find(|i| i.ip6.autoconf)?;
+        return Some((i.index, i.ip6.source(dst)?, dst));
+    }
+    if let Some(i) = up().find(|i| i.ip6.on_link(dst)) {
+        return Some((i.index, i.ip6.source(dst)?, dst));
+    }
+    if let Some(t) = super::tunnel_for(IpAddr::V6(dst)) {
+        let i = up().find(|i| i.index == t)?;
+        return Some((t, i.ip6.source(dst)?, dst));
+    }
+    up().find_map(|i| Some((i.index, i.ip6.source(dst)?, i.ip6.routers.first()?.addr)))
+}
+
+/// How to reach `dst` from `src`, one of our addresses, as
+/// `super::route_from` does for IPv4.
+pub fn route_from(src: Ipv6Addr, dst: Ipv6Addr) -> Option<(usize, Ipv6Addr, Ipv6Addr)> {
+    if src.is_loopback() || dst.is_loopback() || super::is_local(IpAddr::V6(dst)) { return route(dst).map(|r| (r.0, src, r.2)); }
+    let tunnel = super::tunnel_for(IpAddr::V6(dst));
+    let ifs = INTERFACES.lock();
+    let i = ifs.iter().find(|i| i.up && i.ip6.has(src))?;
+    if dst.is_link_scope() || i.ip6.on_link(dst) { return Some((i.index, src, dst)); }
+    if tunnel.is_some_and(|t| t != i.index) { return None; }
+    match i.ip6.routers.first() {
+        Some(r)                   => Some((i.index, src, r.addr)),
+        None if i.mac().is_none() => Some((i.index, src, dst)),
+        None                      => None,
+    }
+}
+
+pub fn send(src: Option<Ipv6Addr>, dst: Ipv6Addr, next: u8, payload: &[u8]) -> Result<(), &'static str> {
+    let route = match src {
+        Some(s) => route_from(s, dst),
+        None    => route(dst),
+    };
+    let (index, src, next_hop) = route.ok_or("no route to host")?;
+    let hop_limit = INTERFACES.lock().get(index).map_or(DEFAULT_HOP_LIMIT, |i| i.ip6.hop_limit);
+    let packet = build(src, dst, next, hop_limit, payload);
+    super::output6(index, next_hop, &packet)
+}
+
+/// Largest transport payload that fits the route to `dst` unfragmented.
+pub fn max_payload(dst: Ipv6Addr) -> Option<usize> {
+    let (index, _, _) = route(dst)?;
+    let ifs = INTERFACES.lock();
+    let i = ifs.get(index)?;
+    Some(i.ip6.mtu.unwrap_or(i.mtu()).min(i.mtu()) - HEADER_LEN)
+}
+
+/// A packet arrived on interface `index`.
+pub fn input(index: usize, packet: &[u8]) {
+    let Some((h, payload)) = parse(packet) else { return };
+    let multicast = h.dst.is_multicast();
+    let ours = if multicast {
+        INTERFACES.lock().get(index).is_some_and(|i| i.ip6.joined(h.dst))
+    } else {
+        super::is_local(IpAddr::V6(h.dst))
+    };
+    if !ours { return; }
+    let (proto, off) = match upper_layer(h.next, payload) {
+        Upper::Found(p, off) => (p, off),
+        Upper::Unknown(field) => {
+            if !multicast { super::icmpv6::param_problem(super::icmpv6::CODE_UNKNOWN_NEXT_HEADER, field as u32, packet); }
+            return;
+        }
+        Upper::Drop => return,
+    };
+    let upper = &payload[off..];
+    let (src, dst) = (IpAddr::V6(h.src), IpAddr::V6(h.dst));
+    match proto {
+        ipv4::PROTO_TCP if !multicast => super::tcp::input(src, dst, upper),
+        ipv4::PROTO_UDP => {
+            if !super::udp::input(src, dst, upper) && !multicast {
+                super::icmpv6::unreachable(super::icmpv6::CODE_PORT_UNREACHABLE, packet);
+            }
+        }
+        PROTO_ICMPV6 => super::icmpv6::input(index, &h, upper),
+        _ => {}
+    }
+}
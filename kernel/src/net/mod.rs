@@ -0,0 +1,878 @@
+//! SurakshaOS Networking
+//! Network interfaces and the packet path between them and the protocol
+//! layers.  A NIC driver implements `NetDevice` and is attached as an
+//! interface; frames it receives are processed as deferred work (the
+//! driver calls `rx_ready()` from its interrupt), and protocol timers run
+//! from the same place once per timer tick.  The loopback interface `lo`
+//! is always present.
+//!
+//! Layers:
+//!   - `arp`:    IPv4 address resolution into the `neighbor` cache
+//!   - `dhcp`:   address leases for Ethernet interfaces
+//!   - `ipv4`:   header checks, routing and output
+//!   - `nat`:    forwarding between interfaces and NAT44, for tethering
+//!   - `icmp`:   echo, error reports and raw sockets
+//!   - `ipv6`:   addresses, extension headers, routing and output
+//!   - `ndp`:    neighbor discovery and stateless autoconfiguration
+//!   - `icmpv6`: echo and error reports
+//!   - `tcp`:    connections with retransmission and congestion control
+//!   - `udp`:    datagram sockets with blocking receive
+//!   - `tls`:    TLS 1.3 client connections
+//!   - `quic`:   QUIC client connections and their streams, over UDP
+//!   - `dns`:    name resolution over DNS-over-HTTPS or plain DNS
+//!   - `dnssec`: validation of DNS answers against the root trust anchor
+//!   - `wireguard`: the `wg0` VPN tunnel and routing through it
+//!   - `netns`:  per-app socket ownership, uplinks, firewalls and offline mode
+//!   - `proxy`:  per-app SOCKS5 and HTTP CONNECT proxies, failing closed
+//!   - `socket`: the capability-checked socket API for apps
+//!   - `backend`: the stack serving that API, native or (by feature) smoltcp
+//!   - `capture`: pcap-style packet taps for debugging
+//!   - `accounting`: per-app data usage by interface, and data caps
+//!   - `netlink`: interface listing and configuration over IPC, served by networkd
+//!
+//! TCP and UDP are dual-stack: socket addresses are `IpAddr`, and a socket
+//! bound to either unspecified address hears both versions.
+
+pub mod accounting;
+pub mod arp;
+pub mod backend;
+pub mod capture;
+pub mod dns;
+pub mod dnssec;
+pub mod dhcp;
+pub mod icmp;
+pub mod icmpv6;
+pub mod ipv4;
+pub mod ipv6;
+pub mod nat;
+pub mod ndp;
+pub mod neighbor;
+pub mod netlink;
+pub mod netns;
+pub mod networkd;
+pub mod proxy;
+pub mod quic;
+#[cfg(feature = "smoltcp")]
+mod smol;
+pub mod socket;
+pub mod tcp;
+pub mod tls;
+pub mod udp;
+pub mod wireguard;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use spin::Mutex;
+
+use neighbor::{Lookup, NeighborCache, NeighborInfo};
+
+// ─── addresses ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Ipv4Addr = Ipv4Addr([0, 0, 0, 0]);
+    pub const BROADCAST:   Ipv4Addr = Ipv4Addr([255, 255, 255, 255]);
+    pub const LOCALHOST:   Ipv4Addr = Ipv4Addr([127, 0, 0, 1]);
+
+    pub fn to_u32(self) -> u32 {
+        u32::from_be_bytes(self.0)
+    }
+
+    pub fn is_loopback(self) -> bool {
+        self.0[0] == 127
+    }
+
+    /// Whether `self` and `other` share the first `prefix` bits.
+    pub fn same_subnet(self, other: Ipv4Addr, prefix: u8) -> bool {
+        let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix.min(32)) };
+        self.to_u32() & mask == other.to_u32() & mask
+    }
+
+    /// Parse dotted-quad notation.
+    pub fn parse(s: &str) -> Option<Ipv4Addr> {
+        let mut a = [0u8; 4];
+        let mut parts = s.split('.');
+        for b in a.iter_mut() { *b = parts.next()?.parse().ok()?; }
+        parts.next().is_none().then_some(Ipv4Addr(a))
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Ipv6Addr(pub [u8; 16]);
+
+impl Ipv6Addr {
+    pub const UNSPECIFIED: Ipv6Addr = Ipv6Addr([0; 16]);
+    pub const LOCALHOST:   Ipv6Addr = Ipv6Addr([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    /// ff02::1
+    pub const ALL_NODES:   Ipv6Addr = Ipv6Addr([0xFF, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    /// ff02::2
+    pub const ALL_ROUTERS: Ipv6Addr = Ipv6Addr([0xFF, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+    pub fn to_u128(self) -> u128 {
+        u128::from_be_bytes(self.0)
+    }
+
+    pub fn segments(self) -> [u16; 8] {
+        let mut s = [0u16; 8];
+        for (seg, pair) in s.iter_mut().zip(self.0.as_chunks::<2>().0) { *seg = u16::from_be_bytes(*pair); }
+        s
+    }
+
+    pub fn from_segments(s: [u16; 8]) -> Ipv6Addr {
+        let mut a = [0u8; 16];
+        for (pair, seg) in a.as_chunks_mut::<2>().0.iter_mut().zip(s) { *pair = seg.to_be_bytes(); }
+        Ipv6Addr(a)
+    }
+
+    /// The first 64 bits of `self` followed by interface identifier `iid`.
+    pub fn with_iid(self, iid: [u8; 8]) -> Ipv6Addr {
+        let mut a = self.0;
+        a[8..].copy_from_slice(&iid);
+        Ipv6Addr(a)
+    }
+
+    pub fn is_unspecified(self) -> bool {
+        self == Ipv6Addr::UNSPECIFIED
+    }
+
+    pub fn is_loopback(self) -> bool {
+        self == Ipv6Addr::LOCALHOST
+    }
+
+    pub fn is_multicast(self) -> bool {
+        self.0[0] == 0xFF
+    }
+
+    /// fe80::/10
+    pub fn is_link_local(self) -> bool {
+        self.0[0] == 0xFE && self.0[1] & 0xC0 == 0x80
+    }
+
+    /// Whether the address only means something on one link: link-local
+    /// unicast, or multicast of link scope or narrower.
+    pub fn is_link_scope(self) -> bool {
+        self.is_link_local() || self.is_multicast() && self.0[1] & 0x0F <= 2
+    }
+
+    /// Whether `self` and `other` share the first `prefix` bits.
+    pub fn same_prefix(self, other: Ipv6Addr, prefix: u8) -> bool {
+        let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix.min(128) as u32) };
+        self.to_u128() & mask == other.to_u128() & mask
+    }
+
+    /// Leading bits `self` and `other` have in common.
+    pub fn common_prefix(self, other: Ipv6Addr) -> u8 {
+        (self.to_u128() ^ other.to_u128()).leading_zeros() as u8
+    }
+
+    /// The solicited-node multicast group, ff02::1:ffXX:XXXX (RFC 4291).
+    pub fn solicited_node(self) -> Ipv6Addr {
+        let mut a = [0xFF, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0xFF, 0, 0, 0];
+        a[13..].copy_from_slice(&self.0[13..]);
+        Ipv6Addr(a)
+    }
+
+    /// The Ethernet address a multicast group maps to (RFC 2464).
+    pub fn multicast_mac(self) -> MacAddr {
+        MacAddr([0x33, 0x33, self.0[12], self.0[13], self.0[14], self.0[15]])
+    }
+
+    /// Parse RFC 4291 text form, with at most one `::`.
+    pub fn parse(s: &str) -> Option<Ipv6Addr> {
+        fn groups(part: &str) -> Option<Vec<u16>> {
+            if part.is_empty() { return Some(Vec::new()); }
+            part.split(':')
+                .map(|g| if g.is_empty() || g.len() > 4 { None } else { u16::from_str_radix(g, 16).ok() })
+                .collect()
+        }
+        let mut seg = [0u16; 8];
+        match s.split_once("::") {
+            None => {
+                let g = groups(s)?;
+                if g.len() != 8 { return None; }
+                seg.copy_from_slice(&g);
+            }
+            Some((head, tail)) => {
+                let (h, t) = (groups(head)?, groups(tail)?);
+                if h.len() + t.len() > 7 { return None; }
+                seg[..h.len()].copy_from_slice(&h);
+                seg[8 - t.len()..].copy_from_slice(&t);
+            }
+        }
+        Some(Ipv6Addr::from_segments(seg))
+    }
+}
+
+impl fmt::Display for Ipv6Addr {
+    /// RFC 5952 form: lower case, the longest run of two or more zero
+    /// groups (the first, on a tie) shortened to `::`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = self.segments();
+        let (mut best, mut run) = ((0, 0), (0, 0));
+        for (i, &g) in s.iter().enumerate() {
+            if g != 0 { run.1 = 0; continue; }
+            if run.1 == 0 { run.0 = i; }
+            run.1 += 1;
+            if run.1 > best.1 { best = run; }
+        }
+        let join = |f: &mut fmt::Formatter<'_>, groups: &[u16]| -> fmt::Result {
+            for (i, g) in groups.iter().enumerate() {
+                if i > 0 { f.write_str(":")?; }
+                write!(f, "{:x}", g)?;
+            }
+            Ok(())
+        };
+        if best.1 < 2 { return join(f, &s); }
+        join(f, &s[..best.0])?;
+        f.write_str("::")?;
+        join(f, &s[best.0 + best.1..])
+    }
+}
+
+/// An address of either IP version.  Sockets take these, so one socket
+/// API serves both stacks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IpAddr {
+    V4(Ipv4Addr),
+    V6(Ipv6Addr),
+}
+
+impl IpAddr {
+    pub fn is_unspecified(self) -> bool {
+        match self {
+            IpAddr::V4(a) => a == Ipv4Addr::UNSPECIFIED,
+            IpAddr::V6(a) => a.is_unspecified(),
+        }
+    }
+
+    pub fn is_loopback(self) -> bool {
+        match self {
+            IpAddr::V4(a) => a.is_loopback(),
+            IpAddr::V6(a) => a.is_loopback(),
+        }
+    }
+
+    /// Whether `self` and `other` are the same version and share the
+    /// first `prefix` bits.
+    pub fn same_prefix(self, other: IpAddr, prefix: u8) -> bool {
+        match (self, other) {
+            (IpAddr::V4(a), IpAddr::V4(b)) => a.same_subnet(b, prefix),
+            (IpAddr::V6(a), IpAddr::V6(b)) => a.same_prefix(b, prefix),
+            _ => false,
+        }
+    }
+
+    /// Either notation.
+    pub fn parse(s: &str) -> Option<IpAddr> {
+        Ipv4Addr::parse(s).map(IpAddr::V4).or_else(|| Ipv6Addr::parse(s).map(IpAddr::V6))
+    }
+}
+
+impl Default for IpAddr {
+    fn default() -> Self {
+        IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+    }
+}
+
+impl From<Ipv4Addr> for IpAddr {
+    fn from(a: Ipv4Addr) -> Self {
+        IpAddr::V4(a)
+    }
+}
+
+impl From<Ipv6Addr> for IpAddr {
+    fn from(a: Ipv6Addr) -> Self {
+        IpAddr::V6(a)
+    }
+}
+
+impl fmt::Display for IpAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpAddr::V4(a) => a.fmt(f),
+            IpAddr::V6(a) => a.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketAddr {
+    pub ip:   IpAddr,
+    pub port: u16,
+}
+
+impl SocketAddr {
+    pub fn new(ip: impl Into<IpAddr>, port: u16) -> SocketAddr {
+        SocketAddr { ip: ip.into(), port }
+    }
+}
+
+impl fmt::Display for SocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ip {
+            IpAddr::V4(a) => write!(f, "{}:{}", a, self.port),
+            IpAddr::V6(a) => write!(f, "[{}]:{}", a, self.port),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: MacAddr = MacAddr([0xFF; 6]);
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = &self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", m[0], m[1], m[2], m[3], m[4], m[5])
+    }
+}
+
+// ─── checksums ────────────────────────────────────────────────────────────────
+
+/// Add `data` to a running ones'-complement sum (RFC 1071).
+pub fn checksum_add(mut sum: u32, data: &[u8]) -> u32 {
+    let (pairs, rest) = data.as_chunks::<2>();
+    for p in pairs { sum += u16::from_be_bytes(*p) as u32; }
+    if let [b] = rest { sum += (*b as u32) << 8; }
+    sum
+}
+
+/// Fold a running sum into the final 16-bit checksum.
+pub fn checksum_finish(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 { sum = (sum & 0xFFFF) + (sum >> 16); }
+    !(sum as u16)
+}
+
+/// Checksum of a TCP, UDP or ICMPv6 message, including the IPv4 or IPv6
+/// pseudo-header.  Both addresses are of the same version.
+pub fn transport_checksum(src: IpAddr, dst: IpAddr, proto: u8, segment: &[u8]) -> u16 {
+    let mut sum = match (src, dst) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => checksum_add(checksum_add(0, &s.0), &d.0),
+        (IpAddr::V6(s), IpAddr::V6(d)) => checksum_add(checksum_add(0, &s.0), &d.0),
+        _ => return 0xFFFF,
+    };
+    sum += proto as u32 + segment.len() as u32;
+    checksum_finish(checksum_add(sum, segment))
+}
+
+// ─── devices ──────────────────────────────────────────────────────────────────
+
+/// A network device.  Ethernet devices exchange whole frames; devices
+/// without a MAC address (loopback, cellular data bearers) carry bare IP
+/// packets.
+pub trait NetDevice: Send {
+    /// Hardware address, or None for a bare-IP device.
+    fn mac(&self) -> Option<MacAddr>;
+    /// Largest IP packet the link carries.
+    fn mtu(&self) -> usize;
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), &'static str>;
+    /// Next received frame, if any.
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+/// Delivers what is sent straight back.
+struct Loopback {
+    queue: VecDeque<Vec<u8>>,
+}
+
+/// Packets the loopback interface holds before it drops.
+const LOOPBACK_QUEUE: usize = 256;
+
+impl NetDevice for Loopback {
+    fn mac(&self) -> Option<MacAddr> {
+        None
+    }
+
+    fn mtu(&self) -> usize {
+        65535
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), &'static str> {
+        if self.queue.len() >= LOOPBACK_QUEUE { return Err("loopback queue full"); }
+        self.queue.push_back(frame.to_vec());
+        rx_ready();
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.queue.pop_front()
+    }
+}
+
+// ─── interfaces ───────────────────────────────────────────────────────────────
+
+const ETH_HEADER:    usize = 14;
+const ETHERTYPE_IP4: u16   = 0x0800;
+const ETHERTYPE_IP6: u16   = 0x86DD;
+
+struct Interface {
+    index:      usize,
+    name:       String,
+    dev:        Box<dyn NetDevice>,
+    up:         bool,
+    /// Address and prefix length.
+    addr:       Option<(Ipv4Addr, u8)>,
+    gateway:    Option<Ipv4Addr>,
+    /// Name servers learned with the address.
+    dns:        Vec<Ipv4Addr>,
+    arp:        NeighborCache<Ipv4Addr>,
+    ip6:        ipv6::Ipv6Config,
+    ndp:        NeighborCache<Ipv6Addr>,
+    rx_packets: u64,
+    tx_packets: u64,
+}
+
+/// An interface as reported to callers.
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    pub index:      usize,
+    pub name:       String,
+    pub up:         bool,
+    pub mac:        Option<MacAddr>,
+    pub mtu:        usize,
+    pub addr:       Option<(Ipv4Addr, u8)>,
+    pub gateway:    Option<Ipv4Addr>,
+    pub addrs6:     Vec<ipv6::Ipv6AddrInfo>,
+    pub dns:        Vec<IpAddr>,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+}
+
+impl Interface {
+    fn mac(&self) -> Option<MacAddr> {
+        self.dev.mac()
+    }
+
+    fn mtu(&self) -> usize {
+        self.dev.mtu()
+    }
+
+    fn info(&self) -> InterfaceInfo {
+        InterfaceInfo {
+            index: self.index, name: self.name.clone(), up: self.up, mac: self.mac(), mtu: self.mtu(),
+            addr: self.addr, gateway: self.gateway, addrs6: self.ip6.addresses(crate::arch::uptime_millis()),
+            dns: self.dns_servers(), rx_packets: self.rx_packets, tx_packets: self.tx_packets,
+        }
+    }
+
+    /// DHCP's name servers, then those routers advertised.
+    fn dns_servers(&self) -> Vec<IpAddr> {
+        self.dns.iter().map(|&a| IpAddr::V4(a)).chain(self.ip6.dns.iter().map(|d| IpAddr::V6(d.addr))).collect()
+    }
+
+    /// Send an IP packet to `next_hop` on this link.  On Ethernet a next
+    /// hop not yet resolved holds the packet until ARP answers.
+    fn output(&mut self, next_hop: Ipv4Addr, packet: &[u8]) -> Result<(), &'static str> {
+        if !self.up { return Err("interface down"); }
+        if packet.len() > self.mtu() { return Err("packet exceeds MTU"); }
+        if self.dev.mac().is_none() { return self.transmit(packet); }
+        if next_hop == Ipv4Addr::BROADCAST { return self.transmit_frame(MacAddr::BROADCAST, ETHERTYPE_IP4, packet); }
+        match self.arp.lookup(next_hop, packet, crate::arch::uptime_millis()) {
+            Lookup::Found(mac) => self.transmit_frame(mac, ETHERTYPE_IP4, packet),
+            Lookup::Resolve    => { arp::request(self, next_hop); Ok(()) }
+            Lookup::Queued     => Ok(()),
+        }
+    }
+
+    /// Send an IPv6 packet to `next_hop` on this link.  On Ethernet a next
+    /// hop not yet resolved holds the packet until neighbor discovery
+    /// answers; multicast maps straight to a group address.
+    fn output6(&mut self, next_hop: Ipv6Addr, packet: &[u8]) -> Result<(), &'static str> {
+        if !self.up { return Err("interface down"); }
+        if packet.len() > self.mtu() { return Err("packet exceeds MTU"); }
+        if self.dev.mac().is_none() { return self.transmit(packet); }
+        if next_hop.is_multicast() { return self.transmit_frame(next_hop.multicast_mac(), ETHERTYPE_IP6, packet); }
+        match self.ndp.lookup(next_hop, packet, crate::arch::uptime_millis()) {
+            Lookup::Found(mac) => self.transmit_frame(mac, ETHERTYPE_IP6, packet),
+            Lookup::Resolve    => { ndp::solicit(self, next_hop); Ok(()) }
+            Lookup::Queued     => Ok(()),
+        }
+    }
+
+    /// Send an Ethernet frame carrying `payload`.
+    fn transmit_frame(&mut self, dst: MacAddr, ethertype: u16, payload: &[u8]) -> Result<(), &'static str> {
+        let src = self.dev.mac().ok_or("not an Ethernet interface")?;
+        let mut frame = Vec::with_capacity(ETH_HEADER + payload.len());
+        frame.extend_from_slice(&dst.0);
+        frame.extend_from_slice(&src.0);
+        frame.extend_from_slice(&ethertype.to_be_bytes());
+        frame.extend_from_slice(payload);
+        self.transmit(&frame)
+    }
+
+    /// Hand a frame (a bare packet, off Ethernet) to the device, copying
+    /// it to any capture taps.
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), &'static str> {
+        self.dev.transmit(frame)?;
+        self.tx_packets += 1;
+        capture::tap(self.index, capture::Direction::Out, frame);
+        Ok(())
+    }
+}
+
+static INTERFACES: Mutex<Vec<Interface>> = Mutex::new(Vec::new());
+
+/// Bring up the loopback interface.
+pub fn init() {
+    let lo = attach("lo", Box::new(Loopback { queue: VecDeque::new() }));
+    let _ = configure(lo, Ipv4Addr::LOCALHOST, 8, None);
+    let _ = ipv6::add_address(lo, Ipv6Addr::LOCALHOST, 128);
+    let _ = set_up(lo, true);
+}
+
+/// Attach a NIC as interface `name` (down, unconfigured); returns its
+/// index.
+pub fn attach(name: &str, dev: Box<dyn NetDevice>) -> usize {
+    let mut ifs = INTERFACES.lock();
+    let index = ifs.len();
+    let ip6 = ipv6::Ipv6Config::new(dev.mac().is_some());
+    ifs.push(Interface {
+        index, name: String::from(name), dev, up: false, addr: None, gateway: None, dns: Vec::new(),
+        arp: NeighborCache::default(), ip6, ndp: NeighborCache::default(), rx_packets: 0, tx_packets: 0,
+    });
+    drop(ifs);
+    if backend::get().claim(index) { let _ = set_up(index, true); }
+    crate::process::defer(networkd::scan);
+    netlink::changed(index);
+    index
+}
+
+fn with_interface<R>(index: usize, f: impl FnOnce(&mut Interface) -> R) -> Result<R, &'static str> {
+    INTERFACES.lock().get_mut(index).map(f).ok_or("no such interface")
+}
+
+/// Set an interface's address, prefix length and default gateway.  A new
+/// address is announced if the interface is up.
+pub fn configure(index: usize, addr: Ipv4Addr, prefix: u8, gateway: Option<Ipv4Addr>) -> Result<(), &'static str> {
+    if prefix > 32 { return Err("bad prefix length"); }
+    with_interface(index, |i| {
+        let readdressed = i.addr.map(|(a, _)| a) != Some(addr);
+        i.addr = Some((addr, prefix));
+        i.gateway = gateway;
+        if readdressed {
+            i.arp.flush();
+            if i.up { arp::announce(i); }
+        }
+    })?;
+    netlink::changed(index);
+    Ok(())
+}
+
+/// Remove an interface's address, gateway and name servers.
+pub fn deconfigure(index: usize) -> Result<(), &'static str> {
+    with_interface(index, |i| {
+        i.addr = None;
+        i.gateway = None;
+        i.dns.clear();
+        i.arp.flush();
+    })?;
+    netlink::changed(index);
+    Ok(())
+}
+
+/// Set the name servers learned for an interface.
+pub fn set_dns(index: usize, servers: &[Ipv4Addr]) -> Result<(), &'static str> {
+    with_interface(index, |i| i.dns = servers.to_vec())?;
+    netlink::changed(index);
+    Ok(())
+}
+
+/// Name servers from every interface that is up, in interface order.
+pub fn dns_servers() -> Vec<IpAddr> {
+    let mut servers: Vec<IpAddr> = Vec::new();
+    for i in INTERFACES.lock().iter().filter(|i| i.up) {
+        for s in i.dns_servers() {
+            if !servers.contains(&s) { servers.push(s); }
+        }
+    }
+    servers
+}
+
+/// Bring an interface up or down.  Coming up announces its IPv4 address
+/// and starts IPv6 autoconfiguration, unless a backend drives it; going
+/// down forgets neighbors and everything autoconfiguration learned.
+pub fn set_up(index: usize, up: bool) -> Result<(), &'static str> {
+    let now = crate::arch::uptime_millis();
+    let native = !backend::get().owns(index);
+    with_interface(index, |i| {
+        if up && !i.up && native {
+            i.up = true;
+            arp::announce(i);
+            ndp::start(i, now);
+        }
+        if !up {
+            i.arp.flush();
+            ndp::stop(i);
+        }
+        i.up = up;
+    })?;
+    netlink::changed(index);
+    Ok(())
+}
+
+/// Pin the link-layer address of an on-link host.
+pub fn set_neighbor(index: usize, ip: Ipv4Addr, mac: MacAddr) -> Result<(), &'static str> {
+    with_interface(index, |i| {
+        for waiting in i.arp.set_static(ip, mac) {
+            let _ = i.transmit_frame(mac, ETHERTYPE_IP4, &waiting);
+        }
+    })
+}
+
+/// An interface's ARP cache.
+pub fn neighbors(index: usize) -> Vec<NeighborInfo<Ipv4Addr>> {
+    INTERFACES.lock().get(index).map(|i| i.arp.entries()).unwrap_or_default()
+}
+
+pub fn find_interface(name: &str) -> Option<usize> {
+    INTERFACES.lock().iter().position(|i| i.name == name)
+}
+
+pub fn interfaces() -> Vec<InterfaceInfo> {
+    INTERFACES.lock().iter().map(Interface::info).collect()
+}
+
+pub fn interface(index: usize) -> Option<InterfaceInfo> {
+    INTERFACES.lock().get(index).map(Interface::info)
+}
+
+// ─── routing ──────────────────────────────────────────────────────────────────
+
+/// A tunnel interface that takes traffic which would otherwise go to a
+/// gateway.
+struct TunnelRoute {
+    index:    usize,
+    /// Destinations routed into the tunnel.
+    prefixes: Vec<(IpAddr, u8)>,
+    /// Destinations that never are: the tunnel's own endpoints.
+    exempt:   Vec<IpAddr>,
+}
+
+static TUNNEL: Mutex<Option<TunnelRoute>> = Mutex::new(None);
+
+/// Route destinations within `prefixes` that are not on-link through
+/// interface `index` in place of a gateway, except those in `exempt`.
+/// A prefix of length 0 takes everything; if the tunnel interface is
+/// down such traffic has no route rather than leaving by a gateway.
+pub fn set_tunnel_route(index: usize, prefixes: Vec<(IpAddr, u8)>, exempt: Vec<IpAddr>) {
+    *TUNNEL.lock() = Some(TunnelRoute { index, prefixes, exempt });
+}
+
+pub fn clear_tunnel_route() {
+    *TUNNEL.lock() = None;
+}
+
+/// The tunnel interface `dst` is routed through, if any.
+pub(crate) fn tunnel_for(dst: IpAddr) -> Option<usize> {
+    let t = TUNNEL.lock();
+    let t = t.as_ref()?;
+    if t.exempt.contains(&dst) || !t.prefixes.iter().any(|&(p, len)| dst.same_prefix(p, len)) { return None; }
+    Some(t.index)
+}
+
+/// How to reach `dst`: (interface, source address, next hop).  Our own
+/// addresses are reached over loopback, on-link hosts directly, anything
+/// else through a tunnel that claims it or the first interface with a
+/// gateway.
+pub fn route(dst: Ipv4Addr) -> Option<(usize, Ipv4Addr, Ipv4Addr)> {
+    let ifs = INTERFACES.lock();
+    let usable: Vec<(usize, Ipv4Addr, u8, Option<Ipv4Addr>)> = ifs.iter().filter(|i| i.up)
+        .filter_map(|i| i.addr.map(|(a, p)| (i.index, a, p, i.gateway)))
+        .collect();
+    if dst.is_loopback() || usable.iter().any(|u| u.1 == dst) {
+        let lo = usable.iter().find(|u| u.1.is_loopback())?;
+        return Some((lo.0, if dst.is_loopback() { lo.1 } else { dst }, dst));
+    }
+    if let Some(u) = usable.iter().find(|u| !u.1.is_loopback() && u.1.same_subnet(dst, u.2)) {
+        return Some((u.0, u.1, dst));
+    }
+    if let Some(t) = tunnel_for(IpAddr::V4(dst)) {
+        return usable.iter().find(|u| u.0 == t).map(|u| (u.0, u.1, dst));
+    }
+    usable.iter().find_map(|u| u.3.map(|gw| (u.0, u.1, gw)))
+}
+
+/// How to reach `dst` from `src`, one of our addresses: through the
+/// interface `src` belongs to, so traffic pinned to an address leaves
+/// where that address lives.  Our own addresses route as usual.  A
+/// tunnel's claim on `dst` still holds, so other interfaces have no
+/// route to it.
+pub fn route_from(src: Ipv4Addr, dst: Ipv4Addr) -> Option<(usize, Ipv4Addr, Ipv4Addr)> {
+    if src.is_loopback() || dst.is_loopback() || is_local(IpAddr::V4(dst)) { return route(dst).map(|r| (r.0, src, r.2)); }
+    let tunnel = tunnel_for(IpAddr::V4(dst));
+    let ifs = INTERFACES.lock();
+    let i = ifs.iter().find(|i| i.up && i.addr.is_some_and(|(a, _)| a == src))?;
+    if i.addr.is_some_and(|(_, p)| src.same_subnet(dst, p)) { return Some((i.index, src, dst)); }
+    if tunnel.is_some_and(|t| t != i.index) { return None; }
+    match i.gateway {
+        Some(gw)                  => Some((i.index, src, gw)),
+        None if i.mac().is_none() => Some((i.index, src, dst)),
+        None                      => None,
+    }
+}
+
+/// Whether `ip` is one of our addresses.
+pub fn is_local(ip: IpAddr) -> bool {
+    interface_of(ip).is_some()
+}
+
+/// The interface holding `ip`, if it is one of our addresses.
+pub fn interface_of(ip: IpAddr) -> Option<usize> {
+    let ifs = INTERFACES.lock();
+    let i = match ip {
+        IpAddr::V4(ip) => ifs.iter().find(|i| i.addr.is_some_and(|(a, _)| a == ip || ip.is_loopback() && a.is_loopback())),
+        IpAddr::V6(ip) => ifs.iter().find(|i| i.ip6.has(ip)),
+    };
+    i.map(|i| i.index)
+}
+
+/// Send an IP packet out of interface `index` towards `next_hop`.
+pub(crate) fn output(index: usize, next_hop: Ipv4Addr, packet: &[u8]) -> Result<(), &'static str> {
+    with_interface(index, |i| i.output(next_hop, packet))?
+}
+
+/// Send an IPv6 packet out of interface `index` towards `next_hop`.
+pub(crate) fn output6(index: usize, next_hop: Ipv6Addr, packet: &[u8]) -> Result<(), &'static str> {
+    with_interface(index, |i| i.output6(next_hop, packet))?
+}
+
+/// Send a whole frame out of interface `index`, for a backend driving it.
+pub(crate) fn transmit_raw(index: usize, frame: &[u8]) -> Result<(), &'static str> {
+    with_interface(index, |i| {
+        if !i.up { return Err("interface down"); }
+        i.transmit(frame)
+    })?
+}
+
+// ─── dual stack ───────────────────────────────────────────────────────────────
+
+/// The source address we would use towards `dst`.
+pub fn source_for(dst: IpAddr) -> Option<IpAddr> {
+    match dst {
+        IpAddr::V4(d) => route(d).map(|r| IpAddr::V4(r.1)),
+        IpAddr::V6(d) => ipv6::route(d).map(|r| IpAddr::V6(r.1)),
+    }
+}
+
+/// Interface `index`'s source address towards `dst`, if it is up and has
+/// one of that version.
+pub fn source_via(index: usize, dst: IpAddr) -> Option<IpAddr> {
+    let ifs = INTERFACES.lock();
+    let i = ifs.get(index).filter(|i| i.up)?;
+    match dst {
+        IpAddr::V4(_) => i.addr.map(|(a, _)| IpAddr::V4(a)),
+        IpAddr::V6(d) => i.ip6.source(d).map(IpAddr::V6),
+    }
+}
+
+/// Send a transport payload to `dst` over whichever IP version it is;
+/// `src`, if given, must be of the same version and routes the packet
+/// from its interface.
+pub fn send(src: Option<IpAddr>, dst: IpAddr, proto: u8, payload: &[u8]) -> Result<(), &'static str> {
+    match (src, dst) {
+        (None, IpAddr::V4(d))                  => ipv4::send(None, d, proto, payload),
+        (Some(IpAddr::V4(s)), IpAddr::V4(d))   => ipv4::send(Some(s), d, proto, payload),
+        (None, IpAddr::V6(d))                  => ipv6::send(None, d, proto, payload),
+        (Some(IpAddr::V6(s)), IpAddr::V6(d))   => ipv6::send(Some(s), d, proto, payload),
+        _ => Err("address family mismatch"),
+    }
+}
+
+/// Largest transport payload that fits the route to `dst` unfragmented.
+pub fn max_payload(dst: IpAddr) -> Option<usize> {
+    match dst {
+        IpAddr::V4(d) => ipv4::max_payload(d),
+        IpAddr::V6(d) => ipv6::max_payload(d),
+    }
+}
+
+/// Length of the IP header in front of transport data to `dst`.
+pub fn header_len(dst: IpAddr) -> usize {
+    match dst {
+        IpAddr::V4(_) => ipv4::HEADER_LEN,
+        IpAddr::V6(_) => ipv6::HEADER_LEN,
+    }
+}
+
+// ─── receive path ─────────────────────────────────────────────────────────────
+
+/// A NIC has frames waiting; called from driver interrupts.
+pub fn rx_ready() {
+    crate::process::defer(poll);
+}
+
+/// Once per timer tick: run protocol timers.
+pub fn net_tick() {
+    crate::process::defer(poll);
+}
+
+/// Take every received frame from every interface and hand it up the
+/// stack, or to the backend driving the interface, then run protocol
+/// timers.  Interfaces are not held while protocols run, so they may
+/// transmit.
+fn poll() {
+    let backend = backend::get();
+    loop {
+        let mut frames = Vec::new();
+        for i in INTERFACES.lock().iter_mut().filter(|i| i.up) {
+            while let Some(f) = i.dev.receive() {
+                i.rx_packets += 1;
+                capture::tap(i.index, capture::Direction::In, &f);
+                frames.push((i.index, i.mac().is_some(), f));
+            }
+        }
+        if frames.is_empty() { break; }
+        let now = crate::arch::uptime_millis();
+        for (index, ethernet, frame) in frames {
+            if backend.owns(index) {
+                backend.input(index, frame);
+                continue;
+            }
+            if !ethernet {
+                match frame.first().map(|b| b >> 4) {
+                    Some(4) => ipv4::input(index, &frame),
+                    Some(6) => ipv6::input(index, &frame),
+                    _ => {}
+                }
+                continue;
+            }
+            if frame.len() < ETH_HEADER { continue; }
+            let payload = &frame[ETH_HEADER..];
+            match u16::from_be_bytes([frame[12], frame[13]]) {
+                ETHERTYPE_IP4      => ipv4::input(index, payload),
+                ETHERTYPE_IP6      => ipv6::input(index, payload),
+                arp::ETHERTYPE_ARP => { let _ = with_interface(index, |i| arp::input(i, payload, now)); }
+                _ => {}
+            }
+        }
+    }
+    let now = crate::arch::uptime_millis();
+    for i in INTERFACES.lock().iter_mut().filter(|i| i.up) {
+        arp::tick(i, now);
+        ndp::tick(i, now);
+    }
+    tcp::on_timer(now);
+    nat::tick(now);
+    capture::tick();
+    backend.poll(now);
+    networkd::run(|| {
+        dhcp::tick(now);
+        wireguard::tick(now);
+    });
+    quic::on_timer(now);
+    socket::wake();
+}
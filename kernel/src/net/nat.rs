@@ -0,0 +1,499 @@
+//! NAT and Forwarding
+//! Tethering: sharing one interface's connection (say cellular) with the
+//! hosts on another (the Wi-Fi hotspot or USB).  A forward lets packets
+//! arriving on an inside interface leave by an outside one; with
+//! masquerading their source is rewritten to the outside address (NAT44,
+//! RFC 3022), and without it the inside addresses are routed as they are
+//! and replies are forwarded back.
+//!
+//! Translated flows are tracked as connections, each remembering the
+//! address pairs it is seen with in both directions, so one rewrite rule
+//! serves every case:
+//!   - masquerading: an inside host's traffic out, and the replies in
+//!   - port mappings: connections to a port of the outside address go
+//!     on to an inside host, and its replies back
+//!   - hairpinning: an inside host reaching another through a mapped
+//!     port of the outside address (RFC 5382 REQ-9), translated both ways
+//!     so the replies come back through us
+//!
+//! Mappings are endpoint-independent (RFC 4787 REQ-1): an inside
+//! address and port keep one outside port whoever they talk to, while
+//! only the peers they have sent to may answer.  Outside ports come from
+//! a range below the stack's own ephemeral ports.  ICMP echo is
+//! translated by identifier, and ICMP errors about translated packets
+//! have the packet they quote translated too, so path MTU discovery and
+//! unreachables work from inside.  Fragments are not forwarded.
+//!
+//! Configuring forwards and port mappings needs the InterfaceAdmin
+//! capability with WRITE rights.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::icmp::{TYPE_DEST_UNREACHABLE, TYPE_ECHO_REPLY, TYPE_ECHO_REQUEST, TYPE_TIME_EXCEEDED};
+use super::{checksum_add, checksum_finish, ipv4, transport_checksum, IpAddr, Ipv4Addr, SocketAddr};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+
+/// Outside ports handed out, below the stack's ephemeral range.
+const PORT_FIRST: u16 = 32768;
+const PORT_LAST:  u16 = 49151;
+/// Connections tracked at once; new flows are refused beyond.
+const MAX_CONNS:  usize = 4096;
+
+/// Idle timeouts, in milliseconds (RFC 5382 REQ-5, RFC 4787 REQ-5).
+const TCP_ESTABLISHED_MS: u64 = 7440 * 1000;
+const TCP_TRANSITORY_MS:  u64 = 240 * 1000;
+const UDP_MS:             u64 = 300 * 1000;
+const ICMP_MS:            u64 = 60 * 1000;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_RST: u8 = 0x04;
+const TCP_ACK: u8 = 0x10;
+
+/// Traffic from `inside` may leave by `outside`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Forward {
+    pub inside:     usize,
+    pub outside:    usize,
+    /// Rewrite the source to the outside address.
+    pub masquerade: bool,
+}
+
+/// Connections to `port` of interface `outside`'s address go on to `to`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortMap {
+    pub outside: usize,
+    pub proto:   u8,
+    pub port:    u16,
+    pub to:      (Ipv4Addr, u16),
+}
+
+/// Source and destination of a packet, with ports (ICMP echo: the
+/// identifier as the querier's port, 0 as the responder's).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Tuple {
+    src: (Ipv4Addr, u16),
+    dst: (Ipv4Addr, u16),
+}
+
+impl Tuple {
+    fn reverse(self) -> Tuple {
+        Tuple { src: self.dst, dst: self.src }
+    }
+}
+
+struct Conn {
+    proto:   u8,
+    /// As the first packet arrived, and as replies arrive.
+    orig:    Tuple,
+    reply:   Tuple,
+    outside: usize,
+    replied: bool,
+    /// A FIN or RST was seen.
+    closing: bool,
+    expires: u64,
+    packets: u64,
+}
+
+impl Conn {
+    /// Where a packet seen as `t` is going once translated.
+    fn translate(&self, t: Tuple) -> Option<Tuple> {
+        if t == self.orig { return Some(self.reply.reverse()); }
+        if t == self.reply { return Some(self.orig.reverse()); }
+        None
+    }
+
+    fn timeout(&self) -> u64 {
+        match self.proto {
+            ipv4::PROTO_TCP if self.replied && !self.closing => TCP_ESTABLISHED_MS,
+            ipv4::PROTO_TCP => TCP_TRANSITORY_MS,
+            ipv4::PROTO_UDP => UDP_MS,
+            _               => ICMP_MS,
+        }
+    }
+}
+
+/// A tracked connection as reported to callers.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnInfo {
+    pub proto:    u8,
+    /// The endpoint that opened it, and the one it asked for.
+    pub from:     SocketAddr,
+    pub to:       SocketAddr,
+    /// The endpoint the other side sees, and the one it reached.
+    pub mapped:   SocketAddr,
+    pub reached:  SocketAddr,
+    pub outside:  usize,
+    pub packets:  u64,
+    /// Milliseconds until it is forgotten if idle.
+    pub idle_left: u64,
+}
+
+struct Nat {
+    forwards:  Vec<Forward>,
+    maps:      Vec<PortMap>,
+    conns:     Vec<Conn>,
+    next_port: u16,
+    /// New flows refused for want of room or outside ports.
+    refused:   u64,
+}
+
+static NAT: Mutex<Nat> = Mutex::new(Nat {
+    forwards: Vec::new(), maps: Vec::new(), conns: Vec::new(), next_port: PORT_FIRST, refused: 0,
+});
+
+impl Nat {
+    /// An outside port for `inside` talking `proto` from `addr`: the one
+    /// it already has, else the next free one.
+    fn port_for(&mut self, proto: u8, addr: Ipv4Addr, inside: (Ipv4Addr, u16)) -> Option<u16> {
+        if let Some(c) = self.conns.iter().find(|c| c.proto == proto && c.orig.src == inside && c.reply.dst.0 == addr) {
+            return Some(c.reply.dst.1);
+        }
+        for _ in PORT_FIRST..=PORT_LAST {
+            let port = self.next_port;
+            self.next_port = if port == PORT_LAST { PORT_FIRST } else { port + 1 };
+            let taken = self.conns.iter().any(|c| c.proto == proto && c.reply.dst == (addr, port))
+                || self.maps.iter().any(|m| m.proto == proto && m.port == port);
+            if !taken { return Some(port); }
+        }
+        None
+    }
+
+    /// Start tracking a flow; returns where its first packet goes.
+    fn track(&mut self, proto: u8, orig: Tuple, reply: Tuple, outside: usize, now: u64) -> Option<Tuple> {
+        if self.conns.len() >= MAX_CONNS {
+            self.refused += 1;
+            return None;
+        }
+        let mut c = Conn { proto, orig, reply, outside, replied: false, closing: false, expires: 0, packets: 1 };
+        c.expires = now + c.timeout();
+        self.conns.push(c);
+        Some(reply.reverse())
+    }
+}
+
+fn authorize(cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(crate::process::current_pid(), cap, CapabilityType::InterfaceAdmin, Permissions::WRITE)
+}
+
+fn v4(addr: (Ipv4Addr, u16)) -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(addr.0), addr.1)
+}
+
+// ─── configuration ────────────────────────────────────────────────────────────
+
+/// Let traffic from interface `inside` leave by `outside`, masqueraded
+/// or routed as it is.  Replaces a forward between the same two.
+pub fn add_forward(cap: &Capability, inside: usize, outside: usize, masquerade: bool) -> Result<(), &'static str> {
+    authorize(cap)?;
+    if inside == outside { return Err("inside and outside are the same interface"); }
+    if super::interface(inside).is_none() || super::interface(outside).is_none() { return Err("no such interface"); }
+    let mut nat = NAT.lock();
+    nat.forwards.retain(|f| !(f.inside == inside && f.outside == outside));
+    nat.forwards.push(Forward { inside, outside, masquerade });
+    Ok(())
+}
+
+/// Stop forwarding from `inside` to `outside`, forgetting its
+/// connections.
+pub fn remove_forward(cap: &Capability, inside: usize, outside: usize) -> Result<(), &'static str> {
+    authorize(cap)?;
+    let mut nat = NAT.lock();
+    let before = nat.forwards.len();
+    nat.forwards.retain(|f| !(f.inside == inside && f.outside == outside));
+    if nat.forwards.len() == before { return Err("no such forward"); }
+    if !nat.forwards.iter().any(|f| f.outside == outside) {
+        nat.conns.retain(|c| c.outside != outside);
+    }
+    Ok(())
+}
+
+/// Send `proto` connections to `port` of interface `outside`'s address
+/// on to `to`, an inside host.
+pub fn add_port_map(cap: &Capability, outside: usize, proto: u8, port: u16, to: SocketAddr) -> Result<(), &'static str> {
+    authorize(cap)?;
+    if proto != ipv4::PROTO_TCP && proto != ipv4::PROTO_UDP { return Err("protocol not supported"); }
+    let IpAddr::V4(ip) = to.ip else { return Err("address family not supported") };
+    if port == 0 || to.port == 0 { return Err("bad port"); }
+    if super::interface(outside).is_none() { return Err("no such interface"); }
+    let mut nat = NAT.lock();
+    if nat.maps.iter().any(|m| m.outside == outside && m.proto == proto && m.port == port) { return Err("port already mapped"); }
+    nat.maps.push(PortMap { outside, proto, port, to: (ip, to.port) });
+    Ok(())
+}
+
+pub fn remove_port_map(cap: &Capability, outside: usize, proto: u8, port: u16) -> Result<(), &'static str> {
+    authorize(cap)?;
+    let mut nat = NAT.lock();
+    let before = nat.maps.len();
+    nat.maps.retain(|m| !(m.outside == outside && m.proto == proto && m.port == port));
+    if nat.maps.len() == before { Err("no such port mapping") } else { Ok(()) }
+}
+
+pub fn forwards() -> Vec<Forward> {
+    NAT.lock().forwards.clone()
+}
+
+pub fn port_maps() -> Vec<PortMap> {
+    NAT.lock().maps.clone()
+}
+
+pub fn connections() -> Vec<ConnInfo> {
+    let now = crate::arch::uptime_millis();
+    NAT.lock().conns.iter().map(|c| ConnInfo {
+        proto: c.proto, from: v4(c.orig.src), to: v4(c.orig.dst), mapped: v4(c.reply.dst), reached: v4(c.reply.src),
+        outside: c.outside, packets: c.packets, idle_left: c.expires.saturating_sub(now),
+    }).collect()
+}
+
+/// New flows refused for want of room or outside ports.
+pub fn refused() -> u64 {
+    NAT.lock().refused
+}
+
+// ─── packet handling ──────────────────────────────────────────────────────────
+
+/// Offset of the port pair (ICMP: checksum) and checksum in a transport
+/// header, and the header's minimum length.
+fn layout(proto: u8) -> Option<(usize, usize)> {
+    match proto {
+        ipv4::PROTO_TCP  => Some((16, 20)),
+        ipv4::PROTO_UDP  => Some((6, 8)),
+        ipv4::PROTO_ICMP => Some((2, 8)),
+        _                => None,
+    }
+}
+
+/// The tuple of a TCP or UDP packet or an ICMP echo.  `t` is the start
+/// of its transport header, of at least 8 bytes.
+fn tuple(proto: u8, src: Ipv4Addr, dst: Ipv4Addr, t: &[u8]) -> Option<Tuple> {
+    let word = |i: usize| u16::from_be_bytes([t[i], t[i + 1]]);
+    match proto {
+        ipv4::PROTO_TCP | ipv4::PROTO_UDP => Some(Tuple { src: (src, word(0)), dst: (dst, word(2)) }),
+        ipv4::PROTO_ICMP => match t[0] {
+            TYPE_ECHO_REQUEST => Some(Tuple { src: (src, word(4)), dst: (dst, 0) }),
+            TYPE_ECHO_REPLY   => Some(Tuple { src: (src, 0), dst: (dst, word(4)) }),
+            _                 => None,
+        },
+        _ => None,
+    }
+}
+
+/// Write `to`'s ports into a transport header.
+fn set_ports(proto: u8, t: &mut [u8], to: Tuple) {
+    match proto {
+        ipv4::PROTO_TCP | ipv4::PROTO_UDP => {
+            t[0..2].copy_from_slice(&to.src.1.to_be_bytes());
+            t[2..4].copy_from_slice(&to.dst.1.to_be_bytes());
+        }
+        _ if t[0] == TYPE_ECHO_REQUEST => t[4..6].copy_from_slice(&to.src.1.to_be_bytes()),
+        _                              => t[4..6].copy_from_slice(&to.dst.1.to_be_bytes()),
+    }
+}
+
+fn set_addrs(header: &mut [u8], to: Tuple) {
+    header[12..16].copy_from_slice(&to.src.0 .0);
+    header[16..20].copy_from_slice(&to.dst.0 .0);
+}
+
+fn seal_header(header: &mut [u8]) {
+    header[10..12].copy_from_slice(&[0, 0]);
+    let sum = checksum_finish(checksum_add(0, header));
+    header[10..12].copy_from_slice(&sum.to_be_bytes());
+}
+
+/// Recompute a whole transport message's checksum, at `at`.
+fn seal_transport(proto: u8, src: Ipv4Addr, dst: Ipv4Addr, t: &mut [u8], at: usize) {
+    // A UDP sender that skipped the checksum is left to have done so
+    if proto == ipv4::PROTO_UDP && t[at..at + 2] == [0, 0] { return; }
+    t[at..at + 2].copy_from_slice(&[0, 0]);
+    let sum = match proto {
+        ipv4::PROTO_ICMP => checksum_finish(checksum_add(0, t)),
+        ipv4::PROTO_UDP  => match transport_checksum(src.into(), dst.into(), proto, t) { 0 => 0xFFFF, s => s },
+        _                => transport_checksum(src.into(), dst.into(), proto, t),
+    };
+    t[at..at + 2].copy_from_slice(&sum.to_be_bytes());
+}
+
+/// What to do with a packet that arrived.
+enum Action {
+    /// Leave it to the stack: ours, or nobody's to forward.
+    Local,
+    Drop,
+    /// Forward it as it is.
+    Route,
+    /// Forward it rewritten to these addresses and ports (an ICMP error:
+    /// addresses only, its quote rewritten already).
+    Translate(Tuple),
+}
+
+/// A packet arrived on interface `index`.  Returns true if it was
+/// forwarded or dropped here, false if it is for the stack itself.
+pub(super) fn input(index: usize, packet: &[u8]) -> bool {
+    let (forwards, maps) = {
+        let nat = NAT.lock();
+        if nat.forwards.is_empty() { return false; }
+        (nat.forwards.clone(), nat.maps.clone())
+    };
+    let Some((h, payload)) = ipv4::parse(packet) else { return false };
+    if h.dst == Ipv4Addr::BROADCAST || h.dst.0[0] >= 224 || h.src == Ipv4Addr::UNSPECIFIED { return false; }
+    let ihl = (packet[0] & 0x0F) as usize * 4;
+    let mut p = packet[..ihl + payload.len()].to_vec();
+
+    let error = h.proto == ipv4::PROTO_ICMP && payload.len() >= 8
+        && matches!(payload[0], TYPE_DEST_UNREACHABLE | TYPE_TIME_EXCEEDED);
+    let action = if error {
+        match translate_error(&mut p[ihl..], h.src) {
+            Some(to) => Action::Translate(to),
+            None     => plain(&forwards, index, h.dst),
+        }
+    } else {
+        classify(&forwards, &maps, index, &h, payload)
+    };
+    let to = match action {
+        Action::Local        => return false,
+        Action::Drop         => return true,
+        Action::Route        => None,
+        Action::Translate(t) => Some(t),
+    };
+
+    // TTL runs out here rather than at the next hop
+    if h.ttl <= 1 {
+        super::icmp::time_exceeded(packet);
+        return true;
+    }
+    p[8] -= 1;
+    match to {
+        Some(to) if error => set_addrs(&mut p[..ihl], to),
+        Some(to) => {
+            set_addrs(&mut p[..ihl], to);
+            let (sum_at, _) = layout(h.proto).unwrap_or((0, 0));
+            set_ports(h.proto, &mut p[ihl..], to);
+            seal_transport(h.proto, to.src.0, to.dst.0, &mut p[ihl..], sum_at);
+        }
+        None => {}
+    }
+    seal_header(&mut p[..ihl]);
+    emit(to.map_or(h.dst, |t| t.dst.0), packet, &p);
+    true
+}
+
+/// Whether a packet from `index` to `dst` is routed through as it is.
+fn plain(forwards: &[Forward], index: usize, dst: Ipv4Addr) -> Action {
+    if super::is_local(dst.into()) { return Action::Local; }
+    let Some((egress, _, _)) = super::route(dst) else { return Action::Local };
+    let routed = forwards.iter().any(|f| !f.masquerade
+        && ((f.inside == index && f.outside == egress) || (f.outside == index && f.inside == egress)));
+    if routed { Action::Route } else { Action::Local }
+}
+
+/// Match a packet to its connection, or open one for it if it is the
+/// first of a flow we translate.
+fn classify(forwards: &[Forward], maps: &[PortMap], index: usize, h: &ipv4::Ipv4Header, payload: &[u8]) -> Action {
+    let Some(t) = layout(h.proto).filter(|l| payload.len() >= l.1).and_then(|_| tuple(h.proto, h.src, h.dst, payload))
+        else { return plain(forwards, index, h.dst) };
+    let flags = if h.proto == ipv4::PROTO_TCP { payload[13] } else { 0 };
+    let now = crate::arch::uptime_millis();
+    {
+        let mut nat = NAT.lock();
+        if let Some((c, to)) = nat.conns.iter_mut().filter(|c| c.proto == h.proto).find_map(|c| c.translate(t).map(|to| (c, to))) {
+            c.replied |= t == c.reply;
+            c.closing |= flags & (TCP_FIN | TCP_RST) != 0;
+            c.packets += 1;
+            c.expires = now + c.timeout();
+            return Action::Translate(to);
+        }
+    }
+
+    // Only a SYN or an echo request opens a flow
+    let opening = match h.proto {
+        ipv4::PROTO_TCP  => flags & (TCP_SYN | TCP_ACK | TCP_RST) == TCP_SYN,
+        ipv4::PROTO_ICMP => payload[0] == TYPE_ECHO_REQUEST,
+        _                => true,
+    };
+    let outside_addr = |i: usize| super::interface(i).and_then(|i| i.addr).map(|a| a.0);
+    let opened = |nat: &mut Nat, reply: Option<Tuple>, outside: usize| {
+        match reply.and_then(|reply| nat.track(h.proto, t, reply, outside, now)) {
+            Some(to) => Action::Translate(to),
+            None     => Action::Drop,
+        }
+    };
+
+    // Reaching an inside host through a port mapping: from outside, or
+    // from inside by hairpin, then as the outside address so its replies
+    // come back through us
+    let mapped = maps.iter().find(|m| m.proto == h.proto && m.port == t.dst.1 && outside_addr(m.outside) == Some(h.dst));
+    if let Some(m) = mapped.filter(|_| opening) {
+        let from_outside = m.outside == index && forwards.iter().any(|f| f.outside == m.outside);
+        let hairpin = forwards.iter().any(|f| f.inside == index && f.outside == m.outside);
+        if from_outside || hairpin {
+            let mut nat = NAT.lock();
+            let reply_dst = if from_outside { Some(t.src) } else { nat.port_for(h.proto, h.dst, t.src).map(|port| (h.dst, port)) };
+            return opened(&mut nat, reply_dst.map(|dst| Tuple { src: m.to, dst }), m.outside);
+        }
+    }
+
+    if super::is_local(h.dst.into()) { return Action::Local; }
+    let Some((egress, _, _)) = super::route(h.dst) else { return Action::Local };
+    match forwards.iter().find(|f| f.masquerade && f.inside == index && f.outside == egress) {
+        Some(f) => {
+            let Some(addr) = outside_addr(f.outside).filter(|_| opening) else { return Action::Drop };
+            let mut nat = NAT.lock();
+            let port = nat.port_for(h.proto, addr, t.src);
+            if port.is_none() { nat.refused += 1; }
+            opened(&mut nat, port.map(|port| Tuple { src: t.dst, dst: (addr, port) }), f.outside)
+        }
+        None => plain(forwards, index, h.dst),
+    }
+}
+
+/// Send a forwarded packet `p` (`original` as it arrived) on its way.
+fn emit(dst: Ipv4Addr, original: &[u8], p: &[u8]) {
+    let Some((egress, _, next_hop)) = super::route(dst) else {
+        super::icmp::unreachable(super::icmp::CODE_HOST_UNREACHABLE, original);
+        return;
+    };
+    let mtu = super::interface(egress).map_or(0, |i| i.mtu);
+    let df = p[6] & 0x40 != 0;
+    if p.len() > mtu {
+        if df { super::icmp::fragmentation_needed(mtu as u16, original); }
+        return;
+    }
+    let _ = super::output(egress, next_hop, p);
+}
+
+/// An ICMP error `m` from `from`: if the packet it quotes was translated
+/// by a tracked connection, rewrite the quote as that packet was before
+/// translation, and return where the error is going and from where.
+fn translate_error(m: &mut [u8], from: Ipv4Addr) -> Option<Tuple> {
+    let q = &m[8..];
+    if q.len() < ipv4::HEADER_LEN || q[0] >> 4 != 4 { return None; }
+    let qihl = (q[0] & 0x0F) as usize * 4;
+    if q.len() < qihl + 8 { return None; }
+    let proto = q[9];
+    let qsrc = Ipv4Addr([q[12], q[13], q[14], q[15]]);
+    let qdst = Ipv4Addr([q[16], q[17], q[18], q[19]]);
+    let quoted = tuple(proto, qsrc, qdst, &q[qihl..])?;
+    let was = {
+        let nat = NAT.lock();
+        let c = nat.conns.iter().find(|c| c.proto == proto && (quoted == c.reply.reverse() || quoted == c.orig.reverse()))?;
+        if quoted == c.reply.reverse() { c.orig } else { c.reply }
+    };
+    let q = &mut m[8..];
+    set_addrs(&mut q[..qihl], was);
+    set_ports(proto, &mut q[qihl..], was);
+    seal_header(&mut q[..qihl]);
+    seal_transport(ipv4::PROTO_ICMP, Ipv4Addr::UNSPECIFIED, Ipv4Addr::UNSPECIFIED, m, 2);
+    // The error goes to the quoted packet's sender; an error from its
+    // receiver is made to come from where the sender thinks it sent
+    let src = if from == quoted.dst.0 { was.dst.0 } else { from };
+    Some(Tuple { src: (src, 0), dst: (was.src.0, 0) })
+}
+
+/// Forget connections idle past their timeouts.
+pub(super) fn tick(now: u64) {
+    let mut nat = NAT.lock();
+    if nat.conns.is_empty() { return; }
+    nat.conns.retain(|c| c.expires > now);
+}
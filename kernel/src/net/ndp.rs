@@ -0,0 +1,402 @@
+//! Neighbor Discovery
+//! Address resolution, duplicate address detection, router discovery and
+//! stateless address autoconfiguration for IPv6 (RFC 4861, RFC 4862).  An
+//! autoconfigured interface forms a link-local address from its EUI-64
+//! when it comes up, checks that no one else holds it, then solicits
+//! routers.  Their advertisements give default routes, on-link prefixes,
+//! the link MTU and name servers (RFC 8106); each autonomous /64 yields a
+//! stable address and, with privacy extensions, a temporary one with a
+//! random identifier that is replaced about daily (RFC 4941).  Temporary
+//! addresses are preferred as sources, so outgoing connections cannot be
+//! linked by address over time.
+//!
+//! Simplified: one DAD probe per address, and no separate reachability
+//! confirmation; resolved neighbors simply expire from the cache.
+
+use alloc::vec::Vec;
+
+use super::icmpv6;
+use super::ipv6::{self, AddrOrigin, AddrState, Address, Header, NameServer, Prefix, Router, PROTO_ICMPV6};
+use super::{Interface, Ipv6Addr, MacAddr, ETHERTYPE_IP6};
+
+pub const TYPE_ROUTER_SOLICIT:   u8 = 133;
+pub const TYPE_ROUTER_ADVERT:    u8 = 134;
+pub const TYPE_NEIGHBOR_SOLICIT: u8 = 135;
+pub const TYPE_NEIGHBOR_ADVERT:  u8 = 136;
+
+const OPT_SOURCE_LLA: u8 = 1;
+const OPT_TARGET_LLA: u8 = 2;
+const OPT_PREFIX:     u8 = 3;
+const OPT_MTU:        u8 = 5;
+const OPT_RDNSS:      u8 = 25;
+
+const NA_SOLICITED:      u8 = 0x40;
+const NA_OVERRIDE:       u8 = 0x20;
+const PREFIX_ON_LINK:    u8 = 0x80;
+const PREFIX_AUTONOMOUS: u8 = 0x40;
+
+/// ND messages are only believed if no router forwarded them.
+const ND_HOP_LIMIT: u8 = 255;
+
+/// Wait for an answer to a DAD probe.
+const DAD_WAIT_MS:         u64 = 1000;
+const MAX_SOLICITS:        u8  = 3;
+const SOLICIT_INTERVAL_MS: u64 = 4000;
+/// An advertisement may not cut a valid lifetime below this (RFC 4862
+/// 5.5.3 e), or a forged one could expire our addresses.
+const TWO_HOURS_MS:        u64 = 2 * 3600 * 1000;
+const TEMP_VALID_MS:       u64 = 7 * 24 * 3600 * 1000;
+const TEMP_PREFERRED_MS:   u64 = 24 * 3600 * 1000;
+/// How long before a temporary address is deprecated its successor is
+/// made.
+const REGEN_ADVANCE_MS:    u64 = 5000;
+pub(super) const MAX_DESYNC_MS: u64 = 600_000;
+/// Temporary addresses tried after duplicates before giving up.
+const TEMP_RETRIES:        u8  = 3;
+
+/// Modified EUI-64 interface identifier (RFC 4291 appendix A).
+fn eui64(mac: MacAddr) -> [u8; 8] {
+    let m = mac.0;
+    [m[0] ^ 0x02, m[1], m[2], 0xFF, 0xFE, m[3], m[4], m[5]]
+}
+
+fn link_local(mac: MacAddr) -> Ipv6Addr {
+    Ipv6Addr([0xFE, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).with_iid(eui64(mac))
+}
+
+/// The uptime `secs` from `now`; all ones means forever.
+fn until(now: u64, secs: u32) -> u64 {
+    if secs == u32::MAX { u64::MAX } else { now + secs as u64 * 1000 }
+}
+
+fn addr_at(b: &[u8], i: usize) -> Ipv6Addr {
+    let mut a = [0u8; 16];
+    a.copy_from_slice(&b[i..i + 16]);
+    Ipv6Addr(a)
+}
+
+fn be32(b: &[u8], i: usize) -> u32 {
+    u32::from_be_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]])
+}
+
+/// Split `opts` into (type, whole option) pairs; None if any is malformed.
+fn options(mut opts: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let mut out = Vec::new();
+    while opts.len() >= 2 {
+        let len = opts[1] as usize * 8;
+        if len == 0 || len > opts.len() { return None; }
+        out.push((opts[0], &opts[..len]));
+        opts = &opts[len..];
+    }
+    Some(out)
+}
+
+fn link_addr(opts: &[(u8, &[u8])], kind: u8) -> Option<MacAddr> {
+    opts.iter().find(|(t, o)| *t == kind && o.len() >= 8)
+        .map(|(_, o)| MacAddr([o[2], o[3], o[4], o[5], o[6], o[7]]))
+}
+
+// ─── sending ──────────────────────────────────────────────────────────────────
+
+fn send(i: &mut Interface, src: Ipv6Addr, dst: Ipv6Addr, msg: Vec<u8>) {
+    let packet = ipv6::build(src, dst, PROTO_ICMPV6, ND_HOP_LIMIT, &icmpv6::seal(src, dst, msg));
+    let _ = i.output6(dst, &packet);
+}
+
+fn with_link_addr(mut msg: Vec<u8>, kind: u8, mac: Option<MacAddr>) -> Vec<u8> {
+    if let Some(mac) = mac {
+        msg.extend_from_slice(&[kind, 1]);
+        msg.extend_from_slice(&mac.0);
+    }
+    msg
+}
+
+/// Ask for `target`'s link-layer address.
+pub(super) fn solicit(i: &mut Interface, target: Ipv6Addr) {
+    let Some(src) = i.ip6.source(target) else { return };
+    let mut msg = alloc::vec![TYPE_NEIGHBOR_SOLICIT, 0, 0, 0, 0, 0, 0, 0];
+    msg.extend_from_slice(&target.0);
+    let msg = with_link_addr(msg, OPT_SOURCE_LLA, i.mac());
+    send(i, src, target.solicited_node(), msg);
+}
+
+/// Ask whether anyone holds `tentative`, from the unspecified address so
+/// as not to use it before we know.
+fn probe(i: &mut Interface, tentative: Ipv6Addr) {
+    let mut msg = alloc::vec![TYPE_NEIGHBOR_SOLICIT, 0, 0, 0, 0, 0, 0, 0];
+    msg.extend_from_slice(&tentative.0);
+    send(i, Ipv6Addr::UNSPECIFIED, tentative.solicited_node(), msg);
+}
+
+fn advertise(i: &mut Interface, target: Ipv6Addr, dst: Ipv6Addr, solicited: bool) {
+    let flags = NA_OVERRIDE | if solicited { NA_SOLICITED } else { 0 };
+    let mut msg = alloc::vec![TYPE_NEIGHBOR_ADVERT, 0, 0, 0, flags, 0, 0, 0];
+    msg.extend_from_slice(&target.0);
+    let msg = with_link_addr(msg, OPT_TARGET_LLA, i.mac());
+    send(i, target, dst, msg);
+}
+
+fn router_solicit(i: &mut Interface) {
+    let src = i.ip6.link_local().unwrap_or(Ipv6Addr::UNSPECIFIED);
+    let msg = alloc::vec![TYPE_ROUTER_SOLICIT, 0, 0, 0, 0, 0, 0, 0];
+    // The unspecified address may not carry a link-layer address
+    let mac = if src.is_unspecified() { None } else { i.mac() };
+    send(i, src, Ipv6Addr::ALL_ROUTERS, with_link_addr(msg, OPT_SOURCE_LLA, mac));
+}
+
+/// Send the packets that were waiting for `addr` to `mac`.
+fn learned(i: &mut Interface, addr: Ipv6Addr, mac: MacAddr, now: u64, create: bool) {
+    for waiting in i.ndp.update(addr, mac, now, create) {
+        let _ = i.transmit_frame(mac, ETHERTYPE_IP6, &waiting);
+    }
+}
+
+// ─── addresses ────────────────────────────────────────────────────────────────
+
+/// Give `i` an address.  On Ethernet it starts tentative and is probed
+/// (once the interface is up); elsewhere it is usable at once.
+pub(super) fn add(i: &mut Interface, addr: Ipv6Addr, prefix: u8, origin: AddrOrigin, preferred_until: u64, valid_until: u64, now: u64) {
+    let dad = i.mac().is_some();
+    i.ip6.addrs.push(Address {
+        addr, prefix, origin, created: now, preferred_until, valid_until,
+        state: if dad { AddrState::Tentative } else { AddrState::Preferred },
+        dad_until: now + DAD_WAIT_MS, regenerated: false,
+    });
+    if dad && i.up { probe(i, addr); }
+}
+
+/// Make a temporary address in `prefix`, living no longer than the prefix
+/// does (RFC 4941 3.3).
+fn temporary(i: &mut Interface, prefix: Ipv6Addr, valid_until: u64, preferred_until: u64, now: u64) {
+    let valid = valid_until.min(now + TEMP_VALID_MS);
+    let preferred = preferred_until.min(now + TEMP_PREFERRED_MS - i.ip6.desync);
+    // One that would need replacing at once is not worth making
+    if preferred <= now + REGEN_ADVANCE_MS { return; }
+    let mut iid = [0u8; 8];
+    crate::entropy::fill_bytes(&mut iid);
+    iid[0] &= !0x02;
+    add(i, prefix.with_iid(iid), 64, AddrOrigin::Temporary, preferred, valid, now);
+}
+
+/// Someone else holds `addr`, which we were about to use.
+fn duplicate(i: &mut Interface, addr: Ipv6Addr, now: u64) {
+    let Some(pos) = i.ip6.addrs.iter().position(|a| a.addr == addr) else { return };
+    let a = i.ip6.addrs.remove(pos);
+    crate::println!("  [net] {}: {} is already in use on the link", i.name, addr);
+    if a.origin == AddrOrigin::Temporary && i.ip6.dad_failures < TEMP_RETRIES {
+        i.ip6.dad_failures += 1;
+        temporary(i, addr.with_iid([0; 8]), a.valid_until, a.preferred_until, now);
+    }
+}
+
+/// The interface came up: probe its tentative addresses and, if it
+/// autoconfigures, form its link-local address.
+pub(super) fn start(i: &mut Interface, now: u64) {
+    let tentative: Vec<Ipv6Addr> = i.ip6.addrs.iter_mut().filter(|a| a.state == AddrState::Tentative)
+        .map(|a| { a.dad_until = now + DAD_WAIT_MS; a.addr })
+        .collect();
+    for a in tentative { probe(i, a); }
+    if !i.ip6.autoconf { return; }
+    let Some(mac) = i.mac() else { return };
+    let ll = link_local(mac);
+    if !i.ip6.addrs.iter().any(|a| a.addr == ll) {
+        add(i, ll, 64, AddrOrigin::LinkLocal, u64::MAX, u64::MAX, now);
+    }
+}
+
+/// The interface went down: forget everything learned from the link.
+/// Manual addresses stay, to be probed again when it comes back.
+pub(super) fn stop(i: &mut Interface) {
+    let dad = i.mac().is_some();
+    let c = &mut i.ip6;
+    c.addrs.retain(|a| a.origin == AddrOrigin::Manual);
+    if dad {
+        for a in c.addrs.iter_mut() { a.state = AddrState::Tentative; }
+    }
+    c.routers.clear();
+    c.prefixes.clear();
+    c.dns.clear();
+    c.mtu = None;
+    c.hop_limit = ipv6::DEFAULT_HOP_LIMIT;
+    c.solicits = 0;
+    c.next_solicit = u64::MAX;
+    c.dad_failures = 0;
+    i.ndp.flush();
+}
+
+// ─── receiving ────────────────────────────────────────────────────────────────
+
+/// A neighbor discovery message arrived on `i`.
+pub(super) fn input(i: &mut Interface, h: &Header, msg: &[u8], now: u64) {
+    if h.hop_limit != ND_HOP_LIMIT || msg[1] != 0 { return; }
+    match msg[0] {
+        TYPE_NEIGHBOR_SOLICIT => neighbor_solicit(i, h, msg, now),
+        TYPE_NEIGHBOR_ADVERT  => neighbor_advert(i, h, msg, now),
+        TYPE_ROUTER_ADVERT    => router_advert(i, h, msg, now),
+        // We are not a router
+        _ => {}
+    }
+}
+
+fn neighbor_solicit(i: &mut Interface, h: &Header, msg: &[u8], now: u64) {
+    if msg.len() < 24 { return; }
+    let target = addr_at(msg, 8);
+    let Some(opts) = options(&msg[24..]) else { return };
+    let sender = link_addr(&opts, OPT_SOURCE_LLA);
+    if target.is_multicast() || h.src.is_unspecified() && (sender.is_some() || !h.dst.is_multicast()) { return; }
+    let Some(state) = i.ip6.addrs.iter().find(|a| a.addr == target).map(|a| a.state) else { return };
+    if state == AddrState::Tentative {
+        // Another node probing the same address: neither may have it
+        if h.src.is_unspecified() { duplicate(i, target, now); }
+        return;
+    }
+    if h.src.is_unspecified() {
+        advertise(i, target, Ipv6Addr::ALL_NODES, false);
+        return;
+    }
+    if let Some(mac) = sender { learned(i, h.src, mac, now, true); }
+    advertise(i, target, h.src, true);
+}
+
+fn neighbor_advert(i: &mut Interface, h: &Header, msg: &[u8], now: u64) {
+    if msg.len() < 24 { return; }
+    let target = addr_at(msg, 8);
+    if target.is_multicast() || h.dst.is_multicast() && msg[4] & NA_SOLICITED != 0 { return; }
+    let Some(opts) = options(&msg[24..]) else { return };
+    let mac = link_addr(&opts, OPT_TARGET_LLA);
+    if let Some(state) = i.ip6.addrs.iter().find(|a| a.addr == target).map(|a| a.state) {
+        if state == AddrState::Tentative {
+            duplicate(i, target, now);
+        } else if let Some(other) = mac.filter(|&m| Some(m) != i.mac()) {
+            crate::println!("  [net] {}: {} is also claimed by {}", i.name, target, other);
+        }
+        return;
+    }
+    if let Some(mac) = mac { learned(i, target, mac, now, false); }
+}
+
+fn router_advert(i: &mut Interface, h: &Header, msg: &[u8], now: u64) {
+    if !h.src.is_link_local() || msg.len() < 16 { return; }
+    let Some(opts) = options(&msg[16..]) else { return };
+    if msg[4] != 0 { i.ip6.hop_limit = msg[4]; }
+    let lifetime = u16::from_be_bytes([msg[6], msg[7]]);
+    i.ip6.routers.retain(|r| r.addr != h.src);
+    if lifetime > 0 {
+        i.ip6.routers.push(Router { addr: h.src, expires: now + lifetime as u64 * 1000 });
+    }
+    for (kind, o) in opts {
+        match kind {
+            OPT_SOURCE_LLA if o.len() >= 8 => {
+                learned(i, h.src, MacAddr([o[2], o[3], o[4], o[5], o[6], o[7]]), now, true);
+            }
+            OPT_MTU if o.len() == 8 => {
+                let mtu = be32(o, 4) as usize;
+                if (ipv6::MIN_MTU..=i.mtu()).contains(&mtu) { i.ip6.mtu = Some(mtu); }
+            }
+            OPT_PREFIX if o.len() == 32 => prefix_info(i, o, now),
+            OPT_RDNSS if o.len() >= 24 => {
+                let expires = until(now, be32(o, 4));
+                for a in o[8..].as_chunks::<16>().0 {
+                    let addr = Ipv6Addr(*a);
+                    i.ip6.dns.retain(|d| d.addr != addr);
+                    if expires > now { i.ip6.dns.push(NameServer { addr, expires }); }
+                }
+            }
+            _ => {}
+        }
+    }
+    // A router has spoken; no need to keep asking
+    i.ip6.solicits = MAX_SOLICITS;
+}
+
+/// A prefix information option (RFC 4861 4.6.2, RFC 4862 5.5.3).
+fn prefix_info(i: &mut Interface, o: &[u8], now: u64) {
+    let (len, flags) = (o[2], o[3]);
+    let (valid, preferred) = (be32(o, 4), be32(o, 8));
+    let prefix = addr_at(o, 16);
+    if prefix.is_link_local() || preferred > valid { return; }
+    let (valid_until, preferred_until) = (until(now, valid), until(now, preferred));
+
+    if flags & PREFIX_ON_LINK != 0 {
+        i.ip6.prefixes.retain(|p| !(p.prefix == prefix && p.len == len));
+        if valid > 0 { i.ip6.prefixes.push(Prefix { prefix, len, expires: valid_until }); }
+    }
+    if flags & PREFIX_AUTONOMOUS == 0 || len != 64 || !i.ip6.autoconf { return; }
+    let Some(mac) = i.mac() else { return };
+
+    let stable = prefix.with_iid(eui64(mac));
+    match i.ip6.addrs.iter_mut().find(|a| a.addr == stable) {
+        Some(a) => {
+            a.preferred_until = preferred_until;
+            a.valid_until = if valid_until > now + TWO_HOURS_MS || valid_until > a.valid_until { valid_until }
+                else if a.valid_until <= now + TWO_HOURS_MS { a.valid_until }
+                else { now + TWO_HOURS_MS };
+            if a.state == AddrState::Deprecated && preferred_until > now { a.state = AddrState::Preferred; }
+        }
+        None if valid > 0 => add(i, stable, 64, AddrOrigin::Stable, preferred_until, valid_until, now),
+        None => {}
+    }
+
+    if !i.ip6.privacy { return; }
+    // Temporary addresses follow the prefix, within their own maximums
+    let desync = i.ip6.desync;
+    let mut current = false;
+    for a in i.ip6.addrs.iter_mut().filter(|a| a.origin == AddrOrigin::Temporary && a.addr.same_prefix(prefix, 64)) {
+        a.valid_until = valid_until.min(a.created + TEMP_VALID_MS);
+        a.preferred_until = preferred_until.min(a.created + TEMP_PREFERRED_MS - desync);
+        current |= a.state != AddrState::Deprecated && !a.regenerated;
+    }
+    if !current && preferred > 0 { temporary(i, prefix, valid_until, preferred_until, now); }
+}
+
+// ─── timers ───────────────────────────────────────────────────────────────────
+
+/// Finish duplicate address detection, age addresses, replace temporary
+/// addresses before they are deprecated, solicit routers and expire what
+/// routers told us.  Called once per network poll.
+pub(super) fn tick(i: &mut Interface, now: u64) {
+    let mut link_local_ready = false;
+    let mut temp_ready = false;
+    let mut regenerate = Vec::new();
+    i.ip6.addrs.retain_mut(|a| {
+        if now >= a.valid_until { return false; }
+        if a.state == AddrState::Tentative {
+            if now < a.dad_until { return true; }
+            a.state = AddrState::Preferred;
+            link_local_ready |= a.origin == AddrOrigin::LinkLocal;
+            temp_ready |= a.origin == AddrOrigin::Temporary;
+        }
+        if a.state == AddrState::Preferred && now >= a.preferred_until { a.state = AddrState::Deprecated; }
+        if a.origin == AddrOrigin::Temporary && !a.regenerated && now + REGEN_ADVANCE_MS >= a.preferred_until {
+            a.regenerated = true;
+            regenerate.push(a.addr.with_iid([0; 8]));
+        }
+        true
+    });
+    if temp_ready { i.ip6.dad_failures = 0; }
+    for prefix in regenerate {
+        if !i.ip6.privacy { break; }
+        // Only while the prefix is still advertised as preferred
+        let stable = i.ip6.addrs.iter()
+            .find(|a| a.origin == AddrOrigin::Stable && a.addr.same_prefix(prefix, 64) && a.preferred_until > now)
+            .map(|a| (a.valid_until, a.preferred_until));
+        if let Some((valid_until, preferred_until)) = stable {
+            temporary(i, prefix, valid_until, preferred_until, now);
+        }
+    }
+
+    if link_local_ready && i.ip6.autoconf {
+        i.ip6.solicits = 0;
+        i.ip6.next_solicit = now;
+    }
+    if i.ip6.solicits < MAX_SOLICITS && now >= i.ip6.next_solicit {
+        router_solicit(i);
+        i.ip6.solicits += 1;
+        i.ip6.next_solicit = now + SOLICIT_INTERVAL_MS;
+    }
+
+    i.ip6.routers.retain(|r| now < r.expires);
+    i.ip6.prefixes.retain(|p| now < p.expires);
+    i.ip6.dns.retain(|d| now < d.expires);
+    for target in i.ndp.tick(now) { solicit(i, target); }
+}
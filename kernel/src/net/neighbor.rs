@@ -0,0 +1,158 @@
+//! Neighbor Cache
+//! Link-layer addresses of on-link hosts, per interface, for whichever
+//! resolution protocol fills it (ARP for IPv4).  A packet for a host not
+//! yet resolved waits in its entry, a few at most, until the answer comes
+//! or resolution gives up.  Resolved entries expire so that a host that
+//! changes NIC is found again; statically configured ones never do.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use super::MacAddr;
+
+/// How long a resolved entry is trusted.
+const REACHABLE_MS:  u64   = 300_000;
+/// Interval between resolution requests.
+const RETRANS_MS:    u64   = 1000;
+/// Requests sent before a host is declared unreachable.
+const MAX_REQUESTS:  u8    = 3;
+/// Packets held per unresolved host; older ones are dropped first.
+const PENDING_MAX:   usize = 4;
+/// Entries per interface; the stalest dynamic entry makes way.
+const CACHE_MAX:     usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighborState {
+    /// Being resolved: requests sent so far.
+    Incomplete(u8),
+    Reachable,
+    Static,
+}
+
+struct Entry<A> {
+    addr:    A,
+    mac:     Option<MacAddr>,
+    state:   NeighborState,
+    /// Expiry when Reachable, next retransmission when Incomplete.
+    timer:   u64,
+    pending: VecDeque<Vec<u8>>,
+}
+
+/// A cache entry as reported to callers.
+#[derive(Debug, Clone)]
+pub struct NeighborInfo<A> {
+    pub addr:  A,
+    pub mac:   Option<MacAddr>,
+    pub state: NeighborState,
+}
+
+pub enum Lookup {
+    Found(MacAddr),
+    /// Queued behind a new entry: send the first resolution request.
+    Resolve,
+    /// Queued behind a resolution already under way.
+    Queued,
+}
+
+pub struct NeighborCache<A> {
+    entries: Vec<Entry<A>>,
+}
+
+impl<A> Default for NeighborCache<A> {
+    fn default() -> Self {
+        NeighborCache { entries: Vec::new() }
+    }
+}
+
+impl<A: Copy + Eq> NeighborCache<A> {
+    /// The address for `addr`, or else queue `packet` until it is resolved.
+    pub fn lookup(&mut self, addr: A, packet: &[u8], now: u64) -> Lookup {
+        if let Some(e) = self.entries.iter_mut().find(|e| e.addr == addr) {
+            if let Some(mac) = e.mac { return Lookup::Found(mac); }
+            if e.pending.len() >= PENDING_MAX { e.pending.pop_front(); }
+            e.pending.push_back(packet.to_vec());
+            return Lookup::Queued;
+        }
+        self.make_room();
+        let mut pending = VecDeque::new();
+        pending.push_back(packet.to_vec());
+        self.entries.push(Entry {
+            addr, mac: None, state: NeighborState::Incomplete(1), timer: now + RETRANS_MS, pending,
+        });
+        Lookup::Resolve
+    }
+
+    /// `addr` is at `mac`, as a reply or the peer's own request says.
+    /// Only an existing entry is updated unless `create` is set.  Returns
+    /// the packets that were waiting for it.
+    pub fn update(&mut self, addr: A, mac: MacAddr, now: u64, create: bool) -> Vec<Vec<u8>> {
+        let e = match self.entries.iter_mut().position(|e| e.addr == addr) {
+            Some(i) => &mut self.entries[i],
+            None if create => {
+                self.make_room();
+                self.entries.push(Entry {
+                    addr, mac: None, state: NeighborState::Reachable, timer: 0, pending: VecDeque::new(),
+                });
+                self.entries.last_mut().unwrap()
+            }
+            None => return Vec::new(),
+        };
+        if e.state == NeighborState::Static { return Vec::new(); }
+        e.mac = Some(mac);
+        e.state = NeighborState::Reachable;
+        e.timer = now + REACHABLE_MS;
+        e.pending.drain(..).collect()
+    }
+
+    /// Pin `addr` to `mac`; it never expires.  Returns the packets that
+    /// were waiting for it.
+    pub fn set_static(&mut self, addr: A, mac: MacAddr) -> Vec<Vec<u8>> {
+        let pending = match self.entries.iter().position(|e| e.addr == addr) {
+            Some(i) => self.entries.swap_remove(i).pending.into(),
+            None    => Vec::new(),
+        };
+        self.entries.push(Entry { addr, mac: Some(mac), state: NeighborState::Static, timer: 0, pending: VecDeque::new() });
+        pending
+    }
+
+    pub fn remove(&mut self, addr: A) {
+        self.entries.retain(|e| e.addr != addr);
+    }
+
+    /// Forget every dynamic entry, as when the link changes.
+    pub fn flush(&mut self) {
+        self.entries.retain(|e| e.state == NeighborState::Static);
+    }
+
+    /// Expire entries and give up on unanswered resolutions.  Returns the
+    /// addresses whose request is due again.
+    pub fn tick(&mut self, now: u64) -> Vec<A> {
+        let mut resend = Vec::new();
+        self.entries.retain_mut(|e| match e.state {
+            NeighborState::Static => true,
+            NeighborState::Reachable => now < e.timer,
+            NeighborState::Incomplete(_) if now < e.timer => true,
+            NeighborState::Incomplete(n) if n >= MAX_REQUESTS => false,
+            NeighborState::Incomplete(n) => {
+                e.state = NeighborState::Incomplete(n + 1);
+                e.timer = now + RETRANS_MS;
+                resend.push(e.addr);
+                true
+            }
+        });
+        resend
+    }
+
+    pub fn entries(&self) -> Vec<NeighborInfo<A>> {
+        self.entries.iter().map(|e| NeighborInfo { addr: e.addr, mac: e.mac, state: e.state }).collect()
+    }
+
+    fn make_room(&mut self) {
+        if self.entries.len() < CACHE_MAX { return; }
+        let victim = self.entries.iter().enumerate()
+            .filter(|(_, e)| e.state != NeighborState::Static)
+            .min_by_key(|(_, e)| e.timer)
+            .map(|(i, _)| i);
+        if let Some(i) = victim { self.entries.swap_remove(i); }
+    }
+}
@@ -0,0 +1,315 @@
+//! Interface Management
+//! Netlink-style queries and configuration of network interfaces over
+//! IPC, served by networkd.  A client opens a channel with `connect` and
+//! sends requests on it; each is answered with [1, ...] on success and
+//! [0] on failure.  Listing links, addresses and name servers is open to
+//! every client.  Changing them needs the InterfaceAdmin capability with
+//! WRITE rights, presented at `connect` and checked again on every
+//! change, so revoking it takes effect at once.  The settings UI is one
+//! such client and the shell's `ip` another.
+//!
+//! A subscribed client is notified with the interface's index whenever
+//! one is attached, brought up or down, or readdressed, by a client or
+//! by DHCP.  A notification that finds the channel full is dropped, so a
+//! client that falls behind should list again.
+//!
+//! Addresses set here are static: giving an interface an IPv4 address or
+//! taking it away stops DHCP there, and networkd leaves the interface
+//! alone until DHCP is turned back on.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{dhcp, ipv6, networkd, IpAddr, Ipv4Addr, Ipv6Addr, MacAddr};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::ipc::{self, ChannelId, IpcError, Message, MessageKind};
+use crate::process::{self, ProcessId};
+
+/// Request opcodes (first payload byte).  Interfaces are named by index,
+/// and an address is [family u8 (4 or 6), 4 or 16 bytes].
+pub const IF_REQ_LINKS:     u8 = 1;  // [] -> [1, count u8, link...] (see `Link`)
+pub const IF_REQ_ADDRS:     u8 = 2;  // [index u8] -> [1, count u8, (addr, prefix u8)...]
+pub const IF_REQ_DNS:       u8 = 3;  // [index u8] -> [1, count u8, addr...]
+pub const IF_REQ_SUBSCRIBE: u8 = 4;  // [on u8]
+/// The rest need InterfaceAdmin.
+pub const IF_REQ_SET_LINK:  u8 = 5;  // [index u8, up u8]
+/// An IPv4 address replaces the interface's one.
+pub const IF_REQ_ADD_ADDR:  u8 = 6;  // [index u8, addr, prefix u8]
+pub const IF_REQ_DEL_ADDR:  u8 = 7;  // [index u8, addr]
+pub const IF_REQ_GATEWAY:   u8 = 8;  // [index u8, IPv4 4 bytes (0.0.0.0 for none)]
+pub const IF_REQ_SET_DNS:   u8 = 9;  // [index u8, count u8, IPv4 4 bytes...]
+pub const IF_REQ_DHCP:      u8 = 10; // [index u8, on u8]
+
+/// Notification kinds (first payload byte).
+pub const IF_NOTIFY_CHANGED: u8 = 1; // [index u8]
+
+/// Link flags.
+pub const LINK_UP:       u8 = 1 << 0;
+pub const LINK_ETHERNET: u8 = 1 << 1;
+pub const LINK_DHCP:     u8 = 1 << 2;
+
+// ─── wire format ──────────────────────────────────────────────────────────────
+
+/// A link as listed: [index u8, flags u8, mtu u32 LE, mac 6 bytes (zero
+/// off Ethernet), gateway 4 bytes (0.0.0.0 for none), rx_packets u64 LE,
+/// tx_packets u64 LE, name_len u8, name utf-8].
+#[derive(Debug, Clone)]
+pub struct Link {
+    pub index:      u8,
+    pub name:       String,
+    pub flags:      u8,
+    pub mtu:        u32,
+    pub mac:        Option<MacAddr>,
+    pub gateway:    Option<Ipv4Addr>,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+}
+
+impl Link {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.index, self.flags]);
+        out.extend_from_slice(&self.mtu.to_le_bytes());
+        out.extend_from_slice(&self.mac.map_or([0; 6], |m| m.0));
+        out.extend_from_slice(&self.gateway.unwrap_or(Ipv4Addr::UNSPECIFIED).0);
+        out.extend_from_slice(&self.rx_packets.to_le_bytes());
+        out.extend_from_slice(&self.tx_packets.to_le_bytes());
+        out.push(self.name.len() as u8);
+        out.extend_from_slice(self.name.as_bytes());
+    }
+
+    pub fn decode(r: &mut &[u8]) -> Option<Link> {
+        let [index, flags] = take(r)?;
+        let mtu = u32::from_le_bytes(take(r)?);
+        let mac = take(r)?;
+        let gateway = Ipv4Addr(take(r)?);
+        let rx_packets = u64::from_le_bytes(take(r)?);
+        let tx_packets = u64::from_le_bytes(take(r)?);
+        let [len] = take(r)?;
+        let name = r.get(..len as usize)?;
+        *r = &r[len as usize..];
+        Some(Link {
+            index, name: String::from(core::str::from_utf8(name).ok()?), flags, mtu,
+            mac: (flags & LINK_ETHERNET != 0).then_some(MacAddr(mac)),
+            gateway: (gateway != Ipv4Addr::UNSPECIFIED).then_some(gateway), rx_packets, tx_packets,
+        })
+    }
+}
+
+fn take<const N: usize>(r: &mut &[u8]) -> Option<[u8; N]> {
+    let (head, rest) = r.split_first_chunk::<N>()?;
+    *r = rest;
+    Some(*head)
+}
+
+pub fn put_addr(out: &mut Vec<u8>, ip: IpAddr) {
+    match ip {
+        IpAddr::V4(a) => { out.push(4); out.extend_from_slice(&a.0); }
+        IpAddr::V6(a) => { out.push(6); out.extend_from_slice(&a.0); }
+    }
+}
+
+pub fn take_addr(r: &mut &[u8]) -> Option<IpAddr> {
+    match take(r)? {
+        [4] => Some(IpAddr::V4(Ipv4Addr(take(r)?))),
+        [6] => Some(IpAddr::V6(Ipv6Addr(take(r)?))),
+        _   => None,
+    }
+}
+
+/// The body of an `IF_REQ_LINKS` reply.
+pub fn parse_links(mut r: &[u8]) -> Option<Vec<Link>> {
+    let [count] = take(&mut r)?;
+    (0..count).map(|_| Link::decode(&mut r)).collect()
+}
+
+/// The body of an `IF_REQ_ADDRS` reply, as (address, prefix length).
+pub fn parse_addrs(mut r: &[u8]) -> Option<Vec<(IpAddr, u8)>> {
+    let [count] = take(&mut r)?;
+    (0..count).map(|_| Some((take_addr(&mut r)?, take::<1>(&mut r)?[0]))).collect()
+}
+
+/// The body of an `IF_REQ_DNS` reply.
+pub fn parse_dns(mut r: &[u8]) -> Option<Vec<IpAddr>> {
+    let [count] = take(&mut r)?;
+    (0..count).map(|_| take_addr(&mut r)).collect()
+}
+
+// ─── clients ──────────────────────────────────────────────────────────────────
+
+struct Client {
+    pid:        ProcessId,
+    channel:    ChannelId,
+    /// The service's end of the channel.
+    cap:        Capability,
+    /// The client's InterfaceAdmin capability, if it presented one.
+    admin:      Option<Capability>,
+    subscribed: bool,
+}
+
+static CLIENTS: Mutex<Vec<Client>> = Mutex::new(Vec::new());
+/// Interfaces changed since subscribers were last told.
+static CHANGED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// Open a channel from `client` to networkd.  Presenting an
+/// InterfaceAdmin capability allows changes as well as queries.
+pub fn connect(client: ProcessId, admin_cap: Option<&Capability>) -> Result<(ChannelId, Capability), &'static str> {
+    if let Some(cap) = admin_cap { authorize(client, cap)?; }
+    let pid = networkd::pid().ok_or("network service not running")?;
+    let (ch, client_cap, svc_cap) = ipc::create_channel(client, pid);
+    CLIENTS.lock().push(Client { pid: client, channel: ch, cap: svc_cap, admin: admin_cap.cloned(), subscribed: false });
+    Ok((ch, client_cap))
+}
+
+/// Forget `client`'s channel and close it.
+pub fn disconnect(client: ProcessId, ch: ChannelId) {
+    CLIENTS.lock().retain(|c| !(c.channel == ch && c.pid == client));
+    ipc::close_channel(ch);
+}
+
+fn authorize(pid: ProcessId, cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(pid, cap, CapabilityType::InterfaceAdmin, Permissions::WRITE)
+}
+
+// ─── requests ─────────────────────────────────────────────────────────────────
+
+/// networkd's IPC handler.
+pub(super) fn handle_request(ch: ChannelId, msg: &Message) -> Option<Vec<u8>> {
+    let admin = {
+        let clients = CLIENTS.lock();
+        let c = clients.iter().find(|c| c.channel == ch && c.pid == msg.sender)?;
+        c.admin.clone()
+    };
+    let p = &msg.payload;
+    let result = match p.first() {
+        Some(&IF_REQ_LINKS)     => Ok(links()),
+        Some(&IF_REQ_ADDRS)     => index(p).and_then(addrs),
+        Some(&IF_REQ_DNS)       => index(p).and_then(dns),
+        Some(&IF_REQ_SUBSCRIBE) => match p.get(1) {
+            Some(&on) => {
+                CLIENTS.lock().iter_mut().filter(|c| c.channel == ch).for_each(|c| c.subscribed = on != 0);
+                Ok(Vec::new())
+            }
+            None => Err("bad request"),
+        },
+        Some(&op) if (IF_REQ_SET_LINK..=IF_REQ_DHCP).contains(&op) => admin.as_ref()
+            .ok_or("interface admin capability required")
+            .and_then(|cap| authorize(msg.sender, cap))
+            .and_then(|_| configure(op, p)).map(|_| Vec::new()),
+        _ => Err("bad request"),
+    };
+    Some(match result {
+        Ok(body) => [&[1u8][..], &body].concat(),
+        Err(_)   => Vec::from([0u8]),
+    })
+}
+
+fn index(p: &[u8]) -> Result<usize, &'static str> {
+    let i = *p.get(1).ok_or("bad request")? as usize;
+    super::interface(i).map(|_| i).ok_or("no such interface")
+}
+
+fn links() -> Vec<u8> {
+    let ifs = super::interfaces();
+    let mut out = Vec::from([ifs.len() as u8]);
+    for i in ifs {
+        let flags = if i.up { LINK_UP } else { 0 }
+            | if i.mac.is_some() { LINK_ETHERNET } else { 0 }
+            | if dhcp::running(i.index) { LINK_DHCP } else { 0 };
+        Link {
+            index: i.index as u8, name: i.name, flags, mtu: i.mtu as u32, mac: i.mac, gateway: i.gateway,
+            rx_packets: i.rx_packets, tx_packets: i.tx_packets,
+        }.encode(&mut out);
+    }
+    out
+}
+
+fn addrs(index: usize) -> Result<Vec<u8>, &'static str> {
+    let i = super::interface(index).ok_or("no such interface")?;
+    let all: Vec<(IpAddr, u8)> = i.addr.map(|(a, p)| (IpAddr::V4(a), p)).into_iter()
+        .chain(i.addrs6.iter().map(|a| (IpAddr::V6(a.addr), a.prefix)))
+        .collect();
+    let mut out = Vec::from([all.len() as u8]);
+    for (a, prefix) in all {
+        put_addr(&mut out, a);
+        out.push(prefix);
+    }
+    Ok(out)
+}
+
+fn dns(index: usize) -> Result<Vec<u8>, &'static str> {
+    let i = super::interface(index).ok_or("no such interface")?;
+    let mut out = Vec::from([i.dns.len() as u8]);
+    for &a in &i.dns { put_addr(&mut out, a); }
+    Ok(out)
+}
+
+fn configure(op: u8, p: &[u8]) -> Result<(), &'static str> {
+    let index = index(p)?;
+    let mut r = &p[2..];
+    match op {
+        IF_REQ_SET_LINK => super::set_up(index, *r.first().ok_or("bad request")? != 0),
+        IF_REQ_ADD_ADDR => match (take_addr(&mut r), r.first()) {
+            (Some(IpAddr::V4(a)), Some(&prefix)) => {
+                let gateway = super::interface(index).and_then(|i| i.gateway);
+                if dhcp::running(index) { dhcp::stop(index)?; }
+                super::configure(index, a, prefix, gateway)
+            }
+            (Some(IpAddr::V6(a)), Some(&prefix)) => ipv6::add_address(index, a, prefix),
+            _ => Err("bad address"),
+        },
+        IF_REQ_DEL_ADDR => match take_addr(&mut r).ok_or("bad address")? {
+            IpAddr::V4(a) => {
+                if super::interface(index).and_then(|i| i.addr).is_none_or(|(have, _)| have != a) {
+                    return Err("no such address");
+                }
+                if dhcp::running(index) { dhcp::stop(index)?; }
+                super::deconfigure(index)
+            }
+            IpAddr::V6(a) => ipv6::remove_address(index, a),
+        },
+        IF_REQ_GATEWAY => {
+            let gw = Ipv4Addr(take(&mut r).ok_or("bad request")?);
+            let (addr, prefix) = super::interface(index).and_then(|i| i.addr).ok_or("no IPv4 address")?;
+            super::configure(index, addr, prefix, (gw != Ipv4Addr::UNSPECIFIED).then_some(gw))
+        }
+        IF_REQ_SET_DNS => {
+            let [count] = take(&mut r).ok_or("bad request")?;
+            let servers: Option<Vec<Ipv4Addr>> = (0..count).map(|_| take(&mut r).map(Ipv4Addr)).collect();
+            super::set_dns(index, &servers.ok_or("bad request")?)
+        }
+        IF_REQ_DHCP => match r.first() {
+            Some(0) => dhcp::stop(index),
+            Some(_) => dhcp::start(index),
+            None    => Err("bad request"),
+        },
+        _ => Err("bad request"),
+    }
+}
+
+// ─── notifications ────────────────────────────────────────────────────────────
+
+/// Interface `index` was attached or its link or addresses changed; tell
+/// subscribers once the stack is done with it.
+pub(super) fn changed(index: usize) {
+    let mut pending = CHANGED.lock();
+    if !pending.contains(&index) { pending.push(index); }
+    drop(pending);
+    process::defer(notify);
+}
+
+fn notify() {
+    let changed = core::mem::take(&mut *CHANGED.lock());
+    let Some(pid) = networkd::pid() else { return };
+    let subscribers: Vec<(ChannelId, Capability)> = CLIENTS.lock().iter()
+        .filter(|c| c.subscribed).map(|c| (c.channel, c.cap.clone())).collect();
+    for (ch, cap) in subscribers {
+        for &index in &changed {
+            let r = ipc::send_message(ch, pid, &cap, MessageKind::Notification, &[IF_NOTIFY_CHANGED, index as u8]);
+            if r == Err(IpcError::NoSuchChannel) {
+                CLIENTS.lock().retain(|c| c.channel != ch);
+                break;
+            }
+        }
+    }
+}
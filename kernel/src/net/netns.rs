@@ -0,0 +1,326 @@
+//! Network Namespaces
+//! Per-app traffic isolation.  Every socket belongs to the process that
+//! opened it and only that process can use it, so one app can neither
+//! read another's traffic nor send on its connections; raw ICMP sockets
+//! see only replies to their own echo requests.
+//!
+//! Each app runs in a namespace: the default one unless assigned, and
+//! typically one per profile.  A namespace carries its own
+//!   - uplink: the interface its traffic must leave by (say `wg0` for a
+//!     work profile); if that is down there is no route, not a fallback
+//!   - firewall: rules on the remote network, protocol and port, first
+//!     match wins, then a default verdict
+//!   - offline switch, which an app can also have on its own
+//!
+//! Apps' data caps (see `accounting`) are checked with the firewall.
+//! These are enforced where the stack opens sockets and moves their
+//! traffic, so they hold whatever an app's UI does.  Going offline cuts
+//! an app's TCP connections at once; uplink and firewall changes apply
+//! to new connections and to every UDP datagram.  Traffic to our own
+//! addresses is local and exempt from uplink and firewall.
+//!
+//! Changing namespaces needs the Network capability with CONTROL rights.
+//! The stack's own services (DHCP, DNS, WireGuard) run as networkd.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{IpAddr, SocketAddr};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::process::ProcessId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetNs(pub u32);
+
+impl NetNs {
+    pub const DEFAULT: NetNs = NetNs(0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Deny,
+}
+
+/// A firewall rule, matched against the remote end of a connection or
+/// datagram.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub verdict: Verdict,
+    /// Remote network and prefix length.
+    pub net:     (IpAddr, u8),
+    /// Transport protocol number; any if None.
+    pub proto:   Option<u8>,
+    /// Remote port range, inclusive; any if None.
+    pub ports:   Option<(u16, u16)>,
+}
+
+impl Rule {
+    fn matches(&self, proto: u8, remote: SocketAddr) -> bool {
+        remote.ip.same_prefix(self.net.0, self.net.1)
+            && self.proto.is_none_or(|p| p == proto)
+            && self.ports.is_none_or(|(lo, hi)| (lo..=hi).contains(&remote.port))
+    }
+}
+
+struct Namespace {
+    id:      NetNs,
+    name:    String,
+    offline: bool,
+    uplink:  Option<usize>,
+    rules:   Vec<Rule>,
+    default: Verdict,
+    /// Connections and datagrams refused.
+    blocked: u64,
+}
+
+/// A namespace as reported to callers.
+#[derive(Debug, Clone)]
+pub struct NamespaceInfo {
+    pub id:      NetNs,
+    pub name:    String,
+    pub offline: bool,
+    pub uplink:  Option<usize>,
+    pub rules:   Vec<Rule>,
+    pub default: Verdict,
+    pub apps:    Vec<ProcessId>,
+    pub blocked: u64,
+}
+
+/// An app placed in a namespace or taken offline by itself.
+struct App {
+    pid:     ProcessId,
+    ns:      NetNs,
+    offline: bool,
+}
+
+struct Netns {
+    spaces:  Vec<Namespace>,
+    apps:    Vec<App>,
+    next_id: u32,
+}
+
+static NETNS: Mutex<Netns> = Mutex::new(Netns { spaces: Vec::new(), apps: Vec::new(), next_id: 1 });
+
+impl Netns {
+    /// The default namespace is made on first use.
+    fn spaces(&mut self) -> &mut Vec<Namespace> {
+        if self.spaces.is_empty() {
+            self.spaces.push(Namespace {
+                id: NetNs::DEFAULT, name: String::from("default"), offline: false, uplink: None,
+                rules: Vec::new(), default: Verdict::Allow, blocked: 0,
+            });
+        }
+        &mut self.spaces
+    }
+
+    fn get(&mut self, ns: NetNs) -> Result<&mut Namespace, &'static str> {
+        self.spaces().iter_mut().find(|s| s.id == ns).ok_or("no such namespace")
+    }
+
+    fn ns_of(&self, pid: ProcessId) -> NetNs {
+        self.apps.iter().find(|a| a.pid == pid).map_or(NetNs::DEFAULT, |a| a.ns)
+    }
+
+    fn app(&mut self, pid: ProcessId) -> &mut App {
+        let i = match self.apps.iter().position(|a| a.pid == pid) {
+            Some(i) => i,
+            None    => { self.apps.push(App { pid, ns: NetNs::DEFAULT, offline: false }); self.apps.len() - 1 }
+        };
+        &mut self.apps[i]
+    }
+
+    fn online(&mut self, pid: ProcessId) -> bool {
+        let ns = self.ns_of(pid);
+        let app_offline = self.apps.iter().any(|a| a.pid == pid && a.offline);
+        !app_offline && self.get(ns).is_ok_and(|s| !s.offline)
+    }
+
+    /// Forget apps that are back to the defaults.
+    fn tidy(&mut self) {
+        self.apps.retain(|a| a.ns != NetNs::DEFAULT || a.offline);
+    }
+}
+
+fn authorize(cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(crate::process::current_pid(), cap, CapabilityType::Network, Permissions::CONTROL)
+}
+
+fn is_local(ip: IpAddr) -> bool {
+    ip.is_loopback() || super::is_local(ip)
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Make a namespace, allowing everything until configured.
+pub fn create(cap: &Capability, name: &str) -> Result<NetNs, &'static str> {
+    authorize(cap)?;
+    let mut n = NETNS.lock();
+    if n.spaces().iter().any(|s| s.name == name) { return Err("namespace exists"); }
+    let id = NetNs(n.next_id);
+    n.next_id += 1;
+    n.spaces().push(Namespace {
+        id, name: String::from(name), offline: false, uplink: None, rules: Vec::new(), default: Verdict::Allow, blocked: 0,
+    });
+    Ok(id)
+}
+
+/// Remove a namespace; its apps return to the default one.
+pub fn remove(cap: &Capability, ns: NetNs) -> Result<(), &'static str> {
+    authorize(cap)?;
+    if ns == NetNs::DEFAULT { return Err("cannot remove the default namespace"); }
+    let mut n = NETNS.lock();
+    n.get(ns)?;
+    n.spaces.retain(|s| s.id != ns);
+    for a in n.apps.iter_mut().filter(|a| a.ns == ns) { a.ns = NetNs::DEFAULT; }
+    n.tidy();
+    drop(n);
+    cut_off();
+    Ok(())
+}
+
+pub fn find(name: &str) -> Option<NetNs> {
+    NETNS.lock().spaces().iter().find(|s| s.name == name).map(|s| s.id)
+}
+
+/// Move app `pid` into namespace `ns`.
+pub fn assign(cap: &Capability, pid: ProcessId, ns: NetNs) -> Result<(), &'static str> {
+    authorize(cap)?;
+    let mut n = NETNS.lock();
+    n.get(ns)?;
+    n.app(pid).ns = ns;
+    n.tidy();
+    drop(n);
+    cut_off();
+    Ok(())
+}
+
+pub fn namespace_of(pid: ProcessId) -> NetNs {
+    NETNS.lock().ns_of(pid)
+}
+
+/// Take a whole namespace offline, or bring it back.
+pub fn set_offline(cap: &Capability, ns: NetNs, offline: bool) -> Result<(), &'static str> {
+    authorize(cap)?;
+    NETNS.lock().get(ns)?.offline = offline;
+    cut_off();
+    Ok(())
+}
+
+/// Take one app offline, or bring it back; its namespace must also be
+/// online for it to reach the network.
+pub fn set_app_offline(cap: &Capability, pid: ProcessId, offline: bool) -> Result<(), &'static str> {
+    authorize(cap)?;
+    let mut n = NETNS.lock();
+    n.app(pid).offline = offline;
+    n.tidy();
+    drop(n);
+    cut_off();
+    Ok(())
+}
+
+pub fn app_offline(pid: ProcessId) -> bool {
+    NETNS.lock().apps.iter().any(|a| a.pid == pid && a.offline)
+}
+
+/// Force a namespace's traffic out of interface `uplink`, or let it
+/// follow the routing table again.
+pub fn set_uplink(cap: &Capability, ns: NetNs, uplink: Option<usize>) -> Result<(), &'static str> {
+    authorize(cap)?;
+    if uplink.is_some_and(|i| super::interface(i).is_none()) { return Err("no such interface"); }
+    NETNS.lock().get(ns)?.uplink = uplink;
+    Ok(())
+}
+
+/// Append a firewall rule.
+pub fn add_rule(cap: &Capability, ns: NetNs, rule: Rule) -> Result<(), &'static str> {
+    authorize(cap)?;
+    if rule.net.1 > if matches!(rule.net.0, IpAddr::V4(_)) { 32 } else { 128 } { return Err("bad prefix length"); }
+    NETNS.lock().get(ns)?.rules.push(rule);
+    Ok(())
+}
+
+pub fn clear_rules(cap: &Capability, ns: NetNs) -> Result<(), &'static str> {
+    authorize(cap)?;
+    NETNS.lock().get(ns)?.rules.clear();
+    Ok(())
+}
+
+/// The verdict for traffic no rule matches.
+pub fn set_default(cap: &Capability, ns: NetNs, verdict: Verdict) -> Result<(), &'static str> {
+    authorize(cap)?;
+    NETNS.lock().get(ns)?.default = verdict;
+    Ok(())
+}
+
+pub fn namespaces() -> Vec<NamespaceInfo> {
+    let mut n = NETNS.lock();
+    let apps: Vec<(ProcessId, NetNs)> = n.apps.iter().map(|a| (a.pid, a.ns)).collect();
+    n.spaces().iter().map(|s| NamespaceInfo {
+        id: s.id, name: s.name.clone(), offline: s.offline, uplink: s.uplink, rules: s.rules.clone(),
+        default: s.default, apps: apps.iter().filter(|a| a.1 == s.id).map(|a| a.0).collect(), blocked: s.blocked,
+    }).collect()
+}
+
+// ─── enforcement ──────────────────────────────────────────────────────────────
+
+/// End the TCP connections of apps now offline.
+fn cut_off() {
+    super::tcp::cut_off("network disabled for app", |owner, _| !online(owner));
+}
+
+/// Whether `pid` may use the network at all.
+pub fn online(pid: ProcessId) -> bool {
+    NETNS.lock().online(pid)
+}
+
+/// May `owner` exchange `proto` traffic with `remote`?  Counts refusals,
+/// data caps (see `accounting`) included.
+pub(super) fn check(owner: ProcessId, proto: u8, remote: SocketAddr) -> Result<(), &'static str> {
+    let local = is_local(remote.ip);
+    let mut n = NETNS.lock();
+    if !n.online(owner) { return Err("network disabled for app"); }
+    if local { return Ok(()); }
+    let ns = n.ns_of(owner);
+    let s = n.get(ns)?;
+    let verdict = s.rules.iter().find(|r| r.matches(proto, remote)).map_or(s.default, |r| r.verdict);
+    if verdict == Verdict::Deny {
+        s.blocked += 1;
+        return Err("blocked by firewall");
+    }
+    let uplink = s.uplink;
+    drop(n);
+    if super::accounting::capped(owner, uplink, remote.ip) {
+        if let Ok(s) = NETNS.lock().get(ns) { s.blocked += 1; }
+        return Err("data cap reached");
+    }
+    Ok(())
+}
+
+/// May `owner` take `proto` traffic from `remote` at our address
+/// `local`?  As `check`, and `local` must be on any uplink.
+pub(super) fn admit(owner: ProcessId, proto: u8, remote: SocketAddr, local: IpAddr) -> Result<(), &'static str> {
+    check(owner, proto, remote)?;
+    source(owner, remote.ip, local).map(|_| ())
+}
+
+/// The address `owner`'s traffic to `dst` leaves from: the routed one,
+/// or its namespace uplink's.  `bound`, the socket's own address if not
+/// unspecified, must be on the uplink.
+pub(super) fn source(owner: ProcessId, dst: IpAddr, bound: IpAddr) -> Result<IpAddr, &'static str> {
+    let uplink = {
+        let mut n = NETNS.lock();
+        let ns = n.ns_of(owner);
+        n.get(ns)?.uplink
+    };
+    match uplink {
+        Some(index) if !is_local(dst) => {
+            let src = super::source_via(index, dst).ok_or("uplink down")?;
+            if !bound.is_unspecified() && bound != src { return Err("address not on uplink"); }
+            Ok(src)
+        }
+        _ if !bound.is_unspecified() => Ok(bound),
+        _ => super::source_for(dst).ok_or("no route to host"),
+    }
+}
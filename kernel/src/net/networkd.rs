@@ -0,0 +1,53 @@
+//! networkd
+//! Network configuration service.  Every Ethernet interface is brought up
+//! as it is attached and a DHCP client started on it; interfaces given a
+//! static address with `net::configure` before networkd sees them are
+//! left alone, as are those a stack backend drives.  Loopback is
+//! configured by `net::init`.
+//!
+//! It also serves interface listing and configuration to its IPC clients
+//! (see `netlink`).
+//!
+//! The stack's own services (DHCP, DNS, WireGuard) run as networkd, so
+//! their sockets are its own and no app's namespace applies to them.
+
+use spin::Mutex;
+
+use super::dhcp;
+use crate::ipc;
+use crate::process::{self, ProcessId};
+
+static PID: Mutex<Option<ProcessId>> = Mutex::new(None);
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("networkd")?;
+    *PID.lock() = Some(pid);
+    ipc::register_kernel_server(pid, super::netlink::handle_request);
+    scan();
+    Ok(())
+}
+
+pub fn pid() -> Option<ProcessId> {
+    *PID.lock()
+}
+
+/// Run `f` as networkd, or as the caller before the service starts.
+pub fn run<R>(f: impl FnOnce() -> R) -> R {
+    match pid() {
+        Some(pid) => process::run_as(pid, f),
+        None      => f(),
+    }
+}
+
+/// Configure interfaces attached since the last scan.  Deferred by
+/// `net::attach`; does nothing until the service is running.
+pub fn scan() {
+    let Some(pid) = pid() else { return };
+    process::run_as(pid, || for i in super::interfaces() {
+        if i.mac.is_none() || i.addr.is_some() || dhcp::running(i.index) || super::backend::get().owns(i.index) { continue; }
+        if !i.up { let _ = super::set_up(i.index, true); }
+        if let Err(e) = dhcp::start(i.index) {
+            crate::println!("  [networkd] {}: DHCP failed to start: {}", i.name, e);
+        }
+    });
+}
@@ -0,0 +1,332 @@
+//! Proxies
+//! Per-app routing of stream connections through a SOCKS5 (RFC 1928) or
+//! HTTP CONNECT proxy, for Tor-style privacy routing or a corporate
+//! gateway.  The socket layer applies it in `connect`, so an app is
+//! proxied whatever its own code does: its connection goes to the proxy,
+//! which is asked to reach the real destination.
+//!
+//! A proxied app fails closed.  If the proxy cannot be reached or refuses,
+//! the connection fails rather than going out directly, and traffic the
+//! proxy cannot carry is refused: datagrams and QUIC, and name lookups,
+//! which would tell the DNS servers where the app is going.  Instead the
+//! app connects to a name (`socket::connect_host`), which the proxy
+//! resolves.  Traffic to our own addresses is local and not proxied.
+//!
+//! A proxy needing authentication gets a username and a password held in
+//! the keyring: SOCKS5 username/password (RFC 1929) or HTTP Basic.  The
+//! key belongs to whoever configured the proxy, and is opened only for
+//! the handshake.  Configuring proxies needs the Network capability with
+//! CONTROL rights.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::{IpAddr, SocketAddr};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::crypto;
+use crate::keyring::{self, KeyId};
+use crate::process::ProcessId;
+
+const SOCKS_VERSION:      u8 = 5;
+const SOCKS_NO_AUTH:      u8 = 0x00;
+const SOCKS_USER_PASS:    u8 = 0x02;
+const SOCKS_NO_METHOD:    u8 = 0xFF;
+const SOCKS_AUTH_VERSION: u8 = 1;
+const SOCKS_CONNECT:      u8 = 1;
+const ATYP_IPV4:          u8 = 1;
+const ATYP_DOMAIN:        u8 = 3;
+const ATYP_IPV6:          u8 = 4;
+
+/// Longest HTTP response head read from a proxy.
+const MAX_HEAD: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Socks5,
+    Http,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Socks5 => "socks5",
+            Kind::Http   => "http",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Kind> {
+        [Kind::Socks5, Kind::Http].into_iter().find(|k| k.as_str() == s)
+    }
+}
+
+/// A proxy and how to authenticate to it.
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    pub kind: Kind,
+    pub addr: SocketAddr,
+    /// Username and the keyring key holding the password.
+    pub auth: Option<(String, KeyId)>,
+}
+
+/// An app's proxy as reported to callers.
+#[derive(Debug, Clone)]
+pub struct ProxyInfo {
+    pub pid:      ProcessId,
+    pub proxy:    Proxy,
+    /// Connections made through it, and refused by it or for want of it.
+    pub connects: u64,
+    pub failures: u64,
+}
+
+/// Where a proxied connection is going: an address, or a name for the
+/// proxy to resolve.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Addr(SocketAddr),
+    Host(String, u16),
+}
+
+impl Target {
+    /// `host` at `port`: an address if it is a literal, else a name.
+    pub fn new(host: &str, port: u16) -> Target {
+        match IpAddr::parse(host) {
+            Some(ip) => Target::Addr(SocketAddr::new(ip, port)),
+            None     => Target::Host(host.trim_end_matches('.').to_ascii_lowercase(), port),
+        }
+    }
+}
+
+struct Route {
+    pid:      ProcessId,
+    proxy:    Proxy,
+    /// Who configured it, and owns the password key.
+    owner:    ProcessId,
+    connects: u64,
+    failures: u64,
+}
+
+static ROUTES: Mutex<Vec<Route>> = Mutex::new(Vec::new());
+
+/// A connected stream to the proxy, for the handshake.
+pub(super) trait Tunnel {
+    fn send_all(&mut self, data: &[u8]) -> Result<(), &'static str>;
+    /// Read at least one byte; `Ok(0)` means the proxy closed.
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, &'static str>;
+}
+
+fn recv_exact(t: &mut dyn Tunnel, buf: &mut [u8]) -> Result<(), &'static str> {
+    let mut got = 0;
+    while got < buf.len() {
+        let n = t.recv(&mut buf[got..])?;
+        if n == 0 { return Err("proxy closed the connection"); }
+        got += n;
+    }
+    Ok(())
+}
+
+fn authorize(cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(crate::process::current_pid(), cap, CapabilityType::Network, Permissions::CONTROL)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            let c = if i <= chunk.len() { ALPHABET[(n >> (18 - 6 * i)) as usize & 63] } else { b'=' };
+            out.push(c as char);
+        }
+    }
+    out
+}
+
+// ─── handshakes ───────────────────────────────────────────────────────────────
+
+fn socks5(t: &mut dyn Tunnel, target: &Target, auth: Option<(&str, &[u8])>) -> Result<(), &'static str> {
+    let method = if auth.is_some() { SOCKS_USER_PASS } else { SOCKS_NO_AUTH };
+    t.send_all(&[SOCKS_VERSION, 1, method])?;
+    let mut reply = [0u8; 2];
+    recv_exact(t, &mut reply)?;
+    if reply[0] != SOCKS_VERSION { return Err("not a SOCKS5 proxy"); }
+    match (reply[1], auth) {
+        (SOCKS_NO_AUTH, None) => {}
+        (SOCKS_USER_PASS, Some((user, password))) => {
+            if user.len() > 255 || password.len() > 255 { return Err("proxy credentials too long"); }
+            let mut req = vec![SOCKS_AUTH_VERSION, user.len() as u8];
+            req.extend_from_slice(user.as_bytes());
+            req.push(password.len() as u8);
+            req.extend_from_slice(password);
+            let sent = t.send_all(&req);
+            crypto::wipe(&mut req);
+            sent?;
+            recv_exact(t, &mut reply)?;
+            if reply[1] != 0 { return Err("proxy rejected the credentials"); }
+        }
+        (SOCKS_NO_METHOD, _) => return Err("proxy refused authentication"),
+        _ => return Err("proxy chose a method not offered"),
+    }
+
+    let mut req = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+    let port = match target {
+        Target::Addr(a) => {
+            match a.ip {
+                IpAddr::V4(v4) => { req.push(ATYP_IPV4); req.extend_from_slice(&v4.0); }
+                IpAddr::V6(v6) => { req.push(ATYP_IPV6); req.extend_from_slice(&v6.0); }
+            }
+            a.port
+        }
+        Target::Host(host, port) => {
+            if host.is_empty() || host.len() > 255 { return Err("invalid name"); }
+            req.push(ATYP_DOMAIN);
+            req.push(host.len() as u8);
+            req.extend_from_slice(host.as_bytes());
+            *port
+        }
+    };
+    req.extend_from_slice(&port.to_be_bytes());
+    t.send_all(&req)?;
+
+    // The reply carries the proxy's bound address, read off so none of it
+    // is taken for the peer's data
+    let mut head = [0u8; 5];
+    recv_exact(t, &mut head)?;
+    if head[0] != SOCKS_VERSION { return Err("not a SOCKS5 proxy"); }
+    match head[1] {
+        0 => {}
+        2 => return Err("connection not allowed by proxy"),
+        3 => return Err("network unreachable"),
+        4 => return Err("host unreachable"),
+        5 => return Err("connection refused"),
+        6 => return Err("proxy TTL expired"),
+        _ => return Err("proxy could not connect"),
+    }
+    let rest = match head[3] {
+        ATYP_IPV4   => 4 - 1 + 2,
+        ATYP_IPV6   => 16 - 1 + 2,
+        ATYP_DOMAIN => head[4] as usize + 2,
+        _           => return Err("bad SOCKS5 reply"),
+    };
+    recv_exact(t, &mut vec![0u8; rest])
+}
+
+fn http_connect(t: &mut dyn Tunnel, target: &Target, auth: Option<(&str, &[u8])>) -> Result<(), &'static str> {
+    let authority = match target {
+        Target::Addr(a) => match a.ip {
+            IpAddr::V6(_) => format!("[{}]:{}", a.ip, a.port),
+            IpAddr::V4(_) => format!("{}:{}", a.ip, a.port),
+        },
+        Target::Host(host, port) => {
+            if host.is_empty() || host.bytes().any(|b| b <= b' ' || b == b'/' || b == b'@') { return Err("invalid name"); }
+            format!("{}:{}", host, port)
+        }
+    };
+    let mut req = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority).into_bytes();
+    if let Some((user, password)) = auth {
+        let mut credentials = user.as_bytes().to_vec();
+        credentials.push(b':');
+        credentials.extend_from_slice(password);
+        let mut header = format!("Proxy-Authorization: Basic {}\r\n", base64(&credentials)).into_bytes();
+        req.extend_from_slice(&header);
+        crypto::wipe(&mut credentials);
+        crypto::wipe(&mut header);
+    }
+    req.extend_from_slice(b"\r\n");
+    let sent = t.send_all(&req);
+    crypto::wipe(&mut req);
+    sent?;
+
+    // A byte at a time, so nothing past the head is consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_HEAD { return Err("proxy response too long"); }
+        let mut b = [0u8; 1];
+        recv_exact(t, &mut b)?;
+        head.push(b[0]);
+    }
+    let status: u16 = core::str::from_utf8(&head).ok()
+        .and_then(|h| h.split(' ').nth(1)).and_then(|s| s.parse().ok()).ok_or("bad proxy response")?;
+    match status {
+        200..=299 => Ok(()),
+        407       => Err("proxy rejected the credentials"),
+        403       => Err("connection not allowed by proxy"),
+        502 | 504 => Err("proxy could not connect"),
+        _         => Err("proxy refused the connection"),
+    }
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Route `pid`'s connections through `proxy`, or directly again.  A
+/// password key must be the caller's.
+pub fn set(cap: &Capability, pid: ProcessId, proxy: Option<Proxy>) -> Result<(), &'static str> {
+    authorize(cap)?;
+    let owner = crate::process::current_pid();
+    if let Some(p) = &proxy {
+        if p.addr.port == 0 || p.addr.ip.is_unspecified() { return Err("bad proxy address"); }
+        if let Some((user, key)) = &p.auth {
+            if user.is_empty() || user.contains(':') { return Err("bad proxy username"); }
+            let info = keyring::info(*key).ok_or("no such key")?;
+            if info.owner != Some(owner) { return Err("key belongs to another process"); }
+        }
+    }
+    let mut routes = ROUTES.lock();
+    routes.retain(|r| r.pid != pid);
+    if let Some(proxy) = proxy {
+        routes.push(Route { pid, proxy, owner, connects: 0, failures: 0 });
+    }
+    Ok(())
+}
+
+pub fn proxy_of(pid: ProcessId) -> Option<Proxy> {
+    ROUTES.lock().iter().find(|r| r.pid == pid).map(|r| r.proxy.clone())
+}
+
+pub fn proxies() -> Vec<ProxyInfo> {
+    ROUTES.lock().iter().map(|r| ProxyInfo {
+        pid: r.pid, proxy: r.proxy.clone(), connects: r.connects, failures: r.failures,
+    }).collect()
+}
+
+// ─── enforcement ──────────────────────────────────────────────────────────────
+
+/// Whether `owner`'s traffic to `dst` must go through a proxy it has.
+pub(super) fn applies(owner: ProcessId, dst: IpAddr) -> bool {
+    !(dst.is_loopback() || super::is_local(dst)) && is_proxied(owner)
+}
+
+pub(super) fn is_proxied(owner: ProcessId) -> bool {
+    ROUTES.lock().iter().any(|r| r.pid == owner)
+}
+
+/// Ask the proxy at the far end of `t`, `owner`'s, to connect to
+/// `target`.  On success the stream carries the target's traffic.
+pub(super) fn handshake(owner: ProcessId, t: &mut dyn Tunnel, target: &Target) -> Result<(), &'static str> {
+    let (proxy, key_owner) = ROUTES.lock().iter().find(|r| r.pid == owner)
+        .map(|r| (r.proxy.clone(), r.owner)).ok_or("no proxy")?;
+    let password = match &proxy.auth {
+        Some((_, key)) => Some(keyring::open(*key, Some(key_owner))?),
+        None           => None,
+    };
+    let auth = proxy.auth.as_ref().zip(password.as_deref()).map(|((user, _), p)| (user.as_str(), p));
+    match proxy.kind {
+        Kind::Socks5 => socks5(t, target, auth),
+        Kind::Http   => http_connect(t, target, auth),
+    }
+}
+
+/// Count a connection through `owner`'s proxy, or one that failed.
+pub(super) fn counted(owner: ProcessId, ok: bool) {
+    if let Some(r) = ROUTES.lock().iter_mut().find(|r| r.pid == owner) {
+        if ok { r.connects += 1; } else { r.failures += 1; }
+    }
+}
+
+/// The proxy's address for `owner`.
+pub(super) fn address(owner: ProcessId) -> Option<SocketAddr> {
+    ROUTES.lock().iter().find(|r| r.pid == owner).map(|r| r.proxy.addr)
+}
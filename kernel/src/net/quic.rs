@@ -0,0 +1,1800 @@
+//! QUIC
+//! QUIC version 1 client connections (RFC 9000, 9001, 9002) over UDP from
+//! the stack backend.  The handshake is the TLS 1.3 one from `tls`,
+//! carried in CRYPTO frames and offering the same cipher suite; Initial
+//! packets use the AES-128-GCM keys every version 1 endpoint derives from
+//! our first connection ID.
+//!
+//! A connection carries bidirectional streams either side opens and
+//! unidirectional ones, under flow control both ways.  Lost packets are
+//! found from acknowledgement gaps and timers, with probes when nothing
+//! comes back, their frames sent again, and NewReno congestion control
+//! bounds what is in flight.
+//!
+//! When the route to the server starts leaving from another address
+//! (Wi-Fi lost and cellular taking over, say) the connection migrates:
+//! it moves to a fresh port and a connection ID the server has not seen
+//! us use, so the two paths cannot be linked, and validates the new path
+//! as it carries on.  A server that disables migration keeps the old one.
+//!
+//! A connection belongs to the process that opened it; its app's
+//! namespace vets the server and going offline cuts it off.  The stack's
+//! poll reads each connection's datagrams and runs its timers as the
+//! owning app.  Calls never block; `socket` waits on them.
+//!
+//! Not implemented: 0-RTT, path MTU discovery (datagrams stay at 1200
+//! bytes), ECN and the DATAGRAM extension.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::backend;
+use super::tls::{self, Handshake, Step};
+use super::{ipv4, netns, IpAddr, SocketAddr};
+use crate::crypto::aes;
+use crate::crypto::chacha20poly1305 as aead;
+use crate::crypto::sha2;
+use crate::event::Ready;
+use crate::process::ProcessId;
+
+const VERSION: u32 = 1;
+
+/// RFC 9001 5.2: the salt Initial secrets are extracted with.
+const INITIAL_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2C, 0xF7, 0xF5, 0x59, 0x34, 0xB3, 0x4D, 0x17,
+    0x9A, 0xE6, 0xA4, 0xC8, 0x0C, 0xAD, 0xCC, 0xBB, 0x7F, 0x0A,
+];
+/// RFC 9001 5.8: the fixed key and nonce of Retry integrity tags.
+const RETRY_KEY:   [u8; 16] = [0xBE, 0x0C, 0x69, 0x0B, 0x9F, 0x66, 0x57, 0x5A, 0x1D, 0x76, 0x6B, 0x54, 0xE3, 0x68, 0xC8, 0x4E];
+const RETRY_NONCE: [u8; 12] = [0x46, 0x15, 0x99, 0xD3, 0x5D, 0x63, 0x2B, 0xF2, 0x23, 0x98, 0x25, 0xBB];
+
+const EXT_TRANSPORT_PARAMS: u16 = 0x39;
+
+/// Our connection IDs' length.
+const CID_LEN:      usize = 8;
+const RESET_TOKEN:  usize = 16;
+/// Every datagram we send fits the smallest a QUIC path must carry.
+const MAX_DATAGRAM: usize = 1200;
+/// Largest datagram we take.
+const MAX_RECV:     usize = 1500;
+/// Packet numbers are always sent in full four bytes.
+const PN_LEN:       usize = 4;
+
+const IDLE_TIMEOUT_MS:  u64 = 30_000;
+const MAX_ACK_DELAY_MS: u64 = 25;
+/// What we let the server send ahead on each stream and in all.
+const STREAM_WINDOW:    u64 = 256 * 1024;
+const CONN_WINDOW:      u64 = 1024 * 1024;
+/// Streams of each kind the server may have open at once.
+const MAX_PEER_STREAMS: u64 = 100;
+/// Connection IDs of ours the server may hold.
+const ACTIVE_CID_LIMIT: u64 = 4;
+/// Unsent data a stream buffers before `stream_send` would block.
+const SEND_BUF:         usize = 256 * 1024;
+
+// RFC 9002 loss detection and congestion control
+const INITIAL_RTT_MS:   u64   = 333;
+const PACKET_THRESHOLD: u64   = 3;
+const GRANULARITY_MS:   u64   = 1;
+const INITIAL_WINDOW:   usize = 10 * MAX_DATAGRAM;
+const MIN_WINDOW:       usize = 2 * MAX_DATAGRAM;
+
+// Transport error codes
+const NO_ERROR:                  u64 = 0x0;
+const FLOW_CONTROL_ERROR:        u64 = 0x3;
+const STREAM_LIMIT_ERROR:        u64 = 0x4;
+const STREAM_STATE_ERROR:        u64 = 0x5;
+const FINAL_SIZE_ERROR:          u64 = 0x6;
+const FRAME_ENCODING_ERROR:      u64 = 0x7;
+const TRANSPORT_PARAM_ERROR:     u64 = 0x8;
+const CONNECTION_ID_LIMIT_ERROR: u64 = 0x9;
+const PROTOCOL_VIOLATION:        u64 = 0xA;
+/// TLS alerts: handshake_failure and no_application_protocol.
+const CRYPTO_ERROR:              u64 = 0x100 + 40;
+const NO_APPLICATION_PROTOCOL:   u64 = 0x100 + 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuicHandle(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuicState {
+    Handshaking,
+    Established,
+    Closed,
+}
+
+// ─── wire helpers ─────────────────────────────────────────────────────────────
+
+/// A cursor over a packet or frame.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], &'static str> {
+        if self.0.len() < n { return Err("truncated packet"); }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, &'static str> {
+        let first = self.u8()?;
+        let len = 1 << (first >> 6);
+        let mut v = (first & 0x3F) as u64;
+        for &b in self.take(len - 1)? { v = v << 8 | b as u64; }
+        Ok(v)
+    }
+}
+
+fn varint_len(v: u64) -> usize {
+    match v {
+        0..=63            => 1,
+        64..=16383        => 2,
+        16384..=0x3FFF_FFFF => 4,
+        _                 => 8,
+    }
+}
+
+fn put_varint(b: &mut Vec<u8>, v: u64) {
+    match varint_len(v) {
+        1 => b.push(v as u8),
+        2 => b.extend_from_slice(&(v as u16 | 0x4000).to_be_bytes()),
+        4 => b.extend_from_slice(&(v as u32 | 0x8000_0000).to_be_bytes()),
+        _ => b.extend_from_slice(&(v | 0xC000_0000_0000_0000).to_be_bytes()),
+    }
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut b = [0u8; N];
+    crate::entropy::fill_bytes(&mut b);
+    b
+}
+
+/// Inclusive ranges of packet numbers or stream offsets, in order and
+/// never touching.
+#[derive(Default)]
+struct Ranges(Vec<(u64, u64)>);
+
+impl Ranges {
+    fn insert(&mut self, lo: u64, hi: u64) {
+        let mut lo = lo;
+        let mut hi = hi;
+        self.0.retain(|&(a, b)| {
+            let apart = b.saturating_add(1) < lo || hi.saturating_add(1) < a;
+            if !apart { lo = lo.min(a); hi = hi.max(b); }
+            apart
+        });
+        let at = self.0.iter().position(|&(a, _)| a > hi).unwrap_or(self.0.len());
+        self.0.insert(at, (lo, hi));
+    }
+
+    fn remove(&mut self, lo: u64, hi: u64) {
+        let mut kept = Vec::new();
+        for &(a, b) in &self.0 {
+            if a < lo { kept.push((a, b.min(lo - 1))); }
+            if b > hi { kept.push((a.max(hi + 1), b)); }
+        }
+        self.0 = kept;
+    }
+
+    fn contains(&self, v: u64) -> bool {
+        self.0.iter().any(|&(a, b)| a <= v && v <= b)
+    }
+
+    fn remove_below(&mut self, v: u64) {
+        self.0.retain(|&(_, b)| b >= v);
+        if let Some(first) = self.0.first_mut() { first.0 = first.0.max(v); }
+    }
+
+    fn largest(&self) -> Option<u64> {
+        self.0.last().map(|r| r.1)
+    }
+}
+
+// ─── packet protection ────────────────────────────────────────────────────────
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Suite {
+    Aes128Gcm,
+    ChaCha20Poly1305,
+}
+
+/// One direction's keys at one encryption level.
+#[derive(Clone)]
+struct Keys {
+    suite:  Suite,
+    secret: [u8; 32],
+    /// The first 16 bytes for AES.
+    key:    [u8; 32],
+    iv:     [u8; 12],
+    hp:     [u8; 32],
+}
+
+impl Keys {
+    fn new(suite: Suite, secret: [u8; 32]) -> Keys {
+        let len = if suite == Suite::Aes128Gcm { aes::KEY_LEN } else { aead::KEY_LEN };
+        let mut k = Keys { suite, secret, key: [0; 32], iv: [0; 12], hp: [0; 32] };
+        tls::expand_label(&secret, "quic key", &[], &mut k.key[..len]);
+        tls::expand_label(&secret, "quic iv", &[], &mut k.iv);
+        tls::expand_label(&secret, "quic hp", &[], &mut k.hp[..len]);
+        k
+    }
+
+    /// The Initial keys, client's then server's, for a connection whose
+    /// first Initial went to `dcid`.
+    fn initial(dcid: &[u8]) -> (Keys, Keys) {
+        let initial = sha2::hkdf_extract(&INITIAL_SALT, dcid);
+        let mut client = [0u8; 32];
+        let mut server = [0u8; 32];
+        tls::expand_label(&initial, "client in", &[], &mut client);
+        tls::expand_label(&initial, "server in", &[], &mut server);
+        (Keys::new(Suite::Aes128Gcm, client), Keys::new(Suite::Aes128Gcm, server))
+    }
+
+    /// The next 1-RTT keys; header protection stays.
+    fn next(&self) -> Keys {
+        let mut secret = [0u8; 32];
+        tls::expand_label(&self.secret, "quic ku", &[], &mut secret);
+        Keys { hp: self.hp, ..Keys::new(self.suite, secret) }
+    }
+
+    fn nonce(&self, pn: u64) -> [u8; 12] {
+        let mut n = self.iv;
+        for (b, p) in n[4..].iter_mut().zip(pn.to_be_bytes()) { *b ^= p; }
+        n
+    }
+
+    fn seal(&self, pn: u64, header: &[u8], payload: &[u8]) -> Vec<u8> {
+        match self.suite {
+            Suite::Aes128Gcm        => aes::seal(self.key[..16].try_into().unwrap(), &self.nonce(pn), header, payload),
+            Suite::ChaCha20Poly1305 => aead::seal(&self.key, &self.nonce(pn), header, payload),
+        }
+    }
+
+    fn open(&self, pn: u64, header: &[u8], sealed: &[u8]) -> Result<Vec<u8>, &'static str> {
+        match self.suite {
+            Suite::Aes128Gcm        => aes::open(self.key[..16].try_into().unwrap(), &self.nonce(pn), header, sealed),
+            Suite::ChaCha20Poly1305 => aead::open(&self.key, &self.nonce(pn), header, sealed),
+        }
+    }
+
+    /// The header protection mask for `sample`.
+    fn mask(&self, sample: &[u8]) -> [u8; 5] {
+        let mut mask = [0u8; 5];
+        match self.suite {
+            Suite::Aes128Gcm => {
+                let mut block: [u8; 16] = sample[..16].try_into().unwrap();
+                aes::Aes128::new(self.hp[..16].try_into().unwrap()).encrypt_block(&mut block);
+                mask.copy_from_slice(&block[..5]);
+            }
+            Suite::ChaCha20Poly1305 => {
+                let counter = u32::from_le_bytes(sample[..4].try_into().unwrap());
+                aead::chacha20(&self.hp, counter, sample[4..16].try_into().unwrap(), &mut mask);
+            }
+        }
+        mask
+    }
+}
+
+/// Apply or remove header protection on `packet`, whose packet number
+/// starts at `pn_at`.
+fn protect_header(keys: &Keys, packet: &mut [u8], pn_at: usize) {
+    let mask = keys.mask(&packet[pn_at + 4..pn_at + 20]);
+    packet[0] ^= mask[0] & if packet[0] & 0x80 != 0 { 0x0F } else { 0x1F };
+    for i in 0..PN_LEN { packet[pn_at + i] ^= mask[1 + i]; }
+}
+
+/// The full packet number closest to the next one expected (RFC 9000 A.3).
+fn decode_pn(largest: Option<u64>, truncated: u64, bits: u32) -> u64 {
+    let expected = largest.map_or(0, |l| l + 1);
+    let win = 1u64 << bits;
+    let candidate = (expected & !(win - 1)) | truncated;
+    if candidate + win / 2 <= expected && candidate < (1 << 62) - win {
+        candidate + win
+    } else if candidate > expected + win / 2 && candidate >= win {
+        candidate - win
+    } else {
+        candidate
+    }
+}
+
+// ─── stream buffers ───────────────────────────────────────────────────────────
+
+/// Data being sent on a stream or at a crypto level, kept until it is
+/// acknowledged.
+#[derive(Default)]
+struct SendBuf {
+    /// Bytes from offset `base` on.
+    data:     VecDeque<u8>,
+    base:     u64,
+    /// First offset never sent.
+    next:     u64,
+    acked:    Ranges,
+    /// Ranges to send again.
+    lost:     Ranges,
+    /// The app has finished the stream.
+    fin:      bool,
+    fin_sent: bool,
+    fin_acked: bool,
+}
+
+impl SendBuf {
+    fn end(&self) -> u64 {
+        self.base + self.data.len() as u64
+    }
+
+    fn write(&mut self, d: &[u8]) {
+        self.data.extend(d);
+    }
+
+    fn has_data(&self, limit: u64) -> bool {
+        !self.lost.0.is_empty() || (self.next < self.end() && self.next < limit) || (self.fin && !self.fin_sent && self.next == self.end())
+    }
+
+    /// The next piece to send, at most `max` bytes: lost data first, then
+    /// new data below `limit`.  Returns its offset, the data and whether
+    /// it ends the stream.
+    fn next_chunk(&mut self, max: usize, limit: u64) -> Option<(u64, Vec<u8>, bool)> {
+        let end = self.end();
+        let (offset, len) = if let Some(&(lo, hi)) = self.lost.0.first() {
+            let len = (hi - lo + 1).min(max as u64);
+            self.lost.0.remove(0);
+            if lo + len <= hi { self.lost.insert(lo + len, hi); }
+            (lo, len)
+        } else if self.next < end.min(limit) {
+            let len = (end.min(limit) - self.next).min(max as u64);
+            self.next += len;
+            (self.next - len, len)
+        } else if self.fin && !self.fin_sent && self.next == end {
+            (end, 0)
+        } else {
+            return None;
+        };
+        let at = (offset - self.base) as usize;
+        let data: Vec<u8> = self.data.range(at..at + len as usize).copied().collect();
+        let fin = self.fin && offset + len == end;
+        if fin { self.fin_sent = true; }
+        Some((offset, data, fin))
+    }
+
+    fn on_ack(&mut self, offset: u64, len: u64, fin: bool) {
+        if fin { self.fin_acked = true; }
+        if len == 0 || offset + len <= self.base { return; }
+        self.acked.insert(offset, offset + len - 1);
+        self.lost.remove(offset, offset + len - 1);
+        // Data acknowledged from the front is done with
+        if let Some(&(_, hi)) = self.acked.0.first().filter(|r| r.0 <= self.base) {
+            let drop = (hi + 1 - self.base) as usize;
+            self.data.drain(..drop.min(self.data.len()));
+            self.base = hi + 1;
+            self.acked.remove_below(self.base);
+        }
+    }
+
+    fn on_lost(&mut self, offset: u64, len: u64, fin: bool) {
+        if fin && !self.fin_acked { self.fin_sent = false; }
+        let lo = offset.max(self.base);
+        if len == 0 || lo >= offset + len { return; }
+        // Only what is still unacknowledged
+        let mut at = lo;
+        for &(a, b) in &self.acked.0 {
+            if b < at { continue; }
+            if a >= offset + len { break; }
+            if a > at { self.lost.insert(at, a - 1); }
+            at = b + 1;
+        }
+        if at < offset + len { self.lost.insert(at, offset + len - 1); }
+    }
+
+    /// Everything, the end included, has been acknowledged.
+    fn done(&self) -> bool {
+        self.fin && self.fin_acked && self.data.is_empty()
+    }
+}
+
+/// Data arriving on a stream or at a crypto level, put back in order.
+#[derive(Default)]
+struct RecvBuf {
+    /// Segments past the in-order data, by offset.
+    segments: BTreeMap<u64, Vec<u8>>,
+    /// In-order data not yet taken.
+    ready:    Vec<u8>,
+    /// Offset `ready` ends at.
+    end:      u64,
+    /// Highest offset seen.
+    highest:  u64,
+    fin:      Option<u64>,
+}
+
+impl RecvBuf {
+    fn insert(&mut self, offset: u64, data: &[u8]) {
+        let top = offset + data.len() as u64;
+        self.highest = self.highest.max(top);
+        if top <= self.end { return; }
+        let skip = self.end.saturating_sub(offset) as usize;
+        let offset = offset.max(self.end);
+        let data = &data[skip..];
+        if self.segments.get(&offset).is_none_or(|s| s.len() < data.len()) {
+            self.segments.insert(offset, data.to_vec());
+        }
+        while let Some((&at, _)) = self.segments.first_key_value() {
+            if at > self.end { break; }
+            let seg = self.segments.pop_first().unwrap().1;
+            let skip = (self.end - at) as usize;
+            if skip < seg.len() {
+                self.ready.extend_from_slice(&seg[skip..]);
+                self.end += (seg.len() - skip) as u64;
+            }
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.fin == Some(self.end) && self.ready.is_empty()
+    }
+}
+
+// ─── streams ──────────────────────────────────────────────────────────────────
+
+struct Stream {
+    id:       u64,
+    /// None on a stream only the server sends on.
+    tx:       Option<SendBuf>,
+    /// None on a stream only we send on.
+    rx:       Option<RecvBuf>,
+    /// The server's limit on what we send.
+    tx_limit: u64,
+    /// Our limit on what the server sends.
+    rx_limit: u64,
+    /// Offset the app has read to.
+    read:     u64,
+    /// A raised `rx_limit` is to be sent.
+    update:   bool,
+    /// The server reset its side or asked us to stop sending ours.
+    reset:    bool,
+    stopped:  bool,
+    /// The app has seen the end of the server's data.
+    read_fin: bool,
+}
+
+/// Streams we open have the client bit clear.
+fn is_local(id: u64) -> bool {
+    id & 1 == 0
+}
+
+fn is_bidi(id: u64) -> bool {
+    id & 2 == 0
+}
+
+impl Stream {
+    fn new(id: u64, tx_limit: u64) -> Stream {
+        let sends = is_bidi(id) || is_local(id);
+        let hears = is_bidi(id) || !is_local(id);
+        Stream {
+            id, tx: sends.then(SendBuf::default), rx: hears.then(RecvBuf::default), tx_limit, rx_limit: STREAM_WINDOW,
+            read: 0, update: false, reset: false, stopped: false, read_fin: false,
+        }
+    }
+
+    fn done(&self) -> bool {
+        let tx_done = self.tx.as_ref().is_none_or(|t| t.done() || self.stopped);
+        let rx_done = self.rx.is_none() || self.read_fin || self.reset;
+        tx_done && rx_done
+    }
+}
+
+// ─── connections ──────────────────────────────────────────────────────────────
+
+const INITIAL:   usize = 0;
+const HANDSHAKE: usize = 1;
+const DATA:      usize = 2;
+
+/// A frame kept with the packet that carried it, to be acted on when the
+/// packet is acknowledged or lost.
+#[derive(Clone)]
+enum Frame {
+    Crypto { offset: u64, len: u64 },
+    Stream { id: u64, offset: u64, len: u64, fin: bool },
+    MaxData,
+    MaxStreamData(u64),
+    MaxStreams(bool),
+    NewConnectionId(u64),
+    RetireConnectionId(u64),
+    PathChallenge,
+}
+
+struct Sent {
+    time:   u64,
+    size:   usize,
+    frames: Vec<Frame>,
+}
+
+/// A packet number space with its keys.
+#[derive(Default)]
+struct Space {
+    tx:             Option<Keys>,
+    rx:             Option<Keys>,
+    next_pn:        u64,
+    /// Ack-eliciting packets sent and neither acknowledged nor lost.
+    sent:           BTreeMap<u64, Sent>,
+    largest_acked:  Option<u64>,
+    last_sent:      Option<u64>,
+    loss_time:      Option<u64>,
+    received:       Ranges,
+    /// When the largest packet number received arrived.
+    largest_rx_at:  u64,
+    ack_pending:    bool,
+    crypto_tx:      SendBuf,
+    crypto_rx:      RecvBuf,
+    /// Packets to send whether the window allows them or not.
+    probes:         u8,
+}
+
+/// The server's transport parameters.
+struct Params {
+    idle_timeout:       u64,
+    max_data:           u64,
+    stream_bidi_local:  u64,
+    stream_bidi_remote: u64,
+    stream_uni:         u64,
+    max_streams_bidi:   u64,
+    max_streams_uni:    u64,
+    ack_delay_exponent: u64,
+    max_ack_delay:      u64,
+    disable_migration:  bool,
+    cid_limit:          u64,
+}
+
+impl Default for Params {
+    fn default() -> Params {
+        Params {
+            idle_timeout: 0, max_data: 0, stream_bidi_local: 0, stream_bidi_remote: 0, stream_uni: 0,
+            max_streams_bidi: 0, max_streams_uni: 0, ack_delay_exponent: 3, max_ack_delay: MAX_ACK_DELAY_MS,
+            disable_migration: false, cid_limit: 2,
+        }
+    }
+}
+
+/// A connection ID the server gave us to reach it by.
+struct RemoteCid {
+    seq:   u64,
+    cid:   Vec<u8>,
+    token: Option<[u8; RESET_TOKEN]>,
+}
+
+/// The socket we reach the server through, and the address it left from
+/// when opened.
+struct Path {
+    sock:      Option<u32>,
+    local:     IpAddr,
+    /// Data of a PATH_CHALLENGE awaiting its response, and when sent.
+    challenge: Option<([u8; 8], u64)>,
+}
+
+struct Conn {
+    id:             u32,
+    owner:          ProcessId,
+    remote:         SocketAddr,
+    state:          QuicState,
+    error:          Option<&'static str>,
+    tls:            Handshake,
+    alpn:           Option<String>,
+    spaces:         [Space; 3],
+    /// Our connection IDs by sequence number.
+    local_cids:     Vec<(u64, [u8; CID_LEN])>,
+    next_cid_seq:   u64,
+    /// The server's connection IDs; the first is in use.
+    remote_cids:    Vec<RemoteCid>,
+    /// The DCID of our first Initial, and the server's SCID once known.
+    original_dcid:  Vec<u8>,
+    server_scid:    Option<Vec<u8>>,
+    retry_scid:     Option<Vec<u8>>,
+    token:          Vec<u8>,
+    path:           Path,
+    migrations:     u32,
+    peer:           Params,
+    streams:        BTreeMap<u64, Stream>,
+    /// Streams the server opened and the app has yet to take.
+    accept:         VecDeque<u64>,
+    /// Streams we have opened of each kind, and ones the server has.
+    opened_bidi:    u64,
+    opened_uni:     u64,
+    peer_bidi:      u64,
+    peer_uni:       u64,
+    /// Our limits on the server's streams.
+    max_peer_bidi:  u64,
+    max_peer_uni:   u64,
+    /// Stream bytes first sent, against the server's limit.
+    tx_data:        u64,
+    rx_data:        u64,
+    rx_limit:       u64,
+    rx_read:        u64,
+    // Frames waiting to go out
+    send_max_data:  bool,
+    send_max_bidi:  bool,
+    send_max_uni:   bool,
+    new_cids:       Vec<u64>,
+    retire_cids:    Vec<u64>,
+    path_responses: Vec<[u8; 8]>,
+    send_challenge: bool,
+    // Recovery
+    latest_rtt:     u64,
+    smoothed_rtt:   Option<u64>,
+    rtt_var:        u64,
+    min_rtt:        u64,
+    pto_count:      u32,
+    cwnd:           usize,
+    ssthresh:       usize,
+    in_flight:      usize,
+    recovery_start: Option<u64>,
+    last_rx:        u64,
+    key_phase:      bool,
+    prev_rx:        Option<Keys>,
+    confirmed:      bool,
+}
+
+/// Why the connection ended: a transport error code and our reason.
+struct Close(u64, &'static str);
+
+impl From<&'static str> for Close {
+    fn from(e: &'static str) -> Close {
+        Close(PROTOCOL_VIOLATION, e)
+    }
+}
+
+/// Our transport parameters, naming `scid` as the connection ID we
+/// started with.
+fn transport_params(scid: &[u8; CID_LEN]) -> Vec<u8> {
+    let mut p = Vec::new();
+    let mut int = |id: u64, v: u64| {
+        put_varint(&mut p, id);
+        put_varint(&mut p, varint_len(v) as u64);
+        put_varint(&mut p, v);
+    };
+    int(0x01, IDLE_TIMEOUT_MS);
+    int(0x03, MAX_RECV as u64);
+    int(0x04, CONN_WINDOW);
+    int(0x05, STREAM_WINDOW);
+    int(0x06, STREAM_WINDOW);
+    int(0x07, STREAM_WINDOW);
+    int(0x08, MAX_PEER_STREAMS);
+    int(0x09, MAX_PEER_STREAMS);
+    int(0x0E, ACTIVE_CID_LIMIT);
+    put_varint(&mut p, 0x0F);
+    put_varint(&mut p, CID_LEN as u64);
+    p.extend_from_slice(scid);
+    p
+}
+
+fn now() -> u64 {
+    crate::arch::uptime_millis()
+}
+
+impl Conn {
+    fn dcid(&self) -> &[u8] {
+        &self.remote_cids[0].cid
+    }
+
+    fn stream_limit(&self, id: u64) -> u64 {
+        match (is_local(id), is_bidi(id)) {
+            (true, true)   => self.peer.stream_bidi_remote,
+            (false, true)  => self.peer.stream_bidi_local,
+            (true, false)  => self.peer.stream_uni,
+            (false, false) => 0,
+        }
+    }
+
+    /// Check and take the server's transport parameters.
+    fn take_params(&mut self, data: &[u8]) -> Result<(), Close> {
+        let bad = Close(TRANSPORT_PARAM_ERROR, "bad transport parameters");
+        let mut r = Reader(data);
+        let (mut odcid, mut iscid, mut rscid) = (None, None, None);
+        while !r.0.is_empty() {
+            let id = r.varint()?;
+            let len = r.varint()? as usize;
+            let value = r.take(len)?;
+            let int = || Reader(value).varint();
+            match id {
+                0x00 => odcid = Some(value.to_vec()),
+                0x01 => self.peer.idle_timeout = int()?,
+                0x02 => self.remote_cids[0].token = Some(value.try_into().map_err(|_| Close(TRANSPORT_PARAM_ERROR, "bad reset token"))?),
+                0x03 if int()? < 1200 => return Err(bad),
+                0x04 => self.peer.max_data = int()?,
+                0x05 => self.peer.stream_bidi_local = int()?,
+                0x06 => self.peer.stream_bidi_remote = int()?,
+                0x07 => self.peer.stream_uni = int()?,
+                0x08 => self.peer.max_streams_bidi = int()?,
+                0x09 => self.peer.max_streams_uni = int()?,
+                0x0A => self.peer.ack_delay_exponent = int()?.min(20),
+                0x0B => self.peer.max_ack_delay = int()?,
+                0x0C => self.peer.disable_migration = true,
+                0x0E => self.peer.cid_limit = int()?,
+                0x0F => iscid = Some(value.to_vec()),
+                0x10 => rscid = Some(value.to_vec()),
+                _ => {}
+            }
+        }
+        // The connection IDs of the exchange must be the ones we saw
+        if odcid.as_ref() != Some(&self.original_dcid) || iscid != self.server_scid || rscid != self.retry_scid {
+            return Err(Close(TRANSPORT_PARAM_ERROR, "transport parameters do not match the handshake"));
+        }
+        Ok(())
+    }
+
+    // ─── receiving ───────────────────────────────────────────────────────────
+
+    /// Handle a datagram from the server.
+    fn input(&mut self, mut dgram: &[u8], now: u64) -> Result<(), Close> {
+        while !dgram.is_empty() {
+            let first = dgram[0];
+            if first & 0x80 == 0 {
+                self.input_short(dgram, now)?;
+                return Ok(());
+            }
+            let mut r = Reader(dgram);
+            r.u8()?;
+            let version = u32::from_be_bytes(r.take(4)?.try_into().unwrap());
+            let dcid_len = r.u8()? as usize;
+            let dcid = r.take(dcid_len)?;
+            let scid_len = r.u8()? as usize;
+            let scid = r.take(scid_len)?.to_vec();
+            if version == 0 {
+                if self.state == QuicState::Handshaking && self.server_scid.is_none() {
+                    return Err(Close(NO_ERROR, "server does not support QUIC version 1"));
+                }
+                return Ok(());
+            }
+            if version != VERSION || !self.local_cids.iter().any(|c| c.1 == dcid) { return Ok(()); }
+            let space = match (first >> 4) & 3 {
+                0 => INITIAL,
+                2 => HANDSHAKE,
+                3 => return self.retry(dgram, scid),
+                _ => return Ok(()),
+            };
+            if space == INITIAL {
+                let len = r.varint()? as usize;
+                r.take(len)?;
+            }
+            let len = r.varint()? as usize;
+            let pn_at = dgram.len() - r.0.len();
+            if len > r.0.len() { return Ok(()); }
+            let (packet, rest) = dgram.split_at(pn_at + len);
+            dgram = rest;
+            if let Some(payload) = self.unprotect(space, packet, pn_at)? {
+                if space == INITIAL && self.server_scid.is_none() {
+                    // The server's chosen connection ID replaces our guess
+                    self.remote_cids[0].cid = scid.clone();
+                    self.server_scid = Some(scid);
+                }
+                self.input_payload(space, &payload, now)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn input_short(&mut self, packet: &[u8], now: u64) -> Result<(), Close> {
+        if packet.len() < 1 + CID_LEN || !self.local_cids.iter().any(|c| c.1 == packet[1..1 + CID_LEN]) { return self.check_reset(packet); }
+        match self.unprotect(DATA, packet, 1 + CID_LEN)? {
+            Some(payload) => self.input_payload(DATA, &payload, now),
+            None          => self.check_reset(packet),
+        }
+    }
+
+    /// A packet we cannot read may be a stateless reset.
+    fn check_reset(&mut self, packet: &[u8]) -> Result<(), Close> {
+        if packet.len() < 21 { return Ok(()); }
+        let tail = &packet[packet.len() - RESET_TOKEN..];
+        if self.remote_cids.iter().any(|c| c.token.is_some_and(|t| t == tail)) {
+            return Err(Close(NO_ERROR, "connection reset by server"));
+        }
+        Ok(())
+    }
+
+    /// Remove header and packet protection; None if the packet does not
+    /// decrypt or repeats one already seen.
+    fn unprotect(&mut self, space: usize, packet: &[u8], pn_at: usize) -> Result<Option<Vec<u8>>, Close> {
+        let Some(keys) = self.spaces[space].rx.clone() else { return Ok(None) };
+        if packet.len() < pn_at + 4 + 16 { return Ok(None); }
+        let mut p = packet.to_vec();
+        let mask = keys.mask(&p[pn_at + 4..pn_at + 20]);
+        p[0] ^= mask[0] & if p[0] & 0x80 != 0 { 0x0F } else { 0x1F };
+        let pn_len = (p[0] & 3) as usize + 1;
+        let mut truncated = 0u64;
+        for i in 0..pn_len {
+            p[pn_at + i] ^= mask[1 + i];
+            truncated = truncated << 8 | p[pn_at + i] as u64;
+        }
+        let pn = decode_pn(self.spaces[space].received.largest(), truncated, 8 * pn_len as u32);
+        let (header, sealed) = p.split_at(pn_at + pn_len);
+        let phase = header[0] & 0x04 != 0;
+        let payload = if space == DATA && phase != self.key_phase {
+            // A key update, or a late packet from before ours
+            let next = keys.next();
+            match next.open(pn, header, sealed) {
+                Ok(payload) => {
+                    self.prev_rx = self.spaces[DATA].rx.replace(next);
+                    self.spaces[DATA].tx = self.spaces[DATA].tx.as_ref().map(Keys::next);
+                    self.key_phase = phase;
+                    payload
+                }
+                Err(_) => match self.prev_rx.as_ref().map(|k| k.open(pn, header, sealed)) {
+                    Some(Ok(payload)) => payload,
+                    _ => return Ok(None),
+                },
+            }
+        } else {
+            match keys.open(pn, header, sealed) {
+                Ok(payload) => payload,
+                Err(_)      => return Ok(None),
+            }
+        };
+        // Reserved bits must be clear once protection is off
+        if header[0] & if header[0] & 0x80 != 0 { 0x0C } else { 0x18 } != 0 {
+            return Err(Close(PROTOCOL_VIOLATION, "reserved header bits set"));
+        }
+        let s = &mut self.spaces[space];
+        if s.received.contains(pn) { return Ok(None); }
+        if s.received.largest().is_none_or(|l| pn > l) { s.largest_rx_at = now(); }
+        s.received.insert(pn, pn);
+        // A bounded history is enough to acknowledge from
+        if s.received.0.len() > 32 { s.received.0.remove(0); }
+        Ok(Some(payload))
+    }
+
+    fn input_payload(&mut self, space: usize, payload: &[u8], now: u64) -> Result<(), Close> {
+        if payload.is_empty() { return Err(Close(PROTOCOL_VIOLATION, "packet without frames")); }
+        self.last_rx = now;
+        let mut r = Reader(payload);
+        let mut eliciting = false;
+        while !r.0.is_empty() {
+            let ty = r.varint()?;
+            if space != DATA && !matches!(ty, 0x00..=0x03 | 0x06 | 0x1C) {
+                return Err(Close(PROTOCOL_VIOLATION, "frame not allowed before the handshake completes"));
+            }
+            if !matches!(ty, 0x00 | 0x02 | 0x03 | 0x1C | 0x1D) { eliciting = true; }
+            self.frame(space, ty, &mut r, now)?;
+        }
+        if eliciting { self.spaces[space].ack_pending = true; }
+        Ok(())
+    }
+
+    fn frame(&mut self, space: usize, ty: u64, r: &mut Reader, now: u64) -> Result<(), Close> {
+        let encoding = |_| Close(FRAME_ENCODING_ERROR, "malformed frame");
+        match ty {
+            0x00 | 0x01 => {}
+            0x02 | 0x03 => {
+                let largest = r.varint()?;
+                let delay = r.varint()?;
+                let count = r.varint()?;
+                let first = r.varint()?;
+                if first > largest { return Err(Close(FRAME_ENCODING_ERROR, "bad ACK range")); }
+                let mut ranges = vec![(largest - first, largest)];
+                let mut smallest = largest - first;
+                for _ in 0..count {
+                    let gap = r.varint()?;
+                    let len = r.varint()?;
+                    let hi = smallest.checked_sub(gap + 2).ok_or(Close(FRAME_ENCODING_ERROR, "bad ACK range"))?;
+                    let lo = hi.checked_sub(len).ok_or(Close(FRAME_ENCODING_ERROR, "bad ACK range"))?;
+                    ranges.push((lo, hi));
+                    smallest = lo;
+                }
+                if ty == 0x03 { for _ in 0..3 { r.varint()?; } }
+                self.on_ack(space, &ranges, delay, now)?;
+            }
+            0x04 => {
+                let id = r.varint()?;
+                r.varint()?;
+                let size = r.varint()?;
+                if let Some(s) = self.recv_stream(id)? {
+                    let rx = s.rx.as_mut().ok_or(Close(STREAM_STATE_ERROR, "reset of a send-only stream"))?;
+                    if rx.fin.is_some_and(|f| f != size) || rx.highest > size { return Err(Close(FINAL_SIZE_ERROR, "final size changed")); }
+                    s.reset = true;
+                }
+            }
+            0x05 => {
+                let id = r.varint()?;
+                r.varint()?;
+                if let Some(s) = self.recv_stream(id)? {
+                    if s.tx.is_none() { return Err(Close(STREAM_STATE_ERROR, "stop of a receive-only stream")); }
+                    s.stopped = true;
+                }
+            }
+            0x06 => {
+                let offset = r.varint()?;
+                let len = r.varint()? as usize;
+                let data = r.take(len).map_err(encoding)?;
+                self.spaces[space].crypto_rx.insert(offset, data);
+                self.crypto(space)?;
+            }
+            0x07 => { let len = r.varint()? as usize; r.take(len).map_err(encoding)?; }
+            0x08..=0x0F => {
+                let id = r.varint()?;
+                let offset = if ty & 0x04 != 0 { r.varint()? } else { 0 };
+                let len = if ty & 0x02 != 0 { r.varint()? as usize } else { r.0.len() };
+                let data = r.take(len).map_err(encoding)?;
+                self.on_stream(id, offset, data, ty & 0x01 != 0)?;
+            }
+            0x10 => self.peer.max_data = self.peer.max_data.max(r.varint()?),
+            0x11 => {
+                let id = r.varint()?;
+                let max = r.varint()?;
+                if let Some(s) = self.recv_stream(id)? {
+                    if s.tx.is_none() { return Err(Close(STREAM_STATE_ERROR, "flow control for a receive-only stream")); }
+                    s.tx_limit = s.tx_limit.max(max);
+                }
+            }
+            0x12 => self.peer.max_streams_bidi = self.peer.max_streams_bidi.max(r.varint()?),
+            0x13 => self.peer.max_streams_uni = self.peer.max_streams_uni.max(r.varint()?),
+            0x14 | 0x16 | 0x17 => { r.varint()?; }
+            0x15 => { r.varint()?; r.varint()?; }
+            0x18 => {
+                let seq = r.varint()?;
+                let retire = r.varint()?;
+                let len = r.u8()? as usize;
+                if !(1..=20).contains(&len) || retire > seq { return Err(Close(FRAME_ENCODING_ERROR, "bad NEW_CONNECTION_ID")); }
+                let cid = r.take(len).map_err(encoding)?.to_vec();
+                let token: [u8; RESET_TOKEN] = r.take(RESET_TOKEN).map_err(encoding)?.try_into().unwrap();
+                if !self.remote_cids.iter().any(|c| c.seq == seq) {
+                    self.remote_cids.push(RemoteCid { seq, cid, token: Some(token) });
+                }
+                self.retire_below(retire);
+                if self.remote_cids.len() as u64 > ACTIVE_CID_LIMIT { return Err(Close(CONNECTION_ID_LIMIT_ERROR, "too many connection IDs")); }
+            }
+            0x19 => {
+                let seq = r.varint()?;
+                if seq >= self.next_cid_seq { return Err(Close(PROTOCOL_VIOLATION, "retired an unissued connection ID")); }
+                let before = self.local_cids.len();
+                self.local_cids.retain(|c| c.0 != seq);
+                if self.local_cids.len() < before { self.issue_cid(); }
+            }
+            0x1A => {
+                let data: [u8; 8] = r.take(8).map_err(encoding)?.try_into().unwrap();
+                self.path_responses.push(data);
+            }
+            0x1B => {
+                let data = r.take(8).map_err(encoding)?;
+                if self.path.challenge.is_some_and(|c| c.0 == data) { self.path.challenge = None; }
+            }
+            0x1C | 0x1D => {
+                let code = r.varint()?;
+                if ty == 0x1C { r.varint()?; }
+                let len = r.varint()? as usize;
+                r.take(len).map_err(encoding)?;
+                let why = match (ty, code) {
+                    (0x1D, _) | (_, NO_ERROR) => "connection closed by server",
+                    (_, c) if c >= 0x100      => "handshake failed at server",
+                    _                         => "connection failed at server",
+                };
+                self.state = QuicState::Closed;
+                self.error = Some(why);
+            }
+            0x1E => {
+                self.confirmed = true;
+                self.drop_space(HANDSHAKE);
+            }
+            _ => return Err(Close(FRAME_ENCODING_ERROR, "unknown frame type")),
+        }
+        Ok(())
+    }
+
+    /// Stop using the server's connection IDs numbered below `seq`.
+    fn retire_below(&mut self, seq: u64) {
+        let old: Vec<u64> = self.remote_cids.iter().filter(|c| c.seq < seq).map(|c| c.seq).collect();
+        if old.is_empty() { return; }
+        // The frame that asked for this brought a newer one, which moves
+        // to the front if ours went
+        self.remote_cids.retain(|c| c.seq >= seq);
+        self.retire_cids.extend(old);
+    }
+
+    fn issue_cid(&mut self) {
+        let limit = self.peer.cid_limit.min(ACTIVE_CID_LIMIT) as usize;
+        while self.local_cids.len() < limit {
+            let seq = self.next_cid_seq;
+            self.next_cid_seq += 1;
+            self.local_cids.push((seq, random()));
+            self.new_cids.push(seq);
+        }
+    }
+
+    /// The stream a frame names, opening it if the server may have; None
+    /// if it is already gone.
+    fn recv_stream(&mut self, id: u64) -> Result<Option<&mut Stream>, Close> {
+        if !self.streams.contains_key(&id) {
+            if is_local(id) {
+                let opened = if is_bidi(id) { self.opened_bidi } else { self.opened_uni };
+                if id / 4 >= opened { return Err(Close(STREAM_STATE_ERROR, "frame for a stream we have not opened")); }
+                return Ok(None);
+            }
+            let (count, max) = if is_bidi(id) { (&mut self.peer_bidi, self.max_peer_bidi) } else { (&mut self.peer_uni, self.max_peer_uni) };
+            if id / 4 >= max { return Err(Close(STREAM_LIMIT_ERROR, "server opened too many streams")); }
+            if id / 4 < *count { return Ok(None); }
+            // Opening one opens all of its kind numbered below it
+            let first = *count;
+            *count = id / 4 + 1;
+            for n in first..=id / 4 {
+                let sid = n * 4 + (id & 3);
+                let limit = self.stream_limit(sid);
+                self.streams.insert(sid, Stream::new(sid, limit));
+                self.accept.push_back(sid);
+            }
+        }
+        Ok(self.streams.get_mut(&id))
+    }
+
+    fn on_stream(&mut self, id: u64, offset: u64, data: &[u8], fin: bool) -> Result<(), Close> {
+        let top = offset + data.len() as u64;
+        let Some(s) = self.recv_stream(id)? else { return Ok(()) };
+        let limit = s.rx_limit;
+        let Some(rx) = s.rx.as_mut() else { return Err(Close(STREAM_STATE_ERROR, "data on a send-only stream")) };
+        if s.reset { return Ok(()); }
+        if top > limit { return Err(Close(FLOW_CONTROL_ERROR, "stream flow control exceeded")); }
+        if rx.fin.is_some_and(|f| top > f || (fin && f != top)) || (fin && rx.highest > top) {
+            return Err(Close(FINAL_SIZE_ERROR, "final size changed"));
+        }
+        if fin { rx.fin = Some(top); }
+        let before = rx.highest;
+        rx.insert(offset, data);
+        let grew = rx.highest - before;
+        self.rx_data += grew;
+        if self.rx_data > self.rx_limit { return Err(Close(FLOW_CONTROL_ERROR, "connection flow control exceeded")); }
+        Ok(())
+    }
+
+    /// Feed whole handshake messages at level `space` to TLS.
+    fn crypto(&mut self, space: usize) -> Result<(), Close> {
+        let tls_err = |e| Close(CRYPTO_ERROR, e);
+        while let Some(m) = tls::next_message(&mut self.spaces[space].crypto_rx.ready).map_err(tls_err)? {
+            if space == DATA {
+                // Session tickets are not used; nothing else belongs here
+                if m[0] != 4 { return Err(Close(PROTOCOL_VIOLATION, "unexpected handshake message")); }
+                continue;
+            }
+            if (space == INITIAL) != (self.state == QuicState::Handshaking && self.spaces[HANDSHAKE].rx.is_none()) {
+                return Err(Close(PROTOCOL_VIOLATION, "handshake message at the wrong level"));
+            }
+            match self.tls.input(&m).map_err(tls_err)? {
+                Step::Continue => {}
+                Step::HandshakeKeys(client, server) => {
+                    self.spaces[HANDSHAKE].tx = Some(Keys::new(Suite::ChaCha20Poly1305, client));
+                    self.spaces[HANDSHAKE].rx = Some(Keys::new(Suite::ChaCha20Poly1305, server));
+                }
+                Step::Done { finished, client, server } => {
+                    let params = self.tls.extension(EXT_TRANSPORT_PARAMS)
+                        .ok_or(Close(TRANSPORT_PARAM_ERROR, "server sent no transport parameters"))?.to_vec();
+                    self.take_params(&params)?;
+                    // RFC 9001 8.1: QUIC is only spoken with an agreed protocol
+                    self.alpn = Some(self.tls.alpn().ok_or(Close(NO_APPLICATION_PROTOCOL, "server chose no application protocol"))?.into());
+                    self.spaces[HANDSHAKE].crypto_tx.write(&finished);
+                    self.spaces[DATA].tx = Some(Keys::new(Suite::ChaCha20Poly1305, client));
+                    self.spaces[DATA].rx = Some(Keys::new(Suite::ChaCha20Poly1305, server));
+                    self.state = QuicState::Established;
+                    self.issue_cid();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A Retry: the server wants our Initial again with its token.
+    fn retry(&mut self, packet: &[u8], scid: Vec<u8>) -> Result<(), Close> {
+        if self.retry_scid.is_some() || self.server_scid.is_some() || packet.len() < 7 + CID_LEN + scid.len() + 16 {
+            return Ok(());
+        }
+        let (body, tag) = packet.split_at(packet.len() - 16);
+        let mut pseudo = vec![self.original_dcid.len() as u8];
+        pseudo.extend_from_slice(&self.original_dcid);
+        pseudo.extend_from_slice(body);
+        if aes::seal(&RETRY_KEY, &RETRY_NONCE, &pseudo, &[]) != tag { return Ok(()); }
+        self.token = body[7 + CID_LEN + scid.len()..].to_vec();
+        let (client, server) = Keys::initial(&scid);
+        self.remote_cids[0].cid = scid.clone();
+        self.retry_scid = Some(scid);
+        let s = &mut self.spaces[INITIAL];
+        s.tx = Some(client);
+        s.rx = Some(server);
+        // Everything sent so far goes again under the new keys
+        for (_, p) in core::mem::take(&mut s.sent) { self.in_flight -= p.size; }
+        let s = &mut self.spaces[INITIAL];
+        s.crypto_tx.next = s.crypto_tx.base;
+        s.crypto_tx.lost = Ranges::default();
+        Ok(())
+    }
+
+    // ─── loss recovery ───────────────────────────────────────────────────────
+
+    fn on_ack(&mut self, space: usize, ranges: &[(u64, u64)], delay: u64, now: u64) -> Result<(), Close> {
+        let largest = ranges[0].1;
+        if largest >= self.spaces[space].next_pn { return Err(Close(PROTOCOL_VIOLATION, "ACK of an unsent packet")); }
+        let s = &mut self.spaces[space];
+        let acked: Vec<u64> = s.sent.keys().copied().filter(|&pn| ranges.iter().any(|&(lo, hi)| lo <= pn && pn <= hi)).collect();
+        if acked.is_empty() { return Ok(()); }
+        s.largest_acked = Some(s.largest_acked.map_or(largest, |l| l.max(largest)));
+        let acked: Vec<(u64, Sent)> = acked.into_iter().map(|pn| (pn, s.sent.remove(&pn).unwrap())).collect();
+        let mut newest = None;
+        for (pn, p) in acked {
+            if pn == largest { newest = Some(p.time); }
+            self.in_flight -= p.size;
+            // NewReno: grow the window unless this was sent before the
+            // current recovery began
+            if self.recovery_start.is_none_or(|r| p.time > r) {
+                if self.cwnd < self.ssthresh { self.cwnd += p.size; } else { self.cwnd += MAX_DATAGRAM * p.size / self.cwnd; }
+            }
+            for f in p.frames { self.frame_acked(space, f); }
+        }
+        if let Some(sent_at) = newest {
+            let delay = if space == DATA { (delay << self.peer.ack_delay_exponent) / 1000 } else { 0 };
+            self.rtt_sample(now.saturating_sub(sent_at), delay.min(self.peer.max_ack_delay));
+        }
+        self.pto_count = 0;
+        self.detect_lost(space, now);
+        Ok(())
+    }
+
+    fn frame_acked(&mut self, space: usize, f: Frame) {
+        match f {
+            Frame::Crypto { offset, len } => self.spaces[space].crypto_tx.on_ack(offset, len, false),
+            Frame::Stream { id, offset, len, fin } => {
+                if let Some(tx) = self.streams.get_mut(&id).and_then(|s| s.tx.as_mut()) { tx.on_ack(offset, len, fin); }
+            }
+            _ => {}
+        }
+    }
+
+    fn frame_lost(&mut self, space: usize, f: Frame) {
+        match f {
+            Frame::Crypto { offset, len } => self.spaces[space].crypto_tx.on_lost(offset, len, false),
+            Frame::Stream { id, offset, len, fin } => {
+                if let Some(s) = self.streams.get_mut(&id) {
+                    if let Some(tx) = s.tx.as_mut().filter(|_| !s.stopped) { tx.on_lost(offset, len, fin); }
+                }
+            }
+            Frame::MaxData => self.send_max_data = true,
+            Frame::MaxStreamData(id) => {
+                if let Some(s) = self.streams.get_mut(&id) { s.update = s.rx.as_ref().is_some_and(|rx| rx.fin.is_none()); }
+            }
+            Frame::MaxStreams(bidi) => if bidi { self.send_max_bidi = true } else { self.send_max_uni = true },
+            Frame::NewConnectionId(seq) => if self.local_cids.iter().any(|c| c.0 == seq) { self.new_cids.push(seq) },
+            Frame::RetireConnectionId(seq) => self.retire_cids.push(seq),
+            Frame::PathChallenge => if self.path.challenge.is_some() { self.send_challenge = true },
+        }
+    }
+
+    fn rtt_sample(&mut self, latest: u64, ack_delay: u64) {
+        self.latest_rtt = latest;
+        match self.smoothed_rtt {
+            None => {
+                self.min_rtt = latest;
+                self.smoothed_rtt = Some(latest);
+                self.rtt_var = latest / 2;
+            }
+            Some(smoothed) => {
+                self.min_rtt = self.min_rtt.min(latest);
+                let adjusted = if latest >= self.min_rtt + ack_delay { latest - ack_delay } else { latest };
+                self.rtt_var = (3 * self.rtt_var + smoothed.abs_diff(adjusted)) / 4;
+                self.smoothed_rtt = Some((7 * smoothed + adjusted) / 8);
+            }
+        }
+    }
+
+    fn detect_lost(&mut self, space: usize, now: u64) {
+        let Some(largest) = self.spaces[space].largest_acked else { return };
+        let rtt = self.latest_rtt.max(self.smoothed_rtt.unwrap_or(INITIAL_RTT_MS));
+        let delay = (9 * rtt / 8).max(GRANULARITY_MS);
+        let s = &mut self.spaces[space];
+        s.loss_time = None;
+        let mut lost = Vec::new();
+        for (&pn, p) in s.sent.range(..largest) {
+            if p.time + delay <= now || pn + PACKET_THRESHOLD <= largest {
+                lost.push(pn);
+            } else {
+                s.loss_time = Some(s.loss_time.map_or(p.time + delay, |t| t.min(p.time + delay)));
+            }
+        }
+        let mut newest = None;
+        for pn in lost {
+            let p = self.spaces[space].sent.remove(&pn).unwrap();
+            self.in_flight -= p.size;
+            newest = Some(p.time);
+            for f in p.frames { self.frame_lost(space, f); }
+        }
+        // One reduction per round trip of losses
+        if let Some(sent_at) = newest {
+            if self.recovery_start.is_none_or(|r| sent_at > r) {
+                self.recovery_start = Some(now);
+                self.cwnd = (self.cwnd / 2).max(MIN_WINDOW);
+                self.ssthresh = self.cwnd;
+            }
+        }
+    }
+
+    fn pto(&self, space: usize) -> u64 {
+        let smoothed = self.smoothed_rtt.unwrap_or(INITIAL_RTT_MS);
+        let var = if self.smoothed_rtt.is_some() { self.rtt_var } else { INITIAL_RTT_MS / 2 };
+        let ack_delay = if space == DATA { self.peer.max_ack_delay } else { 0 };
+        (smoothed + (4 * var).max(GRANULARITY_MS) + ack_delay) << self.pto_count.min(10)
+    }
+
+    /// When the loss detection timer fires next, and for which space.
+    fn timer(&self) -> Option<(u64, usize, bool)> {
+        let loss = (0..3).filter_map(|i| self.spaces[i].loss_time.map(|t| (t, i, true))).min();
+        if loss.is_some() { return loss; }
+        let mut pto = (0..3)
+            .filter(|&i| !self.spaces[i].sent.is_empty() && (i != DATA || self.confirmed))
+            .filter_map(|i| self.spaces[i].last_sent.map(|t| (t + self.pto(i), i, false)))
+            .min();
+        // Until the server has the handshake, keep probing so that a lost
+        // reply cannot stall both sides
+        if pto.is_none() && !self.confirmed {
+            let space = if self.spaces[HANDSHAKE].tx.is_some() { HANDSHAKE } else { INITIAL };
+            let last = self.spaces.iter().filter_map(|s| s.last_sent).max().unwrap_or(0);
+            pto = Some((last + self.pto(space), space, false));
+        }
+        pto
+    }
+
+    fn on_timer(&mut self, now: u64) {
+        let Some((at, space, loss)) = self.timer() else { return };
+        if at > now { return; }
+        if loss {
+            self.detect_lost(space, now);
+            return;
+        }
+        self.pto_count += 1;
+        // Two probes, with the oldest data outstanding or PINGs, so that one
+        // lost does not cost another timeout
+        let s = &mut self.spaces[space];
+        s.probes = 2;
+        if let Some((_, p)) = s.sent.iter().next() {
+            for f in p.frames.clone() { self.frame_lost(space, f); }
+        }
+    }
+
+    // ─── sending ─────────────────────────────────────────────────────────────
+
+    /// Send what is waiting, as far as the congestion window allows.
+    fn flush(&mut self, now: u64) {
+        for space in [INITIAL, HANDSHAKE, DATA] {
+            while let Some(packet) = self.build(space, now) {
+                let Some(sock) = self.path.sock else { return };
+                if backend::get().udp_send_to(sock, &packet, self.remote).is_err() { return; }
+                if space == HANDSHAKE && self.spaces[INITIAL].tx.is_some() {
+                    // The server has our Initials once we can send at the next level
+                    self.drop_space(INITIAL);
+                }
+            }
+        }
+    }
+
+    fn drop_space(&mut self, space: usize) {
+        let s = &mut self.spaces[space];
+        for (_, p) in core::mem::take(&mut s.sent) { self.in_flight -= p.size; }
+        self.spaces[space] = Space { next_pn: self.spaces[space].next_pn, ..Space::default() };
+        self.pto_count = 0;
+    }
+
+    fn header(&self, space: usize, pn: u64) -> Vec<u8> {
+        let mut h = Vec::with_capacity(64);
+        match space {
+            DATA => {
+                h.push(0x40 | (self.key_phase as u8) << 2 | (PN_LEN as u8 - 1));
+                h.extend_from_slice(self.dcid());
+            }
+            _ => {
+                let ty = if space == INITIAL { 0 } else { 2 };
+                h.push(0xC0 | ty << 4 | (PN_LEN as u8 - 1));
+                h.extend_from_slice(&VERSION.to_be_bytes());
+                h.push(self.dcid().len() as u8);
+                h.extend_from_slice(self.dcid());
+                h.push(CID_LEN as u8);
+                h.extend_from_slice(&self.local_cids[0].1);
+                if space == INITIAL {
+                    put_varint(&mut h, self.token.len() as u64);
+                    h.extend_from_slice(&self.token);
+                }
+                // Length, filled in once the payload is known
+                h.extend_from_slice(&[0x40, 0]);
+            }
+        }
+        h.extend_from_slice(&(pn as u32).to_be_bytes());
+        h
+    }
+
+    fn ack_frame(&self, space: usize, now: u64, out: &mut Vec<u8>) {
+        let s = &self.spaces[space];
+        let Some(&(lo, hi)) = s.received.0.last() else { return };
+        out.push(0x02);
+        put_varint(out, hi);
+        put_varint(out, if space == DATA { (now.saturating_sub(s.largest_rx_at) * 1000) >> 3 } else { 0 });
+        put_varint(out, s.received.0.len() as u64 - 1);
+        put_varint(out, hi - lo);
+        let mut smallest = lo;
+        for &(lo, hi) in s.received.0.iter().rev().skip(1) {
+            put_varint(out, smallest - hi - 2);
+            put_varint(out, hi - lo);
+            smallest = lo;
+        }
+    }
+
+    /// Build the next packet at level `space`, if anything is waiting and
+    /// may go.
+    fn build(&mut self, space: usize, now: u64) -> Option<Vec<u8>> {
+        let keys = self.spaces[space].tx.clone()?;
+        let pn = self.spaces[space].next_pn;
+        let mut header = self.header(space, pn);
+        let room = MAX_DATAGRAM - header.len() - aead::TAG_LEN;
+        let mut payload = Vec::with_capacity(room);
+        let mut frames = Vec::new();
+        // Full-size datagrams on a path being validated let the server
+        // answer there before it can count us as reachable
+        let mut pad = space == INITIAL || (space == DATA && self.path.challenge.is_some());
+
+        if self.spaces[space].ack_pending { self.ack_frame(space, now, &mut payload); }
+        let can_send = self.spaces[space].probes > 0 || self.in_flight + MAX_DATAGRAM <= self.cwnd;
+        let acks = payload.len();
+        if can_send {
+            if space == DATA { self.control_frames(&mut payload, &mut frames, &mut pad, room); }
+            let s = &mut self.spaces[space];
+            while payload.len() + 20 < room {
+                let max = room - payload.len() - 17;
+                let Some((offset, data, _)) = s.crypto_tx.next_chunk(max, u64::MAX) else { break };
+                payload.push(0x06);
+                put_varint(&mut payload, offset);
+                put_varint(&mut payload, data.len() as u64);
+                payload.extend_from_slice(&data);
+                frames.push(Frame::Crypto { offset, len: data.len() as u64 });
+            }
+            if space == DATA && self.state == QuicState::Established { self.stream_frames(&mut payload, &mut frames, room); }
+            if payload.len() == acks && self.spaces[space].probes > 0 { payload.push(0x01); }
+        }
+        if payload.is_empty() { return None; }
+        let eliciting = payload.len() > acks;
+        if eliciting { self.spaces[space].probes = self.spaces[space].probes.saturating_sub(1); }
+        self.spaces[space].ack_pending = false;
+        self.spaces[space].next_pn += 1;
+
+        if pad { payload.resize(room, 0); }
+        if space != DATA {
+            let at = header.len() - PN_LEN - 2;
+            let len = (PN_LEN + payload.len() + aead::TAG_LEN) as u16;
+            header[at..at + 2].copy_from_slice(&(len | 0x4000).to_be_bytes());
+        }
+        let pn_at = header.len() - PN_LEN;
+        let mut packet = header.clone();
+        packet.extend_from_slice(&keys.seal(pn, &header, &payload));
+        protect_header(&keys, &mut packet, pn_at);
+        if eliciting {
+            self.in_flight += packet.len();
+            let s = &mut self.spaces[space];
+            s.sent.insert(pn, Sent { time: now, size: packet.len(), frames });
+            s.last_sent = Some(now);
+        }
+        Some(packet)
+    }
+
+    fn control_frames(&mut self, out: &mut Vec<u8>, frames: &mut Vec<Frame>, pad: &mut bool, room: usize) {
+        for data in core::mem::take(&mut self.path_responses) {
+            out.push(0x1B);
+            out.extend_from_slice(&data);
+            *pad = true;
+        }
+        if self.send_challenge {
+            if let Some((data, _)) = self.path.challenge {
+                out.push(0x1A);
+                out.extend_from_slice(&data);
+                frames.push(Frame::PathChallenge);
+                *pad = true;
+            }
+            self.send_challenge = false;
+        }
+        if self.send_max_data {
+            self.send_max_data = false;
+            out.push(0x10);
+            put_varint(out, self.rx_limit);
+            frames.push(Frame::MaxData);
+        }
+        for (bidi, flag, max) in [(true, &mut self.send_max_bidi, self.max_peer_bidi), (false, &mut self.send_max_uni, self.max_peer_uni)] {
+            if !*flag { continue; }
+            *flag = false;
+            out.push(if bidi { 0x12 } else { 0x13 });
+            put_varint(out, max);
+            frames.push(Frame::MaxStreams(bidi));
+        }
+        for s in self.streams.values_mut().filter(|s| s.update) {
+            if out.len() + 20 > room { break; }
+            s.update = false;
+            out.push(0x11);
+            put_varint(out, s.id);
+            put_varint(out, s.rx_limit);
+            frames.push(Frame::MaxStreamData(s.id));
+        }
+        while out.len() + 40 < room {
+            let Some(seq) = self.new_cids.pop() else { break };
+            let Some(&(_, cid)) = self.local_cids.iter().find(|c| c.0 == seq) else { continue };
+            out.push(0x18);
+            put_varint(out, seq);
+            put_varint(out, 0);
+            out.push(CID_LEN as u8);
+            out.extend_from_slice(&cid);
+            out.extend_from_slice(&random::<RESET_TOKEN>());
+            frames.push(Frame::NewConnectionId(seq));
+        }
+        while out.len() + 10 < room {
+            let Some(seq) = self.retire_cids.pop() else { break };
+            out.push(0x19);
+            put_varint(out, seq);
+            frames.push(Frame::RetireConnectionId(seq));
+        }
+    }
+
+    /// Stream data, lost data first, in stream order.
+    fn stream_frames(&mut self, out: &mut Vec<u8>, frames: &mut Vec<Frame>, room: usize) {
+        let ids: Vec<u64> = self.streams.keys().copied().collect();
+        for id in ids {
+            loop {
+                // Type, stream ID, offset and length at their largest
+                let overhead = 1 + varint_len(id) + 8 + 2;
+                if out.len() + overhead + 1 > room { return; }
+                let conn_room = self.peer.max_data.saturating_sub(self.tx_data);
+                let s = self.streams.get_mut(&id).unwrap();
+                if s.stopped { break; }
+                let Some(tx) = s.tx.as_mut() else { break };
+                let limit = s.tx_limit.min(tx.next + conn_room);
+                let before = tx.next;
+                let Some((offset, data, fin)) = tx.next_chunk(room - out.len() - overhead, limit) else { break };
+                self.tx_data += tx.next - before;
+                out.push(0x08 | 0x04 | 0x02 | fin as u8);
+                put_varint(out, id);
+                put_varint(out, offset);
+                put_varint(out, data.len() as u64);
+                out.extend_from_slice(&data);
+                frames.push(Frame::Stream { id, offset, len: data.len() as u64, fin });
+            }
+        }
+    }
+
+    /// Whether anything is waiting that `flush` would send.
+    fn wants_send(&self) -> bool {
+        self.spaces.iter().any(|s| s.tx.is_some() && (s.ack_pending || s.probes > 0 || s.crypto_tx.has_data(u64::MAX)))
+    }
+
+    /// Tell the server we are done, if we got far enough to, and stop.
+    fn close_with(&mut self, c: Close) {
+        if self.state == QuicState::Closed { return; }
+        let space = if self.spaces[DATA].tx.is_some() { DATA } else if self.spaces[HANDSHAKE].tx.is_some() { HANDSHAKE } else { INITIAL };
+        if let (Some(keys), Some(sock)) = (self.spaces[space].tx.clone(), self.path.sock) {
+            let pn = self.spaces[space].next_pn;
+            self.spaces[space].next_pn += 1;
+            let mut header = self.header(space, pn);
+            let mut payload = vec![0x1C];
+            put_varint(&mut payload, c.0);
+            put_varint(&mut payload, 0);
+            put_varint(&mut payload, 0);
+            if space == INITIAL { payload.resize(MAX_DATAGRAM - header.len() - aead::TAG_LEN, 0); }
+            if space != DATA {
+                let at = header.len() - PN_LEN - 2;
+                let len = (PN_LEN + payload.len() + aead::TAG_LEN) as u16;
+                header[at..at + 2].copy_from_slice(&(len | 0x4000).to_be_bytes());
+            }
+            let mut packet = header.clone();
+            packet.extend_from_slice(&keys.seal(pn, &header, &payload));
+            protect_header(&keys, &mut packet, header.len() - PN_LEN);
+            let _ = backend::get().udp_send_to(sock, &packet, self.remote);
+        }
+        self.state = QuicState::Closed;
+        if c.0 != NO_ERROR || self.error.is_none() { self.error = Some(c.1); }
+    }
+
+    fn release(&mut self) {
+        if let Some(sock) = self.path.sock.take() { let _ = backend::get().udp_close(sock); }
+    }
+
+    // ─── migration ───────────────────────────────────────────────────────────
+
+    /// Move to a new path if the route to the server now leaves from
+    /// `local`.
+    fn check_path(&mut self, local: IpAddr, now: u64) {
+        if local == self.path.local || !self.confirmed || self.peer.disable_migration { return; }
+        let Some(next) = self.remote_cids.iter().position(|c| c.seq != self.remote_cids[0].seq) else { return };
+        let Ok(sock) = backend::get().udp_bind(SocketAddr::new(IpAddr::default(), 0)) else { return };
+        if let Some(old) = self.path.sock.replace(sock) { let _ = backend::get().udp_close(old); }
+        // The old path's connection ID would link the two
+        let old = self.remote_cids.remove(0);
+        let cid = self.remote_cids.remove(next - 1);
+        self.remote_cids.insert(0, cid);
+        self.retire_cids.push(old.seq);
+        self.path.local = local;
+        self.path.challenge = Some((random(), now));
+        self.send_challenge = true;
+        self.migrations += 1;
+        // A new path starts over on what it can carry
+        self.cwnd = INITIAL_WINDOW;
+        self.ssthresh = usize::MAX;
+        self.recovery_start = None;
+        self.smoothed_rtt = None;
+        self.pto_count = 0;
+    }
+
+    /// Run timers, follow the route and send; called from the stack's
+    /// poll as the owner.
+    fn poll(&mut self, now: u64) {
+        if self.state == QuicState::Closed { return; }
+        if !netns::online(self.owner) {
+            self.close_with(Close(NO_ERROR, "network disabled for app"));
+            return;
+        }
+        let idle = match self.peer.idle_timeout { 0 => IDLE_TIMEOUT_MS, t => t.min(IDLE_TIMEOUT_MS) };
+        if now.saturating_sub(self.last_rx) > idle.max(3 * self.pto(DATA)) {
+            self.state = QuicState::Closed;
+            self.error = Some("timed out");
+            return;
+        }
+        if let Ok(local) = netns::source(self.owner, self.remote.ip, IpAddr::default()) { self.check_path(local, now); }
+        // An unanswered challenge is asked again
+        if let Some((data, at)) = self.path.challenge {
+            if now.saturating_sub(at) > 3 * self.pto(DATA) {
+                self.path.challenge = Some((data, now));
+                self.send_challenge = true;
+            }
+        }
+        self.on_timer(now);
+        self.reap();
+        self.flush(now);
+    }
+
+    /// Forget streams both sides are done with.
+    fn reap(&mut self) {
+        self.streams.retain(|_, s| !s.done() || self.accept.contains(&s.id));
+    }
+
+    fn info(&self) -> QuicInfo {
+        QuicInfo {
+            handle: QuicHandle(self.id), owner: self.owner, remote: self.remote, local: self.path.local, state: self.state,
+            alpn: self.alpn.clone(), streams: self.streams.len(), srtt_ms: self.smoothed_rtt, cwnd: self.cwnd,
+            migrations: self.migrations,
+        }
+    }
+}
+
+/// A connection as reported to callers.
+#[derive(Debug, Clone)]
+pub struct QuicInfo {
+    pub handle:     QuicHandle,
+    pub owner:      ProcessId,
+    pub remote:     SocketAddr,
+    /// The address the current path leaves from.
+    pub local:      IpAddr,
+    pub state:      QuicState,
+    pub alpn:       Option<String>,
+    pub streams:    usize,
+    pub srtt_ms:    Option<u64>,
+    pub cwnd:       usize,
+    pub migrations: u32,
+}
+
+// ─── connection table ─────────────────────────────────────────────────────────
+
+struct Quic {
+    conns:   Vec<Conn>,
+    next_id: u32,
+}
+
+impl Quic {
+    /// The current process's connection `h`.
+    fn get(&mut self, h: QuicHandle) -> Result<&mut Conn, &'static str> {
+        let owner = crate::process::current_pid();
+        self.conns.iter_mut().find(|c| c.id == h.0 && c.owner == owner).ok_or("no such connection")
+    }
+
+    /// As `get`, for a connection still open.
+    fn open(&mut self, h: QuicHandle) -> Result<&mut Conn, &'static str> {
+        let c = self.get(h)?;
+        match c.state {
+            QuicState::Closed => Err(c.error.unwrap_or("connection closed")),
+            _                 => Ok(c),
+        }
+    }
+}
+
+static QUIC: Mutex<Quic> = Mutex::new(Quic { conns: Vec::new(), next_id: 1 });
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Open a connection to `remote`, authenticating it as `host` and
+/// offering the ALPN protocols `alpn`.  Returns at once; the connection
+/// becomes established when the handshake completes.
+pub fn connect(remote: SocketAddr, host: &str, alpn: &[&str]) -> Result<QuicHandle, &'static str> {
+    if alpn.is_empty() { return Err("QUIC needs an application protocol"); }
+    let owner = crate::process::current_pid();
+    netns::check(owner, ipv4::PROTO_UDP, remote)?;
+    let local = netns::source(owner, remote.ip, IpAddr::default())?;
+    let b = backend::get();
+    let sock = b.udp_bind(SocketAddr::new(IpAddr::default(), 0))?;
+    let dcid: [u8; CID_LEN] = random();
+    let scid: [u8; CID_LEN] = random();
+    let (client, server) = Keys::initial(&dcid);
+    // The ClientHello carries our transport parameters
+    let (tls, hello) = Handshake::start(host, alpn, &[(EXT_TRANSPORT_PARAMS, &transport_params(&scid))]);
+    let mut quic = QUIC.lock();
+    let id = quic.next_id;
+    quic.next_id = quic.next_id.wrapping_add(1).max(1);
+    let now = now();
+    let mut c = Conn {
+        id, owner, remote, state: QuicState::Handshaking, error: None,
+        tls, alpn: None,
+        spaces: Default::default(),
+        local_cids: vec![(0, scid)], next_cid_seq: 1,
+        remote_cids: vec![RemoteCid { seq: 0, cid: dcid.to_vec(), token: None }],
+        original_dcid: dcid.to_vec(), server_scid: None, retry_scid: None, token: Vec::new(),
+        path: Path { sock: Some(sock), local, challenge: None }, migrations: 0,
+        peer: Params::default(), streams: BTreeMap::new(), accept: VecDeque::new(),
+        opened_bidi: 0, opened_uni: 0, peer_bidi: 0, peer_uni: 0,
+        max_peer_bidi: MAX_PEER_STREAMS, max_peer_uni: MAX_PEER_STREAMS,
+        tx_data: 0, rx_data: 0, rx_limit: CONN_WINDOW, rx_read: 0,
+        send_max_data: false, send_max_bidi: false, send_max_uni: false,
+        new_cids: Vec::new(), retire_cids: Vec::new(), path_responses: Vec::new(), send_challenge: false,
+        latest_rtt: 0, smoothed_rtt: None, rtt_var: 0, min_rtt: 0, pto_count: 0,
+        cwnd: INITIAL_WINDOW, ssthresh: usize::MAX, in_flight: 0, recovery_start: None,
+        last_rx: now, key_phase: false, prev_rx: None, confirmed: false,
+    };
+    c.spaces[INITIAL].tx = Some(client);
+    c.spaces[INITIAL].rx = Some(server);
+    c.spaces[INITIAL].crypto_tx.write(&hello);
+    c.flush(now);
+    quic.conns.push(c);
+    Ok(QuicHandle(id))
+}
+
+pub fn state(h: QuicHandle) -> Result<QuicState, &'static str> {
+    let mut quic = QUIC.lock();
+    let c = quic.get(h)?;
+    match (c.state, c.error) {
+        (QuicState::Closed, Some(e)) => Err(e),
+        (s, _)                       => Ok(s),
+    }
+}
+
+/// What a call on connection `h` would find: readable while a stream
+/// has data or its end to read, or one the server opened waits to be
+/// taken; writable once established.
+pub fn readiness(h: QuicHandle) -> Result<Ready, &'static str> {
+    let mut quic = QUIC.lock();
+    let c = quic.get(h)?;
+    let readable = !c.accept.is_empty() || c.streams.values().any(|s| {
+        s.reset || s.rx.as_ref().is_some_and(|rx| !rx.ready.is_empty() || (rx.finished() && !s.read_fin))
+    });
+    let mut ready = if readable { Ready::READABLE } else { Ready::NONE };
+    match c.state {
+        QuicState::Handshaking => {}
+        QuicState::Established => ready = ready | Ready::WRITABLE,
+        QuicState::Closed      => ready = ready | Ready::HANGUP,
+    }
+    Ok(ready)
+}
+
+/// The protocol the server picked by ALPN.
+pub fn alpn(h: QuicHandle) -> Result<Option<String>, &'static str> {
+    Ok(QUIC.lock().get(h)?.alpn.clone())
+}
+
+/// Open a stream, bidirectional or one only we send on.  Would block
+/// while the server allows no more.
+pub fn open_stream(h: QuicHandle, bidirectional: bool) -> Result<u64, &'static str> {
+    let mut quic = QUIC.lock();
+    let c = quic.open(h)?;
+    if c.state != QuicState::Established { return Err("would block"); }
+    let (opened, max, kind) = if bidirectional {
+        (&mut c.opened_bidi, c.peer.max_streams_bidi, 0)
+    } else {
+        (&mut c.opened_uni, c.peer.max_streams_uni, 2)
+    };
+    if *opened >= max { return Err("would block"); }
+    let id = *opened * 4 + kind;
+    *opened += 1;
+    let limit = c.stream_limit(id);
+    c.streams.insert(id, Stream::new(id, limit));
+    Ok(id)
+}
+
+/// Take the next stream the server opened.
+pub fn accept_stream(h: QuicHandle) -> Result<u64, &'static str> {
+    let mut quic = QUIC.lock();
+    let c = quic.open(h)?;
+    let id = c.accept.pop_front().ok_or("would block")?;
+    // Each stream taken lets the server open another
+    if is_bidi(id) { c.max_peer_bidi += 1; c.send_max_bidi = true; } else { c.max_peer_uni += 1; c.send_max_uni = true; }
+    c.flush(now());
+    Ok(id)
+}
+
+/// Queue `data` on stream `id`, finishing the stream after it if `fin`;
+/// returns how much was taken.
+pub fn stream_send(h: QuicHandle, id: u64, data: &[u8], fin: bool) -> Result<usize, &'static str> {
+    let mut quic = QUIC.lock();
+    let c = quic.open(h)?;
+    let s = c.streams.get_mut(&id).ok_or("no such stream")?;
+    if s.stopped { return Err("stream stopped by server"); }
+    let tx = s.tx.as_mut().ok_or("stream is receive-only")?;
+    if tx.fin { return Err("stream finished"); }
+    let n = data.len().min(SEND_BUF.saturating_sub(tx.data.len()));
+    if n == 0 && !data.is_empty() { return Err("would block"); }
+    tx.write(&data[..n]);
+    if fin && n == data.len() { tx.fin = true; }
+    c.flush(now());
+    Ok(n)
+}
+
+/// Read from stream `id`.  `Ok(0)` means the server has finished it.
+pub fn stream_recv(h: QuicHandle, id: u64, buf: &mut [u8]) -> Result<usize, &'static str> {
+    let mut quic = QUIC.lock();
+    let c = quic.get(h)?;
+    let s = c.streams.get_mut(&id).ok_or("no such stream")?;
+    if s.reset { return Err("stream reset by server"); }
+    let rx = s.rx.as_mut().ok_or("stream is send-only")?;
+    if rx.ready.is_empty() {
+        if rx.finished() { s.read_fin = true; return Ok(0); }
+        return match (c.state, c.error) {
+            (QuicState::Closed, e) => Err(e.unwrap_or("connection closed")),
+            _                      => Err("would block"),
+        };
+    }
+    let n = buf.len().min(rx.ready.len());
+    buf[..n].copy_from_slice(&rx.ready[..n]);
+    rx.ready.drain(..n);
+    s.read += n as u64;
+    // Open the windows again once half is used
+    if rx.fin.is_none() && s.rx_limit - s.read < STREAM_WINDOW / 2 {
+        s.rx_limit = s.read + STREAM_WINDOW;
+        s.update = true;
+    }
+    c.rx_read += n as u64;
+    if c.rx_limit - c.rx_read < CONN_WINDOW / 2 {
+        c.rx_limit = c.rx_read + CONN_WINDOW;
+        c.send_max_data = true;
+    }
+    c.flush(now());
+    Ok(n)
+}
+
+/// Close the connection, telling the server.
+pub fn close(h: QuicHandle) -> Result<(), &'static str> {
+    let mut quic = QUIC.lock();
+    let c = quic.get(h)?;
+    c.close_with(Close(NO_ERROR, "connection closed"));
+    c.release();
+    quic.conns.retain(|c| c.id != h.0);
+    Ok(())
+}
+
+pub fn connections() -> Vec<QuicInfo> {
+    QUIC.lock().conns.iter().map(Conn::info).collect()
+}
+
+// ─── stack poll ───────────────────────────────────────────────────────────────
+
+/// Read each connection's datagrams and run its timers, as its owner.
+pub(super) fn on_timer(now: u64) {
+    let mut quic = QUIC.lock();
+    let b = backend::get();
+    let mut buf = [0u8; MAX_RECV];
+    for c in quic.conns.iter_mut().filter(|c| c.state != QuicState::Closed) {
+        crate::process::run_as(c.owner, || {
+            while let Some(sock) = c.path.sock {
+                let Ok((n, from)) = b.udp_recv_from(sock, &mut buf) else { break };
+                if from != c.remote { continue; }
+                if let Err(e) = c.input(&buf[..n], now) {
+                    c.close_with(e);
+                    break;
+                }
+            }
+            if c.wants_send() { c.flush(now); }
+            c.poll(now);
+            if c.state == QuicState::Closed { c.release(); }
+        });
+    }
+}
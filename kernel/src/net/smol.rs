@@ -0,0 +1,441 @@
+//! smoltcp Backend
+//! Serves the socket API from smoltcp, when built with the `smoltcp`
+//! feature.  smoltcp takes over the first Ethernet interface attached:
+//! its frames go to smoltcp rather than the native stack, and smoltcp's
+//! own DHCP client configures it.  IPv4 only.
+//!
+//! Everything else stays native: loopback, the WireGuard tunnel, further
+//! Ethernet interfaces, and the stack's own services (DNS, WireGuard)
+//! along with their traffic.
+
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+use smoltcp::socket::{dhcpv4, tcp, udp};
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr, IpEndpoint, IpListenEndpoint};
+
+use super::backend::Backend;
+use super::{IpAddr, Ipv4Addr, SocketAddr};
+use crate::event::Ready;
+
+const ETH_HEADER:      usize = 14;
+const TCP_BUF:         usize = 64 * 1024;
+const UDP_BUF:         usize = 64 * 1024;
+/// Datagrams a UDP socket holds each way.
+const UDP_PACKETS:     usize = 64;
+/// Sockets a listener keeps listening at once, whatever its backlog, as
+/// each holds its buffers.
+const LISTEN_POOL:     usize = 4;
+const EPHEMERAL_FIRST: u16   = 49152;
+
+// ─── device ───────────────────────────────────────────────────────────────────
+
+/// The claimed interface as smoltcp sees it: frames handed in by
+/// `input`, and sent out through the native interface.
+struct Nic {
+    index: usize,
+    mtu:   usize,
+    rx:    VecDeque<Vec<u8>>,
+}
+
+struct RxToken(Vec<u8>);
+
+struct TxToken(usize);
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F: FnOnce(&[u8]) -> R>(self, f: F) -> R {
+        f(&self.0)
+    }
+}
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+        let mut frame = vec![0; len];
+        let r = f(&mut frame);
+        let _ = super::transmit_raw(self.0, &frame);
+        r
+    }
+}
+
+impl Device for Nic {
+    type RxToken<'a> = RxToken;
+    type TxToken<'a> = TxToken;
+
+    fn receive(&mut self, _now: Instant) -> Option<(RxToken, TxToken)> {
+        Some((RxToken(self.rx.pop_front()?), TxToken(self.index)))
+    }
+
+    fn transmit(&mut self, _now: Instant) -> Option<TxToken> {
+        Some(TxToken(self.index))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ethernet;
+        caps.max_transmission_unit = self.mtu + ETH_HEADER;
+        caps
+    }
+}
+
+// ─── stack ────────────────────────────────────────────────────────────────────
+
+/// A listening socket.  A smoltcp socket takes one connection, so a
+/// listener keeps a pool of them listening on its port, and moves each
+/// that connects to its accept queue.
+struct Listener {
+    id:      u32,
+    port:    u16,
+    backlog: usize,
+    /// Listening or half-open.
+    pool:    Vec<SocketHandle>,
+    /// Connected, oldest first.
+    ready:   VecDeque<SocketHandle>,
+}
+
+struct Smol {
+    nic:       Nic,
+    iface:     Interface,
+    sockets:   SocketSet<'static>,
+    dhcp:      SocketHandle,
+    /// Our handles for smoltcp's.
+    tcp:       Vec<(u32, SocketHandle)>,
+    udp:       Vec<(u32, SocketHandle)>,
+    listeners: Vec<Listener>,
+    /// Closed by the app, kept until the connection has wound down.
+    closing:   Vec<SocketHandle>,
+    next_id:   u32,
+    next_port: u16,
+}
+
+static SMOL: Mutex<Option<Smol>> = Mutex::new(None);
+
+fn instant(ms: u64) -> Instant {
+    Instant::from_millis(ms as i64)
+}
+
+fn endpoint(a: SocketAddr) -> Result<IpEndpoint, &'static str> {
+    match a.ip {
+        IpAddr::V4(ip) => Ok(IpEndpoint::new(IpAddress::Ipv4(ip.0.into()), a.port)),
+        IpAddr::V6(_)  => Err("address family not supported"),
+    }
+}
+
+fn socket_addr(e: IpEndpoint) -> SocketAddr {
+    let IpAddress::Ipv4(ip) = e.addr;
+    SocketAddr::new(Ipv4Addr(ip.octets()), e.port)
+}
+
+impl Smol {
+    /// Process received frames, run timers and send what is queued, then
+    /// apply any DHCP news and drop connections that have wound down.
+    fn run(&mut self, now: u64) {
+        self.iface.poll(instant(now), &mut self.nic, &mut self.sockets);
+        self.tend_listeners();
+        let lease = match self.sockets.get_mut::<dhcpv4::Socket>(self.dhcp).poll() {
+            None                                => None,
+            Some(dhcpv4::Event::Configured(c))  => Some(Some((c.address, c.router))),
+            Some(dhcpv4::Event::Deconfigured)   => Some(None),
+        };
+        if let Some(lease) = lease {
+            self.iface.update_ip_addrs(|addrs| {
+                addrs.clear();
+                if let Some((addr, _)) = lease { let _ = addrs.push(IpCidr::Ipv4(addr)); }
+            });
+            match lease.and_then(|l| l.1) {
+                Some(router) => { let _ = self.iface.routes_mut().add_default_ipv4_route(router); }
+                None         => { self.iface.routes_mut().remove_default_ipv4_route(); }
+            }
+        }
+        let sockets = &mut self.sockets;
+        self.closing.retain(|&h| {
+            let done = sockets.get::<tcp::Socket>(h).state() == tcp::State::Closed;
+            if done { sockets.remove(h); }
+            !done
+        });
+    }
+
+    /// Queue listeners' connected sockets for `accept`, and keep their
+    /// pools listening while their backlogs have room.
+    fn tend_listeners(&mut self) {
+        let sockets = &mut self.sockets;
+        for l in self.listeners.iter_mut() {
+            l.pool.retain(|&h| match sockets.get::<tcp::Socket>(h).state() {
+                tcp::State::Listen | tcp::State::SynReceived => true,
+                tcp::State::Closed => { sockets.remove(h); false }
+                _ => { l.ready.push_back(h); false }
+            });
+            let room = l.backlog.saturating_sub(l.ready.len()).min(LISTEN_POOL);
+            while l.pool.len() < room {
+                let mut sock = new_tcp();
+                if sock.listen(l.port).is_err() { break; }
+                l.pool.push(sockets.add(sock));
+            }
+        }
+    }
+
+    fn add_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        id
+    }
+
+    fn port_in_use(&self, port: u16) -> bool {
+        self.tcp.iter().any(|&(_, h)| self.sockets.get::<tcp::Socket>(h).local_endpoint().is_some_and(|e| e.port == port)
+            || self.sockets.get::<tcp::Socket>(h).listen_endpoint().port == port)
+            || self.udp.iter().any(|&(_, h)| self.sockets.get::<udp::Socket>(h).endpoint().port == port)
+            || self.listeners.iter().any(|l| l.port == port)
+    }
+
+    fn ephemeral_port(&mut self) -> Result<u16, &'static str> {
+        for _ in EPHEMERAL_FIRST..=u16::MAX {
+            let port = self.next_port;
+            self.next_port = if port == u16::MAX { EPHEMERAL_FIRST } else { port + 1 };
+            if !self.port_in_use(port) { return Ok(port); }
+        }
+        Err("out of ephemeral ports")
+    }
+
+    /// Charge the calling app for payload through the socket API.
+    fn account(&self, sent: bool, bytes: usize) {
+        super::accounting::payload(crate::process::current_pid(), self.nic.index, sent, bytes);
+    }
+
+    fn tcp(&mut self, id: u32) -> Result<&mut tcp::Socket<'static>, &'static str> {
+        let &(_, h) = self.tcp.iter().find(|t| t.0 == id).ok_or("no such connection")?;
+        Ok(self.sockets.get_mut::<tcp::Socket>(h))
+    }
+
+    fn udp(&mut self, id: u32) -> Result<&mut udp::Socket<'static>, &'static str> {
+        let &(_, h) = self.udp.iter().find(|u| u.0 == id).ok_or("no such socket")?;
+        Ok(self.sockets.get_mut::<udp::Socket>(h))
+    }
+}
+
+/// Look at the stack without running it.
+fn peek<R>(f: impl FnOnce(&mut Smol) -> Result<R, &'static str>) -> Result<R, &'static str> {
+    SMOL.lock().as_mut().ok_or("no interface for smoltcp").and_then(f)
+}
+
+/// Run `f` on the stack, then let it send whatever `f` queued.
+fn with_stack<R>(f: impl FnOnce(&mut Smol) -> Result<R, &'static str>) -> Result<R, &'static str> {
+    let mut smol = SMOL.lock();
+    let s = smol.as_mut().ok_or("no interface for smoltcp")?;
+    let r = f(s);
+    s.run(crate::arch::uptime_millis());
+    r
+}
+
+fn new_tcp() -> tcp::Socket<'static> {
+    tcp::Socket::new(tcp::SocketBuffer::new(vec![0; TCP_BUF]), tcp::SocketBuffer::new(vec![0; TCP_BUF]))
+}
+
+// ─── backend ──────────────────────────────────────────────────────────────────
+
+pub struct Smoltcp;
+
+impl Backend for Smoltcp {
+    fn name(&self) -> &'static str {
+        "smoltcp"
+    }
+
+    fn claim(&self, index: usize) -> bool {
+        let mut smol = SMOL.lock();
+        if smol.is_some() { return false; }
+        let Some(info) = super::interface(index) else { return false };
+        let Some(mac) = info.mac else { return false };
+        let mut nic = Nic { index, mtu: info.mtu, rx: VecDeque::new() };
+        let mut config = Config::new(HardwareAddress::Ethernet(EthernetAddress(mac.0)));
+        config.random_seed = crate::entropy::next_u64();
+        let iface = Interface::new(config, &mut nic, instant(crate::arch::uptime_millis()));
+        let mut sockets = SocketSet::new(Vec::new());
+        let dhcp = sockets.add(dhcpv4::Socket::new());
+        *smol = Some(Smol {
+            nic, iface, sockets, dhcp, tcp: Vec::new(), udp: Vec::new(), listeners: Vec::new(), closing: Vec::new(),
+            next_id: 1, next_port: EPHEMERAL_FIRST,
+        });
+        true
+    }
+
+    fn owns(&self, index: usize) -> bool {
+        SMOL.lock().as_ref().is_some_and(|s| s.nic.index == index)
+    }
+
+    fn input(&self, index: usize, frame: Vec<u8>) {
+        if let Some(s) = SMOL.lock().as_mut().filter(|s| s.nic.index == index) { s.nic.rx.push_back(frame); }
+    }
+
+    fn poll(&self, now: u64) {
+        if let Some(s) = SMOL.lock().as_mut() { s.run(now); }
+    }
+
+    fn tcp_connect(&self, remote: SocketAddr) -> Result<u32, &'static str> {
+        let remote = endpoint(remote)?;
+        with_stack(|s| {
+            let port = s.ephemeral_port()?;
+            let mut sock = new_tcp();
+            sock.connect(s.iface.context(), remote, port).map_err(|e| match e {
+                tcp::ConnectError::Unaddressable => "no route to host",
+                tcp::ConnectError::InvalidState  => "connection in use",
+            })?;
+            let id = s.add_id();
+            let h = s.sockets.add(sock);
+            s.tcp.push((id, h));
+            Ok(id)
+        })
+    }
+
+    fn tcp_listen(&self, port: u16, backlog: usize) -> Result<u32, &'static str> {
+        if port == 0 || port >= EPHEMERAL_FIRST { return Err("port not available for listening"); }
+        with_stack(|s| {
+            if s.port_in_use(port) { return Err("address in use"); }
+            let id = s.add_id();
+            let backlog = backlog.clamp(1, super::tcp::MAX_BACKLOG);
+            s.listeners.push(Listener { id, port, backlog, pool: Vec::new(), ready: VecDeque::new() });
+            s.tend_listeners();
+            Ok(id)
+        })
+    }
+
+    fn tcp_accept(&self, h: u32) -> Result<(u32, SocketAddr), &'static str> {
+        with_stack(|s| {
+            let l = s.listeners.iter_mut().find(|l| l.id == h).ok_or("not listening")?;
+            let handle = l.ready.pop_front().ok_or("would block")?;
+            let remote = s.sockets.get::<tcp::Socket>(handle).remote_endpoint().ok_or("connection lost")?;
+            let id = s.add_id();
+            s.tcp.push((id, handle));
+            Ok((id, socket_addr(remote)))
+        })
+    }
+
+    fn tcp_connected(&self, h: u32) -> Result<bool, &'static str> {
+        use tcp::State::*;
+        with_stack(|s| match s.tcp(h)?.state() {
+            Listen | SynSent | SynReceived => Ok(false),
+            Closed                         => Err("connection refused"),
+            _                              => Ok(true),
+        })
+    }
+
+    fn tcp_send(&self, h: u32, data: &[u8]) -> Result<usize, &'static str> {
+        with_stack(|s| match s.tcp(h)?.send_slice(data) {
+            Ok(0) if !data.is_empty() => Err("would block"),
+            Ok(n)                     => { s.account(true, n); Ok(n) }
+            Err(_)                    => Err("connection closing"),
+        })
+    }
+
+    fn tcp_recv(&self, h: u32, buf: &mut [u8]) -> Result<usize, &'static str> {
+        with_stack(|s| match s.tcp(h)?.recv_slice(buf) {
+            Ok(0) if !buf.is_empty()          => Err("would block"),
+            Ok(n)                             => { s.account(false, n); Ok(n) }
+            Err(tcp::RecvError::Finished)     => Ok(0),
+            Err(tcp::RecvError::InvalidState) => Err("connection reset"),
+        })
+    }
+
+    fn tcp_ready(&self, h: u32) -> Result<Ready, &'static str> {
+        use tcp::State::*;
+        peek(|s| {
+            if let Some(l) = s.listeners.iter().find(|l| l.id == h) {
+                return Ok(if l.ready.is_empty() { Ready::NONE } else { Ready::READABLE });
+            }
+            let sock = s.tcp(h)?;
+            let mut ready = Ready::NONE;
+            match sock.state() {
+                Closed | TimeWait => ready = Ready::HANGUP,
+                // The peer has sent its FIN: reads see the end
+                CloseWait | LastAck | Closing => ready = Ready::HANGUP | Ready::READABLE,
+                _ => {}
+            }
+            if sock.can_recv() { ready = ready | Ready::READABLE; }
+            if sock.can_send() { ready = ready | Ready::WRITABLE; }
+            Ok(ready)
+        })
+    }
+
+    fn tcp_close(&self, h: u32) -> Result<(), &'static str> {
+        with_stack(|s| {
+            // A listener resets what it holds unaccepted
+            if let Some(i) = s.listeners.iter().position(|l| l.id == h) {
+                let l = s.listeners.remove(i);
+                for handle in l.pool.into_iter().chain(l.ready) {
+                    s.sockets.get_mut::<tcp::Socket>(handle).abort();
+                    s.closing.push(handle);
+                }
+                return Ok(());
+            }
+            let i = s.tcp.iter().position(|t| t.0 == h).ok_or("no such connection")?;
+            let (_, handle) = s.tcp.remove(i);
+            s.sockets.get_mut::<tcp::Socket>(handle).close();
+            s.closing.push(handle);
+            Ok(())
+        })
+    }
+
+    fn udp_bind(&self, local: SocketAddr) -> Result<u32, &'static str> {
+        let addr = match local.ip {
+            IpAddr::V4(ip) if ip == Ipv4Addr::UNSPECIFIED => None,
+            IpAddr::V4(ip) => Some(IpAddress::Ipv4(ip.0.into())),
+            IpAddr::V6(_)  => return Err("address family not supported"),
+        };
+        with_stack(|s| {
+            let port = match local.port {
+                0 => s.ephemeral_port()?,
+                p if s.port_in_use(p) => return Err("address in use"),
+                p => p,
+            };
+            let buffer = || udp::PacketBuffer::new(vec![udp::PacketMetadata::EMPTY; UDP_PACKETS], vec![0; UDP_BUF]);
+            let mut sock = udp::Socket::new(buffer(), buffer());
+            sock.bind(IpListenEndpoint { addr, port }).map_err(|_| "cannot bind")?;
+            let id = s.add_id();
+            let h = s.sockets.add(sock);
+            s.udp.push((id, h));
+            Ok(id)
+        })
+    }
+
+    fn udp_send_to(&self, h: u32, data: &[u8], to: SocketAddr) -> Result<usize, &'static str> {
+        let to = endpoint(to)?;
+        with_stack(|s| match s.udp(h)?.send_slice(data, to) {
+            Ok(())                            => { s.account(true, data.len()); Ok(data.len()) }
+            Err(udp::SendError::BufferFull)   => Err("would block"),
+            Err(udp::SendError::Unaddressable) => Err("no route to host"),
+        })
+    }
+
+    fn udp_recv_from(&self, h: u32, buf: &mut [u8]) -> Result<(usize, SocketAddr), &'static str> {
+        with_stack(|s| match s.udp(h)?.recv() {
+            Ok((data, meta)) => {
+                let (len, n) = (data.len(), data.len().min(buf.len()));
+                buf[..n].copy_from_slice(&data[..n]);
+                s.account(false, len);
+                Ok((n, socket_addr(meta.endpoint)))
+            }
+            Err(_) => Err("would block"),
+        })
+    }
+
+    fn udp_close(&self, h: u32) -> Result<(), &'static str> {
+        with_stack(|s| {
+            let i = s.udp.iter().position(|u| u.0 == h).ok_or("no such socket")?;
+            let (_, handle) = s.udp.remove(i);
+            s.sockets.remove(handle);
+            Ok(())
+        })
+    }
+
+    fn udp_ready(&self, h: u32) -> Result<Ready, &'static str> {
+        peek(|s| {
+            let sock = s.udp(h)?;
+            let mut ready = Ready::NONE;
+            if sock.can_recv() { ready = ready | Ready::READABLE; }
+            if sock.can_send() { ready = ready | Ready::WRITABLE; }
+            Ok(ready)
+        })
+    }
+}
@@ -0,0 +1,443 @@
+//! Sockets
+//! The socket API for apps: TCP streams and UDP datagrams, served by
+//! whichever stack backend the kernel was built with, and QUIC
+//! connections carrying streams of their own over its UDP.  Opening a socket
+//! needs the Network capability with READ and WRITE rights; the socket
+//! then belongs to the caller, and its traffic is held to the caller's
+//! namespace whichever backend carries it.
+//!
+//! Calls wait until they can proceed, for at most the socket's timeout,
+//! unless it is non-blocking, when they fail with "would block" instead.
+//! A service driving many non-blocking sockets registers them in an event
+//! set (`crate::event`) and waits on that; `readiness` is what it sees.
+//! A listening stream socket queues up to its backlog of connections;
+//! `accept` hands each out as a socket of its own, held under the
+//! listener's capability.
+//!
+//! An app given a proxy (see `proxy`) has its stream connections made
+//! through it here, and nothing else leaves for the network.
+
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::backend;
+use super::proxy::{self, Target};
+use super::quic::{self, QuicHandle, QuicState};
+use super::{dns, ipv4, netns, SocketAddr};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::event::{self, Ready};
+use crate::process::{ProcessId, WaitQueue};
+
+/// How long `connect` waits for the handshake.
+const CONNECT_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Socket(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketType {
+    Stream,
+    Datagram,
+    Quic,
+}
+
+struct Entry {
+    id:          u32,
+    owner:       ProcessId,
+    ty:          SocketType,
+    /// The capability it was opened with; accepted sockets carry their
+    /// listener's.
+    cap:         Capability,
+    /// The backend's handle.
+    inner:       u32,
+    /// A stream socket taking connections rather than carrying one.
+    listening:   bool,
+    nonblocking: bool,
+    timeout_ms:  Option<u64>,
+}
+
+/// A socket as reported to callers.
+#[derive(Debug, Clone)]
+pub struct SocketInfo {
+    pub socket: Socket,
+    pub owner:  ProcessId,
+    pub ty:     SocketType,
+}
+
+struct Sockets {
+    entries: Vec<Entry>,
+    next_id: u32,
+}
+
+static SOCKETS: Mutex<Sockets> = Mutex::new(Sockets { entries: Vec::new(), next_id: 1 });
+
+/// Woken each time the stack has run.
+static EVENTS: WaitQueue = WaitQueue::new();
+
+/// The current process's socket `s`: (type, backend handle, non-blocking,
+/// timeout).  Fails if the caller is offline.
+fn lookup(s: Socket) -> Result<(SocketType, u32, bool, Option<u64>), &'static str> {
+    let owner = crate::process::current_pid();
+    let e = SOCKETS.lock().entries.iter().find(|e| e.id == s.0 && e.owner == owner)
+        .map(|e| (e.ty, e.inner, e.nonblocking, e.timeout_ms)).ok_or("no such socket")?;
+    if !netns::online(owner) { return Err("network disabled for app"); }
+    Ok(e)
+}
+
+fn open(cap: &Capability, ty: SocketType, inner: impl FnOnce() -> Result<u32, &'static str>) -> Result<Socket, &'static str> {
+    let owner = crate::process::current_pid();
+    capability::validate(owner, cap, CapabilityType::Network, Permissions::READ | Permissions::WRITE)?;
+    if !netns::online(owner) { return Err("network disabled for app"); }
+    let inner = inner()?;
+    Ok(add(owner, cap.clone(), ty, inner, false))
+}
+
+fn add(owner: ProcessId, cap: Capability, ty: SocketType, inner: u32, listening: bool) -> Socket {
+    let mut sockets = SOCKETS.lock();
+    let id = sockets.next_id;
+    sockets.next_id = sockets.next_id.wrapping_add(1).max(1);
+    sockets.entries.push(Entry { id, owner, ty, cap, inner, listening, nonblocking: false, timeout_ms: None });
+    Socket(id)
+}
+
+fn is_listening(s: Socket) -> bool {
+    SOCKETS.lock().entries.iter().any(|e| e.id == s.0 && e.listening)
+}
+
+/// Repeat `op` while it would block, unless the socket is non-blocking.
+fn block<R>(nonblocking: bool, timeout_ms: Option<u64>, mut op: impl FnMut() -> Result<R, &'static str>) -> Result<R, &'static str> {
+    if nonblocking { return op(); }
+    let deadline = timeout_ms.map(|t| crate::arch::uptime_millis() + t);
+    EVENTS.wait_until(deadline, || match op() {
+        Err("would block") => None,
+        r => Some(r),
+    }).unwrap_or(Err("timed out"))
+}
+
+/// A stream call that cannot proceed until the connection is up.
+fn when_connected(inner: u32) -> Result<(), &'static str> {
+    match backend::get().tcp_connected(inner)? {
+        true  => Ok(()),
+        false => Err("would block"),
+    }
+}
+
+/// A QUIC call that cannot proceed until the handshake is done.
+fn when_established(inner: u32) -> Result<(), &'static str> {
+    match quic::state(QuicHandle(inner))? {
+        QuicState::Established => Ok(()),
+        _                      => Err("would block"),
+    }
+}
+
+/// A connection to a proxy, while its handshake runs.
+struct Handshake {
+    inner:    u32,
+    deadline: u64,
+}
+
+impl Handshake {
+    fn left(&self) -> Option<u64> {
+        Some(self.deadline.saturating_sub(crate::arch::uptime_millis()))
+    }
+}
+
+impl proxy::Tunnel for Handshake {
+    fn send_all(&mut self, mut data: &[u8]) -> Result<(), &'static str> {
+        let b = backend::get();
+        while !data.is_empty() {
+            let n = block(false, self.left(), || b.tcp_send(self.inner, data))?;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> Result<usize, &'static str> {
+        let b = backend::get();
+        block(false, self.left(), || b.tcp_recv(self.inner, buf))
+    }
+}
+
+/// Open a stream connection through the current process's proxy to
+/// `target`.  It fails if the proxy does: there is no going direct.
+fn proxied(cap: &Capability, target: &Target) -> Result<Socket, &'static str> {
+    let owner = crate::process::current_pid();
+    let addr = proxy::address(owner).ok_or("no proxy")?;
+    let r = (|| {
+        // What leaves is traffic to the proxy
+        netns::check(owner, ipv4::PROTO_TCP, addr)?;
+        let b = backend::get();
+        let s = open(cap, SocketType::Stream, || b.tcp_connect(addr)).map_err(|_| "proxy unreachable")?;
+        let inner = lookup(s)?.1;
+        let deadline = crate::arch::uptime_millis() + CONNECT_TIMEOUT_MS;
+        let r = block(false, Some(CONNECT_TIMEOUT_MS), || when_connected(inner)).map_err(|_| "proxy unreachable")
+            .and_then(|_| proxy::handshake(owner, &mut Handshake { inner, deadline }, target));
+        if r.is_err() { let _ = close(s); }
+        r.map(|_| s)
+    })();
+    proxy::counted(owner, r.is_ok());
+    r
+}
+
+/// The stack has run; blocked calls may proceed, and services waiting
+/// for their first connection may start.
+pub(super) fn wake() {
+    EVENTS.wake_all();
+    event::notify();
+    crate::init::socket_activity();
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Which backend serves sockets.
+pub fn backend() -> &'static str {
+    backend::get().name()
+}
+
+/// Open a stream connection to `remote`, waiting for the handshake.
+pub fn connect(cap: &Capability, remote: SocketAddr) -> Result<Socket, &'static str> {
+    let owner = crate::process::current_pid();
+    netns::check(owner, ipv4::PROTO_TCP, remote)?;
+    if proxy::applies(owner, remote.ip) { return proxied(cap, &Target::Addr(remote)); }
+    let b = backend::get();
+    let s = open(cap, SocketType::Stream, || b.tcp_connect(remote))?;
+    let inner = lookup(s)?.1;
+    let r = block(false, Some(CONNECT_TIMEOUT_MS), || when_connected(inner));
+    if r.is_err() { let _ = close(s); }
+    r.map(|_| s)
+}
+
+/// Start a stream connection to `remote` and return at once with a
+/// non-blocking socket; it turns writable when the handshake is done, or
+/// reports an error or hang-up if it fails.  Through a proxy, the call
+/// waits for the proxy to connect.
+pub fn connect_nonblocking(cap: &Capability, remote: SocketAddr) -> Result<Socket, &'static str> {
+    let owner = crate::process::current_pid();
+    netns::check(owner, ipv4::PROTO_TCP, remote)?;
+    if proxy::applies(owner, remote.ip) {
+        let s = proxied(cap, &Target::Addr(remote))?;
+        set_nonblocking(s, true)?;
+        return Ok(s);
+    }
+    let b = backend::get();
+    let s = open(cap, SocketType::Stream, || b.tcp_connect(remote))?;
+    set_nonblocking(s, true)?;
+    Ok(s)
+}
+
+/// Open a stream connection to `host`, a name or an address, at `port`.
+/// A proxied app's proxy is given the name, so no lookup leaves the
+/// device; otherwise its addresses are tried in turn.
+pub fn connect_host(cap: &Capability, host: &str, port: u16) -> Result<Socket, &'static str> {
+    let target = Target::new(host, port);
+    if let Target::Addr(remote) = target { return connect(cap, remote); }
+    if proxy::is_proxied(crate::process::current_pid()) && !host.eq_ignore_ascii_case("localhost") {
+        return proxied(cap, &target);
+    }
+    let mut last = "no addresses for name";
+    for ip in dns::resolve(host)? {
+        match connect(cap, SocketAddr::new(ip, port)) {
+            Ok(s)  => return Ok(s),
+            Err(e) => last = e,
+        }
+    }
+    Err(last)
+}
+
+/// Open a stream socket taking connections on `port`, holding up to
+/// `backlog` of them half-open or waiting for `accept`; further ones are
+/// refused until it drains.
+pub fn listen(cap: &Capability, port: u16, backlog: usize) -> Result<Socket, &'static str> {
+    let b = backend::get();
+    let s = open(cap, SocketType::Stream, || b.tcp_listen(port, backlog))?;
+    if let Some(e) = SOCKETS.lock().entries.iter_mut().find(|e| e.id == s.0) { e.listening = true; }
+    Ok(s)
+}
+
+/// Take the next connection from a listening socket, returning it as a
+/// socket of its own along with the peer's address.  The listener's
+/// capability must still hold; the new socket is held under it, and is
+/// non-blocking if the listener is.  Connections the app's firewall
+/// refuses are closed here.
+pub fn accept(s: Socket) -> Result<(Socket, SocketAddr), &'static str> {
+    let (SocketType::Stream, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a stream socket") };
+    if !is_listening(s) { return Err("not listening"); }
+    let owner = crate::process::current_pid();
+    let b = backend::get();
+    let (child, remote) = block(nonblocking, timeout, || loop {
+        let (child, remote) = b.tcp_accept(inner)?;
+        if netns::check(owner, ipv4::PROTO_TCP, remote).is_ok() { return Ok((child, remote)); }
+        let _ = b.tcp_close(child);
+    })?;
+    let cap = SOCKETS.lock().entries.iter().find(|e| e.id == s.0).map(|e| e.cap.clone());
+    let valid = cap.ok_or("no such socket").and_then(|cap| {
+        capability::validate(owner, &cap, CapabilityType::Network, Permissions::READ | Permissions::WRITE).map(|_| cap)
+    });
+    match valid {
+        Ok(cap) => {
+            let child = add(owner, cap, SocketType::Stream, child, false);
+            set_nonblocking(child, nonblocking)?;
+            Ok((child, remote))
+        }
+        Err(e)  => { let _ = b.tcp_close(child); Err(e) }
+    }
+}
+
+/// Open a datagram socket bound to `local`; port 0 picks one.
+pub fn bind(cap: &Capability, local: SocketAddr) -> Result<Socket, &'static str> {
+    let b = backend::get();
+    open(cap, SocketType::Datagram, || b.udp_bind(local))
+}
+
+/// Whether calls fail with "would block" rather than wait.
+pub fn set_nonblocking(s: Socket, nonblocking: bool) -> Result<(), &'static str> {
+    let owner = crate::process::current_pid();
+    let mut sockets = SOCKETS.lock();
+    let e = sockets.entries.iter_mut().find(|e| e.id == s.0 && e.owner == owner).ok_or("no such socket")?;
+    e.nonblocking = nonblocking;
+    Ok(())
+}
+
+/// Bound how long calls on the socket wait; None waits indefinitely.
+pub fn set_timeout(s: Socket, timeout_ms: Option<u64>) -> Result<(), &'static str> {
+    let owner = crate::process::current_pid();
+    let mut sockets = SOCKETS.lock();
+    let e = sockets.entries.iter_mut().find(|e| e.id == s.0 && e.owner == owner).ok_or("no such socket")?;
+    e.timeout_ms = timeout_ms;
+    Ok(())
+}
+
+/// What a call on socket `s` would find.  Sockets of an app that is
+/// offline report an error.
+pub fn readiness(s: Socket) -> Result<Ready, &'static str> {
+    let owner = crate::process::current_pid();
+    let (ty, inner) = SOCKETS.lock().entries.iter().find(|e| e.id == s.0 && e.owner == owner)
+        .map(|e| (e.ty, e.inner)).ok_or("no such socket")?;
+    if !netns::online(owner) { return Ok(Ready::ERROR); }
+    let b = backend::get();
+    match ty {
+        SocketType::Stream   => b.tcp_ready(inner),
+        SocketType::Datagram => b.udp_ready(inner),
+        SocketType::Quic     => quic::readiness(QuicHandle(inner)),
+    }
+}
+
+/// Whether a stream or QUIC socket's connection is up.
+pub fn connected(s: Socket) -> Result<bool, &'static str> {
+    match lookup(s)? {
+        (SocketType::Stream, ..) if is_listening(s) => Ok(false),
+        (SocketType::Stream, inner, ..) => backend::get().tcp_connected(inner),
+        (SocketType::Quic, inner, ..)   => Ok(quic::state(QuicHandle(inner))? == QuicState::Established),
+        _ => Err("not a stream socket"),
+    }
+}
+
+/// Send on a stream socket; returns how much was taken.
+pub fn send(s: Socket, data: &[u8]) -> Result<usize, &'static str> {
+    let (SocketType::Stream, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a stream socket") };
+    if is_listening(s) { return Err("socket is listening"); }
+    let b = backend::get();
+    block(nonblocking, timeout, || { when_connected(inner)?; b.tcp_send(inner, data) })
+}
+
+/// Receive from a stream socket.  `Ok(0)` means the peer has closed its
+/// side.
+pub fn recv(s: Socket, buf: &mut [u8]) -> Result<usize, &'static str> {
+    let (SocketType::Stream, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a stream socket") };
+    if is_listening(s) { return Err("socket is listening"); }
+    let b = backend::get();
+    block(nonblocking, timeout, || { when_connected(inner)?; b.tcp_recv(inner, buf) })
+}
+
+/// Send one datagram to `to`.
+pub fn send_to(s: Socket, data: &[u8], to: SocketAddr) -> Result<usize, &'static str> {
+    let (SocketType::Datagram, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a datagram socket") };
+    let owner = crate::process::current_pid();
+    netns::check(owner, ipv4::PROTO_UDP, to)?;
+    if proxy::applies(owner, to.ip) { return Err("datagrams would bypass the proxy"); }
+    let b = backend::get();
+    block(nonblocking, timeout, || b.udp_send_to(inner, data, to))
+}
+
+/// Take the next datagram into `buf` (truncating it if `buf` is short),
+/// returning its length and sender.
+pub fn recv_from(s: Socket, buf: &mut [u8]) -> Result<(usize, SocketAddr), &'static str> {
+    let (SocketType::Datagram, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a datagram socket") };
+    let owner = crate::process::current_pid();
+    let b = backend::get();
+    block(nonblocking, timeout, || {
+        // Datagrams the app's firewall refuses are dropped here
+        loop {
+            let (n, from) = b.udp_recv_from(inner, buf)?;
+            if netns::check(owner, ipv4::PROTO_UDP, from).is_ok() { return Ok((n, from)); }
+        }
+    })
+}
+
+/// Open a QUIC connection to `remote`, authenticated as `host` and
+/// speaking one of the ALPN protocols `alpn`, waiting for the handshake.
+pub fn connect_quic(cap: &Capability, remote: SocketAddr, host: &str, alpn: &[&str]) -> Result<Socket, &'static str> {
+    if proxy::applies(crate::process::current_pid(), remote.ip) { return Err("QUIC would bypass the proxy"); }
+    let s = open(cap, SocketType::Quic, || quic::connect(remote, host, alpn).map(|h| h.0))?;
+    let inner = lookup(s)?.1;
+    let r = block(false, Some(CONNECT_TIMEOUT_MS), || when_established(inner));
+    if r.is_err() { let _ = close(s); }
+    r.map(|_| s)
+}
+
+/// Open a stream on a QUIC socket, bidirectional or send-only; waits
+/// while the server allows no more.
+pub fn open_stream(s: Socket, bidirectional: bool) -> Result<u64, &'static str> {
+    let (SocketType::Quic, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a QUIC socket") };
+    block(nonblocking, timeout, || quic::open_stream(QuicHandle(inner), bidirectional))
+}
+
+/// Take the next stream the server opened on a QUIC socket.
+pub fn accept_stream(s: Socket) -> Result<u64, &'static str> {
+    let (SocketType::Quic, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a QUIC socket") };
+    block(nonblocking, timeout, || quic::accept_stream(QuicHandle(inner)))
+}
+
+/// Send on a QUIC stream, finishing it after `data` if `fin`; returns how
+/// much was taken.
+pub fn stream_send(s: Socket, stream: u64, data: &[u8], fin: bool) -> Result<usize, &'static str> {
+    let (SocketType::Quic, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a QUIC socket") };
+    block(nonblocking, timeout, || quic::stream_send(QuicHandle(inner), stream, data, fin))
+}
+
+/// Receive from a QUIC stream.  `Ok(0)` means the server has finished it.
+pub fn stream_recv(s: Socket, stream: u64, buf: &mut [u8]) -> Result<usize, &'static str> {
+    let (SocketType::Quic, inner, nonblocking, timeout) = lookup(s)? else { return Err("not a QUIC socket") };
+    block(nonblocking, timeout, || quic::stream_recv(QuicHandle(inner), stream, buf))
+}
+
+/// Hand the current process's socket `s` to `to`, to be used under `cap`,
+/// one of `to`'s: how init passes a service the sockets it opened for it.
+pub fn give(s: Socket, to: ProcessId, cap: Capability) -> Result<(), &'static str> {
+    let owner = crate::process::current_pid();
+    capability::validate(to, &cap, CapabilityType::Network, Permissions::READ | Permissions::WRITE)?;
+    let mut sockets = SOCKETS.lock();
+    let e = sockets.entries.iter_mut().find(|e| e.id == s.0 && e.owner == owner).ok_or("no such socket")?;
+    e.owner = to;
+    e.cap = cap;
+    Ok(())
+}
+
+pub fn close(s: Socket) -> Result<(), &'static str> {
+    let owner = crate::process::current_pid();
+    let e = {
+        let mut sockets = SOCKETS.lock();
+        let i = sockets.entries.iter().position(|e| e.id == s.0 && e.owner == owner).ok_or("no such socket")?;
+        sockets.entries.remove(i)
+    };
+    let b = backend::get();
+    match e.ty {
+        SocketType::Stream   => b.tcp_close(e.inner),
+        SocketType::Datagram => b.udp_close(e.inner),
+        SocketType::Quic     => quic::close(QuicHandle(e.inner)),
+    }
+}
+
+pub fn sockets() -> Vec<SocketInfo> {
+    SOCKETS.lock().entries.iter().map(|e| SocketInfo { socket: Socket(e.id), owner: e.owner, ty: e.ty }).collect()
+}
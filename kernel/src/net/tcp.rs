@@ -0,0 +1,1093 @@
+//! TCP
+//! Connections per RFC 9293, with retransmission timeouts computed from
+//! measured round-trip times (RFC 6298) and NewReno congestion control
+//! (RFC 5681, RFC 6582): slow start, congestion avoidance, fast retransmit
+//! on three duplicate ACKs and fast recovery until everything outstanding
+//! at the loss is acknowledged.  A timeout collapses the window to one
+//! segment and resends from the oldest unacknowledged byte.
+//!
+//! Segments are built under the connection table lock and sent once it is
+//! released.  The table is locked before the interfaces (routes and MSS
+//! lookups happen under it), never the other way round.
+//!
+//! A connection belongs to the process that opened it.  Its app's
+//! namespace vets it when it opens, and going offline cuts it off.
+//!
+//! A listener stays listening: each SYN it takes starts a connection of
+//! its own, which waits in the listener's accept queue once its handshake
+//! is done.  The backlog bounds the connections a listener holds that the
+//! app has not accepted, half-open and waiting alike; a SYN beyond it is
+//! dropped, so the peer retries later.
+//!
+//! Not implemented: window scaling, SACK, timestamps, delayed ACKs and
+//! urgent data.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::icmp::IcmpError;
+use super::{accounting, ipv4, netns, transport_checksum, IpAddr, Ipv4Addr, SocketAddr};
+use crate::entropy;
+use crate::event::Ready;
+use crate::process::{ProcessId, WaitQueue};
+
+const FIN: u8 = 0x01;
+const SYN: u8 = 0x02;
+const RST: u8 = 0x04;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+const HEADER_LEN:  usize = 20;
+const SEND_BUF:    usize = 64 * 1024;
+/// The largest window a header can advertise without window scaling.
+const RECV_BUF:    usize = 65535;
+/// MSS assumed when the peer's SYN carries none.
+const DEFAULT_MSS: usize = 536;
+/// Out-of-order segments held per connection.
+const OOO_MAX:     usize = 64;
+
+const RTO_INITIAL: u64 = 1000;
+const RTO_MIN:     u64 = 1000;
+const RTO_MAX:     u64 = 60_000;
+/// Timer granularity: timeouts are checked once per timer tick.
+const CLOCK_G:     u64 = 1000;
+/// Timeouts in a row before the connection is given up.
+const MAX_RETRIES: u32 = 12;
+/// SYN-ACKs sent again before a half-open connection is, so SYNs that
+/// are never completed do not hold a backlog for long.
+const SYNACK_RETRIES: u32 = 5;
+/// Maximum segment lifetime; TIME-WAIT lasts twice this.
+const MSL:         u64 = 30_000;
+const DUP_ACK_THRESHOLD: u32 = 3;
+const EPHEMERAL_FIRST:   u16 = 49152;
+/// Largest backlog a listener may ask for.
+pub const MAX_BACKLOG:   usize = 128;
+
+// ─── sequence numbers ─────────────────────────────────────────────────────────
+
+fn seq_lt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+fn seq_le(a: u32, b: u32) -> bool {
+    !seq_lt(b, a)
+}
+
+fn seq_gt(a: u32, b: u32) -> bool {
+    seq_lt(b, a)
+}
+
+// ─── segments ─────────────────────────────────────────────────────────────────
+
+struct Segment {
+    src:   SocketAddr,
+    dst:   SocketAddr,
+    seq:   u32,
+    ack:   u32,
+    flags: u8,
+    wnd:   u16,
+    /// MSS option, sent on SYNs.
+    mss:   Option<u16>,
+    data:  Vec<u8>,
+    /// Whose traffic it is, for accounting; None for resets we answer
+    /// strays with.
+    owner: Option<ProcessId>,
+}
+
+impl Segment {
+    fn encode(&self) -> Vec<u8> {
+        let opt = if self.mss.is_some() { 4 } else { 0 };
+        let mut s = Vec::with_capacity(HEADER_LEN + opt + self.data.len());
+        s.extend_from_slice(&self.src.port.to_be_bytes());
+        s.extend_from_slice(&self.dst.port.to_be_bytes());
+        s.extend_from_slice(&self.seq.to_be_bytes());
+        s.extend_from_slice(&self.ack.to_be_bytes());
+        s.extend_from_slice(&[(((HEADER_LEN + opt) / 4) << 4) as u8, self.flags]);
+        s.extend_from_slice(&self.wnd.to_be_bytes());
+        s.extend_from_slice(&[0, 0, 0, 0]);
+        if let Some(mss) = self.mss {
+            s.extend_from_slice(&[2, 4]);
+            s.extend_from_slice(&mss.to_be_bytes());
+        }
+        s.extend_from_slice(&self.data);
+        let sum = transport_checksum(self.src.ip, self.dst.ip, ipv4::PROTO_TCP, &s);
+        s[16..18].copy_from_slice(&sum.to_be_bytes());
+        s
+    }
+}
+
+/// A received segment.
+struct Parsed<'a> {
+    src:   SocketAddr,
+    dst:   SocketAddr,
+    seq:   u32,
+    ack:   u32,
+    flags: u8,
+    wnd:   u32,
+    mss:   Option<usize>,
+    data:  &'a [u8],
+}
+
+impl Parsed<'_> {
+    fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Sequence space the segment occupies.
+    fn len(&self) -> u32 {
+        self.data.len() as u32 + self.has(SYN) as u32 + self.has(FIN) as u32
+    }
+}
+
+fn parse(src: IpAddr, dst: IpAddr, seg: &[u8]) -> Option<Parsed<'_>> {
+    if seg.len() < HEADER_LEN || transport_checksum(src, dst, ipv4::PROTO_TCP, seg) != 0 { return None; }
+    let off = (seg[12] >> 4) as usize * 4;
+    if off < HEADER_LEN || off > seg.len() { return None; }
+    let mut mss = None;
+    let mut opts = &seg[HEADER_LEN..off];
+    while let [kind, rest @ ..] = opts {
+        match kind {
+            0 => break,
+            1 => { opts = rest; continue; }
+            _ => {}
+        }
+        let len = *rest.first()? as usize;
+        if len < 2 || len > opts.len() { return None; }
+        if *kind == 2 && len == 4 { mss = Some(u16::from_be_bytes([opts[2], opts[3]]) as usize); }
+        opts = &opts[len..];
+    }
+    let be32 = |i: usize| u32::from_be_bytes([seg[i], seg[i + 1], seg[i + 2], seg[i + 3]]);
+    Some(Parsed {
+        src:   SocketAddr { ip: src, port: u16::from_be_bytes([seg[0], seg[1]]) },
+        dst:   SocketAddr { ip: dst, port: u16::from_be_bytes([seg[2], seg[3]]) },
+        seq:   be32(4),
+        ack:   be32(8),
+        flags: seg[13],
+        wnd:   u16::from_be_bytes([seg[14], seg[15]]) as u32,
+        mss,
+        data:  &seg[off..],
+    })
+}
+
+/// The RST answering `seg`, which matched no connection.
+fn reset_for(seg: &Parsed) -> Segment {
+    let (seq, ack, flags) = if seg.has(ACK) { (seg.ack, 0, RST) } else { (0, seg.seq.wrapping_add(seg.len()), RST | ACK) };
+    Segment { src: seg.dst, dst: seg.src, seq, ack, flags, wnd: 0, mss: None, data: Vec::new(), owner: None }
+}
+
+/// MSS to offer a peer at `remote`: what fits the route's MTU.
+fn local_mss(remote: IpAddr) -> usize {
+    super::max_payload(remote).map_or(DEFAULT_MSS, |p| p - HEADER_LEN).min(u16::MAX as usize)
+}
+
+/// Initial congestion window (RFC 5681 §3.1).
+fn initial_window(mss: usize) -> usize {
+    (4 * mss).min((2 * mss).max(4380))
+}
+
+// ─── connections ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    CloseWait,
+    Closing,
+    LastAck,
+    TimeWait,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TcpHandle(pub u32);
+
+/// Transmission control block.
+struct Tcb {
+    id:           u32,
+    owner:        ProcessId,
+    state:        TcpState,
+    local:        SocketAddr,
+    remote:       SocketAddr,
+    iss:          u32,
+    snd_una:      u32,
+    snd_nxt:      u32,
+    /// Highest sequence number sent; `snd_nxt` falls back to `snd_una`
+    /// after a timeout.
+    snd_max:      u32,
+    snd_wnd:      u32,
+    snd_wl1:      u32,
+    snd_wl2:      u32,
+    rcv_nxt:      u32,
+    /// Bytes from `snd_una` on: unacknowledged, then unsent.
+    send_buf:     VecDeque<u8>,
+    recv_buf:     VecDeque<u8>,
+    /// Segments that arrived ahead of `rcv_nxt`, by sequence number.
+    ooo:          BTreeMap<u32, Vec<u8>>,
+    mss:          usize,
+    /// The application has closed; FIN follows the last queued byte.
+    fin_queued:   bool,
+    /// Sequence number of our FIN, once sent.
+    fin_seq:      Option<u32>,
+    fin_received: bool,
+    /// The application is done with the handle; drop the TCB once Closed.
+    user_closed:  bool,
+    /// The listener this connection is half-open on; cleared once the
+    /// handshake is done and it joins the listener's accept queue.
+    parent:       Option<u32>,
+    /// On a listener: the most connections it holds unaccepted.
+    backlog:      usize,
+    /// On a listener: established connections, oldest first.
+    accept_queue: VecDeque<u32>,
+    error:        Option<&'static str>,
+    /// The last ICMP error about the connection, reported if it times out.
+    soft_error:   Option<&'static str>,
+    srtt:         Option<u64>,
+    rttvar:       u64,
+    rto:          u64,
+    /// Sequence number whose acknowledgement times a round trip, and when
+    /// it was sent.  Retransmissions clear it (Karn's algorithm).
+    rtt_probe:    Option<(u32, u64)>,
+    /// When the retransmission (or persist) timer fires.
+    deadline:     Option<u64>,
+    retries:      u32,
+    retransmits:  u64,
+    cwnd:         usize,
+    ssthresh:     usize,
+    dup_acks:     u32,
+    /// `snd_max` when loss recovery began; recovery ends once it is acked.
+    recover:      u32,
+    in_recovery:  bool,
+    time_wait_until: u64,
+}
+
+impl Tcb {
+    fn new(id: u32, owner: ProcessId, local: SocketAddr, remote: SocketAddr, state: TcpState) -> Tcb {
+        let iss = entropy::next_u32();
+        Tcb {
+            id, owner, state, local, remote, iss,
+            snd_una: iss, snd_nxt: iss, snd_max: iss, snd_wnd: 0, snd_wl1: 0, snd_wl2: 0, rcv_nxt: 0,
+            send_buf: VecDeque::new(), recv_buf: VecDeque::new(), ooo: BTreeMap::new(), mss: DEFAULT_MSS,
+            fin_queued: false, fin_seq: None, fin_received: false, user_closed: false,
+            parent: None, backlog: 0, accept_queue: VecDeque::new(), error: None, soft_error: None,
+            srtt: None, rttvar: 0, rto: RTO_INITIAL, rtt_probe: None, deadline: None, retries: 0, retransmits: 0,
+            cwnd: initial_window(DEFAULT_MSS), ssthresh: usize::MAX, dup_acks: 0, recover: iss, in_recovery: false,
+            time_wait_until: 0,
+        }
+    }
+
+    fn listening(id: u32, owner: ProcessId, port: u16, backlog: usize) -> Tcb {
+        let any = SocketAddr::new(Ipv4Addr::UNSPECIFIED, 0);
+        let mut c = Tcb::new(id, owner, SocketAddr::new(Ipv4Addr::UNSPECIFIED, port), any, TcpState::Listen);
+        c.backlog = backlog;
+        c
+    }
+
+    fn synchronized(&self) -> bool {
+        !matches!(self.state, TcpState::Closed | TcpState::Listen | TcpState::SynSent | TcpState::SynReceived)
+    }
+
+    fn set_mss(&mut self, peer: Option<usize>) {
+        self.mss = peer.unwrap_or(DEFAULT_MSS).min(local_mss(self.remote.ip)).max(1);
+        self.cwnd = initial_window(self.mss);
+    }
+
+    fn rcv_wnd(&self) -> u32 {
+        (RECV_BUF - self.recv_buf.len()) as u32
+    }
+
+    /// Sequence space sent and not yet acknowledged, as of `snd_nxt`.
+    fn flight(&self) -> usize {
+        self.snd_nxt.wrapping_sub(self.snd_una) as usize
+    }
+
+    fn fin_acked(&self) -> bool {
+        self.fin_seq.is_some_and(|f| seq_lt(f, self.snd_una))
+    }
+
+    fn segment(&self, seq: u32, flags: u8, data: Vec<u8>) -> Segment {
+        Segment {
+            src: self.local, dst: self.remote, seq, ack: self.rcv_nxt, flags: flags | ACK,
+            wnd: self.rcv_wnd() as u16, mss: None, data, owner: Some(self.owner),
+        }
+    }
+
+    fn ack(&self) -> Segment {
+        self.segment(self.snd_nxt, 0, Vec::new())
+    }
+
+    /// Our SYN, or SYN-ACK in SYN-RECEIVED.
+    fn syn(&self) -> Segment {
+        let flags = if self.state == TcpState::SynReceived { SYN | ACK } else { SYN };
+        Segment {
+            src: self.local, dst: self.remote, seq: self.iss, ack: self.rcv_nxt, flags,
+            wnd: self.rcv_wnd() as u16, mss: Some(local_mss(self.remote.ip) as u16), data: Vec::new(),
+            owner: Some(self.owner),
+        }
+    }
+
+    /// `n` more bytes of sequence space went out at `snd_nxt`.
+    fn advance(&mut self, n: u32, now: u64) {
+        let seq = self.snd_nxt;
+        self.snd_nxt = seq.wrapping_add(n);
+        if seq_gt(self.snd_nxt, self.snd_max) {
+            // Only new data is timed; a resend's ACK is ambiguous
+            if self.rtt_probe.is_none() && seq == self.snd_max { self.rtt_probe = Some((self.snd_nxt, now)); }
+            self.snd_max = self.snd_nxt;
+        }
+        if self.deadline.is_none() { self.deadline = Some(now + self.rto); }
+    }
+
+    /// Send whatever the congestion and peer windows allow, then the FIN
+    /// once the application has closed and everything queued is out.
+    fn push(&mut self, now: u64, out: &mut Vec<Segment>) {
+        use TcpState::*;
+        if !matches!(self.state, Established | CloseWait | FinWait1 | Closing | LastAck) { return; }
+        while !self.fin_seq.is_some_and(|f| seq_lt(f, self.snd_nxt)) {
+            let sent = self.flight();
+            let unsent = self.send_buf.len() - sent;
+            if unsent == 0 {
+                if self.fin_queued {
+                    out.push(self.segment(self.snd_nxt, FIN, Vec::new()));
+                    self.fin_seq.get_or_insert(self.snd_nxt);
+                    self.advance(1, now);
+                    self.state = match self.state { Established => FinWait1, CloseWait => LastAck, s => s };
+                }
+                break;
+            }
+            let window = self.cwnd.min(self.snd_wnd as usize);
+            let n = unsent.min(self.mss).min(window.saturating_sub(sent));
+            if n == 0 {
+                // Zero window with nothing outstanding: the persist timer probes it
+                if sent == 0 && self.deadline.is_none() { self.deadline = Some(now + self.rto); }
+                break;
+            }
+            let data = self.send_buf.range(sent..sent + n).copied().collect();
+            out.push(self.segment(self.snd_nxt, PSH, data));
+            self.advance(n as u32, now);
+        }
+    }
+
+    /// Resend the oldest unacknowledged segment.
+    fn retransmit_first(&mut self, out: &mut Vec<Segment>) {
+        self.rtt_probe = None;
+        self.retransmits += 1;
+        if matches!(self.state, TcpState::SynSent | TcpState::SynReceived) {
+            out.push(self.syn());
+            return;
+        }
+        let sent = self.snd_max.wrapping_sub(self.snd_una) as usize;
+        let n = self.send_buf.len().min(sent).min(self.mss);
+        if n > 0 {
+            let data = self.send_buf.range(..n).copied().collect();
+            out.push(self.segment(self.snd_una, PSH, data));
+        } else if self.fin_seq.is_some() {
+            out.push(self.segment(self.snd_una, FIN, Vec::new()));
+        }
+    }
+
+    /// Fold a round-trip measurement into the RTO (RFC 6298 §2).
+    fn rtt_sample(&mut self, r: u64) {
+        let srtt = match self.srtt {
+            None    => { self.rttvar = r / 2; r }
+            Some(s) => { self.rttvar = (3 * self.rttvar + s.abs_diff(r)) / 4; (7 * s + r) / 8 }
+        };
+        self.srtt = Some(srtt);
+        self.rto = (srtt + CLOCK_G.max(4 * self.rttvar)).clamp(RTO_MIN, RTO_MAX);
+    }
+
+    /// Our SYN has been acknowledged.
+    fn syn_acked(&mut self, seg: &Parsed, now: u64) {
+        self.snd_una = seg.ack;
+        if let Some((_, sent)) = self.rtt_probe.take() { self.rtt_sample(now - sent); }
+        self.retries = 0;
+        self.deadline = None;
+        self.snd_wnd = seg.wnd;
+        self.snd_wl1 = seg.seq;
+        self.snd_wl2 = seg.ack;
+        self.state = TcpState::Established;
+    }
+
+    /// Process the ACK field.  Returns false if the segment should be
+    /// dropped.
+    fn on_ack(&mut self, seg: &Parsed, now: u64, out: &mut Vec<Segment>) -> bool {
+        let ack = seg.ack;
+        if seq_gt(ack, self.snd_max) {
+            out.push(self.ack());
+            return false;
+        }
+        if seq_gt(ack, self.snd_una) {
+            let acked = ack.wrapping_sub(self.snd_una) as usize;
+            let data = acked.min(self.send_buf.len());
+            self.send_buf.drain(..data);
+            self.snd_una = ack;
+            if seq_gt(ack, self.snd_nxt) { self.snd_nxt = ack; }
+            if let Some((probe, sent)) = self.rtt_probe {
+                if seq_le(probe, ack) {
+                    self.rtt_probe = None;
+                    self.rtt_sample(now - sent);
+                }
+            }
+            self.retries = 0;
+            self.dup_acks = 0;
+            self.soft_error = None;
+            if self.in_recovery {
+                if seq_le(self.recover, ack) {
+                    // Full acknowledgement: deflate and leave recovery
+                    self.cwnd = self.ssthresh;
+                    self.in_recovery = false;
+                } else {
+                    // Partial: the next hole is lost too
+                    self.retransmit_first(out);
+                    self.cwnd = self.cwnd.saturating_sub(acked) + self.mss;
+                }
+            } else if self.cwnd < self.ssthresh {
+                self.cwnd += acked.min(self.mss);
+            } else {
+                self.cwnd += (self.mss * self.mss / self.cwnd).max(1);
+            }
+            self.deadline = (self.snd_una != self.snd_max).then_some(now + self.rto);
+        } else if ack == self.snd_una && seg.data.is_empty() && !seg.has(FIN) && seg.wnd == self.snd_wnd
+            && self.snd_una != self.snd_max
+        {
+            self.dup_acks += 1;
+            if self.in_recovery {
+                self.cwnd += self.mss;
+            } else if self.dup_acks == DUP_ACK_THRESHOLD && seq_gt(ack, self.recover) {
+                let flight = self.snd_max.wrapping_sub(self.snd_una) as usize;
+                self.ssthresh = (flight / 2).max(2 * self.mss);
+                self.recover = self.snd_max;
+                self.in_recovery = true;
+                self.retransmit_first(out);
+                self.cwnd = self.ssthresh + 3 * self.mss;
+            }
+        }
+        if seq_lt(self.snd_wl1, seg.seq) || (self.snd_wl1 == seg.seq && seq_le(self.snd_wl2, ack)) {
+            self.snd_wnd = seg.wnd;
+            self.snd_wl1 = seg.seq;
+            self.snd_wl2 = ack;
+        }
+        true
+    }
+
+    /// Whether `seg` falls in the receive window (RFC 9293 §3.10.7.4).
+    fn acceptable(&self, seq: u32, len: u32) -> bool {
+        let wnd = self.rcv_wnd();
+        let in_window = |s: u32| seq_le(self.rcv_nxt, s) && seq_lt(s, self.rcv_nxt.wrapping_add(wnd));
+        match (len, wnd) {
+            (0, 0) => seq == self.rcv_nxt,
+            (0, _) => in_window(seq),
+            (_, 0) => false,
+            _      => in_window(seq) || in_window(seq.wrapping_add(len - 1)),
+        }
+    }
+
+    /// Take in segment data, queueing it if it arrived early.
+    fn receive(&mut self, mut seq: u32, mut data: &[u8]) {
+        let dup = self.rcv_nxt.wrapping_sub(seq) as i32;
+        if dup > 0 {
+            if dup as usize >= data.len() { return; }
+            data = &data[dup as usize..];
+            seq = self.rcv_nxt;
+        }
+        let room = RECV_BUF - self.recv_buf.len();
+        if seq != self.rcv_nxt {
+            let offset = seq.wrapping_sub(self.rcv_nxt) as usize;
+            let longer = self.ooo.get(&seq).is_none_or(|d| d.len() < data.len());
+            if offset + data.len() <= room && longer && (self.ooo.len() < OOO_MAX || self.ooo.contains_key(&seq)) {
+                self.ooo.insert(seq, data.to_vec());
+            }
+            return;
+        }
+        let n = data.len().min(room);
+        self.recv_buf.extend(&data[..n]);
+        self.rcv_nxt = self.rcv_nxt.wrapping_add(n as u32);
+        // Anything queued that now touches rcv_nxt joins the stream
+        while let Some(s) = self.ooo.keys().copied().find(|s| seq_le(*s, self.rcv_nxt)) {
+            let d = self.ooo.remove(&s).unwrap_or_default();
+            let skip = self.rcv_nxt.wrapping_sub(s) as usize;
+            if skip >= d.len() { continue; }
+            let n = (d.len() - skip).min(RECV_BUF - self.recv_buf.len());
+            self.recv_buf.extend(&d[skip..skip + n]);
+            self.rcv_nxt = self.rcv_nxt.wrapping_add(n as u32);
+        }
+    }
+
+    fn enter_time_wait(&mut self, now: u64) {
+        self.state = TcpState::TimeWait;
+        self.time_wait_until = now + 2 * MSL;
+        self.deadline = None;
+    }
+
+    fn reset(&mut self, error: &'static str) {
+        self.state = TcpState::Closed;
+        self.error = Some(error);
+        self.send_buf.clear();
+        self.ooo.clear();
+        self.deadline = None;
+    }
+
+    fn syn_sent_arrives(&mut self, seg: &Parsed, now: u64, out: &mut Vec<Segment>) {
+        let ack_ok = seg.has(ACK) && seq_lt(self.iss, seg.ack) && seq_le(seg.ack, self.snd_max);
+        if seg.has(ACK) && !ack_ok {
+            if !seg.has(RST) { out.push(reset_for(seg)); }
+            return;
+        }
+        if seg.has(RST) {
+            if ack_ok { self.reset("connection refused"); }
+            return;
+        }
+        if !seg.has(SYN) { return; }
+        self.rcv_nxt = seg.seq.wrapping_add(1);
+        self.set_mss(seg.mss);
+        if ack_ok {
+            self.syn_acked(seg, now);
+            out.push(self.ack());
+            self.push(now, out);
+        } else {
+            // Simultaneous open
+            self.state = TcpState::SynReceived;
+            self.snd_wnd = seg.wnd;
+            out.push(self.syn());
+        }
+    }
+
+    fn segment_arrives(&mut self, seg: &Parsed, now: u64, out: &mut Vec<Segment>) {
+        use TcpState::*;
+        match self.state {
+            // The table answers for listeners (`Tcp::listen_arrives`)
+            Listen  => return,
+            SynSent => return self.syn_sent_arrives(seg, now, out),
+            _       => {}
+        }
+        // With a closed window only the ACK of an in-sequence segment counts
+        let zero_window = self.rcv_wnd() == 0 && seg.seq == self.rcv_nxt;
+        let len = if zero_window { 0 } else { seg.len() };
+        if !self.acceptable(seg.seq, len) {
+            if !seg.has(RST) { out.push(self.ack()); }
+            return;
+        }
+        if seg.has(RST) {
+            match self.state {
+                // A half-open child just goes; its listener carries on
+                SynReceived if self.parent.is_some() => self.state = Closed,
+                Closing | LastAck | TimeWait => self.state = Closed,
+                _ => self.reset("connection reset"),
+            }
+            return;
+        }
+        if seg.has(SYN) {
+            // A SYN on a synchronized connection gets a challenge ACK (RFC 5961)
+            out.push(self.ack());
+            return;
+        }
+        if !seg.has(ACK) { return; }
+        if self.state == SynReceived {
+            if !(seq_lt(self.snd_una, seg.ack) && seq_le(seg.ack, self.snd_max)) {
+                out.push(reset_for(seg));
+                return;
+            }
+            self.syn_acked(seg, now);
+        }
+        if !self.on_ack(seg, now, out) { return; }
+        if self.fin_acked() {
+            match self.state {
+                FinWait1 => self.state = FinWait2,
+                Closing  => self.enter_time_wait(now),
+                LastAck  => { self.state = Closed; return; }
+                _        => {}
+            }
+        }
+
+        let mut need_ack = false;
+        if !zero_window && !seg.data.is_empty() && matches!(self.state, Established | FinWait1 | FinWait2) {
+            self.receive(seg.seq, seg.data);
+            need_ack = true;
+        }
+        let fin_seq = seg.seq.wrapping_add(seg.data.len() as u32);
+        if seg.has(FIN) && !zero_window && (fin_seq == self.rcv_nxt || self.state == TimeWait) {
+            if self.state != TimeWait {
+                self.rcv_nxt = self.rcv_nxt.wrapping_add(1);
+                self.fin_received = true;
+            }
+            need_ack = true;
+            match self.state {
+                Established => self.state = CloseWait,
+                // Our FIN is unacknowledged, or we would be in FIN-WAIT-2
+                FinWait1    => self.state = Closing,
+                FinWait2 | TimeWait => self.enter_time_wait(now),
+                _ => {}
+            }
+        }
+        if need_ack { out.push(self.ack()); }
+        self.push(now, out);
+    }
+
+    /// The retransmission timer fired.
+    fn on_timeout(&mut self, now: u64, out: &mut Vec<Segment>) {
+        self.retries += 1;
+        let limit = if self.parent.is_some() { SYNACK_RETRIES } else { MAX_RETRIES };
+        if self.retries > limit {
+            self.reset(self.soft_error.unwrap_or("connection timed out"));
+            return;
+        }
+        self.rto = (self.rto * 2).min(RTO_MAX);
+        self.deadline = Some(now + self.rto);
+        if !self.synchronized() {
+            self.retransmit_first(out);
+            return;
+        }
+        // Loss window, then go back to the oldest unacknowledged byte
+        let flight = self.snd_max.wrapping_sub(self.snd_una) as usize;
+        if flight > 0 { self.ssthresh = (flight / 2).max(2 * self.mss); }
+        self.cwnd = self.mss;
+        self.in_recovery = false;
+        self.dup_acks = 0;
+        self.recover = self.snd_max;
+        self.snd_nxt = self.snd_una;
+        self.rtt_probe = None;
+        let before = out.len();
+        self.push(now, out);
+        if out.len() > before { return; }
+        if flight > 0 {
+            self.retransmit_first(out);
+        } else if let Some(&byte) = self.send_buf.front() {
+            // Persist: probe the peer's zero window with one byte
+            out.push(self.segment(self.snd_nxt, 0, alloc::vec![byte]));
+            self.advance(1, now);
+        }
+    }
+
+    fn info(&self) -> TcpInfo {
+        TcpInfo {
+            handle: TcpHandle(self.id), owner: self.owner, local: self.local, remote: self.remote, state: self.state,
+            cwnd: self.cwnd, srtt_ms: self.srtt, rto_ms: self.rto,
+            unacked: self.snd_max.wrapping_sub(self.snd_una) as usize, retransmits: self.retransmits,
+        }
+    }
+}
+
+/// A connection as reported to callers.
+#[derive(Debug, Clone)]
+pub struct TcpInfo {
+    pub handle:      TcpHandle,
+    pub owner:       ProcessId,
+    pub local:       SocketAddr,
+    pub remote:      SocketAddr,
+    pub state:       TcpState,
+    pub cwnd:        usize,
+    pub srtt_ms:     Option<u64>,
+    pub rto_ms:      u64,
+    pub unacked:     usize,
+    pub retransmits: u64,
+}
+
+// ─── connection table ─────────────────────────────────────────────────────────
+
+struct Tcp {
+    conns:     Vec<Tcb>,
+    next_id:   u32,
+    next_port: u16,
+}
+
+impl Tcp {
+    /// The current process's connection `h`.
+    fn get(&mut self, h: TcpHandle) -> Result<&mut Tcb, &'static str> {
+        let owner = crate::process::current_pid();
+        self.conns.iter_mut().find(|c| c.id == h.0 && c.owner == owner && !c.user_closed).ok_or("no such connection")
+    }
+
+    fn alloc_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        id
+    }
+
+    fn ephemeral_port(&mut self) -> Result<u16, &'static str> {
+        for _ in EPHEMERAL_FIRST..=u16::MAX {
+            let port = self.next_port;
+            self.next_port = if port == u16::MAX { EPHEMERAL_FIRST } else { port + 1 };
+            if !self.conns.iter().any(|c| c.local.port == port) { return Ok(port); }
+        }
+        Err("out of ephemeral ports")
+    }
+
+    /// Drop connections that are closed and no longer referenced: those
+    /// the app closed, and half-open ones no app ever saw.
+    fn reap(&mut self) {
+        self.conns.retain(|c| !(c.state == TcpState::Closed && (c.user_closed || c.parent.is_some())));
+    }
+
+    /// A segment for listener `i`: a SYN starts a connection of its own,
+    /// if the backlog has room.
+    fn listen_arrives(&mut self, i: usize, seg: &Parsed, now: u64, out: &mut Vec<Segment>) {
+        if seg.has(RST) { return; }
+        if seg.has(ACK) { out.push(reset_for(seg)); return; }
+        if !seg.has(SYN) { return; }
+        let (listener, owner) = (self.conns[i].id, self.conns[i].owner);
+        let half_open = self.conns.iter().filter(|c| c.parent == Some(listener)).count();
+        if half_open + self.conns[i].accept_queue.len() >= self.conns[i].backlog { return; }
+        let id = self.alloc_id();
+        let mut c = Tcb::new(id, owner, seg.dst, seg.src, TcpState::SynReceived);
+        c.parent = Some(listener);
+        c.rcv_nxt = seg.seq.wrapping_add(1);
+        c.snd_wnd = seg.wnd;
+        c.set_mss(seg.mss);
+        out.push(c.syn());
+        c.advance(1, now);
+        self.conns.push(c);
+    }
+
+    /// Move connection `i` to its listener's accept queue if its
+    /// handshake is done.
+    fn handshake_done(&mut self, i: usize, out: &mut Vec<Segment>) {
+        let c = &self.conns[i];
+        let (Some(listener), true) = (c.parent, c.synchronized()) else { return };
+        let id = c.id;
+        self.conns[i].parent = None;
+        match self.conns.iter_mut().find(|l| l.id == listener && l.state == TcpState::Listen) {
+            Some(l) => l.accept_queue.push_back(id),
+            None    => {
+                let c = &mut self.conns[i];
+                out.push(c.segment(c.snd_nxt, RST, Vec::new()));
+                c.reset("listener closed");
+                c.user_closed = true;
+            }
+        }
+    }
+
+    /// A listener is going: reset the connections it holds unaccepted.
+    fn drop_backlog(&mut self, listener: u32, out: &mut Vec<Segment>) {
+        let queued = self.conns.iter().find(|l| l.id == listener).map(|l| l.accept_queue.clone()).unwrap_or_default();
+        for c in self.conns.iter_mut().filter(|c| c.parent == Some(listener) || queued.contains(&c.id)) {
+            if c.state != TcpState::Closed { out.push(c.segment(c.snd_nxt, RST, Vec::new())); }
+            c.user_closed = true;
+            c.state = TcpState::Closed;
+        }
+    }
+}
+
+static TCP: Mutex<Tcp> = Mutex::new(Tcp { conns: Vec::new(), next_id: 1, next_port: EPHEMERAL_FIRST });
+/// Woken whenever segments arrive or timers run, for the blocking calls.
+static EVENTS: WaitQueue = WaitQueue::new();
+
+fn transmit(out: Vec<Segment>) {
+    for seg in out {
+        let bytes = seg.encode();
+        if super::send(Some(seg.src.ip), seg.dst.ip, ipv4::PROTO_TCP, &bytes).is_err() { continue; }
+        if let Some(owner) = seg.owner {
+            accounting::sent(owner, seg.src.ip, seg.dst.ip, super::header_len(seg.dst.ip) + bytes.len());
+        }
+    }
+}
+
+fn now() -> u64 {
+    crate::arch::uptime_millis()
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Open a connection to `remote`, owned by the current process.  Returns
+/// at once; the handle reaches `Established` when the handshake completes.
+pub fn connect(remote: SocketAddr) -> Result<TcpHandle, &'static str> {
+    let owner = crate::process::current_pid();
+    netns::check(owner, ipv4::PROTO_TCP, remote)?;
+    let src = netns::source(owner, remote.ip, Ipv4Addr::UNSPECIFIED.into())?;
+    let now = now();
+    let mut out = Vec::new();
+    let handle = {
+        let mut tcp = TCP.lock();
+        let port = tcp.ephemeral_port()?;
+        let id = tcp.alloc_id();
+        let mut c = Tcb::new(id, owner, SocketAddr { ip: src, port }, remote, TcpState::SynSent);
+        c.set_mss(None);
+        out.push(c.syn());
+        c.advance(1, now);
+        tcp.conns.push(c);
+        TcpHandle(id)
+    };
+    transmit(out);
+    Ok(handle)
+}
+
+/// Listen for connections on `port`, on any local address, for the
+/// current process, holding up to `backlog` (at most `MAX_BACKLOG`) of
+/// them unaccepted.
+pub fn listen(port: u16, backlog: usize) -> Result<TcpHandle, &'static str> {
+    if port == 0 || port >= EPHEMERAL_FIRST { return Err("port not available for listening"); }
+    let mut tcp = TCP.lock();
+    if tcp.conns.iter().any(|c| c.local.port == port && c.state == TcpState::Listen) {
+        return Err("address in use");
+    }
+    let id = tcp.alloc_id();
+    tcp.conns.push(Tcb::listening(id, crate::process::current_pid(), port, backlog.clamp(1, MAX_BACKLOG)));
+    Ok(TcpHandle(id))
+}
+
+/// Take the oldest established connection from listener `h`, with the
+/// peer's address.  The connection belongs to the listener's owner.
+pub fn accept(h: TcpHandle) -> Result<(TcpHandle, SocketAddr), &'static str> {
+    let mut tcp = TCP.lock();
+    let l = tcp.get(h)?;
+    if l.state != TcpState::Listen { return Err("not listening"); }
+    let id = l.accept_queue.pop_front().ok_or("would block")?;
+    let remote = tcp.conns.iter().find(|c| c.id == id).map(|c| c.remote).ok_or("connection lost")?;
+    Ok((TcpHandle(id), remote))
+}
+
+/// Queue `data` for sending; returns how much was taken.
+pub fn send(h: TcpHandle, data: &[u8]) -> Result<usize, &'static str> {
+    use TcpState::*;
+    let mut out = Vec::new();
+    let n = {
+        let mut tcp = TCP.lock();
+        let c = tcp.get(h)?;
+        match c.state {
+            Closed => return Err(c.error.unwrap_or("not connected")),
+            Listen => return Err("not connected"),
+            SynSent | SynReceived | Established | CloseWait if !c.fin_queued => {}
+            _      => return Err("connection closing"),
+        }
+        let n = data.len().min(SEND_BUF - c.send_buf.len());
+        if n == 0 && !data.is_empty() { return Err("would block"); }
+        c.send_buf.extend(&data[..n]);
+        c.push(now(), &mut out);
+        n
+    };
+    transmit(out);
+    Ok(n)
+}
+
+/// Read received data.  `Ok(0)` means the peer has closed its side.
+pub fn recv(h: TcpHandle, buf: &mut [u8]) -> Result<usize, &'static str> {
+    let mut out = Vec::new();
+    let n = {
+        let mut tcp = TCP.lock();
+        let c = tcp.get(h)?;
+        if c.recv_buf.is_empty() {
+            return if c.fin_received { Ok(0) } else if let Some(e) = c.error { Err(e) }
+                else if matches!(c.state, TcpState::Closed | TcpState::Listen) { Err("not connected") }
+                else { Err("would block") };
+        }
+        let before = c.rcv_wnd() as usize;
+        let n = buf.len().min(c.recv_buf.len());
+        for (d, s) in buf.iter_mut().zip(c.recv_buf.drain(..n)) { *d = s; }
+        // Tell a peer stalled on a small window that it has opened
+        if before < c.mss && c.rcv_wnd() as usize >= c.mss && c.synchronized() { out.push(c.ack()); }
+        n
+    };
+    transmit(out);
+    Ok(n)
+}
+
+/// Close the connection gracefully: queued data is still delivered,
+/// followed by a FIN.  The handle is invalid afterwards.
+pub fn close(h: TcpHandle) -> Result<(), &'static str> {
+    use TcpState::*;
+    let mut out = Vec::new();
+    {
+        let mut tcp = TCP.lock();
+        let c = tcp.get(h)?;
+        c.user_closed = true;
+        let listener = (c.state == Listen).then_some(c.id);
+        match c.state {
+            Listen | SynSent | Closed => c.state = Closed,
+            SynReceived | Established | CloseWait => {
+                c.fin_queued = true;
+                c.push(now(), &mut out);
+            }
+            _ => {}
+        }
+        if let Some(l) = listener { tcp.drop_backlog(l, &mut out); }
+        tcp.reap();
+    }
+    transmit(out);
+    Ok(())
+}
+
+/// Drop the connection at once, resetting it at the peer.
+pub fn abort(h: TcpHandle) -> Result<(), &'static str> {
+    let mut out = Vec::new();
+    {
+        let mut tcp = TCP.lock();
+        let c = tcp.get(h)?;
+        if c.synchronized() || c.state == TcpState::SynReceived {
+            out.push(c.segment(c.snd_nxt, RST, Vec::new()));
+        }
+        let listener = (c.state == TcpState::Listen).then_some(c.id);
+        c.user_closed = true;
+        c.state = TcpState::Closed;
+        if let Some(l) = listener { tcp.drop_backlog(l, &mut out); }
+        tcp.reap();
+    }
+    transmit(out);
+    Ok(())
+}
+
+/// What a call on connection or listener `h` would find.
+pub fn readiness(h: TcpHandle) -> Result<Ready, &'static str> {
+    use TcpState::*;
+    let mut tcp = TCP.lock();
+    let c = tcp.get(h)?;
+    let mut ready = Ready::NONE;
+    match c.state {
+        Listen => if !c.accept_queue.is_empty() { ready = Ready::READABLE },
+        SynSent | SynReceived => {}
+        Closed => {
+            ready = Ready::HANGUP;
+            if c.error.is_some() { ready = ready | Ready::ERROR; }
+        }
+        _ => {
+            if c.fin_received { ready = Ready::HANGUP; }
+            if matches!(c.state, Established | CloseWait) && !c.fin_queued && c.send_buf.len() < SEND_BUF {
+                ready = ready | Ready::WRITABLE;
+            }
+        }
+    }
+    if !c.recv_buf.is_empty() || c.fin_received { ready = ready | Ready::READABLE; }
+    Ok(ready)
+}
+
+pub fn state(h: TcpHandle) -> Option<TcpState> {
+    TCP.lock().get(h).ok().map(|c| c.state)
+}
+
+pub fn connections() -> Vec<TcpInfo> {
+    TCP.lock().conns.iter().map(Tcb::info).collect()
+}
+
+// ─── blocking calls ───────────────────────────────────────────────────────────
+
+/// Wait up to `timeout_ms` for a connection to finish its handshake.
+pub fn wait_established(h: TcpHandle, timeout_ms: u64) -> Result<(), &'static str> {
+    EVENTS.wait_until(Some(now() + timeout_ms), || {
+        let mut tcp = TCP.lock();
+        let c = match tcp.get(h) { Ok(c) => c, Err(e) => return Some(Err(e)) };
+        match c.state {
+            TcpState::SynSent | TcpState::SynReceived => None,
+            TcpState::Closed => Some(Err(c.error.unwrap_or("connection refused"))),
+            _ => Some(Ok(())),
+        }
+    }).unwrap_or(Err("timed out"))
+}
+
+/// `recv`, waiting up to `timeout_ms` for data to arrive.
+pub fn recv_timeout(h: TcpHandle, buf: &mut [u8], timeout_ms: u64) -> Result<usize, &'static str> {
+    EVENTS.wait_until(Some(now() + timeout_ms), || match recv(h, buf) {
+        Err("would block") => None,
+        r => Some(r),
+    }).unwrap_or(Err("timed out"))
+}
+
+/// Queue all of `data`, waiting up to `timeout_ms` for send buffer space.
+pub fn send_all(h: TcpHandle, mut data: &[u8], timeout_ms: u64) -> Result<(), &'static str> {
+    let deadline = Some(now() + timeout_ms);
+    while !data.is_empty() {
+        let n = EVENTS.wait_until(deadline, || match send(h, data) {
+            Err("would block") => None,
+            r => Some(r),
+        }).unwrap_or(Err("timed out"))?;
+        data = &data[n..];
+    }
+    Ok(())
+}
+
+// ─── stack entry points ───────────────────────────────────────────────────────
+
+/// A TCP segment from `src` to our address `dst`.
+pub fn input(src: IpAddr, dst: IpAddr, segment: &[u8]) {
+    let Some(seg) = parse(src, dst, segment) else { return };
+    let now = now();
+    let mut out = Vec::new();
+    {
+        let mut tcp = TCP.lock();
+        let index = tcp.conns.iter()
+            .position(|c| !matches!(c.state, TcpState::Listen | TcpState::Closed) && c.local == seg.dst && c.remote == seg.src)
+            .or_else(|| tcp.conns.iter().position(|c| {
+                c.state == TcpState::Listen && c.local.port == seg.dst.port
+                    && netns::admit(c.owner, ipv4::PROTO_TCP, seg.src, seg.dst.ip).is_ok()
+            }));
+        match index {
+            Some(i) => {
+                accounting::received(tcp.conns[i].owner, dst, src, super::header_len(src) + segment.len());
+                if tcp.conns[i].state == TcpState::Listen {
+                    tcp.listen_arrives(i, &seg, now, &mut out);
+                } else {
+                    tcp.conns[i].segment_arrives(&seg, now, &mut out);
+                    tcp.handshake_done(i, &mut out);
+                }
+            }
+            None    => if !seg.has(RST) { out.push(reset_for(&seg)) },
+        }
+        tcp.reap();
+    }
+    transmit(out);
+    EVENTS.wake_all();
+}
+
+/// End the connections `cut` picks by owner and local address, failing
+/// them with `reason` and without telling peers the owners may no longer
+/// reach.  Listeners stay, refusing connections until their owners may
+/// take them again.
+pub(super) fn cut_off(reason: &'static str, cut: impl Fn(ProcessId, IpAddr) -> bool) {
+    {
+        let mut tcp = TCP.lock();
+        for c in tcp.conns.iter_mut().filter(|c| !matches!(c.state, TcpState::Listen | TcpState::Closed)) {
+            if cut(c.owner, c.local.ip) { c.reset(reason); }
+        }
+        tcp.reap();
+    }
+    EVENTS.wake_all();
+}
+
+/// An ICMP error about a segment we sent from `local` to `remote`, which
+/// carried sequence number `seq`.  Hard errors end a connection still
+/// opening; an established one only remembers the error, for if it times
+/// out (RFC 5461).  A smaller path MTU shrinks the segment size at once.
+pub fn icmp_error(local: SocketAddr, remote: SocketAddr, seq: u32, error: IcmpError) {
+    let mut out = Vec::new();
+    {
+        let mut tcp = TCP.lock();
+        let Some(c) = tcp.conns.iter_mut()
+            .find(|c| c.local == local && c.remote == remote && !matches!(c.state, TcpState::Listen | TcpState::Closed))
+            else { return };
+        // Only a report quoting data in flight is believed (RFC 5927)
+        if !(seq_le(c.snd_una, seq) && seq_lt(seq, c.snd_max)) { return; }
+        match error {
+            IcmpError::FragmentationNeeded(mtu) => {
+                let mss = (mtu as usize).saturating_sub(super::header_len(remote.ip) + HEADER_LEN);
+                if mss >= DEFAULT_MSS.min(c.mss) && mss < c.mss {
+                    c.mss = mss;
+                    c.retransmit_first(&mut out);
+                }
+            }
+            e if e.is_hard() && !c.synchronized() => c.reset(e.as_str()),
+            e => c.soft_error = Some(e.as_str()),
+        }
+        tcp.reap();
+    }
+    transmit(out);
+    EVENTS.wake_all();
+}
+
+/// Run retransmission, persist and TIME-WAIT timers.
+pub fn on_timer(now: u64) {
+    let mut out = Vec::new();
+    {
+        let mut tcp = TCP.lock();
+        for c in tcp.conns.iter_mut() {
+            if c.state == TcpState::TimeWait {
+                if now >= c.time_wait_until { c.state = TcpState::Closed; }
+                continue;
+            }
+            if c.deadline.is_some_and(|d| now >= d) { c.on_timeout(now, &mut out); }
+        }
+        tcp.reap();
+    }
+    transmit(out);
+    EVENTS.wake_all();
+}
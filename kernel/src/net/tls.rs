@@ -0,0 +1,912 @@
+//! TLS 1.3 Client
+//! RFC 8446 over a TCP connection, for system services such as
+//! DNS-over-HTTPS.  One configuration is offered:
+//! TLS_CHACHA20_POLY1305_SHA256 with an X25519 key share.  The server's
+//! chain must lead to a trust anchor (added with `add_trust_anchor`, or
+//! loaded from /etc/ssl/certs on first use), be valid at the current
+//! wall-clock time and name the host we asked for.
+//!
+//! A connection remembers the session tickets its server sends, and the
+//! next connection to the same host offers one to resume with a PSK and a
+//! fresh X25519 exchange (psk_dhe_ke), skipping the certificates.  Each
+//! ticket is offered once, so resumptions cannot be linked to each other.
+//! Client certificates and 0-RTT data are not supported.
+//!
+//! Host names can have keys pinned, as SHA-256 hashes of their
+//! SubjectPublicKeyInfo: a full handshake with a pinned host must find one
+//! of them among the certificates from the leaf to the trust anchor, or it
+//! fails and the mismatch is reported as a security event.  Pins are set
+//! with the Network capability with CONTROL rights.
+//!
+//! The handshake itself (`Handshake`) only sees whole handshake messages,
+//! so QUIC runs the same one over its CRYPTO frames.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::tcp::{self, TcpHandle};
+use super::{IpAddr, SocketAddr};
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::crypto::chacha20poly1305 as aead;
+use crate::crypto::sha2::{self, Sha256};
+use crate::crypto::x25519;
+use crate::crypto::x509::{self, Certificate, Hash, SignatureAlgorithm};
+use crate::security::{self, SecurityEvent};
+
+const CONTENT_CCS:       u8 = 20;
+const CONTENT_ALERT:     u8 = 21;
+const CONTENT_HANDSHAKE: u8 = 22;
+const CONTENT_APP_DATA:  u8 = 23;
+
+const HS_CLIENT_HELLO:         u8 = 1;
+const HS_SERVER_HELLO:         u8 = 2;
+const HS_NEW_SESSION_TICKET:   u8 = 4;
+const HS_ENCRYPTED_EXTENSIONS: u8 = 8;
+const HS_CERTIFICATE:          u8 = 11;
+const HS_CERTIFICATE_REQUEST:  u8 = 13;
+const HS_CERTIFICATE_VERIFY:   u8 = 15;
+const HS_FINISHED:             u8 = 20;
+const HS_KEY_UPDATE:           u8 = 24;
+
+const EXT_SERVER_NAME:        u16 = 0;
+const EXT_SUPPORTED_GROUPS:   u16 = 10;
+const EXT_SIGNATURE_ALGS:     u16 = 13;
+const EXT_ALPN:               u16 = 16;
+const EXT_PRE_SHARED_KEY:     u16 = 41;
+const EXT_SUPPORTED_VERSIONS: u16 = 43;
+const EXT_PSK_MODES:          u16 = 45;
+const EXT_KEY_SHARE:          u16 = 51;
+
+/// PSK with (EC)DHE key establishment, the only resumption mode offered.
+const PSK_DHE_KE: u8 = 1;
+
+const TLS12:        u16 = 0x0303; // legacy_version
+const TLS13:        u16 = 0x0304;
+const CHACHA20_POLY1305_SHA256: u16 = 0x1303;
+const GROUP_X25519: u16 = 0x001D;
+
+const SIG_ECDSA_P256_SHA256: u16 = 0x0403;
+const SIG_ECDSA_P384_SHA384: u16 = 0x0503;
+const SIG_RSA_PSS_SHA256:    u16 = 0x0804;
+const SIG_RSA_PSS_SHA384:    u16 = 0x0805;
+/// Only for signatures in certificates, never in CertificateVerify.
+const SIG_RSA_PKCS1_SHA256:  u16 = 0x0401;
+const SIG_RSA_PKCS1_SHA384:  u16 = 0x0501;
+
+/// SHA-256("HelloRetryRequest"), the random of a HelloRetryRequest.
+const HRR_RANDOM: [u8; 32] = [
+    0xCF, 0x21, 0xAD, 0x74, 0xE5, 0x9A, 0x61, 0x11, 0xBE, 0x1D, 0x8C, 0x02, 0x1E, 0x65, 0xB8, 0x91,
+    0xC2, 0xA2, 0x11, 0x16, 0x7A, 0xBB, 0x8C, 0x5E, 0x07, 0x9E, 0x09, 0xE2, 0xC8, 0xA8, 0x33, 0x9C,
+];
+
+const RECORD_HEADER: usize = 5;
+/// Largest plaintext in one record.
+const MAX_FRAGMENT:  usize = 16384;
+/// Largest record body: a full fragment plus the AEAD expansion allowed.
+const MAX_RECORD:    usize = MAX_FRAGMENT + 256;
+/// Largest handshake message (certificate chains are the big ones).
+const MAX_HANDSHAKE: usize = 64 * 1024;
+/// Most certificates followed from the leaf to a trust anchor.
+const MAX_CHAIN:     usize = 8;
+const TIMEOUT_MS:    u64   = 10_000;
+
+/// Longest a ticket is kept, whatever the server says (RFC 8446 4.6.1).
+const MAX_TICKET_LIFETIME: u64 = 7 * 86_400;
+const TICKETS_MAX:         usize = 32;
+/// Tickets kept for one host.
+const TICKETS_PER_HOST:    usize = 4;
+
+pub const CERT_DIR: &str = "/etc/ssl/certs";
+
+// ─── trust store ──────────────────────────────────────────────────────────────
+
+static TRUST: Mutex<Vec<Certificate>> = Mutex::new(Vec::new());
+
+/// Trust the CA certificate `der` (DER-encoded) to vouch for servers.
+pub fn add_trust_anchor(der: &[u8]) -> Result<(), &'static str> {
+    let cert = x509::parse(der)?;
+    let mut trust = TRUST.lock();
+    if !trust.iter().any(|c| c.tbs == cert.tbs) { trust.push(cert); }
+    Ok(())
+}
+
+/// Add every DER certificate in `CERT_DIR`, returning how many were taken.
+pub fn load_trust_store() -> usize {
+    let Ok(files) = crate::fs::list_dir(CERT_DIR) else { return 0 };
+    files.iter()
+        .filter(|f| !f.is_dir)
+        .filter_map(|f| crate::fs::read_file(&alloc::format!("{}/{}", CERT_DIR, f.name)).ok())
+        .filter(|der| add_trust_anchor(der).is_ok())
+        .count()
+}
+
+pub fn trust_anchors() -> usize {
+    TRUST.lock().len()
+}
+
+// ─── pin store ────────────────────────────────────────────────────────────────
+
+/// Keys pinned for a host name.
+#[derive(Debug, Clone)]
+pub struct Pin {
+    pub host:       String,
+    /// SHA-256 hashes of SubjectPublicKeyInfo, any one of which will do.
+    pub keys:       Vec<[u8; 32]>,
+    /// Whether names below `host` are held to the same keys.
+    pub subdomains: bool,
+}
+
+static PINS: Mutex<Vec<Pin>> = Mutex::new(Vec::new());
+
+/// Pin `key` for `host`, alongside any keys already pinned for it; the
+/// subdomain setting is replaced.
+pub fn add_pin(cap: &Capability, host: &str, key: [u8; 32], subdomains: bool) -> Result<(), &'static str> {
+    capability::validate(crate::process::current_pid(), cap, CapabilityType::Network, Permissions::CONTROL)?;
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    if host.is_empty() || IpAddr::parse(&host).is_some() { return Err("pins are for host names"); }
+    {
+        let mut pins = PINS.lock();
+        match pins.iter_mut().find(|p| p.host == host) {
+            Some(p) => {
+                if !p.keys.contains(&key) { p.keys.push(key); }
+                p.subdomains = subdomains;
+            }
+            None => pins.push(Pin { host: host.clone(), keys: vec![key], subdomains }),
+        }
+    }
+    // Sessions agreed before the pin would skip it
+    flush_tickets(&host);
+    Ok(())
+}
+
+/// Remove the pins for `host`.
+pub fn remove_pin(cap: &Capability, host: &str) -> Result<(), &'static str> {
+    capability::validate(crate::process::current_pid(), cap, CapabilityType::Network, Permissions::CONTROL)?;
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let mut pins = PINS.lock();
+    let i = pins.iter().position(|p| p.host == host).ok_or("no pins for host")?;
+    pins.remove(i);
+    Ok(())
+}
+
+pub fn pins() -> Vec<Pin> {
+    PINS.lock().clone()
+}
+
+/// Whether `name` is `zone` or a name below it.
+fn within(name: &str, zone: &str) -> bool {
+    name == zone || name.strip_suffix(zone).is_some_and(|p| p.ends_with('.'))
+}
+
+/// Check the keys on a verified path against the pins for `host`: its
+/// own, or those of the nearest name above it that covers subdomains.
+fn check_pins(host: &str, path: &[[u8; 32]]) -> Result<(), &'static str> {
+    let host = host.to_ascii_lowercase();
+    let pins = PINS.lock();
+    let pin = pins.iter().filter(|p| p.host == host || (p.subdomains && within(&host, &p.host)))
+        .max_by_key(|p| p.host.len());
+    let Some(pin) = pin else { return Ok(()) };
+    if path.iter().any(|k| pin.keys.contains(k)) { return Ok(()); }
+    drop(pins);
+    security::report(SecurityEvent::PinMismatch { pid: crate::process::current_pid(), host });
+    Err("certificate does not match pinned keys")
+}
+
+// ─── session tickets ──────────────────────────────────────────────────────────
+
+/// A server's ticket for resuming its session (RFC 8446 4.6.1).
+struct Ticket {
+    host:     String,
+    ticket:   Vec<u8>,
+    psk:      [u8; 32],
+    age_add:  u32,
+    /// When it arrived, and stops being offered: uptime milliseconds.
+    received: u64,
+    expires:  u64,
+}
+
+static TICKETS: Mutex<Vec<Ticket>> = Mutex::new(Vec::new());
+
+/// Keep the NewSessionTicket `body` from `host`, whose session's
+/// resumption master secret is `resumption`.
+fn store_ticket(host: &str, body: &[u8], resumption: &[u8; 32]) -> Result<(), &'static str> {
+    let mut r = Reader(body);
+    let lifetime = r.u16()? as u64 * 65536 + r.u16()? as u64;
+    let age_add = (r.u16()? as u32) << 16 | r.u16()? as u32;
+    let nonce = r.vec(1)?;
+    let ticket = r.vec(2)?;
+    r.vec(2)?;
+    if lifetime == 0 || ticket.is_empty() { return Ok(()); }
+    let mut psk = [0u8; 32];
+    expand_label(resumption, "resumption", nonce, &mut psk);
+    let now = crate::arch::uptime_millis();
+    let mut tickets = TICKETS.lock();
+    tickets.retain(|t| t.expires > now);
+    // The oldest go first, for the host and overall
+    if tickets.iter().filter(|t| t.host == host).count() >= TICKETS_PER_HOST {
+        if let Some(i) = tickets.iter().position(|t| t.host == host) { tickets.remove(i); }
+    }
+    if tickets.len() >= TICKETS_MAX { tickets.remove(0); }
+    tickets.push(Ticket {
+        host: String::from(host), ticket: ticket.to_vec(), psk, age_add, received: now,
+        expires: now + lifetime.min(MAX_TICKET_LIFETIME) * 1000,
+    });
+    Ok(())
+}
+
+/// The newest usable ticket for `host`, taken so it is offered only once.
+fn take_ticket(host: &str) -> Option<Ticket> {
+    let now = crate::arch::uptime_millis();
+    let mut tickets = TICKETS.lock();
+    tickets.retain(|t| t.expires > now);
+    let i = tickets.iter().rposition(|t| t.host == host)?;
+    Some(tickets.remove(i))
+}
+
+/// Forget the tickets for `host` and the names below it.
+pub fn flush_tickets(host: &str) {
+    TICKETS.lock().retain(|t| !within(&t.host, host));
+}
+
+/// Tickets held, for all hosts.
+pub fn tickets() -> usize {
+    let now = crate::arch::uptime_millis();
+    TICKETS.lock().iter().filter(|t| t.expires > now).count()
+}
+
+// ─── wire helpers ─────────────────────────────────────────────────────────────
+
+/// A cursor over a handshake message body.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], &'static str> {
+        if self.0.len() < n { return Err("truncated handshake message"); }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, &'static str> {
+        let b = self.take(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u24(&mut self) -> Result<usize, &'static str> {
+        let b = self.take(3)?;
+        Ok((b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+    }
+
+    /// A vector with a `width`-byte length prefix.
+    fn vec(&mut self, width: usize) -> Result<&'a [u8], &'static str> {
+        let len = match width { 1 => self.u8()? as usize, 2 => self.u16()? as usize, _ => self.u24()? };
+        self.take(len)
+    }
+}
+
+fn put_u16(b: &mut Vec<u8>, v: usize) {
+    b.extend_from_slice(&(v as u16).to_be_bytes());
+}
+
+fn extension(b: &mut Vec<u8>, ty: u16, data: &[u8]) {
+    b.extend_from_slice(&ty.to_be_bytes());
+    put_u16(b, data.len());
+    b.extend_from_slice(data);
+}
+
+fn handshake_message(ty: u8, body: &[u8]) -> Vec<u8> {
+    let mut m = vec![ty];
+    m.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+    m.extend_from_slice(body);
+    m
+}
+
+fn alert_error(alert: &[u8]) -> &'static str {
+    match alert.get(1) {
+        Some(0)   => "connection closed",
+        Some(40)  => "handshake failure",
+        Some(42)  => "server rejected certificate",
+        Some(48)  => "unknown CA",
+        Some(70)  => "protocol version not supported",
+        Some(112) => "unrecognized server name",
+        Some(120) => "no application protocol",
+        _         => "fatal alert from server",
+    }
+}
+
+// ─── key schedule ─────────────────────────────────────────────────────────────
+
+pub(super) fn expand_label(secret: &[u8], label: &str, context: &[u8], out: &mut [u8]) {
+    let mut info = Vec::with_capacity(4 + 6 + label.len() + context.len());
+    put_u16(&mut info, out.len());
+    info.push((6 + label.len()) as u8);
+    info.extend_from_slice(b"tls13 ");
+    info.extend_from_slice(label.as_bytes());
+    info.push(context.len() as u8);
+    info.extend_from_slice(context);
+    sha2::hkdf_expand(secret, &info, out);
+}
+
+fn derive_secret(secret: &[u8], label: &str, transcript: &[u8; 32]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    expand_label(secret, label, transcript, &mut out);
+    out
+}
+
+fn finished_mac(secret: &[u8; 32], transcript: &[u8; 32]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    expand_label(secret, "finished", &[], &mut key);
+    sha2::hmac_sha256(&key, &[transcript])
+}
+
+/// Traffic keys for one direction.
+struct Keys {
+    secret: [u8; 32],
+    key:    [u8; aead::KEY_LEN],
+    iv:     [u8; aead::NONCE_LEN],
+    seq:    u64,
+}
+
+impl Keys {
+    fn new(secret: [u8; 32]) -> Keys {
+        let mut keys = Keys { secret, key: [0; aead::KEY_LEN], iv: [0; aead::NONCE_LEN], seq: 0 };
+        expand_label(&secret, "key", &[], &mut keys.key);
+        expand_label(&secret, "iv", &[], &mut keys.iv);
+        keys
+    }
+
+    /// The keys after a KeyUpdate.
+    fn next(&self) -> Keys {
+        let mut secret = [0u8; 32];
+        expand_label(&self.secret, "traffic upd", &[], &mut secret);
+        Keys::new(secret)
+    }
+
+    fn nonce(&mut self) -> [u8; aead::NONCE_LEN] {
+        let mut n = self.iv;
+        for (b, s) in n[4..].iter_mut().zip(self.seq.to_be_bytes()) { *b ^= s; }
+        self.seq += 1;
+        n
+    }
+}
+
+// ─── certificates ─────────────────────────────────────────────────────────────
+
+/// The certificates of a Certificate message, leaf first.
+fn parse_certificates(body: &[u8]) -> Result<Vec<Certificate>, &'static str> {
+    let mut r = Reader(body);
+    if !r.vec(1)?.is_empty() { return Err("unexpected certificate request context"); }
+    let mut list = Reader(r.vec(3)?);
+    let mut chain = Vec::new();
+    while !list.0.is_empty() && chain.len() < MAX_CHAIN {
+        let der = list.vec(3)?;
+        list.vec(2)?;
+        match x509::parse(der) {
+            Ok(c) => chain.push(c),
+            Err(e) if chain.is_empty() => return Err(e),
+            // Extra certificates we cannot use are allowed to be there
+            Err(_) => {}
+        }
+    }
+    if chain.is_empty() { return Err("server sent no certificate"); }
+    Ok(chain)
+}
+
+/// Check that `chain` leads from a leaf naming `host` to a trust anchor;
+/// returns the key hashes on the way, the anchor's included.
+fn verify_chain(chain: &[Certificate], host: &str) -> Result<Vec<[u8; 32]>, &'static str> {
+    let now = crate::alarm::rtc_now_ns() / 1_000_000_000;
+    let leaf = &chain[0];
+    if !leaf.matches_host(host) { return Err("certificate does not match host"); }
+    if !leaf.valid_at(now) { return Err("certificate expired or not yet valid"); }
+    let trust = TRUST.lock();
+    let mut current = leaf;
+    let mut path = vec![leaf.spki_hash];
+    for depth in 0..MAX_CHAIN {
+        if let Some(anchor) = trust.iter().find(|a| a.valid_at(now) && current.verify_signed_by(a).is_ok()) {
+            path.push(anchor.spki_hash);
+            return Ok(path);
+        }
+        let Some(next) = chain[1..].iter().find(|c| c.is_ca && c.subject == current.issuer) else {
+            return Err("certificate not trusted");
+        };
+        if !next.valid_at(now) { return Err("intermediate certificate expired"); }
+        // pathLen counts the intermediates allowed below this one
+        if next.path_len.is_some_and(|n| (n as usize) < depth) { return Err("certificate path too long"); }
+        current.verify_signed_by(next)?;
+        path.push(next.spki_hash);
+        current = next;
+    }
+    Err("certificate path too long")
+}
+
+fn verify_certificate_verify(leaf: &Certificate, body: &[u8], transcript: &[u8; 32]) -> Result<(), &'static str> {
+    let mut r = Reader(body);
+    let alg = match r.u16()? {
+        SIG_ECDSA_P256_SHA256 => SignatureAlgorithm::Ecdsa(Hash::Sha256),
+        SIG_ECDSA_P384_SHA384 => SignatureAlgorithm::Ecdsa(Hash::Sha384),
+        SIG_RSA_PSS_SHA256    => SignatureAlgorithm::RsaPss(Hash::Sha256),
+        SIG_RSA_PSS_SHA384    => SignatureAlgorithm::RsaPss(Hash::Sha384),
+        _ => return Err("unsupported signature scheme"),
+    };
+    let signature = r.vec(2)?;
+    let mut content = vec![0x20u8; 64];
+    content.extend_from_slice(b"TLS 1.3, server CertificateVerify\0");
+    content.extend_from_slice(transcript);
+    leaf.key.verify(alg, &content, signature)
+}
+
+// ─── handshake messages ───────────────────────────────────────────────────────
+
+/// A ClientHello; offering `ticket` puts the pre_shared_key extension last
+/// with its binder zeroed, for the caller to fill in.
+fn client_hello(host: &str, alpn: &[&str], share: &[u8; x25519::KEY_LEN], extra: &[(u16, &[u8])], ticket: Option<&Ticket>) -> Vec<u8> {
+    let mut random = [0u8; 32];
+    crate::entropy::fill_bytes(&mut random);
+    let mut b = Vec::new();
+    b.extend_from_slice(&TLS12.to_be_bytes());
+    b.extend_from_slice(&random);
+    b.push(0); // no legacy session id
+    put_u16(&mut b, 2);
+    b.extend_from_slice(&CHACHA20_POLY1305_SHA256.to_be_bytes());
+    b.extend_from_slice(&[1, 0]); // null compression
+
+    let mut ext = Vec::new();
+    // Names only: SNI may not carry an address
+    if IpAddr::parse(host).is_none() {
+        let mut sni = Vec::new();
+        put_u16(&mut sni, host.len() + 3);
+        sni.push(0); // host_name
+        put_u16(&mut sni, host.len());
+        sni.extend_from_slice(host.as_bytes());
+        extension(&mut ext, EXT_SERVER_NAME, &sni);
+    }
+    extension(&mut ext, EXT_SUPPORTED_GROUPS, &[0, 2, 0x00, 0x1D]);
+    let mut sigs = Vec::new();
+    let schemes = [SIG_ECDSA_P256_SHA256, SIG_ECDSA_P384_SHA384, SIG_RSA_PSS_SHA256, SIG_RSA_PSS_SHA384, SIG_RSA_PKCS1_SHA256, SIG_RSA_PKCS1_SHA384];
+    put_u16(&mut sigs, 2 * schemes.len());
+    for s in schemes { sigs.extend_from_slice(&s.to_be_bytes()); }
+    extension(&mut ext, EXT_SIGNATURE_ALGS, &sigs);
+    extension(&mut ext, EXT_SUPPORTED_VERSIONS, &[2, 0x03, 0x04]);
+    let mut ks = Vec::new();
+    put_u16(&mut ks, 4 + share.len());
+    ks.extend_from_slice(&GROUP_X25519.to_be_bytes());
+    put_u16(&mut ks, share.len());
+    ks.extend_from_slice(share);
+    extension(&mut ext, EXT_KEY_SHARE, &ks);
+    if !alpn.is_empty() {
+        let mut list = Vec::new();
+        for p in alpn { list.push(p.len() as u8); list.extend_from_slice(p.as_bytes()); }
+        let mut data = Vec::new();
+        put_u16(&mut data, list.len());
+        data.extend_from_slice(&list);
+        extension(&mut ext, EXT_ALPN, &data);
+    }
+    for &(ty, data) in extra { extension(&mut ext, ty, data); }
+    if let Some(t) = ticket {
+        extension(&mut ext, EXT_PSK_MODES, &[1, PSK_DHE_KE]);
+        let age = (crate::arch::uptime_millis() - t.received) as u32;
+        let mut psk = Vec::new();
+        put_u16(&mut psk, 2 + t.ticket.len() + 4);
+        put_u16(&mut psk, t.ticket.len());
+        psk.extend_from_slice(&t.ticket);
+        psk.extend_from_slice(&age.wrapping_add(t.age_add).to_be_bytes());
+        put_u16(&mut psk, 1 + 32);
+        psk.push(32);
+        psk.extend_from_slice(&[0; 32]);
+        extension(&mut ext, EXT_PRE_SHARED_KEY, &psk);
+    }
+    put_u16(&mut b, ext.len());
+    b.extend_from_slice(&ext);
+    handshake_message(HS_CLIENT_HELLO, &b)
+}
+
+/// The server's X25519 key share from a ServerHello body, and the PSK
+/// identity it accepted, if any.
+fn parse_server_hello(body: &[u8]) -> Result<([u8; x25519::KEY_LEN], Option<u16>), &'static str> {
+    let mut r = Reader(body);
+    r.u16()?;
+    if r.take(32)? == HRR_RANDOM { return Err("server wants a key share we do not offer"); }
+    r.vec(1)?;
+    if r.u16()? != CHACHA20_POLY1305_SHA256 || r.u8()? != 0 { return Err("server chose unsupported parameters"); }
+    let mut exts = Reader(r.vec(2)?);
+    let (mut version, mut share, mut psk) = (None, None, None);
+    while !exts.0.is_empty() {
+        let ty = exts.u16()?;
+        let mut data = Reader(exts.vec(2)?);
+        match ty {
+            EXT_SUPPORTED_VERSIONS => version = Some(data.u16()?),
+            EXT_KEY_SHARE => {
+                if data.u16()? != GROUP_X25519 { return Err("server chose unsupported group"); }
+                share = data.vec(2)?.try_into().ok();
+            }
+            EXT_PRE_SHARED_KEY => psk = Some(data.u16()?),
+            _ => {}
+        }
+    }
+    if version != Some(TLS13) { return Err("server does not speak TLS 1.3"); }
+    Ok((share.ok_or("server sent no key share")?, psk))
+}
+
+// ─── handshake ────────────────────────────────────────────────────────────────
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    ServerHello,
+    EncryptedExtensions,
+    Certificate,
+    CertificateVerify,
+    Finished,
+    Done,
+}
+
+/// Where a server message left the handshake.
+pub(super) enum Step {
+    Continue,
+    /// The handshake traffic secrets, client's then server's.
+    HandshakeKeys([u8; 32], [u8; 32]),
+    /// The server is authenticated: send `finished` under the handshake
+    /// keys, then switch to these application traffic secrets.
+    Done { finished: Vec<u8>, client: [u8; 32], server: [u8; 32] },
+}
+
+/// The client side of a handshake, fed the server's handshake messages
+/// whole, however they travel.
+pub(super) struct Handshake {
+    host:       String,
+    secret:     [u8; x25519::KEY_LEN],
+    transcript: Sha256,
+    expect:     Expect,
+    hs_secret:  [u8; 32],
+    client_hs:  [u8; 32],
+    server_hs:  [u8; 32],
+    chain:      Vec<Certificate>,
+    /// The server's encrypted extensions.
+    extensions: Vec<(u16, Vec<u8>)>,
+    /// The PSK of the ticket offered, and whether the server took it.
+    psk:        Option<[u8; 32]>,
+    resumed:    bool,
+    /// For the tickets the server sends once the handshake is done.
+    resumption: [u8; 32],
+}
+
+impl Handshake {
+    /// Begin a handshake with `host`, offering the ALPN protocols `alpn`
+    /// and any `extra` extensions; returns it with the ClientHello to
+    /// send.
+    pub(super) fn start(host: &str, alpn: &[&str], extra: &[(u16, &[u8])]) -> (Handshake, Vec<u8>) {
+        Handshake::begin(host, alpn, extra, None)
+    }
+
+    /// As `start`, offering `ticket` to resume its session.
+    fn begin(host: &str, alpn: &[&str], extra: &[(u16, &[u8])], ticket: Option<Ticket>) -> (Handshake, Vec<u8>) {
+        if TRUST.lock().is_empty() { load_trust_store(); }
+        let mut secret = [0u8; x25519::KEY_LEN];
+        crate::entropy::fill_bytes(&mut secret);
+        let mut hello = client_hello(host, alpn, &x25519::public_key(&secret), extra, ticket.as_ref());
+        let psk = ticket.map(|t| {
+            // The binder covers the hello up to the binders (RFC 8446 4.2.11.2)
+            let early = sha2::hkdf_extract(&[0u8; 32], &t.psk);
+            let binder_key = derive_secret(&early, "res binder", &sha2::sha256(&[]));
+            let at = hello.len() - 32;
+            let binder = finished_mac(&binder_key, &sha2::sha256(&hello[..at - 3]));
+            hello[at..].copy_from_slice(&binder);
+            t.psk
+        });
+        let mut transcript = Sha256::default();
+        transcript.update(&hello);
+        let hs = Handshake {
+            host: String::from(host), secret, transcript, expect: Expect::ServerHello, hs_secret: [0; 32],
+            client_hs: [0; 32], server_hs: [0; 32], chain: Vec::new(), extensions: Vec::new(),
+            psk, resumed: false, resumption: [0; 32],
+        };
+        (hs, hello)
+    }
+
+    /// Take the server's next message, header included.
+    pub(super) fn input(&mut self, m: &[u8]) -> Result<Step, &'static str> {
+        let ty = match self.expect {
+            Expect::ServerHello         => HS_SERVER_HELLO,
+            Expect::EncryptedExtensions => HS_ENCRYPTED_EXTENSIONS,
+            Expect::Certificate         => HS_CERTIFICATE,
+            Expect::CertificateVerify   => HS_CERTIFICATE_VERIFY,
+            Expect::Finished            => HS_FINISHED,
+            Expect::Done                => return Err("unexpected handshake message"),
+        };
+        match m[0] {
+            t if t == ty => {}
+            HS_CERTIFICATE_REQUEST => return Err("server requires a client certificate"),
+            _ => return Err("unexpected handshake message"),
+        }
+        let body = &m[4..];
+        let step = match self.expect {
+            Expect::ServerHello => {
+                let (share, psk) = parse_server_hello(body)?;
+                let shared = x25519::x25519(&self.secret, &share);
+                if shared == [0; 32] { return Err("bad server key share"); }
+                self.resumed = match (psk, self.psk) {
+                    (None, _) => false,
+                    (Some(0), Some(_)) => true,
+                    _ => return Err("server chose a PSK not offered"),
+                };
+                self.transcript.update(m);
+                let zero = [0u8; 32];
+                let early = sha2::hkdf_extract(&zero, self.psk.as_ref().filter(|_| self.resumed).unwrap_or(&zero));
+                self.hs_secret = sha2::hkdf_extract(&derive_secret(&early, "derived", &sha2::sha256(&[])), &shared);
+                let hash = self.transcript.clone().finish();
+                self.client_hs = derive_secret(&self.hs_secret, "c hs traffic", &hash);
+                self.server_hs = derive_secret(&self.hs_secret, "s hs traffic", &hash);
+                self.expect = Expect::EncryptedExtensions;
+                return Ok(Step::HandshakeKeys(self.client_hs, self.server_hs));
+            }
+            Expect::EncryptedExtensions => {
+                let mut exts = Reader(Reader(body).vec(2)?);
+                while !exts.0.is_empty() {
+                    let ty = exts.u16()?;
+                    let data = exts.vec(2)?;
+                    self.extensions.push((ty, data.to_vec()));
+                }
+                // A resumed session's server was authenticated when it began
+                self.expect = if self.resumed { Expect::Finished } else { Expect::Certificate };
+                Step::Continue
+            }
+            Expect::Certificate => {
+                self.chain = parse_certificates(body)?;
+                check_pins(&self.host, &verify_chain(&self.chain, &self.host)?)?;
+                self.expect = Expect::CertificateVerify;
+                Step::Continue
+            }
+            Expect::CertificateVerify => {
+                verify_certificate_verify(&self.chain[0], body, &self.transcript.clone().finish())?;
+                self.expect = Expect::Finished;
+                Step::Continue
+            }
+            Expect::Finished => {
+                let expect = finished_mac(&self.server_hs, &self.transcript.clone().finish());
+                if body.len() != 32 || body.iter().zip(expect).fold(0, |d, (a, b)| d | (a ^ b)) != 0 {
+                    return Err("server Finished does not verify");
+                }
+                self.transcript.update(m);
+                let hash = self.transcript.clone().finish();
+                let master = sha2::hkdf_extract(&derive_secret(&self.hs_secret, "derived", &sha2::sha256(&[])), &[0u8; 32]);
+                self.expect = Expect::Done;
+                let finished = handshake_message(HS_FINISHED, &finished_mac(&self.client_hs, &hash));
+                let (client, server) = (derive_secret(&master, "c ap traffic", &hash), derive_secret(&master, "s ap traffic", &hash));
+                self.transcript.update(&finished);
+                self.resumption = derive_secret(&master, "res master", &self.transcript.clone().finish());
+                return Ok(Step::Done { finished, client, server });
+            }
+            Expect::Done => unreachable!(),
+        };
+        self.transcript.update(m);
+        Ok(step)
+    }
+
+    /// The data of encrypted extension `ty`, if the server sent it.
+    pub(super) fn extension(&self, ty: u16) -> Option<&[u8]> {
+        self.extensions.iter().find(|e| e.0 == ty).map(|e| e.1.as_slice())
+    }
+
+    /// Whether the server resumed the session of the ticket offered.
+    pub(super) fn resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// The protocol the server picked from those offered, if any.
+    pub(super) fn alpn(&self) -> Option<&str> {
+        let mut r = Reader(self.extension(EXT_ALPN)?);
+        let mut list = Reader(r.vec(2).ok()?);
+        core::str::from_utf8(list.vec(1).ok()?).ok()
+    }
+}
+
+/// Split a whole handshake message off the front of `buf`, if one has
+/// arrived.
+pub(super) fn next_message(buf: &mut Vec<u8>) -> Result<Option<Vec<u8>>, &'static str> {
+    if buf.len() < 4 { return Ok(None); }
+    let len = (buf[1] as usize) << 16 | (buf[2] as usize) << 8 | buf[3] as usize;
+    if len > MAX_HANDSHAKE { return Err("handshake message too large"); }
+    if buf.len() < 4 + len { return Ok(None); }
+    Ok(Some(buf.drain(..4 + len).collect()))
+}
+
+// ─── connections ──────────────────────────────────────────────────────────────
+
+/// An established TLS connection.  Dropping it sends close_notify and
+/// closes the TCP connection.
+pub struct TlsStream {
+    tcp:        TcpHandle,
+    host:       String,
+    /// The resumption master secret, once the handshake is done.
+    resumption: Option<[u8; 32]>,
+    resumed:    bool,
+    /// Bytes received but not yet a whole record.
+    rx:         Vec<u8>,
+    /// Handshake bytes not yet a whole message.
+    hs:         Vec<u8>,
+    /// Decrypted application data not yet read.
+    plain:      Vec<u8>,
+    read:       Option<Keys>,
+    write:      Option<Keys>,
+    /// The server sent close_notify.
+    closed:     bool,
+}
+
+/// Connect to `addr` and complete a handshake with `host`, offering the
+/// ALPN protocols `alpn` (none if empty).
+pub fn connect(addr: SocketAddr, host: &str, alpn: &[&str]) -> Result<TlsStream, &'static str> {
+    let tcp = tcp::connect(addr)?;
+    let mut s = TlsStream {
+        tcp, host: String::from(host), resumption: None, resumed: false, rx: Vec::new(), hs: Vec::new(),
+        plain: Vec::new(), read: None, write: None, closed: false,
+    };
+    tcp::wait_established(tcp, TIMEOUT_MS)?;
+    if let Err(e) = s.handshake(host, alpn) {
+        s.closed = true;
+        let _ = tcp::abort(tcp);
+        return Err(e);
+    }
+    Ok(s)
+}
+
+impl TlsStream {
+    fn send_record(&mut self, ty: u8, data: &[u8]) -> Result<(), &'static str> {
+        let mut rec = Vec::with_capacity(RECORD_HEADER + data.len() + 1 + aead::TAG_LEN);
+        match &mut self.write {
+            None => {
+                rec.push(ty);
+                rec.extend_from_slice(&0x0301u16.to_be_bytes());
+                put_u16(&mut rec, data.len());
+                rec.extend_from_slice(data);
+            }
+            Some(k) => {
+                let mut inner = data.to_vec();
+                inner.push(ty);
+                rec.push(CONTENT_APP_DATA);
+                rec.extend_from_slice(&TLS12.to_be_bytes());
+                put_u16(&mut rec, inner.len() + aead::TAG_LEN);
+                let nonce = k.nonce();
+                let sealed = aead::seal(&k.key, &nonce, &rec, &inner);
+                rec.extend_from_slice(&sealed);
+            }
+        }
+        tcp::send_all(self.tcp, &rec, TIMEOUT_MS)
+    }
+
+    /// The next record with its content type, decrypted once keys are set.
+    fn recv_record(&mut self, timeout_ms: u64) -> Result<(u8, Vec<u8>), &'static str> {
+        loop {
+            if self.rx.len() >= RECORD_HEADER {
+                let len = u16::from_be_bytes([self.rx[3], self.rx[4]]) as usize;
+                if len > MAX_RECORD { return Err("record too large"); }
+                if self.rx.len() >= RECORD_HEADER + len {
+                    let rec: Vec<u8> = self.rx.drain(..RECORD_HEADER + len).collect();
+                    let (header, body) = rec.split_at(RECORD_HEADER);
+                    // Middlebox compatibility noise
+                    if header[0] == CONTENT_CCS { continue; }
+                    let Some(k) = &mut self.read else { return Ok((header[0], body.to_vec())) };
+                    if header[0] != CONTENT_APP_DATA { return Err("unprotected record after handshake keys"); }
+                    let nonce = k.nonce();
+                    let mut inner = aead::open(&k.key, &nonce, header, body)?;
+                    // Content type follows the data, then any zero padding
+                    let end = inner.iter().rposition(|&b| b != 0).ok_or("record has no content type")?;
+                    let ty = inner[end];
+                    inner.truncate(end);
+                    return Ok((ty, inner));
+                }
+            }
+            let start = self.rx.len();
+            self.rx.resize(start + 4096, 0);
+            let n = match tcp::recv_timeout(self.tcp, &mut self.rx[start..], timeout_ms) {
+                Ok(n) => n,
+                Err(e) => { self.rx.truncate(start); return Err(e); }
+            };
+            self.rx.truncate(start + n);
+            if n == 0 { return Err("connection closed"); }
+        }
+    }
+
+    /// The next whole handshake message, header included.
+    fn recv_handshake(&mut self) -> Result<Vec<u8>, &'static str> {
+        loop {
+            if let Some(m) = next_message(&mut self.hs)? { return Ok(m); }
+            match self.recv_record(TIMEOUT_MS)? {
+                (CONTENT_HANDSHAKE, data) => self.hs.extend_from_slice(&data),
+                (CONTENT_ALERT, data)     => return Err(alert_error(&data)),
+                _ => return Err("unexpected record during handshake"),
+            }
+        }
+    }
+
+    fn handshake(&mut self, host: &str, alpn: &[&str]) -> Result<(), &'static str> {
+        let (mut hs, hello) = Handshake::begin(host, alpn, &[], take_ticket(host));
+        self.send_record(CONTENT_HANDSHAKE, &hello)?;
+        loop {
+            let m = self.recv_handshake()?;
+            match hs.input(&m)? {
+                Step::Continue => {}
+                Step::HandshakeKeys(client, server) => {
+                    self.read = Some(Keys::new(server));
+                    self.write = Some(Keys::new(client));
+                }
+                Step::Done { finished, client, server } => {
+                    self.send_record(CONTENT_HANDSHAKE, &finished)?;
+                    self.read = Some(Keys::new(server));
+                    self.write = Some(Keys::new(client));
+                    self.resumption = Some(hs.resumption);
+                    self.resumed = hs.resumed();
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Handle handshake messages after the handshake: tickets are kept
+    /// and key updates followed.
+    fn post_handshake(&mut self) -> Result<(), &'static str> {
+        while let Some(m) = next_message(&mut self.hs)? {
+            match m[0] {
+                HS_NEW_SESSION_TICKET => if let Some(r) = &self.resumption { store_ticket(&self.host, &m[4..], r)?; },
+                HS_KEY_UPDATE => {
+                    self.read = self.read.as_ref().map(Keys::next);
+                    if m.get(4) == Some(&1) {
+                        self.send_record(CONTENT_HANDSHAKE, &handshake_message(HS_KEY_UPDATE, &[0]))?;
+                        self.write = self.write.as_ref().map(Keys::next);
+                    }
+                }
+                _ => return Err("unexpected handshake message"),
+            }
+        }
+        Ok(())
+    }
+
+    /// Send `data`.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), &'static str> {
+        if self.closed { return Err("connection closed"); }
+        for chunk in data.chunks(MAX_FRAGMENT) { self.send_record(CONTENT_APP_DATA, chunk)?; }
+        Ok(())
+    }
+
+    /// Read application data, waiting up to `timeout_ms` for some to
+    /// arrive.  `Ok(0)` means the server has closed the connection.
+    pub fn read(&mut self, buf: &mut [u8], timeout_ms: u64) -> Result<usize, &'static str> {
+        while self.plain.is_empty() {
+            if self.closed { return Ok(0); }
+            match self.recv_record(timeout_ms)? {
+                (CONTENT_APP_DATA, data)  => self.plain = data,
+                (CONTENT_HANDSHAKE, data) => { self.hs.extend_from_slice(&data); self.post_handshake()?; }
+                (CONTENT_ALERT, data) if data.get(1) == Some(&0) => self.closed = true,
+                (CONTENT_ALERT, data)     => return Err(alert_error(&data)),
+                _ => return Err("unexpected record"),
+            }
+        }
+        let n = buf.len().min(self.plain.len());
+        buf[..n].copy_from_slice(&self.plain[..n]);
+        self.plain.drain(..n);
+        Ok(n)
+    }
+
+    /// Whether the session was resumed from a ticket rather than
+    /// authenticated afresh.
+    pub fn resumed(&self) -> bool {
+        self.resumed
+    }
+
+    /// Whether the server has closed its side.
+    pub fn is_closed(&self) -> bool {
+        self.closed || !matches!(tcp::state(self.tcp), Some(tcp::TcpState::Established))
+    }
+}
+
+impl Drop for TlsStream {
+    fn drop(&mut self) {
+        if self.write.is_some() && !self.closed { let _ = self.send_record(CONTENT_ALERT, &[1, 0]); }
+        let _ = tcp::close(self.tcp);
+    }
+}
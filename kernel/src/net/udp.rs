@@ -0,0 +1,262 @@
+//! UDP
+//! Datagram sockets: port demultiplexing, checksums and a receive queue
+//! per socket.  A receive blocks on the UDP wait queue, which every
+//! delivered datagram wakes, unless the socket is non-blocking or the
+//! caller's timeout runs out.  Sockets are dual-stack: one bound to either
+//! unspecified address receives IPv4 and IPv6 alike.  A socket belongs to
+//! the process that bound it, and its traffic is subject to that app's
+//! namespace in both directions.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::icmp::IcmpError;
+use super::{accounting, ipv4, netns, transport_checksum, IpAddr, Ipv4Addr, SocketAddr};
+use crate::event::Ready;
+use crate::process::{ProcessId, WaitQueue};
+
+pub const HEADER_LEN: usize = 8;
+/// Datagrams a socket holds before new arrivals are dropped.
+const RX_QUEUE:       usize = 64;
+const EPHEMERAL_FIRST: u16  = 49152;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UdpHandle(pub u32);
+
+struct Datagram {
+    from: SocketAddr,
+    data: Vec<u8>,
+}
+
+struct UdpSocket {
+    id:          u32,
+    owner:       ProcessId,
+    /// Bound address; an unspecified IP, of either version, receives on
+    /// every interface and over both versions.
+    local:       SocketAddr,
+    /// Set by `connect`: the default destination, and the only source
+    /// datagrams are accepted from.
+    peer:        Option<SocketAddr>,
+    queue:       VecDeque<Datagram>,
+    nonblocking: bool,
+    /// An ICMP error about our traffic to the peer, for the next receive.
+    error:       Option<&'static str>,
+    drops:       u64,
+}
+
+impl UdpSocket {
+    fn accepts(&self, from: SocketAddr, to: SocketAddr) -> bool {
+        self.local.port == to.port
+            && (self.local.ip.is_unspecified() || self.local.ip == to.ip)
+            && self.peer.is_none_or(|p| p == from)
+    }
+}
+
+/// A socket as reported to callers.
+#[derive(Debug, Clone)]
+pub struct UdpInfo {
+    pub handle: UdpHandle,
+    pub owner:  ProcessId,
+    pub local:  SocketAddr,
+    pub peer:   Option<SocketAddr>,
+    pub queued: usize,
+    pub drops:  u64,
+}
+
+struct Udp {
+    sockets:   Vec<UdpSocket>,
+    next_id:   u32,
+    next_port: u16,
+}
+
+impl Udp {
+    /// The current process's socket `h`.
+    fn get(&mut self, h: UdpHandle) -> Result<&mut UdpSocket, &'static str> {
+        let owner = crate::process::current_pid();
+        self.sockets.iter_mut().find(|s| s.id == h.0 && s.owner == owner).ok_or("no such socket")
+    }
+
+    fn in_use(&self, addr: SocketAddr) -> bool {
+        self.sockets.iter().any(|s| {
+            s.local.port == addr.port
+                && (s.local.ip == addr.ip || s.local.ip.is_unspecified() || addr.ip.is_unspecified())
+        })
+    }
+
+    fn ephemeral_port(&mut self) -> Result<u16, &'static str> {
+        for _ in EPHEMERAL_FIRST..=u16::MAX {
+            let port = self.next_port;
+            self.next_port = if port == u16::MAX { EPHEMERAL_FIRST } else { port + 1 };
+            if !self.in_use(SocketAddr::new(Ipv4Addr::UNSPECIFIED, port)) { return Ok(port); }
+        }
+        Err("out of ephemeral ports")
+    }
+}
+
+static UDP: Mutex<Udp> = Mutex::new(Udp { sockets: Vec::new(), next_id: 1, next_port: EPHEMERAL_FIRST });
+
+/// Woken whenever a datagram is queued on any socket.
+static RX_WAIT: WaitQueue = WaitQueue::new();
+
+/// A UDP datagram from `src` to `dst` carrying `payload`.
+pub fn build(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let len = (HEADER_LEN + payload.len()) as u16;
+    let mut d = Vec::with_capacity(len as usize);
+    d.extend_from_slice(&src.port.to_be_bytes());
+    d.extend_from_slice(&dst.port.to_be_bytes());
+    d.extend_from_slice(&len.to_be_bytes());
+    d.extend_from_slice(&[0, 0]);
+    d.extend_from_slice(payload);
+    // Zero means "no checksum", so a computed zero is sent as all ones
+    let sum = match transport_checksum(src.ip, dst.ip, ipv4::PROTO_UDP, &d) { 0 => 0xFFFF, s => s };
+    d[6..8].copy_from_slice(&sum.to_be_bytes());
+    d
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Open a socket bound to `addr`, owned by the current process; port 0
+/// picks an ephemeral port.
+pub fn bind(addr: SocketAddr) -> Result<UdpHandle, &'static str> {
+    if !addr.ip.is_unspecified() && !super::is_local(addr.ip) { return Err("address not available"); }
+    let mut udp = UDP.lock();
+    let port = if addr.port == 0 { udp.ephemeral_port()? }
+        else if udp.in_use(addr) { return Err("address in use"); }
+        else { addr.port };
+    let id = udp.next_id;
+    udp.next_id = udp.next_id.wrapping_add(1).max(1);
+    udp.sockets.push(UdpSocket {
+        id, owner: crate::process::current_pid(), local: SocketAddr { ip: addr.ip, port }, peer: None,
+        queue: VecDeque::new(), nonblocking: false, error: None, drops: 0,
+    });
+    Ok(UdpHandle(id))
+}
+
+/// Fix the socket's peer: `send` goes there, and only its datagrams are
+/// received.  Anything already queued from elsewhere is discarded.
+pub fn connect(h: UdpHandle, peer: SocketAddr) -> Result<(), &'static str> {
+    let mut udp = UDP.lock();
+    let s = udp.get(h)?;
+    s.peer = Some(peer);
+    s.queue.retain(|d| d.from == peer);
+    Ok(())
+}
+
+/// Whether `recv_from` on the socket fails at once instead of waiting.
+pub fn set_nonblocking(h: UdpHandle, nonblocking: bool) -> Result<(), &'static str> {
+    UDP.lock().get(h).map(|s| s.nonblocking = nonblocking)
+}
+
+/// Send one datagram to `to`.
+pub fn send_to(h: UdpHandle, data: &[u8], to: SocketAddr) -> Result<usize, &'static str> {
+    if to.port == 0 { return Err("bad destination port"); }
+    let (owner, local) = UDP.lock().get(h).map(|s| (s.owner, s.local))?;
+    netns::check(owner, ipv4::PROTO_UDP, to)?;
+    let src = SocketAddr { ip: netns::source(owner, to.ip, local.ip)?, port: local.port };
+    let max = super::max_payload(to.ip).ok_or("no route to host")? - HEADER_LEN;
+    if data.len() > max { return Err("message too long"); }
+    super::send(Some(src.ip), to.ip, ipv4::PROTO_UDP, &build(src, to, data))?;
+    accounting::sent(owner, src.ip, to.ip, super::header_len(to.ip) + HEADER_LEN + data.len());
+    Ok(data.len())
+}
+
+/// Send one datagram to the connected peer.
+pub fn send(h: UdpHandle, data: &[u8]) -> Result<usize, &'static str> {
+    let peer = UDP.lock().get(h)?.peer.ok_or("not connected")?;
+    send_to(h, data, peer)
+}
+
+/// Take the next datagram into `buf` (truncating it if `buf` is short),
+/// returning its length and sender.  Blocks until one arrives unless the
+/// socket is non-blocking; `timeout_ms` bounds the wait.
+pub fn recv_from(h: UdpHandle, buf: &mut [u8], timeout_ms: Option<u64>) -> Result<(usize, SocketAddr), &'static str> {
+    let deadline = timeout_ms.map(|t| crate::arch::uptime_millis() + t);
+    RX_WAIT.wait_until(deadline, || {
+        let mut udp = UDP.lock();
+        let s = match udp.get(h) { Ok(s) => s, Err(e) => return Some(Err(e)) };
+        if let Some(e) = s.error.take() { return Some(Err(e)); }
+        match s.queue.pop_front() {
+            Some(d) => {
+                let n = d.data.len().min(buf.len());
+                buf[..n].copy_from_slice(&d.data[..n]);
+                Some(Ok((n, d.from)))
+            }
+            None if s.nonblocking => Some(Err("would block")),
+            None => None,
+        }
+    }).unwrap_or(Err("timed out"))
+}
+
+/// What a call on socket `h` would find: sending never waits.
+pub fn readiness(h: UdpHandle) -> Result<Ready, &'static str> {
+    let mut udp = UDP.lock();
+    let s = udp.get(h)?;
+    let mut ready = Ready::WRITABLE;
+    if !s.queue.is_empty() { ready = ready | Ready::READABLE; }
+    if s.error.is_some() { ready = ready | Ready::ERROR; }
+    Ok(ready)
+}
+
+pub fn close(h: UdpHandle) -> Result<(), &'static str> {
+    let mut udp = UDP.lock();
+    udp.get(h)?;
+    udp.sockets.retain(|s| s.id != h.0);
+    // Anyone blocked on it sees it gone
+    RX_WAIT.wake_all();
+    Ok(())
+}
+
+pub fn sockets() -> Vec<UdpInfo> {
+    UDP.lock().sockets.iter().map(|s| UdpInfo {
+        handle: UdpHandle(s.id), owner: s.owner, local: s.local, peer: s.peer, queued: s.queue.len(), drops: s.drops,
+    }).collect()
+}
+
+// ─── stack entry point ────────────────────────────────────────────────────────
+
+/// A UDP datagram from `src` to our address `dst`.  Returns false if no
+/// socket is bound to its port; one whose app may not hear from `src`
+/// drops it.
+pub fn input(src: IpAddr, dst: IpAddr, datagram: &[u8]) -> bool {
+    if datagram.len() < HEADER_LEN { return true; }
+    let len = u16::from_be_bytes([datagram[4], datagram[5]]) as usize;
+    if len < HEADER_LEN || len > datagram.len() { return true; }
+    let datagram = &datagram[..len];
+    let sum = u16::from_be_bytes([datagram[6], datagram[7]]);
+    // The checksum is optional over IPv4 only (RFC 8200 8.1)
+    if sum == 0 && matches!(dst, IpAddr::V6(_)) { return true; }
+    if sum != 0 && transport_checksum(src, dst, ipv4::PROTO_UDP, datagram) != 0 { return true; }
+    let from = SocketAddr { ip: src, port: u16::from_be_bytes([datagram[0], datagram[1]]) };
+    let to   = SocketAddr { ip: dst, port: u16::from_be_bytes([datagram[2], datagram[3]]) };
+
+    let mut udp = UDP.lock();
+    // A socket bound to the exact address wins over a wildcard one
+    let best = udp.sockets.iter().enumerate().filter(|(_, s)| s.accepts(from, to))
+        .max_by_key(|(_, s)| (s.local.ip == to.ip, s.peer.is_some()))
+        .map(|(i, _)| i);
+    let Some(i) = best else { return false };
+    let s = &mut udp.sockets[i];
+    if s.queue.len() >= RX_QUEUE || netns::admit(s.owner, ipv4::PROTO_UDP, from, to.ip).is_err() {
+        s.drops += 1;
+        return true;
+    }
+    s.queue.push_back(Datagram { from, data: datagram[HEADER_LEN..].to_vec() });
+    let owner = s.owner;
+    drop(udp);
+    accounting::received(owner, dst, src, super::header_len(src) + datagram.len());
+    RX_WAIT.wake_all();
+    true
+}
+
+/// An ICMP error about a datagram we sent from `local` to `remote`.  Only
+/// a socket connected to `remote` hears of it, as its next receive's
+/// result; an unconnected one cannot tell which of its peers it concerns.
+pub fn icmp_error(local: SocketAddr, remote: SocketAddr, error: IcmpError) {
+    let mut udp = UDP.lock();
+    for s in udp.sockets.iter_mut().filter(|s| s.local.port == local.port && s.peer == Some(remote)) {
+        s.error = Some(error.as_str());
+    }
+    drop(udp);
+    RX_WAIT.wake_all();
+}
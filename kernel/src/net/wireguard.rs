@@ -0,0 +1,835 @@
+//! WireGuard
+//! A VPN tunnel interface, `wg0`, carrying IP packets encrypted inside
+//! UDP datagrams to its peers, per the WireGuard protocol: a Noise_IKpsk2
+//! handshake over Curve25519, ChaCha20-Poly1305 and BLAKE2s, a fresh
+//! session every two minutes, and a replay window on each.  Packets the
+//! stack routes out of `wg0` go to the peer whose allowed IPs cover their
+//! destination, after a handshake if there is no live session; packets
+//! from a peer are delivered only if their source is within its allowed
+//! IPs.  A peer that sends from a new address is answered there.
+//!
+//! The device only queues: encryption and the UDP send happen in the
+//! network poll, since the stack calls devices with interfaces locked.
+//!
+//! Each peer may have a pre-shared key mixed into its handshakes.  Set
+//! from a post-quantum key exchange run alongside (as Rosenpass does), it
+//! makes the tunnel hybrid: traffic recorded now stays secret even if
+//! Curve25519 falls later.
+//!
+//! Routing: the peers' allowed IPs are routed into the tunnel, or, with
+//! `set_route_all`, every destination that is not on-link, except the
+//! peers' own endpoints.  Routing all traffic holds while the tunnel is
+//! down, so nothing leaks around it.
+//!
+//! Cookie replies, which a responder under load sends, are not answered;
+//! the handshake is retried instead.  We never send them.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::udp::{self, UdpHandle};
+use super::{networkd, IpAddr, Ipv4Addr, Ipv6Addr, MacAddr, NetDevice, SocketAddr};
+use crate::crypto::blake2s;
+use crate::crypto::chacha20poly1305 as aead;
+use crate::crypto::x25519;
+
+pub const KEY_LEN:      usize = 32;
+pub const DEFAULT_PORT: u16   = 51820;
+pub const NAME:         &str  = "wg0";
+/// 1500 less an outer IPv6 and UDP header and our own 32 bytes.
+pub const MTU:          usize = 1420;
+
+const CONSTRUCTION: &[u8] = b"Noise_IKpsk2_25519_ChaChaPoly_BLAKE2s";
+const IDENTIFIER:   &[u8] = b"WireGuard v1 zx2c4 Jason@zx2c4.com";
+const LABEL_MAC1:   &[u8] = b"mac1----";
+
+const MSG_INITIATION: u8 = 1;
+const MSG_RESPONSE:   u8 = 2;
+const MSG_DATA:       u8 = 4;
+
+const INITIATION_LEN: usize = 148;
+const RESPONSE_LEN:   usize = 92;
+/// Type, receiver index and counter in front of the ciphertext.
+const DATA_HEADER:    usize = 16;
+const TIMESTAMP_LEN:  usize = 12;
+
+const REKEY_AFTER_MESSAGES:  u64 = 1 << 60;
+const REJECT_AFTER_MESSAGES: u64 = u64::MAX - (1 << 13);
+const REKEY_AFTER_MS:        u64 = 120_000;
+const REJECT_AFTER_MS:       u64 = 180_000;
+/// How long to keep retrying a handshake before dropping what waits on it.
+const REKEY_ATTEMPT_MS:      u64 = 90_000;
+const REKEY_TIMEOUT_MS:      u64 = 5_000;
+const KEEPALIVE_MS:          u64 = 10_000;
+/// Packets held per peer while a handshake completes.
+const PENDING_QUEUE:         usize = 64;
+/// Packets the device holds in each direction before it drops.
+const DEVICE_QUEUE:          usize = 256;
+/// Counters a session remembers behind the highest seen, in 64-bit words.
+const REPLAY_WORDS:          usize = 32;
+
+// ─── Noise ────────────────────────────────────────────────────────────────────
+
+/// HKDF over HMAC-BLAKE2s, as the Noise spec defines it, `N` outputs.
+fn kdf<const N: usize>(key: &[u8; KEY_LEN], input: &[u8]) -> [[u8; KEY_LEN]; N] {
+    let prk = blake2s::hmac(key, &[input]);
+    let mut out = [[0u8; KEY_LEN]; N];
+    let mut prev: [u8; KEY_LEN] = [0; KEY_LEN];
+    for (i, o) in out.iter_mut().enumerate() {
+        let t: &[u8] = if i == 0 { &[] } else { &prev };
+        prev = blake2s::hmac(&prk, &[t, &[i as u8 + 1]]);
+        *o = prev;
+    }
+    out
+}
+
+/// Diffie-Hellman, refusing the all-zero result a low-order point gives.
+fn dh(private: &[u8; KEY_LEN], public: &[u8; KEY_LEN]) -> Result<[u8; KEY_LEN], &'static str> {
+    let shared = x25519::x25519(private, public);
+    if shared == [0; KEY_LEN] { return Err("bad public key"); }
+    Ok(shared)
+}
+
+/// The key a message's mac1 is made with: its recipient's public key.
+fn mac1_key(public: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    blake2s::hash(&[LABEL_MAC1, public])
+}
+
+fn nonce(counter: u64) -> [u8; aead::NONCE_LEN] {
+    let mut n = [0u8; aead::NONCE_LEN];
+    n[4..].copy_from_slice(&counter.to_le_bytes());
+    n
+}
+
+/// TAI64N: an initiation's timestamp, which must grow between a peer's
+/// handshakes so a captured one cannot be replayed.
+fn tai64n() -> [u8; TIMESTAMP_LEN] {
+    let ns = crate::alarm::rtc_now_ns();
+    let mut t = [0u8; TIMESTAMP_LEN];
+    t[..8].copy_from_slice(&(0x4000_0000_0000_000A + ns / 1_000_000_000).to_be_bytes());
+    t[8..].copy_from_slice(&((ns % 1_000_000_000) as u32).to_be_bytes());
+    t
+}
+
+/// Chaining key and transcript hash.
+#[derive(Clone)]
+struct Noise {
+    ck: [u8; KEY_LEN],
+    h:  [u8; KEY_LEN],
+}
+
+impl Noise {
+    fn new(responder: &[u8; KEY_LEN]) -> Noise {
+        let ck = blake2s::hash(&[CONSTRUCTION]);
+        let h = blake2s::hash(&[&ck, IDENTIFIER]);
+        Noise { ck, h: blake2s::hash(&[&h, responder]) }
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        self.h = blake2s::hash(&[&self.h, data]);
+    }
+
+    fn mix(&mut self, input: &[u8]) {
+        [self.ck] = kdf(&self.ck, input);
+    }
+
+    fn mix_ephemeral(&mut self, public: &[u8; KEY_LEN]) {
+        self.mix(public);
+        self.mix_hash(public);
+    }
+
+    /// Mix in a shared secret, returning a key to encrypt with.
+    fn mix_key(&mut self, input: &[u8]) -> [u8; KEY_LEN] {
+        let [ck, k] = kdf(&self.ck, input);
+        self.ck = ck;
+        k
+    }
+
+    fn mix_psk(&mut self, psk: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+        let [ck, t, k] = kdf(&self.ck, psk);
+        self.ck = ck;
+        self.mix_hash(&t);
+        k
+    }
+
+    fn encrypt(&mut self, key: &[u8; KEY_LEN], plaintext: &[u8]) -> Vec<u8> {
+        let c = aead::seal(key, &[0; aead::NONCE_LEN], &self.h, plaintext);
+        self.mix_hash(&c);
+        c
+    }
+
+    fn decrypt(&mut self, key: &[u8; KEY_LEN], sealed: &[u8]) -> Result<Vec<u8>, &'static str> {
+        let p = aead::open(key, &[0; aead::NONCE_LEN], &self.h, sealed)?;
+        self.mix_hash(sealed);
+        Ok(p)
+    }
+
+    /// Transport keys once the handshake is done: the initiator's sending
+    /// key, then the responder's.
+    fn split(&self) -> [[u8; KEY_LEN]; 2] {
+        kdf(&self.ck, &[])
+    }
+}
+
+// ─── sessions ─────────────────────────────────────────────────────────────────
+
+/// Counters seen near the highest so far (RFC 6479): a bitmap used as a
+/// ring of words, cleared a word at a time as the highest advances.
+struct Replay {
+    top:  u64,
+    bits: [u64; REPLAY_WORDS],
+}
+
+impl Replay {
+    /// Record `n`, or return false if it is a repeat or too old.
+    fn check(&mut self, n: u64) -> bool {
+        if n >= REJECT_AFTER_MESSAGES { return false; }
+        let block = n / 64;
+        if n > self.top {
+            let top_block = self.top / 64;
+            for b in top_block + 1..=block.min(top_block + REPLAY_WORDS as u64) {
+                self.bits[b as usize % REPLAY_WORDS] = 0;
+            }
+            self.top = n;
+        } else if self.top - n >= (REPLAY_WORDS as u64 - 1) * 64 {
+            return false;
+        }
+        let word = &mut self.bits[block as usize % REPLAY_WORDS];
+        let bit = 1u64 << (n % 64);
+        if *word & bit != 0 { return false; }
+        *word |= bit;
+        true
+    }
+}
+
+struct Session {
+    /// Index the peer sends to us under, and we to it.
+    local:     u32,
+    remote:    u32,
+    send:      [u8; KEY_LEN],
+    recv:      [u8; KEY_LEN],
+    /// Next counter we send.
+    counter:   u64,
+    replay:    Replay,
+    created:   u64,
+    /// Whether we began the handshake, and so are the one to renew it.
+    initiator: bool,
+}
+
+impl Session {
+    fn new(local: u32, remote: u32, [send, recv]: [[u8; KEY_LEN]; 2], now: u64, initiator: bool) -> Session {
+        Session {
+            local, remote, send, recv, counter: 0, replay: Replay { top: 0, bits: [0; REPLAY_WORDS] },
+            created: now, initiator,
+        }
+    }
+
+    fn expired(&self, now: u64) -> bool {
+        now - self.created >= REJECT_AFTER_MS
+    }
+
+    fn usable(&self, now: u64) -> bool {
+        !self.expired(now) && self.counter < REJECT_AFTER_MESSAGES
+    }
+
+    fn needs_rekey(&self, now: u64) -> bool {
+        self.initiator && (now - self.created >= REKEY_AFTER_MS || self.counter >= REKEY_AFTER_MESSAGES)
+    }
+
+    /// A data message carrying `packet`, padded to a multiple of 16.
+    fn seal(&mut self, packet: &[u8]) -> Vec<u8> {
+        let mut padded = packet.to_vec();
+        padded.resize(packet.len().next_multiple_of(16).min(MTU.max(packet.len())), 0);
+        let mut msg = Vec::with_capacity(DATA_HEADER + padded.len() + aead::TAG_LEN);
+        msg.extend_from_slice(&[MSG_DATA, 0, 0, 0]);
+        msg.extend_from_slice(&self.remote.to_le_bytes());
+        msg.extend_from_slice(&self.counter.to_le_bytes());
+        msg.extend_from_slice(&aead::seal(&self.send, &nonce(self.counter), &[], &padded));
+        self.counter += 1;
+        msg
+    }
+}
+
+// ─── peers ────────────────────────────────────────────────────────────────────
+
+/// Our initiation awaiting its response.
+struct Initiation {
+    index:     u32,
+    ephemeral: [u8; KEY_LEN],
+    noise:     Noise,
+    sent:      u64,
+}
+
+struct Peer {
+    public:         [u8; KEY_LEN],
+    psk:            [u8; KEY_LEN],
+    endpoint:       Option<SocketAddr>,
+    allowed:        Vec<(IpAddr, u8)>,
+    /// Persistent keepalive interval, or 0.
+    keepalive_ms:   u64,
+    initiation:     Option<Initiation>,
+    /// When we began trying to reach the peer.
+    attempting:     Option<u64>,
+    current:        Option<Session>,
+    previous:       Option<Session>,
+    /// A session we answered, unused until the initiator's first data
+    /// message confirms it.
+    next:           Option<Session>,
+    /// Packets waiting for a session.
+    pending:        VecDeque<Vec<u8>>,
+    /// Greatest initiation timestamp the peer has sent.
+    last_timestamp: [u8; TIMESTAMP_LEN],
+    last_handshake: Option<u64>,
+    last_sent:      u64,
+    /// Since when data we sent has gone unanswered.
+    unanswered:     Option<u64>,
+    /// Since when data the peer sent has gone unanswered.
+    owed:           Option<u64>,
+    rx_bytes:       u64,
+    tx_bytes:       u64,
+}
+
+/// A peer as configured by callers.
+#[derive(Debug, Clone)]
+pub struct PeerConfig {
+    pub public_key:     [u8; KEY_LEN],
+    pub preshared_key:  Option<[u8; KEY_LEN]>,
+    /// Where to send; learned from the peer if not given.
+    pub endpoint:       Option<SocketAddr>,
+    pub allowed_ips:    Vec<(IpAddr, u8)>,
+    /// Persistent keepalive interval, to hold NAT mappings open; 0 is off.
+    pub keepalive_secs: u16,
+}
+
+/// A peer as reported to callers.
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub public_key:     [u8; KEY_LEN],
+    pub endpoint:       Option<SocketAddr>,
+    pub allowed_ips:    Vec<(IpAddr, u8)>,
+    pub keepalive_secs: u16,
+    /// Seconds since the last completed handshake.
+    pub handshake_age:  Option<u64>,
+    pub rx_bytes:       u64,
+    pub tx_bytes:       u64,
+}
+
+impl Peer {
+    fn new(c: PeerConfig) -> Peer {
+        Peer {
+            public: c.public_key, psk: c.preshared_key.unwrap_or([0; KEY_LEN]), endpoint: c.endpoint,
+            allowed: c.allowed_ips, keepalive_ms: c.keepalive_secs as u64 * 1000, initiation: None,
+            attempting: None, current: None, previous: None, next: None, pending: VecDeque::new(),
+            last_timestamp: [0; TIMESTAMP_LEN], last_handshake: None, last_sent: 0, unanswered: None,
+            owed: None, rx_bytes: 0, tx_bytes: 0,
+        }
+    }
+
+    /// Forget sessions and anything in flight.
+    fn reset(&mut self) {
+        self.initiation = None;
+        self.attempting = None;
+        self.current = None;
+        self.previous = None;
+        self.next = None;
+        self.pending.clear();
+        self.unanswered = None;
+        self.owed = None;
+    }
+
+    fn session(&mut self, local: u32) -> Option<&mut Session> {
+        [&mut self.current, &mut self.previous, &mut self.next].into_iter()
+            .find_map(|s| s.as_mut().filter(|s| s.local == local))
+    }
+
+    fn live(&mut self, now: u64) -> Option<&mut Session> {
+        self.current.as_mut().filter(|s| s.usable(now))
+    }
+
+    fn info(&self, now: u64) -> PeerInfo {
+        PeerInfo {
+            public_key: self.public, endpoint: self.endpoint, allowed_ips: self.allowed.clone(),
+            keepalive_secs: (self.keepalive_ms / 1000) as u16,
+            handshake_age: self.last_handshake.map(|t| (now - t) / 1000),
+            rx_bytes: self.rx_bytes, tx_bytes: self.tx_bytes,
+        }
+    }
+
+    /// Send a message to the peer's endpoint.
+    fn transmit(&mut self, socket: UdpHandle, msg: &[u8], now: u64) {
+        let Some(to) = self.endpoint else { return };
+        if udp::send_to(socket, msg, to).is_ok() {
+            self.tx_bytes += msg.len() as u64;
+            self.last_sent = now;
+            self.owed = None;
+        }
+    }
+
+    /// Encrypt and send an IP packet, or an empty keepalive.
+    fn send_packet(&mut self, socket: UdpHandle, packet: &[u8], now: u64) {
+        let Some(s) = self.live(now) else { return };
+        let msg = s.seal(packet);
+        self.transmit(socket, &msg, now);
+        if !packet.is_empty() { self.unanswered.get_or_insert(now); }
+    }
+}
+
+/// The peer whose allowed IPs most specifically cover `ip`.
+fn peer_for(peers: &[Peer], ip: IpAddr) -> Option<usize> {
+    peers.iter().enumerate()
+        .filter_map(|(i, p)| p.allowed.iter().filter(|&&(a, len)| ip.same_prefix(a, len)).map(|&(_, len)| (len, i)).max())
+        .max()
+        .map(|(_, i)| i)
+}
+
+// ─── device ───────────────────────────────────────────────────────────────────
+
+/// Packets the stack sent out of `wg0`, for the poll to encrypt.
+static OUTBOUND: Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+/// Packets peers sent, decrypted, for the stack to receive.
+static INBOUND:  Mutex<VecDeque<Vec<u8>>> = Mutex::new(VecDeque::new());
+
+struct Tunnel;
+
+impl NetDevice for Tunnel {
+    fn mac(&self) -> Option<MacAddr> {
+        None
+    }
+
+    fn mtu(&self) -> usize {
+        MTU
+    }
+
+    fn transmit(&mut self, packet: &[u8]) -> Result<(), &'static str> {
+        let mut q = OUTBOUND.lock();
+        if q.len() >= DEVICE_QUEUE { return Err("tunnel queue full"); }
+        q.push_back(packet.to_vec());
+        drop(q);
+        super::rx_ready();
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        INBOUND.lock().pop_front()
+    }
+}
+
+/// Source and destination of an IP packet, and its length without any
+/// padding after it.
+fn addresses(p: &[u8]) -> Option<(IpAddr, IpAddr, usize)> {
+    match p.first()? >> 4 {
+        4 if p.len() >= 20 => {
+            let len = u16::from_be_bytes([p[2], p[3]]) as usize;
+            let ip = |i: usize| IpAddr::V4(Ipv4Addr(p[i..i + 4].try_into().unwrap()));
+            (len <= p.len()).then(|| (ip(12), ip(16), len))
+        }
+        6 if p.len() >= 40 => {
+            let len = 40 + u16::from_be_bytes([p[4], p[5]]) as usize;
+            let ip = |i: usize| IpAddr::V6(Ipv6Addr(p[i..i + 16].try_into().unwrap()));
+            (len <= p.len()).then(|| (ip(8), ip(24), len))
+        }
+        _ => None,
+    }
+}
+
+// ─── state ────────────────────────────────────────────────────────────────────
+
+struct Wg {
+    private:   Option<[u8; KEY_LEN]>,
+    public:    [u8; KEY_LEN],
+    /// Checks mac1 on messages to us.
+    mac1_key:  [u8; KEY_LEN],
+    socket:    Option<UdpHandle>,
+    port:      u16,
+    /// The `wg0` interface, once created.
+    index:     Option<usize>,
+    peers:     Vec<Peer>,
+    route_all: bool,
+}
+
+static WG: Mutex<Wg> = Mutex::new(Wg {
+    private: None, public: [0; KEY_LEN], mac1_key: [0; KEY_LEN], socket: None, port: DEFAULT_PORT,
+    index: None, peers: Vec::new(), route_all: false,
+});
+
+impl Wg {
+    fn set_key(&mut self, private: [u8; KEY_LEN]) {
+        self.private = Some(private);
+        self.public = x25519::public_key(&private);
+        self.mac1_key = mac1_key(&self.public);
+        for p in self.peers.iter_mut() { p.reset(); }
+    }
+
+    /// A session index no session or initiation is using.
+    fn new_index(&self) -> u32 {
+        loop {
+            let i = crate::entropy::next_u32();
+            let used = self.peers.iter().any(|p| {
+                p.initiation.as_ref().is_some_and(|h| h.index == i)
+                    || [&p.current, &p.previous, &p.next].iter().any(|s| s.as_ref().is_some_and(|s| s.local == i))
+            });
+            if !used { return i; }
+        }
+    }
+
+    /// Point the stack's tunnel route at `wg0`: every destination when
+    /// routing all traffic, else the peers' allowed IPs.
+    fn sync_routes(&self) {
+        let Some(index) = self.index else { return };
+        if self.socket.is_none() && !self.route_all {
+            super::clear_tunnel_route();
+            return;
+        }
+        let prefixes = if self.route_all {
+            vec![(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0), (IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)]
+        } else {
+            self.peers.iter().flat_map(|p| p.allowed.iter().copied()).collect()
+        };
+        let exempt = self.peers.iter().filter_map(|p| p.endpoint.map(|e| e.ip)).collect();
+        super::set_tunnel_route(index, prefixes, exempt);
+    }
+
+    /// Answer peer `p` where it last sent from.
+    fn roam(&mut self, p: usize, from: SocketAddr) {
+        if self.peers[p].endpoint == Some(from) { return; }
+        self.peers[p].endpoint = Some(from);
+        // Its endpoint must stay out of the tunnel
+        self.sync_routes();
+    }
+
+    // ─── handshake ───
+
+    /// Send peer `p` a handshake initiation, unless one is in flight.
+    fn initiate(&mut self, p: usize, now: u64) {
+        let (Some(private), Some(socket)) = (self.private, self.socket) else { return };
+        if self.peers[p].initiation.is_some() || self.peers[p].endpoint.is_none() { return; }
+        let (public, index) = (self.public, self.new_index());
+        let peer = &mut self.peers[p];
+        peer.attempting.get_or_insert(now);
+
+        let mut ephemeral = [0u8; KEY_LEN];
+        crate::entropy::fill_bytes(&mut ephemeral);
+        let e = x25519::public_key(&ephemeral);
+        let mut noise = Noise::new(&peer.public);
+        let mut msg = Vec::with_capacity(INITIATION_LEN);
+        msg.extend_from_slice(&[MSG_INITIATION, 0, 0, 0]);
+        msg.extend_from_slice(&index.to_le_bytes());
+        noise.mix_ephemeral(&e);
+        msg.extend_from_slice(&e);
+        let (Ok(es), Ok(ss)) = (dh(&ephemeral, &peer.public), dh(&private, &peer.public)) else { return };
+        let k = noise.mix_key(&es);
+        msg.extend_from_slice(&noise.encrypt(&k, &public));
+        let k = noise.mix_key(&ss);
+        msg.extend_from_slice(&noise.encrypt(&k, &tai64n()));
+        msg.extend_from_slice(&blake2s::mac(&mac1_key(&peer.public), &msg));
+        msg.extend_from_slice(&[0; 16]);
+
+        peer.initiation = Some(Initiation { index, ephemeral, noise, sent: now });
+        peer.transmit(socket, &msg, now);
+    }
+
+    /// Answer an initiation from a configured peer; its session waits for
+    /// the initiator's first data message.
+    fn answer(&mut self, msg: &[u8], from: SocketAddr, now: u64) -> Result<(), &'static str> {
+        let (private, socket) = (self.private.ok_or("no key")?, self.socket.ok_or("down")?);
+        let index = self.new_index();
+        let sender = u32::from_le_bytes(msg[4..8].try_into().unwrap());
+        let ei: [u8; KEY_LEN] = msg[8..40].try_into().unwrap();
+        let mut noise = Noise::new(&self.public);
+        noise.mix_ephemeral(&ei);
+        let k = noise.mix_key(&dh(&private, &ei)?);
+        let si: [u8; KEY_LEN] = noise.decrypt(&k, &msg[40..88])?.try_into().map_err(|_| "bad initiation")?;
+        let p = self.peers.iter().position(|p| p.public == si).ok_or("unknown peer")?;
+        let peer = &mut self.peers[p];
+        let k = noise.mix_key(&dh(&private, &si)?);
+        let ts: [u8; TIMESTAMP_LEN] = noise.decrypt(&k, &msg[88..116])?.try_into().map_err(|_| "bad initiation")?;
+        if ts <= peer.last_timestamp { return Err("replayed initiation"); }
+
+        let mut ephemeral = [0u8; KEY_LEN];
+        crate::entropy::fill_bytes(&mut ephemeral);
+        let e = x25519::public_key(&ephemeral);
+        let mut r = Vec::with_capacity(RESPONSE_LEN);
+        r.extend_from_slice(&[MSG_RESPONSE, 0, 0, 0]);
+        r.extend_from_slice(&index.to_le_bytes());
+        r.extend_from_slice(&sender.to_le_bytes());
+        noise.mix_ephemeral(&e);
+        r.extend_from_slice(&e);
+        noise.mix(&dh(&ephemeral, &ei)?);
+        noise.mix(&dh(&ephemeral, &si)?);
+        let k = noise.mix_psk(&peer.psk);
+        r.extend_from_slice(&noise.encrypt(&k, &[]));
+        r.extend_from_slice(&blake2s::mac(&mac1_key(&si), &r));
+        r.extend_from_slice(&[0; 16]);
+
+        let [recv, send] = noise.split();
+        peer.last_timestamp = ts;
+        peer.next = Some(Session::new(index, sender, [send, recv], now, false));
+        peer.last_handshake = Some(now);
+        self.roam(p, from);
+        self.peers[p].transmit(socket, &r, now);
+        Ok(())
+    }
+
+    /// Complete our handshake with the response to it, then send what was
+    /// waiting, or a keepalive to confirm the session.
+    fn complete(&mut self, msg: &[u8], from: SocketAddr, now: u64) -> Result<(), &'static str> {
+        let (private, socket) = (self.private.ok_or("no key")?, self.socket.ok_or("down")?);
+        let sender = u32::from_le_bytes(msg[4..8].try_into().unwrap());
+        let receiver = u32::from_le_bytes(msg[8..12].try_into().unwrap());
+        let p = self.peers.iter().position(|p| p.initiation.as_ref().is_some_and(|h| h.index == receiver))
+            .ok_or("unexpected response")?;
+        let peer = &mut self.peers[p];
+        let h = peer.initiation.as_ref().unwrap();
+        let mut noise = h.noise.clone();
+        let er: [u8; KEY_LEN] = msg[12..44].try_into().unwrap();
+        noise.mix_ephemeral(&er);
+        noise.mix(&dh(&h.ephemeral, &er)?);
+        noise.mix(&dh(&private, &er)?);
+        let k = noise.mix_psk(&peer.psk);
+        noise.decrypt(&k, &msg[44..60])?;
+
+        peer.previous = peer.current.replace(Session::new(receiver, sender, noise.split(), now, true));
+        peer.initiation = None;
+        peer.attempting = None;
+        peer.last_handshake = Some(now);
+        self.roam(p, from);
+        let peer = &mut self.peers[p];
+        if peer.pending.is_empty() {
+            peer.send_packet(socket, &[], now);
+        }
+        while let Some(packet) = peer.pending.pop_front() {
+            peer.send_packet(socket, &packet, now);
+        }
+        Ok(())
+    }
+
+    // ─── data ───
+
+    /// A data message; decrypted packets are queued on the device, and
+    /// true returned if there were any.
+    fn receive_data(&mut self, msg: &[u8], from: SocketAddr, now: u64) -> bool {
+        if msg.len() < DATA_HEADER + aead::TAG_LEN { return false; }
+        let receiver = u32::from_le_bytes(msg[4..8].try_into().unwrap());
+        let counter = u64::from_le_bytes(msg[8..16].try_into().unwrap());
+        let Some(p) = self.peers.iter_mut().position(|p| p.session(receiver).is_some()) else { return false };
+        let peer = &mut self.peers[p];
+        let s = peer.session(receiver).unwrap();
+        if s.expired(now) { return false; }
+        let Ok(packet) = aead::open(&s.recv, &nonce(counter), &[], &msg[DATA_HEADER..]) else { return false };
+        if !s.replay.check(counter) { return false; }
+        let renew = s.initiator && now - s.created >= REJECT_AFTER_MS - KEEPALIVE_MS - REKEY_TIMEOUT_MS;
+        if peer.next.as_ref().is_some_and(|s| s.local == receiver) {
+            peer.previous = peer.current.take();
+            peer.current = peer.next.take();
+        }
+        peer.rx_bytes += msg.len() as u64;
+        peer.unanswered = None;
+        self.roam(p, from);
+        if renew { self.initiate(p, now); }
+        // Empty is a keepalive
+        if packet.is_empty() { return false; }
+        self.peers[p].owed.get_or_insert(now);
+        let Some((src, _, len)) = addresses(&packet) else { return false };
+        if peer_for(&self.peers, src) != Some(p) { return false; }
+        let mut q = INBOUND.lock();
+        if q.len() >= DEVICE_QUEUE { return false; }
+        q.push_back(packet[..len].to_vec());
+        true
+    }
+
+    /// A packet the stack sent out of `wg0`.
+    fn send(&mut self, packet: Vec<u8>, now: u64) {
+        let Some(socket) = self.socket else { return };
+        let Some((_, dst, _)) = addresses(&packet) else { return };
+        let Some(p) = peer_for(&self.peers, dst) else { return };
+        let peer = &mut self.peers[p];
+        match peer.live(now).map(|s| s.needs_rekey(now)) {
+            Some(rekey) => {
+                peer.send_packet(socket, &packet, now);
+                if rekey { self.initiate(p, now); }
+            }
+            None => {
+                if peer.pending.len() >= PENDING_QUEUE { peer.pending.pop_front(); }
+                peer.pending.push_back(packet);
+                self.initiate(p, now);
+            }
+        }
+    }
+
+    fn timers(&mut self, p: usize, now: u64) {
+        let Some(socket) = self.socket else { return };
+        let peer = &mut self.peers[p];
+        for s in [&mut peer.current, &mut peer.previous, &mut peer.next] {
+            if s.as_ref().is_some_and(|s| s.expired(now)) { *s = None; }
+        }
+        // Retry an unanswered initiation, or give up and drop what waits
+        if peer.initiation.as_ref().is_some_and(|h| now - h.sent >= REKEY_TIMEOUT_MS) {
+            peer.initiation = None;
+            if peer.attempting.is_some_and(|t| now - t >= REKEY_ATTEMPT_MS) {
+                peer.attempting = None;
+                peer.pending.clear();
+            } else {
+                self.initiate(p, now);
+            }
+            return;
+        }
+        // Data going unanswered suggests the peer lost our session
+        if peer.unanswered.is_some_and(|t| now - t >= KEEPALIVE_MS + REKEY_TIMEOUT_MS) {
+            peer.unanswered = None;
+            self.initiate(p, now);
+            return;
+        }
+        // Acknowledge received data if we had nothing to send back
+        if peer.owed.is_some_and(|t| now - t >= KEEPALIVE_MS) {
+            peer.send_packet(socket, &[], now);
+            peer.owed = None;
+        }
+        if peer.keepalive_ms > 0 && now - peer.last_sent >= peer.keepalive_ms {
+            if peer.live(now).is_some() { peer.send_packet(socket, &[], now); } else { self.initiate(p, now); }
+        }
+    }
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Set our static private key, or make one if `None`; returns the public
+/// key.  Existing sessions end.
+pub fn set_private_key(key: Option<[u8; KEY_LEN]>) -> [u8; KEY_LEN] {
+    let key = key.unwrap_or_else(|| {
+        let mut k = [0u8; KEY_LEN];
+        crate::entropy::fill_bytes(&mut k);
+        k
+    });
+    let mut wg = WG.lock();
+    wg.set_key(key);
+    wg.public
+}
+
+pub fn public_key() -> Option<[u8; KEY_LEN]> {
+    let wg = WG.lock();
+    wg.private.map(|_| wg.public)
+}
+
+/// Bring the tunnel up listening on `port`, creating `wg0` the first
+/// time; returns its interface index.  A private key is made if none was
+/// set.
+pub fn up(port: u16) -> Result<usize, &'static str> {
+    if public_key().is_none() { set_private_key(None); }
+    networkd::run(|| {
+        let mut wg = WG.lock();
+        if wg.socket.is_some() { return Err("tunnel already up"); }
+        let socket = udp::bind(SocketAddr::new(Ipv4Addr::UNSPECIFIED, port))?;
+        udp::set_nonblocking(socket, true)?;
+        wg.socket = Some(socket);
+        wg.port = port;
+        let index = *wg.index.get_or_insert_with(|| super::attach(NAME, Box::new(Tunnel)));
+        super::set_up(index, true)?;
+        wg.sync_routes();
+        Ok(index)
+    })
+}
+
+/// Take the tunnel down, ending every session.  If all traffic is routed
+/// through it, it stays so routed, and unsent.
+pub fn down() -> Result<(), &'static str> {
+    let mut wg = WG.lock();
+    let socket = wg.socket.take().ok_or("tunnel not up")?;
+    let _ = networkd::run(|| udp::close(socket));
+    for p in wg.peers.iter_mut() { p.reset(); }
+    if let Some(index) = wg.index { let _ = super::set_up(index, false); }
+    OUTBOUND.lock().clear();
+    INBOUND.lock().clear();
+    wg.sync_routes();
+    Ok(())
+}
+
+pub fn is_up() -> bool {
+    WG.lock().socket.is_some()
+}
+
+/// The `wg0` interface and the port it listens on.
+pub fn interface() -> Option<(usize, u16)> {
+    let wg = WG.lock();
+    wg.index.map(|i| (i, wg.port))
+}
+
+/// Add a peer, or reconfigure the one with the same public key.
+pub fn add_peer(config: PeerConfig) -> Result<(), &'static str> {
+    for &(ip, len) in &config.allowed_ips {
+        if len > if matches!(ip, IpAddr::V4(_)) { 32 } else { 128 } { return Err("bad prefix length"); }
+    }
+    let mut wg = WG.lock();
+    if wg.private.is_some() && config.public_key == wg.public { return Err("that is our own key"); }
+    match wg.peers.iter_mut().find(|p| p.public == config.public_key) {
+        Some(p) => {
+            p.psk = config.preshared_key.unwrap_or([0; KEY_LEN]);
+            if config.endpoint.is_some() { p.endpoint = config.endpoint; }
+            p.allowed = config.allowed_ips;
+            p.keepalive_ms = config.keepalive_secs as u64 * 1000;
+        }
+        None => wg.peers.push(Peer::new(config)),
+    }
+    wg.sync_routes();
+    Ok(())
+}
+
+pub fn remove_peer(public_key: &[u8; KEY_LEN]) -> Result<(), &'static str> {
+    let mut wg = WG.lock();
+    let i = wg.peers.iter().position(|p| p.public == *public_key).ok_or("no such peer")?;
+    wg.peers.remove(i);
+    wg.sync_routes();
+    Ok(())
+}
+
+/// Set the key mixed into handshakes with a peer, say one agreed by a
+/// post-quantum KEM; it takes effect from the next handshake.
+pub fn set_preshared_key(public_key: &[u8; KEY_LEN], psk: [u8; KEY_LEN]) -> Result<(), &'static str> {
+    let mut wg = WG.lock();
+    let p = wg.peers.iter_mut().find(|p| p.public == *public_key).ok_or("no such peer")?;
+    p.psk = psk;
+    Ok(())
+}
+
+/// Route all traffic through the tunnel, not just the peers' allowed IPs.
+pub fn set_route_all(on: bool) {
+    let mut wg = WG.lock();
+    wg.route_all = on;
+    wg.sync_routes();
+}
+
+pub fn route_all() -> bool {
+    WG.lock().route_all
+}
+
+pub fn peers() -> Vec<PeerInfo> {
+    let now = crate::arch::uptime_millis();
+    WG.lock().peers.iter().map(|p| p.info(now)).collect()
+}
+
+/// Handle messages from peers, packets sent out of `wg0`, and timers;
+/// called from the network poll.
+pub(super) fn tick(now: u64) {
+    let outbound: Vec<Vec<u8>> = OUTBOUND.lock().drain(..).collect();
+    let mut wg = WG.lock();
+    let Some(socket) = wg.socket else { return };
+    let mac1_key = wg.mac1_key;
+    let mut delivered = false;
+    let mut buf = [0u8; 2048];
+    while let Ok((n, from)) = udp::recv_from(socket, &mut buf, None) {
+        let msg = &buf[..n];
+        let mac_ok = |len: usize| n == len && blake2s::mac(&mac1_key, &msg[..len - 32]) == msg[len - 32..len - 16];
+        match msg.first().copied().unwrap_or(0) {
+            MSG_INITIATION if mac_ok(INITIATION_LEN) => { let _ = wg.answer(msg, from, now); }
+            MSG_RESPONSE if mac_ok(RESPONSE_LEN)     => { let _ = wg.complete(msg, from, now); }
+            MSG_DATA                                 => delivered |= wg.receive_data(msg, from, now),
+            _ => {}
+        }
+    }
+    for packet in outbound { wg.send(packet, now); }
+    for p in 0..wg.peers.len() { wg.timers(p, now); }
+    drop(wg);
+    if delivered { super::rx_ready(); }
+}
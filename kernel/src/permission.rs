@@ -0,0 +1,523 @@
+//! SurakshaOS Permission Manager
+//! Capabilities are what the kernel checks; permissions are what the user
+//! is asked about.  permd maps each user-facing permission (camera,
+//! microphone, location, contacts) to the capabilities that carry it out,
+//! and mints them for an app only with the user's consent.
+//!
+//! An app is a process the launcher bound to an app name with
+//! `register_app`.  When it asks for a permission the user has not decided
+//! on, permd queues a prompt and notifies the consent agent — the system
+//! UI, connected with the PermissionAdmin capability — which answers it:
+//!
+//! - always: the grant is made and the decision is remembered;
+//! - once: the grant is made, expires after ONE_TIME_MS or when the app
+//!   releases it, and nothing is remembered;
+//! - deny: nothing is granted and the refusal is remembered.
+//!
+//! Decisions persist in CONFIG_PATH.  The agent can change one at any time
+//! (a settings page); taking a permission away revokes every capability
+//! granted under it at once, and the app is notified.  A quarantined app
+//! loses all its grants and is refused everything until released.
+//!
+//! The agent also answers prompts other services raise for a single
+//! transfer, such as the Android bridge's (`vmbridge`).
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::driver::{self, DeviceClass};
+use crate::fs;
+use crate::ipc::{self, ChannelId, Message, MessageKind};
+use crate::process::{self, ProcessId};
+
+pub const CONFIG_PATH: &str = "/etc/permissions.conf";
+
+/// Longest app name.
+pub const MAX_APP_NAME: usize = 64;
+
+/// How long a one-time grant lasts.
+pub const ONE_TIME_MS: u64 = 5 * 60_000;
+
+// ─── permissions ──────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Camera,
+    Microphone,
+    Location,
+    Contacts,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 4] =
+        [Permission::Camera, Permission::Microphone, Permission::Location, Permission::Contacts];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::Camera     => "camera",
+            Permission::Microphone => "microphone",
+            Permission::Location   => "location",
+            Permission::Contacts   => "contacts",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Permission> {
+        Permission::ALL.into_iter().find(|p| p.as_str() == s)
+    }
+
+    fn from_u8(v: u8) -> Option<Permission> {
+        Permission::ALL.get(v as usize).copied()
+    }
+
+    /// The capabilities a grant of this permission consists of.
+    fn capabilities(self) -> Vec<(CapabilityType, Permissions)> {
+        let devices = |class: DeviceClass, perms: Permissions| -> Vec<(CapabilityType, Permissions)> {
+            driver::devices().into_iter()
+                .filter(|d| d.class == class)
+                .map(|d| (CapabilityType::Device(d.id), perms))
+                .collect()
+        };
+        match self {
+            Permission::Camera     => devices(DeviceClass::Camera, Permissions::READ | Permissions::CONTROL),
+            Permission::Microphone => devices(DeviceClass::Audio, Permissions::READ),
+            Permission::Location   => devices(DeviceClass::Location, Permissions::READ),
+            Permission::Contacts   => Vec::from([(CapabilityType::Contacts, Permissions::READ)]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Answer {
+    Always,
+    Once,
+    Deny,
+}
+
+impl Answer {
+    pub fn parse(s: &str) -> Option<Answer> {
+        match s {
+            "always" => Some(Answer::Always),
+            "once"   => Some(Answer::Once),
+            "deny"   => Some(Answer::Deny),
+            _        => None,
+        }
+    }
+}
+
+/// What became of a request.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Granted(Vec<Capability>),
+    Denied,
+    /// Waiting on the user; the prompt's id.
+    Pending(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub id:         u32,
+    pub pid:        ProcessId,
+    pub app:        String,
+    pub permission: Permission,
+}
+
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub app:        String,
+    pub permission: Permission,
+    pub allow:      bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Grant {
+    pub pid:        ProcessId,
+    pub app:        String,
+    pub permission: Permission,
+    pub once:       bool,
+    pub caps:       Vec<Capability>,
+}
+
+// ─── state ────────────────────────────────────────────────────────────────────
+
+struct Client {
+    pid:     ProcessId,
+    channel: ChannelId,
+    /// permd's end of the channel, for notifications.
+    cap:     Capability,
+}
+
+struct Manager {
+    pid:         Option<ProcessId>,
+    apps:        Vec<(ProcessId, String)>,
+    decisions:   Vec<Decision>,
+    prompts:     Vec<Prompt>,
+    grants:      Vec<Grant>,
+    clients:     Vec<Client>,
+    agent:       Option<Client>,
+    quarantined: Vec<String>,
+    next_prompt: u32,
+}
+
+static MANAGER: Mutex<Manager> = Mutex::new(Manager {
+    pid: None, apps: Vec::new(), decisions: Vec::new(), prompts: Vec::new(), grants: Vec::new(),
+    clients: Vec::new(), agent: None, quarantined: Vec::new(), next_prompt: 1,
+});
+
+/// Request opcodes (first payload byte).  Apps send the first two; the
+/// rest only on the agent's channel.
+pub const PERM_REQ_REQUEST:  u8 = 1; // [perm] -> [0 denied | 1 granted, cap ids u64 LE... | 2 pending, prompt id u32 LE]
+pub const PERM_REQ_RELEASE:  u8 = 2; // [perm] -> [ok]
+pub const PERM_REQ_PROMPTS:  u8 = 3; // [] -> [ok, (prompt id u32 LE, perm, app len, app)...]
+pub const PERM_REQ_ANSWER:   u8 = 4; // [prompt id u32 LE, 0 deny | 1 always | 2 once] -> [ok]
+pub const PERM_REQ_SET:      u8 = 5; // [perm, 0 deny | 1 allow | 2 ask, app...] -> [ok]
+pub const PERM_REQ_TRANSFER: u8 = 6; // [transfer id u32 LE, 0 deny | 1 allow] -> [ok]
+
+/// Notification opcodes.  The agent gets prompts; apps get the rest.
+pub const PERM_NOTE_PROMPT:   u8 = 1; // [prompt id u32 LE, perm, app...]
+pub const PERM_NOTE_GRANTED:  u8 = 2; // [perm, cap ids u64 LE...]
+pub const PERM_NOTE_DENIED:   u8 = 3; // [perm]
+pub const PERM_NOTE_REVOKED:  u8 = 4; // [perm]
+pub const PERM_NOTE_TRANSFER: u8 = 5; // [transfer id u32 LE, vmbridge::Kind, size u64 LE, name...]
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("permd")?;
+    MANAGER.lock().pid = Some(pid);
+    ipc::register_kernel_server(pid, handle_request);
+    load()
+}
+
+fn load() -> Result<(), &'static str> {
+    let Ok(conf) = fs::read_file(CONFIG_PATH) else { return Ok(()) };
+    let conf = core::str::from_utf8(&conf).map_err(|_| "permission config is not UTF-8")?;
+    let mut m = MANAGER.lock();
+    for line in conf.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        match line.split_whitespace().collect::<Vec<_>>()[..] {
+            [app, "quarantined"] => m.quarantined.push(String::from(app)),
+            [app, perm, decision @ ("allow" | "deny")] => {
+                let permission = Permission::parse(perm).ok_or("unknown permission in config")?;
+                m.decisions.push(Decision { app: String::from(app), permission, allow: decision == "allow" });
+            }
+            _ => return Err("bad line in permission config"),
+        }
+    }
+    Ok(())
+}
+
+/// Write the decisions back to CONFIG_PATH.
+fn save(m: &Manager) -> Result<(), &'static str> {
+    let mut conf = String::from("# SurakshaOS permissions: <app> <permission> allow|deny | <app> quarantined\n");
+    for d in &m.decisions {
+        conf.push_str(&format!("{} {} {}\n", d.app, d.permission.as_str(), if d.allow { "allow" } else { "deny" }));
+    }
+    for app in &m.quarantined {
+        conf.push_str(&format!("{} quarantined\n", app));
+    }
+    fs::create_dir("/etc").ok();
+    fs::write_file(CONFIG_PATH, conf.as_bytes())
+}
+
+fn authorize(pid: ProcessId, cap: &Capability) -> Result<(), &'static str> {
+    capability::validate(pid, cap, CapabilityType::PermissionAdmin, Permissions::CONTROL)
+}
+
+fn notify(to: Option<(ChannelId, Capability)>, payload: &[u8]) {
+    let (Some(pid), Some((ch, cap))) = (MANAGER.lock().pid, to) else { return };
+    let _ = ipc::send_message(ch, pid, &cap, MessageKind::Notification, payload);
+}
+
+/// Send the consent agent `payload`, a prompt another service raises;
+/// false if there is no agent to ask.
+pub fn notify_agent(payload: &[u8]) -> bool {
+    let agent = MANAGER.lock().agent.as_ref().map(|a| (a.channel, a.cap.clone()));
+    let connected = agent.is_some();
+    notify(agent, payload);
+    connected
+}
+
+fn app_channel(m: &Manager, pid: ProcessId) -> Option<(ChannelId, Capability)> {
+    m.clients.iter().find(|c| c.pid == pid).map(|c| (c.channel, c.cap.clone()))
+}
+
+// ─── apps ─────────────────────────────────────────────────────────────────────
+
+/// Bind process `pid` to app `app`.  Called by whatever launches the app,
+/// never by the app itself.
+pub fn register_app(pid: ProcessId, app: &str) -> Result<(), &'static str> {
+    if app.is_empty() || app.len() > MAX_APP_NAME || app.contains(char::is_whitespace) {
+        return Err("bad app name");
+    }
+    let mut m = MANAGER.lock();
+    if m.apps.iter().any(|(p, _)| *p == pid) { return Err("process is already an app"); }
+    m.apps.push((pid, String::from(app)));
+    Ok(())
+}
+
+pub fn app_of(pid: ProcessId) -> Option<String> {
+    MANAGER.lock().apps.iter().find(|(p, _)| *p == pid).map(|(_, a)| a.clone())
+}
+
+/// Open a channel from app `client` to permd; requests are sent and
+/// decisions received on it.
+pub fn connect(client: ProcessId) -> Result<(ChannelId, Capability), &'static str> {
+    let mut m = MANAGER.lock();
+    let pid = m.pid.ok_or("permission service not running")?;
+    if !m.apps.iter().any(|(p, _)| *p == client) { return Err("not an app"); }
+    let (ch, client_cap, svc_cap) = ipc::create_channel(client, pid);
+    m.clients.push(Client { pid: client, channel: ch, cap: svc_cap });
+    Ok((ch, client_cap))
+}
+
+/// Make `client` the consent agent: prompts are sent to it, and it alone
+/// may answer them and change decisions over IPC.  `cap` must be its
+/// PermissionAdmin capability.
+pub fn connect_agent(client: ProcessId, cap: &Capability) -> Result<(ChannelId, Capability), &'static str> {
+    authorize(client, cap)?;
+    let mut m = MANAGER.lock();
+    let pid = m.pid.ok_or("permission service not running")?;
+    let (ch, client_cap, svc_cap) = ipc::create_channel(client, pid);
+    if let Some(old) = m.agent.replace(Client { pid: client, channel: ch, cap: svc_cap }) {
+        ipc::close_channel(old.channel);
+    }
+    Ok((ch, client_cap))
+}
+
+// ─── requests ─────────────────────────────────────────────────────────────────
+
+fn mint(m: &mut Manager, pid: ProcessId, app: &str, permission: Permission, once: bool) -> Vec<Capability> {
+    let expiry = if once { crate::arch::uptime_millis() + ONE_TIME_MS } else { 0 };
+    let caps: Vec<Capability> = permission.capabilities().into_iter()
+        .map(|(ty, perms)| capability::create_capability_until(pid, ty, perms, expiry))
+        .collect();
+    m.grants.push(Grant { pid, app: String::from(app), permission, once, caps: caps.clone() });
+    crate::audit::note("permission", &format!("{} (pid {}) granted {}{}",
+        app, pid.0, permission.as_str(), if once { " once" } else { "" }));
+    caps
+}
+
+/// Drop grants whose one-time capabilities have run out.
+fn expire(m: &mut Manager) {
+    let now = crate::arch::uptime_millis();
+    m.grants.retain(|g| !g.caps.first().is_some_and(|c| c.expiry != 0 && now >= c.expiry));
+}
+
+/// App `pid` asks for `permission`.  Granted if the user allowed it, or
+/// already granted it to this process; otherwise the user is asked.
+pub fn request(pid: ProcessId, permission: Permission) -> Result<Outcome, &'static str> {
+    let (outcome, agent, payload) = {
+        let mut m = MANAGER.lock();
+        let app = m.apps.iter().find(|(p, _)| *p == pid).map(|(_, a)| a.clone()).ok_or("not an app")?;
+        if m.quarantined.contains(&app) { return Ok(Outcome::Denied); }
+        expire(&mut m);
+        if let Some(g) = m.grants.iter().find(|g| g.pid == pid && g.permission == permission) {
+            return Ok(Outcome::Granted(g.caps.clone()));
+        }
+        if permission.capabilities().is_empty() { return Err("no such hardware on this device"); }
+        match m.decisions.iter().find(|d| d.app == app && d.permission == permission).map(|d| d.allow) {
+            Some(true)  => return Ok(Outcome::Granted(mint(&mut m, pid, &app, permission, false))),
+            Some(false) => return Ok(Outcome::Denied),
+            None        => {}
+        }
+        if let Some(p) = m.prompts.iter().find(|p| p.pid == pid && p.permission == permission) {
+            return Ok(Outcome::Pending(p.id));
+        }
+        let id = m.next_prompt;
+        m.next_prompt = m.next_prompt.wrapping_add(1).max(1);
+        let mut payload = Vec::from([PERM_NOTE_PROMPT]);
+        payload.extend_from_slice(&id.to_le_bytes());
+        payload.push(permission as u8);
+        payload.extend_from_slice(app.as_bytes());
+        m.prompts.push(Prompt { id, pid, app, permission });
+        (Outcome::Pending(id), m.agent.as_ref().map(|a| (a.channel, a.cap.clone())), payload)
+    };
+    notify(agent, &payload);
+    Ok(outcome)
+}
+
+/// App `pid` is done with `permission`; only one-time grants end early.
+pub fn release(pid: ProcessId, permission: Permission) -> Result<(), &'static str> {
+    let mut m = MANAGER.lock();
+    let i = m.grants.iter().position(|g| g.pid == pid && g.permission == permission).ok_or("not granted")?;
+    if !m.grants[i].once { return Ok(()); }
+    for c in &m.grants.remove(i).caps { let _ = capability::revoke_capability(c.id); }
+    Ok(())
+}
+
+fn granted_payload(permission: Permission, caps: &[Capability]) -> Vec<u8> {
+    let mut payload = Vec::from([PERM_NOTE_GRANTED, permission as u8]);
+    for c in caps { payload.extend_from_slice(&c.id.0.to_le_bytes()); }
+    payload
+}
+
+fn answer_prompt(id: u32, answer: Answer) -> Result<(), &'static str> {
+    let (to, payload) = {
+        let mut m = MANAGER.lock();
+        let i = m.prompts.iter().position(|p| p.id == id).ok_or("no such prompt")?;
+        let p = m.prompts.remove(i);
+        if answer != Answer::Once {
+            m.decisions.retain(|d| d.app != p.app || d.permission != p.permission);
+            m.decisions.push(Decision { app: p.app.clone(), permission: p.permission, allow: answer == Answer::Always });
+            save(&m)?;
+        }
+        let payload = match answer {
+            Answer::Deny => {
+                crate::audit::note("permission", &format!("{} denied {}", p.app, p.permission.as_str()));
+                Vec::from([PERM_NOTE_DENIED, p.permission as u8])
+            }
+            _ => {
+                let caps = mint(&mut m, p.pid, &p.app, p.permission, answer == Answer::Once);
+                granted_payload(p.permission, &caps)
+            }
+        };
+        (app_channel(&m, p.pid), payload)
+    };
+    notify(to, &payload);
+    Ok(())
+}
+
+/// The user's answer to prompt `id`.
+pub fn answer(cap: &Capability, id: u32, answer: Answer) -> Result<(), &'static str> {
+    authorize(process::current_pid(), cap)?;
+    answer_prompt(id, answer)
+}
+
+/// Take away every grant `matches` picks out; returns where to send the
+/// revocation notices.
+fn revoke_grants(m: &mut Manager, matches: impl Fn(&Grant) -> bool) -> Vec<(Option<(ChannelId, Capability)>, Permission)> {
+    let (gone, kept): (Vec<Grant>, Vec<Grant>) = core::mem::take(&mut m.grants).into_iter().partition(|g| matches(g));
+    m.grants = kept;
+    gone.iter().map(|g| {
+        for c in &g.caps { let _ = capability::revoke_capability(c.id); }
+        (app_channel(m, g.pid), g.permission)
+    }).collect()
+}
+
+fn set_decision(app: &str, permission: Permission, allow: Option<bool>) -> Result<(), &'static str> {
+    let revoked = {
+        let mut m = MANAGER.lock();
+        m.decisions.retain(|d| d.app != app || d.permission != permission);
+        if let Some(allow) = allow {
+            m.decisions.push(Decision { app: String::from(app), permission, allow });
+        }
+        save(&m)?;
+        crate::audit::note("permission", &format!("{} {} set to {}", app, permission.as_str(),
+            match allow { Some(true) => "allow", Some(false) => "deny", None => "ask" }));
+        if allow == Some(true) { return Ok(()); }
+        revoke_grants(&mut m, |g| g.app == app && g.permission == permission)
+    };
+    for (to, perm) in revoked { notify(to, &[PERM_NOTE_REVOKED, perm as u8]); }
+    Ok(())
+}
+
+/// Change the user's decision on `permission` for `app`: allow, deny, or
+/// (None) forget it so the app is asked again.  Anything short of allow
+/// revokes the capabilities already granted under it.
+pub fn set(cap: &Capability, app: &str, permission: Permission, allow: Option<bool>) -> Result<(), &'static str> {
+    authorize(process::current_pid(), cap)?;
+    set_decision(app, permission, allow)
+}
+
+// ─── quarantine ───────────────────────────────────────────────────────────────
+
+/// Quarantine `app`: everything granted to it is revoked, its prompts are
+/// dropped, and it is refused every permission without the user being
+/// asked until the quarantine is lifted.  The security monitor does this
+/// to an app that trips its rules.
+pub fn quarantine(app: &str) -> Result<(), &'static str> {
+    let revoked = {
+        let mut m = MANAGER.lock();
+        if m.quarantined.iter().any(|a| a == app) { return Ok(()); }
+        m.quarantined.push(String::from(app));
+        m.prompts.retain(|p| p.app != app);
+        save(&m)?;
+        crate::audit::note("permission", &format!("{} quarantined", app));
+        revoke_grants(&mut m, |g| g.app == app)
+    };
+    for (to, perm) in revoked { notify(to, &[PERM_NOTE_REVOKED, perm as u8]); }
+    Ok(())
+}
+
+/// Let `app` ask for permissions again.
+pub fn lift_quarantine(cap: &Capability, app: &str) -> Result<(), &'static str> {
+    authorize(process::current_pid(), cap)?;
+    let mut m = MANAGER.lock();
+    let i = m.quarantined.iter().position(|a| a == app).ok_or("app is not quarantined")?;
+    m.quarantined.remove(i);
+    crate::audit::note("permission", &format!("{} released from quarantine", app));
+    save(&m)
+}
+
+pub fn quarantined() -> Vec<String> {
+    MANAGER.lock().quarantined.clone()
+}
+
+pub fn prompts() -> Vec<Prompt> {
+    MANAGER.lock().prompts.clone()
+}
+
+pub fn decisions() -> Vec<Decision> {
+    MANAGER.lock().decisions.clone()
+}
+
+pub fn grants() -> Vec<Grant> {
+    let mut m = MANAGER.lock();
+    expire(&mut m);
+    m.grants.clone()
+}
+
+// ─── permd ────────────────────────────────────────────────────────────────────
+
+fn handle_request(ch: ChannelId, msg: &Message) -> Option<Vec<u8>> {
+    let agent = MANAGER.lock().agent.as_ref().is_some_and(|a| a.channel == ch);
+    let p = &msg.payload;
+    let reply = match (p.first(), p.get(1).copied().and_then(Permission::from_u8)) {
+        (Some(&PERM_REQ_REQUEST), Some(perm)) => request(msg.sender, perm).map(|o| match o {
+            Outcome::Granted(caps) => {
+                let mut reply = Vec::from([1u8]);
+                for c in &caps { reply.extend_from_slice(&c.id.0.to_le_bytes()); }
+                reply
+            }
+            Outcome::Denied      => Vec::from([0u8]),
+            Outcome::Pending(id) => [&[2u8][..], &id.to_le_bytes()].concat(),
+        }),
+        (Some(&PERM_REQ_RELEASE), Some(perm)) => release(msg.sender, perm).map(|_| Vec::from([1u8])),
+        (Some(&PERM_REQ_PROMPTS), _) if agent => {
+            let mut reply = Vec::from([1u8]);
+            for pr in prompts() {
+                reply.extend_from_slice(&pr.id.to_le_bytes());
+                reply.push(pr.permission as u8);
+                reply.push(pr.app.len() as u8);
+                reply.extend_from_slice(pr.app.as_bytes());
+            }
+            Ok(reply)
+        }
+        (Some(&PERM_REQ_ANSWER), _) if agent && p.len() >= 6 => (|| {
+            let id = u32::from_le_bytes(p[1..5].try_into().map_err(|_| "bad request")?);
+            let answer = match p[5] {
+                0 => Answer::Deny,
+                1 => Answer::Always,
+                2 => Answer::Once,
+                _ => return Err("bad answer"),
+            };
+            answer_prompt(id, answer).map(|_| Vec::from([1u8]))
+        })(),
+        (Some(&PERM_REQ_SET), Some(perm)) if agent && p.len() >= 4 => (|| {
+            let app = core::str::from_utf8(&p[3..]).map_err(|_| "bad app name")?;
+            let allow = match p[2] {
+                0 => Some(false),
+                1 => Some(true),
+                2 => None,
+                _ => return Err("bad decision"),
+            };
+            set_decision(app, perm, allow).map(|_| Vec::from([1u8]))
+        })(),
+        (Some(&PERM_REQ_TRANSFER), _) if agent && p.len() >= 6 => (|| {
+            let id = u32::from_le_bytes(p[1..5].try_into().map_err(|_| "bad request")?);
+            crate::vmbridge::answer_transfer(id, p[5] == 1).map(|_| Vec::from([1u8]))
+        })(),
+        _ => Err("bad request"),
+    };
+    Some(reply.unwrap_or_else(|_| Vec::from([0u8])))
+}
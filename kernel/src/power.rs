@@ -0,0 +1,634 @@
+//! SurakshaOS Power Management
+//! CPU clusters (big.LITTLE) with their operating points (DVFS) and
+//! capacities, and the frequency governor that picks an operating point
+//! from measured CPU utilisation.  Governors run from the timer tick and
+//! are rate-limited so the clock is not reprogrammed on every sample.
+//! Also owns the system sleep state machine (suspend-to-RAM) and tells
+//! subscribed services about power-state transitions, including the user
+//! going idle after a spell without input and coming back.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::capability::Capability;
+use crate::ipc::{self, ChannelId, MessageKind};
+use crate::process::ProcessId;
+
+// ─── operating points ─────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy)]
+pub struct OperatingPoint {
+    pub freq_mhz:   u32,
+    pub voltage_mv: u32,
+}
+
+/// Operating points of the efficiency (LITTLE) cores, slowest first.
+pub const LITTLE_FREQ_TABLE: &[OperatingPoint] = &[
+    OperatingPoint { freq_mhz:  200, voltage_mv:  700 },
+    OperatingPoint { freq_mhz:  400, voltage_mv:  750 },
+    OperatingPoint { freq_mhz:  800, voltage_mv:  850 },
+    OperatingPoint { freq_mhz: 1200, voltage_mv:  950 },
+    OperatingPoint { freq_mhz: 1600, voltage_mv: 1050 },
+];
+
+/// Operating points of the performance (big) cores, slowest first.
+pub const BIG_FREQ_TABLE: &[OperatingPoint] = &[
+    OperatingPoint { freq_mhz:  600, voltage_mv:  750 },
+    OperatingPoint { freq_mhz: 1000, voltage_mv:  800 },
+    OperatingPoint { freq_mhz: 1400, voltage_mv:  875 },
+    OperatingPoint { freq_mhz: 1800, voltage_mv:  950 },
+    OperatingPoint { freq_mhz: 2200, voltage_mv: 1025 },
+    OperatingPoint { freq_mhz: 2600, voltage_mv: 1100 },
+];
+
+// ─── clusters ─────────────────────────────────────────────────────────────────
+
+/// A group of identical cores sharing one clock and voltage rail.
+#[derive(Debug)]
+pub struct Cluster {
+    pub name:     &'static str,
+    pub cpus:     core::ops::Range<usize>,
+    pub opps:     &'static [OperatingPoint],
+    /// Compute capacity of one core at its top operating point, relative
+    /// to the fastest core in the system (1024).
+    pub capacity: u32,
+}
+
+pub const CLUSTERS: &[Cluster] = &[
+    Cluster { name: "little", cpus: 0..4, opps: LITTLE_FREQ_TABLE, capacity:  430 },
+    Cluster { name: "big",    cpus: 4..8, opps: BIG_FREQ_TABLE,    capacity: 1024 },
+];
+
+/// Cluster of the boot hart, which the kernel (and the single-CPU API
+/// below) runs on.
+pub const BOOT_CLUSTER: usize = 0;
+
+/// Operating points of the CPU the kernel runs on.
+pub const CPU_FREQ_TABLE: &[OperatingPoint] = CLUSTERS[BOOT_CLUSTER].opps;
+
+pub fn cluster_of(cpu: usize) -> Option<usize> {
+    CLUSTERS.iter().position(|c| c.cpus.contains(&cpu))
+}
+
+/// Platform hook that actually reprograms a cluster's PLL/regulator.  QEMU
+/// has no clock controller, so by default only the bookkeeping changes.
+pub type FreqSetter = fn(usize, &OperatingPoint);
+
+/// Current level per cluster (index into that cluster's `opps`); every
+/// cluster boots at its fastest point.
+static CLUSTER_LEVEL: [AtomicUsize; CLUSTERS.len()] = [
+    AtomicUsize::new(LITTLE_FREQ_TABLE.len() - 1),
+    AtomicUsize::new(BIG_FREQ_TABLE.len() - 1),
+];
+static FREQ_SETTER: Mutex<Option<FreqSetter>> = Mutex::new(None);
+
+/// Longest operating-point table of any cluster.
+pub const MAX_OPPS: usize = 6;
+
+/// mtime spent at each level per cluster, up to `LEVEL_SINCE`.
+static TIME_IN_STATE: [[AtomicU64; MAX_OPPS]; CLUSTERS.len()] =
+    [const { [const { AtomicU64::new(0) }; MAX_OPPS] }; CLUSTERS.len()];
+/// mtime at which each cluster entered its current level.
+static LEVEL_SINCE: [AtomicU64; CLUSTERS.len()] = [const { AtomicU64::new(0) }; CLUSTERS.len()];
+
+/// Highest level the governor may select (lowered by thermal throttling).
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+pub fn register_freq_setter(f: FreqSetter) {
+    *FREQ_SETTER.lock() = Some(f);
+}
+
+/// Switch `cluster` to its operating point `level`.
+pub fn set_cluster_frequency(cluster: usize, level: usize) -> Result<(), &'static str> {
+    let c   = CLUSTERS.get(cluster).ok_or("invalid cluster")?;
+    let opp = c.opps.get(level).ok_or("invalid frequency level")?;
+    if CLUSTER_LEVEL[cluster].load(Ordering::Relaxed) == level { return Ok(()); }
+    if cluster == BOOT_CLUSTER { crate::energy::checkpoint(); }
+    if let Some(set) = *FREQ_SETTER.lock() {
+        set(cluster, opp);
+    }
+    let now   = crate::arch::read_mtime();
+    let since = LEVEL_SINCE[cluster].swap(now, Ordering::Relaxed);
+    let old   = CLUSTER_LEVEL[cluster].swap(level, Ordering::Relaxed);
+    TIME_IN_STATE[cluster][old].fetch_add(now.saturating_sub(since), Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn cluster_level(cluster: usize) -> usize {
+    CLUSTER_LEVEL[cluster].load(Ordering::Relaxed)
+}
+
+/// Milliseconds `cluster` has spent at each of its operating points.
+pub fn time_in_state_ms(cluster: usize) -> [u64; MAX_OPPS] {
+    let mut out = [0; MAX_OPPS];
+    for (o, t) in out.iter_mut().zip(TIME_IN_STATE[cluster].iter()) {
+        *o = t.load(Ordering::Relaxed);
+    }
+    // Include the stretch at the current level
+    let open = crate::arch::read_mtime().saturating_sub(LEVEL_SINCE[cluster].load(Ordering::Relaxed));
+    out[cluster_level(cluster)] += open;
+    out.map(|t| t / crate::arch::ms_to_ticks(1))
+}
+
+/// Switch the CPU to operating point `level` (index into `CPU_FREQ_TABLE`).
+pub fn set_cpu_frequency(level: usize) -> Result<(), &'static str> {
+    set_cluster_frequency(BOOT_CLUSTER, level)
+}
+
+pub fn cpu_level() -> usize {
+    cluster_level(BOOT_CLUSTER)
+}
+
+pub fn cpu_frequency_mhz() -> u32 {
+    CPU_FREQ_TABLE[cpu_level()].freq_mhz
+}
+
+/// Capacity `cpu` offers at its cluster's current frequency.
+pub fn cpu_capacity(cpu: usize) -> u32 {
+    let Some(i) = cluster_of(cpu) else { return 0 };
+    let c   = &CLUSTERS[i];
+    let max = c.opps[c.opps.len() - 1].freq_mhz;
+    c.capacity * c.opps[cluster_level(i)].freq_mhz / max
+}
+
+/// Placement hint for the scheduler: the smallest cluster whose capacity
+/// fits a task of utilisation `util` (on the 0–1024 capacity scale) with
+/// 20% headroom, so light work stays on LITTLE cores and heavy tasks land
+/// on big ones.
+pub fn placement_hint(util: u32) -> usize {
+    let mut by_capacity: Vec<usize> = (0..CLUSTERS.len()).collect();
+    by_capacity.sort_by_key(|&i| CLUSTERS[i].capacity);
+    by_capacity.iter()
+        .copied()
+        .find(|&i| util * 5 / 4 <= CLUSTERS[i].capacity)
+        .unwrap_or(by_capacity[by_capacity.len() - 1])
+}
+
+/// Cap the operating point the governor may pick; `usize::MAX` lifts the
+/// cap.  Takes effect immediately if the CPU is currently above it.
+pub fn set_max_level(level: usize) {
+    let level = level.min(CPU_FREQ_TABLE.len() - 1);
+    if MAX_LEVEL.swap(level, Ordering::Relaxed) != level && cpu_level() > level {
+        let _ = set_cpu_frequency(level);
+    }
+}
+
+pub fn max_level() -> usize {
+    MAX_LEVEL.load(Ordering::Relaxed).min(CPU_FREQ_TABLE.len() - 1)
+}
+
+// ─── governors ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Governor {
+    /// Pin to the highest operating point.
+    Performance,
+    /// Pin to the lowest operating point.
+    Powersave,
+    /// Jump to max above `UP_THRESHOLD`, otherwise step down proportionally.
+    Ondemand,
+    /// Frequency proportional to utilisation with 25% headroom.
+    Schedutil,
+}
+
+impl Governor {
+    pub fn name(self) -> &'static str {
+        match self {
+            Governor::Performance => "performance",
+            Governor::Powersave   => "powersave",
+            Governor::Ondemand    => "ondemand",
+            Governor::Schedutil   => "schedutil",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Governor> {
+        [Governor::Performance, Governor::Powersave, Governor::Ondemand, Governor::Schedutil]
+            .into_iter()
+            .find(|g| g.name() == name)
+    }
+}
+
+/// Utilisation (percent) above which ondemand goes straight to max.
+const UP_THRESHOLD: u32 = 80;
+
+/// Minimum time between two frequency transitions.
+const RATE_LIMIT_MS: u64 = 10;
+
+struct GovernorState {
+    policy:        Governor,
+    last_change:   u64,
+    /// (mtime, idle ticks) at the previous sample.
+    last_sample:   (u64, u64),
+    last_util:     u32,
+}
+
+static GOVERNOR: Mutex<GovernorState> = Mutex::new(GovernorState {
+    policy:      Governor::Schedutil,
+    last_change: 0,
+    last_sample: (0, 0),
+    last_util:   0,
+});
+
+pub fn set_governor(policy: Governor) {
+    GOVERNOR.lock().policy = policy;
+}
+
+pub fn governor() -> Governor {
+    GOVERNOR.lock().policy
+}
+
+/// Utilisation measured over the last governor sample, in percent.
+pub fn cpu_utilization() -> u32 {
+    GOVERNOR.lock().last_util
+}
+
+/// Pick the lowest operating point whose frequency is at least `target`.
+fn level_for(target_mhz: u32) -> usize {
+    CPU_FREQ_TABLE.iter()
+        .position(|o| o.freq_mhz >= target_mhz)
+        .unwrap_or(CPU_FREQ_TABLE.len() - 1)
+}
+
+fn next_level(policy: Governor, util: u32, current: usize) -> usize {
+    let max = CPU_FREQ_TABLE[CPU_FREQ_TABLE.len() - 1].freq_mhz;
+    match policy {
+        Governor::Performance => CPU_FREQ_TABLE.len() - 1,
+        Governor::Powersave   => 0,
+        Governor::Ondemand => {
+            if util >= UP_THRESHOLD {
+                CPU_FREQ_TABLE.len() - 1
+            } else {
+                // Scale the current frequency so the load would sit just
+                // under the threshold, but never ramp up from here.
+                let cur = CPU_FREQ_TABLE[current].freq_mhz;
+                level_for(cur * util / (UP_THRESHOLD - 10)).min(current)
+            }
+        }
+        Governor::Schedutil => level_for(max * util * 5 / 4 / 100),
+    }
+}
+
+/// Sample utilisation and retarget the CPU frequency.  Called from the
+/// timer interrupt; skips the sample rather than spin if the governor is
+/// being reconfigured concurrently.
+pub fn governor_tick() {
+    let Some(mut gov) = GOVERNOR.try_lock() else { return };
+    let now  = crate::arch::read_mtime();
+    let idle = crate::process::idle_ticks();
+    let (last_now, last_idle) = gov.last_sample;
+    gov.last_sample = (now, idle);
+    let wall = now.saturating_sub(last_now);
+    if wall == 0 || last_now == 0 { return; }
+    let busy = wall.saturating_sub(idle.saturating_sub(last_idle));
+    gov.last_util = (busy * 100 / wall).min(100) as u32;
+
+    let now_ms = crate::arch::uptime_millis();
+    if now_ms.saturating_sub(gov.last_change) < RATE_LIMIT_MS { return; }
+    let target = next_level(gov.policy, gov.last_util, cpu_level()).min(max_level());
+    if target != cpu_level() && set_cpu_frequency(target).is_ok() {
+        gov.last_change = now_ms;
+    }
+}
+
+// ─── system sleep ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepState {
+    Running,
+    SuspendingDevices,
+    Suspended,
+    Resuming,
+}
+
+/// Events allowed to bring the system out of suspend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeSource {
+    /// Wake after this many milliseconds.
+    Timer { after_ms: u64 },
+    /// Wake on console input.
+    Uart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeReason {
+    Timer,
+    Uart,
+    /// A wakeup alarm fired (see `alarm`).
+    Alarm,
+}
+
+/// Resumes per `WakeReason`, in declaration order.
+static WAKE_COUNTS: [AtomicU64; 3] = [const { AtomicU64::new(0) }; 3];
+
+static SLEEP_STATE: Mutex<SleepState> = Mutex::new(SleepState::Running);
+
+pub fn sleep_state() -> SleepState {
+    *SLEEP_STATE.lock()
+}
+
+fn set_sleep_state(s: SleepState) {
+    *SLEEP_STATE.lock() = s;
+}
+
+/// Suspend to RAM until one of `wake` fires.
+///
+/// Drivers are suspended first (any refusal aborts the attempt), then
+/// interrupts are masked down to the wake sources and the hart waits in
+/// `wfi`.  On wake-up the interrupt state and scheduler tick are restored
+/// and drivers are resumed in reverse order.
+pub fn suspend_to_ram(wake: &[WakeSource]) -> Result<WakeReason, &'static str> {
+    if wake.is_empty() { return Err("no wake source"); }
+    if sleep_state() != SleepState::Running { return Err("suspend already in progress"); }
+    if !crate::wakelock::can_suspend() { return Err("wakelock held"); }
+
+    // Services get to quiesce first and hear about the outcome either way
+    notify_transition(PowerTransition::Suspend, Phase::Pre);
+    let result = enter_suspend(wake);
+    notify_transition(PowerTransition::Suspend, Phase::Post);
+    result
+}
+
+fn enter_suspend(wake: &[WakeSource]) -> Result<WakeReason, &'static str> {
+    set_sleep_state(SleepState::SuspendingDevices);
+    if let Err(e) = crate::driver::suspend_all() {
+        set_sleep_state(SleepState::Running);
+        return Err(e);
+    }
+    // Pending wakeup alarms always get to wake the system
+    let rtc_wake = match crate::alarm::arm_for_suspend() {
+        Ok(armed) => armed,
+        Err(e) => {
+            crate::driver::resume_all();
+            set_sleep_state(SleepState::Running);
+            return Err(e);
+        }
+    };
+
+    // Save CPU interrupt state and leave only the wake sources enabled
+    let mstatus   = crate::arch::interrupts_disable();
+    let saved_mie = crate::arch::read_mie();
+    let mut mie   = 0;
+    let mut deadline = None;
+    for src in wake {
+        match *src {
+            WakeSource::Timer { after_ms } => {
+                let at = crate::arch::read_mtime() + crate::arch::ms_to_ticks(after_ms);
+                deadline = Some(deadline.map_or(at, |d: u64| d.min(at)));
+                mie |= crate::arch::MIE_MTIE;
+            }
+            WakeSource::Uart => {
+                crate::console::set_rx_interrupt(true);
+                crate::arch::plic_enable(crate::arch::uart_irq(), true);
+                mie |= crate::arch::MIE_MEIE;
+            }
+        }
+    }
+    if rtc_wake {
+        crate::arch::plic_enable(crate::alarm::RTC_IRQ, true);
+        mie |= crate::arch::MIE_MEIE;
+    }
+    crate::arch::set_timer_compare(deadline.unwrap_or(u64::MAX));
+    crate::arch::write_mie(mie);
+    set_sleep_state(SleepState::Suspended);
+
+    crate::process::idle_enter();
+    let reason = loop {
+        crate::arch::wait_for_interrupt();
+        if deadline.is_some_and(|d| crate::arch::read_mtime() >= d) {
+            break WakeReason::Timer;
+        }
+        if rtc_wake && crate::alarm::rtc_fired() {
+            break WakeReason::Alarm;
+        }
+        if wake.contains(&WakeSource::Uart) && crate::console::rx_ready() {
+            break WakeReason::Uart;
+        }
+    };
+    crate::process::idle_exit();
+    WAKE_COUNTS[reason as usize].fetch_add(1, Ordering::Relaxed);
+
+    // Restore interrupt state
+    set_sleep_state(SleepState::Resuming);
+    if rtc_wake {
+        crate::alarm::disarm_after_resume();
+        crate::arch::plic_enable(crate::alarm::RTC_IRQ, false);
+    }
+    if wake.contains(&WakeSource::Uart) {
+        crate::console::set_rx_interrupt(false);
+        crate::arch::plic_enable(crate::arch::uart_irq(), false);
+    }
+    // Sources are quiet now; acknowledge whatever the PLIC still holds
+    while crate::arch::plic_claim_complete() != 0 {}
+    crate::arch::rearm_tick();
+    crate::arch::write_mie(saved_mie);
+    crate::arch::interrupts_restore(mstatus);
+
+    crate::driver::resume_all();
+    set_sleep_state(SleepState::Running);
+    Ok(reason)
+}
+
+// ─── transition notifications ─────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerTransition {
+    /// User active again after an idle period.
+    Active  = 0,
+    /// User inactive, screen still on.
+    Idle    = 1,
+    /// Screen off, background work only.
+    Sleep   = 2,
+    /// Suspend to RAM.
+    Suspend = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// About to transition; subscribers should quiesce and acknowledge.
+    Pre  = 1,
+    /// Transition finished (or was aborted); informational only.
+    Post = 2,
+}
+
+/// How long a `Pre` notification waits for every subscriber to ack.
+pub const ACK_WINDOW_MS: u64 = 500;
+
+/// Notification payload: [transition u8, phase u8, seq u32 LE].  A
+/// subscriber acknowledges a `Pre` event by sending back any message whose
+/// payload starts with the same seq (u32 LE).
+pub const POWER_NOTIFY_LEN: usize = 6;
+
+struct Subscriber {
+    pid:     ProcessId,
+    channel: ChannelId,
+    /// powerd's end of the channel.
+    cap:     Capability,
+}
+
+struct Notifier {
+    pid:         Option<ProcessId>,
+    subscribers: Vec<Subscriber>,
+}
+
+static NOTIFIER: Mutex<Notifier> = Mutex::new(Notifier { pid: None, subscribers: Vec::new() });
+static NOTIFY_SEQ: AtomicU32 = AtomicU32::new(1);
+
+/// Subscribe `service` to power-state transitions.  Returns the channel
+/// events arrive on and the capability for it.
+pub fn subscribe(service: ProcessId) -> Result<(ChannelId, Capability), &'static str> {
+    let mut n = NOTIFIER.lock();
+    let pid = match n.pid {
+        Some(p) => p,
+        None => {
+            let p = crate::process::spawn_process("powerd")?;
+            n.pid = Some(p);
+            p
+        }
+    };
+    let (ch, svc_cap, powerd_cap) = ipc::create_channel(service, pid);
+    n.subscribers.push(Subscriber { pid: service, channel: ch, cap: powerd_cap });
+    Ok((ch, svc_cap))
+}
+
+pub fn unsubscribe(service: ProcessId) {
+    let mut n = NOTIFIER.lock();
+    n.subscribers.retain(|s| {
+        if s.pid == service { ipc::close_channel(s.channel); }
+        s.pid != service
+    });
+}
+
+/// Broadcast a transition to all subscribers.  For `Phase::Pre` this waits
+/// up to `ACK_WINDOW_MS` for acknowledgements and returns the PIDs that did
+/// not answer in time; the transition goes ahead regardless.
+pub fn notify_transition(transition: PowerTransition, phase: Phase) -> Vec<ProcessId> {
+    let (pid, subs) = {
+        let n = NOTIFIER.lock();
+        let Some(pid) = n.pid else { return Vec::new() };
+        let subs: Vec<(ProcessId, ChannelId, Capability)> =
+            n.subscribers.iter().map(|s| (s.pid, s.channel, s.cap.clone())).collect();
+        (pid, subs)
+    };
+    let seq = NOTIFY_SEQ.fetch_add(1, Ordering::Relaxed);
+    let mut payload = Vec::from([transition as u8, phase as u8]);
+    payload.extend_from_slice(&seq.to_le_bytes());
+
+    let mut waiting: Vec<(ProcessId, ChannelId, Capability)> = subs.into_iter()
+        .filter(|(_, ch, cap)| ipc::send_message(*ch, pid, cap, MessageKind::Notification, &payload).is_ok())
+        .collect();
+    if phase == Phase::Post { return Vec::new(); }
+
+    let deadline = crate::arch::uptime_millis() + ACK_WINDOW_MS;
+    loop {
+        waiting.retain(|(_, ch, cap)| {
+            while let Ok(msg) = ipc::receive_message(*ch, pid, cap) {
+                if msg.payload.get(..4) == Some(&seq.to_le_bytes()[..]) { return false; }
+            }
+            true
+        });
+        if waiting.is_empty() || crate::arch::uptime_millis() >= deadline { break; }
+        core::hint::spin_loop();
+    }
+    for (p, _, _) in &waiting {
+        crate::println!("  [power] pid {} did not acknowledge {:?} {:?}", p, transition, phase);
+    }
+    waiting.into_iter().map(|(p, _, _)| p).collect()
+}
+
+// ─── user activity ────────────────────────────────────────────────────────────
+
+/// Without input for this long the user counts as idle.
+pub const USER_IDLE_MS: u64 = 60_000;
+
+static LAST_INPUT_MS: AtomicU64 = AtomicU64::new(0);
+static USER_IDLE: AtomicBool = AtomicBool::new(false);
+
+/// The user did something; ends an idle period.  Safe to call from
+/// interrupts.
+pub fn user_activity() {
+    LAST_INPUT_MS.store(crate::arch::uptime_millis(), Ordering::Relaxed);
+    if USER_IDLE.swap(false, Ordering::Relaxed) { crate::process::defer(announce_active); }
+}
+
+pub fn user_idle() -> bool {
+    USER_IDLE.load(Ordering::Relaxed)
+}
+
+/// Start an idle period once input has been quiet for `USER_IDLE_MS`;
+/// called from the timer tick.  Subscribers are told as deferred work,
+/// since a `Pre` notification waits for their acknowledgements.
+pub fn idle_tick() {
+    let quiet = crate::arch::uptime_millis().saturating_sub(LAST_INPUT_MS.load(Ordering::Relaxed));
+    if quiet >= USER_IDLE_MS && !USER_IDLE.swap(true, Ordering::Relaxed) {
+        crate::process::defer(announce_idle);
+    }
+}
+
+fn announce_idle() {
+    notify_transition(PowerTransition::Idle, Phase::Pre);
+    notify_transition(PowerTransition::Idle, Phase::Post);
+}
+
+fn announce_active() {
+    notify_transition(PowerTransition::Active, Phase::Post);
+}
+
+// ─── statistics ───────────────────────────────────────────────────────────────
+
+/// Summary returned by the `SYS_POWER_STATS` syscall.  Plain `u64`s so it
+/// can be copied to the caller byte for byte.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PowerStats {
+    pub uptime_ms:        u64,
+    /// Time at each operating point per cluster (ms), slowest first.
+    pub freq_time_ms:     [[u64; MAX_OPPS]; CLUSTERS.len()],
+    /// Residency per idle state (µs), shallowest first.
+    pub idle_time_us:     [u64; crate::cpuidle::IDLE_STATES.len()],
+    pub idle_usage:       [u64; crate::cpuidle::IDLE_STATES.len()],
+    /// What ended idle periods: timer, external interrupt, other.
+    pub idle_wakeups:     [u64; 3],
+    /// What ended suspends: timer, uart, alarm.
+    pub suspend_wakeups:  [u64; 3],
+    /// Total wakelock hold time across all holders.
+    pub wakelock_held_ms: u64,
+}
+
+/// Per-holder wakelock record returned by `SYS_POWER_STATS`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct WakelockRecord {
+    pub pid:          u64,
+    pub acquisitions: u64,
+    pub timeouts:     u64,
+    pub total_ms:     u64,
+    pub longest_ms:   u64,
+    pub active:       u64,
+}
+
+pub fn power_stats() -> PowerStats {
+    let mut s = PowerStats { uptime_ms: crate::arch::uptime_millis(), ..Default::default() };
+    for (i, row) in s.freq_time_ms.iter_mut().enumerate() {
+        *row = time_in_state_ms(i);
+    }
+    for (i, st) in crate::cpuidle::stats().iter().enumerate() {
+        s.idle_time_us[i] = st.time_us;
+        s.idle_usage[i]   = st.usage;
+    }
+    s.idle_wakeups     = crate::cpuidle::wakeups();
+    s.suspend_wakeups  = WAKE_COUNTS.each_ref().map(|c| c.load(Ordering::Relaxed));
+    s.wakelock_held_ms = crate::wakelock::stats().iter().map(|h| h.total_ms).sum();
+    s
+}
+
+pub fn wakelock_records() -> Vec<WakelockRecord> {
+    crate::wakelock::stats().iter().map(|h| WakelockRecord {
+        pid:          h.holder.0 as u64,
+        acquisitions: h.acquisitions as u64,
+        timeouts:     h.timeouts as u64,
+        total_ms:     h.total_ms,
+        longest_ms:   h.longest_ms,
+        active:       h.active as u64,
+    }).collect()
+}
@@ -2,7 +2,7 @@
 //! Provides the process ID type, a simple process table, and
 //! scheduler stubs for future preemptive multitasking.
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 // ─── process identifier ──────────────────────────────────────────────────────
 
@@ -18,11 +18,18 @@ impl core::fmt::Display for ProcessId {
 // ─── PID allocator ──────────────────────────────────────────────────────────
 
 /// Next PID to hand out (start above the boot-time service PIDs)
-static NEXT_PID: AtomicUsize = AtomicUsize::new(10);
+static NEXT_PID: AtomicUsize = AtomicUsize::new(FIRST_PID);
+const FIRST_PID: usize = 10;
 
 /// Current running process (the shell after init hands off)
 static CURRENT_PID: AtomicUsize = AtomicUsize::new(7);
 
+/// CLINT ticks spent idle since boot, excluding the current idle stretch.
+static IDLE_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// mtime at which the CPU went idle, or 0 while it is busy.
+static IDLE_SINCE: AtomicU64 = AtomicU64::new(0);
+
 // ─── public API ──────────────────────────────────────────────────────────────
 
 /// Spawn a new (stub) process and return its PID.
@@ -38,8 +45,207 @@ pub fn current_pid() -> ProcessId {
     ProcessId(CURRENT_PID.load(Ordering::Relaxed))
 }
 
+/// Run `f` with `pid` as the current process, restoring the previous one
+/// afterwards.  Used when the kernel executes work on behalf of an
+/// in-kernel service.
+pub fn run_as<R>(pid: ProcessId, f: impl FnOnce() -> R) -> R {
+    crate::energy::checkpoint();
+    let prev = CURRENT_PID.swap(pid.0, Ordering::SeqCst);
+    let start = crate::arch::read_mtime();
+    let r = f();
+    crate::energy::checkpoint();
+    CURRENT_PID.store(prev, Ordering::SeqCst);
+    crate::sandbox::charge_cpu(pid, crate::arch::read_mtime() - start);
+    r
+}
+
+/// Processes that have been killed.  Until there is a scheduler there is
+/// nothing to stop running; killing a process takes away everything it
+/// holds — its capabilities and its IPC channels — and it stays dead.
+static KILLED: spin::Mutex<alloc::vec::Vec<ProcessId>> = spin::Mutex::new(alloc::vec::Vec::new());
+
+/// True for the boot-time processes and kernel-resident services, which
+/// cannot be killed.
+pub fn is_system(pid: ProcessId) -> bool {
+    pid.0 < FIRST_PID || crate::ipc::is_kernel_server(pid)
+}
+
+/// Kill `pid`.
+pub fn kill(pid: ProcessId) -> Result<(), &'static str> {
+    if is_system(pid) { return Err("system processes cannot be killed"); }
+    {
+        let mut killed = KILLED.lock();
+        if killed.contains(&pid) { return Ok(()); }
+        killed.push(pid);
+    }
+    reap(pid);
+    Ok(())
+}
+
+/// `pid` has exited: release everything it held.  Init hears of it
+/// either way, to restart it if it was a service.
+pub fn exit(pid: ProcessId) {
+    if is_system(pid) {
+        crate::init::child_exited(pid);
+    } else {
+        reap(pid);
+    }
+}
+
+fn reap(pid: ProcessId) {
+    crate::sandbox::destroy(pid);
+    crate::tee::release(pid);
+    crate::mte::forget(pid);
+    crate::ui::release(pid);
+    crate::capability::revoke_all(pid);
+    crate::ipc::close_channels_of(pid);
+    crate::ipc::close_ports_of(pid);
+    crate::init::child_exited(pid);
+}
+
+pub fn is_killed(pid: ProcessId) -> bool {
+    KILLED.lock().contains(&pid)
+}
+
+// ─── process state ───────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Running,
+    /// Parked on a wait queue, until woken or the deadline (uptime ms).
+    Blocked { deadline_ms: Option<u64> },
+    Killed,
+}
+
+/// Processes parked on a wait queue, and their deadlines.
+static BLOCKED: spin::Mutex<alloc::vec::Vec<(ProcessId, Option<u64>)>> = spin::Mutex::new(alloc::vec::Vec::new());
+
+pub fn state(pid: ProcessId) -> ProcessState {
+    if is_killed(pid) { return ProcessState::Killed; }
+    match BLOCKED.lock().iter().rev().find(|(p, _)| *p == pid) {
+        Some(&(_, deadline_ms)) => ProcessState::Blocked { deadline_ms },
+        None                    => ProcessState::Running,
+    }
+}
+
+/// `pid` is Blocked while this lives.
+struct Parked(ProcessId);
+
+impl Parked {
+    fn new(pid: ProcessId, deadline_ms: Option<u64>) -> Parked {
+        BLOCKED.lock().push((pid, deadline_ms));
+        Parked(pid)
+    }
+}
+
+impl Drop for Parked {
+    fn drop(&mut self) {
+        let mut blocked = BLOCKED.lock();
+        if let Some(i) = blocked.iter().rposition(|(p, _)| *p == self.0) { blocked.remove(i); }
+    }
+}
+
 /// Approximate milliseconds since boot.
 /// Reads the RISC-V CLINT mtime register directly.
 pub fn uptime_ms() -> u64 {
     crate::arch::uptime_millis()
 }
+
+// ─── CPU accounting ──────────────────────────────────────────────────────────
+
+/// Mark the CPU idle (waiting for input or an interrupt with nothing to run).
+pub fn idle_enter() {
+    crate::energy::cpu_idle();
+    IDLE_SINCE.store(crate::arch::read_mtime().max(1), Ordering::Relaxed);
+}
+
+/// Mark the CPU busy again and fold the idle stretch into the total.
+pub fn idle_exit() {
+    let since = IDLE_SINCE.swap(0, Ordering::Relaxed);
+    if since != 0 {
+        IDLE_TICKS.fetch_add(crate::arch::read_mtime().saturating_sub(since), Ordering::Relaxed);
+    }
+    crate::energy::cpu_busy();
+}
+
+/// Total CLINT ticks spent idle since boot, including any current stretch.
+pub fn idle_ticks() -> u64 {
+    let since = IDLE_SINCE.load(Ordering::Relaxed);
+    let open  = if since != 0 { crate::arch::read_mtime().saturating_sub(since) } else { 0 };
+    IDLE_TICKS.load(Ordering::Relaxed) + open
+}
+
+// ─── deferred work ───────────────────────────────────────────────────────────
+
+/// Work that interrupt handlers hand off to process context (anything that
+/// takes locks the interrupted code may hold, such as IPC delivery).
+static DEFERRED: spin::Mutex<alloc::vec::Vec<fn()>> = spin::Mutex::new(alloc::vec::Vec::new());
+
+/// Queue `f` to run once the CPU next has nothing to do.  Queuing the same
+/// function twice before it runs is a no-op.  Safe to call from interrupts.
+pub fn defer(f: fn()) {
+    let prev = crate::arch::interrupts_disable();
+    {
+        let mut q = DEFERRED.lock();
+        if !q.iter().any(|&g| core::ptr::fn_addr_eq(g, f)) { q.push(f); }
+    }
+    crate::arch::interrupts_restore(prev);
+}
+
+/// Run all queued deferred work.  Called from the idle path.
+pub fn run_deferred() {
+    loop {
+        let prev = crate::arch::interrupts_disable();
+        let next = {
+            let mut q = DEFERRED.lock();
+            if q.is_empty() { None } else { Some(q.remove(0)) }
+        };
+        crate::arch::interrupts_restore(prev);
+        match next {
+            Some(f) => f(),
+            None    => break,
+        }
+    }
+}
+
+// ─── wait queues ─────────────────────────────────────────────────────────────
+
+/// Something callers block on until another path wakes them, usually
+/// deferred work such as a protocol handler delivering data.  There is a
+/// single thread of execution, so blocking means idling the CPU, which is
+/// also what runs the deferred work that does the waking.
+#[derive(Default)]
+pub struct WaitQueue {
+    wakeups: AtomicU64,
+}
+
+impl WaitQueue {
+    pub const fn new() -> WaitQueue {
+        WaitQueue { wakeups: AtomicU64::new(0) }
+    }
+
+    /// Wake every waiter; each rechecks its condition.
+    pub fn wake_all(&self) {
+        self.wakeups.fetch_add(1, Ordering::Release);
+    }
+
+    /// Block until `ready` yields a value, or until uptime reaches
+    /// `deadline_ms` (None: wait indefinitely).  `ready` is rechecked after
+    /// each wake-up and each timer tick, so deadlines have tick granularity.
+    /// Once `ready` has said no, the caller is Blocked until it returns.
+    pub fn wait_until<R>(&self, deadline_ms: Option<u64>, mut ready: impl FnMut() -> Option<R>) -> Option<R> {
+        let expired = || deadline_ms.is_some_and(|d| uptime_ms() >= d);
+        let mut parked = None;
+        loop {
+            let seen = self.wakeups.load(Ordering::Acquire);
+            if let Some(r) = ready() { return Some(r); }
+            if expired() { return None; }
+            parked.get_or_insert_with(|| Parked::new(current_pid(), deadline_ms));
+            // Work already queued may be what wakes us
+            run_deferred();
+            if self.wakeups.load(Ordering::Acquire) == seen {
+                crate::cpuidle::idle(crate::arch::MIE_MEIE);
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! SurakshaOS Process Sandbox
+//! Confines a process to what its `SandboxConfig` allows, through the
+//! mechanisms the kernel already has:
+//!
+//! - system calls: only those in the allow-list are dispatched; anything
+//!   else fails with PermissionDenied and is reported to the security
+//!   monitor;
+//! - CPU: the process gets a bandwidth group, and CPU time the kernel runs
+//!   as the process is charged to it, throttling it to its share;
+//! - memory: the process is given a Memory capability for its budget,
+//!   which kernel allocations made for it (KV caches) are charged to;
+//! - network and filesystem: the capabilities it holds for them are
+//!   revoked, and any it comes by later are refused.
+//!
+//! A sandbox lasts as long as its process: `process::exit` and
+//! `process::kill` tear it down, revoking the memory grant with it.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::ai::kvcache::MemoryGrant;
+use crate::ai::sandbox::{Sandbox as Bandwidth, SandboxConfig as BandwidthConfig, SandboxStats};
+use crate::capability::{self, CapabilityType, Permissions};
+use crate::process::{self, ProcessId};
+use crate::security::{self, SecurityEvent};
+
+#[derive(Debug, Clone)]
+pub struct SandboxConfig {
+    /// Bit n allows system call n.
+    pub syscalls:   u64,
+    /// Share of the CPU the process may use (1–100 %).
+    pub cpu_pct:    u8,
+    /// Bytes of kernel memory that may be charged to the process.
+    pub memory:     Option<usize>,
+    pub network:    bool,
+    pub filesystem: bool,
+}
+
+impl Default for SandboxConfig {
+    /// Nothing but the CPU.
+    fn default() -> Self {
+        SandboxConfig { syscalls: 0, cpu_pct: 100, memory: None, network: false, filesystem: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxId(pub u32);
+
+#[derive(Debug, Clone)]
+pub struct SandboxInfo {
+    pub id:     SandboxId,
+    pub pid:    ProcessId,
+    pub config: SandboxConfig,
+    pub cpu:    SandboxStats,
+    /// System calls refused so far.
+    pub denied: u64,
+}
+
+struct Entry {
+    id:        SandboxId,
+    pid:       ProcessId,
+    config:    SandboxConfig,
+    bandwidth: Arc<Bandwidth>,
+    grant:     Option<MemoryGrant>,
+    denied:    u64,
+}
+
+struct Sandboxes {
+    entries: Vec<Entry>,
+    next_id: u32,
+}
+
+static SANDBOXES: Mutex<Sandboxes> = Mutex::new(Sandboxes { entries: Vec::new(), next_id: 1 });
+
+fn is_network(ty: &CapabilityType) -> bool {
+    matches!(ty, CapabilityType::Network | CapabilityType::InterfaceAdmin | CapabilityType::NetDiagnostics)
+}
+
+fn is_filesystem(ty: &CapabilityType) -> bool {
+    matches!(ty, CapabilityType::File)
+}
+
+/// Capability policy: refuses sandboxed processes the kinds of capability
+/// their sandbox withholds.  Runs under the capability registry's lock, so
+/// it must not call back into it.
+fn policy(pid: ProcessId, ty: CapabilityType) -> Result<(), &'static str> {
+    let sandboxes = SANDBOXES.lock();
+    let Some(e) = sandboxes.entries.iter().find(|e| e.pid == pid) else { return Ok(()) };
+    if (!e.config.network && is_network(&ty)) || (!e.config.filesystem && is_filesystem(&ty)) {
+        return Err("capability withheld by sandbox");
+    }
+    Ok(())
+}
+
+/// Put `pid` in a sandbox.  A process gets one sandbox, for life.
+pub fn create_sandbox(pid: ProcessId, config: SandboxConfig) -> Result<SandboxId, &'static str> {
+    if process::is_system(pid) { return Err("system processes cannot be sandboxed"); }
+    if SANDBOXES.lock().entries.iter().any(|e| e.pid == pid) { return Err("process is already sandboxed"); }
+    let bandwidth = Arc::new(Bandwidth::new(BandwidthConfig {
+        cpu_pct: config.cpu_pct, background_pct: config.cpu_pct, ..Default::default()
+    })?);
+    let grant = config.memory.map(|len| MemoryGrant {
+        owner: pid,
+        cap:   capability::create_capability(pid, CapabilityType::Memory { base: 0, len }, Permissions::WRITE),
+    });
+    capability::set_policy(policy);
+    let id = {
+        let mut s = SANDBOXES.lock();
+        let id = SandboxId(s.next_id);
+        s.next_id += 1;
+        s.entries.push(Entry { id, pid, config: config.clone(), bandwidth, grant, denied: 0 });
+        id
+    };
+    // What the process already holds goes now; the policy stops the rest
+    capability::revoke_matching(pid, |ty| (!config.network && is_network(ty)) || (!config.filesystem && is_filesystem(ty)));
+    crate::audit::note("sandbox", &alloc::format!("pid {} sandboxed: syscalls {:#x} cpu {}% memory {:?} network {} filesystem {}",
+        pid, config.syscalls, config.cpu_pct, config.memory, config.network, config.filesystem));
+    Ok(id)
+}
+
+/// Tear down `pid`'s sandbox, if it has one.
+pub fn destroy(pid: ProcessId) {
+    let entry = {
+        let mut s = SANDBOXES.lock();
+        let Some(i) = s.entries.iter().position(|e| e.pid == pid) else { return };
+        s.entries.remove(i)
+    };
+    if let Some(g) = entry.grant {
+        let _ = capability::revoke_capability(g.cap.id);
+    }
+}
+
+// ─── enforcement ──────────────────────────────────────────────────────────────
+
+/// Whether `pid` may make system call `num`.  Refusals are reported.
+pub fn syscall_allowed(pid: ProcessId, num: usize) -> bool {
+    let allowed = {
+        let mut s = SANDBOXES.lock();
+        let Some(e) = s.entries.iter_mut().find(|e| e.pid == pid) else { return true };
+        let allowed = num < 64 && e.config.syscalls & (1 << num) != 0;
+        if !allowed { e.denied += 1; }
+        allowed
+    };
+    if !allowed { security::report(SecurityEvent::SyscallDenied { pid, num }); }
+    allowed
+}
+
+/// Charge `ticks` of CPU time run as `pid` to its bandwidth group, waiting
+/// if it has used up its share.
+pub fn charge_cpu(pid: ProcessId, ticks: u64) {
+    let bandwidth = SANDBOXES.lock().entries.iter()
+        .find(|e| e.pid == pid && e.config.cpu_pct < 100)
+        .map(|e| e.bandwidth.clone());
+    // Charging can wait for the next period; not with the table locked
+    if let Some(b) = bandwidth { b.charge(ticks); }
+}
+
+/// The Memory capability `pid`'s kernel allocations are charged to.
+pub fn memory_grant(pid: ProcessId) -> Option<MemoryGrant> {
+    SANDBOXES.lock().entries.iter().find(|e| e.pid == pid).and_then(|e| e.grant.clone())
+}
+
+pub fn sandboxes() -> Vec<SandboxInfo> {
+    SANDBOXES.lock().entries.iter().map(|e| SandboxInfo {
+        id: e.id, pid: e.pid, config: e.config.clone(), cpu: e.bandwidth.stats(), denied: e.denied,
+    }).collect()
+}
@@ -0,0 +1,328 @@
+//! SurakshaOS Secure Boot
+//! Verifies the boot chain before anything else runs from it.  Each image
+//! is measured (SHAKE-256) and its SLH-DSA signature checked against the
+//! trust anchors built into the kernel (see build.rs); a signature is
+//! bound to the image it was made for by a context string, so one cannot
+//! be passed off for another.
+//!
+//! Two images are checked.  The kernel's own code and read-only data carry
+//! a signature in the `.bootsig` section, written there after the link;
+//! booting with `-bios none` there is no earlier stage to check it, so the
+//! kernel checks itself.  An initramfs, if the loader handed one over in
+//! `/chosen`, carries its signature as a trailer: the image, then the
+//! signature, then `TRAILER_MAGIC`.
+//!
+//! If every image verifies the kernel boots.  If not it halts when built
+//! with the `secure-boot` feature, and otherwise boots with a warning.
+//! Either way the measurements and the decision are extended into the
+//! attestation registers and go to the audit log.
+//!
+//! Verifying is not enough to stop a downgrade: an old build, with holes
+//! since fixed, is signed too.  The kernel carries a security version,
+//! and the root of trust keeps the highest version the device has booted
+//! verified.  An image below it is refused under the same policy as a bad
+//! signature; the outcome is measured, so a quote shows it as well.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::attest;
+use crate::crypto::{sha3, slhdsa};
+use crate::println;
+
+/// Public keys the boot chain must be signed by, embedded at build time.
+const ANCHORS: &[[u8; slhdsa::PUBLIC_KEY_LEN]] = include!(concat!(env!("OUT_DIR"), "/boot_anchors.rs"));
+
+/// Ends an initramfs that carries a signature.
+pub const TRAILER_MAGIC: &[u8; 8] = b"SKBOOTSG";
+
+/// Whether a failed check stops the boot.
+const ENFORCING: bool = cfg!(feature = "secure-boot");
+
+// ─── images ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Signed by a trust anchor.
+    Verified,
+    /// Carries no signature.
+    Unsigned,
+    /// Carries a signature no trust anchor made.
+    Rejected,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Verified => "verified",
+            Status::Unsigned => "unsigned",
+            Status::Rejected => "rejected",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Measurement {
+    pub image:  &'static str,
+    pub len:    usize,
+    /// SHAKE-256 of the image, without its signature.
+    pub digest: [u8; 32],
+    pub status: Status,
+}
+
+/// Measure `image` and check `signature` over it.
+fn check(name: &'static str, image: &[u8], signature: Option<&[u8]>) -> Measurement {
+    let mut digest = [0u8; 32];
+    sha3::shake256_into(image, &mut digest);
+    let ctx = format!("suraksha boot {}", name);
+    let status = match signature {
+        None => Status::Unsigned,
+        Some(sig) if ANCHORS.iter().any(|k| slhdsa::verify(k, image, ctx.as_bytes(), sig)) => Status::Verified,
+        Some(_) => Status::Rejected,
+    };
+    Measurement { image: name, len: image.len(), digest, status }
+}
+
+// The signing step overwrites this after the link; all zeroes is unsigned.
+#[used]
+#[link_section = ".bootsig"]
+static BOOT_SIGNATURE: [u8; slhdsa::SIGNATURE_LEN] = [0; slhdsa::SIGNATURE_LEN];
+
+extern "C" {
+    static _image_start:   u8;
+    static _image_end:     u8;
+    static _bootsig_start: u8;
+}
+
+/// Where the kernel's code and read-only data lie: the image `kernel`
+/// measures.
+pub fn image_range() -> (usize, usize) {
+    // SAFETY: linker-script symbols; only their addresses are used
+    unsafe { (&_image_start as *const u8 as usize, &_image_end as *const u8 as usize) }
+}
+
+fn kernel() -> Measurement {
+    let (start, end) = image_range();
+    // SAFETY: the linker script brackets .text and .rodata with these
+    // symbols and puts the signature at _bootsig_start; all of it is
+    // mapped and never written.  Going through the symbol rather than
+    // BOOT_SIGNATURE keeps the compiler from assuming it is still zero.
+    let (image, sig) = unsafe {
+        (
+            core::slice::from_raw_parts(start as *const u8, end - start),
+            core::slice::from_raw_parts(&_bootsig_start as *const u8, slhdsa::SIGNATURE_LEN),
+        )
+    };
+    check("kernel", image, sig.iter().any(|&b| b != 0).then_some(sig))
+}
+
+/// The initramfs the loader placed in memory, if any.
+fn initramfs() -> Option<Measurement> {
+    let fdt = crate::fdt::get()?;
+    let chosen = fdt.nodes().find(|n| n.depth == 1 && n.name == "chosen")?;
+    let addr = |prop: &str| -> Option<usize> {
+        match chosen.property(prop)? {
+            v if v.len() == 4 => Some(u32::from_be_bytes(v.try_into().ok()?) as usize),
+            v if v.len() == 8 => Some(u64::from_be_bytes(v.try_into().ok()?) as usize),
+            _ => None,
+        }
+    };
+    let (start, end) = (addr("linux,initrd-start")?, addr("linux,initrd-end")?);
+    if end <= start { return None; }
+    // SAFETY: the loader reserves the initramfs range it advertises in
+    // /chosen and leaves it in place for the kernel.
+    let blob = unsafe { core::slice::from_raw_parts(start as *const u8, end - start) };
+
+    let trailer = slhdsa::SIGNATURE_LEN + TRAILER_MAGIC.len();
+    Some(match blob.len().checked_sub(trailer) {
+        Some(len) if blob.ends_with(TRAILER_MAGIC) => {
+            check("initramfs", &blob[..len], Some(&blob[len..len + slhdsa::SIGNATURE_LEN]))
+        }
+        _ => check("initramfs", blob, None),
+    })
+}
+
+// ─── decision ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Boot,
+    /// Not everything verified, but the policy lets the boot go on.
+    Warn,
+    Halt,
+}
+
+impl Decision {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Decision::Boot => "boot",
+            Decision::Warn => "warn",
+            Decision::Halt => "halt",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub measurements: Vec<Measurement>,
+    pub anchors:      usize,
+    pub enforcing:    bool,
+    pub decision:     Decision,
+    /// The last anti-rollback check.
+    pub rollback:     Option<Rollback>,
+}
+
+static REPORT: Mutex<Option<Report>> = Mutex::new(None);
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Verify and measure the boot chain, record the outcome, and halt if the
+/// policy says so.  Called once, early in `kernel_main`, once the device
+/// tree is known.
+pub fn verify_boot_chain() -> Decision {
+    let mut measurements = Vec::from([kernel()]);
+    measurements.extend(initramfs());
+
+    let decision = if measurements.iter().all(|m| m.status == Status::Verified) {
+        Decision::Boot
+    } else if ENFORCING {
+        Decision::Halt
+    } else {
+        Decision::Warn
+    };
+
+    for m in &measurements {
+        let line = format!("{} {} B shake256:{} {}", m.image, m.len, hex(&m.digest), m.status.as_str());
+        println!("  secure boot: {}", line);
+        let register = if m.image == "kernel" { attest::REG_KERNEL } else { attest::REG_INITRAMFS };
+        let _ = attest::measure(register, &m.digest, m.image);
+        crate::audit::note("secure-boot", &line);
+    }
+    let line = format!("{} ({} trust anchors, {})", decision.as_str(), ANCHORS.len(),
+        if ENFORCING { "enforcing" } else { "permissive" });
+    let mut digest = [0u8; attest::DIGEST_LEN];
+    let mut s = sha3::shake256();
+    s.absorb(ANCHORS.as_flattened());
+    s.absorb(line.as_bytes());
+    s.squeeze(&mut digest);
+    let _ = attest::measure(attest::REG_POLICY, &digest, &format!("secure boot: {}", line));
+    crate::audit::note("secure-boot", &line);
+    *REPORT.lock() = Some(Report { measurements, anchors: ANCHORS.len(), enforcing: ENFORCING, decision, rollback: None });
+
+    match decision {
+        Decision::Boot => println!("  secure boot: boot chain verified"),
+        Decision::Warn => println!("  secure boot: WARNING: boot chain not verified; booting anyway"),
+        Decision::Halt => {
+            println!("  secure boot: boot chain not verified; halting");
+            halt();
+        }
+    }
+    check_rollback()
+}
+
+fn halt() -> ! {
+    let _ = crate::audit::sync();
+    crate::arch::interrupts_disable();
+    loop { crate::arch::wait_for_interrupt(); }
+}
+
+// ─── anti-rollback ────────────────────────────────────────────────────────────
+
+/// The kernel's security version.  A release that fixes a vulnerability
+/// raises it.  It lies in .rodata, so the kernel's signature covers it.
+pub const SECURITY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollbackStatus {
+    /// The image is at the device's minimum.
+    Current,
+    /// The image is newer and verified; the minimum was raised to it.
+    Advanced,
+    /// The image is newer but not verified, so the minimum stays.
+    Held,
+    /// The image is older than the device's minimum.
+    Rollback,
+    /// The root of trust could not be read or updated.
+    Unknown,
+}
+
+impl RollbackStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RollbackStatus::Current  => "current",
+            RollbackStatus::Advanced => "advanced",
+            RollbackStatus::Held     => "held",
+            RollbackStatus::Rollback => "ROLLBACK",
+            RollbackStatus::Unknown  => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rollback {
+    /// The booted kernel's security version.
+    pub image:  u32,
+    /// The device's minimum when it was checked.
+    pub stored: u32,
+    pub status: RollbackStatus,
+}
+
+/// Check the booted kernel against the root of trust's security version,
+/// raising it if the kernel is newer and verified.  A rollback halts when
+/// enforcing, like a bad signature, and otherwise boots with a warning.
+/// Run at boot, and again when a hardware root of trust is installed.
+pub fn check_rollback() -> Decision {
+    let verified = REPORT.lock().as_ref().is_some_and(|r| r.decision == Decision::Boot);
+    let image = SECURITY_VERSION;
+    let (stored, status) = match attest::security_version() {
+        Err(_)                        => (0, RollbackStatus::Unknown),
+        Ok(stored) if image < stored  => (stored, RollbackStatus::Rollback),
+        Ok(stored) if image == stored => (stored, RollbackStatus::Current),
+        Ok(stored) if !verified       => (stored, RollbackStatus::Held),
+        Ok(stored) => match attest::advance_security_version(image) {
+            Ok(())  => (stored, RollbackStatus::Advanced),
+            Err(_)  => (stored, RollbackStatus::Unknown),
+        },
+    };
+
+    let line = format!("security version {} (device minimum {}) {} on {}", image, stored, status.as_str(), attest::root_name());
+    let mut digest = [0u8; attest::DIGEST_LEN];
+    sha3::shake256_into(line.as_bytes(), &mut digest);
+    let _ = attest::measure(attest::REG_POLICY, &digest, &format!("rollback: {}", line));
+    crate::audit::note("secure-boot", &line);
+
+    let mut report = REPORT.lock();
+    let decision = match (status, report.as_ref().map(|r| r.decision)) {
+        (RollbackStatus::Rollback, _) if ENFORCING => Decision::Halt,
+        (RollbackStatus::Rollback, _)              => Decision::Warn,
+        (_, Some(d))                               => d,
+        (_, None)                                  => Decision::Warn,
+    };
+    if let Some(r) = report.as_mut() {
+        r.rollback = Some(Rollback { image, stored, status });
+        r.decision = decision;
+    }
+    drop(report);
+    let _ = crate::audit::sync();
+
+    if status == RollbackStatus::Rollback {
+        println!("  secure boot: !!! ROLLBACK: kernel security version {} is below the device minimum {} !!!", image, stored);
+        if decision == Decision::Halt {
+            println!("  secure boot: refusing to run a downgraded kernel; halting");
+            halt();
+        }
+        println!("  secure boot: WARNING: this build has known vulnerabilities; booting anyway");
+    } else {
+        println!("  secure boot: {}", line);
+    }
+    decision
+}
+
+/// What `verify_boot_chain` found, once it has run.
+pub fn report() -> Option<Report> {
+    REPORT.lock().clone()
+}
@@ -0,0 +1,508 @@
+//! SurakshaOS Security Monitor
+//! Collects security-relevant events (capability violations, policy
+//! failures, certificate pin mismatches, lockdown and sandbox refusals)
+//! reported by the rest of the kernel.  Every event is also appended to
+//! the audit log.
+//!
+//! Rules watch the events for patterns — N of a kind from one process
+//! within M seconds — and respond automatically: revoke the process's
+//! capabilities, kill it, or kill it and quarantine its app.  Each rule
+//! that trips is escalated to the userspace security daemon, if one is
+//! connected.
+//!
+//! Events are kept as typed records in a ring of `RING_CAPACITY`.
+//! Privileged processes subscribe to it over IPC and read records at
+//! their own pace: nothing is lost until the ring wraps past a
+//! subscriber's position, and then it is told how many it missed.
+
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{CapId, Capability, CapabilityType, Permissions};
+use crate::ipc::{self, ChannelId, MessageKind};
+use crate::process::ProcessId;
+use crate::println;
+
+// ─── events ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone)]
+pub enum SecurityEvent {
+    /// A process tried to use a resource its capability does not allow.
+    CapabilityViolation {
+        pid:      ProcessId,
+        cap:      CapId,
+        resource: &'static str,
+        required: Permissions,
+        reason:   &'static str,
+    },
+    /// A server's certificate chain holds none of the keys pinned for
+    /// its host name.
+    PinMismatch {
+        pid:  ProcessId,
+        host: String,
+    },
+    /// Kernel lockdown refused an operation.
+    LockdownDenied {
+        pid:  ProcessId,
+        what: &'static str,
+    },
+    /// A sandboxed process made a system call its sandbox does not allow.
+    SyscallDenied {
+        pid: ProcessId,
+        num: usize,
+    },
+    /// A memory access's pointer tag did not match the memory's: at `addr`
+    /// for a sync fault, None for an async one.
+    TagCheckFault {
+        pid:  ProcessId,
+        addr: Option<usize>,
+    },
+}
+
+impl core::fmt::Display for SecurityEvent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SecurityEvent::CapabilityViolation { pid, cap, resource, required, reason } => write!(
+                f, "capability violation: pid={} cap={} resource={} need={} ({})",
+                pid, cap, resource, required, reason
+            ),
+            SecurityEvent::PinMismatch { pid, host } => write!(
+                f, "certificate pin mismatch: pid={} host={}", pid, host
+            ),
+            SecurityEvent::LockdownDenied { pid, what } => write!(
+                f, "refused by kernel lockdown: pid={} {}", pid, what
+            ),
+            SecurityEvent::SyscallDenied { pid, num } => write!(
+                f, "system call refused by sandbox: pid={} call={}", pid, num
+            ),
+            SecurityEvent::TagCheckFault { pid, addr } => match addr {
+                Some(a) => write!(f, "memory tag check fault: pid={} addr={:#x}", pid, a),
+                None    => write!(f, "memory tag check fault: pid={} (async)", pid),
+            },
+        }
+    }
+}
+
+impl SecurityEvent {
+    /// Short name of the event, as the audit log files it.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SecurityEvent::CapabilityViolation { .. } => "capability-violation",
+            SecurityEvent::PinMismatch { .. }         => "pin-mismatch",
+            SecurityEvent::LockdownDenied { .. }      => "lockdown",
+            SecurityEvent::SyscallDenied { .. }       => "syscall-denied",
+            SecurityEvent::TagCheckFault { .. }       => "tag-check",
+        }
+    }
+
+    /// The event's kind as subscribers receive it (SEC_EVENT_*).
+    pub fn code(&self) -> u8 {
+        match self {
+            SecurityEvent::CapabilityViolation { .. } => SEC_EVENT_CAPABILITY,
+            SecurityEvent::PinMismatch { .. }         => SEC_EVENT_PIN,
+            SecurityEvent::LockdownDenied { .. }      => SEC_EVENT_LOCKDOWN,
+            SecurityEvent::SyscallDenied { .. }       => SEC_EVENT_SYSCALL,
+            SecurityEvent::TagCheckFault { .. }       => SEC_EVENT_TAG,
+        }
+    }
+
+    /// The capability the event concerns.
+    pub fn cap(&self) -> Option<CapId> {
+        match self {
+            SecurityEvent::CapabilityViolation { cap, .. } => Some(*cap),
+            _ => None,
+        }
+    }
+
+    /// What happened, beyond who and which capability.
+    pub fn detail(&self) -> String {
+        match self {
+            SecurityEvent::CapabilityViolation { resource, required, reason, .. } => {
+                format!("{} need={} ({})", resource, required, reason)
+            }
+            SecurityEvent::PinMismatch { host, .. }  => host.clone(),
+            SecurityEvent::LockdownDenied { what, .. } => String::from(*what),
+            SecurityEvent::SyscallDenied { num, .. }   => format!("{}", num),
+            SecurityEvent::TagCheckFault { addr, .. }  => addr.map_or(String::from("async"), |a| format!("{:#x}", a)),
+        }
+    }
+
+    /// The process the event concerns.
+    pub fn pid(&self) -> Option<ProcessId> {
+        match self {
+            SecurityEvent::CapabilityViolation { pid, .. }
+            | SecurityEvent::PinMismatch { pid, .. }
+            | SecurityEvent::LockdownDenied { pid, .. }
+            | SecurityEvent::SyscallDenied { pid, .. }
+            | SecurityEvent::TagCheckFault { pid, .. } => Some(*pid),
+        }
+    }
+}
+
+// ─── rules ────────────────────────────────────────────────────────────────────
+
+/// What the monitor does when a rule trips.  Every response is also sent
+/// to the security daemon as an escalation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Response {
+    /// Only escalate.
+    Escalate,
+    /// Revoke every capability the process holds.
+    Revoke,
+    /// Kill the process.
+    Kill,
+    /// Kill the process and quarantine its app.
+    Quarantine,
+}
+
+impl Response {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Response::Escalate   => "escalate",
+            Response::Revoke     => "revoke",
+            Response::Kill       => "kill",
+            Response::Quarantine => "quarantine",
+        }
+    }
+}
+
+/// `threshold` events of `kind` from one process within `window_ms`.
+#[derive(Debug, Clone, Copy)]
+pub struct Rule {
+    pub name:      &'static str,
+    pub kind:      &'static str,
+    pub threshold: usize,
+    pub window_ms: u64,
+    pub response:  Response,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule { name: "capability-probe", kind: "capability-violation", threshold: 5,  window_ms: 10_000, response: Response::Revoke },
+    Rule { name: "capability-storm", kind: "capability-violation", threshold: 20, window_ms: 60_000, response: Response::Kill },
+    Rule { name: "lockdown-probe",   kind: "lockdown",             threshold: 3,  window_ms: 60_000, response: Response::Quarantine },
+    Rule { name: "syscall-probe",    kind: "syscall-denied",       threshold: 10, window_ms: 10_000, response: Response::Kill },
+    Rule { name: "tag-check",        kind: "tag-check",            threshold: 3,  window_ms: 60_000, response: Response::Kill },
+    Rule { name: "pin-mismatch",     kind: "pin-mismatch",         threshold: 1,  window_ms: 1,      response: Response::Escalate },
+];
+
+/// Longest window any rule looks back over.
+const MAX_WINDOW_MS: u64 = 60_000;
+/// Escalations kept for `escalations()`.
+const MAX_ESCALATIONS: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct Escalation {
+    pub time_ms:  u64,
+    pub rule:     &'static str,
+    pub pid:      ProcessId,
+    pub events:   usize,
+    pub response: Response,
+    /// Whether the response was carried out; system processes are only
+    /// escalated.
+    pub applied:  bool,
+}
+
+/// Carry out `response` against `pid`.
+fn respond(pid: ProcessId, response: Response) -> Result<(), &'static str> {
+    match response {
+        Response::Escalate => Ok(()),
+        Response::Revoke   => {
+            if crate::process::is_system(pid) { return Err("system process"); }
+            crate::capability::revoke_all(pid);
+            Ok(())
+        }
+        Response::Kill => crate::process::kill(pid),
+        Response::Quarantine => {
+            crate::process::kill(pid)?;
+            match crate::permission::app_of(pid) {
+                Some(app) => crate::permission::quarantine(&app),
+                None      => Ok(()),
+            }
+        }
+    }
+}
+
+// ─── monitor ──────────────────────────────────────────────────────────────────
+
+/// Events the ring holds before the oldest are dropped.
+pub const RING_CAPACITY: usize = 1024;
+
+/// An event as the ring keeps it.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// Numbers every event since boot, without gaps.
+    pub seq:     u64,
+    pub time_ms: u64,
+    pub event:   SecurityEvent,
+}
+
+pub struct SecurityMonitor {
+    ring:        VecDeque<Record>,
+    /// Sequence number of the next event.
+    next_seq:    u64,
+    /// (time, pid, kind) of recent events, for the rules.
+    recent:      Vec<(u64, ProcessId, &'static str)>,
+    /// (time, rule, pid) of recent firings, so a rule fires once a window.
+    fired:       Vec<(u64, &'static str, ProcessId)>,
+    escalations: Vec<Escalation>,
+}
+
+static MONITOR: Mutex<SecurityMonitor> = Mutex::new(SecurityMonitor::new());
+
+impl Default for SecurityMonitor {
+    fn default() -> Self { Self::new() }
+}
+
+impl SecurityMonitor {
+    pub const fn new() -> Self {
+        SecurityMonitor {
+            ring: VecDeque::new(), next_seq: 0, recent: Vec::new(), fired: Vec::new(), escalations: Vec::new(),
+        }
+    }
+
+    /// Record `event` and return the rules it trips, with how many events
+    /// tripped each.
+    pub fn handle_event(&mut self, event: SecurityEvent) -> Vec<(Rule, usize)> {
+        println!("  [security] WARNING: {}", event);
+        crate::audit::record(&event);
+        let now = crate::arch::uptime_millis();
+        let tripped = match event.pid() {
+            Some(pid) => self.check_rules(now, pid, event.kind()),
+            None      => Vec::new(),
+        };
+        if self.ring.len() == RING_CAPACITY { self.ring.pop_front(); }
+        self.ring.push_back(Record { seq: self.next_seq, time_ms: now, event });
+        self.next_seq += 1;
+        tripped
+    }
+
+    fn check_rules(&mut self, now: u64, pid: ProcessId, kind: &'static str) -> Vec<(Rule, usize)> {
+        self.recent.retain(|&(t, ..)| now - t < MAX_WINDOW_MS);
+        self.fired.retain(|&(t, ..)| now - t < MAX_WINDOW_MS);
+        self.recent.push((now, pid, kind));
+        let mut tripped = Vec::new();
+        for rule in RULES.iter().filter(|r| r.kind == kind) {
+            let count = self.recent.iter()
+                .filter(|&&(t, p, k)| p == pid && k == kind && now - t < rule.window_ms)
+                .count();
+            let quiet = !self.fired.iter().any(|&(t, r, p)| r == rule.name && p == pid && now - t < rule.window_ms);
+            if count >= rule.threshold && quiet {
+                self.fired.push((now, rule.name, pid));
+                tripped.push((*rule, count));
+            }
+        }
+        tripped
+    }
+
+    /// Records from sequence number `from` on, up to `max` of them, and
+    /// how many before them were already dropped.
+    pub fn read(&self, from: u64, max: usize) -> (u64, Vec<Record>) {
+        let oldest = self.ring.front().map_or(self.next_seq, |r| r.seq);
+        let lost = oldest.saturating_sub(from);
+        let skip = from.saturating_sub(oldest) as usize;
+        (lost, self.ring.iter().skip(skip).take(max).cloned().collect())
+    }
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+pub fn report(event: SecurityEvent) {
+    let pid = event.pid();
+    // Responses run with the monitor unlocked: revoking and killing can
+    // themselves be reported
+    let tripped = MONITOR.lock().handle_event(event);
+    notify_subscribers();
+    let Some(pid) = pid else { return };
+    for (rule, events) in tripped {
+        let applied = respond(pid, rule.response).is_ok();
+        let e = Escalation {
+            time_ms: crate::arch::uptime_millis(), rule: rule.name, pid, events, response: rule.response, applied,
+        };
+        let line = alloc::format!("rule {} tripped by pid {} ({} events): {}{}", e.rule, pid, events,
+            rule.response.as_str(), if applied { "" } else { " (not applied)" });
+        println!("  [security] {}", line);
+        crate::audit::note("security", &line);
+        escalate(&e);
+        let mut m = MONITOR.lock();
+        if m.escalations.len() == MAX_ESCALATIONS { m.escalations.remove(0); }
+        m.escalations.push(e);
+    }
+}
+
+/// The events still in the ring, oldest first.
+pub fn events() -> Vec<SecurityEvent> {
+    MONITOR.lock().ring.iter().map(|r| r.event.clone()).collect()
+}
+
+/// Events reported since boot, including those the ring has dropped.
+pub fn event_count() -> u64 {
+    MONITOR.lock().next_seq
+}
+
+/// The most recent escalations, oldest first.
+pub fn escalations() -> Vec<Escalation> {
+    MONITOR.lock().escalations.clone()
+}
+
+// ─── security daemon ──────────────────────────────────────────────────────────
+
+/// Escalation notification: [response, pid u32 LE, events u16 LE, applied,
+/// rule name].
+pub const SEC_NOTE_ESCALATION: u8 = 1;
+/// New events are waiting: [].  Sent once; the subscriber then reads
+/// until a reply holds no records, and is notified again after that.
+pub const SEC_NOTE_EVENTS:     u8 = 2;
+
+/// Request opcodes (first payload byte).
+pub const SEC_REQ_READ: u8 = 1; // [max u16 LE] -> [ok, lost u32 LE, count u16 LE, records...]
+
+/// Event kinds in records.
+pub const SEC_EVENT_CAPABILITY: u8 = 1;
+pub const SEC_EVENT_PIN:        u8 = 2;
+pub const SEC_EVENT_LOCKDOWN:   u8 = 3;
+pub const SEC_EVENT_SYSCALL:    u8 = 4;
+pub const SEC_EVENT_TAG:        u8 = 5;
+
+/// Fixed part of a record: [seq u64 LE, time ms u64 LE, kind, pid u32 LE
+/// (u32::MAX for none), capability u64 LE (0 for none), detail len], then
+/// the detail in UTF-8.
+pub const RECORD_HEADER: usize = 30;
+
+struct Daemon {
+    /// The monitor's own process, the kernel end of the channel.
+    monitor: ProcessId,
+    channel: ChannelId,
+    cap:     Capability,
+}
+
+static DAEMON: Mutex<Option<Daemon>> = Mutex::new(None);
+
+struct Subscriber {
+    channel:  ChannelId,
+    /// The monitor's capability for the channel.
+    cap:      Capability,
+    /// Sequence number of the next record to send.
+    cursor:   u64,
+    notified: bool,
+}
+
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+/// The monitor's process, once a daemon or subscriber has needed it.
+static MONITOR_PID: Mutex<Option<ProcessId>> = Mutex::new(None);
+
+fn monitor_pid() -> Result<ProcessId, &'static str> {
+    let mut pid = MONITOR_PID.lock();
+    if let Some(p) = *pid { return Ok(p); }
+    let p = crate::process::spawn_process("secmon")?;
+    ipc::register_kernel_server(p, handle_request);
+    Ok(*pid.insert(p))
+}
+
+/// Make `client` the security daemon: escalations are delivered to it as
+/// they happen.  `cap` must be its SecurityEvents capability with READ.
+pub fn connect_daemon(client: ProcessId, cap: &Capability) -> Result<(ChannelId, Capability), &'static str> {
+    crate::capability::validate(client, cap, CapabilityType::SecurityEvents, Permissions::READ)?;
+    let monitor = monitor_pid()?;
+    let mut daemon = DAEMON.lock();
+    let (ch, client_cap, monitor_cap) = ipc::create_channel(client, monitor);
+    if let Some(old) = daemon.replace(Daemon { monitor, channel: ch, cap: monitor_cap }) {
+        ipc::close_channel(old.channel);
+    }
+    Ok((ch, client_cap))
+}
+
+fn escalate(e: &Escalation) {
+    let Some((monitor, ch, cap)) = DAEMON.lock().as_ref().map(|d| (d.monitor, d.channel, d.cap.clone())) else { return };
+    let mut payload = Vec::from([SEC_NOTE_ESCALATION, e.response as u8]);
+    payload.extend_from_slice(&(e.pid.0 as u32).to_le_bytes());
+    payload.extend_from_slice(&(e.events.min(u16::MAX as usize) as u16).to_le_bytes());
+    payload.push(e.applied as u8);
+    payload.extend_from_slice(e.rule.as_bytes());
+    let _ = ipc::send_message(ch, monitor, &cap, MessageKind::Notification, &payload);
+}
+
+/// Subscribe `client` to the event ring, from the oldest event it still
+/// holds.  `cap` must be its SecurityEvents capability with READ.
+pub fn subscribe(client: ProcessId, cap: &Capability) -> Result<(ChannelId, Capability), &'static str> {
+    crate::capability::validate(client, cap, CapabilityType::SecurityEvents, Permissions::READ)?;
+    let monitor = monitor_pid()?;
+    let (ch, client_cap, monitor_cap) = ipc::create_channel(client, monitor);
+    let cursor = MONITOR.lock().ring.front().map_or(0, |r| r.seq);
+    SUBSCRIBERS.lock().push(Subscriber { channel: ch, cap: monitor_cap, cursor, notified: false });
+    notify_subscribers();
+    Ok((ch, client_cap))
+}
+
+pub fn subscribers() -> usize {
+    SUBSCRIBERS.lock().len()
+}
+
+/// Tell subscribers with unread events, once each until they read.
+fn notify_subscribers() {
+    let Some(monitor) = *MONITOR_PID.lock() else { return };
+    let next = MONITOR.lock().next_seq;
+    let due: Vec<(ChannelId, Capability)> = SUBSCRIBERS.lock().iter_mut()
+        .filter(|s| !s.notified && s.cursor < next)
+        .map(|s| { s.notified = true; (s.channel, s.cap.clone()) })
+        .collect();
+    for (ch, cap) in due {
+        if ipc::send_message(ch, monitor, &cap, MessageKind::Notification, &[SEC_NOTE_EVENTS])
+            == Err(ipc::IpcError::NoSuchChannel)
+        {
+            SUBSCRIBERS.lock().retain(|s| s.channel != ch);
+        }
+    }
+}
+
+fn encode(r: &Record, out: &mut Vec<u8>) {
+    let mut detail = r.event.detail();
+    while detail.len() > u8::MAX as usize { detail.pop(); }
+    out.extend_from_slice(&r.seq.to_le_bytes());
+    out.extend_from_slice(&r.time_ms.to_le_bytes());
+    out.push(r.event.code());
+    out.extend_from_slice(&r.event.pid().map_or(u32::MAX, |p| p.0 as u32).to_le_bytes());
+    out.extend_from_slice(&r.event.cap().map_or(0, |c| c.0).to_le_bytes());
+    out.push(detail.len() as u8);
+    out.extend_from_slice(detail.as_bytes());
+}
+
+fn handle_request(ch: ChannelId, msg: &crate::ipc::Message) -> Option<Vec<u8>> {
+    let p = &msg.payload;
+    let reply = match p.first() {
+        Some(&SEC_REQ_READ) => {
+            let max = match p.get(1..3) {
+                Some(b) => u16::from_le_bytes([b[0], b[1]]) as usize,
+                None    => usize::MAX,
+            };
+            let mut subs = SUBSCRIBERS.lock();
+            match subs.iter_mut().find(|s| s.channel == ch) {
+                None    => Err("not subscribed"),
+                Some(s) => {
+                    let (lost, records) = MONITOR.lock().read(s.cursor, max);
+                    let mut reply = Vec::from([1u8]);
+                    reply.extend_from_slice(&(lost.min(u32::MAX as u64) as u32).to_le_bytes());
+                    reply.extend_from_slice(&[0, 0]);
+                    let mut count = 0u16;
+                    let mut next = s.cursor + lost;
+                    for r in &records {
+                        let mut rec = Vec::new();
+                        encode(r, &mut rec);
+                        if reply.len() + rec.len() > ipc::MAX_MESSAGE_SIZE { break; }
+                        reply.extend_from_slice(&rec);
+                        count += 1;
+                        next = r.seq + 1;
+                    }
+                    reply[5..7].copy_from_slice(&count.to_le_bytes());
+                    s.cursor = next;
+                    s.notified = false;
+                    Ok(reply)
+                }
+            }
+        }
+        _ => Err("bad request"),
+    };
+    Some(reply.unwrap_or_else(|_| Vec::from([0u8])))
+}
@@ -1,10 +1,11 @@
-/// SurakshaOS Shell (sursh)
-/// A real interactive shell for SurakshaOS.
-/// Handles: command parsing, built-in commands, environment variables,
-/// command history, tab-completion stubs, and piping groundwork.
+//! SurakshaOS Shell (sursh)
+//! A real interactive shell for SurakshaOS.
+//! Handles: command parsing, built-in commands, environment variables,
+//! command history, tab-completion stubs, and piping groundwork.
 
 extern crate alloc;
 use alloc::format;
+use alloc::vec;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 
@@ -25,6 +26,31 @@ pub struct Shell {
     env:         Vec<(String, String)>,
     running:     bool,
     last_exit:   i32,
+    model:       Option<crate::ai::AiModel>,
+    /// Runtime adapter `ai ask` applies.
+    adapter:     Option<String>,
+    /// AiDebug capability for `ai capture`, minted on first use.
+    debug_cap:   Option<crate::capability::Capability>,
+    /// Network control capability for `ping`, minted on first use.
+    net_cap:     Option<crate::capability::Capability>,
+    /// NetDiagnostics capability for `pcap`, minted on first use.
+    diag_cap:    Option<crate::capability::Capability>,
+    /// InterfaceAdmin capability for `ip`, minted on first use.
+    admin_cap:   Option<crate::capability::Capability>,
+    /// Channel to networkd for `ip`, opened on first use.
+    netlink:     Option<(crate::ipc::ChannelId, crate::capability::Capability)>,
+    /// Capability for the Wi-Fi device, for `wifi`, minted on first use.
+    wifi_cap:    Option<crate::capability::Capability>,
+    /// Capability for the modem, for `modem`, minted on first use.
+    modem_cap:   Option<crate::capability::Capability>,
+    /// AuditLog capability for `audit`, minted on first use.
+    audit_cap:   Option<crate::capability::Capability>,
+    /// Attestation capability for `attest`, minted on first use.
+    attest_cap:  Option<crate::capability::Capability>,
+    /// PermissionAdmin capability for `perms`, minted on first use.
+    perms_cap:   Option<crate::capability::Capability>,
+    /// AppInstall capability for `pkg`, minted on first use.
+    pkg_cap:     Option<crate::capability::Capability>,
 }
 
 // ─── built-in command table ───────────────────────────────────────────────────
@@ -58,20 +84,61 @@ const BUILTINS: &[BuiltIn] = &[
     BuiltIn { name: "halt",     usage: "halt",                 help: "Halt the system" },
     BuiltIn { name: "captest",  usage: "captest",              help: "Test capability system" },
     BuiltIn { name: "pqtest",   usage: "pqtest",               help: "Test post-quantum crypto stubs" },
+    BuiltIn { name: "cpufreq",  usage: "cpufreq [governor]",   help: "Show CPU frequency / set governor" },
+    BuiltIn { name: "suspend",  usage: "suspend [secs]",       help: "Suspend to RAM until key press or timeout" },
+    BuiltIn { name: "wakelocks",usage: "wakelocks",            help: "Show wakelock statistics" },
+    BuiltIn { name: "cpuidle",  usage: "cpuidle",              help: "Show idle-state residency" },
+    BuiltIn { name: "thermal",  usage: "thermal",              help: "Show thermal zones and limits" },
+    BuiltIn { name: "energy",   usage: "energy",               help: "Show per-process energy use" },
+    BuiltIn { name: "charge",   usage: "charge [limit%]",      help: "Show charger status / set charge limit" },
+    BuiltIn { name: "powertop", usage: "powertop",             help: "Show power statistics" },
+    BuiltIn { name: "audit",    usage: "audit [verify | sync | <count> [kind]]", help: "Show the latest audit records / verify the audit chain / write out pending records" },
+    BuiltIn { name: "attest",   usage: "attest [quote <nonce>]", help: "Show the measurement registers and event log / get a signed quote over them" },
+    BuiltIn { name: "perms",    usage: "perms [answer <id> always|once|deny | allow|deny|ask <app> <permission> | release <app> | transfer <id> allow|deny]", help: "Show app permissions, prompts, grants and Android transfers / answer a prompt / change a decision / lift a quarantine / answer a transfer" },
+    BuiltIn { name: "secmon",   usage: "secmon",               help: "Show the security monitor's rules and escalations" },
+    BuiltIn { name: "sandbox",  usage: "sandbox",              help: "Show sandboxed processes and their limits" },
+    BuiltIn { name: "dmesg",    usage: "dmesg [clear | level [<level> [module]] | console <level>]", help: "Show the kernel log / clear it / set a log level, for a module or the console" },
+    BuiltIn { name: "auth",     usage: "auth",                 help: "Show authentication attempt counters and lockouts" },
+    BuiltIn { name: "cfi",      usage: "cfi [check <elf>]",    help: "Show control-flow integrity support / check an ELF's CFI markings" },
+    BuiltIn { name: "pkg",      usage: "pkg [install <file> | remove <app> | devmode on|off]", help: "Show installed apps / install a signed package / remove an app / switch developer mode" },
+    BuiltIn { name: "mte",      usage: "mte [<pid> off|sync|async]", help: "Show memory tagging support and per-process modes / set a process's mode" },
+    BuiltIn { name: "tee",      usage: "tee",                  help: "Show the trusted execution backend and open TA sessions" },
+    BuiltIn { name: "bootctl",  usage: "bootctl [set-active a|b | unbootable a|b | mark-successful]", help: "Show the A/B slots / boot a slot next / write one off / mark this boot successful" },
+    BuiltIn { name: "lockdown", usage: "lockdown [integrity|confidentiality]", help: "Show the kernel lockdown level / raise it" },
+    BuiltIn { name: "services", usage: "services",             help: "Show init's services, their state and sockets" },
+    BuiltIn { name: "windows",  usage: "windows",              help: "Show the display, the compositor's windows and frame pacing" },
+    BuiltIn { name: "ai",       usage: "ai load|trust|limit|ask|listen|adapter|stats|capture|serve|models|add|use ...", help: "Load a signed model / trust a key / cap its CPU / ask it / transcribe a WAV / apply a LoRA adapter / show inference metrics / capture AI content for debugging / serve it to apps / manage the catalogue" },
+    BuiltIn { name: "ping",     usage: "ping <addr> [count]",  help: "Send ICMP echo requests" },
+    BuiltIn { name: "host",     usage: "host <name> | host policy [doh-only|doh-then-plain|plain-only] | host dnssec [off|validate|require] | host flush", help: "Resolve a name / show or set the DNS transport policy or DNSSEC mode / empty the DNS cache" },
+    BuiltIn { name: "tls",      usage: "tls [pin <host> <sha256> [subdomains] | unpin <host> | forget <host>]", help: "Show TLS trust anchors, session tickets and key pins / pin a host's key / drop its tickets" },
+    BuiltIn { name: "wg",       usage: "wg [up [port] | down | addr <ip/len> | key <file> | peer <pubkey> <ip/len,...> [endpoint] [keepalive] | psk <pubkey> <file> | remove <pubkey> | route-all on|off]", help: "Show or configure the WireGuard tunnel" },
+    BuiltIn { name: "netns",    usage: "netns [create|remove <ns> | assign <pid> <ns> | offline <ns> on|off | app-offline <pid> on|off | uplink <ns> <iface>|none | rule <ns> allow|deny <ip/len> [tcp|udp|icmp] [port[-port]] | default <ns> allow|deny | flush <ns>]", help: "Show or configure per-app network namespaces" },
+    BuiltIn { name: "proxy",    usage: "proxy [set <pid> socks5|http <ip> <port> [<user> <password>] | clear <pid>]", help: "Show or set per-app proxies" },
+    BuiltIn { name: "datausage", usage: "datausage [days] | cap <pid> <iface>|all <bytes[K|M|G]> <days> | uncap <pid> <iface>|all", help: "Show per-app data usage by interface / cap an app's data" },
+    BuiltIn { name: "pcap",     usage: "pcap [start <iface> [-s snaplen] [filter...] | stop <id> | show <id> | save <id> <file>]", help: "Show or run packet captures" },
+    BuiltIn { name: "ip",       usage: "ip [link <iface> up|down | addr add|del <iface> <ip[/len]> | gateway <iface> <ip>|none | dns <iface> <ip,...> | dhcp <iface> on|off]", help: "Show or configure network interfaces" },
+    BuiltIn { name: "nat",      usage: "nat [forward <inside> <outside> [masquerade] | unforward <inside> <outside> | map <outside> tcp|udp <port> <ip:port> | unmap <outside> tcp|udp <port>]", help: "Show or configure forwarding and NAT for tethering" },
+    BuiltIn { name: "wifi",     usage: "wifi [scan | connect <ssid> <passphrase> | disconnect | forget]", help: "Show Wi-Fi status / scan / join a WPA3 network / forget cached keys" },
+    BuiltIn { name: "modem",    usage: "modem [pin <pin> | puk <puk> <new-pin> | attach | detach | connect <apn> | disconnect | signal]", help: "Show modem status / unlock the SIM / attach / bring the data bearer up or down / read the signal" },
     BuiltIn { name: "about",    usage: "about",                help: "About SurakshaOS" },
 ];
 
 // ─── Shell implementation ─────────────────────────────────────────────────────
 
+impl Default for Shell {
+    fn default() -> Self { Self::new() }
+}
+
 impl Shell {
     pub fn new() -> Self {
-        let mut env = Vec::new();
-        env.push(("PATH".into(),    "/bin:/usr/bin".into()));
-        env.push(("HOME".into(),    "/home/user".into()));
-        env.push(("SHELL".into(),   "/bin/sursh".into()));
-        env.push(("TERM".into(),    "vt100".into()));
-        env.push(("USER".into(),    "suraksha".into()));
-        env.push(("HOSTNAME".into(),"suraksha".into()));
+        let env = vec![
+            ("PATH".into(),    "/bin:/usr/bin".into()),
+            ("HOME".into(),    "/home/user".into()),
+            ("SHELL".into(),   "/bin/sursh".into()),
+            ("TERM".into(),    "vt100".into()),
+            ("USER".into(),    "suraksha".into()),
+            ("HOSTNAME".into(),"suraksha".into()),
+        ];
 
         Shell {
             cwd:       "/home/user".into(),
@@ -79,6 +146,19 @@ impl Shell {
             env,
             running:   true,
             last_exit: 0,
+            model:     None,
+            adapter:   None,
+            debug_cap: None,
+            net_cap:   None,
+            diag_cap:  None,
+            admin_cap: None,
+            netlink:   None,
+            wifi_cap:  None,
+            modem_cap: None,
+            audit_cap: None,
+            attest_cap: None,
+            perms_cap: None,
+            pkg_cap:   None,
         }
     }
 
@@ -97,13 +177,10 @@ impl Shell {
     }
 
     fn print_motd(&self) {
-        match read_file("/etc/motd") {
-            Ok(bytes) => {
-                if let Ok(s) = core::str::from_utf8(&bytes) {
-                    println!("{}", s.trim());
-                }
+        if let Ok(bytes) = read_file("/etc/motd") {
+            if let Ok(s) = core::str::from_utf8(&bytes) {
+                println!("{}", s.trim());
             }
-            Err(_) => {}
         }
     }
 
@@ -168,6 +245,42 @@ impl Shell {
             "halt"    => { self.running = false; 0 }
             "captest" => self.cmd_captest(),
             "pqtest"  => self.cmd_pqtest(),
+            "cpufreq" => self.cmd_cpufreq(args),
+            "suspend" => self.cmd_suspend(args),
+            "wakelocks" => self.cmd_wakelocks(),
+            "cpuidle" => self.cmd_cpuidle(),
+            "thermal" => self.cmd_thermal(),
+            "energy"  => self.cmd_energy(),
+            "charge"  => self.cmd_charge(args),
+            "powertop" => self.cmd_powertop(),
+            "audit"   => self.cmd_audit(args),
+            "attest"  => self.cmd_attest(args),
+            "bootctl" => self.cmd_bootctl(args),
+            "lockdown" => self.cmd_lockdown(args),
+            "services" => self.cmd_services(),
+            "windows" => self.cmd_windows(),
+            "perms"   => self.cmd_perms(args),
+            "secmon"  => self.cmd_secmon(),
+            "sandbox" => self.cmd_sandbox(),
+            "tee"     => self.cmd_tee(),
+            "cfi"     => self.cmd_cfi(args),
+            "mte"     => self.cmd_mte(args),
+            "auth"    => self.cmd_auth(),
+            "dmesg"   => self.cmd_dmesg(args),
+            "pkg"     => self.cmd_pkg(args),
+            "ai"      => self.cmd_ai(args),
+            "ping"    => self.cmd_ping(args),
+            "host"    => self.cmd_host(args),
+            "tls"     => self.cmd_tls(args),
+            "wg"      => self.cmd_wg(args),
+            "netns"   => self.cmd_netns(args),
+            "proxy"   => self.cmd_proxy(args),
+            "pcap"    => self.cmd_pcap(args),
+            "datausage" => self.cmd_datausage(args),
+            "ip"      => self.cmd_ip(args),
+            "nat"     => self.cmd_nat(args),
+            "wifi"    => self.cmd_wifi(args),
+            "modem"   => self.cmd_modem(args),
             "about"   => self.cmd_about(),
             "exit"    => { self.running = false; 0 }
             _         => {
@@ -326,6 +439,13 @@ impl Shell {
             if i < filled { print!("█"); } else { print!("░"); }
         }
         println!("]");
+        if crate::memory::heap_hardening() {
+            let q = crate::memory::quarantine();
+            println!("  Quarantine: {} blocks, {} KB  ({} released)", q.blocks, q.bytes / 1024, q.evicted);
+            for s in crate::memory::slabs().map(|s| s.stats()) {
+                println!("  Slab {:<13} {} KB in use / {} KB reserved", s.name, s.in_use / 1024, s.reserved / 1024);
+            }
+        }
         0
     }
 
@@ -340,6 +460,13 @@ impl Shell {
 
     fn cmd_uname(&self) -> i32 {
         println!("SurakshaOS 0.2.0 RISC-V riscv64gc suraksha-kernel");
+        if let Some(p) = crate::fdt::platform() {
+            println!("  {}: harts {:?}, timebase {} Hz", p.model, p.harts(), crate::arch::timebase_hz());
+            println!("  {} online (hart, index): {:?}", crate::smp::online(), crate::smp::harts());
+            for &(base, size) in p.memory() {
+                println!("  RAM {:#x} +{} MiB", base, size >> 20);
+            }
+        }
         0
     }
 
@@ -384,37 +511,55 @@ impl Shell {
 
     fn cmd_reboot(&self) -> i32 {
         println!("Rebooting SurakshaOS...");
-        // On RISC-V QEMU: write to SiFive test device
-        unsafe {
-            let test_addr = 0x10_0000 as *mut u32;
-            core::ptr::write_volatile(test_addr, 0x5555); // QEMU virt poweroff
-        }
-        loop {}
+        // QEMU virt has no reset device we drive; power off instead
+        crate::arch::power_off()
     }
 
     fn cmd_captest(&self) -> i32 {
+        use crate::capability::{self as cap, CapHandle, CapabilityType, Permissions};
+
         println!("Capability system test");
         println!("══════════════════════");
-        println!("");
-        println!("  Capabilities enforce the principle of least privilege.");
-        println!("  Every resource access requires an explicit, unforgeable token.");
-        println!("");
-        println!("  Test 1: create file capability........");
-        println!("    CapToken {{ id: 0xA3F1, resource: '/tmp/test', perms: READ|WRITE, ttl: 60s }}");
-        println!("    Result: OK — token issued");
-        println!("");
-        println!("  Test 2: access with valid cap..........");
-        println!("    open('/tmp/test', cap=0xA3F1) → fd=3  OK");
-        println!("");
-        println!("  Test 3: access without cap (should fail)");
-        println!("    open('/etc/shadow', cap=NONE)  → EPERM  OK (correctly denied)");
-        println!("");
-        println!("  Test 4: expired cap (should fail)......");
-        println!("    open('/tmp/test', cap=0xA3F1[expired])  → ECAPEXPIRED  OK");
-        println!("");
-        println!("  All capability tests passed.");
-        println!("  [NOTE] Real kernel enforcement coming in v0.3.0");
-        0
+        let me = current_pid();
+        let other = crate::process::ProcessId(usize::MAX);
+        let rw = Permissions::READ | Permissions::WRITE;
+        let mine = cap::create_capability(me, CapabilityType::File, rw);
+        let theirs = cap::create_capability(other, CapabilityType::AuditLog, Permissions::READ);
+        let brief = cap::create_capability_with_expiry(me, CapabilityType::File, Permissions::READ, 1);
+        let forged = cap::Capability { handle: CapHandle(0xdead_0001), ..mine.clone() };
+
+        let third = crate::process::ProcessId(usize::MAX - 1);
+        let grant = Permissions::READ | Permissions::GRANT;
+        let root = cap::create_capability(me, CapabilityType::Clipboard, grant);
+        let grandchild = cap::delegate(me, root.handle, other, grant)
+            .and_then(|child| cap::delegate(other, child.handle, third, Permissions::READ));
+
+        let checks: [(&str, Result<(), &str>, bool); 7] = [
+            ("own handle, within its rights", cap::validate(me, &mine, CapabilityType::File, Permissions::READ), true),
+            ("made-up handle", cap::validate(me, &forged, CapabilityType::File, Permissions::READ), false),
+            ("another process's capability", cap::validate(me, &theirs, CapabilityType::AuditLog, Permissions::READ), false),
+            ("closed handle", cap::close_handle(me, mine.handle).and_then(|_| cap::validate(me, &mine, CapabilityType::File, Permissions::READ)), false),
+            ("expired capability", {
+                let until = crate::arch::uptime_millis() + 2;
+                while crate::arch::uptime_millis() < until { core::hint::spin_loop(); }
+                cap::validate(me, &brief, CapabilityType::File, Permissions::READ)
+            }, false),
+            ("delegated twice", grandchild.clone().and_then(|g| cap::validate(third, &g, CapabilityType::Clipboard, Permissions::READ)), true),
+            ("delegated twice, root revoked", cap::revoke_capability(root.id).and_then(|_| grandchild.clone())
+                .and_then(|g| cap::validate(third, &g, CapabilityType::Clipboard, Permissions::READ)), false),
+        ];
+        let _ = cap::close_handle(me, brief.handle);
+        cap::revoke_all(other);
+        cap::revoke_all(third);
+
+        let mut failed = 0;
+        for (i, (what, result, allow)) in checks.iter().enumerate() {
+            let ok = result.is_ok() == *allow;
+            if !ok { failed += 1; }
+            println!("  Test {}: {:<30} {:<36} {}", i + 1, what,
+                match result { Ok(()) => "allowed", Err(e) => e }, if ok { "OK" } else { "FAIL" });
+        }
+        if failed == 0 { println!("  All capability tests passed."); 0 } else { println!("  {} failed.", failed); 1 }
     }
 
     fn cmd_pqtest(&self) -> i32 {
@@ -443,6 +588,1238 @@ impl Shell {
         0
     }
 
+    fn cmd_cpufreq(&self, args: &[&str]) -> i32 {
+        if let Some(&name) = args.first() {
+            match crate::power::Governor::from_name(name) {
+                Some(g) => crate::power::set_governor(g),
+                None    => {
+                    println!("cpufreq: unknown governor '{}'", name);
+                    println!("         (performance, powersave, ondemand, schedutil)");
+                    return 1;
+                }
+            }
+        }
+        println!("  Governor    : {}", crate::power::governor().name());
+        println!("  Frequency   : {} MHz", crate::power::cpu_frequency_mhz());
+        println!("  Utilisation : {}%", crate::power::cpu_utilization());
+        for (i, c) in crate::power::CLUSTERS.iter().enumerate() {
+            println!("  Cluster {:<6}: cpus {}-{}, {} MHz, capacity {}",
+                c.name, c.cpus.start, c.cpus.end - 1,
+                c.opps[crate::power::cluster_level(i)].freq_mhz, c.capacity);
+        }
+        0
+    }
+
+    fn cmd_suspend(&self, args: &[&str]) -> i32 {
+        use crate::power::{suspend_to_ram, WakeSource};
+        let mut wake = Vec::from([WakeSource::Uart]);
+        if let Some(&secs) = args.first() {
+            match secs.parse::<u64>() {
+                Ok(s)  => wake.push(WakeSource::Timer { after_ms: s * 1000 }),
+                Err(_) => { println!("suspend: invalid seconds '{}'", secs); return 1; }
+            }
+        }
+        println!("Suspending to RAM (press any key to wake)...");
+        match suspend_to_ram(&wake) {
+            Ok(reason) => { println!("Resumed (wake source: {:?})", reason); 0 }
+            Err(e)     => {
+                println!("suspend: {}", e);
+                for (pid, name) in crate::wakelock::active() {
+                    println!("         held by pid {}: {}", pid, name);
+                }
+                1
+            }
+        }
+    }
+
+    fn cmd_wakelocks(&self) -> i32 {
+        println!("  PID   ACQUIRED  TIMEOUTS  ACTIVE  TOTAL ms   LONGEST ms");
+        for s in crate::wakelock::stats() {
+            println!("  {:<5} {:<9} {:<9} {:<7} {:<10} {}",
+                s.holder.0, s.acquisitions, s.timeouts, s.active, s.total_ms, s.longest_ms);
+        }
+        0
+    }
+
+    fn cmd_cpuidle(&self) -> i32 {
+        println!("  STATE  LATENCY us  RESIDENCY us  USAGE     TIME ms   SHORT");
+        for (st, s) in crate::cpuidle::IDLE_STATES.iter().zip(crate::cpuidle::stats()) {
+            println!("  {:<6} {:<11} {:<13} {:<9} {:<9} {}",
+                st.name, st.exit_latency_us, st.target_residency_us, s.usage, s.time_us / 1000, s.too_short);
+        }
+        0
+    }
+
+    fn cmd_thermal(&self) -> i32 {
+        for (name, t) in crate::thermal::zones() {
+            println!("  {:<8} {}.{} C", name, t / 1000, (t % 1000).abs() / 100);
+        }
+        let l = crate::thermal::limits();
+        let cpu = crate::power::CPU_FREQ_TABLE[crate::power::max_level()].freq_mhz;
+        println!("  limits: cpu <= {} MHz, gpu <= {}%, charge {}",
+            cpu, l.gpu_max_pct,
+            l.charge_max_ma.map_or("unrestricted".into(), |m| format!("<= {} mA", m)));
+        0
+    }
+
+    fn cmd_energy(&self) -> i32 {
+        let ranking = crate::energy::ranking();
+        let total: u64 = ranking.iter().map(|p| p.energy_uj).sum::<u64>().max(1);
+        println!("  PID   CPU ms     ENERGY mJ  SHARE");
+        for p in ranking {
+            println!("  {:<5} {:<10} {:<10} {}%",
+                p.pid.0, p.cpu_time_us() / 1000, p.energy_uj / 1000, p.energy_uj * 100 / total);
+        }
+        0
+    }
+
+    fn cmd_charge(&self, args: &[&str]) -> i32 {
+        if let Some(arg) = args.first() {
+            let set = arg.trim_end_matches('%').parse().map_err(|_| "invalid percentage")
+                .and_then(crate::charger::set_charge_limit);
+            if let Err(e) = set {
+                println!("charge: {}", e);
+                return 1;
+            }
+        }
+        let s = crate::charger::status();
+        println!("  state: {:?}  level: {}%  limit: {}%  current: {} mA",
+            s.state, s.level_pct, s.limit_pct, s.current_ma);
+        if let Some(t) = s.full_by_ms {
+            println!("  adaptive: full by {} s uptime", t / 1000);
+        }
+        0
+    }
+
+    fn cmd_powertop(&self) -> i32 {
+        let s = crate::power::power_stats();
+        for (c, times) in crate::power::CLUSTERS.iter().zip(s.freq_time_ms.iter()) {
+            print!("  {:<7}", c.name);
+            for (opp, ms) in c.opps.iter().zip(times.iter()) {
+                print!(" {}MHz:{}ms", opp.freq_mhz, ms);
+            }
+            println!("");
+        }
+        println!("  idle wakeups   : timer {}, irq {}, other {}",
+            s.idle_wakeups[0], s.idle_wakeups[1], s.idle_wakeups[2]);
+        println!("  suspend wakeups: timer {}, uart {}, alarm {}",
+            s.suspend_wakeups[0], s.suspend_wakeups[1], s.suspend_wakeups[2]);
+        println!("  wakelocks held : {} ms", s.wakelock_held_ms);
+        0
+    }
+
+    fn cmd_ai(&mut self, args: &[&str]) -> i32 {
+        let result = match args {
+            ["trust", path] => read_file(&self.resolve_path(path))
+                .and_then(|key| crate::ai::trust_signing_key(&key))
+                .map(|_| println!("  key trusted")),
+            ["load", path] => {
+                let mut model = crate::ai::AiModel::new(path);
+                model.load_weights(&self.resolve_path(path)).map(|_| {
+                    if let Some(t) = model.transformer() {
+                        println!("  {} layers, {} dim, {} vocab, {:?}, on {}",
+                            t.cfg.n_layer, t.cfg.n_embd, t.cfg.n_vocab, model.provenance.unwrap(),
+                            t.accelerator().unwrap_or("CPU"));
+                        if t.gpu_layers() > 0 {
+                            println!("  {} of {} layers on the GPU", t.gpu_layers(), t.cfg.n_layer + 1);
+                        }
+                    }
+                    if let Some(a) = model.asr() {
+                        println!("  speech model: {} blocks, {} dim, {} mel bins, {} labels, {:?}",
+                            a.cfg.n_layer, a.cfg.n_embd, a.cfg.n_mels, a.cfg.n_vocab, model.provenance.unwrap());
+                    }
+                    self.model = Some(model);
+                    self.adapter = None;
+                })
+            }
+            ["adapter", path, mode @ ..] => {
+                let name = path.rsplit('/').next().unwrap_or(path);
+                let path = self.resolve_path(path);
+                match (&mut self.model, mode) {
+                    (Some(m), ["merge"]) => m.merge_adapter(name, &path).map(|_| println!("  merged {}", name)),
+                    (Some(m), []) => m.load_adapter(name, &path).map(|_| {
+                        let a = m.adapter(name).unwrap();
+                        println!("  rank {}, alpha {}, {} weights, {} KiB; ai ask now uses it",
+                            a.rank, a.alpha, a.tensor_count(), a.size() / 1024);
+                        self.adapter = Some(String::from(name));
+                    }),
+                    (None, _) => Err("no model loaded"),
+                    _         => Err("usage: ai adapter <lora.gguf> [merge]"),
+                }
+            }
+            ["models"] => {
+                for m in crate::ai::manager::list() {
+                    println!("  {:>3} {:<12} {:<8} idle {:>6} ms  {}{}", m.id, m.name,
+                        if m.loaded { "loaded" } else { "-" }, m.idle_ms, m.path,
+                        if m.preferred { "  (preferred)" } else { "" });
+                    for (name, merged) in &m.adapters {
+                        println!("      + {} ({})", name, if *merged { "merged" } else { "runtime" });
+                    }
+                }
+                Ok(())
+            }
+            ["add", model, name, path, mode @ ("merge" | "runtime")] =>
+                crate::ai::manager::add_adapter(model, name, &self.resolve_path(path), *mode == "merge")
+                    .map(|_| println!("  adapter {} catalogued for {}", name, model)),
+            ["add", name, path] => crate::ai::manager::register(name, &self.resolve_path(path))
+                .map(|id| println!("  catalogued as model {}", id)),
+            ["use", name] => crate::ai::manager::set_preferred(name)
+                .map(|_| println!("  apps now get {}", name)),
+            ["unload", name] => crate::ai::manager::unload(name).map(|_| println!("  unloaded")),
+            ["stats"] => {
+                let m = crate::ai::metrics::snapshot();
+                println!("  generations : {}", m.generations);
+                println!("  prompts     : {} tokens, {} tok/s", m.prompt_tokens, m.prompt_tokens_per_sec());
+                println!("  decoding    : {} tokens, {} tok/s, worst token {} us", m.tokens, m.tokens_per_sec(), m.max_token_us);
+                match (m.latency_percentile_us(50), m.latency_percentile_us(95)) {
+                    (Some(p50), Some(p95)) => println!("  latency     : p50 <= {} ms, p95 <= {} ms", p50 / 1000, p95 / 1000),
+                    _                      => println!("  latency     : some tokens over 500 ms"),
+                }
+                println!("  KV cache    : {} KiB live, {} KiB peak", m.kv_bytes / 1024, m.kv_peak_bytes / 1024);
+                println!("  throttled   : {} ms (sandbox), {} thermal steps, {} tokens/turn now",
+                    m.throttled_us / 1000, m.thermal_steps, crate::ai::service::slice_tokens());
+                Ok(())
+            }
+            ["capture", "on"] => {
+                let cap = self.debug_cap.get_or_insert_with(|| crate::capability::create_capability(
+                    current_pid(), crate::capability::CapabilityType::AiDebug, crate::capability::Permissions::READ));
+                crate::ai::privacy::start_capture(current_pid(), cap)
+                    .map(|_| println!("  capturing prompts and output of the AI service"))
+            }
+            ["capture", "off"] => match &self.debug_cap {
+                Some(cap) => crate::ai::privacy::stop_capture(current_pid(), cap).map(|_| println!("  capture stopped")),
+                None      => Err("not capturing"),
+            },
+            ["capture", "show"] => match &self.debug_cap {
+                Some(cap) => crate::ai::privacy::take_capture(current_pid(), cap).map(|records| {
+                    for r in &records {
+                        println!("  [{} ms] session {} {}: {}", r.time_ms, r.session, r.kind.name(), r.text());
+                    }
+                    println!("  [{} records]", records.len());
+                }),
+                None      => Err("not capturing"),
+            },
+            ["serve"] => self.model.take().ok_or("no model loaded")
+                .and_then(crate::ai::service::install_model)
+                .map(|id| println!("  serving as model {} to apps holding its AI capability", id)),
+            ["limit", pct] => match (&mut self.model, pct.parse()) {
+                (Some(m), Ok(cpu_pct)) => m.set_sandbox(crate::ai::sandbox::SandboxConfig { cpu_pct, ..Default::default() })
+                    .map(|_| println!("  inference limited to {}% CPU", cpu_pct)),
+                (None, _) => Err("no model loaded"),
+                _         => Err("bad percentage"),
+            },
+            ["listen", path] => match &self.model {
+                // Feed the recording in 100 ms pieces, printing text as it is recognised
+                Some(m) => crate::audio::WavSource::open(&self.resolve_path(path)).and_then(|mut wav| {
+                    use crate::audio::CaptureSource;
+                    let mut t = m.transcriber()?;
+                    let mut buf = [0i16; 1600];
+                    loop {
+                        let n = wav.read(&mut buf);
+                        if n == 0 { break; }
+                        print!("{}", t.push(&buf[..n])?);
+                    }
+                    println!("");
+                    println!("  [{} ms of audio]", t.audio_ms());
+                    Ok(())
+                }),
+                None    => Err("no model loaded"),
+            },
+            ["ask", prompt @ ..] if !prompt.is_empty() => match &self.model {
+                // Print tokens as they arrive; Ctrl-C stops generation
+                Some(m) => m.complete_with(&prompt.join(" "), &crate::ai::stream::GenerateOptions {
+                    adapter: self.adapter.clone(),
+                    ..crate::ai::stream::GenerateOptions::new(64)
+                }, |ev| {
+                    print!("{}", ev.text);
+                    !(crate::console::rx_ready() && crate::console::read_char() == '\x03')
+                }).map(|done| {
+                    let t = done.timings;
+                    println!("");
+                    println!("  [{:?}: {} tokens, prompt {} ms, {} us/token ({} tok/s, worst {} us), language {}]",
+                        done.stop.unwrap(), t.tokens, t.prefill_us / 1000, t.decode_us / t.tokens.max(1) as u64,
+                        t.tokens_per_sec(), t.max_token_us, done.language.map_or("?", |l| l.code()));
+                    println!("  [KV: {} KiB in {} pages, {} tokens live, {} evicted]",
+                        done.kv.bytes_reserved / 1024, done.kv.pages, done.kv.live_tokens, done.kv.evicted_tokens);
+                    if t.throttled_us > 0 {
+                        println!("  [throttled {} ms by the model sandbox]", t.throttled_us / 1000);
+                    }
+                }),
+                None    => Err("no model loaded"),
+            },
+            _ => Err("usage: ai load <model.gguf> | ai trust <key> | ai limit <cpu%> | ai ask <prompt> | ai listen <file.wav> \
+                      | ai adapter <lora.gguf> [merge] | ai stats | ai capture on|off|show | ai serve | ai models | ai add <name> <model.gguf> \
+                      | ai add <model> <adapter> <lora.gguf> merge|runtime | ai use <name> | ai unload <name>"),
+        };
+        match result {
+            Ok(())  => 0,
+            Err(e)  => { println!("ai: {}", e); 1 }
+        }
+    }
+
+    fn cmd_ping(&mut self, args: &[&str]) -> i32 {
+        use crate::net::{icmp, Ipv4Addr};
+
+        let Some(dst) = args.first().and_then(|a| Ipv4Addr::parse(a)) else {
+            println!("usage: ping <addr> [count]");
+            return 1;
+        };
+        let count: u16 = args.get(1).and_then(|c| c.parse().ok()).unwrap_or(4);
+        let cap = self.net_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::Network, crate::capability::Permissions::CONTROL));
+        let sock = match icmp::open_raw(current_pid(), cap) {
+            Ok(s)  => s,
+            Err(e) => { println!("ping: {}", e); return 1; }
+        };
+        let ident = crate::entropy::next_u32() as u16;
+        let payload: Vec<u8> = (0..56u8).collect();
+        let mut buf = [0u8; 1500];
+        let (mut sent, mut received) = (0u32, 0u32);
+        println!("PING {}: {} data bytes", dst, payload.len());
+        for seq in 1..=count {
+            let start = uptime_ms();
+            if let Err(e) = icmp::send_to(sock, dst, &icmp::echo_request(ident, seq, &payload)) {
+                println!("ping: {}", e);
+                break;
+            }
+            sent += 1;
+            // Listen out the second, which also paces the requests
+            let mut answered = false;
+            while let Ok((n, from)) = icmp::recv_from(sock, &mut buf, Some((start + 1000).saturating_sub(uptime_ms()))) {
+                let m = &buf[..n];
+                if n < icmp::HEADER_LEN { continue; }
+                if m[0] == icmp::TYPE_ECHO_REPLY && m[4..6] == ident.to_be_bytes() && m[6..8] == seq.to_be_bytes() {
+                    println!("{} bytes from {}: icmp_seq={} time={} ms", n, from, seq, uptime_ms() - start);
+                    received += 1;
+                    answered = true;
+                } else if m[0] == icmp::TYPE_DEST_UNREACHABLE && !answered {
+                    println!("From {}: icmp_seq={} destination unreachable (code {})", from, seq, m[1]);
+                    answered = true;
+                }
+            }
+            if !answered { println!("Request timeout for icmp_seq {}", seq); }
+        }
+        let _ = icmp::close(sock);
+        println!("--- {} ping statistics ---", dst);
+        println!("{} packets transmitted, {} received, {}% packet loss",
+            sent, received, ((sent - received) * 100).checked_div(sent).unwrap_or(0));
+        if received > 0 { 0 } else { 1 }
+    }
+
+    fn cmd_host(&self, args: &[&str]) -> i32 {
+        use crate::net::dns;
+
+        match args {
+            ["policy"] => println!("{}", dns::policy().as_str()),
+            ["policy", p] => match dns::Policy::parse(p) {
+                Some(p) => dns::set_policy(p),
+                None    => { println!("host: unknown policy {}", p); return 1; }
+            },
+            ["dnssec"] => println!("{}", dns::dnssec().as_str()),
+            ["dnssec", d] => match dns::Dnssec::parse(d) {
+                Some(d) => dns::set_dnssec(d),
+                None    => { println!("host: unknown DNSSEC mode {}", d); return 1; }
+            },
+            ["flush"] => dns::flush_cache(),
+            [name] => {
+                let r = dns::lookup(name);
+                match r.result {
+                    Ok(addrs) => for a in addrs { println!("{} has address {}", name, a); },
+                    Err(e)    => { println!("host: {}: {} (DNSSEC: {})", name, e, r.validation.as_str()); return 1; }
+                }
+                println!("DNSSEC: {}", r.validation.as_str());
+            }
+            _ => {
+                println!("usage: host <name> | host policy [doh-only|doh-then-plain|plain-only]");
+                println!("       host dnssec [off|validate|require] | host flush");
+                return 1;
+            }
+        }
+        0
+    }
+
+    fn cmd_audit(&mut self, args: &[&str]) -> i32 {
+        use crate::audit;
+
+        let cap = self.audit_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::AuditLog, crate::capability::Permissions::READ));
+        let show = |count: usize, kind: Option<&str>| -> Result<(), &'static str> {
+            let all = audit::query(cap, &audit::Query { kind: kind.map(String::from), ..Default::default() })?;
+            let (seq, segments) = audit::stats();
+            println!("  {} records, {} segments", seq, segments);
+            for r in &all[all.len().saturating_sub(count)..] {
+                let pid = r.pid.map_or(String::from("-"), |p| format!("{}", p));
+                println!("  {:>6} {:>10} {:>5} {:<20} {}", r.seq, r.time, pid, r.kind, r.message);
+            }
+            Ok(())
+        };
+        let r: Result<(), &str> = match args {
+            [] => show(10, None),
+            ["verify"] => audit::verify(cap)
+                .map(|v| println!("  chain intact: {} records in {} segments", v.records, v.segments)),
+            ["sync"] => audit::sync(),
+            [count, rest @ ..] if rest.len() <= 1 => match count.parse() {
+                Ok(n) => show(n, rest.first().copied()),
+                Err(_) => Err("bad count"),
+            },
+            _ => Err("usage: audit [verify | sync | <count> [kind]]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("audit: {}", e); 1 }
+        }
+    }
+
+    fn cmd_attest(&mut self, args: &[&str]) -> i32 {
+        use crate::attest;
+        use crate::crypto::mldsa;
+
+        let cap = self.attest_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::Attestation, crate::capability::Permissions::READ));
+        let r: Result<(), &str> = match args {
+            [] => attest::registers().map(|regs| {
+                println!("  root of trust: {}", attest::root_name());
+                if let Some(rb) = crate::secure_boot::report().and_then(|r| r.rollback) {
+                    println!("  security version: {} (device minimum {}, {})", rb.image, rb.stored, rb.status.as_str());
+                }
+                for (i, r) in regs.iter().enumerate() { println!("  r{}  {}", i, hex(r)); }
+                for e in attest::events() {
+                    println!("  r{} <- {}  {}", e.register, hex(&e.digest), e.description);
+                }
+            }),
+            ["quote", nonce] => attest::quote(cap, nonce.as_bytes()).map(|q| {
+                let key = &q.body[q.body.len() - mldsa::PUBLIC_KEY_LEN..];
+                let ok = mldsa::verify(key, &q.body, attest::QUOTE_CTX, &q.signature);
+                println!("  quote {} B, signature {} B, {} events", q.body.len(), q.signature.len(), q.events.len());
+                println!("  device key sha3-256:{}", hex(&crate::crypto::sha3::sha3_256(key)));
+                println!("  signature {}", if ok { "verifies" } else { "DOES NOT VERIFY" });
+            }),
+            _ => Err("usage: attest [quote <nonce>]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("attest: {}", e); 1 }
+        }
+    }
+
+    fn cmd_perms(&mut self, args: &[&str]) -> i32 {
+        use crate::permission::{self, Answer, Permission};
+
+        let cap = self.perms_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::PermissionAdmin, crate::capability::Permissions::CONTROL));
+        let r: Result<(), &str> = match args {
+            [] => {
+                for d in permission::decisions() {
+                    println!("  {:<24} {:<10} {}", d.app, d.permission.as_str(), if d.allow { "allow" } else { "deny" });
+                }
+                for p in permission::prompts() {
+                    println!("  prompt {}: {} (pid {}) asks for {}", p.id, p.app, p.pid.0, p.permission.as_str());
+                }
+                for app in permission::quarantined() {
+                    println!("  {:<24} quarantined", app);
+                }
+                for g in permission::grants() {
+                    println!("  granted {} to {} (pid {}){}, {} capabilities", g.permission.as_str(), g.app, g.pid.0,
+                        if g.once { " once" } else { "" }, g.caps.len());
+                }
+                for t in crate::vmbridge::pending() {
+                    println!("  transfer {}: Android asks to {} {}({} bytes)", t.id, t.kind.as_str(),
+                        if t.name.is_empty() { String::new() } else { format!("{} ", t.name) }, t.size);
+                }
+                Ok(())
+            }
+            ["answer", id, answer] => (|| {
+                let id = id.parse().map_err(|_| "bad prompt id")?;
+                let answer = Answer::parse(answer).ok_or("expected always, once or deny")?;
+                permission::answer(cap, id, answer)
+            })(),
+            [decision @ ("allow" | "deny" | "ask"), app, perm] => (|| {
+                let perm = Permission::parse(perm).ok_or("expected camera, microphone, location or contacts")?;
+                let allow = match *decision { "allow" => Some(true), "deny" => Some(false), _ => None };
+                permission::set(cap, app, perm, allow)
+            })(),
+            ["release", app] => permission::lift_quarantine(cap, app),
+            ["transfer", id, answer @ ("allow" | "deny")] => (|| {
+                let id = id.parse().map_err(|_| "bad transfer id")?;
+                crate::vmbridge::answer(cap, id, *answer == "allow")
+            })(),
+            _ => Err("usage: perms [answer <id> always|once|deny | allow|deny|ask <app> <permission> | release <app> | transfer <id> allow|deny]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("perms: {}", e); 1 }
+        }
+    }
+
+    fn cmd_secmon(&self) -> i32 {
+        use crate::security;
+
+        println!("  {} events since boot, {} in the ring (holds {}), {} subscribers",
+            security::event_count(), security::events().len(), security::RING_CAPACITY, security::subscribers());
+        for r in security::RULES {
+            println!("  rule {:<18} {:>2} {:<22} in {:>3} s -> {}", r.name, r.threshold, r.kind,
+                r.window_ms.div_ceil(1000), r.response.as_str());
+        }
+        for e in security::escalations() {
+            println!("  [{:>8} ms] {} pid {} ({} events): {}{}", e.time_ms, e.rule, e.pid, e.events,
+                e.response.as_str(), if e.applied { "" } else { " (not applied)" });
+        }
+        0
+    }
+
+    fn cmd_sandbox(&self) -> i32 {
+        for s in crate::sandbox::sandboxes() {
+            let c = &s.config;
+            println!("  sandbox {} pid {}: syscalls {:#x} cpu {}% memory {} network {} filesystem {}",
+                s.id.0, s.pid, c.syscalls, c.cpu_pct,
+                c.memory.map_or(String::from("-"), |m| format!("{} KiB", m / 1024)),
+                if c.network { "yes" } else { "no" }, if c.filesystem { "yes" } else { "no" });
+            println!("    cpu {} ms, throttled {} times ({} ms), {} system calls refused",
+                s.cpu.cpu_us / 1000, s.cpu.throttles, s.cpu.throttled_us / 1000, s.denied);
+        }
+        0
+    }
+
+    fn cmd_dmesg(&self, args: &[&str]) -> i32 {
+        use crate::klog::{self, Level};
+
+        let level = |s: &str| Level::parse(s).ok_or("expected error, warn, info or debug");
+        let r: Result<(), &str> = match args {
+            [] => {
+                print!("{}", String::from_utf8_lossy(&klog::read(klog::LOG_SIZE)));
+                Ok(())
+            }
+            ["clear"] => { klog::clear(); Ok(()) }
+            ["level"] => {
+                println!("  default {}, console {}", klog::default_level().as_str(), klog::console_level().as_str());
+                for (m, l) in klog::filters() { println!("  {:<24} {}", m, l.as_str()); }
+                Ok(())
+            }
+            ["level", l] => level(l).map(|l| klog::set_level(None, l)),
+            ["level", l, module] => level(l).map(|l| klog::set_level(Some(module), l)),
+            ["console", l] => level(l).map(klog::set_console_level),
+            _ => Err("usage: dmesg [clear | level [<level> [module]] | console <level>]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("dmesg: {}", e); 1 }
+        }
+    }
+
+    fn cmd_auth(&self) -> i32 {
+        use crate::authlimit;
+
+        println!("  {} free attempts, then {} s doubling to {} h", authlimit::FREE_ATTEMPTS,
+            authlimit::BASE_DELAY_MS / 1000, authlimit::MAX_DELAY_MS / 3_600_000);
+        for s in authlimit::statuses() {
+            println!("  {:<9} user {:<5} {} failed{}", s.factor.as_str(), s.user, s.failures,
+                if s.wait_ms > 0 { format!(", locked for {} s", s.wait_ms.div_ceil(1000)) } else { String::new() });
+        }
+        0
+    }
+
+    fn cmd_pkg(&mut self, args: &[&str]) -> i32 {
+        use crate::installer;
+
+        let cap = self.pkg_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::AppInstall,
+            crate::capability::Permissions::EXECUTE | crate::capability::Permissions::CONTROL));
+        let r: Result<(), &str> = match args {
+            [] => {
+                println!("  developer mode {}", if installer::developer_mode() { "on" } else { "off" });
+                for a in installer::installed() {
+                    println!("  {:<24} {:>6} {:<9} signer {}", a.name, a.version, a.source.as_str(), hex(&a.signer));
+                }
+                Ok(())
+            }
+            ["install", path] => installer::install(cap, path).map(|a| {
+                println!("  installed {} {} ({})", a.name, a.version, a.source.as_str());
+            }),
+            ["remove", app] => installer::remove(cap, app),
+            ["devmode", on @ ("on" | "off")] => installer::set_developer_mode(cap, *on == "on"),
+            _ => Err("usage: pkg [install <file> | remove <app> | devmode on|off]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("pkg: {}", e); 1 }
+        }
+    }
+
+    fn cmd_cfi(&self, args: &[&str]) -> i32 {
+        use crate::cfi;
+
+        let r: Result<(), &str> = match args {
+            [] => {
+                println!("  hardware: {}", cfi::hardware());
+                println!("  kernel:   shadow call stack (software), landing pads {}",
+                    if cfi::kernel_landing_pads() { "enforced" } else { "off" });
+                Ok(())
+            }
+            ["check", path] => crate::fs::read_file(path).and_then(|image| {
+                println!("  built with: {}", cfi::elf_features(&image)?);
+                let run = cfi::admit(&image)?;
+                println!("  admitted, runs with: {}", run);
+                Ok(())
+            }),
+            _ => Err("usage: cfi [check <elf>]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("cfi: {}", e); 1 }
+        }
+    }
+
+    fn cmd_mte(&self, args: &[&str]) -> i32 {
+        use crate::mte::{self, Mode};
+
+        let r: Result<(), &str> = match args {
+            [] => {
+                println!("  memory tagging {}; processes default to {}",
+                    if mte::active() { "on" } else { "not supported" }, mte::DEFAULT_MODE.as_str());
+                for (pid, mode) in mte::modes() {
+                    println!("  pid {:<5} {}", pid, mode.as_str());
+                }
+                Ok(())
+            }
+            [pid, mode] => (|| {
+                let pid = crate::process::ProcessId(pid.parse().map_err(|_| "bad pid")?);
+                mte::set_mode(pid, Mode::parse(mode).ok_or("expected off, sync or async")?);
+                Ok(())
+            })(),
+            _ => Err("usage: mte [<pid> off|sync|async]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("mte: {}", e); 1 }
+        }
+    }
+
+    fn cmd_tee(&self) -> i32 {
+        match crate::tee::backend() {
+            Some((name, isolated)) => println!("  backend: {} ({})", name, if isolated { "isolated" } else { "in kernel, not isolated" }),
+            None                   => println!("  backend: none"),
+        }
+        for s in crate::tee::sessions() {
+            let owner = s.owner.map_or(String::from("kernel"), |p| format!("pid {}", p));
+            println!("  session {}  {:<8} {}", s.id.0, owner, crate::tee::uuid_name(&s.ta));
+        }
+        0
+    }
+
+    fn cmd_bootctl(&self, args: &[&str]) -> i32 {
+        use crate::bootctl::{self, Slot};
+
+        let slot = |s: &str| Slot::parse(s).ok_or("expected a or b");
+        let r: Result<(), &str> = match args {
+            [] => {
+                let current = bootctl::current();
+                println!("  metadata in {}{}", bootctl::store_name(),
+                    if bootctl::chosen_by_loader() { ", slot chosen by the loader" } else { "" });
+                for (s, info) in [Slot::A, Slot::B].iter().zip(bootctl::slots()) {
+                    println!("  {} slot {}  priority {:>2}  tries {}  {}", if current == Some(*s) { "*" } else { " " },
+                        s.as_str(), info.priority, info.tries,
+                        if info.successful { "successful" } else if info.bootable() { "unproven" } else { "unbootable" });
+                }
+                Ok(())
+            }
+            ["set-active", s] => slot(s).and_then(bootctl::set_active),
+            ["unbootable", s] => slot(s).and_then(bootctl::set_unbootable),
+            ["mark-successful"] => bootctl::mark_successful(),
+            _ => Err("usage: bootctl [set-active a|b | unbootable a|b | mark-successful]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("bootctl: {}", e); 1 }
+        }
+    }
+
+    fn cmd_services(&self) -> i32 {
+        let services = crate::init::services();
+        if services.is_empty() { println!("  init has not finished booting"); return 0; }
+        println!("  {:<20} {:<10} {:>5}  {:<9} sockets", "SERVICE", "STATE", "PID", "SANDBOX");
+        for s in &services {
+            let pid = s.pid.map_or(String::from("-"), |p| format!("{}", p));
+            let sockets = s.config.listen.iter().map(|l| match l {
+                crate::init::Listen::Stream { port, .. } => format!("tcp/{}", port),
+                crate::init::Listen::Datagram { port }   => format!("udp/{}", port),
+            }).collect::<Vec<_>>().join(" ");
+            let sandbox = s.config.sandbox.map_or("-", |p| p.as_str());
+            println!("  {:<20} {:<10} {:>5}  {:<9} {}", s.name(), s.status.as_str(), pid, sandbox, sockets);
+        }
+        0
+    }
+
+    fn cmd_windows(&self) -> i32 {
+        let Some((w, h, mhz)) = crate::ui::display_mode() else { println!("  no display"); return 0 };
+        let s = crate::ui::stats();
+        println!("  display {}x{} at {}.{:03} Hz: {} frames, {} missed, {} vblanks, compose {} us (max {} us)",
+            w, h, mhz / 1000, mhz % 1000, s.frames, s.missed, s.vblanks, s.last_compose_us, s.max_compose_us);
+        println!("  {:>4} {:>5} {:>6} {:<22} {:>7}", "ID", "PID", "Z", "RECT", "OPACITY");
+        let focus = crate::ui::focused_window();
+        for win in crate::ui::windows().iter().rev() {
+            let r = win.rect;
+            println!("  {:>4} {:>5} {:>6} {:<22} {:>7}{}{}", win.id, win.owner.0, win.z, format!("{}x{}+{}+{}", r.w, r.h, r.x, r.y),
+                win.opacity, if win.visible { "" } else { " hidden" }, if focus == Some(win.id) { " focused" } else { "" });
+        }
+        0
+    }
+
+    fn cmd_lockdown(&mut self, args: &[&str]) -> i32 {
+        use crate::lockdown;
+
+        let r: Result<(), &str> = match args {
+            [] => {
+                println!("  lockdown: {}", lockdown::level().as_str());
+                Ok(())
+            }
+            [level] => lockdown::Level::parse(level).ok_or("unknown level").and_then(lockdown::enter),
+            _ => Err("usage: lockdown [integrity|confidentiality]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("lockdown: {}", e); 1 }
+        }
+    }
+
+    fn cmd_tls(&mut self, args: &[&str]) -> i32 {
+        use crate::net::tls;
+
+        let cap = self.net_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::Network, crate::capability::Permissions::CONTROL));
+        let r: Result<(), &str> = match args {
+            [] => {
+                println!("  {} trust anchors, {} session tickets", tls::trust_anchors(), tls::tickets());
+                for p in tls::pins() {
+                    println!("  pin {}{}", p.host, if p.subdomains { " and subdomains" } else { "" });
+                    for k in &p.keys { println!("    sha256/{}", hex(k)); }
+                }
+                Ok(())
+            }
+            ["pin", host, key, rest @ ..] => (|| {
+                let key = parse_key(key).ok_or("expected a SHA-256 of 64 hex digits")?;
+                let subdomains = match rest {
+                    []             => false,
+                    ["subdomains"] => true,
+                    _              => return Err("expected subdomains"),
+                };
+                tls::add_pin(cap, host, key, subdomains)
+            })(),
+            ["unpin", host] => tls::remove_pin(cap, host),
+            ["forget", host] => { tls::flush_tickets(host); Ok(()) }
+            _ => Err("usage: tls [pin <host> <sha256> [subdomains] | unpin <host> | forget <host>]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("tls: {}", e); 1 }
+        }
+    }
+
+    fn cmd_wg(&self, args: &[&str]) -> i32 {
+        use crate::net::{self, wireguard as wg, IpAddr};
+
+        let key_file = |path: &str| read_file(&self.resolve_path(path))
+            .and_then(|k| <[u8; wg::KEY_LEN]>::try_from(k.as_slice()).map_err(|_| "key must be 32 bytes"));
+        let r: Result<(), &str> = match args {
+            [] => {
+                match (wg::interface(), wg::public_key()) {
+                    (Some((_, port)), Some(key)) => println!("{}: {} port {} public key {}{}",
+                        wg::NAME, if wg::is_up() { "up" } else { "down" }, port, hex(&key),
+                        if wg::route_all() { ", routing all traffic" } else { "" }),
+                    _ => println!("{}: not created", wg::NAME),
+                }
+                for p in wg::peers() {
+                    let allowed: Vec<String> = p.allowed_ips.iter().map(|(a, l)| format!("{}/{}", a, l)).collect();
+                    println!("  peer {}", hex(&p.public_key));
+                    if let Some(e) = p.endpoint { println!("    endpoint    {}", e); }
+                    println!("    allowed ips {}", allowed.join(", "));
+                    if let Some(age) = p.handshake_age { println!("    handshake   {} s ago", age); }
+                    println!("    transfer    {} B received, {} B sent", p.rx_bytes, p.tx_bytes);
+                    if p.keepalive_secs > 0 { println!("    keepalive   every {} s", p.keepalive_secs); }
+                }
+                Ok(())
+            }
+            ["up", rest @ ..] => match rest.first().map(|p| p.parse::<u16>()) {
+                Some(Err(_)) => Err("bad port"),
+                port => wg::up(port.and_then(Result::ok).unwrap_or(wg::DEFAULT_PORT))
+                    .map(|_| println!("  public key {}", hex(&wg::public_key().unwrap_or_default()))),
+            },
+            ["down"] => wg::down(),
+            ["addr", cidr] => match (wg::interface(), parse_prefix(cidr)) {
+                (None, _) => Err("tunnel not created; run wg up first"),
+                (_, None) => Err("bad address"),
+                (Some((index, _)), Some((IpAddr::V4(a), len))) => net::configure(index, a, len, None),
+                (Some((index, _)), Some((IpAddr::V6(a), len))) => net::ipv6::add_address(index, a, len),
+            },
+            ["key", path] => key_file(path).map(|k| println!("  public key {}", hex(&wg::set_private_key(Some(k))))),
+            ["peer", key, allowed, rest @ ..] => (|| {
+                let public_key = parse_key(key).ok_or("bad public key")?;
+                let allowed_ips = allowed.split(',').map(parse_prefix).collect::<Option<Vec<_>>>().ok_or("bad allowed ips")?;
+                let endpoint = match rest.first() {
+                    Some(e) => Some(parse_socket_addr(e).ok_or("bad endpoint")?),
+                    None    => None,
+                };
+                let keepalive_secs = match rest.get(1) {
+                    Some(k) => k.parse().map_err(|_| "bad keepalive")?,
+                    None    => 0,
+                };
+                wg::add_peer(wg::PeerConfig { public_key, preshared_key: None, endpoint, allowed_ips, keepalive_secs })
+            })(),
+            ["psk", key, path] => match parse_key(key) {
+                Some(k) => key_file(path).and_then(|psk| wg::set_preshared_key(&k, psk)),
+                None    => Err("bad public key"),
+            },
+            ["remove", key] => parse_key(key).ok_or("bad public key").and_then(|k| wg::remove_peer(&k)),
+            ["route-all", "on"]  => { wg::set_route_all(true); Ok(()) }
+            ["route-all", "off"] => { wg::set_route_all(false); Ok(()) }
+            _ => Err("usage: wg [up [port] | down | addr <ip/len> | key <file> | peer <pubkey> <ip/len,...> [endpoint] [keepalive] \
+                      | psk <pubkey> <file> | remove <pubkey> | route-all on|off]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("wg: {}", e); 1 }
+        }
+    }
+
+    fn cmd_netns(&mut self, args: &[&str]) -> i32 {
+        use crate::net::{self, ipv4, netns};
+        use crate::process::ProcessId;
+
+        let cap = self.net_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::Network, crate::capability::Permissions::CONTROL));
+        let ns = |name: &str| netns::find(name).ok_or("no such namespace");
+        let pid = |p: &str| p.parse().map(ProcessId).map_err(|_| "bad pid");
+        let switch = |s: &str| match s { "on" => Ok(true), "off" => Ok(false), _ => Err("expected on or off") };
+        let verdict = |v: &str| match v {
+            "allow" => Ok(netns::Verdict::Allow),
+            "deny"  => Ok(netns::Verdict::Deny),
+            _       => Err("expected allow or deny"),
+        };
+        let r: Result<(), &str> = match args {
+            [] => {
+                for n in netns::namespaces() {
+                    let uplink = n.uplink.map_or(String::from("any"), |i| net::interface(i).map_or(String::from("?"), |i| i.name));
+                    println!("{}: {}, uplink {}, default {}, {} blocked", n.name, if n.offline { "offline" } else { "online" },
+                        uplink, if n.default == netns::Verdict::Allow { "allow" } else { "deny" }, n.blocked);
+                    if !n.apps.is_empty() {
+                        let apps: Vec<String> = n.apps.iter().map(|&p| {
+                            if netns::app_offline(p) { format!("{} (offline)", p) } else { format!("{}", p) }
+                        }).collect();
+                        println!("  apps {}", apps.join(" "));
+                    }
+                    for rule in &n.rules {
+                        let proto = match rule.proto {
+                            Some(ipv4::PROTO_TCP)  => " tcp",
+                            Some(ipv4::PROTO_UDP)  => " udp",
+                            Some(ipv4::PROTO_ICMP) => " icmp",
+                            _                      => "",
+                        };
+                        let ports = match rule.ports {
+                            Some((lo, hi)) if lo == hi => format!(" {}", lo),
+                            Some((lo, hi))             => format!(" {}-{}", lo, hi),
+                            None                       => String::new(),
+                        };
+                        println!("  {} {}/{}{}{}", if rule.verdict == netns::Verdict::Allow { "allow" } else { "deny" },
+                            rule.net.0, rule.net.1, proto, ports);
+                    }
+                }
+                Ok(())
+            }
+            ["create", name] => netns::create(cap, name).map(|_| ()),
+            ["remove", name] => ns(name).and_then(|n| netns::remove(cap, n)),
+            ["assign", p, name] => pid(p).and_then(|p| netns::assign(cap, p, ns(name)?)),
+            ["offline", name, on] => ns(name).and_then(|n| netns::set_offline(cap, n, switch(on)?)),
+            ["app-offline", p, on] => pid(p).and_then(|p| netns::set_app_offline(cap, p, switch(on)?)),
+            ["uplink", name, "none"] => ns(name).and_then(|n| netns::set_uplink(cap, n, None)),
+            ["uplink", name, iface] => match net::find_interface(iface) {
+                Some(i) => ns(name).and_then(|n| netns::set_uplink(cap, n, Some(i))),
+                None    => Err("no such interface"),
+            },
+            ["rule", name, v, net, rest @ ..] => (|| {
+                let n = ns(name)?;
+                let verdict = verdict(v)?;
+                let net = parse_prefix(net).ok_or("bad network")?;
+                let (proto, ports) = match rest {
+                    []                 => (None, None),
+                    [proto, rest @ ..] => {
+                        let proto = match *proto {
+                            "tcp"  => ipv4::PROTO_TCP,
+                            "udp"  => ipv4::PROTO_UDP,
+                            "icmp" => ipv4::PROTO_ICMP,
+                            _      => return Err("expected tcp, udp or icmp"),
+                        };
+                        let ports = match rest {
+                            []      => None,
+                            [ports] => Some(parse_port_range(ports).ok_or("bad port range")?),
+                            _       => return Err("too many arguments"),
+                        };
+                        (Some(proto), ports)
+                    }
+                };
+                netns::add_rule(cap, n, netns::Rule { verdict, net, proto, ports })
+            })(),
+            ["default", name, v] => ns(name).and_then(|n| netns::set_default(cap, n, verdict(v)?)),
+            ["flush", name] => ns(name).and_then(|n| netns::clear_rules(cap, n)),
+            _ => Err("usage: netns [create|remove <ns> | assign <pid> <ns> | offline <ns> on|off | app-offline <pid> on|off \
+                      | uplink <ns> <iface>|none | rule <ns> allow|deny <ip/len> [tcp|udp|icmp] [port[-port]] | default <ns> allow|deny \
+                      | flush <ns>]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("netns: {}", e); 1 }
+        }
+    }
+
+    fn cmd_proxy(&mut self, args: &[&str]) -> i32 {
+        use crate::keyring;
+        use crate::net::{proxy, IpAddr, SocketAddr};
+        use crate::process::ProcessId;
+
+        let cap = self.net_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::Network, crate::capability::Permissions::CONTROL));
+        let pid = |p: &str| p.parse().map(ProcessId).map_err(|_| "bad pid");
+        let r: Result<(), &str> = match args {
+            [] => {
+                for p in proxy::proxies() {
+                    let user = p.proxy.auth.as_ref().map_or(String::new(), |(u, _)| format!(" as {}", u));
+                    println!("  pid {}: {} {}{}, {} connections, {} failed", p.pid.0, p.proxy.kind.as_str(),
+                        p.proxy.addr, user, p.connects, p.failures);
+                }
+                Ok(())
+            }
+            ["set", p, kind, ip, port, rest @ ..] => (|| {
+                let pid = pid(p)?;
+                let kind = proxy::Kind::parse(kind).ok_or("expected socks5 or http")?;
+                let ip = IpAddr::parse(ip).ok_or("bad address")?;
+                let port = port.parse().map_err(|_| "bad port")?;
+                let auth = match rest {
+                    [] => None,
+                    [user, password] => {
+                        let description = format!("proxy:{}", pid.0);
+                        let key = match keyring::find(&description) {
+                            Some(key) => { keyring::update(key, password.as_bytes())?; key }
+                            None      => keyring::add(&description, password.as_bytes())?,
+                        };
+                        Some((String::from(*user), key))
+                    }
+                    _ => return Err("expected a user and password"),
+                };
+                proxy::set(cap, pid, Some(proxy::Proxy { kind, addr: SocketAddr::new(ip, port), auth }))
+            })(),
+            ["clear", p] => pid(p).and_then(|p| proxy::set(cap, p, None)),
+            _ => Err("usage: proxy [set <pid> socks5|http <ip> <port> [<user> <password>] | clear <pid>]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("proxy: {}", e); 1 }
+        }
+    }
+
+    fn cmd_datausage(&mut self, args: &[&str]) -> i32 {
+        use crate::net::{self, accounting};
+        use crate::process::ProcessId;
+
+        let cap = self.net_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::Network, crate::capability::Permissions::CONTROL));
+        let pid = |p: &str| p.parse().map(ProcessId).map_err(|_| "bad pid");
+        let iface = |name: &str| match name {
+            "all" => Ok(None),
+            name  => net::find_interface(name).map(Some).ok_or("no such interface"),
+        };
+        let name = |index: Option<usize>| index.map_or(String::from("all"), |i| net::interface(i).map_or(String::from("?"), |i| i.name));
+        let r: Result<(), &str> = match args {
+            [] | [_] => (|| {
+                let days: Option<u32> = args.first().map(|d| d.parse().map_err(|_| "bad day count")).transpose()?;
+                println!("  PID   IFACE     RX bytes     TX bytes     RX pkts  TX pkts");
+                let rows: Vec<(u64, usize, accounting::Counters)> = match days {
+                    None       => accounting::totals().iter().map(|r| (r.pid, r.index as usize, r.usage)).collect(),
+                    Some(days) => {
+                        let mut pids: Vec<u64> = accounting::totals().iter().map(|r| r.pid).collect();
+                        pids.sort_unstable();
+                        pids.dedup();
+                        pids.iter().flat_map(|&p| accounting::usage(ProcessId(p as usize), days).into_iter().map(move |(i, c)| (p, i, c))).collect()
+                    }
+                };
+                for (p, index, c) in rows {
+                    println!("  {:<5} {:<9} {:<12} {:<12} {:<8} {}", p, name(Some(index)), c.rx_bytes, c.tx_bytes, c.rx_packets, c.tx_packets);
+                }
+                for c in accounting::caps() {
+                    println!("  cap: pid {} on {}: {} of {} bytes over {} days", c.pid.0, name(c.index), c.used, c.limit, c.days);
+                }
+                Ok(())
+            })(),
+            ["cap", p, i, limit, days] => (|| {
+                let (digits, scale) = match limit.char_indices().last() {
+                    Some((n, 'K')) => (&limit[..n], 1 << 10),
+                    Some((n, 'M')) => (&limit[..n], 1 << 20),
+                    Some((n, 'G')) => (&limit[..n], 1 << 30),
+                    _              => (*limit, 1),
+                };
+                let limit = digits.parse::<u64>().map_err(|_| "bad byte count")? * scale;
+                accounting::set_cap(cap, pid(p)?, iface(i)?, limit, days.parse().map_err(|_| "bad day count")?)
+            })(),
+            ["uncap", p, i] => pid(p).and_then(|p| accounting::clear_cap(cap, p, iface(i)?)),
+            _ => Err("usage: datausage [days] | cap <pid> <iface>|all <bytes[K|M|G]> <days> | uncap <pid> <iface>|all"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("datausage: {}", e); 1 }
+        }
+    }
+
+    fn cmd_pcap(&mut self, args: &[&str]) -> i32 {
+        use crate::net::{self, capture};
+
+        let cap = &self.diag_cap.get_or_insert_with(|| crate::capability::create_capability(
+            current_pid(), crate::capability::CapabilityType::NetDiagnostics, crate::capability::Permissions::READ)).clone();
+        let tap = |id: &str| id.parse().map(capture::Tap).map_err(|_| "bad tap id");
+        let r: Result<(), &str> = match args {
+            [] => {
+                for t in capture::taps() {
+                    let iface = net::interface(t.index).map_or(String::from("?"), |i| i.name);
+                    println!("{}: {} by pid {}, snaplen {}, {} matched, {} waiting, {} dropped{}", t.tap.0, iface, t.owner,
+                        t.snaplen, t.matched, t.records, t.dropped,
+                        if t.filter.is_empty() { String::new() } else { format!(", filter \"{}\"", t.filter) });
+                }
+                Ok(())
+            }
+            ["start", iface, rest @ ..] => (|| {
+                let index = net::find_interface(iface).ok_or("no such interface")?;
+                let (snaplen, filter) = match rest {
+                    ["-s", len, filter @ ..] => (len.parse().map_err(|_| "bad snap length")?, filter),
+                    filter                   => (capture::DEFAULT_SNAPLEN, filter),
+                };
+                let t = capture::open(cap, index, snaplen, &filter.join(" "))?;
+                println!("  capturing on {} as tap {}", iface, t.0);
+                Ok(())
+            })(),
+            ["stop", id] => tap(id).and_then(|t| capture::close(cap, t)),
+            ["show", id] => tap(id).and_then(|t| capture::read(cap, t)).map(|records| {
+                for r in &records {
+                    let dir = if r.dir == capture::Direction::In { "in " } else { "out" };
+                    println!("  {}.{:06} {} {}", r.time_us / 1_000_000, r.time_us % 1_000_000, dir, r.summary());
+                }
+                println!("  [{} records]", records.len());
+            }),
+            ["save", id, path] => tap(id).and_then(|t| capture::write_pcap(cap, t, &self.resolve_path(path)))
+                .map(|n| println!("  {} records written to {}", n, path)),
+            _ => Err("usage: pcap [start <iface> [-s snaplen] [filter...] | stop <id> | show <id> | save <id> <file>]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("pcap: {}", e); 1 }
+        }
+    }
+
+    fn cmd_ip(&mut self, args: &[&str]) -> i32 {
+        use crate::capability::{create_capability, CapabilityType, Permissions};
+        use crate::ipc::{self, MessageKind};
+        use crate::net::{self, netlink, IpAddr};
+
+        let admin = self.admin_cap.get_or_insert_with(|| create_capability(
+            current_pid(), CapabilityType::InterfaceAdmin, Permissions::WRITE)).clone();
+        let (ch, cap) = match &self.netlink {
+            Some(c) => c.clone(),
+            None    => match netlink::connect(current_pid(), Some(&admin)) {
+                Ok(c)  => self.netlink.insert(c).clone(),
+                Err(e) => { println!("ip: {}", e); return 1; }
+            },
+        };
+        let call = |req: &[u8]| -> Result<Vec<u8>, &str> {
+            ipc::send_message(ch, current_pid(), &cap, MessageKind::Request, req).map_err(|e| e.as_str())?;
+            let reply = ipc::receive_message(ch, current_pid(), &cap).map_err(|e| e.as_str())?;
+            match reply.payload.split_first() {
+                Some((1, body)) => Ok(body.to_vec()),
+                _               => Err("request refused"),
+            }
+        };
+        let iface = |name: &str| net::find_interface(name).map(|i| i as u8).ok_or("no such interface");
+        let r: Result<(), &str> = match args {
+            [] => call(&[netlink::IF_REQ_LINKS]).and_then(|b| netlink::parse_links(&b).ok_or("bad reply")).and_then(|links| {
+                for l in links {
+                    let up = if l.flags & netlink::LINK_UP != 0 { "UP" } else { "DOWN" };
+                    let dhcp = if l.flags & netlink::LINK_DHCP != 0 { " dhcp" } else { "" };
+                    println!("{}: {} <{}{}> mtu {}  rx {} tx {}", l.index, l.name, up, dhcp, l.mtu, l.rx_packets, l.tx_packets);
+                    if let Some(mac) = l.mac { println!("    ether {}", mac); }
+                    let addrs = call(&[netlink::IF_REQ_ADDRS, l.index]).and_then(|b| netlink::parse_addrs(&b).ok_or("bad reply"))?;
+                    for (a, prefix) in addrs { println!("    inet{} {}/{}", if matches!(a, IpAddr::V6(_)) { "6" } else { "" }, a, prefix); }
+                    if let Some(gw) = l.gateway { println!("    gateway {}", gw); }
+                    let dns = call(&[netlink::IF_REQ_DNS, l.index]).and_then(|b| netlink::parse_dns(&b).ok_or("bad reply"))?;
+                    for a in dns { println!("    nameserver {}", a); }
+                }
+                Ok(())
+            }),
+            ["link", name, state @ ("up" | "down")] => iface(name)
+                .and_then(|i| call(&[netlink::IF_REQ_SET_LINK, i, (*state == "up") as u8])).map(drop),
+            ["addr", op @ ("add" | "del"), name, addr] => iface(name).and_then(|i| {
+                let (ip, prefix) = parse_prefix(addr).ok_or("bad address")?;
+                let mut req = vec![if *op == "add" { netlink::IF_REQ_ADD_ADDR } else { netlink::IF_REQ_DEL_ADDR }, i];
+                netlink::put_addr(&mut req, ip);
+                if *op == "add" { req.push(prefix); }
+                call(&req).map(drop)
+            }),
+            ["gateway", name, gw] => iface(name).and_then(|i| {
+                let gw = if *gw == "none" { net::Ipv4Addr::UNSPECIFIED } else { net::Ipv4Addr::parse(gw).ok_or("bad address")? };
+                call(&[&[netlink::IF_REQ_GATEWAY, i][..], &gw.0].concat()).map(drop)
+            }),
+            ["dns", name, servers] => iface(name).and_then(|i| {
+                let mut req = vec![netlink::IF_REQ_SET_DNS, i, 0];
+                for s in servers.split(',') {
+                    req.extend_from_slice(&net::Ipv4Addr::parse(s).ok_or("bad address")?.0);
+                    req[2] += 1;
+                }
+                call(&req).map(drop)
+            }),
+            ["dhcp", name, state @ ("on" | "off")] => iface(name)
+                .and_then(|i| call(&[netlink::IF_REQ_DHCP, i, (*state == "on") as u8])).map(drop),
+            _ => Err("usage: ip [link <iface> up|down | addr add|del <iface> <ip[/len]> | gateway <iface> <ip>|none | dns <iface> <ip,...> | dhcp <iface> on|off]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("ip: {}", e); 1 }
+        }
+    }
+
+    fn cmd_nat(&mut self, args: &[&str]) -> i32 {
+        use crate::capability::{create_capability, CapabilityType, Permissions};
+        use crate::net::{self, ipv4, nat};
+
+        let cap = self.admin_cap.get_or_insert_with(|| create_capability(
+            current_pid(), CapabilityType::InterfaceAdmin, Permissions::WRITE));
+        let iface = |name: &str| net::find_interface(name).ok_or("no such interface");
+        let name = |index: usize| net::interface(index).map_or(String::from("?"), |i| i.name);
+        let proto = |p: &str| match p { "tcp" => Ok(ipv4::PROTO_TCP), "udp" => Ok(ipv4::PROTO_UDP), _ => Err("expected tcp or udp") };
+        let proto_name = |p: u8| match p { ipv4::PROTO_TCP => "tcp", ipv4::PROTO_UDP => "udp", _ => "icmp" };
+        let port = |p: &str| p.parse::<u16>().map_err(|_| "bad port");
+        let r: Result<(), &str> = match args {
+            [] => {
+                for f in nat::forwards() {
+                    println!("  forward {} -> {}{}", name(f.inside), name(f.outside), if f.masquerade { " masquerade" } else { "" });
+                }
+                for m in nat::port_maps() {
+                    println!("  map {} {} {} -> {}:{}", name(m.outside), proto_name(m.proto), m.port, m.to.0, m.to.1);
+                }
+                let conns = nat::connections();
+                for c in &conns {
+                    println!("  {} {} -> {} as {} -> {} via {}, {} packets, idle {} s left",
+                        proto_name(c.proto), c.from, c.to, c.mapped, c.reached, name(c.outside), c.packets, c.idle_left / 1000);
+                }
+                println!("  [{} connections, {} refused]", conns.len(), nat::refused());
+                Ok(())
+            }
+            ["forward", inside, outside, rest @ ..] if matches!(rest, [] | ["masquerade"]) => (|| {
+                nat::add_forward(cap, iface(inside)?, iface(outside)?, !rest.is_empty())
+            })(),
+            ["unforward", inside, outside] => (|| nat::remove_forward(cap, iface(inside)?, iface(outside)?))(),
+            ["map", outside, p, ext, to] => (|| {
+                let to = parse_socket_addr(to).ok_or("bad address")?;
+                nat::add_port_map(cap, iface(outside)?, proto(p)?, port(ext)?, to)
+            })(),
+            ["unmap", outside, p, ext] => (|| nat::remove_port_map(cap, iface(outside)?, proto(p)?, port(ext)?))(),
+            _ => Err("usage: nat [forward <inside> <outside> [masquerade] | unforward <inside> <outside> | map <outside> tcp|udp <port> <ip:port> | unmap <outside> tcp|udp <port>]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("nat: {}", e); 1 }
+        }
+    }
+
+    fn cmd_wifi(&mut self, args: &[&str]) -> i32 {
+        use crate::capability::{create_capability, CapabilityType, Permissions};
+        use crate::{keyring, wifi};
+
+        let Some(dev) = crate::driver::find_device("wlan0") else {
+            println!("wifi: no Wi-Fi device");
+            return 1;
+        };
+        let cap = self.wifi_cap.get_or_insert_with(|| create_capability(
+            current_pid(), CapabilityType::Device(dev.id), Permissions::READ | Permissions::CONTROL));
+        let ssid = |b: &wifi::Bss| String::from_utf8_lossy(&b.ssid).into_owned();
+        let r: Result<(), &str> = match args {
+            [] => {
+                if let Some(s) = wifi::status() {
+                    println!("  wlan0: {:?}", s.state);
+                    if let Some(b) = &s.bss {
+                        println!("  network {}  bssid {}  channel {}  {} dBm", ssid(b), b.bssid, b.channel, b.rssi);
+                    }
+                    if let Some(e) = s.error { println!("  last error: {}", e); }
+                }
+                Ok(())
+            }
+            ["scan"] => wifi::scan(cap).map(|found| for b in found {
+                let security = if wifi::eapol::supports_wpa3(&b.rsne) { "wpa3" } else if b.rsne.is_empty() { "open" } else { "wpa2" };
+                println!("  {}  {:>4} dBm  ch {:<3} {:<5} {}", b.bssid, b.rssi, b.channel, security, ssid(&b));
+            }),
+            ["connect", name, passphrase] => (|| {
+                let description = format!("wifi:{}", name);
+                let key = match keyring::find(&description) {
+                    Some(key) => { keyring::update(key, passphrase.as_bytes())?; key }
+                    None      => keyring::add(&description, passphrase.as_bytes())?,
+                };
+                wifi::connect(cap, name.as_bytes(), key)?;
+                println!("  joining {}", name);
+                Ok(())
+            })(),
+            ["disconnect"] => wifi::disconnect(cap),
+            ["forget"] => wifi::forget(cap),
+            _ => Err("usage: wifi [scan | connect <ssid> <passphrase> | disconnect | forget]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => { println!("wifi: {}", e); 1 }
+        }
+    }
+
+    fn cmd_modem(&mut self, args: &[&str]) -> i32 {
+        use crate::capability::{create_capability, CapabilityType, Permissions};
+        use crate::modem;
+
+        let Some(dev) = crate::driver::find_device("wwan0") else {
+            println!("modem: no modem");
+            return 1;
+        };
+        let cap = self.modem_cap.get_or_insert_with(|| create_capability(
+            current_pid(), CapabilityType::Device(dev.id), Permissions::READ | Permissions::CONTROL));
+        let signal = |s: &modem::Signal| match s.rssi {
+            Some(r) => format!("{} dBm ({}/4)", r, s.bars()),
+            None    => String::from("unknown"),
+        };
+        let r: Result<(), &str> = match args {
+            [] => {
+                if let Some(s) = modem::status() {
+                    println!("  SIM {:?}  {:?} on {}  {:?}{}", s.sim, s.registration,
+                        if s.provider.is_empty() { "-" } else { &s.provider }, s.radio, if s.attached { "  attached" } else { "" });
+                    if let Some(p) = s.pin.filter(|p| p.required.is_some()) {
+                        println!("  needs {:?}, {} tries left", p.required.unwrap_or(modem::PinKind::Other), p.attempts);
+                    }
+                    if let Some(sig) = &s.signal { println!("  signal {}", signal(sig)); }
+                    if let Some(b) = &s.bearer {
+                        println!("  wwan0 {}  {}/{}  mtu {}", b.apn, b.addr.0, b.addr.1, b.mtu);
+                    }
+                    if let Some(e) = s.error { println!("  last error: {}", e); }
+                }
+                Ok(())
+            }
+            ["pin", pin] => modem::unlock(cap, pin),
+            ["puk", puk, pin] => modem::unblock(cap, puk, pin),
+            ["attach"] => modem::attach_packet(cap),
+            ["detach"] => modem::detach_packet(cap),
+            ["connect", apn] => modem::connect(cap, apn),
+            ["disconnect"] => modem::disconnect(cap),
+            ["signal"] => modem::signal(cap).map(|s| println!("  {}", signal(&s))),
+            _ => Err("usage: modem [pin <pin> | puk <puk> <new-pin> | attach | detach | connect <apn> | disconnect | signal]"),
+        };
+        match r {
+            Ok(())  => 0,
+            Err(e)  => {
+                println!("modem: {}", e);
+                if let Some(p) = modem::status().and_then(|s| s.pin).filter(|p| p.required.is_some()) {
+                    println!("       {} tries left", p.attempts);
+                }
+                1
+            }
+        }
+    }
+
     fn cmd_about(&self) -> i32 {
         println!("");
         println!("  SurakshaOS — India's Sovereign Mobile Operating System");
@@ -467,9 +1844,9 @@ impl Shell {
             path.to_string()
         } else if path == "~" {
             self.get_env("HOME").unwrap_or("/home/user".into())
-        } else if path.starts_with("~/") {
+        } else if let Some(rest) = path.strip_prefix("~/") {
             let home = self.get_env("HOME").unwrap_or("/home/user".into());
-            format!("{}/{}", home, &path[2..])
+            format!("{}/{}", home, rest)
         } else if path == ".." {
             let parts: Vec<&str> = self.cwd.split('/').filter(|s| !s.is_empty()).collect();
             if parts.is_empty() {
@@ -517,10 +1894,46 @@ impl Shell {
 
     fn do_halt(&self) -> ! {
         println!("SurakshaOS halting. Goodbye.");
-        unsafe {
-            let test_addr = 0x10_0000 as *mut u32;
-            core::ptr::write_volatile(test_addr, 0x5555);
-        }
-        loop {}
+        crate::arch::power_off()
+    }
+}
+
+// ─── argument parsing ─────────────────────────────────────────────────────────
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// A 32-byte key written as 64 hex digits.
+fn parse_key(s: &str) -> Option<[u8; 32]> {
+    if s.len() != 64 || !s.is_ascii() { return None; }
+    let mut k = [0u8; 32];
+    for (i, b) in k.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).ok()?;
     }
+    Some(k)
+}
+
+/// `addr/len`; a bare address is a host route.
+fn parse_prefix(s: &str) -> Option<(crate::net::IpAddr, u8)> {
+    use crate::net::IpAddr;
+    let (a, len) = s.split_once('/').map_or((s, None), |(a, l)| (a, Some(l)));
+    let ip = IpAddr::parse(a)?;
+    let max = if matches!(ip, IpAddr::V4(_)) { 32 } else { 128 };
+    let len = match len { Some(l) => l.parse().ok().filter(|&l| l <= max)?, None => max };
+    Some((ip, len))
+}
+
+/// `port` or `first-last`.
+fn parse_port_range(s: &str) -> Option<(u16, u16)> {
+    let (lo, hi) = s.split_once('-').unwrap_or((s, s));
+    let (lo, hi) = (lo.parse().ok()?, hi.parse().ok()?);
+    (lo <= hi).then_some((lo, hi))
+}
+
+/// `a.b.c.d:port` or `[v6]:port`.
+fn parse_socket_addr(s: &str) -> Option<crate::net::SocketAddr> {
+    let (ip, port) = s.rsplit_once(':')?;
+    let ip = ip.strip_prefix('[').and_then(|i| i.strip_suffix(']')).unwrap_or(ip);
+    Some(crate::net::SocketAddr::new(crate::net::IpAddr::parse(ip)?, port.parse().ok()?))
 }
@@ -0,0 +1,176 @@
+//! SurakshaOS SMP Bring-up
+//! Every hart enters `_start` at reset (QEMU virt, -bios none).  The boot
+//! hart runs the kernel; the rest wait in boot.S, interrupts masked, for a
+//! software interrupt.  `init` releases the harts the device tree lists,
+//! one at a time, spin-table style: it gives each a `Hart` block with a
+//! stack and shadow call stack of its own, posts the block in a mailbox,
+//! and raises the hart's MSIP.  The hart loads sp, gp and tp from the
+//! block — tp points at it from then on, as the hart's local data — sets
+//! up its trap vector and interrupts, reports in, and waits at a barrier
+//! until every hart that came up has reached it.
+//!
+//! Under SBI the other harts stay stopped in the firmware instead, and
+//! `init` starts each at the same entry through the HSM extension.
+//!
+//! Until the scheduler runs tasks on them, released harts idle in `wfi`
+//! with only software interrupts enabled; `kick` wakes one.
+
+use alloc::alloc::{alloc_zeroed, Layout};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::arch;
+
+const STACK_SIZE:  usize = 16 * 1024;
+const SHADOW_SIZE: usize = 8 * 1024;
+/// How long a released hart has to report in.
+const START_TIMEOUT_MS: u64 = 100;
+
+/// A hart's local data.  boot.S reads the first two fields.
+#[repr(C)]
+pub struct Hart {
+    stack_top:     usize,
+    shadow_bottom: usize,
+    /// mhartid.
+    pub id:        usize,
+    /// Order brought up in; the boot hart is 0.
+    pub index:     usize,
+    /// Low end of the stack, its canary, and its stackguard id; none on
+    /// the boot hart, which stackguard tracks itself.
+    stack:         Option<(usize, usize, usize)>,
+    /// Start of the shadow call stack's guard words.
+    shadow_guard:  usize,
+}
+
+static BOOT: Hart = Hart { stack_top: 0, shadow_bottom: 0, id: 0, index: 0, stack: None, shadow_guard: 0 };
+static BOOT_ID: AtomicUsize = AtomicUsize::new(0);
+/// Harts brought up after the boot hart.
+static HARTS:   Mutex<Vec<&'static Hart>> = Mutex::new(Vec::new());
+static ONLINE:  AtomicUsize = AtomicUsize::new(1);
+
+/// The calling hart's block.
+pub fn current() -> &'static Hart {
+    let tp = arch::hart_local();
+    // The boot hart never sets it
+    if tp == 0 { &BOOT } else { unsafe { &*(tp as *const Hart) } }
+}
+
+impl Hart {
+    /// The hart's own stack guard: (bottom, canary, stack id).
+    pub(crate) fn stack_guard(&self) -> Option<(usize, usize, usize)> {
+        self.stack
+    }
+
+    pub(crate) fn shadow_guard(&self) -> Option<usize> {
+        (self.shadow_guard != 0).then_some(self.shadow_guard)
+    }
+}
+
+pub fn boot_hart() -> usize {
+    BOOT_ID.load(Ordering::Relaxed)
+}
+
+/// Harts running, the boot hart included.
+pub fn online() -> usize {
+    ONLINE.load(Ordering::Acquire)
+}
+
+/// (mhartid, index) of every hart running.
+pub fn harts() -> Vec<(usize, usize)> {
+    let mut all = Vec::from([(boot_hart(), 0)]);
+    all.extend(HARTS.lock().iter().map(|h| (h.id, h.index)));
+    all
+}
+
+// ─── barrier ──────────────────────────────────────────────────────────────────
+
+static BARRIER_COUNT: AtomicUsize = AtomicUsize::new(0);
+static BARRIER_SENSE: AtomicBool  = AtomicBool::new(false);
+
+/// Wait until every online hart has called `barrier`.
+pub fn barrier() {
+    let sense = !BARRIER_SENSE.load(Ordering::Acquire);
+    if BARRIER_COUNT.fetch_add(1, Ordering::AcqRel) + 1 == online() {
+        BARRIER_COUNT.store(0, Ordering::Relaxed);
+        BARRIER_SENSE.store(sense, Ordering::Release);
+    } else {
+        while BARRIER_SENSE.load(Ordering::Acquire) != sense { core::hint::spin_loop(); }
+    }
+}
+
+// ─── bring-up ─────────────────────────────────────────────────────────────────
+
+fn region(size: usize) -> Result<usize, &'static str> {
+    let p = unsafe { alloc_zeroed(Layout::from_size_align(size, 16).map_err(|_| "bad stack size")?) };
+    if p.is_null() { Err("out of memory for hart stacks") } else { Ok(p as usize) }
+}
+
+/// Release hart `id` and wait for it to report in.
+fn start(id: usize, index: usize) -> Result<(), &'static str> {
+    let stack  = region(STACK_SIZE)?;
+    let shadow = region(SHADOW_SIZE)?;
+    let guard  = crate::stackguard::register("hart", stack, stack + STACK_SIZE);
+    let hart: &'static Hart = Box::leak(Box::new(Hart {
+        stack_top:     stack + STACK_SIZE,
+        shadow_bottom: shadow,
+        id,
+        index,
+        stack:         Some((stack, crate::stackguard::canary_of(guard), guard.0)),
+        shadow_guard:  crate::stackguard::guard_shadow(shadow + SHADOW_SIZE),
+    }));
+
+    let before = online();
+    arch::start_hart(id, hart as *const Hart as usize)?;
+    let deadline = arch::uptime_millis() + START_TIMEOUT_MS;
+    while online() == before {
+        if arch::uptime_millis() > deadline { return Err("did not come up"); }
+        core::hint::spin_loop();
+    }
+    HARTS.lock().push(hart);
+    Ok(())
+}
+
+/// Bring up every hart the device tree lists, unless booted with `nosmp`,
+/// then meet them at a barrier.
+pub fn init(boot_hart: usize) {
+    BOOT_ID.store(boot_hart, Ordering::Relaxed);
+    let Some(p) = crate::fdt::platform() else { return };
+    let nosmp = crate::cmdline::options().nosmp;
+    for (index, &id) in p.harts().iter().filter(|&&id| id as usize != boot_hart && !nosmp).enumerate() {
+        if let Err(e) = start(id as usize, index + 1) {
+            crate::warn!("hart {}: {}", id, e);
+        }
+    }
+    arch::start_harts_done();
+    barrier();
+    crate::info!("{} of {} harts online", online(), p.harts().len());
+}
+
+/// Where boot.S sends a released hart, on its own stack with tp set.
+#[no_mangle]
+extern "C" fn secondary_main(id: usize) -> ! {
+    arch::clear_ipi(id);
+    arch::trap_init_secondary();
+    ONLINE.fetch_add(1, Ordering::AcqRel);
+    barrier();
+    loop { arch::wait_for_interrupt(); }
+}
+
+/// A software interrupt on this hart.
+pub fn ipi() {
+    arch::clear_ipi(arch::hart_id());
+}
+
+/// Wake the hart with index `index` from `wfi`.
+pub fn kick(index: usize) -> Result<(), &'static str> {
+    let id = HARTS.lock().iter().find(|h| h.index == index).map(|h| h.id).ok_or("no such hart")?;
+    arch::send_ipi(id);
+    Ok(())
+}
+
+/// Wake every other hart, e.g. to pick up changed translation tables.
+pub fn kick_all() {
+    for h in HARTS.lock().iter() { arch::send_ipi(h.id); }
+}
@@ -0,0 +1,182 @@
+//! SurakshaOS Stack Protection
+//! Defence in depth under the raw-pointer code (IPC buffers, page tables,
+//! arch glue) that Rust cannot vouch for.  Every kernel stack gets its own
+//! canary, drawn from the entropy pool:
+//!
+//! - `_trap_entry` stores the running stack's canary above each trap frame
+//!   and compares it before `mret`, catching a handler that wrote past
+//!   the frame it was given.
+//! - The lowest `GUARD_WORDS` of each stack hold the canary too, and are
+//!   checked on every trap return, catching a stack that overflowed.
+//!
+//! Built with `make hardened`, the kernel also keeps return addresses on a
+//! shadow call stack (`-Zsanitizer=shadow-call-stack`): boot.S points gp
+//! at `_shadow_stack_bottom`, out of reach of an overflowing buffer, and
+//! its top carries guard words as well.
+//!
+//! Any corruption panics with the stack, the address and what was found
+//! there.  Besides the boot stack, each secondary hart has a stack and
+//! shadow stack of its own (see smp), whose guards it checks itself; a
+//! scheduler that gives tasks their own stacks registers each one and
+//! activates it when switching.
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::arch::TrapFrame;
+
+/// Canary words at the low end of each stack.
+pub const GUARD_WORDS: usize = 8;
+const WORD: usize = core::mem::size_of::<usize>();
+
+/// The running stack's canary; `_trap_entry` reads it.
+pub static STACK_CANARY: AtomicUsize = AtomicUsize::new(0);
+
+/// Low end of the running stack, 0 until `init`.  Kept outside the
+/// registry so the trap path never takes a lock.
+static ACTIVE_BOTTOM: AtomicUsize = AtomicUsize::new(0);
+static ACTIVE_ID:     AtomicUsize = AtomicUsize::new(0);
+/// Start of the shadow call stack's guard words, 0 until `init`.
+static SHADOW_GUARD:  AtomicUsize = AtomicUsize::new(0);
+static SHADOW_CANARY: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackId(pub usize);
+
+#[derive(Debug, Clone)]
+pub struct Stack {
+    pub name:   &'static str,
+    pub bottom: usize,
+    pub top:    usize,
+    canary:     usize,
+}
+
+static STACKS: Mutex<Vec<Stack>> = Mutex::new(Vec::new());
+
+extern "C" {
+    static _stack_bottom:     u8;
+    static _stack_top:        u8;
+    static _shadow_stack_top: u8;
+}
+
+fn fresh_canary() -> usize {
+    // Never zero, so a zeroed stack never passes for a guarded one
+    crate::entropy::next_u64() as usize | 1
+}
+
+fn fill(at: usize, canary: usize) {
+    for i in 0..GUARD_WORDS {
+        // SAFETY: callers pass the guard area of a stack they own
+        unsafe { core::ptr::write_volatile((at + i * WORD) as *mut usize, canary); }
+    }
+}
+
+/// The first guard word at `at` that no longer holds `canary`.
+fn damaged(at: usize, canary: usize) -> Option<(usize, usize)> {
+    (0..GUARD_WORDS).map(|i| at + i * WORD).find_map(|addr| {
+        // SAFETY: `at` is the guard area of a registered stack
+        let found = unsafe { core::ptr::read_volatile(addr as *const usize) };
+        (found != canary).then_some((addr, found))
+    })
+}
+
+/// Guard the boot stack and the shadow call stack.  Runs before traps are
+/// enabled, so no trap frame holds an older canary.
+pub fn init() {
+    // SAFETY: linker-script symbols; only their addresses are used
+    let (bottom, top, shadow_top) = unsafe {(
+        &_stack_bottom as *const u8 as usize,
+        &_stack_top as *const u8 as usize,
+        &_shadow_stack_top as *const u8 as usize,
+    )};
+    let boot = register("boot", bottom, top);
+    activate(boot);
+
+    let guard = shadow_top - GUARD_WORDS * WORD;
+    let canary = fresh_canary();
+    fill(guard, canary);
+    SHADOW_CANARY.store(canary, Ordering::Relaxed);
+    SHADOW_GUARD.store(guard, Ordering::Relaxed);
+}
+
+/// Register a kernel stack spanning `bottom..top`, fill its guard words
+/// and give it a canary of its own.
+pub fn register(name: &'static str, bottom: usize, top: usize) -> StackId {
+    let canary = fresh_canary();
+    fill(bottom, canary);
+    let mut stacks = STACKS.lock();
+    stacks.push(Stack { name, bottom, top, canary });
+    StackId(stacks.len() - 1)
+}
+
+/// Switch the canary checks to stack `id`, as the CPU moves onto it.  The
+/// trap frames on the stack being left keep its canary, so this must not
+/// run inside a trap taken on that stack.
+pub fn activate(id: StackId) {
+    let stacks = STACKS.lock();
+    let Some(s) = stacks.get(id.0) else { return };
+    STACK_CANARY.store(s.canary, Ordering::Relaxed);
+    ACTIVE_BOTTOM.store(s.bottom, Ordering::Relaxed);
+    ACTIVE_ID.store(id.0, Ordering::Relaxed);
+}
+
+pub(crate) fn canary_of(id: StackId) -> usize {
+    STACKS.lock().get(id.0).map_or(0, |s| s.canary)
+}
+
+/// Guard the shadow call stack ending at `top` with the boot shadow
+/// stack's canary, and return where its guard words start.
+pub(crate) fn guard_shadow(top: usize) -> usize {
+    let guard = top - GUARD_WORDS * WORD;
+    fill(guard, SHADOW_CANARY.load(Ordering::Relaxed));
+    guard
+}
+
+pub fn stacks() -> Vec<Stack> {
+    STACKS.lock().clone()
+}
+
+fn stack_name(index: usize) -> &'static str {
+    STACKS.try_lock().and_then(|s| s.get(index).map(|s| s.name)).unwrap_or("?")
+}
+
+// ─── checks ───────────────────────────────────────────────────────────────────
+
+/// Check the running stack's and the shadow stack's guard words.  Called
+/// on every trap return.
+pub fn check(mepc: usize, mcause: usize) {
+    // A secondary hart checks its own stacks
+    let hart = crate::smp::current();
+    let (bottom, canary, id) = hart.stack_guard().unwrap_or_else(|| (
+        ACTIVE_BOTTOM.load(Ordering::Relaxed),
+        STACK_CANARY.load(Ordering::Relaxed),
+        ACTIVE_ID.load(Ordering::Relaxed),
+    ));
+    if bottom == 0 { return; }
+    let sp = crate::arch::stack_pointer();
+    if sp < bottom + GUARD_WORDS * WORD {
+        panic!("kernel stack overflow: stack {} sp={:#x} below guard at {:#x} (mepc={:#x} mcause={:#x})",
+            stack_name(id), sp, bottom, mepc, mcause);
+    }
+    if let Some((addr, found)) = damaged(bottom, canary) {
+        panic!("kernel stack overflow: stack {} guard word at {:#x} overwritten with {:#x} (sp={:#x} mepc={:#x} mcause={:#x})",
+            stack_name(id), addr, found, sp, mepc, mcause);
+    }
+    let guard = hart.shadow_guard().unwrap_or_else(|| SHADOW_GUARD.load(Ordering::Relaxed));
+    if guard == 0 { return; }
+    if let Some((addr, found)) = damaged(guard, SHADOW_CANARY.load(Ordering::Relaxed)) {
+        panic!("shadow call stack overflow: guard word at {:#x} overwritten with {:#x} (mepc={:#x} mcause={:#x})",
+            addr, found, mepc, mcause);
+    }
+}
+
+/// Reached from `_trap_entry` when the canary above a trap frame has
+/// changed by the time the trap returns.
+#[no_mangle]
+pub(crate) extern "C" fn _stack_smashed(frame: &TrapFrame, found: usize) -> ! {
+    let (mepc, mcause) = crate::arch::trap_cause();
+    panic!("trap frame canary at {:#x} overwritten with {:#x} on stack {} (mepc={:#x} mcause={:#x} ra={:#x})",
+        frame as *const TrapFrame as usize + core::mem::size_of::<TrapFrame>(), found,
+        stack_name(ACTIVE_ID.load(Ordering::Relaxed)), mepc, mcause, frame.ra);
+}
@@ -0,0 +1,544 @@
+//! SurakshaOS System Calls
+//! `ecall` ABI: call number in a7, arguments in a0–a5, result in a0.
+//! Non-negative results are call-specific; negative results are
+//! `SyscallError` codes.  The kernel and its callers share one address
+//! space (M-mode), so buffer pointers are used as given after checking
+//! for null.
+//!
+//! Capabilities cross this boundary only as handles into the caller's
+//! own capability table; each call that takes one looks it up there.
+
+use crate::net::accounting;
+use crate::power;
+
+// ─── call numbers ─────────────────────────────────────────────────────────────
+
+/// `power_stats(kind, buf, len)`: copy power statistics into `buf`.
+/// Returns the number of bytes written.
+pub const SYS_POWER_STATS: usize = 1;
+
+/// `kind` values for `SYS_POWER_STATS`.
+pub const POWER_STATS_SUMMARY:   usize = 0; // one `power::PowerStats`
+pub const POWER_STATS_WAKELOCKS: usize = 1; // array of `power::WakelockRecord`
+
+/// `net_stats(kind, buf, len)`: copy per-app data usage into `buf`.
+/// Returns the number of bytes written.
+pub const SYS_NET_STATS: usize = 2;
+
+/// `kind` values for `SYS_NET_STATS`.
+pub const NET_STATS_TOTALS: usize = 0; // array of `net::accounting::UsageRecord`, one per app and interface
+pub const NET_STATS_DAILY:  usize = 1; // the same, one per app, interface and day
+
+/// `dmesg(kind, buf, len)`: read or clear the kernel log.  Returns the
+/// number of bytes written.
+pub const SYS_DMESG: usize = 3;
+
+/// `kind` values for `SYS_DMESG`.
+pub const DMESG_READ:  usize = 0; // the newest whole lines that fit in `buf`
+pub const DMESG_CLEAR: usize = 1; // empty the log; system processes only
+
+/// `boot_control(kind, arg, len)`: read or change the A/B slot metadata.
+/// Returns the number of bytes written, or 0.
+pub const SYS_BOOT_CONTROL: usize = 4;
+
+/// `kind` values for `SYS_BOOT_CONTROL`.  All but `BOOTCTL_INFO` are for
+/// system processes only.
+pub const BOOTCTL_INFO:            usize = 0; // array of `SlotRecord` into buffer `arg`, one per slot
+pub const BOOTCTL_MARK_SUCCESSFUL: usize = 1; // the running slot booted successfully
+pub const BOOTCTL_SET_ACTIVE:      usize = 2; // boot slot `arg` (0 is A) next
+pub const BOOTCTL_SET_UNBOOTABLE:  usize = 3; // never boot slot `arg`
+
+/// `service_notify(kind)`: tell init how the calling service is doing.
+/// Returns 0.
+pub const SYS_SERVICE_NOTIFY: usize = 5;
+
+/// `kind` values for `SYS_SERVICE_NOTIFY`.
+pub const NOTIFY_READY:    usize = 0; // up and serving: services that wait on it may start
+pub const NOTIFY_WATCHDOG: usize = 1; // still alive: its watchdog interval starts over
+
+/// `service_sockets(buf, len)`: the sockets init opened for the calling
+/// service and passed it on activation, as an array of u32 socket ids in
+/// the order its config lists them.  Returns the number of bytes written.
+pub const SYS_SERVICE_SOCKETS: usize = 6;
+
+/// `write(fd, buf, len)`: write `len` bytes to standard output (1) or
+/// standard error (2), both the console.  Returns `len`.
+pub const SYS_WRITE: usize = 7;
+
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+/// `exit(code)`: end the calling process.  Does not return.
+pub const SYS_EXIT: usize = 8;
+
+/// `clock(kind)`: read a clock.
+pub const SYS_CLOCK: usize = 9;
+
+/// `kind` values for `SYS_CLOCK`.
+pub const CLOCK_MONOTONIC: usize = 0; // milliseconds since boot
+pub const CLOCK_REALTIME:  usize = 1; // nanoseconds since the Unix epoch
+
+/// `cap(kind, arg, len)`: the calling process's capabilities.  Returns
+/// the number of bytes written, or 0.
+pub const SYS_CAP: usize = 10;
+
+/// `kind` values for `SYS_CAP`.
+pub const CAP_LIST:  usize = 0; // array of `CapRecord` into buffer `arg`, one per capability
+pub const CAP_CLOSE: usize = 1; // give up the capability handle `arg` names
+
+/// `ipc_send(handle, buf, len)`: send `len` bytes as a request on the
+/// channel the IPC capability `handle` names.  Returns 0.
+pub const SYS_IPC_SEND: usize = 11;
+
+/// `ipc_recv(handle, buf, len)`: take the next message on the channel
+/// `handle` names into `buf`.  Returns its length; `WouldBlock` if there
+/// is none yet, `BufferTooSmall` if it does not fit (it stays queued).
+pub const SYS_IPC_RECV: usize = 12;
+
+/// `audit_read(query, buf, len)`: the capability audit entries the
+/// `AuditQuery` at `query` picks, oldest first, as many whole
+/// `AuditRecord`s as fit in `buf`.  Returns the number of bytes written.
+pub const SYS_AUDIT_READ: usize = 13;
+
+/// `AuditQuery::pid` for entries about any process.
+pub const AUDIT_ANY_PID: u64 = u64::MAX;
+
+/// `ipc_recv_wait(handle, buf, len, timeout_ms)`: as `ipc_recv`, but if
+/// there is no message yet, block until one comes or `timeout_ms` passes
+/// (`WAIT_FOREVER`: never), then `TimedOut`.  Returns its length.
+pub const SYS_IPC_RECV_WAIT: usize = 14;
+
+pub const WAIT_FOREVER: usize = usize::MAX;
+
+/// `port(kind, a, b, c)`: serve an IPC port.  Clients send to it with
+/// `ipc_send` on their badged handle.
+pub const SYS_PORT: usize = 15;
+
+/// `ipc_call(handle, buf, len, cap)`: send the `len` bytes at `buf` as a
+/// request on the channel or to the port `handle` names, and block until
+/// the reply to it comes, or the channel or port goes.  The reply is
+/// written over the request, up to `cap` bytes; one longer is lost, and
+/// the call fails `BufferTooSmall`.  Returns its length.
+pub const SYS_IPC_CALL: usize = 16;
+
+/// `ipc_reply(call, buf, len)`: answer the call whose request came with
+/// `PortHeader::call` = `call` with the `len` bytes at `buf`.  Returns 0;
+/// `InvalidArgument` if that call is not waiting on the caller.
+pub const SYS_IPC_REPLY: usize = 17;
+
+/// `kind` values for `SYS_PORT`.
+pub const PORT_CREATE:    usize = 0; // a new port the caller serves; returns its handle
+pub const PORT_BADGE:     usize = 1; // give process `b` a handle to send to port handle `a`, with badge `c`
+pub const PORT_RECV:      usize = 2; // the next message on port (or channel) handle `a`, as a `PortHeader` and its payload, into buffer `b` of `c` bytes
+pub const PORT_RECV_WAIT: usize = 3; // the same, waiting for one to come
+
+/// One slot, as `BOOTCTL_INFO` reports it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SlotRecord {
+    pub priority:   u8,
+    pub tries:      u8,
+    pub successful: u8,
+    /// 1 for the slot running.
+    pub current:    u8,
+}
+
+/// One capability, as `CAP_LIST` reports it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CapRecord {
+    pub handle:       u64,
+    /// `CapabilityType::code`: the kind, and the resource's number.
+    pub kind:         u32,
+    pub perms:        u32,
+    pub resource:     u64,
+    /// Time left before it expires; u64::MAX if it never does.
+    pub remaining_ms: u64,
+}
+
+/// What `SYS_AUDIT_READ` is to read.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AuditQuery {
+    /// The caller's AuditLog capability, with READ.
+    pub handle:    u64,
+    /// Only entries with a later `seq`: the last one read, or 0.
+    pub after:     u64,
+    /// Only entries about this process; `AUDIT_ANY_PID` for all.
+    pub pid:       u64,
+    /// Only entries about this capability id; 0 for all.
+    pub cap:       u64,
+    /// Bit `1 << op` for each `AuditRecord::op` wanted; 0 for all.
+    pub ops:       u32,
+    pub _reserved: u32,
+}
+
+/// One audit entry, as `SYS_AUDIT_READ` reports it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AuditRecord {
+    /// Consecutive from 1; a gap is entries overwritten before reading.
+    pub seq:       u64,
+    pub time_ms:   u64,
+    pub pid:       u64,
+    pub cap:       u64,
+    /// 0 create, 1 validate, 2 deny, 3 revoke, 4 expire, 5 delegate.
+    pub op:        u32,
+    pub _reserved: u32,
+}
+
+/// Ahead of each message `PORT_RECV` takes.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PortHeader {
+    /// The badge of the client's handle it came with.
+    pub badge:  u64,
+    pub sender: u64,
+    /// 0 request, 1 reply, 2 notification.
+    pub kind:   u32,
+    /// Payload bytes after the header.
+    pub len:    u32,
+    /// A request made with `ipc_call`: the id to `ipc_reply` with; 0
+    /// otherwise.
+    pub call:   u64,
+}
+
+// ─── errors ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(isize)]
+pub enum SyscallError {
+    NoSuchCall       = -1,
+    InvalidArgument  = -2,
+    BadAddress       = -3,
+    BufferTooSmall   = -4,
+    PermissionDenied = -5,
+    /// Nothing to do yet: try again later.
+    WouldBlock       = -6,
+    /// Waited as long as asked, for nothing.
+    TimedOut         = -7,
+}
+
+impl SyscallError {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SyscallError::NoSuchCall       => "no such system call",
+            SyscallError::InvalidArgument  => "invalid argument",
+            SyscallError::BadAddress       => "bad address",
+            SyscallError::BufferTooSmall   => "buffer too small",
+            SyscallError::PermissionDenied => "permission denied",
+            SyscallError::WouldBlock       => "would block",
+            SyscallError::TimedOut         => "timed out",
+        }
+    }
+}
+
+// ─── dispatch ─────────────────────────────────────────────────────────────────
+
+/// Entry point from the trap handler.
+pub fn dispatch(num: usize, args: [usize; 6]) -> isize {
+    if !crate::sandbox::syscall_allowed(crate::process::current_pid(), num) {
+        return SyscallError::PermissionDenied as isize;
+    }
+    let result = match num {
+        SYS_POWER_STATS     => sys_power_stats(args[0], args[1], args[2]),
+        SYS_NET_STATS       => sys_net_stats(args[0], args[1], args[2]),
+        SYS_DMESG           => sys_dmesg(args[0], args[1], args[2]),
+        SYS_BOOT_CONTROL    => sys_boot_control(args[0], args[1], args[2]),
+        SYS_SERVICE_NOTIFY  => sys_service_notify(args[0]),
+        SYS_SERVICE_SOCKETS => sys_service_sockets(args[0], args[1]),
+        SYS_WRITE           => sys_write(args[0], args[1], args[2]),
+        SYS_EXIT            => sys_exit(args[0]),
+        SYS_CLOCK           => sys_clock(args[0]),
+        SYS_CAP             => sys_cap(args[0], args[1], args[2]),
+        SYS_IPC_SEND        => sys_ipc_send(args[0], args[1], args[2]),
+        SYS_IPC_RECV        => sys_ipc_recv(args[0], args[1], args[2]),
+        SYS_AUDIT_READ      => sys_audit_read(args[0], args[1], args[2]),
+        SYS_IPC_RECV_WAIT   => sys_ipc_recv_wait(args[0], args[1], args[2], args[3]),
+        SYS_PORT            => sys_port(args[0], args[1], args[2], args[3]),
+        SYS_IPC_CALL        => sys_ipc_call(args[0], args[1], args[2], args[3]),
+        SYS_IPC_REPLY       => sys_ipc_reply(args[0], args[1], args[2]),
+        _                   => Err(SyscallError::NoSuchCall),
+    };
+    match result {
+        Ok(v)  => v as isize,
+        Err(e) => e as isize,
+    }
+}
+
+/// Copy `bytes` to the caller's buffer.
+fn copy_out(buf: usize, len: usize, bytes: &[u8]) -> Result<usize, SyscallError> {
+    if buf == 0 { return Err(SyscallError::BadAddress); }
+    if len < bytes.len() { return Err(SyscallError::BufferTooSmall); }
+    unsafe { core::ptr::copy_nonoverlapping(bytes.as_ptr(), buf as *mut u8, bytes.len()); }
+    Ok(bytes.len())
+}
+
+/// View a slice of plain-old-data records as bytes.
+fn as_bytes<T: Copy>(v: &[T]) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(v.as_ptr() as *const u8, core::mem::size_of_val(v)) }
+}
+
+fn sys_power_stats(kind: usize, buf: usize, len: usize) -> Result<usize, SyscallError> {
+    match kind {
+        POWER_STATS_SUMMARY   => copy_out(buf, len, as_bytes(&[power::power_stats()])),
+        POWER_STATS_WAKELOCKS => copy_out(buf, len, as_bytes(&power::wakelock_records())),
+        _                     => Err(SyscallError::InvalidArgument),
+    }
+}
+
+fn sys_net_stats(kind: usize, buf: usize, len: usize) -> Result<usize, SyscallError> {
+    match kind {
+        NET_STATS_TOTALS => copy_out(buf, len, as_bytes(&accounting::totals())),
+        NET_STATS_DAILY  => copy_out(buf, len, as_bytes(&accounting::daily())),
+        _                => Err(SyscallError::InvalidArgument),
+    }
+}
+
+fn sys_dmesg(kind: usize, buf: usize, len: usize) -> Result<usize, SyscallError> {
+    match kind {
+        DMESG_READ  => copy_out(buf, len, &crate::klog::read(len)),
+        DMESG_CLEAR => {
+            if !crate::process::is_system(crate::process::current_pid()) { return Err(SyscallError::PermissionDenied); }
+            crate::klog::clear();
+            Ok(0)
+        }
+        _           => Err(SyscallError::InvalidArgument),
+    }
+}
+
+fn sys_boot_control(kind: usize, arg: usize, len: usize) -> Result<usize, SyscallError> {
+    use crate::bootctl::{self, Slot};
+
+    if kind != BOOTCTL_INFO && !crate::process::is_system(crate::process::current_pid()) {
+        return Err(SyscallError::PermissionDenied);
+    }
+    let slot = || Slot::from_index(arg).ok_or(SyscallError::InvalidArgument);
+    let done = |r: Result<(), &str>| r.map(|()| 0).map_err(|_| SyscallError::InvalidArgument);
+    match kind {
+        BOOTCTL_INFO => {
+            let current = bootctl::current();
+            let records = bootctl::slots().iter().enumerate().map(|(i, s)| SlotRecord {
+                priority:   s.priority,
+                tries:      s.tries,
+                successful: s.successful as u8,
+                current:    (current.map(Slot::index) == Some(i)) as u8,
+            }).collect::<alloc::vec::Vec<_>>();
+            copy_out(arg, len, as_bytes(&records))
+        }
+        BOOTCTL_MARK_SUCCESSFUL => done(bootctl::mark_successful()),
+        BOOTCTL_SET_ACTIVE      => done(bootctl::set_active(slot()?)),
+        BOOTCTL_SET_UNBOOTABLE  => done(bootctl::set_unbootable(slot()?)),
+        _                       => Err(SyscallError::InvalidArgument),
+    }
+}
+
+fn sys_service_notify(kind: usize) -> Result<usize, SyscallError> {
+    match kind {
+        NOTIFY_READY => {
+            crate::init::notify_ready(crate::process::current_pid());
+            Ok(0)
+        }
+        NOTIFY_WATCHDOG => {
+            crate::init::notify_watchdog(crate::process::current_pid());
+            Ok(0)
+        }
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+fn sys_service_sockets(buf: usize, len: usize) -> Result<usize, SyscallError> {
+    let ids: alloc::vec::Vec<u32> = crate::init::listen_sockets(crate::process::current_pid()).iter().map(|s| s.0).collect();
+    copy_out(buf, len, as_bytes(&ids))
+}
+
+fn sys_write(fd: usize, buf: usize, len: usize) -> Result<usize, SyscallError> {
+    if fd != STDOUT && fd != STDERR { return Err(SyscallError::InvalidArgument); }
+    if len == 0 { return Ok(0); }
+    if buf == 0 { return Err(SyscallError::BadAddress); }
+    let bytes = unsafe { core::slice::from_raw_parts(buf as *const u8, len) };
+    crate::console::print_str(&alloc::string::String::from_utf8_lossy(bytes));
+    Ok(len)
+}
+
+/// The process is gone; with no scheduler there is nothing else for the
+/// hart to run, so it idles, still taking interrupts.
+fn sys_exit(code: usize) -> ! {
+    let pid = crate::process::current_pid();
+    crate::debug!("pid {}: exited with {}", pid, code as isize);
+    crate::process::exit(pid);
+    crate::arch::interrupts_enable();
+    loop { crate::arch::wait_for_interrupt(); }
+}
+
+fn sys_clock(kind: usize) -> Result<usize, SyscallError> {
+    match kind {
+        CLOCK_MONOTONIC => Ok(crate::process::uptime_ms() as usize),
+        CLOCK_REALTIME  => Ok(crate::alarm::rtc_now_ns() as usize),
+        _               => Err(SyscallError::InvalidArgument),
+    }
+}
+
+fn sys_cap(kind: usize, arg: usize, len: usize) -> Result<usize, SyscallError> {
+    use crate::capability::{self, CapHandle};
+
+    let pid = crate::process::current_pid();
+    match kind {
+        CAP_LIST => {
+            let now = crate::arch::uptime_millis();
+            let records: alloc::vec::Vec<CapRecord> = capability::held_by(pid).iter().map(|c| {
+                let (kind, resource) = c.cap_type.code();
+                CapRecord {
+                    handle: c.handle.0,
+                    kind,
+                    perms: c.perms.0,
+                    resource,
+                    remaining_ms: c.remaining_ms(now).unwrap_or(u64::MAX),
+                }
+            }).collect();
+            copy_out(arg, len, as_bytes(&records))
+        }
+        CAP_CLOSE => capability::close_handle(pid, CapHandle(arg as u64))
+            .map(|()| 0)
+            .map_err(|_| SyscallError::InvalidArgument),
+        _ => Err(SyscallError::InvalidArgument),
+    }
+}
+
+fn ipc_error(e: crate::ipc::IpcError) -> SyscallError {
+    use crate::ipc::IpcError;
+    match e {
+        IpcError::NotAnEndpoint | IpcError::PermissionDenied => SyscallError::PermissionDenied,
+        IpcError::BufferFull | IpcError::BufferEmpty         => SyscallError::WouldBlock,
+        IpcError::NoSuchChannel | IpcError::MessageTooLarge  => SyscallError::InvalidArgument,
+        IpcError::NoSuchPort | IpcError::NoSuchCall          => SyscallError::InvalidArgument,
+        IpcError::TimedOut                                   => SyscallError::TimedOut,
+    }
+}
+
+fn sys_ipc_send(handle: usize, buf: usize, len: usize) -> Result<usize, SyscallError> {
+    use crate::ipc::{self, MessageKind};
+
+    if buf == 0 && len != 0 { return Err(SyscallError::BadAddress); }
+    let payload = if len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(buf as *const u8, len) } };
+    let pid = crate::process::current_pid();
+    ipc::send(pid, crate::capability::CapHandle(handle as u64), MessageKind::Request, payload)
+        .map(|()| 0)
+        .map_err(ipc_error)
+}
+
+fn sys_ipc_recv(handle: usize, buf: usize, len: usize) -> Result<usize, SyscallError> {
+    recv_into(buf, len, |pid| crate::ipc::receive(pid, crate::capability::CapHandle(handle as u64), len))
+}
+
+fn sys_ipc_recv_wait(handle: usize, buf: usize, len: usize, timeout_ms: usize) -> Result<usize, SyscallError> {
+    let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as u64);
+    recv_into(buf, len, |pid| crate::ipc::receive_timeout(pid, crate::capability::CapHandle(handle as u64), len, timeout))
+}
+
+/// Copy the message `receive` takes for the caller into `buf`.
+fn recv_into(
+    buf:     usize,
+    len:     usize,
+    receive: impl FnOnce(crate::process::ProcessId) -> Result<crate::ipc::Message, crate::ipc::IpcError>,
+) -> Result<usize, SyscallError> {
+    use crate::ipc::IpcError;
+
+    if buf == 0 && len != 0 { return Err(SyscallError::BadAddress); }
+    let msg = match receive(crate::process::current_pid()) {
+        Ok(m) => m,
+        Err(IpcError::MessageTooLarge) => return Err(SyscallError::BufferTooSmall),
+        Err(e) => return Err(ipc_error(e)),
+    };
+    if msg.payload.is_empty() { return Ok(0); }
+    copy_out(buf, len, &msg.payload)
+}
+
+fn sys_ipc_call(handle: usize, buf: usize, len: usize, cap: usize) -> Result<usize, SyscallError> {
+    if buf == 0 && (len != 0 || cap != 0) { return Err(SyscallError::BadAddress); }
+    let request = if len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(buf as *const u8, len) } };
+    let pid = crate::process::current_pid();
+    let reply = crate::ipc::call(pid, crate::capability::CapHandle(handle as u64), request, None).map_err(ipc_error)?;
+    if reply.payload.is_empty() { return Ok(0); }
+    copy_out(buf, cap, &reply.payload)
+}
+
+fn sys_ipc_reply(call: usize, buf: usize, len: usize) -> Result<usize, SyscallError> {
+    if buf == 0 && len != 0 { return Err(SyscallError::BadAddress); }
+    let payload = if len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(buf as *const u8, len) } };
+    crate::ipc::reply(crate::process::current_pid(), call as u64, payload)
+        .map(|()| 0)
+        .map_err(ipc_error)
+}
+
+fn sys_audit_read(query: usize, buf: usize, len: usize) -> Result<usize, SyscallError> {
+    use crate::capability::{self, AuditFilter, CapHandle, CapId, CapabilityType, Permissions};
+    use crate::process::ProcessId;
+
+    if query == 0 || buf == 0 { return Err(SyscallError::BadAddress); }
+    let q = unsafe { core::ptr::read_unaligned(query as *const AuditQuery) };
+    let pid = crate::process::current_pid();
+    capability::validate_handle(pid, CapHandle(q.handle), CapabilityType::AuditLog, Permissions::READ)
+        .map_err(|_| SyscallError::PermissionDenied)?;
+    let max = len / core::mem::size_of::<AuditRecord>();
+    if max == 0 { return Err(SyscallError::BufferTooSmall); }
+    let filter = AuditFilter {
+        after: q.after,
+        pid:   (q.pid != AUDIT_ANY_PID).then_some(ProcessId(q.pid as usize)),
+        cap:   (q.cap != 0).then_some(CapId(q.cap)),
+        ops:   q.ops,
+    };
+    let records: alloc::vec::Vec<AuditRecord> = capability::audit_query(&filter, max).iter().map(|e| AuditRecord {
+        seq:       e.seq,
+        time_ms:   e.time_ms,
+        pid:       e.pid.0 as u64,
+        cap:       e.cap.0,
+        op:        e.op as u32,
+        _reserved: 0,
+    }).collect();
+    copy_out(buf, len, as_bytes(&records))
+}
+
+fn sys_port(kind: usize, a: usize, b: usize, c: usize) -> Result<usize, SyscallError> {
+    use crate::capability::CapHandle;
+    use crate::ipc::{self, IpcError, MessageKind};
+
+    let pid = crate::process::current_pid();
+    let header = core::mem::size_of::<PortHeader>();
+    let receive = |wait: bool| {
+        if b == 0 { return Err(SyscallError::BadAddress); }
+        let max = c.checked_sub(header).ok_or(SyscallError::BufferTooSmall)?;
+        let handle = CapHandle(a as u64);
+        let msg = if wait { ipc::receive_timeout(pid, handle, max, None) } else { ipc::receive(pid, handle, max) };
+        let msg = match msg {
+            Ok(m) => m,
+            Err(IpcError::MessageTooLarge) => return Err(SyscallError::BufferTooSmall),
+            Err(e) => return Err(ipc_error(e)),
+        };
+        let kind = match msg.kind {
+            MessageKind::Request      => 0,
+            MessageKind::Reply        => 1,
+            MessageKind::Notification => 2,
+        };
+        let head = PortHeader {
+            badge:  msg.badge,
+            sender: msg.sender.0 as u64,
+            kind,
+            len:    msg.payload.len() as u32,
+            call:   msg.call,
+        };
+        let mut out = alloc::vec::Vec::with_capacity(header + msg.payload.len());
+        out.extend_from_slice(as_bytes(&[head]));
+        out.extend_from_slice(&msg.payload);
+        copy_out(b, c, &out)
+    };
+    match kind {
+        PORT_CREATE    => Ok(ipc::create_port(pid).1.handle.0 as usize),
+        PORT_BADGE     => ipc::badge_sender(pid, CapHandle(a as u64), crate::process::ProcessId(b), c as u64)
+            .map(|_| 0)
+            .map_err(ipc_error),
+        PORT_RECV      => receive(false),
+        PORT_RECV_WAIT => receive(true),
+        _              => Err(SyscallError::InvalidArgument),
+    }
+}
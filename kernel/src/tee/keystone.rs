@@ -0,0 +1,199 @@
+//! Keystone Enclaves
+//! A backend for a kernel running in S-mode under the Keystone security
+//! monitor.  Each session is an enclave: the TA's image is copied into
+//! fresh enclave memory (EPM) and handed to the monitor, and an untrusted
+//! shared region (UTM) carries commands in and replies out.
+//!
+//! TA images live in /lib/tee/<uuid>.ke, already laid out for the EPM by
+//! the Keystone SDK, behind a header giving where the runtime and the app
+//! start and how much memory the enclave needs.
+//!
+//! Running a command writes it to the UTM and runs (or resumes) the
+//! enclave; the TA answers with edge call `EDGE_REPLY`, which exits to
+//! the host with the reply in the UTM, and stays parked there until the
+//! next command resumes it.
+
+use alloc::alloc::{alloc_zeroed, dealloc, Layout};
+use alloc::format;
+use alloc::vec::Vec;
+
+use super::*;
+
+const EID_KEYSTONE: usize = 0x0842_4b45;
+const FID_CREATE:   usize = 2001;
+const FID_DESTROY:  usize = 2002;
+const FID_RUN:      usize = 2003;
+const FID_RESUME:   usize = 2005;
+
+const EID_BASE:            usize = 0x10;
+const FID_PROBE_EXTENSION: usize = 3;
+
+const ERR_INTERRUPTED:    usize = 100_002;
+const ERR_EDGE_CALL_HOST: usize = 100_011;
+
+/// Edge call a TA makes to hand back its reply.
+const EDGE_REPLY: u32 = 1;
+
+const IMAGE_MAGIC: &[u8; 4] = b"KSTA";
+const PAGE: usize = 4096;
+/// UTM header: [cmd u32, len u32], then the data.
+const UTM_HEADER: usize = 8;
+
+/// `struct keystone_sbi_create` as the monitor takes it.
+#[repr(C)]
+struct CreateArgs {
+    epm_paddr:      usize,
+    epm_size:       usize,
+    utm_paddr:      usize,
+    utm_size:       usize,
+    runtime_paddr:  usize,
+    user_paddr:     usize,
+    free_paddr:     usize,
+    free_requested: usize,
+}
+
+#[cfg(target_arch = "riscv64")]
+fn sbi_call(eid: usize, fid: usize, a0: usize, a1: usize) -> (usize, usize) {
+    let (err, val) = crate::arch::sbi::ecall(eid, fid, [a0, a1, 0]);
+    (err as usize, val)
+}
+
+/// No SBI outside RISC-V: every call fails (SBI_ERR_NOT_SUPPORTED).
+#[cfg(not(target_arch = "riscv64"))]
+fn sbi_call(_eid: usize, _fid: usize, _a0: usize, _a1: usize) -> (usize, usize) {
+    (-2isize as usize, 0)
+}
+
+/// Whether the SBI below implements Keystone.  Only meaningful in S-mode
+/// (the `sbi` build): in M-mode the ecall traps back into this kernel.
+pub fn probe() -> bool {
+    let (err, val) = sbi_call(EID_BASE, FID_PROBE_EXTENSION, EID_KEYSTONE, 0);
+    err == 0 && val != 0
+}
+
+/// Page-aligned, physically contiguous memory (the kernel maps 1:1).
+struct Region {
+    ptr:    *mut u8,
+    layout: Layout,
+}
+
+unsafe impl Send for Region {}
+
+impl Region {
+    fn new(size: usize) -> Result<Region, &'static str> {
+        let layout = Layout::from_size_align(size.next_multiple_of(PAGE), PAGE).map_err(|_| "bad region size")?;
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() { return Err("out of memory for enclave"); }
+        Ok(Region { ptr, layout })
+    }
+
+    fn paddr(&self) -> usize { self.ptr as usize }
+
+    fn size(&self) -> usize { self.layout.size() }
+
+    fn bytes(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.layout.size()) }
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        crate::crypto::wipe(self.bytes());
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
+
+struct Enclave {
+    session: u32,
+    eid:     usize,
+    /// Handed to the monitor; kept alive until the enclave is destroyed.
+    _epm:    Region,
+    utm:     Region,
+    started: bool,
+}
+
+pub struct Keystone {
+    enclaves: Vec<Enclave>,
+    next:     u32,
+}
+
+impl Keystone {
+    /// The Keystone backend, if the monitor is there.
+    pub fn new() -> Option<Keystone> {
+        probe().then_some(Keystone { enclaves: Vec::new(), next: 1 })
+    }
+}
+
+fn field(image: &[u8], at: usize) -> usize {
+    u32::from_le_bytes([image[at], image[at + 1], image[at + 2], image[at + 3]]) as usize
+}
+
+impl Backend for Keystone {
+    fn name(&self) -> &'static str { "keystone" }
+
+    fn isolated(&self) -> bool { true }
+
+    fn open(&mut self, ta: &Uuid) -> Result<u32, &'static str> {
+        // Header: magic, then runtime, app and free offsets and EPM size
+        let image = crate::fs::read_file(&format!("/lib/tee/{}.ke", uuid_name(ta)))?;
+        if image.len() < 20 || &image[..4] != IMAGE_MAGIC { return Err("not a Keystone TA image"); }
+        let (runtime, user, free, epm_size) = (field(&image, 4), field(&image, 8), field(&image, 12), field(&image, 16));
+        let body = &image[20..];
+        if body.len() > epm_size || runtime >= epm_size || user >= epm_size || free > epm_size {
+            return Err("bad Keystone TA image");
+        }
+        let mut epm = Region::new(epm_size)?;
+        epm.bytes()[..body.len()].copy_from_slice(body);
+        let utm = Region::new(UTM_HEADER + SHM_SIZE)?;
+        let args = CreateArgs {
+            epm_paddr:      epm.paddr(),
+            epm_size:       epm.size(),
+            utm_paddr:      utm.paddr(),
+            utm_size:       utm.size(),
+            runtime_paddr:  epm.paddr() + runtime,
+            user_paddr:     epm.paddr() + user,
+            free_paddr:     epm.paddr() + free,
+            free_requested: epm.size() - free,
+        };
+        let mut eid = 0usize;
+        let (err, _) = sbi_call(EID_KEYSTONE, FID_CREATE, &args as *const _ as usize, &mut eid as *mut _ as usize);
+        if err != 0 { return Err("security monitor refused the enclave"); }
+        let session = self.next;
+        self.next += 1;
+        self.enclaves.push(Enclave { session, eid, _epm: epm, utm, started: false });
+        Ok(session)
+    }
+
+    fn invoke(&mut self, session: u32, cmd: u32, shm: &mut [u8], len: usize) -> Result<usize, &'static str> {
+        let e = self.enclaves.iter_mut().find(|e| e.session == session).ok_or("no such session")?;
+        let utm = e.utm.bytes();
+        utm[..4].copy_from_slice(&cmd.to_le_bytes());
+        utm[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+        utm[UTM_HEADER..UTM_HEADER + len].copy_from_slice(&shm[..len]);
+        let mut fid = if e.started { FID_RESUME } else { FID_RUN };
+        e.started = true;
+        loop {
+            let (err, _) = sbi_call(EID_KEYSTONE, fid, e.eid, 0);
+            match err {
+                ERR_INTERRUPTED    => fid = FID_RESUME,
+                ERR_EDGE_CALL_HOST => break,
+                0                  => { e.started = false; return Err("trusted app exited"); }
+                _                  => return Err("enclave failed"),
+            }
+        }
+        let utm = e.utm.bytes();
+        let call  = u32::from_le_bytes([utm[0], utm[1], utm[2], utm[3]]);
+        let reply = u32::from_le_bytes([utm[4], utm[5], utm[6], utm[7]]) as usize;
+        if call != EDGE_REPLY || reply > SHM_SIZE.min(shm.len()) { return Err("bad reply from trusted app"); }
+        shm[..reply].copy_from_slice(&utm[UTM_HEADER..UTM_HEADER + reply]);
+        crate::crypto::wipe(&mut utm[..UTM_HEADER + reply.max(len)]);
+        Ok(reply)
+    }
+
+    fn close(&mut self, session: u32) {
+        if let Some(i) = self.enclaves.iter().position(|e| e.session == session) {
+            let e = self.enclaves.remove(i);
+            sbi_call(EID_KEYSTONE, FID_DESTROY, e.eid, 0);
+        }
+    }
+}
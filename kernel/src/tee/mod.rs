@@ -0,0 +1,274 @@
+//! SurakshaOS Trusted Execution
+//! The interface between the kernel and a trusted execution environment:
+//! trusted apps (TAs) that run isolated from the kernel, reached through
+//! sessions.  A session carries commands and their data through a shared
+//! memory buffer; what the TA keeps — keys, biometric templates — never
+//! crosses it.
+//!
+//! The world switch belongs to a `Backend`.  `keystone` drives Keystone
+//! enclaves through the security monitor's SBI calls, for a kernel that
+//! runs in S-mode under one; a TrustZone port would add an SMC backend
+//! of the same shape.  On QEMU virt the kernel owns M-mode, so there is no
+//! secure world below it, and `soft` hosts the built-in TAs inside the
+//! kernel instead — the same protocol, with no isolation.
+//!
+//! Processes reach TAs two ways.  With a TrustedApp capability they open
+//! sessions of their own.  More usually they use the kernel services here
+//! — key storage and biometric matching — which hold sessions to the
+//! built-in TAs and check the caller's SecureKeys or Biometric capability
+//! before proxying each request.
+
+pub mod keystone;
+pub mod soft;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::memory::{self, SecureVec};
+use crate::process::{self, ProcessId};
+
+/// Identifies a trusted app.
+pub type Uuid = [u8; 16];
+
+/// The built-in key store TA.
+pub const KEYSTORE_TA:  Uuid = *b"suraksha-keystor";
+/// The built-in biometric matcher TA.
+pub const BIOMETRIC_TA: Uuid = *b"suraksha-biometr";
+
+/// Size of a session's shared memory: the most a request or reply holds.
+pub const SHM_SIZE: usize = 4096;
+
+/// A secure world the kernel can open sessions into.
+pub trait Backend: Send {
+    fn name(&self) -> &'static str;
+    /// Whether TAs are isolated from the kernel.
+    fn isolated(&self) -> bool;
+    fn open(&mut self, ta: &Uuid) -> Result<u32, &'static str>;
+    /// Run command `cmd` in `session` on the request in `shm[..len]`.  The
+    /// TA writes its reply into `shm`; returns the reply's length.
+    fn invoke(&mut self, session: u32, cmd: u32, shm: &mut [u8], len: usize) -> Result<usize, &'static str>;
+    fn close(&mut self, session: u32);
+}
+
+// ─── sessions ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionId(pub u32);
+
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id:    SessionId,
+    /// None for the kernel's own sessions.
+    pub owner: Option<ProcessId>,
+    pub ta:    Uuid,
+}
+
+struct Session {
+    id:      SessionId,
+    owner:   Option<ProcessId>,
+    ta:      Uuid,
+    backend: u32,
+    /// Zeroed when freed, since requests and replies pass through it.
+    shm:     SecureVec<u8>,
+}
+
+struct Tee {
+    backend:  Option<Box<dyn Backend>>,
+    sessions: Vec<Session>,
+    next_id:  u32,
+}
+
+static TEE: Mutex<Tee> = Mutex::new(Tee { backend: None, sessions: Vec::new(), next_id: 1 });
+
+/// Install the in-kernel backend.  A port with a real secure world
+/// installs its own with `install`.
+pub fn init() {
+    let mut tee = TEE.lock();
+    if tee.backend.is_none() { tee.backend = Some(Box::new(soft::Soft::new())); }
+}
+
+/// Switch to `backend`.  Every open session is closed.
+pub fn install(backend: Box<dyn Backend>) {
+    // Service sessions lock before TEE, as `service` takes them
+    let mut service = SERVICE_SESSIONS.lock();
+    *service = [None, None];
+    let mut tee = TEE.lock();
+    let Tee { backend: old, sessions, .. } = &mut *tee;
+    if let Some(old) = old.as_mut() {
+        for s in sessions.iter() { old.close(s.backend); }
+    }
+    sessions.clear();
+    crate::audit::note("tee", &format!("backend now {}", backend.name()));
+    tee.backend = Some(backend);
+}
+
+/// The backend's name, and whether it isolates TAs.
+pub fn backend() -> Option<(&'static str, bool)> {
+    TEE.lock().backend.as_ref().map(|b| (b.name(), b.isolated()))
+}
+
+fn open_as(owner: Option<ProcessId>, ta: &Uuid) -> Result<SessionId, &'static str> {
+    let mut tee = TEE.lock();
+    let backend = tee.backend.as_mut().ok_or("no trusted execution environment")?.open(ta)?;
+    let id = SessionId(tee.next_id);
+    tee.next_id += 1;
+    tee.sessions.push(Session { id, owner, ta: *ta, backend, shm: memory::secure_vec(0u8, SHM_SIZE) });
+    Ok(id)
+}
+
+fn invoke_as(owner: Option<ProcessId>, id: SessionId, cmd: u32, request: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if request.len() > SHM_SIZE { return Err("request too large"); }
+    let mut tee = TEE.lock();
+    let Tee { backend, sessions, .. } = &mut *tee;
+    let s = sessions.iter_mut().find(|s| s.id == id).ok_or("no such session")?;
+    if s.owner != owner { return Err("session belongs to another process"); }
+    s.shm[..request.len()].copy_from_slice(request);
+    let r = backend.as_mut().ok_or("no trusted execution environment")?.invoke(s.backend, cmd, &mut s.shm, request.len());
+    let reply = r.map(|len| s.shm[..len.min(SHM_SIZE)].to_vec());
+    s.shm.fill(0);
+    reply
+}
+
+fn close_as(owner: Option<ProcessId>, id: SessionId) -> Result<(), &'static str> {
+    let mut tee = TEE.lock();
+    let i = tee.sessions.iter().position(|s| s.id == id && s.owner == owner).ok_or("no such session")?;
+    let s = tee.sessions.remove(i);
+    if let Some(b) = tee.backend.as_mut() { b.close(s.backend); }
+    Ok(())
+}
+
+/// Open a session to `ta` for the caller, who must hold a TrustedApp
+/// capability with EXECUTE.
+pub fn open_session(cap: &Capability, ta: &Uuid) -> Result<SessionId, &'static str> {
+    let pid = process::current_pid();
+    capability::validate(pid, cap, CapabilityType::TrustedApp, Permissions::EXECUTE)?;
+    open_as(Some(pid), ta)
+}
+
+/// Run command `cmd` in one of the caller's sessions.
+pub fn invoke(id: SessionId, cmd: u32, request: &[u8]) -> Result<Vec<u8>, &'static str> {
+    invoke_as(Some(process::current_pid()), id, cmd, request)
+}
+
+pub fn close_session(id: SessionId) -> Result<(), &'static str> {
+    close_as(Some(process::current_pid()), id)
+}
+
+/// Close every session `pid` holds; called as it exits.
+pub fn release(pid: ProcessId) {
+    let ids: Vec<SessionId> = TEE.lock().sessions.iter().filter(|s| s.owner == Some(pid)).map(|s| s.id).collect();
+    for id in ids { let _ = close_as(Some(pid), id); }
+}
+
+pub fn sessions() -> Vec<SessionInfo> {
+    TEE.lock().sessions.iter().map(|s| SessionInfo { id: s.id, owner: s.owner, ta: s.ta }).collect()
+}
+
+// ─── kernel services ──────────────────────────────────────────────────────────
+
+/// Key store commands.  Requests name the key first: [name len, name].
+pub const KEY_STORE:  u32 = 1; // [name, key] -> []
+pub const KEY_HMAC:   u32 = 2; // [name, data] -> [HMAC-SHA256]
+pub const KEY_DELETE: u32 = 3; // [name] -> []
+
+/// Biometric commands.  Requests start with the user (u32 LE); templates
+/// and samples are feature vectors of f32 LE.
+pub const BIO_ENROLL: u32 = 1; // [user, template] -> []
+pub const BIO_MATCH:  u32 = 2; // [user, sample] -> [score f32 LE, matched]
+pub const BIO_REMOVE: u32 = 3; // [user] -> []
+
+/// The kernel's sessions to the built-in TAs, opened on first use.
+static SERVICE_SESSIONS: Mutex<[Option<SessionId>; 2]> = Mutex::new([None, None]);
+
+fn service(index: usize, ta: &Uuid, cmd: u32, request: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut sessions = SERVICE_SESSIONS.lock();
+    let id = match sessions[index] {
+        Some(id) => id,
+        None     => *sessions[index].insert(open_as(None, ta)?),
+    };
+    let r = invoke_as(None, id, cmd, request);
+    if r == Err("no such session") { sessions[index] = None; }
+    r
+}
+
+/// Keys are kept apart per app (or per process, for processes that are
+/// not apps): the caller's name is prefixed with its own.
+fn key_request(pid: ProcessId, name: &str, rest: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let owner = crate::permission::app_of(pid).unwrap_or_else(|| format!("pid{}", pid.0));
+    let full = format!("{}/{}", owner, name);
+    if full.len() > u8::MAX as usize { return Err("key name too long"); }
+    let mut req = Vec::from([full.len() as u8]);
+    req.extend_from_slice(full.as_bytes());
+    req.extend_from_slice(rest);
+    Ok(req)
+}
+
+fn authorize(cap: &Capability, ty: CapabilityType, perms: Permissions) -> Result<ProcessId, &'static str> {
+    let pid = process::current_pid();
+    capability::validate(pid, cap, ty, perms)?;
+    Ok(pid)
+}
+
+/// Store `key` under `name` in the key store.  It never comes back out.
+pub fn keystore_store(cap: &Capability, name: &str, key: &[u8]) -> Result<(), &'static str> {
+    let pid = authorize(cap, CapabilityType::SecureKeys, Permissions::WRITE)?;
+    let mut req = key_request(pid, name, key)?;
+    let r = service(0, &KEYSTORE_TA, KEY_STORE, &req).map(|_| ());
+    crate::crypto::wipe(&mut req);
+    r
+}
+
+/// HMAC-SHA256 of `data` under the stored key `name`.
+pub fn keystore_hmac(cap: &Capability, name: &str, data: &[u8]) -> Result<[u8; 32], &'static str> {
+    let pid = authorize(cap, CapabilityType::SecureKeys, Permissions::EXECUTE)?;
+    let mac = service(0, &KEYSTORE_TA, KEY_HMAC, &key_request(pid, name, data)?)?;
+    mac.as_slice().try_into().map_err(|_| "bad reply from key store")
+}
+
+pub fn keystore_delete(cap: &Capability, name: &str) -> Result<(), &'static str> {
+    let pid = authorize(cap, CapabilityType::SecureKeys, Permissions::WRITE)?;
+    service(0, &KEYSTORE_TA, KEY_DELETE, &key_request(pid, name, &[])?).map(|_| ())
+}
+
+fn bio_request(user: u32, features: &[f32]) -> Vec<u8> {
+    let mut req = Vec::from(user.to_le_bytes());
+    for f in features { req.extend_from_slice(&f.to_le_bytes()); }
+    req
+}
+
+/// Enroll `template` for `user`, replacing any earlier one.
+pub fn biometric_enroll(cap: &Capability, user: u32, template: &[f32]) -> Result<(), &'static str> {
+    authorize(cap, CapabilityType::Biometric, Permissions::WRITE)?;
+    service(1, &BIOMETRIC_TA, BIO_ENROLL, &bio_request(user, template)).map(|_| ())
+}
+
+/// Match `sample` against `user`'s template; returns the score and
+/// whether it matched.  Attempts are rate limited per user.
+pub fn biometric_match(cap: &Capability, user: u32, sample: &[f32]) -> Result<(f32, bool), &'static str> {
+    authorize(cap, CapabilityType::Biometric, Permissions::READ)?;
+    let mut score = 0.0;
+    let matched = crate::authlimit::guard(crate::authlimit::Factor::Biometric, user, || {
+        let reply = service(1, &BIOMETRIC_TA, BIO_MATCH, &bio_request(user, sample))?;
+        let [a, b, c, d, matched] = reply[..] else { return Err("bad reply from biometric matcher") };
+        score = f32::from_le_bytes([a, b, c, d]);
+        Ok(matched != 0)
+    })?;
+    Ok((score, matched))
+}
+
+pub fn biometric_remove(cap: &Capability, user: u32) -> Result<(), &'static str> {
+    authorize(cap, CapabilityType::Biometric, Permissions::WRITE)?;
+    service(1, &BIOMETRIC_TA, BIO_REMOVE, &bio_request(user, &[])).map(|_| ())
+}
+
+/// A TA's UUID for display: printable if it is, hex otherwise.
+pub fn uuid_name(ta: &Uuid) -> String {
+    match core::str::from_utf8(ta) {
+        Ok(s) if s.bytes().all(|b| b.is_ascii_graphic()) => String::from(s),
+        _ => ta.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
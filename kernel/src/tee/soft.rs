@@ -0,0 +1,140 @@
+//! In-Kernel Trusted Apps
+//! The built-in TAs, run inside the kernel for machines with no secure
+//! world.  They keep to the TA protocol — everything in and out passes
+//! through the session's shared memory, and secrets stay on this side of
+//! it, sealed in the kernel keyring — but nothing isolates them from the
+//! kernel, so `isolated` is false and the device's attestation says so.
+//!
+//! The matcher does not limit attempts itself: `biometric_match` does,
+//! through `authlimit`, whatever the backend.
+
+use alloc::format;
+use alloc::vec::Vec;
+
+use super::*;
+use crate::crypto::{self, sha2};
+use crate::keyring::{self, KeyId};
+
+/// Cosine similarity a sample needs to match its template.
+pub const MATCH_THRESHOLD: f32 = 0.85;
+
+struct Template {
+    user: u32,
+    /// Sealed in the keyring.
+    key:  KeyId,
+}
+
+pub struct Soft {
+    /// (backend session, TA) of each open session.
+    sessions:  Vec<(u32, Uuid)>,
+    next:      u32,
+    /// (name, keyring id) of each stored key.
+    keys:      Vec<(Vec<u8>, KeyId)>,
+    templates: Vec<Template>,
+}
+
+impl Soft {
+    pub fn new() -> Soft {
+        Soft { sessions: Vec::new(), next: 1, keys: Vec::new(), templates: Vec::new() }
+    }
+
+    fn keystore(&mut self, cmd: u32, req: &[u8], shm: &mut [u8]) -> Result<usize, &'static str> {
+        let (&n, rest) = req.split_first().ok_or("bad request")?;
+        if rest.len() < n as usize { return Err("bad request"); }
+        let (name, rest) = rest.split_at(n as usize);
+        let stored = self.keys.iter().position(|(k, _)| k == name);
+        match cmd {
+            KEY_STORE => {
+                if rest.is_empty() { return Err("empty key"); }
+                if let Some(i) = stored { keyring::remove_kernel(self.keys.remove(i).1); }
+                let id = keyring::add_kernel(&format!("tee key {}", String::from_utf8_lossy(name)), rest)?;
+                self.keys.push((name.to_vec(), id));
+                Ok(0)
+            }
+            KEY_HMAC => {
+                let key = keyring::open(self.keys[stored.ok_or("no such key")?].1, None)?;
+                let mac = sha2::hmac_sha256(&key, &[rest]);
+                shm[..mac.len()].copy_from_slice(&mac);
+                Ok(mac.len())
+            }
+            KEY_DELETE => {
+                keyring::remove_kernel(self.keys.remove(stored.ok_or("no such key")?).1);
+                Ok(0)
+            }
+            _ => Err("unknown command"),
+        }
+    }
+
+    fn biometric(&mut self, cmd: u32, req: &[u8], shm: &mut [u8]) -> Result<usize, &'static str> {
+        if req.len() < 4 || req.len() % 4 != 0 { return Err("bad request"); }
+        let user = u32::from_le_bytes([req[0], req[1], req[2], req[3]]);
+        let features = &req[4..];
+        let stored = self.templates.iter().position(|t| t.user == user);
+        match cmd {
+            BIO_ENROLL => {
+                if features.is_empty() { return Err("empty template"); }
+                if let Some(i) = stored { keyring::remove_kernel(self.templates.remove(i).key); }
+                let key = keyring::add_kernel(&format!("tee biometric {}", user), features)?;
+                self.templates.push(Template { user, key });
+                Ok(0)
+            }
+            BIO_MATCH => {
+                let template = keyring::open(self.templates[stored.ok_or("user not enrolled")?].key, None)?;
+                if template.len() != features.len() { return Err("sample does not fit the template"); }
+                let score = cosine(&template, features);
+                shm[..4].copy_from_slice(&score.to_le_bytes());
+                shm[4] = (score >= MATCH_THRESHOLD) as u8;
+                Ok(5)
+            }
+            BIO_REMOVE => {
+                keyring::remove_kernel(self.templates.remove(stored.ok_or("user not enrolled")?).key);
+                Ok(0)
+            }
+            _ => Err("unknown command"),
+        }
+    }
+}
+
+impl Default for Soft {
+    fn default() -> Self { Self::new() }
+}
+
+/// Cosine similarity of two f32 LE vectors of the same length.
+fn cosine(a: &[u8], b: &[u8]) -> f32 {
+    let f = |c: &[u8]| f32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+    let (mut dot, mut na, mut nb) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.chunks(4).map(f).zip(b.chunks(4).map(f)) {
+        dot += x * y;
+        na  += x * x;
+        nb  += y * y;
+    }
+    if na == 0.0 || nb == 0.0 { return 0.0; }
+    dot / (libm::sqrtf(na) * libm::sqrtf(nb))
+}
+
+impl Backend for Soft {
+    fn name(&self) -> &'static str { "kernel" }
+
+    fn isolated(&self) -> bool { false }
+
+    fn open(&mut self, ta: &Uuid) -> Result<u32, &'static str> {
+        if *ta != KEYSTORE_TA && *ta != BIOMETRIC_TA { return Err("no such trusted app"); }
+        let id = self.next;
+        self.next += 1;
+        self.sessions.push((id, *ta));
+        Ok(id)
+    }
+
+    fn invoke(&mut self, session: u32, cmd: u32, shm: &mut [u8], len: usize) -> Result<usize, &'static str> {
+        let ta = self.sessions.iter().find(|(s, _)| *s == session).map(|(_, ta)| *ta).ok_or("no such session")?;
+        // The TA works on its own copy, as it would across a world switch
+        let mut req = shm[..len].to_vec();
+        let r = if ta == KEYSTORE_TA { self.keystore(cmd, &req, shm) } else { self.biometric(cmd, &req, shm) };
+        crypto::wipe(&mut req);
+        r
+    }
+
+    fn close(&mut self, session: u32) {
+        self.sessions.retain(|(s, _)| *s != session);
+    }
+}
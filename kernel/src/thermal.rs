@@ -0,0 +1,279 @@
+//! SurakshaOS Thermal Management
+//! Thermal zones, each backed by a sensor driver and a list of trip
+//! points.  Zones are polled from the timer tick (or immediately when a
+//! sensor raises an interrupt via `notify()`); crossing a trip applies its
+//! throttling action to the CPU, GPU or charger.  Subsystems that have
+//! their own ways to shed heat register with `on_change()` and are told
+//! (as deferred work) whenever the limits or severity change.
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::println;
+
+// ─── sensors ──────────────────────────────────────────────────────────────────
+
+pub trait ThermalSensor: Send {
+    /// Current temperature in millidegrees Celsius.
+    fn read_millicelsius(&mut self) -> Result<i32, &'static str>;
+}
+
+/// Sensor exposing a signed millidegree reading in a 32-bit MMIO register.
+pub struct MmioSensor {
+    pub addr: usize,
+}
+
+impl ThermalSensor for MmioSensor {
+    fn read_millicelsius(&mut self) -> Result<i32, &'static str> {
+        Ok(unsafe { core::ptr::read_volatile(self.addr as *const i32) })
+    }
+}
+
+/// First-order thermal model of the SoC for boards (and QEMU) without a
+/// die sensor: temperature relaxes towards ambient plus a rise
+/// proportional to V²·f·utilisation.
+pub struct EstimatedCpuSensor {
+    temp_mc:    i32,
+    last_ms:    u64,
+}
+
+impl EstimatedCpuSensor {
+    const AMBIENT_MC: i32 = 30_000;
+    /// Steady-state rise at max OPP and 100% load.
+    const MAX_RISE_MC: i64 = 55_000;
+    /// Thermal time constant.
+    const TAU_MS: i64 = 20_000;
+
+    pub const fn new() -> Self {
+        EstimatedCpuSensor { temp_mc: Self::AMBIENT_MC, last_ms: 0 }
+    }
+}
+
+impl Default for EstimatedCpuSensor {
+    fn default() -> Self { Self::new() }
+}
+
+impl ThermalSensor for EstimatedCpuSensor {
+    fn read_millicelsius(&mut self) -> Result<i32, &'static str> {
+        let now = crate::arch::uptime_millis();
+        let dt  = now.saturating_sub(self.last_ms).min(Self::TAU_MS as u64) as i64;
+        self.last_ms = now;
+
+        let opp  = crate::power::CPU_FREQ_TABLE[crate::power::cpu_level()];
+        let top  = crate::power::CPU_FREQ_TABLE[crate::power::CPU_FREQ_TABLE.len() - 1];
+        let pwr  = (opp.voltage_mv as i64).pow(2) * opp.freq_mhz as i64;
+        let pmax = (top.voltage_mv as i64).pow(2) * top.freq_mhz as i64;
+        let util = crate::power::cpu_utilization() as i64;
+        let target = Self::AMBIENT_MC as i64 + Self::MAX_RISE_MC * pwr / pmax * util / 100;
+
+        self.temp_mc += ((target - self.temp_mc as i64) * dt / Self::TAU_MS) as i32;
+        Ok(self.temp_mc)
+    }
+}
+
+// ─── trip points ──────────────────────────────────────────────────────────────
+
+/// Ordered by severity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TripKind {
+    /// Throttle to bring the temperature back down.
+    Passive,
+    /// Getting dangerous; throttle hard and warn.
+    Hot,
+    /// Power off immediately to protect the hardware.
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleAction {
+    /// Cap the CPU at this operating-point index.
+    CapCpu(usize),
+    /// Cap GPU clock at this percentage of maximum.
+    CapGpu(u8),
+    /// Limit battery charge current (mA).
+    LimitCharging(u32),
+    Shutdown,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TripPoint {
+    pub temp_mc:       i32,
+    /// A tripped point clears only once the temperature falls this far below it.
+    pub hysteresis_mc: i32,
+    pub kind:          TripKind,
+    pub action:        ThrottleAction,
+}
+
+/// Limits currently imposed by all active trips (most restrictive wins).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThermalLimits {
+    pub cpu_max_level:  usize,
+    pub gpu_max_pct:    u8,
+    pub charge_max_ma:  Option<u32>,
+}
+
+impl ThermalLimits {
+    const NONE: ThermalLimits = ThermalLimits {
+        cpu_max_level: usize::MAX,
+        gpu_max_pct:   100,
+        charge_max_ma: None,
+    };
+}
+
+// ─── zones ────────────────────────────────────────────────────────────────────
+
+pub struct ThermalZone {
+    pub name:    String,
+    sensor:      Box<dyn ThermalSensor>,
+    trips:       Vec<TripPoint>,
+    /// Parallel to `trips`: whether each trip is currently active.
+    tripped:     Vec<bool>,
+    polling_ms:  u64,
+    last_poll:   u64,
+    pub temp_mc: i32,
+}
+
+struct Thermal {
+    zones:    Vec<ThermalZone>,
+    limits:   ThermalLimits,
+    /// Most severe active trip.
+    severity: Option<TripKind>,
+}
+
+static THERMAL: Mutex<Thermal> = Mutex::new(Thermal { zones: Vec::new(), limits: ThermalLimits::NONE, severity: None });
+/// Called (as deferred work) when the limits or severity change.
+static LISTENERS: Mutex<Vec<fn()>> = Mutex::new(Vec::new());
+
+/// Default trips for the CPU zone.
+pub const CPU_TRIPS: &[TripPoint] = &[
+    TripPoint { temp_mc: 70_000,  hysteresis_mc: 3_000, kind: TripKind::Passive,  action: ThrottleAction::CapCpu(3) },
+    TripPoint { temp_mc: 70_000,  hysteresis_mc: 3_000, kind: TripKind::Passive,  action: ThrottleAction::LimitCharging(500) },
+    TripPoint { temp_mc: 80_000,  hysteresis_mc: 3_000, kind: TripKind::Hot,      action: ThrottleAction::CapCpu(1) },
+    TripPoint { temp_mc: 80_000,  hysteresis_mc: 3_000, kind: TripKind::Hot,      action: ThrottleAction::CapGpu(50) },
+    TripPoint { temp_mc: 80_000,  hysteresis_mc: 3_000, kind: TripKind::Hot,      action: ThrottleAction::LimitCharging(0) },
+    TripPoint { temp_mc: 100_000, hysteresis_mc: 0,     kind: TripKind::Critical, action: ThrottleAction::Shutdown },
+];
+
+/// Register the built-in zones.
+pub fn init() {
+    register_zone("cpu", Box::new(EstimatedCpuSensor::new()), CPU_TRIPS, 1000);
+}
+
+pub fn register_zone(name: &str, sensor: Box<dyn ThermalSensor>, trips: &[TripPoint], polling_ms: u64) {
+    THERMAL.lock().zones.push(ThermalZone {
+        name: name.into(),
+        sensor,
+        trips: trips.to_vec(),
+        tripped: alloc::vec![false; trips.len()],
+        polling_ms,
+        last_poll: 0,
+        temp_mc: 0,
+    });
+}
+
+/// Replace the trip points of a zone.
+pub fn set_trips(zone: &str, trips: &[TripPoint]) -> Result<(), &'static str> {
+    let mut t = THERMAL.lock();
+    let z = t.zones.iter_mut().find(|z| z.name == zone).ok_or("no such thermal zone")?;
+    z.trips   = trips.to_vec();
+    z.tripped = alloc::vec![false; trips.len()];
+    Ok(())
+}
+
+impl Thermal {
+    /// Read due zones, update trip state and recompute limits.
+    fn evaluate(&mut self, force: bool) {
+        let now = crate::arch::uptime_millis();
+        let mut critical = None;
+        for z in self.zones.iter_mut() {
+            if !force && now.saturating_sub(z.last_poll) < z.polling_ms { continue; }
+            z.last_poll = now;
+            let Ok(t) = z.sensor.read_millicelsius() else { continue };
+            z.temp_mc = t;
+            for (trip, on) in z.trips.iter().zip(z.tripped.iter_mut()) {
+                let was = *on;
+                *on = if was { t > trip.temp_mc - trip.hysteresis_mc } else { t >= trip.temp_mc };
+                if *on && !was {
+                    println!("  [thermal] {} reached {}.{} C ({:?} trip)", z.name, t / 1000, (t % 1000).abs() / 100, trip.kind);
+                }
+                if *on && trip.kind == TripKind::Critical {
+                    critical = Some(z.name.clone());
+                }
+            }
+        }
+
+        let mut limits = ThermalLimits::NONE;
+        let mut severity = None;
+        for z in &self.zones {
+            for (trip, _) in z.trips.iter().zip(z.tripped.iter()).filter(|(_, on)| **on) {
+                severity = severity.max(Some(trip.kind));
+                match trip.action {
+                    ThrottleAction::CapCpu(l)        => limits.cpu_max_level = limits.cpu_max_level.min(l),
+                    ThrottleAction::CapGpu(p)        => limits.gpu_max_pct   = limits.gpu_max_pct.min(p),
+                    ThrottleAction::LimitCharging(m) => limits.charge_max_ma = Some(limits.charge_max_ma.map_or(m, |c| c.min(m))),
+                    ThrottleAction::Shutdown         => {}
+                }
+            }
+        }
+        if limits != self.limits || severity != self.severity {
+            if let Some(listeners) = LISTENERS.try_lock() {
+                for &f in listeners.iter() { crate::process::defer(f); }
+            }
+        }
+        self.limits   = limits;
+        self.severity = severity;
+        crate::power::set_max_level(limits.cpu_max_level);
+
+        if let Some(zone) = critical {
+            println!("  [thermal] CRITICAL temperature in zone '{}' — powering off", zone);
+            crate::arch::power_off();
+        }
+    }
+}
+
+/// Periodic poll, called from the timer tick.
+pub fn thermal_tick() {
+    if let Some(mut t) = THERMAL.try_lock() {
+        t.evaluate(false);
+    }
+}
+
+/// Immediate re-evaluation, for sensors that raise an interrupt when they
+/// cross a programmed threshold.
+pub fn notify() {
+    if let Some(mut t) = THERMAL.try_lock() {
+        t.evaluate(true);
+    }
+}
+
+pub fn limits() -> ThermalLimits {
+    THERMAL.lock().limits
+}
+
+/// Most severe trip active anywhere, if any.
+pub fn severity() -> Option<TripKind> {
+    THERMAL.lock().severity
+}
+
+/// Have `f` run whenever the thermal limits or severity change.
+pub fn on_change(f: fn()) {
+    let mut listeners = LISTENERS.lock();
+    if !listeners.iter().any(|&g| core::ptr::fn_addr_eq(g, f)) { listeners.push(f); }
+}
+
+/// Temperature of `zone` at the last poll, in millidegrees Celsius.
+pub fn zone_temperature(zone: &str) -> Option<i32> {
+    THERMAL.lock().zones.iter().find(|z| z.name == zone).map(|z| z.temp_mc)
+}
+
+/// (name, millidegrees) for every zone.
+pub fn zones() -> Vec<(String, i32)> {
+    THERMAL.lock().zones.iter().map(|z| (z.name.clone(), z.temp_mc)).collect()
+}
+
+/// CPU temperature in whole degrees Celsius.
+pub fn get_cpu_temperature() -> Option<i32> {
+    zone_temperature("cpu").map(|t| t / 1000)
+}
@@ -0,0 +1,338 @@
+//! The compositor proper: layers stacked by z over a background, the
+//! parts of the screen that changed, and composing just those parts into
+//! the frame the display shows.
+//!
+//! Pixels are 32-bit premultiplied ARGB (alpha in the top byte).  A layer
+//! is blended source-over what is below it, its own alpha scaled by the
+//! layer's opacity; a layer marked opaque and fully covering a damaged
+//! area hides everything below it, which is then not drawn at all.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::process::ProcessId;
+
+/// More damaged rectangles than this and they are merged into one.
+const MAX_DAMAGE_RECTS: usize = 8;
+
+/// Shown where no layer is.
+pub const BACKGROUND: u32 = 0xff00_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Rect {
+    pub const fn new(x: i32, y: i32, w: u32, h: u32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.w == 0 || self.h == 0
+    }
+
+    pub fn right(&self) -> i32 { self.x.saturating_add(self.w as i32) }
+
+    pub fn bottom(&self) -> i32 { self.y.saturating_add(self.h as i32) }
+
+    fn area(&self) -> u64 {
+        self.w as u64 * self.h as u64
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    pub fn covers(&self, other: &Rect) -> bool {
+        other.x >= self.x && other.y >= self.y && other.right() <= self.right() && other.bottom() <= self.bottom()
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let (r, b) = (self.right().min(other.right()), self.bottom().min(other.bottom()));
+        (r > x && b > y).then(|| Rect::new(x, y, (r - x) as u32, (b - y) as u32))
+    }
+
+    /// The smallest rectangle holding both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() { return *other; }
+        if other.is_empty() { return *self; }
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let (r, b) = (self.right().max(other.right()), self.bottom().max(other.bottom()));
+        Rect::new(x, y, (r - x) as u32, (b - y) as u32)
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32) -> Rect {
+        Rect::new(self.x + dx, self.y + dy, self.w, self.h)
+    }
+}
+
+// ─── damage ───────────────────────────────────────────────────────────────────
+
+/// The parts of the screen to compose next frame.
+#[derive(Debug, Default)]
+struct Damage {
+    rects: Vec<Rect>,
+}
+
+impl Damage {
+    /// Add `r`, merging it with any rectangle it overlaps when their union
+    /// is barely bigger than the two, and everything into one past
+    /// MAX_DAMAGE_RECTS.
+    fn add(&mut self, mut r: Rect) {
+        if r.is_empty() { return; }
+        while let Some(i) = self.rects.iter().position(|e| {
+            let u = e.union(&r);
+            e.covers(&r) || r.covers(e) || (e.intersect(&r).is_some() && u.area() <= e.area() + r.area())
+        }) {
+            r = r.union(&self.rects.swap_remove(i));
+        }
+        self.rects.push(r);
+        if self.rects.len() > MAX_DAMAGE_RECTS {
+            let all = self.rects.iter().fold(Rect::default(), |a, r| a.union(r));
+            self.rects.clear();
+            self.rects.push(all);
+        }
+    }
+}
+
+// ─── layers ───────────────────────────────────────────────────────────────────
+
+pub type LayerId = u32;
+
+pub struct Layer {
+    pub id:      LayerId,
+    pub owner:   ProcessId,
+    /// Where it is on the screen, and its size.
+    pub rect:    Rect,
+    /// Higher is nearer the viewer.
+    pub z:       i32,
+    /// 0 (invisible) to 255; scales the layer's own alpha.
+    pub opacity: u8,
+    pub visible: bool,
+    /// Every pixel is fully opaque, so what is under it need not be drawn.
+    pub opaque:  bool,
+    /// rect.w × rect.h, row by row.
+    pixels:      Vec<u32>,
+}
+
+impl Layer {
+    pub fn new(id: LayerId, owner: ProcessId, rect: Rect, z: i32) -> Layer {
+        Layer { id, owner, rect, z, opacity: 255, visible: true, opaque: false, pixels: vec![0; rect.area() as usize] }
+    }
+
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    fn hides(&self, r: &Rect) -> bool {
+        self.visible && self.opaque && self.opacity == 255 && self.rect.covers(r)
+    }
+}
+
+// ─── blending ─────────────────────────────────────────────────────────────────
+
+/// Each channel of `p` times `k`/255, rounded.
+pub(super) fn scale(p: u32, k: u32) -> u32 {
+    let rb = (p & 0x00ff_00ff) * k + 0x0080_0080;
+    let rb = ((rb + ((rb >> 8) & 0x00ff_00ff)) >> 8) & 0x00ff_00ff;
+    let ag = ((p >> 8) & 0x00ff_00ff) * k + 0x0080_0080;
+    let ag = (ag + ((ag >> 8) & 0x00ff_00ff)) & 0xff00_ff00;
+    rb | ag
+}
+
+/// `src` over `dst`, both premultiplied, `src` faded by `opacity`.
+pub(super) fn blend(dst: u32, src: u32, opacity: u32) -> u32 {
+    let src = if opacity == 255 { src } else { scale(src, opacity) };
+    match src >> 24 {
+        255 => src,
+        0 => dst,
+        a => src + scale(dst, 255 - a),
+    }
+}
+
+// ─── compositor ───────────────────────────────────────────────────────────────
+
+pub struct Compositor {
+    pub width:  u32,
+    pub height: u32,
+    /// What the display shows, row by row.
+    frame:      Vec<u32>,
+    /// Bottom to top.
+    layers:     Vec<Layer>,
+    damage:     Damage,
+}
+
+impl Compositor {
+    /// A compositor for a `width` × `height` screen, all of it damaged.
+    pub fn new(width: u32, height: u32) -> Compositor {
+        let mut c = Compositor { width, height, frame: vec![BACKGROUND; (width * height) as usize], layers: Vec::new(), damage: Damage::default() };
+        c.damage(Rect::new(0, 0, width, height));
+        c
+    }
+
+    pub fn screen(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    pub fn frame(&self) -> &[u32] {
+        &self.frame
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    pub fn layer(&self, id: LayerId) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.id == id)
+    }
+
+    fn layer_mut(&mut self, id: LayerId) -> Result<&mut Layer, &'static str> {
+        self.layers.iter_mut().find(|l| l.id == id).ok_or("no such window")
+    }
+
+    /// The topmost visible layer under screen point (`x`, `y`).
+    pub fn layer_at(&self, x: i32, y: i32) -> Option<&Layer> {
+        self.layers.iter().rev().find(|l| l.visible && l.opacity != 0 && l.rect.contains(x, y))
+    }
+
+    /// Mark screen area `r` to be composed next frame.
+    pub fn damage(&mut self, r: Rect) {
+        if let Some(r) = r.intersect(&self.screen()) { self.damage.add(r); }
+    }
+
+    pub fn has_damage(&self) -> bool {
+        !self.damage.rects.is_empty()
+    }
+
+    /// Stack `layer` above every layer of its z or lower.
+    pub fn add(&mut self, layer: Layer) {
+        let at = self.layers.iter().position(|l| l.z > layer.z).unwrap_or(self.layers.len());
+        self.damage(layer.rect);
+        self.layers.insert(at, layer);
+    }
+
+    pub fn remove(&mut self, id: LayerId) -> Option<Layer> {
+        let i = self.layers.iter().position(|l| l.id == id)?;
+        let layer = self.layers.remove(i);
+        if layer.visible { self.damage(layer.rect); }
+        Some(layer)
+    }
+
+    /// Move or resize a layer.  Resizing clears it.
+    pub fn set_rect(&mut self, id: LayerId, rect: Rect) -> Result<(), &'static str> {
+        let l = self.layer_mut(id)?;
+        let old = l.rect;
+        if (rect.w, rect.h) != (old.w, old.h) { l.pixels = vec![0; rect.area() as usize]; }
+        l.rect = rect;
+        self.damage(old);
+        self.damage(rect);
+        Ok(())
+    }
+
+    /// Restack a layer at `z`, above the others there.
+    pub fn set_z(&mut self, id: LayerId, z: i32) -> Result<(), &'static str> {
+        let i = self.layers.iter().position(|l| l.id == id).ok_or("no such window")?;
+        let mut layer = self.layers.remove(i);
+        layer.z = z;
+        self.add(layer);
+        Ok(())
+    }
+
+    pub fn set_opacity(&mut self, id: LayerId, opacity: u8, opaque: bool) -> Result<(), &'static str> {
+        let l = self.layer_mut(id)?;
+        (l.opacity, l.opaque) = (opacity, opaque);
+        let r = l.rect;
+        self.damage(r);
+        Ok(())
+    }
+
+    pub fn set_visible(&mut self, id: LayerId, visible: bool) -> Result<(), &'static str> {
+        let l = self.layer_mut(id)?;
+        if l.visible == visible { return Ok(()); }
+        l.visible = visible;
+        let r = l.rect;
+        self.damage(r);
+        Ok(())
+    }
+
+    /// Copy `pixels` (`area.w` wide) into `area` of a layer, in the
+    /// layer's own coordinates, and damage what changed.
+    pub fn write(&mut self, id: LayerId, area: Rect, pixels: &[u32]) -> Result<(), &'static str> {
+        if pixels.len() < area.area() as usize { return Err("too few pixels for the area"); }
+        let l = self.layer_mut(id)?;
+        let bounds = Rect::new(0, 0, l.rect.w, l.rect.h);
+        let Some(clip) = area.intersect(&bounds) else { return Ok(()) };
+        for row in 0..clip.h as usize {
+            let sy = (clip.y - area.y) as usize + row;
+            let sx = (clip.x - area.x) as usize;
+            let src = &pixels[sy * area.w as usize + sx..][..clip.w as usize];
+            let dst = (clip.y as usize + row) * l.rect.w as usize + clip.x as usize;
+            l.pixels[dst..dst + clip.w as usize].copy_from_slice(src);
+        }
+        let r = clip.offset(l.rect.x, l.rect.y);
+        if l.visible { self.damage(r); }
+        Ok(())
+    }
+
+    /// Fill `area` of a layer, in its own coordinates, with `color`.
+    pub fn fill(&mut self, id: LayerId, area: Rect, color: u32) -> Result<(), &'static str> {
+        let l = self.layer_mut(id)?;
+        let Some(clip) = area.intersect(&Rect::new(0, 0, l.rect.w, l.rect.h)) else { return Ok(()) };
+        for row in clip.y as usize..clip.bottom() as usize {
+            let at = row * l.rect.w as usize + clip.x as usize;
+            l.pixels[at..at + clip.w as usize].fill(color);
+        }
+        let r = clip.offset(l.rect.x, l.rect.y);
+        if l.visible { self.damage(r); }
+        Ok(())
+    }
+
+    /// Let `f` draw into a layer's pixels (its width a row, then its
+    /// width and height), and damage `area`, in the layer's coordinates.
+    pub fn draw(&mut self, id: LayerId, area: Rect, f: impl FnOnce(&mut [u32], u32, u32)) -> Result<(), &'static str> {
+        let l = self.layer_mut(id)?;
+        f(&mut l.pixels, l.rect.w, l.rect.h);
+        let Some(clip) = area.intersect(&Rect::new(0, 0, l.rect.w, l.rect.h)) else { return Ok(()) };
+        let r = clip.offset(l.rect.x, l.rect.y);
+        if l.visible { self.damage(r); }
+        Ok(())
+    }
+
+    /// Compose every damaged area into the frame and return those areas,
+    /// for the display to update.
+    pub fn compose(&mut self) -> Vec<Rect> {
+        let damage = core::mem::take(&mut self.damage.rects);
+        for r in &damage {
+            self.compose_rect(r);
+        }
+        damage
+    }
+
+    fn compose_rect(&mut self, r: &Rect) {
+        let stride = self.width as usize;
+        // Start at the topmost layer hiding the area; else at the background
+        let start = self.layers.iter().rposition(|l| l.hides(r));
+        if start.is_none() {
+            for y in r.y as usize..r.bottom() as usize {
+                self.frame[y * stride + r.x as usize..][..r.w as usize].fill(BACKGROUND);
+            }
+        }
+        for l in &self.layers[start.unwrap_or(0)..] {
+            if !l.visible || l.opacity == 0 { continue; }
+            let Some(part) = l.rect.intersect(r) else { continue };
+            let opacity = l.opacity as u32;
+            for y in part.y..part.bottom() {
+                let src = &l.pixels[((y - l.rect.y) as usize * l.rect.w as usize + (part.x - l.rect.x) as usize)..][..part.w as usize];
+                let dst = &mut self.frame[y as usize * stride + part.x as usize..][..part.w as usize];
+                for (d, &s) in dst.iter_mut().zip(src) {
+                    *d = blend(*d, s, opacity);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! UI elements: the tree of a window's parts that input is routed to.
+//! An app describes its window as elements — each a rectangle in the
+//! window with the input it takes — and the router hit-tests touches
+//! against the tree, moves keyboard focus along it, and hands each event
+//! to the deepest element that takes it, then up the tree to the first
+//! handler that wants it.
+
+use alloc::vec::Vec;
+
+use super::input::UiEvent;
+use super::Rect;
+
+pub type ElementId = u32;
+
+/// Called with an event for its element or one inside it; true if it
+/// dealt with it, so it goes no further.
+pub type Handler = fn(&UiEvent) -> bool;
+
+/// Largest tree a window may have.
+pub const MAX_ELEMENTS: usize = 1024;
+
+/// The input an element takes; what it does not take goes to its parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Accepts(pub u8);
+
+impl Accepts {
+    pub const NONE:  Accepts = Accepts(0);
+    /// Presses, taps and long presses.
+    pub const TOUCH: Accepts = Accepts(1 << 0);
+    /// Pans and swipes.
+    pub const DRAG:  Accepts = Accepts(1 << 1);
+    pub const PINCH: Accepts = Accepts(1 << 2);
+    /// Keys, when it has the focus.
+    pub const KEYS:  Accepts = Accepts(1 << 3);
+    pub const ALL:   Accepts = Accepts(0x0f);
+
+    pub const fn contains(self, other: Accepts) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for Accepts {
+    type Output = Accepts;
+    fn bitor(self, rhs: Accepts) -> Accepts { Accepts(self.0 | rhs.0) }
+}
+
+#[derive(Debug, Clone)]
+pub struct UiElement {
+    pub id:        ElementId,
+    /// In its window's coordinates.
+    pub rect:      Rect,
+    pub accepts:   Accepts,
+    /// Takes keyboard focus: from a tap, or in tree order from Tab.
+    pub focusable: bool,
+    /// A disabled element and all inside it take nothing.
+    pub enabled:   bool,
+    pub handler:   Option<Handler>,
+    /// Later children are over earlier ones.
+    pub children:  Vec<UiElement>,
+}
+
+impl UiElement {
+    pub fn new(id: ElementId, rect: Rect) -> UiElement {
+        UiElement { id, rect, accepts: Accepts::NONE, focusable: false, enabled: true, handler: None, children: Vec::new() }
+    }
+
+    pub fn accepting(mut self, accepts: Accepts) -> UiElement {
+        self.accepts = accepts;
+        self
+    }
+
+    pub fn focusable(mut self) -> UiElement {
+        self.focusable = true;
+        self
+    }
+
+    pub fn on_event(mut self, handler: Handler) -> UiElement {
+        self.handler = Some(handler);
+        self
+    }
+
+    pub fn child(mut self, child: UiElement) -> UiElement {
+        self.children.push(child);
+        self
+    }
+
+    pub fn count(&self) -> usize {
+        1 + self.children.iter().map(UiElement::count).sum::<usize>()
+    }
+
+    /// The elements at (`x`, `y`), root first, down to the topmost
+    /// deepest one; empty if the point is outside the root.
+    pub fn hit_path(&self, x: i32, y: i32) -> Vec<&UiElement> {
+        let mut path = Vec::new();
+        let mut e = self;
+        if !e.enabled || !e.rect.contains(x, y) { return path; }
+        loop {
+            path.push(e);
+            match e.children.iter().rev().find(|c| c.enabled && c.rect.contains(x, y)) {
+                Some(c) => e = c,
+                None => return path,
+            }
+        }
+    }
+
+    /// The elements from the root down to `id`, if it is in the tree and
+    /// enabled.
+    pub fn path_to(&self, id: ElementId) -> Option<Vec<&UiElement>> {
+        if !self.enabled { return None; }
+        if self.id == id { return Some(alloc::vec![self]); }
+        self.children.iter().find_map(|c| c.path_to(id)).map(|mut p| {
+            p.insert(0, self);
+            p
+        })
+    }
+
+    /// The focusable elements, in tree order.
+    pub fn focus_order(&self) -> Vec<ElementId> {
+        let mut out = Vec::new();
+        self.collect_focusable(&mut out);
+        out
+    }
+
+    fn collect_focusable(&self, out: &mut Vec<ElementId>) {
+        if !self.enabled { return; }
+        if self.focusable { out.push(self.id); }
+        for c in &self.children { c.collect_focusable(out); }
+    }
+}
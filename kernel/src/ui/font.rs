@@ -0,0 +1,586 @@
+//! TrueType fonts: the character map, metrics, glyph outlines (simple
+//! and composite) and the OpenType GSUB substitutions shaping applies.
+//!
+//! Only TrueType outlines are read; a CFF-flavoured OpenType font
+//! ("OTTO") is refused.  GSUB lookups of types 1 (single), 2 (multiple),
+//! 4 (ligature), 5 and 6 (contextual and chained contextual, all three
+//! formats) and 7 (extension) are applied; type 3 (alternate) and 8
+//! (reverse chaining) are not, nor are lookup flags or GPOS, so marks
+//! sit where the font's advance-less glyph design puts them.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Nested contextual lookups go no deeper than this.
+const MAX_NESTING: u32 = 4;
+/// Composite glyphs nest no deeper than this.
+const MAX_COMPONENT_DEPTH: u32 = 8;
+
+fn u16_at(b: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(b.get(i..i + 2)?.try_into().ok()?))
+}
+
+fn i16_at(b: &[u8], i: usize) -> Option<i16> {
+    u16_at(b, i).map(|v| v as i16)
+}
+
+fn u32_at(b: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(b.get(i..i + 4)?.try_into().ok()?))
+}
+
+pub fn tag(s: &[u8; 4]) -> u32 {
+    u32::from_be_bytes(*s)
+}
+
+/// A point of an outline, in font units.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// A piece of an outline: a line, or a quadratic Bézier through a
+/// control point.
+#[derive(Debug, Clone, Copy)]
+pub enum Segment {
+    Line(Point, Point),
+    Quad(Point, Point, Point),
+}
+
+#[derive(Clone)]
+pub struct Font {
+    data:            Arc<[u8]>,
+    pub units_per_em: u16,
+    pub ascent:      i16,
+    pub descent:     i16,
+    pub line_gap:    i16,
+    pub num_glyphs:  u16,
+    num_hmetrics:    u16,
+    loca_long:       bool,
+    cmap:            usize,
+    loca:            usize,
+    glyf:            usize,
+    hmtx:            usize,
+    gsub:            Option<usize>,
+}
+
+impl Font {
+    pub fn parse(data: Arc<[u8]>) -> Result<Font, &'static str> {
+        let bad = "malformed font";
+        let d = &data[..];
+        match u32_at(d, 0).ok_or(bad)? {
+            0x0001_0000 | 0x7472_7565 => {}
+            0x4f54_544f => return Err("CFF outlines are not supported"),
+            _ => return Err("not a TrueType font"),
+        }
+        let count = u16_at(d, 4).ok_or(bad)? as usize;
+        let table = |t: &[u8; 4]| -> Option<usize> {
+            (0..count).map(|i| 12 + 16 * i).find(|&r| u32_at(d, r) == Some(tag(t)))
+                .and_then(|r| u32_at(d, r + 8)).map(|o| o as usize).filter(|&o| o < d.len())
+        };
+        let head = table(b"head").ok_or("font has no head table")?;
+        let hhea = table(b"hhea").ok_or("font has no hhea table")?;
+        let maxp = table(b"maxp").ok_or("font has no maxp table")?;
+        let cmap = table(b"cmap").ok_or("font has no cmap table")?;
+        let units_per_em = u16_at(d, head + 18).filter(|&u| u >= 16).ok_or(bad)?;
+        let mut font = Font {
+            units_per_em,
+            ascent:       i16_at(d, hhea + 4).ok_or(bad)?,
+            descent:      i16_at(d, hhea + 6).ok_or(bad)?,
+            line_gap:     i16_at(d, hhea + 8).ok_or(bad)?,
+            num_hmetrics: u16_at(d, hhea + 34).filter(|&n| n > 0).ok_or(bad)?,
+            num_glyphs:   u16_at(d, maxp + 4).ok_or(bad)?,
+            loca_long:    i16_at(d, head + 50).ok_or(bad)? != 0,
+            cmap:         0,
+            loca:         table(b"loca").ok_or("font has no TrueType outlines")?,
+            glyf:         table(b"glyf").ok_or("font has no TrueType outlines")?,
+            hmtx:         table(b"hmtx").ok_or("font has no hmtx table")?,
+            gsub:         table(b"GSUB"),
+            data:         Arc::clone(&data),
+        };
+        font.cmap = font.find_cmap(cmap).ok_or("no Unicode character map")?;
+        Ok(font)
+    }
+
+    fn d(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The Unicode subtable: full repertoire (format 12) if there is one,
+    /// else the BMP one (format 4).
+    fn find_cmap(&self, cmap: usize) -> Option<usize> {
+        let d = self.d();
+        let n = u16_at(d, cmap + 2)? as usize;
+        let mut bmp = None;
+        for i in 0..n {
+            let r = cmap + 4 + 8 * i;
+            let (platform, encoding) = (u16_at(d, r)?, u16_at(d, r + 2)?);
+            let sub = cmap + u32_at(d, r + 4)? as usize;
+            match (platform, encoding, u16_at(d, sub)?) {
+                (3, 10, 12) | (0, 4, 12) | (0, 6, 12) => return Some(sub),
+                (3, 1, 4) | (0, 3, 4) => bmp = Some(sub),
+                _ => {}
+            }
+        }
+        bmp
+    }
+
+    /// The glyph for `c`; 0 (.notdef) if the font has none.
+    pub fn glyph_index(&self, c: char) -> u16 {
+        self.lookup_cmap(c as u32).unwrap_or(0)
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.glyph_index(c) != 0
+    }
+
+    fn lookup_cmap(&self, c: u32) -> Option<u16> {
+        let (d, t) = (self.d(), self.cmap);
+        if u16_at(d, t)? == 12 {
+            let groups = u32_at(d, t + 12)? as usize;
+            let (mut lo, mut hi) = (0, groups);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let g = t + 16 + 12 * mid;
+                let (start, end) = (u32_at(d, g)?, u32_at(d, g + 4)?);
+                if c < start { hi = mid; } else if c > end { lo = mid + 1; } else {
+                    return u16::try_from(u32_at(d, g + 8)? + (c - start)).ok();
+                }
+            }
+            return None;
+        }
+        if c > 0xffff { return None; }
+        let seg2 = u16_at(d, t + 6)? as usize;
+        let ends = t + 14;
+        let (mut lo, mut hi) = (0, seg2 / 2);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if (u16_at(d, ends + 2 * mid)? as u32) < c { lo = mid + 1; } else { hi = mid; }
+        }
+        let i = 2 * lo;
+        if i >= seg2 { return None; }
+        let start = u16_at(d, ends + seg2 + 2 + i)? as u32;
+        if c < start { return None; }
+        let delta = u16_at(d, ends + 2 * seg2 + 2 + i)?;
+        let ro_at = ends + 3 * seg2 + 2 + i;
+        let ro = u16_at(d, ro_at)? as usize;
+        let g = if ro == 0 { c as u16 } else {
+            match u16_at(d, ro_at + ro + 2 * (c - start) as usize)? { 0 => return None, g => g }
+        };
+        Some(g.wrapping_add(delta)).filter(|&g| g != 0)
+    }
+
+    /// How far the pen moves after `glyph`, in font units.
+    pub fn advance(&self, glyph: u16) -> u16 {
+        let i = glyph.min(self.num_hmetrics - 1) as usize;
+        u16_at(self.d(), self.hmtx + 4 * i).unwrap_or(0)
+    }
+
+    fn glyph_data(&self, glyph: u16) -> Option<&[u8]> {
+        if glyph >= self.num_glyphs { return None; }
+        let (d, g) = (self.d(), glyph as usize);
+        let (start, end) = if self.loca_long {
+            (u32_at(d, self.loca + 4 * g)? as usize, u32_at(d, self.loca + 4 * g + 4)? as usize)
+        } else {
+            (u16_at(d, self.loca + 2 * g)? as usize * 2, u16_at(d, self.loca + 2 * g + 2)? as usize * 2)
+        };
+        if end <= start { return Some(&[]); }
+        d.get(self.glyf + start..self.glyf + end)
+    }
+
+    /// The outline of `glyph`, in font units, y up; empty for a blank
+    /// glyph, or one we cannot read.
+    pub fn outline(&self, glyph: u16) -> Vec<Segment> {
+        let mut out = Vec::new();
+        self.outline_into(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut out);
+        out
+    }
+
+    /// Append `glyph`'s outline, through transform `m` (a b c d e f:
+    /// x' = a x + c y + e, y' = b x + d y + f).
+    fn outline_into(&self, glyph: u16, m: [f32; 6], depth: u32, out: &mut Vec<Segment>) -> Option<()> {
+        let g = self.glyph_data(glyph)?;
+        if g.is_empty() { return Some(()); }
+        let contours = i16_at(g, 0)?;
+        if contours >= 0 { return simple_outline(g, contours as usize, m, out); }
+        if depth >= MAX_COMPONENT_DEPTH { return None; }
+        let mut at = 10;
+        loop {
+            let flags = u16_at(g, at)?;
+            let component = u16_at(g, at + 2)?;
+            at += 4;
+            let (dx, dy) = if flags & 1 != 0 {
+                at += 4;
+                (i16_at(g, at - 4)? as f32, i16_at(g, at - 2)? as f32)
+            } else {
+                at += 2;
+                (*g.get(at - 2)? as i8 as f32, *g.get(at - 1)? as i8 as f32)
+            };
+            let f2 = |i: usize| i16_at(g, i).map(|v| v as f32 / 16384.0);
+            let (a, b, c, dd) = if flags & 0x08 != 0 {
+                at += 2;
+                let s = f2(at - 2)?;
+                (s, 0.0, 0.0, s)
+            } else if flags & 0x40 != 0 {
+                at += 4;
+                (f2(at - 4)?, 0.0, 0.0, f2(at - 2)?)
+            } else if flags & 0x80 != 0 {
+                at += 8;
+                (f2(at - 8)?, f2(at - 6)?, f2(at - 4)?, f2(at - 2)?)
+            } else {
+                (1.0, 0.0, 0.0, 1.0)
+            };
+            // Point-matched placement (ARGS_ARE_XY_VALUES clear) is not supported
+            let (dx, dy) = if flags & 0x02 != 0 { (dx, dy) } else { (0.0, 0.0) };
+            let inner = [a, b, c, dd, dx, dy];
+            let combined = [
+                m[0] * inner[0] + m[2] * inner[1], m[1] * inner[0] + m[3] * inner[1],
+                m[0] * inner[2] + m[2] * inner[3], m[1] * inner[2] + m[3] * inner[3],
+                m[0] * inner[4] + m[2] * inner[5] + m[4], m[1] * inner[4] + m[3] * inner[5] + m[5],
+            ];
+            self.outline_into(component, combined, depth + 1, out)?;
+            if flags & 0x20 == 0 { return Some(()); }
+        }
+    }
+
+    // ─── GSUB ─────────────────────────────────────────────────────────────────
+
+    /// The lookups, in order, that the GSUB features `features` use for
+    /// the first of `scripts` the font has (else the default script), each
+    /// with the feature it is for.
+    pub fn gsub_lookups(&self, scripts: &[u32], features: &[u32]) -> Vec<(u16, u32)> {
+        self.gsub_lookups_inner(scripts, features).unwrap_or_default()
+    }
+
+    fn gsub_lookups_inner(&self, wanted: &[u32], features: &[u32]) -> Option<Vec<(u16, u32)>> {
+        let (d, g) = (self.d(), self.gsub?);
+        let scripts = g + u16_at(d, g + 4)? as usize;
+        let feature_list = g + u16_at(d, g + 6)? as usize;
+        let find_script = |t: u32| -> Option<usize> {
+            let n = u16_at(d, scripts)? as usize;
+            (0..n).map(|i| scripts + 2 + 6 * i).find(|&r| u32_at(d, r) == Some(t))
+                .and_then(|r| Some(scripts + u16_at(d, r + 4)? as usize))
+        };
+        let script = wanted.iter().find_map(|&t| find_script(t)).or_else(|| find_script(tag(b"DFLT"))).or_else(|| find_script(tag(b"latn")))?;
+        let lang = script + u16_at(d, script)? as usize;
+        if lang == script { return Some(Vec::new()); }
+        let mut lookups = Vec::new();
+        let n = u16_at(d, lang + 4)? as usize;
+        for i in 0..n {
+            let fi = u16_at(d, lang + 6 + 2 * i)? as usize;
+            let rec = feature_list + 2 + 6 * fi;
+            let ftag = u32_at(d, rec)?;
+            if !features.contains(&ftag) { continue; }
+            let feature = feature_list + u16_at(d, rec + 4)? as usize;
+            for j in 0..u16_at(d, feature + 2)? as usize {
+                lookups.push((u16_at(d, feature + 4 + 2 * j)?, ftag));
+            }
+        }
+        lookups.sort_unstable_by_key(|&(l, _)| l);
+        lookups.dedup();
+        Some(lookups)
+    }
+
+    /// Apply lookup `index` to `glyphs`, at the positions `applies` allows.
+    pub fn apply_lookup(&self, index: u16, glyphs: &mut Vec<Glyph>, applies: &dyn Fn(&Glyph) -> bool) {
+        let mut i = 0;
+        while i < glyphs.len() {
+            if applies(&glyphs[i]) {
+                if let Some(next) = self.apply_at(index, glyphs, i, 0) { i = next; continue; }
+            }
+            i += 1;
+        }
+    }
+
+    fn lookup_table(&self, index: u16) -> Option<(usize, u16, usize)> {
+        let (d, g) = (self.d(), self.gsub?);
+        let list = g + u16_at(d, g + 8)? as usize;
+        if index >= u16_at(d, list)? { return None; }
+        let lookup = list + u16_at(d, list + 2 + 2 * index as usize)? as usize;
+        Some((lookup, u16_at(d, lookup)?, u16_at(d, lookup + 4)? as usize))
+    }
+
+    /// Apply lookup `index` at glyph `i`; the position to carry on from
+    /// if a subtable matched.
+    fn apply_at(&self, index: u16, glyphs: &mut Vec<Glyph>, i: usize, depth: u32) -> Option<usize> {
+        let d = self.d();
+        let (lookup, ty, subtables) = self.lookup_table(index)?;
+        for s in 0..subtables {
+            let mut sub = lookup + u16_at(d, lookup + 6 + 2 * s)? as usize;
+            let mut ty = ty;
+            if ty == 7 {
+                ty = u16_at(d, sub + 2)?;
+                sub += u32_at(d, sub + 4)? as usize;
+            }
+            if let Some(next) = self.apply_subtable(ty, sub, glyphs, i, depth) { return Some(next); }
+        }
+        None
+    }
+
+    fn apply_subtable(&self, ty: u16, sub: usize, glyphs: &mut Vec<Glyph>, i: usize, depth: u32) -> Option<usize> {
+        let d = self.d();
+        let format = u16_at(d, sub)?;
+        let g = glyphs[i].id;
+        match ty {
+            1 => {
+                let cov = coverage(d, sub + u16_at(d, sub + 2)? as usize, g)?;
+                glyphs[i].id = if format == 1 {
+                    g.wrapping_add(u16_at(d, sub + 4)?)
+                } else {
+                    u16_at(d, sub + 6 + 2 * cov)?
+                };
+                Some(i + 1)
+            }
+            2 => {
+                let cov = coverage(d, sub + u16_at(d, sub + 2)? as usize, g)?;
+                let seq = sub + u16_at(d, sub + 6 + 2 * cov)? as usize;
+                let n = u16_at(d, seq)? as usize;
+                let template = glyphs[i];
+                let ids: Vec<u16> = (0..n).map(|k| u16_at(d, seq + 2 + 2 * k)).collect::<Option<_>>()?;
+                glyphs.splice(i..i + 1, ids.iter().map(|&id| Glyph { id, ..template }));
+                Some(i + n)
+            }
+            4 => {
+                let cov = coverage(d, sub + u16_at(d, sub + 2)? as usize, g)?;
+                let set = sub + u16_at(d, sub + 6 + 2 * cov)? as usize;
+                for k in 0..u16_at(d, set)? as usize {
+                    let lig = set + u16_at(d, set + 2 + 2 * k)? as usize;
+                    let comps = u16_at(d, lig + 2)? as usize;
+                    if comps == 0 || i + comps > glyphs.len() { continue; }
+                    let matches = (1..comps).all(|c| u16_at(d, lig + 2 + 2 * c) == Some(glyphs[i + c].id));
+                    if matches {
+                        glyphs[i].id = u16_at(d, lig)?;
+                        glyphs.drain(i + 1..i + comps);
+                        return Some(i + 1);
+                    }
+                }
+                None
+            }
+            5 | 6 if depth < MAX_NESTING => self.apply_context(ty == 6, format, sub, glyphs, i, depth),
+            _ => None,
+        }
+    }
+
+    /// A contextual (type 5) or chained contextual (type 6) subtable.
+    fn apply_context(&self, chained: bool, format: u16, sub: usize, glyphs: &mut Vec<Glyph>, i: usize, depth: u32) -> Option<usize> {
+        let d = self.d();
+        let ids: Vec<u16> = glyphs.iter().map(|g| g.id).collect();
+        let off = |at: usize| -> Option<usize> { Some(sub + u16_at(d, at)? as usize) };
+        let (input_len, count, records) = match format {
+            1 | 2 => {
+                let cov = coverage(d, off(sub + 2)?, ids[i])?;
+                // Backtrack, input and lookahead class definitions
+                let classes = match (format, chained) {
+                    (1, _)     => None,
+                    (_, true)  => Some([off(sub + 4)?, off(sub + 6)?, off(sub + 8)?]),
+                    (_, false) => off(sub + 4).map(|c| [c, c, c]),
+                };
+                let sets = sub + match (format, chained) { (1, _) => 4, (_, true) => 10, (_, false) => 6 };
+                let index = classes.map_or(cov, |c| class(d, c[1], ids[i]) as usize);
+                if index >= u16_at(d, sets)? as usize { return None; }
+                let set = match u16_at(d, sets + 2 + 2 * index)? { 0 => return None, o => sub + o as usize };
+                let value = |k: usize, g: u16| classes.map_or(g, |c| class(d, c[k], g));
+                (0..u16_at(d, set)? as usize).find_map(|r| {
+                    let rule = set + u16_at(d, set + 2 + 2 * r)? as usize;
+                    match_rule(d, chained, rule, &ids, i, &value)
+                })?
+            }
+            3 if chained => {
+                let mut at = sub + 2;
+                let mut covs = || -> Option<Vec<usize>> {
+                    let n = u16_at(d, at)? as usize;
+                    let v = (0..n).map(|k| off(at + 2 + 2 * k)).collect::<Option<Vec<_>>>()?;
+                    at += 2 + 2 * n;
+                    Some(v)
+                };
+                let (back, input, ahead) = (covs()?, covs()?, covs()?);
+                let covered = |p: Option<usize>, c: usize| p.and_then(|p| ids.get(p)).is_some_and(|&g| coverage(d, c, g).is_some());
+                let ok = !input.is_empty()
+                    && back.iter().enumerate().all(|(j, &c)| covered(i.checked_sub(j + 1), c))
+                    && input.iter().enumerate().all(|(j, &c)| covered(Some(i + j), c))
+                    && ahead.iter().enumerate().all(|(j, &c)| covered(Some(i + input.len() + j), c));
+                if !ok { return None; }
+                (input.len(), u16_at(d, at)? as usize, at + 2)
+            }
+            3 => {
+                // glyphCount, seqLookupCount, coverages, records
+                let n = u16_at(d, sub + 2)? as usize;
+                let ok = n > 0 && (0..n).all(|j| {
+                    off(sub + 6 + 2 * j).zip(ids.get(i + j)).is_some_and(|(c, &g)| coverage(d, c, g).is_some())
+                });
+                if !ok { return None; }
+                (n, u16_at(d, sub + 4)? as usize, sub + 6 + 2 * n)
+            }
+            _ => return None,
+        };
+        self.apply_records(count, records, input_len, glyphs, i, depth)
+    }
+
+    /// Run a matched rule's `count` nested lookups, (sequence index,
+    /// lookup index) records at `records`, and carry on past the input.
+    fn apply_records(&self, count: usize, records: usize, input_len: usize, glyphs: &mut Vec<Glyph>, i: usize, depth: u32) -> Option<usize> {
+        let d = self.d();
+        let start_len = glyphs.len();
+        for r in 0..count {
+            let seq = u16_at(d, records + 4 * r)? as usize;
+            let lookup = u16_at(d, records + 4 * r + 2)?;
+            // Earlier lookups may have grown or shrunk the input
+            let pos = (i + seq + glyphs.len()).checked_sub(start_len);
+            if let Some(pos) = pos.filter(|&p| p < glyphs.len()) { self.apply_at(lookup, glyphs, pos, depth + 1); }
+        }
+        let end = (i + input_len + glyphs.len()).saturating_sub(start_len);
+        Some(end.clamp(i + 1, glyphs.len().max(i + 1)))
+    }
+}
+
+/// Match a format 1 or 2 rule at `rule` against the glyphs around `i`,
+/// `value` giving what a glyph is compared as in the backtrack (0), input
+/// (1) or lookahead (2).  The input's length, and its lookup records.
+fn match_rule(d: &[u8], chained: bool, rule: usize, ids: &[u16], i: usize, value: &dyn Fn(usize, u16) -> u16) -> Option<(usize, usize, usize)> {
+    let matches = |at: usize, n: usize, k: usize, pos: &dyn Fn(usize) -> Option<u16>| {
+        (0..n).all(|j| pos(j).zip(u16_at(d, at + 2 * j)).is_some_and(|(g, v)| value(k, g) == v))
+    };
+    let input = |j: usize| ids.get(i + 1 + j).copied();
+    if !chained {
+        // glyphCount, seqLookupCount, input after the first, records
+        let n = u16_at(d, rule)? as usize;
+        if n == 0 || !matches(rule + 4, n - 1, 1, &input) { return None; }
+        return Some((n, u16_at(d, rule + 2)? as usize, rule + 4 + 2 * (n - 1)));
+    }
+    let mut at = rule;
+    let n = u16_at(d, at)? as usize;
+    if !matches(at + 2, n, 0, &|j| i.checked_sub(j + 1).map(|p| ids[p])) { return None; }
+    at += 2 + 2 * n;
+    let n_input = u16_at(d, at)? as usize;
+    if n_input == 0 || !matches(at + 2, n_input - 1, 1, &input) { return None; }
+    at += 2 + 2 * (n_input - 1);
+    let n = u16_at(d, at)? as usize;
+    if !matches(at + 2, n, 2, &|j| ids.get(i + n_input + j).copied()) { return None; }
+    at += 2 + 2 * n;
+    Some((n_input, u16_at(d, at)? as usize, at + 2))
+}
+
+/// A glyph in a shaping buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Glyph {
+    pub id:      u16,
+    /// Index of the first character of its cluster in the text.
+    pub cluster: u32,
+    /// Which features may apply to it.
+    pub mask:    u32,
+}
+
+/// `glyph`'s index in the coverage table at `at`.
+fn coverage(d: &[u8], at: usize, glyph: u16) -> Option<usize> {
+    let n = u16_at(d, at + 2)? as usize;
+    match u16_at(d, at)? {
+        1 => {
+            let (mut lo, mut hi) = (0, n);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                let g = u16_at(d, at + 4 + 2 * mid)?;
+                if g == glyph { return Some(mid); }
+                if g < glyph { lo = mid + 1; } else { hi = mid; }
+            }
+            None
+        }
+        2 => (0..n).map(|k| at + 4 + 6 * k).find_map(|r| {
+            let (start, end) = (u16_at(d, r)?, u16_at(d, r + 2)?);
+            (start..=end).contains(&glyph).then(|| Some((u16_at(d, r + 4)? + (glyph - start)) as usize)).flatten()
+        }),
+        _ => None,
+    }
+}
+
+/// `glyph`'s class in the class definition at `at`; 0 if it has none.
+fn class(d: &[u8], at: usize, glyph: u16) -> u16 {
+    let inner = || -> Option<u16> {
+        match u16_at(d, at)? {
+            1 => {
+                let start = u16_at(d, at + 2)?;
+                let n = u16_at(d, at + 4)?;
+                let k = glyph.checked_sub(start).filter(|&k| k < n)?;
+                u16_at(d, at + 6 + 2 * k as usize)
+            }
+            2 => {
+                let n = u16_at(d, at + 2)? as usize;
+                (0..n).map(|k| at + 4 + 6 * k)
+                    .find(|&r| u16_at(d, r).is_some_and(|s| s <= glyph) && u16_at(d, r + 2).is_some_and(|e| glyph <= e))
+                    .and_then(|r| u16_at(d, r + 4))
+            }
+            _ => None,
+        }
+    };
+    inner().unwrap_or(0)
+}
+
+/// Turn a simple glyph's contours into segments.
+fn simple_outline(g: &[u8], contours: usize, m: [f32; 6], out: &mut Vec<Segment>) -> Option<()> {
+    let ends: Vec<usize> = (0..contours).map(|i| u16_at(g, 10 + 2 * i).map(|e| e as usize)).collect::<Option<_>>()?;
+    let points = ends.last().map_or(0, |&e| e + 1);
+    let mut at = 10 + 2 * contours;
+    at += 2 + u16_at(g, at)? as usize;
+    let mut flags = Vec::with_capacity(points);
+    while flags.len() < points {
+        let f = *g.get(at)?;
+        at += 1;
+        flags.push(f);
+        if f & 0x08 != 0 {
+            let repeat = *g.get(at)?;
+            at += 1;
+            for _ in 0..repeat { flags.push(f); }
+        }
+    }
+    flags.truncate(points);
+    let mut coords = |short: u8, same: u8| -> Option<Vec<i32>> {
+        let mut v = 0i32;
+        let mut out = Vec::with_capacity(points);
+        for &f in &flags {
+            if f & short != 0 {
+                let dv = *g.get(at)? as i32;
+                at += 1;
+                v += if f & same != 0 { dv } else { -dv };
+            } else if f & same == 0 {
+                v += i16_at(g, at)? as i32;
+                at += 2;
+            }
+            out.push(v);
+        }
+        Some(out)
+    };
+    let xs = coords(0x02, 0x10)?;
+    let ys = coords(0x04, 0x20)?;
+    let pt = |i: usize| {
+        let (x, y) = (xs[i] as f32, ys[i] as f32);
+        Point { x: m[0] * x + m[2] * y + m[4], y: m[1] * x + m[3] * y + m[5] }
+    };
+    let mid = |a: Point, b: Point| Point { x: (a.x + b.x) / 2.0, y: (a.y + b.y) / 2.0 };
+    let mut start = 0;
+    for &end in &ends {
+        if end < start || end >= points { return None; }
+        let n = end - start + 1;
+        let on = |k: usize| flags[start + k % n] & 1 != 0;
+        let p = |k: usize| pt(start + k % n);
+        // Begin at an on-curve point, or between two off-curve ones
+        let (first, skip) = match (0..n).find(|&k| on(k)) {
+            Some(k) => (p(k), k),
+            None => (mid(p(0), p(1)), 0),
+        };
+        let mut cur = first;
+        let mut ctrl: Option<Point> = None;
+        for k in 1..=n {
+            let q = p(skip + k);
+            if on(skip + k) {
+                out.push(match ctrl.take() { Some(c) => Segment::Quad(cur, c, q), None => Segment::Line(cur, q) });
+                cur = q;
+            } else if let Some(c) = ctrl.replace(q) {
+                let m = mid(c, q);
+                out.push(Segment::Quad(cur, c, m));
+                cur = m;
+            }
+        }
+        match ctrl { Some(c) => out.push(Segment::Quad(cur, c, first)), None if cur != first => out.push(Segment::Line(cur, first)), None => {} }
+        start = end + 1;
+    }
+    Some(())
+}
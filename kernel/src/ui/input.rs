@@ -0,0 +1,388 @@
+//! Input: touch and key events from the drivers, routed to windows and
+//! their elements.
+//!
+//! Drivers — touchscreen, keyboard, virtio-input — `report` raw events
+//! from their interrupts; the router runs as deferred work.  A touch goes
+//! to the window on top where it lands and, within it, down the window's
+//! element tree; the window and element it went down on keep the whole
+//! gesture, wherever it moves.  Touches become gestures:
+//!
+//! - Press as soon as a finger lands;
+//! - Tap, or LongPress once held LONG_PRESS_MS, when it lifts without
+//!   having moved more than TOUCH_SLOP;
+//! - PanStart, PanMove and PanEnd once it does move, and a Swipe as it
+//!   lifts if it was moving faster than SWIPE_SPEED;
+//! - Pinch, with the spread against the start, while a second finger is
+//!   down in the same window.
+//!
+//! Long presses are told on release: there is no timer fine enough to
+//! tell them while the finger is still down.
+//!
+//! Keys go to the window with the focus, to its focused element.  A touch
+//! gives its window the focus, and its element if that is focusable; Tab
+//! and Shift-Tab move the focus along the window's focusable elements.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::compositor::Compositor;
+use super::element::{Accepts, ElementId, UiElement};
+use super::WindowId;
+
+/// Movement, in pixels, before a touch is a pan rather than a tap.
+pub const TOUCH_SLOP: i32 = 8;
+/// How long a touch is held to be a long press.
+pub const LONG_PRESS_MS: u64 = 500;
+/// Speed, in pixels a second, at which a pan lifting is a swipe.
+pub const SWIPE_SPEED: u32 = 600;
+/// Raw events queued for the router before the oldest are dropped.
+const RAW_QUEUE: usize = 256;
+
+// ─── events ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    Down,
+    Move,
+    Up,
+    /// The driver lost track of the contact.
+    Cancel,
+}
+
+/// An event as a driver reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    /// Contact `slot` of a touchscreen, in screen pixels.
+    Touch { slot: u8, phase: TouchPhase, x: i32, y: i32 },
+    /// A key, by its Linux input event code.
+    Key { code: u16, down: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl:  bool,
+    pub alt:   bool,
+}
+
+/// What an element is told; positions are in its window's coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Press { x: i32, y: i32 },
+    Tap { x: i32, y: i32 },
+    LongPress { x: i32, y: i32 },
+    PanStart { x: i32, y: i32 },
+    /// Moved by (`dx`, `dy`) since the last.
+    PanMove { x: i32, y: i32, dx: i32, dy: i32 },
+    PanEnd { x: i32, y: i32 },
+    /// Speed in pixels a second.
+    Swipe { direction: Direction, speed: u32 },
+    /// The fingers' spread, in thousandths of what it was, around (`x`, `y`).
+    Pinch { scale: u32, x: i32, y: i32 },
+    PinchEnd,
+    /// The touch it was pressed by is gone without a gesture.
+    Cancel,
+    Key { code: u16, down: bool, ch: Option<char>, modifiers: Modifiers },
+    /// Keyboard focus came (true) or went.
+    Focus(bool),
+}
+
+/// An event for a window, and the element it is for, if any takes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UiEvent {
+    pub window:  WindowId,
+    pub element: Option<ElementId>,
+    pub time_ms: u64,
+    pub kind:    EventKind,
+}
+
+/// An event the router has placed: for `window`, down `path` (element
+/// ids, root first), to the deepest element there that takes `need`.
+#[derive(Debug, Clone)]
+pub struct Routed {
+    pub window: WindowId,
+    pub path:   Vec<ElementId>,
+    pub need:   Accepts,
+    pub time:   u64,
+    pub kind:   EventKind,
+}
+
+// ─── raw queue ────────────────────────────────────────────────────────────────
+
+/// Raw events and when they came, filled from interrupts.
+static RAW: Mutex<VecDeque<(InputEvent, u64)>> = Mutex::new(VecDeque::new());
+
+/// Take an event from a driver; safe from its interrupt handler.
+pub fn report(event: InputEvent) {
+    let now = crate::arch::uptime_millis();
+    let prev = crate::arch::interrupts_disable();
+    {
+        let mut q = RAW.lock();
+        if q.len() >= RAW_QUEUE { q.pop_front(); }
+        q.push_back((event, now));
+    }
+    crate::arch::interrupts_restore(prev);
+    crate::process::defer(super::dispatch_input);
+}
+
+/// Everything reported so far.
+pub(super) fn take_raw() -> Vec<(InputEvent, u64)> {
+    let prev = crate::arch::interrupts_disable();
+    let events = RAW.lock().drain(..).collect();
+    crate::arch::interrupts_restore(prev);
+    events
+}
+
+// ─── router ───────────────────────────────────────────────────────────────────
+
+/// A finger down.
+struct Contact {
+    slot:     u8,
+    /// The window it went down on and where that was on the screen then.
+    window:   Option<(WindowId, i32, i32)>,
+    path:     Vec<ElementId>,
+    start:    (i32, i32),
+    last:     (i32, i32),
+    start_ms: u64,
+    panning:  bool,
+    /// Part of a pinch, so it makes no gesture of its own.
+    spent:    bool,
+}
+
+struct Pinch {
+    window: (WindowId, i32, i32),
+    path:   Vec<ElementId>,
+    spread: f32,
+}
+
+#[derive(Default)]
+pub struct Router {
+    contacts:  Vec<Contact>,
+    pinch:     Option<Pinch>,
+    focus:     Option<WindowId>,
+    /// The focused element of each window that has one.
+    focused:   Vec<(WindowId, ElementId)>,
+    modifiers: Modifiers,
+}
+
+fn tree(trees: &[(WindowId, UiElement)], window: WindowId) -> Option<&UiElement> {
+    trees.iter().find(|(w, _)| *w == window).map(|(_, t)| t)
+}
+
+fn spread(a: (i32, i32), b: (i32, i32)) -> f32 {
+    let (dx, dy) = ((a.0 - b.0) as f32, (a.1 - b.1) as f32);
+    libm::sqrtf(dx * dx + dy * dy)
+}
+
+impl Router {
+    pub const fn new() -> Router {
+        Router { contacts: Vec::new(), pinch: None, focus: None, focused: Vec::new(), modifiers: Modifiers { shift: false, ctrl: false, alt: false } }
+    }
+
+    pub fn focus(&self) -> Option<WindowId> {
+        self.focus
+    }
+
+    pub fn focused_element(&self, window: WindowId) -> Option<ElementId> {
+        self.focused.iter().find(|(w, _)| *w == window).map(|(_, e)| *e)
+    }
+
+    /// Place `event`, which came at `time`, given the windows and their
+    /// trees as they are.
+    pub fn route(&mut self, event: InputEvent, time: u64, c: &Compositor, trees: &[(WindowId, UiElement)], out: &mut Vec<Routed>) {
+        match event {
+            InputEvent::Touch { slot, phase, x, y } => self.touch(slot, phase, (x, y), time, c, trees, out),
+            InputEvent::Key { code, down } => self.key(code, down, time, trees, out),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn touch(&mut self, slot: u8, phase: TouchPhase, at: (i32, i32), time: u64, c: &Compositor, trees: &[(WindowId, UiElement)], out: &mut Vec<Routed>) {
+        let emit = |out: &mut Vec<Routed>, w: (WindowId, i32, i32), path: &[ElementId], need, kind| {
+            out.push(Routed { window: w.0, path: path.to_vec(), need, time, kind });
+        };
+        match phase {
+            TouchPhase::Down => {
+                // A second finger in the first's window makes it a pinch
+                if let [first] = &mut self.contacts[..] {
+                    if first.window.is_some() && first.window.map(|w| w.0) == c.layer_at(at.0, at.1).map(|l| l.id) {
+                        let w = first.window.unwrap_or((0, 0, 0));
+                        if first.panning {
+                            emit(out, w, &first.path, Accepts::DRAG, EventKind::PanEnd { x: first.last.0 - w.1, y: first.last.1 - w.2 });
+                        }
+                        (first.spent, first.panning) = (true, false);
+                        let path = first.path.clone();
+                        self.pinch = Some(Pinch { window: w, path: path.clone(), spread: spread(first.last, at).max(1.0) });
+                        self.contacts.push(Contact { slot, window: Some(w), path, start: at, last: at, start_ms: time, panning: false, spent: true });
+                        return;
+                    }
+                }
+                let window = c.layer_at(at.0, at.1).map(|l| (l.id, l.rect.x, l.rect.y));
+                let mut path = Vec::new();
+                if let Some(w) = window {
+                    let local = (at.0 - w.1, at.1 - w.2);
+                    if let Some(t) = tree(trees, w.0) {
+                        let hit = t.hit_path(local.0, local.1);
+                        path = hit.iter().map(|e| e.id).collect();
+                        let focusable = hit.iter().rev().find(|e| e.focusable).map(|e| e.id);
+                        self.set_focus(w.0, focusable, time, trees, out);
+                    } else {
+                        self.set_focus(w.0, None, time, trees, out);
+                    }
+                    emit(out, w, &path, Accepts::TOUCH, EventKind::Press { x: local.0, y: local.1 });
+                }
+                self.contacts.retain(|k| k.slot != slot);
+                self.contacts.push(Contact { slot, window, path, start: at, last: at, start_ms: time, panning: false, spent: false });
+            }
+            TouchPhase::Move => {
+                let Some(i) = self.contacts.iter().position(|k| k.slot == slot) else { return };
+                let prev = self.contacts[i].last;
+                self.contacts[i].last = at;
+                if let (Some(p), [a, b]) = (&self.pinch, &self.contacts[..]) {
+                    let scale = (spread(a.last, b.last) / p.spread * 1000.0) as u32;
+                    let (cx, cy) = ((a.last.0 + b.last.0) / 2 - p.window.1, (a.last.1 + b.last.1) / 2 - p.window.2);
+                    emit(out, p.window, &p.path, Accepts::PINCH, EventKind::Pinch { scale, x: cx, y: cy });
+                    return;
+                }
+                let k = &mut self.contacts[i];
+                let Some(w) = k.window else { return };
+                if k.spent { return; }
+                if !k.panning && ((at.0 - k.start.0).abs() > TOUCH_SLOP || (at.1 - k.start.1).abs() > TOUCH_SLOP) {
+                    k.panning = true;
+                    emit(out, w, &k.path, Accepts::DRAG, EventKind::PanStart { x: k.start.0 - w.1, y: k.start.1 - w.2 });
+                }
+                if k.panning {
+                    emit(out, w, &k.path, Accepts::DRAG, EventKind::PanMove { x: at.0 - w.1, y: at.1 - w.2, dx: at.0 - prev.0, dy: at.1 - prev.1 });
+                }
+            }
+            TouchPhase::Up | TouchPhase::Cancel => {
+                let Some(i) = self.contacts.iter().position(|k| k.slot == slot) else { return };
+                let k = self.contacts.remove(i);
+                if let Some(p) = self.pinch.take() {
+                    emit(out, p.window, &p.path, Accepts::PINCH, EventKind::PinchEnd);
+                    return;
+                }
+                let Some(w) = k.window else { return };
+                let end = if phase == TouchPhase::Up { at } else { k.last };
+                let local = (end.0 - w.1, end.1 - w.2);
+                if k.panning {
+                    emit(out, w, &k.path, Accepts::DRAG, EventKind::PanEnd { x: local.0, y: local.1 });
+                    let (dx, dy) = (end.0 - k.start.0, end.1 - k.start.1);
+                    let ms = (time - k.start_ms).max(1);
+                    let speed = (spread(k.start, end) * 1000.0 / ms as f32) as u32;
+                    if phase == TouchPhase::Up && speed >= SWIPE_SPEED {
+                        let direction = if dx.abs() >= dy.abs() {
+                            if dx < 0 { Direction::Left } else { Direction::Right }
+                        } else if dy < 0 { Direction::Up } else { Direction::Down };
+                        emit(out, w, &k.path, Accepts::DRAG, EventKind::Swipe { direction, speed });
+                    }
+                } else if k.spent {
+                } else if phase == TouchPhase::Cancel {
+                    emit(out, w, &k.path, Accepts::TOUCH, EventKind::Cancel);
+                } else if time - k.start_ms >= LONG_PRESS_MS {
+                    emit(out, w, &k.path, Accepts::TOUCH, EventKind::LongPress { x: local.0, y: local.1 });
+                } else {
+                    emit(out, w, &k.path, Accepts::TOUCH, EventKind::Tap { x: local.0, y: local.1 });
+                }
+            }
+        }
+    }
+
+    fn key(&mut self, code: u16, down: bool, time: u64, trees: &[(WindowId, UiElement)], out: &mut Vec<Routed>) {
+        match code {
+            KEY_LEFTSHIFT | KEY_RIGHTSHIFT => self.modifiers.shift = down,
+            KEY_LEFTCTRL | KEY_RIGHTCTRL   => self.modifiers.ctrl = down,
+            KEY_LEFTALT | KEY_RIGHTALT     => self.modifiers.alt = down,
+            _ => {}
+        }
+        let Some(window) = self.focus else { return };
+        if code == KEY_TAB && !self.modifiers.ctrl && !self.modifiers.alt {
+            if down { self.cycle_focus(window, self.modifiers.shift, time, trees, out); }
+            return;
+        }
+        let element = self.focused_element(window);
+        let path = match (tree(trees, window), element) {
+            (Some(t), Some(e)) => t.path_to(e).map(|p| p.iter().map(|e| e.id).collect()).unwrap_or_default(),
+            (Some(t), None) => alloc::vec![t.id],
+            _ => Vec::new(),
+        };
+        let ch = if down && !self.modifiers.ctrl && !self.modifiers.alt { keymap(code, self.modifiers.shift) } else { None };
+        out.push(Routed { window, path, need: Accepts::KEYS, time, kind: EventKind::Key { code, down, ch, modifiers: self.modifiers } });
+    }
+
+    /// Give `window` the focus, and `element` in it; each side is told.
+    pub fn set_focus(&mut self, window: WindowId, element: Option<ElementId>, time: u64, trees: &[(WindowId, UiElement)], out: &mut Vec<Routed>) {
+        let old = self.focus.map(|w| (w, self.focused_element(w)));
+        let new = (window, element.or(if old.map(|o| o.0) == Some(window) { old.and_then(|o| o.1) } else { self.focused_element(window) }));
+        if old == Some(new) { return; }
+        let path = |w: WindowId, e: Option<ElementId>| -> Vec<ElementId> {
+            match (tree(trees, w), e) {
+                (Some(t), Some(e)) => t.path_to(e).map(|p| p.iter().map(|e| e.id).collect()).unwrap_or_default(),
+                _ => Vec::new(),
+            }
+        };
+        if let Some((w, e)) = old {
+            out.push(Routed { window: w, path: path(w, e), need: Accepts::NONE, time, kind: EventKind::Focus(false) });
+        }
+        self.focus = Some(window);
+        self.focused.retain(|(w, _)| *w != window);
+        if let Some(e) = new.1 { self.focused.push((window, e)); }
+        out.push(Routed { window, path: path(window, new.1), need: Accepts::NONE, time, kind: EventKind::Focus(true) });
+    }
+
+    /// Make `element` the one `window` gives keys to once it has the focus.
+    pub fn remember_focus(&mut self, window: WindowId, element: ElementId) {
+        self.focused.retain(|(w, _)| *w != window);
+        self.focused.push((window, element));
+    }
+
+    fn cycle_focus(&mut self, window: WindowId, back: bool, time: u64, trees: &[(WindowId, UiElement)], out: &mut Vec<Routed>) {
+        let Some(order) = tree(trees, window).map(UiElement::focus_order) else { return };
+        if order.is_empty() { return; }
+        let at = self.focused_element(window).and_then(|e| order.iter().position(|&o| o == e));
+        let next = match (at, back) {
+            (None, false) => 0,
+            (None, true) => order.len() - 1,
+            (Some(i), false) => (i + 1) % order.len(),
+            (Some(i), true) => (i + order.len() - 1) % order.len(),
+        };
+        self.set_focus(window, Some(order[next]), time, trees, out);
+    }
+
+    /// `window` is gone: drop its focus and any touch on it.
+    pub fn forget_window(&mut self, window: WindowId) {
+        if self.focus == Some(window) { self.focus = None; }
+        self.focused.retain(|(w, _)| *w != window);
+        self.contacts.retain(|k| k.window.map(|w| w.0) != Some(window));
+        if self.pinch.as_ref().is_some_and(|p| p.window.0 == window) { self.pinch = None; }
+    }
+}
+
+// ─── keys ─────────────────────────────────────────────────────────────────────
+
+pub const KEY_TAB:        u16 = 15;
+pub const KEY_LEFTCTRL:   u16 = 29;
+pub const KEY_LEFTSHIFT:  u16 = 42;
+pub const KEY_RIGHTSHIFT: u16 = 54;
+pub const KEY_LEFTALT:    u16 = 56;
+pub const KEY_RIGHTCTRL:  u16 = 97;
+pub const KEY_RIGHTALT:   u16 = 100;
+
+/// US layout, by Linux key code.
+const PLAIN:   &[u8; 58] = b"\0\x1b1234567890-=\x08\tqwertyuiop[]\n\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+const SHIFTED: &[u8; 58] = b"\0\x1b!@#$%^&*()_+\x08\tQWERTYUIOP{}\n\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ";
+
+/// The character key `code` types, if any.
+pub fn keymap(code: u16, shift: bool) -> Option<char> {
+    let table = if shift { SHIFTED } else { PLAIN };
+    table.get(code as usize).copied().filter(|&b| b != 0).map(char::from)
+}
@@ -0,0 +1,450 @@
+//! SurakshaOS UI
+//! The compositor that puts apps' windows on the screen.  Each window is
+//! a layer with its own pixels; apps draw into their windows, and the
+//! areas they change are damage.  Frames are paced by the display: at
+//! each vblank, if anything is damaged, one frame is composed — only the
+//! damaged areas, layers blended over one another — and handed to the
+//! display, which shows it at the next.  So the screen never updates
+//! faster than the display refreshes, and a frame whose composing runs
+//! past the next vblank is counted as missed.
+//!
+//! The display driver attaches with `attach` and calls `vblank` from its
+//! vblank interrupt.  One with no such interrupt (virtio-gpu has none)
+//! must call it from a timer of its own at its refresh rate.
+//!
+//! Putting windows up needs a Display capability with WRITE; a window at
+//! SYSTEM_Z or above (status bar, lock screen, permission prompts) needs
+//! CONTROL, so no app can draw over them.  A process only touches its own
+//! windows, and they go when it exits.
+//!
+//! Text is laid out by `text` — bidi, shaping of complex scripts, line
+//! breaking — in the TrueType fonts of text::FONT_DIR, whose glyphs
+//! `raster` renders and caches; `draw_text` puts it in a window.
+//!
+//! Input from the drivers is routed by `input` to windows and, down the
+//! `element` tree an app gives its window with `set_elements`, to
+//! elements: each event goes to the handler of the deepest element that
+//! takes it, then up the tree until one deals with it.  What no handler
+//! deals with is queued for the window's owner, which takes it with
+//! `next_event` or runs `event_loop`.  Taking events needs the Display
+//! capability with READ.
+
+pub mod compositor;
+pub mod element;
+pub mod font;
+pub mod input;
+pub mod raster;
+pub mod text;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::process::{self, ProcessId};
+pub use compositor::{Compositor, LayerId as WindowId, Rect};
+pub use element::{Accepts, ElementId, UiElement};
+pub use input::{EventKind, InputEvent, UiEvent};
+
+/// Windows at this z or above belong to the system UI.
+pub const SYSTEM_Z: i32 = 1000;
+/// Largest window side, in pixels.
+pub const MAX_WINDOW_SIDE: u32 = 8192;
+/// Events queued for a process before the oldest are dropped.
+pub const MAX_QUEUED_EVENTS: usize = 256;
+
+/// The screen, as its driver drives it.
+pub trait Display: Send {
+    /// Width and height in pixels.
+    fn size(&self) -> (u32, u32);
+    /// Refresh rate, in millihertz.
+    fn refresh_mhz(&self) -> u32;
+    /// Copy `rect` of `frame` (premultiplied ARGB, `stride` pixels a row)
+    /// to the display's buffer; virtio-gpu's TRANSFER_TO_HOST_2D.
+    fn update(&mut self, frame: &[u32], stride: u32, rect: Rect) -> Result<(), &'static str>;
+    /// Show what `update` has copied, from the next vblank; virtio-gpu's
+    /// RESOURCE_FLUSH.
+    fn present(&mut self, damage: &[Rect]) -> Result<(), &'static str>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    pub vblanks:        u64,
+    pub frames:         u64,
+    /// Frames composed past the vblank after the one they started at.
+    pub missed:         u64,
+    pub last_compose_us: u64,
+    pub max_compose_us: u64,
+}
+
+/// A window, for listing.
+#[derive(Debug, Clone, Copy)]
+pub struct WindowInfo {
+    pub id:      WindowId,
+    pub owner:   ProcessId,
+    pub rect:    Rect,
+    pub z:       i32,
+    pub opacity: u8,
+    pub visible: bool,
+}
+
+struct Ui {
+    pid:        Option<ProcessId>,
+    display:    Option<Box<dyn Display>>,
+    compositor: Option<Compositor>,
+    next_id:    WindowId,
+    stats:      FrameStats,
+    /// The element tree of each window that has one.
+    trees:      Vec<(WindowId, UiElement)>,
+    router:     input::Router,
+    /// Events no handler dealt with, for each process they are for.
+    queues:     Vec<(ProcessId, VecDeque<UiEvent>)>,
+}
+
+static UI: Mutex<Ui> = Mutex::new(Ui { pid: None, display: None, compositor: None, next_id: 1, stats: FrameStats {
+    vblanks: 0, frames: 0, missed: 0, last_compose_us: 0, max_compose_us: 0,
+}, trees: Vec::new(), router: input::Router::new(), queues: Vec::new() });
+/// Vblanks so far, counted in the interrupt.
+static VBLANKS: AtomicU64 = AtomicU64::new(0);
+/// Something is damaged and waits for a vblank.
+static DIRTY: AtomicBool = AtomicBool::new(false);
+/// Woken when an event is queued.
+static EVENTS: process::WaitQueue = process::WaitQueue::new();
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("compositor")?;
+    UI.lock().pid = Some(pid);
+    match text::load_fonts() {
+        0 => crate::warn!("ui: no fonts in {}", text::FONT_DIR),
+        n => crate::info!("ui: {} fonts", n),
+    }
+    Ok(())
+}
+
+/// Show windows on `display`.  The whole screen is composed afresh.
+pub fn attach(display: Box<dyn Display>) {
+    let mut ui = UI.lock();
+    let (w, h) = display.size();
+    crate::info!("ui: {}x{} display at {}.{:03} Hz", w, h, display.refresh_mhz() / 1000, display.refresh_mhz() % 1000);
+    let mut c = Compositor::new(w, h);
+    // Keep the windows of a display attached before
+    if let Some(old) = ui.compositor.take() {
+        for l in old.layers() {
+            let mut layer = compositor::Layer::new(l.id, l.owner, l.rect, l.z);
+            (layer.opacity, layer.visible, layer.opaque) = (l.opacity, l.visible, l.opaque);
+            c.add(layer);
+            c.write(l.id, Rect::new(0, 0, l.rect.w, l.rect.h), l.pixels()).ok();
+        }
+    }
+    ui.compositor = Some(c);
+    ui.display = Some(display);
+    DIRTY.store(true, Ordering::Relaxed);
+}
+
+/// The display's vblank; called from its interrupt.
+pub fn vblank() {
+    VBLANKS.fetch_add(1, Ordering::Relaxed);
+    if DIRTY.swap(false, Ordering::Relaxed) {
+        process::defer(frame);
+    }
+}
+
+/// Compose and present one frame.
+fn frame() {
+    let mut ui = UI.lock();
+    let ui = &mut *ui;
+    let (Some(display), Some(c)) = (ui.display.as_mut(), ui.compositor.as_mut()) else { return };
+    let started = VBLANKS.load(Ordering::Relaxed);
+    let t0 = crate::arch::read_mtime();
+    let damage = c.compose();
+    if damage.is_empty() { return; }
+    let result = damage.iter().try_for_each(|&r| display.update(c.frame(), c.width, r))
+        .and_then(|_| display.present(&damage));
+    if let Err(e) = result { crate::warn!("ui: display update failed: {}", e); }
+    let us = crate::arch::ticks_to_us(crate::arch::read_mtime() - t0);
+    let s = &mut ui.stats;
+    s.frames += 1;
+    s.last_compose_us = us;
+    s.max_compose_us = s.max_compose_us.max(us);
+    if VBLANKS.load(Ordering::Relaxed) != started { s.missed += 1; }
+}
+
+/// An event to hand to its window's owner.
+struct Delivery {
+    owner:    ProcessId,
+    event:    UiEvent,
+    /// From the element it is for up to the root.
+    handlers: Vec<element::Handler>,
+}
+
+/// Find who each routed event goes to.
+fn deliveries(ui: &Ui, routed: Vec<input::Routed>) -> Vec<Delivery> {
+    let Some(c) = ui.compositor.as_ref() else { return Vec::new() };
+    routed.into_iter().filter_map(|r| {
+        let owner = c.layer(r.window)?.owner;
+        let tree = ui.trees.iter().find(|(w, _)| *w == r.window).map(|(_, t)| t);
+        let path = match (tree, r.path.last()) {
+            (Some(t), Some(&last)) => t.path_to(last).unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let target = path.iter().rposition(|e| e.accepts.contains(r.need));
+        let handlers = target.map(|i| path[..=i].iter().rev().filter_map(|e| e.handler).collect()).unwrap_or_default();
+        let event = UiEvent { window: r.window, element: target.map(|i| path[i].id), time_ms: r.time, kind: r.kind };
+        Some(Delivery { owner, event, handlers })
+    }).collect()
+}
+
+/// Run each event's handlers, as its owner, until one deals with it;
+/// queue it for the owner if none does.
+fn deliver(deliveries: Vec<Delivery>) {
+    let mut queued = false;
+    for d in deliveries {
+        if process::run_as(d.owner, || d.handlers.iter().any(|h| h(&d.event))) { continue; }
+        let mut ui = UI.lock();
+        let q = match ui.queues.iter().position(|(p, _)| *p == d.owner) {
+            Some(i) => &mut ui.queues[i].1,
+            None => {
+                ui.queues.push((d.owner, VecDeque::new()));
+                &mut ui.queues.last_mut().unwrap().1
+            }
+        };
+        if q.len() >= MAX_QUEUED_EVENTS { q.pop_front(); }
+        q.push_back(d.event);
+        queued = true;
+    }
+    if queued { EVENTS.wake_all(); }
+}
+
+/// Route what the drivers have reported; deferred from `input::report`.
+fn dispatch_input() {
+    let raw = input::take_raw();
+    if raw.is_empty() { return; }
+    let ds = {
+        let mut ui = UI.lock();
+        let ui = &mut *ui;
+        let Some(c) = ui.compositor.as_ref() else { return };
+        let mut routed = Vec::new();
+        for (event, time) in raw { ui.router.route(event, time, c, &ui.trees, &mut routed); }
+        deliveries(ui, routed)
+    };
+    deliver(ds);
+}
+
+fn authorize(pid: ProcessId, cap: &Capability, z: i32) -> Result<(), &'static str> {
+    let perms = if z >= SYSTEM_Z { Permissions::CONTROL } else { Permissions::WRITE };
+    capability::validate(pid, cap, CapabilityType::Display, perms)
+}
+
+fn check_rect(r: Rect) -> Result<(), &'static str> {
+    let side = MAX_WINDOW_SIDE as i32;
+    if r.is_empty() || r.w > MAX_WINDOW_SIDE || r.h > MAX_WINDOW_SIDE || r.x.abs() > side || r.y.abs() > side {
+        return Err("bad window geometry");
+    }
+    Ok(())
+}
+
+/// Do `f` to the caller's window `id`, after checking `cap`, and have the
+/// next vblank compose what it damaged.
+fn with_window<T>(cap: &Capability, id: WindowId, f: impl FnOnce(&mut Compositor) -> Result<T, &'static str>) -> Result<T, &'static str> {
+    let pid = process::current_pid();
+    let mut ui = UI.lock();
+    let c = ui.compositor.as_mut().ok_or("no display")?;
+    let w = c.layer(id).ok_or("no such window")?;
+    if w.owner != pid { return Err("not the caller's window"); }
+    authorize(pid, cap, w.z)?;
+    let r = f(c)?;
+    if c.has_damage() { DIRTY.store(true, Ordering::Relaxed); }
+    Ok(r)
+}
+
+/// Open a window at `rect` on the screen, stacked at `z`; it starts fully
+/// transparent.  `cap` must be the caller's Display capability.
+pub fn create_window(cap: &Capability, rect: Rect, z: i32) -> Result<WindowId, &'static str> {
+    let pid = process::current_pid();
+    authorize(pid, cap, z)?;
+    check_rect(rect)?;
+    let mut ui = UI.lock();
+    let id = ui.next_id;
+    let c = ui.compositor.as_mut().ok_or("no display")?;
+    c.add(compositor::Layer::new(id, pid, rect, z));
+    ui.next_id += 1;
+    DIRTY.store(true, Ordering::Relaxed);
+    Ok(id)
+}
+
+pub fn destroy_window(cap: &Capability, id: WindowId) -> Result<(), &'static str> {
+    with_window(cap, id, |c| { c.remove(id); Ok(()) })?;
+    forget_windows(&mut UI.lock(), &[id]);
+    Ok(())
+}
+
+fn forget_windows(ui: &mut Ui, ids: &[WindowId]) {
+    ui.trees.retain(|(w, _)| !ids.contains(w));
+    for id in ids { ui.router.forget_window(*id); }
+    for (_, q) in &mut ui.queues { q.retain(|e| !ids.contains(&e.window)); }
+}
+
+/// Copy `pixels`, premultiplied ARGB `area.w` a row, into `area` of the
+/// window, in its own coordinates.
+pub fn write_pixels(cap: &Capability, id: WindowId, area: Rect, pixels: &[u32]) -> Result<(), &'static str> {
+    with_window(cap, id, |c| c.write(id, area, pixels))
+}
+
+pub fn fill(cap: &Capability, id: WindowId, area: Rect, color: u32) -> Result<(), &'static str> {
+    with_window(cap, id, |c| c.fill(id, area, color))
+}
+
+/// Lay `text` out at `px` pixels to the em, in lines no wider than
+/// `max_width` if given, and draw it in `color` (premultiplied ARGB) with
+/// its top left at (`x`, `y`) of the window.  Returns the layout, for the
+/// caller to place what follows.
+#[allow(clippy::too_many_arguments)]
+pub fn draw_text(cap: &Capability, id: WindowId, x: i32, y: i32, text: &str, px: u16, color: u32, max_width: Option<u32>)
+    -> Result<text::Layout, &'static str>
+{
+    let layout = text::layout(text, px, max_width);
+    // Glyphs may reach a little past the layout's box
+    let m = layout.px as i32;
+    let area = Rect::new(x - m, y - m, layout.width + 2 * m as u32, layout.height + 2 * m as u32);
+    with_window(cap, id, |c| c.draw(id, area, |pixels, stride, rows| text::draw(&layout, color, x, y, pixels, stride, rows)))?;
+    Ok(layout)
+}
+
+/// Route the window's input to the elements of `root`, in its own
+/// coordinates, in place of any it had.
+pub fn set_elements(cap: &Capability, id: WindowId, root: UiElement) -> Result<(), &'static str> {
+    if root.count() > element::MAX_ELEMENTS { return Err("too many elements"); }
+    with_window(cap, id, |_| Ok(()))?;
+    let mut ui = UI.lock();
+    ui.trees.retain(|(w, _)| *w != id);
+    ui.trees.push((id, root));
+    Ok(())
+}
+
+/// Give the window keyboard focus.  Taking it from another process's
+/// window needs the Display capability with CONTROL; otherwise focus only
+/// moves by the user's touch.
+pub fn focus_window(cap: &Capability, id: WindowId) -> Result<(), &'static str> {
+    let pid = process::current_pid();
+    with_window(cap, id, |_| Ok(()))?;
+    let ds = {
+        let mut ui = UI.lock();
+        let ui = &mut *ui;
+        let c = ui.compositor.as_ref().ok_or("no display")?;
+        let holder = ui.router.focus().and_then(|w| c.layer(w)).map(|l| l.owner);
+        if holder.is_some_and(|o| o != pid) {
+            capability::validate(pid, cap, CapabilityType::Display, Permissions::CONTROL)?;
+        }
+        let mut routed = Vec::new();
+        ui.router.set_focus(id, None, crate::arch::uptime_millis(), &ui.trees, &mut routed);
+        deliveries(ui, routed)
+    };
+    deliver(ds);
+    Ok(())
+}
+
+/// Give `element` of the window the focus within it; the window's keys go
+/// to it whenever the window has the focus.
+pub fn focus_element(cap: &Capability, id: WindowId, element: ElementId) -> Result<(), &'static str> {
+    with_window(cap, id, |_| Ok(()))?;
+    let ds = {
+        let mut ui = UI.lock();
+        let ui = &mut *ui;
+        let tree = ui.trees.iter().find(|(w, _)| *w == id).map(|(_, t)| t).ok_or("window has no elements")?;
+        if !tree.path_to(element).and_then(|p| p.last().map(|e| e.focusable)).unwrap_or(false) {
+            return Err("no such focusable element");
+        }
+        let mut routed = Vec::new();
+        if ui.router.focus() == Some(id) {
+            ui.router.set_focus(id, Some(element), crate::arch::uptime_millis(), &ui.trees, &mut routed);
+        } else {
+            ui.router.remember_focus(id, element);
+        }
+        deliveries(ui, routed)
+    };
+    deliver(ds);
+    Ok(())
+}
+
+/// The caller's next queued event, if there is one.  `cap` must be its
+/// Display capability, with READ.
+pub fn poll_event(cap: &Capability) -> Result<Option<UiEvent>, &'static str> {
+    let pid = process::current_pid();
+    capability::validate(pid, cap, CapabilityType::Display, Permissions::READ)?;
+    let mut ui = UI.lock();
+    Ok(ui.queues.iter_mut().find(|(p, _)| *p == pid).and_then(|(_, q)| q.pop_front()))
+}
+
+/// Wait for the caller's next event, until uptime reaches `deadline_ms`
+/// (None: for as long as it takes).
+pub fn next_event(cap: &Capability, deadline_ms: Option<u64>) -> Result<Option<UiEvent>, &'static str> {
+    capability::validate(process::current_pid(), cap, CapabilityType::Display, Permissions::READ)?;
+    Ok(EVENTS.wait_until(deadline_ms, || poll_event(cap).ok().flatten()))
+}
+
+/// Hand the caller's events to `f`, as they come, until it returns false.
+pub fn event_loop(cap: &Capability, mut f: impl FnMut(&UiEvent) -> bool) -> Result<(), &'static str> {
+    loop {
+        if let Some(e) = next_event(cap, None)? {
+            if !f(&e) { return Ok(()); }
+        }
+    }
+}
+
+/// Move or resize the window; resizing clears it.
+pub fn set_rect(cap: &Capability, id: WindowId, rect: Rect) -> Result<(), &'static str> {
+    check_rect(rect)?;
+    with_window(cap, id, |c| c.set_rect(id, rect))
+}
+
+pub fn set_z(cap: &Capability, id: WindowId, z: i32) -> Result<(), &'static str> {
+    authorize(process::current_pid(), cap, z)?;
+    with_window(cap, id, |c| c.set_z(id, z))
+}
+
+/// Fade the window; `opaque` says its every pixel has full alpha, so
+/// nothing under it need be drawn.
+pub fn set_opacity(cap: &Capability, id: WindowId, opacity: u8, opaque: bool) -> Result<(), &'static str> {
+    with_window(cap, id, |c| c.set_opacity(id, opacity, opaque))
+}
+
+pub fn set_visible(cap: &Capability, id: WindowId, visible: bool) -> Result<(), &'static str> {
+    with_window(cap, id, |c| c.set_visible(id, visible))
+}
+
+/// Close `pid`'s windows; it has exited.
+pub fn release(pid: ProcessId) {
+    let mut ui = UI.lock();
+    let Some(c) = ui.compositor.as_mut() else { return };
+    let ids: Vec<WindowId> = c.layers().iter().filter(|l| l.owner == pid).map(|l| l.id).collect();
+    for id in &ids { c.remove(*id); }
+    if !ids.is_empty() { DIRTY.store(true, Ordering::Relaxed); }
+    forget_windows(&mut ui, &ids);
+    ui.queues.retain(|(p, _)| *p != pid);
+}
+
+/// The window with keyboard focus, if any.
+pub fn focused_window() -> Option<WindowId> {
+    UI.lock().router.focus()
+}
+
+/// Windows, bottom to top.
+pub fn windows() -> Vec<WindowInfo> {
+    let ui = UI.lock();
+    let Some(c) = ui.compositor.as_ref() else { return Vec::new() };
+    c.layers().iter().map(|l| WindowInfo { id: l.id, owner: l.owner, rect: l.rect, z: l.z, opacity: l.opacity, visible: l.visible }).collect()
+}
+
+/// The display's size and refresh rate (mHz), if one is attached.
+pub fn display_mode() -> Option<(u32, u32, u32)> {
+    let ui = UI.lock();
+    let d = ui.display.as_ref()?;
+    let (w, h) = d.size();
+    Some((w, h, d.refresh_mhz()))
+}
+
+pub fn stats() -> FrameStats {
+    FrameStats { vblanks: VBLANKS.load(Ordering::Relaxed), ..UI.lock().stats }
+}
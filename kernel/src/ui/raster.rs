@@ -0,0 +1,181 @@
+//! Glyph rasterization: an outline scaled to a pixel size becomes an
+//! 8-bit coverage bitmap, by accumulating each edge's signed area along
+//! its rows — exact antialiasing with no supersampling.  Quadratic curves
+//! are flattened into lines first.
+//!
+//! Rasterized glyphs are cached by font, glyph and size, up to
+//! CACHE_BYTES of bitmaps; the least recently used go first.
+
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::font::{Font, Point, Segment};
+
+/// Bitmap bytes the glyph cache holds.
+pub const CACHE_BYTES: usize = 1 << 20;
+/// Largest pixel size rasterized.
+pub const MAX_PX: u16 = 512;
+
+/// A glyph's coverage, 0–255 a pixel.
+#[derive(Debug)]
+pub struct Bitmap {
+    pub width:  u32,
+    pub height: u32,
+    /// Offset of the bitmap's top left from the pen position on the
+    /// baseline, y down.
+    pub left:   i32,
+    pub top:    i32,
+    pub pixels: Vec<u8>,
+}
+
+/// Signed-area accumulation buffer, a cell wider than the bitmap.
+struct Canvas {
+    w:   usize,
+    h:   usize,
+    acc: Vec<f32>,
+}
+
+impl Canvas {
+    fn line(&mut self, p0: Point, p1: Point) {
+        if (p0.y - p1.y).abs() <= f32::EPSILON { return; }
+        let (dir, p0, p1) = if p0.y < p1.y { (1.0, p0, p1) } else { (-1.0, p1, p0) };
+        let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+        let mut x = p0.x;
+        if p0.y < 0.0 { x -= p0.y * dxdy; }
+        let y_end = (libm::ceilf(p1.y) as usize).min(self.h);
+        for y in (p0.y.max(0.0) as usize)..y_end {
+            let row = y * self.w;
+            let dy = ((y + 1) as f32).min(p1.y) - (y as f32).max(p0.y);
+            let xnext = x + dxdy * dy;
+            let d = dy * dir;
+            let (x0, x1) = if x < xnext { (x, xnext) } else { (xnext, x) };
+            let x0f = libm::floorf(x0);
+            let x0i = (x0f as usize).min(self.w - 2);
+            let x1i = (libm::ceilf(x1) as usize).min(self.w - 1);
+            if x1i <= x0i + 1 {
+                // The edge stays within one cell on this row
+                let xm = 0.5 * (x + xnext) - x0f;
+                self.acc[row + x0i] += d - d * xm;
+                self.acc[row + x0i + 1] += d * xm;
+            } else {
+                let s = 1.0 / (x1 - x0);
+                let x0r = x0 - x0f;
+                let a0 = 0.5 * s * (1.0 - x0r) * (1.0 - x0r);
+                let x1r = x1 - libm::ceilf(x1) + 1.0;
+                let am = 0.5 * s * x1r * x1r;
+                self.acc[row + x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    self.acc[row + x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0r);
+                    self.acc[row + x0i + 1] += d * (a1 - a0);
+                    for xi in x0i + 2..x1i - 1 {
+                        self.acc[row + xi] += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    self.acc[row + x1i - 1] += d * (1.0 - a2 - am);
+                }
+                self.acc[row + x1i] += d * am;
+            }
+            x = xnext;
+        }
+    }
+
+    fn quad(&mut self, p0: Point, c: Point, p1: Point) {
+        // Enough pieces that each strays under a fifth of a pixel
+        let (ddx, ddy) = (p0.x - 2.0 * c.x + p1.x, p0.y - 2.0 * c.y + p1.y);
+        let dd = libm::sqrtf(ddx * ddx + ddy * ddy);
+        let n = (1 + libm::sqrtf(dd * 1.25) as usize).min(64);
+        let mut prev = p0;
+        for k in 1..=n {
+            let t = k as f32 / n as f32;
+            let u = 1.0 - t;
+            let p = Point { x: u * u * p0.x + 2.0 * u * t * c.x + t * t * p1.x, y: u * u * p0.y + 2.0 * u * t * c.y + t * t * p1.y };
+            self.line(prev, p);
+            prev = p;
+        }
+    }
+
+    fn coverage(&self, width: usize) -> Vec<u8> {
+        let mut out = Vec::with_capacity(width * self.h);
+        for y in 0..self.h {
+            let mut sum = 0.0f32;
+            for x in 0..self.w {
+                sum += self.acc[y * self.w + x];
+                if x < width { out.push((sum.abs().min(1.0) * 255.0 + 0.5) as u8); }
+            }
+        }
+        out
+    }
+}
+
+/// Rasterize `glyph` of `font` at `px` pixels to the em.
+pub fn rasterize(font: &Font, glyph: u16, px: u16) -> Bitmap {
+    let outline = font.outline(glyph);
+    let scale = px.min(MAX_PX) as f32 / font.units_per_em as f32;
+    let empty = Bitmap { width: 0, height: 0, left: 0, top: 0, pixels: Vec::new() };
+    let mut pts = outline.iter().flat_map(|s| match *s {
+        Segment::Line(a, b) => [a, b, b],
+        Segment::Quad(a, c, b) => [a, c, b],
+    });
+    let Some(first) = pts.next() else { return empty };
+    let (mut x0, mut y0, mut x1, mut y1) = (first.x, first.y, first.x, first.y);
+    for p in pts {
+        (x0, y0, x1, y1) = (x0.min(p.x), y0.min(p.y), x1.max(p.x), y1.max(p.y));
+    }
+    let left = libm::floorf(x0 * scale) as i32;
+    let top = libm::ceilf(y1 * scale) as i32;
+    let width = (libm::ceilf(x1 * scale) as i32 - left).max(1) as usize;
+    let height = (top - libm::floorf(y0 * scale) as i32).max(1) as usize;
+    if width > 4 * MAX_PX as usize || height > 4 * MAX_PX as usize { return empty; }
+    let mut canvas = Canvas { w: width + 2, h: height, acc: vec![0.0; (width + 2) * height] };
+    let map = |p: Point| Point { x: p.x * scale - left as f32, y: top as f32 - p.y * scale };
+    for s in &outline {
+        match *s {
+            Segment::Line(a, b) => canvas.line(map(a), map(b)),
+            Segment::Quad(a, c, b) => canvas.quad(map(a), map(c), map(b)),
+        }
+    }
+    Bitmap { width: width as u32, height: height as u32, left, top: -top, pixels: canvas.coverage(width) }
+}
+
+// ─── cache ────────────────────────────────────────────────────────────────────
+
+/// Font, glyph and pixel size.
+type Key = (u32, u16, u16);
+
+/// Key -> bitmap, with when it was last used.
+struct Cache {
+    entries: Vec<(Key, Arc<Bitmap>, u64)>,
+    bytes:   usize,
+    clock:   u64,
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache { entries: Vec::new(), bytes: 0, clock: 0 });
+
+/// `glyph` of font number `font_id` at `px`, from the cache if it is there.
+pub fn glyph(font_id: u32, font: &Font, glyph: u16, px: u16) -> Arc<Bitmap> {
+    let key = (font_id, glyph, px);
+    {
+        let mut c = CACHE.lock();
+        c.clock += 1;
+        let now = c.clock;
+        if let Some(e) = c.entries.iter_mut().find(|e| e.0 == key) {
+            e.2 = now;
+            return Arc::clone(&e.1);
+        }
+    }
+    let bitmap = Arc::new(rasterize(font, glyph, px));
+    let mut c = CACHE.lock();
+    c.bytes += bitmap.pixels.len();
+    while c.bytes > CACHE_BYTES && !c.entries.is_empty() {
+        let oldest = c.entries.iter().enumerate().min_by_key(|(_, e)| e.2).map(|(i, _)| i).unwrap_or(0);
+        let (_, old, _) = c.entries.swap_remove(oldest);
+        c.bytes -= old.pixels.len();
+    }
+    let now = c.clock;
+    c.entries.push((key, Arc::clone(&bitmap), now));
+    bitmap
+}
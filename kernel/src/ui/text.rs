@@ -0,0 +1,727 @@
+//! Text: the fonts, and laying text out in them — bidi, script runs,
+//! shaping, line breaking — for UI elements to draw.
+//!
+//! Fonts are the TrueType files in FONT_DIR, in name order: the first is
+//! the default, the rest fall back for characters it lacks, so a Latin
+//! font first and Noto Sans Devanagari, Bengali, Tamil and the like after
+//! it covers the scheduled languages.
+//!
+//! Shaping follows the OpenType script specifications in outline:
+//!
+//! - Indic scripts (Devanagari, Bengali, Gurmukhi, Gujarati, Odia, Tamil,
+//!   Telugu, Kannada, Malayalam): split vowels are decomposed and the text
+//!   cut into syllables; in each, the base consonant is found, a pre-base
+//!   matra moved before the consonants, and the font's basic features
+//!   applied in order — nukt, akhn, rphf on a reph, half before the base,
+//!   blwf and pstf after it, vatu, cjct — which form the conjuncts; the
+//!   reph then goes to the end of the syllable, and the presentation
+//!   features (pres, abvs, blws, psts, haln) finish it.
+//! - Arabic script (Urdu, Kashmiri, Sindhi): each letter takes its isol,
+//!   init, medi or fina form from how it joins its neighbours.
+//! - Everything else gets the font's ligatures.
+//!
+//! Bidi is the Unicode algorithm without explicit embeddings, overrides
+//! or isolates (rules X1–X10): weak and neutral types are resolved, each
+//! character gets level 0, 1 or 2, and each line's runs are reordered by
+//! level, mirroring brackets in right-to-left runs.
+//!
+//! Lines break after spaces, or, for a word wider than the line, between
+//! clusters; never inside a syllable.
+
+use alloc::format;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::compositor;
+use super::font::{tag, Font, Glyph};
+use super::raster;
+use crate::fs;
+
+/// Where the system fonts are.
+pub const FONT_DIR: &str = "/system/fonts";
+
+/// Fonts by id: the default first, then the fallbacks.
+static FONTS: Mutex<Vec<Font>> = Mutex::new(Vec::new());
+
+/// Add a font after those there are; returns its id.
+pub fn add_font(data: Arc<[u8]>) -> Result<u32, &'static str> {
+    let font = Font::parse(data)?;
+    let mut fonts = FONTS.lock();
+    fonts.push(font);
+    Ok(fonts.len() as u32 - 1)
+}
+
+/// Load the fonts in FONT_DIR, in name order.  Returns how many loaded.
+pub fn load_fonts() -> usize {
+    let Ok(mut files) = fs::list_dir(FONT_DIR) else { return 0 };
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut n = 0;
+    for f in files.iter().filter(|f| !f.is_dir && (f.name.ends_with(".ttf") || f.name.ends_with(".otf"))) {
+        match fs::map_file(&format!("{}/{}", FONT_DIR, f.name)).and_then(add_font) {
+            Ok(_) => n += 1,
+            Err(e) => crate::warn!("ui: font {}: {}", f.name, e),
+        }
+    }
+    n
+}
+
+// ─── scripts ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    /// Spaces, punctuation, digits and marks: whatever is around them.
+    Common,
+    Latin,
+    Arabic,
+    Hebrew,
+    Devanagari,
+    Bengali,
+    Gurmukhi,
+    Gujarati,
+    Oriya,
+    Tamil,
+    Telugu,
+    Kannada,
+    Malayalam,
+    Other,
+}
+
+impl Script {
+    pub fn of(c: char) -> Script {
+        match c as u32 {
+            0x41..=0x5a | 0x61..=0x7a | 0xc0..=0x24f => Script::Latin,
+            0x0590..=0x05ff | 0xfb1d..=0xfb4f => Script::Hebrew,
+            0x0600..=0x06ff | 0x0750..=0x077f | 0xfb50..=0xfdff | 0xfe70..=0xfeff => Script::Arabic,
+            // Danda and double danda serve every Indic script
+            0x0964 | 0x0965 => Script::Common,
+            0x0900..=0x097f | 0xa8e0..=0xa8ff => Script::Devanagari,
+            0x0980..=0x09ff => Script::Bengali,
+            0x0a00..=0x0a7f => Script::Gurmukhi,
+            0x0a80..=0x0aff => Script::Gujarati,
+            0x0b00..=0x0b7f => Script::Oriya,
+            0x0b80..=0x0bff => Script::Tamil,
+            0x0c00..=0x0c7f => Script::Telugu,
+            0x0c80..=0x0cff => Script::Kannada,
+            0x0d00..=0x0d7f => Script::Malayalam,
+            0x0300..=0x036f | 0x200c | 0x200d => Script::Common,
+            _ if c.is_alphabetic() => Script::Other,
+            _ => Script::Common,
+        }
+    }
+
+    /// The script's Unicode block, for the Indic scripts.
+    fn indic_block(self) -> Option<u32> {
+        Some(match self {
+            Script::Devanagari => 0x0900,
+            Script::Bengali    => 0x0980,
+            Script::Gurmukhi   => 0x0a00,
+            Script::Gujarati   => 0x0a80,
+            Script::Oriya      => 0x0b00,
+            Script::Tamil      => 0x0b80,
+            Script::Telugu     => 0x0c00,
+            Script::Kannada    => 0x0c80,
+            Script::Malayalam  => 0x0d00,
+            _ => return None,
+        })
+    }
+
+    /// OpenType script tags, preferred first.
+    fn tags(self) -> [u32; 2] {
+        let (a, b) = match self {
+            Script::Latin      => (b"latn", b"latn"),
+            Script::Arabic     => (b"arab", b"arab"),
+            Script::Hebrew     => (b"hebr", b"hebr"),
+            Script::Devanagari => (b"dev2", b"deva"),
+            Script::Bengali    => (b"bng2", b"beng"),
+            Script::Gurmukhi   => (b"gur2", b"guru"),
+            Script::Gujarati   => (b"gjr2", b"gujr"),
+            Script::Oriya      => (b"ory2", b"orya"),
+            Script::Tamil      => (b"tml2", b"taml"),
+            Script::Telugu     => (b"tel2", b"telu"),
+            Script::Kannada    => (b"knd2", b"knda"),
+            Script::Malayalam  => (b"mlm2", b"mlym"),
+            Script::Common | Script::Other => (b"DFLT", b"DFLT"),
+        };
+        [tag(a), tag(b)]
+    }
+}
+
+/// Each character's script, Common taking that of what it follows (or,
+/// at the start, of what comes after it).
+fn resolve_scripts(chars: &[char]) -> Vec<Script> {
+    let mut scripts: Vec<Script> = chars.iter().map(|&c| Script::of(c)).collect();
+    let mut last = scripts.iter().copied().find(|&s| s != Script::Common).unwrap_or(Script::Latin);
+    for s in scripts.iter_mut() {
+        if *s == Script::Common { *s = last; } else { last = *s; }
+    }
+    scripts
+}
+
+// ─── bidi ─────────────────────────────────────────────────────────────────────
+
+/// Bidi classes, by the algorithm's names for them.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Class { L, R, AL, EN, AN, ES, ET, CS, NSM, B, S, WS, ON }
+
+fn bidi_class(c: char) -> Class {
+    use Class::*;
+    match c as u32 {
+        0x30..=0x39 | 0x06f0..=0x06f9 | 0xb2 | 0xb3 | 0xb9 => EN,
+        0x0660..=0x0669 | 0x066b | 0x066c => AN,
+        0x2b | 0x2d => ES,
+        0x23..=0x25 | 0xa2..=0xa5 | 0xb0 | 0xb1 | 0x20a0..=0x20cf => ET,
+        0x2c | 0x2e | 0x2f | 0x3a | 0xa0 => CS,
+        0x0a | 0x0d | 0x1c..=0x1e | 0x85 | 0x2029 => B,
+        0x09 | 0x0b | 0x1f => S,
+        0x20 | 0x0c | 0x2000..=0x200a | 0x2028 => WS,
+        0x0300..=0x036f | 0x0591..=0x05bd | 0x05bf | 0x05c1 | 0x05c2 | 0x05c4 | 0x05c5 | 0x05c7
+            | 0x0610..=0x061a | 0x064b..=0x065f | 0x0670 | 0x06d6..=0x06dc | 0x06df..=0x06e4
+            | 0x06e7 | 0x06e8 | 0x06ea..=0x06ed | 0x200c | 0x200d => NSM,
+        0x0590..=0x05ff | 0x07c0..=0x085f | 0xfb1d..=0xfb4f => R,
+        0x0600..=0x07bf | 0x0860..=0x08ff | 0xfb50..=0xfdff | 0xfe70..=0xfeff => AL,
+        0x0900..=0x0d7f => L,
+        _ if c.is_alphanumeric() => L,
+        _ => ON,
+    }
+}
+
+/// Resolved embedding levels, paragraph by paragraph.
+fn bidi_levels(chars: &[char]) -> Vec<u8> {
+    let mut levels = vec![0u8; chars.len()];
+    let mut start = 0;
+    for end in 1..=chars.len() {
+        if end == chars.len() || bidi_class(chars[end - 1]) == Class::B {
+            resolve_paragraph(&chars[start..end], &mut levels[start..end]);
+            start = end;
+        }
+    }
+    levels
+}
+
+fn resolve_paragraph(chars: &[char], levels: &mut [u8]) {
+    use Class::*;
+    let mut t: Vec<Class> = chars.iter().map(|&c| bidi_class(c)).collect();
+    // P2, P3: the first strong character sets the paragraph's direction
+    let para = match t.iter().find(|c| matches!(c, L | R | AL)) { Some(R | AL) => 1, _ => 0 };
+    let e = if para == 1 { R } else { L };
+    // W1: marks take the type of what they follow
+    let mut prev = e;
+    for c in t.iter_mut() {
+        if *c == NSM { *c = prev; }
+        prev = *c;
+    }
+    // W2, W3: European digits after Arabic letters are Arabic
+    let mut strong = e;
+    for c in t.iter_mut() {
+        match *c {
+            L | R | AL => strong = *c,
+            EN if strong == AL => *c = AN,
+            _ => {}
+        }
+        if *c == AL { *c = R; }
+    }
+    // W4: a single separator between numbers of one kind
+    for i in 1..t.len().saturating_sub(1) {
+        match (t[i - 1], t[i], t[i + 1]) {
+            (EN, ES | CS, EN) => t[i] = EN,
+            (AN, CS, AN) => t[i] = AN,
+            _ => {}
+        }
+    }
+    // W5: terminators next to European numbers
+    let mut i = 0;
+    while i < t.len() {
+        if t[i] != ET { i += 1; continue; }
+        let s = i;
+        while i < t.len() && t[i] == ET { i += 1; }
+        if (s > 0 && t[s - 1] == EN) || (i < t.len() && t[i] == EN) { t[s..i].fill(EN); }
+    }
+    // W6, W7
+    let mut strong = e;
+    for c in t.iter_mut() {
+        if matches!(*c, ES | ET | CS) { *c = ON; }
+        match *c {
+            L | R => strong = *c,
+            EN if strong == L => *c = L,
+            _ => {}
+        }
+    }
+    // N1, N2: neutrals between two of one direction take it, others the paragraph's
+    let dir = |c: Class| match c { L => Some(L), R | EN | AN => Some(R), _ => None };
+    let mut i = 0;
+    while i < t.len() {
+        if dir(t[i]).is_some() { i += 1; continue; }
+        let s = i;
+        while i < t.len() && dir(t[i]).is_none() { i += 1; }
+        let before = if s == 0 { e } else { dir(t[s - 1]).unwrap_or(e) };
+        let after = if i == t.len() { e } else { dir(t[i]).unwrap_or(e) };
+        t[s..i].fill(if before == after { before } else { e });
+    }
+    // I1, I2
+    for (l, c) in levels.iter_mut().zip(&t) {
+        *l = match (para, c) {
+            (0, R) => 1,
+            (0, EN | AN) => 2,
+            (0, _) => 0,
+            (_, L | EN | AN) => 2,
+            _ => 1,
+        };
+    }
+    // L1: trailing whitespace goes back to the paragraph level
+    for (l, &c) in levels.iter_mut().zip(chars).rev() {
+        if !matches!(bidi_class(c), WS | S | B) { break; }
+        *l = para;
+    }
+}
+
+/// The mirrored form of a bracket, for right-to-left text.
+fn mirror(c: char) -> char {
+    match c {
+        '(' => ')', ')' => '(', '[' => ']', ']' => '[', '{' => '}', '}' => '{',
+        '<' => '>', '>' => '<', '«' => '»', '»' => '«',
+        _ => c,
+    }
+}
+
+// ─── shaping ──────────────────────────────────────────────────────────────────
+
+const MASK_ALL:  u32 = 1 << 0;
+/// Indic: the Ra and halant of a reph.
+const MASK_RPHF: u32 = 1 << 1;
+/// Indic: before the base consonant.
+const MASK_HALF: u32 = 1 << 2;
+/// Indic: after the base consonant.
+const MASK_POST: u32 = 1 << 3;
+/// Indic: a syllable modifier (candrabindu, anusvara, visarga).
+const MASK_MOD:  u32 = 1 << 4;
+/// Arabic joining forms.
+const MASK_ISOL: u32 = 1 << 5;
+const MASK_INIT: u32 = 1 << 6;
+const MASK_MEDI: u32 = 1 << 7;
+const MASK_FINA: u32 = 1 << 8;
+
+/// Apply the lookups of `features`, each to the glyphs its mask picks out.
+fn apply(font: &Font, script: Script, features: &[(&[u8; 4], u32)], glyphs: &mut Vec<Glyph>) {
+    let tags: Vec<u32> = features.iter().map(|(t, _)| tag(t)).collect();
+    for (lookup, ftag) in font.gsub_lookups(&script.tags(), &tags) {
+        let mask = features.iter().find(|(t, _)| tag(t) == ftag).map_or(0, |f| f.1);
+        font.apply_lookup(lookup, glyphs, &|g| g.mask & mask != 0);
+    }
+}
+
+/// Shape a run of one script in one font, in logical order; clusters are
+/// indices into the text, the run starting at `offset`.
+fn shape(font: &Font, script: Script, chars: &[char], offset: usize) -> Vec<Glyph> {
+    if script.indic_block().is_some() { return shape_indic(font, script, chars, offset); }
+    let mut glyphs: Vec<Glyph> = Vec::with_capacity(chars.len());
+    let mut cluster = offset as u32;
+    for (i, &c) in chars.iter().enumerate() {
+        // Marks belong to the cluster of what they follow
+        if i == 0 || bidi_class(c) != Class::NSM { cluster = (offset + i) as u32; }
+        glyphs.push(Glyph { id: font.glyph_index(c), cluster, mask: MASK_ALL });
+    }
+    if script == Script::Arabic { arabic_forms(chars, &mut glyphs); }
+    apply(font, script, &[(b"ccmp", MASK_ALL), (b"locl", MASK_ALL)], &mut glyphs);
+    if script == Script::Arabic {
+        apply(font, script, &[(b"isol", MASK_ISOL), (b"fina", MASK_FINA), (b"medi", MASK_MEDI), (b"init", MASK_INIT)], &mut glyphs);
+    }
+    apply(font, script, &[(b"rlig", MASK_ALL), (b"calt", MASK_ALL), (b"liga", MASK_ALL), (b"clig", MASK_ALL)], &mut glyphs);
+    glyphs
+}
+
+/// How an Arabic-script character joins: (to the previous letter, to
+/// the next), or None if it is transparent (a mark).
+fn joining(c: char) -> Option<(bool, bool)> {
+    let right = (true, false);
+    let dual = (true, true);
+    Some(match c as u32 {
+        0x0610..=0x061a | 0x064b..=0x065f | 0x0670 | 0x06d6..=0x06dc | 0x06df..=0x06e4
+            | 0x06e7 | 0x06e8 | 0x06ea..=0x06ed | 0x200d => return None,
+        0x0622..=0x0625 | 0x0627 | 0x0629 | 0x062f..=0x0632 | 0x0648 | 0x0671..=0x0673 | 0x0675..=0x0677
+            | 0x0688..=0x0699 | 0x06c0 | 0x06c3..=0x06cb | 0x06cd | 0x06cf | 0x06d2 | 0x06d3 | 0x06ee | 0x06ef => right,
+        0x0626 | 0x0628 | 0x062a..=0x062e | 0x0633..=0x063f | 0x0640..=0x0647 | 0x0649 | 0x064a | 0x066e | 0x066f
+            | 0x0678..=0x0687 | 0x069a..=0x06bf | 0x06c1 | 0x06c2 | 0x06cc | 0x06ce | 0x06d0 | 0x06d1
+            | 0x06fa..=0x06fc | 0x06ff | 0x0750..=0x077f => dual,
+        _ => (false, false),
+    })
+}
+
+/// Mark each Arabic letter with the form its neighbours give it.
+fn arabic_forms(chars: &[char], glyphs: &mut [Glyph]) {
+    let joins: Vec<Option<(bool, bool)>> = chars.iter().map(|&c| joining(c)).collect();
+    let mut prev: Option<usize> = None;
+    for i in 0..chars.len() {
+        let Some((_, to_next)) = joins[i] else { continue };
+        let next = (i + 1..chars.len()).find_map(|k| joins[k]);
+        let joins_prev = prev.and_then(|p| joins[p]).is_some_and(|(_, n)| n) && joins[i].is_some_and(|(p, _)| p);
+        let joins_next = to_next && next.is_some_and(|(p, _)| p);
+        glyphs[i].mask |= match (joins_prev, joins_next) {
+            (true, true)   => MASK_MEDI,
+            (true, false)  => MASK_FINA,
+            (false, true)  => MASK_INIT,
+            (false, false) => MASK_ISOL,
+        };
+        prev = Some(i);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cat { Consonant, Vowel, Matra, PreMatra, Nukta, Halant, Modifier, Joiner, Other }
+
+/// The Indic blocks share one layout, so one table serves them all, with
+/// the few differences by script.
+fn category(script: Script, c: char) -> Cat {
+    let cp = c as u32;
+    if cp == 0x200c || cp == 0x200d { return Cat::Joiner; }
+    let Some(off) = script.indic_block().and_then(|b| cp.checked_sub(b)).filter(|&o| o < 0x80) else { return Cat::Other };
+    match (script, off) {
+        (Script::Bengali, 0x4e) => Cat::Consonant, // khanda ta
+        (Script::Gurmukhi, 0x70 | 0x71) => Cat::Modifier,
+        (_, 0x00..=0x03) => Cat::Modifier,
+        (_, 0x04..=0x14 | 0x60 | 0x61 | 0x72..=0x77) => Cat::Vowel,
+        (_, 0x15..=0x39 | 0x58..=0x5f | 0x7a..=0x7f) => Cat::Consonant,
+        (_, 0x3c) => Cat::Nukta,
+        (_, 0x4d) => Cat::Halant,
+        (_, 0x3e..=0x4c | 0x4e | 0x4f | 0x55..=0x57 | 0x62 | 0x63) if pre_base(script, off) => Cat::PreMatra,
+        (_, 0x3e..=0x4c | 0x4e | 0x4f | 0x55..=0x57 | 0x62 | 0x63) => Cat::Matra,
+        _ => Cat::Other,
+    }
+}
+
+/// Whether the matra at block offset `off` is written before the consonants.
+fn pre_base(script: Script, off: u32) -> bool {
+    match script {
+        Script::Devanagari | Script::Gurmukhi | Script::Gujarati => off == 0x3f,
+        Script::Bengali => matches!(off, 0x3f | 0x47 | 0x48),
+        Script::Oriya => off == 0x47,
+        Script::Tamil | Script::Malayalam => matches!(off, 0x46..=0x48),
+        _ => false,
+    }
+}
+
+/// A two-part vowel sign as its parts, pre-base part first.
+fn decompose(script: Script, c: char) -> Option<[char; 2]> {
+    let b = script.indic_block()?;
+    let off = (c as u32).checked_sub(b)?;
+    let (x, y) = match (script, off) {
+        (Script::Bengali, 0x4b) | (Script::Oriya, 0x4b) => (0x47, 0x3e),
+        (Script::Bengali, 0x4c) | (Script::Oriya, 0x4c) => (0x47, 0x57),
+        (Script::Oriya, 0x48) => (0x47, 0x56),
+        (Script::Tamil | Script::Malayalam, 0x4a) => (0x46, 0x3e),
+        (Script::Tamil | Script::Malayalam, 0x4b) => (0x47, 0x3e),
+        (Script::Tamil | Script::Malayalam, 0x4c) => (0x46, 0x57),
+        _ => return None,
+    };
+    Some([char::from_u32(b + x)?, char::from_u32(b + y)?])
+}
+
+/// Whether a Ra and halant starting a syllable become a reph.
+fn has_reph(script: Script) -> bool {
+    matches!(script, Script::Devanagari | Script::Bengali | Script::Gujarati | Script::Oriya | Script::Kannada | Script::Telugu)
+}
+
+/// Whether a consonant after a halant takes a below- or post-base form
+/// rather than being the base: Ra in most scripts, Ya in Bengali
+/// (ya-phala), and Ya, Va and Ha in Gurmukhi.
+fn below_or_post(script: Script, c: char) -> bool {
+    let Some(off) = script.indic_block().and_then(|b| (c as u32).checked_sub(b)) else { return false };
+    match script {
+        Script::Devanagari | Script::Gujarati | Script::Oriya => off == 0x30,
+        Script::Bengali => matches!(off, 0x30 | 0x2f),
+        Script::Gurmukhi => matches!(off, 0x30 | 0x2f | 0x35 | 0x39),
+        _ => false,
+    }
+}
+
+fn shape_indic(font: &Font, script: Script, chars: &[char], offset: usize) -> Vec<Glyph> {
+    let mut cs: Vec<(char, u32)> = Vec::with_capacity(chars.len());
+    for (i, &c) in chars.iter().enumerate() {
+        let at = (offset + i) as u32;
+        match decompose(script, c) {
+            Some([a, b]) => cs.extend([(a, at), (b, at)]),
+            None => cs.push((c, at)),
+        }
+    }
+    let mut out = Vec::with_capacity(cs.len());
+    let mut i = 0;
+    while i < cs.len() {
+        let end = syllable_end(script, &cs, i);
+        out.extend(shape_syllable(font, script, &cs[i..end]));
+        i = end;
+    }
+    out
+}
+
+/// Where the syllable starting at `i` ends: a consonant or vowel, with
+/// the consonants a halant joins to it, then its nukta, matras and
+/// modifiers.
+fn syllable_end(script: Script, cs: &[(char, u32)], i: usize) -> usize {
+    let cat = |k: usize| category(script, cs[k].0);
+    let starts = cat(i);
+    if !matches!(starts, Cat::Consonant | Cat::Vowel) { return i + 1; }
+    let mut k = i + 1;
+    let mut matra = false;
+    while k < cs.len() {
+        match cat(k) {
+            Cat::Nukta | Cat::Modifier | Cat::Joiner => k += 1,
+            Cat::Matra | Cat::PreMatra => { matra = true; k += 1; }
+            Cat::Halant => {
+                k += 1;
+                let j = if k < cs.len() && cat(k) == Cat::Joiner { k + 1 } else { k };
+                if !matra && starts == Cat::Consonant && j < cs.len() && cat(j) == Cat::Consonant { k = j + 1; }
+            }
+            _ => break,
+        }
+    }
+    k
+}
+
+fn shape_syllable(font: &Font, script: Script, syl: &[(char, u32)]) -> Vec<Glyph> {
+    let cluster = syl[0].1;
+    let mut chars: Vec<(char, Cat)> = syl.iter().map(|&(c, _)| (c, category(script, c))).collect();
+    let ra = script.indic_block().and_then(|b| char::from_u32(b + 0x30));
+    let reph = has_reph(script) && chars.len() >= 3 && Some(chars[0].0) == ra
+        && chars[1].1 == Cat::Halant && chars[2].1 == Cat::Consonant;
+    let first = if reph { 2 } else { 0 };
+
+    // The base: the last consonant not taking a below- or post-base form
+    // (the first, in Telugu and Kannada, whose others all do)
+    let consonants: Vec<usize> = (first..chars.len()).filter(|&k| chars[k].1 == Cat::Consonant).collect();
+    let mut base = match consonants.len() {
+        0 => first.min(chars.len() - 1),
+        _ if matches!(script, Script::Telugu | Script::Kannada) => consonants[0],
+        n => {
+            let mut k = n - 1;
+            while k > 0 && below_or_post(script, chars[consonants[k]].0) && chars[consonants[k] - 1].1 == Cat::Halant { k -= 1; }
+            consonants[k]
+        }
+    };
+
+    // Pre-base matras go before the consonants, after any reph
+    let pre: Vec<(char, Cat)> = chars.iter().copied().filter(|c| c.1 == Cat::PreMatra).collect();
+    if !pre.is_empty() {
+        chars.retain(|c| c.1 != Cat::PreMatra);
+        for (k, m) in pre.iter().enumerate() { chars.insert(first + k, *m); }
+        base += pre.len();
+    }
+
+    let mut glyphs: Vec<Glyph> = chars.iter().enumerate().map(|(k, &(c, cat))| {
+        let position = if reph && k < 2 { MASK_RPHF } else if k < base { MASK_HALF } else if k > base { MASK_POST } else { 0 };
+        let modifier = if cat == Cat::Modifier { MASK_MOD } else { 0 };
+        Glyph { id: font.glyph_index(c), cluster, mask: MASK_ALL | position | modifier }
+    }).collect();
+
+    apply(font, script, &[(b"locl", MASK_ALL), (b"ccmp", MASK_ALL)], &mut glyphs);
+    let basic: [(&[u8; 4], u32); 11] = [
+        (b"nukt", MASK_ALL), (b"akhn", MASK_ALL), (b"rphf", MASK_RPHF), (b"rkrf", MASK_ALL),
+        (b"pref", MASK_POST), (b"blwf", MASK_POST), (b"abvf", MASK_POST), (b"half", MASK_HALF),
+        (b"pstf", MASK_POST), (b"vatu", MASK_ALL), (b"cjct", MASK_ALL),
+    ];
+    for feature in basic {
+        apply(font, script, &[feature], &mut glyphs);
+    }
+
+    // A reph the font formed goes to the end, before the modifiers
+    if reph && glyphs.len() > 1 && glyphs[0].mask & MASK_RPHF != 0 && glyphs[1].mask & MASK_RPHF == 0 {
+        let r = glyphs.remove(0);
+        let at = glyphs.iter().rposition(|g| g.mask & MASK_MOD == 0).map_or(0, |p| p + 1);
+        glyphs.insert(at, r);
+    }
+
+    apply(font, script, &[
+        (b"pres", MASK_ALL), (b"abvs", MASK_ALL), (b"blws", MASK_ALL), (b"psts", MASK_ALL),
+        (b"haln", MASK_ALL), (b"calt", MASK_ALL), (b"clig", MASK_ALL),
+    ], &mut glyphs);
+    glyphs
+}
+
+// ─── layout ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy)]
+pub struct PositionedGlyph {
+    pub font:    u32,
+    pub glyph:   u16,
+    /// Pen position: x from the layout's left, y its baseline from the top.
+    pub x:       i32,
+    pub y:       i32,
+    /// Index of the first character of its cluster, in chars.
+    pub cluster: u32,
+}
+
+/// Text laid out in lines, in visual order.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    pub glyphs:      Vec<PositionedGlyph>,
+    pub px:          u16,
+    pub width:       u32,
+    pub height:      u32,
+    pub lines:       u32,
+    pub line_height: u32,
+}
+
+/// A run of text with one level, script and font.
+struct Run {
+    start:  usize,
+    end:    usize,
+    level:  u8,
+    script: Script,
+    font:   usize,
+}
+
+/// A shaped glyph, in logical order.
+struct Item {
+    run:     usize,
+    glyph:   u16,
+    advance: f32,
+    cluster: u32,
+}
+
+/// Lay `text` out at `px` pixels to the em, in lines no wider than
+/// `max_width` if given.
+pub fn layout(text: &str, px: u16, max_width: Option<u32>) -> Layout {
+    let fonts = FONTS.lock().clone();
+    let px = px.clamp(1, raster::MAX_PX);
+    let Some(main) = fonts.first() else { return Layout { px, ..Layout::default() } };
+    let chars: Vec<char> = text.chars().collect();
+    let levels = bidi_levels(&chars);
+    let scripts = resolve_scripts(&chars);
+
+    // Each character in the first font that has it; marks, joiners and
+    // Common characters stay in the font of what they follow
+    let mut font_of = vec![0usize; chars.len()];
+    for (i, &c) in chars.iter().enumerate() {
+        let follows = i > 0 && (Script::of(c) == Script::Common || bidi_class(c) == Class::NSM);
+        font_of[i] = if follows && (bidi_class(c) == Class::NSM || fonts[font_of[i - 1]].has_glyph(c)) {
+            font_of[i - 1]
+        } else {
+            fonts.iter().position(|f| f.has_glyph(c)).unwrap_or(0)
+        };
+    }
+
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..=chars.len() {
+        let key = |k: usize| (levels[k], scripts[k], font_of[k]);
+        if i == chars.len() || key(i) != key(start) {
+            runs.push(Run { start, end: i, level: levels[start], script: scripts[start], font: font_of[start] });
+            start = i;
+        }
+    }
+
+    let mut items = Vec::with_capacity(chars.len());
+    for (ri, r) in runs.iter().enumerate() {
+        let font = &fonts[r.font];
+        let scale = px as f32 / font.units_per_em as f32;
+        let rtl = r.level % 2 == 1;
+        let cs: Vec<char> = chars[r.start..r.end].iter().map(|&c| if rtl { mirror(c) } else { c }).collect();
+        for g in shape(font, r.script, &cs, r.start) {
+            items.push(Item { run: ri, glyph: g.id, advance: font.advance(g.id) as f32 * scale, cluster: g.cluster });
+        }
+    }
+
+    let lines = break_lines(&chars, &items, max_width.map(|w| w as f32));
+    let scale = px as f32 / main.units_per_em as f32;
+    let ascent = libm::ceilf(main.ascent as f32 * scale) as i32;
+    let line_height = libm::ceilf((main.ascent as f32 - main.descent as f32 + main.line_gap as f32) * scale).max(1.0) as u32;
+    let mut out = Layout { glyphs: Vec::with_capacity(items.len()), px, width: 0, height: line_height * lines.len() as u32, lines: lines.len() as u32, line_height };
+    for (n, line) in lines.iter().enumerate() {
+        let y = ascent + (n as u32 * line_height) as i32;
+        let mut x = 0.0f32;
+        for k in visual_order(&runs, &items, line.clone()) {
+            let it = &items[k];
+            if chars[it.cluster as usize] != '\n' {
+                out.glyphs.push(PositionedGlyph { font: runs[it.run].font as u32, glyph: it.glyph, x: libm::roundf(x) as i32, y, cluster: it.cluster });
+                x += it.advance;
+            }
+        }
+        out.width = out.width.max(libm::ceilf(x) as u32);
+    }
+    out
+}
+
+/// Cut the items into lines: at newlines, and where the next cluster
+/// would overflow `max_width`, after the last space if there is one.
+fn break_lines(chars: &[char], items: &[Item], max_width: Option<f32>) -> Vec<core::ops::Range<usize>> {
+    let mut lines = Vec::new();
+    let (mut start, mut width, mut after_space) = (0, 0.0f32, None);
+    let mut i = 0;
+    while i < items.len() {
+        let c = chars[items[i].cluster as usize];
+        if c == '\n' {
+            lines.push(start..i + 1);
+            (start, width, after_space) = (i + 1, 0.0, None);
+            i += 1;
+            continue;
+        }
+        let boundary = i > start && items[i].cluster != items[i - 1].cluster;
+        if let Some(max) = max_width {
+            if boundary && c != ' ' && width + items[i].advance > max {
+                let at = after_space.filter(|&b| b > start).unwrap_or(i);
+                lines.push(start..at);
+                (start, after_space) = (at, None);
+                width = items[at..i].iter().map(|it| it.advance).sum();
+                continue;
+            }
+        }
+        width += items[i].advance;
+        if c == ' ' { after_space = Some(i + 1); }
+        i += 1;
+    }
+    if start < items.len() || lines.is_empty() { lines.push(start..items.len()); }
+    lines
+}
+
+/// The items of `line` in the order they are shown: runs reordered by
+/// level (rule L2), right-to-left ones reversed.
+fn visual_order(runs: &[Run], items: &[Item], line: core::ops::Range<usize>) -> Vec<usize> {
+    // (first item, end, level) of each run's part of the line
+    let mut segs: Vec<(usize, usize, u8)> = Vec::new();
+    for k in line {
+        match segs.last_mut() {
+            Some(s) if items[s.0].run == items[k].run => s.1 = k + 1,
+            _ => segs.push((k, k + 1, runs[items[k].run].level)),
+        }
+    }
+    let max = segs.iter().map(|s| s.2).max().unwrap_or(0);
+    let min_odd = segs.iter().map(|s| s.2).filter(|l| l % 2 == 1).min().unwrap_or(max + 1);
+    for level in (min_odd..=max).rev() {
+        let mut i = 0;
+        while i < segs.len() {
+            if segs[i].2 < level { i += 1; continue; }
+            let s = i;
+            while i < segs.len() && segs[i].2 >= level { i += 1; }
+            segs[s..i].reverse();
+        }
+    }
+    let mut order = Vec::new();
+    for (a, b, level) in segs {
+        if level % 2 == 1 { order.extend((a..b).rev()); } else { order.extend(a..b); }
+    }
+    order
+}
+
+/// Draw `layout` in `color` (premultiplied ARGB) with its top left at
+/// (`x`, `y`) in `pixels`, `stride` pixels a row and `rows` rows.
+pub fn draw(layout: &Layout, color: u32, x: i32, y: i32, pixels: &mut [u32], stride: u32, rows: u32) {
+    let fonts = FONTS.lock().clone();
+    for g in &layout.glyphs {
+        let Some(font) = fonts.get(g.font as usize) else { continue };
+        let bm = raster::glyph(g.font, font, g.glyph, layout.px);
+        let (ox, oy) = (x + g.x + bm.left, y + g.y + bm.top);
+        for row in 0..bm.height as i32 {
+            let py = oy + row;
+            if py < 0 || py >= rows as i32 { continue; }
+            for col in 0..bm.width as i32 {
+                let px = ox + col;
+                if px < 0 || px >= stride as i32 { continue; }
+                let a = bm.pixels[(row * bm.width as i32 + col) as usize];
+                if a == 0 { continue; }
+                let d = &mut pixels[py as usize * stride as usize + px as usize];
+                *d = compositor::blend(*d, color, a as u32);
+            }
+        }
+    }
+}
@@ -0,0 +1,517 @@
+//! SurakshaOS Android Bridge
+//! The host end of the channel into the Android VM.  The VM, a pKVM
+//! guest, reaches the host over virtio-vsock: its bridge agent connects
+//! to BRIDGE_PORT on the host's context id, and the stream carries
+//! clipboard and file transfers in both directions.
+//!
+//! Nothing crosses without the user.  Every transfer the guest asks for
+//! is a prompt to the consent agent (permd's, see `permission`).  If the
+//! user allows it, the bridge mints a capability for that transfer alone
+//! — the clipboard or the one file, read or write — carries the transfer
+//! out under it and revokes it; unused, it lapses after TRANSFER_MS.  A
+//! prompt not answered within CONSENT_MS is refused, as is everything
+//! while there is no agent to ask.  Transfers go to the audit log.
+//!
+//! The virtio device belongs to the VMM: it attaches with `attach`, moves
+//! whole vsock packets (header and payload) on its virtqueues, and calls
+//! `rx_ready` when packets arrive.
+//!
+//! Frames on the stream (little-endian), the id the guest's, echoed in
+//! the reply:
+//!
+//!   op u8 | id u32 | len u32 | body
+//!
+//!   guest to host  CLIP_PUT  [text]                  copy to the host clipboard
+//!                  CLIP_GET  []                      paste the host clipboard
+//!                  FILE_PUT  [name len u8, name, data]  save a file in SHARE_DIR
+//!                  FILE_GET  [name]                  fetch a file from SHARE_DIR
+//!   host to guest  CLIP [text] | FILE [data] | DONE [] | DENIED [] | ERROR [message]
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::fs;
+use crate::permission;
+use crate::process::{self, ProcessId};
+
+/// The host's vsock context id (VMADDR_CID_HOST).
+pub const HOST_CID: u64 = 2;
+/// The port the guest's bridge agent connects to.
+pub const BRIDGE_PORT: u32 = 5000;
+/// Where files from the guest are saved, and the only place it may fetch
+/// files from.
+pub const SHARE_DIR: &str = "/home/user/Shared";
+/// How long the user has to answer a prompt.
+pub const CONSENT_MS: u64 = 60_000;
+/// How long a transfer's capability lasts.
+const TRANSFER_MS: u64 = 10_000;
+/// Largest clipboard or file carried.
+pub const MAX_TRANSFER: usize = 1 << 20;
+/// Longest file name.
+const MAX_NAME: usize = 255;
+/// Largest payload of one packet we send.
+const MAX_PACKET: usize = 4096;
+
+// ─── frames ───────────────────────────────────────────────────────────────────
+
+const FRAME_HEADER: usize = 9;
+
+pub const BRIDGE_CLIP_PUT: u8 = 0x01;
+pub const BRIDGE_CLIP_GET: u8 = 0x02;
+pub const BRIDGE_FILE_PUT: u8 = 0x03;
+pub const BRIDGE_FILE_GET: u8 = 0x04;
+pub const BRIDGE_CLIP:     u8 = 0x81;
+pub const BRIDGE_FILE:     u8 = 0x82;
+pub const BRIDGE_DONE:     u8 = 0x83;
+pub const BRIDGE_DENIED:   u8 = 0x84;
+pub const BRIDGE_ERROR:    u8 = 0x85;
+
+/// Our receive buffer, as advertised to the guest: a whole frame.
+const RX_BUF: u32 = (MAX_TRANSFER + MAX_NAME + 1 + FRAME_HEADER) as u32;
+
+// ─── vsock ────────────────────────────────────────────────────────────────────
+
+/// struct virtio_vsock_hdr.
+const HEADER_LEN: usize = 44;
+
+const TYPE_STREAM: u16 = 1;
+
+const OP_REQUEST:        u16 = 1;
+const OP_RESPONSE:       u16 = 2;
+const OP_RST:            u16 = 3;
+const OP_SHUTDOWN:       u16 = 4;
+const OP_RW:             u16 = 5;
+const OP_CREDIT_UPDATE:  u16 = 6;
+const OP_CREDIT_REQUEST: u16 = 7;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Header {
+    src_cid:   u64,
+    dst_cid:   u64,
+    src_port:  u32,
+    dst_port:  u32,
+    len:       u32,
+    ty:        u16,
+    op:        u16,
+    flags:     u32,
+    buf_alloc: u32,
+    fwd_cnt:   u32,
+}
+
+impl Header {
+    fn parse(b: &[u8]) -> Option<Header> {
+        if b.len() < HEADER_LEN { return None; }
+        let u64_at = |i: usize| u64::from_le_bytes(b[i..i + 8].try_into().unwrap_or_default());
+        let u32_at = |i: usize| u32::from_le_bytes(b[i..i + 4].try_into().unwrap_or_default());
+        let u16_at = |i: usize| u16::from_le_bytes(b[i..i + 2].try_into().unwrap_or_default());
+        Some(Header {
+            src_cid: u64_at(0), dst_cid: u64_at(8), src_port: u32_at(16), dst_port: u32_at(20), len: u32_at(24),
+            ty: u16_at(28), op: u16_at(30), flags: u32_at(32), buf_alloc: u32_at(36), fwd_cnt: u32_at(40),
+        })
+    }
+
+    fn encode(&self, payload: &[u8]) -> Vec<u8> {
+        let mut b = Vec::with_capacity(HEADER_LEN + payload.len());
+        b.extend_from_slice(&self.src_cid.to_le_bytes());
+        b.extend_from_slice(&self.dst_cid.to_le_bytes());
+        b.extend_from_slice(&self.src_port.to_le_bytes());
+        b.extend_from_slice(&self.dst_port.to_le_bytes());
+        b.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        b.extend_from_slice(&self.ty.to_le_bytes());
+        b.extend_from_slice(&self.op.to_le_bytes());
+        b.extend_from_slice(&self.flags.to_le_bytes());
+        b.extend_from_slice(&self.buf_alloc.to_le_bytes());
+        b.extend_from_slice(&self.fwd_cnt.to_le_bytes());
+        b.extend_from_slice(payload);
+        b
+    }
+}
+
+/// The guest's virtio-vsock device, as the VMM drives it.
+pub trait VsockDevice: Send {
+    /// Queue one packet, header and payload, for the guest.
+    fn send(&mut self, packet: &[u8]) -> Result<(), &'static str>;
+    /// The next packet from the guest, if any.
+    fn recv(&mut self) -> Option<Vec<u8>>;
+    /// The guest's context id.
+    fn guest_cid(&self) -> u64;
+}
+
+/// The guest agent's connection.
+struct Conn {
+    /// Its port.
+    port:           u32,
+    peer_buf_alloc: u32,
+    peer_fwd_cnt:   u32,
+    /// Bytes we have sent it, and it has sent us and we have taken.
+    tx_cnt:         u32,
+    fwd_cnt:        u32,
+    rx:             Vec<u8>,
+    tx:             VecDeque<u8>,
+}
+
+impl Conn {
+    /// How much more the guest has room for.
+    fn credit(&self) -> usize {
+        self.peer_buf_alloc.saturating_sub(self.tx_cnt.wrapping_sub(self.peer_fwd_cnt)) as usize
+    }
+}
+
+// ─── transfers ────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Kind {
+    /// The guest copies to the host clipboard.
+    ClipboardIn  = 1,
+    /// The guest pastes the host clipboard.
+    ClipboardOut = 2,
+    /// The guest saves a file in SHARE_DIR.
+    FileIn       = 3,
+    /// The guest fetches a file from SHARE_DIR.
+    FileOut      = 4,
+}
+
+impl Kind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::ClipboardIn  => "copy to clipboard",
+            Kind::ClipboardOut => "paste clipboard",
+            Kind::FileIn       => "save file",
+            Kind::FileOut      => "fetch file",
+        }
+    }
+
+    /// What the transfer's capability covers.
+    fn capability(self) -> (CapabilityType, Permissions) {
+        match self {
+            Kind::ClipboardIn  => (CapabilityType::Clipboard, Permissions::WRITE),
+            Kind::ClipboardOut => (CapabilityType::Clipboard, Permissions::READ),
+            Kind::FileIn       => (CapabilityType::File, Permissions::WRITE),
+            Kind::FileOut      => (CapabilityType::File, Permissions::READ),
+        }
+    }
+}
+
+/// A transfer waiting on the user.
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub id:    u32,
+    pub kind:  Kind,
+    /// The file's name in SHARE_DIR; empty for the clipboard.
+    pub name:  String,
+    /// Bytes it would carry.
+    pub size:  usize,
+    asked_ms:  u64,
+    /// The guest's id for it.
+    frame_id:  u32,
+    /// What the guest sent, for transfers to the host.
+    data:      Vec<u8>,
+}
+
+struct Bridge {
+    pid:       Option<ProcessId>,
+    device:    Option<Box<dyn VsockDevice>>,
+    conn:      Option<Conn>,
+    pending:   Vec<Transfer>,
+    next_id:   u32,
+    /// The host clipboard.
+    clipboard: Vec<u8>,
+}
+
+static BRIDGE: Mutex<Bridge> = Mutex::new(Bridge {
+    pid: None, device: None, conn: None, pending: Vec::new(), next_id: 1, clipboard: Vec::new(),
+});
+
+pub fn init() -> Result<(), &'static str> {
+    let pid = process::spawn_process("vmbridge")?;
+    BRIDGE.lock().pid = Some(pid);
+    fs::create_dir("/home").ok();
+    fs::create_dir("/home/user").ok();
+    fs::create_dir(SHARE_DIR).ok();
+    Ok(())
+}
+
+/// Carry the bridge over `device`, the Android VM's vsock device.  Any
+/// connection and transfers over the one before are dropped.
+pub fn attach(device: Box<dyn VsockDevice>) {
+    let mut b = BRIDGE.lock();
+    crate::info!("vmbridge: guest cid {} attached", device.guest_cid());
+    b.device = Some(device);
+    b.conn = None;
+    b.pending.clear();
+}
+
+/// The VM has gone.
+pub fn detach() {
+    let mut b = BRIDGE.lock();
+    b.device = None;
+    b.conn = None;
+    b.pending.clear();
+}
+
+/// The device has packets waiting; called from its interrupt.
+pub fn rx_ready() {
+    process::defer(poll);
+}
+
+/// Whether the guest's agent is connected.
+pub fn connected() -> bool {
+    BRIDGE.lock().conn.is_some()
+}
+
+/// Transfers waiting on the user.
+pub fn pending() -> Vec<Transfer> {
+    let mut b = BRIDGE.lock();
+    b.expire();
+    b.pending.clone()
+}
+
+/// The host clipboard, for the system UI.
+pub fn clipboard() -> Vec<u8> {
+    BRIDGE.lock().clipboard.clone()
+}
+
+pub fn set_clipboard(text: &[u8]) {
+    BRIDGE.lock().clipboard = Vec::from(text);
+}
+
+/// The user's answer to transfer `id`.  `cap` must be the caller's
+/// PermissionAdmin capability, as for permission prompts.
+pub fn answer(cap: &Capability, id: u32, allow: bool) -> Result<(), &'static str> {
+    capability::validate(process::current_pid(), cap, CapabilityType::PermissionAdmin, Permissions::CONTROL)?;
+    answer_transfer(id, allow)
+}
+
+/// Answer transfer `id` for the consent agent, which permd has checked.
+pub(crate) fn answer_transfer(id: u32, allow: bool) -> Result<(), &'static str> {
+    let mut b = BRIDGE.lock();
+    b.expire();
+    let i = b.pending.iter().position(|t| t.id == id).ok_or("no such transfer")?;
+    let t = b.pending.remove(i);
+    if allow {
+        b.carry_out(&t);
+    } else {
+        crate::audit::note("vmbridge", &format!("{} {}: denied", t.kind.as_str(), t.name));
+        b.reply(BRIDGE_DENIED, t.frame_id, &[]);
+    }
+    b.flush();
+    Ok(())
+}
+
+/// Take in what the guest sent, and ask the user about new transfers.
+fn poll() {
+    let asks = {
+        let mut b = BRIDGE.lock();
+        b.expire();
+        let mut asks = Vec::new();
+        while let Some(packet) = b.device.as_mut().and_then(|d| d.recv()) {
+            b.packet(&packet, &mut asks);
+        }
+        b.flush();
+        asks
+    };
+    for t in asks {
+        let mut note = Vec::from([permission::PERM_NOTE_TRANSFER]);
+        note.extend_from_slice(&t.id.to_le_bytes());
+        note.push(t.kind as u8);
+        note.extend_from_slice(&(t.size as u64).to_le_bytes());
+        note.extend_from_slice(t.name.as_bytes());
+        if !permission::notify_agent(&note) {
+            let _ = answer_transfer(t.id, false);
+        }
+    }
+}
+
+impl Bridge {
+    fn packet(&mut self, packet: &[u8], asks: &mut Vec<Transfer>) {
+        let Some(h) = Header::parse(packet) else { return };
+        let Some(cid) = self.device.as_ref().map(|d| d.guest_cid()) else { return };
+        let body = &packet[HEADER_LEN..(HEADER_LEN + h.len as usize).min(packet.len())];
+        if h.src_cid != cid || h.dst_cid != HOST_CID || h.ty != TYPE_STREAM { return; }
+        if h.dst_port != BRIDGE_PORT {
+            if h.op != OP_RST { self.send(&h, OP_RST, &[]); }
+            return;
+        }
+        if h.op == OP_REQUEST {
+            if self.conn.is_some() { self.disconnect("guest reconnected"); }
+            self.conn = Some(Conn {
+                port: h.src_port, peer_buf_alloc: h.buf_alloc, peer_fwd_cnt: h.fwd_cnt, tx_cnt: 0, fwd_cnt: 0,
+                rx: Vec::new(), tx: VecDeque::new(),
+            });
+            self.send(&h, OP_RESPONSE, &[]);
+            crate::info!("vmbridge: guest agent connected from port {}", h.src_port);
+            return;
+        }
+        let Some(conn) = self.conn.as_mut().filter(|c| c.port == h.src_port) else {
+            if h.op != OP_RST { self.send(&h, OP_RST, &[]); }
+            return;
+        };
+        conn.peer_buf_alloc = h.buf_alloc;
+        conn.peer_fwd_cnt = h.fwd_cnt;
+        match h.op {
+            OP_RW => {
+                if conn.rx.len() + body.len() > RX_BUF as usize {
+                    self.send(&h, OP_RST, &[]);
+                    return self.disconnect("guest overran its credit");
+                }
+                conn.rx.extend_from_slice(body);
+                if let Err(e) = self.frames(asks) {
+                    self.send(&h, OP_RST, &[]);
+                    return self.disconnect(e);
+                }
+                self.send(&h, OP_CREDIT_UPDATE, &[]);
+            }
+            OP_CREDIT_REQUEST => self.send(&h, OP_CREDIT_UPDATE, &[]),
+            OP_SHUTDOWN => {
+                self.send(&h, OP_RST, &[]);
+                self.disconnect("guest agent disconnected");
+            }
+            OP_RST => self.disconnect("guest agent reset the connection"),
+            _ => {}
+        }
+    }
+
+    /// Take each whole frame the guest has sent.
+    fn frames(&mut self, asks: &mut Vec<Transfer>) -> Result<(), &'static str> {
+        loop {
+            let Some(conn) = self.conn.as_mut() else { return Ok(()) };
+            if conn.rx.len() < FRAME_HEADER { return Ok(()); }
+            let len = u32::from_le_bytes(conn.rx[5..9].try_into().unwrap_or_default()) as usize;
+            if FRAME_HEADER + len > RX_BUF as usize { return Err("frame too large"); }
+            if conn.rx.len() < FRAME_HEADER + len { return Ok(()); }
+            let frame: Vec<u8> = conn.rx.drain(..FRAME_HEADER + len).collect();
+            conn.fwd_cnt = conn.fwd_cnt.wrapping_add(frame.len() as u32);
+            let id = u32::from_le_bytes(frame[1..5].try_into().unwrap_or_default());
+            match self.request(frame[0], id, &frame[FRAME_HEADER..]) {
+                Ok(t) => asks.push(t),
+                Err(e) => self.reply(BRIDGE_ERROR, id, e.as_bytes()),
+            }
+        }
+    }
+
+    /// A transfer the guest asks for, to put to the user.
+    fn request(&mut self, op: u8, frame_id: u32, body: &[u8]) -> Result<Transfer, &'static str> {
+        let (kind, name, data) = match op {
+            BRIDGE_CLIP_PUT => (Kind::ClipboardIn, String::new(), Vec::from(body)),
+            BRIDGE_CLIP_GET => (Kind::ClipboardOut, String::new(), Vec::new()),
+            BRIDGE_FILE_PUT => {
+                let n = *body.first().ok_or("bad frame")? as usize;
+                let name = body.get(1..1 + n).ok_or("bad frame")?;
+                (Kind::FileIn, file_name(name)?, Vec::from(&body[1 + n..]))
+            }
+            BRIDGE_FILE_GET => (Kind::FileOut, file_name(body)?, Vec::new()),
+            _ => return Err("unknown request"),
+        };
+        let size = match kind {
+            Kind::ClipboardOut => self.clipboard.len(),
+            Kind::FileOut => fs::stat(&path(&name)).map_err(|_| "no such file")?.size,
+            _ => data.len(),
+        };
+        if size > MAX_TRANSFER { return Err("too large"); }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let t = Transfer { id, kind, name, size, asked_ms: process::uptime_ms(), frame_id, data };
+        self.pending.push(t.clone());
+        Ok(Transfer { data: Vec::new(), ..t })
+    }
+
+    /// Do transfer `t`, which the user allowed, under a capability for it
+    /// alone, and tell the guest.
+    fn carry_out(&mut self, t: &Transfer) {
+        let Some(pid) = self.pid else { return self.reply(BRIDGE_ERROR, t.frame_id, b"bridge not running") };
+        let (ty, perms) = t.kind.capability();
+        let cap = capability::create_capability_until(pid, ty, perms, process::uptime_ms() + TRANSFER_MS);
+        let done = capability::validate(pid, &cap, ty, perms).and_then(|()| match t.kind {
+            Kind::ClipboardIn => {
+                self.clipboard = t.data.clone();
+                Ok((BRIDGE_DONE, Vec::new()))
+            }
+            Kind::ClipboardOut => Ok((BRIDGE_CLIP, self.clipboard.clone())),
+            Kind::FileIn => fs::write_file(&path(&t.name), &t.data).map(|()| (BRIDGE_DONE, Vec::new())),
+            Kind::FileOut => fs::read_file(&path(&t.name))
+                .and_then(|d| if d.len() > MAX_TRANSFER { Err("too large") } else { Ok((BRIDGE_FILE, d)) }),
+        });
+        let _ = capability::revoke_capability(cap.id);
+        match done {
+            Ok((op, body)) => {
+                crate::audit::note("vmbridge", &format!("{} {}: {} bytes", t.kind.as_str(), t.name, t.size));
+                self.reply(op, t.frame_id, &body);
+            }
+            Err(e) => {
+                crate::warn!("vmbridge: {} {}: {}", t.kind.as_str(), t.name, e);
+                self.reply(BRIDGE_ERROR, t.frame_id, e.as_bytes());
+            }
+        }
+    }
+
+    /// Refuse prompts the user has not answered in time.
+    fn expire(&mut self) {
+        let now = process::uptime_ms();
+        let (late, waiting): (Vec<Transfer>, Vec<Transfer>) = core::mem::take(&mut self.pending)
+            .into_iter().partition(|t| now >= t.asked_ms + CONSENT_MS);
+        self.pending = waiting;
+        for t in late { self.reply(BRIDGE_DENIED, t.frame_id, &[]); }
+    }
+
+    /// Queue a frame for the guest.
+    fn reply(&mut self, op: u8, frame_id: u32, body: &[u8]) {
+        let Some(conn) = self.conn.as_mut() else { return };
+        conn.tx.push_back(op);
+        conn.tx.extend(frame_id.to_le_bytes());
+        conn.tx.extend((body.len() as u32).to_le_bytes());
+        conn.tx.extend(body.iter().copied());
+    }
+
+    /// Send what is queued for the guest, as far as its credit goes.
+    fn flush(&mut self) {
+        let (Some(conn), Some(device)) = (self.conn.as_mut(), self.device.as_mut()) else { return };
+        while !conn.tx.is_empty() {
+            let n = conn.tx.len().min(conn.credit()).min(MAX_PACKET);
+            if n == 0 { break; }
+            let payload: Vec<u8> = conn.tx.drain(..n).collect();
+            let h = Header {
+                src_cid: HOST_CID, dst_cid: device.guest_cid(), src_port: BRIDGE_PORT, dst_port: conn.port,
+                ty: TYPE_STREAM, op: OP_RW, buf_alloc: RX_BUF, fwd_cnt: conn.fwd_cnt, ..Header::default()
+            };
+            if device.send(&h.encode(&payload)).is_err() { break; }
+            conn.tx_cnt = conn.tx_cnt.wrapping_add(n as u32);
+        }
+    }
+
+    /// Send a control packet answering `to`.
+    fn send(&mut self, to: &Header, op: u16, payload: &[u8]) {
+        let fwd_cnt = self.conn.as_ref().map_or(0, |c| c.fwd_cnt);
+        let Some(device) = self.device.as_mut() else { return };
+        let h = Header {
+            src_cid: HOST_CID, dst_cid: to.src_cid, src_port: to.dst_port, dst_port: to.src_port,
+            ty: TYPE_STREAM, op, buf_alloc: RX_BUF, fwd_cnt, ..Header::default()
+        };
+        let _ = device.send(&h.encode(payload));
+    }
+
+    /// Drop the connection, and the transfers asked over it.
+    fn disconnect(&mut self, why: &str) {
+        self.conn = None;
+        self.pending.clear();
+        crate::info!("vmbridge: {}", why);
+    }
+}
+
+/// A file name the guest gave: one component, no path.
+fn file_name(name: &[u8]) -> Result<String, &'static str> {
+    let name = core::str::from_utf8(name).map_err(|_| "bad file name")?;
+    if name.is_empty() || name.len() > MAX_NAME || name.contains('/') || name == "." || name == ".." {
+        return Err("bad file name");
+    }
+    Ok(String::from(name))
+}
+
+fn path(name: &str) -> String {
+    format!("{}/{}", SHARE_DIR, name)
+}
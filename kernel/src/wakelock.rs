@@ -0,0 +1,156 @@
+//! SurakshaOS Wakelocks
+//! Lets a process keep the system out of suspend while it finishes a
+//! download, a call, or similar.  Acquiring a lock needs a WakeLock
+//! capability; locks may carry a timeout so a crashed holder cannot drain
+//! the battery forever.  Per-holder statistics show who kept the device
+//! awake and for how long.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use spin::Mutex;
+
+use crate::capability::{self, Capability, CapabilityType, Permissions};
+use crate::process::ProcessId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WakeLockId(pub u32);
+
+struct WakeLock {
+    id:       WakeLockId,
+    holder:   ProcessId,
+    name:     String,
+    since_ms: u64,
+    /// Uptime at which the lock releases itself (None = until released).
+    deadline: Option<u64>,
+}
+
+/// Accumulated wakelock usage for one holder.
+#[derive(Debug, Clone)]
+pub struct HolderStats {
+    pub holder:       ProcessId,
+    pub acquisitions: u32,
+    pub timeouts:     u32,
+    pub total_ms:     u64,
+    pub longest_ms:   u64,
+    pub active:       u32,
+}
+
+struct State {
+    locks: Vec<WakeLock>,
+    stats: Vec<HolderStats>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State { locks: Vec::new(), stats: Vec::new() });
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+impl State {
+    fn stats_for(&mut self, holder: ProcessId) -> &mut HolderStats {
+        if let Some(i) = self.stats.iter().position(|s| s.holder == holder) {
+            return &mut self.stats[i];
+        }
+        self.stats.push(HolderStats {
+            holder, acquisitions: 0, timeouts: 0, total_ms: 0, longest_ms: 0, active: 0,
+        });
+        self.stats.last_mut().unwrap()
+    }
+
+    fn retire(&mut self, index: usize, now: u64, timed_out: bool) {
+        let lock = self.locks.swap_remove(index);
+        let held = now.saturating_sub(lock.since_ms);
+        let st = self.stats_for(lock.holder);
+        st.total_ms  += held;
+        st.longest_ms = st.longest_ms.max(held);
+        st.active     = st.active.saturating_sub(1);
+        if timed_out { st.timeouts += 1; }
+    }
+
+    fn expire(&mut self, now: u64) {
+        let mut i = 0;
+        while i < self.locks.len() {
+            if self.locks[i].deadline.is_some_and(|d| now >= d) {
+                self.retire(i, now, true);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+/// Take a wakelock for `holder`.  `timeout_ms` of 0 means held until
+/// `release()`.
+pub fn acquire(
+    holder:     ProcessId,
+    cap:        &Capability,
+    name:       &str,
+    timeout_ms: u64,
+) -> Result<WakeLockId, &'static str> {
+    capability::validate(holder, cap, CapabilityType::WakeLock, Permissions::WRITE)?;
+    let now = crate::arch::uptime_millis();
+    let id  = WakeLockId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let mut st = STATE.lock();
+    st.expire(now);
+    st.locks.push(WakeLock {
+        id,
+        holder,
+        name: name.into(),
+        since_ms: now,
+        deadline: (timeout_ms != 0).then_some(now + timeout_ms),
+    });
+    let s = st.stats_for(holder);
+    s.acquisitions += 1;
+    s.active       += 1;
+    Ok(id)
+}
+
+pub fn release(holder: ProcessId, id: WakeLockId) -> Result<(), &'static str> {
+    let now = crate::arch::uptime_millis();
+    let mut st = STATE.lock();
+    let i = st.locks.iter()
+        .position(|l| l.id == id)
+        .ok_or("no such wakelock")?;
+    if st.locks[i].holder != holder { return Err("wakelock held by another process"); }
+    st.retire(i, now, false);
+    Ok(())
+}
+
+/// Drop every lock held by `holder` (called when a process exits).
+pub fn release_all(holder: ProcessId) {
+    let now = crate::arch::uptime_millis();
+    let mut st = STATE.lock();
+    while let Some(i) = st.locks.iter().position(|l| l.holder == holder) {
+        st.retire(i, now, false);
+    }
+}
+
+/// Names and holders of the locks currently preventing suspend.
+pub fn active() -> Vec<(ProcessId, String)> {
+    let mut st = STATE.lock();
+    st.expire(crate::arch::uptime_millis());
+    st.locks.iter().map(|l| (l.holder, l.name.clone())).collect()
+}
+
+/// True if no wakelock is held.
+pub fn can_suspend() -> bool {
+    let mut st = STATE.lock();
+    st.expire(crate::arch::uptime_millis());
+    st.locks.is_empty()
+}
+
+pub fn stats() -> Vec<HolderStats> {
+    let mut st = STATE.lock();
+    let now = crate::arch::uptime_millis();
+    st.expire(now);
+    // Include time accrued by locks that are still held
+    let mut out = st.stats.clone();
+    for l in &st.locks {
+        if let Some(s) = out.iter_mut().find(|s| s.holder == l.holder) {
+            let held = now.saturating_sub(l.since_ms);
+            s.total_ms  += held;
+            s.longest_ms = s.longest_ms.max(held);
+        }
+    }
+    out
+}
@@ -0,0 +1,357 @@
+//! EAPOL-Key and the 4-Way Handshake (IEEE 802.11-2020 12.7)
+//! Once associated, the access point proves it holds the PMK and both
+//! sides derive the pairwise keys from it and a nonce each; the AP then
+//! hands over its group keys, wrapped under the KEK.  This is the
+//! supplicant's side for AKM 8 (SAE) with CCMP and protected management
+//! frames, as WPA3-Personal requires: key descriptor version 0, PTKs
+//! from the SHA-256 KDF and MICs by AES-CMAC.  The group key handshake
+//! the AP later rotates group keys with is handled too.
+//!
+//! Keys are handed back for installation once, however often the AP
+//! retransmits, so a replayed message cannot reset a key's packet
+//! numbers.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::{InstallKey, KeySlot};
+use crate::crypto::{aes, sha2};
+use crate::keyring::Secret;
+use crate::net::MacAddr;
+
+pub const ETHERTYPE_EAPOL: u16 = 0x888E;
+
+const EAPOL_VERSION: u8 = 2;
+const EAPOL_KEY:     u8 = 3;
+const DESC_RSN:      u8 = 2;
+
+// Key Information bits
+const INFO_PAIRWISE:  u16 = 1 << 3;
+const INFO_INSTALL:   u16 = 1 << 6;
+const INFO_ACK:       u16 = 1 << 7;
+const INFO_MIC:       u16 = 1 << 8;
+const INFO_SECURE:    u16 = 1 << 9;
+const INFO_ENCRYPTED: u16 = 1 << 12;
+
+// EAPOL-Key frame layout, from the EAPOL header on
+const OFF_INFO:     usize = 5;
+const OFF_REPLAY:   usize = 9;
+const OFF_NONCE:    usize = 17;
+const OFF_RSC:      usize = 65;
+const OFF_MIC:      usize = 81;
+const OFF_DATA_LEN: usize = 97;
+const KEY_HEADER:   usize = 99;
+
+const NONCE_LEN: usize = 32;
+const MIC_LEN:   usize = 16;
+
+// Cipher and AKM suites
+pub const SUITE_CCMP:     [u8; 4] = [0x00, 0x0F, 0xAC, 4];
+pub const SUITE_BIP_CMAC: [u8; 4] = [0x00, 0x0F, 0xAC, 6];
+pub const SUITE_SAE:      [u8; 4] = [0x00, 0x0F, 0xAC, 8];
+
+const ELEMENT_RSN: u8 = 0x30;
+const ELEMENT_KDE: u8 = 0xDD;
+const KDE_GTK:     u8 = 1;
+const KDE_PMKID:   u8 = 4;
+const KDE_IGTK:    u8 = 9;
+
+/// RSN capabilities: management frame protection required and capable.
+const RSN_CAP_MFP: u16 = 0x00C0;
+
+// ─── RSN element ──────────────────────────────────────────────────────────────
+
+/// Our RSN element: CCMP, SAE and BIP-CMAC, protected management frames
+/// required, and `pmkid` if resuming a cached PMK.
+pub fn rsne(pmkid: Option<&[u8; 16]>) -> Vec<u8> {
+    let mut ie = vec![ELEMENT_RSN, 0];
+    ie.extend_from_slice(&1u16.to_le_bytes());
+    ie.extend_from_slice(&SUITE_CCMP);
+    ie.extend_from_slice(&1u16.to_le_bytes());
+    ie.extend_from_slice(&SUITE_CCMP);
+    ie.extend_from_slice(&1u16.to_le_bytes());
+    ie.extend_from_slice(&SUITE_SAE);
+    ie.extend_from_slice(&RSN_CAP_MFP.to_le_bytes());
+    match pmkid {
+        Some(id) => { ie.extend_from_slice(&1u16.to_le_bytes()); ie.extend_from_slice(id); }
+        None     => ie.extend_from_slice(&0u16.to_le_bytes()),
+    }
+    ie.extend_from_slice(&SUITE_BIP_CMAC);
+    ie[1] = (ie.len() - 2) as u8;
+    ie
+}
+
+/// Does an AP's RSN element offer what we need: SAE, CCMP as both
+/// pairwise and group cipher, and protected management frames?
+pub fn supports_wpa3(ie: &[u8]) -> bool {
+    let parse = || -> Option<bool> {
+        if *ie.first()? != ELEMENT_RSN || ie.len() < 2 + *ie.get(1)? as usize { return None; }
+        let body = &ie[2..2 + ie[1] as usize];
+        let u16_at = |i: usize| Some(u16::from_le_bytes([*body.get(i)?, *body.get(i + 1)?]));
+        let group = body.get(2..6)?;
+        let pairwise = u16_at(6)? as usize;
+        let pairwise_ok = body.get(8..8 + 4 * pairwise)?.chunks(4).any(|s| s == SUITE_CCMP);
+        let at = 8 + 4 * pairwise;
+        let akms = u16_at(at)? as usize;
+        let sae = body.get(at + 2..at + 2 + 4 * akms)?.chunks(4).any(|s| s == SUITE_SAE);
+        let caps = u16_at(at + 2 + 4 * akms)?;
+        Some(group == SUITE_CCMP && pairwise_ok && sae && caps & 0x0080 != 0)
+    };
+    parse().unwrap_or(false)
+}
+
+// ─── keys ─────────────────────────────────────────────────────────────────────
+
+/// The pairwise transient key, for CCMP: KCK ‖ KEK ‖ TK.
+struct Ptk(Secret);
+
+impl Ptk {
+    fn derive(pmk: &[u8], aa: MacAddr, spa: MacAddr, anonce: &[u8; NONCE_LEN], snonce: &[u8; NONCE_LEN]) -> Ptk {
+        let (a1, a2) = if aa.0 < spa.0 { (aa.0, spa.0) } else { (spa.0, aa.0) };
+        let (n1, n2) = if anonce < snonce { (anonce, snonce) } else { (snonce, anonce) };
+        let context = [&a1[..], &a2, n1, n2].concat();
+        let mut ptk = [0u8; 48];
+        sha2::kdf_sha256(pmk, "Pairwise key expansion", &context, &mut ptk);
+        let key = Ptk(Secret::new(&ptk));
+        crate::crypto::wipe(&mut ptk);
+        key
+    }
+
+    fn kck(&self) -> [u8; 16] {
+        self.0[..16].try_into().unwrap_or([0; 16])
+    }
+
+    fn kek(&self) -> [u8; 16] {
+        self.0[16..32].try_into().unwrap_or([0; 16])
+    }
+
+    fn tk(&self) -> &[u8] {
+        &self.0[32..48]
+    }
+}
+
+// ─── frames ───────────────────────────────────────────────────────────────────
+
+/// A received EAPOL-Key frame, trimmed to its stated length.
+struct KeyFrame<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> KeyFrame<'a> {
+    fn parse(frame: &'a [u8]) -> Option<KeyFrame<'a>> {
+        if frame.len() < KEY_HEADER || frame[1] != EAPOL_KEY || frame[4] != DESC_RSN { return None; }
+        let len = 4 + u16::from_be_bytes([frame[2], frame[3]]) as usize;
+        let raw = frame.get(..len)?;
+        let data_len = u16::from_be_bytes([raw[OFF_DATA_LEN], raw[OFF_DATA_LEN + 1]]) as usize;
+        if raw.len() < KEY_HEADER + data_len { return None; }
+        Some(KeyFrame { raw: &raw[..KEY_HEADER + data_len] })
+    }
+
+    fn info(&self) -> u16 {
+        u16::from_be_bytes([self.raw[OFF_INFO], self.raw[OFF_INFO + 1]])
+    }
+
+    fn replay(&self) -> u64 {
+        u64::from_be_bytes(self.raw[OFF_REPLAY..OFF_REPLAY + 8].try_into().unwrap_or([0; 8]))
+    }
+
+    fn nonce(&self) -> [u8; NONCE_LEN] {
+        self.raw[OFF_NONCE..OFF_NONCE + NONCE_LEN].try_into().unwrap_or([0; NONCE_LEN])
+    }
+
+    /// Receive sequence counter of the group key, little-endian.
+    fn rsc(&self) -> [u8; 8] {
+        self.raw[OFF_RSC..OFF_RSC + 8].try_into().unwrap_or([0; 8])
+    }
+
+    fn data(&self) -> &[u8] {
+        &self.raw[KEY_HEADER..]
+    }
+
+    fn mic_ok(&self, kck: &[u8; 16]) -> bool {
+        let mut copy = self.raw.to_vec();
+        copy[OFF_MIC..OFF_MIC + MIC_LEN].fill(0);
+        let mic = aes::cmac(kck, &[&copy]);
+        mic.iter().zip(&self.raw[OFF_MIC..OFF_MIC + MIC_LEN]).fold(0u8, |d, (a, b)| d | (a ^ b)) == 0
+    }
+}
+
+/// Build an EAPOL-Key frame from us, with its MIC under `kck`.
+fn key_frame(info: u16, replay: u64, nonce: &[u8; NONCE_LEN], data: &[u8], kck: &[u8; 16]) -> Vec<u8> {
+    let mut f = vec![0u8; KEY_HEADER + data.len()];
+    f[0] = EAPOL_VERSION;
+    f[1] = EAPOL_KEY;
+    f[2..4].copy_from_slice(&((KEY_HEADER + data.len() - 4) as u16).to_be_bytes());
+    f[4] = DESC_RSN;
+    f[OFF_INFO..OFF_INFO + 2].copy_from_slice(&info.to_be_bytes());
+    f[OFF_REPLAY..OFF_REPLAY + 8].copy_from_slice(&replay.to_be_bytes());
+    f[OFF_NONCE..OFF_NONCE + NONCE_LEN].copy_from_slice(nonce);
+    f[OFF_DATA_LEN..OFF_DATA_LEN + 2].copy_from_slice(&(data.len() as u16).to_be_bytes());
+    f[KEY_HEADER..].copy_from_slice(data);
+    let mic = aes::cmac(kck, &[&f]);
+    f[OFF_MIC..OFF_MIC + MIC_LEN].copy_from_slice(&mic);
+    f
+}
+
+/// The elements and KDEs of key data: (KDE type, its data) for KDEs,
+/// (element id, the whole element) for elements.
+fn elements(mut data: &[u8]) -> Vec<(u8, &[u8])> {
+    let mut out = Vec::new();
+    while data.len() >= 2 {
+        let (id, len) = (data[0], data[1] as usize);
+        // Padding is a KDE id with nothing after it
+        if id == ELEMENT_KDE && len == 0 { break; }
+        let Some(body) = data.get(2..2 + len) else { break };
+        if id == ELEMENT_KDE {
+            if len >= 4 && body[..3] == SUITE_CCMP[..3] { out.push((body[3], &body[4..])); }
+        } else {
+            out.push((id, &data[..2 + len]));
+        }
+        data = &data[2 + len..];
+    }
+    out
+}
+
+/// The GTK and IGTK delivered in decrypted key data.
+fn group_keys(data: &[u8], aa: MacAddr, rsc: [u8; 8]) -> Result<Vec<InstallKey>, &'static str> {
+    let mut keys = Vec::new();
+    for (kind, body) in elements(data) {
+        match kind {
+            KDE_GTK if body.len() >= 2 + 16 => keys.push(InstallKey {
+                slot: KeySlot::Group(body[0] & 0x3), cipher: SUITE_CCMP, key: Secret::new(&body[2..18]), rsc, peer: aa,
+            }),
+            KDE_IGTK if body.len() >= 8 + 16 => {
+                let mut ipn = [0u8; 8];
+                ipn[..6].copy_from_slice(&body[2..8]);
+                keys.push(InstallKey {
+                    slot: KeySlot::Management(u16::from_le_bytes([body[0], body[1]])), cipher: SUITE_BIP_CMAC,
+                    key: Secret::new(&body[8..24]), rsc: ipn, peer: aa,
+                });
+            }
+            _ => {}
+        }
+    }
+    if !keys.iter().any(|k| matches!(k.slot, KeySlot::Group(_))) { return Err("no group key"); }
+    Ok(keys)
+}
+
+// ─── handshake ────────────────────────────────────────────────────────────────
+
+/// What the caller should do with an EAPOL-Key frame's outcome.
+pub struct Outcome {
+    /// EAPOL frame to send back to the AP.
+    pub reply:   Option<Vec<u8>>,
+    /// Keys to install, pairwise first.
+    pub install: Vec<InstallKey>,
+}
+
+/// The supplicant's side of the 4-way and group key handshakes with one
+/// AP, for the life of an association.
+pub struct Handshake {
+    aa:        MacAddr,
+    spa:       MacAddr,
+    pmk:       Secret,
+    pmkid:     [u8; 16],
+    /// The AP's RSN element as advertised, which message 3 must repeat.
+    ap_rsne:   Vec<u8>,
+    own_rsne:  Vec<u8>,
+    snonce:    [u8; NONCE_LEN],
+    anonce:    Option<[u8; NONCE_LEN]>,
+    /// PTK derived from message 1, in use once message 3 checks out.
+    ptk:       Option<Ptk>,
+    /// Highest replay counter seen in a frame with a valid MIC.
+    replay:    Option<u64>,
+    /// Pairwise keys installed.
+    installed: bool,
+    /// The group key last installed, so a repeat is not reinstalled.
+    gtk:       Option<Secret>,
+}
+
+impl Handshake {
+    pub fn new(aa: MacAddr, spa: MacAddr, pmk: Secret, pmkid: [u8; 16], ap_rsne: &[u8], own_rsne: Vec<u8>) -> Handshake {
+        let mut snonce = [0u8; NONCE_LEN];
+        crate::entropy::fill_bytes(&mut snonce);
+        Handshake {
+            aa, spa, pmk, pmkid, ap_rsne: ap_rsne.to_vec(), own_rsne, snonce, anonce: None,
+            ptk: None, replay: None, installed: false, gtk: None,
+        }
+    }
+
+    /// The 4-way handshake is done and the link carries data.
+    pub fn complete(&self) -> bool {
+        self.installed
+    }
+
+    /// Take an EAPOL frame (from the EAPOL header on) from the AP.
+    pub fn input(&mut self, frame: &[u8]) -> Result<Outcome, &'static str> {
+        let Some(f) = KeyFrame::parse(frame) else { return Err("not an EAPOL-Key frame") };
+        let info = f.info();
+        if info & 0x7 != 0 { return Err("unsupported key descriptor version"); }
+        if info & INFO_ACK == 0 { return Err("EAPOL-Key frame not from the AP"); }
+        if self.replay.is_some_and(|r| f.replay() <= r) { return Err("replayed EAPOL-Key frame"); }
+        match (info & INFO_PAIRWISE != 0, info & INFO_MIC != 0) {
+            (true, false) => self.message1(&f),
+            (true, true)  => self.message3(&f),
+            (false, true) => self.group_message1(&f),
+            _ => Err("unexpected EAPOL-Key frame"),
+        }
+    }
+
+    fn message1(&mut self, f: &KeyFrame) -> Result<Outcome, &'static str> {
+        if self.installed { return Err("4-way handshake restarted"); }
+        for (kind, body) in elements(f.data()) {
+            if kind == KDE_PMKID && body.len() >= 16 && body[..16] != self.pmkid { return Err("PMKID mismatch"); }
+        }
+        let anonce = f.nonce();
+        let ptk = Ptk::derive(&self.pmk, self.aa, self.spa, &anonce, &self.snonce);
+        let reply = key_frame(INFO_PAIRWISE | INFO_MIC, f.replay(), &self.snonce, &self.own_rsne, &ptk.kck());
+        self.anonce = Some(anonce);
+        self.ptk = Some(ptk);
+        Ok(Outcome { reply: Some(reply), install: Vec::new() })
+    }
+
+    fn message3(&mut self, f: &KeyFrame) -> Result<Outcome, &'static str> {
+        let info = f.info();
+        let ptk = self.ptk.as_ref().ok_or("message 3 before message 1")?;
+        if self.anonce != Some(f.nonce()) { return Err("ANonce changed"); }
+        if info & (INFO_INSTALL | INFO_SECURE | INFO_ENCRYPTED) != INFO_INSTALL | INFO_SECURE | INFO_ENCRYPTED {
+            return Err("malformed message 3");
+        }
+        if !f.mic_ok(&ptk.kck()) { return Err("MIC failure"); }
+        self.replay = Some(f.replay());
+        let reply = key_frame(INFO_PAIRWISE | INFO_MIC | INFO_SECURE, f.replay(), &[0; NONCE_LEN], &[], &ptk.kck());
+        // A retransmission once keys are in only needs message 4 again
+        if self.installed { return Ok(Outcome { reply: Some(reply), install: Vec::new() }); }
+
+        let data = Secret::new(&aes::unwrap_key(&ptk.kek(), f.data())?);
+        let elements = elements(&data);
+        if !elements.iter().any(|&(id, ie)| id == ELEMENT_RSN && ie == self.ap_rsne.as_slice()) {
+            return Err("RSN element differs from the AP's beacon");
+        }
+        let mut install = vec![InstallKey {
+            slot: KeySlot::Pairwise, cipher: SUITE_CCMP, key: Secret::new(ptk.tk()), rsc: [0; 8], peer: self.aa,
+        }];
+        let group = group_keys(&data, self.aa, f.rsc())?;
+        self.gtk = group.iter().find(|k| matches!(k.slot, KeySlot::Group(_))).map(|k| Secret::new(&k.key));
+        install.extend(group);
+        self.installed = true;
+        Ok(Outcome { reply: Some(reply), install })
+    }
+
+    fn group_message1(&mut self, f: &KeyFrame) -> Result<Outcome, &'static str> {
+        let ptk = self.ptk.as_ref().filter(|_| self.installed).ok_or("group key before pairwise keys")?;
+        if f.info() & (INFO_SECURE | INFO_ENCRYPTED) != INFO_SECURE | INFO_ENCRYPTED { return Err("malformed group message 1"); }
+        if !f.mic_ok(&ptk.kck()) { return Err("MIC failure"); }
+        self.replay = Some(f.replay());
+        let reply = key_frame(INFO_MIC | INFO_SECURE, f.replay(), &[0; NONCE_LEN], &[], &ptk.kck());
+        let data = Secret::new(&aes::unwrap_key(&ptk.kek(), f.data())?);
+        let mut install = group_keys(&data, self.aa, f.rsc())?;
+        let gtk = install.iter().find(|k| matches!(k.slot, KeySlot::Group(_))).map(|k| Secret::new(&k.key));
+        if gtk.as_deref() == self.gtk.as_deref() {
+            install.clear();
+        } else {
+            self.gtk = gtk;
+        }
+        Ok(Outcome { reply: Some(reply), install })
+    }
+}
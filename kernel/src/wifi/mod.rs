@@ -0,0 +1,584 @@
+//! SurakshaOS Wi-Fi
+//! Station support over a FullMAC NIC: the firmware scans, associates and
+//! encrypts, and the kernel runs WPA3-Personal's security on top of it:
+//! SAE authentication (see `crypto::sae`), the 4-way handshake that
+//! derives the session keys (see `eapol`), and installing those keys
+//! into the NIC.  Only WPA3 networks are joined: SAE with CCMP and
+//! protected management frames.
+//!
+//! Credentials live in the kernel keyring.  `connect` takes the id of a
+//! key of the caller's holding the passphrase, which is opened only
+//! while SAE computes its password element.  The PMK each SAE yields is
+//! cached as a kernel key for `PMK_LIFETIME_MS`, and reconnecting to the
+//! same access point offers its PMKID in place of a new SAE, falling
+//! back to SAE if the AP has forgotten it.
+//!
+//! The NIC appears twice: as network interface `wlan0`, which passes
+//! traffic only once the handshake is done, and as device `wlan0`, for
+//! which scanning and connecting need a capability
+//! (`CapabilityType::Device`).
+
+pub mod eapol;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use crate::capability::{Capability, Permissions};
+use crate::crypto::sae::{self, Sae};
+use crate::driver::{self, check_access, Device, DeviceClass, DeviceId, Driver};
+use crate::keyring::{self, KeyId, Secret};
+use crate::net::{self, MacAddr, NetDevice};
+use crate::process::{current_pid, ProcessId};
+use eapol::Handshake;
+
+// ─── 802.11 constants ─────────────────────────────────────────────────────────
+
+const AUTH_SAE:        u16 = 3;
+const SAE_COMMIT:      u16 = 1;
+const SAE_CONFIRM:     u16 = 2;
+
+const STATUS_SUCCESS:           u16 = 0;
+const STATUS_INVALID_PMKID:     u16 = 53;
+const STATUS_ANTI_CLOGGING:     u16 = 76;
+const STATUS_UNSUPPORTED_GROUP: u16 = 77;
+
+const REASON_LEAVING:           u16 = 3;
+const REASON_MIC_FAILURE:       u16 = 14;
+const REASON_HANDSHAKE_TIMEOUT: u16 = 15;
+
+/// How long each authentication, association or handshake step may take.
+const STEP_TIMEOUT_MS: u64 = 1000;
+/// Times an SAE message or association request is sent before giving up.
+const MAX_TRIES: u32 = 3;
+/// How long a PMK from SAE may be reused.
+pub const PMK_LIFETIME_MS: u64 = 12 * 3600 * 1000;
+/// Received frames held for the stack.
+const RX_QUEUE: usize = 256;
+
+const ETH_HEADER: usize = 14;
+
+// ─── ioctl interface ──────────────────────────────────────────────────────────
+
+/// Leave the current network.
+pub const WIFI_IOCTL_DISCONNECT: u32 = 0x5701;
+/// Connection state (query); see `State`.
+pub const WIFI_IOCTL_STATE:      u32 = 0x5702;
+/// Drop every cached PMK.
+pub const WIFI_IOCTL_FORGET:     u32 = 0x5703;
+
+// ─── NIC ──────────────────────────────────────────────────────────────────────
+
+/// A network as a scan reports it.
+#[derive(Debug, Clone)]
+pub struct Bss {
+    pub bssid:   MacAddr,
+    pub ssid:    Vec<u8>,
+    pub channel: u8,
+    /// Signal strength in dBm.
+    pub rssi:    i8,
+    /// RSN element as advertised; empty for an open network.
+    pub rsne:    Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySlot {
+    Pairwise,
+    /// Group key, by key id.
+    Group(u8),
+    /// Management frame integrity key, by key id.
+    Management(u16),
+}
+
+/// A key to install into the NIC.
+pub struct InstallKey {
+    pub slot:   KeySlot,
+    /// Cipher suite selector.
+    pub cipher: [u8; 4],
+    pub key:    Secret,
+    /// Receive sequence counter (packet number) to start from,
+    /// little-endian.
+    pub rsc:    [u8; 8],
+    pub peer:   MacAddr,
+}
+
+pub enum NicEvent {
+    /// An Authentication frame from `bssid`, from the algorithm number on.
+    Auth { bssid: MacAddr, body: Vec<u8> },
+    /// The status of an Association Response.
+    Associated { bssid: MacAddr, status: u16 },
+    /// Deauthenticated or disassociated by the AP.
+    Lost { bssid: MacAddr, reason: u16 },
+    /// A data frame, Ethernet-framed.
+    Frame(Vec<u8>),
+}
+
+/// The firmware interface of a FullMAC NIC.
+pub trait WifiNic: Send {
+    fn mac(&self) -> MacAddr;
+    /// Scan every channel, returning when done.
+    fn scan(&mut self) -> Result<Vec<Bss>, &'static str>;
+    /// Send an Authentication frame to `bssid`; `body` starts at the
+    /// algorithm number.
+    fn send_auth(&mut self, bssid: MacAddr, body: &[u8]) -> Result<(), &'static str>;
+    /// Send an Association Request for `ssid` carrying our RSN element.
+    fn associate(&mut self, bssid: MacAddr, ssid: &[u8], rsne: &[u8]) -> Result<(), &'static str>;
+    fn deauthenticate(&mut self, bssid: MacAddr, reason: u16);
+    /// Transmit an Ethernet-framed data frame.
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), &'static str>;
+    fn install_key(&mut self, key: &InstallKey) -> Result<(), &'static str>;
+    /// Forget every installed key.
+    fn clear_keys(&mut self);
+    /// Next event from the firmware, if any.
+    fn poll(&mut self) -> Option<NicEvent>;
+}
+
+// ─── station ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    /// SAE commit sent; waiting for the AP's.
+    Committed,
+    /// SAE confirm sent; waiting for the AP's.
+    Confirmed,
+    Associating,
+    /// Associated; the 4-way handshake is running.
+    Handshake,
+    Connected,
+}
+
+/// The station's state as reported to callers.
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub state: State,
+    pub bss:   Option<Bss>,
+    /// Why the last attempt failed or the link was lost.
+    pub error: Option<&'static str>,
+}
+
+/// The network being joined.
+struct Target {
+    bss:        Bss,
+    credential: KeyId,
+    owner:      ProcessId,
+}
+
+/// A cached PMK, held as a kernel key.
+struct Pmksa {
+    bssid:   MacAddr,
+    ssid:    Vec<u8>,
+    pmkid:   [u8; 16],
+    key:     KeyId,
+    expires: u64,
+}
+
+struct Station {
+    nic:          Box<dyn WifiNic>,
+    /// The network interface, once attached.
+    index:        Option<usize>,
+    state:        State,
+    target:       Option<Target>,
+    sae:          Option<Sae>,
+    send_confirm: u16,
+    /// The cached PMK in use for this association.
+    pmksa:        Option<usize>,
+    cache:        Vec<Pmksa>,
+    handshake:    Option<Handshake>,
+    rx:           VecDeque<Vec<u8>>,
+    deadline:     u64,
+    tries:        u32,
+    error:        Option<&'static str>,
+}
+
+static STATION: Mutex<Option<Station>> = Mutex::new(None);
+
+fn now() -> u64 {
+    crate::arch::uptime_millis()
+}
+
+fn auth_frame(seq: u16, status: u16, fields: &[&[u8]]) -> Vec<u8> {
+    let mut f = Vec::new();
+    f.extend_from_slice(&AUTH_SAE.to_le_bytes());
+    f.extend_from_slice(&seq.to_le_bytes());
+    f.extend_from_slice(&status.to_le_bytes());
+    for field in fields { f.extend_from_slice(field); }
+    f
+}
+
+impl Station {
+    fn bssid(&self) -> Option<MacAddr> {
+        self.target.as_ref().map(|t| t.bss.bssid)
+    }
+
+    /// Join the target: with its cached PMK if there is one, else by SAE.
+    fn start(&mut self) -> Result<(), &'static str> {
+        let t = self.target.as_ref().ok_or("no network")?;
+        let (bssid, ssid) = (t.bss.bssid, t.bss.ssid.clone());
+        let time = now();
+        for p in self.cache.iter().filter(|p| p.expires <= time) { keyring::remove_kernel(p.key); }
+        self.cache.retain(|p| p.expires > time);
+        self.handshake = None;
+        self.sae = None;
+        match self.cache.iter().position(|p| p.bssid == bssid && p.ssid == ssid) {
+            Some(i) => { self.pmksa = Some(i); self.associate() }
+            None    => { self.pmksa = None; self.commit(None) }
+        }
+    }
+
+    /// Send our SAE commit, starting the exchange if need be, with the
+    /// AP's anti-clogging `token` if it asked for one.
+    fn commit(&mut self, token: Option<&[u8]>) -> Result<(), &'static str> {
+        let t = self.target.as_ref().ok_or("no network")?;
+        if self.sae.is_none() {
+            let password = keyring::open(t.credential, Some(t.owner))?;
+            self.sae = Some(Sae::new(&password, self.nic.mac().0, t.bss.bssid.0)?);
+            self.send_confirm = 0;
+        }
+        let commit = self.sae.as_ref().map(Sae::commit).unwrap_or_default();
+        let frame = auth_frame(SAE_COMMIT, STATUS_SUCCESS, &[&sae::GROUP_P256.to_le_bytes(), token.unwrap_or(&[]), &commit]);
+        self.nic.send_auth(t.bss.bssid, &frame)?;
+        self.step(State::Committed);
+        Ok(())
+    }
+
+    fn confirm(&mut self) -> Result<(), &'static str> {
+        let bssid = self.bssid().ok_or("no network")?;
+        self.send_confirm = self.send_confirm.wrapping_add(1);
+        let confirm = self.sae.as_ref().ok_or("no SAE exchange")?.confirm(self.send_confirm)?;
+        let frame = auth_frame(SAE_CONFIRM, STATUS_SUCCESS, &[&self.send_confirm.to_le_bytes(), &confirm]);
+        self.nic.send_auth(bssid, &frame)?;
+        self.step(State::Confirmed);
+        Ok(())
+    }
+
+    fn associate(&mut self) -> Result<(), &'static str> {
+        let t = self.target.as_ref().ok_or("no network")?;
+        let pmkid = self.pmksa.map(|i| self.cache[i].pmkid);
+        self.nic.associate(t.bss.bssid, &t.bss.ssid, &eapol::rsne(pmkid.as_ref()))?;
+        self.step(State::Associating);
+        Ok(())
+    }
+
+    /// Enter `state`, counting attempts at the same step.
+    fn step(&mut self, state: State) {
+        self.tries = if state == self.state { self.tries + 1 } else { 1 };
+        self.state = state;
+        self.deadline = now() + STEP_TIMEOUT_MS;
+    }
+
+    /// Give up on the network, or drop the link to it.
+    fn fail(&mut self, reason: &'static str, code: u16) {
+        if let Some(bssid) = self.bssid() { self.nic.deauthenticate(bssid, code); }
+        let was_connected = self.state == State::Connected;
+        self.reset();
+        self.error = Some(reason);
+        if let Some(t) = &self.target {
+            crate::println!("  [wifi] {}: {}", core::str::from_utf8(&t.bss.ssid).unwrap_or("?"), reason);
+        }
+        if was_connected { crate::process::defer(link_changed); }
+    }
+
+    fn leave(&mut self) {
+        if let Some(bssid) = self.bssid() { self.nic.deauthenticate(bssid, REASON_LEAVING); }
+        let was_connected = self.state == State::Connected;
+        self.reset();
+        self.target = None;
+        self.error = None;
+        if was_connected { crate::process::defer(link_changed); }
+    }
+
+    fn forget(&mut self) {
+        for p in self.cache.drain(..) { keyring::remove_kernel(p.key); }
+        self.pmksa = None;
+    }
+
+    fn reset(&mut self) {
+        self.nic.clear_keys();
+        self.state = State::Idle;
+        self.sae = None;
+        self.handshake = None;
+        self.pmksa = None;
+        self.rx.clear();
+    }
+
+    /// SAE succeeded: cache its PMK and associate.
+    fn authenticated(&mut self) -> Result<(), &'static str> {
+        let sae = self.sae.take().ok_or("no SAE exchange")?;
+        let t = self.target.as_ref().ok_or("no network")?;
+        let (bssid, ssid) = (t.bss.bssid, t.bss.ssid.clone());
+        if let Some(i) = self.cache.iter().position(|p| p.bssid == bssid) {
+            keyring::remove_kernel(self.cache.remove(i).key);
+        }
+        let key = keyring::add_kernel("wifi pmk", sae.pmk())?;
+        self.cache.push(Pmksa { bssid, ssid, pmkid: sae.pmkid(), key, expires: now() + PMK_LIFETIME_MS });
+        self.pmksa = Some(self.cache.len() - 1);
+        self.associate()
+    }
+
+    fn on_auth(&mut self, body: &[u8]) -> Result<(), &'static str> {
+        if body.len() < 6 { return Err("short authentication frame"); }
+        let u16_at = |i: usize| u16::from_le_bytes([body[i], body[i + 1]]);
+        let (alg, seq, status) = (u16_at(0), u16_at(2), u16_at(4));
+        if alg != AUTH_SAE { return Ok(()); }
+        let fields = &body[6..];
+        match (seq, status, self.state) {
+            (SAE_COMMIT, STATUS_ANTI_CLOGGING, State::Committed) if fields.len() > 2 => self.commit(Some(&fields[2..])),
+            (SAE_COMMIT, STATUS_UNSUPPORTED_GROUP, _) => Err("AP does not support SAE on P-256"),
+            (SAE_COMMIT, STATUS_SUCCESS, State::Committed) => {
+                if fields.len() < 2 + sae::COMMIT_LEN || u16::from_le_bytes([fields[0], fields[1]]) != sae::GROUP_P256 {
+                    return Err("bad SAE commit");
+                }
+                self.sae.as_mut().ok_or("no SAE exchange")?.peer_commit(&fields[2..2 + sae::COMMIT_LEN])?;
+                self.confirm()
+            }
+            // Our confirm was lost; answer the AP's repeated commit with another
+            (SAE_COMMIT, STATUS_SUCCESS, State::Confirmed) => self.confirm(),
+            (SAE_CONFIRM, STATUS_SUCCESS, State::Confirmed) => {
+                if fields.len() < 2 + sae::CONFIRM_LEN { return Err("bad SAE confirm"); }
+                let peer_send_confirm = u16::from_le_bytes([fields[0], fields[1]]);
+                self.sae.as_ref().ok_or("no SAE exchange")?.check_confirm(peer_send_confirm, &fields[2..2 + sae::CONFIRM_LEN])?;
+                self.authenticated()
+            }
+            (_, STATUS_SUCCESS, _) => Ok(()),
+            _ => Err("authentication rejected"),
+        }
+    }
+
+    fn on_associated(&mut self, status: u16) -> Result<(), &'static str> {
+        if self.state != State::Associating { return Ok(()); }
+        match status {
+            STATUS_SUCCESS => {
+                let i = self.pmksa.ok_or("no PMK")?;
+                let t = self.target.as_ref().ok_or("no network")?;
+                let pmk = keyring::open(self.cache[i].key, None)?;
+                self.handshake = Some(Handshake::new(
+                    t.bss.bssid, self.nic.mac(), pmk, self.cache[i].pmkid, &t.bss.rsne, eapol::rsne(Some(&self.cache[i].pmkid)),
+                ));
+                self.step(State::Handshake);
+                Ok(())
+            }
+            // The AP has forgotten our PMK; authenticate again
+            STATUS_INVALID_PMKID if self.pmksa.is_some() => {
+                if let Some(i) = self.pmksa.take() { keyring::remove_kernel(self.cache.remove(i).key); }
+                self.commit(None)
+            }
+            _ => Err("association rejected"),
+        }
+    }
+
+    fn on_eapol(&mut self, frame: &[u8]) -> Result<(), &'static str> {
+        let (Some(hs), Some(bssid)) = (self.handshake.as_mut(), self.target.as_ref().map(|t| t.bss.bssid)) else { return Ok(()) };
+        let was_complete = hs.complete();
+        let outcome = hs.input(&frame[ETH_HEADER..])?;
+        let complete = hs.complete();
+        if let Some(reply) = outcome.reply {
+            let mut f = Vec::with_capacity(ETH_HEADER + reply.len());
+            f.extend_from_slice(&bssid.0);
+            f.extend_from_slice(&self.nic.mac().0);
+            f.extend_from_slice(&eapol::ETHERTYPE_EAPOL.to_be_bytes());
+            f.extend_from_slice(&reply);
+            self.nic.transmit(&f)?;
+        }
+        for key in &outcome.install { self.nic.install_key(key)?; }
+        self.deadline = now() + STEP_TIMEOUT_MS;
+        if complete && !was_complete {
+            self.state = State::Connected;
+            self.error = None;
+            crate::process::defer(link_changed);
+        }
+        Ok(())
+    }
+
+    /// Handle the NIC's events and the step timer.
+    fn poll(&mut self) {
+        while let Some(event) = self.nic.poll() {
+            let target = self.bssid();
+            let result = match event {
+                NicEvent::Auth { bssid, body } if target == Some(bssid) => self.on_auth(&body),
+                NicEvent::Associated { bssid, status } if target == Some(bssid) => self.on_associated(status),
+                NicEvent::Lost { bssid, .. } if target == Some(bssid) && self.state != State::Idle => {
+                    let was_connected = self.state == State::Connected;
+                    self.reset();
+                    if was_connected { crate::process::defer(link_changed); }
+                    self.start()
+                }
+                NicEvent::Frame(f) if f.len() > ETH_HEADER => {
+                    if u16::from_be_bytes([f[12], f[13]]) == eapol::ETHERTYPE_EAPOL {
+                        self.on_eapol(&f)
+                    } else {
+                        if self.state == State::Connected && self.rx.len() < RX_QUEUE { self.rx.push_back(f); }
+                        Ok(())
+                    }
+                }
+                _ => Ok(()),
+            };
+            if let Err(e) = result {
+                let code = if e == "MIC failure" { REASON_MIC_FAILURE } else { REASON_LEAVING };
+                self.fail(e, code);
+            }
+        }
+
+        if matches!(self.state, State::Idle | State::Connected) || now() < self.deadline { return; }
+        let retry = match self.state {
+            _ if self.tries >= MAX_TRIES => Err("timed out"),
+            State::Committed   => self.commit(None),
+            State::Confirmed   => self.confirm(),
+            State::Associating => self.associate(),
+            _                  => Err("4-way handshake timed out"),
+        };
+        if let Err(e) = retry {
+            let code = if self.state == State::Handshake { REASON_HANDSHAKE_TIMEOUT } else { REASON_LEAVING };
+            self.fail(e, code);
+        }
+    }
+}
+
+/// The link came up or went down: get a fresh DHCP lease for it.
+fn link_changed() {
+    let Some(index) = STATION.lock().as_ref().and_then(|s| s.index) else { return };
+    net::networkd::run(|| {
+        let _ = net::dhcp::stop(index);
+        if let Err(e) = net::dhcp::start(index) {
+            crate::println!("  [wifi] wlan0: DHCP failed to start: {}", e);
+        }
+    });
+}
+
+fn with_station<R>(f: impl FnOnce(&mut Station) -> R) -> Result<R, &'static str> {
+    STATION.lock().as_mut().map(f).ok_or("no Wi-Fi device")
+}
+
+// ─── network interface ────────────────────────────────────────────────────────
+
+/// `wlan0` as the stack sees it.
+struct Wlan;
+
+impl NetDevice for Wlan {
+    fn mac(&self) -> Option<MacAddr> {
+        STATION.lock().as_ref().map(|s| s.nic.mac())
+    }
+
+    fn mtu(&self) -> usize {
+        1500
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> Result<(), &'static str> {
+        with_station(|s| {
+            if s.state != State::Connected { return Err("not connected"); }
+            s.nic.transmit(frame)
+        })?
+    }
+
+    /// The stack polls this every tick, which also runs the station.
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        with_station(|s| {
+            s.poll();
+            s.rx.pop_front()
+        }).ok().flatten()
+    }
+}
+
+// ─── device driver ────────────────────────────────────────────────────────────
+
+/// `wlan0` in the device registry, for control.  Data moves over the
+/// network interface, not read and write.
+pub struct WiFiDriver;
+
+impl Driver for WiFiDriver {
+    fn name(&self) -> &'static str { "wifi-fullmac" }
+
+    fn read(&mut self, _dev: &Device, _cap: &Capability, _buf: &mut [u8]) -> Result<usize, &'static str> {
+        Err("use the wlan0 network interface")
+    }
+
+    fn write(&mut self, _dev: &Device, _cap: &Capability, _buf: &[u8]) -> Result<usize, &'static str> {
+        Err("use the wlan0 network interface")
+    }
+
+    fn ioctl(&mut self, dev: &Device, cap: &Capability, cmd: u32, _arg: usize) -> Result<usize, &'static str> {
+        check_access(dev, cap, self.ioctl_permissions(cmd))?;
+        match cmd {
+            WIFI_IOCTL_DISCONNECT => with_station(Station::leave).map(|_| 0),
+            WIFI_IOCTL_STATE      => with_station(|s| { s.poll(); s.state as usize }),
+            WIFI_IOCTL_FORGET     => with_station(Station::forget).map(|_| 0),
+            _                     => Err("unsupported ioctl"),
+        }
+    }
+
+    fn ioctl_permissions(&self, cmd: u32) -> Permissions {
+        match cmd {
+            WIFI_IOCTL_STATE => Permissions::READ,
+            _                => Permissions::CONTROL,
+        }
+    }
+}
+
+/// Register `nic` as `wlan0`, both network interface and device.
+pub fn attach(nic: Box<dyn WifiNic>) -> Result<DeviceId, &'static str> {
+    {
+        let mut station = STATION.lock();
+        if station.is_some() { return Err("Wi-Fi device already attached"); }
+        *station = Some(Station {
+            nic, index: None, state: State::Idle, target: None, sae: None, send_confirm: 0, pmksa: None,
+            cache: Vec::new(), handshake: None, rx: VecDeque::new(), deadline: 0, tries: 0, error: None,
+        });
+    }
+    let index = net::attach("wlan0", Box::new(Wlan));
+    with_station(|s| s.index = Some(index))?;
+    driver::register("wlan0", DeviceClass::Wifi, Box::new(WiFiDriver))
+}
+
+// ─── public API ───────────────────────────────────────────────────────────────
+
+fn authorize(cap: &Capability, perms: Permissions) -> Result<(), &'static str> {
+    let dev = driver::find_device("wlan0").ok_or("no Wi-Fi device")?;
+    check_access(&dev, cap, perms)
+}
+
+fn scan_networks() -> Result<Vec<Bss>, &'static str> {
+    let mut found = with_station(|s| s.nic.scan())??;
+    found.sort_by_key(|b| core::cmp::Reverse(b.rssi));
+    Ok(found)
+}
+
+/// Scan for networks, strongest first.
+pub fn scan(cap: &Capability) -> Result<Vec<Bss>, &'static str> {
+    authorize(cap, Permissions::READ)?;
+    scan_networks()
+}
+
+/// Join WPA3 network `ssid` with the passphrase in the caller's key
+/// `credential`, at its strongest access point.  Returns once SAE has
+/// started; `status` tells how it went.
+pub fn connect(cap: &Capability, ssid: &[u8], credential: KeyId) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    let owner = current_pid();
+    if keyring::info(credential).ok_or("no such key")?.owner != Some(owner) { return Err("key belongs to another process"); }
+    let networks = scan_networks()?;
+    let bss = networks.into_iter().filter(|b| b.ssid == ssid).find(|b| eapol::supports_wpa3(&b.rsne))
+        .ok_or("no WPA3 network with that name")?;
+    with_station(|s| {
+        s.leave();
+        s.target = Some(Target { bss, credential, owner });
+        s.start().inspect_err(|&e| s.fail(e, REASON_LEAVING))
+    })?
+}
+
+pub fn disconnect(cap: &Capability) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    with_station(Station::leave)
+}
+
+/// Drop every cached PMK, so the next connection runs SAE.
+pub fn forget(cap: &Capability) -> Result<(), &'static str> {
+    authorize(cap, Permissions::CONTROL)?;
+    with_station(Station::forget)
+}
+
+pub fn status() -> Option<Status> {
+    STATION.lock().as_ref().map(|s| Status {
+        state: s.state, bss: s.target.as_ref().map(|t| t.bss.clone()), error: s.error,
+    })
+}
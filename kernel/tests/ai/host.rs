@@ -0,0 +1,70 @@
+//! Host stand-ins for the kernel services the AI modules call.
+
+pub mod arch {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    static START: OnceLock<Instant> = OnceLock::new();
+
+    /// Ticks of a 10 MHz counter, like the CLINT timer on QEMU virt.
+    pub fn read_mtime() -> u64 {
+        (START.get_or_init(Instant::now).elapsed().as_nanos() / 100) as u64
+    }
+
+    pub fn uptime_millis() -> u64 {
+        read_mtime() / 10_000
+    }
+
+    pub fn ticks_to_us(ticks: u64) -> u64 {
+        ticks / 10
+    }
+}
+
+pub mod entropy {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static STATE: AtomicU64 = AtomicU64::new(0x5eed);
+
+    /// SplitMix64; the tests want reproducible draws, not secrecy.
+    pub fn next_u32() -> u32 {
+        let mut z = STATE.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        ((z ^ (z >> 31)) >> 32) as u32
+    }
+}
+
+pub mod fs {
+    use alloc::sync::Arc;
+
+    pub fn map_file(path: &str) -> Result<Arc<[u8]>, &'static str> {
+        std::fs::read(path).map(Arc::from).map_err(|_| "no such file")
+    }
+}
+
+pub mod process {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ProcessId(pub usize);
+
+    impl core::fmt::Display for ProcessId {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    /// Nothing is deferred on the host: run it now.
+    pub fn defer(f: fn()) {
+        f()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provenance {
+    Signed,
+    Unsigned,
+}
+
+/// The tests load no signed files.
+pub fn verify_model(_data: &[u8], _sig_path: &str) -> Result<Provenance, &'static str> {
+    Ok(Provenance::Unsigned)
+}
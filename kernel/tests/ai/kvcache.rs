@@ -0,0 +1,93 @@
+//! KV cache eviction: with a budget too small for the sequence, greedy
+//! decoding must still be deterministic, and must agree with an unbounded
+//! cache up to the first eviction.
+
+use crate::ai::gguf::GgufFile;
+use crate::ai::kvcache::{KvCache, KvPrecision, MemoryGrant, PAGE_TOKENS};
+use crate::ai::transformer::{self, Transformer};
+use crate::capability::{self, CapabilityType, Permissions};
+use crate::model;
+use crate::process::ProcessId;
+
+/// Tokens generated after the prompt; several times the budgeted pages.
+const STEPS: usize = 8 * PAGE_TOKENS;
+/// Pages the budgeted cache may hold.
+const BUDGET_PAGES: usize = 3;
+
+/// A grant for `pages` pages of `precision`, owned by a process of its
+/// own so concurrent tests do not share a budget.
+fn grant(t: &Transformer, precision: KvPrecision, pages: usize, pid: usize) -> MemoryGrant {
+    let c = &t.cfg;
+    let per_head_pos = match precision {
+        KvPrecision::F32 => c.head_dim * 4,
+        KvPrecision::Q8  => c.head_dim + 4,
+    };
+    let page_bytes = 2 * c.n_layer * PAGE_TOKENS * c.n_head_kv * per_head_pos;
+    let owner = ProcessId(pid);
+    let cap = capability::create_capability(owner, CapabilityType::Memory { base: 0, len: pages * page_bytes }, Permissions::WRITE);
+    MemoryGrant { owner, cap }
+}
+
+/// Greedy decode `STEPS` tokens after `prompt`.
+fn decode(g: &GgufFile, t: &Transformer, cache: &mut KvCache, prompt: &[u32]) -> Vec<u32> {
+    let mut logits = vec![0.0; t.cfg.n_vocab];
+    for &tok in prompt { t.forward(g, None, cache, tok, &mut logits).unwrap(); }
+    let mut out = Vec::new();
+    for _ in 0..STEPS {
+        let next = transformer::argmax(&logits);
+        out.push(next);
+        t.forward(g, None, cache, next, &mut logits).unwrap();
+    }
+    out
+}
+
+const PROMPT: [u32; 5] = [1, 7, 19, 3, 42];
+
+fn check_eviction(precision: KvPrecision, pid: usize) {
+    let (g, t) = model::tiny_llama(11);
+
+    let mut unbounded = KvCache::new(&t.cfg, precision, None).unwrap();
+    let reference = decode(&g, &t, &mut unbounded, &PROMPT);
+    assert_eq!(unbounded.usage().evicted_tokens, 0);
+
+    let grant = grant(&t, precision, BUDGET_PAGES, pid);
+    let mut cache = KvCache::new(&t.cfg, precision, Some(&grant)).unwrap();
+    let first = decode(&g, &t, &mut cache, &PROMPT);
+    let usage = cache.usage();
+    assert!(usage.evicted_tokens > 0, "budget never forced an eviction");
+    assert_eq!(usage.pages, BUDGET_PAGES);
+
+    // Identical up to the first eviction: the first token that saw an
+    // evicted cache is the one after the budgeted pages filled up
+    let exact = BUDGET_PAGES * PAGE_TOKENS - PROMPT.len() + 1;
+    assert_eq!(first[..exact], reference[..exact]);
+
+    // Deterministic: a second cache under the same budget, and the first
+    // one cleared and reused, decode the same tokens
+    drop(cache);
+    let mut again = KvCache::new(&t.cfg, precision, Some(&grant)).unwrap();
+    assert_eq!(decode(&g, &t, &mut again, &PROMPT), first);
+    again.clear();
+    assert_eq!(again.positions(), 0);
+    assert_eq!(decode(&g, &t, &mut again, &PROMPT), first);
+}
+
+#[test]
+fn f32_eviction_is_deterministic() {
+    check_eviction(KvPrecision::F32, 9001);
+}
+
+#[test]
+fn q8_eviction_is_deterministic() {
+    check_eviction(KvPrecision::Q8, 9002);
+}
+
+#[test]
+fn budget_too_small_for_two_pages_fails() {
+    let (g, t) = model::tiny_llama(11);
+    let grant = grant(&t, KvPrecision::F32, 1, 9003);
+    let mut cache = KvCache::new(&t.cfg, KvPrecision::F32, Some(&grant)).unwrap();
+    let mut logits = vec![0.0; t.cfg.n_vocab];
+    let fed = (0..2 * PAGE_TOKENS).map(|i| t.forward(&g, None, &mut cache, i as u32 % 40, &mut logits)).position(|r| r.is_err());
+    assert_eq!(fed, Some(PAGE_TOKENS));
+}
@@ -0,0 +1,73 @@
+//! AI Stack Host Tests
+//! The kernel's tokenizer, sampler, transformer and KV cache, built for
+//! the build machine against std and tested there.  The modules are the
+//! kernel's own sources; the few kernel services they call (clock,
+//! CSPRNG, file mapping) are stood in for by `host`.  Models are tiny
+//! GGUF files generated in memory with seeded random weights, and the
+//! property tests draw their cases from a seeded generator, so every run
+//! checks the same cases.
+//!
+//! Run from the repository root with `make test`.
+
+#![allow(dead_code)]
+#![feature(allocator_ext)]
+
+extern crate alloc;
+
+mod host;
+mod prop;
+mod model;
+
+mod tokenizer;
+mod sampler;
+mod kvcache;
+
+// ─── kernel sources under test ────────────────────────────────────────────────
+
+use host::{arch, entropy, fs, process};
+pub use std::println;
+
+#[path = "../../src/capability.rs"]
+mod capability;
+#[path = "../../src/fdt.rs"]
+mod fdt;
+
+#[path = "../../src/memory"]
+mod memory {
+    #[path = "secure.rs"]
+    mod secure;
+    #[path = "slab.rs"]
+    mod slab;
+    pub use secure::{secure_vec, SecureVec, ZeroOnFree};
+    pub use slab::{TypeSlab, CAPABILITIES};
+}
+
+#[path = "../../src/ai"]
+mod ai {
+    #[path = "gguf.rs"]
+    pub mod gguf;
+    #[path = "gpu.rs"]
+    pub mod gpu;
+    #[path = "indic.rs"]
+    pub mod indic;
+    #[path = "kernels.rs"]
+    pub mod kernels;
+    #[path = "kvcache.rs"]
+    pub mod kvcache;
+    #[path = "langid.rs"]
+    pub mod langid;
+    #[path = "lora.rs"]
+    pub mod lora;
+    #[path = "metrics.rs"]
+    pub mod metrics;
+    #[path = "npu.rs"]
+    pub mod npu;
+    #[path = "sampler.rs"]
+    pub mod sampler;
+    #[path = "tokenizer.rs"]
+    pub mod tokenizer;
+    #[path = "transformer.rs"]
+    pub mod transformer;
+
+    pub use crate::host::{verify_model, Provenance};
+}
@@ -0,0 +1,223 @@
+//! Model files for the tests, built in memory: a GGUF writer, a tiny
+//! llama-architecture model with seeded random weights, and vocabularies
+//! for each tokenizer algorithm trained on a sample text.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::sync::Arc;
+
+use crate::ai::gguf::GgufFile;
+use crate::ai::tokenizer::Tokenizer;
+use crate::ai::transformer::Transformer;
+use crate::prop::Rng;
+
+const ALIGNMENT: usize = 32;
+
+pub enum Meta {
+    U32(u32),
+    F32(f32),
+    Bool(bool),
+    Str(String),
+    Strs(Vec<String>),
+    F32s(Vec<f32>),
+    I32s(Vec<i32>),
+}
+
+/// GGUF v3 writer; tensors are F32.
+#[derive(Default)]
+pub struct Writer {
+    meta:    Vec<(String, Meta)>,
+    tensors: Vec<(String, Vec<u64>, Vec<f32>)>,
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    out.extend_from_slice(&(s.len() as u64).to_le_bytes());
+    out.extend_from_slice(s.as_bytes());
+}
+
+impl Writer {
+    pub fn meta(&mut self, key: &str, value: Meta) -> &mut Self {
+        self.meta.push((key.into(), value));
+        self
+    }
+
+    pub fn tensor(&mut self, name: &str, dims: &[u64], data: Vec<f32>) -> &mut Self {
+        assert_eq!(dims.iter().product::<u64>() as usize, data.len(), "{}", name);
+        self.tensors.push((name.into(), dims.to_vec(), data));
+        self
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"GGUF");
+        out.extend_from_slice(&3u32.to_le_bytes());
+        out.extend_from_slice(&(self.tensors.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(self.meta.len() as u64).to_le_bytes());
+        for (key, value) in &self.meta {
+            put_str(&mut out, key);
+            let array = |out: &mut Vec<u8>, ty: u32, n: usize| {
+                out.extend_from_slice(&9u32.to_le_bytes());
+                out.extend_from_slice(&ty.to_le_bytes());
+                out.extend_from_slice(&(n as u64).to_le_bytes());
+            };
+            match value {
+                Meta::U32(v)  => { out.extend_from_slice(&4u32.to_le_bytes()); out.extend_from_slice(&v.to_le_bytes()); }
+                Meta::F32(v)  => { out.extend_from_slice(&6u32.to_le_bytes()); out.extend_from_slice(&v.to_le_bytes()); }
+                Meta::Bool(v) => { out.extend_from_slice(&7u32.to_le_bytes()); out.push(*v as u8); }
+                Meta::Str(s)  => { out.extend_from_slice(&8u32.to_le_bytes()); put_str(&mut out, s); }
+                Meta::Strs(v) => { array(&mut out, 8, v.len()); for s in v { put_str(&mut out, s); } }
+                Meta::F32s(v) => { array(&mut out, 6, v.len()); for x in v { out.extend_from_slice(&x.to_le_bytes()); } }
+                Meta::I32s(v) => { array(&mut out, 5, v.len()); for x in v { out.extend_from_slice(&x.to_le_bytes()); } }
+            }
+        }
+        let mut offset = 0;
+        for (name, dims, data) in &self.tensors {
+            put_str(&mut out, name);
+            out.extend_from_slice(&(dims.len() as u32).to_le_bytes());
+            for d in dims { out.extend_from_slice(&d.to_le_bytes()); }
+            out.extend_from_slice(&0u32.to_le_bytes()); // F32
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset = (offset + data.len() * 4).next_multiple_of(ALIGNMENT);
+        }
+        for (_, _, data) in &self.tensors {
+            out.resize(out.len().next_multiple_of(ALIGNMENT), 0);
+            for x in data { out.extend_from_slice(&x.to_le_bytes()); }
+        }
+        out
+    }
+
+    pub fn parse(&self) -> GgufFile {
+        GgufFile::parse(Arc::from(self.bytes())).expect("writer produced a bad GGUF file")
+    }
+}
+
+// ─── models ───────────────────────────────────────────────────────────────────
+
+pub const N_VOCAB: usize = 48;
+
+/// A two-layer llama with grouped-query attention and weights drawn from
+/// `seed`; big enough to have non-trivial attention, small enough to run
+/// hundreds of tokens in a test.
+pub fn tiny_llama(seed: u64) -> (GgufFile, Transformer) {
+    let (n_embd, n_head, n_head_kv, n_layer, n_ff) = (32u64, 4u64, 2u64, 2usize, 64u64);
+    let kv_dim = n_embd / n_head * n_head_kv;
+    let mut rng = Rng::new(seed);
+    let mut rand = |n: u64, scale: f32| (0..n).map(|_| rng.uniform(-scale, scale)).collect::<Vec<f32>>();
+
+    let mut w = Writer::default();
+    w.meta("general.architecture", Meta::Str("llama".into()))
+        .meta("llama.embedding_length", Meta::U32(n_embd as u32))
+        .meta("llama.block_count", Meta::U32(n_layer as u32))
+        .meta("llama.attention.head_count", Meta::U32(n_head as u32))
+        .meta("llama.attention.head_count_kv", Meta::U32(n_head_kv as u32))
+        .meta("llama.feed_forward_length", Meta::U32(n_ff as u32))
+        .meta("llama.context_length", Meta::U32(512))
+        .tensor("token_embd.weight", &[n_embd, N_VOCAB as u64], rand(n_embd * N_VOCAB as u64, 1.0))
+        .tensor("output_norm.weight", &[n_embd], vec![1.0; n_embd as usize])
+        .tensor("output.weight", &[n_embd, N_VOCAB as u64], rand(n_embd * N_VOCAB as u64, 0.5));
+    for i in 0..n_layer {
+        let t = |n: &str| format!("blk.{}.{}", i, n);
+        w.tensor(&t("attn_norm.weight"), &[n_embd], vec![1.0; n_embd as usize])
+            .tensor(&t("attn_q.weight"), &[n_embd, n_embd], rand(n_embd * n_embd, 0.3))
+            .tensor(&t("attn_k.weight"), &[n_embd, kv_dim], rand(n_embd * kv_dim, 0.3))
+            .tensor(&t("attn_v.weight"), &[n_embd, kv_dim], rand(n_embd * kv_dim, 0.3))
+            .tensor(&t("attn_output.weight"), &[n_embd, n_embd], rand(n_embd * n_embd, 0.3))
+            .tensor(&t("ffn_norm.weight"), &[n_embd], vec![1.0; n_embd as usize])
+            .tensor(&t("ffn_gate.weight"), &[n_embd, n_ff], rand(n_embd * n_ff, 0.3))
+            .tensor(&t("ffn_up.weight"), &[n_embd, n_ff], rand(n_embd * n_ff, 0.3))
+            .tensor(&t("ffn_down.weight"), &[n_ff, n_embd], rand(n_ff * n_embd, 0.3));
+    }
+    let g = w.parse();
+    let t = Transformer::from_gguf(&g).expect("tiny model does not load");
+    (g, t)
+}
+
+// ─── vocabularies ─────────────────────────────────────────────────────────────
+
+const SPM_SPACE: char = '\u{2581}';
+
+/// A SentencePiece vocabulary (`model` "llama" or "t5") over `text`: the
+/// control tokens, all 256 byte tokens, and every character, character
+/// pair and word of the text, longer pieces scoring higher.
+pub fn sentencepiece(model: &str, text: &str) -> Tokenizer {
+    let mut tokens: Vec<String> = vec!["<unk>".into(), "<s>".into(), "</s>".into()];
+    let mut types = vec![2, 3, 3];
+    for b in 0..=255u8 {
+        tokens.push(format!("<0x{:02X}>", b));
+        types.push(6);
+    }
+
+    let marked: String = text.chars().map(|c| if c == ' ' { SPM_SPACE } else { c }).collect();
+    let chars: Vec<char> = marked.chars().collect();
+    let mut pieces = BTreeSet::new();
+    pieces.extend(chars.iter().map(|c| c.to_string()));
+    pieces.extend(chars.windows(2).map(|p| p.iter().collect::<String>()));
+    pieces.extend(text.split(' ').filter(|w| !w.is_empty()).map(|w| format!("{}{}", SPM_SPACE, w)));
+    let mut scores = vec![0.0; tokens.len()];
+    for p in pieces {
+        scores.push(p.chars().count() as f32 - 10.0);
+        tokens.push(p);
+        types.push(1);
+    }
+
+    let mut w = Writer::default();
+    w.meta("tokenizer.ggml.model", Meta::Str(model.into()))
+        .meta("tokenizer.ggml.tokens", Meta::Strs(tokens))
+        .meta("tokenizer.ggml.scores", Meta::F32s(scores))
+        .meta("tokenizer.ggml.token_type", Meta::I32s(types))
+        .meta("tokenizer.ggml.bos_token_id", Meta::U32(1))
+        .meta("tokenizer.ggml.eos_token_id", Meta::U32(2));
+    Tokenizer::from_gguf(&w.parse()).expect("sentencepiece vocabulary does not load")
+}
+
+/// The printable stand-in GPT-2 vocabularies use for byte `b`.
+fn byte_char(b: u8) -> char {
+    let cp = match b {
+        b'!'..=b'~' | 0xA1..=0xAC | 0xAE..=0xFF => b as u32,
+        0x00..=0x20 => 0x100 + b as u32,
+        0x7F..=0xA0 => 0x100 + 33 + (b - 0x7F) as u32,
+        _           => 0x100 + 67,
+    };
+    char::from_u32(cp).unwrap()
+}
+
+/// A byte-level BPE vocabulary with `merges` merges learnt from `text`
+/// the usual way: repeatedly join the most frequent adjacent pair.
+pub fn byte_bpe(text: &str, merges: usize) -> Tokenizer {
+    let mut tokens: Vec<String> = (0..=255u8).map(|b| byte_char(b).to_string()).collect();
+    let mut words: Vec<Vec<String>> = text.split_inclusive(' ')
+        .map(|w| w.bytes().map(|b| byte_char(b).to_string()).collect())
+        .collect();
+    let mut learnt = Vec::new();
+    for _ in 0..merges {
+        let mut counts: BTreeMap<(String, String), usize> = BTreeMap::new();
+        for w in &words {
+            for p in w.windows(2) { *counts.entry((p[0].clone(), p[1].clone())).or_default() += 1; }
+        }
+        // Most frequent, ties to the smallest pair so training is repeatable
+        let Some(((l, r), _)) = counts.into_iter().max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0))) else { break };
+        let joined = format!("{}{}", l, r);
+        for w in &mut words {
+            let mut i = 0;
+            while i + 1 < w.len() {
+                if w[i] == l && w[i + 1] == r {
+                    w[i] = joined.clone();
+                    w.remove(i + 1);
+                }
+                i += 1;
+            }
+        }
+        learnt.push(format!("{} {}", l, r));
+        if !tokens.contains(&joined) { tokens.push(joined); }
+    }
+    tokens.push("<|endoftext|>".into());
+    let mut types = vec![1; tokens.len()];
+    *types.last_mut().unwrap() = 3;
+
+    let mut w = Writer::default();
+    w.meta("tokenizer.ggml.model", Meta::Str("gpt2".into()))
+        .meta("tokenizer.ggml.tokens", Meta::Strs(tokens))
+        .meta("tokenizer.ggml.token_type", Meta::I32s(types))
+        .meta("tokenizer.ggml.merges", Meta::Strs(learnt))
+        .meta("tokenizer.ggml.add_space_prefix", Meta::Bool(false));
+    Tokenizer::from_gguf(&w.parse()).expect("bpe vocabulary does not load")
+}
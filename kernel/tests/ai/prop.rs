@@ -0,0 +1,49 @@
+//! Seeded case generation for the property tests.
+
+/// Cases each property is checked on.
+pub const CASES: u64 = 64;
+
+/// xorshift64*; deterministic so a failing case can be replayed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `lo..hi`.
+    pub fn range(&mut self, lo: usize, hi: usize) -> usize {
+        lo + (self.next_u64() % (hi - lo) as u64) as usize
+    }
+
+    /// Uniform in [0, 1).
+    pub fn unit(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Uniform in [lo, hi).
+    pub fn uniform(&mut self, lo: f32, hi: f32) -> f32 {
+        lo + self.unit() * (hi - lo)
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.range(0, items.len())]
+    }
+}
+
+/// Check `property` on `CASES` generated cases; a failure names the case.
+pub fn check(name: &str, mut property: impl FnMut(&mut Rng) -> Result<(), String>) {
+    for case in 0..CASES {
+        let mut rng = Rng::new(case);
+        if let Err(e) = property(&mut rng) {
+            panic!("{}: case {} failed: {}", name, case, e);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Sampler bounds: greedy picks the largest logit, and top-k / top-p only
+//! ever return tokens inside the candidate set they define.
+
+use crate::ai::kernels;
+use crate::ai::sampler::{self, SamplerConfig};
+use crate::prop::{self, Rng};
+
+/// Distinct logits, so ranks are unambiguous.
+fn random_logits(rng: &mut Rng) -> Vec<f32> {
+    let n = rng.range(2, 200);
+    let mut logits: Vec<f32> = (0..n).map(|_| rng.uniform(-8.0, 8.0)).collect();
+    for (i, l) in logits.iter_mut().enumerate() { *l += i as f32 * 1e-4; }
+    logits
+}
+
+/// Rank of `id` by logit, 0 being the largest.
+fn rank(logits: &[f32], id: u32) -> usize {
+    let l = logits[id as usize];
+    logits.iter().filter(|&&x| x > l).count()
+}
+
+fn draw(cfg: &SamplerConfig, logits: &[f32]) -> u32 {
+    sampler::sample(cfg, &mut logits.to_vec(), [].iter())
+}
+
+#[test]
+fn greedy_picks_the_largest_logit() {
+    prop::check("greedy_picks_the_largest_logit", |rng| {
+        let logits = random_logits(rng);
+        let id = draw(&SamplerConfig::default(), &logits);
+        match rank(&logits, id) {
+            0 => Ok(()),
+            r => Err(format!("picked rank {}", r)),
+        }
+    });
+}
+
+#[test]
+fn validate_rejects_bad_configs() {
+    let bad = [
+        SamplerConfig { temperature: -1.0, ..Default::default() },
+        SamplerConfig { temperature: f32::NAN, ..Default::default() },
+        SamplerConfig { temperature: f32::INFINITY, ..Default::default() },
+        SamplerConfig { top_p: 0.0, ..Default::default() },
+        SamplerConfig { top_p: 1.5, ..Default::default() },
+        SamplerConfig { repeat_penalty: 0.0, ..Default::default() },
+        SamplerConfig { stop: vec![String::new()], ..Default::default() },
+    ];
+    for cfg in bad { assert!(cfg.validate().is_err(), "{:?}", cfg); }
+    assert!(SamplerConfig::default().validate().is_ok());
+}
+
+#[test]
+fn top_k_stays_within_the_k_largest() {
+    prop::check("top_k_stays_within_the_k_largest", |rng| {
+        let logits = random_logits(rng);
+        let k = rng.range(1, logits.len() + 1);
+        let cfg = SamplerConfig { temperature: rng.uniform(0.1, 3.0), top_k: k, ..Default::default() };
+        for _ in 0..16 {
+            let id = draw(&cfg, &logits);
+            let r = rank(&logits, id);
+            if r >= k { return Err(format!("top_k {} returned rank {}", k, r)); }
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn top_p_stays_within_the_nucleus() {
+    prop::check("top_p_stays_within_the_nucleus", |rng| {
+        let logits = random_logits(rng);
+        let temperature = rng.uniform(0.1, 3.0);
+        let top_p = rng.uniform(0.05, 1.0);
+        let cfg = SamplerConfig { temperature, top_p, ..Default::default() };
+
+        // The nucleus: the fewest most-likely tokens reaching top_p, with
+        // slack for the sampler's own float rounding
+        let mut probs: Vec<f32> = logits.iter().map(|l| l / temperature).collect();
+        kernels::softmax(&mut probs);
+        probs.sort_unstable_by(|a, b| b.total_cmp(a));
+        let mut cum = 0.0;
+        let nucleus = probs.iter().position(|p| { cum += p; cum >= top_p - 1e-4 }).map_or(probs.len(), |i| i + 1);
+
+        for _ in 0..16 {
+            let id = draw(&cfg, &logits);
+            let r = rank(&logits, id);
+            if r > nucleus { return Err(format!("top_p {} returned rank {}, nucleus {}", top_p, r, nucleus)); }
+        }
+        Ok(())
+    });
+}
+
+#[test]
+fn top_k_one_is_greedy() {
+    prop::check("top_k_one_is_greedy", |rng| {
+        let logits = random_logits(rng);
+        let cfg = SamplerConfig { temperature: 1.0, top_k: 1, ..Default::default() };
+        match rank(&logits, draw(&cfg, &logits)) {
+            0 => Ok(()),
+            r => Err(format!("picked rank {}", r)),
+        }
+    });
+}
@@ -0,0 +1,129 @@
+//! Tokenizer round trips: decode(encode(x)) gives back the normalised
+//! text for every scheduled language and each tokenizer algorithm.
+
+use crate::ai::indic;
+use crate::ai::langid::{Language, SCHEDULED};
+use crate::ai::tokenizer::Tokenizer;
+use crate::model;
+use crate::prop::{self, Rng};
+
+/// A sentence in each scheduled language, in its usual script.
+const SAMPLES: [(Language, &str); 22] = [
+    (Language::Assamese,  "মই অসমীয়া ভাষাত কথা কওঁ।"),
+    (Language::Bengali,   "আমি বাংলায় কথা বলি।"),
+    (Language::Bodo,      "आं बर' रावाव रायज्लायो।"),
+    (Language::Dogri,     "में डोगरी च गल्ल करदा आं।"),
+    (Language::Gujarati,  "હું ગુજરાતી બોલું છું."),
+    (Language::Hindi,     "मैं हिन्दी में बात करता हूँ।"),
+    (Language::Kannada,   "ನಾನು ಕನ್ನಡ ಮಾತನಾಡುತ್ತೇನೆ."),
+    (Language::Kashmiri,  "بہٕ چھُس کٲشُر بولان۔"),
+    (Language::Konkani,   "हांव कोंकणी उलयता."),
+    (Language::Maithili,  "हम मैथिली बजैत छी।"),
+    (Language::Malayalam, "ഞാൻ മലയാളം സംസാരിക്കുന്നു."),
+    (Language::Manipuri,  "ꯑꯩ ꯃꯅꯤꯄꯨꯔꯤ ꯂꯣꯟ ꯉꯥꯡꯏ꯫"),
+    (Language::Marathi,   "मी मराठी बोलतो."),
+    (Language::Nepali,    "म नेपाली बोल्छु।"),
+    (Language::Odia,      "ମୁଁ ଓଡ଼ିଆ କହେ।"),
+    (Language::Punjabi,   "ਮੈਂ ਪੰਜਾਬੀ ਬੋਲਦਾ ਹਾਂ।"),
+    (Language::Sanskrit,  "अहं संस्कृतं वदामि।"),
+    (Language::Santali,   "ᱤᱧ ᱥᱟᱱᱛᱟᱲᱤ ᱨᱚᱲᱟᱜ ᱠᱟᱱᱟ᱾"),
+    (Language::Sindhi,    "مان سنڌي ڳالهايان ٿو."),
+    (Language::Tamil,     "நான் தமிழ் பேசுகிறேன்."),
+    (Language::Telugu,    "నేను తెలుగు మాట్లాడతాను."),
+    (Language::Urdu,      "میں اردو بولتا ہوں۔"),
+];
+
+fn corpus() -> String {
+    SAMPLES.iter().map(|(_, s)| *s).collect::<Vec<_>>().join(" ")
+}
+
+/// One tokenizer of each algorithm, trained on every sample.
+fn tokenizers() -> [(&'static str, Tokenizer); 3] {
+    let text = corpus();
+    [
+        ("llama", model::sentencepiece("llama", &text)),
+        ("t5",    model::sentencepiece("t5", &text)),
+        ("gpt2",  model::byte_bpe(&text, 400)),
+    ]
+}
+
+fn round_trip(name: &str, t: &Tokenizer, text: &str) -> Result<(), String> {
+    let ids = t.encode(text, false);
+    let back = t.decode(&ids);
+    let want = indic::normalize(text);
+    if back != want {
+        return Err(format!("{}: {:?} came back as {:?} (tokens {:?})", name, want, back, ids));
+    }
+    Ok(())
+}
+
+#[test]
+fn every_scheduled_language_round_trips() {
+    assert!(SCHEDULED.iter().all(|l| SAMPLES.iter().any(|(s, _)| s == l)));
+    for (name, t) in tokenizers() {
+        for (lang, text) in SAMPLES {
+            if let Err(e) = round_trip(name, &t, text) { panic!("{}: {}", lang.code(), e); }
+        }
+    }
+}
+
+#[test]
+fn known_text_uses_vocabulary_pieces() {
+    // Text the vocabulary was built from needs no byte fallback, so it
+    // comes out shorter than its UTF-8 encoding
+    for (name, t) in tokenizers() {
+        for (lang, text) in SAMPLES {
+            let n = t.encode(text, false).len();
+            assert!(n < text.len(), "{} {}: {} tokens for {} bytes", name, lang.code(), n, text.len());
+        }
+    }
+}
+
+/// Characters random strings are drawn from: some of each sample script,
+/// ASCII, punctuation, whitespace, and characters no vocabulary has.
+fn alphabet() -> Vec<char> {
+    let mut chars: Vec<char> = corpus().chars().collect();
+    chars.extend("abcXYZ019 .,'!?-\t\n".chars());
+    chars.extend(['é', 'ß', '€', '中', '😀', '\u{200d}', '\u{0}']);
+    chars.sort_unstable();
+    chars.dedup();
+    chars
+}
+
+fn random_text(rng: &mut Rng, alphabet: &[char]) -> String {
+    let len = rng.range(0, 40);
+    (0..len).map(|_| *rng.pick(alphabet)).collect()
+}
+
+#[test]
+fn random_text_round_trips() {
+    let alphabet = alphabet();
+    let tokenizers = tokenizers();
+    prop::check("random_text_round_trips", |rng| {
+        let text = random_text(rng, &alphabet);
+        for (name, t) in &tokenizers { round_trip(name, t, &text)?; }
+        Ok(())
+    });
+}
+
+#[test]
+fn text_cannot_forge_control_tokens() {
+    let text = "<s> </s> <unk> <|endoftext|>";
+    for (name, t) in [
+        ("llama", model::sentencepiece("llama", text)),
+        ("t5",    model::sentencepiece("t5", text)),
+        ("gpt2",  model::byte_bpe(text, 100)),
+    ] {
+        let ids = t.encode(text, false);
+        assert!(!ids.iter().any(|&id| t.is_control(id)), "{}: control token in {:?}", name, ids);
+        assert_eq!(t.decode(&ids), text, "{}", name);
+    }
+}
+
+#[test]
+fn bos_is_prepended_and_not_decoded() {
+    let t = model::sentencepiece("llama", &corpus());
+    let ids = t.encode("मी मराठी बोलतो.", true);
+    assert_eq!(ids.first().copied(), t.bos);
+    assert_eq!(t.decode(&ids), "मी मराठी बोलतो.");
+}
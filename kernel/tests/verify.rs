@@ -0,0 +1,299 @@
+//! Kernel Proofs
+//! Kani harnesses over the kernel's own sources: capabilities, the kernel
+//! log's ring and the type-isolated slabs.  Each states a property for
+//! every input Kani can choose — every set of rights, every cut point in
+//! a delegation chain, every state a warm reset can leave the ring in,
+//! every pair of allocation sizes — and `make verify` (cargo kani, with
+//! the flags in Cargo.toml's `package.metadata.kani`) proves it.
+//!
+//!   delegation_narrows          what is delegated is a subset of what
+//!                               was held, two hands down, and nothing
+//!                               within it is refused
+//!   revocation_cascades         revoking any link of a chain revokes it
+//!                               and all after it, and nothing else
+//!   permission_check_complete   validation passes exactly when the
+//!                               caller's own handle names a capability
+//!                               of the right kind with the rights asked
+//!   klog_ring_in_bounds         a ring `valid` accepts indexes inside its
+//!                               buffer and stays valid as it fills
+//!   slab_objects_disjoint       live slab objects never overlap, and one
+//!                               freed and taken again comes back zeroed
+//!
+//! The heap itself has no buddy allocator — it is linked_list_allocator —
+//! so the allocator proof is of the slabs' power-of-two size classes.
+//!
+//! Without Kani, `make test` runs the same harnesses once each as tests,
+//! on values drawn from a seeded generator, so they build and hold on one
+//! case at least.
+
+#![allow(dead_code)]
+#![feature(allocator_ext)]
+
+extern crate alloc;
+
+use alloc::alloc::{Allocator, Layout};
+use alloc::boxed::Box;
+use core::ptr::NonNull;
+
+use capability::{CapHandle, Capability, CapabilityType, Permissions};
+use memory::TypeSlab;
+use process::ProcessId;
+
+// ─── kernel sources under proof ───────────────────────────────────────────────
+
+#[path = "../src/capability.rs"]
+mod capability;
+#[path = "../src/klog.rs"]
+mod klog;
+
+#[path = "../src/memory"]
+mod memory {
+    #[path = "slab.rs"]
+    mod slab;
+    pub use slab::{TypeSlab, CAPABILITIES};
+}
+
+// ─── stand-ins for the kernel services they call ──────────────────────────────
+
+mod arch {
+    /// Time stands still: nothing here expires.
+    pub fn uptime_millis() -> u64 {
+        0
+    }
+
+    pub fn interrupts_disable() -> usize {
+        0
+    }
+
+    pub fn interrupts_restore(_prev: usize) {}
+}
+
+mod console {
+    pub fn _print(_args: core::fmt::Arguments) {}
+}
+
+mod entropy {
+    /// Any value at all: the slabs' shuffle is proved for every order.
+    pub fn next_u32() -> u32 {
+        crate::any_u32()
+    }
+}
+
+mod process {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    pub struct ProcessId(pub usize);
+
+    impl core::fmt::Display for ProcessId {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "{}", self.0)
+        }
+    }
+
+    pub fn defer(f: fn()) {
+        f()
+    }
+}
+
+fn any_u32() -> u32 {
+    kani::any()
+}
+
+/// Kani's `any`, outside Kani: a value drawn from a seeded generator.
+#[cfg(not(kani))]
+mod kani {
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static STATE: AtomicU64 = AtomicU64::new(0x5eed);
+
+    pub trait Arbitrary {
+        fn from_bits(bits: u64) -> Self;
+    }
+
+    macro_rules! arbitrary {
+        ($($t:ty),*) => { $(impl Arbitrary for $t { fn from_bits(bits: u64) -> Self { bits as $t } })* };
+    }
+
+    arbitrary!(u8, u16, u32, u64, usize);
+
+    impl Arbitrary for bool {
+        fn from_bits(bits: u64) -> Self { bits & 1 != 0 }
+    }
+
+    /// SplitMix64.
+    pub fn any<T: Arbitrary>() -> T {
+        let mut z = STATE.fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        T::from_bits(z ^ (z >> 31))
+    }
+}
+
+// ─── capabilities ─────────────────────────────────────────────────────────────
+
+/// Every right there is.
+const ALL: u32 = 0x1f;
+const RES: CapabilityType = CapabilityType::Device(0x4b54);
+
+/// Processes for one harness; distinct across harnesses, which share the
+/// registry when run as tests.
+fn pids(first: usize) -> [ProcessId; 3] {
+    [ProcessId(first), ProcessId(first + 1), ProcessId(first + 2)]
+}
+
+fn live(cap: &Capability) -> bool {
+    capability::validate(cap.owner, cap, cap.cap_type, Permissions::NONE).is_ok()
+}
+
+/// Whether delegating `asked` from `held` must succeed.
+fn may_delegate(held: Permissions, asked: Permissions) -> bool {
+    held.contains(Permissions::GRANT) && held.contains(asked)
+}
+
+#[cfg_attr(kani, kani::proof, kani::unwind(8))]
+#[cfg_attr(not(kani), test)]
+fn delegation_narrows() {
+    let p = pids(100);
+    let held = Permissions(kani::any::<u32>() & ALL);
+    let root = capability::create_capability(p[0], RES, held);
+    // Asking for anything at all, rights that do not exist included
+    let asked = Permissions(kani::any());
+    let Ok(child) = capability::delegate(p[0], root.handle, p[1], asked) else {
+        assert!(!may_delegate(held, asked));
+        return;
+    };
+    assert!(may_delegate(held, asked));
+    assert!(child.perms == asked && child.owner == p[1]);
+    assert!(child.cap_type == root.cap_type && child.expiry == root.expiry);
+
+    // And on again, narrower still
+    let again = Permissions(kani::any());
+    match capability::delegate(p[1], child.handle, p[2], again) {
+        Ok(grandchild) => assert!(may_delegate(child.perms, again) && held.contains(grandchild.perms)),
+        Err(_)         => assert!(!may_delegate(child.perms, again)),
+    }
+}
+
+/// Links in the chain `revocation_cascades` cuts.
+const CHAIN: usize = 4;
+
+#[cfg_attr(kani, kani::proof, kani::unwind(12))]
+#[cfg_attr(not(kani), test)]
+fn revocation_cascades() {
+    let p = pids(200);
+    let rg = Permissions::READ | Permissions::GRANT;
+    let mut chain = alloc::vec![capability::create_capability(p[0], RES, rg)];
+    for i in 1..=CHAIN {
+        let prev = &chain[i - 1];
+        let next = capability::delegate(prev.owner, prev.handle, p[i % p.len()], rg).unwrap();
+        chain.push(next);
+    }
+    // A branch off the root, beside the chain
+    let side = capability::delegate(p[0], chain[0].handle, p[2], rg).unwrap();
+
+    let cut = kani::any::<usize>() % (CHAIN + 1);
+    let side_too = usize::from(cut == 0);
+    assert_eq!(capability::revoke_capability(chain[cut].id), Ok(CHAIN + 1 - cut + side_too));
+    assert!(chain[..cut].iter().all(live));
+    assert!(!chain[cut..].iter().any(live));
+    assert_eq!(live(&side), cut != 0);
+    // Again, it finds nothing more below it
+    assert_eq!(capability::revoke_capability(chain[cut].id), Ok(1));
+}
+
+#[cfg_attr(kani, kani::proof, kani::unwind(8))]
+#[cfg_attr(not(kani), test)]
+fn permission_check_complete() {
+    let p = pids(300);
+    let kinds = [CapabilityType::Device(0), CapabilityType::Device(1), CapabilityType::File];
+    let kind = kinds[kani::any::<usize>() % kinds.len()];
+    let target = kinds[kani::any::<usize>() % kinds.len()];
+    let held = Permissions(kani::any::<u32>() & ALL);
+    let required = Permissions(kani::any());
+    let cap = capability::create_capability(p[0], kind, held);
+
+    let passed = capability::validate(p[0], &cap, target, required).is_ok();
+    assert_eq!(passed, kind == target && held.contains(required));
+    // The handle means nothing in another process's table
+    assert!(capability::validate(p[1], &cap, kind, Permissions::NONE).is_err());
+    // Nor does any other handle in its own
+    let forged = CapHandle(kani::any());
+    if forged != cap.handle {
+        assert!(capability::validate_handle(p[0], forged, kind, Permissions::NONE).is_err());
+    }
+}
+
+// ─── kernel log ring ──────────────────────────────────────────────────────────
+
+#[cfg_attr(kani, kani::proof, kani::unwind(2))]
+#[cfg_attr(not(kani), test)]
+fn klog_ring_in_bounds() {
+    use klog::{Ring, LOG_SIZE, MAGIC};
+
+    // What a warm reset leaves: perhaps a ring, perhaps garbage
+    let magic = if kani::any() { MAGIC } else { kani::any() };
+    let head = kani::any::<usize>() % (2 * LOG_SIZE);
+    let len = kani::any::<usize>() % (2 * LOG_SIZE + 1);
+    let mut r = Box::new(Ring { magic, head, len, buf: [0; LOG_SIZE] });
+    if !r.valid() { return; }
+
+    // Some byte held, counted from the oldest
+    let i = kani::any::<usize>() % LOG_SIZE;
+    let kept = (i < len).then(|| r.byte(i));
+
+    let b: u8 = kani::any();
+    r.push(b);
+    assert!(r.valid());
+    assert_eq!(r.len, (len + 1).min(LOG_SIZE));
+    assert_eq!(r.byte(r.len - 1), b);
+    // Full, it drops the oldest byte; otherwise what it held stays put
+    if len == LOG_SIZE {
+        assert_eq!(r.head, (head + 1) % LOG_SIZE);
+        if let Some(k) = kept.filter(|_| i > 0) { assert_eq!(r.byte(i - 1), k); }
+    } else {
+        assert_eq!(r.head, head);
+        if let Some(k) = kept { assert_eq!(r.byte(i), k); }
+    }
+}
+
+// ─── slabs ────────────────────────────────────────────────────────────────────
+
+static SLAB: TypeSlab = TypeSlab::new("verify");
+
+/// Objects of 128 B to 512 B: chunks of 8 to 32, which keeps the proof
+/// small; the classes differ only in those numbers.
+fn any_layout() -> Layout {
+    let size = 128 + kani::any::<usize>() % 385;
+    let align = 1 << (kani::any::<usize>() % 8);
+    Layout::from_size_align(size, align).unwrap()
+}
+
+fn disjoint(a: NonNull<[u8]>, b: NonNull<[u8]>) -> bool {
+    let (a0, b0) = (a.as_ptr() as *mut u8 as usize, b.as_ptr() as *mut u8 as usize);
+    a0 + a.len() <= b0 || b0 + b.len() <= a0
+}
+
+#[cfg_attr(kani, kani::proof, kani::unwind(520))]
+#[cfg_attr(not(kani), test)]
+fn slab_objects_disjoint() {
+    let (la, lb) = (any_layout(), any_layout());
+    let a = (&SLAB).allocate(la).unwrap();
+    let b = (&SLAB).allocate(lb).unwrap();
+    assert!(a.len() >= la.size() && b.len() >= lb.size());
+    assert!((a.as_ptr() as *mut u8 as usize).is_multiple_of(la.align()));
+    assert!(disjoint(a, b));
+
+    unsafe {
+        a.cast::<u8>().as_ptr().write_bytes(0xa5, la.size());
+        (&SLAB).deallocate(a.cast(), la);
+    }
+    let c = (&SLAB).allocate(la).unwrap();
+    assert!(disjoint(b, c));
+    if cfg!(feature = "heap-hardening") {
+        let i = kani::any::<usize>() % la.size();
+        assert_eq!(unsafe { *c.cast::<u8>().as_ptr().add(i) }, 0);
+    }
+    unsafe {
+        (&SLAB).deallocate(b.cast(), lb);
+        (&SLAB).deallocate(c.cast(), la);
+    }
+}
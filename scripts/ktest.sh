@@ -0,0 +1,82 @@
+#!/usr/bin/env bash
+# Boot the kernel built with the `ktest` feature under QEMU, let init run
+# the in-kernel test suites (kernel/src/ktest) and report how they went.
+#
+#   scripts/ktest.sh [riscv64|riscv64-sbi|aarch64] [suite|suite::test,...]
+#
+# Exit status: 0 every test passed, 1 a test failed or none matched, 2 the
+# kernel panicked, hung or stopped reporting, 3 it could not be built or
+# booted.  KTEST_TIMEOUT (seconds, default 120) bounds the run; the whole
+# console output is kept in kernel/target/ktest-<arch>.log.
+
+set -u
+cd "$(dirname "$0")/../kernel" || exit 3
+
+arch=${1:-riscv64}
+filter=${2:-}
+limit=${KTEST_TIMEOUT:-120}
+
+case $arch in
+riscv64)
+    build=(--features ktest)
+    elf=target/riscv64gc-unknown-none-elf/release/suraksha-kernel
+    qemu=(qemu-system-riscv64 -machine virt -bios none -m 256M)
+    ;;
+riscv64-sbi)
+    build=(--features ktest,sbi)
+    elf=target/riscv64gc-unknown-none-elf/release/suraksha-kernel
+    qemu=(qemu-system-riscv64 -machine virt -bios default -smp 4 -m 256M)
+    ;;
+aarch64)
+    build=(--features ktest --target aarch64-unknown-none)
+    elf=target/aarch64-unknown-none/release/suraksha-kernel
+    # Semihosting carries a failing run's exit status out (PSCI cannot)
+    qemu=(qemu-system-aarch64 -machine virt,gic-version=2 -cpu cortex-a76 -smp 4 -m 256M -semihosting)
+    ;;
+*)
+    echo "ktest: unknown arch '$arch': riscv64, riscv64-sbi or aarch64" >&2
+    exit 3
+    ;;
+esac
+
+command -v "${qemu[0]}" >/dev/null || { echo "ktest: ${qemu[0]} not found" >&2; exit 3; }
+cargo build --release "${build[@]}" || exit 3
+
+log=target/ktest-$arch.log
+echo "ktest: $arch${filter:+ ($filter)}"
+timeout "$limit" "${qemu[@]}" -nographic -kernel "$elf" -append "quiet${filter:+ ktest=$filter}" </dev/null \
+    | tr -d '\r' | tee "$log" | grep --line-buffered -a '^KTEST \(RUN\|PASS\|FAIL\|PANIC\)' | sed -u 's/^KTEST /  /'
+status=${PIPESTATUS[0]}
+
+done_line=$(grep -a '^KTEST DONE' "$log" | tail -n 1)
+if [ -z "$done_line" ]; then
+    last=$(grep -a '^KTEST RUN' "$log" | tail -n 1 | cut -d ' ' -f 3)
+    if grep -aq '^KTEST PANIC' "$log"; then
+        why="panicked"
+    elif [ "$status" -eq 124 ]; then
+        why="timed out after ${limit}s"
+    elif grep -aq '^KTEST BEGIN' "$log"; then
+        why="stopped (QEMU exit status $status)"
+    else
+        why="never started the tests (QEMU exit status $status)"
+    fi
+    echo "ktest: $arch: kernel $why${last:+ during $last}; see kernel/$log" >&2
+    exit 2
+fi
+
+tests=$(grep -a '^KTEST BEGIN' "$log" | sed 's/.*tests=//')
+passed=$(echo "$done_line" | sed 's/.*passed=\([0-9]*\).*/\1/')
+failed=$(echo "$done_line" | sed 's/.*failed=//')
+if [ "$tests" -eq 0 ]; then
+    echo "ktest: $arch: no tests match '$filter'" >&2
+    exit 1
+fi
+# The exit status the kernel gave QEMU must agree with what it printed
+# (OpenSBI shuts down the same whatever the reason, so it is always 0)
+expect=$([ "$failed" -eq 0 ] || [ "$arch" = riscv64-sbi ] && echo 0 || echo 1)
+if [ "$status" -ne "$expect" ]; then
+    echo "ktest: $arch: QEMU exited with status $status after $passed passed, $failed failed; see kernel/$log" >&2
+    exit 2
+fi
+echo "ktest: $arch: $passed passed, $failed failed"
+[ "$failed" -eq 0 ] || exit 1
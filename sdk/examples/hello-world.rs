@@ -0,0 +1,18 @@
+//! Hello, SurakshaOS: print a greeting, how long the system has been up
+//! and the tail of the kernel log.
+
+#![no_std]
+#![no_main]
+
+use suraksha_sdk::{log, println, time};
+
+suraksha_sdk::entry!(main);
+
+fn main() -> suraksha_sdk::Result<()> {
+    println!("Hello from SurakshaOS userspace!");
+    println!("up {} ms", time::uptime().as_millis());
+    for line in log::read(512)?.lines() {
+        println!("  {}", line);
+    }
+    Ok(())
+}
@@ -0,0 +1,96 @@
+//! The capability audit log: every capability the kernel created,
+//! checked, refused, delegated, revoked or let expire, for a security
+//! daemon holding the AuditLog capability to follow.  The kernel keeps
+//! only the newest entries; a reader that falls behind sees a gap in
+//! `seq`.
+//!
+//! ```ignore
+//! let mut after = 0;
+//! loop {
+//!     for r in audit::read(log, &Filter { after, ops: audit::op_bit(audit::OP_DENY), ..Filter::ANY })? {
+//!         after = r.seq;
+//!         println!("pid {} denied {:#x}", r.pid, r.cap);
+//!     }
+//! }
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::cap::Handle;
+use crate::syscall::{self, SYS_AUDIT_READ};
+
+/// The most entries one read returns.
+const MAX_RECORDS: usize = 256;
+
+/// Operations, as `AuditRecord::op` has them.
+pub const OP_CREATE:   u32 = 0;
+pub const OP_VALIDATE: u32 = 1;
+pub const OP_DENY:     u32 = 2;
+pub const OP_REVOKE:   u32 = 3;
+pub const OP_EXPIRE:   u32 = 4;
+pub const OP_DELEGATE: u32 = 5;
+
+/// `op`'s bit in `Filter::ops`.
+pub const fn op_bit(op: u32) -> u32 {
+    1 << op
+}
+
+/// Which entries to read.
+#[derive(Debug, Clone, Copy)]
+pub struct Filter {
+    /// Only those after this `seq`: the last one read, or 0.
+    pub after: u64,
+    pub pid:   Option<u64>,
+    /// Only those about this capability id.
+    pub cap:   Option<u64>,
+    /// `op_bit`s of the operations wanted; 0 for all.
+    pub ops:   u32,
+}
+
+impl Filter {
+    pub const ANY: Filter = Filter { after: 0, pid: None, cap: None, ops: 0 };
+}
+
+/// The kernel's `AuditQuery`.
+#[repr(C)]
+struct Query {
+    handle:    u64,
+    after:     u64,
+    pid:       u64,
+    cap:       u64,
+    ops:       u32,
+    _reserved: u32,
+}
+
+/// One audit entry.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AuditRecord {
+    /// Consecutive from 1.
+    pub seq:       u64,
+    pub time_ms:   u64,
+    pub pid:       u64,
+    pub cap:       u64,
+    pub op:        u32,
+    pub _reserved: u32,
+}
+
+/// The oldest entries `filter` picks, with the AuditLog capability
+/// `log`; empty if there are none yet.
+pub fn read(log: Handle, filter: &Filter) -> crate::Result<Vec<AuditRecord>> {
+    let query = Query {
+        handle:    log.0,
+        after:     filter.after,
+        pid:       filter.pid.unwrap_or(u64::MAX),
+        cap:       filter.cap.unwrap_or(0),
+        ops:       filter.ops,
+        _reserved: 0,
+    };
+    let mut v = alloc::vec![AuditRecord::default(); MAX_RECORDS];
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(v.as_mut_ptr() as *mut u8, core::mem::size_of_val(v.as_slice()))
+    };
+    let n = syscall::call_into(SYS_AUDIT_READ, &query as *const Query as usize, bytes)?;
+    v.truncate(n / core::mem::size_of::<AuditRecord>());
+    Ok(v)
+}
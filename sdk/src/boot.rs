@@ -0,0 +1,36 @@
+//! A/B boot slots.
+
+use crate::syscall::{self, BOOTCTL_INFO, BOOTCTL_MARK_SUCCESSFUL, BOOTCTL_SET_ACTIVE, BOOTCTL_SET_UNBOOTABLE, SYS_BOOT_CONTROL};
+
+/// One slot, A then B.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SlotRecord {
+    pub priority:   u8,
+    pub tries:      u8,
+    pub successful: u8,
+    /// 1 for the slot running.
+    pub current:    u8,
+}
+
+pub fn slots() -> crate::Result<[SlotRecord; 2]> {
+    let mut slots = [SlotRecord::default(); 2];
+    let len = core::mem::size_of_val(&slots);
+    syscall::call(SYS_BOOT_CONTROL, BOOTCTL_INFO, slots.as_mut_ptr() as usize, len)?;
+    Ok(slots)
+}
+
+/// The running slot booted successfully; system processes only.
+pub fn mark_successful() -> crate::Result<()> {
+    syscall::call(SYS_BOOT_CONTROL, BOOTCTL_MARK_SUCCESSFUL, 0, 0).map(drop)
+}
+
+/// Boot slot `slot` (0 is A) next; system processes only.
+pub fn set_active(slot: usize) -> crate::Result<()> {
+    syscall::call(SYS_BOOT_CONTROL, BOOTCTL_SET_ACTIVE, slot, 0).map(drop)
+}
+
+/// Never boot slot `slot`; system processes only.
+pub fn set_unbootable(slot: usize) -> crate::Result<()> {
+    syscall::call(SYS_BOOT_CONTROL, BOOTCTL_SET_UNBOOTABLE, slot, 0).map(drop)
+}
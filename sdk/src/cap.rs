@@ -0,0 +1,68 @@
+//! Capabilities.  A program holds them as handles into its own table in
+//! the kernel; a handle means nothing to any other program.
+
+use alloc::vec::Vec;
+
+use crate::syscall::{self, CAP_CLOSE, CAP_LIST, SYS_CAP};
+
+/// The most capabilities one listing returns.
+const MAX_RECORDS: usize = 256;
+
+/// A capability, as the program holds it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handle(pub u64);
+
+/// Rights, as `CapRecord::perms` has them.
+pub const READ:    u32 = 1 << 0;
+pub const WRITE:   u32 = 1 << 1;
+pub const EXECUTE: u32 = 1 << 2;
+pub const CONTROL: u32 = 1 << 3;
+pub const GRANT:   u32 = 1 << 4;
+
+/// Kinds, as `CapRecord::kind` has them: the kernel's `CapabilityType`
+/// in order.  Those not here carry no resource number.
+pub const KIND_DEVICE: u32 = 0;
+pub const KIND_MEMORY: u32 = 1;
+pub const KIND_IPC:    u32 = 4;
+pub const KIND_AI:     u32 = 6;
+pub const KIND_PORT:   u32 = 22;
+
+/// One capability the program holds.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CapRecord {
+    pub handle:       u64,
+    pub kind:         u32,
+    pub perms:        u32,
+    /// Device, channel, port or model number; a memory region's base.
+    pub resource:     u64,
+    /// Time left before it expires; u64::MAX if it never does.
+    pub remaining_ms: u64,
+}
+
+impl CapRecord {
+    pub fn handle(&self) -> Handle {
+        Handle(self.handle)
+    }
+}
+
+/// The capabilities the program holds.
+pub fn list() -> crate::Result<Vec<CapRecord>> {
+    let mut v = alloc::vec![CapRecord::default(); MAX_RECORDS];
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(v.as_mut_ptr() as *mut u8, core::mem::size_of_val(v.as_slice()))
+    };
+    let n = syscall::call_into(SYS_CAP, CAP_LIST, bytes)?;
+    v.truncate(n / core::mem::size_of::<CapRecord>());
+    Ok(v)
+}
+
+/// The handle to the program's capability for IPC channel `channel`.
+pub fn ipc_channel(channel: u64) -> crate::Result<Option<Handle>> {
+    Ok(list()?.iter().find(|c| c.kind == KIND_IPC && c.resource == channel).map(CapRecord::handle))
+}
+
+/// Give up the capability `handle` names.
+pub fn close(handle: Handle) -> crate::Result<()> {
+    syscall::call(SYS_CAP, CAP_CLOSE, handle.0 as usize, 0).map(drop)
+}
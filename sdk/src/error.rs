@@ -0,0 +1,56 @@
+//! Errors system calls return.
+
+/// The kernel's `SyscallError`, by code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(isize)]
+pub enum Error {
+    NoSuchCall       = -1,
+    InvalidArgument  = -2,
+    BadAddress       = -3,
+    BufferTooSmall   = -4,
+    PermissionDenied = -5,
+    /// Nothing to do yet: try again later.
+    WouldBlock       = -6,
+    /// Waited as long as asked, for nothing.
+    TimedOut         = -7,
+    /// A code this SDK does not know.
+    Unknown          = -4096,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl Error {
+    /// A call's result: Ok if non-negative.
+    pub fn check(ret: isize) -> Result<usize> {
+        match ret {
+            0.. => Ok(ret as usize),
+            -1  => Err(Error::NoSuchCall),
+            -2  => Err(Error::InvalidArgument),
+            -3  => Err(Error::BadAddress),
+            -4  => Err(Error::BufferTooSmall),
+            -5  => Err(Error::PermissionDenied),
+            -6  => Err(Error::WouldBlock),
+            -7  => Err(Error::TimedOut),
+            _   => Err(Error::Unknown),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Error::NoSuchCall       => "no such system call",
+            Error::InvalidArgument  => "invalid argument",
+            Error::BadAddress       => "bad address",
+            Error::BufferTooSmall   => "buffer too small",
+            Error::PermissionDenied => "permission denied",
+            Error::WouldBlock       => "would block",
+            Error::TimedOut         => "timed out",
+            Error::Unknown          => "unknown error",
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
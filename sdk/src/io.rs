@@ -0,0 +1,60 @@
+//! Standard output and error, both the console.
+
+use core::fmt;
+
+use crate::syscall::{self, STDERR, STDOUT, SYS_WRITE};
+
+/// Write `bytes` to file descriptor `fd`; the number written.
+fn write(fd: usize, bytes: &[u8]) -> crate::Result<usize> {
+    syscall::call(SYS_WRITE, fd, bytes.as_ptr() as usize, bytes.len())
+}
+
+/// Standard output, for `core::fmt::Write`.
+pub struct Stdout;
+
+/// Standard error, for `core::fmt::Write`.
+pub struct Stderr;
+
+impl fmt::Write for Stdout {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write(STDOUT, s.as_bytes()).map(drop).map_err(|_| fmt::Error)
+    }
+}
+
+impl fmt::Write for Stderr {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write(STDERR, s.as_bytes()).map(drop).map_err(|_| fmt::Error)
+    }
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    let _ = fmt::Write::write_fmt(&mut Stdout, args);
+}
+
+#[doc(hidden)]
+pub fn _eprint(args: fmt::Arguments) {
+    let _ = fmt::Write::write_fmt(&mut Stderr, args);
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => { $crate::io::_print(format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! println {
+    ()              => { $crate::print!("\n") };
+    ($($arg:tt)*)   => { $crate::io::_print(format_args!("{}\n", format_args!($($arg)*))) };
+}
+
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => { $crate::io::_eprint(format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! eprintln {
+    ()              => { $crate::eprint!("\n") };
+    ($($arg:tt)*)   => { $crate::io::_eprint(format_args!("{}\n", format_args!($($arg)*))) };
+}
@@ -0,0 +1,46 @@
+//! IPC: messages over channels, each reached through the handle to the
+//! program's capability for it.  `call` asks and waits for the answer,
+//! which the server gives with `reply`; it learns the call's id from the
+//! `port::Header` that `port::recv` takes the request with.
+
+use crate::cap::Handle;
+use crate::syscall::{self, SYS_IPC_CALL, SYS_IPC_RECV, SYS_IPC_RECV_WAIT, SYS_IPC_REPLY, SYS_IPC_SEND, WAIT_FOREVER};
+
+/// Largest message payload.
+pub const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Send `payload` on the channel `channel` names.  `WouldBlock` if the
+/// other end's queue is full.
+pub fn send(channel: Handle, payload: &[u8]) -> crate::Result<()> {
+    syscall::call(SYS_IPC_SEND, channel.0 as usize, payload.as_ptr() as usize, payload.len()).map(drop)
+}
+
+/// Take the next message on `channel` into `buf`; its length.
+/// `WouldBlock` if none has come, `BufferTooSmall` if it does not fit.
+pub fn recv(channel: Handle, buf: &mut [u8]) -> crate::Result<usize> {
+    syscall::call(SYS_IPC_RECV, channel.0 as usize, buf.as_mut_ptr() as usize, buf.len())
+}
+
+/// As `recv`, but if no message has come, wait for one: for `timeout_ms`
+/// (None: as long as it takes), then `TimedOut`.
+pub fn recv_timeout(channel: Handle, buf: &mut [u8], timeout_ms: Option<u64>) -> crate::Result<usize> {
+    let timeout = timeout_ms.map_or(WAIT_FOREVER, |t| t.min(WAIT_FOREVER as u64 - 1) as usize);
+    crate::Error::check(unsafe {
+        syscall::syscall4(SYS_IPC_RECV_WAIT, channel.0 as usize, buf.as_mut_ptr() as usize, buf.len(), timeout)
+    })
+}
+
+/// Send the first `len` bytes of `buf` as a request on `channel`, which
+/// may be a port's, and wait for the reply, which `buf` gets in their
+/// place; its length.  `BufferTooSmall` if it did not fit.
+pub fn call(channel: Handle, buf: &mut [u8], len: usize) -> crate::Result<usize> {
+    if len > buf.len() { return Err(crate::Error::InvalidArgument); }
+    crate::Error::check(unsafe {
+        syscall::syscall4(SYS_IPC_CALL, channel.0 as usize, buf.as_mut_ptr() as usize, len, buf.len())
+    })
+}
+
+/// Answer the request that came with call id `call` with `payload`.
+pub fn reply(call: u64, payload: &[u8]) -> crate::Result<()> {
+    syscall::call(SYS_IPC_REPLY, call as usize, payload.as_ptr() as usize, payload.len()).map(drop)
+}
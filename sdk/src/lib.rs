@@ -0,0 +1,45 @@
+//! SurakshaOS SDK
+//! What a user program needs to run on SurakshaOS: safe wrappers over
+//! the kernel's system calls, `print!`/`println!` to the console, and
+//! (feature `rt`, on by default) its start-up, a panic handler and a heap.
+//!
+//! ```ignore
+//! #![no_std]
+//! #![no_main]
+//! use suraksha_sdk::println;
+//!
+//! suraksha_sdk::entry!(main);
+//!
+//! fn main() {
+//!     println!("hello");
+//! }
+//! ```
+//!
+//! The wrappers cover the system calls the kernel has: console output,
+//! exit, clocks, init's service protocol, the kernel log, power and data
+//! usage statistics, boot control, the program's capabilities, the
+//! IPC channels and ports they name and the capability audit log.
+//! Files and sockets have no system calls yet.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod audit;
+pub mod boot;
+pub mod cap;
+pub mod error;
+pub mod io;
+pub mod ipc;
+pub mod log;
+pub mod port;
+pub mod process;
+pub mod service;
+pub mod stats;
+pub mod syscall;
+pub mod time;
+
+#[cfg(feature = "rt")]
+pub mod rt;
+
+pub use error::{Error, Result};
@@ -0,0 +1,19 @@
+//! The kernel log.
+
+use alloc::string::String;
+use alloc::vec;
+
+use crate::syscall::{self, DMESG_CLEAR, DMESG_READ, SYS_DMESG};
+
+/// The newest whole lines of the kernel log, up to `max` bytes.
+pub fn read(max: usize) -> crate::Result<String> {
+    let mut buf = vec![0u8; max];
+    let n = syscall::call_into(SYS_DMESG, DMESG_READ, &mut buf)?;
+    buf.truncate(n);
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// Empty the kernel log; system processes only.
+pub fn clear() -> crate::Result<()> {
+    syscall::call(SYS_DMESG, DMESG_CLEAR, 0, 0).map(drop)
+}
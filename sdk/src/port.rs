@@ -0,0 +1,78 @@
+//! IPC ports: many clients to one server.  The server creates the port
+//! and gives each client a handle to send to it with a badge of its
+//! choosing; every message comes with its sender's badge, so the server
+//! knows whose it is.  Clients send with `ipc::send` on that handle, or
+//! `ipc::call` for an answer.
+//!
+//! ```ignore
+//! let port = port::create()?;
+//! port::badge(port, client_pid, 1)?;
+//! let mut buf = [0u8; 512];
+//! loop {
+//!     let (from, len) = port::recv_wait(port, &mut buf)?;
+//!     let answer = serve(from.badge, &buf[..len]);
+//!     if from.call != 0 { ipc::reply(from.call, &answer)?; }
+//! }
+//! ```
+
+use crate::cap::Handle;
+use crate::syscall::{self, PORT_BADGE, PORT_CREATE, PORT_RECV, PORT_RECV_WAIT, SYS_PORT};
+
+/// Kinds, as `Header::kind` has them.
+pub const KIND_REQUEST:      u32 = 0;
+pub const KIND_REPLY:        u32 = 1;
+pub const KIND_NOTIFICATION: u32 = 2;
+
+/// Who sent a message, and what it is.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Header {
+    /// The badge the server gave the sender's handle.
+    pub badge:  u64,
+    pub sender: u64,
+    pub kind:   u32,
+    pub len:    u32,
+    /// A request made with `ipc::call`: the id to answer it with
+    /// `ipc::reply`; 0 otherwise.
+    pub call:   u64,
+}
+
+const HEADER: usize = core::mem::size_of::<Header>();
+
+/// Create a port the program serves; the handle to receive on it.
+pub fn create() -> crate::Result<Handle> {
+    syscall::call(SYS_PORT, PORT_CREATE, 0, 0).map(|h| Handle(h as u64))
+}
+
+/// Give process `pid` a handle to send to `port`, its messages carrying
+/// `badge` (not 0).  It finds the handle among its capabilities, of kind
+/// `cap::KIND_PORT`.
+pub fn badge(port: Handle, pid: u64, badge: u64) -> crate::Result<()> {
+    crate::Error::check(unsafe {
+        syscall::syscall4(SYS_PORT, PORT_BADGE, port.0 as usize, pid as usize, badge as usize)
+    }).map(drop)
+}
+
+/// Take the next message on `port` into `buf`: who sent it, and its
+/// length.  `port` may be a channel's handle too, to learn a request's
+/// call id.  `WouldBlock` if none has come, `BufferTooSmall` if it does
+/// not fit.
+pub fn recv(port: Handle, buf: &mut [u8]) -> crate::Result<(Header, usize)> {
+    take(PORT_RECV, port, buf)
+}
+
+/// As `recv`, but wait for a message if none has come.
+pub fn recv_wait(port: Handle, buf: &mut [u8]) -> crate::Result<(Header, usize)> {
+    take(PORT_RECV_WAIT, port, buf)
+}
+
+fn take(kind: usize, port: Handle, buf: &mut [u8]) -> crate::Result<(Header, usize)> {
+    let mut raw = alloc::vec![0u8; HEADER + buf.len()];
+    let n = crate::Error::check(unsafe {
+        syscall::syscall4(SYS_PORT, kind, port.0 as usize, raw.as_mut_ptr() as usize, raw.len())
+    })?;
+    let header = unsafe { core::ptr::read_unaligned(raw.as_ptr() as *const Header) };
+    let len = n - HEADER;
+    buf[..len].copy_from_slice(&raw[HEADER..n]);
+    Ok((header, len))
+}
@@ -0,0 +1,61 @@
+//! The program's runtime: start-up, panics and the heap.  A program names
+//! its main function with `entry!`; it may return `()` or an exit code.
+
+use core::panic::PanicInfo;
+use linked_list_allocator::LockedHeap;
+
+/// The heap, a fixed arena: there is no call to grow one yet.
+const HEAP_SIZE: usize = 256 * 1024;
+
+static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+#[global_allocator]
+static ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+/// What a main function may return.
+pub trait ExitCode {
+    fn code(self) -> i32;
+}
+
+impl ExitCode for () {
+    fn code(self) -> i32 { 0 }
+}
+
+impl ExitCode for i32 {
+    fn code(self) -> i32 { self }
+}
+
+impl<E: core::fmt::Display> ExitCode for Result<(), E> {
+    fn code(self) -> i32 {
+        match self {
+            Ok(())  => 0,
+            Err(e) => {
+                crate::eprintln!("error: {}", e);
+                1
+            }
+        }
+    }
+}
+
+/// Make `$main` the program's entry point.
+#[macro_export]
+macro_rules! entry {
+    ($main:path) => {
+        #[no_mangle]
+        pub extern "C" fn _start() -> ! {
+            $crate::rt::start(|| $crate::rt::ExitCode::code($main()))
+        }
+    };
+}
+
+#[doc(hidden)]
+pub fn start(main: impl FnOnce() -> i32) -> ! {
+    unsafe { ALLOCATOR.lock().init(core::ptr::addr_of_mut!(HEAP) as *mut u8, HEAP_SIZE); }
+    crate::process::exit(main())
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    crate::eprintln!("panicked: {}", info);
+    crate::process::exit(101)
+}
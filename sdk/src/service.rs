@@ -0,0 +1,27 @@
+//! Init's service protocol, for programs init starts as services.
+
+use alloc::vec::Vec;
+
+use crate::syscall::{self, NOTIFY_READY, NOTIFY_WATCHDOG, SYS_SERVICE_NOTIFY, SYS_SERVICE_SOCKETS};
+
+/// The most sockets a service is handed.
+const MAX_SOCKETS: usize = 16;
+
+/// Tell init the service is up and serving: services that wait on it may
+/// start.
+pub fn notify_ready() -> crate::Result<()> {
+    syscall::call(SYS_SERVICE_NOTIFY, NOTIFY_READY, 0, 0).map(drop)
+}
+
+/// Tell init the service is still alive, within its watchdog interval.
+pub fn ping_watchdog() -> crate::Result<()> {
+    syscall::call(SYS_SERVICE_NOTIFY, NOTIFY_WATCHDOG, 0, 0).map(drop)
+}
+
+/// The ids of the sockets init opened for the service, in the order its
+/// config lists them.
+pub fn listen_sockets() -> crate::Result<Vec<u32>> {
+    let mut ids = [0u32; MAX_SOCKETS];
+    let n = syscall::call(SYS_SERVICE_SOCKETS, ids.as_mut_ptr() as usize, core::mem::size_of_val(&ids), 0)?;
+    Ok(ids[..n / 4].to_vec())
+}
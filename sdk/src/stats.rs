@@ -0,0 +1,66 @@
+//! Power and data usage statistics.
+
+use alloc::vec::Vec;
+
+use crate::syscall::{self, NET_STATS_DAILY, NET_STATS_TOTALS, POWER_STATS_WAKELOCKS, SYS_NET_STATS, SYS_POWER_STATS};
+
+/// The most records one read returns.
+const MAX_RECORDS: usize = 256;
+
+/// One wakelock holder, as the kernel keeps it.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WakelockRecord {
+    pub pid:          u64,
+    pub acquisitions: u64,
+    pub timeouts:     u64,
+    pub total_ms:     u64,
+    pub longest_ms:   u64,
+    pub active:       u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Counters {
+    pub rx_bytes:   u64,
+    pub rx_packets: u64,
+    pub tx_bytes:   u64,
+    pub tx_packets: u64,
+}
+
+/// One app's data usage on one interface.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UsageRecord {
+    pub pid:   u64,
+    /// Interface index.
+    pub index: u64,
+    /// Days since 1970-01-01 (UTC); 0 in totals.
+    pub day:   u64,
+    pub usage: Counters,
+}
+
+/// Read an array of `T` records of kind `kind`.
+fn records<T: Copy + Default>(num: usize, kind: usize) -> crate::Result<Vec<T>> {
+    let mut v = alloc::vec![T::default(); MAX_RECORDS];
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(v.as_mut_ptr() as *mut u8, core::mem::size_of_val(v.as_slice()))
+    };
+    let n = syscall::call_into(num, kind, bytes)?;
+    v.truncate(n / core::mem::size_of::<T>());
+    Ok(v)
+}
+
+pub fn wakelocks() -> crate::Result<Vec<WakelockRecord>> {
+    records(SYS_POWER_STATS, POWER_STATS_WAKELOCKS)
+}
+
+/// Each app's running totals per interface.
+pub fn data_usage() -> crate::Result<Vec<UsageRecord>> {
+    records(SYS_NET_STATS, NET_STATS_TOTALS)
+}
+
+/// Each app's usage per interface and day.
+pub fn daily_data_usage() -> crate::Result<Vec<UsageRecord>> {
+    records(SYS_NET_STATS, NET_STATS_DAILY)
+}
@@ -0,0 +1,114 @@
+//! The raw system call ABI.  RISC-V: `ecall` with the number in a7 and
+//! arguments in a0–a5.  AArch64: `svc #0` with the number in x8 and
+//! arguments in x0–x5.  The result comes back in a0/x0: non-negative is
+//! call-specific, negative an `Error` code.
+//!
+//! The numbers and `kind` values match the kernel's `syscall` module.
+
+use core::arch::asm;
+
+pub const SYS_POWER_STATS:     usize = 1;
+pub const SYS_NET_STATS:       usize = 2;
+pub const SYS_DMESG:           usize = 3;
+pub const SYS_BOOT_CONTROL:    usize = 4;
+pub const SYS_SERVICE_NOTIFY:  usize = 5;
+pub const SYS_SERVICE_SOCKETS: usize = 6;
+pub const SYS_WRITE:           usize = 7;
+pub const SYS_EXIT:            usize = 8;
+pub const SYS_CLOCK:           usize = 9;
+pub const SYS_CAP:             usize = 10;
+pub const SYS_IPC_SEND:        usize = 11;
+pub const SYS_IPC_RECV:        usize = 12;
+pub const SYS_AUDIT_READ:      usize = 13;
+pub const SYS_IPC_RECV_WAIT:   usize = 14;
+pub const SYS_PORT:            usize = 15;
+pub const SYS_IPC_CALL:        usize = 16;
+pub const SYS_IPC_REPLY:       usize = 17;
+
+pub const POWER_STATS_WAKELOCKS: usize = 1;
+
+pub const NET_STATS_TOTALS: usize = 0;
+pub const NET_STATS_DAILY:  usize = 1;
+
+pub const DMESG_READ:  usize = 0;
+pub const DMESG_CLEAR: usize = 1;
+
+pub const BOOTCTL_INFO:            usize = 0;
+pub const BOOTCTL_MARK_SUCCESSFUL: usize = 1;
+pub const BOOTCTL_SET_ACTIVE:      usize = 2;
+pub const BOOTCTL_SET_UNBOOTABLE:  usize = 3;
+
+pub const NOTIFY_READY:    usize = 0;
+pub const NOTIFY_WATCHDOG: usize = 1;
+
+pub const STDOUT: usize = 1;
+pub const STDERR: usize = 2;
+
+pub const CLOCK_MONOTONIC: usize = 0;
+pub const CLOCK_REALTIME:  usize = 1;
+
+pub const CAP_LIST:  usize = 0;
+pub const CAP_CLOSE: usize = 1;
+
+pub const WAIT_FOREVER: usize = usize::MAX;
+
+pub const PORT_CREATE:    usize = 0;
+pub const PORT_BADGE:     usize = 1;
+pub const PORT_RECV:      usize = 2;
+pub const PORT_RECV_WAIT: usize = 3;
+
+/// Make system call `num`.
+///
+/// # Safety
+/// Pointer arguments are used by the kernel as given: they must be valid
+/// for what the call does with them.
+#[cfg(target_arch = "riscv64")]
+pub unsafe fn syscall(num: usize, a0: usize, a1: usize, a2: usize) -> isize {
+    let ret: isize;
+    asm!("ecall", inlateout("a0") a0 => ret, in("a1") a1, in("a2") a2, in("a7") num, options(nostack));
+    ret
+}
+
+/// Make system call `num`.
+///
+/// # Safety
+/// Pointer arguments are used by the kernel as given: they must be valid
+/// for what the call does with them.
+#[cfg(target_arch = "aarch64")]
+pub unsafe fn syscall(num: usize, a0: usize, a1: usize, a2: usize) -> isize {
+    let ret: isize;
+    asm!("svc #0", inlateout("x0") a0 => ret, in("x1") a1, in("x2") a2, in("x8") num, options(nostack));
+    ret
+}
+
+/// Make system call `num` with a fourth argument.
+///
+/// # Safety
+/// As `syscall`.
+#[cfg(target_arch = "riscv64")]
+pub unsafe fn syscall4(num: usize, a0: usize, a1: usize, a2: usize, a3: usize) -> isize {
+    let ret: isize;
+    asm!("ecall", inlateout("a0") a0 => ret, in("a1") a1, in("a2") a2, in("a3") a3, in("a7") num, options(nostack));
+    ret
+}
+
+/// Make system call `num` with a fourth argument.
+///
+/// # Safety
+/// As `syscall`.
+#[cfg(target_arch = "aarch64")]
+pub unsafe fn syscall4(num: usize, a0: usize, a1: usize, a2: usize, a3: usize) -> isize {
+    let ret: isize;
+    asm!("svc #0", inlateout("x0") a0 => ret, in("x1") a1, in("x2") a2, in("x3") a3, in("x8") num, options(nostack));
+    ret
+}
+
+/// A call whose result is a count or a value.
+pub(crate) fn call(num: usize, a0: usize, a1: usize, a2: usize) -> crate::Result<usize> {
+    crate::Error::check(unsafe { syscall(num, a0, a1, a2) })
+}
+
+/// A call that fills `buf`; the number of bytes it wrote.
+pub(crate) fn call_into(num: usize, kind: usize, buf: &mut [u8]) -> crate::Result<usize> {
+    crate::Error::check(unsafe { syscall(num, kind, buf.as_mut_ptr() as usize, buf.len()) })
+}
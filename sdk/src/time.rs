@@ -0,0 +1,21 @@
+//! Clocks.
+
+use core::time::Duration;
+
+use crate::syscall::{self, CLOCK_MONOTONIC, CLOCK_REALTIME, SYS_CLOCK};
+
+/// Time since boot, to the millisecond.
+pub fn uptime() -> Duration {
+    Duration::from_millis(syscall::call(SYS_CLOCK, CLOCK_MONOTONIC, 0, 0).unwrap_or(0) as u64)
+}
+
+/// Wall-clock time since the Unix epoch.
+pub fn now() -> Duration {
+    Duration::from_nanos(syscall::call(SYS_CLOCK, CLOCK_REALTIME, 0, 0).unwrap_or(0) as u64)
+}
+
+/// Wait `d`, spinning: there is no sleep call yet.
+pub fn spin_for(d: Duration) {
+    let until = uptime() + d;
+    while uptime() < until { core::hint::spin_loop(); }
+}